@@ -18,12 +18,20 @@ pub fn get_random_nonce() -> ring::aead::Nonce {
 /// Encrypt `plaintext` using `key`.
 ///
 /// Prefixes the ciphertext with a nonce.
-pub fn encrypt(mut plaintext: Vec<u8>, key: &LessSafeKey) -> Result<Vec<u8>> {
+pub fn encrypt(plaintext: Vec<u8>, key: &LessSafeKey) -> Result<Vec<u8>> {
+    encrypt_with_aad(plaintext, key, &[])
+}
+
+/// Encrypt `plaintext` using `key`, additionally authenticating `aad` which
+/// has to be supplied again for decryption.
+///
+/// Prefixes the ciphertext with a nonce.
+pub fn encrypt_with_aad(mut plaintext: Vec<u8>, key: &LessSafeKey, aad: &[u8]) -> Result<Vec<u8>> {
     let nonce = get_random_nonce();
     // prefix ciphertext with nonce
     let mut ciphertext: Vec<u8> = nonce.as_ref().to_vec();
 
-    key.seal_in_place_append_tag(nonce, Aad::empty(), &mut plaintext)
+    key.seal_in_place_append_tag(nonce, Aad::from(aad), &mut plaintext)
         .map_err(|_| anyhow::format_err!("Encryption failed due to unspecified aead error"))?;
 
     ciphertext.append(&mut plaintext);
@@ -35,6 +43,16 @@ pub fn encrypt(mut plaintext: Vec<u8>, key: &LessSafeKey) -> Result<Vec<u8>> {
 ///
 /// Expect nonce in the prefix, like [`encrypt`] produces.
 pub fn decrypt<'c>(ciphertext: &'c mut [u8], key: &LessSafeKey) -> Result<&'c [u8]> {
+    decrypt_with_aad(ciphertext, key, &[])
+}
+
+/// Decrypts a `ciphertext` produced by [`encrypt_with_aad`] with the same
+/// `aad`.
+pub fn decrypt_with_aad<'c>(
+    ciphertext: &'c mut [u8],
+    key: &LessSafeKey,
+    aad: &[u8],
+) -> Result<&'c [u8]> {
     if ciphertext.len() < NONCE_LEN {
         bail!("Ciphertext too short: {}", ciphertext.len());
     }
@@ -43,7 +61,7 @@ pub fn decrypt<'c>(ciphertext: &'c mut [u8], key: &LessSafeKey) -> Result<&'c [u
 
     key.open_in_place(
         Nonce::assume_unique_for_key(nonce_bytes.try_into().expect("nonce size known")),
-        Aad::empty(),
+        Aad::from(aad),
         encrypted_bytes,
     )
     .map_err(|_| format_err!("Decryption failed due to unspecified aead error"))?;
@@ -88,14 +106,40 @@ pub fn encrypted_read(key: &LessSafeKey, file: PathBuf) -> Result<Vec<u8>> {
 /// * `password` - Strong user-created password
 /// * `salt` - Nonce >8 bytes to discourage rainbow attacks
 pub fn get_encryption_key(password: &str, salt: &str) -> Result<LessSafeKey> {
+    let key = stretch_password(password, salt)?;
+    let key = UnboundKey::new(&ring::aead::CHACHA20_POLY1305, &key)
+        .map_err(|_| anyhow::Error::msg("Unable to create key"))?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Key used to encrypt database values at rest.
+///
+/// Uses AES-256-GCM with a random `secret` that is stored encrypted with the
+/// key from [`get_encryption_key`], so changing the password doesn't require
+/// re-encrypting the database.
+pub fn get_database_key_from_secret(secret: [u8; 32]) -> LessSafeKey {
+    let key =
+        UnboundKey::new(&ring::aead::AES_256_GCM, &secret).expect("AES-256 keys are 32 bytes");
+    LessSafeKey::new(key)
+}
+
+/// Key for secrets that are already uniformly random, like the result of a
+/// Diffie-Hellman key exchange, so they don't need to be stretched.
+///
+/// Uses ChaCha20-Poly1305 like [`get_encryption_key`].
+pub fn get_key_from_secret(secret: [u8; 32]) -> LessSafeKey {
+    let key = UnboundKey::new(&ring::aead::CHACHA20_POLY1305, &secret)
+        .expect("ChaCha20 keys are 32 bytes");
+    LessSafeKey::new(key)
+}
+
+fn stretch_password(password: &str, salt: &str) -> Result<[u8; ring::digest::SHA256_OUTPUT_LEN]> {
     let mut key = [0u8; ring::digest::SHA256_OUTPUT_LEN];
 
     argon2()
         .hash_password_into(password.as_bytes(), salt.as_bytes(), &mut key)
         .map_err(|e| format_err!("could not hash password").context(e))?;
-    let key = UnboundKey::new(&ring::aead::CHACHA20_POLY1305, &key)
-        .map_err(|_| anyhow::Error::msg("Unable to create key"))?;
-    Ok(LessSafeKey::new(key))
+    Ok(key)
 }
 
 /// Generates a B64-encoded random salt string of the recommended 16 byte length
@@ -115,7 +159,10 @@ fn argon2() -> Argon2<'static> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{decrypt, encrypt, get_encryption_key};
+    use crate::{
+        decrypt, decrypt_with_aad, encrypt, encrypt_with_aad, get_database_key_from_secret,
+        get_encryption_key,
+    };
 
     #[test]
     fn encrypts_and_decrypts() {
@@ -129,4 +176,16 @@ mod tests {
 
         assert_eq!(decrypted, message.as_bytes());
     }
+
+    #[test]
+    fn encrypts_and_decrypts_with_aad() {
+        let key = get_database_key_from_secret([42; 32]);
+        let message = "hello world";
+
+        let mut cipher_text = encrypt_with_aad(message.as_bytes().to_vec(), &key, b"a").unwrap();
+        assert!(decrypt_with_aad(&mut cipher_text.clone(), &key, b"b").is_err());
+        let decrypted = decrypt_with_aad(&mut cipher_text, &key, b"a").unwrap();
+
+        assert_eq!(decrypted, message.as_bytes());
+    }
 }
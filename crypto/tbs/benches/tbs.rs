@@ -6,7 +6,7 @@ mod bench {
 
     use tbs::{
         blind_message, combine_valid_shares, dealer_keygen, sign_blinded_msg, unblind_signature,
-        verify, BlindingKey, Message,
+        verify, verify_batch, BlindingKey, Message,
     };
     use test::Bencher;
 
@@ -76,4 +76,25 @@ mod bench {
 
         bencher.iter(|| verify(msg, sig, pk));
     }
+
+    #[bench]
+    fn bench_verify_batch(bencher: &mut Bencher) {
+        let (pk, _pks, sks) = dealer_keygen(4, 5);
+        let items = (0..100u8)
+            .map(|i| {
+                let msg = Message::from_bytes(&[i]);
+                let bkey = BlindingKey::random();
+                let bmsg = blind_message(msg, bkey);
+                let shares = sks
+                    .iter()
+                    .map(|sk| sign_blinded_msg(bmsg, *sk))
+                    .enumerate()
+                    .collect::<Vec<_>>();
+                let bsig = combine_valid_shares(shares, 4);
+                (msg, unblind_signature(bkey, bsig))
+            })
+            .collect::<Vec<_>>();
+
+        bencher.iter(|| verify_batch(&items, pk));
+    }
 }
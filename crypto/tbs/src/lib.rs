@@ -8,7 +8,7 @@ use std::hash::Hasher;
 use bls12_381::{pairing, G1Affine, G1Projective, G2Affine, G2Projective};
 pub use bls12_381::{G1Affine as MessagePoint, G2Affine as PubKeyPoint, Scalar};
 use ff::Field;
-use group::Curve;
+use group::{Curve, Group};
 use rand::rngs::OsRng;
 use rand::RngCore;
 use serde::{Deserialize, Serialize};
@@ -203,6 +203,22 @@ pub fn verify(msg: Message, sig: Signature, pk: AggregatePublicKey) -> bool {
     pairing(&msg.0, &pk.0) == pairing(&sig.0, &G2Affine::generator())
 }
 
+/// Verifies a batch of signatures under the same key with two pairings instead
+/// of two per signature. Every message and signature is weighted with a random
+/// scalar, so invalid signatures can't cancel each other out. If the batch is
+/// invalid it doesn't tell which signature is, use [`verify`] for that.
+pub fn verify_batch(items: &[(Message, Signature)], pk: AggregatePublicKey) -> bool {
+    let (msg_sum, sig_sum) = items.iter().fold(
+        (G1Projective::identity(), G1Projective::identity()),
+        |(msg_sum, sig_sum), (msg, sig)| {
+            let weight = Scalar::random(OsRng);
+            (msg_sum + msg.0 * weight, sig_sum + sig.0 * weight)
+        },
+    );
+
+    pairing(&msg_sum.to_affine(), &pk.0) == pairing(&sig_sum.to_affine(), &G2Affine::generator())
+}
+
 pub fn verify_blind_share(
     msg: BlindedMessage,
     sig: BlindedSignatureShare,
@@ -239,7 +255,7 @@ impl Aggregatable for Vec<PublicKeyShare> {
 mod tests {
     use crate::{
         blind_message, combine_valid_shares, dealer_keygen, sign_blinded_msg, unblind_signature,
-        verify, Aggregatable, BlindingKey, Message,
+        verify, verify_batch, Aggregatable, BlindingKey, Message,
     };
 
     #[test]
@@ -288,6 +304,37 @@ mod tests {
         assert!(verify(msg, sig, pk));
     }
 
+    #[test]
+    fn test_verify_batch() {
+        let threshold = 3;
+        let (pk, _pks, sks) = dealer_keygen(threshold, 4);
+
+        let mut items = (0..10u8)
+            .map(|i| {
+                let msg = Message::from_bytes(&[i]);
+                let bkey = BlindingKey::random();
+                let bmsg = blind_message(msg, bkey);
+                let shares = sks
+                    .iter()
+                    .enumerate()
+                    .map(|(idx, sk)| (idx, sign_blinded_msg(bmsg, *sk)))
+                    .collect::<Vec<_>>();
+                let bsig = combine_valid_shares(shares, threshold);
+                (msg, unblind_signature(bkey, bsig))
+            })
+            .collect::<Vec<_>>();
+
+        assert!(verify_batch(&[], pk));
+        assert!(verify_batch(&items, pk));
+
+        // Swapping two signatures invalidates the batch
+        let first_sig = items[0].1;
+        items[0].1 = items[1].1;
+        items[1].1 = first_sig;
+        assert!(!verify_batch(&items, pk));
+        assert!(!verify(items[0].0, items[0].1, pk));
+    }
+
     #[test]
     #[should_panic(expected = "Not enough signature shares")]
     fn test_insufficient_shares() {
@@ -0,0 +1,268 @@
+//! Conformance of the gateway's lightning backends
+//!
+//! The gateway talks to its lightning node through `ILnRpcClient`, which has
+//! an implementation per node implementation. Bugs often only affect one of
+//! them, e.g. route hints being built from the wrong side of a channel, so
+//! the same scripted scenarios are run against the gateway of every backend
+//! and the observable outcomes have to be identical. Each gateway pays and is
+//! paid by the lightning node on the other end of its channel.
+//!
+//! A new backend only has to be added to the list of backends in
+//! [`gateway_conformance_test`].
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use fedimint_cli::LnInvoiceResponse;
+use fedimint_logging::LOG_DEVIMINT;
+use tokio::time::sleep;
+use tracing::info;
+
+use crate::federation::Federation;
+use crate::{cmd, DevFed, Gatewayd, LightningNode};
+
+/// Amount of every payment, the gateways charge the same fees so the balances
+/// have to change by the same amounts
+const PAYMENT_AMOUNT_MSAT: u64 = 2_000;
+
+/// Expiry of invoices that are paid after they expired
+const SHORT_EXPIRY: Duration = Duration::from_secs(1);
+
+const INVOICE_EXPIRY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Scenario {
+    /// A client pays an invoice of the peer through the gateway
+    OutgoingPayment,
+    /// The peer pays an invoice of a client through the gateway
+    IncomingPayment,
+    /// A client pays an invoice that already expired, the gateway has to give
+    /// up and the client gets refunded
+    ExpiredOutgoingPayment,
+    /// The route hints the gateway registers with the federation
+    RouteHints,
+}
+
+const SCENARIOS: [Scenario; 4] = [
+    Scenario::OutgoingPayment,
+    Scenario::IncomingPayment,
+    Scenario::ExpiredOutgoingPayment,
+    Scenario::RouteHints,
+];
+
+/// What a scenario observed, has to be the same for every backend
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Outcome {
+    Payment {
+        /// Whether the recipient saw the payment
+        paid: bool,
+        client_balance_change: i64,
+        gateway_balance_change: i64,
+    },
+    RouteHints {
+        hints: usize,
+        /// Whether every hint is a single hop from the channel peer
+        from_peer: bool,
+    },
+    /// The scenario failed before observing anything
+    Error,
+}
+
+/// A gateway and the lightning node on the other end of its channel
+struct Backend<'a> {
+    gateway: &'a Gatewayd,
+    peer: LightningNode,
+}
+
+/// Runs every scenario against the gateway of each lightning backend and
+/// fails if their outcomes differ
+pub async fn gateway_conformance_test(dev_fed: DevFed) -> Result<()> {
+    let DevFed {
+        cln,
+        lnd,
+        fed,
+        gw_cln,
+        gw_lnd,
+        ..
+    } = dev_fed;
+
+    fed.pegin(10_000).await?;
+    fed.pegin_gateway(20_000, &gw_cln).await?;
+    fed.pegin_gateway(20_000, &gw_lnd).await?;
+
+    let backends = [
+        Backend {
+            gateway: &gw_cln,
+            peer: LightningNode::Lnd(lnd),
+        },
+        Backend {
+            gateway: &gw_lnd,
+            peer: LightningNode::Cln(cln),
+        },
+    ];
+
+    let mut outcomes = BTreeMap::<Scenario, Vec<(String, Outcome)>>::new();
+    for backend in &backends {
+        let name = backend
+            .gateway
+            .ln
+            .as_ref()
+            .context("gateway must have a lightning node")?
+            .name()
+            .to_string();
+        fed.use_gateway(backend.gateway).await?;
+        for scenario in SCENARIOS {
+            let outcome = match run_scenario(&fed, backend, scenario).await {
+                Ok(outcome) => outcome,
+                Err(e) => {
+                    info!(LOG_DEVIMINT, backend = %name, ?scenario, "Scenario failed: {e:?}");
+                    Outcome::Error
+                }
+            };
+            info!(LOG_DEVIMINT, backend = %name, ?scenario, ?outcome, "Scenario finished");
+            outcomes
+                .entry(scenario)
+                .or_default()
+                .push((name.clone(), outcome));
+        }
+    }
+
+    // Errors count as mismatches even if every backend failed the same way
+    let mismatches = outcomes
+        .iter()
+        .filter(|(_, outcomes)| {
+            outcomes
+                .iter()
+                .any(|(_, outcome)| *outcome == Outcome::Error)
+                || outcomes.windows(2).any(|pair| pair[0].1 != pair[1].1)
+        })
+        .map(|(scenario, outcomes)| format!("{scenario:?}: {outcomes:?}"))
+        .collect::<Vec<_>>();
+    if !mismatches.is_empty() {
+        bail!(
+            "Gateway backends behave differently:\n{}",
+            mismatches.join("\n")
+        );
+    }
+
+    info!(LOG_DEVIMINT, "fm success: gateway-conformance-test");
+    Ok(())
+}
+
+async fn run_scenario(
+    fed: &Federation,
+    backend: &Backend<'_>,
+    scenario: Scenario,
+) -> Result<Outcome> {
+    // Labels have to be unique on the peer across runs of the scenarios
+    let label = format!("conformance-{scenario:?}-{}", rand::random::<u64>());
+    match scenario {
+        Scenario::OutgoingPayment => {
+            let invoice = backend
+                .peer
+                .create_invoice(PAYMENT_AMOUNT_MSAT, &label, INVOICE_EXPIRY)
+                .await?;
+            let balances = Balances::fetch(fed, backend.gateway).await?;
+            cmd!(fed, "ln-pay", invoice.bolt11.clone()).run().await?;
+            balances
+                .payment_outcome(
+                    fed,
+                    backend.gateway,
+                    backend.peer.invoice_paid(&invoice).await?,
+                )
+                .await
+        }
+        Scenario::IncomingPayment => {
+            let balances = Balances::fetch(fed, backend.gateway).await?;
+            let invoice: LnInvoiceResponse = serde_json::from_value(
+                cmd!(
+                    fed,
+                    "ln-invoice",
+                    format!("--amount={PAYMENT_AMOUNT_MSAT}msat"),
+                    format!("--description={label}")
+                )
+                .out_json()
+                .await?,
+            )?;
+            let paid = backend.peer.pay_invoice(invoice.invoice).await.is_ok();
+            if paid {
+                cmd!(fed, "wait-invoice", invoice.operation_id)
+                    .run()
+                    .await?;
+            }
+            balances.payment_outcome(fed, backend.gateway, paid).await
+        }
+        Scenario::ExpiredOutgoingPayment => {
+            let invoice = backend
+                .peer
+                .create_invoice(PAYMENT_AMOUNT_MSAT, &label, SHORT_EXPIRY)
+                .await?;
+            sleep(SHORT_EXPIRY * 2).await;
+            let balances = Balances::fetch(fed, backend.gateway).await?;
+            // Returns once the client got refunded
+            cmd!(fed, "ln-pay", invoice.bolt11.clone()).run().await?;
+            balances
+                .payment_outcome(
+                    fed,
+                    backend.gateway,
+                    backend.peer.invoice_paid(&invoice).await?,
+                )
+                .await
+        }
+        Scenario::RouteHints => {
+            let peer_pub_key = backend.peer.pub_key().await?;
+            let info = cmd!(backend.gateway, "info").out_json().await?;
+            let route_hints = info["federations"][0]["registration"]["route_hints"]
+                .as_array()
+                .context("route_hints must be a list")?;
+            let from_peer = route_hints.iter().all(|hint| {
+                hint.as_array().map_or(false, |hops| {
+                    hops.len() == 1 && hops[0]["src_node_id"].as_str() == Some(&peer_pub_key)
+                })
+            });
+            Ok(Outcome::RouteHints {
+                hints: route_hints.len(),
+                from_peer,
+            })
+        }
+    }
+}
+
+/// Balances before a payment
+struct Balances {
+    client: u64,
+    gateway: u64,
+}
+
+impl Balances {
+    async fn fetch(fed: &Federation, gateway: &Gatewayd) -> Result<Self> {
+        Ok(Balances {
+            client: fed.client_balance().await?,
+            gateway: gateway_balance(fed, gateway).await?,
+        })
+    }
+
+    async fn payment_outcome(
+        &self,
+        fed: &Federation,
+        gateway: &Gatewayd,
+        paid: bool,
+    ) -> Result<Outcome> {
+        Ok(Outcome::Payment {
+            paid,
+            client_balance_change: fed.client_balance().await? as i64 - self.client as i64,
+            gateway_balance_change: gateway_balance(fed, gateway).await? as i64
+                - self.gateway as i64,
+        })
+    }
+}
+
+async fn gateway_balance(fed: &Federation, gateway: &Gatewayd) -> Result<u64> {
+    let fed_id = fed.federation_id().await;
+    cmd!(gateway, "balance", "--federation-id={fed_id}")
+        .out_json()
+        .await?
+        .as_u64()
+        .context("balance must be a number")
+}
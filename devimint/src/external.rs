@@ -1,15 +1,16 @@
 use std::env;
 use std::fmt::{Display, Formatter};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{Context, Result};
-use bitcoincore_rpc::bitcoin::hashes::hex::ToHex;
+use bitcoincore_rpc::bitcoin::hashes::hex::{FromHex, ToHex};
 use bitcoincore_rpc::{bitcoin, RpcApi};
 use cln_rpc::ClnRpc;
 use fedimint_core::encoding::Encodable;
+use fedimint_core::util::write_overwrite_async;
 use fedimint_logging::LOG_DEVIMINT;
 use tokio::fs;
 use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
@@ -20,7 +21,7 @@ use tracing::{info, warn};
 
 use crate::cmd;
 use crate::util::{poll, ProcessHandle, ProcessManager};
-use crate::vars::utf8;
+use crate::vars::{instance_dir, instance_name, instance_port, utf8};
 
 #[derive(Clone)]
 pub struct Bitcoind {
@@ -81,6 +82,90 @@ impl Bitcoind {
         Ok(())
     }
 
+    pub async fn block_height(&self) -> Result<u64> {
+        Ok(self.client().get_block_count()?)
+    }
+
+    /// Mines blocks until the chain reaches `height`, fails if it is already
+    /// higher
+    pub async fn mine_to_height(&self, height: u64) -> Result<()> {
+        let current = self.block_height().await?;
+        anyhow::ensure!(
+            current <= height,
+            "Chain is at height {current}, can't mine to {height}"
+        );
+        self.mine_blocks(height - current).await
+    }
+
+    /// Makes bitcoind believe the current time is `timestamp`, blocks mined
+    /// afterwards use it as their timestamp as long as it is above the median
+    /// time of the previous blocks
+    ///
+    /// A `timestamp` of 0 goes back to the system time.
+    pub async fn set_mock_time(&self, timestamp: u64) -> Result<()> {
+        self.client()
+            .call::<()>("setmocktime", &[timestamp.into()])?;
+        Ok(())
+    }
+
+    /// Mines `amt` blocks `interval` apart, starting `interval` after the
+    /// timestamp of the current tip
+    ///
+    /// Leaves the mock time at the timestamp of the last block, so later
+    /// blocks don't go back in time.
+    pub async fn mine_blocks_with_interval(&self, amt: u64, interval: Duration) -> Result<()> {
+        let client = self.client();
+        let addr = client.get_new_address(None, None)?;
+        let mut time = u64::from(self.tip_time().await?);
+        for _ in 0..amt {
+            time += interval.as_secs();
+            self.set_mock_time(time).await?;
+            client.generate_to_address(1, &addr)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the time of the chain forward by mining a single block
+    /// `duration` after the current tip, e.g. to expire timelocks
+    pub async fn advance_time(&self, duration: Duration) -> Result<()> {
+        self.mine_blocks_with_interval(1, duration).await
+    }
+
+    /// Timestamp of the block at the tip of the chain
+    pub async fn tip_time(&self) -> Result<u32> {
+        let client = self.client();
+        let tip = client.get_best_block_hash()?;
+        Ok(client.get_block_header(&tip)?.time)
+    }
+
+    /// Replaces the last `depth` blocks with `new_blocks` freshly mined ones,
+    /// returning the hashes of the orphaned blocks
+    ///
+    /// Transactions of the orphaned blocks return to the mempool, so unless
+    /// they conflict with the new chain they are mined again.
+    pub async fn reorg(&self, depth: u64, new_blocks: u64) -> Result<Vec<bitcoin::BlockHash>> {
+        let client = self.client();
+        let height = self.block_height().await?;
+        anyhow::ensure!(
+            0 < depth && depth <= height,
+            "Can't reorg {depth} blocks of a chain at height {height}"
+        );
+
+        let fork_height = height - depth + 1;
+        let orphaned = (fork_height..=height)
+            .map(|height| client.get_block_hash(height))
+            .collect::<Result<Vec<_>, _>>()?;
+        client.invalidate_block(&orphaned[0])?;
+        self.mine_blocks(new_blocks).await?;
+
+        info!(
+            LOG_DEVIMINT,
+            "Reorged {depth} blocks at height {fork_height}, chain is now at height {}",
+            self.block_height().await?
+        );
+        Ok(orphaned)
+    }
+
     pub async fn send_to(&self, addr: String, amt: u64) -> Result<bitcoin::Txid> {
         let amt = bitcoin::Amount::from_sat(amt);
         let tx = self.client().send_to_address(
@@ -113,17 +198,42 @@ impl Bitcoind {
     }
 }
 
+/// Port of the first lightningd's p2p listener, see `cfg/lightningd.conf`
+const CLN_P2P_PORT: u16 = 9000;
+/// Port of the first lightningd's gateway extension
+const CLN_EXTENSION_PORT: u16 = 8177;
+
 #[derive(Clone)]
 pub struct Lightningd {
     pub(crate) rpc: Arc<Mutex<ClnRpc>>,
     pub(crate) process: ProcessHandle,
     pub(crate) bitcoind: Bitcoind,
+    pub(crate) instance: usize,
 }
 
 impl Lightningd {
     pub async fn new(process_mgr: &ProcessManager, bitcoind: Bitcoind) -> Result<Self> {
-        let cln_dir = &process_mgr.globals.FM_CLN_DIR;
-        let process = Lightningd::start(process_mgr, cln_dir).await?;
+        Self::new_instance(process_mgr, bitcoind, 0).await
+    }
+
+    /// Starts an additional lightningd with its own directory and ports,
+    /// instance 0 is the default one
+    pub async fn new_instance(
+        process_mgr: &ProcessManager,
+        bitcoind: Bitcoind,
+        instance: usize,
+    ) -> Result<Self> {
+        let cln_dir = instance_dir(&process_mgr.globals.FM_CLN_DIR, instance).await?;
+        if instance != 0 {
+            let p2p_addr = format!("127.0.0.1:{}", instance_port(CLN_P2P_PORT, instance));
+            write_overwrite_async(
+                cln_dir.join("config"),
+                include_str!("cfg/lightningd.conf")
+                    .replace(&format!("127.0.0.1:{CLN_P2P_PORT}"), &p2p_addr),
+            )
+            .await?;
+        }
+        let process = Lightningd::start(process_mgr, &cln_dir, instance).await?;
 
         let socket_cln = cln_dir.join("regtest/lightning-rpc");
         poll("lightningd", || async {
@@ -135,23 +245,35 @@ impl Lightningd {
             bitcoind,
             rpc: Arc::new(Mutex::new(rpc)),
             process,
+            instance,
         })
     }
 
-    pub async fn start(process_mgr: &ProcessManager, cln_dir: &Path) -> Result<ProcessHandle> {
+    pub async fn start(
+        process_mgr: &ProcessManager,
+        cln_dir: &Path,
+        instance: usize,
+    ) -> Result<ProcessHandle> {
         let extension_path = cmd!("which", "gateway-cln-extension")
             .out_string()
             .await
             .context("gateway-cln-extension not on path")?;
+        let extension_port = instance_port(CLN_EXTENSION_PORT, instance);
         let cmd = cmd!(
             "lightningd",
             "--dev-fast-gossip",
             "--dev-bitcoind-poll=1",
             format!("--lightning-dir={}", utf8(cln_dir)),
             "--plugin={extension_path}"
+        )
+        .env(
+            "FM_CLN_EXTENSION_LISTEN_ADDRESS",
+            format!("0.0.0.0:{extension_port}"),
         );
 
-        process_mgr.spawn_daemon("lightningd", cmd).await
+        process_mgr
+            .spawn_daemon(&instance_name("lightningd", instance), cmd)
+            .await
     }
 
     pub async fn request<R: cln_rpc::model::IntoRequest>(&self, request: R) -> Result<R::Response>
@@ -183,43 +305,89 @@ impl Lightningd {
             .to_string())
     }
 
+    /// Address of the gateway extension for `gatewayd` to connect to
+    pub fn extension_addr(&self) -> String {
+        format!(
+            "http://localhost:{}",
+            instance_port(CLN_EXTENSION_PORT, self.instance)
+        )
+    }
+
+    pub fn p2p_port(&self) -> u16 {
+        instance_port(CLN_P2P_PORT, self.instance)
+    }
+
     pub async fn kill(self) -> Result<()> {
         self.process.kill().await
     }
 }
 
+/// Ports of the first lnd, see `cfg/lnd.conf`
+const LND_P2P_PORT: u16 = 9734;
+const LND_RPC_PORT: u16 = 11009;
+const LND_REST_PORT: u16 = 8180;
+
 #[derive(Clone)]
 pub struct Lnd {
     pub(crate) client: Arc<Mutex<tonic_lnd::LndClient>>,
     pub(crate) process: ProcessHandle,
     pub(crate) _bitcoind: Bitcoind,
+    pub(crate) instance: usize,
+    pub(crate) dir: PathBuf,
 }
 
 impl Lnd {
     pub async fn new(process_mgr: &ProcessManager, bitcoind: Bitcoind) -> Result<Self> {
-        let (process, client) = Lnd::start(process_mgr).await?;
+        Self::new_instance(process_mgr, bitcoind, 0).await
+    }
+
+    /// Starts an additional lnd with its own directory and ports, instance 0
+    /// is the default one
+    pub async fn new_instance(
+        process_mgr: &ProcessManager,
+        bitcoind: Bitcoind,
+        instance: usize,
+    ) -> Result<Self> {
+        let dir = instance_dir(&process_mgr.globals.FM_LND_DIR, instance).await?;
+        if instance != 0 {
+            let conf = [LND_P2P_PORT, LND_RPC_PORT, LND_REST_PORT]
+                .into_iter()
+                .fold(include_str!("cfg/lnd.conf").to_owned(), |conf, port| {
+                    conf.replace(
+                        &format!(":{port}"),
+                        &format!(":{}", instance_port(port, instance)),
+                    )
+                });
+            write_overwrite_async(dir.join("lnd.conf"), conf).await?;
+        }
+        let (process, client) = Lnd::start(process_mgr, &dir, instance).await?;
         let this = Self {
             _bitcoind: bitcoind,
             client: Arc::new(Mutex::new(client)),
             process,
+            instance,
+            dir,
         };
         // wait for lnd rpc to be active
         poll("lnd", || async { Ok(this.pub_key().await.is_ok()) }).await?;
         Ok(this)
     }
 
-    pub async fn start(process_mgr: &ProcessManager) -> Result<(ProcessHandle, LndClient)> {
-        let cmd = cmd!(
-            "lnd",
-            format!("--lnddir={}", utf8(&process_mgr.globals.FM_LND_DIR))
-        );
+    pub async fn start(
+        process_mgr: &ProcessManager,
+        dir: &Path,
+        instance: usize,
+    ) -> Result<(ProcessHandle, LndClient)> {
+        let cmd = cmd!("lnd", format!("--lnddir={}", utf8(dir)));
 
-        let process = process_mgr.spawn_daemon("lnd", cmd).await?;
-        let lnd_rpc_addr = &process_mgr.globals.FM_LND_RPC_ADDR;
-        let lnd_macaroon = &process_mgr.globals.FM_LND_MACAROON;
-        let lnd_tls_cert = &process_mgr.globals.FM_LND_TLS_CERT;
+        let process = process_mgr
+            .spawn_daemon(&instance_name("lnd", instance), cmd)
+            .await?;
+        let lnd_rpc_addr = Self::rpc_addr_of(instance);
+        let lnd_macaroon = Self::macaroon_of(dir);
+        let lnd_tls_cert = Self::tls_cert_of(dir);
         poll("lnd", || async {
-            Ok(fs::try_exists(lnd_tls_cert).await? && fs::try_exists(lnd_macaroon).await?)
+            Ok(fs::try_exists(&lnd_tls_cert).await? && fs::try_exists(&lnd_macaroon).await?)
         })
         .await?;
 
@@ -243,6 +411,34 @@ impl Lnd {
         Ok((process, client))
     }
 
+    fn rpc_addr_of(instance: usize) -> String {
+        format!("http://localhost:{}", instance_port(LND_RPC_PORT, instance))
+    }
+
+    fn tls_cert_of(dir: &Path) -> PathBuf {
+        dir.join("tls.cert")
+    }
+
+    fn macaroon_of(dir: &Path) -> PathBuf {
+        dir.join("data/chain/bitcoin/regtest/admin.macaroon")
+    }
+
+    pub fn rpc_addr(&self) -> String {
+        Self::rpc_addr_of(self.instance)
+    }
+
+    pub fn tls_cert(&self) -> PathBuf {
+        Self::tls_cert_of(&self.dir)
+    }
+
+    pub fn macaroon(&self) -> PathBuf {
+        Self::macaroon_of(&self.dir)
+    }
+
+    pub fn p2p_port(&self) -> u16 {
+        instance_port(LND_P2P_PORT, self.instance)
+    }
+
     pub async fn client_lock(&self) -> Result<MappedMutexGuard<'_, tonic_lnd::LightningClient>> {
         let guard = self.client.lock().await;
         Ok(MutexGuard::map(guard, |client| client.lightning()))
@@ -278,60 +474,43 @@ impl Lnd {
 }
 
 pub async fn open_channel(bitcoind: &Bitcoind, cln: &Lightningd, lnd: &Lnd) -> Result<()> {
-    tokio::try_join!(cln.await_block_processing(), lnd.await_block_processing())?;
+    open_channel_between(
+        bitcoind,
+        &LightningNode::Cln(cln.clone()),
+        &LightningNode::Lnd(lnd.clone()),
+        10_000_000,
+        5_000_000,
+    )
+    .await
+}
+
+/// Opens a channel funded by `from` and pushes `push_sats` of it to `to`
+pub async fn open_channel_between(
+    bitcoind: &Bitcoind,
+    from: &LightningNode,
+    to: &LightningNode,
+    capacity_sats: u64,
+    push_sats: u64,
+) -> Result<()> {
+    tokio::try_join!(from.await_block_processing(), to.await_block_processing())?;
     info!(LOG_DEVIMINT, "block sync done");
-    let cln_addr = cln
-        .request(cln_rpc::model::NewaddrRequest { addresstype: None })
-        .await?
-        .bech32
-        .context("bech32 should be present")?;
+    let from_addr = from.new_address().await?;
 
-    bitcoind.send_to(cln_addr, 100_000_000).await?;
+    bitcoind.send_to(from_addr, capacity_sats * 10).await?;
     bitcoind.mine_blocks(10).await?;
 
-    let lnd_pubkey = lnd.pub_key().await?;
-
-    cln.request(cln_rpc::model::ConnectRequest {
-        id: lnd_pubkey.parse()?,
-        host: Some("127.0.0.1".to_owned()),
-        port: Some(9734),
-    })
-    .await?;
+    let to_pubkey = to.pub_key().await?;
+    from.connect(&to_pubkey, to.p2p_port()).await?;
 
     poll("fund channel", || async {
-        Ok(cln
-            .request(cln_rpc::model::FundchannelRequest {
-                id: lnd_pubkey.parse()?,
-                amount: cln_rpc::primitives::AmountOrAll::Amount(
-                    cln_rpc::primitives::Amount::from_sat(10_000_000),
-                ),
-                push_msat: Some(cln_rpc::primitives::Amount::from_sat(5_000_000)),
-                feerate: None,
-                announce: None,
-                minconf: None,
-                close_to: None,
-                request_amt: None,
-                compact_lease: None,
-                utxos: None,
-                mindepth: None,
-                reserve: None,
-            })
+        Ok(from
+            .fund_channel(&to_pubkey, capacity_sats, push_sats)
             .await
             .is_ok())
     })
     .await?;
 
-    poll("list peers", || async {
-        Ok(!cln
-            .request(cln_rpc::model::ListpeersRequest {
-                id: Some(lnd_pubkey.parse()?),
-                level: None,
-            })
-            .await?
-            .peers
-            .is_empty())
-    })
-    .await?;
+    poll("list peers", || async { from.has_peer(&to_pubkey).await }).await?;
     bitcoind.mine_blocks(10).await?;
     Ok(())
 }
@@ -349,9 +528,294 @@ impl LightningNode {
             LightningNode::Lnd(_) => LightningNodeName::Lnd,
         }
     }
+
+    pub fn instance(&self) -> usize {
+        match self {
+            LightningNode::Cln(cln) => cln.instance,
+            LightningNode::Lnd(lnd) => lnd.instance,
+        }
+    }
+
+    pub async fn pub_key(&self) -> Result<String> {
+        match self {
+            LightningNode::Cln(cln) => cln.pub_key().await,
+            LightningNode::Lnd(lnd) => lnd.pub_key().await,
+        }
+    }
+
+    pub fn p2p_port(&self) -> u16 {
+        match self {
+            LightningNode::Cln(cln) => cln.p2p_port(),
+            LightningNode::Lnd(lnd) => lnd.p2p_port(),
+        }
+    }
+
+    pub async fn await_block_processing(&self) -> Result<()> {
+        match self {
+            LightningNode::Cln(cln) => cln.await_block_processing().await,
+            LightningNode::Lnd(lnd) => lnd.await_block_processing().await,
+        }
+    }
+
+    async fn new_address(&self) -> Result<String> {
+        match self {
+            LightningNode::Cln(cln) => cln
+                .request(cln_rpc::model::NewaddrRequest { addresstype: None })
+                .await?
+                .bech32
+                .context("bech32 should be present"),
+            LightningNode::Lnd(lnd) => Ok(lnd
+                .client_lock()
+                .await?
+                .new_address(tonic_lnd::lnrpc::NewAddressRequest {
+                    ..Default::default()
+                })
+                .await?
+                .into_inner()
+                .address),
+        }
+    }
+
+    async fn connect(&self, pubkey: &str, port: u16) -> Result<()> {
+        match self {
+            LightningNode::Cln(cln) => {
+                cln.request(cln_rpc::model::ConnectRequest {
+                    id: pubkey.parse()?,
+                    host: Some("127.0.0.1".to_owned()),
+                    port: Some(port),
+                })
+                .await?;
+            }
+            LightningNode::Lnd(lnd) => {
+                lnd.client_lock()
+                    .await?
+                    .connect_peer(tonic_lnd::lnrpc::ConnectPeerRequest {
+                        addr: Some(tonic_lnd::lnrpc::LightningAddress {
+                            pubkey: pubkey.to_owned(),
+                            host: format!("127.0.0.1:{port}"),
+                        }),
+                        ..Default::default()
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn fund_channel(&self, pubkey: &str, capacity_sats: u64, push_sats: u64) -> Result<()> {
+        match self {
+            LightningNode::Cln(cln) => {
+                cln.request(cln_rpc::model::FundchannelRequest {
+                    id: pubkey.parse()?,
+                    amount: cln_rpc::primitives::AmountOrAll::Amount(
+                        cln_rpc::primitives::Amount::from_sat(capacity_sats),
+                    ),
+                    push_msat: Some(cln_rpc::primitives::Amount::from_sat(push_sats)),
+                    feerate: None,
+                    announce: None,
+                    minconf: None,
+                    close_to: None,
+                    request_amt: None,
+                    compact_lease: None,
+                    utxos: None,
+                    mindepth: None,
+                    reserve: None,
+                })
+                .await?;
+            }
+            LightningNode::Lnd(lnd) => {
+                lnd.client_lock()
+                    .await?
+                    .open_channel_sync(tonic_lnd::lnrpc::OpenChannelRequest {
+                        node_pubkey: Vec::from_hex(pubkey)?,
+                        local_funding_amount: capacity_sats as i64,
+                        push_sat: push_sats as i64,
+                        ..Default::default()
+                    })
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates an invoice for `amount_msat` that expires after `expiry`,
+    /// `label` has to be unique on the node
+    pub async fn create_invoice(
+        &self,
+        amount_msat: u64,
+        label: &str,
+        expiry: Duration,
+    ) -> Result<NodeInvoice> {
+        match self {
+            LightningNode::Cln(cln) => {
+                let bolt11 = cln
+                    .request(cln_rpc::model::InvoiceRequest {
+                        amount_msat: cln_rpc::primitives::AmountOrAny::Amount(
+                            cln_rpc::primitives::Amount::from_msat(amount_msat),
+                        ),
+                        description: label.to_owned(),
+                        label: label.to_owned(),
+                        expiry: Some(expiry.as_secs()),
+                        fallbacks: None,
+                        preimage: None,
+                        exposeprivatechannels: None,
+                        cltv: None,
+                        deschashonly: None,
+                    })
+                    .await?
+                    .bolt11;
+                Ok(NodeInvoice {
+                    bolt11,
+                    id: NodeInvoiceId::Cln {
+                        label: label.to_owned(),
+                    },
+                })
+            }
+            LightningNode::Lnd(lnd) => {
+                let invoice = lnd
+                    .client_lock()
+                    .await?
+                    .add_invoice(tonic_lnd::lnrpc::Invoice {
+                        memo: label.to_owned(),
+                        value_msat: amount_msat as i64,
+                        expiry: expiry.as_secs() as i64,
+                        ..Default::default()
+                    })
+                    .await?
+                    .into_inner();
+                Ok(NodeInvoice {
+                    bolt11: invoice.payment_request,
+                    id: NodeInvoiceId::Lnd {
+                        r_hash: invoice.r_hash,
+                    },
+                })
+            }
+        }
+    }
+
+    /// Whether `invoice` created by this node was paid
+    pub async fn invoice_paid(&self, invoice: &NodeInvoice) -> Result<bool> {
+        match (self, &invoice.id) {
+            (LightningNode::Cln(cln), NodeInvoiceId::Cln { label }) => Ok(cln
+                .request(cln_rpc::model::ListinvoicesRequest {
+                    label: Some(label.clone()),
+                    invstring: None,
+                    payment_hash: None,
+                    offer_id: None,
+                })
+                .await?
+                .invoices
+                .iter()
+                .any(|invoice| {
+                    matches!(
+                        invoice.status,
+                        cln_rpc::model::ListinvoicesInvoicesStatus::PAID
+                    )
+                })),
+            (LightningNode::Lnd(lnd), NodeInvoiceId::Lnd { r_hash }) => Ok(lnd
+                .client_lock()
+                .await?
+                .lookup_invoice(tonic_lnd::lnrpc::PaymentHash {
+                    r_hash: r_hash.clone(),
+                    ..Default::default()
+                })
+                .await?
+                .into_inner()
+                .state()
+                == tonic_lnd::lnrpc::invoice::InvoiceState::Settled),
+            _ => Err(anyhow::anyhow!(
+                "Invoice {} wasn't created by this node",
+                invoice.bolt11
+            )),
+        }
+    }
+
+    /// Pays `bolt11`, fails unless the payment succeeded
+    pub async fn pay_invoice(&self, bolt11: String) -> Result<()> {
+        match self {
+            LightningNode::Cln(cln) => {
+                let status = cln
+                    .request(cln_rpc::model::PayRequest {
+                        bolt11,
+                        amount_msat: None,
+                        label: None,
+                        riskfactor: None,
+                        maxfeepercent: None,
+                        retry_for: None,
+                        maxdelay: None,
+                        exemptfee: None,
+                        localinvreqid: None,
+                        exclude: None,
+                        maxfee: None,
+                        description: None,
+                    })
+                    .await?
+                    .status;
+                anyhow::ensure!(
+                    matches!(status, cln_rpc::model::PayStatus::COMPLETE),
+                    "CLN payment didn't complete: {status:?}"
+                );
+            }
+            LightningNode::Lnd(lnd) => {
+                let response = lnd
+                    .client_lock()
+                    .await?
+                    .send_payment_sync(tonic_lnd::lnrpc::SendRequest {
+                        payment_request: bolt11,
+                        ..Default::default()
+                    })
+                    .await?
+                    .into_inner();
+                anyhow::ensure!(
+                    response.payment_error.is_empty(),
+                    "LND payment failed: {}",
+                    response.payment_error
+                );
+            }
+        }
+        Ok(())
+    }
+
+    async fn has_peer(&self, pubkey: &str) -> Result<bool> {
+        match self {
+            LightningNode::Cln(cln) => Ok(!cln
+                .request(cln_rpc::model::ListpeersRequest {
+                    id: Some(pubkey.parse()?),
+                    level: None,
+                })
+                .await?
+                .peers
+                .is_empty()),
+            LightningNode::Lnd(lnd) => Ok(lnd
+                .client_lock()
+                .await?
+                .list_peers(tonic_lnd::lnrpc::ListPeersRequest {
+                    ..Default::default()
+                })
+                .await?
+                .into_inner()
+                .peers
+                .iter()
+                .any(|peer| peer.pub_key == pubkey)),
+        }
+    }
+}
+
+/// An invoice created with [`LightningNode::create_invoice`]
+#[derive(Debug, Clone)]
+pub struct NodeInvoice {
+    pub bolt11: String,
+    id: NodeInvoiceId,
+}
+
+/// How the node that created an invoice looks it up
+#[derive(Debug, Clone)]
+enum NodeInvoiceId {
+    Cln { label: String },
+    Lnd { r_hash: Vec<u8> },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LightningNodeName {
     Cln,
     Lnd,
@@ -0,0 +1,303 @@
+//! Fault injection for the connections of a federation
+//!
+//! Peers don't connect to each other directly. Every peer dials the other
+//! peers and its bitcoin backend through a TCP proxy run by devimint, which
+//! can delay the traffic of a link or cut it entirely while tests observe
+//! how the federation recovers.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
+use fedimint_core::PeerId;
+use fedimint_logging::LOG_DEVIMINT;
+use fedimint_server::config::ServerConfig;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tracing::debug;
+
+use crate::federation::BASE_PORT;
+use crate::vars::{self, instance_port};
+
+/// Proxies listen on ports after the ones used by the peers themselves
+const PROXY_BASE_PORT: u16 = BASE_PORT + 500;
+
+/// A connection that faults can be injected into
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Link {
+    /// P2P connection between two peers, the lower id first
+    Peers(usize, usize),
+    /// Connection of a peer to the bitcoin backend
+    Bitcoind,
+}
+
+impl Link {
+    fn peers(a: usize, b: usize) -> Self {
+        Link::Peers(a.min(b), a.max(b))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct Faults {
+    latency: BTreeMap<Link, Duration>,
+    cut: BTreeSet<Link>,
+}
+
+/// Port peer `from` dials to reach peer `to`
+fn peer_proxy_port(instance: usize, servers: usize, from: usize, to: usize) -> u16 {
+    instance_port(PROXY_BASE_PORT, instance) + 1 + (from * servers + to) as u16
+}
+
+fn bitcoind_proxy_port(instance: usize) -> u16 {
+    instance_port(PROXY_BASE_PORT, instance)
+}
+
+/// Bitcoin RPC config of the federation `instance` connecting through the
+/// proxy of its [`FaultInjector`]
+pub(crate) fn proxied_bitcoin_rpc(instance: usize) -> Result<BitcoinRpcConfig> {
+    let mut bitcoin_rpc = BitcoinRpcConfig::from_env_vars()?;
+    bitcoin_rpc
+        .url
+        .set_port(Some(bitcoind_proxy_port(instance)))
+        .map_err(|_| anyhow::anyhow!("Bitcoin RPC url can't have a port"))?;
+    Ok(bitcoin_rpc)
+}
+
+/// Makes the peers of the federation `instance` dial each other through the
+/// proxies of its [`FaultInjector`]
+pub(crate) fn proxy_p2p_endpoints(
+    instance: usize,
+    configs: &mut BTreeMap<PeerId, ServerConfig>,
+) -> Result<()> {
+    let servers = configs.len();
+    for (peer, cfg) in configs.iter_mut() {
+        for (other, endpoint) in cfg.local.p2p_endpoints.iter_mut() {
+            if other == peer {
+                continue;
+            }
+            let port = peer_proxy_port(instance, servers, peer.to_usize(), other.to_usize());
+            endpoint
+                .url
+                .set_port(Some(port))
+                .map_err(|_| anyhow::anyhow!("P2P url can't have a port"))?;
+        }
+    }
+    Ok(())
+}
+
+/// Runs the proxies the configs of a federation were set up to connect
+/// through, they are shut down when this is dropped
+pub struct FaultInjector {
+    faults: watch::Sender<Faults>,
+    proxies: Vec<JoinHandle<()>>,
+    servers: usize,
+}
+
+impl FaultInjector {
+    pub async fn start(
+        instance: usize,
+        members: &BTreeMap<usize, vars::Fedimintd>,
+    ) -> Result<Self> {
+        let (faults, _) = watch::channel(Faults::default());
+        let mut proxies = vec![];
+
+        let bitcoin_rpc = BitcoinRpcConfig::from_env_vars()?;
+        let bitcoind_target = format!(
+            "{}:{}",
+            bitcoin_rpc
+                .url
+                .host_str()
+                .context("Bitcoin RPC url has no host")?,
+            bitcoin_rpc
+                .url
+                .port_or_known_default()
+                .context("Bitcoin RPC url has no port")?
+        );
+        proxies.push(
+            spawn_proxy(
+                bitcoind_proxy_port(instance),
+                bitcoind_target,
+                Link::Bitcoind,
+                faults.subscribe(),
+            )
+            .await?,
+        );
+
+        let servers = members.len();
+        for from in members.keys() {
+            for (to, vars) in members {
+                if from == to {
+                    continue;
+                }
+                proxies.push(
+                    spawn_proxy(
+                        peer_proxy_port(instance, servers, *from, *to),
+                        vars.FM_BIND_P2P.clone(),
+                        Link::peers(*from, *to),
+                        faults.subscribe(),
+                    )
+                    .await?,
+                );
+            }
+        }
+
+        Ok(Self {
+            faults,
+            proxies,
+            servers,
+        })
+    }
+
+    /// Delays all traffic between the peers `a` and `b` by `latency`
+    pub fn set_latency(&self, a: usize, b: usize, latency: Duration) {
+        self.faults.send_modify(|faults| {
+            faults.latency.insert(Link::peers(a, b), latency);
+        });
+    }
+
+    /// Cuts all connections between the peers in `side` and the other peers
+    pub fn partition(&self, side: &[usize]) {
+        self.faults.send_modify(|faults| {
+            for a in side {
+                for b in (0..self.servers).filter(|peer| !side.contains(peer)) {
+                    faults.cut.insert(Link::peers(*a, b));
+                }
+            }
+        });
+    }
+
+    /// Makes the bitcoin backend unreachable for all peers
+    pub fn set_bitcoind_outage(&self, outage: bool) {
+        self.faults.send_modify(|faults| {
+            if outage {
+                faults.cut.insert(Link::Bitcoind);
+            } else {
+                faults.cut.remove(&Link::Bitcoind);
+            }
+        });
+    }
+
+    /// Removes all faults
+    pub fn heal(&self) {
+        self.faults.send_replace(Faults::default());
+    }
+}
+
+impl Drop for FaultInjector {
+    fn drop(&mut self) {
+        for proxy in &self.proxies {
+            proxy.abort();
+        }
+    }
+}
+
+async fn spawn_proxy(
+    port: u16,
+    target: String,
+    link: Link,
+    faults: watch::Receiver<Faults>,
+) -> Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .await
+        .with_context(|| format!("Could not bind proxy on port {port}"))?;
+
+    Ok(tokio::spawn(async move {
+        loop {
+            let inbound = match listener.accept().await {
+                Ok((inbound, _)) => inbound,
+                Err(e) => {
+                    debug!(LOG_DEVIMINT, "proxy for {link:?} failed to accept: {e}");
+                    continue;
+                }
+            };
+            if faults.borrow().cut.contains(&link) {
+                continue;
+            }
+
+            let target = target.clone();
+            let faults = faults.clone();
+            tokio::spawn(async move {
+                if let Err(e) = proxy_connection(inbound, &target, link, faults).await {
+                    debug!(LOG_DEVIMINT, "proxy connection for {link:?} closed: {e}");
+                }
+            });
+        }
+    }))
+}
+
+async fn proxy_connection(
+    inbound: TcpStream,
+    target: &str,
+    link: Link,
+    faults: watch::Receiver<Faults>,
+) -> Result<()> {
+    let outbound = TcpStream::connect(target).await?;
+    let (inbound_read, inbound_write) = inbound.into_split();
+    let (outbound_read, outbound_write) = outbound.into_split();
+
+    tokio::select! {
+        res = forward(inbound_read, outbound_write, link, faults.clone()) => res,
+        res = forward(outbound_read, inbound_write, link, faults.clone()) => res,
+        () = await_cut(link, faults) => Ok(()),
+    }
+}
+
+/// Returns once `link` gets cut or the [`FaultInjector`] is dropped
+async fn await_cut(link: Link, mut faults: watch::Receiver<Faults>) {
+    loop {
+        if faults.borrow_and_update().cut.contains(&link) {
+            return;
+        }
+        if faults.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Copies from `reader` to `writer`, delaying every chunk by the latency of
+/// `link` at the time it was read
+async fn forward(
+    mut reader: OwnedReadHalf,
+    mut writer: OwnedWriteHalf,
+    link: Link,
+    faults: watch::Receiver<Faults>,
+) -> Result<()> {
+    let (sender, mut receiver) = mpsc::unbounded_channel::<(Instant, Vec<u8>)>();
+
+    let read = async move {
+        let mut buf = vec![0; 8192];
+        loop {
+            let len = reader.read(&mut buf).await?;
+            if len == 0 {
+                return anyhow::Ok(());
+            }
+            let latency = faults
+                .borrow()
+                .latency
+                .get(&link)
+                .copied()
+                .unwrap_or_default();
+            if sender
+                .send((Instant::now() + latency, buf[..len].to_vec()))
+                .is_err()
+            {
+                return Ok(());
+            }
+        }
+    };
+    let write = async move {
+        while let Some((deadline, bytes)) = receiver.recv().await {
+            tokio::time::sleep_until(deadline).await;
+            writer.write_all(&bytes).await?;
+        }
+        anyhow::Ok(())
+    };
+
+    tokio::try_join!(read, write)?;
+    Ok(())
+}
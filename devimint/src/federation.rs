@@ -3,8 +3,6 @@ use std::collections::BTreeMap;
 use anyhow::{anyhow, Context};
 use bitcoincore_rpc::bitcoin::Network;
 use fedimint_aead::random_salt;
-use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
-use fedimint_core::core::LEGACY_HARDCODED_INSTANCE_ID_WALLET;
 use fedimint_core::db::mem_impl::MemDatabase;
 use fedimint_core::util::write_new;
 use fedimint_core::{Amount, PeerId};
@@ -12,11 +10,13 @@ use fedimint_server::config::io::{write_server_config, PLAINTEXT_PASSWORD, SALT_
 use fedimint_server::config::ServerConfig;
 use fedimint_testing::federation::local_config_gen_params;
 use fedimint_wallet_client::config::WalletClientConfig;
-use fedimintd::attach_default_module_gen_params;
 use fedimintd::fedimintd::Fedimintd as FedimintBuilder;
+use fedimintd::{attach_default_module_gen_params, default_mint_amounts};
 use tokio::fs;
 
 use super::*; // TODO: remove this
+use crate::faults::{proxied_bitcoin_rpc, proxy_p2p_endpoints, FaultInjector};
+use crate::vars::{instance_dir, instance_port};
 
 pub struct Federation {
     // client is only for internal use, use cli commands instead
@@ -24,6 +24,9 @@ pub struct Federation {
     members: BTreeMap<usize, Fedimintd>,
     vars: BTreeMap<usize, vars::Fedimintd>,
     bitcoind: Bitcoind,
+    instance: usize,
+    cfg_dir: PathBuf,
+    faults: FaultInjector,
 }
 
 impl Federation {
@@ -32,16 +35,28 @@ impl Federation {
         bitcoind: Bitcoind,
         vars: BTreeMap<usize, vars::Fedimintd>,
     ) -> Result<Self> {
+        Self::new_instance(process_mgr, bitcoind, 0, vars).await
+    }
+
+    /// Starts the peers of an additional federation set up by
+    /// [`run_config_gen`] for `instance`, instance 0 is the default one
+    pub async fn new_instance(
+        process_mgr: &ProcessManager,
+        bitcoind: Bitcoind,
+        instance: usize,
+        vars: BTreeMap<usize, vars::Fedimintd>,
+    ) -> Result<Self> {
+        let faults = FaultInjector::start(instance, &vars).await?;
         let mut members = BTreeMap::new();
         for (peer, var) in &vars {
             members.insert(
                 *peer,
-                Fedimintd::new(process_mgr, bitcoind.clone(), *peer, var).await?,
+                Fedimintd::new(process_mgr, bitcoind.clone(), instance, *peer, var).await?,
             );
         }
 
-        let workdir: PathBuf = env::var("FM_DATA_DIR")?.parse()?;
-        let cfg_path = workdir.join("client.json");
+        let cfg_dir = instance_dir(&process_mgr.globals.FM_DATA_DIR, instance).await?;
+        let cfg_path = cfg_dir.join("client.json");
         let cfg: UserClientConfig = load_from_file(&cfg_path)?;
         let decoders = module_decode_stubs();
         let db = Database::new(MemDatabase::new(), module_decode_stubs());
@@ -56,6 +71,9 @@ impl Federation {
             vars,
             bitcoind,
             client: Arc::new(client),
+            instance,
+            cfg_dir,
+            faults,
         })
     }
 
@@ -65,7 +83,14 @@ impl Federation {
         }
         self.members.insert(
             peer,
-            Fedimintd::new(process_mgr, self.bitcoind.clone(), peer, &self.vars[&peer]).await?,
+            Fedimintd::new(
+                process_mgr,
+                self.bitcoind.clone(),
+                self.instance,
+                peer,
+                &self.vars[&peer],
+            )
+            .await?,
         );
         Ok(())
     }
@@ -78,8 +103,52 @@ impl Federation {
         Ok(())
     }
 
+    /// Kills and restarts `peer` `cycles` times, keeping it down for
+    /// `downtime` and up for `uptime` each cycle
+    pub async fn crash_loop(
+        &mut self,
+        process_mgr: &ProcessManager,
+        peer: usize,
+        cycles: usize,
+        downtime: Duration,
+        uptime: Duration,
+    ) -> Result<()> {
+        for cycle in 0..cycles {
+            info!(
+                LOG_DEVIMINT,
+                "crash loop of fedimintd-{peer}: cycle {cycle}"
+            );
+            self.kill_server(peer).await?;
+            fedimint_core::task::sleep(downtime).await;
+            self.start_server(process_mgr, peer).await?;
+            fedimint_core::task::sleep(uptime).await;
+        }
+        Ok(())
+    }
+
+    /// Delays all traffic between the peers `a` and `b` by `latency`
+    pub fn set_latency(&self, a: usize, b: usize, latency: Duration) {
+        self.faults.set_latency(a, b, latency);
+    }
+
+    /// Cuts all connections between the peers in `side` and the other peers
+    /// until [`Federation::heal_network`] is called
+    pub fn partition(&self, side: &[usize]) {
+        self.faults.partition(side);
+    }
+
+    /// Makes bitcoind unreachable for all peers while `outage` is set
+    pub fn set_bitcoind_outage(&self, outage: bool) {
+        self.faults.set_bitcoind_outage(outage);
+    }
+
+    /// Removes all injected latency, partitions and outages
+    pub fn heal_network(&self) {
+        self.faults.heal();
+    }
+
     pub async fn cmd(&self) -> Command {
-        let cfg_dir = env::var("FM_DATA_DIR").unwrap();
+        let cfg_dir = utf8(&self.cfg_dir);
         cmd!("fedimint-cli", "--data-dir={cfg_dir}")
     }
 
@@ -127,11 +196,11 @@ impl Federation {
     }
 
     pub async fn await_block_sync(&self) -> Result<()> {
-        let wallet_cfg: WalletClientConfig = self
+        let (_, wallet_cfg) = self
             .client
             .config()
             .0
-            .get_module(LEGACY_HARDCODED_INSTANCE_ID_WALLET)?;
+            .get_first_module_by_kind::<WalletClientConfig>(fedimint_wallet_client::KIND)?;
         let finality_delay = wallet_cfg.finality_delay;
         let btc_height = self.bitcoind.client().get_blockchain_info()?.blocks;
         let expected = btc_height - (finality_delay as u64);
@@ -141,27 +210,27 @@ impl Federation {
         Ok(())
     }
 
-    pub async fn await_gateways_registered(&self) -> Result<()> {
+    pub async fn await_gateways_registered(&self, num_gateways: usize) -> Result<()> {
         poll("gateways registered", || async {
             Ok(cmd!(self, "list-gateways")
                 .out_json()
                 .await?
                 .as_array()
-                .map_or(false, |x| x.len() == 2))
+                .map_or(false, |x| x.len() == num_gateways))
         })
         .await?;
         Ok(())
     }
 
     pub async fn await_all_peers(&self) -> Result<()> {
-        cmd!(
-            self,
-            "dev",
-            "api",
-            "module_{LEGACY_HARDCODED_INSTANCE_ID_WALLET}_block_height"
-        )
-        .run()
-        .await?;
+        let (wallet_id, _) = self
+            .client
+            .config()
+            .0
+            .get_first_module_by_kind_cfg(fedimint_wallet_client::KIND)?;
+        cmd!(self, "dev", "api", "module_{wallet_id}_block_height")
+            .run()
+            .await?;
         Ok(())
     }
 
@@ -202,15 +271,17 @@ impl Fedimintd {
     pub async fn new(
         process_mgr: &ProcessManager,
         bitcoind: Bitcoind,
+        instance: usize,
         peer_id: usize,
         env: &vars::Fedimintd,
     ) -> Result<Self> {
-        info!("fedimintd-{peer_id} started");
+        let name = match instance {
+            0 => format!("fedimintd-{peer_id}"),
+            instance => format!("fedimintd-{instance}-{peer_id}"),
+        };
+        info!("{name} started");
         let process = process_mgr
-            .spawn_daemon(
-                &format!("fedimintd-{peer_id}"),
-                cmd!("fedimintd").envs(env.vars()),
-            )
+            .spawn_daemon(&name, cmd!("fedimintd").envs(env.vars()))
             .await?;
 
         Ok(Self {
@@ -226,30 +297,40 @@ impl Fedimintd {
 }
 
 /// Base port for devimint
-const BASE_PORT: u16 = 8173 + 10000;
+pub(crate) const BASE_PORT: u16 = 8173 + 10000;
 
+/// Generates the configs of the federation `instance`, additional instances
+/// use their own config directory and ports
 pub async fn run_config_gen(
     process_mgr: &ProcessManager,
+    instance: usize,
     servers: usize,
     write_password: bool,
 ) -> Result<BTreeMap<usize, vars::Fedimintd>> {
     // TODO: Use proper builder
     let mut fed = FedimintBuilder::new()?.with_default_modules();
     attach_default_module_gen_params(
-        BitcoinRpcConfig::from_env_vars()?,
+        proxied_bitcoin_rpc(instance)?,
+        None,
         &mut fed.server_gen_params,
-        Amount::from_sats(100_000_000),
+        default_mint_amounts(Amount::from_sats(100_000_000)),
         Network::Regtest,
         10,
+        false,
+        None,
     );
 
     let peers: Vec<_> = (0..servers).map(|id| PeerId::from(id as u16)).collect();
-    let params = local_config_gen_params(&peers, BASE_PORT, fed.server_gen_params.clone())?;
-    let configs = ServerConfig::trusted_dealer_gen(&params, fed.server_gens.clone());
+    let base_port = instance_port(BASE_PORT, instance);
+    let params = local_config_gen_params(&peers, base_port, fed.server_gen_params.clone())?;
+    let mut configs = ServerConfig::trusted_dealer_gen(&params, fed.server_gens.clone());
+    proxy_p2p_endpoints(instance, &mut configs)?;
+    let cfg_dir = instance_dir(&process_mgr.globals.FM_DATA_DIR, instance).await?;
     let mut fedimintd_envs = BTreeMap::new();
     for (peer, cfg) in configs {
-        let bind_metrics_api = format!("127.0.0.1:{}", 3000 + peer.to_usize());
-        let envs = vars::Fedimintd::init(&process_mgr.globals, &cfg, bind_metrics_api).await?;
+        let metrics_port = instance_port(3000, instance) + peer.to_usize() as u16;
+        let bind_metrics_api = format!("127.0.0.1:{metrics_port}");
+        let envs = vars::Fedimintd::init(&cfg_dir, &cfg, bind_metrics_api).await?;
         let password = cfg.private.api_auth.0.clone();
         let data_dir = envs.FM_DATA_DIR.clone();
         fedimintd_envs.insert(peer.to_usize(), envs);
@@ -261,9 +342,8 @@ pub async fn run_config_gen(
     }
 
     let out_dir = &fedimintd_envs[&0].FM_DATA_DIR;
-    let cfg_dir = &process_mgr.globals.FM_DATA_DIR;
     let out_dir = utf8(out_dir);
-    let cfg_dir = utf8(cfg_dir);
+    let cfg_dir = utf8(&cfg_dir);
     // copy configs to config directory
     fs::rename(
         format!("{out_dir}/client-connect"),
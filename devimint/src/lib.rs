@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 use std::env;
 use std::future::Future;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -13,6 +13,8 @@ use fedimint_client_legacy::modules::mint::MintClientGen;
 use fedimint_client_legacy::{module_decode_stubs, UserClient, UserClientConfig};
 use fedimint_core::config::load_from_file;
 use fedimint_core::db::Database;
+use fedimint_core::task::TaskGroup;
+use fedimint_core::util::write_overwrite_async;
 use fedimint_ln_client::LightningClientGen;
 use fedimint_logging::LOG_DEVIMINT;
 use fedimint_wallet_client::WalletClientGen;
@@ -22,15 +24,20 @@ use tracing::info;
 pub mod util;
 pub mod vars;
 use util::*;
-use vars::utf8;
+use vars::{instance_name, instance_port, utf8};
 
 mod external;
 pub use external::{
-    external_daemons, open_channel, Bitcoind, Electrs, Esplora, ExternalDaemons, LightningNode,
-    Lightningd, Lnd,
+    external_daemons, open_channel, open_channel_between, Bitcoind, Electrs, Esplora,
+    ExternalDaemons, LightningNode, LightningNodeName, Lightningd, Lnd, NodeInvoice,
 };
 
+pub mod conformance;
+pub mod faults;
 pub mod federation;
+pub mod scenario;
+pub mod soak;
+pub mod topology;
 
 pub struct DevFed {
     pub bitcoind: Bitcoind,
@@ -44,49 +51,60 @@ pub struct DevFed {
     pub faucet: Faucet,
 }
 
+/// Port of the first gateway's API, gateways of additional instances of the
+/// same lightning implementation are offset from it
+const GATEWAY_CLN_PORT: u16 = 8175;
+const GATEWAY_LND_PORT: u16 = 28175;
+
 #[derive(Clone)]
 pub struct Gatewayd {
     _process: ProcessHandle,
     pub ln: Option<LightningNode>,
+    api_addr: String,
 }
 
 impl Gatewayd {
+    /// Starts a gateway for `ln`, every lightning node can only have a single
+    /// gateway
     pub async fn new(process_mgr: &ProcessManager, ln: LightningNode) -> Result<Self> {
         let ln_name = ln.name();
-        let test_dir = &process_mgr.globals.FM_TEST_DIR;
-        let gateway_env: HashMap<String, String> = match ln {
-            LightningNode::Cln(_) => HashMap::from_iter([
-                (
-                    "FM_GATEWAY_DATA_DIR".to_owned(),
-                    format!("{}/gw-cln", utf8(test_dir)),
-                ),
-                (
-                    "FM_GATEWAY_LISTEN_ADDR".to_owned(),
-                    "127.0.0.1:8175".to_owned(),
-                ),
-                (
-                    "FM_GATEWAY_API_ADDR".to_owned(),
-                    "http://127.0.0.1:8175".to_owned(),
-                ),
-            ]),
-            LightningNode::Lnd(_) => HashMap::from_iter([
-                (
-                    "FM_GATEWAY_DATA_DIR".to_owned(),
-                    format!("{}/gw-lnd", utf8(test_dir)),
-                ),
-                (
-                    "FM_GATEWAY_LISTEN_ADDR".to_owned(),
-                    "127.0.0.1:28175".to_owned(),
-                ),
-                (
-                    "FM_GATEWAY_API_ADDR".to_owned(),
-                    "http://127.0.0.1:28175".to_owned(),
-                ),
-            ]),
+        let instance = ln.instance();
+        let name = instance_name(&format!("gw-{ln_name}"), instance);
+        let port = match ln {
+            LightningNode::Cln(_) => instance_port(GATEWAY_CLN_PORT, instance),
+            LightningNode::Lnd(_) => instance_port(GATEWAY_LND_PORT, instance),
         };
+        let api_addr = format!("http://127.0.0.1:{port}");
+        let mut gateway_env: HashMap<String, String> = HashMap::from_iter([
+            (
+                "FM_GATEWAY_DATA_DIR".to_owned(),
+                format!("{}/{name}", utf8(&process_mgr.globals.FM_TEST_DIR)),
+            ),
+            (
+                "FM_GATEWAY_LISTEN_ADDR".to_owned(),
+                format!("127.0.0.1:{port}"),
+            ),
+            ("FM_GATEWAY_API_ADDR".to_owned(), api_addr.clone()),
+        ]);
+        match &ln {
+            LightningNode::Cln(cln) => {
+                gateway_env.insert("FM_GATEWAY_LIGHTNING_ADDR".to_owned(), cln.extension_addr());
+            }
+            LightningNode::Lnd(lnd) => {
+                gateway_env.insert("FM_LND_RPC_ADDR".to_owned(), lnd.rpc_addr());
+                gateway_env.insert(
+                    "FM_LND_TLS_CERT".to_owned(),
+                    utf8(&lnd.tls_cert()).to_owned(),
+                );
+                gateway_env.insert(
+                    "FM_LND_MACAROON".to_owned(),
+                    utf8(&lnd.macaroon()).to_owned(),
+                );
+            }
+        }
         let process = process_mgr
             .spawn_daemon(
-                &format!("gatewayd-{ln_name}"),
+                &instance_name(&format!("gatewayd-{ln_name}"), instance),
                 cmd!("gatewayd", ln_name).envs(gateway_env),
             )
             .await?;
@@ -94,6 +112,7 @@ impl Gatewayd {
         Ok(Self {
             ln: Some(ln),
             _process: process,
+            api_addr,
         })
     }
 
@@ -112,22 +131,15 @@ impl Gatewayd {
     }
 
     pub async fn cmd(&self) -> Command {
-        match &self.ln {
-            Some(LightningNode::Cln(_)) => {
-                cmd!("gateway-cli", "--rpcpassword=theresnosecondbest")
-            }
-            Some(LightningNode::Lnd(_)) => {
-                cmd!(
-                    "gateway-cli",
-                    "--rpcpassword=theresnosecondbest",
-                    "-a",
-                    "http://127.0.0.1:28175"
-                )
-            }
-            None => {
-                panic!("Cannot execute command when gateway is disconnected from Lightning Node");
-            }
+        if self.ln.is_none() {
+            panic!("Cannot execute command when gateway is disconnected from Lightning Node");
         }
+        cmd!(
+            "gateway-cli",
+            "--rpcpassword=theresnosecondbest",
+            "-a",
+            &self.api_addr
+        )
     }
 
     pub async fn gateway_pub_key(&self) -> Result<String> {
@@ -184,6 +196,38 @@ impl Faucet {
     }
 }
 
+/// Creates the test directory, sets up logging to it and exports the
+/// environment the daemons and CLIs expect
+pub async fn setup(test_dir: &Path, fed_size: usize) -> Result<(ProcessManager, TaskGroup)> {
+    use std::fmt::Write;
+
+    let globals = vars::Global::new(test_dir, fed_size).await?;
+    let log_file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .append(true)
+        .open(globals.FM_LOGS_DIR.join("devimint.log"))
+        .await?
+        .into_std()
+        .await;
+
+    fedimint_logging::TracingSetup::default()
+        .with_file(Some(log_file))
+        .init()?;
+
+    let mut env_string = String::new();
+    for (var, value) in globals.vars() {
+        writeln!(env_string, r#"export {var}="{value}""#)?; // hope that value doesn't contain a "
+        std::env::set_var(var, value);
+    }
+    write_overwrite_async(globals.FM_TEST_DIR.join("env"), env_string).await?;
+    info!("Test setup in {:?}", globals.FM_DATA_DIR);
+    let process_mgr = ProcessManager::new(globals);
+    let task_group = TaskGroup::new();
+    task_group.install_kill_handler();
+    Ok((process_mgr, task_group))
+}
+
 pub async fn dev_fed(process_mgr: &ProcessManager) -> Result<DevFed> {
     let start_time = fedimint_core::time::now();
     let bitcoind = Bitcoind::new(process_mgr).await?;
@@ -207,14 +251,14 @@ pub async fn dev_fed(process_mgr: &ProcessManager) -> Result<DevFed> {
         Esplora::new(process_mgr, bitcoind.clone()),
         async {
             let fed_size = process_mgr.globals.FM_FED_SIZE;
-            let members = run_config_gen(process_mgr, fed_size, true).await?;
+            let members = run_config_gen(process_mgr, 0, fed_size, true).await?;
             info!(LOG_DEVIMINT, "config gen done");
             Federation::new(process_mgr, bitcoind.clone(), members).await
         },
     )?;
     info!(LOG_DEVIMINT, "federation and gateways started");
     tokio::try_join!(gw_cln.connect_fed(&fed), gw_lnd.connect_fed(&fed))?;
-    fed.await_gateways_registered().await?;
+    fed.await_gateways_registered(2).await?;
     info!(LOG_DEVIMINT, "gateways registered");
     fed.use_gateway(&gw_cln).await?;
     info!(
@@ -8,7 +8,11 @@ use bitcoincore_rpc::bitcoin::hashes::hex::ToHex;
 use bitcoincore_rpc::bitcoin::Txid;
 use clap::{Parser, Subcommand};
 use cln_rpc::primitives::{Amount as ClnRpcAmount, AmountOrAny};
+use devimint::conformance::gateway_conformance_test;
+use devimint::faults::FaultInjector;
 use devimint::federation::{run_config_gen, Federation, Fedimintd};
+use devimint::soak::{soak_test, SoakConfig};
+use devimint::topology::{topology_fed, GatewaySpec, Topology, TopologyFed};
 use devimint::util::{poll, poll_value, ProcessManager};
 use devimint::{
     cmd, dev_fed, external_daemons, vars, Bitcoind, DevFed, LightningNode, Lightningd, Lnd,
@@ -191,6 +195,53 @@ async fn cli_tests(dev_fed: DevFed) -> Result<()> {
         .out_json()
         .await?;
 
+    info!("Testing guardian operations with admin client");
+    let epoch_count = cmd!(fed, "dev", "epoch-count").out_json().await?["count"]
+        .as_u64()
+        .unwrap();
+    cmd!(fed, "admin", "trigger-epoch")
+        .env("FM_PASSWORD", "pass0")
+        .env("FM_OUR_ID", "0")
+        .run()
+        .await?;
+    poll("triggered epoch", || async {
+        let count = cmd!(fed, "dev", "epoch-count").out_json().await?["count"]
+            .as_u64()
+            .unwrap();
+        Ok(count > epoch_count)
+    })
+    .await?;
+    let peers = cmd!(fed, "admin", "peers")
+        .env("FM_PASSWORD", "pass0")
+        .env("FM_OUR_ID", "0")
+        .out_json()
+        .await?;
+    anyhow::ensure!(
+        peers.as_object().map_or(false, |peers| !peers.is_empty()),
+        "admin peers returned no guardians"
+    );
+    let audit = cmd!(fed, "admin", "audit")
+        .env("FM_PASSWORD", "pass0")
+        .env("FM_OUR_ID", "0")
+        .out_json()
+        .await?;
+    anyhow::ensure!(
+        audit["net_assets_msat"]
+            .as_i64()
+            .map_or(false, |net| net >= 0),
+        "admin audit reported negative net assets"
+    );
+    cmd!(fed, "admin", "pending-peg-outs")
+        .env("FM_PASSWORD", "pass0")
+        .env("FM_OUR_ID", "0")
+        .run()
+        .await?;
+    cmd!(fed, "admin", "wallet-resync", "--from-height=0")
+        .env("FM_PASSWORD", "pass0")
+        .env("FM_OUR_ID", "0")
+        .run()
+        .await?;
+
     let plaintext_one =
         fs::read_to_string(format!("{data_dir}/server-0/config-plaintext.json")).await?;
     let plaintext_two =
@@ -212,6 +263,13 @@ async fn cli_tests(dev_fed: DevFed) -> Result<()> {
     let connect_info = cmd!(fed, "dev", "decode-connect-info", connect_string.clone())
         .out_json()
         .await?;
+    let api_endpoints = connect_info["api_endpoints"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .map(|(peer_id, url)| format!("{peer_id}={}", url.as_str().unwrap()))
+        .collect::<Vec<_>>()
+        .join(",");
     anyhow::ensure!(
         cmd!(
             fed,
@@ -222,7 +280,8 @@ async fn cli_tests(dev_fed: DevFed) -> Result<()> {
                 "--download-token={}",
                 connect_info["download_token"].as_str().unwrap()
             ),
-            "--id={fed_id}"
+            "--id={fed_id}",
+            "--api-endpoint={api_endpoints}"
         )
         .out_json()
         .await?["connect_info"]
@@ -965,6 +1024,165 @@ async fn reconnect_test(dev_fed: DevFed, process_mgr: &ProcessManager) -> Result
     Ok(())
 }
 
+async fn fault_injection_test(dev_fed: DevFed, process_mgr: &ProcessManager) -> Result<()> {
+    #[allow(unused_variables)]
+    let DevFed {
+        bitcoind,
+        cln,
+        lnd,
+        mut fed,
+        gw_cln,
+        gw_lnd,
+        electrs,
+        esplora,
+        faucet,
+    } = dev_fed;
+
+    bitcoind.mine_blocks(110).await?;
+    fed.await_block_sync().await?;
+    fed.await_all_peers().await?;
+
+    // a partitioned minority doesn't stop consensus and catches up once healed
+    fed.partition(&[3]);
+    fed.generate_epochs(5).await?;
+    fed.heal_network();
+    fed.await_all_peers().await?;
+    info!(LOG_DEVIMINT, "Federation survived a partitioned peer");
+
+    // latency between peers slows consensus down but doesn't stop it
+    fed.set_latency(0, 1, Duration::from_millis(500));
+    fed.set_latency(2, 3, Duration::from_millis(500));
+    fed.generate_epochs(5).await?;
+    fed.heal_network();
+    info!(LOG_DEVIMINT, "Federation survived latency between peers");
+
+    // blocks mined during a bitcoind outage are picked up afterwards
+    fed.set_bitcoind_outage(true);
+    bitcoind.mine_blocks(10).await?;
+    fedimint_core::task::sleep(Duration::from_secs(5)).await;
+    fed.set_bitcoind_outage(false);
+    fed.await_block_sync().await?;
+    info!(LOG_DEVIMINT, "Federation survived a bitcoind outage");
+
+    // a crash looping peer doesn't stop consensus and rejoins in the end
+    fed.crash_loop(
+        process_mgr,
+        3,
+        3,
+        Duration::from_secs(2),
+        Duration::from_secs(5),
+    )
+    .await?;
+    fed.generate_epochs(5).await?;
+    fed.await_all_peers().await?;
+
+    info!(LOG_DEVIMINT, "fm success: fault-injection-test");
+    Ok(())
+}
+
+async fn reorg_test(dev_fed: DevFed) -> Result<()> {
+    #[allow(unused_variables)]
+    let DevFed {
+        bitcoind,
+        cln,
+        lnd,
+        fed,
+        gw_cln,
+        gw_lnd,
+        electrs,
+        esplora,
+        faucet,
+    } = dev_fed;
+
+    let start_height = bitcoind.block_height().await? + 110;
+    bitcoind.mine_to_height(start_height).await?;
+    fed.await_block_sync().await?;
+    anyhow::ensure!(
+        bitcoind.block_height().await? == start_height,
+        "Mined to the wrong height"
+    );
+
+    // reorgs shallower than the finality delay are never seen by consensus
+    let orphaned = bitcoind.reorg(5, 8).await?;
+    anyhow::ensure!(orphaned.len() == 5, "Expected 5 orphaned blocks");
+    fed.await_block_sync().await?;
+    fed.pegin(10_000).await?;
+    info!(LOG_DEVIMINT, "Federation survived a shallow reorg");
+
+    // block timestamps follow the mock time
+    let tip_time = bitcoind.tip_time().await?;
+    bitcoind
+        .advance_time(Duration::from_secs(24 * 60 * 60))
+        .await?;
+    anyhow::ensure!(
+        bitcoind.tip_time().await? == tip_time + 24 * 60 * 60,
+        "Tip timestamp didn't advance by a day"
+    );
+    bitcoind
+        .mine_blocks_with_interval(10, Duration::from_secs(600))
+        .await?;
+    fed.await_block_sync().await?;
+    bitcoind.set_mock_time(0).await?;
+
+    info!(LOG_DEVIMINT, "fm success: reorg-test");
+    Ok(())
+}
+
+/// Pays an invoice of one federation's client from another federation, routed
+/// over a channel between the gateways' lightning nodes
+async fn cross_federation_test(process_mgr: &ProcessManager) -> Result<()> {
+    let topology = Topology {
+        federations: 2,
+        gateways: vec![
+            GatewaySpec {
+                lightning_node: 0,
+                federations: vec![0, 1],
+            },
+            GatewaySpec {
+                lightning_node: 1,
+                federations: vec![0, 1],
+            },
+        ],
+        ..Topology::dev_fed()
+    };
+    let TopologyFed { feds, gateways, .. } = topology_fed(process_mgr, &topology).await?;
+    let (fed_a, fed_b) = (&feds[0], &feds[1]);
+    let (gw_cln, gw_lnd) = (&gateways[0], &gateways[1]);
+
+    fed_a.pegin(10_000).await?;
+    fed_b.pegin_gateway(20_000, gw_lnd).await?;
+    fed_a.use_gateway(gw_cln).await?;
+    fed_b.use_gateway(gw_lnd).await?;
+
+    info!("Testing payment from federation A to federation B");
+    let initial_balance_b = fed_b.client_balance().await?;
+    let ln_response_val = cmd!(
+        fed_b,
+        "ln-invoice",
+        "--amount=1000msat",
+        "--description='cross-federation'"
+    )
+    .out_json()
+    .await?;
+    let ln_invoice_response: LnInvoiceResponse = serde_json::from_value(ln_response_val)?;
+    cmd!(fed_a, "ln-pay", ln_invoice_response.invoice)
+        .run()
+        .await?;
+    cmd!(fed_b, "wait-invoice", ln_invoice_response.operation_id)
+        .run()
+        .await?;
+
+    let final_balance_b = fed_b.client_balance().await?;
+    anyhow::ensure!(
+        final_balance_b - initial_balance_b == 1000,
+        "Client balance of federation B changed by {}, expected 1000",
+        final_balance_b - initial_balance_b
+    );
+
+    info!(LOG_DEVIMINT, "cross_federation_test: success");
+    Ok(())
+}
+
 #[derive(Subcommand)]
 enum Cmd {
     ExternalDaemons,
@@ -975,6 +1193,24 @@ enum Cmd {
     CliTests,
     LoadTestToolTest,
     LightningReconnectTest,
+    CrossFederationTest,
+    FaultInjectionTest,
+    ReorgTest,
+    /// Runs the same payment scenarios against the gateway of every lightning
+    /// backend and compares the outcomes
+    GatewayConformanceTest,
+    /// Performs random operations for a long time while restarting peers
+    SoakTest {
+        /// Seed of a failed run to replay, random if not set
+        #[clap(long)]
+        seed: Option<u64>,
+        /// How long to run in seconds
+        #[clap(long, default_value = "3600")]
+        duration_secs: u64,
+        /// Number of clients transacting with each other
+        #[clap(long, default_value = "4")]
+        clients: usize,
+    },
     #[clap(flatten)]
     Rpc(RpcCmd),
 }
@@ -1014,12 +1250,14 @@ async fn write_ready_file<T>(global: &vars::Global, result: Result<T>) -> Result
 async fn run_ui(process_mgr: &ProcessManager, task_group: &TaskGroup) -> Result<()> {
     let bitcoind = Bitcoind::new(process_mgr).await?;
     let fed_size = process_mgr.globals.FM_FED_SIZE;
-    let members = run_config_gen(process_mgr, fed_size, false).await?;
+    let members = run_config_gen(process_mgr, 0, fed_size, false).await?;
+    // peers connect to each other through the proxies, don't drop them either
+    let _faults = FaultInjector::start(0, &members).await?;
     // don't drop fedimintds
     let _fedimintds = futures::future::try_join_all(members.into_iter().map(|(peer, vars)| {
         let bitcoind = bitcoind.clone();
         async move {
-            let fm = Fedimintd::new(process_mgr, bitcoind.clone(), peer, &vars).await?;
+            let fm = Fedimintd::new(process_mgr, bitcoind.clone(), 0, peer, &vars).await?;
             let server_addr = &vars.FM_BIND_API;
 
             poll("waiting for ui/api startup", || async {
@@ -1036,37 +1274,12 @@ async fn run_ui(process_mgr: &ProcessManager, task_group: &TaskGroup) -> Result<
     Ok(())
 }
 
-use std::fmt::Write;
 use std::str::FromStr;
 
 use fedimint_core::encoding::Decodable;
 
 async fn setup(arg: CommonArgs) -> Result<(ProcessManager, TaskGroup)> {
-    let globals = vars::Global::new(&arg.test_dir, arg.fed_size).await?;
-    let log_file = fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .append(true)
-        .open(globals.FM_LOGS_DIR.join("devimint.log"))
-        .await?
-        .into_std()
-        .await;
-
-    fedimint_logging::TracingSetup::default()
-        .with_file(Some(log_file))
-        .init()?;
-
-    let mut env_string = String::new();
-    for (var, value) in globals.vars() {
-        writeln!(env_string, r#"export {var}="{value}""#)?; // hope that value doesn't contain a "
-        std::env::set_var(var, value);
-    }
-    write_overwrite_async(globals.FM_TEST_DIR.join("env"), env_string).await?;
-    info!("Test setup in {:?}", globals.FM_DATA_DIR);
-    let process_mgr = ProcessManager::new(globals);
-    let task_group = TaskGroup::new();
-    task_group.install_kill_handler();
-    Ok((process_mgr, task_group))
+    devimint::setup(&arg.test_dir, arg.fed_size).await
 }
 
 #[tokio::main]
@@ -1118,6 +1331,39 @@ async fn main() -> Result<()> {
             let dev_fed = dev_fed(&process_mgr).await?;
             lightning_gw_reconnect_test(dev_fed, &process_mgr).await?;
         }
+        Cmd::CrossFederationTest => {
+            let (process_mgr, _) = setup(args.common).await?;
+            cross_federation_test(&process_mgr).await?;
+        }
+        Cmd::FaultInjectionTest => {
+            let (process_mgr, _) = setup(args.common).await?;
+            let dev_fed = dev_fed(&process_mgr).await?;
+            fault_injection_test(dev_fed, &process_mgr).await?;
+        }
+        Cmd::ReorgTest => {
+            let (process_mgr, _) = setup(args.common).await?;
+            let dev_fed = dev_fed(&process_mgr).await?;
+            reorg_test(dev_fed).await?;
+        }
+        Cmd::GatewayConformanceTest => {
+            let (process_mgr, _) = setup(args.common).await?;
+            let dev_fed = dev_fed(&process_mgr).await?;
+            gateway_conformance_test(dev_fed).await?;
+        }
+        Cmd::SoakTest {
+            seed,
+            duration_secs,
+            clients,
+        } => {
+            let (process_mgr, _) = setup(args.common).await?;
+            let dev_fed = dev_fed(&process_mgr).await?;
+            let config = SoakConfig {
+                seed: seed.unwrap_or_else(rand::random),
+                duration: Duration::from_secs(duration_secs),
+                clients,
+            };
+            soak_test(dev_fed, &process_mgr, config).await?;
+        }
         Cmd::Rpc(rpc) => rpc_command(rpc, args.common).await?,
     }
     Ok(())
@@ -0,0 +1,229 @@
+//! Scripted end-to-end tests against real daemons
+//!
+//! Module authors depend on devimint as a library and describe a test as a
+//! [`Scenario`], a sequence of named steps run against a [`DevFed`]. Common
+//! steps and balance assertions are provided, anything else is a custom step.
+//! When a step fails the end of every daemon log is printed, so the output of
+//! a CI run shows what the daemons were doing at the time.
+//!
+//! ```ignore
+//! let (process_mgr, _) = devimint::setup(&test_dir, 4).await?;
+//! Scenario::new("deposit and pay")
+//!     .pegin(10_000)
+//!     .expect_client_balance(10_000_000)
+//!     .step("pay invoice", |dev_fed| {
+//!         Box::pin(async move { cmd!(dev_fed.fed, "ln-pay", INVOICE).run().await })
+//!     })
+//!     .run_dev_fed(&process_mgr)
+//!     .await?;
+//! ```
+
+use std::env;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use fedimint_logging::LOG_DEVIMINT;
+use futures::future::LocalBoxFuture;
+use tokio::fs;
+use tracing::{error, info};
+
+use crate::external::LightningNodeName;
+use crate::util::{poll_value, ProcessManager};
+use crate::{dev_fed, DevFed, Gatewayd};
+
+/// How long balance assertions wait for the expected balance, payments and
+/// deposits take a few epochs to settle
+const ASSERTION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Number of lines printed from the end of every log when a step failed
+const LOG_TAIL_LINES: usize = 50;
+
+type StepFn = Box<dyn for<'a> FnOnce(&'a DevFed) -> LocalBoxFuture<'a, Result<()>>>;
+
+/// A named sequence of steps, run in order until one fails
+pub struct Scenario {
+    name: String,
+    steps: Vec<(String, StepFn)>,
+}
+
+impl Scenario {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            steps: vec![],
+        }
+    }
+
+    /// Adds a custom step
+    pub fn step<F>(mut self, name: impl Into<String>, step: F) -> Self
+    where
+        F: for<'a> FnOnce(&'a DevFed) -> LocalBoxFuture<'a, Result<()>> + 'static,
+    {
+        self.steps.push((name.into(), Box::new(step)));
+        self
+    }
+
+    /// Appends the steps of `other`, so common sequences can be shared between
+    /// scenarios
+    pub fn then(mut self, other: Scenario) -> Self {
+        self.steps.extend(other.steps);
+        self
+    }
+
+    /// Deposits `amount_sats` into the federation client
+    pub fn pegin(self, amount_sats: u64) -> Self {
+        self.step(format!("peg in {amount_sats} sats"), move |dev_fed| {
+            Box::pin(dev_fed.fed.pegin(amount_sats))
+        })
+    }
+
+    /// Deposits `amount_sats` into the federation for the gateway `gateway`
+    pub fn pegin_gateway(self, amount_sats: u64, gateway: LightningNodeName) -> Self {
+        self.step(
+            format!("peg in {amount_sats} sats for the {gateway} gateway"),
+            move |dev_fed| {
+                Box::pin(
+                    dev_fed
+                        .fed
+                        .pegin_gateway(amount_sats, dev_fed.gateway(gateway)),
+                )
+            },
+        )
+    }
+
+    /// Mines `blocks` blocks and waits for the federation to sync them
+    pub fn mine_blocks(self, blocks: u64) -> Self {
+        self.step(format!("mine {blocks} blocks"), move |dev_fed| {
+            Box::pin(async move {
+                dev_fed.bitcoind.mine_blocks(blocks).await?;
+                dev_fed.fed.await_block_sync().await
+            })
+        })
+    }
+
+    /// Waits until the federation client holds exactly `msats`
+    pub fn expect_client_balance(self, msats: u64) -> Self {
+        self.step(
+            format!("expect client balance {msats} msat"),
+            move |dev_fed| {
+                Box::pin(expect_balance("client", msats, move || {
+                    dev_fed.fed.client_balance()
+                }))
+            },
+        )
+    }
+
+    /// Waits until the gateway `gateway` holds exactly `msats` in the
+    /// federation
+    pub fn expect_gateway_balance(self, gateway: LightningNodeName, msats: u64) -> Self {
+        self.step(
+            format!("expect {gateway} gateway balance {msats} msat"),
+            move |dev_fed| {
+                Box::pin(expect_balance("gateway", msats, move || async move {
+                    let fed_id = dev_fed.fed.federation_id().await;
+                    cmd!(
+                        dev_fed.gateway(gateway),
+                        "balance",
+                        "--federation-id={fed_id}"
+                    )
+                    .out_json()
+                    .await?
+                    .as_u64()
+                    .context("balance must be a number")
+                }))
+            },
+        )
+    }
+
+    /// Runs the steps against `dev_fed`, printing the end of all daemon logs
+    /// if one fails
+    pub async fn run(self, dev_fed: &DevFed) -> Result<()> {
+        let scenario = self.name;
+        let start_time = fedimint_core::time::now();
+        let steps = self.steps.len();
+        for (idx, (step, run_step)) in self.steps.into_iter().enumerate() {
+            info!(LOG_DEVIMINT, %scenario, "Step {}/{steps}: {step}", idx + 1);
+            if let Err(e) = run_step(dev_fed).await {
+                error!(LOG_DEVIMINT, %scenario, %step, ?e, "Scenario step failed");
+                let logs_dir = env::var("FM_LOGS_DIR").unwrap_or_default();
+                if let Err(log_error) = print_log_tails(Path::new(&logs_dir)).await {
+                    error!(LOG_DEVIMINT, ?log_error, "Unable to print the daemon logs");
+                }
+                return Err(e.context(format!("Scenario {scenario} failed at step {step}")));
+            }
+        }
+        info!(
+            LOG_DEVIMINT,
+            %scenario,
+            "Scenario succeeded in {:?}",
+            start_time.elapsed()?
+        );
+        Ok(())
+    }
+
+    /// Starts the daemons of [`dev_fed`] and runs the steps against them
+    pub async fn run_dev_fed(self, process_mgr: &ProcessManager) -> Result<()> {
+        let dev_fed = dev_fed(process_mgr).await?;
+        self.run(&dev_fed).await
+    }
+}
+
+impl DevFed {
+    /// The gateway connected to the lightning node `name`
+    pub fn gateway(&self, name: LightningNodeName) -> &Gatewayd {
+        match name {
+            LightningNodeName::Cln => &self.gw_cln,
+            LightningNodeName::Lnd => &self.gw_lnd,
+        }
+    }
+}
+
+/// Polls `balance` until it returns `expected` or [`ASSERTION_TIMEOUT`]
+/// passed
+async fn expect_balance<F, Fut>(owner: &str, expected: u64, balance: F) -> Result<()>
+where
+    F: Fn() -> Fut,
+    Fut: std::future::Future<Output = Result<u64>>,
+{
+    let result = tokio::time::timeout(
+        ASSERTION_TIMEOUT,
+        poll_value(&format!("{owner} balance"), || async {
+            let current = balance().await?;
+            Ok((current == expected).then_some(current))
+        }),
+    )
+    .await;
+    match result {
+        Ok(balance) => balance.map(|_| ()),
+        Err(_) => {
+            let actual = balance().await?;
+            bail!(
+                "Expected {owner} balance of {expected} msat, was {actual} msat after \
+                 {ASSERTION_TIMEOUT:?}"
+            )
+        }
+    }
+}
+
+/// Prints the last [`LOG_TAIL_LINES`] lines of every log in `logs_dir`
+pub(crate) async fn print_log_tails(logs_dir: &Path) -> Result<()> {
+    let mut logs = vec![];
+    let mut entries = fs::read_dir(logs_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if entry.path().extension().map_or(false, |ext| ext == "log") {
+            logs.push(entry.path());
+        }
+    }
+    logs.sort();
+
+    for log in logs {
+        let content = fs::read_to_string(&log).await?;
+        let lines = content.lines().collect::<Vec<_>>();
+        eprintln!("==> {} <==", log.display());
+        for line in &lines[lines.len().saturating_sub(LOG_TAIL_LINES)..] {
+            eprintln!("{line}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,319 @@
+//! Long-running randomized tests of a federation
+//!
+//! The soak test performs random peg-ins, peg-outs, lightning payments and
+//! e-cash transfers between several clients for hours while restarting
+//! random peers. All choices are derived from a seed, so a failure can be
+//! replayed by passing the seed from the failure report again. The timing of
+//! the daemons isn't deterministic though, so a replay isn't guaranteed to
+//! fail the same way.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context, Result};
+use fedimint_cli::LnInvoiceResponse;
+use fedimint_core::util::write_overwrite_async;
+use fedimint_logging::LOG_DEVIMINT;
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+use tokio::fs;
+use tracing::{error, info};
+
+use crate::federation::Federation;
+use crate::scenario::print_log_tails;
+use crate::util::{poll_value, Command, ProcessManager};
+use crate::{Bitcoind, DevFed};
+
+/// Operations that don't finish in time are considered stuck
+const OPERATION_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Invariants are checked after this many operations and at the end
+const INVARIANT_CHECK_INTERVAL: usize = 10;
+
+/// Chance of killing a random peer after an operation, it is brought back up
+/// after the next operation
+const PEER_RESTART_PROBABILITY: f64 = 0.05;
+
+/// Blocks mined after a deposit, enough to pass the finality delay
+const DEPOSIT_CONFIRMATIONS: u64 = 21;
+
+#[derive(Debug, Clone)]
+pub struct SoakConfig {
+    /// Seed all random choices are derived from
+    pub seed: u64,
+    /// How long to keep performing operations
+    pub duration: Duration,
+    /// Number of clients transacting with each other
+    pub clients: usize,
+}
+
+/// A `fedimint-cli` client with its own data directory
+struct SoakClient {
+    name: String,
+    data_dir: PathBuf,
+}
+
+impl SoakClient {
+    async fn join(
+        name: String,
+        data_dir: PathBuf,
+        connect_string: &str,
+        gateway_pub_key: &str,
+    ) -> Result<Self> {
+        fs::create_dir_all(&data_dir).await?;
+        let client = Self { name, data_dir };
+        cmd!(client, "join-federation", connect_string)
+            .run()
+            .await?;
+        cmd!(client, "switch-gateway", gateway_pub_key)
+            .run()
+            .await?;
+        Ok(client)
+    }
+
+    async fn cmd(&self) -> Command {
+        let data_dir = self.data_dir.display();
+        cmd!("fedimint-cli", "--data-dir={data_dir}")
+    }
+
+    async fn balance(&self) -> Result<u64> {
+        cmd!(self, "info").out_json().await?["total_msat"]
+            .as_u64()
+            .context("total_msat must be a number")
+    }
+}
+
+/// The randomly chosen actions
+#[derive(Debug, Clone, Copy)]
+enum Operation {
+    PegIn,
+    PegOut,
+    LightningPayment,
+    EcashTransfer,
+}
+
+const OPERATIONS: [Operation; 4] = [
+    Operation::PegIn,
+    Operation::PegOut,
+    Operation::LightningPayment,
+    Operation::EcashTransfer,
+];
+
+/// Runs the soak test until `config.duration` passed or an invariant is
+/// violated, in the latter case a report is written to `FM_TEST_DIR`
+pub async fn soak_test(
+    dev_fed: DevFed,
+    process_mgr: &ProcessManager,
+    config: SoakConfig,
+) -> Result<()> {
+    let DevFed {
+        bitcoind,
+        mut fed,
+        gw_cln,
+        ..
+    } = dev_fed;
+    info!(LOG_DEVIMINT, seed = config.seed, "Starting soak test");
+
+    let globals = &process_mgr.globals;
+    let connect_string = fs::read_to_string(globals.FM_DATA_DIR.join("client-connect")).await?;
+    let gateway_pub_key = gw_cln.gateway_pub_key().await?;
+    let mut clients = vec![];
+    for idx in 0..config.clients {
+        let name = format!("soak-{idx}");
+        let data_dir = globals.FM_DATA_DIR.join(&name);
+        clients.push(SoakClient::join(name, data_dir, &connect_string, &gateway_pub_key).await?);
+    }
+    // Consensus needs all but `max_evil` peers
+    let max_evil = (globals.FM_FED_SIZE - 1) / 3;
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let mut history = vec![];
+    let start_time = fedimint_core::time::now();
+    let result = async {
+        let mut down_peer = None;
+        let mut iteration = 0;
+        while start_time.elapsed()? < config.duration {
+            iteration += 1;
+            let operation = *OPERATIONS.choose(&mut rng).expect("not empty");
+            let description = run_operation(&mut rng, &bitcoind, &clients, operation).await?;
+            info!(LOG_DEVIMINT, iteration, "{description}");
+            history.push(description);
+
+            if let Some(peer) = down_peer.take() {
+                history.push(format!("start peer {peer}"));
+                fed.start_server(process_mgr, peer).await?;
+            } else if max_evil > 0 && rng.gen_bool(PEER_RESTART_PROBABILITY) {
+                let peer = rng.gen_range(0..globals.FM_FED_SIZE);
+                history.push(format!("kill peer {peer}"));
+                fed.kill_server(peer).await?;
+                down_peer = Some(peer);
+            }
+
+            if iteration % INVARIANT_CHECK_INTERVAL == 0 {
+                check_invariants(&fed, down_peer).await?;
+            }
+        }
+        if let Some(peer) = down_peer {
+            fed.start_server(process_mgr, peer).await?;
+        }
+        check_invariants(&fed, None).await
+    }
+    .await;
+
+    if let Err(e) = result {
+        let report = json!({
+            "seed": config.seed,
+            "history_len": history.len(),
+            "error": format!("{e:?}"),
+            "history": history,
+        });
+        let report_path = globals.FM_TEST_DIR.join("soak-report.json");
+        write_overwrite_async(&report_path, serde_json::to_string_pretty(&report)?).await?;
+        error!(
+            LOG_DEVIMINT,
+            seed = config.seed,
+            report = %report_path.display(),
+            "Soak test failed"
+        );
+        if let Err(log_error) = print_log_tails(&globals.FM_LOGS_DIR).await {
+            error!(LOG_DEVIMINT, ?log_error, "Unable to print the daemon logs");
+        }
+        return Err(e.context(format!("Soak test with seed {} failed", config.seed)));
+    }
+
+    info!(
+        LOG_DEVIMINT,
+        seed = config.seed,
+        steps = history.len(),
+        "fm success: soak-test"
+    );
+    Ok(())
+}
+
+/// Performs `operation` with random clients and amounts, falls back to a
+/// peg-in if the chosen client can't afford it
+async fn run_operation(
+    rng: &mut StdRng,
+    bitcoind: &Bitcoind,
+    clients: &[SoakClient],
+    operation: Operation,
+) -> Result<String> {
+    let sender = clients.choose(rng).expect("at least one client");
+    let receiver = clients.choose(rng).expect("at least one client");
+    let balance = sender.balance().await?;
+
+    let run = async {
+        match operation {
+            Operation::PegOut if balance > 50_000_000 => {
+                let amount_sats = rng.gen_range(1_000..=balance / 10_000);
+                let address = bitcoind.get_new_address().await?;
+                cmd!(
+                    sender,
+                    "withdraw",
+                    "--address",
+                    &address,
+                    "--amount",
+                    format!("{amount_sats} sat")
+                )
+                .run()
+                .await?;
+                Ok(format!("{} pegs out {amount_sats} sats", sender.name))
+            }
+            Operation::LightningPayment if balance > 100_000 => {
+                let amount_msats = rng.gen_range(1_000..=balance / 2);
+                let invoice: LnInvoiceResponse = serde_json::from_value(
+                    cmd!(
+                        receiver,
+                        "ln-invoice",
+                        format!("--amount={amount_msats}msat"),
+                        "--description=soak"
+                    )
+                    .out_json()
+                    .await?,
+                )?;
+                cmd!(sender, "ln-pay", invoice.invoice).run().await?;
+                cmd!(receiver, "wait-invoice", invoice.operation_id)
+                    .run()
+                    .await?;
+                Ok(format!(
+                    "{} pays {} {amount_msats} msat over lightning",
+                    sender.name, receiver.name
+                ))
+            }
+            Operation::EcashTransfer if balance > 10_000 => {
+                let amount_msats = rng.gen_range(1_000..=balance / 2);
+                let notes = cmd!(sender, "spend", amount_msats).out_json().await?["notes"]
+                    .as_str()
+                    .context("spend returned no notes")?
+                    .to_owned();
+                cmd!(receiver, "reissue", notes).run().await?;
+                Ok(format!(
+                    "{} sends {} {amount_msats} msat of e-cash",
+                    sender.name, receiver.name
+                ))
+            }
+            _ => {
+                let amount_sats = rng.gen_range(10_000..100_000);
+                pegin(bitcoind, sender, amount_sats).await?;
+                Ok(format!("{} pegs in {amount_sats} sats", sender.name))
+            }
+        }
+    };
+
+    match tokio::time::timeout(OPERATION_TIMEOUT, run).await {
+        Ok(description) => description,
+        Err(_) => bail!("{operation:?} of {} got stuck", sender.name),
+    }
+}
+
+async fn pegin(bitcoind: &Bitcoind, client: &SoakClient, amount_sats: u64) -> Result<()> {
+    let deposit = cmd!(client, "deposit-address").out_json().await?;
+    let address = deposit["address"]
+        .as_str()
+        .context("address must be a string")?;
+    let operation_id = deposit["operation_id"]
+        .as_str()
+        .context("operation_id must be a string")?;
+    bitcoind.send_to(address.to_owned(), amount_sats).await?;
+    bitcoind.mine_blocks(DEPOSIT_CONFIRMATIONS).await?;
+    cmd!(client, "await-deposit", operation_id).run().await
+}
+
+/// The federation is solvent and has no peg-outs waiting for signatures
+/// after the peers had time to catch up, asks a peer other than `down_peer`
+async fn check_invariants(fed: &Federation, down_peer: Option<usize>) -> Result<()> {
+    let peer = if down_peer == Some(0) { 1 } else { 0 };
+    let admin_cmd = |command: &'static str| async move {
+        cmd!(fed, "admin", command)
+            .env("FM_PASSWORD", format!("pass{peer}"))
+            .env("FM_OUR_ID", peer.to_string())
+            .out_json()
+            .await
+    };
+
+    let audit = admin_cmd("audit").await?;
+    let net_assets = audit["net_assets_msat"]
+        .as_i64()
+        .context("net_assets_msat must be a number")?;
+    ensure!(
+        net_assets >= 0,
+        "Audit reports net assets of {net_assets} msat"
+    );
+
+    tokio::time::timeout(
+        OPERATION_TIMEOUT,
+        poll_value("pending peg-outs to be signed", || async {
+            let peg_outs = admin_cmd("pending-peg-outs").await?;
+            let unsigned = peg_outs
+                .as_array()
+                .context("pending peg-outs must be a list")?
+                .iter()
+                .any(|peg_out| peg_out["state"].get("signing").is_some());
+            Ok((!unsigned).then_some(()))
+        }),
+    )
+    .await
+    .context("Peg-outs are stuck waiting for signatures")?
+}
@@ -0,0 +1,237 @@
+//! Declarative setup of several federations, lightning nodes and gateways
+//!
+//! [`crate::dev_fed`] always starts one federation with a gateway for each
+//! of a CLN and an LND node. Tests of cross-federation payments need more
+//! than that, so they describe the environment as a [`Topology`] instead.
+
+use std::collections::BTreeSet;
+
+use anyhow::{bail, ensure, Result};
+use fedimint_logging::LOG_DEVIMINT;
+use tracing::info;
+
+use crate::external::{open_channel_between, LightningNodeName};
+use crate::federation::{run_config_gen, Federation};
+use crate::util::ProcessManager;
+use crate::{Bitcoind, Gatewayd, LightningNode, Lightningd, Lnd};
+
+/// Describes the daemons to start, all of them share a single bitcoind
+#[derive(Debug, Clone)]
+pub struct Topology {
+    /// Number of federations, each with `FM_FED_SIZE` peers
+    pub federations: usize,
+    /// Lightning nodes to start, referenced by their index
+    pub lightning_nodes: Vec<LightningNodeName>,
+    /// Channels to open between the lightning nodes
+    pub channels: Vec<ChannelSpec>,
+    /// Gateways to start, at most one per lightning node
+    pub gateways: Vec<GatewaySpec>,
+}
+
+/// A channel funded by the lightning node `from`
+#[derive(Debug, Clone)]
+pub struct ChannelSpec {
+    pub from: usize,
+    pub to: usize,
+    pub capacity_sats: u64,
+    /// Amount given to `to` when opening, so it can pay back
+    pub push_sats: u64,
+}
+
+/// A gateway for the lightning node `lightning_node` registered with every
+/// federation in `federations`
+#[derive(Debug, Clone)]
+pub struct GatewaySpec {
+    pub lightning_node: usize,
+    pub federations: Vec<usize>,
+}
+
+impl Topology {
+    /// The same daemons [`crate::dev_fed`] starts, except for esplora,
+    /// electrs and the faucet
+    pub fn dev_fed() -> Self {
+        Self {
+            federations: 1,
+            lightning_nodes: vec![LightningNodeName::Cln, LightningNodeName::Lnd],
+            channels: vec![ChannelSpec {
+                from: 0,
+                to: 1,
+                capacity_sats: 10_000_000,
+                push_sats: 5_000_000,
+            }],
+            gateways: vec![
+                GatewaySpec {
+                    lightning_node: 0,
+                    federations: vec![0],
+                },
+                GatewaySpec {
+                    lightning_node: 1,
+                    federations: vec![0],
+                },
+            ],
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(self.federations > 0, "Topology needs a federation");
+
+        let num_nodes = self.lightning_nodes.len();
+        for channel in &self.channels {
+            ensure!(
+                channel.from < num_nodes && channel.to < num_nodes,
+                "Channel {channel:?} references unknown lightning node"
+            );
+            ensure!(
+                channel.from != channel.to,
+                "Channel {channel:?} connects a node to itself"
+            );
+        }
+
+        let mut nodes_with_gateway = BTreeSet::new();
+        for gateway in &self.gateways {
+            ensure!(
+                gateway.lightning_node < num_nodes,
+                "Gateway {gateway:?} references unknown lightning node"
+            );
+            if !nodes_with_gateway.insert(gateway.lightning_node) {
+                bail!(
+                    "Lightning node {} has more than one gateway",
+                    gateway.lightning_node
+                );
+            }
+            ensure!(
+                gateway
+                    .federations
+                    .iter()
+                    .all(|fed| *fed < self.federations),
+                "Gateway {gateway:?} references unknown federation"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Number of gateways that register with the federation `fed`
+    fn gateways_of(&self, fed: usize) -> usize {
+        self.gateways
+            .iter()
+            .filter(|gateway| gateway.federations.contains(&fed))
+            .count()
+    }
+}
+
+/// The daemons started for a [`Topology`], indexed the same way
+pub struct TopologyFed {
+    pub bitcoind: Bitcoind,
+    pub lightning_nodes: Vec<LightningNode>,
+    pub feds: Vec<Federation>,
+    pub gateways: Vec<Gatewayd>,
+}
+
+pub async fn topology_fed(
+    process_mgr: &ProcessManager,
+    topology: &Topology,
+) -> Result<TopologyFed> {
+    topology.validate()?;
+    let start_time = fedimint_core::time::now();
+    let bitcoind = Bitcoind::new(process_mgr).await?;
+
+    let (lightning_nodes, feds) = tokio::try_join!(
+        start_lightning_nodes(process_mgr, &bitcoind, &topology.lightning_nodes),
+        futures::future::try_join_all((0..topology.federations).map(|instance| {
+            let bitcoind = bitcoind.clone();
+            async move {
+                let fed_size = process_mgr.globals.FM_FED_SIZE;
+                let members = run_config_gen(process_mgr, instance, fed_size, true).await?;
+                Federation::new_instance(process_mgr, bitcoind, instance, members).await
+            }
+        }))
+    )?;
+    info!(LOG_DEVIMINT, "federations and lightning nodes started");
+
+    // Every channel mines blocks, so they are opened one after another
+    for channel in &topology.channels {
+        open_channel_between(
+            &bitcoind,
+            &lightning_nodes[channel.from],
+            &lightning_nodes[channel.to],
+            channel.capacity_sats,
+            channel.push_sats,
+        )
+        .await?;
+    }
+    info!(LOG_DEVIMINT, "channels opened");
+
+    let gateways = futures::future::try_join_all(topology.gateways.iter().map(|gateway| {
+        Gatewayd::new(process_mgr, lightning_nodes[gateway.lightning_node].clone())
+    }))
+    .await?;
+    for (gateway, spec) in gateways.iter().zip(&topology.gateways) {
+        for fed in &spec.federations {
+            gateway.connect_fed(&feds[*fed]).await?;
+        }
+    }
+
+    for (instance, fed) in feds.iter().enumerate() {
+        let num_gateways = topology.gateways_of(instance);
+        if num_gateways == 0 {
+            continue;
+        }
+        fed.await_gateways_registered(num_gateways).await?;
+
+        let (gateway, _) = gateways
+            .iter()
+            .zip(&topology.gateways)
+            .find(|(_, spec)| spec.federations.contains(&instance))
+            .expect("Federation has a gateway");
+        fed.use_gateway(gateway).await?;
+    }
+    info!(LOG_DEVIMINT, "gateways registered");
+
+    info!(
+        LOG_DEVIMINT,
+        "starting topology took {:?}",
+        start_time.elapsed()?
+    );
+    Ok(TopologyFed {
+        bitcoind,
+        lightning_nodes,
+        feds,
+        gateways,
+    })
+}
+
+/// Starts the lightning nodes, numbering the instances of each
+/// implementation separately
+async fn start_lightning_nodes(
+    process_mgr: &ProcessManager,
+    bitcoind: &Bitcoind,
+    names: &[LightningNodeName],
+) -> Result<Vec<LightningNode>> {
+    let mut num_cln = 0;
+    let mut num_lnd = 0;
+    let nodes = names.iter().map(|name| {
+        let bitcoind = bitcoind.clone();
+        let instance = match name {
+            LightningNodeName::Cln => &mut num_cln,
+            LightningNodeName::Lnd => &mut num_lnd,
+        };
+        let current = *instance;
+        *instance += 1;
+
+        async move {
+            anyhow::Ok(match name {
+                LightningNodeName::Cln => LightningNode::Cln(
+                    Lightningd::new_instance(process_mgr, bitcoind, current).await?,
+                ),
+                LightningNodeName::Lnd => {
+                    LightningNode::Lnd(Lnd::new_instance(process_mgr, bitcoind, current).await?)
+                }
+            })
+        }
+    });
+
+    let nodes = futures::future::try_join_all(nodes).await?;
+    info!(LOG_DEVIMINT, "lightning started");
+    Ok(nodes)
+}
@@ -79,6 +79,35 @@ pub fn utf8(path: &Path) -> &str {
     path.as_os_str().to_str().expect("must be valid utf8")
 }
 
+/// Ports of additional instances of a daemon are offset by multiples of this
+/// from the ports of the first instance
+const INSTANCE_PORT_OFFSET: u16 = 1000;
+
+/// Port of the `instance`-th instance of a daemon whose first instance uses
+/// `port`
+pub fn instance_port(port: u16, instance: usize) -> u16 {
+    port + INSTANCE_PORT_OFFSET * instance as u16
+}
+
+/// Directory of the `instance`-th instance of a daemon whose first instance
+/// uses `dir`, additional instances get a sibling directory with a suffix
+pub async fn instance_dir(dir: &Path, instance: usize) -> anyhow::Result<PathBuf> {
+    if instance == 0 {
+        return Ok(dir.to_owned());
+    }
+    let name = dir.file_name().expect("dir has a name");
+    mkdir(dir.with_file_name(format!("{}-{instance}", utf8(Path::new(name))))).await
+}
+
+/// Process name of the `instance`-th instance of a daemon
+pub fn instance_name(name: &str, instance: usize) -> String {
+    if instance == 0 {
+        name.to_owned()
+    } else {
+        format!("{name}-{instance}")
+    }
+}
+
 declare_vars! {
     Global = (test_dir: &Path, fed_size: usize) =>
     {
@@ -162,12 +191,12 @@ impl Global {
 //
 // * `id` - ID of the server. Used to calculate port numbers.
 declare_vars! {
-    Fedimintd = (globals: &Global, cfg: &ServerConfig, bind_metrics_api: String) => {
+    Fedimintd = (cfg_dir: &Path, cfg: &ServerConfig, bind_metrics_api: String) => {
         FM_BIND_P2P: String = cfg.local.fed_bind.to_string();
         FM_P2P_URL: String = cfg.local.p2p_endpoints[&cfg.local.identity].url.to_string();
         FM_BIND_API: String = cfg.local.api_bind.to_string();
         FM_BIND_METRICS_API: String = bind_metrics_api;
         FM_API_URL: String = cfg.consensus.api_endpoints[&cfg.local.identity].url.to_string();
-        FM_DATA_DIR: PathBuf = mkdir(globals.FM_DATA_DIR.join(format!("server-{}", cfg.local.identity.to_usize()))).await?;
+        FM_DATA_DIR: PathBuf = mkdir(cfg_dir.join(format!("server-{}", cfg.local.identity.to_usize()))).await?;
     }
 }
@@ -0,0 +1,101 @@
+//! JSON-RPC over HTTP on a Unix domain socket
+//!
+//! Lets guardians reach bitcoind through a socket, e.g. one forwarded to its
+//! RPC port by a local proxy, so the RPC doesn't have to be exposed over TCP.
+//! Requests are sent as HTTP/1.0, so responses are never chunked.
+
+use std::fmt;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use bitcoincore_rpc::jsonrpc::{self, Request, Response, Transport};
+use bitcoincore_rpc::Auth;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Timeout for reading and writing a request, same as the HTTP transport's
+const RPC_TIMEOUT: Duration = Duration::from_secs(15);
+
+pub struct UnixHttpTransport {
+    path: PathBuf,
+    /// Read again for every request, bitcoind writes a new cookie file when
+    /// it restarts
+    auth: Auth,
+}
+
+impl UnixHttpTransport {
+    pub fn new(path: PathBuf, auth: Auth) -> Self {
+        Self { path, auth }
+    }
+
+    fn authorization(&self) -> anyhow::Result<Option<String>> {
+        let (user, pass) = self.auth.clone().get_user_pass()?;
+        Ok(user.map(|user| {
+            let credentials = format!("{user}:{}", pass.unwrap_or_default());
+            format!("Basic {}", base64::encode(credentials))
+        }))
+    }
+
+    fn request<R: DeserializeOwned>(&self, body: &impl Serialize) -> anyhow::Result<R> {
+        let body = serde_json::to_vec(body)?;
+        let mut stream = UnixStream::connect(&self.path)
+            .with_context(|| format!("Unable to connect to {}", self.path.display()))?;
+        stream.set_read_timeout(Some(RPC_TIMEOUT))?;
+        stream.set_write_timeout(Some(RPC_TIMEOUT))?;
+
+        let mut request = format!(
+            "POST / HTTP/1.0\r\n\
+             Host: localhost\r\n\
+             Content-Type: application/json\r\n\
+             Content-Length: {}\r\n",
+            body.len()
+        );
+        if let Some(authorization) = self.authorization()? {
+            request.push_str(&format!("Authorization: {authorization}\r\n"));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes())?;
+        stream.write_all(&body)?;
+        stream.flush()?;
+
+        let mut response = vec![];
+        stream.read_to_end(&mut response)?;
+        let header_end = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .context("Incomplete HTTP response")?;
+        let status_line = String::from_utf8_lossy(&response[..header_end])
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .to_owned();
+        let body = &response[header_end + 4..];
+
+        // Like the HTTP transport we parse error responses too, bitcoind returns
+        // RPC errors with status 500
+        match serde_json::from_slice(body) {
+            Ok(response) => Ok(response),
+            Err(_) if !status_line.contains(" 200 ") => bail!("Request failed: {status_line}"),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+impl Transport for UnixHttpTransport {
+    fn send_request(&self, request: Request) -> Result<Response, jsonrpc::Error> {
+        self.request(&request)
+            .map_err(|e| jsonrpc::Error::Transport(e.into()))
+    }
+
+    fn send_batch(&self, requests: &[Request]) -> Result<Vec<Response>, jsonrpc::Error> {
+        self.request(&requests)
+            .map_err(|e| jsonrpc::Error::Transport(e.into()))
+    }
+
+    fn fmt_target(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unix://{}", self.path.display())
+    }
+}
@@ -1,31 +1,82 @@
 use std::fmt;
+use std::time::Duration;
 
 use anyhow::anyhow as format_err;
-use bitcoin::{BlockHash, Network, Script, Transaction, Txid};
+use bitcoin::{BlockHash, BlockHeader, Network, Script, Transaction, Txid};
 use bitcoin_hashes::hex::ToHex;
 use electrum_client::ElectrumApi;
-use fedimint_core::task::{block_in_place, TaskHandle};
+use fedimint_core::task::{block_in_place, sleep, TaskHandle};
 use fedimint_core::txoproof::TxOutProof;
 use fedimint_core::{apply, async_trait_maybe_send, Feerate};
 use tracing::{info, warn};
 use url::Url;
 
-use crate::{DynBitcoindRpc, IBitcoindRpc, IBitcoindRpcFactory, RetryClient};
+use crate::{
+    BlockEventSender, BlockEvents, BlockNotifier, DynBitcoindRpc, IBitcoindRpc,
+    IBitcoindRpcFactory, RetryClient,
+};
+
+/// How often the subscription connection is checked for new headers, the
+/// client only reads notifications off the connection while waiting for the
+/// response to a request, a cheap `server.ping`
+const HEADER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Delay before reconnecting a failed subscription
+const SUBSCRIPTION_RETRY_DELAY: Duration = Duration::from_secs(5);
 
 #[derive(Debug)]
 pub struct ElectrumFactory;
 
 impl IBitcoindRpcFactory for ElectrumFactory {
     fn create_connection(&self, url: &Url, handle: TaskHandle) -> anyhow::Result<DynBitcoindRpc> {
-        Ok(RetryClient::new(ElectrumClient::new(url)?, handle).into())
+        Ok(RetryClient::new(ElectrumClient::new(url, handle.clone())?, handle).into())
     }
 }
 
-pub struct ElectrumClient(electrum_client::Client);
+pub struct ElectrumClient {
+    client: electrum_client::Client,
+    url: Url,
+    blocks: BlockNotifier,
+    handle: TaskHandle,
+}
 
 impl ElectrumClient {
-    fn new(url: &Url) -> anyhow::Result<Self> {
-        Ok(Self(electrum_client::Client::new(url.as_str())?))
+    fn new(url: &Url, handle: TaskHandle) -> anyhow::Result<Self> {
+        Ok(Self {
+            client: electrum_client::Client::new(url.as_str())?,
+            url: url.clone(),
+            blocks: BlockNotifier::new(),
+            handle,
+        })
+    }
+
+    /// Forwards the headers the server announces on a connection of its own
+    /// until `handle` shuts down, reconnecting after errors
+    async fn listen_for_blocks(url: Url, sender: BlockEventSender, handle: TaskHandle) {
+        while !handle.is_shutting_down() {
+            if let Err(error) = Self::forward_headers(&url, &sender, &handle).await {
+                warn!(?error, "Electrum header subscription failed, reconnecting");
+                sleep(SUBSCRIPTION_RETRY_DELAY).await;
+            }
+        }
+    }
+
+    async fn forward_headers(
+        url: &Url,
+        sender: &BlockEventSender,
+        handle: &TaskHandle,
+    ) -> anyhow::Result<()> {
+        let client = electrum_client::Client::new(url.as_str())?;
+        // Answered with the current tip, which isn't new
+        block_in_place(|| client.block_headers_subscribe())?;
+        while !handle.is_shutting_down() {
+            block_in_place(|| client.ping())?;
+            while let Some(notification) = block_in_place(|| client.block_headers_pop())? {
+                sender.send_replace(Some(notification.header.block_hash()));
+            }
+            sleep(HEADER_POLL_INTERVAL).await;
+        }
+        Ok(())
     }
 }
 
@@ -38,7 +89,7 @@ impl fmt::Debug for ElectrumClient {
 #[apply(async_trait_maybe_send!)]
 impl IBitcoindRpc for ElectrumClient {
     async fn get_network(&self) -> anyhow::Result<Network> {
-        let resp = block_in_place(|| self.0.server_features())?;
+        let resp = block_in_place(|| self.client.server_features())?;
         Ok(match resp.genesis_hash.to_hex().as_str() {
             crate::MAINNET_GENESIS_BLOCK_HASH => Network::Bitcoin,
             crate::TESTNET_GENESIS_BLOCK_HASH => Network::Testnet,
@@ -51,11 +102,11 @@ impl IBitcoindRpc for ElectrumClient {
     }
 
     async fn get_block_height(&self) -> anyhow::Result<u64> {
-        Ok(block_in_place(|| self.0.block_headers_subscribe_raw())?.height as u64)
+        Ok(block_in_place(|| self.client.block_headers_subscribe_raw())?.height as u64)
     }
 
     async fn get_block_hash(&self, height: u64) -> anyhow::Result<BlockHash> {
-        let result = block_in_place(|| self.0.block_headers(height as usize, 1))?;
+        let result = block_in_place(|| self.client.block_headers(height as usize, 1))?;
         Ok(result
             .headers
             .get(0)
@@ -63,9 +114,15 @@ impl IBitcoindRpc for ElectrumClient {
             .block_hash())
     }
 
+    async fn get_block_header(&self, height: u64) -> anyhow::Result<BlockHeader> {
+        Ok(block_in_place(|| {
+            self.client.block_header(height as usize)
+        })?)
+    }
+
     async fn get_fee_rate(&self, confirmation_target: u16) -> anyhow::Result<Option<Feerate>> {
-        let estimate = block_in_place(|| self.0.estimate_fee(confirmation_target as usize))?;
-        let min_fee = block_in_place(|| self.0.relay_fee())?;
+        let estimate = block_in_place(|| self.client.estimate_fee(confirmation_target as usize))?;
+        let min_fee = block_in_place(|| self.client.relay_fee())?;
 
         // convert fee rate estimate or min fee to sats
         let sats_per_kvb = estimate.max(min_fee) * 100_000_000f64;
@@ -78,13 +135,13 @@ impl IBitcoindRpc for ElectrumClient {
         let mut bytes = vec![];
         bitcoin::consensus::Encodable::consensus_encode(&transaction, &mut bytes)
             .expect("can't fail");
-        let _ = block_in_place(|| self.0.transaction_broadcast_raw(&bytes)).map_err(|error| {
+        let _ = block_in_place(|| self.client.transaction_broadcast_raw(&bytes)).map_err(|error| {
             info!(?error, "Error broadcasting transaction");
         });
     }
 
     async fn get_tx_block_height(&self, txid: &Txid) -> anyhow::Result<Option<u64>> {
-        let tx = block_in_place(|| self.0.transaction_get(txid))
+        let tx = block_in_place(|| self.client.transaction_get(txid))
             .map_err(|error| info!(?error, "Unable to get raw transaction"));
         match tx.ok() {
             None => Ok(None),
@@ -93,7 +150,8 @@ impl IBitcoindRpc for ElectrumClient {
                     .output
                     .first()
                     .ok_or(format_err!("Transaction must contain at least one output"))?;
-                let history = block_in_place(|| self.0.script_get_history(&output.script_pubkey))?;
+                let history =
+                    block_in_place(|| self.client.script_get_history(&output.script_pubkey))?;
                 Ok(history.first().map(|history| history.height as u64))
             }
         }
@@ -104,9 +162,11 @@ impl IBitcoindRpc for ElectrumClient {
         script: &Script,
     ) -> anyhow::Result<Vec<bitcoin::Transaction>> {
         let mut results = vec![];
-        let transactions = block_in_place(|| self.0.script_get_history(script))?;
+        let transactions = block_in_place(|| self.client.script_get_history(script))?;
         for history in transactions.into_iter() {
-            results.push(block_in_place(|| self.0.transaction_get(&history.tx_hash))?);
+            results.push(block_in_place(|| {
+                self.client.transaction_get(&history.tx_hash)
+            })?);
         }
         Ok(results)
     }
@@ -116,4 +176,16 @@ impl IBitcoindRpc for ElectrumClient {
         // electrum regardless right now
         unimplemented!()
     }
+
+    async fn subscribe_blocks(&self) -> Option<BlockEvents> {
+        let url = self.url.clone();
+        let handle = self.handle.clone();
+        Some(
+            self.blocks
+                .subscribe("electrum block notifications", move |sender| {
+                    Self::listen_for_blocks(url, sender, handle)
+                })
+                .await,
+        )
+    }
 }
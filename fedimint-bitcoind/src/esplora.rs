@@ -1,7 +1,7 @@
 use std::collections::HashMap;
 
 use anyhow::format_err;
-use bitcoin::{BlockHash, Network, Script, Transaction, Txid};
+use bitcoin::{BlockHash, BlockHeader, Network, Script, Transaction, Txid};
 use bitcoin_hashes::hex::ToHex;
 use fedimint_core::task::TaskHandle;
 use fedimint_core::txoproof::TxOutProof;
@@ -64,6 +64,11 @@ impl IBitcoindRpc for EsploraClient {
         Ok(self.0.get_block_hash(height as u32).await?)
     }
 
+    async fn get_block_header(&self, height: u64) -> anyhow::Result<BlockHeader> {
+        let hash = self.0.get_block_hash(height as u32).await?;
+        Ok(self.0.get_header_by_hash(&hash).await?)
+    }
+
     async fn get_fee_rate(&self, confirmation_target: u16) -> anyhow::Result<Option<Feerate>> {
         let fee_estimates: HashMap<String, f64> = self.0.get_fee_estimates().await?;
 
@@ -7,13 +7,14 @@ use std::time::Duration;
 
 use anyhow::format_err;
 pub use anyhow::Result;
-use bitcoin::{BlockHash, Network, Script, Transaction, Txid};
+use bitcoin::{BlockHash, BlockHeader, Network, Script, Transaction, Txid};
 use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
-use fedimint_core::task::TaskHandle;
+use fedimint_core::task::{TaskGroup, TaskHandle};
 use fedimint_core::txoproof::TxOutProof;
 use fedimint_core::{apply, async_trait_maybe_send, dyn_newtype_define, Feerate};
 use fedimint_logging::LOG_BLOCKCHAIN;
 use lazy_static::lazy_static;
+use tokio::sync::watch;
 use tracing::info;
 use url::Url;
 
@@ -96,6 +97,13 @@ pub trait IBitcoindRpc: Debug {
     /// by a certain number of blocks.
     async fn get_block_hash(&self, height: u64) -> Result<BlockHash>;
 
+    /// Returns the header of the block at a given height
+    ///
+    /// # Panics
+    /// If the node does not know a block for that height, see
+    /// [`Self::get_block_hash`].
+    async fn get_block_header(&self, height: u64) -> Result<BlockHeader>;
+
     /// Estimates the fee rate for a given confirmation target. Make sure that
     /// all federation members use the same algorithm to avoid widely
     /// diverging results. If the node is not ready yet to return a fee rate
@@ -129,6 +137,83 @@ pub trait IBitcoindRpc: Debug {
 
     /// Returns a prooft that a tx is included in the bitcoin blockchain
     async fn get_txout_proof(&self, txid: Txid) -> Result<TxOutProof>;
+
+    /// Subscribes to notifications of new blocks at the chain tip, so callers
+    /// waiting for the block height to change don't have to poll it
+    ///
+    /// Returns `None` if the backend can't push notifications.
+    async fn subscribe_blocks(&self) -> Option<BlockEvents> {
+        None
+    }
+}
+
+/// Sends the hash of every new chain tip to the [`BlockEvents`] subscribed
+pub type BlockEventSender = watch::Sender<Option<BlockHash>>;
+
+/// Notifications of new blocks, see [`IBitcoindRpc::subscribe_blocks`]
+#[derive(Debug, Clone)]
+pub struct BlockEvents(watch::Receiver<Option<BlockHash>>);
+
+impl BlockEvents {
+    pub fn channel() -> (BlockEventSender, BlockEvents) {
+        let (sender, receiver) = watch::channel(None);
+        (sender, BlockEvents(receiver))
+    }
+
+    /// Another subscriber, which only sees blocks arriving from now on
+    pub fn resubscribe(&self) -> BlockEvents {
+        let mut receiver = self.0.clone();
+        receiver.borrow_and_update();
+        BlockEvents(receiver)
+    }
+
+    /// Waits for a block that arrived after the last one this subscriber saw,
+    /// or after it subscribed, and returns its hash
+    ///
+    /// Blocks arriving in quick succession may be reported as one. Returns
+    /// `None` once the backend stopped sending notifications.
+    pub async fn next_block(&mut self) -> Option<BlockHash> {
+        self.0.changed().await.ok()?;
+        *self.0.borrow()
+    }
+}
+
+/// Starts listening for block notifications when the first subscriber asks
+/// for them, backends can be created outside of a runtime and most users
+/// never subscribe
+#[cfg(any(feature = "electrum-client", feature = "zeromq"))]
+#[derive(Debug)]
+pub(crate) struct BlockNotifier {
+    events: BlockEvents,
+    /// Taken by the listener once it started
+    sender: Mutex<Option<BlockEventSender>>,
+}
+
+#[cfg(any(feature = "electrum-client", feature = "zeromq"))]
+impl BlockNotifier {
+    pub(crate) fn new() -> Self {
+        let (sender, events) = BlockEvents::channel();
+        BlockNotifier {
+            events,
+            sender: Mutex::new(Some(sender)),
+        }
+    }
+
+    /// Subscribes to the notifications, spawning the future returned by
+    /// `listen` for the first subscriber
+    pub(crate) async fn subscribe<L, Fut>(&self, name: &str, listen: L) -> BlockEvents
+    where
+        L: FnOnce(BlockEventSender) -> Fut,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let sender = self.sender.lock().expect("lock poisoned").take();
+        if let Some(sender) = sender {
+            let listener = listen(sender);
+            // Stops on its own once the task handle of the backend shuts down
+            TaskGroup::new().spawn(name, |_| listener).await;
+        }
+        self.events.resubscribe()
+    }
 }
 
 dyn_newtype_define! {
@@ -199,6 +284,11 @@ where
             .await
     }
 
+    async fn get_block_header(&self, height: u64) -> Result<BlockHeader> {
+        self.retry_call(|| async { self.inner.get_block_header(height).await })
+            .await
+    }
+
     async fn get_fee_rate(&self, confirmation_target: u16) -> Result<Option<Feerate>> {
         self.retry_call(|| async { self.inner.get_fee_rate(confirmation_target).await })
             .await
@@ -222,4 +312,8 @@ where
         self.retry_call(|| async { self.inner.get_txout_proof(txid).await })
             .await
     }
+
+    async fn subscribe_blocks(&self) -> Option<BlockEvents> {
+        self.inner.subscribe_blocks().await
+    }
 }
@@ -18,10 +18,11 @@ use fedimint_core::time::now;
 use fedimint_core::{Amount, ParseAmountError, TieredMulti, TieredSummary};
 use fedimint_ln_client::contracts::ContractId;
 use fedimint_ln_client::{
-    InternalPayState, LightningClientExt, LnPayState, LnReceiveState, PayType,
+    InternalPayState, LightningClientExt, LightningOperationMetadata, LnPayState, LnReceiveState,
+    PayType,
 };
 use fedimint_mint_client::{MintClientExt, MintClientModule, SpendableNote};
-use fedimint_wallet_client::{WalletClientExt, WithdrawState};
+use fedimint_wallet_client::{DepositMetadata, WalletClientExt, WalletInstance, WithdrawState};
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -69,11 +70,31 @@ pub enum ClientCmd {
         description: String,
         #[clap(long)]
         expiry_time: Option<u64>,
+        /// Note stored with the operation
+        #[clap(long)]
+        memo: Option<String>,
+        /// Id of the order the invoice is for in an external system
+        #[clap(long)]
+        external_id: Option<String>,
     },
     /// Wait for incoming invoice to be paid
     WaitInvoice { operation_id: OperationId },
     /// Pay a lightning invoice via a gateway
-    LnPay { bolt11: lightning_invoice::Invoice },
+    LnPay {
+        bolt11: lightning_invoice::Invoice,
+        /// Note stored with the operation
+        #[clap(long)]
+        memo: Option<String>,
+        /// Id of the order the payment is for in an external system
+        #[clap(long)]
+        external_id: Option<String>,
+    },
+    /// Push a payment to a lightning node via a gateway, without an invoice
+    LnKeysend {
+        destination: secp256k1::PublicKey,
+        #[clap(long, value_parser = parse_fedimint_amount)]
+        amount: Amount,
+    },
     /// List registered gateways
     ListGateways,
     /// Switch active gateway
@@ -82,7 +103,24 @@ pub enum ClientCmd {
         pubkey: secp256k1::XOnlyPublicKey,
     },
     /// Generate a new deposit address, funds sent to it can later be claimed
-    DepositAddress,
+    DepositAddress {
+        /// Label of whoever the address is generated for, returned once the
+        /// deposit is claimed
+        #[clap(long)]
+        label: Option<String>,
+        /// Amount the depositor is asked to send, included in the returned
+        /// BIP21 URI
+        #[clap(long)]
+        amount: Option<bitcoin::Amount>,
+        #[clap(long = "metadata")]
+        /// Deposit metadata, encoded as `key=value` (use
+        /// `--metadata=key=value`, possibly multiple times)
+        metadata: Vec<String>,
+        /// Network of the wallet module to deposit to, if the federation runs
+        /// several. Defaults to the first one.
+        #[clap(long)]
+        network: Option<bitcoin::Network>,
+    },
     /// Wait for desposit on previously generated address
     AwaitDeposit { operation_id: OperationId },
     /// Withdraw funds from the federation
@@ -91,6 +129,10 @@ pub enum ClientCmd {
         amount: bitcoin::Amount,
         #[clap(long)]
         address: bitcoin::Address,
+        /// Network of the wallet module to withdraw from, if the federation
+        /// runs several. Defaults to the first one.
+        #[clap(long)]
+        network: Option<bitcoin::Network>,
     },
     /// Upload the (encrypted) snapshot of mint notes to federation
     Backup {
@@ -171,11 +213,14 @@ pub async fn handle_ng_command(
             amount,
             description,
             expiry_time,
+            memo,
+            external_id,
         } => {
             client.select_active_gateway().await?;
 
+            let metadata = LightningOperationMetadata { memo, external_id };
             let (operation_id, invoice) = client
-                .create_bolt11_invoice(amount, description, expiry_time)
+                .create_bolt11_invoice_with_metadata(amount, description, expiry_time, metadata)
                 .await?;
             Ok(serde_json::to_value(LnInvoiceResponse {
                 operation_id,
@@ -196,6 +241,9 @@ pub async fn handle_ng_command(
                     LnReceiveState::Canceled { reason } => {
                         return Err(reason.into());
                     }
+                    LnReceiveState::Expired => {
+                        return Err(anyhow!("Invoice expired unpaid"));
+                    }
                     _ => {}
                 }
 
@@ -204,10 +252,17 @@ pub async fn handle_ng_command(
 
             return Err(anyhow::anyhow!("Lightning receive failed"));
         }
-        ClientCmd::LnPay { bolt11 } => {
+        ClientCmd::LnPay {
+            bolt11,
+            memo,
+            external_id,
+        } => {
             client.select_active_gateway().await?;
 
-            let (pay_type, contract_id) = client.pay_bolt11_invoice(bolt11).await?;
+            let metadata = LightningOperationMetadata { memo, external_id };
+            let (pay_type, contract_id) = client
+                .pay_bolt11_invoice_with_metadata(bolt11, metadata)
+                .await?;
 
             match pay_type {
                 PayType::Internal(operation_id) => {
@@ -268,6 +323,35 @@ pub async fn handle_ng_command(
 
             return Err(anyhow::anyhow!("Lightning Payment failed"));
         }
+        ClientCmd::LnKeysend {
+            destination,
+            amount,
+        } => {
+            let (operation_id, contract_id) = client.pay_keysend(destination, amount).await?;
+            let mut updates = client.subscribe_ln_pay(operation_id).await?.into_stream();
+
+            while let Some(update) = updates.next().await {
+                match update {
+                    LnPayState::Success { preimage } => {
+                        return Ok(serde_json::to_value(PayInvoiceResponse {
+                            operation_id,
+                            contract_id,
+                            preimage,
+                        })
+                        .unwrap());
+                    }
+                    LnPayState::Refunded { gateway_error } => {
+                        info!("{gateway_error}");
+                        return get_note_summary(&client).await;
+                    }
+                    _ => {}
+                }
+
+                info!("Update: {:?}", update);
+            }
+
+            return Err(anyhow::anyhow!("Keysend payment failed"));
+        }
         ClientCmd::ListGateways => {
             let gateways = client.fetch_registered_gateways().await?;
             if gateways.is_empty() {
@@ -297,13 +381,23 @@ pub async fn handle_ng_command(
             gateway_json["active"] = json!(true);
             Ok(serde_json::to_value(gateway_json).unwrap())
         }
-        ClientCmd::DepositAddress => {
-            let (operation_id, address) = client
-                .get_deposit_address(now() + Duration::from_secs(600))
+        ClientCmd::DepositAddress {
+            label,
+            amount,
+            metadata,
+            network,
+        } => {
+            let metadata = DepositMetadata {
+                label,
+                metadata: metadata_from_clap_cli(metadata)?,
+            };
+            let (operation_id, uri) = wallet_instance(&client, network)?
+                .get_deposit_uri(now() + Duration::from_secs(600), amount, metadata)
                 .await?;
             Ok(serde_json::json! {
                 {
-                    "address": address,
+                    "address": uri.address,
+                    "uri": uri.to_string(),
                     "operation_id": operation_id,
                 }
             })
@@ -347,13 +441,18 @@ pub async fn handle_ng_command(
                 "secret": hex_secret,
             }))
         }
-        ClientCmd::Withdraw { amount, address } => {
-            let fees = client.get_withdraw_fee(address.clone(), amount).await?;
+        ClientCmd::Withdraw {
+            amount,
+            address,
+            network,
+        } => {
+            let wallet = wallet_instance(&client, network)?;
+            let fees = wallet.get_withdraw_fee(address.clone(), amount).await?;
             let absolute_fees = fees.amount();
 
             info!("Attempting withdraw with fees: {fees:?}");
 
-            let operation_id = client.withdraw(address, amount, fees).await?;
+            let operation_id = wallet.withdraw(address, amount, fees).await?;
 
             let mut updates = client
                 .subscribe_withdraw_updates(operation_id)
@@ -385,6 +484,17 @@ pub async fn handle_ng_command(
     }
 }
 
+/// Wallet module on `network`, the first one if not set
+fn wallet_instance(
+    client: &Client,
+    network: Option<bitcoin::Network>,
+) -> anyhow::Result<WalletInstance<'_>> {
+    match network {
+        Some(network) => WalletInstance::by_network(client, network),
+        None => WalletInstance::first(client),
+    }
+}
+
 async fn get_note_summary(client: &Client) -> anyhow::Result<serde_json::Value> {
     let (mint_client, _) = client.get_first_module::<MintClientModule>(&fedimint_mint_client::KIND);
     let summary = mint_client
@@ -0,0 +1,193 @@
+//! Offline decoding of consensus encoded data
+//!
+//! Only the client config is needed to know which module decodes which
+//! instance id, so users can share hex blobs of stuck transactions or epochs
+//! and they can be inspected without connecting to the federation.
+
+use std::fmt::Display;
+
+use bitcoin_hashes::hex::ToHex;
+use clap::Subcommand;
+use fedimint_core::config::ClientConfig;
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::epoch::{ConsensusItem, MetaUpdate, SignedEpochOutcome};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::transaction::Transaction;
+use fedimint_core::TieredMulti;
+use fedimint_mint_client::SpendableNote;
+use fedimint_wallet_client::txoproof::PegInProof;
+use serde_json::{json, Value};
+
+use crate::client::parse_ecash;
+
+#[derive(Debug, Clone, Subcommand)]
+pub enum DecodeCmd {
+    /// Decode a hex encoded transaction
+    Transaction { hex: String },
+    /// Decode base64 encoded e-cash notes as created by `spend`, their spend
+    /// keys are not printed
+    Notes {
+        #[clap(value_parser = parse_ecash)]
+        notes: TieredMulti<SpendableNote>,
+    },
+    /// Decode a hex encoded peg-in proof
+    PegInProof { hex: String },
+    /// Decode a hex encoded consensus item
+    ConsensusItem { hex: String },
+    /// Decode a hex encoded signed epoch outcome as printed by `admin
+    /// last-epoch`
+    Epoch { hex: String },
+}
+
+pub fn handle_decode_command(
+    command: DecodeCmd,
+    config: &ClientConfig,
+    decoders: &ModuleDecoderRegistry,
+) -> anyhow::Result<Value> {
+    match command {
+        DecodeCmd::Transaction { hex } => {
+            let tx: Transaction = Decodable::consensus_decode_hex(&hex, decoders)?;
+            transaction_json(&tx, config)
+        }
+        DecodeCmd::Notes { notes } => {
+            let notes_json = notes
+                .iter()
+                .flat_map(|(amount, tier_notes)| {
+                    tier_notes.iter().map(move |note| {
+                        json!({
+                            "amount_msat": amount,
+                            "nonce": note.note.0,
+                        })
+                    })
+                })
+                .collect::<Vec<_>>();
+            Ok(json!({
+                "total_msat": notes.total_amount(),
+                "denominations_msat": notes.summary(),
+                "notes": notes_json,
+            }))
+        }
+        DecodeCmd::PegInProof { hex } => {
+            let proof: PegInProof = Decodable::consensus_decode_hex(&hex, decoders)?;
+            let tx_out = proof.tx_output();
+            Ok(json!({
+                "outpoint": proof.outpoint().to_string(),
+                "proof_block": proof.proof_block().to_string(),
+                "value_sat": tx_out.value,
+                "script_pubkey": tx_out.script_pubkey.as_bytes().to_hex(),
+                "tweak_contract_key": proof.tweak_contract_key().to_string(),
+            }))
+        }
+        DecodeCmd::ConsensusItem { hex } => {
+            let item: ConsensusItem = Decodable::consensus_decode_hex(&hex, decoders)?;
+            consensus_item_json(&item, config)
+        }
+        DecodeCmd::Epoch { hex } => {
+            let epoch: SignedEpochOutcome = Decodable::consensus_decode_hex(&hex, decoders)?;
+            let contributions = epoch
+                .outcome
+                .items
+                .iter()
+                .map(|(peer, items)| {
+                    let items = items
+                        .iter()
+                        .map(|item| consensus_item_json(item, config))
+                        .collect::<anyhow::Result<Vec<_>>>()?;
+                    Ok(json!({
+                        "peer": peer,
+                        "items": items,
+                    }))
+                })
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            Ok(json!({
+                "epoch": epoch.outcome.epoch,
+                "hash": epoch.hash.to_string(),
+                "last_hash": epoch.outcome.last_hash.map(|hash| hash.to_string()),
+                "signed": epoch.signature.is_some(),
+                "contributions": contributions,
+                "rejected_txs": epoch
+                    .outcome
+                    .rejected_txs
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>(),
+            }))
+        }
+    }
+}
+
+fn transaction_json(tx: &Transaction, config: &ClientConfig) -> anyhow::Result<Value> {
+    let inputs = tx
+        .inputs
+        .iter()
+        .map(|input| module_item_json(input.module_instance_id(), input, config))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let outputs = tx
+        .outputs
+        .iter()
+        .map(|output| module_item_json(output.module_instance_id(), output, config))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    Ok(json!({
+        "txid": tx.tx_hash().to_string(),
+        "inputs": inputs,
+        "outputs": outputs,
+        "signature": tx.signature.map(|signature| signature.to_string()),
+    }))
+}
+
+fn consensus_item_json(item: &ConsensusItem, config: &ClientConfig) -> anyhow::Result<Value> {
+    Ok(match item {
+        ConsensusItem::ConsensusUpgrade(_) => json!({ "type": "consensus_upgrade" }),
+        ConsensusItem::ClientConfigSignatureShare(_) => {
+            json!({ "type": "client_config_signature_share" })
+        }
+        ConsensusItem::EpochOutcomeSignatureShare(_) => {
+            json!({ "type": "epoch_outcome_signature_share" })
+        }
+        ConsensusItem::Transaction(tx) => json!({
+            "type": "transaction",
+            "transaction": transaction_json(tx, config)?,
+        }),
+        ConsensusItem::Module(module_item) => json!({
+            "type": "module",
+            "item": module_item_json(module_item.module_instance_id(), module_item, config)?,
+        }),
+        ConsensusItem::MetaUpdate(MetaUpdate(meta)) => json!({
+            "type": "meta_update",
+            "meta": meta,
+        }),
+        ConsensusItem::ModuleAddition(addition) => json!({
+            "type": "module_addition",
+            "addition": addition,
+        }),
+        ConsensusItem::EncryptedModule(_) => json!({ "type": "encrypted_module" }),
+        ConsensusItem::DecryptionShare(share) => json!({
+            "type": "decryption_share",
+            "item_id": share.item_id.to_string(),
+        }),
+        ConsensusItem::ModuleAdditionCancel(cancel) => json!({
+            "type": "module_addition_cancel",
+            "module_instance_id": cancel.module_instance_id,
+        }),
+    })
+}
+
+/// Modules only expose a textual description of their items, the raw
+/// encoding is included so it can be decoded by module specific tools
+fn module_item_json(
+    module_instance_id: ModuleInstanceId,
+    item: &(impl Display + Encodable),
+    config: &ClientConfig,
+) -> anyhow::Result<Value> {
+    Ok(json!({
+        "module_instance_id": module_instance_id,
+        "kind": config
+            .modules
+            .get(&module_instance_id)
+            .map(|module| module.kind().to_string()),
+        "description": item.to_string(),
+        "hex": item.consensus_encode_to_hex()?,
+    }))
+}
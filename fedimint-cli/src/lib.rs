@@ -1,4 +1,5 @@
 mod client;
+mod decode;
 mod utils;
 
 use core::fmt;
@@ -12,40 +13,51 @@ use std::sync::Arc;
 use std::time::Duration;
 use std::{fs, result};
 
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
 use fedimint_aead::{encrypted_read, encrypted_write, get_encryption_key};
 use fedimint_client::module::gen::{ClientModuleGen, ClientModuleGenRegistry, IClientModuleGen};
 use fedimint_client::secret::PlainRootSecretStrategy;
 use fedimint_client::sm::OperationId;
 use fedimint_client::{ClientBuilder, ClientSecret};
-use fedimint_core::admin_client::WsAdminClient;
+use fedimint_core::admin_client::{
+    AuditReport, DatabaseBackupRequest, ModuleAdditionRequest, WsAdminClient,
+};
 use fedimint_core::api::{
     ClientConfigDownloadToken, FederationApiExt, FederationError, GlobalFederationApi,
-    IFederationApi, IGlobalFederationApi, WsClientConnectInfo, WsFederationApi,
+    IFederationApi, WsClientConnectInfo, WsFederationApi,
 };
-use fedimint_core::config::{load_from_file, ClientConfig, FederationId};
+use fedimint_core::config::{load_from_file, ClientConfig, ConfigGenModuleParams, FederationId};
+use fedimint_core::core::{ModuleInstanceId, ModuleKind};
 use fedimint_core::db::DatabaseValue;
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::epoch::{SerdeEpochHistory, SignedEpochOutcome};
 use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::module::{ApiAuth, ApiRequestErased};
 use fedimint_core::query::EventuallyConsistent;
+use fedimint_core::receipt::{verify_receipt, SignedTransactionReceipt};
 use fedimint_core::task::{self, TaskGroup};
-use fedimint_core::{PeerId, TieredMulti};
+use fedimint_core::{PeerId, TieredMulti, TransactionId};
 use fedimint_ln_client::LightningClientGen;
 use fedimint_logging::TracingSetup;
+use fedimint_mint_client::common::db::LiabilityViolation;
+use fedimint_mint_client::velocity::VelocityPolicy;
 use fedimint_mint_client::{MintClientExt, MintClientGen, SpendableNote};
 use fedimint_server::config::io::SALT_FILE;
 use fedimint_wallet_client::api::WalletFederationApi;
-use fedimint_wallet_client::{WalletClientGen, WalletClientModule};
+use fedimint_wallet_client::{
+    BackendDivergence, BitcoindOutage, BlockResyncResponse, DescriptorMigrationStatus,
+    PendingPegOut, SpendableUTXO, UnclaimedDeposit, UtxoFreezeVote, WalletClientGen,
+    WalletClientModule, WalletUtxo,
+};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use thiserror::Error;
 use tracing::{debug, info};
 use url::Url;
-use utils::{from_hex, parse_peer_id};
+use utils::{from_hex, parse_api_endpoint, parse_peer_id};
 
-use crate::client::ClientCmd;
+use crate::client::{parse_gateway_pub_key, ClientCmd};
+use crate::decode::DecodeCmd;
 
 /// Type of output the cli produces
 #[derive(Serialize)]
@@ -72,6 +84,9 @@ enum CliOutput {
         url: Url,
         download_token: String,
         id: FederationId,
+        api_endpoints: BTreeMap<PeerId, Url>,
+        network: Option<bitcoin::Network>,
+        gateway: Option<bitcoin::secp256k1::XOnlyPublicKey>,
     },
 
     JoinFederation {
@@ -84,6 +99,21 @@ enum CliOutput {
 
     SignalUpgrade,
 
+    UpdateMeta,
+
+    UpdateVelocityPolicy,
+
+    ExitSafeMode,
+
+    FreezeUtxo {
+        outpoint: bitcoin::OutPoint,
+        frozen: bool,
+    },
+
+    ProposeModuleAddition,
+
+    CancelModuleAddition,
+
     EpochCount {
         count: u64,
     },
@@ -94,6 +124,8 @@ enum CliOutput {
 
     ForceEpoch,
 
+    TriggerEpoch,
+
     ConfigDecrypt,
 
     ConfigEncrypt,
@@ -107,6 +139,64 @@ impl fmt::Display for CliOutput {
     }
 }
 
+/// Version of the envelope and command outputs printed with `--output json`,
+/// bumped whenever one of them changes in an incompatible way
+pub const CLI_JSON_SCHEMA_VERSION: u32 = 1;
+
+/// How the cli prints results and errors
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+enum OutputFormat {
+    /// Pretty printed JSON on stdout, errors on stderr
+    #[default]
+    Pretty,
+    /// A single line [`JsonEnvelope`] on stdout for results and errors
+    Json,
+}
+
+/// What the cli prints with `--output json`, see `docs/cli-json-output.md`
+#[derive(Serialize)]
+struct JsonEnvelope<'a> {
+    schema_version: u32,
+    ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<&'a CliOutput>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<JsonError<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonError<'a> {
+    code: u16,
+    kind: &'a CliErrorKind,
+    message: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<String>,
+}
+
+impl<'a> JsonEnvelope<'a> {
+    fn new(result: &'a CliOutputResult) -> Self {
+        match result {
+            Ok(output) => JsonEnvelope {
+                schema_version: CLI_JSON_SCHEMA_VERSION,
+                ok: true,
+                result: Some(output),
+                error: None,
+            },
+            Err(err) => JsonEnvelope {
+                schema_version: CLI_JSON_SCHEMA_VERSION,
+                ok: false,
+                result: None,
+                error: Some(JsonError {
+                    code: err.kind.code(),
+                    kind: &err.kind,
+                    message: &err.message,
+                    details: err.raw_error.as_ref().map(|e| format!("{e:#}")),
+                }),
+            },
+        }
+    }
+}
+
 /// Types of error the cli return
 #[derive(Debug, Serialize, Deserialize)]
 enum CliErrorKind {
@@ -123,6 +213,26 @@ enum CliErrorKind {
     MissingAuth,
 }
 
+impl CliErrorKind {
+    /// Code identifying the kind in `--output json` mode, codes are never
+    /// reused once assigned
+    fn code(&self) -> u16 {
+        match self {
+            CliErrorKind::NetworkError => 1,
+            CliErrorKind::IOError => 2,
+            CliErrorKind::InvalidValue => 3,
+            CliErrorKind::OSError => 4,
+            CliErrorKind::GeneralFederationError => 5,
+            CliErrorKind::AlreadySpent => 6,
+            CliErrorKind::Timeout => 7,
+            CliErrorKind::InsufficientBalance => 8,
+            CliErrorKind::SerializationError => 9,
+            CliErrorKind::GeneralFailure => 10,
+            CliErrorKind::MissingAuth => 11,
+        }
+    }
+}
+
 /// `Result` with `CliError` as `Error`
 type CliResult<E> = Result<E, CliError>;
 
@@ -249,6 +359,10 @@ struct Opts {
     #[arg(long, env = "FM_PASSWORD")]
     password: Option<String>,
 
+    /// Format of the output, `json` is meant for scripts
+    #[arg(long, env = "FM_CLI_OUTPUT", value_enum, default_value_t)]
+    output: OutputFormat,
+
     #[clap(subcommand)]
     command: Command,
 }
@@ -355,6 +469,10 @@ enum Command {
     #[clap(subcommand)]
     Dev(DevCmd),
 
+    /// Decode consensus encoded data without connecting to the federation
+    #[clap(subcommand)]
+    Decode(DecodeCmd),
+
     /// Join a federation using it's ConnectInfo
     JoinFederation {
         connect: String,
@@ -378,6 +496,156 @@ enum AdminCmd {
 
     /// Signal a consensus upgrade
     SignalUpgrade,
+
+    /// Show the database migrations applied by the server
+    MigrationJournal,
+
+    /// Vote for replacing the federation meta sent to clients, takes effect
+    /// once a threshold of guardians voted for the same meta
+    UpdateMeta {
+        /// The complete new meta as a JSON object of strings
+        meta_json: String,
+    },
+
+    /// Show the meta votes that didn't reach the threshold yet
+    MetaVotes,
+
+    /// Vote for adding a module instance to the running federation, it's added
+    /// once all guardians voted for the same module and consensus params
+    ProposeModuleAddition {
+        /// Id of the new module instance
+        module_instance_id: ModuleInstanceId,
+        /// Kind of the module, e.g. `mint`
+        kind: String,
+        /// The local config gen params of the module as JSON
+        local_params_json: String,
+        /// The consensus config gen params of the module as JSON, must be the
+        /// same for all guardians
+        consensus_params_json: String,
+        /// The DKG of the module runs after this epoch has been processed
+        activation_epoch: u64,
+    },
+
+    /// Show the module addition votes and the scheduled module addition
+    ModuleAdditionStatus,
+
+    /// Cancel the addition of a module instance, e.g. because a guardian
+    /// can't take part in its DKG
+    CancelModuleAddition {
+        /// Id of the module instance that shouldn't be added
+        module_instance_id: ModuleInstanceId,
+    },
+
+    /// Show the misbehavior scores of the guardians and which ones are
+    /// quarantined
+    PeerScores,
+
+    /// Back up the server database into a directory on the server while it
+    /// keeps running
+    BackupDatabase {
+        /// Directory the backup is written to, relative to the data directory
+        /// of the guardian
+        path: PathBuf,
+        /// Only export the changes since the last snapshot in `path`
+        #[clap(long)]
+        incremental: bool,
+        /// Create a native database checkpoint instead of a logical snapshot
+        #[clap(long, conflicts_with = "incremental")]
+        checkpoint: bool,
+    },
+
+    /// Re-encrypt the configs of the guardian under a new password and switch
+    /// the API over to it, the current password keeps working for a few
+    /// minutes. If the guardian is started with `FM_PASSWORD` it has to be
+    /// updated before the next restart.
+    RotatePassword {
+        /// The new password, the guardian generates a random one if not set
+        #[clap(long)]
+        new_password: Option<String>,
+    },
+
+    /// Run an epoch now even if no guardian has anything to contribute
+    TriggerEpoch,
+
+    /// Show the connection status and misbehavior of the other guardians
+    Peers,
+
+    /// List the peg-outs that are being signed or awaiting confirmation
+    PendingPegOuts,
+
+    /// List deposits to previously used addresses that no e-cash was issued
+    /// for yet
+    UnclaimedDeposits,
+
+    /// Vote for freezing a UTXO of the federation, frozen UTXOs aren't spent by
+    /// peg-outs until a threshold of guardians votes to unfreeze them
+    FreezeUtxo {
+        /// The UTXO as `txid:vout`
+        outpoint: bitcoin::OutPoint,
+        /// Vote for unfreezing the UTXO instead
+        #[clap(long)]
+        unfreeze: bool,
+    },
+
+    /// List the UTXOs frozen by the guardians
+    FrozenUtxos,
+
+    /// List all UTXOs of the federation's wallet, including the frozen ones
+    WalletUtxos {
+        /// Also write the UTXOs to a CSV file
+        #[clap(long)]
+        csv: Option<PathBuf>,
+    },
+
+    /// Fetch the block hashes up to the consensus height from bitcoind again,
+    /// restoring the ones missing from the database
+    WalletResync {
+        /// First block height to fetch
+        #[clap(long)]
+        from_height: u32,
+    },
+
+    /// Show whether the primary and secondary bitcoin backend of the guardian
+    /// currently disagree
+    BackendDivergence,
+
+    /// Show whether the guardian's bitcoind is currently unreachable for the
+    /// wallet's proposals
+    BitcoindOutage,
+
+    /// Show the progress of sweeping the funds of legacy peg-in descriptors
+    /// into the current one
+    DescriptorMigration,
+
+    /// Vote for the daily limits of registered accounts, takes effect once a
+    /// threshold of guardians voted for the same policy. Only possible if
+    /// velocity limits are enabled in the federation config.
+    UpdateVelocityPolicy {
+        /// The complete new policy as JSON with the `tiers` mapping tier names
+        /// to msat limits and the `accounts` mapping account keys to tiers
+        policy_json: String,
+    },
+
+    /// Show the velocity policy votes that didn't reach the threshold yet
+    VelocityPolicyVotes,
+
+    /// Vote to exit the mint's safe mode after investigating the liability
+    /// violation that caused it. The mint resumes once a threshold of guardians
+    /// voted, the current shortfall is accepted from then on.
+    ExitSafeMode,
+
+    /// Show the votes to exit safe mode that didn't reach the threshold yet
+    SafeModeExitVotes,
+
+    /// Show the balance sheet of the federation broken down by module
+    Audit {
+        /// Also write the items of the balance sheet to a CSV file
+        #[clap(long)]
+        csv: Option<PathBuf>,
+    },
+
+    /// Show consensus health, balances and the status of every module at once
+    Dashboard,
 }
 
 #[derive(Debug, Clone, Subcommand)]
@@ -412,11 +680,39 @@ enum DevCmd {
         download_token: ClientConfigDownloadToken,
         #[clap(long = "id")]
         id: FederationId,
+        /// API endpoints of the guardians as `<peer id>=<url>`, separated by
+        /// commas
+        #[clap(long = "api-endpoint", value_parser = parse_api_endpoint, value_delimiter = ',')]
+        api_endpoints: Vec<(PeerId, Url)>,
+        /// Bitcoin network the federation runs on
+        #[clap(long = "network")]
+        network: Option<bitcoin::Network>,
+        /// Gateway recommended to clients
+        #[clap(long = "gateway", value_parser = parse_gateway_pub_key)]
+        gateway: Option<bitcoin::secp256k1::XOnlyPublicKey>,
     },
 
     /// Gets the current epoch count
     EpochCount,
 
+    /// Gets the anonymized activity stats of an epoch signed by the federation
+    EpochStats { epoch: u64 },
+
+    /// Gets a receipt of how the federation processed a transaction, signed by
+    /// the federation, to prove a payment to third parties
+    TransactionReceipt { txid: TransactionId },
+
+    /// Checks a receipt without contacting the federation
+    VerifyReceipt {
+        /// Hex encoded receipt, as printed by `transaction-receipt`
+        receipt: SignedTransactionReceipt,
+        /// Transaction the receipt has to be about
+        #[clap(long = "txid")]
+        txid: TransactionId,
+        #[clap(long = "federation-id")]
+        federation_id: FederationId,
+    },
+
     ConfigDecrypt {
         /// Encrypted config file
         #[arg(long = "in-file")]
@@ -494,14 +790,34 @@ impl FedimintCli {
 
     pub fn with_default_modules(self) -> Self {
         self.with_module(LightningClientGen)
-            .with_module(MintClientGen)
+            .with_module(MintClientGen::default())
             .with_module(WalletClientGen::default())
     }
 
+    /// Attaches the client modules registered by external crates linked into
+    /// the binary, modules attached before take precedence
+    #[cfg(feature = "plugins")]
+    pub fn with_plugins(mut self) -> Self {
+        fedimint_client::module::plugin::attach_plugins(&mut self.module_gens);
+        self
+    }
+
     pub async fn run(self) {
         let cli = Opts::parse();
+        let output_format = cli.output;
+
+        let result = self.handle_command(cli).await;
+        if output_format == OutputFormat::Json {
+            let envelope =
+                serde_json::to_string(&JsonEnvelope::new(&result)).expect("Envelope serializes");
+            let _ = writeln!(std::io::stdout(), "{envelope}");
+            if result.is_err() {
+                exit(1);
+            }
+            return;
+        }
 
-        match self.handle_command(cli).await {
+        match result {
             Ok(output) => {
                 // ignore if there's anyone reading the stuff we're writing out
                 let _ = writeln!(std::io::stdout(), "{output}");
@@ -518,15 +834,13 @@ impl FedimintCli {
             Command::JoinFederation { connect } => {
                 let connect_obj: WsClientConnectInfo = WsClientConnectInfo::from_str(&connect)
                     .map_err_cli_msg(CliErrorKind::InvalidValue, "invalid connect info")?;
-                let api = Arc::new(WsFederationApi::from_connect_info(&[connect_obj.clone()]))
-                    as Arc<dyn IGlobalFederationApi + Send + Sync + 'static>;
-                let cfg: ClientConfig = api
-                    .download_client_config(&connect_obj)
-                    .await
-                    .map_err_cli_msg(
-                        CliErrorKind::NetworkError,
-                        "couldn't download config from peer",
-                    )?;
+                let cfg: ClientConfig =
+                    WsFederationApi::download_verified_client_config(&connect_obj)
+                        .await
+                        .map_err_cli_msg(
+                            CliErrorKind::NetworkError,
+                            "couldn't download and verify config",
+                        )?;
                 std::fs::create_dir_all(cli.workdir()?)
                     .map_err_cli_msg(CliErrorKind::IOError, "failed to create config directory")?;
                 let cfg_path = cli.workdir()?.join("client.json");
@@ -612,6 +926,309 @@ impl FedimintCli {
                 cli.admin_client().await?.signal_upgrade().await?;
                 Ok(CliOutput::SignalUpgrade)
             }
+            Command::Admin(AdminCmd::MigrationJournal) => {
+                let journal = cli.admin_client().await?.migration_journal().await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(journal)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::UpdateMeta { meta_json }) => {
+                let meta: BTreeMap<String, String> = serde_json::from_str(&meta_json)
+                    .map_err_cli_msg(CliErrorKind::InvalidValue, "invalid meta")?;
+                cli.admin_client().await?.update_meta(meta).await?;
+                Ok(CliOutput::UpdateMeta)
+            }
+            Command::Admin(AdminCmd::MetaVotes) => {
+                let votes = cli.admin_client().await?.meta_votes().await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(votes)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::ProposeModuleAddition {
+                module_instance_id,
+                kind,
+                local_params_json,
+                consensus_params_json,
+                activation_epoch,
+            }) => {
+                let local = serde_json::from_str(&local_params_json)
+                    .map_err_cli_msg(CliErrorKind::InvalidValue, "invalid local params")?;
+                let consensus = serde_json::from_str(&consensus_params_json)
+                    .map_err_cli_msg(CliErrorKind::InvalidValue, "invalid consensus params")?;
+                cli.admin_client()
+                    .await?
+                    .propose_module_addition(ModuleAdditionRequest {
+                        module_instance_id,
+                        kind: ModuleKind::clone_from_str(&kind),
+                        params: ConfigGenModuleParams::new(Some(local), Some(consensus)),
+                        activation_epoch,
+                    })
+                    .await?;
+                Ok(CliOutput::ProposeModuleAddition)
+            }
+            Command::Admin(AdminCmd::ModuleAdditionStatus) => {
+                let status = cli.admin_client().await?.module_addition_status().await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(status)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::CancelModuleAddition { module_instance_id }) => {
+                cli.admin_client()
+                    .await?
+                    .cancel_module_addition(module_instance_id)
+                    .await?;
+                Ok(CliOutput::CancelModuleAddition)
+            }
+            Command::Admin(AdminCmd::PeerScores) => {
+                let scores = cli.admin_client().await?.peer_scores().await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(scores)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::TriggerEpoch) => {
+                cli.admin_client().await?.trigger_epoch().await?;
+                Ok(CliOutput::TriggerEpoch)
+            }
+            Command::Admin(AdminCmd::Peers) => {
+                let peers = cli.admin_client().await?.peer_connectivity().await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(peers)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::PendingPegOuts) => {
+                let wallet = wallet_instance_id(&cli.load_config()?)?;
+                let peg_outs: Vec<PendingPegOut> = cli
+                    .admin_client()
+                    .await?
+                    .module_request_auth(wallet, "pending_peg_outs", ApiRequestErased::default())
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(peg_outs)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::UnclaimedDeposits) => {
+                let wallet = wallet_instance_id(&cli.load_config()?)?;
+                let deposits: Vec<(bitcoin::OutPoint, UnclaimedDeposit)> = cli
+                    .admin_client()
+                    .await?
+                    .module_request_auth(wallet, "unclaimed_deposits", ApiRequestErased::default())
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(deposits)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::FreezeUtxo { outpoint, unfreeze }) => {
+                let wallet = wallet_instance_id(&cli.load_config()?)?;
+                cli.admin_client()
+                    .await?
+                    .module_request_auth::<()>(
+                        wallet,
+                        "freeze_utxo",
+                        ApiRequestErased::new(UtxoFreezeVote {
+                            outpoint,
+                            frozen: !unfreeze,
+                        }),
+                    )
+                    .await?;
+                Ok(CliOutput::FreezeUtxo {
+                    outpoint,
+                    frozen: !unfreeze,
+                })
+            }
+            Command::Admin(AdminCmd::FrozenUtxos) => {
+                let wallet = wallet_instance_id(&cli.load_config()?)?;
+                let utxos: Vec<(bitcoin::OutPoint, SpendableUTXO)> = cli
+                    .admin_client()
+                    .await?
+                    .module_request_auth(wallet, "frozen_utxos", ApiRequestErased::default())
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(utxos)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::WalletUtxos { csv }) => {
+                let wallet = wallet_instance_id(&cli.load_config()?)?;
+                let utxos: Vec<WalletUtxo> = cli
+                    .admin_client()
+                    .await?
+                    .module_request_auth(wallet, "utxos", ApiRequestErased::default())
+                    .await?;
+                if let Some(path) = csv {
+                    fs::write(path, wallet_utxos_csv(&utxos))
+                        .map_err_cli_msg(CliErrorKind::IOError, "couldn't write utxo csv")?;
+                }
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(utxos)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::WalletResync { from_height }) => {
+                let wallet = wallet_instance_id(&cli.load_config()?)?;
+                let response: BlockResyncResponse = cli
+                    .admin_client()
+                    .await?
+                    .module_request_auth(
+                        wallet,
+                        "resync_block_hashes",
+                        ApiRequestErased::new(from_height),
+                    )
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(response)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::BackendDivergence) => {
+                let wallet = wallet_instance_id(&cli.load_config()?)?;
+                let divergence: Option<BackendDivergence> = cli
+                    .admin_client()
+                    .await?
+                    .module_request_auth(wallet, "backend_divergence", ApiRequestErased::default())
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(divergence)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::BitcoindOutage) => {
+                let wallet = wallet_instance_id(&cli.load_config()?)?;
+                let outage: Option<BitcoindOutage> = cli
+                    .admin_client()
+                    .await?
+                    .module_request_auth(wallet, "bitcoind_outage", ApiRequestErased::default())
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(outage)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::DescriptorMigration) => {
+                let wallet = wallet_instance_id(&cli.load_config()?)?;
+                let status: DescriptorMigrationStatus = cli
+                    .admin_client()
+                    .await?
+                    .module_request_auth(
+                        wallet,
+                        "descriptor_migration",
+                        ApiRequestErased::default(),
+                    )
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(status)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::UpdateVelocityPolicy { policy_json }) => {
+                let policy: VelocityPolicy = serde_json::from_str(&policy_json)
+                    .map_err_cli_msg(CliErrorKind::InvalidValue, "invalid velocity policy")?;
+                let mint = mint_instance_id(&cli.load_config()?)?;
+                cli.admin_client()
+                    .await?
+                    .module_request_auth::<()>(
+                        mint,
+                        "update_velocity_policy",
+                        ApiRequestErased::new(policy),
+                    )
+                    .await?;
+                Ok(CliOutput::UpdateVelocityPolicy)
+            }
+            Command::Admin(AdminCmd::VelocityPolicyVotes) => {
+                let mint = mint_instance_id(&cli.load_config()?)?;
+                let votes: BTreeMap<PeerId, VelocityPolicy> = cli
+                    .admin_client()
+                    .await?
+                    .module_request_auth(mint, "velocity_policy_votes", ApiRequestErased::default())
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(votes)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::ExitSafeMode) => {
+                let mint = mint_instance_id(&cli.load_config()?)?;
+                let admin_client = cli.admin_client().await?;
+                let violation: Option<LiabilityViolation> = admin_client
+                    .module_request_auth(mint, "safe_mode", ApiRequestErased::default())
+                    .await?;
+                let violation = violation
+                    .ok_or_cli_msg(CliErrorKind::GeneralFailure, "the mint is not in safe mode")?;
+                admin_client
+                    .module_request_auth::<()>(
+                        mint,
+                        "exit_safe_mode",
+                        ApiRequestErased::new(violation),
+                    )
+                    .await?;
+                Ok(CliOutput::ExitSafeMode)
+            }
+            Command::Admin(AdminCmd::SafeModeExitVotes) => {
+                let mint = mint_instance_id(&cli.load_config()?)?;
+                let votes: BTreeMap<PeerId, LiabilityViolation> = cli
+                    .admin_client()
+                    .await?
+                    .module_request_auth(mint, "safe_mode_exit_votes", ApiRequestErased::default())
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(votes)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::Audit { csv }) => {
+                let report = cli.admin_client().await?.audit().await?;
+                if let Some(path) = csv {
+                    fs::write(path, audit_csv(&report))
+                        .map_err_cli_msg(CliErrorKind::IOError, "couldn't write audit csv")?;
+                }
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(report)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::Dashboard) => {
+                let status = cli.admin_client().await?.admin_status().await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(status)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::BackupDatabase {
+                path,
+                incremental,
+                checkpoint,
+            }) => {
+                let backup = cli
+                    .admin_client()
+                    .await?
+                    .backup_database(DatabaseBackupRequest {
+                        path,
+                        incremental,
+                        checkpoint,
+                    })
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(backup)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Admin(AdminCmd::RotatePassword { new_password }) => {
+                let rotation = cli
+                    .admin_client()
+                    .await?
+                    .rotate_password(new_password.map(ApiAuth))
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(rotation)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
             Command::Dev(DevCmd::Api {
                 method,
                 params,
@@ -683,17 +1300,26 @@ impl FedimintCli {
                         .consensus_encode_to_hex()
                         .expect("encodes"),
                     id: connect_info.id,
+                    api_endpoints: connect_info.api_endpoints,
+                    network: connect_info.network,
+                    gateway: connect_info.gateway,
                 })
             }
             Command::Dev(DevCmd::EncodeConnectInfo {
                 url,
                 download_token,
                 id,
+                api_endpoints,
+                network,
+                gateway,
             }) => Ok(CliOutput::ConnectInfo {
                 connect_info: WsClientConnectInfo {
                     url,
                     download_token,
                     id,
+                    api_endpoints: api_endpoints.into_iter().collect(),
+                    network,
+                    gateway,
                 },
             }),
             Command::Dev(DevCmd::EpochCount) => {
@@ -705,6 +1331,37 @@ impl FedimintCli {
                     .await?;
                 Ok(CliOutput::EpochCount { count })
             }
+            Command::Dev(DevCmd::EpochStats { epoch }) => {
+                let client = cli.build_client_ng(&self.module_gens).await?;
+                let stats = client
+                    .api()
+                    .fetch_epoch_stats(epoch, client.federation_id())
+                    .await?;
+                Ok(CliOutput::Raw(
+                    serde_json::to_value(stats.stats)
+                        .map_err_cli_msg(CliErrorKind::GeneralFailure, "invalid response")?,
+                ))
+            }
+            Command::Dev(DevCmd::TransactionReceipt { txid }) => {
+                let client = cli.build_client_ng(&self.module_gens).await?;
+                let receipt = client
+                    .api()
+                    .fetch_transaction_receipt(txid, client.federation_id())
+                    .await?;
+                Ok(CliOutput::Raw(json!({
+                    "receipt": receipt.receipt,
+                    "encoded": receipt.to_string(),
+                })))
+            }
+            Command::Dev(DevCmd::VerifyReceipt {
+                receipt,
+                txid,
+                federation_id,
+            }) => {
+                verify_receipt(&receipt, &federation_id, txid)
+                    .map_err_cli_msg(CliErrorKind::InvalidValue, "invalid receipt")?;
+                Ok(CliOutput::Raw(json!({ "receipt": receipt.receipt })))
+            }
             Command::Dev(DevCmd::ConfigDecrypt {
                 in_file,
                 out_file,
@@ -763,6 +1420,14 @@ impl FedimintCli {
                     transaction: (format!("{tx:?}")),
                 })
             }
+            Command::Decode(command) => {
+                let cfg = cli.load_config()?;
+                let decoders = cli.load_decoders(&cfg, &self.module_gens);
+                Ok(CliOutput::Raw(
+                    decode::handle_decode_command(command, &cfg, &decoders)
+                        .map_err_cli_msg(CliErrorKind::SerializationError, "failed to decode")?,
+                ))
+            }
             Command::Completion { shell } => {
                 clap_complete::generate(
                     shell,
@@ -790,6 +1455,60 @@ pub struct LnInvoiceResponse {
     pub invoice: String,
 }
 
+fn mint_instance_id(cfg: &ClientConfig) -> CliResult<ModuleInstanceId> {
+    cfg.modules
+        .iter()
+        .find(|(_, module)| module.kind() == &fedimint_mint_client::KIND)
+        .map(|(id, _)| *id)
+        .ok_or_cli_msg(CliErrorKind::InvalidValue, "federation has no mint module")
+}
+
+fn wallet_instance_id(cfg: &ClientConfig) -> CliResult<ModuleInstanceId> {
+    cfg.modules
+        .iter()
+        .find(|(_, module)| module.kind() == &fedimint_wallet_client::KIND)
+        .map(|(id, _)| *id)
+        .ok_or_cli_msg(
+            CliErrorKind::InvalidValue,
+            "federation has no wallet module",
+        )
+}
+
+/// One row per item of the balance sheet, quoting names since they are debug
+/// formatted database keys
+fn audit_csv(report: &AuditReport) -> String {
+    let mut csv = "module_instance_id,kind,item,msat\n".to_string();
+    for (module_instance_id, module) in &report.modules {
+        for item in &module.items {
+            csv.push_str(&format!(
+                "{module_instance_id},{},\"{}\",{}\n",
+                module.kind,
+                item.name.replace('"', "\"\""),
+                item.milli_sat
+            ));
+        }
+    }
+    csv
+}
+
+/// One row per UTXO, unknown confirmations are left empty
+fn wallet_utxos_csv(utxos: &[WalletUtxo]) -> String {
+    let mut csv = "outpoint,sat,tweak_fingerprint,frozen,confirmations\n".to_string();
+    for utxo in utxos {
+        csv.push_str(&format!(
+            "{},{},{},{},{}\n",
+            utxo.outpoint,
+            utxo.amount.to_sat(),
+            utxo.tweak_fingerprint,
+            utxo.frozen,
+            utxo.confirmations
+                .map(|confirmations| confirmations.to_string())
+                .unwrap_or_default()
+        ));
+    }
+    csv
+}
+
 /// Convert clap arguments to backup metadata
 fn metadata_from_clap_cli(metadata: Vec<String>) -> Result<BTreeMap<String, String>, CliError> {
     let metadata: BTreeMap<String, String> = metadata
@@ -811,6 +1530,86 @@ fn metadata_from_clap_cli(metadata: Vec<String>) -> Result<BTreeMap<String, Stri
     Ok(metadata)
 }
 
+#[test]
+fn json_envelope_test() {
+    let result: CliOutputResult = Ok(CliOutput::EpochCount { count: 3 });
+    assert_eq!(
+        serde_json::to_value(JsonEnvelope::new(&result)).unwrap(),
+        json!({
+            "schema_version": CLI_JSON_SCHEMA_VERSION,
+            "ok": true,
+            "result": { "count": 3 },
+        })
+    );
+
+    let result: CliOutputResult = Err(anyhow::format_err!("connection refused"))
+        .map_err_cli_msg(CliErrorKind::NetworkError, "failed to connect");
+    assert_eq!(
+        serde_json::to_value(JsonEnvelope::new(&result)).unwrap(),
+        json!({
+            "schema_version": CLI_JSON_SCHEMA_VERSION,
+            "ok": false,
+            "error": {
+                "code": 1,
+                "kind": "NetworkError",
+                "message": "failed to connect",
+                "details": "connection refused",
+            },
+        })
+    );
+}
+
+#[test]
+fn audit_csv_test() {
+    use fedimint_core::admin_client::ModuleAuditReport;
+    use fedimint_core::module::audit::AuditItem;
+
+    let report = AuditReport {
+        epoch_count: 1,
+        net_assets_msat: 1000,
+        modules: BTreeMap::from([(
+            2,
+            ModuleAuditReport {
+                kind: ModuleKind::from_static_str("wallet"),
+                net_assets_msat: 1000,
+                items: vec![AuditItem {
+                    name: "UTXOKey(\"a\", 1)".to_string(),
+                    milli_sat: 1000,
+                }],
+            },
+        )]),
+    };
+    assert_eq!(
+        audit_csv(&report),
+        "module_instance_id,kind,item,msat\n2,wallet,\"UTXOKey(\"\"a\"\", 1)\",1000\n"
+    );
+}
+
+#[test]
+fn wallet_utxos_csv_test() {
+    let utxo = WalletUtxo {
+        outpoint: bitcoin::OutPoint::null(),
+        amount: bitcoin::Amount::from_sat(1000),
+        tweak_fingerprint: WalletUtxo::tweak_fingerprint(&[0; 32]),
+        frozen: false,
+        confirmations: Some(6),
+    };
+    let frozen = WalletUtxo {
+        frozen: true,
+        confirmations: None,
+        ..utxo.clone()
+    };
+    let outpoint = format!("{}:4294967295", "0".repeat(64));
+    assert_eq!(
+        wallet_utxos_csv(&[utxo, frozen]),
+        format!(
+            "outpoint,sat,tweak_fingerprint,frozen,confirmations\n\
+             {outpoint},1000,66687aad,false,6\n\
+             {outpoint},1000,66687aad,true,\n"
+        )
+    );
+}
+
 #[test]
 fn metadata_from_clap_cli_test() {
     for (args, expected) in [
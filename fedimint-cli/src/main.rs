@@ -2,6 +2,9 @@ use fedimint_cli::FedimintCli;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    FedimintCli::new()?.with_default_modules().run().await;
+    let cli = FedimintCli::new()?.with_default_modules();
+    #[cfg(feature = "plugins")]
+    let cli = cli.with_plugins();
+    cli.run().await;
     Ok(())
 }
@@ -1,9 +1,11 @@
 use std::num::ParseIntError;
 
+use anyhow::format_err;
 use bitcoin_hashes::hex::FromHex;
 use fedimint_core::encoding::Decodable;
 use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::PeerId;
+use url::Url;
 
 pub fn from_hex<D: Decodable>(s: &str) -> Result<D, anyhow::Error> {
     let bytes = Vec::from_hex(s)?;
@@ -16,3 +18,11 @@ pub fn from_hex<D: Decodable>(s: &str) -> Result<D, anyhow::Error> {
 pub fn parse_peer_id(s: &str) -> Result<PeerId, ParseIntError> {
     Ok(PeerId::from(s.parse::<u16>()?))
 }
+
+/// Parses the API endpoint of a guardian given as `<peer id>=<url>`
+pub fn parse_api_endpoint(s: &str) -> Result<(PeerId, Url), anyhow::Error> {
+    let (peer_id, url) = s
+        .split_once('=')
+        .ok_or_else(|| format_err!("expected <peer id>=<url>"))?;
+    Ok((parse_peer_id(peer_id)?, url.parse()?))
+}
@@ -4,9 +4,11 @@ use std::pin::Pin;
 use std::sync::Arc;
 
 use async_trait::async_trait;
-use fedimint_core::api::{DynModuleApi, IFederationApi, IGlobalFederationApi, JsonRpcResult};
+use fedimint_core::api::{
+    DynGlobalApi, DynModuleApi, IFederationApi, IGlobalFederationApi, JsonRpcResult,
+};
 use fedimint_core::core::ModuleInstanceId;
-use fedimint_core::module::ApiRequest;
+use fedimint_core::module::{ApiRequest, CorrelationId};
 use fedimint_core::PeerId;
 use futures::Future;
 use jsonrpsee_types::error::INVALID_PARAMS_CODE;
@@ -110,6 +112,10 @@ where
         unimplemented!()
     }
 
+    fn with_correlation_id(&self, _correlation_id: CorrelationId) -> DynGlobalApi {
+        unimplemented!()
+    }
+
     async fn request_raw(
         &self,
         _peer_id: PeerId,
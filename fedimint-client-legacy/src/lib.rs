@@ -873,7 +873,10 @@ impl Client<UserClientConfig> {
                 let amount = c.amount;
                 (contract_id, amount)
             }
-            LightningOutput::Offer(_) | LightningOutput::CancelOutgoing { .. } => {
+            LightningOutput::Offer(_)
+            | LightningOutput::CancelOutgoing { .. }
+            | LightningOutput::CancelableOffer { .. }
+            | LightningOutput::CancelOffer { .. } => {
                 panic!()
             } // FIXME: impl TryFrom
         };
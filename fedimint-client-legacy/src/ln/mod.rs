@@ -67,12 +67,13 @@ impl ClientModule for LnClient {
                 amount: account_output.amount,
                 fee: self.config.fee_consensus.contract_output,
             },
-            LightningOutput::Offer(_) | LightningOutput::CancelOutgoing { .. } => {
-                TransactionItemAmount {
-                    amount: Amount::ZERO,
-                    fee: Amount::ZERO,
-                }
-            }
+            LightningOutput::Offer(_)
+            | LightningOutput::CancelOutgoing { .. }
+            | LightningOutput::CancelableOffer { .. }
+            | LightningOutput::CancelOffer { .. } => TransactionItemAmount {
+                amount: Amount::ZERO,
+                fee: Amount::ZERO,
+            },
         }
     }
 }
@@ -26,7 +26,7 @@ use tracing::{error, info};
 use super::db::NextECashNoteIndexKeyPrefix;
 use super::*;
 use crate::api::MintFederationApi;
-use crate::modules::mint::{MintConsensusItem, MintInput, MintOutput};
+use crate::modules::mint::{MintConsensusItem, MintInput, MintOutput, MintSignatureItem};
 
 impl MintClient {
     /// Prepare an encrypted backup and send it to federation for storing
@@ -741,7 +741,7 @@ impl EcashRecoveryTracker {
         }
     }
 
-    pub fn handle_output_confirmation(&mut self, peer_id: PeerId, sigs: &MintConsensusItem) {
+    pub fn handle_output_confirmation(&mut self, peer_id: PeerId, sigs: &MintSignatureItem) {
         let enough_shares = if let Some((output_data, peer_shares)) =
             self.pending_outputs.get_mut(&sigs.out_point)
         {
@@ -896,10 +896,15 @@ impl EcashRecoveryTracker {
             }
             ConsensusItem::Module(module_item) => {
                 if module_item.module_instance_id() == LEGACY_HARDCODED_INSTANCE_ID_MINT {
-                    let mint_item = module_item
+                    let mint_item = match module_item
                         .as_any()
                         .downcast_ref::<MintConsensusItem>()
-                        .expect("mint key just checked");
+                        .expect("mint key just checked")
+                    {
+                        MintConsensusItem::PartialSignatures(item) => item,
+                        MintConsensusItem::VelocityPolicy(_)
+                        | MintConsensusItem::ExitSafeMode(_) => return,
+                    };
 
                     self.handle_output_confirmation(peer_id, mint_item);
                 }
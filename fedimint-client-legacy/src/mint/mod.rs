@@ -194,7 +194,7 @@ impl MintClient {
         // remove the spent ecash from the DB
         let mut input_ecash: Vec<(Amount, SpendableNote)> = vec![];
         for input in &tx.inputs {
-            if let Input::Mint(MintInput(notes)) = input {
+            if let Input::Mint(MintInput(notes, _, _)) = input {
                 for (amount, note) in notes.clone() {
                     let key = NoteKey {
                         amount,
@@ -265,7 +265,7 @@ impl MintClient {
         let denominations = TieredSummary::represent_amount(
             amount,
             &self.summary().await,
-            &self.config.tbs_pks,
+            &self.config.issued_tiers(),
             notes_per_denomination,
         );
         for (amt, num) in denominations.iter() {
@@ -308,7 +308,7 @@ impl MintClient {
             })
             .collect::<Result<Vec<_>>>()?;
         let (key_pairs, input) = note_key_pairs.into_iter().unzip();
-        Ok((key_pairs, Input::Mint(MintInput(input))))
+        Ok((key_pairs, Input::Mint(MintInput(input, vec![], vec![]))))
     }
 
     pub async fn notes(&self) -> TieredMulti<SpendableNote> {
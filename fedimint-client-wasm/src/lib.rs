@@ -0,0 +1,150 @@
+//! JavaScript API of the Fedimint client
+//!
+//! Exposes joining a federation and spending and receiving e-cash to web
+//! wallets, with the client state persisted in IndexedDB.
+#![cfg(target_family = "wasm")]
+
+use std::time::Duration;
+
+use fedimint_client::secret::PlainRootSecretStrategy;
+use fedimint_client::{Client, ClientBuilder};
+use fedimint_core::api::{WsClientConnectInfo, WsFederationApi};
+use fedimint_core::config::ClientConfig;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::task::TaskGroup;
+use fedimint_core::{Amount, TieredMulti};
+use fedimint_indexeddb::IndexedDb;
+use fedimint_ln_client::LightningClientGen;
+use fedimint_mint_client::{
+    MintClientExt, MintClientGen, ReissueExternalNotesState, SpendableNote,
+};
+use fedimint_wallet_client::WalletClientGen;
+use futures::StreamExt;
+use js_sys::Promise;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::future_to_promise;
+
+/// Spent notes that the recipient didn't reissue within this time are
+/// reclaimed automatically
+const SPEND_CANCEL_AFTER: Duration = Duration::from_secs(3600);
+
+#[wasm_bindgen]
+pub struct WasmClient {
+    client: Client,
+    // Keeps the executor running as long as the client is alive
+    _task_group: TaskGroup,
+}
+
+#[wasm_bindgen]
+impl WasmClient {
+    /// Downloads and verifies the config of the federation in `connect_info`
+    /// and starts a client storing its state in the IndexedDB database
+    /// `db_name`.
+    ///
+    /// The config has to be stored by the caller, e.g. in local storage, to
+    /// reopen the client with [`WasmClient::open`].
+    pub async fn join(connect_info: String, db_name: String) -> Result<WasmClient, JsError> {
+        let connect_info: WsClientConnectInfo = connect_info.parse().map_err(js_error)?;
+        let config = WsFederationApi::download_verified_client_config(&connect_info).await?;
+        Self::start(config, &db_name).await
+    }
+
+    /// Reopens a client that previously joined with [`WasmClient::join`]
+    pub async fn open(config: String, db_name: String) -> Result<WasmClient, JsError> {
+        let config: ClientConfig = serde_json::from_str(&config)?;
+        Self::start(config, &db_name).await
+    }
+
+    /// The client config as JSON
+    pub fn config(&self) -> Result<String, JsError> {
+        Ok(serde_json::to_string(self.client.get_config())?)
+    }
+
+    // Async methods can't borrow `self`, so they return a promise of a future
+    // owning a clone of the client instead
+
+    /// Balance available for spending in msat
+    pub fn balance(&self) -> Promise {
+        let client = self.client.clone();
+        future_to_promise(async move { Ok(client.get_balance().await.msats.into()) })
+    }
+
+    /// Takes notes worth at least `amount_msat` out of the wallet and returns
+    /// them base64 encoded, to be given to the recipient
+    pub fn spend(&self, amount_msat: u64) -> Promise {
+        let client = self.client.clone();
+        future_to_promise(async move { Ok(spend(&client, amount_msat).await?.into()) })
+    }
+
+    /// Reissues base64 encoded notes received from someone else and returns
+    /// their amount in msat once they are ours
+    pub fn receive(&self, notes: String) -> Promise {
+        let client = self.client.clone();
+        future_to_promise(async move { Ok(receive(&client, &notes).await?.into()) })
+    }
+}
+
+impl WasmClient {
+    async fn start(config: ClientConfig, db_name: &str) -> Result<WasmClient, JsError> {
+        let mut builder = ClientBuilder::default();
+        builder.with_module(LightningClientGen);
+        builder.with_module(MintClientGen::default());
+        builder.with_module(WalletClientGen::default());
+        builder.with_primary_module(1);
+        builder.with_config(config);
+        builder.with_database(IndexedDb::open(db_name).await.map_err(js_error)?);
+
+        let mut task_group = TaskGroup::new();
+        let client = builder
+            .build::<PlainRootSecretStrategy>(&mut task_group)
+            .await
+            .map_err(js_error)?;
+        Ok(WasmClient {
+            client,
+            _task_group: task_group,
+        })
+    }
+}
+
+async fn spend(client: &Client, amount_msat: u64) -> Result<String, JsError> {
+    let (_operation_id, notes) = client
+        .spend_notes(Amount::from_msats(amount_msat), SPEND_CANCEL_AFTER, ())
+        .await
+        .map_err(js_error)?;
+    let mut bytes = Vec::new();
+    notes.consensus_encode(&mut bytes)?;
+    Ok(base64::encode(bytes))
+}
+
+async fn receive(client: &Client, notes: &str) -> Result<u64, JsError> {
+    let notes = TieredMulti::<SpendableNote>::consensus_decode(
+        &mut std::io::Cursor::new(base64::decode(notes)?),
+        &ModuleDecoderRegistry::default(),
+    )?;
+    let amount = notes.total_amount();
+
+    let operation_id = client
+        .reissue_external_notes(notes, ())
+        .await
+        .map_err(js_error)?;
+    let mut updates = client
+        .subscribe_reissue_external_notes(operation_id)
+        .await
+        .map_err(js_error)?
+        .into_stream();
+    while let Some(update) = updates.next().await {
+        match update {
+            ReissueExternalNotesState::Done => return Ok(amount.msats),
+            ReissueExternalNotesState::Failed(error) => {
+                return Err(JsError::new(&format!("Reissue failed: {error}")))
+            }
+            _ => {}
+        }
+    }
+    Err(JsError::new("Reissue ended without an outcome"))
+}
+
+fn js_error(error: anyhow::Error) -> JsError {
+    JsError::new(&format!("{error:#}"))
+}
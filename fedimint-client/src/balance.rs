@@ -0,0 +1,39 @@
+use fedimint_core::Amount;
+use serde::{Deserialize, Serialize};
+
+use crate::sm::OperationId;
+
+/// A change of the client balance, see [`crate::Client::subscribe_balance_changes`]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BalanceEvent {
+    /// Balance of the client after the change
+    pub balance: Amount,
+    /// Operation that changed the balance
+    pub operation_id: OperationId,
+    /// Amount the operation added to the balance, `None` if it spent funds
+    pub received: Option<Amount>,
+    pub kind: BalanceEventKind,
+}
+
+/// Why the balance changed
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum BalanceEventKind {
+    /// E-cash received out of band was reissued
+    EcashReceived,
+    /// Funds from a peg-in were claimed
+    PegInConfirmed,
+    /// A lightning payment to us was settled
+    LightningPaymentSettled,
+    /// Any other change, e.g. funds were spent or change was returned
+    Other,
+}
+
+/// A change of the balance of the primary module, see
+/// [`crate::module::ClientModule::subscribe_balance_changes`]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ModuleBalanceChange {
+    /// Operation of the state transition that changed the balance
+    pub operation_id: OperationId,
+    /// Amount added to the balance, `None` if funds were spent
+    pub received: Option<Amount>,
+}
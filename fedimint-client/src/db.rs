@@ -8,6 +8,7 @@ use serde::Serialize;
 use strum_macros::EnumIter;
 
 use crate::oplog::OperationLogEntry;
+use crate::scheduler::{ScheduledPayment, ScheduledPaymentId};
 use crate::secret::RootSecretStrategy;
 use crate::sm::OperationId;
 use crate::ClientSecret;
@@ -18,6 +19,7 @@ pub enum DbKeyPrefix {
     ClientSecret = 0x29,
     OperationLog = 0x2c,
     ChronologicalOperationLog = 0x2d,
+    ScheduledPayment = 0x2e,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -91,3 +93,20 @@ impl_db_lookup!(
     key = ChronologicalOperationLogKey,
     query_prefix = ChronologicalOperationLogKeyPrefix
 );
+
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct ScheduledPaymentKey(pub ScheduledPaymentId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ScheduledPaymentKeyPrefix;
+
+impl_db_record!(
+    key = ScheduledPaymentKey,
+    value = ScheduledPayment,
+    db_prefix = DbKeyPrefix::ScheduledPayment
+);
+
+impl_db_lookup!(
+    key = ScheduledPaymentKey,
+    query_prefix = ScheduledPaymentKeyPrefix
+);
@@ -0,0 +1,40 @@
+//! Fee previews, so wallets can show the total cost of an operation before
+//! submitting it
+//!
+//! Module clients describe their operations with types implementing
+//! [`FeeEstimate`], which are previewed with [`Client::estimate_fees`]. The
+//! federation fees are computed by funding the transaction of the operation
+//! like it would be submitted, but without committing the selected funds.
+use fedimint_core::task::{MaybeSend, MaybeSync};
+use fedimint_core::{apply, async_trait_maybe_send, Amount};
+use serde::{Deserialize, Serialize};
+
+use crate::Client;
+
+/// Fees an operation is expected to pay, by who charges them
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FeeBreakdown {
+    /// Fees of the inputs and outputs of the federation transaction,
+    /// including the ones used for funding and change
+    pub federation_fee: Amount,
+    /// Fee of the bitcoin transaction of a peg-out
+    pub on_chain_fee: Amount,
+    /// Routing fee charged by the gateway of a lightning payment
+    pub gateway_fee: Amount,
+}
+
+impl FeeBreakdown {
+    pub fn total(&self) -> Amount {
+        self.federation_fee + self.on_chain_fee + self.gateway_fee
+    }
+}
+
+/// An operation whose fees can be previewed with [`Client::estimate_fees`]
+///
+/// Estimates are only valid at the time they are made: on-chain fee rates,
+/// gateway fees and the notes selected for funding can change until the
+/// operation is submitted.
+#[apply(async_trait_maybe_send!)]
+pub trait FeeEstimate: MaybeSend + MaybeSync {
+    async fn estimate_fees(&self, client: &Client) -> anyhow::Result<FeeBreakdown>;
+}
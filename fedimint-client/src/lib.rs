@@ -65,12 +65,17 @@
 //! actually available ones will be determined by the config loaded at runtime.
 //!
 //! For a hacky instantiation of a complete client see the [`ng` subcommand of `fedimint-cli`](https://github.com/fedimint/fedimint/blob/55f9d88e17d914b92a7018de677d16e57ed42bf6/fedimint-cli/src/ng.rs#L56-L73).
+//!
+//! Explorers and monitoring tools that only display public state of a
+//! federation can use an [`observer::FederationObserver`] instead, which
+//! doesn't need a root secret or a database.
 
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, HashSet};
 use std::fmt::{Debug, Formatter};
 use std::io::{Error, Read, Write};
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{anyhow, bail};
 use async_stream::stream;
@@ -82,6 +87,7 @@ use fedimint_core::config::{ClientConfig, FederationId, ModuleGenRegistry};
 use fedimint_core::core::{DynInput, DynOutput, IInput, IOutput, ModuleInstanceId, ModuleKind};
 use fedimint_core::db::{AutocommitError, Database, DatabaseTransaction, IDatabase};
 use fedimint_core::encoding::{Decodable, DecodeError, Encodable};
+use fedimint_core::epoch::{EpochItemFilter, FilteredEpochItems};
 use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::module::{
     ApiVersion, MultiApiVersion, SupportedApiVersionsSummary, SupportedCoreApiVersions,
@@ -106,7 +112,9 @@ use serde::Serialize;
 use tracing::{info, warn};
 
 use crate::backup::Metadata;
+use crate::balance::{BalanceEvent, BalanceEventKind};
 use crate::db::ClientSecretKey;
+use crate::fees::{FeeBreakdown, FeeEstimate};
 use crate::module::gen::{
     ClientModuleGen, ClientModuleGenRegistry, DynClientModuleGen, IClientModuleGen,
 };
@@ -120,6 +128,7 @@ use crate::sm::{
     ClientSMDatabaseTransaction, DynState, Executor, GlobalContext, IState, Notifier, OperationId,
     OperationState, State,
 };
+use crate::transaction::psft::PsftContribution;
 use crate::transaction::{
     tx_submission_sm_decoder, ClientInput, ClientOutput, TransactionBuilder,
     TransactionBuilderBalance, TxSubmissionContext, TxSubmissionError, TxSubmissionStates,
@@ -128,16 +137,26 @@ use crate::transaction::{
 
 /// Client backup
 pub mod backup;
+/// Events describing changes of the client balance
+pub mod balance;
 /// Database keys used by the client
 pub mod db;
+/// Previews of the fees of operations
+pub mod fees;
 /// Module client interface definitions
 pub mod module;
+/// Read-only access to a federation without a client secret
+pub mod observer;
 /// Operation log subsystem of the client
 pub mod oplog;
+/// Recurring payments made by the client on a schedule
+pub mod scheduler;
 /// Secret handling & derivation
 pub mod secret;
 /// Client state machine interfaces and executor implementation
 pub mod sm;
+/// Encrypted exports of the client state for moving devices
+pub mod snapshot;
 /// Structs and interfaces to construct Fedimint transactions
 pub mod transaction;
 
@@ -363,6 +382,8 @@ impl Debug for ClientInner {
 #[derive(Clone, Debug)]
 struct ModuleGlobalClientContext {
     client: Arc<ClientInner>,
+    /// The client's API, tagging requests with the operation's correlation id
+    api: DynGlobalApi,
     module_instance_id: ModuleInstanceId,
     operation: OperationId,
 }
@@ -374,7 +395,7 @@ impl IGlobalClientContext for ModuleGlobalClientContext {
     }
 
     fn api(&self) -> &DynGlobalApi {
-        &self.client.api
+        &self.api
     }
 
     fn decoders(&self) -> &ModuleDecoderRegistry {
@@ -473,6 +494,10 @@ pub struct Client {
 const SUPPORTED_CORE_API_VERSIONS: &[fedimint_core::module::ApiVersion] =
     &[ApiVersion { major: 0, minor: 0 }];
 
+/// How long to wait before retrying to fetch the items of an epoch, see
+/// [`Client::subscribe_epoch_items`]
+const EPOCH_ITEMS_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
 pub type ModuleGlobalContextGen = ContextGen<DynGlobalClientContext>;
 
 impl Client {
@@ -606,19 +631,31 @@ impl Client {
         let id = self
             .get_first_instance(module_kind)
             .unwrap_or_else(|| panic!("No modules found of kind {module_kind}"));
+        self.get_module(id).unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    /// Returns a reference to a typed module client instance by id, for
+    /// federations running several instances of a module kind
+    pub fn get_module<M: ClientModule>(
+        &self,
+        id: ModuleInstanceId,
+    ) -> anyhow::Result<(&M, ClientModuleInstance)> {
         let module: &M = self
             .inner
             .try_get_module(id)
-            .unwrap_or_else(|| panic!("Unknown module instance {id}"))
+            .ok_or(anyhow!("Unknown module instance {id}"))?
             .as_any()
             .downcast_ref::<M>()
-            .unwrap_or_else(|| panic!("Module is not of type {}", std::any::type_name::<M>()));
+            .ok_or(anyhow!(
+                "Module {id} is not of type {}",
+                std::any::type_name::<M>()
+            ))?;
         let instance = ClientModuleInstance {
             id,
             db: self.db().new_isolated(id),
             api: self.api().with_module(id),
         };
-        (module, instance)
+        Ok((module, instance))
     }
 
     pub fn get_module_client_dyn(
@@ -664,6 +701,17 @@ impl Client {
             .map(|(instance_id, _, _)| instance_id)
     }
 
+    /// Returns the instance ids of all modules of the given kind, ordered by
+    /// their instance id
+    pub fn get_instances(&self, module_kind: &ModuleKind) -> Vec<ModuleInstanceId> {
+        self.inner
+            .modules
+            .iter_modules()
+            .filter(|(_, kind, _module)| *kind == module_kind)
+            .map(|(instance_id, _, _)| instance_id)
+            .collect()
+    }
+
     /// Returns the data from which the client's root secret is derived (e.g.
     /// BIP39 seed phrase struct).
     pub async fn root_secret_encoding<S>(&self) -> S::Encoding
@@ -698,6 +746,48 @@ impl Client {
             .expect("primary module must be present")
     }
 
+    /// Itemized fees `operation` would pay if it was submitted now
+    pub async fn estimate_fees(
+        &self,
+        operation: &impl FeeEstimate,
+    ) -> anyhow::Result<FeeBreakdown> {
+        operation.estimate_fees(self).await
+    }
+
+    /// Federation fees of the transaction `tx_builder` gets finalized into,
+    /// including the inputs funding it and the change output. Nothing is
+    /// written to the database.
+    pub async fn estimate_transaction_fees(
+        &self,
+        tx_builder: TransactionBuilder,
+    ) -> anyhow::Result<Amount> {
+        // Funding selects notes and generates change, all of which is discarded
+        let mut dbtx = self.db().begin_transaction().await;
+        dbtx.ignore_uncommitted();
+        let (tx_builder, _) = self
+            .inner
+            .fund_transaction(&mut dbtx, OperationId::new_random(), tx_builder)
+            .await?;
+        Ok(self.inner.transaction_builder_fees(&tx_builder))
+    }
+
+    /// Federation fees of issuing `amount` into the primary module, i.e. what
+    /// funds received by an operation pay when they are claimed
+    pub async fn estimate_receive_fees(&self, amount: Amount) -> Amount {
+        let mut dbtx = self.db().begin_transaction().await;
+        dbtx.ignore_uncommitted();
+        let output = self
+            .primary_module()
+            .create_exact_output(
+                self.inner.primary_module_instance,
+                &mut dbtx,
+                OperationId::new_random(),
+                amount,
+            )
+            .await;
+        self.primary_module().output_amount(&output.output).fee
+    }
+
     /// Balance available to the client for spending
     pub async fn get_balance(&self) -> Amount {
         self.primary_module()
@@ -708,22 +798,80 @@ impl Client {
             .await
     }
 
-    /// Returns a stream that yields the current client balance every time it
-    /// changes.
-    pub async fn subscribe_balance_changes(&self) -> BoxStream<'_, Amount> {
+    /// Returns a stream that yields an event with the current client balance
+    /// and what changed it every time it changes, e.g. because e-cash was
+    /// received or a peg-in was confirmed.
+    pub async fn subscribe_balance_changes(&self) -> BoxStream<'_, BalanceEvent> {
         let mut balance_changes = self.primary_module().subscribe_balance_changes().await;
         Box::pin(stream! {
-            while let Some(()) = balance_changes.next().await {
+            while let Some(change) = balance_changes.next().await {
                 let mut dbtx = self.db().begin_transaction().await;
                 let balance = self
                     .primary_module()
                     .get_balance(self.inner.primary_module_instance, &mut dbtx)
                     .await;
-                yield balance;
+                let kind = match change.received {
+                    Some(_) => self.balance_event_kind(change.operation_id).await,
+                    None => BalanceEventKind::Other,
+                };
+                yield BalanceEvent {
+                    balance,
+                    operation_id: change.operation_id,
+                    received: change.received,
+                    kind,
+                };
             }
         })
     }
 
+    /// Returns a stream of the items matching `filter` of every epoch starting
+    /// with `start_epoch`, waiting for epochs that haven't happened yet
+    ///
+    /// The federation filters the items, so light clients can build history
+    /// views without downloading whole epochs. Failed requests are retried.
+    pub fn subscribe_epoch_items(
+        &self,
+        start_epoch: u64,
+        filter: EpochItemFilter,
+    ) -> BoxStream<'_, FilteredEpochItems> {
+        Box::pin(stream! {
+            let mut epoch = start_epoch;
+            loop {
+                match self
+                    .api()
+                    .await_epoch_items(epoch, &filter, self.decoders())
+                    .await
+                {
+                    Ok(items) => {
+                        epoch += 1;
+                        yield items;
+                    }
+                    Err(e) => {
+                        warn!("Fetching items of epoch {epoch} failed: {e}");
+                        fedimint_core::task::sleep(EPOCH_ITEMS_RETRY_INTERVAL).await;
+                    }
+                }
+            }
+        })
+    }
+
+    /// Asks the module that started the operation why it received funds
+    async fn balance_event_kind(&self, operation_id: OperationId) -> BalanceEventKind {
+        let Some(operation) = self.operation_log().get_operation(operation_id).await else {
+            return BalanceEventKind::Other;
+        };
+        let Some(instance) =
+            self.get_first_instance(&ModuleKind::clone_from_str(operation.operation_type()))
+        else {
+            return BalanceEventKind::Other;
+        };
+
+        self.inner
+            .get_module(instance)
+            .balance_event_kind(&operation)
+            .unwrap_or(BalanceEventKind::Other)
+    }
+
     pub async fn discover_common_api_version(&self) -> anyhow::Result<ApiVersionSet> {
         Ok(self
             .api()
@@ -738,7 +886,8 @@ impl Client {
     }
 
     /// Query the federation for API version support and then calculate
-    /// the best API version to use (supported by most guardians).
+    /// the best API version to use (the highest one supported by a threshold
+    /// of guardians).
     pub async fn discover_common_api_version_static(
         config: &ClientConfig,
         client_module_gen: &ClientModuleGenRegistry,
@@ -808,6 +957,9 @@ struct ClientInner {
     root_secret: DerivableSecret,
     operation_log: OperationLog,
     secp_ctx: Secp256k1<secp256k1_zkp::All>,
+    /// Our contributions to partially signed transactions that weren't
+    /// submitted yet, see [`transaction::psft`]
+    psft_contributions: std::sync::Mutex<BTreeMap<OperationId, PsftContribution>>,
 }
 
 impl ClientInner {
@@ -822,6 +974,9 @@ impl ClientInner {
         Arc::new(move |module_instance, operation| {
             ModuleGlobalClientContext {
                 client: client_inner.clone(),
+                api: client_inner
+                    .api
+                    .with_correlation_id(operation.correlation_id()),
                 module_instance_id: module_instance,
                 operation,
             }
@@ -885,28 +1040,88 @@ impl ClientInner {
         }
     }
 
+    /// Sums the fees of all inputs and outputs of a transaction
+    fn transaction_builder_fees(&self, builder: &TransactionBuilder) -> Amount {
+        let input_fees = builder.inputs.iter().map(|input| {
+            self.get_module(input.input.module_instance_id())
+                .input_amount(&input.input)
+                .fee
+        });
+        let output_fees = builder.outputs.iter().map(|output| {
+            self.get_module(output.output.module_instance_id())
+                .output_amount(&output.output)
+                .fee
+        });
+        input_fees.chain(output_fees).sum()
+    }
+
+    /// Returns a module that can fund transactions or receive change, i.e.
+    /// one that supports being a primary module
+    fn funding_module(&self, instance: ModuleInstanceId) -> anyhow::Result<&DynClientModule> {
+        let module = self
+            .modules
+            .get(instance)
+            .ok_or_else(|| anyhow!("Funding module instance {instance} not found"))?;
+        if !module.supports_being_primary() {
+            bail!("Module instance {instance} can't fund transactions");
+        }
+        Ok(module)
+    }
+
     /// Adds funding to a transaction or removes overfunding via change.
     async fn finalize_transaction(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
         operation_id: OperationId,
-        mut partial_transaction: TransactionBuilder,
+        partial_transaction: TransactionBuilder,
     ) -> anyhow::Result<(
         Transaction,
         Vec<DynState<DynGlobalClientContext>>,
         Option<u64>,
     )> {
-        if let TransactionBuilderBalance::Underfunded(missing_amount) =
-            self.transaction_builder_balance(&partial_transaction)
-        {
-            let input = self
-                .primary_module()
-                .create_sufficient_input(
-                    self.primary_module_instance,
-                    dbtx,
-                    operation_id,
-                    missing_amount,
-                )
+        let (partial_transaction, change_idx) = self
+            .fund_transaction(dbtx, operation_id, partial_transaction)
+            .await?;
+
+        let (tx, states) = partial_transaction.build(&self.secp_ctx, thread_rng());
+
+        Ok((tx, states, change_idx))
+    }
+
+    /// Balances the transaction with funding inputs and a change output,
+    /// returning the index of the change output
+    async fn fund_transaction(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        operation_id: OperationId,
+        mut partial_transaction: TransactionBuilder,
+    ) -> anyhow::Result<(TransactionBuilder, Option<u64>)> {
+        let funding_modules = if partial_transaction.funding_modules.is_empty() {
+            vec![self.primary_module_instance]
+        } else {
+            partial_transaction.funding_modules.clone()
+        };
+
+        for (idx, &instance) in funding_modules.iter().enumerate() {
+            let TransactionBuilderBalance::Underfunded(missing_amount) =
+                self.transaction_builder_balance(&partial_transaction)
+            else {
+                break;
+            };
+            let module = self.funding_module(instance)?;
+
+            // The last module has to cover the rest or fail with insufficient funds
+            let amount = if idx + 1 == funding_modules.len() {
+                missing_amount
+            } else {
+                missing_amount.min(module.get_balance(instance, dbtx).await)
+            };
+            if amount == Amount::ZERO {
+                continue;
+            }
+
+            let input = module
+                .create_sufficient_input(instance, dbtx, operation_id, amount)
                 .await?;
             partial_transaction.inputs.push(input);
         }
@@ -915,14 +1130,12 @@ impl ClientInner {
         if let TransactionBuilderBalance::Overfunded(excess_amount) =
             self.transaction_builder_balance(&partial_transaction)
         {
+            let change_module = partial_transaction
+                .change_module
+                .unwrap_or(self.primary_module_instance);
             let output = self
-                .primary_module()
-                .create_exact_output(
-                    self.primary_module_instance,
-                    dbtx,
-                    operation_id,
-                    excess_amount,
-                )
+                .funding_module(change_module)?
+                .create_exact_output(change_module, dbtx, operation_id, excess_amount)
                 .await;
             change_idx = Some(partial_transaction.outputs.len() as u64);
             partial_transaction.outputs.push(output);
@@ -936,9 +1149,7 @@ impl ClientInner {
             "Transaction is balanced after the previous two operations"
         );
 
-        let (tx, states) = partial_transaction.build(&self.secp_ctx, thread_rng());
-
-        Ok((tx, states, change_idx))
+        Ok((partial_transaction, change_idx))
     }
 
     async fn finalize_and_submit_transaction(
@@ -1228,6 +1439,10 @@ impl ClientBuilder {
         // TODO: pass to module's `init`
         let common_api_versions =
             Client::discover_common_api_version_static(&config, &self.module_gens, &api).await?;
+        // Module requests are routed to the endpoints of the negotiated api versions
+        let api = DynGlobalApi::from(
+            WsFederationApi::from_config(&config).with_api_versions(&common_api_versions),
+        );
 
         let root_secret = get_client_root_secret::<S>(&db).await;
 
@@ -1240,8 +1455,19 @@ impl ClientBuilder {
                     continue;
                 };
 
+                if !module_gen.supports_consensus_version(module_config.version) {
+                    if module_instance == primary_module_instance {
+                        bail!("Primary module instance {module_instance} of kind {kind} runs consensus version {:?}, which the client doesn't support", module_config.version);
+                    }
+                    warn!("Module kind {kind} of instance {module_instance} runs consensus version {:?}, which the client doesn't support, skipping", module_config.version);
+                    continue;
+                }
+
                 let Some(&api_version) = common_api_versions.modules.get(&module_instance) else {
-                    warn!("Module kind {kind} of instance {module_instance} has not compatible api version, skipping");
+                    if module_instance == primary_module_instance {
+                        bail!("Primary module instance {module_instance} of kind {kind} has no api version supported by both the client ({:?}) and a threshold of guardians", module_gen.supported_api_versions());
+                    }
+                    warn!("Module kind {kind} of instance {module_instance} has no api version supported by both the client ({:?}) and a threshold of guardians, skipping", module_gen.supported_api_versions());
                     continue;
                 };
 
@@ -1296,6 +1522,7 @@ impl ClientBuilder {
             secp_ctx: Secp256k1::new(),
             root_secret,
             operation_log: OperationLog::new(db),
+            psft_contributions: Default::default(),
         });
 
         Ok(Client {
@@ -1422,7 +1649,7 @@ pub fn client_decoders<'a>(
     for (id, kind) in module_kinds {
         let Some(init) = registry.get(kind) else {
             info!("Detected configuration for unsupported module kind: {kind}");
-            continue
+            continue;
         };
 
         modules.insert(
@@ -6,7 +6,8 @@ use fedimint_core::config::{ClientModuleConfig, ModuleGenRegistry, TypedClientMo
 use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
 use fedimint_core::db::Database;
 use fedimint_core::module::{
-    ApiVersion, CommonModuleGen, ExtendsCommonModuleGen, IDynCommonModuleGen, MultiApiVersion,
+    ApiVersion, CommonModuleGen, ExtendsCommonModuleGen, IDynCommonModuleGen,
+    ModuleConsensusVersion, MultiApiVersion,
 };
 use fedimint_core::task::{MaybeSend, MaybeSync};
 use fedimint_core::{apply, async_trait_maybe_send, dyn_newtype_define};
@@ -27,6 +28,13 @@ pub trait ClientModuleGen: ExtendsCommonModuleGen + Sized {
     /// that this client module implementation can use.
     fn supported_api_versions(&self) -> MultiApiVersion;
 
+    /// Whether this client module understands the consensus `version` a
+    /// federation runs the module with. Module instances of unsupported
+    /// versions are skipped when building the client.
+    fn supports_consensus_version(&self, _version: ModuleConsensusVersion) -> bool {
+        true
+    }
+
     /// Initialize a [`ClientModule`] instance from its config
     #[allow(clippy::too_many_arguments)]
     async fn init(
@@ -52,6 +60,9 @@ pub trait IClientModuleGen: IDynCommonModuleGen + Debug + MaybeSend + MaybeSync
     /// See [`ClientModuleGen::supported_api_versions`]
     fn supported_api_versions(&self) -> MultiApiVersion;
 
+    /// See [`ClientModuleGen::supports_consensus_version`]
+    fn supports_consensus_version(&self, version: ModuleConsensusVersion) -> bool;
+
     #[allow(clippy::too_many_arguments)]
     async fn init(
         &self,
@@ -87,6 +98,10 @@ where
         <Self as ClientModuleGen>::supported_api_versions(self)
     }
 
+    fn supports_consensus_version(&self, version: ModuleConsensusVersion) -> bool {
+        <Self as ClientModuleGen>::supports_consensus_version(self, version)
+    }
+
     async fn init(
         &self,
         cfg: ClientModuleConfig,
@@ -15,11 +15,16 @@ use fedimint_core::{
     TransactionId,
 };
 
+use crate::balance::{BalanceEventKind, ModuleBalanceChange};
+use crate::oplog::OperationLogEntry;
 use crate::sm::{Context, DynContext, DynState, Executor, OperationId, State};
 use crate::transaction::{ClientInput, ClientOutput};
 use crate::{Client, DynGlobalClientContext};
 
 pub mod gen;
+/// Client modules of external crates
+#[cfg(feature = "plugins")]
+pub mod plugin;
 
 pub type ClientModuleRegistry = ModuleRegistry<DynClientModule>;
 
@@ -178,11 +183,18 @@ pub trait ClientModule: Debug + MaybeSend + MaybeSync + 'static {
         unimplemented!()
     }
 
-    /// Returns a stream that will output the updated module balance each time
-    /// it changes.
-    async fn subscribe_balance_changes(&self) -> BoxStream<'static, ()> {
+    /// Returns a stream that will output a change each time the module balance
+    /// changes.
+    async fn subscribe_balance_changes(&self) -> BoxStream<'static, ModuleBalanceChange> {
         unimplemented!()
     }
+
+    /// Classifies funds an operation of this module added to the balance of
+    /// the primary module, `None` if it's none of the
+    /// [`BalanceEventKind`]s the module knows
+    fn balance_event_kind(&self, _operation: &OperationLogEntry) -> Option<BalanceEventKind> {
+        None
+    }
 }
 
 /// Type-erased version of [`ClientModule`]
@@ -261,7 +273,9 @@ pub trait IClientModule: Debug {
         dbtx: &mut DatabaseTransaction<'_>,
     ) -> Amount;
 
-    async fn subscribe_balance_changes(&self) -> BoxStream<'static, ()>;
+    async fn subscribe_balance_changes(&self) -> BoxStream<'static, ModuleBalanceChange>;
+
+    fn balance_event_kind(&self, operation: &OperationLogEntry) -> Option<BalanceEventKind>;
 }
 
 #[apply(async_trait_maybe_send!)]
@@ -398,9 +412,13 @@ where
         <T as ClientModule>::get_balance(self, &mut dbtx.with_module_prefix(module_instance)).await
     }
 
-    async fn subscribe_balance_changes(&self) -> BoxStream<'static, ()> {
+    async fn subscribe_balance_changes(&self) -> BoxStream<'static, ModuleBalanceChange> {
         <T as ClientModule>::subscribe_balance_changes(self).await
     }
+
+    fn balance_event_kind(&self, operation: &OperationLogEntry) -> Option<BalanceEventKind> {
+        <T as ClientModule>::balance_event_kind(self, operation)
+    }
 }
 
 dyn_newtype_define!(
@@ -0,0 +1,79 @@
+//! Client modules of external crates registered at link time
+//!
+//! Applications like `fedimint-cli` attach the client modules they know
+//! about to their [`ClientModuleGenRegistry`]. Authors of third-party modules
+//! don't have to fork them to add their own: their crate registers its
+//! [`ClientModuleGen`](crate::module::gen::ClientModuleGen) with
+//! [`register_client_module!`](crate::register_client_module) and an
+//! application built with the `plugins` feature picks up every registered
+//! module that is linked into it with [`attach_plugins`].
+//!
+//! ```ignore
+//! fedimint_client::register_client_module!(DummyClientGen);
+//! ```
+//!
+//! Whether a plugin can talk to a federation is checked like for any other
+//! module when building the client, against the api versions and
+//! [consensus versions](crate::module::gen::ClientModuleGen::supports_consensus_version)
+//! of the module instances in the federation's config.
+
+use fedimint_core::core::ModuleKind;
+#[doc(hidden)]
+pub use inventory;
+use tracing::{info, warn};
+
+use crate::module::gen::{ClientModuleGenRegistry, DynClientModuleGen, IClientModuleGen};
+
+/// A client module registered by an external crate
+pub struct ClientModulePlugin {
+    /// Name of the crate that registered the module, for diagnostics
+    pub crate_name: &'static str,
+    pub gen: fn() -> DynClientModuleGen,
+}
+
+inventory::collect!(ClientModulePlugin);
+
+/// Registers a client module gen so [`attach_plugins`] finds it, has to be
+/// called once at the top level of the module's crate
+#[macro_export]
+macro_rules! register_client_module {
+    ($gen:expr) => {
+        const _: () = {
+            fn gen() -> $crate::module::gen::DynClientModuleGen {
+                $crate::module::gen::DynClientModuleGen::from($gen)
+            }
+
+            $crate::module::plugin::inventory::submit! {
+                $crate::module::plugin::ClientModulePlugin {
+                    crate_name: env!("CARGO_PKG_NAME"),
+                    gen,
+                }
+            }
+        };
+    };
+}
+
+/// Attaches all registered plugins to `registry` and returns their kinds.
+/// Modules already attached to the registry take precedence over plugins of
+/// the same kind.
+pub fn attach_plugins(registry: &mut ClientModuleGenRegistry) -> Vec<ModuleKind> {
+    let mut attached = vec![];
+    for plugin in inventory::iter::<ClientModulePlugin> {
+        let gen = (plugin.gen)();
+        let kind = IClientModuleGen::module_kind(&*gen);
+        if registry.get(&kind).is_some() {
+            warn!(
+                "Client module plugin of kind {kind} from {} is already attached, skipping",
+                plugin.crate_name
+            );
+            continue;
+        }
+        info!(
+            "Attaching client module plugin of kind {kind} from {}",
+            plugin.crate_name
+        );
+        registry.attach(gen);
+        attached.push(kind);
+    }
+    attached
+}
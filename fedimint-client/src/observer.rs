@@ -0,0 +1,142 @@
+//! Read-only access to a federation without a client secret
+//!
+//! A [`FederationObserver`] is created from an invite alone. It neither
+//! generates a root secret nor opens a database, so it can't hold or spend
+//! money, but it can query everything the federation makes public: the
+//! outcome of transactions and their outputs, and through the module API
+//! extension traits e.g. the registered lightning gateways, the balances of
+//! lightning contracts or the consensus block height of the wallet.
+//!
+//! ```ignore
+//! let observer = FederationObserver::from_connect_info(&connect_info, &module_gens).await?;
+//! let gateways = observer
+//!     .module_api(&LightningCommonGen::KIND)?
+//!     .fetch_gateways()
+//!     .await?;
+//! ```
+
+use anyhow::anyhow;
+use fedimint_core::api::{
+    DynGlobalApi, DynModuleApi, FederationResult, GlobalFederationApi, IGlobalFederationApi,
+    OutputOutcomeError, WsClientConnectInfo, WsFederationApi,
+};
+use fedimint_core::config::{ClientConfig, FederationId};
+use fedimint_core::core::{ModuleInstanceId, ModuleKind, OutputOutcome};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::outcome::TransactionStatus;
+use fedimint_core::{OutPoint, TransactionId};
+
+use crate::module::gen::ClientModuleGenRegistry;
+use crate::{client_decoders, Client};
+
+/// Queries the public state of a federation, see the [module docs](self)
+#[derive(Clone)]
+pub struct FederationObserver {
+    config: ClientConfig,
+    decoders: ModuleDecoderRegistry,
+    api: DynGlobalApi,
+}
+
+impl FederationObserver {
+    /// Downloads the client config using the invite `connect_info` and
+    /// verifies it with the guardians
+    pub async fn from_connect_info(
+        connect_info: &WsClientConnectInfo,
+        module_gens: &ClientModuleGenRegistry,
+    ) -> anyhow::Result<Self> {
+        let config = WsFederationApi::download_verified_client_config(connect_info).await?;
+        Self::from_config(config, module_gens).await
+    }
+
+    /// Observes the federation of `config`, outcomes of modules missing from
+    /// `module_gens` can't be decoded
+    pub async fn from_config(
+        config: ClientConfig,
+        module_gens: &ClientModuleGenRegistry,
+    ) -> anyhow::Result<Self> {
+        let decoders = client_decoders(
+            module_gens,
+            config
+                .modules
+                .iter()
+                .map(|(module_instance, module_config)| (*module_instance, module_config.kind())),
+        )?;
+
+        let api = DynGlobalApi::from(WsFederationApi::from_config(&config));
+        let common_api_versions =
+            Client::discover_common_api_version_static(&config, module_gens, &api).await?;
+        // Module requests are routed to the endpoints of the negotiated api versions
+        let api = DynGlobalApi::from(
+            WsFederationApi::from_config(&config).with_api_versions(&common_api_versions),
+        );
+
+        Ok(Self {
+            config,
+            decoders,
+            api,
+        })
+    }
+
+    pub fn federation_id(&self) -> FederationId {
+        self.config.federation_id
+    }
+
+    pub fn config(&self) -> &ClientConfig {
+        &self.config
+    }
+
+    pub fn decoders(&self) -> &ModuleDecoderRegistry {
+        &self.decoders
+    }
+
+    /// API for the global endpoints, e.g. to fetch epochs
+    pub fn api(&self) -> &DynGlobalApi {
+        &self.api
+    }
+
+    /// API of the first module instance of `kind`, to be used with the API
+    /// extension trait of the module
+    pub fn module_api(&self, kind: &ModuleKind) -> anyhow::Result<DynModuleApi> {
+        let module_instance = self
+            .config
+            .modules
+            .iter()
+            .find(|(_, module_config)| module_config.kind() == kind)
+            .map(|(module_instance, _)| *module_instance)
+            .ok_or_else(|| anyhow!("The federation has no module of kind {kind}"))?;
+        Ok(self.api.with_module(module_instance))
+    }
+
+    /// Status of the transaction `txid`, `None` if the federation doesn't know
+    /// it (yet)
+    pub async fn transaction_status(
+        &self,
+        txid: TransactionId,
+    ) -> FederationResult<Option<TransactionStatus>> {
+        self.api.fetch_tx_outcome(&txid).await
+    }
+
+    /// Waits until the federation processed the transaction `txid`
+    pub async fn await_transaction_status(
+        &self,
+        txid: TransactionId,
+    ) -> FederationResult<TransactionStatus> {
+        self.api.await_tx_outcome(&txid).await
+    }
+
+    /// Outcome of the output `out_point` created by the module instance
+    /// `module_instance`, `None` if it isn't available yet
+    pub async fn output_outcome<R>(
+        &self,
+        module_instance: ModuleInstanceId,
+        out_point: OutPoint,
+    ) -> Result<Option<R>, OutputOutcomeError>
+    where
+        R: OutputOutcome,
+    {
+        let decoder = self.decoders.get(module_instance).ok_or_else(|| {
+            OutputOutcomeError::Core(anyhow!("No decoder for module instance {module_instance}"))
+        })?;
+        self.api.fetch_output_outcome(out_point, decoder).await
+    }
+}
@@ -0,0 +1,368 @@
+//! Recurring payments, like a weekly allowance paid to a lightning address or
+//! a monthly peg-out
+//!
+//! Payments are scheduled with [`Client::schedule_payment`] and persisted in
+//! the client database. A [`PaymentScheduler`] started by the application
+//! makes them when they are due, using the [`ScheduledPaymentHandler`] that
+//! was registered for their kind, e.g. by a module client.
+//!
+//! Payments are made at most once per scheduled time: the next run is
+//! persisted before paying, so a crash while paying skips that payment
+//! instead of risking paying twice. Runs missed while the client was offline
+//! are skipped as well.
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, ensure};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::task::{sleep, MaybeSend, MaybeSync, TaskGroup};
+use fedimint_core::time::now;
+use fedimint_core::{apply, async_trait_maybe_send, maybe_add_send_sync};
+use futures::StreamExt;
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tokio::select;
+use tracing::{info, warn};
+
+use crate::db::{ScheduledPaymentKey, ScheduledPaymentKeyPrefix};
+use crate::sm::OperationId;
+use crate::Client;
+
+/// Longest time the scheduler sleeps, so it notices newly scheduled payments
+const MAX_SCHEDULER_SLEEP: Duration = Duration::from_secs(60);
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Encodable,
+    Decodable,
+    Serialize,
+    Deserialize,
+)]
+pub struct ScheduledPaymentId(pub u64);
+
+impl fmt::Display for ScheduledPaymentId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A payment the client makes repeatedly
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct ScheduledPayment {
+    /// [`ScheduledPaymentHandler::KIND`] of the handler making the payment
+    pub kind: String,
+    /// JSON encoded [`ScheduledPaymentHandler::Intent`]
+    pub intent: String,
+    pub interval: Duration,
+    pub next_payment: SystemTime,
+    pub paused: bool,
+    /// Number of payments started so far
+    pub payments: u64,
+    /// Operation of the last payment that was started
+    pub last_operation: Option<OperationId>,
+    /// Why the last payment couldn't be started
+    pub last_error: Option<String>,
+}
+
+impl ScheduledPayment {
+    fn is_due(&self, time: SystemTime) -> bool {
+        !self.paused && self.next_payment <= time
+    }
+
+    /// Moves the next payment past `time`, skipping missed payments
+    fn advance(&mut self, time: SystemTime) {
+        while self.next_payment <= time {
+            self.next_payment += self.interval;
+        }
+    }
+}
+
+/// Makes the payments of a kind, e.g. paying a lightning address
+#[apply(async_trait_maybe_send!)]
+pub trait ScheduledPaymentHandler: fmt::Debug + MaybeSend + MaybeSync + 'static {
+    /// Unique name of the kind of payments made by this handler
+    const KIND: &'static str;
+
+    /// What to pay, persisted with the schedule
+    type Intent: Serialize + DeserializeOwned + MaybeSend;
+
+    /// Starts a payment, returning the operation that tracks it
+    async fn pay(&self, client: &Client, intent: Self::Intent) -> anyhow::Result<OperationId>;
+}
+
+#[apply(async_trait_maybe_send!)]
+trait IScheduledPaymentHandler: fmt::Debug {
+    async fn pay(&self, client: &Client, intent: &str) -> anyhow::Result<OperationId>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<T> IScheduledPaymentHandler for T
+where
+    T: ScheduledPaymentHandler,
+{
+    async fn pay(&self, client: &Client, intent: &str) -> anyhow::Result<OperationId> {
+        let intent = serde_json::from_str(intent)?;
+        ScheduledPaymentHandler::pay(self, client, intent).await
+    }
+}
+
+/// Makes the scheduled payments of a client when they are due
+#[derive(Clone)]
+pub struct PaymentScheduler {
+    client: Client,
+    handlers: BTreeMap<&'static str, Arc<maybe_add_send_sync!(dyn IScheduledPaymentHandler)>>,
+}
+
+impl fmt::Debug for PaymentScheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PaymentScheduler")
+            .field("handlers", &self.handlers.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl PaymentScheduler {
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            handlers: BTreeMap::new(),
+        }
+    }
+
+    /// Registers the handler for payments of kind `H::KIND`, payments of kinds
+    /// without a handler are never made
+    pub fn with_handler<H: ScheduledPaymentHandler>(mut self, handler: H) -> Self {
+        self.handlers.insert(H::KIND, Arc::new(handler));
+        self
+    }
+
+    pub async fn start(self, tg: &mut TaskGroup) {
+        tg.spawn("payment_scheduler", move |handle| async move {
+            let shutdown_future = handle.make_shutdown_rx().await;
+            select! {
+                _ = shutdown_future => {
+                    info!("Shutting down payment scheduler");
+                },
+                _ = self.run() => {},
+            };
+        })
+        .await;
+    }
+
+    async fn run(&self) {
+        loop {
+            let next_payment = self.make_due_payments().await;
+            let sleep_time = next_payment
+                .and_then(|next_payment| next_payment.duration_since(now()).ok())
+                .unwrap_or(MAX_SCHEDULER_SLEEP)
+                .min(MAX_SCHEDULER_SLEEP);
+            sleep(sleep_time).await;
+        }
+    }
+
+    /// Starts all payments that are due, returns when the next one is
+    async fn make_due_payments(&self) -> Option<SystemTime> {
+        let time = now();
+        for (id, payment) in self.client.list_scheduled_payments().await {
+            if !payment.is_due(time) {
+                continue;
+            }
+            let handler = match self.handlers.get(payment.kind.as_str()) {
+                Some(handler) => handler,
+                None => continue,
+            };
+
+            if let Err(e) = self.make_payment(id, handler.as_ref(), time).await {
+                warn!(%id, "Failed to make scheduled payment: {e}");
+            }
+        }
+
+        self.client
+            .list_scheduled_payments()
+            .await
+            .into_iter()
+            .filter(|(_, payment)| {
+                !payment.paused && self.handlers.contains_key(payment.kind.as_str())
+            })
+            .map(|(_, payment)| payment.next_payment)
+            .min()
+    }
+
+    async fn make_payment(
+        &self,
+        id: ScheduledPaymentId,
+        handler: &maybe_add_send_sync!(dyn IScheduledPaymentHandler),
+        time: SystemTime,
+    ) -> anyhow::Result<()> {
+        let payment = self
+            .client
+            .update_scheduled_payment(id, |payment| {
+                // The payment might have been paused in the meantime
+                ensure!(payment.is_due(time), "Payment isn't due anymore");
+                payment.advance(time);
+                Ok(())
+            })
+            .await?;
+
+        let result = handler.pay(&self.client, &payment.intent).await;
+        self.client
+            .update_scheduled_payment(id, |payment| {
+                match &result {
+                    Ok(operation_id) => {
+                        payment.payments += 1;
+                        payment.last_operation = Some(*operation_id);
+                        payment.last_error = None;
+                    }
+                    Err(e) => payment.last_error = Some(e.to_string()),
+                }
+                Ok(())
+            })
+            .await?;
+
+        result.map(|_| ())
+    }
+}
+
+impl Client {
+    /// Schedules paying `intent` with the handler `H` every `interval`,
+    /// starting at `first_payment`
+    ///
+    /// The payments are only made while a [`PaymentScheduler`] with a handler
+    /// for `H::KIND` runs.
+    pub async fn schedule_payment<H: ScheduledPaymentHandler>(
+        &self,
+        intent: H::Intent,
+        first_payment: SystemTime,
+        interval: Duration,
+    ) -> anyhow::Result<ScheduledPaymentId> {
+        ensure!(interval > Duration::ZERO, "Interval must not be zero");
+
+        let id = ScheduledPaymentId(rand::thread_rng().gen());
+        let payment = ScheduledPayment {
+            kind: H::KIND.to_string(),
+            intent: serde_json::to_string(&intent)?,
+            interval,
+            next_payment: first_payment,
+            paused: false,
+            payments: 0,
+            last_operation: None,
+            last_error: None,
+        };
+
+        let mut dbtx = self.inner.db.begin_transaction().await;
+        dbtx.insert_new_entry(&ScheduledPaymentKey(id), &payment)
+            .await;
+        dbtx.commit_tx().await;
+
+        Ok(id)
+    }
+
+    pub async fn list_scheduled_payments(&self) -> Vec<(ScheduledPaymentId, ScheduledPayment)> {
+        self.inner
+            .db
+            .begin_transaction()
+            .await
+            .find_by_prefix(&ScheduledPaymentKeyPrefix)
+            .await
+            .map(|(key, payment)| (key.0, payment))
+            .collect()
+            .await
+    }
+
+    pub async fn get_scheduled_payment(&self, id: ScheduledPaymentId) -> Option<ScheduledPayment> {
+        self.inner
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&ScheduledPaymentKey(id))
+            .await
+    }
+
+    /// Stops making the payment until it is resumed
+    pub async fn pause_scheduled_payment(&self, id: ScheduledPaymentId) -> anyhow::Result<()> {
+        self.update_scheduled_payment(id, |payment| {
+            payment.paused = true;
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Continues making the payment, payments missed while it was paused are
+    /// skipped
+    pub async fn resume_scheduled_payment(&self, id: ScheduledPaymentId) -> anyhow::Result<()> {
+        self.update_scheduled_payment(id, |payment| {
+            payment.paused = false;
+            payment.advance(now());
+            Ok(())
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Stops making the payment for good, payments that were already started
+    /// aren't affected
+    pub async fn cancel_scheduled_payment(&self, id: ScheduledPaymentId) -> anyhow::Result<()> {
+        let mut dbtx = self.inner.db.begin_transaction().await;
+        dbtx.remove_entry(&ScheduledPaymentKey(id))
+            .await
+            .ok_or_else(|| anyhow!("Unknown scheduled payment {id}"))?;
+        dbtx.commit_tx().await;
+        Ok(())
+    }
+
+    async fn update_scheduled_payment(
+        &self,
+        id: ScheduledPaymentId,
+        update: impl FnOnce(&mut ScheduledPayment) -> anyhow::Result<()>,
+    ) -> anyhow::Result<ScheduledPayment> {
+        let mut dbtx = self.inner.db.begin_transaction().await;
+        let mut payment = dbtx
+            .get_value(&ScheduledPaymentKey(id))
+            .await
+            .ok_or_else(|| anyhow!("Unknown scheduled payment {id}"))?;
+        update(&mut payment)?;
+        dbtx.insert_entry(&ScheduledPaymentKey(id), &payment).await;
+        dbtx.commit_tx_result().await?;
+        Ok(payment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn advance_skips_missed_payments() {
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let mut payment = ScheduledPayment {
+            kind: "test".to_string(),
+            intent: "null".to_string(),
+            interval: Duration::from_secs(100),
+            next_payment: start,
+            paused: false,
+            payments: 0,
+            last_operation: None,
+            last_error: None,
+        };
+
+        assert!(!payment.is_due(start - Duration::from_secs(1)));
+        assert!(payment.is_due(start));
+
+        payment.advance(start + Duration::from_secs(250));
+        assert_eq!(payment.next_payment, start + Duration::from_secs(300));
+
+        payment.paused = true;
+        assert!(!payment.is_due(start + Duration::from_secs(300)));
+    }
+}
@@ -11,9 +11,11 @@ mod notifier;
 use std::fmt::{Debug, Display, Formatter};
 use std::str::FromStr;
 
+use bitcoin_hashes::{sha256, Hash};
 pub use dbtx::ClientSMDatabaseTransaction;
 pub use executor::{ActiveState, Executor, ExecutorBuilder, InactiveState};
 use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::CorrelationId;
 use fedimint_core::task::{MaybeSend, MaybeSync};
 pub use notifier::{ModuleNotifier, Notifier};
 use rand::RngCore;
@@ -59,6 +61,16 @@ impl OperationId {
         rng.fill_bytes(&mut bytes);
         Self(bytes)
     }
+
+    /// Sent along with the API requests made on behalf of the operation, so
+    /// guardians can log them together. Derived by hashing, so the operation
+    /// id itself isn't revealed.
+    pub fn correlation_id(&self) -> CorrelationId {
+        let hash = sha256::Hash::hash(&self.0);
+        let mut correlation_id = [0; 8];
+        correlation_id.copy_from_slice(&hash[..8]);
+        CorrelationId(correlation_id)
+    }
 }
 
 impl Display for OperationId {
@@ -0,0 +1,192 @@
+//! Encrypted exports of the whole client state for moving to another device
+//!
+//! Unlike a [backup](crate::backup) a snapshot can be imported without the
+//! help of the federation: it contains the complete client database, i.e. the
+//! root secret, e-cash notes, state machines and the operation log, along with
+//! the client config. It is encrypted with a password chosen by the user.
+//!
+//! Once a snapshot was imported the client it was taken from must not be used
+//! anymore, both clients would try to spend the same notes.
+
+use anyhow::{bail, Context};
+use fedimint_core::config::ClientConfig;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::task::TaskGroup;
+use fedimint_logging::LOG_CLIENT;
+use futures::StreamExt;
+use tracing::{info, warn};
+
+use crate::secret::RootSecretStrategy;
+use crate::{Client, ClientBuilder, DatabaseSource};
+
+/// Prefix identifying snapshot files
+const SNAPSHOT_MAGIC: &[u8] = b"fedimint-client-snapshot";
+
+/// Version of the snapshot format, snapshots of other versions are rejected
+pub const SNAPSHOT_VERSION: u16 = 1;
+
+/// Plaintext contents of a snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub struct ClientSnapshot {
+    /// Version of `fedimint-client` that created the snapshot
+    pub client_version: String,
+    pub config: ClientConfig,
+    /// Raw key-value pairs of the client database
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+}
+
+/// Follows the magic bytes, authenticated along with the ciphertext
+#[derive(Debug, Encodable, Decodable)]
+struct SnapshotHeader {
+    version: u16,
+    /// Salt for stretching the password
+    salt: String,
+}
+
+impl ClientSnapshot {
+    /// Encrypts the snapshot with a key derived from `password`
+    pub fn encrypt(&self, password: &str) -> anyhow::Result<Vec<u8>> {
+        seal(self.consensus_encode_to_vec()?, password)
+    }
+
+    /// Decrypts a snapshot created by [`ClientSnapshot::encrypt`]
+    pub fn decrypt(archive: &[u8], password: &str) -> anyhow::Result<Self> {
+        let plaintext = open(archive, password)?;
+        Ok(Decodable::consensus_decode(
+            &mut plaintext.as_slice(),
+            &ModuleDecoderRegistry::default(),
+        )?)
+    }
+}
+
+fn seal(plaintext: Vec<u8>, password: &str) -> anyhow::Result<Vec<u8>> {
+    let header = SnapshotHeader {
+        version: SNAPSHOT_VERSION,
+        salt: fedimint_aead::random_salt(),
+    };
+    let mut archive = SNAPSHOT_MAGIC.to_vec();
+    header.consensus_encode(&mut archive)?;
+
+    let key = fedimint_aead::get_encryption_key(password, &header.salt)?;
+    let ciphertext = fedimint_aead::encrypt_with_aad(plaintext, &key, &archive)?;
+    archive.extend(ciphertext);
+    Ok(archive)
+}
+
+fn open(archive: &[u8], password: &str) -> anyhow::Result<Vec<u8>> {
+    let Some(mut rest) = archive.strip_prefix(SNAPSHOT_MAGIC) else {
+        bail!("Not a client snapshot");
+    };
+    let header = SnapshotHeader::consensus_decode(&mut rest, &ModuleDecoderRegistry::default())
+        .context("Invalid snapshot header")?;
+    if header.version != SNAPSHOT_VERSION {
+        bail!(
+            "Unsupported snapshot version {}, this client supports version {SNAPSHOT_VERSION}",
+            header.version
+        );
+    }
+    let (authenticated, ciphertext) = archive.split_at(archive.len() - rest.len());
+
+    let key = fedimint_aead::get_encryption_key(password, &header.salt)?;
+    let mut ciphertext = ciphertext.to_vec();
+    let plaintext = fedimint_aead::decrypt_with_aad(&mut ciphertext, &key, authenticated)
+        .context("Wrong password or corrupted snapshot")?;
+    Ok(plaintext.to_vec())
+}
+
+impl Client {
+    /// Exports the client database and config encrypted with `password`, see
+    /// [`ClientBuilder::build_from_snapshot`] for importing it on another
+    /// device
+    ///
+    /// The database is read in a single transaction, so the snapshot is
+    /// consistent even if operations are running.
+    pub async fn export_snapshot(&self, password: &str) -> anyhow::Result<Vec<u8>> {
+        let mut dbtx = self.db().begin_transaction().await;
+        let entries = dbtx
+            .raw_find_by_prefix(&[])
+            .await?
+            .collect::<Vec<_>>()
+            .await;
+        info!(target: LOG_CLIENT, entries = entries.len(), "Exporting client snapshot");
+
+        ClientSnapshot {
+            client_version: env!("CARGO_PKG_VERSION").to_string(),
+            config: self.get_config().clone(),
+            entries,
+        }
+        .encrypt(password)
+    }
+}
+
+impl ClientBuilder {
+    /// Imports a snapshot created by [`Client::export_snapshot`] into the
+    /// database given to the builder and builds the client from it
+    ///
+    /// The database has to be empty. If no config was given to the builder the
+    /// one contained in the snapshot is used.
+    pub async fn build_from_snapshot<S>(
+        mut self,
+        tg: &mut TaskGroup,
+        snapshot: &[u8],
+        password: &str,
+    ) -> anyhow::Result<Client>
+    where
+        S: RootSecretStrategy,
+    {
+        let snapshot = ClientSnapshot::decrypt(snapshot, password)?;
+        if snapshot.client_version != env!("CARGO_PKG_VERSION") {
+            warn!(
+                target: LOG_CLIENT,
+                snapshot_client_version = %snapshot.client_version,
+                "Importing a snapshot created by a different client version"
+            );
+        }
+
+        match self.config.as_ref().map(|config| config.federation_id) {
+            Some(federation_id) if federation_id != snapshot.config.federation_id => {
+                bail!("Snapshot was created by a client of a different federation")
+            }
+            Some(_) => {}
+            None => self.config = Some(snapshot.config),
+        }
+
+        let Some(DatabaseSource::Fresh(db)) = &self.db else {
+            bail!("Snapshots can only be imported into a fresh database");
+        };
+        let mut dbtx = db.begin_transaction().await;
+        if dbtx.raw_find_by_prefix(&[]).await?.next().await.is_some() {
+            bail!("Database is not empty, cannot import snapshot");
+        }
+        for (key, value) in &snapshot.entries {
+            dbtx.raw_insert_bytes(key, value).await?;
+        }
+        dbtx.commit_tx().await?;
+        info!(target: LOG_CLIENT, entries = snapshot.entries.len(), "Imported client snapshot");
+
+        self.build::<S>(tg).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_encryption_roundtrip() {
+        let archive = seal(b"client state".to_vec(), "password").unwrap();
+        assert_eq!(open(&archive, "password").unwrap(), b"client state");
+        assert!(open(&archive, "wrong password").is_err());
+        assert!(open(&archive[1..], "password").is_err());
+
+        // Snapshots of other format versions are rejected before decrypting
+        let version_offset = SNAPSHOT_MAGIC.len();
+        let mut tampered = archive.clone();
+        tampered[version_offset] ^= 1;
+        assert!(open(&tampered, "password")
+            .unwrap_err()
+            .to_string()
+            .contains("Unsupported snapshot version"));
+    }
+}
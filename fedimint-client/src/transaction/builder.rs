@@ -59,6 +59,12 @@ where
 pub struct TransactionBuilder {
     pub(crate) inputs: Vec<ClientInput>,
     pub(crate) outputs: Vec<ClientOutput>,
+    /// Modules that fund the transaction if it's underfunded, in order of
+    /// preference, the primary module if empty
+    pub(crate) funding_modules: Vec<ModuleInstanceId>,
+    /// Module that receives the change if the transaction is overfunded, the
+    /// primary module if `None`
+    pub(crate) change_module: Option<ModuleInstanceId>,
 }
 
 impl TransactionBuilder {
@@ -76,6 +82,24 @@ impl TransactionBuilder {
         self
     }
 
+    /// Adds a module to fund the transaction from when finalizing it
+    ///
+    /// Funding modules are drained in the order they were added, each one
+    /// contributes at most its balance except the last, which has to cover
+    /// whatever is still missing. Modules have to support being a primary
+    /// module.
+    pub fn with_funding_module(mut self, module_instance: ModuleInstanceId) -> Self {
+        self.funding_modules.push(module_instance);
+        self
+    }
+
+    /// Sets the module the change is paid into when finalizing the
+    /// transaction, it has to support being a primary module
+    pub fn with_change_module(mut self, module_instance: ModuleInstanceId) -> Self {
+        self.change_module = Some(module_instance);
+        self
+    }
+
     pub fn build<C, R: RngCore + CryptoRng>(
         self,
         secp_ctx: &Secp256k1<C>,
@@ -1,4 +1,5 @@
 mod builder;
+pub mod psft;
 mod sm;
 
 pub use builder::*;
@@ -0,0 +1,373 @@
+//! Transactions funded by several clients, e.g. users splitting the bill for a
+//! single peg-out
+//!
+//! The parties pass a [`PartiallySignedTransaction`] around, hex encoded with
+//! `consensus_encode_to_hex` and decoded with the [`Client::decoders`], in
+//! three rounds:
+//!
+//! 1. every party adds its inputs and outputs with
+//!    [`Client::contribute_to_partial_transaction`]
+//! 2. every party adds its nonces with [`Client::add_partial_transaction_nonces`]
+//!    once nobody contributes anymore
+//! 3. every party signs with [`Client::sign_partial_transaction`] once all
+//!    nonces are known
+//!
+//! Afterwards every party calls [`Client::submit_partial_transaction`], which
+//! submits the transaction and tracks the party's own inputs and outputs.
+//! Submitting the same transaction more than once is harmless.
+//!
+//! Contributions are only kept in memory since the secret signing nonces must
+//! never be reused. If the client restarts before signing, the reserved funds
+//! have to be recovered with [`Client::cancel_partial_transaction`] before the
+//! restart or are lost until the operation is recovered otherwise.
+use std::cmp::Ordering;
+
+use anyhow::{anyhow, bail, ensure};
+use fedimint_core::transaction::{PartiallySignedTransaction, PsftSecretNonces};
+use fedimint_core::{Amount, TransactionId};
+use rand::thread_rng;
+
+use crate::sm::{DynState, OperationId, OperationState};
+use crate::transaction::{
+    ClientInput, ClientOutput, TransactionBuilder, TxSubmissionStates,
+    TRANSACTION_SUBMISSION_MODULE_INSTANCE,
+};
+use crate::{Client, ClientInner, DynGlobalClientContext};
+
+/// What we added to a [`PartiallySignedTransaction`], needed to sign it and to
+/// track our inputs and outputs once it is submitted
+pub(crate) struct PsftContribution {
+    inputs: Vec<(usize, ClientInput)>,
+    outputs: Vec<(usize, ClientOutput)>,
+    /// Set once we added our nonces, cancelling isn't safe anymore afterwards
+    signing: bool,
+    secret_nonces: Option<PsftSecretNonces>,
+}
+
+impl Client {
+    /// Adds the inputs and outputs of `tx_builder` to `psft`, funding them
+    /// and contributing exactly `amount` towards the outputs of the other
+    /// parties
+    ///
+    /// The primary module adds the missing funds and change, so the funds are
+    /// reserved until the transaction is submitted or the contribution is
+    /// cancelled.
+    pub async fn contribute_to_partial_transaction(
+        &self,
+        operation_id: OperationId,
+        psft: &mut PartiallySignedTransaction,
+        mut tx_builder: TransactionBuilder,
+        amount: Amount,
+    ) -> anyhow::Result<()> {
+        ensure!(
+            !psft.is_signing(),
+            "Signing of the transaction already started"
+        );
+        ensure!(
+            !self.inner.psft_contributions().contains_key(&operation_id),
+            "Already contributed to a transaction as operation {operation_id:?}"
+        );
+
+        let mut dbtx = self.inner.db.begin_transaction().await;
+        if ClientInner::operation_exists(&mut dbtx, operation_id).await {
+            bail!("There already exists an operation with id {operation_id:?}")
+        }
+
+        let (in_amount, out_amount) = self.inner.contribution_amounts(&tx_builder);
+        let required_amount = out_amount + amount;
+        if in_amount < required_amount {
+            let input = self
+                .inner
+                .primary_module()
+                .create_sufficient_input(
+                    self.inner.primary_module_instance,
+                    &mut dbtx,
+                    operation_id,
+                    required_amount - in_amount,
+                )
+                .await?;
+            tx_builder.inputs.push(input);
+        }
+
+        let (in_amount, _) = self.inner.contribution_amounts(&tx_builder);
+        if in_amount > required_amount {
+            let output = self
+                .inner
+                .primary_module()
+                .create_exact_output(
+                    self.inner.primary_module_instance,
+                    &mut dbtx,
+                    operation_id,
+                    in_amount - required_amount,
+                )
+                .await;
+            tx_builder.outputs.push(output);
+        }
+
+        let mut contribution = PsftContribution {
+            inputs: vec![],
+            outputs: vec![],
+            signing: false,
+            secret_nonces: None,
+        };
+        for input in tx_builder.inputs {
+            let keys = input.keys.iter().map(|key| key.x_only_public_key().0);
+            let idx = psft.add_input(input.input.clone(), keys)?;
+            contribution.inputs.push((idx, input));
+        }
+        for output in tx_builder.outputs {
+            let idx = psft.add_output(output.output.clone())?;
+            contribution.outputs.push((idx, output));
+        }
+
+        dbtx.commit_tx().await;
+        self.inner
+            .psft_contributions()
+            .insert(operation_id, contribution);
+
+        Ok(())
+    }
+
+    /// Adds our nonces to `psft` after checking that it still contains our
+    /// contribution and is balanced, nothing can be contributed afterwards
+    pub async fn add_partial_transaction_nonces(
+        &self,
+        operation_id: OperationId,
+        psft: &mut PartiallySignedTransaction,
+    ) -> anyhow::Result<()> {
+        self.inner.psft_transaction_balance(psft)?;
+
+        let mut contributions = self.inner.psft_contributions();
+        let contribution = contributions
+            .get_mut(&operation_id)
+            .ok_or_else(|| anyhow!("No contribution for operation {operation_id:?}"))?;
+        ensure!(
+            !contribution.signing,
+            "Already added nonces for operation {operation_id:?}"
+        );
+        ensure!(
+            contribution
+                .inputs
+                .iter()
+                .all(|(idx, input)| psft.inputs.get(*idx) == Some(&input.input)),
+            "Transaction lacks our inputs"
+        );
+        ensure!(
+            contribution
+                .outputs
+                .iter()
+                .all(|(idx, output)| psft.outputs.get(*idx) == Some(&output.output)),
+            "Transaction lacks our outputs"
+        );
+
+        let secret_nonces = psft.add_nonces(&contribution.keys(), thread_rng())?;
+        contribution.signing = true;
+        contribution.secret_nonces = Some(secret_nonces);
+
+        Ok(())
+    }
+
+    /// Adds our partial signatures to `psft` once every party added its
+    /// nonces
+    pub async fn sign_partial_transaction(
+        &self,
+        operation_id: OperationId,
+        psft: &mut PartiallySignedTransaction,
+    ) -> anyhow::Result<()> {
+        let mut contributions = self.inner.psft_contributions();
+        let contribution = contributions
+            .get_mut(&operation_id)
+            .ok_or_else(|| anyhow!("No contribution for operation {operation_id:?}"))?;
+        let secret_nonces = contribution
+            .secret_nonces
+            .take()
+            .ok_or_else(|| anyhow!("No nonces for operation {operation_id:?}"))?;
+
+        psft.add_partial_signatures(&contribution.keys(), secret_nonces)?;
+
+        Ok(())
+    }
+
+    /// Submits the fully signed `psft` to the federation and tracks our inputs
+    /// and outputs in it, if we contributed any
+    pub async fn submit_partial_transaction<M>(
+        &self,
+        operation_id: OperationId,
+        operation_type: &str,
+        operation_meta: M,
+        psft: PartiallySignedTransaction,
+    ) -> anyhow::Result<TransactionId>
+    where
+        M: serde::Serialize,
+    {
+        let transaction = psft.finalize()?;
+        let txid = transaction.tx_hash();
+
+        let mut dbtx = self.inner.db.begin_transaction().await;
+        if ClientInner::operation_exists(&mut dbtx, operation_id).await {
+            bail!("There already exists an operation with id {operation_id:?}")
+        }
+
+        let contribution = self.inner.psft_contributions().remove(&operation_id);
+        let mut states = contribution
+            .map(|contribution| contribution.states(txid))
+            .unwrap_or_default();
+        states.push(DynState::from_typed(
+            TRANSACTION_SUBMISSION_MODULE_INSTANCE,
+            OperationState {
+                operation_id,
+                state: TxSubmissionStates::Created {
+                    txid,
+                    tx: transaction,
+                    next_submission: fedimint_core::time::now(),
+                },
+            },
+        ));
+
+        self.inner
+            .executor
+            .add_state_machines_dbtx(&mut dbtx, states)
+            .await?;
+        self.operation_log()
+            .add_operation_log_entry(&mut dbtx, operation_id, operation_type, operation_meta)
+            .await;
+        dbtx.commit_tx().await;
+
+        Ok(txid)
+    }
+
+    /// Gives up on our contribution before signing it and returns the reserved
+    /// funds to us in a transaction of the same operation
+    pub async fn cancel_partial_transaction(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<TransactionId> {
+        let contribution = {
+            let mut contributions = self.inner.psft_contributions();
+            match contributions.get(&operation_id) {
+                Some(contribution) if contribution.signing => {
+                    bail!("Already added nonces, the transaction might still be signed")
+                }
+                Some(_) => contributions.remove(&operation_id).expect("Checked above"),
+                None => bail!("No contribution for operation {operation_id:?}"),
+            }
+        };
+
+        let tx_builder = contribution
+            .inputs
+            .into_iter()
+            .fold(TransactionBuilder::new(), |builder, (_, input)| {
+                builder.with_input(input)
+            });
+
+        self.finalize_and_submit_transaction(
+            operation_id,
+            "cancel_partial_transaction",
+            |_, _| (),
+            tx_builder,
+        )
+        .await
+    }
+}
+
+impl ClientInner {
+    fn psft_contributions(
+        &self,
+    ) -> std::sync::MutexGuard<'_, std::collections::BTreeMap<OperationId, PsftContribution>> {
+        self.psft_contributions
+            .lock()
+            .expect("Contributions lock poisoned")
+    }
+
+    /// Returns the amount of the inputs and the amount plus fees of the
+    /// outputs of our contribution
+    fn contribution_amounts(&self, builder: &TransactionBuilder) -> (Amount, Amount) {
+        let in_amount = builder
+            .inputs
+            .iter()
+            .map(|input| {
+                self.get_module(input.input.module_instance_id())
+                    .input_amount(&input.input)
+                    .amount
+            })
+            .sum();
+        let out_amount = builder
+            .outputs
+            .iter()
+            .map(|output| {
+                let item_amount = self
+                    .get_module(output.output.module_instance_id())
+                    .output_amount(&output.output);
+                item_amount.amount + item_amount.fee
+            })
+            .chain(builder.inputs.iter().map(|input| {
+                self.get_module(input.input.module_instance_id())
+                    .input_amount(&input.input)
+                    .fee
+            }))
+            .sum();
+
+        (in_amount, out_amount)
+    }
+
+    /// Checks that the inputs of all parties pay for all outputs and fees
+    fn psft_transaction_balance(&self, psft: &PartiallySignedTransaction) -> anyhow::Result<()> {
+        let mut in_amount = Amount::ZERO;
+        let mut out_amount = Amount::ZERO;
+
+        for input in &psft.inputs {
+            let module = self
+                .try_get_module(input.module_instance_id())
+                .ok_or_else(|| anyhow!("Input of unknown module {input}"))?;
+            let item_amount = module.input_amount(input);
+            in_amount += item_amount.amount;
+            out_amount += item_amount.fee;
+        }
+
+        for output in &psft.outputs {
+            let module = self
+                .try_get_module(output.module_instance_id())
+                .ok_or_else(|| anyhow!("Output of unknown module {output}"))?;
+            let item_amount = module.output_amount(output);
+            out_amount += item_amount.amount + item_amount.fee;
+        }
+
+        match in_amount.cmp(&out_amount) {
+            Ordering::Equal => Ok(()),
+            Ordering::Less => bail!("Transaction is underfunded by {}", out_amount - in_amount),
+            Ordering::Greater => bail!("Transaction is overfunded by {}", in_amount - out_amount),
+        }
+    }
+}
+
+impl PsftContribution {
+    fn keys(&self) -> Vec<fedimint_core::core::KeyPair> {
+        self.inputs
+            .iter()
+            .flat_map(|(_, input)| input.keys.iter().cloned())
+            .collect()
+    }
+
+    fn states(self, txid: TransactionId) -> Vec<DynState<DynGlobalClientContext>> {
+        self.inputs
+            .into_iter()
+            .map(|(idx, input)| (idx, input.state_machines))
+            .chain(
+                self.outputs
+                    .into_iter()
+                    .map(|(idx, output)| (idx, output.state_machines)),
+            )
+            .flat_map(|(idx, state_gen)| state_gen(txid, idx as u64))
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for PsftContribution {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PsftContribution")
+            .field("inputs", &self.inputs.len())
+            .field("outputs", &self.outputs.len())
+            .field("signing", &self.signing)
+            .finish()
+    }
+}
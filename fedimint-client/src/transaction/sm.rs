@@ -11,7 +11,7 @@ use fedimint_core::transaction::Transaction;
 use fedimint_core::{task, TransactionId};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
-use tracing::warn;
+use tracing::{error, warn};
 
 use crate::sm::{Context, DynContext, OperationId, OperationState, State, StateTransition};
 use crate::{DynGlobalClientContext, DynState};
@@ -206,7 +206,13 @@ async fn trigger_created_accepted(
             Ok(TransactionStatus::Accepted { epoch, .. }) => break Ok(epoch),
             Ok(TransactionStatus::Rejected(error)) => break Err(error),
             Err(error) => {
-                if error.is_retryable() {
+                if let Some(inconsistent) = error.inconsistent_responses::<TransactionStatus>() {
+                    error!(
+                        target: LOG_TARGET,
+                        responses = ?inconsistent.responses,
+                        "Federation members disagree on the transaction outcome"
+                    );
+                } else if error.is_retryable() {
                     // FIXME: what to do in this case?
                     warn!(target: LOG_TARGET, ?error, "Federation returned error");
                 }
@@ -238,7 +244,7 @@ mod tests {
     use fedimint_core::db::mem_impl::MemDatabase;
     use fedimint_core::db::Database;
     use fedimint_core::module::registry::ModuleDecoderRegistry;
-    use fedimint_core::module::ApiRequestErased;
+    use fedimint_core::module::{ApiRequestErased, CorrelationId};
     use fedimint_core::task::{sleep, TaskGroup};
     use fedimint_core::transaction::SerdeTransaction;
     use fedimint_core::util::BoxStream;
@@ -285,6 +291,10 @@ mod tests {
             unimplemented!()
         }
 
+        fn with_correlation_id(&self, _correlation_id: CorrelationId) -> DynGlobalApi {
+            unimplemented!()
+        }
+
         async fn request_raw(
             &self,
             _peer_id: PeerId,
@@ -1,34 +1,57 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
+use std::path::PathBuf;
+use std::time::SystemTime;
 
 use bitcoin_hashes::sha256;
 use fedimint_core::task::MaybeSend;
+use futures::{Stream, StreamExt};
+use jsonrpsee_core::client::SubscriptionClientT;
+#[cfg(target_family = "wasm")]
+use jsonrpsee_wasm_client::Client as WsClient;
+#[cfg(not(target_family = "wasm"))]
+use jsonrpsee_ws_client::WsClient;
 use serde::{Deserialize, Serialize};
 use threshold_crypto::PublicKey;
 use tokio_rustls::rustls;
 use url::Url;
 
 use crate::api::{
-    DynGlobalApi, FederationApiExt, FederationResult, GlobalFederationApi, ServerStatus,
-    StatusResponse, WsFederationApi,
+    ConsensusStatus, DynGlobalApi, FederationApiExt, FederationError, FederationResult,
+    GlobalFederationApi, JsonRpcClient, PeerConsensusStatus, ServerStatus, StatusResponse,
+    WsFederationApi,
 };
-use crate::config::ServerModuleGenParamsRegistry;
-use crate::epoch::{SerdeEpochHistory, SignedEpochOutcome};
+use crate::config::{ConfigGenModuleParams, ServerModuleGenParamsRegistry};
+use crate::core::{ModuleInstanceId, ModuleKind};
+use crate::db::snapshot::DatabaseSnapshot;
+use crate::db::MigrationJournalEntry;
+use crate::encoding::{Decodable, Encodable};
+use crate::epoch::{ModuleAddition, SerdeEpochHistory, SignedEpochOutcome};
+use crate::module::audit::AuditItem;
 use crate::module::registry::ModuleDecoderRegistry;
-use crate::module::{ApiAuth, ApiRequestErased};
+use crate::module::{ApiAuth, ApiRequestErased, SerdeModuleEncoding};
 use crate::PeerId;
 
 /// For a guardian to communicate with their server
 // TODO: Maybe should have it's own CLI client so it doesn't need to be in core
 pub struct WsAdminClient {
     inner: DynGlobalApi,
+    url: Url,
     auth: ApiAuth,
 }
 
+/// Method subscribing to the DKG progress of a guardian
+pub const SUBSCRIBE_DKG_PROGRESS: &str = "subscribe_dkg_progress";
+/// Method the DKG progress is sent with to subscribers
+pub const DKG_PROGRESS_NOTIFICATION: &str = "dkg_progress";
+/// Method ending a subscription to the DKG progress
+pub const UNSUBSCRIBE_DKG_PROGRESS: &str = "unsubscribe_dkg_progress";
+
 impl WsAdminClient {
     pub fn new(url: Url, our_id: PeerId, auth: ApiAuth) -> Self {
         Self {
-            inner: WsFederationApi::new(vec![(our_id, url)]).into(),
+            inner: WsFederationApi::new(vec![(our_id, url.clone())]).into(),
+            url,
             auth,
         }
     }
@@ -59,9 +82,27 @@ impl WsAdminClient {
     /// Note this call will fail until the leader has their API running and has
     /// `set_server_connections` so clients should retry.
     ///
-    /// This call is not authenticated because it's guardian-to-guardian
-    pub async fn add_config_gen_peer(&self, peer: PeerServerParams) -> FederationResult<()> {
-        self.request("add_config_gen_peer", ApiRequestErased::new(peer))
+    /// This call is not authenticated because it's guardian-to-guardian, but
+    /// requires an `invite_code` if the leader created any invites.
+    pub async fn add_config_gen_peer(
+        &self,
+        peer: PeerServerParams,
+        invite_code: Option<String>,
+    ) -> FederationResult<()> {
+        self.request(
+            "add_config_gen_peer",
+            ApiRequestErased::new(AddConfigGenPeerRequest { peer, invite_code }),
+        )
+        .await
+    }
+
+    /// Leader creates a one-time invite code for a guardian which it needs to
+    /// pass in `set_config_gen_connections`
+    ///
+    /// Once the leader created an invite, only guardians with a valid invite
+    /// code can join config gen.
+    pub async fn create_config_gen_invite(&self) -> FederationResult<String> {
+        self.request_auth("create_config_gen_invite", ApiRequestErased::default())
             .await
     }
 
@@ -139,6 +180,64 @@ impl WsAdminClient {
             .await
     }
 
+    /// Subscribes to the DKG progress over a new websocket connection. The
+    /// stream starts with the current progress followed by every update and
+    /// ends when the connection is closed.
+    pub async fn subscribe_dkg_progress(
+        &self,
+    ) -> FederationResult<impl Stream<Item = FederationResult<DkgProgress>>> {
+        let client = WsClient::connect(&self.url)
+            .await
+            .map_err(|e| FederationError::general(e.into()))?;
+        let params = [ApiRequestErased::default().with_auth(&self.auth).to_json()];
+        let subscription = client
+            .subscribe::<DkgProgress, _>(
+                SUBSCRIBE_DKG_PROGRESS,
+                &params[..],
+                UNSUBSCRIBE_DKG_PROGRESS,
+            )
+            .await
+            .map_err(|e| FederationError::general(e.into()))?;
+
+        // The subscription ends once the client is dropped
+        Ok(futures::stream::unfold(
+            (client, subscription),
+            |(client, mut subscription)| async move {
+                let progress = subscription
+                    .next()
+                    .await?
+                    .map_err(|e| FederationError::general(e.into()));
+                Some((progress, (client, subscription)))
+            },
+        ))
+    }
+
+    /// After DKG failed, runs it again only for the parts that failed, plus the
+    /// modules in `rerun_modules` which will be regenerated even if they
+    /// succeeded before. All guardians need to restart DKG.
+    pub async fn restart_dkg(
+        &self,
+        rerun_modules: BTreeSet<ModuleInstanceId>,
+    ) -> FederationResult<()> {
+        self.request_auth("restart_dkg", ApiRequestErased::new(rerun_modules))
+            .await
+    }
+
+    /// After DKG failed, discards our checkpointed keys of a module so they are
+    /// generated again by `restart_dkg`. The other guardians generate them
+    /// again as well, since guardians only reuse the parts all of them have
+    /// results for.
+    pub async fn reset_module_dkg(
+        &self,
+        module_instance_id: ModuleInstanceId,
+    ) -> FederationResult<()> {
+        self.request_auth(
+            "reset_module_dkg",
+            ApiRequestErased::new(module_instance_id),
+        )
+        .await
+    }
+
     /// After DKG, returns the hash of the consensus config tweaked with our id.
     /// We need to share this with all other peers to complete verification.
     pub async fn get_verify_config_hash(&self) -> FederationResult<BTreeMap<PeerId, sha256::Hash>> {
@@ -162,6 +261,144 @@ impl WsAdminClient {
             .await
     }
 
+    /// Backs up the server database into `request.path` on the server while
+    /// it keeps running
+    pub async fn backup_database(
+        &self,
+        request: DatabaseBackupRequest,
+    ) -> FederationResult<DatabaseBackupResponse> {
+        self.request_auth("backup_database", ApiRequestErased::new(request))
+            .await
+    }
+
+    /// Re-encrypts the configs of the guardian under a new password, generated
+    /// by the server if `new_password` is `None`, and switches the API over to
+    /// it. The current password keeps working for a grace period.
+    pub async fn rotate_password(
+        &self,
+        new_password: Option<ApiAuth>,
+    ) -> FederationResult<PasswordRotationResponse> {
+        self.request_auth(
+            "rotate_password",
+            ApiRequestErased::new(PasswordRotationRequest { new_password }),
+        )
+        .await
+    }
+
+    /// Votes for replacing the federation meta sent to clients with `meta`,
+    /// it is replaced once a threshold of guardians voted for the same meta
+    pub async fn update_meta(&self, meta: BTreeMap<String, String>) -> FederationResult<()> {
+        self.request_auth("update_meta", ApiRequestErased::new(meta))
+            .await
+    }
+
+    /// Returns the meta votes of guardians that didn't reach the threshold yet
+    pub async fn meta_votes(&self) -> FederationResult<BTreeMap<PeerId, BTreeMap<String, String>>> {
+        self.request_auth("meta_votes", ApiRequestErased::default())
+            .await
+    }
+
+    /// Votes for adding a module instance, it's added once all guardians voted
+    /// for the same module and consensus params
+    pub async fn propose_module_addition(
+        &self,
+        request: ModuleAdditionRequest,
+    ) -> FederationResult<()> {
+        self.request_auth("propose_module_addition", ApiRequestErased::new(request))
+            .await
+    }
+
+    /// Cancels the pending or scheduled addition of a module instance, any
+    /// guardian can cancel it since its DKG needs all of them
+    pub async fn cancel_module_addition(
+        &self,
+        module_instance_id: ModuleInstanceId,
+    ) -> FederationResult<()> {
+        self.request_auth(
+            "cancel_module_addition",
+            ApiRequestErased::new(module_instance_id),
+        )
+        .await
+    }
+
+    /// Returns the pending votes for module additions and the scheduled one
+    pub async fn module_addition_status(&self) -> FederationResult<ModuleAdditionStatus> {
+        self.request_auth("module_addition_status", ApiRequestErased::default())
+            .await
+    }
+
+    /// Returns the misbehavior scores of the guardians, including the ones
+    /// that are quarantined
+    pub async fn peer_scores(&self) -> FederationResult<BTreeMap<PeerId, PeerScore>> {
+        self.request_auth("peer_scores", ApiRequestErased::default())
+            .await
+    }
+
+    /// Returns the database migrations that were applied by the server
+    pub async fn migration_journal(&self) -> FederationResult<Vec<MigrationJournalEntry>> {
+        self.request_auth("migration_journal", ApiRequestErased::default())
+            .await
+    }
+
+    /// Makes the guardian propose an epoch even if it has nothing to
+    /// contribute, the other guardians join it
+    pub async fn trigger_epoch(&self) -> FederationResult<()> {
+        self.request_auth("trigger_epoch", ApiRequestErased::default())
+            .await
+    }
+
+    /// Returns the connection status and misbehavior of every other guardian
+    /// as seen by this guardian
+    pub async fn peer_connectivity(&self) -> FederationResult<BTreeMap<PeerId, PeerConnectivity>> {
+        self.request_auth("peer_connectivity", ApiRequestErased::default())
+            .await
+    }
+
+    /// Returns the balance sheet of the federation broken down by module
+    pub async fn audit(&self) -> FederationResult<AuditReport> {
+        self.request_auth("audit", ApiRequestErased::default())
+            .await
+    }
+
+    /// Returns the consensus health, balances and module state of the
+    /// federation in one call, meant to be polled by dashboards
+    pub async fn admin_status(&self) -> FederationResult<AdminStatus> {
+        self.request_auth("admin_status", ApiRequestErased::default())
+            .await
+    }
+
+    /// Returns what an API gateway needs to serve this guardian's API, see
+    /// `fedimint_server::net::gateway`
+    pub async fn api_gateway_info(&self) -> FederationResult<ApiGatewayInfo> {
+        self.request_auth("api_gateway_info", ApiRequestErased::default())
+            .await
+    }
+
+    /// Returns the raw database entries of a batch of epochs starting at
+    /// `from_epoch` and of the transactions accepted in them, to be imported
+    /// into the replica of an API gateway
+    pub async fn replicate_epochs(
+        &self,
+        from_epoch: u64,
+    ) -> FederationResult<SerdeModuleEncoding<DatabaseSnapshot>> {
+        self.request_auth("replicate_epochs", ApiRequestErased::new(from_epoch))
+            .await
+    }
+
+    /// Calls an authenticated endpoint of the module `module_instance_id`
+    pub async fn module_request_auth<Ret>(
+        &self,
+        module_instance_id: ModuleInstanceId,
+        method: &str,
+        params: ApiRequestErased,
+    ) -> FederationResult<Ret>
+    where
+        Ret: serde::de::DeserializeOwned + Eq + Debug + Clone + MaybeSend,
+    {
+        self.request_auth(&format!("module_{module_instance_id}_{method}"), params)
+            .await
+    }
+
     async fn request_auth<Ret>(
         &self,
         method: &str,
@@ -193,6 +430,39 @@ pub struct ConfigGenConnectionsRequest {
     /// Url of "leader" guardian to send our connection info to
     /// Will be `None` if we are the leader
     pub leader_api_url: Option<Url>,
+    /// One-time invite code created by the leader
+    #[serde(default)]
+    pub invite_code: Option<String>,
+}
+
+/// Sent by a guardian to the leader to join config gen
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct AddConfigGenPeerRequest {
+    /// Connection info of the guardian
+    pub peer: PeerServerParams,
+    /// One-time invite code created by the leader
+    pub invite_code: Option<String>,
+}
+
+/// Stage of a single part of the distributed key generation
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub enum DkgStage {
+    #[default]
+    Pending,
+    Running,
+    Done,
+    Failed(String),
+}
+
+/// Progress of the distributed key generation of a guardian
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct DkgProgress {
+    /// Incremented with every update
+    pub version: u64,
+    /// Stage of the consensus keys
+    pub global: DkgStage,
+    /// Stage of each module's keys
+    pub modules: BTreeMap<ModuleInstanceId, DkgStage>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Eq, PartialEq)]
@@ -221,6 +491,9 @@ pub struct ConfigGenParamsConsensus {
     pub meta: BTreeMap<String, String>,
     /// Config gen params (also contains local params from us)
     pub modules: ServerModuleGenParamsRegistry,
+    /// When misbehaving guardians get quarantined
+    #[serde(default)]
+    pub misbehavior: MisbehaviorThresholds,
 }
 
 /// The config gen params response which includes our peer id
@@ -241,7 +514,182 @@ pub struct ConfigGenParamsRequest {
     pub modules: ServerModuleGenParamsRegistry,
 }
 
-mod serde_tls_cert {
+/// Sent by admin user to back up the server database
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct DatabaseBackupRequest {
+    /// Directory the backup is written to, relative to the guardian's data
+    /// directory
+    pub path: PathBuf,
+    /// Only export entries changed since the last snapshot in `path`
+    pub incremental: bool,
+    /// Create a native database checkpoint instead of a logical snapshot
+    pub checkpoint: bool,
+}
+
+/// Describes the backup that was written by the server
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct DatabaseBackupResponse {
+    /// File or directory containing the backup
+    pub path: PathBuf,
+    /// Id of the logical snapshot, `None` for checkpoints
+    pub snapshot_id: Option<sha256::Hash>,
+    /// Id of the snapshot an incremental snapshot is based on
+    pub base: Option<sha256::Hash>,
+    /// Number of entries in the logical snapshot
+    pub entries: u64,
+}
+
+/// Sent by admin user to change the password of their guardian
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct PasswordRotationRequest {
+    /// The new password, a random one is generated if `None`
+    pub new_password: Option<ApiAuth>,
+}
+
+/// The password the guardian switched to
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct PasswordRotationResponse {
+    pub new_password: ApiAuth,
+    /// Until when the previous password is still accepted by the API
+    pub previous_valid_until: SystemTime,
+}
+
+/// Sent by every guardian's admin to add a new module instance to the running
+/// federation
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ModuleAdditionRequest {
+    /// Id of the new module instance, must not be in use yet
+    pub module_instance_id: ModuleInstanceId,
+    pub kind: ModuleKind,
+    /// Local params stay on the server, consensus params must be the same for
+    /// all guardians
+    pub params: ConfigGenModuleParams,
+    /// The DKG of the module runs after this epoch has been processed
+    pub activation_epoch: u64,
+}
+
+/// Votes of the guardians for adding a module and the addition waiting for
+/// its activation epoch
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ModuleAdditionStatus {
+    pub votes: BTreeMap<PeerId, ModuleAddition>,
+    pub scheduled: Option<ModuleAddition>,
+}
+
+/// How misbehavior of guardians observed in consensus is scored and when they
+/// get quarantined, must be the same for all guardians
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Eq, PartialEq, Encodable, Decodable)]
+pub struct MisbehaviorThresholds {
+    /// Score at which a guardian gets quarantined
+    pub quarantine_score: u64,
+    /// Number of epochs the contributions of a quarantined guardian are
+    /// ignored for
+    pub quarantine_epochs: u64,
+    /// Added for a missing or invalid signature or decryption share
+    pub invalid_signature: u64,
+    /// Added for every epoch the guardian didn't contribute to. Whether a
+    /// contribution gets ordered depends on the network as much as on the
+    /// guardian, so it should stay low enough to not quarantine guardians
+    /// behind a slow link.
+    pub missing_proposal: u64,
+    /// Added for contributing conflicting signature shares in one epoch
+    pub equivocation: u64,
+    /// Added for invalid consensus items, including misbehavior reported by
+    /// modules
+    pub invalid_item: u64,
+    /// Removed for every epoch the guardian contributed to
+    pub decay: u64,
+}
+
+impl Default for MisbehaviorThresholds {
+    fn default() -> Self {
+        Self {
+            quarantine_score: 100,
+            quarantine_epochs: 100,
+            invalid_signature: 20,
+            missing_proposal: 0,
+            equivocation: 50,
+            invalid_item: 20,
+            decay: 1,
+        }
+    }
+}
+
+/// Misbehavior of a guardian observed in consensus
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq, Encodable, Decodable)]
+pub struct PeerScore {
+    /// Decaying score, the guardian gets quarantined once it reaches
+    /// `MisbehaviorThresholds::quarantine_score`
+    pub score: u64,
+    pub invalid_signatures: u64,
+    /// Epochs the guardian didn't contribute to, only counted towards the
+    /// score with `MisbehaviorThresholds::missing_proposal`
+    pub missing_proposals: u64,
+    pub equivocations: u64,
+    pub invalid_items: u64,
+    /// Contributions of the guardian are ignored until this epoch
+    pub quarantined_until: Option<u64>,
+}
+
+/// Connectivity of another guardian as seen by the guardian answering
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct PeerConnectivity {
+    pub consensus: PeerConsensusStatus,
+    /// `None` if the guardian never misbehaved
+    pub score: Option<PeerScore>,
+}
+
+/// Balance sheet of the federation as seen by a guardian
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct AuditReport {
+    /// Number of epochs processed when the report was created
+    pub epoch_count: u64,
+    /// Assets minus liabilities of all modules, must never be negative
+    pub net_assets_msat: i64,
+    pub modules: BTreeMap<ModuleInstanceId, ModuleAuditReport>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ModuleAuditReport {
+    pub kind: ModuleKind,
+    pub net_assets_msat: i64,
+    /// Database entries the balance sheet of the module consists of
+    pub items: Vec<AuditItem>,
+}
+
+/// Status of the federation as seen by a guardian, aggregated for dashboards
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct AdminStatus {
+    /// Number of epochs processed when the status was created
+    pub epoch_count: u64,
+    pub consensus: ConsensusStatus,
+    /// Scores of the guardians that misbehaved so far
+    pub peer_scores: BTreeMap<PeerId, PeerScore>,
+    /// Assets minus liabilities of all modules
+    pub net_assets_msat: i64,
+    pub modules: BTreeMap<ModuleInstanceId, ModuleAdminStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ModuleAdminStatus {
+    pub kind: ModuleKind,
+    pub net_assets_msat: i64,
+    /// Module specific status, e.g. the pending peg-outs of the wallet, `None`
+    /// if the module doesn't provide one
+    pub status: Option<serde_json::Value>,
+}
+
+/// What an API gateway needs to know about the guardian it serves the API of
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ApiGatewayInfo {
+    /// Kinds of the module instances, for decoding the replicated epochs
+    pub modules: BTreeMap<ModuleInstanceId, ModuleKind>,
+    /// Methods of the guardian's API, the ones the gateway can't answer from
+    /// its replica are forwarded
+    pub methods: Vec<String>,
+}
+
+pub mod serde_tls_cert {
     use std::borrow::Cow;
 
     use bitcoin_hashes::hex::{FromHex, ToHex};
@@ -16,7 +16,7 @@ use bitcoin::secp256k1;
 use bitcoin_hashes::sha256;
 use fedimint_core::config::{ClientConfig, ClientConfigResponse, FederationId};
 use fedimint_core::core::ModuleInstanceId;
-use fedimint_core::encoding::Encodable;
+use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::fmt_utils::AbbreviateDebug;
 use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::task::{MaybeSend, MaybeSync, RwLock, RwLockWriteGuard};
@@ -24,7 +24,6 @@ use fedimint_core::time::now;
 use fedimint_core::{
     apply, async_trait_maybe_send, dyn_newtype_define, NumPeers, OutPoint, PeerId, TransactionId,
 };
-use fedimint_derive::Decodable;
 use fedimint_logging::LOG_NET_API;
 use futures::stream::FuturesUnordered;
 use futures::{Future, StreamExt};
@@ -43,14 +42,25 @@ use url::Url;
 
 use crate::backup::ClientBackupSnapshot;
 use crate::core::backup::SignedBackupRequest;
-use crate::core::{Decoder, OutputOutcome};
-use crate::epoch::{SerdeEpochHistory, SignedEpochOutcome};
-use crate::module::{ApiRequestErased, ApiVersion, SupportedApiVersionsSummary};
+use crate::core::{Decoder, ModuleKind, OutputOutcome};
+use crate::epoch::{
+    EpochItemFilter, EpochItemsRequest, EpochTimingEstimate, FilteredEpochItems, SerdeEpochHistory,
+    SerdeFilteredEpochItems, SerdeTransactionInclusionProof, SignedEpochOutcome,
+    TransactionInclusionProof,
+};
+use crate::join::SignedFederationSnapshot;
+use crate::module::error::ApiErrorData;
+use crate::module::{
+    ApiError, ApiRequestErased, ApiVersion, CorrelationId, SupportedApiVersionsSummary,
+};
 use crate::outcome::TransactionStatus;
 use crate::query::{
-    CurrentConsensus, DiscoverApiVersionSet, EventuallyConsistent, QueryStep, QueryStrategy,
-    UnionResponsesSingle, VerifiableResponse,
+    CombineEpochStatsShares, CombineReceiptShares, CombineSnapshotShares, ConsistentResponses,
+    CurrentConsensus, DiscoverApiVersionSet, EventuallyConsistent, InconsistentResponses,
+    QueryStep, QueryStrategy, UnionResponsesSingle, VerifiableResponse,
 };
+use crate::receipt::SignedTransactionReceipt;
+use crate::stats::SignedEpochStats;
 use crate::task;
 use crate::transaction::{SerdeTransaction, Transaction};
 
@@ -82,12 +92,31 @@ impl MemberError {
                 JsonRpcError::Transport(_) => true,
                 JsonRpcError::MaxSlotsExceeded => true,
                 JsonRpcError::RequestTimeout => true,
-                JsonRpcError::Call(e) => e.code() == 404,
+                JsonRpcError::Call(_) => self.api_error().map_or(false, |e| e.retriable),
                 _ => false,
             },
             MemberError::InvalidResponse(_) => false,
         }
     }
+
+    /// The error returned by the member's API, `None` if the request failed
+    /// for another reason. Guardians that don't attach the module and
+    /// retriable flag to their errors get the defaults for the code.
+    pub fn api_error(&self) -> Option<ApiError> {
+        let MemberError::Rpc(JsonRpcError::Call(e)) = self else {
+            return None;
+        };
+        let mut api_error = ApiError::new(e.code(), e.message().to_owned());
+        if let Some(data) = e
+            .data()
+            .and_then(|data| serde_json::from_str::<ApiErrorData>(data.get()).ok())
+        {
+            api_error.module = data.module;
+            api_error.retriable = data.retriable;
+            api_error.correlation_id = data.correlation_id;
+        }
+        Some(api_error)
+    }
 }
 
 /// An API request error when calling an entire federation
@@ -118,9 +147,35 @@ impl fmt::Display for FederationError {
 }
 
 impl FederationError {
+    /// An error that isn't caused by a specific peer
+    pub fn general(error: anyhow::Error) -> Self {
+        FederationError {
+            general: Some(error),
+            members: BTreeMap::new(),
+        }
+    }
+
     pub fn is_retryable(&self) -> bool {
         self.members.iter().any(|(_, e)| e.is_retryable())
     }
+
+    /// The errors returned by the members' APIs, see
+    /// [`MemberError::api_error`]
+    pub fn api_errors(&self) -> BTreeMap<PeerId, ApiError> {
+        self.members
+            .iter()
+            .filter_map(|(peer, e)| Some((*peer, e.api_error()?)))
+            .collect()
+    }
+
+    /// The diverging responses if the members disagreed on a query all
+    /// honest members answer the same
+    pub fn inconsistent_responses<R>(&self) -> Option<&InconsistentResponses<R>>
+    where
+        R: Debug + Send + Sync + 'static,
+    {
+        self.general.as_ref()?.downcast_ref()
+    }
 }
 
 type OutputOutcomeResult<O> = result::Result<O, OutputOutcomeError>;
@@ -155,6 +210,19 @@ pub trait IFederationApi: Debug + MaybeSend + MaybeSync {
 
     fn with_module(&self, id: ModuleInstanceId) -> DynModuleApi;
 
+    /// The same API, but all its requests carry `correlation_id`, including
+    /// the ones of module APIs derived from it
+    fn with_correlation_id(&self, correlation_id: CorrelationId) -> DynGlobalApi;
+
+    /// All federation members ordered by how likely they are to answer
+    /// quickly, healthiest first
+    ///
+    /// Implementations that don't track the health of members return them in
+    /// peer id order.
+    fn peers_by_health(&self) -> Vec<PeerId> {
+        self.all_members().iter().copied().collect()
+    }
+
     /// Make request to a specific federation member by `peer_id`
     async fn request_raw(
         &self,
@@ -288,6 +356,94 @@ pub trait FederationApiExt: IFederationApi {
         }
     }
 
+    /// Make a request whose response can be verified on its own, e.g. by a
+    /// federation signature, so a single member can be trusted to answer it.
+    ///
+    /// The healthiest member is asked first. If it fails or doesn't answer
+    /// within [`REQUEST_HEDGE_DELAY`] the next member is asked as well, and
+    /// so on, returning the first response that passes `verify`.
+    async fn request_fastest<MemberRet, Ret>(
+        &self,
+        method: String,
+        params: ApiRequestErased,
+        verify: impl Fn(MemberRet) -> anyhow::Result<Ret> + MaybeSend + MaybeSync,
+    ) -> FederationResult<Ret>
+    where
+        MemberRet: serde::de::DeserializeOwned,
+        Ret: MaybeSend,
+    {
+        #[cfg(not(target_family = "wasm"))]
+        let mut futures = FuturesUnordered::<Pin<Box<dyn Future<Output = _> + Send>>>::new();
+        #[cfg(target_family = "wasm")]
+        let mut futures = FuturesUnordered::<Pin<Box<dyn Future<Output = _>>>>::new();
+
+        let method = &method;
+        let params = &params;
+        let mut peers = self.peers_by_health().into_iter();
+        let mut member_errors = BTreeMap::new();
+        // Every iteration starts because we just started, a member failed or
+        // the pending members took too long, so we ask the next member
+        loop {
+            if let Some(peer) = peers.next() {
+                futures.push(Box::pin(async move {
+                    PeerResponse {
+                        peer,
+                        result: self.request_raw(peer, method, &[params.to_json()]).await,
+                    }
+                }));
+            }
+            if futures.is_empty() {
+                return Err(FederationError {
+                    general: None,
+                    members: member_errors,
+                });
+            }
+
+            // Members that take too long are not cancelled, they may still
+            // answer before the next one
+            let PeerResponse { peer, result } =
+                match task::timeout(REQUEST_HEDGE_DELAY, futures.next()).await {
+                    Ok(response) => response.expect("Checked that futures is not empty"),
+                    Err(_) => continue,
+                };
+            let result = result
+                .map_err(MemberError::Rpc)
+                .and_then(|value| {
+                    serde_json::from_value::<MemberRet>(value)
+                        .map_err(|e| MemberError::ResponseDeserialization(e.into()))
+                })
+                .and_then(|response| {
+                    verify(response).map_err(|e| MemberError::InvalidResponse(e.to_string()))
+                });
+            match result {
+                Ok(response) => return Ok(response),
+                Err(error) => {
+                    debug!(target: LOG_NET_API, %peer, method, %error, "Member failed, trying next one");
+                    member_errors.insert(peer, error);
+                }
+            }
+        }
+    }
+
+    /// Make a request a threshold of members have to agree on, for responses
+    /// that can't be verified on their own but that we must not be lied to
+    /// about
+    async fn request_threshold_consensus<Ret>(
+        &self,
+        method: String,
+        params: ApiRequestErased,
+    ) -> FederationResult<Ret>
+    where
+        Ret: serde::de::DeserializeOwned + Eq + Debug + Clone + MaybeSend,
+    {
+        self.request_with_strategy(
+            CurrentConsensus::new(self.all_members().threshold()),
+            method,
+            params,
+        )
+        .await
+    }
+
     async fn request_current_consensus<Ret>(
         &self,
         method: String,
@@ -365,6 +521,24 @@ pub trait GlobalFederationApi {
 
     async fn fetch_epoch_count(&self) -> FederationResult<u64>;
 
+    /// Fetches a proof that `txid` was accepted in a signed epoch, so we
+    /// don't have to download the epoch to check it
+    async fn fetch_transaction_inclusion_proof(
+        &self,
+        txid: TransactionId,
+        epoch_pk: PublicKey,
+        decoders: &ModuleDecoderRegistry,
+    ) -> FederationResult<TransactionInclusionProof>;
+
+    /// Waits for `epoch` and fetches its items matching `filter`, the
+    /// federation filters them so we don't have to download the whole epoch
+    async fn await_epoch_items(
+        &self,
+        epoch: u64,
+        filter: &EpochItemFilter,
+        decoders: &ModuleDecoderRegistry,
+    ) -> FederationResult<FilteredEpochItems>;
+
     async fn fetch_output_outcome<R>(
         &self,
         out_point: OutPoint,
@@ -388,6 +562,38 @@ pub trait GlobalFederationApi {
         info: &WsClientConnectInfo,
     ) -> FederationResult<ClientConfig>;
 
+    /// Fetches the config and module state a new client needs, signed by a
+    /// threshold of guardians, see [`crate::join`]
+    async fn download_join_snapshot(
+        &self,
+        info: &WsClientConnectInfo,
+    ) -> FederationResult<SignedFederationSnapshot>;
+
+    /// Fetches the aggregate activity of `epoch` signed by a threshold of the
+    /// guardians of the federation `federation_id`
+    async fn fetch_epoch_stats(
+        &self,
+        epoch: u64,
+        federation_id: FederationId,
+    ) -> FederationResult<SignedEpochStats>;
+
+    /// Fetches a receipt of how the federation `federation_id` processed the
+    /// transaction `txid`, signed by a threshold of its guardians, see
+    /// [`crate::receipt`]
+    async fn fetch_transaction_receipt(
+        &self,
+        txid: TransactionId,
+        federation_id: FederationId,
+    ) -> FederationResult<SignedTransactionReceipt>;
+
+    /// Fetches how long the recent epochs of a guardian took, to estimate
+    /// when a transaction submitted now will be processed
+    async fn fetch_epoch_timing(&self) -> FederationResult<EpochTimingEstimate>;
+
+    /// Fetches the hash of the client config if a threshold of peers agree on
+    /// it
+    async fn client_config_hash(&self) -> FederationResult<sha256::Hash>;
+
     /// Fetches the server consensus hash if enough peers agree on it
     async fn consensus_config_hash(&self) -> FederationResult<sha256::Hash>;
 
@@ -456,18 +662,30 @@ where
     }
 
     /// Fetch the outcome of an entire transaction
+    ///
+    /// Members that haven't processed the transaction yet answer `None`, in
+    /// which case the query fails with [`InconsistentResponses`] and can be
+    /// retried.
     async fn fetch_tx_outcome(
         &self,
         tx: &TransactionId,
     ) -> FederationResult<Option<TransactionStatus>> {
-        self.request_current_consensus("fetch_transaction".to_owned(), ApiRequestErased::new(tx))
-            .await
+        self.request_with_strategy(
+            ConsistentResponses::new(self.all_members()),
+            "fetch_transaction".to_owned(),
+            ApiRequestErased::new(tx),
+        )
+        .await
     }
 
     /// Await the outcome of an entire transaction
     async fn await_tx_outcome(&self, tx: &TransactionId) -> FederationResult<TransactionStatus> {
-        self.request_current_consensus("wait_transaction".to_owned(), ApiRequestErased::new(tx))
-            .await
+        self.request_with_strategy(
+            ConsistentResponses::new(self.all_members()),
+            "wait_transaction".to_owned(),
+            ApiRequestErased::new(tx),
+        )
+        .await
     }
 
     async fn fetch_epoch_history(
@@ -531,6 +749,74 @@ where
         .await
     }
 
+    async fn fetch_transaction_inclusion_proof(
+        &self,
+        txid: TransactionId,
+        epoch_pk: PublicKey,
+        decoders: &ModuleDecoderRegistry,
+    ) -> FederationResult<TransactionInclusionProof> {
+        struct ValidProofWrapper {
+            decoders: ModuleDecoderRegistry,
+            strategy: VerifiableResponse<TransactionInclusionProof>,
+        }
+
+        impl QueryStrategy<SerdeTransactionInclusionProof, TransactionInclusionProof>
+            for ValidProofWrapper
+        {
+            fn process(
+                &mut self,
+                peer: PeerId,
+                result: MemberResult<SerdeTransactionInclusionProof>,
+            ) -> QueryStep<TransactionInclusionProof> {
+                let response = result.and_then(|proof| {
+                    proof
+                        .try_into_inner(&self.decoders)
+                        .map_err(|e| MemberError::Rpc(jsonrpsee_core::Error::Custom(e.to_string())))
+                });
+                self.strategy.process(peer, response)
+            }
+        }
+
+        // A single valid proof is enough since it's signed by the federation
+        let qs = ValidProofWrapper {
+            decoders: decoders.clone(),
+            strategy: VerifiableResponse::new(
+                self.all_members().one_honest(),
+                true,
+                move |proof: &TransactionInclusionProof| proof.verify(&epoch_pk, txid).is_ok(),
+            ),
+        };
+
+        self.request_with_strategy::<SerdeTransactionInclusionProof, _>(
+            qs,
+            "fetch_transaction_inclusion_proof".to_owned(),
+            ApiRequestErased::new(txid),
+        )
+        .await
+    }
+
+    async fn await_epoch_items(
+        &self,
+        epoch: u64,
+        filter: &EpochItemFilter,
+        decoders: &ModuleDecoderRegistry,
+    ) -> FederationResult<FilteredEpochItems> {
+        // Filtered items carry no signature, so we need a threshold to agree on them
+        let items: SerdeFilteredEpochItems = self
+            .request_current_consensus(
+                "await_epoch_items".to_owned(),
+                ApiRequestErased::new(EpochItemsRequest {
+                    epoch,
+                    filter: filter.clone(),
+                }),
+            )
+            .await?;
+
+        items
+            .try_into_inner(decoders)
+            .map_err(|e| FederationError::general(e.0))
+    }
+
     async fn fetch_output_outcome<R>(
         &self,
         out_point: OutPoint,
@@ -569,22 +855,75 @@ where
         info: &WsClientConnectInfo,
     ) -> FederationResult<ClientConfig> {
         let id = info.id;
-        let qs = VerifiableResponse::new(
-            self.all_members().total(),
-            false,
-            move |config: &ClientConfigResponse| {
+        self.request_fastest(
+            "config".to_owned(),
+            ApiRequestErased::new(info.to_string()),
+            move |config: ClientConfigResponse| {
                 let hash = config.client_config.consensus_hash();
-                id.0.verify(&config.signature.0, hash)
+                ensure!(
+                    id.0.verify(&config.signature.0, hash),
+                    "Invalid config signature"
+                );
+                Ok(config.client_config)
             },
-        );
+        )
+        .await
+    }
 
+    async fn download_join_snapshot(
+        &self,
+        info: &WsClientConnectInfo,
+    ) -> FederationResult<SignedFederationSnapshot> {
         self.request_with_strategy(
-            qs,
-            "config".to_owned(),
+            CombineSnapshotShares::new(info.id, self.all_members()),
+            "join_snapshot".to_owned(),
             ApiRequestErased::new(info.to_string()),
         )
         .await
-        .map(|cfg: ClientConfigResponse| cfg.client_config)
+    }
+
+    async fn fetch_epoch_stats(
+        &self,
+        epoch: u64,
+        federation_id: FederationId,
+    ) -> FederationResult<SignedEpochStats> {
+        self.request_with_strategy(
+            CombineEpochStatsShares::new(federation_id, self.all_members()),
+            "epoch_stats".to_owned(),
+            ApiRequestErased::new(epoch),
+        )
+        .await
+    }
+
+    async fn fetch_transaction_receipt(
+        &self,
+        txid: TransactionId,
+        federation_id: FederationId,
+    ) -> FederationResult<SignedTransactionReceipt> {
+        self.request_with_strategy(
+            CombineReceiptShares::new(federation_id, txid, self.all_members()),
+            "transaction_receipt".to_owned(),
+            ApiRequestErased::new(txid),
+        )
+        .await
+    }
+
+    async fn fetch_epoch_timing(&self) -> FederationResult<EpochTimingEstimate> {
+        // Guardians measure their own timing, any of them gives a good estimate
+        self.request_fastest(
+            "epoch_timing".to_owned(),
+            ApiRequestErased::default(),
+            |timing: EpochTimingEstimate| Ok(timing),
+        )
+        .await
+    }
+
+    async fn client_config_hash(&self) -> FederationResult<sha256::Hash> {
+        self.request_threshold_consensus(
+            "client_config_hash".to_owned(),
+            ApiRequestErased::default(),
+        )
+        .await
     }
 
     async fn consensus_config_hash(&self) -> FederationResult<sha256::Hash> {
@@ -593,12 +932,8 @@ where
     }
 
     async fn upload_backup(&self, request: &SignedBackupRequest) -> FederationResult<()> {
-        self.request_with_strategy(
-            CurrentConsensus::new(self.all_members().threshold()),
-            "backup".to_owned(),
-            ApiRequestErased::new(request),
-        )
-        .await
+        self.request_threshold_consensus("backup".to_owned(), ApiRequestErased::new(request))
+            .await
     }
 
     async fn download_backup(
@@ -625,7 +960,7 @@ where
     ) -> FederationResult<ApiVersionSet> {
         self.request_with_strategy(
             DiscoverApiVersionSet::new(
-                self.all_members().len(),
+                self.all_members(),
                 now().add(Duration::from_secs(3)),
                 client_versions.clone(),
             ),
@@ -644,19 +979,55 @@ pub struct WsFederationApi<C = WsClient> {
     peers: BTreeSet<PeerId>,
     members: Arc<Vec<FederationMember<C>>>,
     module_id: Option<ModuleInstanceId>,
+    /// Negotiated major api versions of the modules, requests to these modules
+    /// are routed to the endpoints of that version
+    module_api_versions: Arc<BTreeMap<ModuleInstanceId, u32>>,
+    /// Added to all requests that don't carry a correlation id yet
+    correlation_id: Option<CorrelationId>,
 }
 
+/// Time after which [`FederationApiExt::request_fastest`] additionally asks the
+/// next member
+pub const REQUEST_HEDGE_DELAY: Duration = Duration::from_secs(2);
+
 #[derive(Debug)]
 struct FederationMember<C> {
     url: Url,
     peer_id: PeerId,
     client: RwLock<Option<C>>,
+    health: std::sync::Mutex<PeerHealth>,
+}
+
+/// How well a federation member answered our recent requests
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct PeerHealth {
+    /// Requests that failed to reach the member since the last one that
+    /// succeeded
+    consecutive_failures: u32,
+    /// Moving average of the latency of the requests that reached the member
+    latency: Option<Duration>,
+}
+
+impl PeerHealth {
+    fn record(&mut self, result: &JsonRpcResult<Value>, latency: Duration) {
+        match result {
+            // An error returned by the member still means it's reachable
+            Ok(_) | Err(JsonRpcError::Call(_)) => {
+                self.consecutive_failures = 0;
+                self.latency = Some(match self.latency {
+                    Some(average) => (average * 3 + latency) / 4,
+                    None => latency,
+                });
+            }
+            Err(_) => self.consecutive_failures = self.consecutive_failures.saturating_add(1),
+        }
+    }
 }
 
 /// Information required for client to construct [`WsFederationApi`] instance
 ///
 /// Can be used to download the configs and bootstrap a client
-#[derive(Clone, Debug, Eq, PartialEq, Encodable)]
+#[derive(Clone, Debug, Eq, PartialEq, Encodable, Decodable)]
 pub struct WsClientConnectInfo {
     /// Url to reach an API that we can download configs from
     pub url: Url,
@@ -664,6 +1035,33 @@ pub struct WsClientConnectInfo {
     pub download_token: ClientConfigDownloadToken,
     /// Authentication id for the federation
     pub id: FederationId,
+    /// API endpoints of all guardians, used to download the config if `url`
+    /// is unreachable (empty for v1 connect infos)
+    pub api_endpoints: BTreeMap<PeerId, Url>,
+    /// Bitcoin network the federation runs on
+    pub network: Option<bitcoin::Network>,
+    /// Gateway the federation recommends to its clients
+    pub gateway: Option<secp256k1::XOnlyPublicKey>,
+}
+
+impl WsClientConnectInfo {
+    /// Creates a connect info that only contains the fields of the v1 format
+    pub fn new(url: Url, download_token: ClientConfigDownloadToken, id: FederationId) -> Self {
+        WsClientConnectInfo {
+            url,
+            download_token,
+            id,
+            api_endpoints: BTreeMap::new(),
+            network: None,
+            gateway: None,
+        }
+    }
+
+    /// Whether the connect info can be encoded in the v1 format understood by
+    /// older clients
+    fn is_v1(&self) -> bool {
+        self.api_endpoints.is_empty() && self.network.is_none() && self.gateway.is_none()
+    }
 }
 
 /// Size of a download token
@@ -682,16 +1080,30 @@ pub struct ClientConfigDownloadToken(pub [u8; CONFIG_DOWNLOAD_TOKEN_BYTES]);
 /// ```
 const BECH32_HRP: &str = "fed1";
 
+/// HRP of the v2 format, whose data is the consensus encoding of
+/// [`WsClientConnectInfo`]
+const BECH32_HRP_V2: &str = "fed2";
+
 impl FromStr for WsClientConnectInfo {
     type Err = anyhow::Error;
 
     fn from_str(encoded: &str) -> Result<Self, Self::Err> {
         let (hrp, data, variant) = bech32::decode(encoded)?;
 
-        ensure!(hrp == BECH32_HRP, "Invalid HRP in bech32 encoding");
+        ensure!(
+            hrp == BECH32_HRP || hrp == BECH32_HRP_V2,
+            "Invalid HRP in bech32 encoding"
+        );
         ensure!(variant == Bech32m, "Expected Bech32m encoding");
 
         let bytes: Vec<u8> = Vec::<u8>::from_base32(&data)?;
+        if hrp == BECH32_HRP_V2 {
+            return Ok(Self::consensus_decode(
+                &mut Cursor::new(bytes),
+                &ModuleDecoderRegistry::default(),
+            )?);
+        }
+
         let mut cursor = Cursor::new(bytes);
         let mut id_bytes = [0; PK_SIZE];
         cursor.read_exact(&mut id_bytes)?;
@@ -706,17 +1118,27 @@ impl FromStr for WsClientConnectInfo {
 
         let url = std::str::from_utf8(&url_bytes)?;
 
-        Ok(Self {
-            url: url.parse()?,
-            download_token: ClientConfigDownloadToken(download_token),
-            id: FederationId(PublicKey::from_bytes(id_bytes)?),
-        })
+        Ok(Self::new(
+            url.parse()?,
+            ClientConfigDownloadToken(download_token),
+            FederationId(PublicKey::from_bytes(id_bytes)?),
+        ))
     }
 }
 
 /// Parses the connect info from a bech32 string
+///
+/// Uses the v1 format if the connect info has no v2 fields, so it can be
+/// parsed by older clients
 impl Display for WsClientConnectInfo {
     fn fmt(&self, formatter: &mut Formatter<'_>) -> fmt::Result {
+        if !self.is_v1() {
+            let data = self.consensus_encode_to_vec().map_err(|_| fmt::Error)?;
+            let encode =
+                bech32::encode(BECH32_HRP_V2, data.to_base32(), Bech32m).map_err(|_| fmt::Error)?;
+            return formatter.write_str(&encode);
+        }
+
         let mut data = vec![];
         data.extend(self.id.0.to_bytes());
         let url_bytes = self.url.as_str().as_bytes();
@@ -767,10 +1189,41 @@ impl<C: JsonRpcClient + Debug + 'static> IFederationApi for WsFederationApi<C> {
             peers: self.peers.clone(),
             members: self.members.clone(),
             module_id: Some(id),
+            module_api_versions: self.module_api_versions.clone(),
+            correlation_id: self.correlation_id,
+        }
+        .into()
+    }
+
+    fn with_correlation_id(&self, correlation_id: CorrelationId) -> DynGlobalApi {
+        WsFederationApi {
+            peers: self.peers.clone(),
+            members: self.members.clone(),
+            module_id: self.module_id,
+            module_api_versions: self.module_api_versions.clone(),
+            correlation_id: Some(correlation_id),
         }
         .into()
     }
 
+    fn peers_by_health(&self) -> Vec<PeerId> {
+        let mut members = self
+            .members
+            .iter()
+            .map(|member| (member.health(), member.peer_id))
+            .collect::<Vec<_>>();
+        members.sort_by_key(|(health, peer_id)| {
+            (
+                health.consecutive_failures,
+                // Members we haven't measured yet go first so every member gets
+                // measured eventually
+                health.latency.unwrap_or(Duration::ZERO),
+                *peer_id,
+            )
+        });
+        members.into_iter().map(|(_, peer_id)| peer_id).collect()
+    }
+
     async fn request_raw(
         &self,
         peer_id: PeerId,
@@ -785,9 +1238,22 @@ impl<C: JsonRpcClient + Debug + 'static> IFederationApi for WsFederationApi<C> {
 
         let method = match self.module_id {
             None => method.to_string(),
-            Some(id) => format!("module_{id}_{method}"),
+            Some(id) => match self.module_api_versions.get(&id) {
+                Some(major) => format!("module_{id}_v{major}_{method}"),
+                None => format!("module_{id}_{method}"),
+            },
         };
-        member.request(&method, params).await
+        let params = match self.correlation_id {
+            Some(correlation_id) => params
+                .iter()
+                .map(|param| add_correlation_id(param.clone(), correlation_id))
+                .collect(),
+            None => params.to_vec(),
+        };
+        let start = now();
+        let result = member.request(&method, &params).await;
+        member.record_health(&result, now().duration_since(start).unwrap_or_default());
+        result
     }
 }
 
@@ -844,6 +1310,84 @@ impl WsFederationApi<WsClient> {
                 .collect(),
         )
     }
+
+    /// Downloads the client config using the connect info, then verifies with
+    /// all guardians listed in the config that a threshold of them agree on
+    /// it.
+    ///
+    /// The federation id of the connect info is pinned, so a malicious
+    /// guardian we bootstrap from can neither make us join another federation
+    /// nor hand out a config the federation didn't agree on.
+    pub async fn download_verified_client_config(
+        info: &WsClientConnectInfo,
+    ) -> FederationResult<ClientConfig> {
+        let mut result = Self::from_connect_info(&[info.clone()])
+            .download_client_config(info)
+            .await;
+        // Every guardian has its own download token, which it shares in its
+        // connect info
+        for (peer_id, url) in &info.api_endpoints {
+            if result.is_ok() {
+                break;
+            }
+            if *url == info.url {
+                continue;
+            }
+            let api = Self::new(vec![(*peer_id, url.clone())]);
+            result = async {
+                let code: String = api
+                    .request_current_consensus(
+                        "connection_code".to_owned(),
+                        ApiRequestErased::default(),
+                    )
+                    .await?;
+                let peer_info = WsClientConnectInfo {
+                    id: info.id,
+                    ..WsClientConnectInfo::from_str(&code).map_err(FederationError::general)?
+                };
+                api.download_client_config(&peer_info).await
+            }
+            .await;
+        }
+        let config = result?;
+        if config.federation_id != info.id {
+            return Err(FederationError::general(anyhow!(
+                "Config is for federation {} instead of {}",
+                config.federation_id,
+                info.id
+            )));
+        }
+
+        let hash = Self::from_config(&config).client_config_hash().await?;
+        if hash != config.consensus_hash() {
+            return Err(FederationError::general(anyhow!(
+                "Guardians agree on a different config than the one downloaded"
+            )));
+        }
+        Ok(config)
+    }
+
+    /// Joins in a single round trip by asking all guardians listed in the
+    /// connect info for their snapshot of the federation, unlike
+    /// [`Self::download_verified_client_config`] this also returns the module
+    /// state a new client needs
+    pub async fn download_join_snapshot_from_connect_info(
+        info: &WsClientConnectInfo,
+    ) -> FederationResult<SignedFederationSnapshot> {
+        if info.api_endpoints.is_empty() {
+            return Err(FederationError::general(anyhow!(
+                "Connect info doesn't list the endpoints of the guardians"
+            )));
+        }
+        Self::new(
+            info.api_endpoints
+                .iter()
+                .map(|(peer_id, url)| (*peer_id, url.clone()))
+                .collect(),
+        )
+        .download_join_snapshot(info)
+        .await
+    }
 }
 
 impl<C> WsFederationApi<C> {
@@ -851,6 +1395,21 @@ impl<C> WsFederationApi<C> {
         self.members.iter().map(|member| member.peer_id).collect()
     }
 
+    /// Routes module requests to the endpoints of the negotiated api versions,
+    /// so a module can change its API without breaking older clients
+    pub fn with_api_versions(self, api_versions: &ApiVersionSet) -> Self {
+        WsFederationApi {
+            module_api_versions: Arc::new(
+                api_versions
+                    .modules
+                    .iter()
+                    .map(|(id, version)| (*id, version.major))
+                    .collect(),
+            ),
+            ..self
+        }
+    }
+
     /// Creates a new API client
     pub fn new_with_client(members: Vec<(PeerId, Url)>) -> Self {
         WsFederationApi {
@@ -869,21 +1428,45 @@ impl<C> WsFederationApi<C> {
                             peer_id,
                             url,
                             client: RwLock::new(None),
+                            health: Default::default(),
                         }
                     })
                     .collect(),
             ),
             module_id: None,
+            module_api_versions: Default::default(),
+            correlation_id: None,
         }
     }
 }
 
+/// Adds `correlation_id` to `param` if it is an [`ApiRequestErased`] that
+/// doesn't carry one yet
+fn add_correlation_id(mut param: Value, correlation_id: CorrelationId) -> Value {
+    if let Value::Object(request) = &mut param {
+        request.entry("correlation_id").or_insert_with(|| {
+            serde_json::to_value(correlation_id).expect("serialization can't fail")
+        });
+    }
+    param
+}
+
 #[derive(Debug)]
 pub struct PeerResponse<R> {
     pub peer: PeerId,
     pub result: JsonRpcResult<R>,
 }
 
+impl<C> FederationMember<C> {
+    fn health(&self) -> PeerHealth {
+        *self.health.lock().unwrap()
+    }
+
+    fn record_health(&self, result: &JsonRpcResult<Value>, latency: Duration) {
+        self.health.lock().unwrap().record(result, latency);
+    }
+}
+
 impl<C: JsonRpcClient> FederationMember<C> {
     #[instrument(level = "trace", fields(peer = %self.peer_id, %method), skip_all)]
     pub async fn request(&self, method: &str, params: &[Value]) -> JsonRpcResult<Value> {
@@ -976,9 +1559,13 @@ pub struct PeerConsensusStatus {
     pub last_contribution_timestamp_seconds: Option<u64>,
     pub connection_status: PeerConnectionStatus,
     /// Indicates that this peer needs attention from the operator. For instance
-    /// it may be suffering too many disconnections or it hasn't contributed for
-    /// the consensus in a long time
+    /// it may be suffering too many disconnections, it hasn't contributed for
+    /// the consensus in a long time or its clock is off
     pub flagged: bool,
+    /// How far the peer's clock is ahead of ours, `None` if we didn't receive
+    /// a message from it yet
+    #[serde(default)]
+    pub clock_skew_ms: Option<i64>,
 }
 
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
@@ -1022,6 +1609,33 @@ pub enum ServerStatus {
 pub struct StatusResponse {
     pub server: ServerStatus,
     pub consensus: Option<ConsensusStatus>,
+    /// Health of the modules while consensus is running
+    #[serde(default)]
+    pub modules: BTreeMap<ModuleInstanceId, ModuleHealth>,
+}
+
+/// Health of the background tasks of a module
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ModuleHealth {
+    pub kind: ModuleKind,
+    /// False while one of the tasks waits to be restarted after a panic, a
+    /// monitoring tool should generate an alert if this is the case
+    pub healthy: bool,
+    /// Tasks that are restarted if they panic, by name
+    pub tasks: BTreeMap<String, task::TaskHealth>,
+}
+
+impl ModuleHealth {
+    pub fn new(kind: ModuleKind, tasks: BTreeMap<String, task::TaskHealth>) -> Self {
+        let healthy = tasks
+            .values()
+            .all(|task| task.running || task.last_panic.is_none());
+        Self {
+            kind,
+            healthy,
+            tasks,
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1059,6 +1673,12 @@ mod tests {
 
     struct Client<C: SimpleClient>(C);
 
+    impl<C: SimpleClient> fmt::Debug for Client<C> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.write_str("Client")
+        }
+    }
+
     #[apply(async_trait_maybe_send!)]
     impl<C: SimpleClient + MaybeSend + MaybeSync> JsonRpcClient for Client<C> {
         fn is_connected(&self) -> bool {
@@ -1104,6 +1724,7 @@ mod tests {
             url: Url::from_str("http://127.0.0.1").expect("Could not parse"),
             peer_id: PeerId::from(0),
             client: RwLock::new(None),
+            health: Default::default(),
         }
     }
 
@@ -1161,6 +1782,69 @@ mod tests {
         );
     }
 
+    #[test]
+    fn peers_are_ordered_by_health() {
+        struct NullClient;
+
+        #[apply(async_trait_maybe_send!)]
+        impl SimpleClient for NullClient {
+            async fn connect() -> Result<Self> {
+                Ok(NullClient)
+            }
+
+            async fn request(&self, _method: &str) -> Result<String> {
+                Ok("null".to_string())
+            }
+        }
+
+        let api = WsFederationApi::<Client<NullClient>>::new_with_client(
+            (0..4)
+                .map(|i| {
+                    let url = Url::from_str(&format!("ws://127.0.0.1:{}", 5000 + i)).unwrap();
+                    (PeerId::from(i), url)
+                })
+                .collect(),
+        );
+        let ok = Ok(Value::Null);
+        let failed = Err(JsonRpcError::Transport(anyhow!("unreachable")));
+        let record = |peer: u16, result: &JsonRpcResult<Value>, latency_ms: u64| {
+            api.members[peer as usize].record_health(result, Duration::from_millis(latency_ms));
+        };
+
+        record(0, &ok, 300);
+        record(1, &failed, 0);
+        record(2, &ok, 100);
+        // peer 3 hasn't been measured yet
+        assert_eq!(api.peers_by_health(), [3, 2, 0, 1].map(PeerId::from));
+
+        record(3, &failed, 0);
+        record(3, &failed, 0);
+        record(1, &ok, 50);
+        assert_eq!(api.peers_by_health(), [1, 2, 0, 3].map(PeerId::from));
+
+        // the latency is averaged, so a single slow response doesn't demote
+        // a member below one that is always slow
+        record(1, &ok, 600);
+        assert_eq!(api.peers_by_health(), [2, 1, 0, 3].map(PeerId::from));
+    }
+
+    #[test]
+    fn adds_correlation_id_to_requests() {
+        let request = ApiRequestErased::new(42).to_json();
+        // Servers that don't know correlation ids yet don't receive the field
+        assert!(request.get("correlation_id").is_none());
+
+        let correlation_id = CorrelationId([1, 2, 3, 4, 5, 6, 7, 8]);
+        let stamped = add_correlation_id(request, correlation_id);
+        assert_eq!(stamped["correlation_id"], "0102030405060708");
+        let request: ApiRequestErased = serde_json::from_value(stamped.clone()).unwrap();
+        assert_eq!(request.correlation_id, Some(correlation_id));
+
+        // Ids set on the request itself take precedence
+        let restamped = add_correlation_id(stamped, CorrelationId([0; 8]));
+        assert_eq!(restamped["correlation_id"], "0102030405060708");
+    }
+
     #[test_log::test(tokio::test)]
     async fn concurrent_requests() {
         static CONNECTION_COUNT: AtomicUsize = AtomicUsize::new(0);
@@ -1255,13 +1939,14 @@ mod tests {
 
     #[test]
     fn converts_connect_string() {
-        let connect = WsClientConnectInfo {
-            url: "ws://test1".parse().unwrap(),
-            id: FederationId::dummy(),
-            download_token: ClientConfigDownloadToken(OsRng::default().gen()),
-        };
+        let connect = WsClientConnectInfo::new(
+            "ws://test1".parse().unwrap(),
+            ClientConfigDownloadToken(OsRng::default().gen()),
+            FederationId::dummy(),
+        );
 
         let bech32 = connect.to_string();
+        assert!(bech32.starts_with("fed11"));
         let connect_parsed = WsClientConnectInfo::from_str(&bech32).expect("parses");
         assert_eq!(connect, connect_parsed);
 
@@ -1270,5 +1955,21 @@ mod tests {
         assert_eq!(connect_as_string, bech32);
         let connect_parsed_json: WsClientConnectInfo = serde_json::from_str(&json).unwrap();
         assert_eq!(connect_parsed_json, connect_parsed);
+
+        // With guardian endpoints or hints the v2 format is used
+        let connect_v2 = WsClientConnectInfo {
+            api_endpoints: BTreeMap::from([
+                (PeerId::from(0), "ws://test1".parse().unwrap()),
+                (PeerId::from(1), "ws://test2".parse().unwrap()),
+            ]),
+            network: Some(bitcoin::Network::Regtest),
+            ..connect
+        };
+        let bech32_v2 = connect_v2.to_string();
+        assert!(bech32_v2.starts_with("fed21"));
+        assert_eq!(
+            WsClientConnectInfo::from_str(&bech32_v2).expect("parses"),
+            connect_v2
+        );
     }
 }
@@ -9,6 +9,10 @@ use url::Url;
 pub const FM_BITCOIN_RPC_KIND: &str = "FM_BITCOIN_RPC_KIND";
 /// Env var for bitcoin URL
 pub const FM_BITCOIN_RPC_URL: &str = "FM_BITCOIN_RPC_URL";
+/// Env var for the kind of the bitcoin RPC used to cross-check the primary one
+pub const FM_SECONDARY_BITCOIN_RPC_KIND: &str = "FM_SECONDARY_BITCOIN_RPC_KIND";
+/// Env var for the URL of the bitcoin RPC used to cross-check the primary one
+pub const FM_SECONDARY_BITCOIN_RPC_URL: &str = "FM_SECONDARY_BITCOIN_RPC_URL";
 
 /// Configuration for the bitcoin RPC
 #[derive(Debug, Clone, Serialize, Deserialize, Decodable, Encodable)]
@@ -27,4 +31,18 @@ impl BitcoinRpcConfig {
                 .map_err(anyhow::Error::from)?,
         })
     }
+
+    /// Reads the optional secondary bitcoin RPC, `None` if its kind isn't set
+    pub fn secondary_from_env_vars() -> anyhow::Result<Option<Self>> {
+        let Ok(kind) = env::var(FM_SECONDARY_BITCOIN_RPC_KIND) else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            kind,
+            url: env::var(FM_SECONDARY_BITCOIN_RPC_URL)
+                .map_err(anyhow::Error::from)?
+                .parse()
+                .map_err(anyhow::Error::from)?,
+        }))
+    }
 }
@@ -271,6 +271,21 @@ impl ClientConfig {
     pub fn federation_name(&self) -> Option<&str> {
         self.meta.get(META_FEDERATION_NAME_KEY).map(|x| &**x)
     }
+
+    /// Welcome message from config metadata (if set)
+    pub fn welcome_message(&self) -> Option<&str> {
+        self.meta.get(META_WELCOME_MESSAGE_KEY).map(|x| &**x)
+    }
+
+    /// Federation icon url from config metadata (if set)
+    pub fn federation_icon_url(&self) -> Option<&str> {
+        self.meta.get(META_FEDERATION_ICON_URL_KEY).map(|x| &**x)
+    }
+
+    /// Guardian contact info from config metadata (if set)
+    pub fn contact_info(&self) -> Option<&str> {
+        self.meta.get(META_CONTACT_INFO_KEY).map(|x| &**x)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -651,10 +666,24 @@ pub trait TypedClientModuleConfig:
 pub enum DkgPeerMsg {
     PublicKey(secp256k1::PublicKey),
     DistributedGen(SupportedDkgMessage),
+    /// Consensus encoding of a message of a module specific DKG
+    Module(Vec<u8>),
+    /// Parts of the DKG we kept the results of from a previous run
+    CachedParts(DkgCachedParts),
     // Dkg completed on our side
     Done,
 }
 
+/// Parts of a distributed key generation a peer kept the results of from a
+/// previous run
+#[derive(Serialize, Deserialize, Debug, Clone, Default, Eq, PartialEq)]
+pub struct DkgCachedParts {
+    /// Whether the consensus keys were generated
+    pub global_keys: bool,
+    /// Modules whose configs were generated
+    pub modules: BTreeSet<ModuleInstanceId>,
+}
+
 /// Result of running DKG
 pub type DkgResult<T> = Result<T, DkgError>;
 
@@ -797,6 +826,15 @@ impl SGroup for G1Projective {
 /// of the config
 pub const META_FEDERATION_NAME_KEY: &str = "federation_name";
 
+/// Key under which a welcome message for users can be sent to clients
+pub const META_WELCOME_MESSAGE_KEY: &str = "welcome_message";
+
+/// Key under which the url of the federation's icon can be sent to clients
+pub const META_FEDERATION_ICON_URL_KEY: &str = "federation_icon_url";
+
+/// Key under which the contact info of the guardians can be sent to clients
+pub const META_CONTACT_INFO_KEY: &str = "contact_info";
+
 pub fn load_from_file<T: DeserializeOwned>(path: &Path) -> Result<T, anyhow::Error> {
     let file = std::fs::File::open(path)?;
     Ok(serde_json::from_reader(file)?)
@@ -11,12 +11,14 @@ use fedimint_core::module::audit::Audit;
 use fedimint_core::{apply, async_trait_maybe_send, OutPoint, PeerId};
 
 use super::*;
-use crate::db::ModuleDatabaseTransaction;
+use crate::db::{DatabaseTransaction, ModuleDatabaseTransaction};
 use crate::maybe_add_send_sync;
+use crate::module::interconnect::{ErasedInterconnectValue, InterconnectHandler};
 use crate::module::{
-    ApiEndpoint, ApiEndpointContext, ApiRequestErased, ConsensusProposal, InputMeta, ModuleCommon,
-    ModuleError, ServerModule, TransactionItemAmount,
+    ApiEndpoint, ApiEndpointContext, ApiRequestErased, ApiVersion, ConsensusProposal, InputMeta,
+    ModuleCommon, ModuleError, ServerModule, TransactionItemAmount,
 };
+use crate::stats::ItemActivity;
 use crate::task::{MaybeSend, MaybeSync};
 
 pub trait IVerificationCache: Debug {
@@ -64,13 +66,20 @@ pub trait IServerModule: Debug {
         module_instance_id: ModuleInstanceId,
     ) -> ConsensusProposal<DynModuleConsensusItem>;
 
+    /// Whether `consensus_item` should be encrypted until it has been ordered
+    fn encrypt_consensus_item(&self, consensus_item: &DynModuleConsensusItem) -> bool;
+
+    /// Whether `consensus_item` should be journaled until it has been ordered
+    fn journal_consensus_item(&self, consensus_item: &DynModuleConsensusItem) -> bool;
+
     /// This function is called once before transaction processing starts.
     ///
     /// All module consensus items of this round are supplied as
     /// `consensus_items`. The database transaction will be committed to the
     /// database after all other modules ran `begin_consensus_epoch`, so the
     /// results are available when processing transactions. Returns any
-    /// peers that need to be dropped.
+    /// misbehaving peers, their misbehavior is scored and may get them
+    /// quarantined.
     async fn begin_consensus_epoch<'a>(
         &self,
         dbtx: &mut ModuleDatabaseTransaction<'a>,
@@ -147,7 +156,8 @@ pub trait IServerModule: Debug {
     /// finalization code before the next epoch.
     ///
     /// Passes in the `consensus_peers` that contributed to this epoch and
-    /// returns a list of peers to drop if any are misbehaving.
+    /// returns a list of misbehaving peers, their misbehavior is scored and may
+    /// get them quarantined.
     async fn end_consensus_epoch<'a>(
         &self,
         consensus_peers: &BTreeSet<PeerId>,
@@ -172,11 +182,35 @@ pub trait IServerModule: Debug {
     /// occurred in the database and consensus should halt.
     async fn audit(&self, dbtx: &mut ModuleDatabaseTransaction<'_>, audit: &mut Audit);
 
+    /// Contribution of an accepted input to the public epoch stats
+    fn input_activity(&self, input: &DynInput) -> ItemActivity;
+
+    /// Contribution of an accepted output to the public epoch stats
+    fn output_activity(&self, output: &DynOutput) -> ItemActivity;
+
+    /// Returns the consensus encoded state a joining client needs from this
+    /// module
+    async fn join_snapshot(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) -> Option<Vec<u8>>;
+
+    /// Returns the state of the module shown on guardian dashboards
+    async fn admin_status(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> Option<serde_json::Value>;
+
     /// Returns a list of custom API endpoints defined by the module. These are
     /// made available both to users as well as to other modules. They thus
     /// should be deterministic, only dependant on their input and the
     /// current epoch.
     fn api_endpoints(&self) -> Vec<ApiEndpoint<DynServerModule>>;
+
+    /// Returns the API endpoints served under the major `version` of the
+    /// module's API
+    fn api_endpoints_for_version(&self, version: ApiVersion) -> Vec<ApiEndpoint<DynServerModule>>;
+
+    /// Returns the typed calls the module serves to the other modules of its
+    /// federation
+    fn interconnect_handlers(&self) -> Vec<InterconnectHandler<DynServerModule>>;
 }
 
 dyn_newtype_define!(
@@ -213,13 +247,36 @@ where
             .map(|v| DynModuleConsensusItem::from_typed(module_instance_id, v))
     }
 
+    /// Whether `consensus_item` should be encrypted until it has been ordered
+    fn encrypt_consensus_item(&self, consensus_item: &DynModuleConsensusItem) -> bool {
+        <Self as ServerModule>::encrypt_consensus_item(
+            self,
+            consensus_item
+                .as_any()
+                .downcast_ref::<<<Self as ServerModule>::Common as ModuleCommon>::ConsensusItem>()
+                .expect("incorrect consensus item type passed to module plugin"),
+        )
+    }
+
+    /// Whether `consensus_item` should be journaled until it has been ordered
+    fn journal_consensus_item(&self, consensus_item: &DynModuleConsensusItem) -> bool {
+        <Self as ServerModule>::journal_consensus_item(
+            self,
+            consensus_item
+                .as_any()
+                .downcast_ref::<<<Self as ServerModule>::Common as ModuleCommon>::ConsensusItem>()
+                .expect("incorrect consensus item type passed to module plugin"),
+        )
+    }
+
     /// This function is called once before transaction processing starts.
     ///
     /// All module consensus items of this round are supplied as
     /// `consensus_items`. The database transaction will be committed to the
     /// database after all other modules ran `begin_consensus_epoch`, so the
     /// results are available when processing transactions. Returns any
-    /// peers that need to be dropped.
+    /// misbehaving peers, their misbehavior is scored and may get them
+    /// quarantined.
     async fn begin_consensus_epoch<'a>(
         &self,
         dbtx: &mut ModuleDatabaseTransaction<'a>,
@@ -378,7 +435,8 @@ where
     /// finalization code before the next epoch.
     ///
     /// Passes in the `consensus_peers` that contributed to this epoch and
-    /// returns a list of peers to drop if any are misbehaving.
+    /// returns a list of misbehaving peers, their misbehavior is scored and may
+    /// get them quarantined.
     async fn end_consensus_epoch<'a>(
         &self,
         consensus_peers: &BTreeSet<PeerId>,
@@ -411,23 +469,96 @@ where
         <Self as ServerModule>::audit(self, dbtx, audit).await
     }
 
+    fn input_activity(&self, input: &DynInput) -> ItemActivity {
+        <Self as ServerModule>::input_activity(
+            self,
+            input
+                .as_any()
+                .downcast_ref::<<<Self as ServerModule>::Common as ModuleCommon>::Input>()
+                .expect("incorrect input type passed to module plugin"),
+        )
+    }
+
+    fn output_activity(&self, output: &DynOutput) -> ItemActivity {
+        <Self as ServerModule>::output_activity(
+            self,
+            output
+                .as_any()
+                .downcast_ref::<<<Self as ServerModule>::Common as ModuleCommon>::Output>()
+                .expect("incorrect output type passed to module plugin"),
+        )
+    }
+
+    async fn join_snapshot(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) -> Option<Vec<u8>> {
+        <Self as ServerModule>::join_snapshot(self, dbtx).await
+    }
+
+    async fn admin_status(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> Option<serde_json::Value> {
+        <Self as ServerModule>::admin_status(self, dbtx).await
+    }
+
     fn api_endpoints(&self) -> Vec<ApiEndpoint<DynServerModule>> {
-        <Self as ServerModule>::api_endpoints(self)
+        erase_api_endpoints(<Self as ServerModule>::api_endpoints(self))
+    }
+
+    fn api_endpoints_for_version(&self, version: ApiVersion) -> Vec<ApiEndpoint<DynServerModule>> {
+        erase_api_endpoints(<Self as ServerModule>::api_endpoints_for_version(
+            self, version,
+        ))
+    }
+
+    fn interconnect_handlers(&self) -> Vec<InterconnectHandler<DynServerModule>> {
+        <Self as ServerModule>::interconnect_handlers(self)
             .into_iter()
-            .map(|ApiEndpoint { path, handler }| ApiEndpoint {
-                path,
-                handler: Box::new(
-                    move |module: &DynServerModule,
-                          context: ApiEndpointContext<'_>,
-                          value: ApiRequestErased| {
-                        let typed_module = module
-                            .as_any()
-                            .downcast_ref::<T>()
-                            .expect("the dispatcher should always call with the right module");
-                        Box::pin(handler(typed_module, context, value))
-                    },
-                ),
-            })
+            .map(
+                |InterconnectHandler {
+                     name,
+                     version,
+                     handler,
+                 }| InterconnectHandler {
+                    name,
+                    version,
+                    handler: Box::new(
+                        move |module: &DynServerModule,
+                              dbtx: DatabaseTransaction<'_>,
+                              request: ErasedInterconnectValue| {
+                            let typed_module = module
+                                .as_any()
+                                .downcast_ref::<T>()
+                                .expect("the dispatcher should always call with the right module");
+                            handler(typed_module, dbtx, request)
+                        },
+                    ),
+                },
+            )
             .collect()
     }
 }
+
+/// Turns the endpoints of a typed module into ones callable with the
+/// `DynServerModule` holding it
+fn erase_api_endpoints<T>(endpoints: Vec<ApiEndpoint<T>>) -> Vec<ApiEndpoint<DynServerModule>>
+where
+    T: ServerModule + 'static + Sync,
+{
+    endpoints
+        .into_iter()
+        .map(|ApiEndpoint { path, handler }| ApiEndpoint {
+            path,
+            handler: Box::new(
+                move |module: &DynServerModule,
+                      context: ApiEndpointContext<'_>,
+                      value: ApiRequestErased| {
+                    let typed_module = module
+                        .as_any()
+                        .downcast_ref::<T>()
+                        .expect("the dispatcher should always call with the right module");
+                    Box::pin(handler(typed_module, context, value))
+                },
+            ),
+        })
+        .collect()
+}
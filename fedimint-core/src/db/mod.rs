@@ -2,15 +2,17 @@ use std::collections::BTreeMap;
 use std::error::Error;
 use std::fmt::Debug;
 use std::marker::PhantomData;
+use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
+use std::time::SystemTime;
 
 use anyhow::{Context, Result};
 use fedimint_core::util::BoxFuture;
 use fedimint_logging::LOG_DB;
 use futures::{Stream, StreamExt};
 use macro_rules_attribute::apply;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 use thiserror::Error;
 use tracing::{debug, error, info, instrument, trace, warn};
@@ -23,6 +25,7 @@ use crate::{async_trait_maybe_send, maybe_add_send, timing};
 
 pub mod mem_impl;
 pub mod notifications;
+pub mod snapshot;
 
 pub use test_utils::*;
 
@@ -86,6 +89,15 @@ pub type PrefixStream<'a> = Pin<Box<maybe_add_send!(dyn Stream<Item = (Vec<u8>,
 #[apply(async_trait_maybe_send!)]
 pub trait IDatabase: Debug + MaybeSend + MaybeSync + 'static {
     async fn begin_transaction<'a>(&'a self) -> Box<dyn ISingleUseDatabaseTransaction<'a>>;
+
+    /// Creates a consistent, native copy of the whole database at
+    /// `backup_path` while the database stays available for reads and writes.
+    ///
+    /// Backends that can't do this cheaply should not override it, callers
+    /// can fall back to [`Database::export_snapshot`] instead.
+    fn checkpoint(&self, _backup_path: &Path) -> Result<()> {
+        anyhow::bail!("Database backend does not support checkpoints")
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -330,6 +342,13 @@ impl Database {
         }
     }
 
+    /// Creates a native checkpoint of the whole database (including other
+    /// modules' data if this is an isolated database), see
+    /// [`IDatabase::checkpoint`].
+    pub fn checkpoint(&self, backup_path: &Path) -> Result<()> {
+        self.inner_db.db.checkpoint(backup_path)
+    }
+
     /// Waits for key to be present in database.
     pub async fn wait_key_exists<K>(&self, key: &K) -> K::Value
     where
@@ -561,11 +580,12 @@ impl<'a, Tx: IDatabaseTransaction<'a> + MaybeSend> ISingleUseDatabaseTransaction
 pub struct CommitTracker {
     is_committed: bool,
     has_writes: bool,
+    ignore_uncommitted: bool,
 }
 
 impl Drop for CommitTracker {
     fn drop(&mut self) {
-        if self.has_writes && !self.is_committed {
+        if self.has_writes && !self.is_committed && !self.ignore_uncommitted {
             warn!(
                 target: LOG_DB,
                 "DatabaseTransaction has writes and has not called commit."
@@ -1056,6 +1076,7 @@ impl<'parent> DatabaseTransaction<'parent> {
             commit_tracker: CommitTracker {
                 is_committed: false,
                 has_writes: false,
+                ignore_uncommitted: false,
             },
         }
     }
@@ -1095,6 +1116,34 @@ impl<'parent> DatabaseTransaction<'parent> {
         }
     }
 
+    /// Streams the raw entries whose key starts with `key_prefix`, for copying
+    /// a database without knowing its records
+    pub async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> Result<PrefixStream<'_>> {
+        self.tx.raw_find_by_prefix(key_prefix).await
+    }
+
+    /// Inserts a raw entry, for copying a database without knowing its
+    /// records. Waiters on `key` are notified once the transaction commits.
+    pub async fn raw_insert_bytes(&mut self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.commit_tracker.has_writes = true;
+        self.tx.add_notification_key(key)?;
+        self.tx.raw_insert_bytes(key, value).await
+    }
+
+    /// Removes a raw entry, see [`DatabaseTransaction::raw_insert_bytes`]
+    pub async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.commit_tracker.has_writes = true;
+        self.tx.add_notification_key(key)?;
+        self.tx.raw_remove_entry(key).await
+    }
+
+    /// Don't warn about uncommitted writes when the transaction is dropped,
+    /// for transactions that are discarded on purpose
+    pub fn ignore_uncommitted(&mut self) -> &mut Self {
+        self.commit_tracker.ignore_uncommitted = true;
+        self
+    }
+
     pub async fn commit_tx_result(mut self) -> Result<()> {
         self.commit_tracker.is_committed = true;
         return self.tx.commit_tx().await;
@@ -1320,10 +1369,8 @@ where
     }
 
     fn to_bytes(&self) -> Vec<u8> {
-        let mut bytes = Vec::new();
-        self.consensus_encode(&mut bytes)
-            .expect("writing to vec can't fail");
-        bytes
+        // Values like PSBTs or epoch history can be several megabytes
+        self.consensus_encode_to_vec_exact()
     }
 }
 
@@ -1424,7 +1471,9 @@ macro_rules! impl_db_lookup{
 #[derive(Debug, Encodable, Decodable, Serialize)]
 pub struct DatabaseVersionKey;
 
-#[derive(Debug, Encodable, Decodable, Serialize, Clone, PartialOrd, Ord, PartialEq, Eq)]
+#[derive(
+    Debug, Encodable, Decodable, Serialize, Deserialize, Clone, PartialOrd, Ord, PartialEq, Eq,
+)]
 pub struct DatabaseVersion(pub u64);
 
 impl_db_record!(
@@ -1445,6 +1494,33 @@ impl DatabaseVersion {
     }
 }
 
+/// Records a migration step that was applied to the database, keyed by the
+/// version the database was migrated from
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct MigrationJournalKey(pub DatabaseVersion);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MigrationJournalKeyPrefix;
+
+#[derive(Debug, Clone, Encodable, Decodable, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MigrationJournalEntry {
+    /// Kind of the module the migration belongs to (or "Global")
+    pub kind: String,
+    pub from: DatabaseVersion,
+    pub to: DatabaseVersion,
+    pub applied_at: SystemTime,
+}
+
+impl_db_record!(
+    key = MigrationJournalKey,
+    value = MigrationJournalEntry,
+    db_prefix = DbKeyPrefix::MigrationJournal
+);
+impl_db_lookup!(
+    key = MigrationJournalKey,
+    query_prefix = MigrationJournalKeyPrefix
+);
+
 impl std::fmt::Display for DbKeyPrefix {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{self:?}")
@@ -1456,6 +1532,7 @@ impl std::fmt::Display for DbKeyPrefix {
 pub enum DbKeyPrefix {
     DatabaseVersion = 0x50,
     ClientBackup = 0x51,
+    MigrationJournal = 0x52,
 }
 
 #[derive(Debug, Error)]
@@ -1533,6 +1610,26 @@ pub type MigrationMap<'a> = BTreeMap<
     ) -> Pin<Box<dyn futures::Future<Output = anyhow::Result<()>> + Send + 'b>>,
 >;
 
+/// Returns `true` if `apply_migrations` would need to run any migrations to
+/// bring the database to `target_db_version`.
+///
+/// Fails if the on disk database version is newer than `target_db_version`,
+/// since the code can't safely read such a database.
+pub async fn needs_migration(
+    db: &Database,
+    kind: &str,
+    target_db_version: &DatabaseVersion,
+) -> Result<bool, anyhow::Error> {
+    let mut dbtx = db.begin_transaction().await;
+    match dbtx.get_value(&DatabaseVersionKey).await {
+        Some(disk_version) if disk_version > *target_db_version => Err(anyhow::anyhow!(
+            "On disk database version {disk_version} for module {kind} was higher than the code database version {target_db_version}."
+        )),
+        Some(disk_version) => Ok(disk_version < *target_db_version),
+        None => Ok(false),
+    }
+}
+
 /// `apply_migrations` iterates from the on disk database version for the module
 /// up to `target_db_version` and executes all of the migrations that exist in
 /// the `MigrationMap`. Each migration in `MigrationMap` updates the database to
@@ -1541,6 +1638,9 @@ pub type MigrationMap<'a> = BTreeMap<
 /// atomically). This function is called before the module is initialized and as
 /// long as the correct migrations are supplied in `MigrationMap`, the module
 /// will be able to read and write from the database successfully.
+///
+/// Every applied migration step is recorded in the migration journal of the
+/// database (see [`MigrationJournalKey`]) as part of the same transaction.
 pub async fn apply_migrations<'a>(
     db: &'a Database,
     kind: String,
@@ -1559,13 +1659,31 @@ pub async fn apply_migrations<'a>(
         }
 
         while current_db_version < target_db_version {
-            if let Some(migration) = migrations.get(&current_db_version) {
-                migration(&mut dbtx).await?;
-            } else {
-                panic!("Missing migration for version {current_db_version}");
-            }
+            let Some(migration) = migrations.get(&current_db_version) else {
+                return Err(anyhow::anyhow!(
+                    "Missing migration for module {kind} from version {current_db_version}"
+                ));
+            };
+            migration(&mut dbtx).await.with_context(|| {
+                format!("Migration of module {kind} from version {current_db_version} failed")
+            })?;
 
+            let from = current_db_version.clone();
             current_db_version.increment();
+            info!(
+                target: LOG_DB,
+                "Migrated {} module db from version {} to {}", kind, from, current_db_version
+            );
+            dbtx.insert_entry(
+                &MigrationJournalKey(from.clone()),
+                &MigrationJournalEntry {
+                    kind: kind.clone(),
+                    from,
+                    to: current_db_version.clone(),
+                    applied_at: crate::time::now(),
+                },
+            )
+            .await;
             dbtx.insert_entry(&DatabaseVersionKey, &current_db_version)
                 .await;
         }
@@ -1582,6 +1700,19 @@ pub async fn apply_migrations<'a>(
     Ok(())
 }
 
+/// Returns all migration steps recorded in the journal of `db`, oldest first
+pub async fn get_migration_journal(db: &Database) -> Vec<MigrationJournalEntry> {
+    let mut dbtx = db.begin_transaction().await;
+    let journal = dbtx
+        .find_by_prefix(&MigrationJournalKeyPrefix)
+        .await
+        .map(|(_, entry)| entry)
+        .collect::<Vec<_>>()
+        .await;
+    dbtx.commit_tx().await;
+    journal
+}
+
 #[allow(unused_imports)]
 mod test_utils {
     use std::time::Duration;
@@ -1589,8 +1720,8 @@ mod test_utils {
     use futures::{Future, FutureExt, StreamExt};
 
     use super::{
-        apply_migrations, Database, DatabaseTransaction, DatabaseVersion, DatabaseVersionKey,
-        MigrationMap,
+        apply_migrations, get_migration_journal, needs_migration, Database, DatabaseTransaction,
+        DatabaseVersion, DatabaseVersionKey, MigrationMap,
     };
     use crate::core::ModuleKind;
     use crate::db::mem_impl::MemDatabase;
@@ -2210,6 +2341,18 @@ mod test_utils {
         for (key, val) in test_keys {
             assert_eq!(key.0, val.0 + 1);
         }
+        dbtx.commit_tx().await;
+
+        let journal = get_migration_journal(&db).await;
+        assert_eq!(journal.len(), 1);
+        assert_eq!(journal[0].from, DatabaseVersion(0));
+        assert_eq!(journal[0].to, DatabaseVersion(1));
+        assert!(!needs_migration(&db, "TestModule", &DatabaseVersion(1))
+            .await
+            .unwrap());
+        assert!(needs_migration(&db, "TestModule", &DatabaseVersion(0))
+            .await
+            .is_err());
     }
 
     #[allow(dead_code)]
@@ -0,0 +1,204 @@
+//! Logical, consistent exports of a [`Database`] that can be taken while the
+//! database is in use.
+//!
+//! A full snapshot contains every key-value pair visible in a single read
+//! transaction. An incremental snapshot only contains the entries that changed
+//! relative to a previous snapshot, described by its [`SnapshotManifest`].
+//! Restoring means importing the full snapshot followed by all incremental
+//! snapshots built on top of it, in order.
+
+use std::collections::BTreeMap;
+
+use anyhow::ensure;
+use bitcoin_hashes::{sha256, Hash};
+use futures::StreamExt;
+
+use super::Database;
+use crate::encoding::{Decodable, Encodable};
+
+/// Consistent logical export of all key-value pairs of a database
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub struct DatabaseSnapshot {
+    /// Id of the snapshot this one is relative to, `None` for full snapshots
+    pub base: Option<sha256::Hash>,
+    /// Changed entries, a `None` value means the key was removed since `base`
+    pub entries: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+}
+
+/// Compact description of the database state captured by a snapshot, used as
+/// the base when creating the next incremental snapshot
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub struct SnapshotManifest {
+    /// Id of the snapshot the manifest was created with
+    pub id: sha256::Hash,
+    /// Hashes of all values present in the database at the time
+    pub entries: BTreeMap<Vec<u8>, sha256::Hash>,
+}
+
+impl DatabaseSnapshot {
+    /// Unique id of the snapshot, incremental snapshots reference their base
+    /// snapshot by it
+    pub fn id(&self) -> sha256::Hash {
+        self.consensus_hash()
+    }
+
+    pub fn is_incremental(&self) -> bool {
+        self.base.is_some()
+    }
+}
+
+impl Database {
+    /// Exports all entries of the database visible in a single transaction.
+    ///
+    /// If a `base` manifest is given, only entries that changed since the
+    /// corresponding snapshot are exported. Returns the snapshot and the
+    /// manifest to be used as base for the next one.
+    pub async fn export_snapshot(
+        &self,
+        base: Option<&SnapshotManifest>,
+    ) -> anyhow::Result<(DatabaseSnapshot, SnapshotManifest)> {
+        ensure!(
+            self.module_instance_id.is_none(),
+            "Snapshots can only be taken of the whole database"
+        );
+
+        let mut dbtx = self.inner_db.db.begin_transaction().await;
+        let current = dbtx
+            .raw_find_by_prefix(&[])
+            .await?
+            .collect::<BTreeMap<Vec<u8>, Vec<u8>>>()
+            .await;
+
+        let hashes = current
+            .iter()
+            .map(|(key, value)| (key.clone(), sha256::Hash::hash(value)))
+            .collect::<BTreeMap<_, _>>();
+
+        let entries = match base {
+            Some(base) => {
+                let mut changed = current
+                    .into_iter()
+                    .filter(|(key, _)| base.entries.get(key) != hashes.get(key))
+                    .map(|(key, value)| (key, Some(value)))
+                    .collect::<BTreeMap<_, _>>();
+                changed.extend(
+                    base.entries
+                        .keys()
+                        .filter(|key| !hashes.contains_key(*key))
+                        .map(|key| (key.clone(), None)),
+                );
+                changed
+            }
+            None => current
+                .into_iter()
+                .map(|(key, value)| (key, Some(value)))
+                .collect(),
+        };
+
+        let snapshot = DatabaseSnapshot {
+            base: base.map(|base| base.id),
+            entries,
+        };
+        let manifest = SnapshotManifest {
+            id: snapshot.id(),
+            entries: hashes,
+        };
+
+        Ok((snapshot, manifest))
+    }
+
+    /// Applies a snapshot to the database atomically. Full snapshots should be
+    /// imported into an empty database, incremental ones on top of their base.
+    ///
+    /// Tasks waiting for keys of the snapshot are notified, so a database
+    /// kept in sync by importing snapshots can be served like the original.
+    pub async fn import_snapshot(&self, snapshot: &DatabaseSnapshot) -> anyhow::Result<()> {
+        ensure!(
+            self.module_instance_id.is_none(),
+            "Snapshots can only be imported into the whole database"
+        );
+
+        let mut dbtx = self.begin_transaction().await;
+        for (key, value) in &snapshot.entries {
+            match value {
+                Some(value) => {
+                    dbtx.raw_insert_bytes(key, value).await?;
+                }
+                None => {
+                    dbtx.raw_remove_entry(key).await?;
+                }
+            }
+        }
+        dbtx.commit_tx_result().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::mem_impl::MemDatabase;
+    use crate::db::{future_returns_shortly, TestKey, TestVal};
+    use crate::module::registry::ModuleDecoderRegistry;
+
+    #[tokio::test]
+    async fn test_incremental_snapshot_restore() {
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(&TestKey(1), &TestVal(1)).await;
+        dbtx.insert_entry(&TestKey(2), &TestVal(2)).await;
+        dbtx.commit_tx().await;
+
+        let (full, manifest) = db.export_snapshot(None).await.unwrap();
+        assert!(!full.is_incremental());
+        assert_eq!(full.entries.len(), 2);
+
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.remove_entry(&TestKey(1)).await;
+        dbtx.insert_entry(&TestKey(2), &TestVal(20)).await;
+        dbtx.insert_entry(&TestKey(3), &TestVal(3)).await;
+        dbtx.commit_tx().await;
+
+        let (incremental, _) = db.export_snapshot(Some(&manifest)).await.unwrap();
+        assert_eq!(incremental.base, Some(full.id()));
+        assert_eq!(incremental.entries.len(), 3);
+
+        let restored = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        restored.import_snapshot(&full).await.unwrap();
+        restored.import_snapshot(&incremental).await.unwrap();
+
+        let mut dbtx = restored.begin_transaction().await;
+        assert_eq!(dbtx.get_value(&TestKey(1)).await, None);
+        assert_eq!(dbtx.get_value(&TestKey(2)).await, Some(TestVal(20)));
+        assert_eq!(dbtx.get_value(&TestKey(3)).await, Some(TestVal(3)));
+        dbtx.commit_tx().await;
+    }
+
+    #[tokio::test]
+    async fn test_import_notifies_waiters() {
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(&TestKey(1), &TestVal(1)).await;
+        dbtx.commit_tx().await;
+        let (snapshot, _) = db.export_snapshot(None).await.unwrap();
+
+        let replica = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        let waiter = tokio::spawn({
+            let replica = replica.clone();
+            async move {
+                let sub = replica.wait_key_exists(&TestKey(1));
+                tx.send(()).unwrap();
+                sub.await
+            }
+        });
+        rx.await.unwrap();
+
+        replica.import_snapshot(&snapshot).await.unwrap();
+        assert_eq!(
+            future_returns_shortly(async { waiter.await.unwrap() }).await,
+            Some(TestVal(1)),
+            "should notify"
+        );
+    }
+}
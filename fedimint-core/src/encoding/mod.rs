@@ -23,6 +23,10 @@ use url::Url;
 
 use crate::module::registry::ModuleDecoderRegistry;
 
+/// Maximum number of bytes preallocated for a collection based on its encoded
+/// length, bigger collections grow while being decoded
+const MAX_DECODE_PREALLOC: usize = 1024 * 1024;
+
 /// Object-safe trait for things that can encode themselves
 ///
 /// Like `rust-bitcoin`'s `consensus_encode`, but without generics,
@@ -73,6 +77,24 @@ pub trait Encodable {
         Ok(bytes)
     }
 
+    /// Length of the encoding in bytes, computed without buffering it
+    fn consensus_encoded_len(&self) -> usize {
+        self.consensus_encode(&mut std::io::sink())
+            .expect("writing to sink cannot fail")
+    }
+
+    /// [`Self::consensus_encode`] to a `Vec<u8>` allocated with the exact size
+    /// of the encoding
+    ///
+    /// Encodes twice, but for multi-megabyte values (e.g. PSBTs or epoch
+    /// history) avoids reallocating and copying the buffer as it grows.
+    fn consensus_encode_to_vec_exact(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.consensus_encoded_len());
+        self.consensus_encode(&mut bytes)
+            .expect("writing to vec cannot fail");
+        bytes
+    }
+
     fn consensus_encode_to_hex(&self) -> Result<String, std::io::Error> {
         let mut bytes = vec![];
         self.consensus_encode(&mut bytes)?;
@@ -170,6 +192,7 @@ impl_encode_decode_num!(u64);
 impl_encode_decode_num!(u32);
 impl_encode_decode_num!(u16);
 impl_encode_decode_num!(u8);
+impl_encode_decode_num!(i64);
 
 macro_rules! impl_encode_decode_tuple {
     ($($x:ident),*) => (
@@ -228,7 +251,13 @@ where
         modules: &ModuleDecoderRegistry,
     ) -> Result<Self, DecodeError> {
         let len = u64::consensus_decode(d, modules)?;
-        (0..len).map(|_| T::consensus_decode(d, modules)).collect()
+        // The length comes from untrusted data, so we only trust it up to a limit
+        let capacity = (len as usize).min(MAX_DECODE_PREALLOC / std::mem::size_of::<T>().max(1));
+        let mut items = Vec::with_capacity(capacity);
+        for _ in 0..len {
+            items.push(T::consensus_decode(d, modules)?);
+        }
+        Ok(items)
     }
 }
 
@@ -823,4 +852,21 @@ mod tests {
             ],
         );
     }
+
+    #[test_log::test]
+    fn test_large_values() {
+        let value = vec![vec![42u8; 1024]; 4 * 1024];
+        let bytes = value.consensus_encode_to_vec_exact();
+        assert_eq!(bytes.len(), value.consensus_encoded_len());
+        assert_eq!(bytes.capacity(), bytes.len());
+        assert_eq!(bytes, value.consensus_encode_to_vec().unwrap());
+        test_roundtrip(value);
+
+        // A length prefix claiming more items than there are doesn't allocate them
+        let bytes = u64::MAX.consensus_encode_to_vec().unwrap();
+        assert!(
+            Vec::<u64>::consensus_decode(&mut &bytes[..], &ModuleDecoderRegistry::default())
+                .is_err()
+        );
+    }
 }
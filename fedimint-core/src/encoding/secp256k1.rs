@@ -80,6 +80,38 @@ impl Decodable for secp256k1_zkp::schnorr::Signature {
     }
 }
 
+impl Encodable for secp256k1_zkp::MusigPubNonce {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+        self.serialize().consensus_encode(writer)
+    }
+}
+
+impl Decodable for secp256k1_zkp::MusigPubNonce {
+    fn consensus_decode<D: Read>(
+        d: &mut D,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        secp256k1_zkp::MusigPubNonce::from_slice(&<[u8; 66]>::consensus_decode(d, modules)?)
+            .map_err(DecodeError::from_err)
+    }
+}
+
+impl Encodable for secp256k1_zkp::MusigPartialSignature {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+        self.serialize().consensus_encode(writer)
+    }
+}
+
+impl Decodable for secp256k1_zkp::MusigPartialSignature {
+    fn consensus_decode<D: Read>(
+        d: &mut D,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        secp256k1_zkp::MusigPartialSignature::from_slice(&<[u8; 32]>::consensus_decode(d, modules)?)
+            .map_err(DecodeError::from_err)
+    }
+}
+
 impl Encodable for bitcoin::KeyPair {
     fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
         self.secret_bytes().consensus_encode(writer)
@@ -1,15 +1,21 @@
 use std::collections::{BTreeMap, BTreeSet};
 
 use bitcoin_hashes::sha256::Hash as Sha256;
-use fedimint_core::core::DynModuleConsensusItem as ModuleConsensusItem;
+use fedimint_core::core::{
+    DynModuleConsensusItem as ModuleConsensusItem, ModuleInstanceId, ModuleKind,
+};
 use fedimint_core::encoding::{Decodable, DecodeError, Encodable, UnzipConsensus};
 use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::module::SerdeModuleEncoding;
 use fedimint_core::{PeerId, TransactionId};
 use itertools::Itertools;
 use serde::{Deserialize, Serialize};
-use threshold_crypto::{PublicKey, PublicKeySet, Signature, SignatureShare};
+use thiserror::Error;
+use threshold_crypto::{
+    Ciphertext, DecryptionShare, PublicKey, PublicKeySet, SecretKeyShare, Signature, SignatureShare,
+};
 
+use crate::merkle::{leaf_hash, MerkleProof, MerkleTree};
 use crate::timing;
 use crate::transaction::Transaction;
 
@@ -27,12 +33,162 @@ pub enum ConsensusItem {
     Transaction(Transaction),
     /// Any data that modules require consensus on
     Module(ModuleConsensusItem),
+    /// Vote to replace the federation meta sent to clients
+    MetaUpdate(MetaUpdate),
+    /// Vote to add a new module instance to the running federation
+    ModuleAddition(ModuleAddition),
+    /// Module consensus item that is only revealed after it has been ordered
+    EncryptedModule(EncryptedConsensusItem),
+    /// Decryption share for an ordered `EncryptedConsensusItem`
+    DecryptionShare(DecryptionShareItem),
+    /// Cancels a module addition whose DKG can't complete
+    ModuleAdditionCancel(ModuleAdditionCancel),
 }
 
 /// May eventually contains consensus info about the upgrade
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
 pub struct ConsensusUpgrade;
 
+/// A guardian's vote for the new `meta` of the client config, it replaces the
+/// current meta once a threshold of guardians voted for the same one
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
+pub struct MetaUpdate(pub BTreeMap<String, String>);
+
+/// A guardian's vote to add a module instance, it gets scheduled once all
+/// guardians voted for the same one since every guardian takes part in its DKG
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct ModuleAddition {
+    pub module_instance_id: ModuleInstanceId,
+    pub kind: ModuleKind,
+    /// Consensus config gen params of the module as JSON
+    pub consensus_params: String,
+    /// The DKG of the module runs after this epoch has been processed
+    pub activation_epoch: u64,
+}
+
+/// Cancels the pending or scheduled addition of a module instance. Its DKG
+/// needs every guardian, so any of them can cancel it, e.g. once the DKG
+/// failed because a guardian is offline.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
+pub struct ModuleAdditionCancel {
+    pub module_instance_id: ModuleInstanceId,
+}
+
+/// A module consensus item encrypted to the federation's threshold public key.
+///
+/// Guardians can't see its content before it has been ordered, a threshold of
+/// decryption shares contributed in later epochs reveals it. This prevents a
+/// malicious guardian from censoring or reordering items based on their
+/// content.
+///
+/// The ciphertext binds the item to the peer that encrypted it, so a peer
+/// that replays someone else's ciphertext can't get the item attributed to
+/// itself.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
+pub struct EncryptedConsensusItem(pub SerdeCiphertext);
+
+/// A guardian's decryption share for the `EncryptedConsensusItem` with the
+/// given id
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
+pub struct DecryptionShareItem {
+    pub item_id: Sha256,
+    pub share: SerdeDecryptionShare,
+}
+
+#[derive(Debug, Error)]
+pub enum DecryptItemError {
+    #[error("Not enough valid decryption shares, valid peers: {0:?}")]
+    NotEnoughValidShares(BTreeSet<PeerId>),
+    #[error("Decrypted item is for unknown module instance {0}")]
+    UnknownModule(ModuleInstanceId),
+    #[error("Decrypted item could not be decoded: {0}")]
+    Decode(DecodeError),
+}
+
+impl EncryptedConsensusItem {
+    /// Encrypts `item` proposed by `peer` to the federation's threshold public
+    /// key `pk`
+    pub fn encrypt(pk: &PublicKey, peer: PeerId, item: &ModuleConsensusItem) -> Self {
+        let mut bytes = vec![];
+        peer.consensus_encode(&mut bytes)
+            .and_then(|_| item.consensus_encode(&mut bytes))
+            .expect("Encoding to vec can't fail");
+        EncryptedConsensusItem(SerdeCiphertext(pk.encrypt(bytes)))
+    }
+
+    /// The id under which decryption shares reference this item
+    pub fn id(&self) -> Sha256 {
+        self.consensus_hash()
+    }
+
+    /// Checks that the ciphertext is well-formed, otherwise guardians can't
+    /// produce decryption shares for it
+    pub fn verify(&self) -> bool {
+        self.0 .0.verify()
+    }
+
+    /// Creates our decryption share, returns `None` if the ciphertext is
+    /// invalid
+    pub fn decryption_share(&self, sks: &SecretKeyShare) -> Option<DecryptionShareItem> {
+        sks.decrypt_share(&self.0 .0)
+            .map(|share| DecryptionShareItem {
+                item_id: self.id(),
+                share: SerdeDecryptionShare(share),
+            })
+    }
+
+    /// Checks the decryption share of `peer` for this item
+    pub fn verify_share(
+        &self,
+        pks: &PublicKeySet,
+        peer: PeerId,
+        share: &SerdeDecryptionShare,
+    ) -> bool {
+        pks.public_key_share(peer.to_usize())
+            .verify_decryption_share(&share.0, &self.0 .0)
+    }
+
+    /// Combines the decryption shares from peers, ignoring bad shares, and
+    /// decodes the revealed module consensus item together with the peer that
+    /// encrypted it
+    pub fn decrypt(
+        &self,
+        pks: &PublicKeySet,
+        shares: &BTreeMap<PeerId, SerdeDecryptionShare>,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<(PeerId, ModuleConsensusItem), DecryptItemError> {
+        let valid_shares: BTreeMap<_, _> = shares
+            .iter()
+            .filter(|(peer, share)| self.verify_share(pks, **peer, share))
+            .map(|(peer, share)| (peer.to_usize(), &share.0))
+            .collect();
+
+        let bytes = pks.decrypt(valid_shares.clone(), &self.0 .0).map_err(|_| {
+            DecryptItemError::NotEnoughValidShares(
+                valid_shares
+                    .keys()
+                    .map(|peer| PeerId::from(*peer as u16))
+                    .collect(),
+            )
+        })?;
+
+        let mut bytes = bytes.as_slice();
+        let peer =
+            PeerId::consensus_decode(&mut bytes, modules).map_err(DecryptItemError::Decode)?;
+
+        // Check the module instance before decoding since decoding an unknown one panics
+        let module_instance_id = ModuleInstanceId::consensus_decode(&mut &bytes[..], modules)
+            .map_err(DecryptItemError::Decode)?;
+        if modules.get(module_instance_id).is_none() {
+            return Err(DecryptItemError::UnknownModule(module_instance_id));
+        }
+
+        let item = ModuleConsensusItem::consensus_decode(&mut bytes, modules)
+            .map_err(DecryptItemError::Decode)?;
+        Ok((peer, item))
+    }
+}
+
 pub type SerdeConsensusItem = SerdeModuleEncoding<ConsensusItem>;
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
@@ -41,6 +197,12 @@ pub struct SerdeSignatureShare(pub SignatureShare);
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct SerdeSignature(pub Signature);
 
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SerdeCiphertext(pub Ciphertext);
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct SerdeDecryptionShare(pub DecryptionShare);
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
 pub struct SignedEpochOutcome {
     pub outcome: EpochOutcome,
@@ -110,7 +272,7 @@ impl SignedEpochOutcome {
         };
 
         SignedEpochOutcome {
-            hash: outcome.consensus_hash(),
+            hash: outcome.hash(),
             outcome,
             signature: None,
         }
@@ -159,14 +321,14 @@ impl SignedEpochOutcome {
             match prev_epoch {
                 None => return Err(EpochVerifyError::MissingPreviousEpoch),
                 Some(prev_epoch) => {
-                    if Some(prev_epoch.outcome.consensus_hash()) != self.outcome.last_hash {
+                    if Some(prev_epoch.outcome.hash()) != self.outcome.last_hash {
                         return Err(EpochVerifyError::InvalidPreviousEpochHash);
                     }
                 }
             }
         }
 
-        if self.hash == self.outcome.consensus_hash() {
+        if self.hash == self.outcome.hash() {
             Ok(())
         } else {
             Err(EpochVerifyError::InvalidEpochHash)
@@ -182,6 +344,8 @@ pub enum EpochVerifyError {
     InvalidEpochHash,
     InvalidPreviousEpochHash,
     NotEnoughValidSigShares(BTreeSet<PeerId>),
+    InvalidInclusionProof,
+    RejectedTransaction,
 }
 
 impl From<BTreeSet<PeerId>> for EpochVerifyError {
@@ -190,6 +354,218 @@ impl From<BTreeSet<PeerId>> for EpochVerifyError {
     }
 }
 
+/// Selects the accepted items of an epoch a client is interested in, so light
+/// clients don't have to download whole epochs to build their history
+///
+/// Only transactions and module consensus items are ever selected.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EpochItemFilter {
+    /// Only select items of these module instances, a transaction is selected
+    /// if any of its inputs or outputs belongs to one of them. All modules
+    /// if empty.
+    pub modules: BTreeSet<ModuleInstanceId>,
+    /// Only select the transactions with these ids, e.g. the ones of our own
+    /// outpoints. If not empty no module consensus items are selected.
+    pub txids: BTreeSet<TransactionId>,
+}
+
+impl EpochItemFilter {
+    pub fn matches(&self, item: &ConsensusItem) -> bool {
+        match item {
+            ConsensusItem::Transaction(tx) => {
+                (self.txids.is_empty() || self.txids.contains(&tx.tx_hash()))
+                    && (self.modules.is_empty()
+                        || tx
+                            .inputs
+                            .iter()
+                            .map(|input| input.module_instance_id())
+                            .chain(tx.outputs.iter().map(|output| output.module_instance_id()))
+                            .any(|module| self.modules.contains(&module)))
+            }
+            ConsensusItem::Module(item) => {
+                self.txids.is_empty()
+                    && (self.modules.is_empty()
+                        || self.modules.contains(&item.module_instance_id()))
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Request for the items of an epoch matching a filter, waits for the epoch if
+/// it hasn't happened yet
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EpochItemsRequest {
+    pub epoch: u64,
+    pub filter: EpochItemFilter,
+}
+
+/// How long a guardian's recent epochs took, for clients to estimate when
+/// their transactions will be included
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EpochTimingEstimate {
+    /// Time between the completions of the recent epochs, oldest first
+    pub recent_intervals_ms: Vec<u64>,
+    /// Median time from proposing to completing an epoch, `None` if no epoch
+    /// was completed since the guardian started
+    pub median_epoch_duration_ms: Option<u64>,
+    /// How long the epoch in progress has been running, `None` if idle
+    pub current_epoch_elapsed_ms: Option<u64>,
+    /// Expected time until an item submitted now is part of a completed
+    /// epoch, it has to wait for the epoch in progress to complete first
+    pub expected_inclusion_ms: Option<u64>,
+}
+
+/// The items of an epoch selected by an [`EpochItemFilter`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
+pub struct FilteredEpochItems {
+    pub epoch: u64,
+    /// Selected items in the order they were accepted, an item contributed by
+    /// several peers only appears once
+    pub items: Vec<ConsensusItem>,
+    /// Selected transactions that turned out to be invalid
+    pub rejected_txs: BTreeSet<TransactionId>,
+}
+
+pub type SerdeFilteredEpochItems = SerdeModuleEncoding<FilteredEpochItems>;
+
+/// What the threshold signature of an epoch is over, it commits to the
+/// accepted items with a merkle root so single items can be proven to light
+/// clients
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct EpochHeader {
+    pub epoch: u64,
+    pub last_hash: Option<Sha256>,
+    /// Root of the [`MerkleTree`] of the `(PeerId, ConsensusItem)` pairs of
+    /// the epoch in the order they were accepted
+    pub items_root: Sha256,
+    pub rejected_txs: BTreeSet<TransactionId>,
+}
+
+impl EpochHeader {
+    pub fn hash(&self) -> Sha256 {
+        self.consensus_hash()
+    }
+}
+
+/// Proves that a transaction was accepted in a signed epoch without the rest
+/// of the epoch
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable)]
+pub struct TransactionInclusionProof {
+    pub header: EpochHeader,
+    pub signature: SerdeSignature,
+    /// Peer whose contribution the proven leaf is
+    pub peer: PeerId,
+    pub transaction: Transaction,
+    pub proof: MerkleProof,
+}
+
+pub type SerdeTransactionInclusionProof = SerdeModuleEncoding<TransactionInclusionProof>;
+
+impl TransactionInclusionProof {
+    /// Checks that `txid` was accepted in an epoch signed by `pk`
+    pub fn verify(&self, pk: &PublicKey, txid: TransactionId) -> Result<(), EpochVerifyError> {
+        if self.transaction.tx_hash() != txid {
+            return Err(EpochVerifyError::InvalidInclusionProof);
+        }
+        if self.header.rejected_txs.contains(&txid) {
+            return Err(EpochVerifyError::RejectedTransaction);
+        }
+
+        let leaf = leaf_hash(&(
+            self.peer,
+            ConsensusItem::Transaction(self.transaction.clone()),
+        ));
+        if !self.proof.verify(leaf, self.header.items_root) {
+            return Err(EpochVerifyError::InvalidInclusionProof);
+        }
+
+        if !pk.verify(&self.signature.0, self.header.hash()) {
+            return Err(EpochVerifyError::InvalidSignature);
+        }
+        Ok(())
+    }
+}
+
+impl SignedEpochOutcome {
+    /// Proof that the transaction `txid` was accepted in this epoch, `None`
+    /// if it wasn't or the epoch isn't signed yet
+    pub fn inclusion_proof(&self, txid: TransactionId) -> Option<TransactionInclusionProof> {
+        let signature = self.signature.clone()?;
+        let leaves = self.outcome.leaves().collect::<Vec<_>>();
+        let (index, (peer, transaction)) =
+            leaves
+                .iter()
+                .enumerate()
+                .find_map(|(idx, leaf)| match leaf {
+                    (peer, ConsensusItem::Transaction(tx)) if tx.tx_hash() == txid => {
+                        Some((idx, (*peer, tx.clone())))
+                    }
+                    _ => None,
+                })?;
+        let proof = MerkleTree::new(leaves.iter().map(leaf_hash).collect()).proof(index)?;
+
+        Some(TransactionInclusionProof {
+            header: self.outcome.header(),
+            signature,
+            peer,
+            transaction,
+            proof,
+        })
+    }
+}
+
+impl EpochOutcome {
+    /// The `(PeerId, ConsensusItem)` pairs committed to by the header
+    fn leaves(&self) -> impl Iterator<Item = (PeerId, ConsensusItem)> + '_ {
+        self.items
+            .iter()
+            .flat_map(|(peer, items)| items.iter().map(|item| (*peer, item.clone())))
+    }
+
+    pub fn header(&self) -> EpochHeader {
+        EpochHeader {
+            epoch: self.epoch,
+            last_hash: self.last_hash,
+            items_root: MerkleTree::new(self.leaves().map(|leaf| leaf_hash(&leaf)).collect())
+                .root(),
+            rejected_txs: self.rejected_txs.clone(),
+        }
+    }
+
+    /// Hash of the [`EpochHeader`], which the federation signs
+    pub fn hash(&self) -> Sha256 {
+        self.header().hash()
+    }
+
+    /// Selects the items matching `filter`
+    pub fn filter_items(&self, filter: &EpochItemFilter) -> FilteredEpochItems {
+        let items: Vec<ConsensusItem> = self
+            .items
+            .iter()
+            .flat_map(|(_, items)| items)
+            .filter(|item| filter.matches(item))
+            .unique()
+            .cloned()
+            .collect();
+
+        let rejected_txs = items
+            .iter()
+            .filter_map(|item| match item {
+                ConsensusItem::Transaction(tx) => Some(tx.tx_hash()),
+                _ => None,
+            })
+            .filter(|txid| self.rejected_txs.contains(txid))
+            .collect();
+
+        FilteredEpochItems {
+            epoch: self.epoch,
+            items,
+            rejected_txs,
+        }
+    }
+}
+
 impl Encodable for SerdeSignature {
     fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
         self.0.to_bytes().consensus_encode(writer)
@@ -226,22 +602,61 @@ impl Decodable for SerdeSignatureShare {
     }
 }
 
+impl Encodable for SerdeCiphertext {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        bincode::serialize(&self.0)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
+            .consensus_encode(writer)
+    }
+}
+
+impl Decodable for SerdeCiphertext {
+    fn consensus_decode<D: std::io::Read>(
+        d: &mut D,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let bytes = Vec::<u8>::consensus_decode(d, modules)?;
+        Ok(SerdeCiphertext(
+            bincode::deserialize(&bytes).map_err(DecodeError::from_err)?,
+        ))
+    }
+}
+
+impl Encodable for SerdeDecryptionShare {
+    fn consensus_encode<W: std::io::Write>(&self, writer: &mut W) -> Result<usize, std::io::Error> {
+        bincode::serialize(&self.0)
+            .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e))?
+            .consensus_encode(writer)
+    }
+}
+
+impl Decodable for SerdeDecryptionShare {
+    fn consensus_decode<D: std::io::Read>(
+        d: &mut D,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let bytes = Vec::<u8>::consensus_decode(d, modules)?;
+        Ok(SerdeDecryptionShare(
+            bincode::deserialize(&bytes).map_err(DecodeError::from_err)?,
+        ))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::{BTreeMap, BTreeSet};
 
     use bitcoin::hashes::Hash;
-    use bitcoin_hashes::sha256;
-    use fedimint_core::encoding::Encodable;
     use fedimint_core::epoch::combine_sigs;
-    use fedimint_core::PeerId;
+    use fedimint_core::{PeerId, TransactionId};
     use rand::rngs::OsRng;
     use threshold_crypto::{SecretKey, SecretKeySet};
 
     use crate::epoch::{
-        ConsensusItem, EpochOutcome, EpochVerifyError, SerdeSignature, SerdeSignatureShare, Sha256,
-        SignedEpochOutcome,
+        ConsensusItem, EpochItemFilter, EpochOutcome, EpochVerifyError, MetaUpdate, SerdeSignature,
+        SerdeSignatureShare, Sha256, SignedEpochOutcome,
     };
+    use crate::transaction::Transaction;
 
     fn signed_history(
         epoch: u16,
@@ -249,7 +664,7 @@ mod tests {
         sk: &SecretKey,
     ) -> SignedEpochOutcome {
         let missing_sig = history(epoch, prev_epoch, None);
-        let signature = sk.sign(missing_sig.outcome.consensus_hash::<sha256::Hash>());
+        let signature = sk.sign(missing_sig.outcome.hash());
         history(epoch, prev_epoch, Some(SerdeSignature(signature)))
     }
 
@@ -268,7 +683,7 @@ mod tests {
         };
 
         SignedEpochOutcome {
-            hash: outcome.consensus_hash(),
+            hash: outcome.hash(),
             outcome,
             signature,
         }
@@ -428,4 +843,108 @@ mod tests {
             Err(EpochVerifyError::InvalidSignature)
         );
     }
+
+    #[test]
+    fn filters_items() {
+        let tx = Transaction {
+            inputs: vec![],
+            outputs: vec![],
+            signature: None,
+        };
+        let meta = ConsensusItem::MetaUpdate(MetaUpdate(BTreeMap::new()));
+        let outcome = EpochOutcome {
+            epoch: 3,
+            last_hash: None,
+            items: vec![
+                (
+                    PeerId::from(0),
+                    vec![ConsensusItem::Transaction(tx.clone()), meta.clone()],
+                ),
+                (
+                    PeerId::from(1),
+                    vec![ConsensusItem::Transaction(tx.clone())],
+                ),
+            ],
+            rejected_txs: BTreeSet::from([tx.tx_hash()]),
+        };
+
+        let filter = EpochItemFilter {
+            modules: BTreeSet::new(),
+            txids: BTreeSet::from([tx.tx_hash()]),
+        };
+        let filtered = outcome.filter_items(&filter);
+        assert_eq!(filtered.epoch, 3);
+        assert_eq!(filtered.items, vec![ConsensusItem::Transaction(tx.clone())]);
+        assert_eq!(filtered.rejected_txs, BTreeSet::from([tx.tx_hash()]));
+        assert!(!filter.matches(&meta));
+
+        let filter = EpochItemFilter {
+            modules: BTreeSet::new(),
+            txids: BTreeSet::from([TransactionId::all_zeros()]),
+        };
+        let filtered = outcome.filter_items(&filter);
+        assert!(filtered.items.is_empty());
+        assert!(filtered.rejected_txs.is_empty());
+    }
+
+    #[test]
+    fn proves_transaction_inclusion() {
+        let sk: SecretKey = SecretKey::random();
+        let pk = sk.public_key();
+        let tx = Transaction {
+            inputs: vec![],
+            outputs: vec![],
+            signature: None,
+        };
+        let outcome = EpochOutcome {
+            epoch: 0,
+            last_hash: None,
+            items: vec![
+                (
+                    PeerId::from(0),
+                    vec![ConsensusItem::MetaUpdate(MetaUpdate(BTreeMap::new()))],
+                ),
+                (
+                    PeerId::from(1),
+                    vec![ConsensusItem::Transaction(tx.clone())],
+                ),
+            ],
+            rejected_txs: BTreeSet::new(),
+        };
+        let mut epoch = SignedEpochOutcome {
+            hash: outcome.hash(),
+            signature: Some(SerdeSignature(sk.sign(outcome.hash()))),
+            outcome,
+        };
+
+        let proof = epoch
+            .inclusion_proof(tx.tx_hash())
+            .expect("tx was accepted");
+        assert_eq!(proof.verify(&pk, tx.tx_hash()), Ok(()));
+        assert_eq!(
+            proof.verify(&SecretKey::random().public_key(), tx.tx_hash()),
+            Err(EpochVerifyError::InvalidSignature)
+        );
+        assert_eq!(
+            proof.verify(&pk, TransactionId::all_zeros()),
+            Err(EpochVerifyError::InvalidInclusionProof)
+        );
+
+        let mut wrong_peer = proof.clone();
+        wrong_peer.peer = PeerId::from(0);
+        assert_eq!(
+            wrong_peer.verify(&pk, tx.tx_hash()),
+            Err(EpochVerifyError::InvalidInclusionProof)
+        );
+
+        let mut rejected = proof;
+        rejected.header.rejected_txs.insert(tx.tx_hash());
+        assert_eq!(
+            rejected.verify(&pk, tx.tx_hash()),
+            Err(EpochVerifyError::RejectedTransaction)
+        );
+
+        epoch.signature = None;
+        assert_eq!(epoch.inclusion_proof(tx.tx_hash()), None);
+    }
 }
@@ -0,0 +1,106 @@
+//! Snapshots of the federation state a new client needs to become operational
+//!
+//! Instead of downloading the config and then querying every module for its
+//! state (gateways, block height, fee rates...) a joining client requests a
+//! [`FederationSnapshot`] from all guardians at once. Each guardian signs its
+//! snapshot with its share of the auth key, so a threshold of guardians
+//! returning the same snapshot yields a [`SignedFederationSnapshot`] that
+//! verifies against the [`FederationId`].
+//!
+//! Download tokens are per guardian, so only the guardian that issued the
+//! connect info returns the config itself, the others just sign its hash.
+
+use std::collections::BTreeMap;
+
+use bitcoin_hashes::sha256::{Hash as Sha256, HashEngine};
+use bitcoin_hashes::{Hash, HashEngine as BitcoinHashEngine};
+use serde::{Deserialize, Serialize};
+use threshold_crypto::PublicKeySet;
+
+use crate::config::{ClientConfig, FederationId};
+use crate::core::ModuleInstanceId;
+use crate::encoding::{Decodable, DecodeError, Encodable};
+use crate::epoch::{SerdeSignature, SerdeSignatureShare};
+use crate::module::registry::ModuleDecoderRegistry;
+use crate::PeerId;
+
+/// Separates snapshot signatures from the other messages signed with the auth
+/// key, like the client config hash
+const SNAPSHOT_SIGNING_TAG: &[u8] = b"fedimint-federation-snapshot";
+
+/// Client relevant state of the federation at the time of the request
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct FederationSnapshot {
+    /// Hash of the client config including the current meta
+    pub config_hash: Sha256,
+    /// Consensus encoded snapshots of the modules that provide one
+    pub modules: BTreeMap<ModuleInstanceId, Vec<u8>>,
+}
+
+impl FederationSnapshot {
+    /// Hash the guardians sign
+    pub fn signing_hash(&self) -> Sha256 {
+        let mut engine = HashEngine::default();
+        engine.input(SNAPSHOT_SIGNING_TAG);
+        self.consensus_encode(&mut engine)
+            .expect("hashing is infallible");
+        Sha256::from_engine(engine)
+    }
+
+    /// Decodes the snapshot of module `module_instance_id`, `None` if it
+    /// didn't provide one
+    pub fn module<T: Decodable>(
+        &self,
+        module_instance_id: ModuleInstanceId,
+    ) -> Option<Result<T, DecodeError>> {
+        self.modules.get(&module_instance_id).map(|bytes| {
+            T::consensus_decode(&mut bytes.as_slice(), &ModuleDecoderRegistry::default())
+        })
+    }
+}
+
+/// A guardian's snapshot along with its signature share
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FederationSnapshotShare {
+    pub snapshot: FederationSnapshot,
+    /// Only returned if the download token of the request is ours
+    pub config: Option<ClientConfig>,
+    /// Public keys of the auth key shares, needed to combine the signature
+    pub auth_pk_set: PublicKeySet,
+    pub share: SerdeSignatureShare,
+}
+
+impl FederationSnapshotShare {
+    /// Checks that the share was created by `peer` for a federation with
+    /// `federation_id`
+    pub fn verify(&self, peer: PeerId, federation_id: &FederationId) -> bool {
+        let config_matches = self.config.as_ref().map_or(true, |config| {
+            config.consensus_hash() == self.snapshot.config_hash
+        });
+        config_matches
+            && self.auth_pk_set.public_key() == federation_id.0
+            && self
+                .auth_pk_set
+                .public_key_share(peer.to_usize())
+                .verify(&self.share.0, self.snapshot.signing_hash())
+    }
+}
+
+/// A snapshot signed by a threshold of guardians
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SignedFederationSnapshot {
+    pub config: ClientConfig,
+    pub snapshot: FederationSnapshot,
+    pub signature: SerdeSignature,
+}
+
+impl SignedFederationSnapshot {
+    /// Checks the snapshot was signed by the federation with `federation_id`
+    pub fn verify(&self, federation_id: &FederationId) -> bool {
+        self.config.federation_id == *federation_id
+            && self.config.consensus_hash() == self.snapshot.config_hash
+            && federation_id
+                .0
+                .verify(&self.signature.0, self.snapshot.signing_hash())
+    }
+}
@@ -35,11 +35,15 @@ pub mod encoding;
 pub mod epoch;
 pub mod fmt_utils;
 pub mod hex;
+pub mod join;
 pub mod macros;
+pub mod merkle;
 pub mod module;
 pub mod net;
 pub mod outcome;
 pub mod query;
+pub mod receipt;
+pub mod stats;
 pub mod task;
 pub mod tiered;
 pub mod tiered_multi;
@@ -0,0 +1,191 @@
+//! Merkle tree over the items of an epoch, lets a light client check that a
+//! single item is part of a signed epoch without downloading the others.
+//!
+//! Leaves and inner nodes are hashed with different prefixes so an inner node
+//! can't be passed off as a leaf. A node without a sibling is promoted to the
+//! next level as is instead of being paired with itself, which would let two
+//! different lists of leaves share a root.
+
+use bitcoin_hashes::sha256::{Hash as Sha256, HashEngine};
+use bitcoin_hashes::{Hash, HashEngine as BitcoinHashEngine};
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::{Decodable, Encodable};
+
+const LEAF_PREFIX: u8 = 0x00;
+const NODE_PREFIX: u8 = 0x01;
+
+/// Hash of a leaf containing `data`
+pub fn leaf_hash<T: Encodable>(data: &T) -> Sha256 {
+    let mut engine = HashEngine::default();
+    engine.input(&[LEAF_PREFIX]);
+    data.consensus_encode(&mut engine)
+        .expect("hashing is infallible");
+    Sha256::from_engine(engine)
+}
+
+fn node_hash(left: &Sha256, right: &Sha256) -> Sha256 {
+    let mut engine = HashEngine::default();
+    engine.input(&[NODE_PREFIX]);
+    engine.input(&left[..]);
+    engine.input(&right[..]);
+    Sha256::from_engine(engine)
+}
+
+#[derive(Debug, Clone)]
+pub struct MerkleTree {
+    /// Every level of the tree starting with the leaves, the last one only
+    /// contains the root
+    levels: Vec<Vec<Sha256>>,
+}
+
+impl MerkleTree {
+    pub fn new(leaves: Vec<Sha256>) -> Self {
+        let mut levels = vec![leaves];
+        while levels.last().expect("has leaves").len() > 1 {
+            let level = levels
+                .last()
+                .expect("has leaves")
+                .chunks(2)
+                .map(|pair| match pair {
+                    [left, right] => node_hash(left, right),
+                    [single] => *single,
+                    _ => unreachable!("chunks of two"),
+                })
+                .collect();
+            levels.push(level);
+        }
+        MerkleTree { levels }
+    }
+
+    /// The root, all zeros if there are no leaves
+    pub fn root(&self) -> Sha256 {
+        self.levels
+            .last()
+            .and_then(|level| level.first())
+            .copied()
+            .unwrap_or_else(Sha256::all_zeros)
+    }
+
+    /// Proof that the leaf at `leaf_index` is part of the tree
+    pub fn proof(&self, leaf_index: usize) -> Option<MerkleProof> {
+        let leaf_count = self.levels[0].len();
+        if leaf_index >= leaf_count {
+            return None;
+        }
+
+        let mut index = leaf_index;
+        let mut siblings = vec![];
+        for level in &self.levels[..self.levels.len() - 1] {
+            if let Some(sibling) = level.get(index ^ 1) {
+                siblings.push(*sibling);
+            }
+            index /= 2;
+        }
+
+        Some(MerkleProof {
+            leaf_index: leaf_index as u64,
+            leaf_count: leaf_count as u64,
+            siblings,
+        })
+    }
+}
+
+/// Path from a leaf to the root of a [`MerkleTree`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct MerkleProof {
+    pub leaf_index: u64,
+    pub leaf_count: u64,
+    /// Siblings of the nodes on the path from the leaf to the root, nodes
+    /// that were promoted don't have one
+    pub siblings: Vec<Sha256>,
+}
+
+impl MerkleProof {
+    /// Checks that `leaf` is part of the tree with `root`
+    pub fn verify(&self, leaf: Sha256, root: Sha256) -> bool {
+        if self.leaf_index >= self.leaf_count {
+            return false;
+        }
+
+        let mut hash = leaf;
+        let mut index = self.leaf_index;
+        let mut len = self.leaf_count;
+        let mut siblings = self.siblings.iter();
+        while len > 1 {
+            if index % 2 == 1 {
+                match siblings.next() {
+                    Some(sibling) => hash = node_hash(sibling, &hash),
+                    None => return false,
+                }
+            } else if index + 1 < len {
+                match siblings.next() {
+                    Some(sibling) => hash = node_hash(&hash, sibling),
+                    None => return false,
+                }
+            }
+            index /= 2;
+            len = (len + 1) / 2;
+        }
+
+        siblings.next().is_none() && hash == root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn leaves(count: u64) -> Vec<Sha256> {
+        (0..count).map(|i| leaf_hash(&i)).collect()
+    }
+
+    #[test]
+    fn proves_every_leaf() {
+        for count in 1..=9 {
+            let leaves = leaves(count);
+            let tree = MerkleTree::new(leaves.clone());
+
+            for (index, leaf) in leaves.iter().enumerate() {
+                let proof = tree.proof(index).expect("leaf exists");
+                assert!(proof.verify(*leaf, tree.root()));
+                assert!(!proof.verify(leaf_hash(&100u64), tree.root()));
+            }
+            assert_eq!(tree.proof(count as usize), None);
+        }
+    }
+
+    #[test]
+    fn rejects_tampered_proofs() {
+        let leaves = leaves(5);
+        let tree = MerkleTree::new(leaves.clone());
+        let proof = tree.proof(2).expect("leaf exists");
+
+        let mut wrong_index = proof.clone();
+        wrong_index.leaf_index = 3;
+        assert!(!wrong_index.verify(leaves[2], tree.root()));
+
+        let mut extra_sibling = proof.clone();
+        extra_sibling.siblings.push(leaves[0]);
+        assert!(!extra_sibling.verify(leaves[2], tree.root()));
+
+        let mut wrong_count = proof;
+        wrong_count.leaf_count = 4;
+        assert!(!wrong_count.verify(leaves[2], tree.root()));
+    }
+
+    #[test]
+    fn duplicated_leaves_change_root() {
+        let mut leaves = leaves(3);
+        let root = MerkleTree::new(leaves.clone()).root();
+        leaves.push(leaves[2]);
+        assert_ne!(root, MerkleTree::new(leaves).root());
+    }
+
+    #[test]
+    fn empty_tree() {
+        let tree = MerkleTree::new(vec![]);
+        assert_eq!(tree.root(), Sha256::all_zeros());
+        assert_eq!(tree.proof(0), None);
+    }
+}
@@ -1,6 +1,7 @@
 use std::fmt::{Display, Formatter};
 
 use futures::StreamExt;
+use serde::{Deserialize, Serialize};
 
 use crate::db::{DatabaseKey, DatabaseLookup, DatabaseRecord, ModuleDatabaseTransaction};
 
@@ -10,6 +11,10 @@ pub struct Audit {
 }
 
 impl Audit {
+    pub fn items(&self) -> &[AuditItem] {
+        &self.items
+    }
+
     pub fn sum(&self) -> AuditItem {
         let mut sum = 0;
 
@@ -56,6 +61,7 @@ impl Display for Audit {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub struct AuditItem {
     pub name: String,
     pub milli_sat: i64,
@@ -0,0 +1,121 @@
+//! A clock the guardians agree on without trusting any single guardian
+//!
+//! Every guardian periodically votes for the unix time of its local clock and
+//! the module stores the latest vote of each peer under its own database key.
+//! The consensus time is the latest time a threshold of guardians voted for, so
+//! at least one honest guardian's clock reached it. It never decreases since
+//! votes that would move a peer's clock backwards are ignored.
+
+use std::time::SystemTime;
+
+use futures::StreamExt;
+
+use crate::db::{DatabaseKey, DatabaseLookup, DatabaseRecord, ModuleDatabaseTransaction};
+use crate::PeerId;
+
+/// The unix time in seconds by our local clock
+pub fn unix_time() -> u64 {
+    crate::time::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Time is after the unix epoch")
+        .as_secs()
+}
+
+/// Time votes of all peers stored under the module's database key `K`, which
+/// can be queried with the prefix `KP`
+#[derive(Debug)]
+pub struct ThresholdClock<K, KP> {
+    vote_key: fn(PeerId) -> K,
+    vote_prefix: KP,
+    our_id: PeerId,
+    threshold: usize,
+}
+
+impl<K, KP> ThresholdClock<K, KP>
+where
+    K: DatabaseKey + DatabaseRecord<Value = u64>,
+    KP: DatabaseLookup<Record = K>,
+{
+    pub fn new(
+        vote_key: fn(PeerId) -> K,
+        vote_prefix: KP,
+        our_id: PeerId,
+        threshold: usize,
+    ) -> Self {
+        Self {
+            vote_key,
+            vote_prefix,
+            our_id,
+            threshold,
+        }
+    }
+
+    /// Our latest time vote, zero if we never voted
+    pub async fn our_vote(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) -> u64 {
+        dbtx.get_value(&(self.vote_key)(self.our_id))
+            .await
+            .unwrap_or(0)
+    }
+
+    /// The time to vote for if our latest vote is at least `interval` seconds
+    /// old
+    pub async fn proposal(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        interval: u64,
+    ) -> Option<u64> {
+        let now = unix_time();
+        (self.our_vote(dbtx).await + interval <= now).then_some(now)
+    }
+
+    /// Stores the peers' votes unless they would move their clock backwards
+    pub async fn process_votes(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        votes: impl IntoIterator<Item = (PeerId, u64)>,
+    ) {
+        for (peer_id, time) in votes {
+            let key = (self.vote_key)(peer_id);
+            if time > dbtx.get_value(&key).await.unwrap_or(0) {
+                dbtx.insert_entry(&key, &time).await;
+            }
+        }
+    }
+
+    /// The latest unix time in seconds a threshold of guardians voted for
+    pub async fn consensus_time(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) -> u64 {
+        let votes = dbtx
+            .find_by_prefix(&self.vote_prefix)
+            .await
+            .map(|(_, time)| time)
+            .collect::<Vec<_>>()
+            .await;
+
+        threshold_time(votes, self.threshold)
+    }
+}
+
+/// The `threshold`-highest of the `votes`, zero if there are fewer votes or the
+/// threshold is zero
+fn threshold_time(mut votes: Vec<u64>, threshold: usize) -> u64 {
+    votes.sort_unstable_by(|a, b| b.cmp(a));
+    threshold
+        .checked_sub(1)
+        .and_then(|idx| votes.get(idx))
+        .copied()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::threshold_time;
+
+    #[test]
+    fn threshold_time_needs_threshold_votes() {
+        assert_eq!(threshold_time(vec![], 3), 0);
+        assert_eq!(threshold_time(vec![10, 30], 3), 0);
+        assert_eq!(threshold_time(vec![10, 40, 30, 20], 3), 20);
+        assert_eq!(threshold_time(vec![10, 40, 30, 20], 1), 40);
+        assert_eq!(threshold_time(vec![10, 40, 30, 20], 0), 0);
+    }
+}
@@ -0,0 +1,123 @@
+//! Error codes shared by the core and all modules
+//!
+//! Clients need to tell errors apart without parsing messages, e.g. to retry a
+//! request later instead of giving up on it. Every [`ApiError`] and
+//! [`ModuleError`] therefore carries one of the [`code`]s, a flag whether the
+//! same request might succeed later and, if it originates from a module, the
+//! module's kind.
+//!
+//! [`ApiError`]: crate::module::ApiError
+//! [`ModuleError`]: crate::module::ModuleError
+
+use serde::{Deserialize, Serialize};
+
+use crate::core::ModuleKind;
+use crate::module::CorrelationId;
+
+/// Error codes, loosely following the HTTP status codes
+pub mod code {
+    /// The request is malformed or invalid and will never succeed as is
+    pub const BAD_REQUEST: i32 = 400;
+    /// The request requires guardian authentication
+    pub const UNAUTHORIZED: i32 = 401;
+    /// The requested item doesn't exist, at least not yet
+    pub const NOT_FOUND: i32 = 404;
+    /// The request conflicts with the state of the federation, e.g. it spends
+    /// an e-cash note that was already spent
+    pub const CONFLICT: i32 = 409;
+    /// A limit set by the federation was exceeded
+    pub const LIMIT_EXCEEDED: i32 = 429;
+    /// Something went wrong on the guardian's side
+    pub const SERVER_ERROR: i32 = 500;
+    /// The federation can't process the request at the moment
+    pub const UNAVAILABLE: i32 = 503;
+
+    /// Whether errors with `code` are usually resolved by retrying later
+    pub fn retriable_by_default(code: i32) -> bool {
+        matches!(code, NOT_FOUND | UNAVAILABLE)
+    }
+}
+
+/// Attached as the data of JSON-RPC errors returned by the API
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiErrorData {
+    /// Kind of the module the error originates from, `None` for core errors
+    pub module: Option<ModuleKind>,
+    /// Whether the same request might succeed later
+    pub retriable: bool,
+    /// Correlation id of the request that failed, to look it up in the
+    /// guardian's logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<CorrelationId>,
+}
+
+/// Implemented by the error types modules return from input and output
+/// validation, see [`IntoCodedModuleError`]
+///
+/// [`IntoCodedModuleError`]: crate::module::IntoCodedModuleError
+pub trait ModuleErrorCode: std::error::Error + Send + Sync + 'static {
+    /// One of the [`code`]s
+    fn code(&self) -> i32;
+
+    /// Whether the same input or output might be accepted later
+    fn retriable(&self) -> bool {
+        code::retriable_by_default(self.code())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::module::{ApiError, IntoCodedModuleError, ModuleError};
+
+    #[derive(Debug, thiserror::Error)]
+    enum TestError {
+        #[error("Already spent")]
+        Spent,
+        #[error("Not synced yet")]
+        NotSynced,
+    }
+
+    impl ModuleErrorCode for TestError {
+        fn code(&self) -> i32 {
+            match self {
+                TestError::Spent => code::CONFLICT,
+                TestError::NotSynced => code::UNAVAILABLE,
+            }
+        }
+    }
+
+    #[test]
+    fn module_errors_keep_their_code() {
+        let spent = Err::<(), _>(TestError::Spent)
+            .into_module_error()
+            .unwrap_err();
+        let error = ApiError::from_module_error("mint".into(), &spent);
+        assert_eq!(error.code, code::CONFLICT);
+        assert_eq!(error.message, "Already spent");
+        assert!(!error.retriable);
+
+        let not_synced = ModuleError::coded(&TestError::NotSynced);
+        let error =
+            ApiError::from_module_error("wallet".into(), &not_synced).in_module("ln".into());
+        assert_eq!(
+            error.data(),
+            ApiErrorData {
+                module: Some("wallet".into()),
+                retriable: true,
+                correlation_id: None,
+            }
+        );
+    }
+
+    #[test]
+    fn uncoded_errors_are_bad_requests() {
+        let error = ModuleError::Other(anyhow::anyhow!("Invalid"));
+        assert_eq!(error.code(), code::BAD_REQUEST);
+        assert!(!error.retriable());
+        assert_eq!(
+            ApiError::not_found("Unknown".to_string()).data().module,
+            None
+        );
+    }
+}
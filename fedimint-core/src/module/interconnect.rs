@@ -0,0 +1,205 @@
+//! Typed calls between the server modules of a federation
+//!
+//! A module that wants to be called by other modules declares an
+//! [`InterconnectMethod`] for every call in its common crate, together with an
+//! extension trait on [`DynModuleInterconnect`] that callers use like a stub.
+//! The module serves the calls by returning handlers built with
+//! [`interconnect_handler!`](crate::module::interconnect::interconnect_handler)
+//! from `ServerModule::interconnect_handlers`.
+//!
+//! Requests and responses are passed between modules as they are, so the
+//! compiler checks both sides against the same types and nothing is encoded.
+use std::any::Any;
+use std::fmt::Debug;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use crate::core::ModuleKind;
+use crate::db::{DatabaseTransaction, ModuleDatabaseTransaction};
+use crate::module::ApiError;
+use crate::task::MaybeSend;
+use crate::{
+    apply, async_trait_maybe_send, dyn_newtype_define, maybe_add_send, maybe_add_send_sync,
+};
+
+/// A request or response passed between modules, only the caller and the
+/// handler know its type
+pub type ErasedInterconnectValue = Box<maybe_add_send!(dyn Any)>;
+
+/// A call a module of kind `KIND` serves to the other modules of its
+/// federation
+///
+/// A module can serve several versions of a call at once, so it can change the
+/// types of a call without breaking modules compiled against an older version.
+pub trait InterconnectMethod: 'static {
+    /// Kind of the module serving the call
+    const KIND: ModuleKind;
+    /// Name of the call, unique within the module
+    const NAME: &'static str;
+    /// Callers only reach handlers of exactly this version
+    const VERSION: u32;
+
+    type Request: MaybeSend + 'static;
+    type Response: MaybeSend + 'static;
+}
+
+/// Allows a server module to call the other modules of its federation, e.g.
+/// to consume data attested by an oracle module
+///
+/// Responses reflect the database as of the last committed consensus epoch,
+/// so modules should only use them in consensus if the data doesn't change
+/// anymore once it exists, like a finished oracle round.
+#[apply(async_trait_maybe_send!)]
+pub trait IModuleInterconnect: Debug {
+    /// Calls version `version` of the call `name` served by the first module
+    /// of `kind`
+    async fn call_erased(
+        &self,
+        kind: &ModuleKind,
+        name: &'static str,
+        version: u32,
+        request: ErasedInterconnectValue,
+    ) -> Result<ErasedInterconnectValue, ApiError>;
+}
+
+dyn_newtype_define! {
+    #[derive(Clone)]
+    pub DynModuleInterconnect(Arc<IModuleInterconnect>)
+}
+
+impl DynModuleInterconnect {
+    /// Calls the first module serving `M`
+    pub async fn call<M: InterconnectMethod>(
+        &self,
+        request: M::Request,
+    ) -> Result<M::Response, ApiError> {
+        let response = self
+            .call_erased(&M::KIND, M::NAME, M::VERSION, Box::new(request))
+            .await?;
+        response
+            .downcast::<M::Response>()
+            .map(|response| *response)
+            .map_err(|_| {
+                ApiError::server_error(format!(
+                    "Invalid response type from {} call {}",
+                    M::KIND,
+                    M::NAME
+                ))
+            })
+    }
+}
+
+#[apply(async_trait_maybe_send!)]
+pub trait TypedInterconnectHandler {
+    type State: Sync;
+    type Method: InterconnectMethod;
+
+    async fn handle<'a, 'b>(
+        state: &'a Self::State,
+        dbtx: &'a mut ModuleDatabaseTransaction<'b>,
+        request: <Self::Method as InterconnectMethod>::Request,
+    ) -> Result<<Self::Method as InterconnectMethod>::Response, ApiError>;
+}
+
+/// # Example
+///
+/// ```rust
+/// # use fedimint_core::core::ModuleKind;
+/// # use fedimint_core::module::interconnect::{
+/// #     interconnect_handler, InterconnectHandler, InterconnectMethod,
+/// # };
+/// struct State;
+///
+/// struct Double;
+///
+/// impl InterconnectMethod for Double {
+///     const KIND: ModuleKind = ModuleKind::from_static_str("dummy");
+///     const NAME: &'static str = "double";
+///     const VERSION: u32 = 0;
+///     type Request = u64;
+///     type Response = u64;
+/// }
+///
+/// let _: InterconnectHandler<State> = interconnect_handler! {
+///     Double,
+///     async |_state: &State, _dbtx, request| {
+///         Ok(request * 2)
+///     }
+/// };
+/// ```
+#[macro_export]
+macro_rules! __interconnect_handler {
+    (
+        $method:ty,
+        async |$state:ident: &$state_ty:ty, $dbtx:ident, $request:ident| $body:block
+    ) => {{
+        struct Handler;
+
+        #[$crate::apply($crate::async_trait_maybe_send!)]
+        impl $crate::module::interconnect::TypedInterconnectHandler for Handler {
+            type State = $state_ty;
+            type Method = $method;
+
+            async fn handle<'a, 'b>(
+                $state: &'a Self::State,
+                $dbtx: &'a mut $crate::db::ModuleDatabaseTransaction<'b>,
+                $request: <$method as $crate::module::interconnect::InterconnectMethod>::Request,
+            ) -> ::std::result::Result<
+                <$method as $crate::module::interconnect::InterconnectMethod>::Response,
+                $crate::module::ApiError,
+            > {
+                $body
+            }
+        }
+
+        $crate::module::interconnect::InterconnectHandler::from_typed::<Handler>()
+    }};
+}
+
+pub use __interconnect_handler as interconnect_handler;
+
+type InterconnectHandlerFnReturn<'a> =
+    Pin<Box<maybe_add_send!(dyn Future<Output = Result<ErasedInterconnectValue, ApiError>> + 'a)>>;
+type InterconnectHandlerFn<M> = Box<
+    maybe_add_send_sync!(
+        dyn for<'a> Fn(
+            &'a M,
+            DatabaseTransaction<'a>,
+            ErasedInterconnectValue,
+        ) -> InterconnectHandlerFnReturn<'a>
+    ),
+>;
+
+/// A call served by a module `M` to the other modules of its federation
+pub struct InterconnectHandler<M> {
+    pub name: &'static str,
+    pub version: u32,
+    /// Handles a request with an isolated database transaction of the module
+    /// that is never committed
+    pub handler: InterconnectHandlerFn<M>,
+}
+
+// <()> is used to avoid specify state.
+impl InterconnectHandler<()> {
+    pub fn from_typed<H: TypedInterconnectHandler>() -> InterconnectHandler<H::State> {
+        InterconnectHandler {
+            name: <H::Method as InterconnectMethod>::NAME,
+            version: <H::Method as InterconnectMethod>::VERSION,
+            handler: Box::new(|state, mut dbtx, request| {
+                Box::pin(async move {
+                    let request = request
+                        .downcast::<<H::Method as InterconnectMethod>::Request>()
+                        .map_err(|_| {
+                            ApiError::bad_request(format!(
+                                "Invalid request type for call {}",
+                                <H::Method as InterconnectMethod>::NAME
+                            ))
+                        })?;
+                    let response = H::handle(state, &mut dbtx.get_isolated(), *request).await?;
+                    Ok(Box::new(response) as ErasedInterconnectValue)
+                })
+            }),
+        }
+    }
+}
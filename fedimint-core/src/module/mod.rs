@@ -1,4 +1,7 @@
 pub mod audit;
+pub mod clock;
+pub mod error;
+pub mod interconnect;
 pub mod registry;
 use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
@@ -28,8 +31,11 @@ use crate::db::{
 };
 use crate::encoding::{Decodable, DecodeError, Encodable};
 use crate::module::audit::Audit;
+use crate::module::error::ModuleErrorCode;
+use crate::module::interconnect::{DynModuleInterconnect, InterconnectHandler};
 use crate::net::peers::MuxPeerConnections;
 use crate::server::{DynServerModule, VerificationCache};
+use crate::stats::ItemActivity;
 use crate::task::{MaybeSend, TaskGroup};
 use crate::{
     apply, async_trait_maybe_send, dyn_newtype_define, maybe_add_send, maybe_add_send_sync, Amount,
@@ -66,6 +72,9 @@ pub struct ApiRequest<T> {
     pub auth: Option<ApiAuth>,
     /// Parameters required by the API
     pub params: T,
+    /// Identifies the operation the request belongs to in the guardian's logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<CorrelationId>,
 }
 
 pub type ApiRequestErased = ApiRequest<JsonValue>;
@@ -75,6 +84,7 @@ impl Default for ApiRequestErased {
         Self {
             auth: None,
             params: JsonValue::Null,
+            correlation_id: None,
         }
     }
 }
@@ -85,6 +95,7 @@ impl ApiRequestErased {
             auth: None,
             params: serde_json::to_value(params)
                 .expect("parameter serialization error - this should not happen"),
+            correlation_id: None,
         }
     }
 
@@ -95,7 +106,14 @@ impl ApiRequestErased {
     pub fn with_auth(self, auth: &ApiAuth) -> Self {
         Self {
             auth: Some(auth.clone()),
-            params: self.params,
+            ..self
+        }
+    }
+
+    pub fn with_correlation_id(self, correlation_id: CorrelationId) -> Self {
+        Self {
+            correlation_id: Some(correlation_id),
+            ..self
         }
     }
 
@@ -105,6 +123,7 @@ impl ApiRequestErased {
         Ok(ApiRequest {
             auth: self.auth,
             params: serde_json::from_value::<T>(self.params)?,
+            correlation_id: self.correlation_id,
         })
     }
 }
@@ -113,31 +132,105 @@ impl ApiRequestErased {
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ApiAuth(pub String);
 
-#[derive(Debug, Clone)]
+/// Sent along with API requests so operators can find all requests belonging
+/// to one user operation in the logs of each guardian
+///
+/// Clients derive it from the operation, it carries no meaning otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct CorrelationId(#[serde(with = "::hex::serde")] pub [u8; 8]);
+
+impl std::fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&::hex::encode(self.0))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ApiError {
+    /// One of the [`error::code`]s
     pub code: i32,
     pub message: String,
+    /// Kind of the module the error originates from, `None` for core errors
+    pub module: Option<ModuleKind>,
+    /// Whether the same request might succeed later
+    pub retriable: bool,
+    /// Correlation id of the request that failed
+    pub correlation_id: Option<CorrelationId>,
 }
 
 impl ApiError {
     pub fn new(code: i32, message: String) -> Self {
-        Self { code, message }
+        Self {
+            code,
+            message,
+            module: None,
+            retriable: error::code::retriable_by_default(code),
+            correlation_id: None,
+        }
     }
 
     pub fn not_found(message: String) -> Self {
-        Self::new(404, message)
+        Self::new(error::code::NOT_FOUND, message)
     }
 
     pub fn bad_request(message: String) -> Self {
-        Self::new(400, message)
+        Self::new(error::code::BAD_REQUEST, message)
     }
 
     pub fn unauthorized() -> Self {
-        Self::new(401, "Request missing required authorization".to_string())
+        Self::new(
+            error::code::UNAUTHORIZED,
+            "Request missing required authorization".to_string(),
+        )
+    }
+
+    pub fn conflict(message: String) -> Self {
+        Self::new(error::code::CONFLICT, message)
+    }
+
+    pub fn unavailable(message: String) -> Self {
+        Self::new(error::code::UNAVAILABLE, message)
     }
 
     pub fn server_error(message: String) -> Self {
-        Self::new(500, message)
+        Self::new(error::code::SERVER_ERROR, message)
+    }
+
+    /// Error of an input or output of module `kind` that was rejected with
+    /// `error`
+    pub fn from_module_error(kind: ModuleKind, error: &ModuleError) -> Self {
+        Self {
+            code: error.code(),
+            message: error.to_string(),
+            module: Some(kind),
+            retriable: error.retriable(),
+            correlation_id: None,
+        }
+    }
+
+    /// Attributes the error to module `kind` unless it already is
+    pub fn in_module(mut self, kind: ModuleKind) -> Self {
+        self.module.get_or_insert(kind);
+        self
+    }
+
+    pub fn with_retriable(mut self, retriable: bool) -> Self {
+        self.retriable = retriable;
+        self
+    }
+
+    pub fn with_correlation_id(mut self, correlation_id: Option<CorrelationId>) -> Self {
+        self.correlation_id = correlation_id;
+        self
+    }
+
+    /// Data sent along with the code and message to the client
+    pub fn data(&self) -> error::ApiErrorData {
+        error::ApiErrorData {
+            module: self.module.clone(),
+            retriable: self.retriable,
+            correlation_id: self.correlation_id,
+        }
     }
 }
 
@@ -147,6 +240,7 @@ pub struct ApiEndpointContext<'a> {
     dbtx: DatabaseTransaction<'a>,
     has_auth: bool,
     request_auth: Option<ApiAuth>,
+    correlation_id: Option<CorrelationId>,
 }
 
 impl<'a> ApiEndpointContext<'a> {
@@ -156,12 +250,14 @@ impl<'a> ApiEndpointContext<'a> {
         dbtx: DatabaseTransaction<'a>,
         has_auth: bool,
         request_auth: Option<ApiAuth>,
+        correlation_id: Option<CorrelationId>,
     ) -> Self {
         Self {
             db,
             dbtx,
             has_auth,
             request_auth,
+            correlation_id,
         }
     }
 
@@ -183,6 +279,12 @@ impl<'a> ApiEndpointContext<'a> {
         self.has_auth
     }
 
+    /// Correlation id the client attached to the request, see
+    /// [`CorrelationId`]
+    pub fn correlation_id(&self) -> Option<CorrelationId> {
+        self.correlation_id
+    }
+
     /// Waits for key to be present in database.
     pub fn wait_key_exists<K>(&self, key: K) -> impl Future<Output = K::Value>
     where
@@ -215,10 +317,7 @@ impl<'a> ApiEndpointContext<'a> {
                 "API server error when writing to database: {:?}",
                 _err
             );
-            ApiError {
-                code: 500,
-                message: "API server error when writing to database".to_string(),
-            }
+            ApiError::server_error("API server error when writing to database".to_string())
         })
     }
 }
@@ -366,8 +465,42 @@ impl ApiEndpoint<()> {
 
 #[derive(Error, Debug)]
 pub enum ModuleError {
+    /// An error without a code, treated as a [`error::code::BAD_REQUEST`]
     #[error(transparent)]
     Other(#[from] anyhow::Error),
+    /// An error with one of the [`error::code`]s, see [`IntoCodedModuleError`]
+    #[error("{message}")]
+    Coded {
+        code: i32,
+        retriable: bool,
+        message: String,
+    },
+}
+
+impl ModuleError {
+    pub fn code(&self) -> i32 {
+        match self {
+            ModuleError::Other(_) => error::code::BAD_REQUEST,
+            ModuleError::Coded { code, .. } => *code,
+        }
+    }
+
+    /// Whether the same input or output might be accepted later
+    pub fn retriable(&self) -> bool {
+        match self {
+            ModuleError::Other(_) => false,
+            ModuleError::Coded { retriable, .. } => *retriable,
+        }
+    }
+
+    /// Keeps the code of `error`
+    pub fn coded(error: &impl ModuleErrorCode) -> Self {
+        ModuleError::Coded {
+            code: error.code(),
+            retriable: error.retriable(),
+            message: error.to_string(),
+        }
+    }
 }
 
 /// Extension trait with a function to map `Result`s used by modules to
@@ -395,6 +528,24 @@ where
     }
 }
 
+/// Like [`IntoModuleError`], but keeps the [`error::code`] of module errors
+/// implementing [`ModuleErrorCode`] so clients can act on it
+pub trait IntoCodedModuleError {
+    type Target;
+    fn into_module_error(self) -> Self::Target;
+}
+
+impl<O, E> IntoCodedModuleError for Result<O, E>
+where
+    E: ModuleErrorCode,
+{
+    type Target = Result<O, ModuleError>;
+
+    fn into_module_error(self) -> Self::Target {
+        self.map_err(|e| ModuleError::coded(&e))
+    }
+}
+
 /// Operations common to Server and Client side module gen dyn newtypes
 ///
 /// Due to conflict of `impl Trait for T` for both `ServerModuleGen` and
@@ -447,12 +598,26 @@ pub trait IServerModuleGen: IDynCommonModuleGen {
 
     fn database_version(&self) -> DatabaseVersion;
 
+    /// See [`ServerModuleGen::versions`]
+    fn versions(&self, core: CoreConsensusVersion) -> &[ModuleConsensusVersion];
+
     /// Initialize the [`DynServerModule`] instance from its config
+    ///
+    /// `module_instance_id` and `our_id` identify the instance and our peer,
+    /// e.g. to look up our public keys without the secret ones.
+    /// `interconnect` can be used to call other modules of the federation
+    /// once all of them are initialized. `task_group` belongs to the module
+    /// alone, long-running tasks should be spawned with
+    /// [`TaskGroup::spawn_supervised`] so a panic doesn't stop them for good.
+    #[allow(clippy::too_many_arguments)]
     async fn init(
         &self,
         cfg: ServerModuleConfig,
+        module_instance_id: ModuleInstanceId,
+        our_id: PeerId,
         db: Database,
         task_group: &mut TaskGroup,
+        interconnect: DynModuleInterconnect,
     ) -> anyhow::Result<DynServerModule>;
 
     /// Retrieves the `MigrationMap` from the module to be applied to the
@@ -869,11 +1034,22 @@ pub trait ServerModuleGen: ExtendsCommonModuleGen + Sized {
     }
 
     /// Initialize the [`DynServerModule`] instance from its config
+    ///
+    /// `module_instance_id` and `our_id` identify the instance and our peer,
+    /// e.g. to look up our public keys without the secret ones.
+    /// `interconnect` can be used to call other modules of the federation
+    /// once all of them are initialized. `task_group` belongs to the module
+    /// alone, long-running tasks should be spawned with
+    /// [`TaskGroup::spawn_supervised`] so a panic doesn't stop them for good.
+    #[allow(clippy::too_many_arguments)]
     async fn init(
         &self,
         cfg: ServerModuleConfig,
+        module_instance_id: ModuleInstanceId,
+        our_id: PeerId,
         db: Database,
         task_group: &mut TaskGroup,
+        interconnect: DynModuleInterconnect,
     ) -> anyhow::Result<DynServerModule>;
 
     /// Retrieves the `MigrationMap` from the module to be applied to the
@@ -931,13 +1107,29 @@ where
         <Self as ServerModuleGen>::DATABASE_VERSION
     }
 
+    fn versions(&self, core: CoreConsensusVersion) -> &[ModuleConsensusVersion] {
+        <Self as ServerModuleGen>::versions(self, core)
+    }
+
     async fn init(
         &self,
         cfg: ServerModuleConfig,
+        module_instance_id: ModuleInstanceId,
+        our_id: PeerId,
         db: Database,
         task_group: &mut TaskGroup,
+        interconnect: DynModuleInterconnect,
     ) -> anyhow::Result<DynServerModule> {
-        <Self as ServerModuleGen>::init(self, cfg, db, task_group).await
+        <Self as ServerModuleGen>::init(
+            self,
+            cfg,
+            module_instance_id,
+            our_id,
+            db,
+            task_group,
+            interconnect,
+        )
+        .await
     }
 
     fn get_database_migrations(&self) -> MigrationMap {
@@ -1098,13 +1290,44 @@ pub trait ServerModule: Debug + Sized {
         dbtx: &mut ModuleDatabaseTransaction<'_>,
     ) -> ConsensusProposal<<Self::Common as ModuleCommon>::ConsensusItem>;
 
+    /// Whether `consensus_item` should be encrypted to the federation's
+    /// threshold key until it has been ordered, so guardians can't censor or
+    /// reorder it based on its content.
+    ///
+    /// Encrypted items are passed to `begin_consensus_epoch` in the epoch a
+    /// threshold of decryption shares for them was ordered in. Items a peer
+    /// proposed together can thus be revealed in different epochs, so a module
+    /// must not expect an encrypted item in the epoch it was proposed in.
+    fn encrypt_consensus_item(
+        &self,
+        consensus_item: &<Self::Common as ModuleCommon>::ConsensusItem,
+    ) -> bool {
+        let _ = consensus_item;
+        false
+    }
+
+    /// Whether `consensus_item` should be journaled until it has been ordered.
+    ///
+    /// Journaled items are persisted when first proposed and proposed again
+    /// after a restart, even if the module doesn't propose them anymore. The
+    /// same item may be ordered more than once, so `begin_consensus_epoch` has
+    /// to process them idempotently.
+    fn journal_consensus_item(
+        &self,
+        consensus_item: &<Self::Common as ModuleCommon>::ConsensusItem,
+    ) -> bool {
+        let _ = consensus_item;
+        false
+    }
+
     /// This function is called once before transaction processing starts.
     ///
     /// All module consensus items of this round are supplied as
     /// `consensus_items`. The database transaction will be committed to the
     /// database after all other modules ran `begin_consensus_epoch`, so the
     /// results are available when processing transactions. Returns any
-    /// peers that need to be dropped.
+    /// misbehaving peers, their misbehavior is scored and may get them
+    /// quarantined.
     async fn begin_consensus_epoch<'a, 'b>(
         &'a self,
         dbtx: &mut ModuleDatabaseTransaction<'b>,
@@ -1184,7 +1407,8 @@ pub trait ServerModule: Debug + Sized {
     /// finalization code before the next epoch.
     ///
     /// Passes in the `consensus_peers` that contributed to this epoch and
-    /// returns a list of peers to drop if any are misbehaving.
+    /// returns a list of misbehaving peers, their misbehavior is scored and may
+    /// get them quarantined.
     async fn end_consensus_epoch<'a, 'b>(
         &'a self,
         consensus_peers: &BTreeSet<PeerId>,
@@ -1208,11 +1432,66 @@ pub trait ServerModule: Debug + Sized {
     /// occurred in the database and consensus should halt.
     async fn audit(&self, dbtx: &mut ModuleDatabaseTransaction<'_>, audit: &mut Audit);
 
+    /// Contribution of an accepted `input` to the public epoch stats, see
+    /// [`crate::stats`]
+    ///
+    /// Only depends on the input, so every guardian reports the same activity.
+    fn input_activity(&self, input: &<Self::Common as ModuleCommon>::Input) -> ItemActivity {
+        let _ = input;
+        ItemActivity::default()
+    }
+
+    /// Contribution of an accepted `output` to the public epoch stats
+    fn output_activity(&self, output: &<Self::Common as ModuleCommon>::Output) -> ItemActivity {
+        let _ = output;
+        ItemActivity::default()
+    }
+
+    /// Returns the consensus encoded state a joining client needs from this
+    /// module, see [`crate::join`].
+    ///
+    /// Guardians only sign the snapshot together if they return the same bytes,
+    /// so it should be derived from consensus state.
+    async fn join_snapshot(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) -> Option<Vec<u8>> {
+        let _ = dbtx;
+        None
+    }
+
+    /// Returns the state of the module guardians want to see on their
+    /// dashboard, see [`crate::admin_client::AdminStatus`]
+    ///
+    /// Unlike the other calls this may depend on local state, e.g. on whether
+    /// our bitcoind is reachable.
+    async fn admin_status(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> Option<serde_json::Value> {
+        let _ = dbtx;
+        None
+    }
+
     /// Returns a list of custom API endpoints defined by the module. These are
     /// made available both to users as well as to other modules. They thus
     /// should be deterministic, only dependant on their input and the
     /// current epoch.
     fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>>;
+
+    /// Returns the API endpoints served under the major `version` of the
+    /// module's API, clients that negotiated that version call these.
+    ///
+    /// Defaults to `api_endpoints` for every supported version, modules that
+    /// changed their API incompatibly return the endpoints of the requested
+    /// major version.
+    fn api_endpoints_for_version(&self, version: ApiVersion) -> Vec<ApiEndpoint<Self>> {
+        let _ = version;
+        self.api_endpoints()
+    }
+
+    /// Returns the typed calls the module serves to the other modules of its
+    /// federation, see [`interconnect`]
+    fn interconnect_handlers(&self) -> Vec<InterconnectHandler<Self>> {
+        vec![]
+    }
 }
 
 /// Creates a struct that can be used to make our module-decodable structs
@@ -1224,10 +1503,7 @@ pub struct SerdeModuleEncoding<T: Encodable + Decodable>(Vec<u8>, #[serde(skip)]
 
 impl<T: Encodable + Decodable> From<&T> for SerdeModuleEncoding<T> {
     fn from(value: &T) -> Self {
-        let mut bytes = vec![];
-        fedimint_core::encoding::Encodable::consensus_encode(value, &mut bytes)
-            .expect("Writing to buffer can never fail");
-        Self(bytes, PhantomData)
+        Self(value.consensus_encode_to_vec_exact(), PhantomData)
     }
 }
 
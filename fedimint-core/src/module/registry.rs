@@ -52,6 +52,14 @@ impl<M> ModuleRegistry<M> {
     pub fn get_with_kind(&self, id: ModuleInstanceId) -> Option<&(ModuleKind, M)> {
         self.0.get(&id)
     }
+
+    /// Return an instance id higher than the ids of all modules in the
+    /// registry, for adding a module without hardcoding its id
+    pub fn next_instance_id(&self) -> ModuleInstanceId {
+        self.0.keys().next_back().map_or(0, |id| {
+            id.checked_add(1).expect("Ran out of module instance ids")
+        })
+    }
 }
 
 impl<M: std::fmt::Debug> ModuleRegistry<M> {
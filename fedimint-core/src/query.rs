@@ -6,13 +6,20 @@ use std::time::SystemTime;
 use anyhow::format_err;
 use fedimint_core::task::{MaybeSend, MaybeSync};
 use fedimint_core::time::now;
-use fedimint_core::{maybe_add_send_sync, PeerId};
+use fedimint_core::{maybe_add_send_sync, NumPeers, PeerId, TransactionId};
+use thiserror::Error;
+use threshold_crypto::PublicKeySet;
 use tracing::debug;
 
 use crate::api::{self, ApiVersionSet, MemberError};
+use crate::config::{ClientConfig, FederationId};
+use crate::epoch::{combine_sigs, SerdeSignatureShare};
+use crate::join::{FederationSnapshot, FederationSnapshotShare, SignedFederationSnapshot};
 use crate::module::{
     ApiVersion, SupportedApiVersionsSummary, SupportedCoreApiVersions, SupportedModuleApiVersions,
 };
+use crate::receipt::{SignedTransactionReceipt, TransactionReceipt, TransactionReceiptShare};
+use crate::stats::{EpochStats, EpochStatsShare, SignedEpochStats};
 
 /// Returns a result from the first responding peer
 pub struct TrustAllPeers;
@@ -198,6 +205,364 @@ impl<R: Eq + Clone + Debug> QueryStrategy<R> for EventuallyConsistent<R> {
     }
 }
 
+/// Members answered differently to a query all honest members answer the same
+#[derive(Debug, Error)]
+#[error("Federation members returned inconsistent responses: {responses:?}")]
+pub struct InconsistentResponses<R: Debug> {
+    pub responses: BTreeMap<PeerId, R>,
+}
+
+/// Returns the response of the first `one_honest` members if they are all
+/// equal and fails with [`InconsistentResponses`] otherwise
+///
+/// Unlike [`CurrentConsensus`] it doesn't wait for other members to break the
+/// tie, so a divergence gets surfaced instead of trusting whoever agrees first.
+pub struct ConsistentResponses<R> {
+    responses: BTreeMap<PeerId, R>,
+    errors: BTreeMap<PeerId, MemberError>,
+    required: usize,
+    total: usize,
+}
+
+impl<R> ConsistentResponses<R> {
+    pub fn new(peers: &BTreeSet<PeerId>) -> Self {
+        Self {
+            responses: BTreeMap::new(),
+            errors: BTreeMap::new(),
+            required: peers.one_honest(),
+            total: peers.total(),
+        }
+    }
+}
+
+impl<R: Eq + Clone + Debug + Send + Sync + 'static> QueryStrategy<R> for ConsistentResponses<R> {
+    fn process(&mut self, peer: PeerId, result: api::MemberResult<R>) -> QueryStep<R> {
+        match result {
+            Ok(response) => {
+                self.responses.insert(peer, response);
+            }
+            Err(error) => {
+                self.errors.insert(peer, error);
+            }
+        }
+
+        if self.responses.len() >= self.required {
+            let mut responses = self.responses.values();
+            let first = responses.next().expect("At least one response");
+            return if responses.all(|response| response == first) {
+                QueryStep::Success(first.clone())
+            } else {
+                QueryStep::Failure {
+                    general: Some(
+                        InconsistentResponses {
+                            responses: mem::take(&mut self.responses),
+                        }
+                        .into(),
+                    ),
+                    members: BTreeMap::new(),
+                }
+            };
+        }
+
+        if self.total - self.errors.len() < self.required {
+            return QueryStep::Failure {
+                general: None,
+                members: mem::take(&mut self.errors),
+            };
+        }
+
+        QueryStep::Continue
+    }
+}
+
+/// Combines the signature shares of the members that returned the same
+/// [`FederationSnapshot`] into one signed by the federation
+///
+/// Guardians can briefly disagree on a snapshot (e.g. a gateway only
+/// registered with some of them yet), so this fails once every member answered
+/// without a threshold of them agreeing. Retrying later should succeed.
+pub struct CombineSnapshotShares {
+    federation_id: FederationId,
+    snapshots: Vec<SnapshotShares>,
+    errors: BTreeMap<PeerId, MemberError>,
+    responded: BTreeSet<PeerId>,
+    total: usize,
+}
+
+/// Shares of the members that returned the same snapshot
+struct SnapshotShares {
+    snapshot: FederationSnapshot,
+    auth_pk_set: PublicKeySet,
+    config: Option<ClientConfig>,
+    shares: BTreeMap<PeerId, SerdeSignatureShare>,
+}
+
+impl CombineSnapshotShares {
+    pub fn new(federation_id: FederationId, peers: &BTreeSet<PeerId>) -> Self {
+        Self {
+            federation_id,
+            snapshots: vec![],
+            errors: BTreeMap::new(),
+            responded: BTreeSet::new(),
+            total: peers.total(),
+        }
+    }
+}
+
+impl QueryStrategy<FederationSnapshotShare, SignedFederationSnapshot> for CombineSnapshotShares {
+    fn process(
+        &mut self,
+        peer: PeerId,
+        result: api::MemberResult<FederationSnapshotShare>,
+    ) -> QueryStep<SignedFederationSnapshot> {
+        self.responded.insert(peer);
+
+        match result {
+            Ok(share) if share.verify(peer, &self.federation_id) => {
+                // Honest guardians agree on the key set as well, grouping by it means a
+                // forged one can't invalidate their shares
+                let position = self.snapshots.iter().position(|group| {
+                    group.snapshot == share.snapshot && group.auth_pk_set == share.auth_pk_set
+                });
+                let index = position.unwrap_or_else(|| {
+                    self.snapshots.push(SnapshotShares {
+                        snapshot: share.snapshot,
+                        auth_pk_set: share.auth_pk_set,
+                        config: None,
+                        shares: BTreeMap::new(),
+                    });
+                    self.snapshots.len() - 1
+                });
+                let group = &mut self.snapshots[index];
+                group.shares.insert(peer, share.share);
+                if share.config.is_some() {
+                    group.config = share.config;
+                }
+
+                let enough_shares = group.shares.len() > group.auth_pk_set.threshold();
+                if let (true, Some(config)) = (enough_shares, &group.config) {
+                    let hash = group.snapshot.signing_hash();
+                    if let Ok(signature) = combine_sigs(&group.auth_pk_set, &group.shares, &hash) {
+                        let signed = SignedFederationSnapshot {
+                            config: config.clone(),
+                            snapshot: group.snapshot.clone(),
+                            signature,
+                        };
+                        if signed.verify(&self.federation_id) {
+                            return QueryStep::Success(signed);
+                        }
+                    }
+                }
+            }
+            Ok(_) => {
+                self.errors.insert(
+                    peer,
+                    MemberError::InvalidResponse("Invalid snapshot signature share".to_string()),
+                );
+            }
+            Err(error) => {
+                self.errors.insert(peer, error);
+            }
+        }
+
+        if self.responded.len() >= self.total {
+            return QueryStep::Failure {
+                general: Some(format_err!(
+                    "No threshold of guardians agreed on a federation snapshot we got the config of"
+                )),
+                members: mem::take(&mut self.errors),
+            };
+        }
+
+        QueryStep::Continue
+    }
+}
+
+/// Combines the signature shares of a threshold of members that returned the
+/// same [`EpochStats`]
+pub struct CombineEpochStatsShares {
+    federation_id: FederationId,
+    /// Shares grouped by the stats and key set they were returned with
+    stats: Vec<(
+        EpochStats,
+        PublicKeySet,
+        BTreeMap<PeerId, SerdeSignatureShare>,
+    )>,
+    errors: BTreeMap<PeerId, MemberError>,
+    responded: BTreeSet<PeerId>,
+    total: usize,
+}
+
+impl CombineEpochStatsShares {
+    pub fn new(federation_id: FederationId, peers: &BTreeSet<PeerId>) -> Self {
+        Self {
+            federation_id,
+            stats: vec![],
+            errors: BTreeMap::new(),
+            responded: BTreeSet::new(),
+            total: peers.total(),
+        }
+    }
+}
+
+impl QueryStrategy<EpochStatsShare, SignedEpochStats> for CombineEpochStatsShares {
+    fn process(
+        &mut self,
+        peer: PeerId,
+        result: api::MemberResult<EpochStatsShare>,
+    ) -> QueryStep<SignedEpochStats> {
+        self.responded.insert(peer);
+
+        match result {
+            Ok(share) if share.verify(peer, &self.federation_id) => {
+                let EpochStatsShare {
+                    stats,
+                    auth_pk_set,
+                    share,
+                } = share;
+                let position = self
+                    .stats
+                    .iter()
+                    .position(|group| group.0 == stats && group.1 == auth_pk_set);
+                let index = position.unwrap_or_else(|| {
+                    self.stats.push((stats, auth_pk_set, BTreeMap::new()));
+                    self.stats.len() - 1
+                });
+                let (stats, auth_pk_set, shares) = &mut self.stats[index];
+                shares.insert(peer, share);
+
+                if shares.len() > auth_pk_set.threshold() {
+                    if let Ok(signature) = combine_sigs(auth_pk_set, shares, &stats.signing_hash())
+                    {
+                        let signed = SignedEpochStats {
+                            stats: stats.clone(),
+                            signature,
+                        };
+                        if signed.verify(&self.federation_id) {
+                            return QueryStep::Success(signed);
+                        }
+                    }
+                }
+            }
+            Ok(_) => {
+                self.errors.insert(
+                    peer,
+                    MemberError::InvalidResponse("Invalid epoch stats signature share".to_string()),
+                );
+            }
+            Err(error) => {
+                self.errors.insert(peer, error);
+            }
+        }
+
+        if self.responded.len() >= self.total {
+            return QueryStep::Failure {
+                general: Some(format_err!(
+                    "No threshold of guardians agreed on the epoch stats"
+                )),
+                members: mem::take(&mut self.errors),
+            };
+        }
+
+        QueryStep::Continue
+    }
+}
+
+/// Combines the signature shares of a threshold of members that returned the
+/// same [`TransactionReceipt`] for `txid`
+pub struct CombineReceiptShares {
+    federation_id: FederationId,
+    txid: TransactionId,
+    /// Shares grouped by the receipt and key set they were returned with
+    receipts: Vec<(
+        TransactionReceipt,
+        PublicKeySet,
+        BTreeMap<PeerId, SerdeSignatureShare>,
+    )>,
+    errors: BTreeMap<PeerId, MemberError>,
+    responded: BTreeSet<PeerId>,
+    total: usize,
+}
+
+impl CombineReceiptShares {
+    pub fn new(federation_id: FederationId, txid: TransactionId, peers: &BTreeSet<PeerId>) -> Self {
+        Self {
+            federation_id,
+            txid,
+            receipts: vec![],
+            errors: BTreeMap::new(),
+            responded: BTreeSet::new(),
+            total: peers.total(),
+        }
+    }
+}
+
+impl QueryStrategy<TransactionReceiptShare, SignedTransactionReceipt> for CombineReceiptShares {
+    fn process(
+        &mut self,
+        peer: PeerId,
+        result: api::MemberResult<TransactionReceiptShare>,
+    ) -> QueryStep<SignedTransactionReceipt> {
+        self.responded.insert(peer);
+
+        match result {
+            Ok(share)
+                if share.receipt.txid == self.txid && share.verify(peer, &self.federation_id) =>
+            {
+                let TransactionReceiptShare {
+                    receipt,
+                    auth_pk_set,
+                    share,
+                } = share;
+                let position = self
+                    .receipts
+                    .iter()
+                    .position(|group| group.0 == receipt && group.1 == auth_pk_set);
+                let index = position.unwrap_or_else(|| {
+                    self.receipts.push((receipt, auth_pk_set, BTreeMap::new()));
+                    self.receipts.len() - 1
+                });
+                let (receipt, auth_pk_set, shares) = &mut self.receipts[index];
+                shares.insert(peer, share);
+
+                if shares.len() > auth_pk_set.threshold() {
+                    if let Ok(signature) =
+                        combine_sigs(auth_pk_set, shares, &receipt.signing_hash())
+                    {
+                        let signed = SignedTransactionReceipt {
+                            receipt: receipt.clone(),
+                            signature,
+                        };
+                        if signed.verify(&self.federation_id) {
+                            return QueryStep::Success(signed);
+                        }
+                    }
+                }
+            }
+            Ok(_) => {
+                self.errors.insert(
+                    peer,
+                    MemberError::InvalidResponse("Invalid receipt signature share".to_string()),
+                );
+            }
+            Err(error) => {
+                self.errors.insert(peer, error);
+            }
+        }
+
+        if self.responded.len() >= self.total {
+            return QueryStep::Failure {
+                general: Some(format_err!(
+                    "No threshold of guardians agreed on a receipt for the transaction"
+                )),
+                members: mem::take(&mut self.errors),
+            };
+        }
+
+        QueryStep::Continue
+    }
+}
+
 /// Returns when `required` responses are equal
 pub struct CurrentConsensus<R> {
     /// Previously received responses/results
@@ -307,20 +672,25 @@ impl<R> QueryStrategy<R, BTreeMap<PeerId, R>> for AllOrDeadline<R> {
 
 /// Query for supported api versions from all the guardians (with a deadline)
 /// and calculate the best versions to use for each component (core + modules).
+///
+/// The best version of a component is the highest major version the client
+/// supports that a threshold of guardians support as well.
 pub struct DiscoverApiVersionSet {
     inner: AllOrDeadline<SupportedApiVersionsSummary>,
     client_versions: SupportedApiVersionsSummary,
+    threshold: usize,
 }
 
 impl DiscoverApiVersionSet {
     pub fn new(
-        num_peers: usize,
+        peers: &BTreeSet<PeerId>,
         deadline: SystemTime,
         client_versions: SupportedApiVersionsSummary,
     ) -> Self {
         Self {
-            inner: AllOrDeadline::new(num_peers, deadline),
+            inner: AllOrDeadline::new(peers.len(), deadline),
             client_versions,
+            threshold: peers.threshold(),
         }
     }
 }
@@ -328,10 +698,12 @@ impl DiscoverApiVersionSet {
 fn discover_common_core_api_version(
     client_versions: &SupportedCoreApiVersions,
     peer_versions: BTreeMap<PeerId, SupportedCoreApiVersions>,
+    threshold: usize,
 ) -> Option<ApiVersion> {
     let mut best_major = None;
-    let mut best_major_peer_num = 0;
 
+    // client versions are sorted by major, so the last compatible one is the
+    // highest
     for client_api_version in &client_versions.api {
         let peers_compatible_num = peer_versions
             .values()
@@ -348,9 +720,8 @@ fn discover_common_core_api_version(
             })
             .count();
 
-        if best_major_peer_num < peers_compatible_num {
+        if threshold <= peers_compatible_num {
             best_major = Some(client_api_version);
-            best_major_peer_num = peers_compatible_num;
         }
     }
 
@@ -371,7 +742,7 @@ fn discover_common_core_api_version_sanity() {
         .unwrap(),
     };
 
-    assert!(discover_common_core_api_version(&client_versions, BTreeMap::from([])).is_none());
+    assert!(discover_common_core_api_version(&client_versions, BTreeMap::from([]), 1).is_none());
     assert_eq!(
         discover_common_core_api_version(
             &client_versions,
@@ -382,7 +753,8 @@ fn discover_common_core_api_version_sanity() {
                     api: MultiApiVersion::try_from_iter([ApiVersion { major: 2, minor: 4 }])
                         .unwrap(),
                 }
-            )])
+            )]),
+            1
         ),
         Some(ApiVersion { major: 2, minor: 3 })
     );
@@ -396,7 +768,8 @@ fn discover_common_core_api_version_sanity() {
                     api: MultiApiVersion::try_from_iter([ApiVersion { major: 2, minor: 4 }])
                         .unwrap(),
                 }
-            )])
+            )]),
+            1
         ),
         None
     );
@@ -428,19 +801,67 @@ fn discover_common_core_api_version_sanity() {
                             .unwrap(),
                     }
                 )
-            ])
+            ]),
+            1
+        ),
+        Some(ApiVersion { major: 3, minor: 1 })
+    );
+
+    let peer_versions = BTreeMap::from([
+        (
+            PeerId(0),
+            SupportedCoreApiVersions {
+                core_consensus,
+                api: MultiApiVersion::try_from_iter([
+                    ApiVersion { major: 2, minor: 3 },
+                    ApiVersion { major: 3, minor: 1 },
+                ])
+                .unwrap(),
+            },
+        ),
+        (
+            PeerId(1),
+            SupportedCoreApiVersions {
+                core_consensus,
+                api: MultiApiVersion::try_from_iter([ApiVersion { major: 2, minor: 5 }]).unwrap(),
+            },
         ),
+        (
+            PeerId(2),
+            SupportedCoreApiVersions {
+                core_consensus,
+                api: MultiApiVersion::try_from_iter([
+                    ApiVersion { major: 2, minor: 4 },
+                    ApiVersion { major: 3, minor: 2 },
+                ])
+                .unwrap(),
+            },
+        ),
+    ]);
+    // the highest version supported by a threshold of peers wins
+    assert_eq!(
+        discover_common_core_api_version(&client_versions, peer_versions.clone(), 2),
         Some(ApiVersion { major: 3, minor: 1 })
     );
+    assert_eq!(
+        discover_common_core_api_version(&client_versions, peer_versions.clone(), 3),
+        Some(ApiVersion { major: 2, minor: 3 })
+    );
+    assert_eq!(
+        discover_common_core_api_version(&client_versions, peer_versions, 4),
+        None
+    );
 }
 
 fn discover_common_module_api_version(
     client_versions: &SupportedModuleApiVersions,
     peer_versions: BTreeMap<PeerId, SupportedModuleApiVersions>,
+    threshold: usize,
 ) -> Option<ApiVersion> {
     let mut best_major = None;
-    let mut best_major_peer_num = 0;
 
+    // client versions are sorted by major, so the last compatible one is the
+    // highest
     for client_api_version in &client_versions.api {
         let peers_compatible_num = peer_versions
             .values()
@@ -458,9 +879,8 @@ fn discover_common_module_api_version(
             })
             .count();
 
-        if best_major_peer_num < peers_compatible_num {
+        if threshold <= peers_compatible_num {
             best_major = Some(client_api_version);
-            best_major_peer_num = peers_compatible_num;
         }
     }
 
@@ -470,6 +890,7 @@ fn discover_common_module_api_version(
 fn discover_common_api_versions_set(
     client_versions: &SupportedApiVersionsSummary,
     peer_versions: BTreeMap<PeerId, SupportedApiVersionsSummary>,
+    threshold: usize,
 ) -> anyhow::Result<ApiVersionSet> {
     Ok(ApiVersionSet {
         core: discover_common_core_api_version(
@@ -480,8 +901,15 @@ fn discover_common_api_versions_set(
                     (*peer_id, peer_supported_api_versions.core.clone())
                 })
                 .collect(),
+            threshold,
         )
-        .ok_or_else(|| format_err!("Could not find a common core API version"))?,
+        .ok_or_else(|| {
+            format_err!(
+                "Could not find a core API version supported by the client ({:?}) and a threshold of {threshold} out of {} responding peers",
+                client_versions.core.api,
+                peer_versions.len()
+            )
+        })?,
         modules: client_versions
             .modules
             .iter()
@@ -498,6 +926,7 @@ fn discover_common_api_versions_set(
                                     .map(|versions| (*peer_id, versions.clone()))
                             })
                             .collect(),
+                        threshold,
                     );
                     discover_common_module_api_version.map(|v| (*module_instance_id, v))
                 },
@@ -514,7 +943,7 @@ impl QueryStrategy<SupportedApiVersionsSummary, ApiVersionSet> for DiscoverApiVe
     ) -> QueryStep<ApiVersionSet> {
         match self.inner.process(peer, result) {
             QueryStep::Success(o) => {
-                match discover_common_api_versions_set(&self.client_versions, o) {
+                match discover_common_api_versions_set(&self.client_versions, o, self.threshold) {
                     Ok(o) => QueryStep::Success(o),
                     Err(e) => QueryStep::Failure {
                         general: Some(e),
@@ -557,3 +986,168 @@ pub enum QueryStep<R> {
         members: BTreeMap<PeerId, MemberError>,
     },
 }
+
+#[test]
+fn consistent_responses_surface_divergence() {
+    let peers = (0..4).map(PeerId).collect::<BTreeSet<_>>();
+
+    let mut strategy = ConsistentResponses::new(&peers);
+    assert!(matches!(
+        strategy.process(PeerId(0), Ok(1)),
+        QueryStep::Continue
+    ));
+    assert!(matches!(
+        strategy.process(PeerId(1), Ok(1)),
+        QueryStep::Success(1)
+    ));
+
+    let mut strategy = ConsistentResponses::<u32>::new(&peers);
+    assert!(matches!(
+        strategy.process(PeerId(0), Ok(1)),
+        QueryStep::Continue
+    ));
+    match strategy.process(PeerId(2), Ok(2)) {
+        QueryStep::Failure {
+            general: Some(error),
+            ..
+        } => {
+            let inconsistent = error
+                .downcast_ref::<InconsistentResponses<u32>>()
+                .expect("Inconsistency error");
+            assert_eq!(
+                inconsistent.responses,
+                BTreeMap::from([(PeerId(0), 1), (PeerId(2), 2)])
+            );
+        }
+        _ => panic!("Expected a failure"),
+    }
+
+    // once too many members failed we can't get enough responses anymore
+    let mut strategy = ConsistentResponses::<u32>::new(&peers);
+    for peer in 0..2 {
+        let error = MemberError::InvalidPeerId {
+            peer_id: PeerId(peer),
+        };
+        assert!(matches!(
+            strategy.process(PeerId(peer), Err(error)),
+            QueryStep::Continue
+        ));
+    }
+    assert!(matches!(
+        strategy.process(
+            PeerId(2),
+            Err(MemberError::InvalidPeerId { peer_id: PeerId(2) })
+        ),
+        QueryStep::Failure { general: None, .. }
+    ));
+}
+
+#[test]
+fn combines_snapshot_shares_of_a_threshold() {
+    use threshold_crypto::SecretKeySet;
+
+    let peers = (0..4).map(PeerId).collect::<BTreeSet<_>>();
+    let sks = SecretKeySet::random(peers.degree(), &mut rand::rngs::OsRng);
+    let federation_id = FederationId(sks.public_keys().public_key());
+    let config = ClientConfig {
+        federation_id,
+        api_endpoints: BTreeMap::new(),
+        epoch_pk: sks.public_keys().public_key(),
+        consensus_version: 0.into(),
+        meta: BTreeMap::new(),
+        modules: BTreeMap::new(),
+    };
+    let snapshot = |gateways: u8| FederationSnapshot {
+        config_hash: config.consensus_hash(),
+        modules: BTreeMap::from([(0, vec![gateways])]),
+    };
+    let share =
+        |peer: PeerId, snapshot: FederationSnapshot, with_config: bool| FederationSnapshotShare {
+            share: SerdeSignatureShare(
+                sks.secret_key_share(peer.to_usize())
+                    .sign(snapshot.signing_hash()),
+            ),
+            config: with_config.then(|| config.clone()),
+            auth_pk_set: sks.public_keys(),
+            snapshot,
+        };
+
+    let mut strategy = CombineSnapshotShares::new(federation_id, &peers);
+    assert!(matches!(
+        strategy.process(PeerId(0), Ok(share(PeerId(0), snapshot(1), true))),
+        QueryStep::Continue
+    ));
+    // a guardian that is behind doesn't count towards the threshold
+    assert!(matches!(
+        strategy.process(PeerId(1), Ok(share(PeerId(1), snapshot(0), false))),
+        QueryStep::Continue
+    ));
+    // neither does one signing with someone else's share
+    assert!(matches!(
+        strategy.process(PeerId(2), Ok(share(PeerId(1), snapshot(1), false))),
+        QueryStep::Continue
+    ));
+    match strategy.process(PeerId(3), Ok(share(PeerId(3), snapshot(1), false))) {
+        QueryStep::Failure { members, .. } => {
+            assert_eq!(members.keys().collect::<Vec<_>>(), vec![&PeerId(2)]);
+        }
+        _ => panic!("Expected a failure"),
+    }
+
+    // the config may come from any guardian of the threshold
+    let mut strategy = CombineSnapshotShares::new(federation_id, &peers);
+    for peer in 0..2 {
+        assert!(matches!(
+            strategy.process(PeerId(peer), Ok(share(PeerId(peer), snapshot(1), false))),
+            QueryStep::Continue
+        ));
+    }
+    match strategy.process(PeerId(3), Ok(share(PeerId(3), snapshot(1), true))) {
+        QueryStep::Success(signed) => {
+            assert!(signed.verify(&federation_id));
+            assert_eq!(signed.snapshot, snapshot(1));
+            assert_eq!(signed.config, config);
+        }
+        _ => panic!("Expected a success"),
+    }
+}
+
+#[test]
+fn combines_epoch_stats_shares_of_a_threshold() {
+    use threshold_crypto::SecretKeySet;
+
+    let peers = (0..4).map(PeerId).collect::<BTreeSet<_>>();
+    let sks = SecretKeySet::random(peers.degree(), &mut rand::rngs::OsRng);
+    let federation_id = FederationId(sks.public_keys().public_key());
+    let stats = |transactions: u64| EpochStats::new(3, transactions, []);
+    let share = |peer: PeerId, stats: EpochStats| EpochStatsShare {
+        share: SerdeSignatureShare(
+            sks.secret_key_share(peer.to_usize())
+                .sign(stats.signing_hash()),
+        ),
+        auth_pk_set: sks.public_keys(),
+        stats,
+    };
+
+    let mut strategy = CombineEpochStatsShares::new(federation_id, &peers);
+    assert!(matches!(
+        strategy.process(PeerId(0), Ok(share(PeerId(0), stats(1)))),
+        QueryStep::Continue
+    ));
+    // a guardian returning different stats doesn't count towards the threshold
+    assert!(matches!(
+        strategy.process(PeerId(1), Ok(share(PeerId(1), stats(2)))),
+        QueryStep::Continue
+    ));
+    assert!(matches!(
+        strategy.process(PeerId(2), Ok(share(PeerId(2), stats(1)))),
+        QueryStep::Continue
+    ));
+    match strategy.process(PeerId(3), Ok(share(PeerId(3), stats(1)))) {
+        QueryStep::Success(signed) => {
+            assert!(signed.verify(&federation_id));
+            assert_eq!(signed.stats, stats(1));
+        }
+        _ => panic!("Expected a success"),
+    }
+}
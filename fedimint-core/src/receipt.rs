@@ -0,0 +1,219 @@
+//! Receipts proving how the federation processed a transaction
+//!
+//! A client that paid someone through the federation, e.g. a merchant's
+//! invoice, may have to prove the payment to a third party that can't query
+//! the federation. Once a transaction was processed every guardian signs a
+//! [`TransactionReceipt`] binding its id, amounts and outcome with its share
+//! of the auth key. Like [epoch stats](crate::stats) a threshold of shares over
+//! the same receipt yields a [`SignedTransactionReceipt`], which anybody who
+//! knows the [`FederationId`] can check with [`verify_receipt`].
+//!
+//! Guardians only remember the amounts of transactions they processed since
+//! they support receipts, older transactions can't get one.
+
+use std::fmt;
+use std::str::FromStr;
+
+use bitcoin_hashes::sha256::{Hash as Sha256, HashEngine};
+use bitcoin_hashes::{Hash, HashEngine as BitcoinHashEngine};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use threshold_crypto::PublicKeySet;
+
+use crate::config::FederationId;
+use crate::encoding::{Decodable, Encodable};
+use crate::epoch::{SerdeSignature, SerdeSignatureShare};
+use crate::module::registry::ModuleDecoderRegistry;
+use crate::{Amount, PeerId, TransactionId};
+
+/// Separates receipt signatures from the other messages signed with the auth
+/// key, like epoch stats
+const RECEIPT_SIGNING_TAG: &[u8] = b"fedimint-transaction-receipt";
+
+/// Amounts moved by an accepted transaction, the inputs fund the outputs and
+/// the fees
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct TransactionAmounts {
+    pub inputs: Amount,
+    pub outputs: Amount,
+    pub fees: Amount,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub enum ReceiptOutcome {
+    /// The transaction was accepted in `epoch`, its outputs will be or were
+    /// issued
+    Accepted {
+        epoch: u64,
+        amounts: TransactionAmounts,
+    },
+    /// The transaction was rejected, none of its inputs were spent
+    Rejected,
+}
+
+/// What the guardians attest about a transaction
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct TransactionReceipt {
+    pub txid: TransactionId,
+    pub outcome: ReceiptOutcome,
+}
+
+impl TransactionReceipt {
+    /// Hash the guardians sign
+    pub fn signing_hash(&self) -> Sha256 {
+        let mut engine = HashEngine::default();
+        engine.input(RECEIPT_SIGNING_TAG);
+        self.consensus_encode(&mut engine)
+            .expect("hashing is infallible");
+        Sha256::from_engine(engine)
+    }
+}
+
+/// A guardian's receipt along with its signature share
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TransactionReceiptShare {
+    pub receipt: TransactionReceipt,
+    /// Public keys of the auth key shares, needed to combine the signature
+    pub auth_pk_set: PublicKeySet,
+    pub share: SerdeSignatureShare,
+}
+
+impl TransactionReceiptShare {
+    /// Checks that the share was created by `peer` for a federation with
+    /// `federation_id`
+    pub fn verify(&self, peer: PeerId, federation_id: &FederationId) -> bool {
+        self.auth_pk_set.public_key() == federation_id.0
+            && self
+                .auth_pk_set
+                .public_key_share(peer.to_usize())
+                .verify(&self.share.0, self.receipt.signing_hash())
+    }
+}
+
+/// Receipt signed by a threshold of guardians, encoded as hex to be handed
+/// to third parties
+#[derive(Debug, Clone, Eq, PartialEq, Encodable, Decodable)]
+pub struct SignedTransactionReceipt {
+    pub receipt: TransactionReceipt,
+    pub signature: SerdeSignature,
+}
+
+impl SignedTransactionReceipt {
+    /// Checks that the receipt was signed by the federation with
+    /// `federation_id`
+    pub fn verify(&self, federation_id: &FederationId) -> bool {
+        federation_id
+            .0
+            .verify(&self.signature.0, self.receipt.signing_hash())
+    }
+}
+
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum ReceiptError {
+    #[error("Receipt is for transaction {0}")]
+    WrongTransaction(TransactionId),
+    #[error("Receipt wasn't signed by the federation")]
+    InvalidSignature,
+}
+
+/// Checks offline that `receipt` was signed by the federation with
+/// `federation_id` and is about the transaction `txid`
+pub fn verify_receipt(
+    receipt: &SignedTransactionReceipt,
+    federation_id: &FederationId,
+    txid: TransactionId,
+) -> Result<(), ReceiptError> {
+    if receipt.receipt.txid != txid {
+        return Err(ReceiptError::WrongTransaction(receipt.receipt.txid));
+    }
+    if !receipt.verify(federation_id) {
+        return Err(ReceiptError::InvalidSignature);
+    }
+    Ok(())
+}
+
+impl fmt::Display for SignedTransactionReceipt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = self.consensus_encode_to_hex().map_err(|_| fmt::Error)?;
+        f.write_str(&hex)
+    }
+}
+
+impl FromStr for SignedTransactionReceipt {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Receipts don't contain module items, so no decoders are needed
+        Ok(Self::consensus_decode_hex(
+            s.trim(),
+            &ModuleDecoderRegistry::default(),
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use threshold_crypto::SecretKeySet;
+
+    use super::*;
+    use crate::epoch::combine_sigs;
+
+    fn receipt(txid: TransactionId) -> TransactionReceipt {
+        TransactionReceipt {
+            txid,
+            outcome: ReceiptOutcome::Accepted {
+                epoch: 4,
+                amounts: TransactionAmounts {
+                    inputs: Amount::from_sats(1_010),
+                    outputs: Amount::from_sats(1_000),
+                    fees: Amount::from_sats(10),
+                },
+            },
+        }
+    }
+
+    #[test]
+    fn threshold_signed_receipt_verifies_offline() {
+        let sks = SecretKeySet::random(1, &mut rand::thread_rng());
+        let pks = sks.public_keys();
+        let federation_id = FederationId(pks.public_key());
+        let txid = TransactionId::from_inner([1; 32]);
+        let receipt = receipt(txid);
+
+        let shares = (0..2)
+            .map(|peer| TransactionReceiptShare {
+                receipt: receipt.clone(),
+                auth_pk_set: pks.clone(),
+                share: SerdeSignatureShare(sks.secret_key_share(peer).sign(receipt.signing_hash())),
+            })
+            .collect::<Vec<_>>();
+        assert!(shares[0].verify(PeerId::from(0), &federation_id));
+        assert!(!shares[0].verify(PeerId::from(1), &federation_id));
+
+        let signature = combine_sigs(
+            &pks,
+            &shares
+                .iter()
+                .enumerate()
+                .map(|(peer, share)| (PeerId::from(peer as u16), share.share.clone()))
+                .collect::<BTreeMap<_, _>>(),
+            &receipt.signing_hash(),
+        )
+        .unwrap();
+        let signed = SignedTransactionReceipt { receipt, signature };
+
+        // Survives being handed around as hex
+        let signed: SignedTransactionReceipt = signed.to_string().parse().unwrap();
+        assert_eq!(verify_receipt(&signed, &federation_id, txid), Ok(()));
+        assert_eq!(
+            verify_receipt(&signed, &federation_id, TransactionId::from_inner([2; 32])),
+            Err(ReceiptError::WrongTransaction(txid))
+        );
+        assert_eq!(
+            verify_receipt(&signed, &FederationId::dummy(), txid),
+            Err(ReceiptError::InvalidSignature)
+        );
+    }
+}
@@ -0,0 +1,195 @@
+//! Public aggregate activity of the federation per epoch
+//!
+//! Explorers want to show how a federation is used without parsing the raw
+//! epoch history and knowing every module's transaction types. Guardians
+//! derive [`EpochStats`] from the accepted transactions of an epoch, with the
+//! modules reporting the activity of their inputs and outputs. Volumes are
+//! rounded down to a power of ten, so single deposits or withdrawals can't be
+//! recognized.
+//!
+//! Like [join snapshots](crate::join) the stats are signed with the guardians'
+//! shares of the auth key, a threshold of guardians returning the same stats
+//! yields [`SignedEpochStats`] that verify against the [`FederationId`].
+
+use std::collections::BTreeSet;
+
+use bitcoin_hashes::sha256::{Hash as Sha256, HashEngine};
+use bitcoin_hashes::{Hash, HashEngine as BitcoinHashEngine};
+use secp256k1_zkp::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+use threshold_crypto::PublicKeySet;
+
+use crate::config::FederationId;
+use crate::encoding::{Decodable, Encodable};
+use crate::epoch::{SerdeSignature, SerdeSignatureShare};
+use crate::{Amount, PeerId};
+
+/// Separates stats signatures from the other messages signed with the auth
+/// key, like the client config hash
+const STATS_SIGNING_TAG: &[u8] = b"fedimint-epoch-stats";
+
+/// Contribution of a single accepted input or output to the [`EpochStats`],
+/// reported by the module it belongs to
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ItemActivity {
+    /// Value deposited into the federation from outside
+    pub peg_in: Amount,
+    /// Value withdrawn from the federation
+    pub peg_out: Amount,
+    /// Lightning gateway taking part in a payment
+    pub gateway: Option<XOnlyPublicKey>,
+}
+
+/// Aggregate activity of an epoch
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct EpochStats {
+    pub epoch: u64,
+    /// Number of accepted transactions
+    pub transactions: u64,
+    /// Total value pegged in, see [`volume_bucket`]
+    pub peg_in_volume: Amount,
+    /// Total value pegged out, see [`volume_bucket`]
+    pub peg_out_volume: Amount,
+    /// Number of distinct lightning gateways taking part in payments
+    pub active_gateways: u64,
+}
+
+impl EpochStats {
+    /// Aggregates the `activity` of the items of the `transactions` accepted
+    /// in `epoch`
+    pub fn new(
+        epoch: u64,
+        transactions: u64,
+        activity: impl IntoIterator<Item = ItemActivity>,
+    ) -> Self {
+        let mut peg_in = Amount::ZERO;
+        let mut peg_out = Amount::ZERO;
+        let mut gateways = BTreeSet::new();
+        for item in activity {
+            peg_in += item.peg_in;
+            peg_out += item.peg_out;
+            gateways.extend(item.gateway);
+        }
+
+        EpochStats {
+            epoch,
+            transactions,
+            peg_in_volume: volume_bucket(peg_in),
+            peg_out_volume: volume_bucket(peg_out),
+            active_gateways: gateways.len() as u64,
+        }
+    }
+
+    /// Hash the guardians sign
+    pub fn signing_hash(&self) -> Sha256 {
+        let mut engine = HashEngine::default();
+        engine.input(STATS_SIGNING_TAG);
+        self.consensus_encode(&mut engine)
+            .expect("hashing is infallible");
+        Sha256::from_engine(engine)
+    }
+}
+
+/// Rounds `amount` down to a power of ten of sats, amounts below one sat
+/// round down to zero
+pub fn volume_bucket(amount: Amount) -> Amount {
+    let sats = amount.msats / 1000;
+    if sats == 0 {
+        return Amount::ZERO;
+    }
+    let mut bucket = 1;
+    while bucket <= sats / 10 {
+        bucket *= 10;
+    }
+    Amount::from_sats(bucket)
+}
+
+/// A guardian's stats along with its signature share
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct EpochStatsShare {
+    pub stats: EpochStats,
+    /// Public keys of the auth key shares, needed to combine the signature
+    pub auth_pk_set: PublicKeySet,
+    pub share: SerdeSignatureShare,
+}
+
+impl EpochStatsShare {
+    /// Checks that the share was created by `peer` for a federation with
+    /// `federation_id`
+    pub fn verify(&self, peer: PeerId, federation_id: &FederationId) -> bool {
+        self.auth_pk_set.public_key() == federation_id.0
+            && self
+                .auth_pk_set
+                .public_key_share(peer.to_usize())
+                .verify(&self.share.0, self.stats.signing_hash())
+    }
+}
+
+/// Stats signed by a threshold of guardians
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SignedEpochStats {
+    pub stats: EpochStats,
+    pub signature: SerdeSignature,
+}
+
+impl SignedEpochStats {
+    /// Checks the stats were signed by the federation with `federation_id`
+    pub fn verify(&self, federation_id: &FederationId) -> bool {
+        federation_id
+            .0
+            .verify(&self.signature.0, self.stats.signing_hash())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volumes_are_rounded_down_to_powers_of_ten() {
+        assert_eq!(volume_bucket(Amount::from_msats(999)), Amount::ZERO);
+        assert_eq!(volume_bucket(Amount::from_sats(1)), Amount::from_sats(1));
+        assert_eq!(volume_bucket(Amount::from_sats(99)), Amount::from_sats(10));
+        assert_eq!(
+            volume_bucket(Amount::from_sats(123_456)),
+            Amount::from_sats(100_000)
+        );
+    }
+
+    #[test]
+    fn aggregates_item_activity() {
+        let gateway = secp256k1_zkp::KeyPair::from_seckey_slice(secp256k1_zkp::SECP256K1, &[1; 32])
+            .unwrap()
+            .x_only_public_key()
+            .0;
+        let stats = EpochStats::new(
+            7,
+            3,
+            [
+                ItemActivity {
+                    peg_in: Amount::from_sats(60_000),
+                    ..Default::default()
+                },
+                ItemActivity {
+                    peg_in: Amount::from_sats(50_000),
+                    gateway: Some(gateway),
+                    ..Default::default()
+                },
+                ItemActivity {
+                    gateway: Some(gateway),
+                    ..Default::default()
+                },
+            ],
+        );
+        assert_eq!(
+            stats,
+            EpochStats {
+                epoch: 7,
+                transactions: 3,
+                peg_in_volume: Amount::from_sats(100_000),
+                peg_out_volume: Amount::ZERO,
+                active_gateways: 1,
+            }
+        );
+    }
+}
@@ -1,6 +1,6 @@
 #![cfg_attr(target_family = "wasm", allow(dead_code))]
 
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::atomic::AtomicBool;
@@ -15,6 +15,7 @@ use futures::channel::oneshot;
 use futures::future::BoxFuture;
 use futures::lock::Mutex;
 pub use imp::*;
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 #[cfg(not(target_family = "wasm"))]
 use tokio::sync::oneshot;
@@ -29,6 +30,25 @@ type JoinHandle<T> = futures::future::Ready<anyhow::Result<T>>;
 #[error("deadline has elapsed")]
 pub struct Elapsed;
 
+/// Delay before restarting a supervised task after its first panic, doubled
+/// for every further panic
+const SUPERVISED_TASK_MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Longest delay before restarting a supervised task, a task that ran this
+/// long without panicking starts over with the minimum delay
+const SUPERVISED_TASK_MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// Health of a task spawned with [`TaskGroup::spawn_supervised`]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskHealth {
+    /// False while the task waits to be restarted or after it finished
+    pub running: bool,
+    /// How often the task panicked and was restarted
+    pub restarts: u32,
+    /// Message of the last panic
+    pub last_panic: Option<String>,
+}
+
 #[derive(Debug, Default)]
 struct TaskGroupInner {
     /// Was the shutdown requested, either externally or due to any task
@@ -37,6 +57,7 @@ struct TaskGroupInner {
     #[allow(clippy::type_complexity)]
     on_shutdown: Mutex<Vec<Box<dyn FnOnce() -> BoxFuture<'static, ()> + Send + 'static>>>,
     join: Mutex<VecDeque<(String, JoinHandle<()>)>>,
+    supervised: std::sync::Mutex<BTreeMap<String, TaskHealth>>,
 }
 
 impl TaskGroupInner {
@@ -98,7 +119,7 @@ impl TaskGroup {
         let new_tg = Self::new();
         self.make_handle()
             .on_shutdown({
-                let new_tg = self.clone();
+                let new_tg = new_tg.clone();
                 Box::new(move || {
                     Box::pin(async move {
                         new_tg.shutdown().await;
@@ -192,6 +213,88 @@ impl TaskGroup {
         rx
     }
 
+    /// Spawns a long-running task that is restarted if it panics
+    ///
+    /// A panic is logged and doesn't affect the other tasks of the group.
+    /// The task is started again by calling `f` after a delay that grows with
+    /// every consecutive panic. Tasks are only restarted while the group is
+    /// running, and not at all once `f` returned normally. Their health is
+    /// reported by [`Self::task_health`].
+    #[cfg(not(target_family = "wasm"))]
+    pub async fn spawn_supervised<Fut>(
+        &mut self,
+        name: impl Into<String>,
+        f: impl Fn(TaskHandle) -> Fut + Send + Sync + 'static,
+    ) where
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        use std::panic::AssertUnwindSafe;
+
+        use futures::FutureExt;
+
+        let name = name.into();
+        let inner = self.inner.clone();
+        let set_health = move |name: &str, update: &dyn Fn(&mut TaskHealth)| {
+            update(
+                inner
+                    .supervised
+                    .lock()
+                    .expect("poisoned")
+                    .entry(name.to_owned())
+                    .or_default(),
+            )
+        };
+
+        let task_name = name.clone();
+        self.spawn(name, move |handle| async move {
+            let mut shutdown_rx = handle.make_shutdown_rx().await;
+            let mut backoff = SUPERVISED_TASK_MIN_BACKOFF;
+            loop {
+                set_health(&task_name, &|health| health.running = true);
+                let started = now();
+                let result = AssertUnwindSafe(f(handle.clone())).catch_unwind().await;
+                set_health(&task_name, &|health| health.running = false);
+
+                let Err(panic) = result else {
+                    break;
+                };
+                let message = panic
+                    .downcast_ref::<&str>()
+                    .map(|message| message.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "unknown panic".to_owned());
+                if started.elapsed().unwrap_or_default() >= SUPERVISED_TASK_MAX_BACKOFF {
+                    backoff = SUPERVISED_TASK_MIN_BACKOFF;
+                }
+                error!(
+                    target: LOG_TASK,
+                    task = %task_name,
+                    panic = %message,
+                    "Supervised task panicked, restarting it in {backoff:?}"
+                );
+                set_health(&task_name, &|health| {
+                    health.restarts += 1;
+                    health.last_panic = Some(message.clone());
+                });
+
+                tokio::select! {
+                    _ = sleep(backoff) => {},
+                    _ = &mut shutdown_rx => break,
+                }
+                if handle.is_shutting_down() {
+                    break;
+                }
+                backoff = (backoff * 2).min(SUPERVISED_TASK_MAX_BACKOFF);
+            }
+        })
+        .await;
+    }
+
+    /// Health of the tasks spawned with [`Self::spawn_supervised`] by name
+    pub fn task_health(&self) -> BTreeMap<String, TaskHealth> {
+        self.inner.supervised.lock().expect("poisoned").clone()
+    }
+
     #[cfg(not(target_family = "wasm"))]
     pub async fn spawn_local<Fut>(
         &mut self,
@@ -245,6 +348,18 @@ impl TaskGroup {
         rx
     }
 
+    #[cfg(target_family = "wasm")]
+    pub async fn spawn_local<Fut>(
+        &mut self,
+        name: impl Into<String>,
+        f: impl FnOnce(TaskHandle) -> Fut + 'static,
+    ) where
+        Fut: Future<Output = ()> + 'static,
+    {
+        // all tasks are local on wasm
+        self.spawn(name, f).await;
+    }
+
     pub async fn join_all(self, timeout: Option<Duration>) -> Result<(), anyhow::Error> {
         let mut errors = vec![];
 
@@ -392,6 +507,10 @@ mod imp {
         tokio::task::block_in_place(f)
     }
 
+    pub async fn yield_now() {
+        tokio::task::yield_now().await
+    }
+
     pub async fn sleep(duration: Duration) {
         tokio::time::sleep(duration).await
     }
@@ -442,6 +561,21 @@ mod imp {
         f()
     }
 
+    /// Lets the other tasks spawned on the event loop run
+    pub async fn yield_now() {
+        let mut yielded = false;
+        futures::future::poll_fn(|cx| {
+            if yielded {
+                std::task::Poll::Ready(())
+            } else {
+                yielded = true;
+                cx.waker().wake_by_ref();
+                std::task::Poll::Pending
+            }
+        })
+        .await
+    }
+
     pub async fn sleep(duration: Duration) {
         gloo_timers::future::sleep(duration.min(Duration::from_millis(i32::MAX as _))).await
     }
@@ -571,3 +705,65 @@ impl<T: Sync> MaybeSync for T {}
 
 #[cfg(target_family = "wasm")]
 impl<T> MaybeSync for T {}
+
+#[cfg(all(test, not(target_family = "wasm")))]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn shutdown_propagates_to_subgroups() {
+        let task_group = TaskGroup::new();
+        let subgroup = task_group.make_subgroup().await;
+        let nested = subgroup.make_subgroup().await;
+
+        task_group.shutdown().await;
+        assert!(task_group.make_handle().is_shutting_down());
+        assert!(subgroup.make_handle().is_shutting_down());
+        assert!(nested.make_handle().is_shutting_down());
+    }
+
+    #[tokio::test]
+    async fn subgroup_shutdown_leaves_parent_running() {
+        let task_group = TaskGroup::new();
+        let subgroup = task_group.make_subgroup().await;
+
+        subgroup.shutdown().await;
+        assert!(subgroup.make_handle().is_shutting_down());
+        assert!(!task_group.make_handle().is_shutting_down());
+    }
+
+    #[tokio::test]
+    async fn supervised_task_is_restarted_after_panic() {
+        let mut task_group = TaskGroup::new();
+        let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+        let (done_tx, mut done_rx) = tokio::sync::mpsc::unbounded_channel::<()>();
+        task_group
+            .spawn_supervised("flaky", {
+                let attempts = attempts.clone();
+                move |_handle| {
+                    let attempts = attempts.clone();
+                    let done_tx = done_tx.clone();
+                    async move {
+                        if attempts.fetch_add(1, SeqCst) == 0 {
+                            panic!("first attempt fails");
+                        }
+                        let _ = done_tx.send(());
+                    }
+                }
+            })
+            .await;
+
+        timeout(Duration::from_secs(10), done_rx.recv())
+            .await
+            .expect("task was restarted");
+        assert_eq!(attempts.load(SeqCst), 2);
+
+        let health = task_group.task_health().remove("flaky").expect("reported");
+        assert_eq!(health.restarts, 1);
+        assert_eq!(health.last_panic.as_deref(), Some("first attempt fails"));
+        // A panic in a supervised task doesn't shut down the group
+        assert!(!task_group.make_handle().is_shutting_down());
+        task_group.shutdown_join_all(None).await.unwrap();
+    }
+}
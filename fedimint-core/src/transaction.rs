@@ -6,7 +6,10 @@ use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::module::SerdeModuleEncoding;
 use fedimint_core::{Amount, TransactionId};
 use rand::Rng;
-use secp256k1_zkp::{schnorr, Secp256k1, Signing, Verification};
+use secp256k1_zkp::{
+    schnorr, MusigAggNonce, MusigPartialSignature, MusigPubNonce, MusigSecNonce, Secp256k1,
+    Signing, Verification,
+};
 use thiserror::Error;
 
 /// An atomic value transfer operation within the Fedimint system and consensus
@@ -161,6 +164,260 @@ where
     session.partial_sig_agg(&partial_sigs)
 }
 
+/// A transaction built and signed by several parties, e.g. users that split
+/// the funding of a peg-out
+///
+/// Parties pass it around encoded, adding their inputs and outputs first. Once
+/// nobody adds anything anymore it is signed in two MuSig2 rounds: every party
+/// adds the nonces of its input keys and, once all nonces are known, its
+/// partial signatures. Anyone can then finalize and submit the transaction.
+#[derive(Debug, Clone, Default, Encodable, Decodable)]
+pub struct PartiallySignedTransaction {
+    pub inputs: Vec<DynInput>,
+    pub outputs: Vec<DynOutput>,
+    /// The keys of all inputs in order, each of them has to sign
+    pub signers: Vec<PsftSigner>,
+}
+
+/// A key that has to sign a [`PartiallySignedTransaction`] and its progress
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct PsftSigner {
+    pub key: XOnlyPublicKey,
+    pub nonce: Option<MusigPubNonce>,
+    pub partial_signature: Option<MusigPartialSignature>,
+}
+
+/// Secret nonces of the keys we sign a [`PartiallySignedTransaction`] with,
+/// they must only be used once and can't be serialized for that reason
+pub struct PsftSecretNonces {
+    txid: TransactionId,
+    nonces: Vec<(usize, MusigSecNonce)>,
+}
+
+impl std::fmt::Debug for PsftSecretNonces {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PsftSecretNonces")
+            .field("txid", &self.txid)
+            .field(
+                "positions",
+                &self.nonces.iter().map(|(p, _)| p).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl PartiallySignedTransaction {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an input that has to be signed by `keys`
+    pub fn add_input(
+        &mut self,
+        input: DynInput,
+        keys: impl IntoIterator<Item = XOnlyPublicKey>,
+    ) -> Result<usize, PsftError> {
+        self.ensure_not_signing()?;
+        self.inputs.push(input);
+        self.signers.extend(keys.into_iter().map(|key| PsftSigner {
+            key,
+            nonce: None,
+            partial_signature: None,
+        }));
+        Ok(self.inputs.len() - 1)
+    }
+
+    pub fn add_output(&mut self, output: DynOutput) -> Result<usize, PsftError> {
+        self.ensure_not_signing()?;
+        self.outputs.push(output);
+        Ok(self.outputs.len() - 1)
+    }
+
+    pub fn tx_hash(&self) -> TransactionId {
+        Transaction::tx_hash_from_parts(&self.inputs, &self.outputs)
+    }
+
+    /// Whether signing started, after which the transaction can't change
+    /// anymore
+    pub fn is_signing(&self) -> bool {
+        self.signers.iter().any(|signer| signer.nonce.is_some())
+    }
+
+    /// First signing round, adds the public nonces of `keys` and returns the
+    /// secret ones needed for the second round
+    pub fn add_nonces<R>(
+        &mut self,
+        keys: &[bitcoin::KeyPair],
+        mut rng: R,
+    ) -> Result<PsftSecretNonces, PsftError>
+    where
+        R: rand::RngCore + rand::CryptoRng,
+    {
+        let ctx = secp256k1_zkp::SECP256K1;
+        let txid = self.tx_hash();
+        let msg = secp256k1_zkp::Message::from_slice(&txid[..]).expect("hash has right length");
+        let pre_session = new_pre_session(&self.signer_keys()?, ctx);
+
+        let mut nonces = vec![];
+        for key in keys {
+            let positions = self.positions_of(&key.x_only_public_key().0);
+            if positions.is_empty() {
+                return Err(PsftError::UnknownKey(key.x_only_public_key().0));
+            }
+            for position in positions {
+                if self.signers[position].nonce.is_some() {
+                    return Err(PsftError::AlreadySigned(self.signers[position].key));
+                }
+                let session_id: [u8; 32] = rng.gen();
+                let (sec_nonce, pub_nonce) = pre_session
+                    .nonce_gen(ctx, session_id, key.into(), msg, None)
+                    .expect("should not fail for valid inputs (ensured by type system)");
+                self.signers[position].nonce = Some(pub_nonce);
+                nonces.push((position, sec_nonce));
+            }
+        }
+
+        Ok(PsftSecretNonces { txid, nonces })
+    }
+
+    /// Second signing round, adds our partial signatures once all nonces are
+    /// known
+    pub fn add_partial_signatures(
+        &mut self,
+        keys: &[bitcoin::KeyPair],
+        secret_nonces: PsftSecretNonces,
+    ) -> Result<(), PsftError> {
+        if self.tx_hash() != secret_nonces.txid {
+            return Err(PsftError::TransactionChanged);
+        }
+        let ctx = secp256k1_zkp::SECP256K1;
+        let msg = secp256k1_zkp::Message::from_slice(&secret_nonces.txid[..])
+            .expect("hash has right length");
+        let pre_session = new_pre_session(&self.signer_keys()?, ctx);
+        let session =
+            secp256k1_zkp::MusigSession::new(ctx, &pre_session, self.agg_nonce()?, msg, None);
+
+        for (position, mut sec_nonce) in secret_nonces.nonces {
+            let signer_key = self.signers[position].key;
+            let key = keys
+                .iter()
+                .find(|key| key.x_only_public_key().0 == signer_key)
+                .ok_or(PsftError::UnknownKey(signer_key))?;
+            let partial_signature = session
+                .partial_sign(ctx, &mut sec_nonce, key, &pre_session)
+                .expect("Should not fail for cooperative protocol runs");
+            self.signers[position].partial_signature = Some(partial_signature);
+        }
+
+        Ok(())
+    }
+
+    /// Merges the nonces and partial signatures of a copy of the same
+    /// transaction that other parties worked on
+    pub fn combine(&mut self, other: PartiallySignedTransaction) -> Result<(), PsftError> {
+        if self.tx_hash() != other.tx_hash() || self.signer_keys()? != other.signer_keys()? {
+            return Err(PsftError::TransactionChanged);
+        }
+        for (signer, other) in self.signers.iter_mut().zip(other.signers) {
+            if signer.nonce.is_none() {
+                signer.nonce = other.nonce;
+            }
+            if signer.partial_signature.is_none() {
+                signer.partial_signature = other.partial_signature;
+            }
+        }
+        Ok(())
+    }
+
+    /// Aggregates the partial signatures into the transaction to submit
+    pub fn finalize(self) -> Result<Transaction, PsftError> {
+        let signature = if self.signers.is_empty() {
+            None
+        } else {
+            let ctx = secp256k1_zkp::SECP256K1;
+            let msg = secp256k1_zkp::Message::from_slice(&self.tx_hash()[..])
+                .expect("hash has right length");
+            let pre_session = new_pre_session(&self.signer_keys()?, ctx);
+            let session =
+                secp256k1_zkp::MusigSession::new(ctx, &pre_session, self.agg_nonce()?, msg, None);
+            let partial_signatures = self
+                .signers
+                .iter()
+                .map(|signer| {
+                    signer
+                        .partial_signature
+                        .as_ref()
+                        .cloned()
+                        .ok_or(PsftError::MissingPartialSignature(signer.key))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Some(session.partial_sig_agg(&partial_signatures))
+        };
+
+        Ok(Transaction {
+            inputs: self.inputs,
+            outputs: self.outputs,
+            signature,
+        })
+    }
+
+    fn ensure_not_signing(&self) -> Result<(), PsftError> {
+        if self.is_signing() {
+            return Err(PsftError::SigningStarted);
+        }
+        Ok(())
+    }
+
+    fn signer_keys(&self) -> Result<Vec<XOnlyPublicKey>, PsftError> {
+        if self.signers.is_empty() {
+            return Err(PsftError::NothingToSign);
+        }
+        Ok(self.signers.iter().map(|signer| signer.key).collect())
+    }
+
+    fn positions_of(&self, key: &XOnlyPublicKey) -> Vec<usize> {
+        self.signers
+            .iter()
+            .enumerate()
+            .filter(|(_, signer)| signer.key == *key)
+            .map(|(position, _)| position)
+            .collect()
+    }
+
+    fn agg_nonce(&self) -> Result<MusigAggNonce, PsftError> {
+        let nonces = self
+            .signers
+            .iter()
+            .map(|signer| {
+                signer
+                    .nonce
+                    .as_ref()
+                    .cloned()
+                    .ok_or(PsftError::MissingNonce(signer.key))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(MusigAggNonce::new(secp256k1_zkp::SECP256K1, &nonces))
+    }
+}
+
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum PsftError {
+    #[error("Signing started, inputs and outputs can't be added anymore")]
+    SigningStarted,
+    #[error("The transaction changed since it was signed")]
+    TransactionChanged,
+    #[error("The transaction has no inputs that need to be signed")]
+    NothingToSign,
+    #[error("Key {0} doesn't sign any input")]
+    UnknownKey(XOnlyPublicKey),
+    #[error("Key {0} already signed")]
+    AlreadySigned(XOnlyPublicKey),
+    #[error("Key {0} didn't add its nonce yet")]
+    MissingNonce(XOnlyPublicKey),
+    #[error("Key {0} didn't sign yet")]
+    MissingPartialSignature(XOnlyPublicKey),
+}
+
 #[derive(Debug, Error)]
 pub enum TransactionError {
     #[error("The transaction is unbalanced (in={inputs}, out={outputs}, fee={fee})")]
@@ -179,3 +436,57 @@ pub enum TransactionError {
     #[error("The transaction did not have a signature although there were inputs to be signed")]
     MissingSignature,
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::thread_rng;
+
+    use super::*;
+
+    #[test]
+    fn partially_signed_transaction_signed_by_two_parties() {
+        let ctx = secp256k1_zkp::global::SECP256K1;
+        let alice = [
+            bitcoin::KeyPair::new(ctx, &mut thread_rng()),
+            bitcoin::KeyPair::new(ctx, &mut thread_rng()),
+        ];
+        let bob = [bitcoin::KeyPair::new(ctx, &mut thread_rng())];
+
+        let mut psft = PartiallySignedTransaction::new();
+        psft.signers = alice
+            .iter()
+            .chain(&bob)
+            .map(|key| PsftSigner {
+                key: key.x_only_public_key().0,
+                nonce: None,
+                partial_signature: None,
+            })
+            .collect();
+
+        let mut bob_psft = psft.clone();
+        let alice_nonces = psft.add_nonces(&alice, thread_rng()).unwrap();
+        let bob_nonces = bob_psft.add_nonces(&bob, thread_rng()).unwrap();
+        assert_eq!(
+            bob_psft.add_nonces(&bob, thread_rng()).unwrap_err(),
+            PsftError::AlreadySigned(bob[0].x_only_public_key().0)
+        );
+        psft.combine(bob_psft).unwrap();
+
+        let mut bob_psft = psft.clone();
+        psft.add_partial_signatures(&alice, alice_nonces).unwrap();
+        assert!(matches!(
+            psft.clone().finalize(),
+            Err(PsftError::MissingPartialSignature(_))
+        ));
+        bob_psft.add_partial_signatures(&bob, bob_nonces).unwrap();
+        psft.combine(bob_psft).unwrap();
+
+        let keys = psft
+            .signers
+            .iter()
+            .map(|signer| signer.key)
+            .collect::<Vec<_>>();
+        let tx = psft.finalize().unwrap();
+        tx.validate_signature(keys.into_iter()).unwrap();
+    }
+}
@@ -6,24 +6,30 @@ use fedimint_client_legacy::db as ClientRange;
 use fedimint_client_legacy::ln::db as ClientLightningRange;
 use fedimint_client_legacy::mint::db as ClientMintRange;
 use fedimint_client_legacy::wallet::db as ClientWalletRange;
+use fedimint_core::admin_client::PeerScore;
 use fedimint_core::config::ServerModuleGenRegistry;
 use fedimint_core::db::notifications::Notifications;
-use fedimint_core::db::{DatabaseTransaction, DatabaseVersionKey, SingleUseDatabaseTransaction};
+use fedimint_core::db::{
+    DatabaseTransaction, DatabaseVersionKey, ISingleUseDatabaseTransaction,
+    SingleUseDatabaseTransaction,
+};
 use fedimint_core::encoding::Encodable;
-use fedimint_core::module::DynServerModuleGen;
+use fedimint_core::epoch::ModuleAddition;
 use fedimint_core::module::__reexports::serde_json;
 use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::{push_db_key_items, push_db_pair_items, push_db_pair_items_no_serde};
-use fedimint_ln_server::LightningGen;
-use fedimint_mint_server::MintGen;
 use fedimint_rocksdb::RocksDbReadOnly;
 use fedimint_server::config::io::read_server_config;
 use fedimint_server::config::ServerConfig;
 use fedimint_server::db as ConsensusRange;
-use fedimint_wallet_server::WalletGen;
+use fedimint_server::encrypted_db::{
+    database_encryption_key, encrypted_transaction, is_database_encrypted,
+};
 use futures::StreamExt;
 use strum::IntoEnumIterator;
 
+use crate::server_module_inits;
+
 #[derive(Debug, serde::Serialize)]
 struct SerdeWrapper(#[serde(with = "hex::serde")] Vec<u8>);
 
@@ -55,6 +61,7 @@ impl<'a> DatabaseDump<'a> {
         modules: Vec<String>,
         prefixes: Vec<String>,
     ) -> DatabaseDump<'a> {
+        let db_path = PathBuf::from(&data_dir);
         let read_only = match RocksDbReadOnly::open_read_only(data_dir) {
             Ok(db) => db,
             Err(_) => {
@@ -81,15 +88,18 @@ impl<'a> DatabaseDump<'a> {
             };
         }
 
-        let module_inits = ServerModuleGenRegistry::from(vec![
-            DynServerModuleGen::from(WalletGen),
-            DynServerModuleGen::from(MintGen),
-            DynServerModuleGen::from(LightningGen),
-        ]);
+        let module_inits = server_module_inits();
 
         let cfg = read_server_config(&password, cfg_dir).unwrap();
         let decoders = module_inits.decoders(cfg.iter_module_instances()).unwrap();
-        let dbtx = DatabaseTransaction::new(Box::new(single_use), decoders, notifications);
+        let mut single_use: Box<dyn ISingleUseDatabaseTransaction<'a>> = Box::new(single_use);
+        let db_dir = db_path.parent().expect("Database path has no parent");
+        if is_database_encrypted(db_dir) {
+            let key = database_encryption_key(&password, db_dir)
+                .expect("Unable to decrypt the database encryption key");
+            single_use = encrypted_transaction(single_use, Box::leak(Box::new(key)));
+        }
+        let dbtx = DatabaseTransaction::new(single_use, decoders, notifications);
 
         DatabaseDump {
             serialized: BTreeMap::new(),
@@ -243,8 +253,123 @@ impl<'a> DatabaseDump<'a> {
                         "Client Config Download"
                     );
                 }
+                ConsensusRange::DbKeyPrefix::FederationMeta => {
+                    let meta = dbtx.get_value(&ConsensusRange::FederationMetaKey).await;
+                    if let Some(meta) = meta {
+                        consensus.insert("FederationMeta".to_string(), Box::new(meta));
+                    }
+                }
+                ConsensusRange::DbKeyPrefix::MetaVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::MetaVoteKeyPrefix,
+                        ConsensusRange::MetaVoteKey,
+                        BTreeMap<String, String>,
+                        consensus,
+                        "Meta Votes"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::ModuleAdditionVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::ModuleAdditionVoteKeyPrefix,
+                        ConsensusRange::ModuleAdditionVoteKey,
+                        ModuleAddition,
+                        consensus,
+                        "Module Addition Votes"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::ScheduledModuleAddition => {
+                    let addition = dbtx
+                        .get_value(&ConsensusRange::ScheduledModuleAdditionKey)
+                        .await;
+                    if let Some(addition) = addition {
+                        consensus.insert("ScheduledModuleAddition".to_string(), Box::new(addition));
+                    }
+                }
+                ConsensusRange::DbKeyPrefix::ModuleAdditionLocalParams => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::ModuleAdditionLocalParamsKeyPrefix,
+                        ConsensusRange::ModuleAdditionLocalParamsKey,
+                        String,
+                        consensus,
+                        "Module Addition Local Params"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::ModuleAdditionFailed => {
+                    push_db_key_items!(
+                        dbtx,
+                        ConsensusRange::ModuleAdditionFailedKeyPrefix,
+                        ConsensusRange::ModuleAdditionFailedKey,
+                        consensus,
+                        "Failed Module Additions"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::PendingEncryptedItem => {
+                    push_db_pair_items_no_serde!(
+                        dbtx,
+                        ConsensusRange::PendingEncryptedItemKeyPrefix,
+                        ConsensusRange::PendingEncryptedItemKey,
+                        fedimint_server::consensus::PendingEncryptedItem,
+                        consensus,
+                        "Pending Encrypted Items"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::RevealedEncryptedItem => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::RevealedEncryptedItemKeyPrefix,
+                        ConsensusRange::RevealedEncryptedItemKey,
+                        fedimint_core::PeerId,
+                        consensus,
+                        "Revealed Encrypted Items"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::DecryptionShare => {
+                    push_db_pair_items_no_serde!(
+                        dbtx,
+                        ConsensusRange::DecryptionShareKeyPrefix,
+                        ConsensusRange::DecryptionShareKey,
+                        fedimint_core::epoch::SerdeDecryptionShare,
+                        consensus,
+                        "Decryption Shares"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::PeerScore => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::PeerScoreKeyPrefix,
+                        ConsensusRange::PeerScoreKey,
+                        PeerScore,
+                        consensus,
+                        "Peer Scores"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::JournaledItem => {
+                    push_db_pair_items_no_serde!(
+                        dbtx,
+                        ConsensusRange::JournaledItemKeyPrefix,
+                        ConsensusRange::JournaledItemKey,
+                        fedimint_core::core::DynModuleConsensusItem,
+                        consensus,
+                        "Journaled Items"
+                    );
+                }
+                ConsensusRange::DbKeyPrefix::TransactionAmounts => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ConsensusRange::TransactionAmountsKeyPrefix,
+                        ConsensusRange::TransactionAmountsKey,
+                        fedimint_core::receipt::TransactionAmounts,
+                        consensus,
+                        "Transaction Amounts"
+                    );
+                }
                 // Module is a global prefix for all module data
                 ConsensusRange::DbKeyPrefix::Module => {}
+                // Only used to check the password of an encrypted database
+                ConsensusRange::DbKeyPrefix::EncryptedDatabaseCanary => {}
             }
         }
 
@@ -0,0 +1,288 @@
+#![allow(where_clauses_object_safety)] // https://github.com/dtolnay/async-trait/issues/228
+//! Library behind the `dbtool` binary, can be used to inspect and repair
+//! databases from other tools and tests.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use fedimint_core::config::ServerModuleGenRegistry;
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::db::notifications::Notifications;
+use fedimint_core::db::{DatabaseTransaction, IDatabase, MODULE_GLOBAL_PREFIX};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::module::DynServerModuleGen;
+use fedimint_ln_server::LightningGen;
+use fedimint_mint_server::MintGen;
+use fedimint_server::config::ServerConfig;
+use fedimint_server::encrypted_db::{
+    database_encryption_key, is_database_encrypted, EncryptedDatabase,
+};
+use fedimint_wallet_server::WalletGen;
+use futures::StreamExt;
+use serde::Serialize;
+
+pub mod dump;
+
+/// Module generators of all modules `dbtool` knows how to decode
+pub fn server_module_inits() -> ServerModuleGenRegistry {
+    ServerModuleGenRegistry::from(vec![
+        DynServerModuleGen::from(WalletGen),
+        DynServerModuleGen::from(MintGen),
+        DynServerModuleGen::from(LightningGen),
+    ])
+}
+
+/// Raw key-value pair read from the database
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DbEntry {
+    #[serde(with = "hex::serde")]
+    pub key: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub value: Vec<u8>,
+}
+
+/// Part of the database a key belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyOwner {
+    /// Key of the consensus, identified by its first byte
+    Consensus(u8),
+    /// Key inside the isolated database of a module instance
+    Module(ModuleInstanceId),
+}
+
+/// Low-level access to the raw entries of a database.
+///
+/// All modifying operations return the entries they affected. In dry-run mode
+/// they are computed the same way but the transaction is never committed, so
+/// the database stays untouched.
+pub struct DbTool {
+    db: Box<dyn IDatabase>,
+    dry_run: bool,
+}
+
+impl DbTool {
+    pub fn new(db: Box<dyn IDatabase>, dry_run: bool) -> Self {
+        DbTool { db, dry_run }
+    }
+
+    /// Opens the RocksDB database at `path`. If it's encrypted the values are
+    /// decrypted and encrypted with the key protected by `password`, so
+    /// rewritten entries stay readable.
+    pub async fn open(
+        path: impl AsRef<Path>,
+        password: Option<&str>,
+        dry_run: bool,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let db = fedimint_rocksdb::RocksDb::open(path).context("Error opening RocksDB")?;
+        let data_dir = path.parent().context("Database path has no parent")?;
+        if !is_database_encrypted(data_dir) {
+            return Ok(Self::new(Box::new(db), dry_run));
+        }
+
+        let Some(password) = password else {
+            bail!("The database is encrypted, a password is required")
+        };
+        let key = database_encryption_key(password, data_dir)?;
+        let db = EncryptedDatabase::open(db, key)
+            .await
+            .context("Unable to open the encrypted database, wrong password?")?;
+        Ok(Self::new(Box::new(db), dry_run))
+    }
+
+    /// Returns the prefix under which all keys of a module instance are stored
+    pub fn module_prefix(module_instance_id: ModuleInstanceId) -> Vec<u8> {
+        let mut prefix = vec![MODULE_GLOBAL_PREFIX];
+        module_instance_id
+            .consensus_encode(&mut prefix)
+            .expect("Write to vec can't fail");
+        prefix
+    }
+
+    /// Determines which part of the database `key` belongs to
+    pub fn key_owner(key: &[u8]) -> Option<KeyOwner> {
+        match key.split_first() {
+            Some((&MODULE_GLOBAL_PREFIX, mut rest)) => {
+                ModuleInstanceId::consensus_decode(&mut rest, &ModuleDecoderRegistry::default())
+                    .ok()
+                    .map(KeyOwner::Module)
+            }
+            Some((prefix, _)) => Some(KeyOwner::Consensus(*prefix)),
+            None => None,
+        }
+    }
+
+    /// Lists all entries where the key begins with `prefix`
+    pub async fn list(&self, prefix: &[u8]) -> Result<Vec<DbEntry>> {
+        let mut dbtx = self.db.begin_transaction().await;
+        let entries = dbtx
+            .raw_find_by_prefix(prefix)
+            .await?
+            .map(|(key, value)| DbEntry { key, value })
+            .collect::<Vec<_>>()
+            .await;
+        Ok(entries)
+    }
+
+    /// Writes `value` under `key`, returns the previous value if present
+    pub async fn write(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        let mut dbtx = self.db.begin_transaction().await;
+        let previous = dbtx.raw_insert_bytes(key, value).await?;
+        if !self.dry_run {
+            dbtx.commit_tx().await?;
+        }
+        Ok(previous)
+    }
+
+    /// Deletes the entry under `key`, returns its value if present
+    pub async fn delete(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let mut dbtx = self.db.begin_transaction().await;
+        let previous = dbtx.raw_remove_entry(key).await?;
+        if !self.dry_run {
+            dbtx.commit_tx().await?;
+        }
+        Ok(previous)
+    }
+
+    /// Deletes all entries where the key begins with `prefix`
+    pub async fn delete_prefix(&self, prefix: &[u8]) -> Result<Vec<DbEntry>> {
+        let entries = self.list(prefix).await?;
+
+        let mut dbtx = self.db.begin_transaction().await;
+        for entry in &entries {
+            dbtx.raw_remove_entry(&entry.key).await?;
+        }
+        if !self.dry_run {
+            dbtx.commit_tx().await?;
+        }
+        Ok(entries)
+    }
+
+    /// Moves all entries with keys beginning with `from` to keys beginning
+    /// with `to`, keeping the rest of the key. Returns the entries under their
+    /// new keys.
+    pub async fn rewrite_prefix(&self, from: &[u8], to: &[u8]) -> Result<Vec<DbEntry>> {
+        let entries = self.list(from).await?;
+
+        let mut dbtx = self.db.begin_transaction().await;
+        let mut rewritten = vec![];
+        for DbEntry { key, value } in entries {
+            let new_key = [to, &key[from.len()..]].concat();
+            if dbtx.raw_get_bytes(&new_key).await?.is_some() {
+                bail!(
+                    "Rewriting would overwrite existing key {}",
+                    hex::encode(&new_key)
+                );
+            }
+            dbtx.raw_remove_entry(&key).await?;
+            dbtx.raw_insert_bytes(&new_key, &value).await?;
+            rewritten.push(DbEntry {
+                key: new_key,
+                value,
+            });
+        }
+        if !self.dry_run {
+            dbtx.commit_tx().await?;
+        }
+        Ok(rewritten)
+    }
+
+    /// Decodes the entries of a module instance using the `dump_database`
+    /// hook of its module generator. If `prefix_names` isn't empty only the
+    /// given prefixes are decoded.
+    pub async fn decode_module(
+        &self,
+        cfg: &ServerConfig,
+        module_inits: &ServerModuleGenRegistry,
+        module_instance_id: ModuleInstanceId,
+        prefix_names: Vec<String>,
+    ) -> Result<BTreeMap<String, Box<dyn erased_serde::Serialize + Send>>> {
+        let Some(module_cfg) = cfg.consensus.modules.get(&module_instance_id) else {
+            bail!("No module instance with id {module_instance_id} in config")
+        };
+        let Some(init) = module_inits.get(&module_cfg.kind) else {
+            bail!("Unsupported module kind {}", module_cfg.kind)
+        };
+        let decoders = module_inits.decoders(cfg.iter_module_instances())?;
+
+        let notifications = Notifications::new();
+        let mut dbtx =
+            DatabaseTransaction::new(self.db.begin_transaction().await, decoders, &notifications);
+        let mut module_dbtx = dbtx.with_module_prefix(module_instance_id);
+        let decoded = init
+            .dump_database(&mut module_dbtx, prefix_names)
+            .await
+            .collect();
+        Ok(decoded)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::db::mem_impl::MemDatabase;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_dry_run_and_rewrite() {
+        let tool = DbTool::new(Box::new(MemDatabase::new()), false);
+        let module_prefix = DbTool::module_prefix(1);
+        let key = [module_prefix.as_slice(), &[0x01, 0x02]].concat();
+        tool.write(&key, &[42]).await.unwrap();
+        assert_eq!(DbTool::key_owner(&key), Some(KeyOwner::Module(1)));
+        assert_eq!(DbTool::key_owner(&[0x04]), Some(KeyOwner::Consensus(0x04)));
+
+        let dry_run = DbTool {
+            db: tool.db,
+            dry_run: true,
+        };
+        assert_eq!(
+            dry_run.delete_prefix(&module_prefix).await.unwrap().len(),
+            1
+        );
+        assert_eq!(dry_run.list(&module_prefix).await.unwrap().len(), 1);
+
+        let tool = DbTool::new(dry_run.db, false);
+        let new_prefix = DbTool::module_prefix(2);
+        let rewritten = tool
+            .rewrite_prefix(&module_prefix, &new_prefix)
+            .await
+            .unwrap();
+        assert_eq!(
+            rewritten[0].key,
+            [new_prefix.as_slice(), &[0x01, 0x02]].concat()
+        );
+        assert!(tool.list(&module_prefix).await.unwrap().is_empty());
+        assert_eq!(tool.list(&new_prefix).await.unwrap(), rewritten);
+    }
+
+    #[tokio::test]
+    async fn test_rewrite_encrypted_database() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let db_path = data_dir.path().join("database");
+        {
+            let key = database_encryption_key("pass", data_dir.path()).unwrap();
+            let rocksdb = fedimint_rocksdb::RocksDb::open(&db_path).unwrap();
+            let tool = DbTool::new(
+                Box::new(EncryptedDatabase::open(rocksdb, key).await.unwrap()),
+                false,
+            );
+            tool.write(&[0x01, 0x02], b"secret").await.unwrap();
+        }
+
+        assert!(DbTool::open(&db_path, None, false).await.is_err());
+        assert!(DbTool::open(&db_path, Some("wrong"), false).await.is_err());
+
+        let tool = DbTool::open(&db_path, Some("pass"), false).await.unwrap();
+        tool.rewrite_prefix(&[0x01], &[0x03]).await.unwrap();
+        assert_eq!(
+            tool.list(&[0x03]).await.unwrap(),
+            vec![DbEntry {
+                key: vec![0x03, 0x02],
+                value: b"secret".to_vec(),
+            }]
+        );
+    }
+}
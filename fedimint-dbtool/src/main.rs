@@ -1,22 +1,27 @@
 #![allow(where_clauses_object_safety)] // https://github.com/dtolnay/async-trait/issues/228
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use bitcoin_hashes::hex::ToHex;
 use bytes::Bytes;
 use clap::{Parser, Subcommand};
-use fedimint_core::db::IDatabase;
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_dbtool::dump::DatabaseDump;
+use fedimint_dbtool::{server_module_inits, DbEntry, DbTool};
 use fedimint_logging::TracingSetup;
-use futures::StreamExt;
-
-use crate::dump::DatabaseDump;
-
-mod dump;
+use fedimint_server::config::io::read_server_config;
 
 #[derive(Debug, Clone, Parser)]
 struct Options {
     #[clap(long)]
     database: String,
+    /// Only print what would be changed without modifying the database
+    #[clap(long)]
+    dry_run: bool,
+    /// Guardian password, needed to decrypt the server's configuration file
+    /// and an encrypted database
+    #[arg(long, env = "FM_PASSWORD", global = true)]
+    password: Option<String>,
     #[command(subcommand)]
     command: DbCommand,
 }
@@ -25,10 +30,14 @@ struct Options {
 /// (keys, values) have to be hex encoded.
 #[derive(Debug, Clone, Subcommand)]
 enum DbCommand {
-    /// List all key-value pairs where the key begins with `prefix`
+    /// List all key-value pairs where the key begins with `prefix`. If
+    /// `module` is given the prefix is relative to the module's isolated
+    /// database.
     List {
         #[arg(long, value_parser = hex_parser)]
         prefix: Bytes,
+        #[arg(long)]
+        module: Option<ModuleInstanceId>,
     },
     /// Write a key-value pair to the database, overwriting the previous value
     /// if present
@@ -43,16 +52,36 @@ enum DbCommand {
         #[arg(long, value_parser = hex_parser)]
         key: Bytes,
     },
+    /// Delete all entries where the key begins with `prefix`
+    DeletePrefix {
+        #[arg(long, value_parser = hex_parser)]
+        prefix: Bytes,
+    },
+    /// Move all entries where the key begins with `from` to keys beginning
+    /// with `to`
+    Rewrite {
+        #[arg(long, value_parser = hex_parser)]
+        from: Bytes,
+        #[arg(long, value_parser = hex_parser)]
+        to: Bytes,
+    },
+    /// Decode the entries of a single module instance to JSON using the
+    /// module's decoders. Requires the password.
+    Decode {
+        #[clap(long)]
+        cfg_dir: PathBuf,
+        #[arg(long)]
+        module: ModuleInstanceId,
+        #[arg(long, required = false)]
+        prefixes: Option<String>,
+    },
     /// Dump a subset of the specified database and serialize the retrieved data
     /// to JSON. Module and prefix are used to specify which subset of the
-    /// database to dump. Password is used to decrypt the server's
-    /// configuration file. If dumping the client database, the password can
-    /// be an arbitrary string.
+    /// database to dump. Requires the password, if dumping the client
+    /// database it can be an arbitrary string.
     Dump {
         #[clap(long)]
         cfg_dir: PathBuf,
-        #[arg(long, env = "FM_PASSWORD")]
-        password: String,
         #[arg(long, required = false)]
         modules: Option<String>,
         #[arg(long, required = false)]
@@ -69,68 +98,111 @@ fn print_kv(key: &[u8], value: &[u8]) {
     println!("{} {}", key.to_hex(), value.to_hex());
 }
 
+fn print_entries(entries: &[DbEntry]) {
+    for entry in entries {
+        print_kv(&entry.key, &entry.value);
+    }
+}
+
+fn split_list(list: Option<String>) -> Vec<String> {
+    match list {
+        Some(list) => list
+            .split(',')
+            .map(|s| s.to_string().to_lowercase())
+            .collect::<Vec<String>>(),
+        None => Vec::new(),
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     TracingSetup::default().init()?;
     let options: Options = Options::parse();
 
     match options.command {
-        DbCommand::List { prefix } => {
-            let rocksdb: Box<dyn IDatabase> =
-                Box::new(fedimint_rocksdb::RocksDb::open(&options.database).unwrap());
-            let mut dbtx = rocksdb.begin_transaction().await;
-            let prefix_iter = dbtx
-                .raw_find_by_prefix(&prefix)
-                .await?
-                .collect::<Vec<_>>()
-                .await;
-            for (key, value) in prefix_iter {
-                print_kv(&key, &value);
-            }
-            dbtx.commit_tx().await.expect("Error committing to RocksDb");
+        DbCommand::List { prefix, module } => {
+            let tool = DbTool::open(
+                &options.database,
+                options.password.as_deref(),
+                options.dry_run,
+            )
+            .await?;
+            let prefix = match module {
+                Some(module_instance_id) => [
+                    DbTool::module_prefix(module_instance_id).as_slice(),
+                    &prefix[..],
+                ]
+                .concat(),
+                None => prefix.to_vec(),
+            };
+            print_entries(&tool.list(&prefix).await?);
         }
         DbCommand::Write { key, value } => {
-            let rocksdb: Box<dyn IDatabase> =
-                Box::new(fedimint_rocksdb::RocksDb::open(&options.database).unwrap());
-            let mut dbtx = rocksdb.begin_transaction().await;
-            dbtx.raw_insert_bytes(&key, &value)
-                .await
-                .expect("Error inserting entry into RocksDb");
-            dbtx.commit_tx().await.expect("Error committing to RocksDb");
+            let tool = DbTool::open(
+                &options.database,
+                options.password.as_deref(),
+                options.dry_run,
+            )
+            .await?;
+            if let Some(previous) = tool.write(&key, &value).await? {
+                print_kv(&key, &previous);
+            }
         }
         DbCommand::Delete { key } => {
-            let rocksdb: Box<dyn IDatabase> =
-                Box::new(fedimint_rocksdb::RocksDb::open(&options.database).unwrap());
-            let mut dbtx = rocksdb.begin_transaction().await;
-            dbtx.raw_remove_entry(&key)
-                .await
-                .expect("Error removing entry from RocksDb");
-            dbtx.commit_tx().await.expect("Error committing to RocksDb");
+            let tool = DbTool::open(
+                &options.database,
+                options.password.as_deref(),
+                options.dry_run,
+            )
+            .await?;
+            if let Some(previous) = tool.delete(&key).await? {
+                print_kv(&key, &previous);
+            }
+        }
+        DbCommand::DeletePrefix { prefix } => {
+            let tool = DbTool::open(
+                &options.database,
+                options.password.as_deref(),
+                options.dry_run,
+            )
+            .await?;
+            print_entries(&tool.delete_prefix(&prefix).await?);
+        }
+        DbCommand::Rewrite { from, to } => {
+            let tool = DbTool::open(
+                &options.database,
+                options.password.as_deref(),
+                options.dry_run,
+            )
+            .await?;
+            print_entries(&tool.rewrite_prefix(&from, &to).await?);
+        }
+        DbCommand::Decode {
+            cfg_dir,
+            module,
+            prefixes,
+        } => {
+            let password = options.password.context("A password is required")?;
+            let tool = DbTool::open(&options.database, Some(&password), options.dry_run).await?;
+            let cfg = read_server_config(&password, cfg_dir)?;
+            let decoded = tool
+                .decode_module(&cfg, &server_module_inits(), module, split_list(prefixes))
+                .await?;
+            println!("{}", serde_json::to_string_pretty(&decoded)?);
         }
         DbCommand::Dump {
             cfg_dir,
             modules,
             prefixes,
-            password,
         } => {
-            let modules = match modules {
-                Some(mods) => mods
-                    .split(',')
-                    .map(|s| s.to_string().to_lowercase())
-                    .collect::<Vec<String>>(),
-                None => Vec::new(),
-            };
-
-            let prefix_names = match prefixes {
-                Some(db_prefixes) => db_prefixes
-                    .split(',')
-                    .map(|s| s.to_string().to_lowercase())
-                    .collect::<Vec<String>>(),
-                None => Vec::new(),
-            };
-
-            let mut dbdump =
-                DatabaseDump::new(cfg_dir, options.database, password, modules, prefix_names);
+            let password = options.password.context("A password is required")?;
+            let mut dbdump = DatabaseDump::new(
+                cfg_dir,
+                options.database,
+                password,
+                split_list(modules),
+                split_list(prefixes),
+            );
             dbdump.dump_database().await;
         }
     }
@@ -0,0 +1,71 @@
+//! Golden files pinning the consensus encoding of the types federations agree
+//! on and persist.
+//!
+//! Every test vector is built from fixed seeds, encoded and compared against
+//! the hex in `vectors/<name>.hex`. The committed bytes are also decoded again,
+//! so a change to an `Encodable`/`Decodable` derive that would make peers or
+//! databases of different versions disagree fails CI instead of a federation.
+
+use std::fmt::Debug;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::{env, fs};
+
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use rand::SeedableRng;
+use rand_chacha::ChaCha20Rng;
+
+/// Set to overwrite the golden files after an intentional wire format change
+pub const REGENERATE_ENV: &str = "FM_REGENERATE_ENCODING_VECTORS";
+
+/// Directory the golden files are committed to
+pub fn vectors_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("vectors")
+}
+
+/// Deterministic rng so test vectors don't change between runs
+pub fn seeded_rng(seed: u64) -> ChaCha20Rng {
+    ChaCha20Rng::seed_from_u64(seed)
+}
+
+/// Checks `value` encodes to the golden file `name` and that its bytes decode
+/// back to `value`.
+///
+/// Like database snapshots, a missing golden file is written instead of
+/// compared against, it then has to be committed.
+pub fn check_encoding<T>(name: &str, value: &T, decoders: &ModuleDecoderRegistry)
+where
+    T: Encodable + Decodable + Eq + Debug,
+{
+    let bytes = value
+        .consensus_encode_to_vec()
+        .expect("writing to vec cannot fail");
+    let path = vectors_dir().join(format!("{name}.hex"));
+
+    let golden = if path.exists() && env::var_os(REGENERATE_ENV).is_none() {
+        let golden = fs::read_to_string(&path).expect("Failed to read golden file");
+        let golden = hex::decode(golden.trim()).expect("Golden file is not hex");
+        assert_eq!(
+            hex::encode(&golden),
+            hex::encode(&bytes),
+            "Consensus encoding of {name} changed, if this is intentional run with {REGENERATE_ENV}=1"
+        );
+        golden
+    } else {
+        fs::create_dir_all(vectors_dir()).expect("Failed to create vectors dir");
+        fs::write(&path, format!("{}\n", hex::encode(&bytes)))
+            .expect("Failed to write golden file");
+        bytes
+    };
+
+    let mut cursor = Cursor::new(&golden);
+    let decoded = T::consensus_decode(&mut cursor, decoders)
+        .unwrap_or_else(|e| panic!("Failed to decode golden file of {name}: {e:?}"));
+    assert_eq!(&decoded, value, "Golden file of {name} decodes differently");
+    assert_eq!(
+        cursor.position(),
+        golden.len() as u64,
+        "Decoding {name} didn't consume all bytes"
+    );
+}
@@ -0,0 +1,331 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use bitcoin::hashes::{sha256, Hash};
+use fedimint_core::core::{
+    IntoDynInstance, LEGACY_HARDCODED_INSTANCE_ID_LN, LEGACY_HARDCODED_INSTANCE_ID_MINT,
+    LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+};
+use fedimint_core::epoch::{
+    ConsensusItem, EpochOutcome, MetaUpdate, SerdeSignature, SerdeSignatureShare,
+    SignedEpochOutcome,
+};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::module::CommonModuleGen;
+use fedimint_core::transaction::Transaction;
+use fedimint_core::{Amount, Feerate, OutPoint, PeerId, TieredMulti, TransactionId};
+use fedimint_encoding_tests::{check_encoding, seeded_rng};
+use fedimint_ln_common::contracts::incoming::IncomingContractOffer;
+use fedimint_ln_common::contracts::{
+    ContractId, EncryptedPreimage, Preimage, PreimageDecryptionShare,
+};
+use fedimint_ln_common::{
+    LightningCommonGen, LightningConsensusItem, LightningInput, LightningOutput,
+    LightningOutputOutcome,
+};
+use fedimint_mint_common::{
+    BlindNonce, MintCommonGen, MintConsensusItem, MintInput, MintOutput, MintOutputBlindSignatures,
+    MintOutputOutcome, MintOutputSignatureShare, MintSignatureItem, Nonce, Note,
+};
+use fedimint_wallet_common::{
+    PegOut, PegOutFees, PegOutSignatureItem, Rbf, RoundConsensusItem, WalletCommonGen,
+    WalletConsensusItem, WalletOutput, WalletOutputOutcome,
+};
+use rand_chacha::ChaCha20Rng;
+use secp256k1::{KeyPair, Message, Secp256k1};
+use threshold_crypto::SecretKeySet;
+
+fn decoders() -> ModuleDecoderRegistry {
+    ModuleDecoderRegistry::from_iter([
+        (
+            LEGACY_HARDCODED_INSTANCE_ID_LN,
+            LightningCommonGen::KIND,
+            LightningCommonGen::decoder(),
+        ),
+        (
+            LEGACY_HARDCODED_INSTANCE_ID_MINT,
+            MintCommonGen::KIND,
+            MintCommonGen::decoder(),
+        ),
+        (
+            LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+            WalletCommonGen::KIND,
+            WalletCommonGen::decoder(),
+        ),
+    ])
+}
+
+fn keypair(rng: &mut ChaCha20Rng) -> KeyPair {
+    KeyPair::new(&Secp256k1::new(), rng)
+}
+
+fn hash(data: &[u8]) -> sha256::Hash {
+    sha256::Hash::hash(data)
+}
+
+fn out_point() -> OutPoint {
+    OutPoint {
+        txid: TransactionId::from_inner([7; 32]),
+        out_idx: 3,
+    }
+}
+
+fn tbs_key(seed: u64) -> tbs::SecretKeyShare {
+    tbs::SecretKeyShare(tbs::Scalar::from(seed))
+}
+
+fn blind_nonce(rng: &mut ChaCha20Rng) -> BlindNonce {
+    let nonce = Nonce(keypair(rng).x_only_public_key().0);
+    BlindNonce(tbs::BlindedMessage(nonce.to_message().0))
+}
+
+fn mint_input(rng: &mut ChaCha20Rng) -> MintInput {
+    let notes = [1, 1, 1024]
+        .into_iter()
+        .map(|msats| {
+            let nonce = Nonce(keypair(rng).x_only_public_key().0);
+            let signature =
+                tbs::sign_blinded_msg(tbs::BlindedMessage(nonce.to_message().0), tbs_key(msats));
+            (
+                Amount::from_msats(msats),
+                Note(nonce, tbs::Signature(signature.0)),
+            )
+        })
+        .collect();
+    MintInput(notes, vec![], vec![])
+}
+
+fn mint_output(rng: &mut ChaCha20Rng) -> MintOutput {
+    MintOutput(
+        [(Amount::from_msats(2), blind_nonce(rng))]
+            .into_iter()
+            .collect(),
+    )
+}
+
+fn threshold_keys(rng: &mut ChaCha20Rng) -> SecretKeySet {
+    SecretKeySet::random(1, rng)
+}
+
+fn encrypted_preimage(rng: &mut ChaCha20Rng, keys: &SecretKeySet) -> EncryptedPreimage {
+    EncryptedPreimage(
+        keys.public_keys()
+            .public_key()
+            .encrypt_with_rng(rng, [9; 32]),
+    )
+}
+
+fn peg_out_fees() -> PegOutFees {
+    PegOutFees::new(2_000, 871)
+}
+
+fn wallet_output() -> WalletOutput {
+    WalletOutput::PegOut(PegOut {
+        recipient: bitcoin::Address::p2wsh(
+            &bitcoin::Script::new_op_return(b"golden"),
+            bitcoin::Network::Regtest,
+        ),
+        amount: bitcoin::Amount::from_sat(50_000),
+        fees: peg_out_fees(),
+    })
+}
+
+fn transaction(rng: &mut ChaCha20Rng) -> Transaction {
+    let key = keypair(rng);
+    let mut tx = Transaction {
+        inputs: vec![
+            mint_input(rng).into_dyn(LEGACY_HARDCODED_INSTANCE_ID_MINT),
+            LightningInput {
+                contract_id: ContractId::from_inner([4; 32]),
+                amount: Amount::from_sats(10),
+                witness: Some(Preimage([5; 32])),
+            }
+            .into_dyn(LEGACY_HARDCODED_INSTANCE_ID_LN),
+        ],
+        outputs: vec![
+            mint_output(rng).into_dyn(LEGACY_HARDCODED_INSTANCE_ID_MINT),
+            wallet_output().into_dyn(LEGACY_HARDCODED_INSTANCE_ID_WALLET),
+        ],
+        signature: None,
+    };
+    let message = Message::from_slice(&tx.tx_hash()[..]).expect("32 bytes");
+    tx.signature = Some(Secp256k1::new().sign_schnorr_no_aux_rand(&message, &key));
+    tx
+}
+
+fn consensus_items(rng: &mut ChaCha20Rng) -> Vec<ConsensusItem> {
+    let keys = threshold_keys(rng);
+    vec![
+        ConsensusItem::EpochOutcomeSignatureShare(SerdeSignatureShare(
+            keys.secret_key_share(0).sign([1; 32]),
+        )),
+        ConsensusItem::Transaction(transaction(rng)),
+        ConsensusItem::Module(
+            LightningConsensusItem::BlockHeight(800_000).into_dyn(LEGACY_HARDCODED_INSTANCE_ID_LN),
+        ),
+        ConsensusItem::MetaUpdate(MetaUpdate(BTreeMap::from([(
+            "federation_name".to_string(),
+            "golden".to_string(),
+        )]))),
+    ]
+}
+
+#[test]
+fn core_types() {
+    let decoders = decoders();
+    let mut rng = seeded_rng(0);
+
+    check_encoding("amount", &Amount::from_msats(1_234_567), &decoders);
+    check_encoding("out_point", &out_point(), &decoders);
+    check_encoding("peer_id", &PeerId::from(3), &decoders);
+    check_encoding(
+        "feerate",
+        &Feerate {
+            sats_per_kvb: 1_000,
+        },
+        &decoders,
+    );
+    check_encoding("transaction", &transaction(&mut rng), &decoders);
+}
+
+#[test]
+fn epoch_types() {
+    let decoders = decoders();
+    let mut rng = seeded_rng(1);
+    let keys = threshold_keys(&mut rng);
+
+    for (idx, item) in consensus_items(&mut rng).into_iter().enumerate() {
+        check_encoding(&format!("consensus_item_{idx}"), &item, &decoders);
+    }
+
+    let outcome = EpochOutcome {
+        epoch: 42,
+        last_hash: Some(hash(b"epoch 41")),
+        items: vec![(PeerId::from(0), consensus_items(&mut rng))],
+        rejected_txs: BTreeSet::from([TransactionId::from_inner([8; 32])]),
+    };
+    check_encoding("epoch_header", &outcome.header(), &decoders);
+    let epoch_hash = outcome.hash();
+    let signed = SignedEpochOutcome {
+        signature: Some(SerdeSignature(keys.secret_key().sign(epoch_hash))),
+        hash: epoch_hash,
+        outcome,
+    };
+    check_encoding("signed_epoch_outcome", &signed, &decoders);
+}
+
+#[test]
+fn mint_types() {
+    let decoders = decoders();
+    let mut rng = seeded_rng(2);
+
+    check_encoding("mint_input", &mint_input(&mut rng), &decoders);
+    check_encoding("mint_output", &mint_output(&mut rng), &decoders);
+
+    let blind_nonce = blind_nonce(&mut rng);
+    let share = tbs::sign_blinded_msg(blind_nonce.0, tbs_key(2));
+    check_encoding(
+        "mint_output_outcome",
+        &MintOutputOutcome(Some(MintOutputBlindSignatures(
+            [(Amount::from_msats(2), tbs::BlindedSignature(share.0))]
+                .into_iter()
+                .collect(),
+        ))),
+        &decoders,
+    );
+    check_encoding(
+        "mint_consensus_item",
+        &MintConsensusItem::PartialSignatures(MintSignatureItem {
+            out_point: out_point(),
+            signatures: MintOutputSignatureShare(TieredMulti::from_iter([(
+                Amount::from_msats(2),
+                (blind_nonce.0, share),
+            )])),
+        }),
+        &decoders,
+    );
+}
+
+#[test]
+fn lightning_types() {
+    let decoders = decoders();
+    let mut rng = seeded_rng(3);
+    let keys = threshold_keys(&mut rng);
+    let encrypted_preimage = encrypted_preimage(&mut rng, &keys);
+
+    check_encoding(
+        "ln_input",
+        &LightningInput {
+            contract_id: ContractId::from_inner([4; 32]),
+            amount: Amount::from_sats(10),
+            witness: Some(Preimage([5; 32])),
+        },
+        &decoders,
+    );
+    let offer = IncomingContractOffer {
+        amount: Amount::from_sats(21),
+        hash: hash(&[9; 32]),
+        encrypted_preimage: encrypted_preimage.clone(),
+        expiry_time: Some(1_700_000_000),
+    };
+    check_encoding(
+        "ln_output_outcome",
+        &LightningOutputOutcome::Offer { id: offer.id() },
+        &decoders,
+    );
+    check_encoding("ln_output", &LightningOutput::Offer(offer), &decoders);
+    check_encoding(
+        "ln_consensus_item",
+        &LightningConsensusItem::DecryptPreimage(
+            ContractId::from_inner([4; 32]),
+            PreimageDecryptionShare(
+                keys.secret_key_share(0)
+                    .decrypt_share_no_verify(&encrypted_preimage.0),
+            ),
+        ),
+        &decoders,
+    );
+}
+
+#[test]
+fn wallet_types() {
+    let decoders = decoders();
+    let mut rng = seeded_rng(4);
+    let secp = Secp256k1::new();
+    let txid = bitcoin::Txid::from_inner([6; 32]);
+
+    check_encoding("wallet_output", &wallet_output(), &decoders);
+    check_encoding(
+        "wallet_output_rbf",
+        &WalletOutput::Rbf(Rbf {
+            fees: peg_out_fees(),
+            txid,
+        }),
+        &decoders,
+    );
+    check_encoding(
+        "wallet_output_outcome",
+        &WalletOutputOutcome(txid),
+        &decoders,
+    );
+    check_encoding(
+        "wallet_consensus_item_round",
+        &WalletConsensusItem::RoundConsensus(RoundConsensusItem {
+            block_height: 800_000,
+            fee_rate: Feerate {
+                sats_per_kvb: 1_000,
+            },
+            randomness: [3; 32],
+        }),
+        &decoders,
+    );
+
+    let message = Message::from_slice(&[2; 32]).expect("32 bytes");
+    check_encoding(
+        "wallet_consensus_item_peg_out_signature",
+        &WalletConsensusItem::PegOutSignature(PegOutSignatureItem {
+            txid,
+            signature: vec![secp.sign_ecdsa(&message, &keypair(&mut rng).secret_key())],
+        }),
+        &decoders,
+    );
+}
@@ -0,0 +1,8 @@
+#![no_main]
+
+use fedimint_wallet_common::txoproof::PegInProof;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    fedimint_fuzz::fuzz_decode::<PegInProof>(data);
+});
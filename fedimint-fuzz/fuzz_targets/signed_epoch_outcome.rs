@@ -0,0 +1,8 @@
+#![no_main]
+
+use fedimint_core::epoch::SignedEpochOutcome;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    fedimint_fuzz::fuzz_decode::<SignedEpochOutcome>(data);
+});
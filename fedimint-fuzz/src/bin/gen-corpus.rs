@@ -0,0 +1,101 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use bitcoin_hashes::hex::ToHex;
+use bitcoin_hashes::{sha256, Hash};
+use clap::Parser;
+use fedimint_core::db::Database;
+use fedimint_core::encoding::Encodable;
+use fedimint_core::epoch::ConsensusItem;
+use fedimint_fuzz::decoders;
+use fedimint_ln_common::LightningConsensusItem;
+use fedimint_mint_common::MintConsensusItem;
+use fedimint_rocksdb::RocksDb;
+use fedimint_server::config::io::DB_FILE;
+use fedimint_server::db::EpochHistoryKeyPrefix;
+use fedimint_wallet_common::{WalletConsensusItem, WalletInput};
+use futures::StreamExt;
+
+/// Seeds the fuzzing corpus with the consensus items a federation agreed on
+///
+/// Point it at the server data dirs of a stopped devimint federation, e.g.
+/// `$FM_DATA_DIR/server-0`. Databases encrypted at rest are not supported.
+#[derive(Debug, Parser)]
+struct Options {
+    /// Directory containing one corpus directory per fuzz target
+    #[arg(long, default_value = "corpus")]
+    corpus: PathBuf,
+    /// Server data directories to read the epoch history from
+    #[arg(required = true)]
+    data_dirs: Vec<PathBuf>,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let opts = Options::parse();
+
+    for data_dir in opts.data_dirs {
+        let rocksdb = RocksDb::open(data_dir.join(DB_FILE))
+            .with_context(|| format!("Error opening DB in {data_dir:?}"))?;
+        let db = Database::new(rocksdb, decoders());
+        let mut dbtx = db.begin_transaction().await;
+
+        let epochs = dbtx
+            .find_by_prefix(&EpochHistoryKeyPrefix)
+            .await
+            .map(|(_, epoch)| epoch)
+            .collect::<Vec<_>>()
+            .await;
+
+        for epoch in epochs {
+            write_seed(&opts.corpus, "signed_epoch_outcome", &epoch)?;
+
+            for (_, items) in &epoch.outcome.items {
+                for item in items {
+                    write_seed(&opts.corpus, "consensus_item", item)?;
+                    write_item_seeds(&opts.corpus, item)?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes the seeds for the fuzz targets of the types contained in `item`
+fn write_item_seeds(corpus: &Path, item: &ConsensusItem) -> anyhow::Result<()> {
+    match item {
+        ConsensusItem::Transaction(tx) => {
+            write_seed(corpus, "transaction", tx)?;
+
+            for input in &tx.inputs {
+                if let Some(WalletInput(proof)) = input.as_any().downcast_ref::<WalletInput>() {
+                    write_seed(corpus, "peg_in_proof", proof.as_ref())?;
+                }
+            }
+        }
+        ConsensusItem::Module(item) => {
+            let item = item.as_any();
+            if let Some(item) = item.downcast_ref::<WalletConsensusItem>() {
+                write_seed(corpus, "wallet_consensus_item", item)?;
+            } else if let Some(item) = item.downcast_ref::<MintConsensusItem>() {
+                write_seed(corpus, "mint_consensus_item", item)?;
+            } else if let Some(item) = item.downcast_ref::<LightningConsensusItem>() {
+                write_seed(corpus, "ln_consensus_item", item)?;
+            }
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Stores the encoding of `value` named by its hash, so repeated items are
+/// only written once
+fn write_seed(corpus: &Path, target: &str, value: &impl Encodable) -> anyhow::Result<()> {
+    let bytes = value.consensus_encode_to_vec()?;
+    let dir = corpus.join(target);
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(dir.join(sha256::Hash::hash(&bytes).to_hex()), bytes)?;
+    Ok(())
+}
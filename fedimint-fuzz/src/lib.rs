@@ -0,0 +1,62 @@
+//! Shared code of the fuzz targets
+//!
+//! Every target feeds arbitrary bytes to the `Decodable` implementation of a
+//! type that peers send us as part of consensus. Decoding may fail, but it
+//! must never panic, and whatever decodes successfully has to re-encode to a
+//! stable representation.
+
+use std::io::Cursor;
+
+use fedimint_core::core::{
+    LEGACY_HARDCODED_INSTANCE_ID_LN, LEGACY_HARDCODED_INSTANCE_ID_MINT,
+    LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::module::CommonModuleGen;
+use fedimint_ln_common::LightningCommonGen;
+use fedimint_mint_common::MintCommonGen;
+use fedimint_wallet_common::WalletCommonGen;
+
+/// Decoders for the module instances of a federation set up by devimint, so
+/// the corpus generated from its databases decodes
+pub fn decoders() -> ModuleDecoderRegistry {
+    ModuleDecoderRegistry::from_iter([
+        (
+            LEGACY_HARDCODED_INSTANCE_ID_LN,
+            LightningCommonGen::KIND,
+            LightningCommonGen::decoder(),
+        ),
+        (
+            LEGACY_HARDCODED_INSTANCE_ID_MINT,
+            MintCommonGen::KIND,
+            MintCommonGen::decoder(),
+        ),
+        (
+            LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+            WalletCommonGen::KIND,
+            WalletCommonGen::decoder(),
+        ),
+    ])
+}
+
+/// Decodes `data` as `T` and checks that a successfully decoded value
+/// round-trips through its encoding
+pub fn fuzz_decode<T: Encodable + Decodable>(data: &[u8]) {
+    let decoders = decoders();
+    let value = match T::consensus_decode(&mut Cursor::new(data), &decoders) {
+        Ok(value) => value,
+        Err(_) => return,
+    };
+
+    let encoded = value
+        .consensus_encode_to_vec()
+        .expect("Encoding to vec can't fail");
+    let decoded = T::consensus_decode(&mut Cursor::new(&encoded), &decoders)
+        .expect("Re-encoded value has to decode");
+    let reencoded = decoded
+        .consensus_encode_to_vec()
+        .expect("Encoding to vec can't fail");
+
+    assert_eq!(encoded, reencoded, "Encoding is not stable");
+}
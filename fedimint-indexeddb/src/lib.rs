@@ -0,0 +1,231 @@
+//! IndexedDB-backed database for clients running in the browser
+//!
+//! IndexedDB transactions are committed as soon as they have no pending
+//! requests, so they can't stay open across the arbitrary awaits of our
+//! database transactions. Instead the whole database is kept in memory,
+//! transactions are checked optimistically for write conflicts like in RocksDB
+//! and every commit is written through to IndexedDB before it becomes visible.
+#![cfg(target_family = "wasm")]
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, bail, Result};
+use fedimint_core::db::{
+    IDatabase, IDatabaseTransaction, ISingleUseDatabaseTransaction, PrefixStream,
+    SingleUseDatabaseTransaction,
+};
+use fedimint_core::{apply, async_trait_maybe_send};
+use futures::stream;
+use js_sys::Uint8Array;
+use rexie::{ObjectStore, Rexie, TransactionMode};
+use wasm_bindgen::JsValue;
+
+const STORE_NAME: &str = "kv";
+
+pub struct IndexedDb {
+    name: String,
+    rexie: Rexie,
+    state: Mutex<DbState>,
+    /// Serializes commits, so they reach IndexedDB in the order they become
+    /// visible in memory
+    commit_lock: futures::lock::Mutex<()>,
+}
+
+#[derive(Default)]
+struct DbState {
+    data: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Incremented with every commit
+    version: u64,
+    /// Version of the commit that last wrote each key
+    key_versions: BTreeMap<Vec<u8>, u64>,
+}
+
+impl fmt::Debug for IndexedDb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IndexedDb")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+impl IndexedDb {
+    /// Opens the IndexedDB database `name`, creating it if it doesn't exist
+    /// yet, and loads it into memory
+    pub async fn open(name: &str) -> Result<IndexedDb> {
+        let rexie = Rexie::builder(name)
+            .version(1)
+            .add_object_store(ObjectStore::new(STORE_NAME))
+            .build()
+            .await
+            .map_err(idb_error)?;
+
+        let transaction = rexie
+            .transaction(&[STORE_NAME], TransactionMode::ReadOnly)
+            .map_err(idb_error)?;
+        let data = transaction
+            .store(STORE_NAME)
+            .map_err(idb_error)?
+            .get_all(None, None, None, None)
+            .await
+            .map_err(idb_error)?
+            .into_iter()
+            .map(|(key, value)| (to_bytes(&key), to_bytes(&value)))
+            .collect();
+        transaction.done().await.map_err(idb_error)?;
+
+        Ok(IndexedDb {
+            name: name.to_owned(),
+            rexie,
+            state: Mutex::new(DbState {
+                data,
+                ..Default::default()
+            }),
+            commit_lock: Default::default(),
+        })
+    }
+
+    async fn persist(&self, writes: &BTreeMap<Vec<u8>, Option<Vec<u8>>>) -> Result<()> {
+        let transaction = self
+            .rexie
+            .transaction(&[STORE_NAME], TransactionMode::ReadWrite)
+            .map_err(idb_error)?;
+        let store = transaction.store(STORE_NAME).map_err(idb_error)?;
+        for (key, value) in writes {
+            let key = JsValue::from(Uint8Array::from(key.as_slice()));
+            match value {
+                Some(value) => {
+                    let value = JsValue::from(Uint8Array::from(value.as_slice()));
+                    store.put(&value, Some(&key)).await.map_err(idb_error)?;
+                }
+                None => store.delete(&key).await.map_err(idb_error)?,
+            }
+        }
+        transaction.done().await.map_err(idb_error)
+    }
+}
+
+fn to_bytes(value: &JsValue) -> Vec<u8> {
+    // Binary keys come back as `ArrayBuffer`s and values as `Uint8Array`s,
+    // both of which can be viewed as an `Uint8Array`
+    Uint8Array::new(value).to_vec()
+}
+
+fn idb_error(error: rexie::Error) -> anyhow::Error {
+    anyhow!("IndexedDB error: {error}")
+}
+
+#[apply(async_trait_maybe_send!)]
+impl IDatabase for IndexedDb {
+    async fn begin_transaction<'a>(&'a self) -> Box<dyn ISingleUseDatabaseTransaction<'a>> {
+        let state = self.state.lock().unwrap();
+        let mut tx = IndexedDbTransaction {
+            db: self,
+            version: state.version,
+            tx_data: state.data.clone(),
+            writes: BTreeMap::new(),
+            savepoint: Default::default(),
+        };
+        drop(state);
+
+        tx.set_tx_savepoint().await;
+        Box::new(SingleUseDatabaseTransaction::new(tx))
+    }
+}
+
+pub struct IndexedDbTransaction<'a> {
+    db: &'a IndexedDb,
+    /// Version of the database the transaction started from
+    version: u64,
+    tx_data: BTreeMap<Vec<u8>, Vec<u8>>,
+    /// Pending writes, `None` for deletions
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    savepoint: (
+        BTreeMap<Vec<u8>, Vec<u8>>,
+        BTreeMap<Vec<u8>, Option<Vec<u8>>>,
+    ),
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<'a> IDatabaseTransaction<'a> for IndexedDbTransaction<'a> {
+    async fn raw_insert_bytes(&mut self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.writes.insert(key.to_vec(), Some(value.to_vec()));
+        Ok(self.tx_data.insert(key.to_vec(), value.to_vec()))
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        Ok(self.tx_data.get(key).cloned())
+    }
+
+    async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        self.writes.insert(key.to_vec(), None);
+        Ok(self.tx_data.remove(key))
+    }
+
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> Result<PrefixStream<'_>> {
+        let data = self
+            .tx_data
+            .range::<[u8], _>(key_prefix..)
+            .take_while(|(key, _)| key.starts_with(key_prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect::<Vec<_>>();
+        Ok(Box::pin(stream::iter(data)))
+    }
+
+    async fn raw_find_by_prefix_sorted_descending(
+        &mut self,
+        key_prefix: &[u8],
+    ) -> Result<PrefixStream<'_>> {
+        let data = self
+            .tx_data
+            .range::<[u8], _>(key_prefix..)
+            .take_while(|(key, _)| key.starts_with(key_prefix))
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .rev()
+            .collect::<Vec<_>>();
+        Ok(Box::pin(stream::iter(data)))
+    }
+
+    async fn commit_tx(self) -> Result<()> {
+        if self.writes.is_empty() {
+            return Ok(());
+        }
+
+        let _commit_guard = self.db.commit_lock.lock().await;
+        {
+            let state = self.db.state.lock().unwrap();
+            for key in self.writes.keys() {
+                if state
+                    .key_versions
+                    .get(key)
+                    .map_or(false, |version| self.version < *version)
+                {
+                    bail!("Write conflict on key {key:?}");
+                }
+            }
+        }
+
+        self.db.persist(&self.writes).await?;
+
+        let mut state = self.db.state.lock().unwrap();
+        state.version += 1;
+        let version = state.version;
+        for (key, value) in self.writes {
+            match value {
+                Some(value) => state.data.insert(key.clone(), value),
+                None => state.data.remove(&key),
+            };
+            state.key_versions.insert(key, version);
+        }
+        Ok(())
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) {
+        (self.tx_data, self.writes) = self.savepoint.clone();
+    }
+
+    async fn set_tx_savepoint(&mut self) {
+        self.savepoint = (self.tx_data.clone(), self.writes.clone());
+    }
+}
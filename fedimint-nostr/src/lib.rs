@@ -0,0 +1,275 @@
+//! Announcement and discovery of federations and gateways over Nostr
+//!
+//! Guardians and gateways publish their metadata as parameterized replaceable
+//! events (NIP-33) tagged with the federation id, so relays only keep the
+//! latest announcement of every author for each federation. Clients query
+//! relays for these events and only accept the ones with a valid signature,
+//! which lets users find federations and gateways without a central directory.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context};
+use fedimint_core::api::WsClientConnectInfo;
+use fedimint_core::config::FederationId;
+use fedimint_core::core::ModuleKind;
+use nostr_sdk::prelude::{
+    Client, Event, EventBuilder, EventId, Filter, Keys, Kind, SecretKey, Tag, XOnlyPublicKey,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use tracing::debug;
+use url::Url;
+
+/// Event kind of [`FederationAnnouncement`]s
+pub const FEDERATION_ANNOUNCEMENT_KIND: u64 = 38173;
+
+/// Event kind of [`GatewayAnnouncement`]s
+pub const GATEWAY_ANNOUNCEMENT_KIND: u64 = 38174;
+
+/// How often announcements are republished, in case relays dropped them
+pub const ANNOUNCEMENT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Metadata published as the content of an announcement event
+pub trait Announcement: Serialize + DeserializeOwned {
+    /// The event kind of this announcement
+    const KIND: u64;
+
+    /// The federation the announcement is about, used as the identifier of
+    /// the replaceable event
+    fn federation_id(&self) -> FederationId;
+
+    /// Checks that the announcement is consistent in itself
+    fn validate(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Published by guardians so users can find and join their federation
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FederationAnnouncement {
+    pub federation_id: FederationId,
+    /// Name of the federation from its meta config, if it set one
+    pub name: Option<String>,
+    /// Invite code to join the federation
+    pub connect_info: WsClientConnectInfo,
+    /// Kinds of the modules the federation runs
+    pub modules: BTreeSet<ModuleKind>,
+}
+
+impl Announcement for FederationAnnouncement {
+    const KIND: u64 = FEDERATION_ANNOUNCEMENT_KIND;
+
+    fn federation_id(&self) -> FederationId {
+        self.federation_id
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        ensure!(
+            self.connect_info.id == self.federation_id,
+            "Invite code belongs to a different federation"
+        );
+        Ok(())
+    }
+}
+
+/// Published by gateways for every federation they route payments for
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GatewayAnnouncement {
+    pub federation_id: FederationId,
+    /// Public URL of the gateway API
+    pub api: Url,
+    /// Hex encoded public key of the gateway's lightning node
+    pub lightning_pub_key: String,
+    pub fees: GatewayFees,
+    /// Kinds of the modules the gateway supports
+    pub modules: BTreeSet<ModuleKind>,
+}
+
+impl Announcement for GatewayAnnouncement {
+    const KIND: u64 = GATEWAY_ANNOUNCEMENT_KIND;
+
+    fn federation_id(&self) -> FederationId {
+        self.federation_id
+    }
+}
+
+/// Routing fees charged by a gateway
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GatewayFees {
+    pub base_msat: u32,
+    pub proportional_millionths: u32,
+}
+
+/// An announcement with a verified signature of its author
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Announced<A> {
+    pub author: XOnlyPublicKey,
+    /// Unix timestamp of the announcement in seconds
+    pub created_at: u64,
+    pub announcement: A,
+}
+
+/// Publishes signed announcements to a set of relays
+pub struct NostrAnnouncer {
+    keys: Keys,
+    client: Client,
+}
+
+impl NostrAnnouncer {
+    /// Connects to `relays`, signing announcements with `secret_key`
+    pub async fn new(secret_key: [u8; 32], relays: &[Url]) -> anyhow::Result<NostrAnnouncer> {
+        let keys = Keys::new(SecretKey::from_slice(&secret_key)?);
+        let client = connect(&keys, relays).await?;
+        Ok(NostrAnnouncer { keys, client })
+    }
+
+    /// The public key announcements are signed with
+    pub fn public_key(&self) -> XOnlyPublicKey {
+        self.keys.public_key()
+    }
+
+    /// Publishes `announcement`, replacing our previous announcement for the
+    /// same federation
+    pub async fn announce<A: Announcement>(&self, announcement: &A) -> anyhow::Result<EventId> {
+        let event = announcement_event(&self.keys, announcement)?;
+        Ok(self.client.send_event(event).await?)
+    }
+}
+
+/// Queries `relays` for announcements, optionally only the ones about
+/// `federation_id`, waiting at most `timeout` for their responses
+///
+/// Events with invalid signatures or content are skipped and only the latest
+/// announcement of every author for each federation is returned.
+pub async fn discover<A: Announcement>(
+    relays: &[Url],
+    federation_id: Option<FederationId>,
+    timeout: Duration,
+) -> anyhow::Result<Vec<Announced<A>>> {
+    // Reading doesn't require an identity
+    let client = connect(&Keys::generate(), relays).await?;
+    let mut filter = Filter::new().kind(Kind::Custom(A::KIND));
+    if let Some(federation_id) = federation_id {
+        filter = filter.identifier(federation_id.to_string());
+    }
+    let events = client.get_events_of(vec![filter], Some(timeout)).await;
+    if let Err(e) = client.disconnect().await {
+        debug!("Failed to disconnect from relays: {e}");
+    }
+
+    let mut latest = BTreeMap::<(XOnlyPublicKey, FederationId), Announced<A>>::new();
+    for event in events? {
+        let announced = match parse_announcement::<A>(&event) {
+            Ok(announced) => announced,
+            Err(e) => {
+                debug!(event = %event.id, "Ignoring invalid announcement: {e:#}");
+                continue;
+            }
+        };
+        let key = (announced.author, announced.announcement.federation_id());
+        if latest
+            .get(&key)
+            .map_or(true, |previous| previous.created_at < announced.created_at)
+        {
+            latest.insert(key, announced);
+        }
+    }
+    Ok(latest.into_values().collect())
+}
+
+async fn connect(keys: &Keys, relays: &[Url]) -> anyhow::Result<Client> {
+    ensure!(!relays.is_empty(), "No relays given");
+    let client = Client::new(keys);
+    for relay in relays {
+        client.add_relay(relay.as_str(), None).await?;
+    }
+    client.connect().await;
+    Ok(client)
+}
+
+fn announcement_event<A: Announcement>(keys: &Keys, announcement: &A) -> anyhow::Result<Event> {
+    let tags = [Tag::Identifier(announcement.federation_id().to_string())];
+    Ok(EventBuilder::new(
+        Kind::Custom(A::KIND),
+        serde_json::to_string(announcement)?,
+        &tags,
+    )
+    .to_event(keys)?)
+}
+
+/// Verifies the signature of `event` and decodes the announcement it contains
+pub fn parse_announcement<A: Announcement>(event: &Event) -> anyhow::Result<Announced<A>> {
+    ensure!(event.kind == Kind::Custom(A::KIND), "Unexpected event kind");
+    event.verify().context("Invalid signature")?;
+
+    let announcement: A = serde_json::from_str(&event.content)?;
+    let identifier = event.tags.iter().find_map(|tag| match tag {
+        Tag::Identifier(identifier) => Some(identifier),
+        _ => None,
+    });
+    match identifier {
+        Some(identifier) if *identifier == announcement.federation_id().to_string() => {}
+        Some(_) => bail!("Identifier doesn't match the federation id"),
+        None => bail!("Missing identifier"),
+    }
+    announcement.validate()?;
+
+    Ok(Announced {
+        author: event.pubkey,
+        created_at: event.created_at.as_u64(),
+        announcement,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::api::ClientConfigDownloadToken;
+
+    use super::*;
+
+    fn federation_id() -> FederationId {
+        FederationId(threshold_crypto::SecretKey::random().public_key())
+    }
+
+    fn announcement(federation_id: FederationId) -> FederationAnnouncement {
+        FederationAnnouncement {
+            federation_id,
+            name: Some("Test Federation".to_string()),
+            connect_info: WsClientConnectInfo::new(
+                "ws://127.0.0.1:8174".parse().unwrap(),
+                ClientConfigDownloadToken([0; 32]),
+                federation_id,
+            ),
+            modules: BTreeSet::from([ModuleKind::from_static_str("mint")]),
+        }
+    }
+
+    #[test]
+    fn announcements_are_verified() {
+        let keys = Keys::generate();
+        let announcement = announcement(federation_id());
+
+        let event = announcement_event(&keys, &announcement).unwrap();
+        let announced = parse_announcement::<FederationAnnouncement>(&event).unwrap();
+        assert_eq!(announced.author, keys.public_key());
+        assert_eq!(announced.announcement, announcement);
+
+        // Changing the content invalidates the signature
+        let mut tampered = event.clone();
+        tampered.content = tampered.content.replace("Test", "Fake");
+        assert!(parse_announcement::<FederationAnnouncement>(&tampered).is_err());
+
+        // Gateway announcements use a different kind
+        assert!(parse_announcement::<GatewayAnnouncement>(&event).is_err());
+    }
+
+    #[test]
+    fn invite_code_must_match_federation() {
+        let mut announcement = announcement(federation_id());
+        announcement.connect_info.id = federation_id();
+
+        let event = announcement_event(&Keys::generate(), &announcement).unwrap();
+        assert!(parse_announcement::<FederationAnnouncement>(&event).is_err());
+    }
+}
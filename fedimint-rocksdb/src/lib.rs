@@ -88,6 +88,12 @@ impl IDatabase for RocksDb {
         let single_use = SingleUseDatabaseTransaction::new(rocksdb_tx);
         Box::new(single_use)
     }
+
+    fn checkpoint(&self, backup_path: &Path) -> Result<()> {
+        let checkpoint = rocksdb::checkpoint::Checkpoint::new(&self.0)?;
+        checkpoint.create_checkpoint(backup_path)?;
+        Ok(())
+    }
 }
 
 #[async_trait]
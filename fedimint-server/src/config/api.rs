@@ -1,15 +1,17 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::net::SocketAddr;
 use std::path::PathBuf;
 use std::sync::{Mutex, MutexGuard};
 
 use async_trait::async_trait;
+use bitcoin_hashes::hex::ToHex;
 use bitcoin_hashes::sha256::HashEngine;
 use bitcoin_hashes::{sha256, Hash};
 use fedimint_aead::random_salt;
 use fedimint_core::admin_client::{
-    ConfigGenConnectionsRequest, ConfigGenParamsConsensus, ConfigGenParamsRequest,
-    ConfigGenParamsResponse, PeerServerParams, WsAdminClient,
+    serde_tls_cert, AddConfigGenPeerRequest, ConfigGenConnectionsRequest, ConfigGenParamsConsensus,
+    ConfigGenParamsRequest, ConfigGenParamsResponse, DkgProgress, DkgStage, PeerServerParams,
+    WsAdminClient, DKG_PROGRESS_NOTIFICATION, SUBSCRIBE_DKG_PROGRESS, UNSUBSCRIBE_DKG_PROGRESS,
 };
 use fedimint_core::api::{ServerStatus, StatusResponse};
 use fedimint_core::config::{
@@ -25,14 +27,28 @@ use fedimint_core::task::TaskGroup;
 use fedimint_core::util::write_new;
 use fedimint_core::PeerId;
 use itertools::Itertools;
+use jsonrpsee::types::error::CallError;
+use jsonrpsee::types::ErrorObject;
+use jsonrpsee::RpcModule;
+use rand::rngs::OsRng;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::Sender;
+use tokio::sync::watch;
 use tokio_rustls::rustls;
-use tracing::error;
+use tracing::{error, info, warn};
 use url::Url;
 
-use crate::config::io::{read_server_config, write_server_config, PLAINTEXT_PASSWORD, SALT_FILE};
-use crate::config::{gen_cert_and_key, ConfigGenParams, ServerConfig};
+use crate::config::io::{
+    dkg_checkpoint_exists, finish_config_update, read_dkg_checkpoint, read_server_config,
+    remove_dkg_checkpoint, write_dkg_checkpoint, write_server_config, PLAINTEXT_PASSWORD,
+    SALT_FILE,
+};
+use crate::config::{
+    gen_cert_and_key, serde_tls_key, set_dkg_stage, ConfigGenParams, DkgCache, ServerConfig,
+};
 use crate::db::ConsensusUpgradeKey;
+use crate::net::api::RpcHandlerCtx;
 use crate::net::peers::DelayCalculator;
 use crate::HasApiContext;
 
@@ -50,6 +66,8 @@ pub struct ConfigGenApi {
     config_generated_tx: Sender<ServerConfig>,
     /// Task group for running DKG
     task_group: TaskGroup,
+    /// Progress of the DKG, for streaming it to the UI
+    dkg_progress: watch::Sender<DkgProgress>,
 }
 
 impl ConfigGenApi {
@@ -66,14 +84,37 @@ impl ConfigGenApi {
             db,
             config_generated_tx,
             task_group: task_group.clone(),
+            dkg_progress: watch::channel(DkgProgress::default()).0,
         }
     }
 
     // Sets the auth and decryption key derived from the password
+    //
+    // If we crashed during DKG, restores its state from the checkpoint so it
+    // can be resumed with `restart_dkg`
     pub fn set_password(&self, auth: ApiAuth) -> ApiResult<()> {
         let mut state = self.require_status(ServerStatus::AwaitingPassword)?;
+        let checkpoint = if dkg_checkpoint_exists(&self.data_dir) {
+            let checkpoint = read_dkg_checkpoint(&auth.0, self.data_dir.clone()).map_err(|_| {
+                ApiError::bad_request(
+                    "Unable to decrypt DKG checkpoint, use the password set before the restart"
+                        .to_string(),
+                )
+            })?;
+            Some(checkpoint)
+        } else {
+            None
+        };
+
         state.auth = Some(auth);
         state.status = ServerStatus::SharingConfigGenParams;
+        if let Some(checkpoint) = checkpoint {
+            info!(
+                target: fedimint_logging::LOG_NET_PEER_DKG,
+                "Restoring unfinished DKG from checkpoint"
+            );
+            state.restore_dkg_checkpoint(checkpoint, &self.dkg_progress)?;
+        }
         Ok(())
     }
 
@@ -85,6 +126,15 @@ impl ConfigGenApi {
         Ok(state)
     }
 
+    /// Whether a request with `auth` is authorized
+    fn has_auth(&self, auth: Option<&ApiAuth>) -> bool {
+        match &self.state.lock().expect("lock poisoned").auth {
+            // The first client to connect gets the set the password
+            None => true,
+            Some(configured_auth) => Some(configured_auth) == auth,
+        }
+    }
+
     /// Sets our connection info, possibly sending it to a leader
     pub async fn set_config_gen_connections(
         &self,
@@ -107,8 +157,9 @@ impl ConfigGenApi {
             // Note PeerIds don't really exist at this point, but id doesn't matter because
             // it's not used in the WS client for anything, perhaps it should be removed
             let client = WsAdminClient::new(url, PeerId::from(0), state.auth()?);
+            let invite_code = state.local_connection()?.invite_code;
             client
-                .add_config_gen_peer(state.our_peer_info()?)
+                .add_config_gen_peer(state.our_peer_info()?, invite_code)
                 .await
                 .map_err(|_| ApiError::not_found("Unable to connect to the leader".to_string()))?;
         }
@@ -117,12 +168,43 @@ impl ConfigGenApi {
 
     /// Called from `set_config_gen_connections` to add a peer's connection info
     /// to the leader
-    pub fn add_config_gen_peer(&self, peer: PeerServerParams) -> ApiResult<()> {
+    ///
+    /// Once the leader created invites, every peer needs an invite code that
+    /// wasn't used by another peer yet.
+    pub fn add_config_gen_peer(&self, request: AddConfigGenPeerRequest) -> ApiResult<()> {
         let mut state = self.state.lock().expect("lock poisoned");
+        let AddConfigGenPeerRequest { peer, invite_code } = request;
+
+        if !state.invites.is_empty() {
+            let Some(invite_code) = invite_code else {
+                return Self::bad_request("Invite code required");
+            };
+            match state.invites.get_mut(&invite_code) {
+                None => return Err(ApiError::unauthorized()),
+                // Peers resend their info on every status change
+                Some(Some(api_url)) if *api_url != peer.api_url => {
+                    return Self::bad_request("Invite code was already used");
+                }
+                Some(redeemed_by) => *redeemed_by = Some(peer.api_url.clone()),
+            }
+        }
+
         state.peers.insert(peer.api_url.clone(), peer);
         Ok(())
     }
 
+    /// Creates a one-time invite code for a peer, only the leader can do this
+    pub fn create_config_gen_invite(&self) -> ApiResult<String> {
+        let mut state = self.require_status(ServerStatus::SharingConfigGenParams)?;
+        if state.local_connection()?.leader_api_url.is_some() {
+            return Self::bad_request("Only the leader can create invites");
+        }
+
+        let invite_code = OsRng.gen::<[u8; 16]>().to_hex();
+        state.invites.insert(invite_code.clone(), None);
+        Ok(invite_code)
+    }
+
     /// Returns the peers that have called `add_config_gen_peer` on the leader
     pub async fn get_config_gen_peers(&self) -> ApiResult<Vec<PeerServerParams>> {
         let state = self.state.lock().expect("lock poisoned");
@@ -172,6 +254,7 @@ impl ConfigGenApi {
                 peers: state.get_peer_info(),
                 meta: request.meta.clone(),
                 modules: request.modules.clone(),
+                misbehavior: Default::default(),
             },
         };
 
@@ -191,20 +274,91 @@ impl ConfigGenApi {
         let (params, registry) = {
             let mut state = self.require_status(ServerStatus::SharingConfigGenParams)?;
             state.status = ServerStatus::ReadyForConfigGen;
+            let params = state.get_config_gen_params(&request, response.consensus)?;
+            state.dkg_params = Some(params.clone());
+            (params, state.settings.registry.clone())
+        };
+        self.update_leader().await?;
+
+        self.run_distributed_gen(params, registry).await
+    }
+
+    /// After DKG failed, runs it again for the failed parts and the modules in
+    /// `rerun_modules`, keeping the keys of all other parts
+    pub async fn restart_dkg(&self, rerun_modules: BTreeSet<ModuleInstanceId>) -> ApiResult<()> {
+        let (params, registry) = {
+            let mut state = self.require_status(ServerStatus::ConfigGenFailed)?;
+            let params = state
+                .dkg_params
+                .clone()
+                .ok_or(ApiError::bad_request("DKG was never started".to_string()))?;
+            for module_instance_id in rerun_modules {
+                state.dkg_cache.invalidate_module(module_instance_id);
+            }
+            state.status = ServerStatus::ReadyForConfigGen;
+            (params, state.settings.registry.clone())
+        };
+        self.update_leader().await?;
+
+        self.run_distributed_gen(params, registry).await
+    }
+
+    /// After DKG failed, discards the results of a module so the next
+    /// `restart_dkg` runs its DKG again
+    pub fn reset_module_dkg(&self, module_instance_id: ModuleInstanceId) -> ApiResult<()> {
+        let mut state = self.require_status(ServerStatus::ConfigGenFailed)?;
+        let params = state
+            .dkg_params
+            .clone()
+            .ok_or(ApiError::bad_request("DKG was never started".to_string()))?;
+        if params.consensus.modules.get(module_instance_id).is_none() {
+            return Self::bad_request(&format!("Unknown module {module_instance_id}"));
+        }
+
+        state.dkg_cache.invalidate_module(module_instance_id);
+        self.write_dkg_checkpoint(&state.dkg_checkpoint()?, &state.auth()?);
+        set_dkg_stage(
+            &self.dkg_progress,
+            Some(module_instance_id),
+            DkgStage::Pending,
+        );
+        Ok(())
+    }
+
+    /// Runs DKG, reusing the results of previous runs, and updates our status
+    ///
+    /// The results are checkpointed to the data dir while DKG runs, so they
+    /// survive a crash.
+    async fn run_distributed_gen(
+        &self,
+        params: ConfigGenParams,
+        registry: ServerModuleGenRegistry,
+    ) -> ApiResult<()> {
+        let (mut cache, checkpoint, auth) = {
+            let state = self.state.lock().expect("lock poisoned");
             (
-                state.get_config_gen_params(&request, response.consensus)?,
-                state.settings.registry.clone(),
+                state.dkg_cache.clone(),
+                state.dkg_checkpoint()?,
+                state.auth()?,
             )
         };
-        self.update_leader().await?;
+        self.write_dkg_checkpoint(&checkpoint, &auth);
 
-        // Run DKG
         let mut task_group = self.task_group.make_subgroup().await;
-        let config = ServerConfig::distributed_gen(
+        let config = ServerConfig::distributed_gen_resumable(
             &params,
             registry,
             DelayCalculator::PROD_DEFAULT,
             &mut task_group,
+            &mut cache,
+            &self.dkg_progress,
+            &|cache: &DkgCache| {
+                let checkpoint = DkgCheckpoint {
+                    cache: cache.clone(),
+                    ..checkpoint.clone()
+                };
+                self.write_dkg_checkpoint(&checkpoint, &auth);
+            },
         )
         .await;
         task_group
@@ -214,9 +368,16 @@ impl ConfigGenApi {
 
         {
             let mut state = self.state.lock().expect("lock poisoned");
+            state.dkg_cache = cache;
             match config {
                 Ok(config) => {
                     self.write_configs(&config, &state)?;
+                    if let Err(e) = remove_dkg_checkpoint(&self.data_dir) {
+                        warn!(
+                            target: fedimint_logging::LOG_NET_PEER_DKG,
+                            "Unable to remove DKG checkpoint {:?}", e
+                        );
+                    }
                     state.status = ServerStatus::VerifyingConfigs;
                     state.config = Some(config);
                 }
@@ -244,13 +405,28 @@ impl ConfigGenApi {
         Ok(get_verification_hashes(&config))
     }
 
+    /// Persists the state of an unfinished DKG, failing to do so only means we
+    /// cannot resume it after a crash, so errors are just logged
+    fn write_dkg_checkpoint(&self, checkpoint: &DkgCheckpoint, auth: &ApiAuth) {
+        if let Err(e) = write_dkg_checkpoint(checkpoint, self.data_dir.clone(), &auth.0) {
+            warn!(
+                target: fedimint_logging::LOG_NET_PEER_DKG,
+                "Unable to write DKG checkpoint {:?}", e
+            );
+        }
+    }
+
     /// Writes the configs to disk after they are generated
     fn write_configs(&self, config: &ServerConfig, state: &ConfigGenState) -> ApiResult<()> {
         let auth = config.private.api_auth.0.clone();
         let io_error = |e| ApiError::server_error(format!("Unable to write to data dir {e:?}"));
         // TODO: Make writing password optional
         write_new(self.data_dir.join(PLAINTEXT_PASSWORD), &auth).map_err(io_error)?;
-        write_new(self.data_dir.join(SALT_FILE), random_salt()).map_err(io_error)?;
+        // An encrypted database created the salt already, its key is encrypted with it
+        let salt_path = self.data_dir.join(SALT_FILE);
+        if !salt_path.exists() {
+            write_new(salt_path, random_salt()).map_err(io_error)?;
+        }
         write_server_config(
             config,
             self.data_dir.clone(),
@@ -265,6 +441,9 @@ impl ConfigGenApi {
     /// Will force shut down the config gen API so the consensus API can start.
     /// Removes the upgrade flag when called.
     pub async fn start_consensus(&self, auth: ApiAuth) -> ApiResult<()> {
+        // We may have crashed while replacing the config files
+        finish_config_update(&self.data_dir)
+            .map_err(|e| ApiError::server_error(format!("Unable to update configs {e:?}")))?;
         let cfg = read_server_config(&auth.0, self.data_dir.clone())
             .map_err(|e| ApiError::bad_request(format!("Unable to decrypt configs {e:?}")))?;
 
@@ -359,20 +538,46 @@ pub struct ConfigGenState {
     status: ServerStatus,
     /// Configs that have been generated
     config: Option<ServerConfig>,
+    /// Invite codes created by the leader, with the api url of the peer that
+    /// redeemed them
+    invites: BTreeMap<String, Option<Url>>,
+    /// Params DKG was started with, used when restarting it
+    dkg_params: Option<ConfigGenParams>,
+    /// Results of the DKG parts that succeeded
+    dkg_cache: DkgCache,
 }
 
 /// Our local connection info
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 struct ConfigGenLocalConnection {
     /// Our TLS private key
+    #[serde(with = "serde_tls_key")]
     tls_private: rustls::PrivateKey,
     /// Our TLS public cert
+    #[serde(with = "serde_tls_cert")]
     tls_cert: rustls::Certificate,
     /// Our guardian name
     our_name: String,
     /// Url of "leader" guardian to send our connection info to
     /// Will be `None` if we are the leader
     leader_api_url: Option<Url>,
+    /// Invite code to send to the leader
+    invite_code: Option<String>,
+}
+
+/// State of a DKG that has not completed yet, written to the data dir so a
+/// guardian that crashed during DKG can resume it instead of everyone having
+/// to start over
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct DkgCheckpoint {
+    /// Our local connection, peers know us by its TLS cert
+    local: ConfigGenLocalConnection,
+    /// The config gen params we requested
+    requested_params: ConfigGenParamsRequest,
+    /// The consensus params DKG was started with
+    consensus: ConfigGenParamsConsensus,
+    /// Results of the DKG parts that succeeded
+    cache: DkgCache,
 }
 
 impl ConfigGenState {
@@ -385,6 +590,9 @@ impl ConfigGenState {
             requested_params: None,
             status: ServerStatus::AwaitingPassword,
             config: None,
+            invites: Default::default(),
+            dkg_params: None,
+            dkg_cache: Default::default(),
         }
     }
 
@@ -396,10 +604,68 @@ impl ConfigGenState {
             tls_cert,
             our_name: request.our_name,
             leader_api_url: request.leader_api_url,
+            invite_code: request.invite_code,
         });
         Ok(())
     }
 
+    /// Returns the checkpoint of the DKG that was started
+    fn dkg_checkpoint(&self) -> ApiResult<DkgCheckpoint> {
+        let params = self
+            .dkg_params
+            .clone()
+            .ok_or(ApiError::bad_request("DKG was never started".to_string()))?;
+        Ok(DkgCheckpoint {
+            local: self.local_connection()?,
+            requested_params: self.requested_params.clone().ok_or(ApiError::bad_request(
+                "Config params were not set on this guardian".to_string(),
+            ))?,
+            consensus: params.consensus,
+            cache: self.dkg_cache.clone(),
+        })
+    }
+
+    /// Restores the state of an unfinished DKG, afterwards we are in the same
+    /// state as if DKG had failed
+    fn restore_dkg_checkpoint(
+        &mut self,
+        checkpoint: DkgCheckpoint,
+        progress: &watch::Sender<DkgProgress>,
+    ) -> ApiResult<()> {
+        let DkgCheckpoint {
+            local,
+            requested_params,
+            consensus,
+            cache,
+        } = checkpoint;
+
+        self.peers = consensus
+            .peers
+            .values()
+            .filter(|peer| peer.cert != local.tls_cert)
+            .map(|peer| (peer.api_url.clone(), peer.clone()))
+            .collect();
+        self.local = Some(local);
+        let params = self.get_config_gen_params(&requested_params, consensus)?;
+        for (module_instance_id, _, _) in params.consensus.modules.iter_modules() {
+            let stage = if cache.modules.contains_key(&module_instance_id) {
+                DkgStage::Done
+            } else {
+                DkgStage::Pending
+            };
+            set_dkg_stage(progress, Some(module_instance_id), stage);
+        }
+        if cache.global_keys.is_some() {
+            set_dkg_stage(progress, None, DkgStage::Done);
+        }
+
+        self.requested_params = Some(requested_params);
+        self.dkg_params = Some(params);
+        self.dkg_cache = cache;
+        self.status = ServerStatus::ConfigGenFailed;
+        Ok(())
+    }
+
     fn local_connection(&self) -> ApiResult<ConfigGenLocalConnection> {
         self.local.clone().ok_or(ApiError::bad_request(
             "Our connection info not set yet".to_string(),
@@ -513,17 +779,17 @@ impl HasApiContext<ConfigGenApi> for ConfigGenApi {
             db = self.db.new_isolated(id);
             dbtx = dbtx.new_module_tx(id)
         }
-        let state = self.state.lock().expect("locks");
-        let auth = request.auth.as_ref();
-        let has_auth = match state.auth.clone() {
-            // The first client to connect gets the set the password
-            None => true,
-            Some(configured_auth) => Some(&configured_auth) == auth,
-        };
+        let has_auth = self.has_auth(request.auth.as_ref());
 
         (
             self,
-            ApiEndpointContext::new(db, dbtx, has_auth, request.auth.clone()),
+            ApiEndpointContext::new(
+                db,
+                dbtx,
+                has_auth,
+                request.auth.clone(),
+                request.correlation_id,
+            ),
         )
     }
 }
@@ -548,9 +814,16 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConfigGenApi>> {
         },
         api_endpoint! {
             "add_config_gen_peer",
-            async |config: &ConfigGenApi, _context, peer: PeerServerParams| -> () {
+            async |config: &ConfigGenApi, _context, request: AddConfigGenPeerRequest| -> () {
                 // No auth required since this is an API-to-API call and the peer connections will be manually accepted or not in the UI
-                config.add_config_gen_peer(peer)
+                config.add_config_gen_peer(request)
+            }
+        },
+        api_endpoint! {
+            "create_config_gen_invite",
+            async |config: &ConfigGenApi, context, _v: ()| -> String {
+                check_auth(context)?;
+                config.create_config_gen_invite()
             }
         },
         api_endpoint! {
@@ -587,6 +860,20 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConfigGenApi>> {
                 config.run_dkg().await
             }
         },
+        api_endpoint! {
+            "restart_dkg",
+            async |config: &ConfigGenApi, context, rerun_modules: BTreeSet<ModuleInstanceId>| -> () {
+                check_auth(context)?;
+                config.restart_dkg(rerun_modules).await
+            }
+        },
+        api_endpoint! {
+            "reset_module_dkg",
+            async |config: &ConfigGenApi, context, module_instance_id: ModuleInstanceId| -> () {
+                check_auth(context)?;
+                config.reset_module_dkg(module_instance_id)
+            }
+        },
         api_endpoint! {
             "get_verify_config_hash",
             async |config: &ConfigGenApi, context, _v: ()| -> BTreeMap<PeerId, sha256::Hash> {
@@ -611,7 +898,8 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConfigGenApi>> {
                 let server = config.server_status().await;
                 Ok(StatusResponse {
                     server,
-                    consensus: None
+                    consensus: None,
+                    modules: BTreeMap::new(),
                 })
             }
         },
@@ -626,6 +914,48 @@ fn check_auth(context: &mut ApiEndpointContext) -> ApiResult<()> {
     }
 }
 
+/// Attaches the subscription streaming the DKG progress to the UI over the
+/// websocket, it sends the current progress and then every update
+pub fn attach_dkg_progress_subscription(rpc_module: &mut RpcModule<RpcHandlerCtx<ConfigGenApi>>) {
+    rpc_module
+        .register_subscription(
+            SUBSCRIBE_DKG_PROGRESS,
+            DKG_PROGRESS_NOTIFICATION,
+            UNSUBSCRIBE_DKG_PROGRESS,
+            |params, mut sink, rpc_state| {
+                let config = &rpc_state.rpc_context;
+                let authorized = params
+                    .one::<ApiRequestErased>()
+                    .map_or(false, |request| config.has_auth(request.auth.as_ref()));
+                if !authorized {
+                    let error = ApiError::unauthorized();
+                    let _ = sink.reject(CallError::Custom(ErrorObject::owned(
+                        error.code,
+                        error.message,
+                        None::<()>,
+                    )));
+                    return Ok(());
+                }
+
+                let progress = futures::stream::unfold(
+                    (config.dkg_progress.subscribe(), true),
+                    |(mut receiver, first)| async move {
+                        if !first && receiver.changed().await.is_err() {
+                            return None;
+                        }
+                        let progress = receiver.borrow_and_update().clone();
+                        Some((progress, (receiver, false)))
+                    },
+                );
+                tokio::spawn(async move {
+                    let _ = sink.pipe_from_stream(progress).await;
+                });
+                Ok(())
+            },
+        )
+        .expect("Failed to register subscription");
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -635,7 +965,7 @@ mod tests {
     use std::sync::Arc;
     use std::time::Duration;
 
-    use fedimint_core::admin_client::{ConfigGenParamsRequest, WsAdminClient};
+    use fedimint_core::admin_client::{ConfigGenParamsRequest, DkgStage, WsAdminClient};
     use fedimint_core::api::{FederationResult, ServerStatus, StatusResponse};
     use fedimint_core::config::{ServerModuleGenParamsRegistry, ServerModuleGenRegistry};
     use fedimint_core::db::mem_impl::MemDatabase;
@@ -651,10 +981,11 @@ mod tests {
     use fedimint_logging::TracingSetup;
     use fedimint_testing::fixtures::test_dir;
     use futures::future::join_all;
+    use futures::StreamExt;
     use itertools::Itertools;
     use url::Url;
 
-    use crate::config::api::{ConfigGenConnectionsRequest, ConfigGenSettings};
+    use crate::config::api::{ConfigGenApi, ConfigGenConnectionsRequest, ConfigGenSettings};
     use crate::config::io::{read_server_config, PLAINTEXT_PASSWORD};
     use crate::config::{DynServerModuleGen, ServerConfig, DEFAULT_MAX_CLIENT_CONNECTIONS};
     use crate::fedimint_core::module::ServerModuleGen;
@@ -667,6 +998,7 @@ mod tests {
         settings: ConfigGenSettings,
         amount: Amount,
         dir: PathBuf,
+        invite_code: Option<String>,
     }
 
     impl TestConfigApi {
@@ -709,6 +1041,8 @@ mod tests {
                 data_dir: dir.clone(),
                 settings: settings.clone(),
                 db,
+                grpc_bind: None,
+                nostr_relays: vec![],
             };
 
             // our id doesn't really exist at this point
@@ -722,6 +1056,7 @@ mod tests {
                     settings,
                     amount: Amount::from_sats(port as u64),
                     dir,
+                    invite_code: None,
                 },
                 api,
             )
@@ -744,6 +1079,7 @@ mod tests {
                 .set_config_gen_connections(ConfigGenConnectionsRequest {
                     our_name: self.name.clone(),
                     leader_api_url: leader.clone(),
+                    invite_code: self.invite_code.clone(),
                 })
                 .await
         }
@@ -811,6 +1147,73 @@ mod tests {
         }
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dkg_checkpoint_restore() {
+        let (data_dir, _maybe_tmp_dir_guard) = test_dir("test-dkg-checkpoint");
+        let (peer, _) = TestConfigApi::new(18303, 0, data_dir).await;
+        let auth = ApiAuth("password".to_string());
+        let new_api = || {
+            ConfigGenApi::new(
+                peer.dir.clone(),
+                peer.settings.clone(),
+                Database::new(MemDatabase::new(), ModuleDecoderRegistry::default()),
+                tokio::sync::mpsc::channel(1).0,
+                &mut TaskGroup::new(),
+            )
+        };
+
+        // Start DKG without running it, as if we crashed during it
+        let api = new_api();
+        api.set_password(auth.clone()).unwrap();
+        api.set_config_gen_connections(ConfigGenConnectionsRequest {
+            our_name: peer.name.clone(),
+            leader_api_url: None,
+            invite_code: None,
+        })
+        .await
+        .unwrap();
+        let request = peer.settings.default_params.clone();
+        api.set_config_gen_params(request.clone()).await.unwrap();
+        let response = api.get_consensus_config_gen_params(&request).await.unwrap();
+        {
+            let mut state = api.state.lock().unwrap();
+            let params = state
+                .get_config_gen_params(&request, response.consensus)
+                .unwrap();
+            state.dkg_params = Some(params);
+            api.write_dkg_checkpoint(&state.dkg_checkpoint().unwrap(), &auth);
+        }
+
+        // After a restart the checkpoint requires the same password
+        let restarted = new_api();
+        assert!(restarted
+            .set_password(ApiAuth("wrong".to_string()))
+            .is_err());
+        restarted.set_password(auth).unwrap();
+        assert_eq!(
+            restarted.server_status().await,
+            ServerStatus::ConfigGenFailed
+        );
+        {
+            let state = restarted.state.lock().unwrap();
+            let params = state.dkg_params.clone().unwrap();
+            assert_eq!(params.local.our_id, response.our_current_id);
+            assert_eq!(
+                state.local_connection().unwrap().tls_cert,
+                api.state
+                    .lock()
+                    .unwrap()
+                    .local_connection()
+                    .unwrap()
+                    .tls_cert
+            );
+        }
+
+        // Only modules that exist can be reset
+        restarted.reset_module_dkg(0).unwrap();
+        assert!(restarted.reset_module_dkg(1).is_err());
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_config_api() {
         let _ = TracingSetup::default().init();
@@ -856,12 +1259,25 @@ mod tests {
                 );
                 follower.client.set_password().await.unwrap();
                 let leader_url = Some(leader.settings.api_url.clone());
+                // Once invites exist, peers can only join with an unused invite
+                assert!(follower.set_connections(&leader_url).await.is_err());
+                follower.invite_code =
+                    Some(leader.client.create_config_gen_invite().await.unwrap());
                 follower.set_connections(&leader_url).await.unwrap();
                 follower.name = format!("{}_", follower.name);
                 follower.set_connections(&leader_url).await.unwrap();
                 follower.set_config_gen_params().await;
             }
 
+            // Invites cannot be used twice
+            let used_invite = followers[0].invite_code.clone();
+            let own_invite = std::mem::replace(&mut followers[1].invite_code, used_invite);
+            assert!(followers[1]
+                .set_connections(&Some(leader.settings.api_url.clone()))
+                .await
+                .is_err());
+            followers[1].invite_code = own_invite;
+
             // Confirm we can get peer servers if we are the leader
             let peers = leader.client.get_config_gen_peers().await.unwrap();
             let names: Vec<_> = peers.into_iter().map(|peer| peer.name).sorted().collect();
@@ -897,6 +1313,13 @@ mod tests {
                 result.expect("DKG failed");
             }
 
+            // The DKG progress subscription starts with every part done
+            let mut progress =
+                Box::pin(followers[0].client.subscribe_dkg_progress().await.unwrap());
+            let progress = progress.next().await.unwrap().unwrap();
+            assert_eq!(progress.global, DkgStage::Done);
+            assert_eq!(progress.modules, BTreeMap::from([(0, DkgStage::Done)]));
+
             // verify config hashes equal for all peers
             let mut hashes = HashSet::new();
             for peer in followers.iter() {
@@ -7,15 +7,19 @@ use anyhow::{ensure, format_err};
 use async_trait::async_trait;
 use bitcoin::secp256k1;
 use bitcoin_hashes::sha256::{Hash as Sha256, HashEngine};
-use fedimint_core::config::{DkgGroup, DkgMessage, DkgPeerMsg, DkgResult, ISupportedDkgMessage};
+use fedimint_core::config::{
+    serde_binary_human_readable, DkgGroup, DkgMessage, DkgPeerMsg, DkgResult, ISupportedDkgMessage,
+};
 use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::module::PeerHandle;
 use fedimint_core::net::peers::MuxPeerConnections;
 use fedimint_core::{BitcoinHash, PeerId};
 use hbbft::crypto::poly::Commitment;
 use hbbft::crypto::{G1Projective, G2Projective, PublicKeySet, SecretKeyShare};
 use serde::de::DeserializeOwned;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use tbs::hash::hash_bytes_to_curve;
 use tbs::poly::Poly;
 use tbs::Scalar;
@@ -392,9 +396,11 @@ pub struct DkgKeys<G> {
 }
 
 /// Our secret key share of a threshold key
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ThresholdKeys {
+    #[serde(with = "serde_binary_human_readable")]
     pub public_key_set: PublicKeySet,
+    #[serde(with = "serde_binary_human_readable")]
     pub secret_key_share: SerdeSecret<SecretKeyShare>,
 }
 
@@ -506,6 +512,16 @@ pub trait PeerHandleOps {
         dkg_key: String,
         key: secp256k1::PublicKey,
     ) -> DkgResult<BTreeMap<PeerId, secp256k1::PublicKey>>;
+
+    /// Sends every peer its message from `messages` and returns the ones they
+    /// sent us, including our own
+    async fn exchange_encodable<T>(
+        &self,
+        dkg_key: String,
+        messages: BTreeMap<PeerId, T>,
+    ) -> DkgResult<BTreeMap<PeerId, T>>
+    where
+        T: Encodable + Decodable + Send + Sync;
 }
 
 #[async_trait]
@@ -562,4 +578,53 @@ impl<'a> PeerHandleOps for PeerHandle<'a> {
 
         Ok(peer_peg_in_keys)
     }
+
+    async fn exchange_encodable<T>(
+        &self,
+        dkg_key: String,
+        mut messages: BTreeMap<PeerId, T>,
+    ) -> DkgResult<BTreeMap<PeerId, T>>
+    where
+        T: Encodable + Decodable + Send + Sync,
+    {
+        let mut received = BTreeMap::new();
+        if let Some(ours) = messages.remove(&self.our_id) {
+            received.insert(self.our_id, ours);
+        }
+
+        for (peer, message) in messages {
+            let bytes = message
+                .consensus_encode_to_vec()
+                .expect("encoding to vec can't fail");
+            self.connections
+                .send(
+                    &[peer],
+                    (self.module_instance_id, dkg_key.clone()),
+                    DkgPeerMsg::Module(bytes),
+                )
+                .await?;
+        }
+
+        while received.len() < self.peers.len() {
+            match self
+                .connections
+                .receive((self.module_instance_id, dkg_key.clone()))
+                .await?
+            {
+                (peer, DkgPeerMsg::Module(bytes)) => {
+                    let message =
+                        T::consensus_decode(&mut &bytes[..], &ModuleDecoderRegistry::default())
+                            .map_err(|e| format_err!("Invalid message from {peer}: {e}"))?;
+                    received.insert(peer, message);
+                }
+                (peer, msg) => {
+                    return Err(
+                        format_err!("Invalid message received from: {peer}: {msg:?}").into(),
+                    );
+                }
+            }
+        }
+
+        Ok(received)
+    }
 }
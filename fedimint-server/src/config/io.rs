@@ -3,12 +3,16 @@ use std::fs;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
-use fedimint_aead::{encrypted_read, encrypted_write, get_encryption_key, LessSafeKey};
+use fedimint_aead::{
+    encrypted_read, encrypted_write, get_encryption_key, random_salt, LessSafeKey,
+};
 use fedimint_core::config::ServerModuleGenRegistry;
+use fedimint_core::util::write_new;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
-use crate::config::ServerConfig;
+use crate::config::{ServerConfig, ServerConfigConsensus};
+use crate::encrypted_db::{is_database_encrypted, rewrap_database_key};
 
 /// Version of the server code (should be the same among peers)
 pub const CODE_VERSION: &str = env!("FEDIMINT_BUILD_CODE_VERSION");
@@ -38,6 +42,23 @@ pub const PLAINTEXT_PASSWORD: &str = "password.private";
 /// Database file name
 pub const DB_FILE: &str = "database";
 
+/// Encrypted state of a DKG that has not completed yet
+pub const DKG_CHECKPOINT: &str = "dkg-checkpoint";
+
+/// Salt for the encryption key of the DKG checkpoint
+const DKG_CHECKPOINT_SALT: &str = "dkg-checkpoint.salt";
+
+/// Temporary directory the config files are written to before replacing the
+/// current ones
+const CONFIG_UPDATE_DIR: &str = "config-update";
+
+/// A complete config update that is moved into place, renaming
+/// [`CONFIG_UPDATE_DIR`] to it commits the update
+const CONFIG_COMMIT_DIR: &str = "config-commit";
+
+/// Config with an added module, staged before we confirm the module's DKG
+const STAGED_CONFIG_DIR: &str = "config-staged";
+
 pub const JSON_EXT: &str = "json";
 
 const ENCRYPTED_EXT: &str = "encrypt";
@@ -54,6 +75,16 @@ pub fn read_server_config(password: &str, path: PathBuf) -> anyhow::Result<Serve
     })
 }
 
+/// Reads the consensus config, which isn't encrypted and can be read without
+/// the password, `None` if no config was generated yet
+pub fn read_consensus_config(path: &Path) -> anyhow::Result<Option<ServerConfigConsensus>> {
+    let path = path.join(CONSENSUS_CONFIG);
+    if !path.with_extension(JSON_EXT).exists() {
+        return Ok(None);
+    }
+    plaintext_json_read(path).map(Some)
+}
+
 /// Reads a plaintext json file into a struct
 fn plaintext_json_read<T: Serialize + DeserializeOwned>(path: PathBuf) -> anyhow::Result<T> {
     let string = fs::read_to_string(path.with_extension(JSON_EXT))?;
@@ -88,6 +119,214 @@ pub fn write_server_config(
     encrypted_json_write(&server.private, &key, path.join(PRIVATE_CONFIG))
 }
 
+/// Replaces the configuration files of a running server whose config changed,
+/// e.g. because a module was added
+///
+/// The files are written to a temporary directory first, so an error leaves
+/// the current files untouched. Once that directory is complete it is renamed
+/// in one step, a crash after that is finished by [`finish_config_update`].
+pub fn overwrite_server_config(
+    server: &ServerConfig,
+    path: PathBuf,
+    password: &str,
+    module_config_gens: &ServerModuleGenRegistry,
+) -> anyhow::Result<()> {
+    let salt = fs::read_to_string(path.join(SALT_FILE))?;
+    replace_server_config(server, path, password, password, &salt, module_config_gens)
+}
+
+/// Re-encrypts the private config of a running server under its new password
+/// `server.private.api_auth` with a fresh salt
+///
+/// If the password is stored in plaintext for restarts it gets replaced too,
+/// as does the key of an encrypted database. Like [`overwrite_server_config`]
+/// an error leaves the current files untouched.
+pub fn rotate_config_password(
+    server: &ServerConfig,
+    old_password: &str,
+    path: PathBuf,
+    module_config_gens: &ServerModuleGenRegistry,
+) -> anyhow::Result<()> {
+    replace_server_config(
+        server,
+        path,
+        old_password,
+        &server.private.api_auth.0,
+        &random_salt(),
+        module_config_gens,
+    )
+}
+
+fn replace_server_config(
+    server: &ServerConfig,
+    path: PathBuf,
+    old_password: &str,
+    password: &str,
+    salt: &str,
+    module_config_gens: &ServerModuleGenRegistry,
+) -> anyhow::Result<()> {
+    let tmp_path = path.join(CONFIG_UPDATE_DIR);
+    if tmp_path.exists() {
+        fs::remove_dir_all(&tmp_path)?;
+    }
+    fs::create_dir(&tmp_path)?;
+    write_new(tmp_path.join(SALT_FILE), salt)?;
+    if path.join(PLAINTEXT_PASSWORD).exists() {
+        write_new(tmp_path.join(PLAINTEXT_PASSWORD), password)?;
+    }
+    if is_database_encrypted(&path) {
+        let old_salt = fs::read_to_string(path.join(SALT_FILE))?;
+        rewrap_database_key(
+            &get_encryption_key(old_password, &old_salt)?,
+            &get_encryption_key(password, salt)?,
+            &path,
+            &tmp_path,
+        )?;
+    }
+
+    write_server_config(server, tmp_path.clone(), password, module_config_gens)?;
+    sync_dir(&tmp_path)?;
+
+    finish_config_update(&path)?;
+    fs::rename(&tmp_path, path.join(CONFIG_COMMIT_DIR))?;
+    finish_config_update(&path)
+}
+
+/// Moves a committed config update into place and discards an incomplete
+/// one, has to run before the config is read after a crash
+pub fn finish_config_update(path: &Path) -> anyhow::Result<()> {
+    let commit_path = path.join(CONFIG_COMMIT_DIR);
+    if commit_path.exists() {
+        for entry in fs::read_dir(&commit_path)? {
+            let entry = entry?;
+            fs::rename(entry.path(), path.join(entry.file_name()))?;
+        }
+        fs::remove_dir(&commit_path)?;
+    }
+
+    let tmp_path = path.join(CONFIG_UPDATE_DIR);
+    if tmp_path.exists() {
+        fs::remove_dir_all(&tmp_path)?;
+    }
+    Ok(())
+}
+
+/// Stages the config of a running server with an added module before the
+/// module's DKG is confirmed to our peers, since they restart with the module
+/// as soon as everyone confirmed
+pub fn stage_server_config(
+    server: &ServerConfig,
+    path: PathBuf,
+    password: &str,
+    module_config_gens: &ServerModuleGenRegistry,
+) -> anyhow::Result<()> {
+    let tmp_path = path.join(format!("{STAGED_CONFIG_DIR}-tmp"));
+    if tmp_path.exists() {
+        fs::remove_dir_all(&tmp_path)?;
+    }
+    fs::create_dir(&tmp_path)?;
+    write_new(
+        tmp_path.join(SALT_FILE),
+        fs::read_to_string(path.join(SALT_FILE))?,
+    )?;
+    write_server_config(server, tmp_path.clone(), password, module_config_gens)?;
+    sync_dir(&tmp_path)?;
+
+    remove_staged_server_config(&path)?;
+    fs::rename(&tmp_path, path.join(STAGED_CONFIG_DIR))?;
+    Ok(())
+}
+
+/// Reads the config staged by [`stage_server_config`], `None` if there is none
+pub fn read_staged_server_config(
+    password: &str,
+    path: &Path,
+) -> anyhow::Result<Option<ServerConfig>> {
+    let staged_path = path.join(STAGED_CONFIG_DIR);
+    if !staged_path.exists() {
+        return Ok(None);
+    }
+    read_server_config(password, staged_path).map(Some)
+}
+
+/// Removes the staged config once it was written or the addition failed
+pub fn remove_staged_server_config(path: &Path) -> anyhow::Result<()> {
+    let staged_path = path.join(STAGED_CONFIG_DIR);
+    if staged_path.exists() {
+        fs::remove_dir_all(staged_path)?;
+    }
+    Ok(())
+}
+
+/// Flushes the files in `path` and the directory itself to disk, so renaming
+/// the directory afterwards never exposes partially written files
+fn sync_dir(path: &Path) -> anyhow::Result<()> {
+    for entry in fs::read_dir(path)? {
+        fs::File::open(entry?.path())?.sync_all()?;
+    }
+    fs::File::open(path)?.sync_all()?;
+    Ok(())
+}
+
+/// Returns true if an unfinished DKG left a checkpoint in `path`
+pub fn dkg_checkpoint_exists(path: &Path) -> bool {
+    path.join(DKG_CHECKPOINT)
+        .with_extension(ENCRYPTED_EXT)
+        .exists()
+}
+
+/// Reads the DKG checkpoint, fails if the password differs from the one it was
+/// written with
+pub fn read_dkg_checkpoint<T: Serialize + DeserializeOwned>(
+    password: &str,
+    path: PathBuf,
+) -> anyhow::Result<T> {
+    let salt = fs::read_to_string(path.join(DKG_CHECKPOINT_SALT))?;
+    let key = get_encryption_key(password, &salt)?;
+    encrypted_json_read(&key, path.join(DKG_CHECKPOINT))
+}
+
+/// Writes the DKG checkpoint (encrypted since it contains our key shares),
+/// replacing the previous one
+pub fn write_dkg_checkpoint<T: Serialize + DeserializeOwned>(
+    checkpoint: &T,
+    path: PathBuf,
+    password: &str,
+) -> anyhow::Result<()> {
+    let salt_path = path.join(DKG_CHECKPOINT_SALT);
+    if !salt_path.exists() {
+        write_new(&salt_path, random_salt())?;
+    }
+    let salt = fs::read_to_string(salt_path)?;
+    let key = get_encryption_key(password, &salt)?;
+
+    // Write to a temporary file first so a crash never leaves a partial file
+    let tmp_path = path.join(format!("{DKG_CHECKPOINT}-tmp"));
+    let tmp_file = tmp_path.with_extension(ENCRYPTED_EXT);
+    if tmp_file.exists() {
+        fs::remove_file(&tmp_file)?;
+    }
+    encrypted_json_write(checkpoint, &key, tmp_path)?;
+    fs::rename(
+        tmp_file,
+        path.join(DKG_CHECKPOINT).with_extension(ENCRYPTED_EXT),
+    )?;
+    Ok(())
+}
+
+/// Removes the DKG checkpoint once the configs were written
+pub fn remove_dkg_checkpoint(path: &Path) -> anyhow::Result<()> {
+    for file in [
+        path.join(DKG_CHECKPOINT).with_extension(ENCRYPTED_EXT),
+        path.join(DKG_CHECKPOINT_SALT),
+    ] {
+        if file.exists() {
+            fs::remove_file(file)?;
+        }
+    }
+    Ok(())
+}
+
 /// Writes struct into a plaintext json file
 fn plaintext_json_write<T: Serialize + DeserializeOwned>(
     obj: &T,
@@ -120,3 +359,31 @@ fn encrypted_json_write<T: Serialize + DeserializeOwned>(
     let bytes = serde_json::to_string(obj)?.into_bytes();
     encrypted_write(bytes, key, path.with_extension(ENCRYPTED_EXT))
 }
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::{finish_config_update, CONFIG_COMMIT_DIR, CONFIG_UPDATE_DIR, LOCAL_CONFIG};
+
+    #[test]
+    fn finish_config_update_rolls_forward_committed_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path();
+        fs::write(path.join(LOCAL_CONFIG), "old").unwrap();
+
+        // Crashed while writing an update, it's discarded
+        fs::create_dir(path.join(CONFIG_UPDATE_DIR)).unwrap();
+        fs::write(path.join(CONFIG_UPDATE_DIR).join(LOCAL_CONFIG), "partial").unwrap();
+        finish_config_update(path).unwrap();
+        assert!(!path.join(CONFIG_UPDATE_DIR).exists());
+        assert_eq!(fs::read_to_string(path.join(LOCAL_CONFIG)).unwrap(), "old");
+
+        // Crashed after committing an update, it's moved into place
+        fs::create_dir(path.join(CONFIG_COMMIT_DIR)).unwrap();
+        fs::write(path.join(CONFIG_COMMIT_DIR).join(LOCAL_CONFIG), "new").unwrap();
+        finish_config_update(path).unwrap();
+        assert!(!path.join(CONFIG_COMMIT_DIR).exists());
+        assert_eq!(fs::read_to_string(path.join(LOCAL_CONFIG)).unwrap(), "new");
+    }
+}
@@ -4,7 +4,9 @@ use std::net::SocketAddr;
 use std::time::Duration;
 
 use anyhow::{bail, format_err};
-use fedimint_core::admin_client::ConfigGenParamsConsensus;
+use fedimint_core::admin_client::{
+    ConfigGenParamsConsensus, DkgProgress, DkgStage, MisbehaviorThresholds,
+};
 use fedimint_core::api::{ClientConfigDownloadToken, WsClientConnectInfo};
 use fedimint_core::cancellable::Cancelled;
 pub use fedimint_core::config::*;
@@ -17,17 +19,21 @@ use fedimint_core::module::{
     ApiAuth, ApiVersion, CoreConsensusVersion, DynServerModuleGen, MultiApiVersion, PeerHandle,
     SupportedApiVersionsSummary, SupportedCoreApiVersions,
 };
-use fedimint_core::net::peers::{IMuxPeerConnections, IPeerConnections, PeerConnections};
+use fedimint_core::net::peers::{
+    IMuxPeerConnections, IPeerConnections, MuxPeerConnections, PeerConnections,
+};
 use fedimint_core::task::{timeout, Elapsed, TaskGroup};
 use fedimint_core::{timing, PeerId};
 use fedimint_logging::{LOG_NET_PEER, LOG_NET_PEER_DKG};
-use futures::future::join_all;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
 use hbbft::crypto::serde_impl::SerdeSecret;
 use hbbft::NetworkInfo;
 use rand::rngs::OsRng;
 use rand::Rng;
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use tokio::sync::watch;
 use tokio_rustls::rustls;
 use tracing::{error, info};
 
@@ -140,6 +146,9 @@ pub struct ServerConfigConsensus {
     pub modules_json: BTreeMap<ModuleInstanceId, JsonWithKind>,
     /// Additional config the federation wants to transmit to the clients
     pub meta: BTreeMap<String, String>,
+    /// When misbehaving peers get quarantined
+    #[serde(default)]
+    pub misbehavior: MisbehaviorThresholds,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -254,6 +263,7 @@ impl ServerConfig {
             modules: Default::default(),
             modules_json: Default::default(),
             meta: params.consensus.meta,
+            misbehavior: params.consensus.misbehavior,
         };
         let mut cfg = Self {
             consensus,
@@ -270,11 +280,20 @@ impl ServerConfig {
             .url
             .clone();
         let download_token = self.local.download_token.clone();
+        let api_endpoints = self
+            .consensus
+            .api_endpoints
+            .iter()
+            .map(|(peer_id, peer)| (*peer_id, peer.url.clone()))
+            .collect();
 
         WsClientConnectInfo {
             url,
             download_token,
             id,
+            api_endpoints,
+            network: None,
+            gateway: None,
         }
     }
 
@@ -457,6 +476,37 @@ impl ServerConfig {
         registry: ServerModuleGenRegistry,
         delay_calculator: DelayCalculator,
         task_group: &mut TaskGroup,
+    ) -> DkgResult<Self> {
+        let (progress, _) = watch::channel(DkgProgress::default());
+        Self::distributed_gen_resumable(
+            params,
+            registry,
+            delay_calculator,
+            task_group,
+            &mut DkgCache::default(),
+            &progress,
+            &|_: &DkgCache| {},
+        )
+        .await
+    }
+
+    /// Runs the distributed key gen algorithm, reporting the stage of every
+    /// part to `progress`.
+    ///
+    /// Parts that already have results in `cache` are skipped and parts that
+    /// succeed are added to it, so calling this again after a failure only
+    /// reruns the failed parts. Before starting, the peers agree on the parts
+    /// to skip: a part is only skipped if every peer has its results cached,
+    /// otherwise all of them run it again. `checkpoint` is called every time
+    /// the cache changed, so it can be persisted.
+    pub async fn distributed_gen_resumable(
+        params: &ConfigGenParams,
+        registry: ServerModuleGenRegistry,
+        delay_calculator: DelayCalculator,
+        task_group: &mut TaskGroup,
+        cache: &mut DkgCache,
+        progress: &watch::Sender<DkgProgress>,
+        checkpoint: &(dyn Fn(&DkgCache) + Sync),
     ) -> DkgResult<Self> {
         let _timing /* logs on drop */ = timing::TimeReporter::new("distributed-gen").info();
         let server_conn = connect(
@@ -474,6 +524,10 @@ impl ServerConfig {
         if peers.len() == 1 {
             let server =
                 Self::trusted_dealer_gen(&HashMap::from([(*our_id, params.clone())]), registry);
+            set_dkg_stage(progress, None, DkgStage::Done);
+            for (module_instance_id, _, _) in params.consensus.modules.iter_modules() {
+                set_dkg_stage(progress, Some(module_instance_id), DkgStage::Done);
+            }
             return Ok(server[our_id].clone());
         }
         info!(
@@ -481,83 +535,93 @@ impl ServerConfig {
             "Peer {} running distributed key generation...", our_id
         );
 
-        // hbbft uses a lower threshold of signing keys (f+1)
-        let mut dkg = DkgRunner::new(KeyType::Hbbft, peers.one_honest(), our_id, peers);
-        dkg.add(KeyType::Auth, peers.threshold());
-        dkg.add(KeyType::Epoch, peers.threshold());
+        agree_on_cached_parts(&connections, peers, our_id, cache).await?;
+        checkpoint(cache);
 
-        // run DKG for epoch and hbbft keys
-        let keys = dkg.run_g1(MODULE_INSTANCE_ID_GLOBAL, &connections).await?;
-        let auth_keys = keys[&KeyType::Auth].threshold_crypto();
-        let hbbft_keys = keys[&KeyType::Hbbft].threshold_crypto();
-        let epoch_keys = keys[&KeyType::Epoch].threshold_crypto();
+        let (auth_keys, epoch_keys, hbbft_keys) = match cache.global_keys.clone() {
+            Some(keys) => {
+                set_dkg_stage(progress, None, DkgStage::Done);
+                keys
+            }
+            None => {
+                set_dkg_stage(progress, None, DkgStage::Running);
+                // hbbft uses a lower threshold of signing keys (f+1)
+                let mut dkg = DkgRunner::new(KeyType::Hbbft, peers.one_honest(), our_id, peers);
+                dkg.add(KeyType::Auth, peers.threshold());
+                dkg.add(KeyType::Epoch, peers.threshold());
+
+                // run DKG for epoch and hbbft keys
+                let keys = match dkg.run_g1(MODULE_INSTANCE_ID_GLOBAL, &connections).await {
+                    Ok(keys) => keys,
+                    Err(e) => {
+                        set_dkg_stage(progress, None, DkgStage::Failed(e.to_string()));
+                        return Err(e);
+                    }
+                };
+                let keys = (
+                    keys[&KeyType::Auth].threshold_crypto(),
+                    keys[&KeyType::Epoch].threshold_crypto(),
+                    keys[&KeyType::Hbbft].threshold_crypto(),
+                );
+                cache.global_keys = Some(keys.clone());
+                checkpoint(cache);
+                set_dkg_stage(progress, None, DkgStage::Done);
+                keys
+            }
+        };
 
         let mut registered_modules = registry.kinds();
         let mut module_cfgs: BTreeMap<ModuleInstanceId, ServerModuleConfig> = Default::default();
-        let modules = params.consensus.modules.iter_modules();
+        let cached_modules: BTreeSet<ModuleInstanceId> = cache.modules.keys().copied().collect();
+        let modules = params
+            .consensus
+            .modules
+            .iter_modules()
+            .filter(|(module_instance_id, _, _)| !cached_modules.contains(module_instance_id));
         let modules_runner = modules.map(|(module_instance_id, kind, module_params)| {
             let dkg = PeerHandle::new(&connections, module_instance_id, *our_id, peers.clone());
             let registry = registry.clone();
 
             async move {
+                set_dkg_stage(progress, Some(module_instance_id), DkgStage::Running);
                 let result = match registry.get(kind) {
                     None => Err(DkgError::ModuleNotFound(kind.clone())),
                     Some(gen) => gen.distributed_gen(&dkg, module_params).await,
                 };
+                let stage = match &result {
+                    Ok(_) => DkgStage::Done,
+                    Err(e) => DkgStage::Failed(e.to_string()),
+                };
+                set_dkg_stage(progress, Some(module_instance_id), stage);
                 (module_instance_id, result)
             }
         });
-        for (module_instance_id, config) in join_all(modules_runner).await {
-            let config = config?;
+        let mut modules_runner: FuturesUnordered<_> = modules_runner.collect();
+        let mut first_error = None;
+        while let Some((module_instance_id, config)) = modules_runner.next().await {
+            match config {
+                Ok(config) => {
+                    cache.modules.insert(module_instance_id, config);
+                    checkpoint(cache);
+                }
+                Err(e) => {
+                    first_error.get_or_insert(e);
+                }
+            }
+        }
+        if let Some(e) = first_error {
+            return Err(e);
+        }
+        for (module_instance_id, config) in &cache.modules {
+            set_dkg_stage(progress, Some(*module_instance_id), DkgStage::Done);
             registered_modules.remove(config.consensus_json.kind());
-            module_cfgs.insert(module_instance_id, config);
+            module_cfgs.insert(*module_instance_id, config.clone());
         }
         if !registered_modules.is_empty() {
             return Err(DkgError::ParamsNotFound(registered_modules));
         }
 
-        info!(
-            target: LOG_NET_PEER_DKG,
-            "Sending confirmations to other peers."
-        );
-        // Note: Since our outgoing buffers are asynchronous, we don't actually know
-        // if other peers received our message, just because we received theirs.
-        // That's why we need to do a one last best effort sync.
-        let dkg_done = "DKG DONE".to_string();
-        connections
-            .send(
-                peers,
-                (MODULE_INSTANCE_ID_GLOBAL, dkg_done.clone()),
-                DkgPeerMsg::Done,
-            )
-            .await?;
-
-        info!(
-            target: LOG_NET_PEER_DKG,
-            "Waiting for confirmations from other peers."
-        );
-        if let Err(Elapsed) = timeout(Duration::from_secs(30), async {
-            let mut done_peers = BTreeSet::from([*our_id]);
-
-            while done_peers.len() < peers.len() {
-                match connections.receive((MODULE_INSTANCE_ID_GLOBAL, dkg_done.clone())).await {
-                    Ok((peer_id, DkgPeerMsg::Done)) => {
-                        info!(
-                            target: LOG_NET_PEER_DKG,
-                            pper_id = %peer_id, "Got completion confirmation");
-                        done_peers.insert(peer_id);
-                    },
-                    Ok((peer_id, msg)) => {
-                        error!(target: LOG_NET_PEER_DKG, %peer_id, ?msg, "Received incorrect message after dkg was supposed to be finished. Probably dkg multiplexing bug.");
-                    },
-                    Err(Cancelled) => {/* ignore shutdown for time being, we'll timeout soon anyway */},
-                }
-            }
-        })
-        .await
-        {
-            error!(target: LOG_NET_PEER_DKG, "Timeout waiting for dkg completion confirmation from other peers");
-        };
+        confirm_dkg_done(&connections, peers, our_id).await?;
 
         let server = ServerConfig::from(
             params.clone(),
@@ -577,6 +641,158 @@ impl ServerConfig {
     }
 }
 
+/// Results of the parts of a distributed key generation that already
+/// succeeded, so a failed DKG can be restarted without redoing them
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DkgCache {
+    /// Auth, epoch and hbbft keys
+    global_keys: Option<(ThresholdKeys, ThresholdKeys, ThresholdKeys)>,
+    /// Configs of the modules by instance id
+    modules: BTreeMap<ModuleInstanceId, ServerModuleConfig>,
+}
+
+impl DkgCache {
+    /// Forgets the config of a module so the next DKG generates it again
+    pub fn invalidate_module(&mut self, module_instance_id: ModuleInstanceId) {
+        self.modules.remove(&module_instance_id);
+    }
+
+    fn cached_parts(&self) -> DkgCachedParts {
+        DkgCachedParts {
+            global_keys: self.global_keys.is_some(),
+            modules: self.modules.keys().copied().collect(),
+        }
+    }
+
+    /// Forgets the results of all parts that aren't in `parts`
+    fn retain(&mut self, parts: &DkgCachedParts) {
+        if !parts.global_keys {
+            self.global_keys = None;
+        }
+        self.modules
+            .retain(|module_instance_id, _| parts.modules.contains(module_instance_id));
+    }
+}
+
+/// Sends the parts of the DKG we have cached to the other peers and forgets
+/// the parts any of them is missing
+///
+/// The DKG of a part can complete on some peers and fail on others, and
+/// guardians can ask to rerun different modules. Agreeing on the parts to
+/// reuse before starting makes all peers run the DKG of the same parts, which
+/// they need to do together.
+async fn agree_on_cached_parts(
+    connections: &MuxPeerConnections<(ModuleInstanceId, String), DkgPeerMsg>,
+    peers: &[PeerId],
+    our_id: &PeerId,
+    cache: &mut DkgCache,
+) -> DkgResult<()> {
+    let key = (MODULE_INSTANCE_ID_GLOBAL, "DKG CACHED PARTS".to_string());
+    let mut agreed = cache.cached_parts();
+    connections
+        .send(peers, key.clone(), DkgPeerMsg::CachedParts(agreed.clone()))
+        .await?;
+
+    let mut received_peers = BTreeSet::from([*our_id]);
+    while received_peers.len() < peers.len() {
+        match connections.receive(key.clone()).await? {
+            (peer_id, DkgPeerMsg::CachedParts(parts)) => {
+                if received_peers.insert(peer_id) {
+                    agreed.global_keys &= parts.global_keys;
+                    agreed.modules.retain(|id| parts.modules.contains(id));
+                }
+            }
+            (peer_id, msg) => {
+                return Err(DkgError::Failed(format_err!(
+                    "Peer {peer_id} sent {msg:?} instead of its cached DKG parts"
+                )));
+            }
+        }
+    }
+
+    let ours = cache.cached_parts();
+    if ours != agreed {
+        info!(
+            target: LOG_NET_PEER_DKG,
+            cached = ?ours,
+            ?agreed,
+            "Other peers are missing DKG results we cached, running those parts again"
+        );
+    }
+    cache.retain(&agreed);
+    Ok(())
+}
+
+/// Sends our DKG completion to the other peers and waits a limited time for
+/// theirs
+pub(crate) async fn confirm_dkg_done(
+    connections: &MuxPeerConnections<(ModuleInstanceId, String), DkgPeerMsg>,
+    peers: &[PeerId],
+    our_id: &PeerId,
+) -> DkgResult<()> {
+    info!(
+        target: LOG_NET_PEER_DKG,
+        "Sending confirmations to other peers."
+    );
+    // Note: Since our outgoing buffers are asynchronous, we don't actually know
+    // if other peers received our message, just because we received theirs.
+    // That's why we need to do a one last best effort sync.
+    let dkg_done = "DKG DONE".to_string();
+    connections
+        .send(
+            peers,
+            (MODULE_INSTANCE_ID_GLOBAL, dkg_done.clone()),
+            DkgPeerMsg::Done,
+        )
+        .await?;
+
+    info!(
+        target: LOG_NET_PEER_DKG,
+        "Waiting for confirmations from other peers."
+    );
+    if let Err(Elapsed) = timeout(Duration::from_secs(30), async {
+        let mut done_peers = BTreeSet::from([*our_id]);
+
+        while done_peers.len() < peers.len() {
+            match connections.receive((MODULE_INSTANCE_ID_GLOBAL, dkg_done.clone())).await {
+                Ok((peer_id, DkgPeerMsg::Done)) => {
+                    info!(
+                        target: LOG_NET_PEER_DKG,
+                        pper_id = %peer_id, "Got completion confirmation");
+                    done_peers.insert(peer_id);
+                },
+                Ok((peer_id, msg)) => {
+                    error!(target: LOG_NET_PEER_DKG, %peer_id, ?msg, "Received incorrect message after dkg was supposed to be finished. Probably dkg multiplexing bug.");
+                },
+                Err(Cancelled) => {/* ignore shutdown for time being, we'll timeout soon anyway */},
+            }
+        }
+    })
+    .await
+    {
+        error!(target: LOG_NET_PEER_DKG, "Timeout waiting for dkg completion confirmation from other peers");
+    };
+
+    Ok(())
+}
+
+/// Updates the stage of the global keys (`None`) or a module's keys
+fn set_dkg_stage(
+    progress: &watch::Sender<DkgProgress>,
+    module_instance_id: Option<ModuleInstanceId>,
+    stage: DkgStage,
+) {
+    progress.send_modify(|progress| {
+        progress.version += 1;
+        match module_instance_id {
+            None => progress.global = stage,
+            Some(id) => {
+                progress.modules.insert(id, stage);
+            }
+        }
+    });
+}
+
 /// The types of keys to run distributed key generation for
 #[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
 pub enum KeyType {
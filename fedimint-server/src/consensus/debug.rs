@@ -40,5 +40,15 @@ fn item_message(item: &ConsensusItem) -> String {
             tx_debug
         }
         ConsensusItem::ConsensusUpgrade(_) => "Consensus Upgrade".to_string(),
+        ConsensusItem::MetaUpdate(_) => "Meta Update".to_string(),
+        ConsensusItem::ModuleAddition(addition) => format!(
+            "Module Addition {} ({})",
+            addition.module_instance_id, addition.kind
+        ),
+        ConsensusItem::EncryptedModule(item) => format!("Encrypted Module CI: id={}", item.id()),
+        ConsensusItem::DecryptionShare(share) => format!("Decryption Share: id={}", share.item_id),
+        ConsensusItem::ModuleAdditionCancel(cancel) => {
+            format!("Module Addition Cancel {}", cancel.module_instance_id)
+        }
     }
 }
@@ -0,0 +1,80 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use fedimint_core::core::ModuleKind;
+use fedimint_core::db::Database;
+use fedimint_core::module::interconnect::{ErasedInterconnectValue, IModuleInterconnect};
+use fedimint_core::module::registry::ServerModuleRegistry;
+use fedimint_core::module::ApiError;
+use tokio::sync::OnceCell;
+
+/// Lets modules call the interconnect handlers of other modules of this server
+///
+/// Modules receive it when they are initialized, so the registry is only set
+/// once all of them are.
+#[derive(Debug, Clone)]
+pub struct ServerModuleInterconnect {
+    db: Database,
+    modules: Arc<OnceCell<ServerModuleRegistry>>,
+}
+
+impl ServerModuleInterconnect {
+    pub fn new(db: Database) -> Self {
+        Self {
+            db,
+            modules: Arc::new(OnceCell::new()),
+        }
+    }
+
+    /// Makes the modules available for calls, can only be done once
+    pub fn set_modules(&self, modules: ServerModuleRegistry) {
+        self.modules
+            .set(modules)
+            .expect("Modules are only set once");
+    }
+}
+
+#[async_trait]
+impl IModuleInterconnect for ServerModuleInterconnect {
+    async fn call_erased(
+        &self,
+        kind: &ModuleKind,
+        name: &'static str,
+        version: u32,
+        request: ErasedInterconnectValue,
+    ) -> Result<ErasedInterconnectValue, ApiError> {
+        let modules = self
+            .modules
+            .get()
+            .ok_or_else(|| ApiError::server_error("Modules are not initialized yet".to_string()))?;
+        let (module_instance_id, _, module) = modules
+            .iter_modules()
+            .find(|(_, module_kind, _)| *module_kind == kind)
+            .ok_or_else(|| ApiError::not_found(format!("No {kind} module")))?;
+        let handlers = module
+            .interconnect_handlers()
+            .into_iter()
+            .filter(|handler| handler.name == name)
+            .collect::<Vec<_>>();
+        if handlers.is_empty() {
+            return Err(ApiError::not_found(format!("No {kind} call {name}")));
+        }
+        let Some(handler) = handlers.iter().find(|handler| handler.version == version) else {
+            let versions = handlers
+                .iter()
+                .map(|handler| handler.version.to_string())
+                .collect::<Vec<_>>();
+            return Err(ApiError::not_found(format!(
+                "{kind} call {name} is served in versions {}, not {version}",
+                versions.join(", ")
+            )));
+        };
+
+        let dbtx = self
+            .db
+            .begin_transaction()
+            .await
+            .new_module_tx(module_instance_id);
+        (handler.handler)(module, dbtx, request).await
+    }
+}
@@ -1,34 +1,45 @@
 #![allow(clippy::let_unit_value)]
 
 pub mod debug;
+pub mod interconnect;
 pub mod server;
+pub mod timing;
 
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::iter::FromIterator;
 
+use bitcoin_hashes::sha256;
 use fedimint_core::config::ServerModuleGenRegistry;
-use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::core::{DynModuleConsensusItem, ModuleInstanceId};
 use fedimint_core::db::{Database, DatabaseTransaction};
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::epoch::*;
 use fedimint_core::module::audit::Audit;
 use fedimint_core::module::registry::{ModuleDecoderRegistry, ServerModuleRegistry};
 use fedimint_core::module::{ModuleError, TransactionItemAmount};
+use fedimint_core::receipt::TransactionAmounts;
 use fedimint_core::server::DynVerificationCache;
 use fedimint_core::{timing, Amount, NumPeers, OutPoint, PeerId, TransactionId};
 use fedimint_logging::LOG_CONSENSUS;
 use futures::future::select_all;
 use futures::StreamExt;
+use hbbft::crypto::PublicKeySet;
 use hbbft::honey_badger::Batch;
 use itertools::Itertools;
 use thiserror::Error;
-use tracing::{error, info_span, instrument, trace, warn, Instrument};
+use tracing::field::display;
+use tracing::{error, info, info_span, instrument, trace, warn, Instrument};
 
 use crate::config::ServerConfig;
 use crate::consensus::TransactionSubmissionError::TransactionReplayError;
 use crate::db::{
-    AcceptedTransactionKey, ClientConfigSignatureKey, ConsensusUpgradeKey, DropPeerKey,
-    DropPeerKeyPrefix, EpochHistoryKey, LastEpochKey, RejectedTransactionKey,
+    AcceptedTransactionKey, ClientConfigSignatureKey, ConsensusUpgradeKey,
+    DecryptionShareItemPrefix, DecryptionShareKey, DropPeerKey, DropPeerKeyPrefix, EpochHistoryKey,
+    FederationMetaKey, JournaledItemKey, JournaledItemKeyPrefix, LastEpochKey, MetaVoteKey,
+    MetaVoteKeyPrefix, ModuleAdditionFailedKey, ModuleAdditionLocalParamsKey,
+    ModuleAdditionVoteKey, ModuleAdditionVoteKeyPrefix, PeerScoreKey, PendingEncryptedItemKey,
+    PendingEncryptedItemKeyPrefix, RejectedTransactionKey, RevealedEncryptedItemKey,
+    ScheduledModuleAdditionKey, TransactionAmountsKey,
 };
 use crate::net::api::ConsensusApi;
 use crate::transaction::{Transaction, TransactionError};
@@ -56,11 +67,10 @@ impl From<EpochOutcome> for ConsensusOutcomeConversion {
     }
 }
 
-/// Proposed HBBFT consensus changes including removing peers
+/// Proposed HBBFT consensus changes
 #[derive(Debug, Clone)]
 pub struct ConsensusProposal {
     pub items: Vec<ConsensusItem>,
-    pub drop_peers: Vec<PeerId>,
     pub force_new_epoch: bool,
 }
 
@@ -70,6 +80,21 @@ pub enum ApiEvent {
     Transaction(Transaction),
     UpgradeSignal,
     ForceProcessOutcome(EpochOutcome),
+    MetaUpdate(BTreeMap<String, String>),
+    ModuleAddition(ModuleAddition),
+    ModuleAdditionCancel(ModuleInstanceId),
+    /// Triggers an epoch without contributing anything
+    TriggerEpoch,
+}
+
+/// Misbehavior of a peer observed while processing an epoch, weighted by the
+/// `MisbehaviorThresholds` of the config
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Misbehavior {
+    InvalidSignature,
+    MissingProposal,
+    Equivocation,
+    InvalidItem,
 }
 
 // TODO: we should make other fields private and get rid of this
@@ -87,6 +112,10 @@ pub struct FedimintConsensus {
     pub api: ConsensusApi,
     /// Cache of `ApiEvent` to include in a proposal
     pub api_event_cache: HashSet<ApiEvent>,
+    /// Ciphertexts of the module items we currently propose encrypted, keyed by
+    /// the hash of the item. Re-proposing the same ciphertext lets it be
+    /// deduplicated once it has been ordered.
+    pub encrypted_items: std::sync::Mutex<HashMap<sha256::Hash, EncryptedConsensusItem>>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
@@ -95,6 +124,14 @@ pub struct AcceptedTransaction {
     pub transaction: Transaction,
 }
 
+/// An ordered encrypted module consensus item and the peers that contributed
+/// it, only the peer that encrypted it may do so
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Encodable, Decodable)]
+pub struct PendingEncryptedItem {
+    pub peers: BTreeSet<PeerId>,
+    pub item: EncryptedConsensusItem,
+}
+
 #[derive(Debug)]
 struct VerificationCaches {
     caches: HashMap<ModuleInstanceId, DynVerificationCache>,
@@ -142,24 +179,49 @@ impl FedimintConsensus {
                 |dbtx| {
                     let consensus_outcome = consensus_outcome.clone();
                     let reference_rejected_txs = reference_rejected_txs.clone();
-                    let peers: BTreeSet<PeerId> = consensus_outcome.contributions.keys().copied().collect();
+                    let contributing_peers: BTreeSet<PeerId> = consensus_outcome.contributions.keys().copied().collect();
 
                     Box::pin(async move {
                         let epoch = consensus_outcome.epoch;
                         let outcome = consensus_outcome.clone();
 
+                        // Contributions of quarantined peers are ignored
+                        let quarantined_peers = self.quarantined_peers(dbtx).await;
+                        let peers: BTreeSet<PeerId> = contributing_peers
+                            .difference(&quarantined_peers)
+                            .copied()
+                            .collect();
+                        self.decay_peer_scores(dbtx, &peers).await;
+                        self.process_equivocations(dbtx, &outcome).await;
+
                         let UnzipConsensusItem {
                             epoch_outcome_signature_share: _epoch_outcome_signature_share_cis,
                             client_config_signature_share: _client_config_signature_share_cis,
                             transaction: transaction_cis,
                             consensus_upgrade: consensus_upgrade_cis,
                             module: module_cis,
+                            meta_update: meta_update_cis,
+                            module_addition: module_addition_cis,
+                            encrypted_module: encrypted_module_cis,
+                            decryption_share: decryption_share_cis,
+                            module_addition_cancel: module_addition_cancel_cis,
                         } = consensus_outcome
                             .contributions
                             .into_iter()
+                            .filter(|(peer, _)| !quarantined_peers.contains(peer))
                             .flat_map(|(peer, cis)| cis.into_iter().map(move |ci| (peer, ci)))
                             .unzip_consensus_item();
 
+                        let mut module_cis = module_cis;
+                        module_cis.extend(
+                            self.process_encrypted_items(
+                                dbtx,
+                                &encrypted_module_cis,
+                                &decryption_share_cis,
+                            )
+                            .await,
+                        );
+                        self.clear_journaled_items(dbtx, &module_cis).await;
                         self.process_module_consensus_items(dbtx, &module_cis, &peers).await;
                         self.process_upgrade_items(dbtx, &consensus_upgrade_cis).await;
 
@@ -181,6 +243,15 @@ impl FedimintConsensus {
                         let epoch_history = self
                             .finalize_process_epoch(dbtx, outcome.clone(), rejected_txs, &peers)
                             .await;
+                        // After the client config signature shares of this epoch were processed
+                        self.process_meta_updates(dbtx, &meta_update_cis).await;
+                        self.process_module_additions(
+                            dbtx,
+                            &module_addition_cis,
+                            &module_addition_cancel_cis,
+                        )
+                        .await;
+                        self.update_quarantine(dbtx, epoch, &contributing_peers).await;
                         Result::<_, ()>::Ok(epoch_history)
                     })
                 },
@@ -206,7 +277,7 @@ impl FedimintConsensus {
         consensus_peers: &BTreeSet<PeerId>,
     ) {
         let _timing /* logs on drop */ = timing::TimeReporter::new("process_module_consensus_items");
-        let mut drop_peers = vec![];
+        let mut misbehaving_peers = vec![];
         let per_module_cis: HashMap<
             ModuleInstanceId,
             Vec<(PeerId, fedimint_core::core::DynModuleConsensusItem)>,
@@ -217,17 +288,42 @@ impl FedimintConsensus {
 
         for (module_key, module_cis) in per_module_cis {
             let moduletx = &mut dbtx.with_module_prefix(module_key);
-            let mut module_drop_peers = self
+            let mut module_misbehaving_peers = self
                 .modules
                 .get_expect(module_key)
                 .begin_consensus_epoch(moduletx, module_cis, consensus_peers)
                 .await;
-            drop_peers.append(&mut module_drop_peers);
+            misbehaving_peers.append(&mut module_misbehaving_peers);
         }
 
-        for peer in drop_peers {
-            dbtx.insert_entry(&DropPeerKey(peer), &()).await;
+        for peer in misbehaving_peers {
+            self.record_misbehavior(dbtx, peer, Misbehavior::InvalidItem)
+                .await;
+        }
+    }
+
+    /// Processes the ordered encrypted items and decryption shares, see
+    /// [`process_encrypted_items`], and scores the misbehaving peers
+    async fn process_encrypted_items(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        encrypted_cis: &[(PeerId, EncryptedConsensusItem)],
+        decryption_share_cis: &[(PeerId, DecryptionShareItem)],
+    ) -> Vec<(PeerId, DynModuleConsensusItem)> {
+        let (revealed, misbehavior) = process_encrypted_items(
+            dbtx,
+            &self.cfg.consensus.epoch_pk_set,
+            &self.decoders(),
+            encrypted_cis,
+            decryption_share_cis,
+        )
+        .await;
+
+        for (peer, misbehavior) in misbehavior {
+            self.record_misbehavior(dbtx, peer, misbehavior).await;
         }
+
+        revealed
     }
 
     /// Applies all valid fedimint transactions to the database transaction
@@ -253,7 +349,12 @@ impl FedimintConsensus {
                 continue;
             }
 
-            let span = info_span!("Processing transaction");
+            let correlation_id = self.api.take_transaction_correlation_id(txid);
+            let span = info_span!(
+                "Processing transaction",
+                %txid,
+                correlation_id = correlation_id.map(display)
+            );
             async {
                 trace!(?transaction);
 
@@ -295,7 +396,7 @@ impl FedimintConsensus {
     }
 
     /// Saves the epoch history, calls `end_consensus_epoch` on all modules and
-    /// bans misbehaving peers
+    /// scores misbehaving peers
     async fn finalize_process_epoch(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
@@ -304,36 +405,49 @@ impl FedimintConsensus {
         consensus_peers: &BTreeSet<PeerId>,
     ) -> SignedEpochOutcome {
         let _timing /* logs on drop */ = timing::TimeReporter::new("finalize_process_epoch");
-        let mut drop_peers = Vec::<PeerId>::new();
+        let mut invalid_sig_peers = Vec::<PeerId>::new();
 
-        self.save_client_config_sig(dbtx, &outcome, &mut drop_peers)
+        self.save_client_config_sig(dbtx, &outcome, consensus_peers, &mut invalid_sig_peers)
             .await;
 
         let epoch_history = self
-            .save_epoch_history(outcome.clone(), dbtx, &mut drop_peers, rejected_txs)
+            .save_epoch_history(
+                outcome.clone(),
+                dbtx,
+                consensus_peers,
+                &mut invalid_sig_peers,
+                rejected_txs,
+            )
             .await;
 
+        let mut module_misbehaving_peers = Vec::<PeerId>::new();
         for (module_key, _, module) in self.modules.iter_modules() {
-            let module_drop_peers = module
+            let misbehaving_peers = module
                 .end_consensus_epoch(consensus_peers, &mut dbtx.with_module_prefix(module_key))
                 .await;
-            drop_peers.extend(module_drop_peers);
+            module_misbehaving_peers.extend(misbehaving_peers);
         }
 
-        for peer in drop_peers {
-            dbtx.insert_entry(&DropPeerKey(peer), &()).await;
+        for peer in invalid_sig_peers {
+            self.record_misbehavior(dbtx, peer, Misbehavior::InvalidSignature)
+                .await;
+        }
+        for peer in module_misbehaving_peers {
+            self.record_misbehavior(dbtx, peer, Misbehavior::InvalidItem)
+                .await;
         }
 
         epoch_history
     }
 
     /// If the client config hash isn't already signed, aggregate signature
-    /// shares from peers dropping those that don't contribute.
+    /// shares from peers reporting those that don't contribute.
     async fn save_client_config_sig(
         &self,
         dbtx: &mut DatabaseTransaction<'_>,
         outcome: &HbbftConsensusOutcome,
-        drop_peers: &mut Vec<PeerId>,
+        consensus_peers: &BTreeSet<PeerId>,
+        invalid_sig_peers: &mut Vec<PeerId>,
     ) {
         let sig = dbtx
             .get_isolated()
@@ -342,8 +456,11 @@ impl FedimintConsensus {
 
         if sig.is_none() {
             let _timing /* logs on drop */ = timing::TimeReporter::new("combine and verify client config sigs");
-            let client_hash = self.api.client_cfg.consensus_hash();
-            let peers: Vec<PeerId> = outcome.contributions.keys().cloned().collect();
+            let client_hash = self
+                .api
+                .client_config(&mut dbtx.get_isolated())
+                .await
+                .consensus_hash();
             let pks = self.cfg.consensus.auth_pk_set.clone();
 
             let shares: BTreeMap<_, _> = outcome
@@ -367,9 +484,9 @@ impl FedimintConsensus {
                         target: LOG_CONSENSUS,
                         "Did not receive enough valid client config sig shares"
                     );
-                    for peer in peers {
-                        if !contributing_peers.contains(&peer) {
-                            drop_peers.push(peer);
+                    for peer in consensus_peers {
+                        if !contributing_peers.contains(peer) {
+                            invalid_sig_peers.push(*peer);
                         }
                     }
                 }
@@ -392,6 +509,352 @@ impl FedimintConsensus {
         }
     }
 
+    /// Records the meta votes of peers and replaces the federation meta once a
+    /// threshold of peers voted for the same one
+    async fn process_meta_updates(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        meta_updates: &[(PeerId, MetaUpdate)],
+    ) {
+        if meta_updates.is_empty() {
+            return;
+        }
+
+        for (peer, MetaUpdate(meta)) in meta_updates {
+            dbtx.insert_entry(&MetaVoteKey(*peer), meta).await;
+        }
+
+        let votes = dbtx
+            .find_by_prefix(&MetaVoteKeyPrefix)
+            .await
+            .map(|(_, meta)| meta)
+            .collect::<Vec<_>>()
+            .await;
+        let threshold = self.cfg.consensus.api_endpoints.threshold();
+        let agreed = votes
+            .iter()
+            .find(|meta| votes.iter().filter(|vote| vote == meta).count() >= threshold);
+
+        if let Some(meta) = agreed {
+            info!(target: LOG_CONSENSUS, ?meta, "Federation meta updated");
+            dbtx.insert_entry(&FederationMetaKey, meta).await;
+            dbtx.remove_by_prefix(&MetaVoteKeyPrefix).await;
+            // The client config changed so it needs to be signed again
+            dbtx.remove_entry(&ClientConfigSignatureKey).await;
+        }
+    }
+
+    /// Records the module addition votes of peers and schedules the addition
+    /// once all peers voted for the same one, cancellations remove the votes
+    /// and the scheduled addition of a module
+    async fn process_module_additions(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        module_additions: &[(PeerId, ModuleAddition)],
+        cancels: &[(PeerId, ModuleAdditionCancel)],
+    ) {
+        if module_additions.is_empty() && cancels.is_empty() {
+            return;
+        }
+
+        for (peer, cancel) in cancels {
+            let module_instance_id = cancel.module_instance_id;
+            let votes = dbtx
+                .find_by_prefix(&ModuleAdditionVoteKeyPrefix)
+                .await
+                .collect::<Vec<_>>()
+                .await;
+            for (key, addition) in votes {
+                if addition.module_instance_id == module_instance_id {
+                    dbtx.remove_entry(&key).await;
+                }
+            }
+
+            let scheduled = dbtx.get_value(&ScheduledModuleAdditionKey).await;
+            if scheduled.map_or(false, |addition| {
+                addition.module_instance_id == module_instance_id
+            }) {
+                warn!(
+                    target: LOG_CONSENSUS,
+                    %peer,
+                    module_instance_id,
+                    "Module addition cancelled"
+                );
+                dbtx.remove_entry(&ScheduledModuleAdditionKey).await;
+            }
+            dbtx.remove_entry(&ModuleAdditionFailedKey(module_instance_id))
+                .await;
+        }
+
+        for (peer, addition) in module_additions {
+            if self
+                .cfg
+                .consensus
+                .modules
+                .contains_key(&addition.module_instance_id)
+                || self.module_inits.get(&addition.kind).is_none()
+            {
+                warn!(target: LOG_CONSENSUS, %peer, ?addition, "Ignoring invalid module addition");
+                continue;
+            }
+            dbtx.insert_entry(&ModuleAdditionVoteKey(*peer), addition)
+                .await;
+        }
+
+        // Only one module can be added at a time
+        if dbtx.get_value(&ScheduledModuleAdditionKey).await.is_some() {
+            return;
+        }
+
+        let votes = dbtx
+            .find_by_prefix(&ModuleAdditionVoteKeyPrefix)
+            .await
+            .map(|(_, addition)| addition)
+            .collect::<Vec<_>>()
+            .await;
+        // Every peer takes part in the DKG of the module, so all have to agree
+        let peers = self.cfg.consensus.api_endpoints.len();
+        let agreed = votes
+            .iter()
+            .find(|addition| votes.iter().filter(|vote| vote == addition).count() == peers);
+
+        if let Some(addition) = agreed {
+            info!(target: LOG_CONSENSUS, ?addition, "Module addition scheduled");
+            dbtx.insert_entry(&ScheduledModuleAdditionKey, addition)
+                .await;
+            dbtx.remove_by_prefix(&ModuleAdditionVoteKeyPrefix).await;
+        }
+    }
+
+    /// Removes the scheduled module addition once our config contains the
+    /// module, the client config changed so it needs to be signed again
+    pub async fn complete_module_addition(&self) {
+        let mut dbtx = self.db.begin_transaction().await;
+        let Some(addition) = dbtx.get_value(&ScheduledModuleAdditionKey).await else {
+            return;
+        };
+        if !self
+            .cfg
+            .consensus
+            .modules
+            .contains_key(&addition.module_instance_id)
+        {
+            return;
+        }
+
+        info!(target: LOG_CONSENSUS, ?addition, "Module addition completed");
+        dbtx.remove_entry(&ScheduledModuleAdditionKey).await;
+        dbtx.remove_entry(&ModuleAdditionLocalParamsKey(addition.module_instance_id))
+            .await;
+        dbtx.remove_entry(&ClientConfigSignatureKey).await;
+        dbtx.commit_tx().await;
+    }
+
+    /// Returns the module addition all peers agreed on
+    pub async fn scheduled_module_addition(&self) -> Option<ModuleAddition> {
+        self.db
+            .begin_transaction()
+            .await
+            .get_value(&ScheduledModuleAdditionKey)
+            .await
+    }
+
+    /// Returns the scheduled module addition if its activation epoch has been
+    /// processed, our config doesn't contain the module yet and its DKG didn't
+    /// fail for us
+    pub async fn due_module_addition(&self) -> Option<ModuleAddition> {
+        let mut dbtx = self.db.begin_transaction().await;
+        let addition = dbtx.get_value(&ScheduledModuleAdditionKey).await?;
+        let last_epoch = dbtx.get_value(&LastEpochKey).await?;
+        let added = self
+            .cfg
+            .consensus
+            .modules
+            .contains_key(&addition.module_instance_id);
+        let failed = dbtx
+            .get_value(&ModuleAdditionFailedKey(addition.module_instance_id))
+            .await
+            .is_some();
+
+        (!added && !failed && last_epoch.0 >= addition.activation_epoch).then_some(addition)
+    }
+
+    /// Records that the DKG of a module addition failed for us, so consensus
+    /// resumes and we propose cancelling the addition instead of retrying it
+    pub async fn fail_module_addition(&self, addition: &ModuleAddition) {
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.insert_entry(&ModuleAdditionFailedKey(addition.module_instance_id), &())
+            .await;
+        dbtx.commit_tx().await;
+    }
+
+    /// Returns the peers whose contributions are currently ignored
+    async fn quarantined_peers(&self, dbtx: &mut DatabaseTransaction<'_>) -> BTreeSet<PeerId> {
+        dbtx.find_by_prefix(&DropPeerKeyPrefix)
+            .await
+            .map(|(key, _)| key.0)
+            .collect()
+            .await
+    }
+
+    /// Adds the misbehavior to the score of `peer`, quarantined peers aren't
+    /// scored since their contributions are ignored anyway
+    async fn record_misbehavior(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        peer: PeerId,
+        misbehavior: Misbehavior,
+    ) {
+        let thresholds = &self.cfg.consensus.misbehavior;
+        let mut score = dbtx
+            .get_value(&PeerScoreKey(peer))
+            .await
+            .unwrap_or_default();
+        if score.quarantined_until.is_some() {
+            return;
+        }
+
+        let weight = match misbehavior {
+            Misbehavior::InvalidSignature => {
+                score.invalid_signatures += 1;
+                thresholds.invalid_signature
+            }
+            Misbehavior::MissingProposal => {
+                score.missing_proposals += 1;
+                thresholds.missing_proposal
+            }
+            Misbehavior::Equivocation => {
+                score.equivocations += 1;
+                thresholds.equivocation
+            }
+            Misbehavior::InvalidItem => {
+                score.invalid_items += 1;
+                thresholds.invalid_item
+            }
+        };
+        score.score = score.score.saturating_add(weight);
+
+        if misbehavior != Misbehavior::MissingProposal {
+            warn!(
+                target: LOG_CONSENSUS,
+                %peer, ?misbehavior, score = score.score, "Peer misbehaved"
+            );
+        }
+        dbtx.insert_entry(&PeerScoreKey(peer), &score).await;
+    }
+
+    /// Lowers the scores of the peers that contributed to the epoch
+    async fn decay_peer_scores(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        consensus_peers: &BTreeSet<PeerId>,
+    ) {
+        for peer in consensus_peers {
+            if let Some(mut score) = dbtx.get_value(&PeerScoreKey(*peer)).await {
+                if score.score > 0 {
+                    score.score = score
+                        .score
+                        .saturating_sub(self.cfg.consensus.misbehavior.decay);
+                    dbtx.insert_entry(&PeerScoreKey(*peer), &score).await;
+                }
+            }
+        }
+    }
+
+    /// Reports peers that contributed conflicting signature shares in one
+    /// epoch, honest peers contribute at most one of each
+    async fn process_equivocations(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        outcome: &HbbftConsensusOutcome,
+    ) {
+        for (peer, items) in outcome.contributions.iter() {
+            let mut epoch_sigs = HashSet::new();
+            let mut config_sigs = HashSet::new();
+            let mut decryption_shares = HashMap::new();
+            let mut equivocated = false;
+
+            for item in items {
+                equivocated |= match item {
+                    ConsensusItem::EpochOutcomeSignatureShare(sig) => {
+                        epoch_sigs.insert(sig);
+                        epoch_sigs.len() > 1
+                    }
+                    ConsensusItem::ClientConfigSignatureShare(sig) => {
+                        config_sigs.insert(sig);
+                        config_sigs.len() > 1
+                    }
+                    ConsensusItem::DecryptionShare(share) => decryption_shares
+                        .insert(share.item_id, &share.share)
+                        .map_or(false, |other| other != &share.share),
+                    _ => false,
+                };
+            }
+
+            if equivocated {
+                self.record_misbehavior(dbtx, *peer, Misbehavior::Equivocation)
+                    .await;
+            }
+        }
+    }
+
+    /// Counts the epochs peers didn't contribute to, quarantines the peers
+    /// that reached the threshold and lifts expired quarantines.
+    ///
+    /// Missing proposals only add `MisbehaviorThresholds::missing_proposal`,
+    /// which is zero by default: whether a contribution gets ordered in time
+    /// depends on the network as much as on the peer, so honest peers behind a
+    /// slow link would get quarantined.
+    async fn update_quarantine(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        epoch: u64,
+        contributing_peers: &BTreeSet<PeerId>,
+    ) {
+        let thresholds = &self.cfg.consensus.misbehavior;
+        let all_peers = &self.cfg.consensus.api_endpoints;
+        let mut num_quarantined = self.quarantined_peers(dbtx).await.len();
+
+        for peer in all_peers.keys() {
+            if !contributing_peers.contains(peer) {
+                self.record_misbehavior(dbtx, *peer, Misbehavior::MissingProposal)
+                    .await;
+            }
+
+            let mut score = match dbtx.get_value(&PeerScoreKey(*peer)).await {
+                Some(score) => score,
+                None => continue,
+            };
+
+            match score.quarantined_until {
+                Some(until) if until <= epoch => {
+                    info!(target: LOG_CONSENSUS, %peer, "Lifting quarantine of peer");
+                    score.score = 0;
+                    score.quarantined_until = None;
+                    dbtx.remove_entry(&DropPeerKey(*peer)).await;
+                    num_quarantined -= 1;
+                }
+                // Quarantining more than the tolerated number of faulty peers would halt
+                // consensus
+                None if score.score >= thresholds.quarantine_score
+                    && num_quarantined < all_peers.max_evil() =>
+                {
+                    let until = epoch + thresholds.quarantine_epochs;
+                    error!(
+                        target: LOG_CONSENSUS,
+                        %peer, score = score.score, "Quarantining misbehaving peer until epoch {until}"
+                    );
+                    score.quarantined_until = Some(until);
+                    dbtx.insert_entry(&DropPeerKey(*peer), &()).await;
+                    num_quarantined += 1;
+                }
+                _ => continue,
+            }
+
+            dbtx.insert_entry(&PeerScoreKey(*peer), &score).await;
+        }
+    }
+
     /// Returns true if a threshold of peers have signaled to upgrade
     pub async fn is_at_upgrade_threshold(&self) -> bool {
         self.db
@@ -407,11 +870,11 @@ impl FedimintConsensus {
         &self,
         outcome: HbbftConsensusOutcome,
         dbtx: &mut DatabaseTransaction<'a>,
-        drop_peers: &mut Vec<PeerId>,
+        consensus_peers: &BTreeSet<PeerId>,
+        invalid_sig_peers: &mut Vec<PeerId>,
         rejected_txs: BTreeSet<TransactionId>,
     ) -> SignedEpochOutcome {
         let prev_epoch_key = EpochHistoryKey(outcome.epoch.saturating_sub(1));
-        let peers: Vec<PeerId> = outcome.contributions.keys().cloned().collect();
         let maybe_prev_epoch = dbtx.get_value(&prev_epoch_key).await;
 
         let current = SignedEpochOutcome::new(
@@ -434,13 +897,13 @@ impl FedimintConsensus {
                         target: LOG_CONSENSUS,
                         "Unable to sign epoch {}", prev_epoch_key.0
                     );
-                    for peer in peers {
-                        if !contributing_peers.contains(&peer) {
+                    for peer in consensus_peers {
+                        if !contributing_peers.contains(peer) {
                             warn!(
                                 target: LOG_CONSENSUS,
-                                "Dropping {} for not contributing valid epoch sigs.", peer
+                                "{} didn't contribute valid epoch sigs.", peer
                             );
-                            drop_peers.push(peer);
+                            invalid_sig_peers.push(*peer);
                         }
                     }
                 }
@@ -479,13 +942,6 @@ impl FedimintConsensus {
     pub async fn get_consensus_proposal(&self) -> ConsensusProposal {
         let mut dbtx = self.db.begin_transaction().await;
 
-        let drop_peers = dbtx
-            .find_by_prefix(&DropPeerKeyPrefix)
-            .await
-            .map(|(key, _)| key.0)
-            .collect()
-            .await;
-
         let mut items: Vec<ConsensusItem> = self
             .api_event_cache
             .iter()
@@ -494,10 +950,34 @@ impl FedimintConsensus {
                 ApiEvent::Transaction(tx) => Some(ConsensusItem::Transaction(tx)),
                 ApiEvent::UpgradeSignal => Some(ConsensusItem::ConsensusUpgrade(ConsensusUpgrade)),
                 ApiEvent::ForceProcessOutcome(_) => None,
+                ApiEvent::MetaUpdate(meta) => Some(ConsensusItem::MetaUpdate(MetaUpdate(meta))),
+                ApiEvent::ModuleAddition(addition) => Some(ConsensusItem::ModuleAddition(addition)),
+                ApiEvent::ModuleAdditionCancel(module_instance_id) => {
+                    Some(ConsensusItem::ModuleAdditionCancel(ModuleAdditionCancel {
+                        module_instance_id,
+                    }))
+                }
+                ApiEvent::TriggerEpoch => None,
             })
             .collect();
         let mut force_new_epoch = false;
 
+        // Cancel a module addition whose DKG failed for us until it's cancelled
+        if let Some(addition) = dbtx.get_value(&ScheduledModuleAdditionKey).await {
+            let module_instance_id = addition.module_instance_id;
+            if dbtx
+                .get_value(&ModuleAdditionFailedKey(module_instance_id))
+                .await
+                .is_some()
+            {
+                items.push(ConsensusItem::ModuleAdditionCancel(ModuleAdditionCancel {
+                    module_instance_id,
+                }));
+                force_new_epoch = true;
+            }
+        }
+
+        let mut module_items = vec![];
         for (instance_id, _, module) in self.modules.iter_modules() {
             let consensus_proposal = module
                 .consensus_proposal(&mut dbtx.with_module_prefix(instance_id), instance_id)
@@ -505,13 +985,62 @@ impl FedimintConsensus {
             if consensus_proposal.forces_new_epoch() {
                 force_new_epoch = true;
             }
+            module_items.extend(consensus_proposal.into_items());
+        }
 
-            items.extend(
-                consensus_proposal
-                    .into_items()
-                    .into_iter()
-                    .map(ConsensusItem::Module),
-            );
+        // Journaled items are proposed until they were ordered, even if the module lost
+        // track of them, e.g. because we restarted. They don't force a new epoch, we
+        // might never get them ordered while quarantined.
+        let journaled = self.journal_module_items(&module_items).await;
+        let proposed = module_items
+            .iter()
+            .map(|item| item.consensus_hash())
+            .collect::<HashSet<sha256::Hash>>();
+        for (hash, item) in journaled {
+            if !proposed.contains(&hash) {
+                module_items.push(item);
+            }
+        }
+
+        // Encryption is randomized, so we keep proposing the ciphertext we created first
+        // for an item. Otherwise every proposal of it would be ordered as a new item.
+        let epoch_pk = self.cfg.consensus.epoch_pk_set.public_key();
+        {
+            let mut encrypted_items = self.encrypted_items.lock().expect("lock poisoned");
+            let mut still_proposed = HashMap::new();
+            for item in module_items {
+                let module = self.modules.get_expect(item.module_instance_id());
+                if module.encrypt_consensus_item(&item) {
+                    let hash = item.consensus_hash();
+                    let encrypted = encrypted_items.remove(&hash).unwrap_or_else(|| {
+                        EncryptedConsensusItem::encrypt(&epoch_pk, self.cfg.local.identity, &item)
+                    });
+                    items.push(ConsensusItem::EncryptedModule(encrypted.clone()));
+                    still_proposed.insert(hash, encrypted);
+                } else {
+                    items.push(ConsensusItem::Module(item));
+                }
+            }
+            *encrypted_items = still_proposed;
+        }
+
+        // Contribute our decryption shares for ordered encrypted items, new epochs are
+        // forced until they are revealed
+        let pending_items = dbtx
+            .find_by_prefix(&PendingEncryptedItemKeyPrefix)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+        for (key, pending) in pending_items {
+            let our_share = DecryptionShareKey(key.0, self.cfg.local.identity);
+            if dbtx.get_value(&our_share).await.is_some() {
+                continue;
+            }
+
+            if let Some(share) = pending.item.decryption_share(&self.cfg.private.epoch_sks) {
+                items.push(ConsensusItem::DecryptionShare(share));
+                force_new_epoch = true;
+            }
         }
 
         if let Some(epoch) = dbtx.get_value(&LastEpochKey).await {
@@ -531,7 +1060,11 @@ impl FedimintConsensus {
             .get_value(&ClientConfigSignatureKey)
             .await;
         if sig.is_none() {
-            let hash = self.api.client_cfg.consensus_hash();
+            let hash = self
+                .api
+                .client_config(&mut dbtx.get_isolated())
+                .await
+                .consensus_hash();
             let timing = timing::TimeReporter::new("sign client config");
             let share = self.cfg.private.auth_sks.0.sign(hash);
             drop(timing);
@@ -541,11 +1074,49 @@ impl FedimintConsensus {
 
         ConsensusProposal {
             items,
-            drop_peers,
             force_new_epoch,
         }
     }
 
+    /// Persists the `items` the modules want journaled and returns all
+    /// journaled items that weren't ordered yet
+    async fn journal_module_items(
+        &self,
+        items: &[DynModuleConsensusItem],
+    ) -> Vec<(sha256::Hash, DynModuleConsensusItem)> {
+        let mut dbtx = self.db.begin_transaction().await;
+        for item in items {
+            let module = self.modules.get_expect(item.module_instance_id());
+            if module.journal_consensus_item(item) {
+                dbtx.insert_entry(&JournaledItemKey(item.consensus_hash()), item)
+                    .await;
+            }
+        }
+        let journaled = dbtx
+            .find_by_prefix(&JournaledItemKeyPrefix)
+            .await
+            .map(|(key, item)| (key.0, item))
+            .collect::<Vec<_>>()
+            .await;
+        dbtx.commit_tx().await;
+        journaled
+    }
+
+    /// Removes our items ordered in this epoch from the journal, so they aren't
+    /// proposed again
+    async fn clear_journaled_items(
+        &self,
+        dbtx: &mut DatabaseTransaction<'_>,
+        module_cis: &[(PeerId, DynModuleConsensusItem)],
+    ) {
+        for (peer, item) in module_cis {
+            if *peer == self.cfg.local.identity {
+                dbtx.remove_entry(&JournaledItemKey(item.consensus_hash()))
+                    .await;
+            }
+        }
+    }
+
     async fn process_transaction<'a>(
         &self,
         dbtx: &mut DatabaseTransaction<'a>,
@@ -575,7 +1146,9 @@ impl FedimintConsensus {
                     caches.get_cache(input.module_instance_id()),
                 )
                 .await
-                .map_err(|e| TransactionSubmissionError::ModuleError(tx_hash, e))?;
+                .map_err(|e| {
+                    TransactionSubmissionError::ModuleError(tx_hash, input.module_instance_id(), e)
+                })?;
             pub_keys.push(meta.pub_keys);
             funding_verifier.add_input(meta.amount);
         }
@@ -595,11 +1168,16 @@ impl FedimintConsensus {
                     out_point,
                 )
                 .await
-                .map_err(|e| TransactionSubmissionError::ModuleError(tx_hash, e))?;
+                .map_err(|e| {
+                    TransactionSubmissionError::ModuleError(tx_hash, output.module_instance_id(), e)
+                })?;
             funding_verifier.add_output(amount);
         }
 
-        funding_verifier.verify_funding()?;
+        let amounts = funding_verifier.verify_funding()?;
+        // Kept for receipts, the amounts can't be recomputed later on
+        dbtx.insert_new_entry(&TransactionAmountsKey(tx_hash), &amounts)
+            .await;
 
         Ok(())
     }
@@ -640,6 +1218,120 @@ impl FedimintConsensus {
     }
 }
 
+/// Stores the newly ordered encrypted items and the valid decryption shares for
+/// pending ones. Returns the items revealed by a threshold of shares together
+/// with the peers that encrypted them, and the misbehavior observed.
+///
+/// A ciphertext reveals the peer that encrypted it, every other peer that
+/// contributed it replayed it. Ciphertexts that were revealed already are
+/// ignored, so an item is passed to its module only once.
+async fn process_encrypted_items(
+    dbtx: &mut DatabaseTransaction<'_>,
+    pks: &PublicKeySet,
+    decoders: &ModuleDecoderRegistry,
+    encrypted_cis: &[(PeerId, EncryptedConsensusItem)],
+    decryption_share_cis: &[(PeerId, DecryptionShareItem)],
+) -> (
+    Vec<(PeerId, DynModuleConsensusItem)>,
+    Vec<(PeerId, Misbehavior)>,
+) {
+    let mut misbehavior = vec![];
+
+    for (peer, item) in encrypted_cis {
+        if !item.verify() {
+            warn!(target: LOG_CONSENSUS, %peer, "Invalid encrypted item");
+            misbehavior.push((*peer, Misbehavior::InvalidItem));
+            continue;
+        }
+
+        let item_id = item.id();
+        if let Some(author) = dbtx.get_value(&RevealedEncryptedItemKey(item_id)).await {
+            // The author keeps proposing its item until its module dropped it
+            if author != *peer {
+                warn!(target: LOG_CONSENSUS, %peer, %author, "Replayed revealed encrypted item");
+                misbehavior.push((*peer, Misbehavior::InvalidItem));
+            }
+            continue;
+        }
+
+        let key = PendingEncryptedItemKey(item_id);
+        let mut pending = dbtx
+            .get_value(&key)
+            .await
+            .unwrap_or_else(|| PendingEncryptedItem {
+                peers: BTreeSet::new(),
+                item: item.clone(),
+            });
+        pending.peers.insert(*peer);
+        dbtx.insert_entry(&key, &pending).await;
+    }
+
+    let mut updated_items = BTreeSet::new();
+    for (peer, share) in decryption_share_cis {
+        // Shares for items that were revealed already are stale
+        let pending = match dbtx
+            .get_value(&PendingEncryptedItemKey(share.item_id))
+            .await
+        {
+            Some(pending) => pending,
+            None => continue,
+        };
+
+        if pending.item.verify_share(pks, *peer, &share.share) {
+            dbtx.insert_entry(&DecryptionShareKey(share.item_id, *peer), &share.share)
+                .await;
+            updated_items.insert(share.item_id);
+        } else {
+            warn!(target: LOG_CONSENSUS, %peer, "Invalid decryption share");
+            misbehavior.push((*peer, Misbehavior::InvalidSignature));
+        }
+    }
+
+    let mut revealed = vec![];
+    for item_id in updated_items {
+        let shares: BTreeMap<PeerId, SerdeDecryptionShare> = dbtx
+            .find_by_prefix(&DecryptionShareItemPrefix(item_id))
+            .await
+            .map(|(key, share)| (key.1, share))
+            .collect()
+            .await;
+
+        if shares.len() <= pks.threshold() {
+            continue;
+        }
+
+        let pending = dbtx
+            .remove_entry(&PendingEncryptedItemKey(item_id))
+            .await
+            .expect("Item is pending");
+        for peer in shares.keys() {
+            dbtx.remove_entry(&DecryptionShareKey(item_id, *peer)).await;
+        }
+
+        match pending.item.decrypt(pks, &shares, decoders) {
+            Ok((author, item)) => {
+                for peer in pending.peers.iter().filter(|peer| **peer != author) {
+                    warn!(target: LOG_CONSENSUS, %peer, %author, "Replayed encrypted item");
+                    misbehavior.push((*peer, Misbehavior::InvalidItem));
+                }
+                if pending.peers.contains(&author) {
+                    revealed.push((author, item));
+                }
+                dbtx.insert_new_entry(&RevealedEncryptedItemKey(item_id), &author)
+                    .await;
+            }
+            Err(error) => {
+                for peer in pending.peers {
+                    warn!(target: LOG_CONSENSUS, %peer, %error, "Undecryptable encrypted item");
+                    misbehavior.push((peer, Misbehavior::InvalidItem));
+                }
+            }
+        }
+    }
+
+    (revealed, misbehavior)
+}
+
 impl FundingVerifier {
     pub fn add_input(&mut self, input_amount: TransactionItemAmount) {
         self.input_amount += input_amount.amount;
@@ -651,9 +1343,14 @@ impl FundingVerifier {
         self.fee_amount += output_amount.fee;
     }
 
-    pub fn verify_funding(self) -> Result<(), TransactionError> {
+    /// Returns the amounts of a balanced transaction
+    pub fn verify_funding(self) -> Result<TransactionAmounts, TransactionError> {
         if self.input_amount == (self.output_amount + self.fee_amount) {
-            Ok(())
+            Ok(TransactionAmounts {
+                inputs: self.input_amount,
+                outputs: self.output_amount,
+                fees: self.fee_amount,
+            })
         } else {
             Err(TransactionError::UnbalancedTransaction {
                 inputs: self.input_amount,
@@ -678,10 +1375,122 @@ impl Default for FundingVerifier {
 pub enum TransactionSubmissionError {
     #[error("High level transaction error: {0}")]
     TransactionError(#[from] TransactionError),
-    #[error("Module input or output error in tx {0}: {1}")]
-    ModuleError(TransactionId, ModuleError),
+    #[error("Module input or output error in tx {0}: {2}")]
+    ModuleError(TransactionId, ModuleInstanceId, ModuleError),
     #[error("Transaction channel was closed")]
     TxChannelError,
     #[error("Transaction was already successfully processed: {0}")]
     TransactionReplayError(TransactionId),
 }
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::core::{DynModuleConsensusItem, ModuleInstanceId};
+    use fedimint_core::db::mem_impl::MemDatabase;
+    use fedimint_core::db::Database;
+    use fedimint_core::epoch::{EncryptedConsensusItem, SerdeSignatureShare};
+    use fedimint_core::module::registry::ModuleDecoderRegistry;
+    use fedimint_core::module::CommonModuleGen;
+    use fedimint_core::{PeerId, ServerModule};
+    use fedimint_dummy_common::{DummyCommonGen, DummyConsensusItem};
+    use fedimint_dummy_server::Dummy;
+    use hbbft::crypto::SecretKeySet;
+    use rand::rngs::OsRng;
+
+    use super::{process_encrypted_items, Misbehavior};
+
+    const DUMMY_INSTANCE_ID: ModuleInstanceId = 0;
+
+    fn decoders() -> ModuleDecoderRegistry {
+        ModuleDecoderRegistry::from_iter([(
+            DUMMY_INSTANCE_ID,
+            DummyCommonGen::KIND,
+            <Dummy as ServerModule>::decoder(),
+        )])
+    }
+
+    fn dummy_item(sks: &SecretKeySet) -> DynModuleConsensusItem {
+        let share = SerdeSignatureShare(sks.secret_key_share(0).sign("message"));
+        DynModuleConsensusItem::from_typed(
+            DUMMY_INSTANCE_ID,
+            DummyConsensusItem::Sign("message".to_string(), share),
+        )
+    }
+
+    /// Processes one epoch in which `submitters` order `item` and `sharers`
+    /// contribute their decryption shares for it
+    async fn process_epoch(
+        db: &Database,
+        sks: &SecretKeySet,
+        item: &EncryptedConsensusItem,
+        submitters: &[u16],
+        sharers: &[u16],
+    ) -> (
+        Vec<(PeerId, DynModuleConsensusItem)>,
+        Vec<(PeerId, Misbehavior)>,
+    ) {
+        let encrypted_cis = submitters
+            .iter()
+            .map(|peer| (PeerId::from(*peer), item.clone()))
+            .collect::<Vec<_>>();
+        let decryption_share_cis = sharers
+            .iter()
+            .map(|peer| {
+                let share = item
+                    .decryption_share(&sks.secret_key_share(*peer as usize))
+                    .expect("Ciphertext is valid");
+                (PeerId::from(*peer), share)
+            })
+            .collect::<Vec<_>>();
+
+        let mut dbtx = db.begin_transaction().await;
+        let result = process_encrypted_items(
+            &mut dbtx,
+            &sks.public_keys(),
+            &decoders(),
+            &encrypted_cis,
+            &decryption_share_cis,
+        )
+        .await;
+        dbtx.commit_tx().await;
+        result
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn reveals_items_to_their_author_and_rejects_replays() {
+        let db = Database::new(MemDatabase::new(), decoders());
+        let sks = SecretKeySet::random(1, &mut OsRng);
+        let item = dummy_item(&sks);
+        let encrypted =
+            EncryptedConsensusItem::encrypt(&sks.public_keys().public_key(), 0.into(), &item);
+
+        // Not revealed before a threshold of shares was ordered
+        let (revealed, misbehavior) = process_epoch(&db, &sks, &encrypted, &[0, 1], &[0]).await;
+        assert!(revealed.is_empty());
+        assert!(misbehavior.is_empty());
+
+        // Peer 1 ordered the ciphertext of peer 0 as well
+        let (revealed, misbehavior) = process_epoch(&db, &sks, &encrypted, &[], &[2]).await;
+        assert_eq!(revealed, vec![(0.into(), item)]);
+        assert_eq!(misbehavior, vec![(1.into(), Misbehavior::InvalidItem)]);
+
+        // The author re-proposing its item is ignored, anyone else replays it
+        let (revealed, misbehavior) =
+            process_epoch(&db, &sks, &encrypted, &[0, 3], &[0, 1, 2]).await;
+        assert!(revealed.is_empty());
+        assert_eq!(misbehavior, vec![(3.into(), Misbehavior::InvalidItem)]);
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn doesnt_reveal_items_ordered_only_by_others() {
+        let db = Database::new(MemDatabase::new(), decoders());
+        let sks = SecretKeySet::random(1, &mut OsRng);
+        let item = dummy_item(&sks);
+        let encrypted =
+            EncryptedConsensusItem::encrypt(&sks.public_keys().public_key(), 0.into(), &item);
+
+        let (revealed, misbehavior) = process_epoch(&db, &sks, &encrypted, &[1], &[1, 2]).await;
+        assert!(revealed.is_empty());
+        assert_eq!(misbehavior, vec![(1.into(), Misbehavior::InvalidItem)]);
+    }
+}
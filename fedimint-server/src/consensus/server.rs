@@ -1,22 +1,29 @@
 use std::cmp::{max, min};
-use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 
-use anyhow::bail;
+use anyhow::{bail, format_err};
+use async_trait::async_trait;
 use fedimint_core::api::{
     ConsensusContribution, DynGlobalApi, GlobalFederationApi, WsFederationApi,
 };
 use fedimint_core::cancellable::Cancellable;
-use fedimint_core::config::ServerModuleGenRegistry;
-use fedimint_core::db::{apply_migrations, Database};
+use fedimint_core::config::{
+    ConfigGenModuleParams, DkgPeerMsg, ServerModuleConfig, ServerModuleGenRegistry,
+};
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::db::{apply_migrations, needs_migration, Database};
 use fedimint_core::encoding::DecodeError;
 use fedimint_core::epoch::{
-    ConsensusItem, EpochOutcome, EpochVerifyError, SerdeConsensusItem, SignedEpochOutcome,
+    ConsensusItem, EpochOutcome, EpochVerifyError, ModuleAddition, SerdeConsensusItem,
+    SignedEpochOutcome,
 };
 use fedimint_core::module::registry::{ModuleDecoderRegistry, ModuleRegistry};
+use fedimint_core::module::PeerHandle;
 use fedimint_core::net::peers::PeerConnections;
-use fedimint_core::task::{sleep, RwLock, TaskGroup, TaskHandle};
+use fedimint_core::task::{block_in_place, sleep, RwLock, TaskGroup, TaskHandle};
 use fedimint_core::{NumPeers, PeerId};
 use futures::stream::Peekable;
 use futures::{FutureExt, StreamExt};
@@ -27,19 +34,29 @@ use jsonrpsee::core::Serialize;
 use rand::rngs::OsRng;
 use rand::{CryptoRng, RngCore};
 use serde::Deserialize;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, oneshot};
 use tokio_stream::wrappers::ReceiverStream;
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
-use crate::config::ServerConfig;
+use crate::config::io::{
+    read_staged_server_config, remove_staged_server_config, stage_server_config,
+};
+use crate::config::{confirm_dkg_done, ServerConfig};
+use crate::consensus::interconnect::ServerModuleInterconnect;
 use crate::consensus::{
     ApiEvent, ConsensusOutcomeConversion, ConsensusProposal, FedimintConsensus,
     HbbftConsensusOutcome, HbbftSerdeConsensusOutcome,
 };
-use crate::db::{get_global_database_migrations, LastEpochKey, GLOBAL_DATABASE_VERSION};
+use crate::db::{
+    get_global_database_migrations, LastEpochKey, ModuleAdditionLocalParamsKey,
+    GLOBAL_DATABASE_VERSION,
+};
 use crate::fedimint_core::encoding::Encodable;
-use crate::fedimint_core::net::peers::IPeerConnections;
-use crate::net::api::{ConsensusApi, ExpiringCache};
+use crate::fedimint_core::net::peers::{IMuxPeerConnections, IPeerConnections};
+use crate::multiplexed::{
+    ModuleMultiplexed, PeerConnectionMultiplexer, MAX_PEER_OUT_OF_ORDER_MESSAGES,
+};
+use crate::net::api::{ApiAuthState, ConsensusApi, ExpiringCache};
 use crate::net::connect::{Connector, TlsTcpConnector};
 use crate::net::peers::{DelayCalculator, PeerConnector, PeerSlice, ReconnectPeerConnections};
 use crate::{LOG_CONSENSUS, LOG_CORE};
@@ -51,11 +68,28 @@ const NUM_EPOCHS_REJOIN_AHEAD: u64 = 10;
 /// How many txs can be stored in memory before blocking the API
 const TRANSACTION_BUFFER_SIZE: usize = 1000;
 
+/// How long to wait for the DKG of an added module before restarting it
+const MODULE_DKG_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Message of the DKG of a module that is added to the running federation
+pub type ModuleDkgMessage = ModuleMultiplexed<(ModuleInstanceId, String), DkgPeerMsg>;
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 #[allow(clippy::large_enum_variant)]
 pub enum EpochMessage {
     Continue(Message<PeerId>),
     RejoinRequest(u64),
+    ModuleDkg(ModuleDkgMessage),
+}
+
+/// Why the consensus loop stopped
+#[allow(clippy::large_enum_variant)]
+pub enum ConsensusExit {
+    /// Shutting down or a threshold of peers signaled an upgrade
+    Shutdown,
+    /// Consensus needs to restart with the config, which contains a newly
+    /// added module unless its DKG failed and has to be retried
+    Restart(ServerConfig),
 }
 
 type EpochStep = Step<Vec<SerdeConsensusItem>, PeerId>;
@@ -69,6 +103,8 @@ enum EpochTriggerEvent {
     ModuleProposalEvent,
     /// A rejoining peer wants us to run an empty epoch
     RunEpochRequest,
+    /// We are shutting down and shouldn't propose to a new epoch
+    Shutdown,
 }
 
 pub(crate) type LatestContributionByPeer = HashMap<PeerId, ConsensusContribution>;
@@ -103,6 +139,11 @@ pub struct ConsensusServer {
     pub last_processed_epoch: Option<SignedEpochOutcome>,
     /// Used for decoding module specific-values
     pub decoders: ModuleDecoderRegistry,
+    /// DKG messages of a module addition sent by peers that started its DKG
+    /// before us
+    pub early_dkg_messages: VecDeque<(PeerId, ModuleDkgMessage)>,
+    /// Fires once the process shuts down, set while running consensus
+    pub shutdown_rx: Option<oneshot::Receiver<()>>,
 }
 
 impl ConsensusServer {
@@ -127,6 +168,33 @@ impl ConsensusServer {
         .await
     }
 
+    /// Checks the on disk versions of the global and all module databases,
+    /// returns `true` if any migrations will be applied when the server is
+    /// created.
+    ///
+    /// Fails if any database is newer than the code, in which case the server
+    /// must not be started.
+    pub async fn has_pending_migrations(
+        cfg: &ServerConfig,
+        db: &Database,
+        module_inits: &ServerModuleGenRegistry,
+    ) -> anyhow::Result<bool> {
+        let mut pending = needs_migration(db, "Global", &GLOBAL_DATABASE_VERSION).await?;
+
+        for (module_id, module_cfg) in &cfg.consensus.modules {
+            let kind = &module_cfg.kind;
+            let Some(init) = module_inits.get(kind) else {
+                bail!("Detected configuration for unsupported module kind: {kind}")
+            };
+
+            let isolated_db = db.new_isolated(*module_id);
+            pending |=
+                needs_migration(&isolated_db, kind.as_str(), &init.database_version()).await?;
+        }
+
+        Ok(pending)
+    }
+
     /// Creates a server that can simulate network and delays
     ///
     /// Initializes modules and runs any database migrations
@@ -140,6 +208,8 @@ impl ConsensusServer {
     ) -> anyhow::Result<Self> {
         // Apply database migrations and build `ServerModuleRegistry`
         let mut modules = BTreeMap::new();
+        let mut module_task_groups = BTreeMap::new();
+        let interconnect = ServerModuleInterconnect::new(db.clone());
 
         apply_migrations(
             &db,
@@ -166,10 +236,21 @@ impl ConsensusServer {
             )
             .await?;
 
+            // Every module gets its own subgroup, so the health of its tasks can be
+            // reported separately
+            let mut module_task_group = task_group.make_subgroup().await;
             let module = init
-                .init(cfg.get_module_config(*module_id)?, isolated_db, task_group)
+                .init(
+                    cfg.get_module_config(*module_id)?,
+                    *module_id,
+                    cfg.local.identity,
+                    isolated_db,
+                    &mut module_task_group,
+                    interconnect.clone().into(),
+                )
                 .await?;
             modules.insert(*module_id, (kind, module));
+            module_task_groups.insert(*module_id, module_task_group);
         }
 
         // Check the configs are valid
@@ -209,6 +290,7 @@ impl ConsensusServer {
         let (api_sender, api_receiver) = mpsc::channel(TRANSACTION_BUFFER_SIZE);
         let client_cfg = cfg.consensus.to_client_config(&module_inits)?;
         let modules = ModuleRegistry::from(modules);
+        interconnect.set_modules(modules.clone());
 
         let latest_contribution_by_peer: Arc<RwLock<LatestContributionByPeer>> = Default::default();
         let supported_api_versions =
@@ -218,6 +300,7 @@ impl ConsensusServer {
             cfg: cfg.clone(),
             db: db.clone(),
             modules: modules.clone(),
+            module_inits: module_inits.clone(),
             client_cfg,
             api_sender,
             supported_api_versions,
@@ -226,6 +309,11 @@ impl ConsensusServer {
             // keep the status for a short time to protect the system against a denial-of-service
             // attack
             consensus_status_cache: ExpiringCache::new(Duration::from_millis(500)),
+            api_auth: Arc::new(RwLock::new(ApiAuthState::new(cfg.private.api_auth.clone()))),
+            data_dir: None,
+            transaction_correlation_ids: Default::default(),
+            module_task_groups,
+            epoch_timing: Default::default(),
         };
 
         // Build consensus processor
@@ -236,6 +324,7 @@ impl ConsensusServer {
             db: db.clone(),
             api: consensus_api,
             api_event_cache: Default::default(),
+            encrypted_items: Default::default(),
         };
 
         Ok(ConsensusServer {
@@ -252,18 +341,42 @@ impl ConsensusServer {
             pending_forced_epochs: 0,
             last_processed_epoch: None,
             decoders: modules.decoder_registry(),
+            early_dkg_messages: Default::default(),
+            shutdown_rx: None,
         })
     }
 
-    /// Loop `run_conensus_epoch` until shut down
-    pub async fn run_consensus(mut self, task_handle: TaskHandle) -> anyhow::Result<()> {
+    /// Lets guardians rotate their password, which re-encrypts the configs in
+    /// `data_dir`
+    pub fn with_data_dir(mut self, data_dir: PathBuf) -> Self {
+        self.consensus.api.data_dir = Some(data_dir);
+        self
+    }
+
+    /// Loop `run_conensus_epoch` until shut down or a module gets added
+    pub async fn run_consensus(mut self, task_handle: TaskHandle) -> anyhow::Result<ConsensusExit> {
         let our_hash = self.cfg.consensus.consensus_hash();
+        let staged_cfg = self.read_staged_config().await?;
+        let staged_hash = staged_cfg
+            .as_ref()
+            .map(|cfg| cfg.consensus.consensus_hash());
 
         // Confirm our hash matches with peers
         loop {
             info!(target: LOG_CONSENSUS, "Waiting for peers config {our_hash}");
             match self.api.consensus_config_hash().await {
                 Ok(consensus_hash) if consensus_hash == our_hash => break,
+                // We crashed after confirming the DKG of an added module
+                Ok(consensus_hash) if Some(consensus_hash) == staged_hash => {
+                    info!(target: LOG_CONSENSUS, "Peers added the staged module, restarting");
+                    return Ok(ConsensusExit::Restart(
+                        staged_cfg.expect("staged hash is set"),
+                    ));
+                }
+                // Peers may still be restarting after adding a module
+                Ok(_) if self.consensus.scheduled_module_addition().await.is_some() => {
+                    warn!(target: LOG_CONSENSUS, "Waiting for peers to add the scheduled module")
+                }
                 Ok(_) => bail!("Our consensus config doesn't match peers!"),
                 Err(e) => {
                     warn!(target: LOG_CONSENSUS, "ERROR {:?}", e)
@@ -272,7 +385,14 @@ impl ConsensusServer {
             sleep(Duration::from_millis(100)).await;
         }
 
+        self.consensus.complete_module_addition().await;
+        // Peers may be waiting for us in the DKG of a module addition
+        if let Some(addition) = self.consensus.due_module_addition().await {
+            return self.run_module_addition(addition).await;
+        }
+
         let mut rng = OsRng;
+        self.shutdown_rx = Some(task_handle.make_shutdown_rx().await);
         self.start_consensus().await;
 
         while !task_handle.is_shutting_down() {
@@ -293,6 +413,12 @@ impl ConsensusServer {
                 self.process_outcome(outcome)
                     .await
                     .expect("failed to process epoch");
+                self.consensus
+                    .api
+                    .epoch_timing
+                    .lock()
+                    .expect("poisoned")
+                    .epoch_completed(fedimint_core::time::now());
             }
 
             if self.consensus.is_at_upgrade_threshold().await {
@@ -302,10 +428,85 @@ impl ConsensusServer {
                 );
                 break;
             }
+
+            if let Some(addition) = self.consensus.due_module_addition().await {
+                return self.run_module_addition(addition).await;
+            }
         }
 
-        info!(target: LOG_CONSENSUS, "Consensus task shut down");
-        Ok(())
+        info!(
+            target: LOG_CONSENSUS,
+            last_processed_epoch = ?self.last_processed_epoch.as_ref().map(|e| e.outcome.epoch),
+            "Consensus task shut down"
+        );
+        Ok(ConsensusExit::Shutdown)
+    }
+
+    /// Reads the config with an added module we staged before confirming the
+    /// module's DKG
+    async fn read_staged_config(&self) -> anyhow::Result<Option<ServerConfig>> {
+        let Some(data_dir) = &self.consensus.api.data_dir else {
+            return Ok(None);
+        };
+        let password = self.consensus.api.api_auth.read().await.current().0.clone();
+        block_in_place(|| read_staged_server_config(&password, data_dir))
+    }
+
+    /// Pauses consensus to run the DKG of a scheduled module addition over our
+    /// peer connections, returning the config consensus has to restart with
+    ///
+    /// If the DKG fails, e.g. because a peer is offline, consensus resumes with
+    /// the current config and we propose cancelling the addition, so one
+    /// guardian can't halt the federation.
+    async fn run_module_addition(self, addition: ModuleAddition) -> anyhow::Result<ConsensusExit> {
+        info!(
+            target: LOG_CONSENSUS,
+            module_instance_id = addition.module_instance_id,
+            kind = %addition.kind,
+            "Running DKG of added module"
+        );
+        let local_params = self
+            .consensus
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&ModuleAdditionLocalParamsKey(addition.module_instance_id))
+            .await;
+        let connections = ModuleDkgConnections {
+            connections: self.connections,
+            early_messages: self.early_dkg_messages,
+        };
+        let password = self.consensus.api.api_auth.read().await.current().0.clone();
+        let staging = self
+            .consensus
+            .api
+            .data_dir
+            .as_deref()
+            .map(|data_dir| (data_dir, password.as_str()));
+
+        let dkg = run_module_dkg(
+            &self.cfg,
+            &self.consensus.module_inits,
+            &addition,
+            local_params,
+            connections,
+            staging,
+        );
+        let error = match tokio::time::timeout(MODULE_DKG_TIMEOUT, dkg).await {
+            Ok(Ok(cfg)) => {
+                info!(target: LOG_CONSENSUS, "Module added, restarting consensus");
+                return Ok(ConsensusExit::Restart(cfg));
+            }
+            Ok(Err(e)) => format!("{e:?}"),
+            Err(_) => "timed out".to_string(),
+        };
+
+        warn!(target: LOG_CONSENSUS, "DKG of added module failed, cancelling it: {error}");
+        if let Some(data_dir) = &self.consensus.api.data_dir {
+            block_in_place(|| remove_staged_server_config(data_dir))?;
+        }
+        self.consensus.fail_module_addition(&addition).await;
+        Ok(ConsensusExit::Restart(self.cfg))
     }
 
     /// Starts consensus by skipping to the last saved epoch history  and
@@ -368,7 +569,7 @@ impl ConsensusServer {
                         last_outcome.epoch,
                         self.last_processed_epoch
                             .as_ref()
-                            .map(|epoch| epoch.outcome.consensus_hash()),
+                            .map(|epoch| epoch.outcome.hash()),
                         None,
                         true,
                     )
@@ -443,6 +644,7 @@ impl ConsensusServer {
                 tokio::select! {
                     _ = Pin::new(&mut self.api_receiver).peek() => (),
                     () = self.consensus.await_consensus_proposal() => (),
+                    () = shutdown_requested(&mut self.shutdown_rx) => return Ok(vec![]),
                 }
             }
             let proposal = self.process_events_then_propose(override_proposal).await;
@@ -457,6 +659,8 @@ impl ConsensusServer {
         // process messages until new epoch or we have a proposal
         let mut outcomes: Vec<HbbftConsensusOutcome> = loop {
             match self.await_next_epoch().await? {
+                // Stop before proposing to the next epoch
+                EpochTriggerEvent::Shutdown => return Ok(vec![]),
                 EpochTriggerEvent::NewMessage(msg) if self.start_next_epoch(&msg) => {
                     break self.handle_message(msg).await?
                 }
@@ -466,9 +670,6 @@ impl ConsensusServer {
         };
         let proposal = self.process_events_then_propose(override_proposal).await;
 
-        for peer in proposal.drop_peers.iter() {
-            self.connections.ban_peer(*peer).await;
-        }
         let step = self.propose_epoch(proposal, rng).await?;
         outcomes.append(&mut self.handle_step(step).await?);
 
@@ -484,6 +685,12 @@ impl ConsensusServer {
         &mut self,
         override_proposal: Option<ConsensusProposal>,
     ) -> ConsensusProposal {
+        self.consensus
+            .api
+            .epoch_timing
+            .lock()
+            .expect("poisoned")
+            .epoch_started(fedimint_core::time::now());
         while let Some(Some(event)) = self.api_receiver.next().now_or_never() {
             match event {
                 ApiEvent::ForceProcessOutcome(outcome) => self.force_process_epoch(outcome).await,
@@ -557,7 +764,8 @@ impl ConsensusServer {
         tokio::select! {
             _peek = Pin::new(&mut self.api_receiver).peek() => Ok(EpochTriggerEvent::ApiEvent),
             () = self.consensus.await_consensus_proposal() => Ok(EpochTriggerEvent::ModuleProposalEvent),
-            msg = self.connections.receive() => Ok(EpochTriggerEvent::NewMessage(msg?))
+            msg = self.connections.receive() => Ok(EpochTriggerEvent::NewMessage(msg?)),
+            () = shutdown_requested(&mut self.shutdown_rx) => Ok(EpochTriggerEvent::Shutdown),
         }
     }
 
@@ -565,6 +773,7 @@ impl ConsensusServer {
         match msg {
             (_, EpochMessage::Continue(peer_msg)) => self.hbbft.epoch() <= peer_msg.epoch(),
             (_, EpochMessage::RejoinRequest(_)) => false,
+            (_, EpochMessage::ModuleDkg(_)) => false,
         }
     }
 
@@ -597,6 +806,14 @@ impl ConsensusServer {
                 );
                 Ok(vec![])
             }
+            (peer, EpochMessage::ModuleDkg(dkg_msg)) => {
+                if self.early_dkg_messages.len() < MAX_PEER_OUT_OF_ORDER_MESSAGES as usize {
+                    self.early_dkg_messages.push_back((peer, dkg_msg));
+                } else {
+                    warn!(target: LOG_CONSENSUS, %peer, "Dropping early module DKG message");
+                }
+                Ok(vec![])
+            }
         }
     }
 
@@ -649,6 +866,105 @@ impl ConsensusServer {
     }
 }
 
+/// Runs the DKG of an added module, returning our config with the module
+///
+/// Once all peers confirmed the DKG they restart with the module, so our new
+/// config is staged in the data dir given by `staging` before we confirm.
+async fn run_module_dkg(
+    cfg: &ServerConfig,
+    module_inits: &ServerModuleGenRegistry,
+    addition: &ModuleAddition,
+    local_params: Option<String>,
+    connections: ModuleDkgConnections,
+    staging: Option<(&Path, &str)>,
+) -> anyhow::Result<ServerConfig> {
+    let Some(local_params) = local_params else {
+        bail!("Our local params are missing, the module addition has to be proposed")
+    };
+    let gen = module_inits
+        .get(&addition.kind)
+        .ok_or_else(|| format_err!("Unsupported module kind {}", addition.kind))?;
+    let params = ConfigGenModuleParams::new(
+        Some(serde_json::from_str(&local_params)?),
+        Some(serde_json::from_str(&addition.consensus_params)?),
+    );
+    let our_id = cfg.local.identity;
+    let peers: Vec<PeerId> = cfg.local.p2p_endpoints.keys().copied().collect();
+
+    // in case we are running by ourselves, avoid DKG
+    if peers.len() == 1 {
+        let module_cfg = gen
+            .trusted_dealer_gen(&peers, &params)
+            .remove(&our_id)
+            .ok_or_else(|| format_err!("No module config generated for us"))?;
+        return with_added_module(cfg, module_inits, addition, module_cfg);
+    }
+
+    let connections = PeerConnectionMultiplexer::new(connections.into_dyn()).into_dyn();
+    let dkg = PeerHandle::new(
+        &connections,
+        addition.module_instance_id,
+        our_id,
+        peers.clone(),
+    );
+    let module_cfg = gen.distributed_gen(&dkg, &params).await?;
+    let cfg = with_added_module(cfg, module_inits, addition, module_cfg)?;
+    if let Some((data_dir, password)) = staging {
+        block_in_place(|| stage_server_config(&cfg, data_dir.to_owned(), password, module_inits))?;
+    }
+    confirm_dkg_done(&connections, &peers, &our_id).await?;
+
+    Ok(cfg)
+}
+
+fn with_added_module(
+    cfg: &ServerConfig,
+    module_inits: &ServerModuleGenRegistry,
+    addition: &ModuleAddition,
+    module_cfg: ServerModuleConfig,
+) -> anyhow::Result<ServerConfig> {
+    let mut cfg = cfg.clone();
+    cfg.add_modules(BTreeMap::from([(addition.module_instance_id, module_cfg)]));
+    cfg.validate_config(&cfg.local.identity, module_inits)?;
+    Ok(cfg)
+}
+
+/// Consensus connections used for the DKG of an added module, consensus
+/// messages are dropped since consensus is paused until the DKG is done
+struct ModuleDkgConnections {
+    connections: PeerConnections<EpochMessage>,
+    /// DKG messages consensus received before the DKG started
+    early_messages: VecDeque<(PeerId, ModuleDkgMessage)>,
+}
+
+#[async_trait]
+impl IPeerConnections<ModuleDkgMessage> for ModuleDkgConnections {
+    async fn send(&mut self, peers: &[PeerId], msg: ModuleDkgMessage) -> Cancellable<()> {
+        self.connections
+            .send(peers, EpochMessage::ModuleDkg(msg))
+            .await
+    }
+
+    async fn receive(&mut self) -> Cancellable<(PeerId, ModuleDkgMessage)> {
+        if let Some(msg) = self.early_messages.pop_front() {
+            return Ok(msg);
+        }
+
+        loop {
+            match self.connections.receive().await? {
+                (peer, EpochMessage::ModuleDkg(msg)) => return Ok((peer, msg)),
+                (peer, _) => {
+                    debug!(target: LOG_CONSENSUS, %peer, "Dropping consensus message during DKG")
+                }
+            }
+        }
+    }
+
+    async fn ban_peer(&mut self, peer: PeerId) {
+        self.connections.ban_peer(peer).await;
+    }
+}
+
 fn module_parse_outcome(
     outcome: HbbftSerdeConsensusOutcome,
     module_registry: &ModuleDecoderRegistry,
@@ -684,3 +1000,16 @@ fn module_parse_outcome(
 
     (outcome, ban_peers)
 }
+
+/// Resolves once `shutdown_rx` fired, never if there is none
+async fn shutdown_requested(shutdown_rx: &mut Option<oneshot::Receiver<()>>) {
+    match shutdown_rx {
+        Some(rx) => {
+            // The sender being dropped means the task group is gone as well
+            let _ = rx.await;
+            // A completed receiver must not be polled again
+            *shutdown_rx = None;
+        }
+        None => std::future::pending().await,
+    }
+}
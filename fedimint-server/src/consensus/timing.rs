@@ -0,0 +1,125 @@
+//! Tracks how long our epochs take
+//!
+//! Epochs only run when there is something to agree on, so the time until an
+//! item is included mostly depends on how long running an epoch takes. If an
+//! epoch is in progress a new item has to wait for it to complete and is
+//! included in the following one.
+
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime};
+
+use fedimint_core::epoch::EpochTimingEstimate;
+
+/// Number of recent epochs the estimates are based on
+const EPOCH_TIMING_WINDOW: usize = 20;
+
+#[derive(Debug, Clone)]
+struct CompletedEpoch {
+    completed_at: SystemTime,
+    /// `None` for epochs we didn't propose to, e.g. ones we caught up on
+    duration: Option<Duration>,
+}
+
+#[derive(Debug, Default)]
+pub struct EpochTimingTracker {
+    recent: VecDeque<CompletedEpoch>,
+    /// When we proposed to the epoch in progress
+    current_started_at: Option<SystemTime>,
+}
+
+impl EpochTimingTracker {
+    /// Called when we propose to an epoch
+    pub fn epoch_started(&mut self, now: SystemTime) {
+        self.current_started_at.get_or_insert(now);
+    }
+
+    /// Called for every epoch we processed
+    pub fn epoch_completed(&mut self, now: SystemTime) {
+        let duration = self
+            .current_started_at
+            .take()
+            .map(|started_at| now.duration_since(started_at).unwrap_or_default());
+        self.recent.push_back(CompletedEpoch {
+            completed_at: now,
+            duration,
+        });
+        if self.recent.len() > EPOCH_TIMING_WINDOW {
+            self.recent.pop_front();
+        }
+    }
+
+    pub fn estimate(&self, now: SystemTime) -> EpochTimingEstimate {
+        let recent_intervals_ms = self
+            .recent
+            .iter()
+            .zip(self.recent.iter().skip(1))
+            .map(|(prev, next)| {
+                duration_ms(
+                    next.completed_at
+                        .duration_since(prev.completed_at)
+                        .unwrap_or_default(),
+                )
+            })
+            .collect();
+
+        let mut durations = self
+            .recent
+            .iter()
+            .filter_map(|epoch| epoch.duration)
+            .collect::<Vec<_>>();
+        durations.sort();
+        let median_duration = durations.get(durations.len() / 2).copied();
+
+        let current_elapsed = self
+            .current_started_at
+            .map(|started_at| now.duration_since(started_at).unwrap_or_default());
+        let expected_inclusion = median_duration.map(|median| {
+            let remaining =
+                current_elapsed.map_or(Duration::ZERO, |elapsed| median.saturating_sub(elapsed));
+            remaining + median
+        });
+
+        EpochTimingEstimate {
+            recent_intervals_ms,
+            median_epoch_duration_ms: median_duration.map(duration_ms),
+            current_epoch_elapsed_ms: current_elapsed.map(duration_ms),
+            expected_inclusion_ms: expected_inclusion.map(duration_ms),
+        }
+    }
+}
+
+fn duration_ms(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_inclusion_from_median_duration() {
+        let start = SystemTime::UNIX_EPOCH;
+        let secs = |secs| start + Duration::from_secs(secs);
+        let mut tracker = EpochTimingTracker::default();
+        assert_eq!(tracker.estimate(start), EpochTimingEstimate::default());
+
+        for (started, completed) in [(0, 2), (10, 14), (20, 23)] {
+            tracker.epoch_started(secs(started));
+            tracker.epoch_completed(secs(completed));
+        }
+        // An epoch we caught up on doesn't count towards the durations
+        tracker.epoch_completed(secs(24));
+
+        let idle = tracker.estimate(secs(30));
+        assert_eq!(idle.recent_intervals_ms, vec![12_000, 9_000, 1_000]);
+        assert_eq!(idle.median_epoch_duration_ms, Some(3_000));
+        assert_eq!(idle.current_epoch_elapsed_ms, None);
+        assert_eq!(idle.expected_inclusion_ms, Some(3_000));
+
+        // Items submitted now wait for the epoch in progress
+        tracker.epoch_started(secs(30));
+        let busy = tracker.estimate(secs(31));
+        assert_eq!(busy.current_epoch_elapsed_ms, Some(1_000));
+        assert_eq!(busy.expected_inclusion_ms, Some(5_000));
+    }
+}
@@ -1,15 +1,21 @@
-use std::collections::BTreeSet;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
 
+use bitcoin_hashes::sha256;
+use fedimint_core::admin_client::PeerScore;
 use fedimint_core::api::ClientConfigDownloadToken;
+use fedimint_core::core::{DynModuleConsensusItem, ModuleInstanceId};
 use fedimint_core::db::{DatabaseVersion, MigrationMap, MODULE_GLOBAL_PREFIX};
 use fedimint_core::encoding::{Decodable, Encodable};
-use fedimint_core::epoch::{SerdeSignature, SignedEpochOutcome};
+use fedimint_core::epoch::{
+    ModuleAddition, SerdeDecryptionShare, SerdeSignature, SignedEpochOutcome,
+};
+use fedimint_core::receipt::TransactionAmounts;
 use fedimint_core::{impl_db_lookup, impl_db_record, PeerId, TransactionId};
 use serde::Serialize;
 use strum_macros::EnumIter;
 
-use crate::consensus::AcceptedTransaction;
+use crate::consensus::{AcceptedTransaction, PendingEncryptedItem};
 
 pub const GLOBAL_DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
 
@@ -24,6 +30,20 @@ pub enum DbKeyPrefix {
     ClientConfigSignature = 0x07,
     ConsensusUpgrade = 0x08,
     ClientConfigDownload = 0x09,
+    FederationMeta = 0x0a,
+    MetaVote = 0x0b,
+    ModuleAdditionVote = 0x0c,
+    ScheduledModuleAddition = 0x0d,
+    ModuleAdditionLocalParams = 0x0e,
+    PendingEncryptedItem = 0x0f,
+    DecryptionShare = 0x10,
+    PeerScore = 0x11,
+    JournaledItem = 0x12,
+    TransactionAmounts = 0x13,
+    /// Only written by `EncryptedDatabase`
+    EncryptedDatabaseCanary = 0x14,
+    ModuleAdditionFailed = 0x15,
+    RevealedEncryptedItem = 0x16,
     Module = MODULE_GLOBAL_PREFIX,
 }
 
@@ -67,6 +87,8 @@ impl_db_lookup!(
     query_prefix = RejectedTransactionKeyPrefix
 );
 
+/// Peers that are quarantined, their contributions are ignored until
+/// `PeerScore::quarantined_until`
 #[derive(Debug, Encodable, Decodable, Serialize)]
 pub struct DropPeerKey(pub PeerId);
 
@@ -90,6 +112,7 @@ impl_db_record!(
     key = EpochHistoryKey,
     value = SignedEpochOutcome,
     db_prefix = DbKeyPrefix::EpochHistory,
+    notify_on_modify = true,
 );
 impl_db_lookup!(key = EpochHistoryKey, query_prefix = EpochHistoryKeyPrefix);
 
@@ -144,6 +167,201 @@ impl_db_lookup!(
     query_prefix = ClientConfigDownloadKeyPrefix
 );
 
+/// Latest federation meta agreed on by the guardians, replaces the meta from
+/// the config
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct FederationMetaKey;
+
+impl_db_record!(
+    key = FederationMetaKey,
+    value = BTreeMap<String, String>,
+    db_prefix = DbKeyPrefix::FederationMeta,
+);
+
+/// Meta a guardian voted for that didn't reach the threshold yet
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct MetaVoteKey(pub PeerId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MetaVoteKeyPrefix;
+
+impl_db_record!(
+    key = MetaVoteKey,
+    value = BTreeMap<String, String>,
+    db_prefix = DbKeyPrefix::MetaVote,
+);
+impl_db_lookup!(key = MetaVoteKey, query_prefix = MetaVoteKeyPrefix);
+
+/// Module addition a guardian voted for that not all guardians agreed on yet
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ModuleAdditionVoteKey(pub PeerId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ModuleAdditionVoteKeyPrefix;
+
+impl_db_record!(
+    key = ModuleAdditionVoteKey,
+    value = ModuleAddition,
+    db_prefix = DbKeyPrefix::ModuleAdditionVote,
+);
+impl_db_lookup!(
+    key = ModuleAdditionVoteKey,
+    query_prefix = ModuleAdditionVoteKeyPrefix
+);
+
+/// Module addition all guardians agreed on, its DKG runs once the activation
+/// epoch has been processed
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ScheduledModuleAdditionKey;
+
+impl_db_record!(
+    key = ScheduledModuleAdditionKey,
+    value = ModuleAddition,
+    db_prefix = DbKeyPrefix::ScheduledModuleAddition,
+);
+
+/// Our local config gen params (as JSON) for a module we proposed to add
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ModuleAdditionLocalParamsKey(pub ModuleInstanceId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ModuleAdditionLocalParamsKeyPrefix;
+
+impl_db_record!(
+    key = ModuleAdditionLocalParamsKey,
+    value = String,
+    db_prefix = DbKeyPrefix::ModuleAdditionLocalParams,
+);
+impl_db_lookup!(
+    key = ModuleAdditionLocalParamsKey,
+    query_prefix = ModuleAdditionLocalParamsKeyPrefix
+);
+
+/// Set when the DKG of a scheduled module addition failed for us, we propose
+/// cancelling it instead of retrying
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct ModuleAdditionFailedKey(pub ModuleInstanceId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ModuleAdditionFailedKeyPrefix;
+
+impl_db_record!(
+    key = ModuleAdditionFailedKey,
+    value = (),
+    db_prefix = DbKeyPrefix::ModuleAdditionFailed,
+);
+impl_db_lookup!(
+    key = ModuleAdditionFailedKey,
+    query_prefix = ModuleAdditionFailedKeyPrefix
+);
+
+/// Ordered encrypted consensus items that wait for a threshold of decryption
+/// shares, keyed by their id
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct PendingEncryptedItemKey(pub sha256::Hash);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct PendingEncryptedItemKeyPrefix;
+
+impl_db_record!(
+    key = PendingEncryptedItemKey,
+    value = PendingEncryptedItem,
+    db_prefix = DbKeyPrefix::PendingEncryptedItem,
+);
+impl_db_lookup!(
+    key = PendingEncryptedItemKey,
+    query_prefix = PendingEncryptedItemKeyPrefix
+);
+
+/// Encrypted consensus items that were revealed already, keyed by their id
+/// and mapping to the peer that encrypted them. Ordering one of them again is
+/// a replay.
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct RevealedEncryptedItemKey(pub sha256::Hash);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct RevealedEncryptedItemKeyPrefix;
+
+impl_db_record!(
+    key = RevealedEncryptedItemKey,
+    value = PeerId,
+    db_prefix = DbKeyPrefix::RevealedEncryptedItem,
+);
+impl_db_lookup!(
+    key = RevealedEncryptedItemKey,
+    query_prefix = RevealedEncryptedItemKeyPrefix
+);
+
+/// Ordered decryption share of a peer for a pending encrypted item
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct DecryptionShareKey(pub sha256::Hash, pub PeerId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct DecryptionShareKeyPrefix;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct DecryptionShareItemPrefix(pub sha256::Hash);
+
+impl_db_record!(
+    key = DecryptionShareKey,
+    value = SerdeDecryptionShare,
+    db_prefix = DbKeyPrefix::DecryptionShare,
+);
+impl_db_lookup!(
+    key = DecryptionShareKey,
+    query_prefix = DecryptionShareKeyPrefix,
+    query_prefix = DecryptionShareItemPrefix
+);
+
+/// Misbehavior score of a peer, see `MisbehaviorThresholds`
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct PeerScoreKey(pub PeerId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct PeerScoreKeyPrefix;
+
+impl_db_record!(
+    key = PeerScoreKey,
+    value = PeerScore,
+    db_prefix = DbKeyPrefix::PeerScore,
+);
+impl_db_lookup!(key = PeerScoreKey, query_prefix = PeerScoreKeyPrefix);
+
+/// Module consensus item of ours that is proposed until it has been ordered,
+/// keyed by its hash
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct JournaledItemKey(pub sha256::Hash);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct JournaledItemKeyPrefix;
+
+impl_db_record!(
+    key = JournaledItemKey,
+    value = DynModuleConsensusItem,
+    db_prefix = DbKeyPrefix::JournaledItem,
+);
+impl_db_lookup!(
+    key = JournaledItemKey,
+    query_prefix = JournaledItemKeyPrefix
+);
+
+/// Amounts of an accepted transaction, signed into its receipt
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct TransactionAmountsKey(pub TransactionId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct TransactionAmountsKeyPrefix;
+
+impl_db_record!(
+    key = TransactionAmountsKey,
+    value = TransactionAmounts,
+    db_prefix = DbKeyPrefix::TransactionAmounts,
+);
+impl_db_lookup!(
+    key = TransactionAmountsKey,
+    query_prefix = TransactionAmountsKeyPrefix
+);
+
 pub fn get_global_database_migrations<'a>() -> MigrationMap<'a> {
     MigrationMap::new()
 }
@@ -180,7 +398,7 @@ mod fedimint_migration_tests {
         AcceptedTransactionKey, ClientConfigSignatureKey, ConsensusUpgradeKey, DropPeerKey,
         EpochHistoryKey, LastEpochKey, RejectedTransactionKey,
     };
-    use crate::consensus::AcceptedTransaction;
+    use crate::consensus::{AcceptedTransaction, PendingEncryptedItem};
     use crate::core::DynOutput;
     use crate::db::{
         get_global_database_migrations, AcceptedTransactionKeyPrefix, ClientConfigDownloadKey,
@@ -396,6 +614,21 @@ mod fedimint_migration_tests {
                                     "validate_migrations was not able to read any ClientConfigDownloadKey"
                                 );
                             }
+                            // Added after v0, the snapshot doesn't contain them
+                            DbKeyPrefix::FederationMeta
+                            | DbKeyPrefix::MetaVote
+                            | DbKeyPrefix::ModuleAdditionVote
+                            | DbKeyPrefix::ScheduledModuleAddition
+                            | DbKeyPrefix::ModuleAdditionLocalParams
+                            | DbKeyPrefix::PendingEncryptedItem
+                            | DbKeyPrefix::DecryptionShare
+                            | DbKeyPrefix::PeerScore
+                            | DbKeyPrefix::JournaledItem
+                            | DbKeyPrefix::TransactionAmounts
+                            | DbKeyPrefix::ModuleAdditionFailed
+                            | DbKeyPrefix::RevealedEncryptedItem => {}
+                            // Only exists in encrypted databases
+                            DbKeyPrefix::EncryptedDatabaseCanary => {}
                             // Module prefix is reserved for modules, no migration testing is needed
                             DbKeyPrefix::Module => {}
                     }
@@ -0,0 +1,376 @@
+//! Transparent encryption of database values at rest
+//!
+//! Values are encrypted with a random key, keys stay in plaintext so prefix
+//! lookups keep working. Each value is authenticated together with its key,
+//! so values can't be swapped between keys without being detected.
+//!
+//! The database key is stored in the data dir, encrypted with the same key
+//! as the private config. Rotating the guardian password re-encrypts it along
+//! with the configs.
+//!
+//! A canary record written when the database is first opened lets a wrong
+//! password fail at startup instead of on the first read of a value.
+
+use std::fmt::{Debug, Formatter};
+use std::fs;
+use std::path::Path;
+
+use anyhow::{format_err, Context, Result};
+use async_trait::async_trait;
+use fedimint_aead::{
+    decrypt_with_aad, encrypt_with_aad, encrypted_read, encrypted_write,
+    get_database_key_from_secret, get_encryption_key, random_salt, LessSafeKey,
+};
+use fedimint_core::db::{IDatabase, ISingleUseDatabaseTransaction, PrefixStream};
+use fedimint_core::util::write_new;
+use futures::StreamExt;
+use rand::rngs::OsRng;
+use rand::Rng;
+
+use crate::config::io::SALT_FILE;
+use crate::db::DbKeyPrefix;
+
+/// Random database encryption key, encrypted with the config encryption key
+pub const DB_KEY_FILE: &str = "database.key";
+
+/// Key of the record used to check the password when opening the database
+const CANARY_KEY: [u8; 1] = [DbKeyPrefix::EncryptedDatabaseCanary as u8];
+const CANARY_VALUE: &[u8] = b"fedimint encrypted database";
+
+/// Returns true if the database in `data_dir` is encrypted, tools have to
+/// open it through [`EncryptedDatabase`] then
+pub fn is_database_encrypted(data_dir: &Path) -> bool {
+    data_dir.join(DB_KEY_FILE).exists()
+}
+
+/// Decrypts the database encryption key in `data_dir` with the config
+/// encryption key of `password`. On first use the config salt and the
+/// database key are created, config generation keeps the salt.
+pub fn database_encryption_key(password: &str, data_dir: &Path) -> Result<LessSafeKey> {
+    let salt_path = data_dir.join(SALT_FILE);
+    if !salt_path.exists() {
+        write_new(&salt_path, random_salt())?;
+    }
+    let salt = fs::read_to_string(&salt_path)
+        .with_context(|| format!("Unable to read config salt {salt_path:?}"))?;
+    let config_key = get_encryption_key(password, &salt)?;
+
+    let key_path = data_dir.join(DB_KEY_FILE);
+    if !key_path.exists() {
+        // Written to a temporary file first, a partially written key would make the
+        // database unreadable
+        let tmp_path = data_dir.join(format!("{DB_KEY_FILE}.tmp"));
+        if tmp_path.exists() {
+            fs::remove_file(&tmp_path)?;
+        }
+        let secret: [u8; 32] = OsRng.gen();
+        encrypted_write(secret.to_vec(), &config_key, tmp_path.clone())?;
+        fs::File::open(&tmp_path)?.sync_all()?;
+        fs::rename(&tmp_path, &key_path)?;
+    }
+
+    Ok(get_database_key_from_secret(read_database_secret(
+        &config_key,
+        data_dir,
+    )?))
+}
+
+/// Re-encrypts the database key in `from_dir` with `new_key` into `to_dir`,
+/// when the config encryption key changes. Does nothing if the database
+/// isn't encrypted.
+pub fn rewrap_database_key(
+    old_key: &LessSafeKey,
+    new_key: &LessSafeKey,
+    from_dir: &Path,
+    to_dir: &Path,
+) -> Result<()> {
+    if !is_database_encrypted(from_dir) {
+        return Ok(());
+    }
+    let secret = read_database_secret(old_key, from_dir)?;
+    encrypted_write(secret.to_vec(), new_key, to_dir.join(DB_KEY_FILE))
+}
+
+fn read_database_secret(config_key: &LessSafeKey, data_dir: &Path) -> Result<[u8; 32]> {
+    let secret = encrypted_read(config_key, data_dir.join(DB_KEY_FILE))
+        .context("Unable to decrypt the database key, wrong password?")?;
+    secret
+        .try_into()
+        .map_err(|_| format_err!("Database key has the wrong length"))
+}
+
+/// Wraps another database and encrypts all values before they are written to
+/// it
+pub struct EncryptedDatabase {
+    inner: Box<dyn IDatabase>,
+    key: LessSafeKey,
+}
+
+impl EncryptedDatabase {
+    /// Wraps `inner`, fails if it was encrypted with a key derived from
+    /// another password or isn't encrypted at all
+    pub async fn open(inner: impl IDatabase, key: LessSafeKey) -> Result<Self> {
+        let db = EncryptedDatabase {
+            inner: Box::new(inner),
+            key,
+        };
+        db.check_canary().await?;
+        Ok(db)
+    }
+
+    /// Decrypts the canary record, creates it if the database has none yet
+    async fn check_canary(&self) -> Result<()> {
+        let mut dbtx = self.inner.begin_transaction().await;
+        if let Some(canary) = dbtx.raw_get_bytes(&CANARY_KEY).await? {
+            let canary = decrypt_value(&self.key, &CANARY_KEY, canary)?;
+            anyhow::ensure!(canary == CANARY_VALUE, "Database canary corrupted");
+            return Ok(());
+        }
+
+        // Databases encrypted before the canary existed are checked with their
+        // first value instead
+        let first = dbtx.raw_find_by_prefix(&[]).await?.next().await;
+        if let Some((db_key, value)) = first {
+            decrypt_value(&self.key, &db_key, value)?;
+        }
+
+        let canary = encrypt_with_aad(CANARY_VALUE.to_vec(), &self.key, &CANARY_KEY)?;
+        dbtx.raw_insert_bytes(&CANARY_KEY, &canary).await?;
+        dbtx.commit_tx().await
+    }
+}
+
+impl Debug for EncryptedDatabase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EncryptedDatabase({:?})", self.inner)
+    }
+}
+
+#[async_trait]
+impl IDatabase for EncryptedDatabase {
+    async fn begin_transaction<'a>(&'a self) -> Box<dyn ISingleUseDatabaseTransaction<'a>> {
+        Box::new(EncryptedTransaction {
+            inner: self.inner.begin_transaction().await,
+            key: &self.key,
+        })
+    }
+
+    fn checkpoint(&self, backup_path: &Path) -> Result<()> {
+        // The checkpoint only contains encrypted values
+        self.inner.checkpoint(backup_path)
+    }
+}
+
+struct EncryptedTransaction<'a> {
+    inner: Box<dyn ISingleUseDatabaseTransaction<'a>>,
+    key: &'a LessSafeKey,
+}
+
+/// Decrypts and encrypts the values of a transaction of an encrypted database
+/// opened without [`EncryptedDatabase`], e.g. read-only
+pub fn encrypted_transaction<'a>(
+    inner: Box<dyn ISingleUseDatabaseTransaction<'a>>,
+    key: &'a LessSafeKey,
+) -> Box<dyn ISingleUseDatabaseTransaction<'a>> {
+    Box::new(EncryptedTransaction { inner, key })
+}
+
+fn decrypt_value(key: &LessSafeKey, db_key: &[u8], mut value: Vec<u8>) -> Result<Vec<u8>> {
+    Ok(decrypt_with_aad(&mut value, key, db_key)
+        .context("Unable to decrypt database value, wrong password?")?
+        .to_vec())
+}
+
+impl<'a> EncryptedTransaction<'a> {
+    fn decrypt_optional(&self, db_key: &[u8], value: Option<Vec<u8>>) -> Result<Option<Vec<u8>>> {
+        value
+            .map(|value| decrypt_value(self.key, db_key, value))
+            .transpose()
+    }
+
+    /// Decrypts the values as they are read. Like a read error of the
+    /// underlying database, a value that can't be decrypted panics since the
+    /// stream can't return errors.
+    fn decrypt_stream<'s>(key: &'s LessSafeKey, stream: PrefixStream<'s>) -> PrefixStream<'s> {
+        Box::pin(stream.map(move |(db_key, value)| {
+            let value = decrypt_value(key, &db_key, value).expect("Corrupted database value");
+            (db_key, value)
+        }))
+    }
+}
+
+#[async_trait]
+impl<'a> ISingleUseDatabaseTransaction<'a> for EncryptedTransaction<'a> {
+    async fn raw_insert_bytes(&mut self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>> {
+        let encrypted = encrypt_with_aad(value.to_vec(), self.key, key)?;
+        let previous = self.inner.raw_insert_bytes(key, &encrypted).await?;
+        self.decrypt_optional(key, previous)
+    }
+
+    async fn raw_get_bytes(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let value = self.inner.raw_get_bytes(key).await?;
+        self.decrypt_optional(key, value)
+    }
+
+    async fn raw_remove_entry(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let value = self.inner.raw_remove_entry(key).await?;
+        self.decrypt_optional(key, value)
+    }
+
+    async fn raw_find_by_prefix(&mut self, key_prefix: &[u8]) -> Result<PrefixStream<'_>> {
+        let stream = self.inner.raw_find_by_prefix(key_prefix).await?;
+        Ok(Self::decrypt_stream(self.key, stream))
+    }
+
+    async fn raw_find_by_prefix_sorted_descending(
+        &mut self,
+        key_prefix: &[u8],
+    ) -> Result<PrefixStream<'_>> {
+        let stream = self
+            .inner
+            .raw_find_by_prefix_sorted_descending(key_prefix)
+            .await?;
+        Ok(Self::decrypt_stream(self.key, stream))
+    }
+
+    async fn raw_remove_by_prefix(&mut self, key_prefix: &[u8]) -> Result<()> {
+        self.inner.raw_remove_by_prefix(key_prefix).await
+    }
+
+    async fn commit_tx(&mut self) -> Result<()> {
+        self.inner.commit_tx().await
+    }
+
+    async fn rollback_tx_to_savepoint(&mut self) -> Result<()> {
+        self.inner.rollback_tx_to_savepoint().await
+    }
+
+    async fn set_tx_savepoint(&mut self) -> Result<()> {
+        self.inner.set_tx_savepoint().await
+    }
+
+    fn add_notification_key(&mut self, key: &[u8]) -> Result<()> {
+        self.inner.add_notification_key(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::db::mem_impl::MemDatabase;
+    use fedimint_core::db::Database;
+    use fedimint_core::module::registry::ModuleDecoderRegistry;
+
+    use super::*;
+
+    async fn open_encrypted_db() -> EncryptedDatabase {
+        let key = get_database_key_from_secret(OsRng.gen());
+        EncryptedDatabase::open(MemDatabase::new(), key)
+            .await
+            .unwrap()
+    }
+
+    fn database(db: EncryptedDatabase) -> Database {
+        Database::new(db, ModuleDecoderRegistry::default())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dbtx_insert_elements() {
+        fedimint_core::db::verify_insert_elements(database(open_encrypted_db().await)).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dbtx_find_by_prefix() {
+        fedimint_core::db::verify_find_by_prefix(database(open_encrypted_db().await)).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_dbtx_remove_by_prefix() {
+        fedimint_core::db::verify_remove_by_prefix(database(open_encrypted_db().await)).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_values_encrypted_at_rest() {
+        let db = open_encrypted_db().await;
+
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.raw_insert_bytes(&[0x01], b"secret").await.unwrap();
+        assert_eq!(
+            dbtx.raw_get_bytes(&[0x01]).await.unwrap(),
+            Some(b"secret".to_vec())
+        );
+        dbtx.commit_tx().await.unwrap();
+
+        let mut inner_dbtx = db.inner.begin_transaction().await;
+        let stored = inner_dbtx.raw_get_bytes(&[0x01]).await.unwrap().unwrap();
+        assert!(!stored.windows(6).any(|window| window == b"secret"));
+
+        // Reading with another key fails
+        let wrong_key = get_database_key_from_secret(OsRng.gen());
+        assert!(decrypt_value(&wrong_key, &[0x01], stored.clone()).is_err());
+        // Moving a value to another key is detected
+        assert!(decrypt_value(&db.key, &[0x02], stored).is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_wrong_key_fails_on_open() {
+        let db = open_encrypted_db().await;
+        db.check_canary().await.unwrap();
+
+        let wrong = EncryptedDatabase {
+            inner: db.inner,
+            key: get_database_key_from_secret(OsRng.gen()),
+        };
+        assert!(wrong.check_canary().await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    #[should_panic(expected = "Corrupted database value")]
+    async fn test_corrupted_value_panics_when_read() {
+        let db = open_encrypted_db().await;
+
+        let mut inner_dbtx = db.inner.begin_transaction().await;
+        inner_dbtx
+            .raw_insert_bytes(&[0x01], b"garbage")
+            .await
+            .unwrap();
+        inner_dbtx.commit_tx().await.unwrap();
+
+        let mut dbtx = db.begin_transaction().await;
+        let stream = dbtx.raw_find_by_prefix(&[0x01]).await.unwrap();
+        stream.collect::<Vec<_>>().await;
+    }
+
+    #[test]
+    fn test_database_key_follows_password_rotation() {
+        let data_dir = tempfile::tempdir().unwrap();
+        let key = database_encryption_key("pass", data_dir.path()).unwrap();
+        assert!(is_database_encrypted(data_dir.path()));
+        let mut encrypted = encrypt_with_aad(b"value".to_vec(), &key, &[]).unwrap();
+
+        // The key is created once, opening again returns the same one
+        let reopened = database_encryption_key("pass", data_dir.path()).unwrap();
+        assert_eq!(
+            decrypt_with_aad(&mut encrypted.clone(), &reopened, &[]).unwrap(),
+            b"value"
+        );
+        assert!(database_encryption_key("wrong", data_dir.path()).is_err());
+
+        // Re-encrypting it under a new password keeps the database readable
+        let salt = fs::read_to_string(data_dir.path().join(SALT_FILE)).unwrap();
+        let old_key = get_encryption_key("pass", &salt).unwrap();
+        let new_key = get_encryption_key("new", &salt).unwrap();
+        let rotated_dir = tempfile::tempdir().unwrap();
+        fs::copy(
+            data_dir.path().join(SALT_FILE),
+            rotated_dir.path().join(SALT_FILE),
+        )
+        .unwrap();
+        rewrap_database_key(&old_key, &new_key, data_dir.path(), rotated_dir.path()).unwrap();
+
+        let rotated = database_encryption_key("new", rotated_dir.path()).unwrap();
+        assert_eq!(
+            decrypt_with_aad(&mut encrypted, &rotated, &[]).unwrap(),
+            b"value"
+        );
+    }
+}
@@ -9,9 +9,9 @@ use std::time::Duration;
 
 use anyhow::{anyhow as format_err, Context};
 use async_trait::async_trait;
-use config::io::PLAINTEXT_PASSWORD;
+use config::io::{overwrite_server_config, remove_staged_server_config, PLAINTEXT_PASSWORD};
 use config::ServerConfig;
-use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::core::{ModuleInstanceId, ModuleKind};
 use fedimint_core::db::Database;
 use fedimint_core::epoch::ConsensusItem;
 use fedimint_core::module::{ApiAuth, ApiEndpoint, ApiEndpointContext, ApiError, ApiRequestErased};
@@ -26,12 +26,14 @@ use jsonrpsee::types::ErrorObject;
 use jsonrpsee::RpcModule;
 use rand::rngs::OsRng;
 use tokio::runtime::Runtime;
-use tracing::{error, info};
+use tracing::field::display;
+use tracing::{error, info, info_span, Instrument};
+use url::Url;
 
 use crate::config::api::{ConfigGenApi, ConfigGenSettings};
-use crate::consensus::server::ConsensusServer;
+use crate::consensus::server::{ConsensusExit, ConsensusServer};
 use crate::consensus::HbbftConsensusOutcome;
-use crate::net::api::RpcHandlerCtx;
+use crate::net::api::{ConsensusApi, RpcHandlerCtx};
 use crate::net::connect::TlsTcpConnector;
 use crate::net::peers::ReconnectPeerConnections;
 
@@ -41,6 +43,9 @@ pub mod consensus;
 /// Provides interfaces for ACID-compliant data store backends
 pub mod db;
 
+/// Transparent encryption of database values at rest
+pub mod encrypted_db;
+
 /// Networking for mint-to-mint and client-to-mint communiccation
 pub mod net;
 
@@ -50,9 +55,22 @@ pub mod config;
 /// Implementation of multiplexed peer connections
 pub mod multiplexed;
 
+/// Online backups of the server database
+pub mod snapshot;
+
+/// Readiness and shutdown notifications for systemd
+pub mod sd_notify;
+
+/// Signing with guardian keys held locally or by an external signer
+pub mod signer;
+
 /// How long to wait before timing out client connections
 const API_ENDPOINT_TIMEOUT: Duration = Duration::from_secs(60);
 
+/// Prefix of the database checkpoints created in the data dir before running
+/// migrations
+pub const DB_MIGRATION_CHECKPOINT_PREFIX: &str = "db-pre-migration-";
+
 /// Has the context necessary for serving API endpoints
 ///
 /// Returns the specific `State` the endpoint requires and the
@@ -74,6 +92,10 @@ pub struct FedimintServer {
     pub settings: ConfigGenSettings,
     /// Database shared by the API and consensus
     pub db: Database,
+    /// Address to additionally serve the consensus API on over gRPC
+    pub grpc_bind: Option<SocketAddr>,
+    /// Nostr relays to announce the federation on, none disables announcing
+    pub nostr_relays: Vec<Url>,
 }
 
 impl FedimintServer {
@@ -85,20 +107,68 @@ impl FedimintServer {
             .run_config_gen(task_group.make_subgroup().await)
             .await?;
 
-        let server = ConsensusServer::new(
-            cfg,
-            self.db.clone(),
-            self.settings.registry.clone(),
-            &mut task_group,
-        )
-        .await
-        .unwrap();
+        if ConsensusServer::has_pending_migrations(&cfg, &self.db, &self.settings.registry).await? {
+            self.checkpoint_before_migration()?;
+        }
+
+        let mut cfg = cfg;
+        loop {
+            // Consensus restarts after adding a module, so its connections and
+            // module tasks run in a subgroup that can be shut down on its own
+            let mut consensus_task_group = task_group.make_subgroup().await;
+            if !self.nostr_relays.is_empty() {
+                net::announce::spawn_federation_announcer(
+                    &cfg,
+                    self.nostr_relays.clone(),
+                    &mut consensus_task_group,
+                )
+                .await;
+            }
+            let server = ConsensusServer::new(
+                cfg,
+                self.db.clone(),
+                self.settings.registry.clone(),
+                &mut consensus_task_group,
+            )
+            .await?
+            .with_data_dir(self.data_dir.clone());
+            let api_auth = server.consensus.api.api_auth.clone();
+
+            info!(target: LOG_CONSENSUS, "Starting consensus API");
+            let handler = Self::spawn_consensus_api(&server, true).await;
+            let grpc_handler = match self.grpc_bind {
+                Some(grpc_bind) => {
+                    Some(net::grpc::spawn_grpc_api(grpc_bind, &server.consensus.api).await)
+                }
+                None => None,
+            };
+            sd_notify::ready("Running consensus");
 
-        info!(target: LOG_CONSENSUS, "Starting consensus API");
-        let handler = Self::spawn_consensus_api(&server, true).await;
+            let exit = server.run_consensus(task_group.make_handle()).await?;
+            handler.stop().await;
+            if let Some(grpc_handler) = grpc_handler {
+                grpc_handler.stop().await;
+            }
 
-        server.run_consensus(task_group.make_handle()).await?;
-        handler.stop().await;
+            match exit {
+                ConsensusExit::Shutdown => break,
+                ConsensusExit::Restart(mut new_cfg) => {
+                    info!(target: LOG_CONSENSUS, "Restarting consensus");
+                    sd_notify::status("Restarting consensus");
+                    consensus_task_group.shutdown().await;
+                    // The password may have been rotated while consensus was running
+                    new_cfg.private.api_auth = api_auth.read().await.current().clone();
+                    overwrite_server_config(
+                        &new_cfg,
+                        self.data_dir.clone(),
+                        &new_cfg.private.api_auth.0,
+                        &self.settings.registry,
+                    )?;
+                    remove_staged_server_config(&self.data_dir)?;
+                    cfg = new_cfg;
+                }
+            }
+        }
 
         info!(target: LOG_CONSENSUS, "Shutting down tasks");
         task_group.shutdown().await;
@@ -106,6 +176,23 @@ impl FedimintServer {
         Ok(())
     }
 
+    /// Creates a checkpoint of the database in the data dir, so a failed
+    /// migration can be recovered from by restoring it
+    fn checkpoint_before_migration(&self) -> anyhow::Result<()> {
+        let timestamp = fedimint_core::time::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .expect("time to work")
+            .as_secs();
+        let path = self
+            .data_dir
+            .join(format!("{DB_MIGRATION_CHECKPOINT_PREFIX}{timestamp}"));
+
+        info!(target: LOG_CONSENSUS, ?path, "Creating database checkpoint before migrating");
+        self.db
+            .checkpoint(&path)
+            .context("Unable to create database checkpoint before migrating")
+    }
+
     /// Generates the `ServerConfig`
     ///
     /// If a local password file exists, will try to read the configs from the
@@ -136,9 +223,12 @@ impl FedimintServer {
         }
 
         let mut rpc_module = RpcHandlerCtx::new_module(config_gen);
-        Self::attach_endpoints(&mut rpc_module, config::api::server_endpoints(), None);
+        Self::attach_endpoints(&mut rpc_module, config::api::server_endpoints(), None, None);
+        config::api::attach_dkg_progress_subscription(&mut rpc_module);
         let handler =
             Self::spawn_api("config-gen", &self.settings.api_bind, rpc_module, 10, true).await;
+        // Setting up the federation is done through the API, so we are ready
+        sd_notify::ready("Waiting for config generation");
 
         let cfg = config_generated_rx.recv().await.expect("should not close");
         handler.stop().await;
@@ -153,28 +243,80 @@ impl FedimintServer {
     ) -> FedimintApiHandler {
         let api = &server.consensus.api;
         let cfg = &api.cfg.local;
-        let mut rpc_module = RpcHandlerCtx::new_module(api.clone());
-        Self::attach_endpoints(&mut rpc_module, net::api::server_endpoints(), None);
-        for (id, _, module) in api.modules.iter_modules() {
-            Self::attach_endpoints(&mut rpc_module, module.api_endpoints(), Some(id));
-        }
-
         Self::spawn_api(
             "consensus",
             &cfg.api_bind,
-            rpc_module,
+            Self::consensus_rpc_module(api),
             cfg.max_connections,
             force_shutdown,
         )
         .await
     }
 
+    /// Creates the `RpcModule` with all endpoints of the `ConsensusApi`
+    pub(crate) fn consensus_rpc_module(
+        api: &ConsensusApi,
+    ) -> RpcModule<RpcHandlerCtx<ConsensusApi>> {
+        let mut rpc_module = RpcHandlerCtx::new_module(api.clone());
+        Self::attach_endpoints(&mut rpc_module, net::api::server_endpoints(), None, None);
+        for (id, kind, module) in api.modules.iter_modules() {
+            Self::attach_endpoints(
+                &mut rpc_module,
+                module.api_endpoints(),
+                Some((id, kind)),
+                None,
+            );
+            // Clients that negotiated an api version call the endpoints of its major version
+            let Some(versions) = api.supported_api_versions.modules.get(&id) else {
+                continue;
+            };
+            for version in &versions.api {
+                Self::attach_endpoints(
+                    &mut rpc_module,
+                    module.api_endpoints_for_version(version),
+                    Some((id, kind)),
+                    Some(version.major),
+                );
+            }
+        }
+        rpc_module
+    }
+
+    /// Names of the methods of [`FedimintServer::consensus_rpc_module`],
+    /// without registering the endpoints
+    pub(crate) fn consensus_api_methods(api: &ConsensusApi) -> Vec<String> {
+        let mut methods = net::api::server_endpoints()
+            .into_iter()
+            .map(|endpoint| endpoint.path.to_owned())
+            .collect::<Vec<_>>();
+        for (id, _, module) in api.modules.iter_modules() {
+            methods.extend(
+                module
+                    .api_endpoints()
+                    .into_iter()
+                    .map(|endpoint| method_name(endpoint.path, Some(id), None)),
+            );
+            let Some(versions) = api.supported_api_versions.modules.get(&id) else {
+                continue;
+            };
+            for version in &versions.api {
+                methods.extend(
+                    module
+                        .api_endpoints_for_version(version)
+                        .into_iter()
+                        .map(|endpoint| method_name(endpoint.path, Some(id), Some(version.major))),
+                );
+            }
+        }
+        methods
+    }
+
     /// Spawns an API server
     ///
     /// `force_shutdown` runs the API in a new runtime that the
     /// `FedimintApiHandler` can force to shutdown, otherwise the task cannot
     /// easily be killed.
-    async fn spawn_api<T>(
+    pub(crate) async fn spawn_api<T>(
         name: &'static str,
         api_bind: &SocketAddr,
         module: RpcModule<RpcHandlerCtx<T>>,
@@ -207,23 +349,30 @@ impl FedimintServer {
     }
 
     /// Attaches `endpoints` to the `RpcModule`
-    fn attach_endpoints<State, T>(
+    ///
+    /// Module endpoints are named as described in [`method_name`]. Their
+    /// errors are attributed to the module's kind.
+    pub(crate) fn attach_endpoints<State, T>(
         rpc_module: &mut RpcModule<RpcHandlerCtx<T>>,
         endpoints: Vec<ApiEndpoint<State>>,
-        module_instance_id: Option<ModuleInstanceId>,
+        module: Option<(ModuleInstanceId, &ModuleKind)>,
+        api_major_version: Option<u32>,
     ) where
         T: HasApiContext<State> + Sync + Send + 'static,
         State: Sync + Send + 'static,
     {
+        let module_instance_id = module.map(|(id, _)| id);
+        let module_kind = module.map(|(_, kind)| kind.clone());
         for endpoint in endpoints {
-            let path = if let Some(module_instance_id) = module_instance_id {
-                // This memory leak is fine because it only happens on server startup
-                // and path has to live till the end of program anyways.
-                Box::leak(
-                    format!("module_{}_{}", module_instance_id, endpoint.path).into_boxed_str(),
-                )
-            } else {
-                endpoint.path
+            let module_kind = module_kind.clone();
+            let path = match module_instance_id {
+                // This memory leak is fine because it only happens on server startup and
+                // path has to live till the end of program anyways.
+                Some(_) => Box::leak(
+                    method_name(endpoint.path, module_instance_id, api_major_version)
+                        .into_boxed_str(),
+                ),
+                None => endpoint.path,
             };
             // Check if paths contain any abnormal characters
             if path.contains(|c: char| !matches!(c, '0'..='9' | 'a'..='z' | '_')) {
@@ -235,49 +384,93 @@ impl FedimintServer {
             let handler: &'static _ = Box::leak(endpoint.handler);
 
             rpc_module
-                .register_async_method(path, move |params, rpc_state| async move {
-                    let params = params.one::<serde_json::Value>()?;
-                    let rpc_context = &rpc_state.rpc_context;
-
-                    // Using AssertUnwindSafe here is far from ideal. In theory this means we could
-                    // end up with an inconsistent state in theory. In practice most API functions
-                    // are only reading and the few that do write anything are atomic. Lastly, this
-                    // is only the last line of defense
-                    AssertUnwindSafe(tokio::time::timeout(API_ENDPOINT_TIMEOUT, async {
-                        let request = serde_json::from_value(params)
-                            .map_err(|e| ApiError::bad_request(e.to_string()))?;
-                        let (state, context) =
-                            rpc_context.context(&request, module_instance_id).await;
-
-                        (handler)(state, context, request).await
-                    }))
-                    .catch_unwind()
-                    .await
-                    .map_err(|_| {
-                        error!(
+                .register_async_method(path, move |params, rpc_state| {
+                    let module_kind = module_kind.clone();
+                    async move {
+                        let params = params.one::<serde_json::Value>()?;
+                        let rpc_context = &rpc_state.rpc_context;
+                        let request = serde_json::from_value::<ApiRequestErased>(params)
+                            .map_err(|e| ApiError::bad_request(e.to_string()));
+                        let correlation_id = request
+                            .as_ref()
+                            .ok()
+                            .and_then(|request| request.correlation_id);
+                        let span = info_span!(
                             target: LOG_NET_API,
-                            path, "API handler panicked, DO NOT IGNORE, FIX IT!!!"
+                            "api_request",
+                            method = path,
+                            correlation_id = correlation_id.map(display)
                         );
-                        jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
-                            500,
-                            "API handler panicked",
-                            None::<()>,
-                        )))
-                    })?
-                    .map_err(|tokio::time::error::Elapsed { .. }| {
-                        jsonrpsee::core::Error::RequestTimeout
-                    })?
-                    .map_err(|e| {
-                        jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
-                            e.code, e.message, None::<()>,
-                        )))
-                    })
+
+                        // Using AssertUnwindSafe here is far from ideal. In theory this means we
+                        // could end up with an inconsistent state in theory. In practice most API
+                        // functions are only reading and the few that do write anything are
+                        // atomic. Lastly, this is only the last line of defense
+                        AssertUnwindSafe(tokio::time::timeout(
+                            API_ENDPOINT_TIMEOUT,
+                            async {
+                                let request = request?;
+                                let (state, context) =
+                                    rpc_context.context(&request, module_instance_id).await;
+
+                                (handler)(state, context, request).await
+                            }
+                            .instrument(span),
+                        ))
+                        .catch_unwind()
+                        .await
+                        .map_err(|_| {
+                            error!(
+                                target: LOG_NET_API,
+                                path, "API handler panicked, DO NOT IGNORE, FIX IT!!!"
+                            );
+                            jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
+                                500,
+                                "API handler panicked",
+                                None::<()>,
+                            )))
+                        })?
+                        .map_err(|tokio::time::error::Elapsed { .. }| {
+                            jsonrpsee::core::Error::RequestTimeout
+                        })?
+                        .map_err(|e| {
+                            let e = match &module_kind {
+                                Some(kind) => e.in_module(kind.clone()),
+                                None => e,
+                            }
+                            .with_correlation_id(correlation_id);
+                            let data = e.data();
+                            jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
+                                e.code,
+                                e.message,
+                                Some(data),
+                            )))
+                        })
+                    }
                 })
                 .expect("Failed to register async method");
         }
     }
 }
 
+/// Name of the method serving the endpoint `path`
+///
+/// Module endpoints are prefixed with the module instance id and, if given,
+/// the major version of the module's API they belong to.
+fn method_name(
+    path: &str,
+    module_instance_id: Option<ModuleInstanceId>,
+    api_major_version: Option<u32>,
+) -> String {
+    match (module_instance_id, api_major_version) {
+        (Some(module_instance_id), Some(major)) => {
+            format!("module_{module_instance_id}_v{major}_{path}")
+        }
+        (Some(module_instance_id), None) => format!("module_{module_instance_id}_{path}"),
+        (None, _) => path.to_owned(),
+    }
+}
+
 pub struct FedimintApiHandler {
     runtime: Option<Runtime>,
     handle: ServerHandle,
@@ -0,0 +1,74 @@
+//! Announces the federation on Nostr relays while consensus is running
+
+use bitcoin_hashes::{sha256, Hash};
+use fedimint_core::config::META_FEDERATION_NAME_KEY;
+use fedimint_core::task::{sleep, TaskGroup};
+use fedimint_logging::LOG_NET_API;
+use fedimint_nostr::{FederationAnnouncement, NostrAnnouncer, ANNOUNCEMENT_INTERVAL};
+use tracing::{info, warn};
+use url::Url;
+
+use crate::config::ServerConfig;
+
+/// Domain separation for deriving the Nostr key of a guardian
+const NOSTR_KEY_TAG: &[u8] = b"fedimint-nostr-announcement-key";
+
+pub fn federation_announcement(cfg: &ServerConfig) -> FederationAnnouncement {
+    let connect_info = cfg.get_connect_info();
+    FederationAnnouncement {
+        federation_id: connect_info.id,
+        name: cfg.consensus.meta.get(META_FEDERATION_NAME_KEY).cloned(),
+        connect_info,
+        modules: cfg
+            .consensus
+            .modules_json
+            .values()
+            .map(|module| module.kind().clone())
+            .collect(),
+    }
+}
+
+/// The Nostr key is derived from our TLS key, so guardians keep the same
+/// identity across restarts without having to manage another secret
+fn nostr_secret_key(cfg: &ServerConfig) -> [u8; 32] {
+    sha256::Hash::hash(&[NOSTR_KEY_TAG, &cfg.private.tls_key.0].concat()).into_inner()
+}
+
+/// Republishes our announcement of the federation to `relays` every
+/// [`ANNOUNCEMENT_INTERVAL`] until `task_group` shuts down
+pub async fn spawn_federation_announcer(
+    cfg: &ServerConfig,
+    relays: Vec<Url>,
+    task_group: &mut TaskGroup,
+) {
+    let announcement = federation_announcement(cfg);
+    let secret_key = nostr_secret_key(cfg);
+
+    task_group
+        .spawn("nostr announcer", move |handle| async move {
+            let announcer = match NostrAnnouncer::new(secret_key, &relays).await {
+                Ok(announcer) => announcer,
+                Err(e) => {
+                    warn!(target: LOG_NET_API, "Failed to connect to nostr relays: {e:#}");
+                    return;
+                }
+            };
+            info!(
+                target: LOG_NET_API,
+                public_key = %announcer.public_key(),
+                "Announcing federation on nostr"
+            );
+
+            let mut shutdown_rx = handle.make_shutdown_rx().await;
+            loop {
+                if let Err(e) = announcer.announce(&announcement).await {
+                    warn!(target: LOG_NET_API, "Failed to announce federation: {e:#}");
+                }
+                tokio::select! {
+                    _ = sleep(ANNOUNCEMENT_INTERVAL) => {},
+                    _ = &mut shutdown_rx => break,
+                }
+            }
+        })
+        .await;
+}
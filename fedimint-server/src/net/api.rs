@@ -1,53 +1,82 @@
 //! Implements the client API through which users interact with the federation
 use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::{Duration, Instant, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
 use bitcoin_hashes::sha256;
+use fedimint_core::admin_client::{
+    AdminStatus, ApiGatewayInfo, AuditReport, DatabaseBackupRequest, DatabaseBackupResponse,
+    ModuleAdditionRequest, ModuleAdditionStatus, ModuleAdminStatus, ModuleAuditReport,
+    PasswordRotationRequest, PasswordRotationResponse, PeerConnectivity, PeerScore,
+};
 use fedimint_core::api::{
-    ConsensusStatus, PeerConnectionStatus, PeerConsensusStatus, ServerStatus, StatusResponse,
-    WsClientConnectInfo,
+    ConsensusStatus, ModuleHealth, PeerConnectionStatus, PeerConsensusStatus, ServerStatus,
+    StatusResponse, WsClientConnectInfo,
 };
 use fedimint_core::backup::ClientBackupKey;
-use fedimint_core::config::{ClientConfig, ClientConfigResponse};
+use fedimint_core::config::{ClientConfig, ClientConfigResponse, ServerModuleGenRegistry};
 use fedimint_core::core::backup::SignedBackupRequest;
 use fedimint_core::core::ModuleInstanceId;
-use fedimint_core::db::{Database, DatabaseTransaction, ModuleDatabaseTransaction};
-use fedimint_core::epoch::{SerdeEpochHistory, SignedEpochOutcome};
+use fedimint_core::db::snapshot::DatabaseSnapshot;
+use fedimint_core::db::{
+    get_migration_journal, Database, DatabaseKeyPrefix, DatabaseTransaction, DatabaseValue,
+    MigrationJournalEntry, ModuleDatabaseTransaction,
+};
+use fedimint_core::epoch::{
+    ConsensusItem, EpochItemFilter, EpochItemsRequest, EpochTimingEstimate, FilteredEpochItems,
+    ModuleAddition, SerdeEpochHistory, SerdeFilteredEpochItems, SerdeSignatureShare,
+    SerdeTransactionInclusionProof, SignedEpochOutcome, TransactionInclusionProof,
+};
+use fedimint_core::join::{FederationSnapshot, FederationSnapshotShare};
+use fedimint_core::module::audit::Audit;
 use fedimint_core::module::registry::ServerModuleRegistry;
 use fedimint_core::module::{
-    api_endpoint, ApiEndpoint, ApiEndpointContext, ApiError, ApiRequestErased,
-    SupportedApiVersionsSummary,
+    api_endpoint, ApiAuth, ApiEndpoint, ApiEndpointContext, ApiError, ApiRequestErased,
+    CorrelationId, SerdeModuleEncoding, SupportedApiVersionsSummary,
 };
 use fedimint_core::outcome::TransactionStatus;
+use fedimint_core::receipt::{ReceiptOutcome, TransactionReceipt, TransactionReceiptShare};
 use fedimint_core::server::DynServerModule;
+use fedimint_core::stats::{EpochStats, EpochStatsShare};
+use fedimint_core::task::{block_in_place, TaskGroup};
+use fedimint_core::time::now;
 use fedimint_core::transaction::Transaction;
 use fedimint_core::{OutPoint, PeerId, TransactionId};
 use fedimint_logging::LOG_NET_API;
+use futures::StreamExt;
+use itertools::Itertools;
 use jsonrpsee::RpcModule;
+use rand::distributions::Alphanumeric;
+use rand::Rng;
 use secp256k1_zkp::SECP256K1;
 use tokio::sync::mpsc::error::SendError;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::RwLock;
 use tracing::{debug, info};
 
-use super::peers::PeerStatusChannels;
+use super::peers::{PeerStatusChannels, MAX_CLOCK_SKEW};
 use crate::backup::ClientBackupSnapshot;
 use crate::config::api::{get_verification_hashes, ApiResult};
+use crate::config::io::rotate_config_password;
 use crate::config::ServerConfig;
 use crate::consensus::server::LatestContributionByPeer;
+use crate::consensus::timing::EpochTimingTracker;
 use crate::consensus::{
     AcceptedTransaction, ApiEvent, FundingVerifier, TransactionSubmissionError,
 };
 use crate::db::{
     AcceptedTransactionKey, ClientConfigDownloadKey, ClientConfigSignatureKey, EpochHistoryKey,
-    LastEpochKey, RejectedTransactionKey,
+    FederationMetaKey, LastEpochKey, MetaVoteKeyPrefix, ModuleAdditionLocalParamsKey,
+    ModuleAdditionVoteKeyPrefix, PeerScoreKeyPrefix, RejectedTransactionKey,
+    ScheduledModuleAdditionKey, TransactionAmountsKey,
 };
 use crate::fedimint_core::encoding::Encodable;
+use crate::snapshot;
 use crate::transaction::SerdeTransaction;
-use crate::HasApiContext;
+use crate::{FedimintServer, HasApiContext};
 
 /// A state that has context for the API, passed to each rpc handler callback
 #[derive(Clone)]
@@ -77,6 +106,8 @@ pub struct ConsensusApi {
     pub db: Database,
     /// Modules registered with the federation
     pub modules: ServerModuleRegistry,
+    /// Module generators, used to validate modules proposed for addition
+    pub module_inits: ServerModuleGenRegistry,
     /// Cached client config
     pub client_cfg: ClientConfig,
     /// For sending API events to consensus such as transactions
@@ -85,6 +116,71 @@ pub struct ConsensusApi {
     pub latest_contribution_by_peer: Arc<RwLock<LatestContributionByPeer>>,
     pub consensus_status_cache: ExpiringCache<ApiResult<ConsensusStatus>>,
     pub supported_api_versions: SupportedApiVersionsSummary,
+    /// Admin passwords, shared with the server so a rotated password is kept
+    /// when consensus restarts
+    pub api_auth: Arc<RwLock<ApiAuthState>>,
+    /// Directory of our configs, the password can't be rotated without it and
+    /// database backups are written into it
+    pub data_dir: Option<PathBuf>,
+    /// Correlation ids of the transactions submitted through our API, so they
+    /// show up in the logs when the transactions are processed
+    pub transaction_correlation_ids: Arc<std::sync::Mutex<BTreeMap<TransactionId, CorrelationId>>>,
+    /// Task groups the modules spawn their tasks in
+    pub module_task_groups: BTreeMap<ModuleInstanceId, TaskGroup>,
+    /// Durations of our recent epochs, updated by the consensus server
+    pub epoch_timing: Arc<std::sync::Mutex<EpochTimingTracker>>,
+}
+
+/// Limits the correlation ids kept for transactions that never make it into an
+/// epoch
+const MAX_TRANSACTION_CORRELATION_IDS: usize = 10_000;
+
+/// Epochs sent to an API gateway per replication request, gateways that are
+/// further behind catch up over several requests
+const MAX_REPLICATED_EPOCHS: u64 = 100;
+
+/// How long the previous admin password stays valid after rotating it, so
+/// tools still using it can be switched over without failing requests
+pub const PASSWORD_ROTATION_GRACE_PERIOD: Duration = Duration::from_secs(10 * 60);
+
+/// Admin passwords accepted by the API
+#[derive(Debug, Clone)]
+pub struct ApiAuthState {
+    current: ApiAuth,
+    /// Password before the last rotation and until when it's accepted
+    previous: Option<(ApiAuth, SystemTime)>,
+}
+
+impl ApiAuthState {
+    pub fn new(current: ApiAuth) -> Self {
+        ApiAuthState {
+            current,
+            previous: None,
+        }
+    }
+
+    pub fn current(&self) -> &ApiAuth {
+        &self.current
+    }
+
+    pub fn accepts(&self, auth: &ApiAuth, now: SystemTime) -> bool {
+        *auth == self.current
+            || self
+                .previous
+                .as_ref()
+                .map_or(false, |(previous, valid_until)| {
+                    auth == previous && now < *valid_until
+                })
+    }
+
+    /// Switches to `new`, the current password is accepted until the returned
+    /// time
+    pub fn rotate(&mut self, new: ApiAuth, now: SystemTime) -> SystemTime {
+        let valid_until = now + PASSWORD_ROTATION_GRACE_PERIOD;
+        let previous = std::mem::replace(&mut self.current, new);
+        self.previous = Some((previous, valid_until));
+        valid_until
+    }
 }
 
 impl ConsensusApi {
@@ -95,6 +191,7 @@ impl ConsensusApi {
     pub async fn submit_transaction(
         &self,
         transaction: Transaction,
+        correlation_id: Option<CorrelationId>,
     ) -> Result<(), TransactionSubmissionError> {
         // we already processed the transaction before the request was received
         if self
@@ -126,7 +223,9 @@ impl ConsensusApi {
                     input,
                 )
                 .await
-                .map_err(|e| TransactionSubmissionError::ModuleError(tx_hash, e))?;
+                .map_err(|e| {
+                    TransactionSubmissionError::ModuleError(tx_hash, input.module_instance_id(), e)
+                })?;
 
             pub_keys.push(meta.pub_keys);
             funding_verifier.add_input(meta.amount);
@@ -142,12 +241,21 @@ impl ConsensusApi {
                     output,
                 )
                 .await
-                .map_err(|e| TransactionSubmissionError::ModuleError(tx_hash, e))?;
+                .map_err(|e| {
+                    TransactionSubmissionError::ModuleError(tx_hash, output.module_instance_id(), e)
+                })?;
             funding_verifier.add_output(amount);
         }
 
         funding_verifier.verify_funding()?;
 
+        if let Some(correlation_id) = correlation_id {
+            let mut correlation_ids = self.transaction_correlation_ids.lock().expect("poisoned");
+            if correlation_ids.len() < MAX_TRANSACTION_CORRELATION_IDS {
+                correlation_ids.insert(tx_hash, correlation_id);
+            }
+        }
+
         self.api_sender
             .send(ApiEvent::Transaction(transaction))
             .await
@@ -155,6 +263,33 @@ impl ConsensusApi {
         Ok(())
     }
 
+    /// Correlation id the transaction was submitted with through our API, if
+    /// any, is only returned once
+    pub fn take_transaction_correlation_id(&self, txid: TransactionId) -> Option<CorrelationId> {
+        self.transaction_correlation_ids
+            .lock()
+            .expect("poisoned")
+            .remove(&txid)
+    }
+
+    /// Maps the reason a submitted transaction was rejected to the error
+    /// returned to the client, keeping the code of module errors
+    fn transaction_api_error(&self, error: TransactionSubmissionError) -> ApiError {
+        match &error {
+            TransactionSubmissionError::ModuleError(_, module_instance_id, module_error) => {
+                match self.modules.get_with_kind(*module_instance_id) {
+                    Some((kind, _)) => ApiError::from_module_error(kind.clone(), module_error),
+                    None => ApiError::bad_request(error.to_string()),
+                }
+            }
+            TransactionSubmissionError::TxChannelError => ApiError::unavailable(error.to_string()),
+            TransactionSubmissionError::TransactionError(_)
+            | TransactionSubmissionError::TransactionReplayError(_) => {
+                ApiError::bad_request(error.to_string())
+            }
+        }
+    }
+
     pub async fn transaction_status(
         &self,
         txid: TransactionId,
@@ -262,7 +397,405 @@ impl ConsensusApi {
             }
         }
 
-        Ok(self.client_cfg.clone())
+        Ok(self.client_config(dbtx).await)
+    }
+
+    /// Returns our snapshot of the state a joining client needs and our
+    /// signature share over it, the client combines the shares of a threshold
+    /// of guardians
+    pub async fn join_snapshot(
+        &self,
+        info: WsClientConnectInfo,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> ApiResult<FederationSnapshotShare> {
+        // Only the guardian that issued the download token hands out the config,
+        // counting the download towards its limit
+        let config = if info.download_token == self.cfg.local.download_token {
+            Some(self.download_client_config(info, dbtx).await?)
+        } else {
+            None
+        };
+        let config_hash = self.client_config(dbtx).await.consensus_hash();
+
+        let mut module_dbtx = self.db.begin_transaction().await;
+        let mut modules = BTreeMap::new();
+        for (module_instance_id, _, module) in self.modules.iter_modules() {
+            if let Some(snapshot) = module
+                .join_snapshot(&mut module_dbtx.with_module_prefix(module_instance_id))
+                .await
+            {
+                modules.insert(module_instance_id, snapshot);
+            }
+        }
+
+        let snapshot = FederationSnapshot {
+            config_hash,
+            modules,
+        };
+        let share = self.cfg.private.auth_sks.0.sign(snapshot.signing_hash());
+        Ok(FederationSnapshotShare {
+            snapshot,
+            config,
+            auth_pk_set: self.cfg.consensus.auth_pk_set.clone(),
+            share: SerdeSignatureShare(share),
+        })
+    }
+
+    /// Returns the aggregate activity of `epoch` and our signature share over
+    /// it, `None` if the epoch wasn't processed yet
+    pub async fn epoch_stats(&self, epoch: u64) -> Option<EpochStatsShare> {
+        let outcome = self
+            .db
+            .begin_transaction()
+            .await
+            .get_value(&EpochHistoryKey(epoch))
+            .await?
+            .outcome;
+
+        // Every peer contributes the transactions it received, so they show up
+        // more than once
+        let transactions = outcome
+            .items
+            .iter()
+            .flat_map(|(_, items)| items)
+            .filter_map(|item| match item {
+                ConsensusItem::Transaction(tx) => Some(tx),
+                _ => None,
+            })
+            .filter(|tx| !outcome.rejected_txs.contains(&tx.tx_hash()))
+            .unique_by(|tx| tx.tx_hash())
+            .collect::<Vec<_>>();
+        let mut activity = vec![];
+        for tx in &transactions {
+            for input in &tx.inputs {
+                if let Some(module) = self.modules.get(input.module_instance_id()) {
+                    activity.push(module.input_activity(input));
+                }
+            }
+            for output in &tx.outputs {
+                if let Some(module) = self.modules.get(output.module_instance_id()) {
+                    activity.push(module.output_activity(output));
+                }
+            }
+        }
+
+        let stats = EpochStats::new(epoch, transactions.len() as u64, activity);
+        let share = self.cfg.private.auth_sks.0.sign(stats.signing_hash());
+        Some(EpochStatsShare {
+            stats,
+            auth_pk_set: self.cfg.consensus.auth_pk_set.clone(),
+            share: SerdeSignatureShare(share),
+        })
+    }
+
+    /// Our share of the receipt of `txid`, `None` if it wasn't processed yet
+    /// or was accepted before we started keeping the amounts of transactions
+    pub async fn transaction_receipt(
+        &self,
+        txid: TransactionId,
+    ) -> Option<TransactionReceiptShare> {
+        let mut dbtx = self.db.begin_transaction().await;
+
+        let outcome = if let Some(accepted) = dbtx.get_value(&AcceptedTransactionKey(txid)).await {
+            ReceiptOutcome::Accepted {
+                epoch: accepted.epoch,
+                amounts: dbtx.get_value(&TransactionAmountsKey(txid)).await?,
+            }
+        } else if dbtx
+            .get_value(&RejectedTransactionKey(txid))
+            .await
+            .is_some()
+        {
+            ReceiptOutcome::Rejected
+        } else {
+            return None;
+        };
+
+        let receipt = TransactionReceipt { txid, outcome };
+        let share = self.cfg.private.auth_sks.0.sign(receipt.signing_hash());
+        Some(TransactionReceiptShare {
+            receipt,
+            auth_pk_set: self.cfg.consensus.auth_pk_set.clone(),
+            share: SerdeSignatureShare(share),
+        })
+    }
+
+    /// Raw database entries of up to [`MAX_REPLICATED_EPOCHS`] epochs starting
+    /// at `from_epoch` and of the transactions accepted in them, to be imported
+    /// by [API gateways](crate::net::gateway)
+    ///
+    /// The last epoch is set to the last one included, so a gateway never
+    /// believes to have epochs it didn't receive yet.
+    pub async fn replicate_epochs(&self, from_epoch: u64) -> DatabaseSnapshot {
+        let mut dbtx = self.db.begin_transaction().await;
+        let mut entries = BTreeMap::new();
+        for epoch in from_epoch..from_epoch.saturating_add(MAX_REPLICATED_EPOCHS) {
+            let key = EpochHistoryKey(epoch);
+            let Some(history) = dbtx.get_value(&key).await else {
+                break;
+            };
+            for item in history.outcome.items.iter().flat_map(|(_, items)| items) {
+                let ConsensusItem::Transaction(tx) = item else {
+                    continue;
+                };
+                let accepted_key = AcceptedTransactionKey(tx.tx_hash());
+                if let Some(accepted) = dbtx.get_value(&accepted_key).await {
+                    entries.insert(
+                        DatabaseKeyPrefix::to_bytes(&accepted_key),
+                        Some(DatabaseValue::to_bytes(&accepted)),
+                    );
+                }
+            }
+            entries.insert(
+                DatabaseKeyPrefix::to_bytes(&key),
+                Some(DatabaseValue::to_bytes(&history)),
+            );
+            entries.insert(
+                DatabaseKeyPrefix::to_bytes(&LastEpochKey),
+                Some(DatabaseValue::to_bytes(&key)),
+            );
+        }
+
+        DatabaseSnapshot {
+            base: None,
+            entries,
+        }
+    }
+
+    /// Returns the client config with the latest meta agreed on by the
+    /// guardians
+    pub async fn client_config(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) -> ClientConfig {
+        let mut client_cfg = self.client_cfg.clone();
+        if let Some(meta) = dbtx.get_value(&FederationMetaKey).await {
+            client_cfg.meta = meta;
+        }
+        client_cfg
+    }
+
+    /// Votes for replacing the federation meta with `meta`
+    pub async fn update_meta(
+        &self,
+        meta: BTreeMap<String, String>,
+    ) -> Result<(), SendError<ApiEvent>> {
+        self.api_sender.send(ApiEvent::MetaUpdate(meta)).await
+    }
+
+    /// Returns the meta votes of the guardians that didn't reach the threshold
+    /// yet
+    pub async fn meta_votes(&self) -> BTreeMap<PeerId, BTreeMap<String, String>> {
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.find_by_prefix(&MetaVoteKeyPrefix)
+            .await
+            .map(|(key, meta)| (key.0, meta))
+            .collect()
+            .await
+    }
+
+    /// Stores our local params of the module and votes for adding it
+    pub async fn propose_module_addition(&self, request: ModuleAdditionRequest) -> ApiResult<()> {
+        let ModuleAdditionRequest {
+            module_instance_id,
+            kind,
+            params,
+            activation_epoch,
+        } = request;
+
+        if self.cfg.consensus.modules.contains_key(&module_instance_id) {
+            return Err(ApiError::bad_request(format!(
+                "Module instance {module_instance_id} already exists"
+            )));
+        }
+        let gen = self
+            .module_inits
+            .get(&kind)
+            .ok_or_else(|| ApiError::bad_request(format!("Unsupported module kind {kind}")))?;
+        gen.validate_params(&params)
+            .map_err(|e| ApiError::bad_request(e.to_string()))?;
+        let (Some(local), Some(consensus)) = (params.local, params.consensus) else {
+            return Err(ApiError::bad_request(
+                "Local and consensus params are required".to_string(),
+            ));
+        };
+
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.insert_entry(
+            &ModuleAdditionLocalParamsKey(module_instance_id),
+            &local.to_string(),
+        )
+        .await;
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|e| ApiError::server_error(e.to_string()))?;
+
+        let addition = ModuleAddition {
+            module_instance_id,
+            kind,
+            consensus_params: consensus.to_string(),
+            activation_epoch,
+        };
+        self.api_sender
+            .send(ApiEvent::ModuleAddition(addition))
+            .await
+            .map_err(|_| ApiError::server_error("Unable to send signal to server".to_string()))
+    }
+
+    /// Proposes cancelling the addition of a module instance
+    pub async fn cancel_module_addition(
+        &self,
+        module_instance_id: ModuleInstanceId,
+    ) -> ApiResult<()> {
+        self.api_sender
+            .send(ApiEvent::ModuleAdditionCancel(module_instance_id))
+            .await
+            .map_err(|_| ApiError::server_error("Unable to send signal to server".to_string()))
+    }
+
+    /// Returns the module addition votes that not all guardians agreed on yet
+    /// and the scheduled addition
+    pub async fn module_addition_status(&self) -> ModuleAdditionStatus {
+        let mut dbtx = self.db.begin_transaction().await;
+        let votes = dbtx
+            .find_by_prefix(&ModuleAdditionVoteKeyPrefix)
+            .await
+            .map(|(key, addition)| (key.0, addition))
+            .collect()
+            .await;
+        let scheduled = dbtx.get_value(&ScheduledModuleAdditionKey).await;
+
+        ModuleAdditionStatus { votes, scheduled }
+    }
+
+    /// Returns the misbehavior scores of all peers that misbehaved so far
+    pub async fn peer_scores(&self) -> BTreeMap<PeerId, PeerScore> {
+        let mut dbtx = self.db.begin_transaction().await;
+        dbtx.find_by_prefix(&PeerScoreKeyPrefix)
+            .await
+            .map(|(key, score)| (key.0, score))
+            .collect()
+            .await
+    }
+
+    /// Combines the consensus status of the other peers with their scores
+    pub async fn peer_connectivity(&self) -> ApiResult<BTreeMap<PeerId, PeerConnectivity>> {
+        let mut scores = self.peer_scores().await;
+        Ok(self
+            .get_consensus_status()
+            .await?
+            .status_by_peer
+            .into_iter()
+            .map(|(peer, consensus)| {
+                let connectivity = PeerConnectivity {
+                    consensus,
+                    score: scores.remove(&peer),
+                };
+                (peer, connectivity)
+            })
+            .collect())
+    }
+
+    /// Runs the audit of every module separately so the balance sheet can be
+    /// attributed to them
+    pub async fn audit_report(&self) -> AuditReport {
+        let mut dbtx = self.db.begin_transaction().await;
+        let mut modules = BTreeMap::new();
+        for (module_instance_id, kind, module) in self.modules.iter_modules() {
+            let mut audit = Audit::default();
+            module
+                .audit(&mut dbtx.with_module_prefix(module_instance_id), &mut audit)
+                .await;
+            modules.insert(
+                module_instance_id,
+                ModuleAuditReport {
+                    kind: kind.clone(),
+                    net_assets_msat: audit.sum().milli_sat,
+                    items: audit.items().to_vec(),
+                },
+            );
+        }
+
+        AuditReport {
+            epoch_count: self.get_epoch_count().await,
+            net_assets_msat: modules.values().map(|module| module.net_assets_msat).sum(),
+            modules,
+        }
+    }
+
+    /// Combines the consensus status, the balance sheet and the status of
+    /// every module, so dashboards don't need a call per module
+    pub async fn admin_status(&self) -> ApiResult<AdminStatus> {
+        let consensus = self
+            .consensus_status_cache
+            .get(|| self.get_consensus_status())
+            .await?;
+        let audit = self.audit_report().await;
+
+        let mut dbtx = self.db.begin_transaction().await;
+        let mut modules = BTreeMap::new();
+        for (module_instance_id, kind, module) in self.modules.iter_modules() {
+            let status = module
+                .admin_status(&mut dbtx.with_module_prefix(module_instance_id))
+                .await;
+            let net_assets_msat = audit
+                .modules
+                .get(&module_instance_id)
+                .map_or(0, |report| report.net_assets_msat);
+            modules.insert(
+                module_instance_id,
+                ModuleAdminStatus {
+                    kind: kind.clone(),
+                    net_assets_msat,
+                    status,
+                },
+            );
+        }
+
+        Ok(AdminStatus {
+            epoch_count: audit.epoch_count,
+            consensus,
+            peer_scores: self.peer_scores().await,
+            net_assets_msat: audit.net_assets_msat,
+            modules,
+        })
+    }
+
+    /// Re-encrypts our configs under a new password and switches the API over
+    /// to it
+    pub async fn rotate_password(
+        &self,
+        request: PasswordRotationRequest,
+    ) -> ApiResult<PasswordRotationResponse> {
+        let Some(data_dir) = &self.data_dir else {
+            return Err(ApiError::server_error(
+                "Configs aren't stored in a data directory".to_string(),
+            ));
+        };
+        let new_password = request.new_password.unwrap_or_else(random_password);
+        if new_password.0.is_empty() {
+            return Err(ApiError::bad_request(
+                "Password must not be empty".to_string(),
+            ));
+        }
+
+        // Holding the lock while writing the configs serializes rotations
+        let mut api_auth = self.api_auth.write().await;
+        let mut cfg = self.cfg.clone();
+        cfg.private.api_auth = new_password.clone();
+        block_in_place(|| {
+            rotate_config_password(
+                &cfg,
+                &api_auth.current().0,
+                data_dir.clone(),
+                &self.module_inits,
+            )
+        })
+        .map_err(|e| ApiError::server_error(format!("Unable to write configs: {e}")))?;
+
+        let previous_valid_until = api_auth.rotate(new_password.clone(), now());
+        info!(target: LOG_NET_API, "Rotated the guardian password");
+        Ok(PasswordRotationResponse {
+            new_password,
+            previous_valid_until,
+        })
     }
 
     pub async fn epoch_history(&self, epoch: u64) -> Option<SignedEpochOutcome> {
@@ -273,6 +806,28 @@ impl ConsensusApi {
             .await
     }
 
+    /// Proof that `txid` was accepted, once the epoch it was accepted in is
+    /// signed
+    pub async fn transaction_inclusion_proof(
+        &self,
+        txid: TransactionId,
+    ) -> Option<TransactionInclusionProof> {
+        let mut dbtx = self.db.begin_transaction().await;
+        let accepted = dbtx.get_value(&AcceptedTransactionKey(txid)).await?;
+        dbtx.get_value(&EpochHistoryKey(accepted.epoch))
+            .await?
+            .inclusion_proof(txid)
+    }
+
+    /// Waits for `epoch` and returns its items matching `filter`
+    pub async fn epoch_items(&self, epoch: u64, filter: &EpochItemFilter) -> FilteredEpochItems {
+        self.db
+            .wait_key_exists(&EpochHistoryKey(epoch))
+            .await
+            .outcome
+            .filter_items(filter)
+    }
+
     pub async fn get_epoch_count(&self) -> u64 {
         self.db
             .begin_transaction()
@@ -288,6 +843,22 @@ impl ConsensusApi {
         self.api_sender.send(ApiEvent::UpgradeSignal).await
     }
 
+    /// Returns the migrations applied to the global and all module databases,
+    /// oldest first
+    pub async fn migration_journal(&self) -> Vec<MigrationJournalEntry> {
+        let mut journal = get_migration_journal(&self.db).await;
+        for (module_instance_id, _, _) in self.modules.iter_modules() {
+            journal.extend(get_migration_journal(&self.db.new_isolated(module_instance_id)).await);
+        }
+        journal.sort_by_key(|entry| entry.applied_at);
+        journal
+    }
+
+    /// Makes consensus propose an epoch even if there is nothing to contribute
+    pub async fn trigger_epoch(&self) -> Result<(), SendError<ApiEvent>> {
+        self.api_sender.send(ApiEvent::TriggerEpoch).await
+    }
+
     /// Force process an outcome
     pub async fn force_process_outcome(&self, outcome: SerdeEpochHistory) -> ApiResult<()> {
         let event = outcome
@@ -299,11 +870,27 @@ impl ConsensusApi {
             .map_err(|_| ApiError::server_error("Unable send event".to_string()))
     }
 
+    /// Health of the supervised tasks of every module
+    pub fn module_health(&self) -> BTreeMap<ModuleInstanceId, ModuleHealth> {
+        self.modules
+            .iter_modules()
+            .map(|(id, kind, _)| {
+                let tasks = self
+                    .module_task_groups
+                    .get(&id)
+                    .map(TaskGroup::task_health)
+                    .unwrap_or_default();
+                (id, ModuleHealth::new(kind.clone(), tasks))
+            })
+            .collect()
+    }
+
     pub async fn get_consensus_status(&self) -> ApiResult<ConsensusStatus> {
         let our_last_contribution = self.get_epoch_count().await;
         let latest_contribution_by_peer = self.latest_contribution_by_peer.read().await.clone();
         let peers_connection_status: HashMap<PeerId, anyhow::Result<PeerConnectionStatus>> =
             self.peer_status_channels.get_all_status().await;
+        let clock_skews = self.peer_status_channels.clock_skews();
         // How much time we consider a contribution recent for a "grace time".
         // For instance, even if a peer isn't connected right now, if it contributed
         // recently then we won't flag it.
@@ -313,6 +900,7 @@ impl ConsensusApi {
             latest_contribution_by_peer,
             our_last_contribution,
             peers_connection_status,
+            clock_skews,
             MAX_DURATION_FOR_RECENT_CONTRIBUTION,
         ))
     }
@@ -360,6 +948,7 @@ fn calculate_consensus_status(
     latest_contribution_by_peer: LatestContributionByPeer,
     our_last_contribution: u64,
     peers_connection_status: HashMap<PeerId, anyhow::Result<PeerConnectionStatus>>,
+    clock_skews: HashMap<PeerId, i64>,
     max_duration_for_recent_contribution: Duration,
 ) -> ConsensusStatus {
     let mut peers = peers_connection_status
@@ -402,6 +991,11 @@ fn calculate_consensus_status(
                     consensus_status.connection_status = PeerConnectionStatus::Connected;
                 }
             };
+            if let Some(&skew_ms) = clock_skews.get(&peer) {
+                consensus_status.clock_skew_ms = Some(skew_ms);
+                consensus_status.flagged |=
+                    skew_ms.unsigned_abs() > MAX_CLOCK_SKEW.as_millis() as u64;
+            }
             (peer, consensus_status)
         })
         .collect::<HashMap<_, _>>();
@@ -439,13 +1033,18 @@ impl HasApiContext<ConsensusApi> for ConsensusApi {
             db = self.db.new_isolated(id);
             dbtx = dbtx.new_module_tx(id)
         }
+        let has_auth = match &request.auth {
+            Some(auth) => self.api_auth.read().await.accepts(auth, now()),
+            None => false,
+        };
         (
             self,
             ApiEndpointContext::new(
                 db,
                 dbtx,
-                request.auth == Some(self.cfg.private.api_auth.clone()),
+                has_auth,
                 request.auth.clone(),
+                request.correlation_id,
             ),
         )
     }
@@ -476,14 +1075,14 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConsensusApi>> {
         },
         api_endpoint! {
             "transaction",
-            async |fedimint: &ConsensusApi, _context, serde_transaction: SerdeTransaction| -> TransactionId {
+            async |fedimint: &ConsensusApi, context, serde_transaction: SerdeTransaction| -> TransactionId {
                 let transaction = serde_transaction.try_into_inner(&fedimint.modules.decoder_registry()).map_err(|e| ApiError::bad_request(e.to_string()))?;
 
                 let tx_id = transaction.tx_hash();
 
-                fedimint.submit_transaction(transaction)
+                fedimint.submit_transaction(transaction, context.correlation_id())
                     .await
-                    .map_err(|e| ApiError::bad_request(e.to_string()))?;
+                    .map_err(|e| fedimint.transaction_api_error(e))?;
 
                 Ok(tx_id)
             }
@@ -520,6 +1119,35 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConsensusApi>> {
                 Ok((&epoch).into())
             }
         },
+        api_endpoint! {
+            "await_epoch_items",
+            async |fedimint: &ConsensusApi, _context, request: EpochItemsRequest| -> SerdeFilteredEpochItems {
+                let items = fedimint.epoch_items(request.epoch, &request.filter).await;
+                Ok((&items).into())
+            }
+        },
+        api_endpoint! {
+            "fetch_transaction_inclusion_proof",
+            async |fedimint: &ConsensusApi, _context, txid: TransactionId| -> SerdeTransactionInclusionProof {
+                let proof = fedimint.transaction_inclusion_proof(txid).await
+                    .ok_or_else(|| ApiError::not_found(format!("transaction {txid} isn't part of a signed epoch")))?;
+                Ok((&proof).into())
+            }
+        },
+        api_endpoint! {
+            "epoch_stats",
+            async |fedimint: &ConsensusApi, _context, epoch: u64| -> EpochStatsShare {
+                fedimint.epoch_stats(epoch).await
+                    .ok_or_else(|| ApiError::not_found(format!("epoch {epoch} wasn't processed yet")))
+            }
+        },
+        api_endpoint! {
+            "transaction_receipt",
+            async |fedimint: &ConsensusApi, _context, txid: TransactionId| -> TransactionReceiptShare {
+                fedimint.transaction_receipt(txid).await
+                    .ok_or_else(|| ApiError::not_found(format!("no receipt for transaction {txid}")))
+            }
+        },
         api_endpoint! {
             "fetch_epoch_count",
             async |fedimint: &ConsensusApi, _context, _v: ()| -> u64 {
@@ -546,6 +1174,20 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConsensusApi>> {
                 })
             }
         },
+        api_endpoint! {
+            "join_snapshot",
+            async |fedimint: &ConsensusApi, context, connection_code: String| -> FederationSnapshotShare {
+                let info = connection_code.parse()
+                    .map_err(|_| ApiError::bad_request("Could not parse connection code".to_string()))?;
+                fedimint.join_snapshot(info, &mut context.dbtx()).await
+            }
+        },
+        api_endpoint! {
+            "client_config_hash",
+            async |fedimint: &ConsensusApi, context, _v: ()| -> sha256::Hash {
+                Ok(fedimint.client_config(&mut context.dbtx()).await.consensus_hash())
+            }
+        },
         api_endpoint! {
             "config_hash",
             async |fedimint: &ConsensusApi, _context, _v: ()| -> sha256::Hash {
@@ -584,10 +1226,17 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConsensusApi>> {
                     .await?;
                 Ok(StatusResponse {
                     server: ServerStatus::ConsensusRunning,
-                    consensus: Some(consensus_status)
+                    consensus: Some(consensus_status),
+                    modules: fedimint.module_health(),
                 })
             }
         },
+        api_endpoint! {
+            "epoch_timing",
+            async |fedimint: &ConsensusApi, _context, _v: ()| -> EpochTimingEstimate {
+                Ok(fedimint.epoch_timing.lock().expect("poisoned").estimate(now()))
+            }
+        },
         api_endpoint! {
             "get_verify_config_hash",
             async |fedimint: &ConsensusApi, context, _v: ()| -> BTreeMap<PeerId, sha256::Hash> {
@@ -598,6 +1247,167 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConsensusApi>> {
                 }
             }
         },
+        api_endpoint! {
+            "api_gateway_info",
+            async |fedimint: &ConsensusApi, context, _v: ()| -> ApiGatewayInfo {
+                if !context.has_auth() {
+                    return Err(ApiError::unauthorized());
+                }
+                Ok(ApiGatewayInfo {
+                    modules: fedimint
+                        .modules
+                        .iter_modules()
+                        .map(|(id, kind, _)| (id, kind.clone()))
+                        .collect(),
+                    methods: FedimintServer::consensus_api_methods(fedimint),
+                })
+            }
+        },
+        api_endpoint! {
+            "replicate_epochs",
+            async |fedimint: &ConsensusApi, context, from_epoch: u64| -> SerdeModuleEncoding<DatabaseSnapshot> {
+                if !context.has_auth() {
+                    return Err(ApiError::unauthorized());
+                }
+                Ok((&fedimint.replicate_epochs(from_epoch).await).into())
+            }
+        },
+        api_endpoint! {
+            "backup_database",
+            async |fedimint: &ConsensusApi, context, request: DatabaseBackupRequest| -> DatabaseBackupResponse {
+                if !context.has_auth() {
+                    return Err(ApiError::unauthorized());
+                }
+                let Some(data_dir) = &fedimint.data_dir else {
+                    return Err(ApiError::server_error(
+                        "No data directory to back up into".to_string(),
+                    ));
+                };
+                let backup_dir = block_in_place(|| snapshot::backup_dir(data_dir, &request.path))
+                    .map_err(|e| ApiError::bad_request(e.to_string()))?;
+                let response = if request.checkpoint {
+                    block_in_place(|| snapshot::write_checkpoint(&fedimint.db, &backup_dir))
+                } else {
+                    snapshot::write_snapshot(&fedimint.db, &backup_dir, request.incremental).await
+                };
+                response.map_err(|e| ApiError::server_error(format!("Database backup failed: {e}")))
+            }
+        },
+        api_endpoint! {
+            "rotate_password",
+            async |fedimint: &ConsensusApi, context, request: PasswordRotationRequest| -> PasswordRotationResponse {
+                if !context.has_auth() {
+                    return Err(ApiError::unauthorized());
+                }
+                fedimint.rotate_password(request).await
+            }
+        },
+        api_endpoint! {
+            "update_meta",
+            async |fedimint: &ConsensusApi, context, meta: BTreeMap<String, String>| -> () {
+                if !context.has_auth() {
+                    return Err(ApiError::unauthorized());
+                }
+                fedimint.update_meta(meta).await.map_err(|_| ApiError::server_error("Unable to send signal to server".to_string()))
+            }
+        },
+        api_endpoint! {
+            "meta_votes",
+            async |fedimint: &ConsensusApi, context, _v: ()| -> BTreeMap<PeerId, BTreeMap<String, String>> {
+                if context.has_auth() {
+                    Ok(fedimint.meta_votes().await)
+                } else {
+                    Err(ApiError::unauthorized())
+                }
+            }
+        },
+        api_endpoint! {
+            "propose_module_addition",
+            async |fedimint: &ConsensusApi, context, request: ModuleAdditionRequest| -> () {
+                if !context.has_auth() {
+                    return Err(ApiError::unauthorized());
+                }
+                fedimint.propose_module_addition(request).await
+            }
+        },
+        api_endpoint! {
+            "cancel_module_addition",
+            async |fedimint: &ConsensusApi, context, module_instance_id: ModuleInstanceId| -> () {
+                if !context.has_auth() {
+                    return Err(ApiError::unauthorized());
+                }
+                fedimint.cancel_module_addition(module_instance_id).await
+            }
+        },
+        api_endpoint! {
+            "module_addition_status",
+            async |fedimint: &ConsensusApi, context, _v: ()| -> ModuleAdditionStatus {
+                if context.has_auth() {
+                    Ok(fedimint.module_addition_status().await)
+                } else {
+                    Err(ApiError::unauthorized())
+                }
+            }
+        },
+        api_endpoint! {
+            "peer_scores",
+            async |fedimint: &ConsensusApi, context, _v: ()| -> BTreeMap<PeerId, PeerScore> {
+                if context.has_auth() {
+                    Ok(fedimint.peer_scores().await)
+                } else {
+                    Err(ApiError::unauthorized())
+                }
+            }
+        },
+        api_endpoint! {
+            "migration_journal",
+            async |fedimint: &ConsensusApi, context, _v: ()| -> Vec<MigrationJournalEntry> {
+                if context.has_auth() {
+                    Ok(fedimint.migration_journal().await)
+                } else {
+                    Err(ApiError::unauthorized())
+                }
+            }
+        },
+        api_endpoint! {
+            "trigger_epoch",
+            async |fedimint: &ConsensusApi, context, _v: ()| -> () {
+                if !context.has_auth() {
+                    return Err(ApiError::unauthorized());
+                }
+                fedimint.trigger_epoch().await.map_err(|_| ApiError::server_error("Unable to send signal to server".to_string()))
+            }
+        },
+        api_endpoint! {
+            "peer_connectivity",
+            async |fedimint: &ConsensusApi, context, _v: ()| -> BTreeMap<PeerId, PeerConnectivity> {
+                if context.has_auth() {
+                    fedimint.peer_connectivity().await
+                } else {
+                    Err(ApiError::unauthorized())
+                }
+            }
+        },
+        api_endpoint! {
+            "audit",
+            async |fedimint: &ConsensusApi, context, _v: ()| -> AuditReport {
+                if context.has_auth() {
+                    Ok(fedimint.audit_report().await)
+                } else {
+                    Err(ApiError::unauthorized())
+                }
+            }
+        },
+        api_endpoint! {
+            "admin_status",
+            async |fedimint: &ConsensusApi, context, _v: ()| -> AdminStatus {
+                if context.has_auth() {
+                    fedimint.admin_status().await
+                } else {
+                    Err(ApiError::unauthorized())
+                }
+            }
+        },
         api_endpoint! {
             "backup",
             async |fedimint: &ConsensusApi, context, request: SignedBackupRequest| -> () {
@@ -617,6 +1427,16 @@ pub fn server_endpoints() -> Vec<ApiEndpoint<ConsensusApi>> {
     ]
 }
 
+fn random_password() -> ApiAuth {
+    ApiAuth(
+        rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(32)
+            .map(char::from)
+            .collect(),
+    )
+}
+
 /// Very simple cache mostly used to protect endpoints against denial of service
 /// attacks
 #[derive(Clone)]
@@ -686,6 +1506,7 @@ mod tests {
             latest_contribution_by_peer,
             our_last_contribution,
             peers_connection_status,
+            HashMap::new(),
             max_duration_for_recent_contribution,
         );
         assert_eq!(result.peers_online, 2);
@@ -725,6 +1546,7 @@ mod tests {
             latest_contribution_by_peer,
             our_last_contribution,
             peers_connection_status,
+            HashMap::new(),
             max_duration_for_recent_contribution,
         );
         assert_eq!(result.peers_online, 1);
@@ -763,6 +1585,7 @@ mod tests {
             latest_contribution_by_peer,
             our_last_contribution,
             peers_connection_status,
+            HashMap::new(),
             max_duration_for_recent_contribution,
         );
         assert_eq!(result.peers_online, 1);
@@ -771,6 +1594,52 @@ mod tests {
         assert!(result.status_by_peer.values().all(|p| p.flagged));
     }
 
+    #[test]
+    fn test_server_status_clock_skew_flagged() {
+        let now = now();
+        let our_last_contribution = 1;
+        let latest_contribution_by_peer = HashMap::from([
+            (
+                PeerId::from(0),
+                ConsensusContribution {
+                    value: 1,
+                    time: now,
+                },
+            ),
+            (
+                PeerId::from(1),
+                ConsensusContribution {
+                    value: 1,
+                    time: now,
+                },
+            ),
+        ]);
+        let peers_connection_status = HashMap::from([
+            (PeerId::from(0), Ok(PeerConnectionStatus::Connected)),
+            (PeerId::from(1), Ok(PeerConnectionStatus::Connected)),
+        ]);
+        let skew_ms = MAX_CLOCK_SKEW.as_millis() as i64 + 1;
+        let clock_skews = HashMap::from([(PeerId::from(0), 5), (PeerId::from(1), -skew_ms)]);
+        let result = calculate_consensus_status(
+            latest_contribution_by_peer,
+            our_last_contribution,
+            peers_connection_status,
+            clock_skews,
+            Duration::from_secs(5),
+        );
+        assert_eq!(result.peers_flagged, 1);
+        assert!(!result.status_by_peer[&PeerId::from(0)].flagged);
+        assert_eq!(
+            result.status_by_peer[&PeerId::from(0)].clock_skew_ms,
+            Some(5)
+        );
+        assert!(result.status_by_peer[&PeerId::from(1)].flagged);
+        assert_eq!(
+            result.status_by_peer[&PeerId::from(1)].clock_skew_ms,
+            Some(-skew_ms)
+        );
+    }
+
     #[tokio::test]
     async fn test_expiring_cache() {
         let cache = ExpiringCache::new(Duration::from_secs(1));
@@ -798,4 +1667,26 @@ mod tests {
             .await;
         assert_eq!(result, 2);
     }
+
+    #[test]
+    fn test_previous_password_accepted_during_grace_period() {
+        let now = now();
+        let old = ApiAuth("old".to_string());
+        let new = ApiAuth("new".to_string());
+        let mut auth = ApiAuthState::new(old.clone());
+        assert!(auth.accepts(&old, now));
+        assert!(!auth.accepts(&new, now));
+
+        let valid_until = auth.rotate(new.clone(), now);
+        assert_eq!(auth.current(), &new);
+        assert!(auth.accepts(&new, now));
+        assert!(auth.accepts(&old, now));
+        assert!(!auth.accepts(&old, valid_until));
+        assert!(auth.accepts(&new, valid_until));
+
+        // Only the password before the last rotation is kept
+        auth.rotate(ApiAuth("newer".to_string()), now);
+        assert!(!auth.accepts(&old, now));
+        assert!(auth.accepts(&new, now));
+    }
 }
@@ -0,0 +1,321 @@
+//! Stateless API gateway in front of a guardian's consensus node
+//!
+//! Guardians expecting a lot of read traffic can run any number of gateways
+//! and firewall the API port of their consensus node, so that only the
+//! gateways can reach it. A gateway authenticates to the consensus node with
+//! the guardian's admin password and keeps an in-memory replica of the epoch
+//! history, pulled in batches of raw database entries.
+//!
+//! Requests for the epoch history are answered from the replica. All other
+//! requests are forwarded to the consensus node, including transaction
+//! submissions, transaction outcomes, admin calls and module endpoints, which
+//! need the state of the modules. The methods are discovered when the gateway
+//! starts, so gateways have to be restarted after a module was added.
+//!
+//! Only the epoch history is replicated, a gateway never sees secrets of the
+//! guardian like the nonces of pending signatures. It does hold the admin
+//! password though, so it should run on infrastructure of the guardian.
+
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use fedimint_core::admin_client::WsAdminClient;
+use fedimint_core::api::{DynGlobalApi, IFederationApi, MemberError, WsFederationApi};
+use fedimint_core::config::ServerModuleGenRegistry;
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::db::mem_impl::MemDatabase;
+use fedimint_core::db::Database;
+use fedimint_core::epoch::{
+    EpochItemFilter, EpochItemsRequest, FilteredEpochItems, SerdeEpochHistory,
+    SerdeFilteredEpochItems, SerdeTransactionInclusionProof, SignedEpochOutcome,
+    TransactionInclusionProof,
+};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::module::{
+    api_endpoint, ApiAuth, ApiEndpoint, ApiEndpointContext, ApiError, ApiRequestErased,
+};
+use fedimint_core::task::{sleep, TaskGroup, TaskHandle};
+use fedimint_core::{PeerId, TransactionId};
+use fedimint_logging::LOG_NET_API;
+use jsonrpsee::types::error::CallError;
+use jsonrpsee::types::ErrorObject;
+use tracing::{debug, info, warn};
+use url::Url;
+
+use crate::db::{AcceptedTransactionKey, EpochHistoryKey, LastEpochKey};
+use crate::net::api::RpcHandlerCtx;
+use crate::{FedimintServer, HasApiContext};
+
+/// How long to wait before asking the consensus node for new epochs once the
+/// replica caught up
+const REPLICATION_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone)]
+pub struct ApiGatewaySettings {
+    /// Address to serve the API on
+    pub api_bind: SocketAddr,
+    /// Max number of client connections
+    pub max_connections: u32,
+    /// API of the guardian's consensus node
+    pub consensus_api_url: Url,
+    /// Id of the guardian
+    pub our_id: PeerId,
+    /// Admin password of the guardian, authenticates the gateway
+    pub auth: ApiAuth,
+}
+
+/// Serves the epoch history from the replica
+pub struct ReplicaApi {
+    db: Database,
+}
+
+impl ReplicaApi {
+    pub async fn epoch_history(&self, epoch: u64) -> Option<SignedEpochOutcome> {
+        self.db
+            .begin_transaction()
+            .await
+            .get_value(&EpochHistoryKey(epoch))
+            .await
+    }
+
+    pub async fn transaction_inclusion_proof(
+        &self,
+        txid: TransactionId,
+    ) -> Option<TransactionInclusionProof> {
+        let mut dbtx = self.db.begin_transaction().await;
+        let accepted = dbtx.get_value(&AcceptedTransactionKey(txid)).await?;
+        dbtx.get_value(&EpochHistoryKey(accepted.epoch))
+            .await?
+            .inclusion_proof(txid)
+    }
+
+    /// Waits until `epoch` was replicated and returns its items matching
+    /// `filter`
+    pub async fn epoch_items(&self, epoch: u64, filter: &EpochItemFilter) -> FilteredEpochItems {
+        self.db
+            .wait_key_exists(&EpochHistoryKey(epoch))
+            .await
+            .outcome
+            .filter_items(filter)
+    }
+
+    pub async fn epoch_count(&self) -> u64 {
+        epoch_count(&self.db).await
+    }
+}
+
+async fn epoch_count(db: &Database) -> u64 {
+    db.begin_transaction()
+        .await
+        .get_value(&LastEpochKey)
+        .await
+        .map(|last_epoch| last_epoch.0 + 1)
+        .unwrap_or(0)
+}
+
+/// Endpoints of the consensus API that are answered from the replica, they
+/// behave the same as the originals
+pub fn replica_endpoints() -> Vec<ApiEndpoint<ReplicaApi>> {
+    vec![
+        api_endpoint! {
+            "fetch_epoch_history",
+            async |replica: &ReplicaApi, _context, epoch: u64| -> SerdeEpochHistory {
+                let epoch = replica.epoch_history(epoch).await
+                  .ok_or_else(|| ApiError::not_found(format!("epoch {epoch} not found")))?;
+                Ok((&epoch).into())
+            }
+        },
+        api_endpoint! {
+            "await_epoch_items",
+            async |replica: &ReplicaApi, _context, request: EpochItemsRequest| -> SerdeFilteredEpochItems {
+                let items = replica.epoch_items(request.epoch, &request.filter).await;
+                Ok((&items).into())
+            }
+        },
+        api_endpoint! {
+            "fetch_transaction_inclusion_proof",
+            async |replica: &ReplicaApi, _context, txid: TransactionId| -> SerdeTransactionInclusionProof {
+                let proof = replica.transaction_inclusion_proof(txid).await
+                    .ok_or_else(|| ApiError::not_found(format!("transaction {txid} isn't part of a signed epoch")))?;
+                Ok((&proof).into())
+            }
+        },
+        api_endpoint! {
+            "fetch_epoch_count",
+            async |replica: &ReplicaApi, _context, _v: ()| -> u64 {
+                Ok(replica.epoch_count().await)
+            }
+        },
+    ]
+}
+
+struct ApiGateway {
+    replica: ReplicaApi,
+    /// API of the consensus node, requests are forwarded to
+    consensus_api: DynGlobalApi,
+    our_id: PeerId,
+}
+
+impl ApiGateway {
+    /// Calls `method` on the consensus node, errors it returns are passed on
+    /// unchanged
+    async fn forward(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> Result<serde_json::Value, ApiError> {
+        self.consensus_api
+            .request_raw(self.our_id, method, &[params])
+            .await
+            .map_err(|e| {
+                let error = MemberError::Rpc(e);
+                error.api_error().unwrap_or_else(|| {
+                    ApiError::unavailable(format!("Consensus node unreachable: {error}"))
+                })
+            })
+    }
+}
+
+#[async_trait]
+impl HasApiContext<ReplicaApi> for ApiGateway {
+    async fn context(
+        &self,
+        request: &ApiRequestErased,
+        _id: Option<ModuleInstanceId>,
+    ) -> (&ReplicaApi, ApiEndpointContext<'_>) {
+        // The replica endpoints don't require authentication, admin calls are
+        // forwarded along with their password
+        (
+            &self.replica,
+            ApiEndpointContext::new(
+                self.replica.db.clone(),
+                self.replica.db.begin_transaction().await,
+                false,
+                request.auth.clone(),
+                request.correlation_id,
+            ),
+        )
+    }
+}
+
+/// Serves the API of the guardian's consensus node until `task_group` shuts
+/// down, `module_gens` have to include the modules of the federation
+pub async fn run_api_gateway(
+    settings: ApiGatewaySettings,
+    module_gens: &ServerModuleGenRegistry,
+    mut task_group: TaskGroup,
+) -> anyhow::Result<()> {
+    let admin_client = WsAdminClient::new(
+        settings.consensus_api_url.clone(),
+        settings.our_id,
+        settings.auth.clone(),
+    );
+    let info = admin_client
+        .api_gateway_info()
+        .await
+        .context("Unable to reach the consensus node")?;
+    let decoders = module_gens.decoders(info.modules.iter().map(|(id, kind)| (*id, kind)))?;
+    let replica = Database::new(MemDatabase::new(), decoders);
+
+    let replica_db = replica.clone();
+    task_group
+        .spawn("replicate epochs", move |handle| async move {
+            replicate_epochs(&admin_client, &replica_db, handle).await;
+        })
+        .await;
+
+    let gateway = ApiGateway {
+        replica: ReplicaApi { db: replica },
+        consensus_api: WsFederationApi::new(vec![(settings.our_id, settings.consensus_api_url)])
+            .into(),
+        our_id: settings.our_id,
+    };
+    let mut rpc_module = RpcHandlerCtx::new_module(gateway);
+    let endpoints = replica_endpoints();
+    let local_methods = endpoints
+        .iter()
+        .map(|endpoint| endpoint.path)
+        .collect::<Vec<_>>();
+    FedimintServer::attach_endpoints(&mut rpc_module, endpoints, None, None);
+
+    let forwarded = info
+        .methods
+        .into_iter()
+        .filter(|method| !local_methods.contains(&method.as_str()));
+    for method in forwarded {
+        // Leaked once on startup, the path has to live until the end of the program
+        let method: &'static str = Box::leak(method.into_boxed_str());
+        rpc_module
+            .register_async_method(method, move |params, rpc_state| async move {
+                let params = params.one::<serde_json::Value>()?;
+                rpc_state
+                    .rpc_context
+                    .forward(method, params)
+                    .await
+                    .map_err(|e| {
+                        let data = e.data();
+                        jsonrpsee::core::Error::Call(CallError::Custom(ErrorObject::owned(
+                            e.code,
+                            e.message,
+                            Some(data),
+                        )))
+                    })
+            })
+            .expect("Failed to register async method");
+    }
+
+    let handler = FedimintServer::spawn_api(
+        "api-gateway",
+        &settings.api_bind,
+        rpc_module,
+        settings.max_connections,
+        false,
+    )
+    .await;
+    info!(
+        target: LOG_NET_API,
+        consensus_api = %settings.consensus_api_url,
+        "Serving the API of guardian {}",
+        settings.our_id
+    );
+
+    let _ = task_group.make_handle().make_shutdown_rx().await.await;
+    handler.stop().await;
+    Ok(())
+}
+
+/// Keeps importing new epochs into `replica` until `handle` shuts down
+async fn replicate_epochs(admin_client: &WsAdminClient, replica: &Database, handle: TaskHandle) {
+    let mut shutdown_rx = handle.make_shutdown_rx().await;
+    while !handle.is_shutting_down() {
+        let count = epoch_count(replica).await;
+        // Epochs are signed in the following epoch, so the last one we have is
+        // requested again to get its signature
+        let from_epoch = count.saturating_sub(1);
+        match admin_client.replicate_epochs(from_epoch).await {
+            Ok(snapshot) => {
+                let result = match snapshot.try_into_inner(&ModuleDecoderRegistry::default()) {
+                    Ok(snapshot) => replica.import_snapshot(&snapshot).await,
+                    Err(e) => Err(e.into()),
+                };
+                if let Err(e) = result {
+                    warn!(target: LOG_NET_API, "Failed to import epochs: {e:#}");
+                }
+            }
+            Err(e) => warn!(target: LOG_NET_API, "Failed to replicate epochs: {e}"),
+        }
+
+        let new_count = epoch_count(replica).await;
+        if new_count > count {
+            debug!(target: LOG_NET_API, epochs = new_count, "Replicated epochs");
+            // Catch up without waiting if we are further behind
+            continue;
+        }
+        tokio::select! {
+            _ = sleep(REPLICATION_INTERVAL) => {},
+            _ = &mut shutdown_rx => break,
+        }
+    }
+}
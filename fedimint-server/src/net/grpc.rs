@@ -0,0 +1,216 @@
+//! gRPC interface of the consensus API
+//!
+//! Serves the endpoints of the websocket API to integrations that prefer
+//! clients generated from `proto/fedimint.proto`, together with typed
+//! streaming subscriptions to epochs and transactions.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use fedimint_core::encoding::Encodable;
+use fedimint_core::module::{ApiAuth, ApiRequestErased};
+use fedimint_core::outcome::TransactionStatus;
+use fedimint_core::TransactionId;
+use fedimint_logging::LOG_NET_API;
+use futures::{stream, Stream, StreamExt};
+use jsonrpsee::core::params::ArrayParams;
+use jsonrpsee::types::error::{CallError, METHOD_NOT_FOUND_CODE};
+use jsonrpsee::RpcModule;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::TcpListenerStream;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status};
+use tracing::{error, info};
+
+use crate::db::EpochHistoryKey;
+use crate::net::api::{ConsensusApi, RpcHandlerCtx};
+use crate::FedimintServer;
+
+pub mod proto {
+    tonic::include_proto!("fedimint");
+}
+
+use proto::fedimint_api_server::{FedimintApi, FedimintApiServer};
+use proto::transaction_status::State;
+
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+struct GrpcApi {
+    api: Arc<ConsensusApi>,
+    /// The endpoints of the websocket API, called by [`FedimintApi::call`]
+    rpc_module: RpcModule<RpcHandlerCtx<ConsensusApi>>,
+}
+
+#[tonic::async_trait]
+impl FedimintApi for GrpcApi {
+    async fn call(
+        &self,
+        request: Request<proto::CallRequest>,
+    ) -> Result<Response<proto::CallResponse>, Status> {
+        let request = request.into_inner();
+        let params = if request.params_json.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::from_str(&request.params_json)
+                .map_err(|e| Status::invalid_argument(format!("Invalid params: {e}")))?
+        };
+
+        let mut rpc_params = ArrayParams::new();
+        rpc_params
+            .insert(ApiRequestErased {
+                auth: request.auth.map(ApiAuth),
+                params,
+            })
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let result: serde_json::Value = self
+            .rpc_module
+            .call(&request.method, rpc_params)
+            .await
+            .map_err(call_error_status)?;
+
+        Ok(Response::new(proto::CallResponse {
+            result_json: result.to_string(),
+        }))
+    }
+
+    async fn epoch_count(
+        &self,
+        _request: Request<proto::EmptyRequest>,
+    ) -> Result<Response<proto::EpochCountResponse>, Status> {
+        Ok(Response::new(proto::EpochCountResponse {
+            count: self.api.get_epoch_count().await,
+        }))
+    }
+
+    type SubscribeEpochsStream = ResponseStream<proto::SignedEpoch>;
+
+    async fn subscribe_epochs(
+        &self,
+        request: Request<proto::SubscribeEpochsRequest>,
+    ) -> Result<Response<Self::SubscribeEpochsStream>, Status> {
+        let db = self.api.db.clone();
+        let epochs = stream::unfold(request.into_inner().start_epoch, move |epoch| {
+            let db = db.clone();
+            async move {
+                // Epochs are signed in the following epoch, so we stay one behind
+                let (outcome, _) = db
+                    .wait_key_check(&EpochHistoryKey(epoch), |outcome| {
+                        outcome.filter(|outcome| outcome.signature.is_some())
+                    })
+                    .await;
+                let signed_epoch = outcome
+                    .consensus_encode_to_vec()
+                    .map(|outcome| proto::SignedEpoch { epoch, outcome })
+                    .map_err(|e| Status::internal(e.to_string()));
+                Some((signed_epoch, epoch + 1))
+            }
+        });
+
+        Ok(Response::new(Box::pin(epochs)))
+    }
+
+    type SubscribeTransactionStream = ResponseStream<proto::TransactionStatus>;
+
+    async fn subscribe_transaction(
+        &self,
+        request: Request<proto::SubscribeTransactionRequest>,
+    ) -> Result<Response<Self::SubscribeTransactionStream>, Status> {
+        let txid = TransactionId::from_str(&request.into_inner().txid)
+            .map_err(|e| Status::invalid_argument(format!("Invalid txid: {e}")))?;
+
+        let updates = match self.api.transaction_status(txid).await {
+            Some(status) => {
+                stream::once(async move { Ok(transaction_status(Some(status))) }).boxed()
+            }
+            None => {
+                let api = self.api.clone();
+                stream::once(async { Ok(transaction_status(None)) })
+                    .chain(stream::once(async move {
+                        Ok(transaction_status(Some(
+                            api.wait_transaction_status(txid).await,
+                        )))
+                    }))
+                    .boxed()
+            }
+        };
+
+        Ok(Response::new(updates))
+    }
+}
+
+fn transaction_status(status: Option<TransactionStatus>) -> proto::TransactionStatus {
+    match status {
+        None => proto::TransactionStatus {
+            state: State::Pending.into(),
+            ..Default::default()
+        },
+        Some(TransactionStatus::Accepted { epoch, .. }) => proto::TransactionStatus {
+            state: State::Accepted.into(),
+            epoch,
+            ..Default::default()
+        },
+        Some(TransactionStatus::Rejected(error)) => proto::TransactionStatus {
+            state: State::Rejected.into(),
+            error,
+            ..Default::default()
+        },
+    }
+}
+
+/// Maps the errors of the websocket endpoints to the closest gRPC status
+fn call_error_status(error: jsonrpsee::core::Error) -> Status {
+    match error {
+        jsonrpsee::core::Error::Call(CallError::Custom(error)) => {
+            let message = error.message().to_owned();
+            match error.code() {
+                400 => Status::invalid_argument(message),
+                401 => Status::unauthenticated(message),
+                404 => Status::not_found(message),
+                METHOD_NOT_FOUND_CODE => Status::unimplemented(message),
+                _ => Status::internal(message),
+            }
+        }
+        jsonrpsee::core::Error::RequestTimeout => Status::deadline_exceeded("Request timed out"),
+        error => Status::internal(error.to_string()),
+    }
+}
+
+/// Runs the gRPC API on `grpc_bind` next to the websocket consensus API
+pub async fn spawn_grpc_api(grpc_bind: SocketAddr, api: &ConsensusApi) -> GrpcApiHandler {
+    let listener = TcpListener::bind(grpc_bind)
+        .await
+        .unwrap_or_else(|e| panic!("Could not bind gRPC API to {grpc_bind}: {e}"));
+    let service = FedimintApiServer::new(GrpcApi {
+        api: Arc::new(api.clone()),
+        rpc_module: FedimintServer::consensus_rpc_module(api),
+    });
+
+    info!(target: LOG_NET_API, "Starting gRPC api on {grpc_bind}");
+    let handle = tokio::spawn(async move {
+        if let Err(e) = Server::builder()
+            .add_service(service)
+            .serve_with_incoming(TcpListenerStream::new(listener))
+            .await
+        {
+            error!(target: LOG_NET_API, "gRPC API failed: {e}");
+        }
+    });
+
+    GrpcApiHandler { handle }
+}
+
+pub struct GrpcApiHandler {
+    handle: JoinHandle<()>,
+}
+
+impl GrpcApiHandler {
+    /// Stops the API, aborting open subscriptions instead of waiting for them
+    pub async fn stop(self) {
+        self.handle.abort();
+        let _ = self.handle.await;
+    }
+}
@@ -1,5 +1,8 @@
+pub mod announce;
 pub mod api;
 pub mod connect;
 pub mod framed;
+pub mod gateway;
+pub mod grpc;
 pub mod peers;
 mod queue;
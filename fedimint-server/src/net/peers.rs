@@ -10,13 +10,15 @@ use std::collections::{BTreeSet, HashMap};
 use std::fmt::Debug;
 use std::net::SocketAddr;
 use std::ops::Sub;
-use std::time::Duration;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 use async_trait::async_trait;
 use fedimint_core::api::PeerConnectionStatus;
 use fedimint_core::cancellable::{Cancellable, Cancelled};
 use fedimint_core::net::peers::IPeerConnections;
 use fedimint_core::task::{TaskGroup, TaskHandle};
+use fedimint_core::time::now;
 use fedimint_core::PeerId;
 use fedimint_logging::LOG_NET_PEER;
 use futures::future::select_all;
@@ -39,6 +41,14 @@ use crate::net::queue::{MessageId, MessageQueue, UniqueMessage};
 /// [`ReconnectPeerConnections`]
 pub type PeerConnector<M> = AnyConnector<PeerMessage<M>>;
 
+/// How far the clock of a peer may drift from ours before it is flagged,
+/// beyond that invoice expiries and timeouts of the guardians disagree
+pub const MAX_CLOCK_SKEW: Duration = Duration::from_secs(20);
+
+/// How often we ping connected peers so their clock skew is known even if no
+/// messages are exchanged
+const PING_INTERVAL: Duration = Duration::from_secs(60);
+
 /// Connection manager that automatically reconnects to peers
 ///
 /// `ReconnectPeerConnections` is based on a
@@ -71,8 +81,11 @@ pub struct NetworkConfig {
 /// it appears in the public interface.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PeerMessage<M> {
-    msg: UniqueMessage<M>,
+    /// `None` for pings, which are sent after connecting and periodically
+    msg: Option<UniqueMessage<M>>,
     ack: Option<MessageId>,
+    /// Clock of the sender, used to detect clock skew between peers
+    sent_at: SystemTime,
 }
 
 struct PeerConnectionStateMachine<M> {
@@ -87,15 +100,28 @@ struct PeerStatusQuery {
 type PeerStatusChannelSender = Sender<PeerStatusQuery>;
 type PeerStatusChannelReceiver = Receiver<PeerStatusQuery>;
 
+/// Last clock skew in milliseconds we observed for each peer, positive if the
+/// peer's clock is ahead of ours
+type ClockSkews = Arc<Mutex<HashMap<PeerId, i64>>>;
+
 /// Keeps the references to a `PeerStatusChannelSender` for each `PeerId`, which
 /// can be used to ask the corresponding `PeerConnectionStateMachine` for the
 /// current `PeerConnectionStatus`
 #[derive(Clone)]
-pub struct PeerStatusChannels(HashMap<PeerId, PeerStatusChannelSender>);
+pub struct PeerStatusChannels {
+    channels: HashMap<PeerId, PeerStatusChannelSender>,
+    clock_skews: ClockSkews,
+}
 
 impl PeerStatusChannels {
+    /// Returns the clock skew in milliseconds of every peer we received a
+    /// message from, positive if the peer's clock is ahead of ours
+    pub fn clock_skews(&self) -> HashMap<PeerId, i64> {
+        self.clock_skews.lock().expect("lock poisoned").clone()
+    }
+
     pub async fn get_all_status(&self) -> HashMap<PeerId, anyhow::Result<PeerConnectionStatus>> {
-        let results = self.0.iter().map(|(peer_id, sender)| async {
+        let results = self.channels.iter().map(|(peer_id, sender)| async {
             let (response_sender, response_receiver) = oneshot::channel();
             let query = PeerStatusQuery { response_sender };
             let sender_response = sender
@@ -178,6 +204,7 @@ struct CommonPeerConnectionState<M> {
     incoming_connections: Receiver<AnyFramedTransport<PeerMessage<M>>>,
     last_received: Option<MessageId>,
     status_query_receiver: PeerStatusChannelReceiver,
+    clock_skews: ClockSkews,
 }
 
 struct DisconnectedPeerConnectionState {
@@ -187,6 +214,7 @@ struct DisconnectedPeerConnectionState {
 
 struct ConnectedPeerConnectionState<M> {
     connection: AnyFramedTransport<PeerMessage<M>>,
+    next_ping: Instant,
 }
 
 enum PeerConnectionState<M> {
@@ -213,6 +241,7 @@ where
         let mut connection_senders = HashMap::new();
         let mut status_query_senders = HashMap::new();
         let mut connections = HashMap::new();
+        let clock_skews = ClockSkews::default();
 
         for (peer, peer_address) in cfg.peers.iter().filter(|(&peer, _)| peer != cfg.identity) {
             let (connection_sender, connection_receiver) =
@@ -227,6 +256,7 @@ where
                 shared_connector.clone(),
                 connection_receiver,
                 status_query_receiver,
+                clock_skews.clone(),
                 task_group,
             )
             .await;
@@ -242,7 +272,10 @@ where
             .await;
         (
             ReconnectPeerConnections { connections },
-            PeerStatusChannels(status_query_senders),
+            PeerStatusChannels {
+                channels: status_query_senders,
+                clock_skews,
+            },
         )
     }
 
@@ -437,6 +470,9 @@ where
             Some(msg_res) = connected.connection.next() => {
                 self.receive_message(connected, msg_res).await
             },
+            () = tokio::time::sleep_until(connected.next_ping) => {
+                self.send_ping(connected).await
+            },
             _ = task_handle.make_shutdown_rx().await => {
                 return None;
             },
@@ -452,9 +488,15 @@ where
             peer = ?self.peer, %disconnect_count,
             resend_queue_len = self.resend_queue.queue.len(),
             "Received incoming connection");
-        match self.resend_buffer_contents(&mut new_connection).await {
+        // The ping lets the peer check our clock right away
+        let result = match self.ping_message(&mut new_connection).await {
+            Ok(()) => self.resend_buffer_contents(&mut new_connection).await,
+            Err(e) => Err(e),
+        };
+        match result {
             Ok(()) => PeerConnectionState::Connected(ConnectedPeerConnectionState {
                 connection: new_connection,
+                next_ping: Instant::now() + PING_INTERVAL,
             }),
             Err(e) => self.disconnect_err(e, disconnect_count),
         }
@@ -467,8 +509,9 @@ where
         for msg in self.resend_queue.iter().cloned() {
             connection
                 .send(PeerMessage {
-                    msg,
+                    msg: Some(msg),
                     ack: self.last_received,
+                    sent_at: now(),
                 })
                 .await?
         }
@@ -476,6 +519,61 @@ where
         Ok(())
     }
 
+    async fn ping_message(
+        &self,
+        connection: &mut AnyFramedTransport<PeerMessage<M>>,
+    ) -> Result<(), anyhow::Error> {
+        connection
+            .send(PeerMessage {
+                msg: None,
+                ack: self.last_received,
+                sent_at: now(),
+            })
+            .await
+    }
+
+    async fn send_ping(
+        &mut self,
+        mut connected: ConnectedPeerConnectionState<M>,
+    ) -> PeerConnectionState<M> {
+        trace!(target: LOG_NET_PEER, peer = ?self.peer, "Sending ping");
+        match self.ping_message(&mut connected.connection).await {
+            Ok(()) => {
+                connected.next_ping = Instant::now() + PING_INTERVAL;
+                PeerConnectionState::Connected(connected)
+            }
+            Err(e) => self.disconnect_err(e, 0),
+        }
+    }
+
+    /// Records the clock skew of the peer, logging when it starts or stops
+    /// exceeding [`MAX_CLOCK_SKEW`]
+    fn record_clock_skew(&self, sent_at: SystemTime) {
+        let skew_ms = clock_skew_ms(sent_at, now());
+        let previous_ms = self
+            .clock_skews
+            .lock()
+            .expect("lock poisoned")
+            .insert(self.peer, skew_ms);
+
+        let is_skewed = |skew_ms: i64| skew_ms.unsigned_abs() > MAX_CLOCK_SKEW.as_millis() as u64;
+        match (previous_ms.map_or(false, is_skewed), is_skewed(skew_ms)) {
+            (false, true) => warn!(
+                target: LOG_NET_PEER,
+                peer = ?self.peer,
+                skew_ms,
+                "Clock of peer differs from ours, check that both are synchronized"
+            ),
+            (true, false) => info!(
+                target: LOG_NET_PEER,
+                peer = ?self.peer,
+                skew_ms,
+                "Clock of peer is in sync again"
+            ),
+            _ => {}
+        }
+    }
+
     fn disconnect(&self, mut disconnect_count: u64) -> PeerConnectionState<M> {
         disconnect_count += 1;
 
@@ -515,8 +613,9 @@ where
         match connected
             .connection
             .send(PeerMessage {
-                msg: umsg,
+                msg: Some(umsg),
                 ack: self.last_received,
+                sent_at: now(),
             })
             .await
         {
@@ -543,7 +642,15 @@ where
         &mut self,
         msg_res: Result<PeerMessage<M>, anyhow::Error>,
     ) -> Result<(), anyhow::Error> {
-        let PeerMessage { msg, ack } = msg_res?;
+        let PeerMessage { msg, ack, sent_at } = msg_res?;
+        self.record_clock_skew(sent_at);
+        let Some(msg) = msg else {
+            trace!(target: LOG_NET_PEER, peer = ?self.peer, "Received ping");
+            if let Some(ack) = ack {
+                self.resend_queue.ack(ack);
+            }
+            return Ok(());
+        };
         trace!(target: LOG_NET_PEER,peer = ?self.peer, id = ?msg.id, "Received incoming message");
 
         let expected = self
@@ -682,6 +789,7 @@ where
         connect: SharedAnyConnector<PeerMessage<M>>,
         incoming_connections: Receiver<AnyFramedTransport<PeerMessage<M>>>,
         status_query_receiver: PeerStatusChannelReceiver,
+        clock_skews: ClockSkews,
         task_group: &mut TaskGroup,
     ) -> PeerConnection<M> {
         let (outgoing_sender, outgoing_receiver) = tokio::sync::mpsc::channel::<M>(1024);
@@ -698,6 +806,7 @@ where
                     connect,
                     incoming_connections,
                     status_query_receiver,
+                    clock_skews,
                     &handle,
                 )
                 .await
@@ -729,6 +838,7 @@ where
         connect: SharedAnyConnector<PeerMessage<M>>,
         incoming_connections: Receiver<AnyFramedTransport<PeerMessage<M>>>,
         status_query_receiver: PeerStatusChannelReceiver,
+        clock_skews: ClockSkews,
         task_handle: &TaskHandle,
     ) {
         let common = CommonPeerConnectionState {
@@ -742,6 +852,7 @@ where
             incoming_connections,
             status_query_receiver,
             last_received: None,
+            clock_skews,
         };
         let initial_state = common.disconnect(0);
 
@@ -754,16 +865,26 @@ where
     }
 }
 
+/// Milliseconds the clock of a peer that sent a message at `sent_at` is ahead
+/// of ours, we received it at `received_at`. Includes the network latency, which
+/// is negligible compared to [`MAX_CLOCK_SKEW`].
+fn clock_skew_ms(sent_at: SystemTime, received_at: SystemTime) -> i64 {
+    match sent_at.duration_since(received_at) {
+        Ok(ahead) => ahead.as_millis() as i64,
+        Err(behind) => -(behind.duration().as_millis() as i64),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::collections::HashMap;
-    use std::time::Duration;
+    use std::time::{Duration, SystemTime};
 
     use fedimint_core::task::TaskGroup;
     use fedimint_core::PeerId;
     use futures::Future;
 
-    use super::DelayCalculator;
+    use super::{clock_skew_ms, DelayCalculator};
     use crate::net::connect::mock::{MockNetwork, StreamReliability};
     use crate::net::connect::Connector;
     use crate::net::peers::{IPeerConnections, NetworkConfig, ReconnectPeerConnections};
@@ -841,6 +962,9 @@ mod tests {
             let status = peer_status_client_c.get_all_status().await;
             assert_eq!(status.len(), 2);
             assert!(status.values().all(|s| s.is_ok()));
+            // all peers share our clock
+            let skews = peer_status_client_c.clock_skews();
+            assert!(skews[&PeerId::from(1)].abs() < 1_000);
         }
 
         task_group.shutdown().await;
@@ -862,4 +986,18 @@ mod tests {
         assert!((10..20).contains(&c.reconnection_delay(1).as_millis()));
         assert!((10000..11000).contains(&c.reconnection_delay(10).as_millis()));
     }
+
+    #[test]
+    fn test_clock_skew() {
+        let received_at = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        assert_eq!(clock_skew_ms(received_at, received_at), 0);
+        assert_eq!(
+            clock_skew_ms(received_at + Duration::from_millis(1_500), received_at),
+            1_500
+        );
+        assert_eq!(
+            clock_skew_ms(received_at - Duration::from_secs(30), received_at),
+            -30_000
+        );
+    }
 }
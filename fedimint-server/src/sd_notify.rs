@@ -0,0 +1,59 @@
+//! Reports our state to the service manager, see `sd_notify(3)`
+//!
+//! Lets `fedimintd` run as a `Type=notify` systemd service, so units depending
+//! on it only start once its API is reachable and the manager knows when a
+//! stop is in progress. Does nothing unless `NOTIFY_SOCKET` is set.
+
+use std::env;
+
+use fedimint_logging::LOG_CORE;
+use tracing::warn;
+
+/// Environment variable the service manager passes its socket in
+const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+
+/// The API is up, `status` describes what we are doing
+pub fn ready(status: &str) {
+    notify(&format!("READY=1\nSTATUS={status}"));
+}
+
+/// Updates the status shown by `systemctl status`
+pub fn status(status: &str) {
+    notify(&format!("STATUS={status}"));
+}
+
+/// We received a shutdown signal and are stopping gracefully
+pub fn stopping() {
+    notify("STOPPING=1\nSTATUS=Shutting down");
+}
+
+#[cfg(unix)]
+fn notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    use tracing::debug;
+
+    let Some(socket_path) = env::var_os(NOTIFY_SOCKET_ENV) else {
+        return;
+    };
+    // Sockets in the abstract namespace can't be addressed through std yet,
+    // systemd itself always uses a path
+    if socket_path.to_string_lossy().starts_with('@') {
+        warn!(target: LOG_CORE, ?socket_path, "Abstract notify sockets are not supported");
+        return;
+    }
+
+    let result =
+        UnixDatagram::unbound().and_then(|socket| socket.send_to(state.as_bytes(), &socket_path));
+    match result {
+        Ok(_) => debug!(target: LOG_CORE, %state, "Notified service manager"),
+        Err(e) => warn!(target: LOG_CORE, ?e, "Unable to notify service manager"),
+    }
+}
+
+#[cfg(not(unix))]
+fn notify(_state: &str) {
+    if env::var_os(NOTIFY_SOCKET_ENV).is_some() {
+        warn!(target: LOG_CORE, "Notifying the service manager is only supported on unix");
+    }
+}
@@ -0,0 +1,718 @@
+//! Signing with the secret keys of a guardian
+//!
+//! Modules don't use their secret keys directly but go through a
+//! [`IGuardianSigner`]. By default it's a [`LocalSigner`] holding the keys from
+//! the private config, if [`FM_GUARDIAN_SIGNER_URL`] is set the keys are used
+//! by an HSM or signer daemon instead, see `proto/signer.proto`. Keys are
+//! identified by the instance id of the module using them. A guardian using
+//! an external signer can remove the secret keys from its private config.
+//!
+//! The secret FROST nonces never leave the signer either. A module only gets
+//! the commitments to them from [`IGuardianSigner::frost_commit`], and the
+//! signer deletes the nonces of a session before signing with them, so a
+//! compromised host can't make it reuse a nonce and extract the key share.
+//! Nonces live in memory only, after a restart the module has to commit to
+//! fresh ones.
+
+use std::collections::BTreeMap;
+use std::fmt::Debug;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, ensure, Context};
+use async_trait::async_trait;
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::epoch::{SerdeCiphertext, SerdeDecryptionShare};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::tiered::Tiered;
+use fedimint_core::Amount;
+use frost_secp256k1_tr as frost;
+use frost_secp256k1_tr::keys::{KeyPackage, SigningShare, VerifyingShare};
+use rand::rngs::OsRng;
+use secp256k1_zkp::{ecdsa, Message, PublicKey, Scalar, Secp256k1, SecretKey, SECP256K1};
+use threshold_crypto::{Ciphertext, DecryptionShare};
+use tokio::task::JoinHandle;
+use tonic::transport::{Channel, Endpoint, Server};
+use tonic::{Request, Response, Status};
+use tracing::info;
+
+pub mod proto {
+    tonic::include_proto!("signer");
+}
+
+use proto::guardian_signer_client::GuardianSignerClient;
+use proto::guardian_signer_server::{GuardianSigner, GuardianSignerServer};
+
+/// Env var pointing to the signer daemon, keys stay in the config if unset
+pub const FM_GUARDIAN_SIGNER_URL: &str = "FM_GUARDIAN_SIGNER_URL";
+
+/// Uses the secret keys of a guardian on behalf of a module
+#[async_trait]
+pub trait IGuardianSigner: Debug + Send + Sync {
+    /// Signs `digest` with the secp256k1 key after adding `tweak` to it
+    async fn sign_ecdsa(
+        &self,
+        tweak: &Scalar,
+        digest: &Message,
+    ) -> anyhow::Result<ecdsa::Signature>;
+
+    /// Creates a blind signature share of `msg` with the key share of `tier`
+    async fn sign_blinded(
+        &self,
+        tier: Amount,
+        msg: tbs::BlindedMessage,
+    ) -> anyhow::Result<tbs::BlindedSignatureShare>;
+
+    /// Creates our decryption share of a threshold encrypted `ciphertext`
+    async fn decrypt_share(&self, ciphertext: &Ciphertext) -> anyhow::Result<DecryptionShare>;
+
+    /// Generates `count` nonces for signing the messages of `session` with
+    /// the FROST key share and returns the commitments to them. The secret
+    /// nonces stay in the signer, replacing earlier ones of the session.
+    async fn frost_commit(
+        &self,
+        session: [u8; 32],
+        count: usize,
+    ) -> anyhow::Result<Vec<frost::round1::SigningCommitments>>;
+
+    /// Creates a FROST signature share of every signing package of
+    /// `session`, for the taproot key spend of an output whose internal key
+    /// is the group key plus the package's tweak. The nonces of the session
+    /// are deleted first, so signing a session twice fails.
+    async fn sign_frost(
+        &self,
+        session: [u8; 32],
+        packages: &[(frost::SigningPackage, Scalar)],
+    ) -> anyhow::Result<Vec<frost::round2::SignatureShare>>;
+
+    /// Deletes the nonces of `session` without signing
+    async fn frost_discard(&self, session: [u8; 32]) -> anyhow::Result<()>;
+}
+
+pub type DynGuardianSigner = Arc<dyn IGuardianSigner>;
+
+/// Returns the signer for the keys of the module instance
+/// `module_instance_id`, which are `local` unless [`FM_GUARDIAN_SIGNER_URL`]
+/// is set. Without an external signer all keys the module uses have to be in
+/// its private config.
+pub fn guardian_signer(
+    module_instance_id: ModuleInstanceId,
+    local: LocalSigner,
+) -> anyhow::Result<DynGuardianSigner> {
+    match std::env::var(FM_GUARDIAN_SIGNER_URL) {
+        Ok(url) => {
+            info!(%url, module_instance_id, "Using external guardian signer");
+            Ok(Arc::new(RemoteSigner::new(&url, module_instance_id)?))
+        }
+        Err(_) => Ok(Arc::new(local)),
+    }
+}
+
+/// Signs with secret keys held in memory
+#[derive(Default)]
+pub struct LocalSigner {
+    ecdsa_key: Option<SecretKey>,
+    tbs_keys: Option<Tiered<tbs::SecretKeyShare>>,
+    threshold_key: Option<threshold_crypto::SecretKeyShare>,
+    frost_key: Option<KeyPackage>,
+    frost_nonces: Mutex<BTreeMap<[u8; 32], Vec<frost::round1::SigningNonces>>>,
+}
+
+impl LocalSigner {
+    pub fn with_ecdsa_key(mut self, key: impl Into<Option<SecretKey>>) -> Self {
+        self.ecdsa_key = key.into();
+        self
+    }
+
+    pub fn with_tbs_keys(mut self, keys: impl Into<Option<Tiered<tbs::SecretKeyShare>>>) -> Self {
+        self.tbs_keys = keys.into();
+        self
+    }
+
+    pub fn with_threshold_key(
+        mut self,
+        key: impl Into<Option<threshold_crypto::SecretKeyShare>>,
+    ) -> Self {
+        self.threshold_key = key.into();
+        self
+    }
+
+    pub fn with_frost_key(mut self, key: impl Into<Option<KeyPackage>>) -> Self {
+        self.frost_key = key.into();
+        self
+    }
+
+    fn frost_key(&self) -> anyhow::Result<&KeyPackage> {
+        self.frost_key.as_ref().context("Signer holds no FROST key")
+    }
+}
+
+/// Adds `tweak` to the key share and the public keys of `key_package`, the
+/// Lagrange coefficients add up to one so the shares of the tweaked group key
+/// are the tweaked shares
+fn tweak_key_package(key_package: &KeyPackage, tweak: &Scalar) -> anyhow::Result<KeyPackage> {
+    let tweak_point = |bytes: Vec<u8>| -> anyhow::Result<Vec<u8>> {
+        Ok(PublicKey::from_slice(&bytes)?
+            .add_exp_tweak(SECP256K1, tweak)?
+            .serialize()
+            .to_vec())
+    };
+    let signing_share =
+        SecretKey::from_slice(&key_package.signing_share().serialize())?.add_tweak(tweak)?;
+
+    Ok(KeyPackage::new(
+        *key_package.identifier(),
+        SigningShare::deserialize(&signing_share.secret_bytes())?,
+        VerifyingShare::deserialize(&tweak_point(key_package.verifying_share().serialize()?)?)?,
+        frost::VerifyingKey::deserialize(&tweak_point(key_package.verifying_key().serialize()?)?)?,
+        *key_package.min_signers(),
+    ))
+}
+
+impl Debug for LocalSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalSigner")
+            .field("ecdsa_key", &self.ecdsa_key.is_some())
+            .field("tbs_keys", &self.tbs_keys.is_some())
+            .field("threshold_key", &self.threshold_key.is_some())
+            .field("frost_key", &self.frost_key.is_some())
+            .finish()
+    }
+}
+
+#[async_trait]
+impl IGuardianSigner for LocalSigner {
+    async fn sign_ecdsa(
+        &self,
+        tweak: &Scalar,
+        digest: &Message,
+    ) -> anyhow::Result<ecdsa::Signature> {
+        let key = self
+            .ecdsa_key
+            .as_ref()
+            .context("Signer holds no ECDSA key")?
+            .add_tweak(tweak)?;
+        Ok(Secp256k1::signing_only().sign_ecdsa(digest, &key))
+    }
+
+    async fn sign_blinded(
+        &self,
+        tier: Amount,
+        msg: tbs::BlindedMessage,
+    ) -> anyhow::Result<tbs::BlindedSignatureShare> {
+        let key = self
+            .tbs_keys
+            .as_ref()
+            .context("Signer holds no blind signing keys")?
+            .tier(&tier)?;
+        Ok(tbs::sign_blinded_msg(msg, *key))
+    }
+
+    async fn decrypt_share(&self, ciphertext: &Ciphertext) -> anyhow::Result<DecryptionShare> {
+        self.threshold_key
+            .as_ref()
+            .context("Signer holds no threshold key")?
+            .decrypt_share(ciphertext)
+            .context("Invalid ciphertext")
+    }
+
+    async fn frost_commit(
+        &self,
+        session: [u8; 32],
+        count: usize,
+    ) -> anyhow::Result<Vec<frost::round1::SigningCommitments>> {
+        let key = self.frost_key()?;
+        let (nonces, commitments) = (0..count)
+            .map(|_| frost::round1::commit(key.signing_share(), &mut OsRng))
+            .unzip();
+        self.frost_nonces
+            .lock()
+            .expect("lock poisoned")
+            .insert(session, nonces);
+        Ok(commitments)
+    }
+
+    async fn sign_frost(
+        &self,
+        session: [u8; 32],
+        packages: &[(frost::SigningPackage, Scalar)],
+    ) -> anyhow::Result<Vec<frost::round2::SignatureShare>> {
+        let key = self.frost_key()?;
+        let nonces = self
+            .frost_nonces
+            .lock()
+            .expect("lock poisoned")
+            .remove(&session)
+            .context("No FROST nonces for the session, they are used only once")?;
+        ensure!(
+            nonces.len() == packages.len(),
+            "Committed to {} nonces, got {} signing packages",
+            nonces.len(),
+            packages.len()
+        );
+
+        nonces
+            .iter()
+            .zip(packages)
+            .map(|(nonces, (package, tweak))| {
+                let key = tweak_key_package(key, tweak)?;
+                Ok(frost::round2::sign_with_tweak(package, nonces, &key, None)?)
+            })
+            .collect()
+    }
+
+    async fn frost_discard(&self, session: [u8; 32]) -> anyhow::Result<()> {
+        self.frost_nonces
+            .lock()
+            .expect("lock poisoned")
+            .remove(&session);
+        Ok(())
+    }
+}
+
+/// Signs with keys held by a signer daemon
+#[derive(Debug)]
+pub struct RemoteSigner {
+    client: GuardianSignerClient<Channel>,
+    module_instance_id: ModuleInstanceId,
+}
+
+impl RemoteSigner {
+    /// Connects lazily to the signer at `url` on the first request
+    pub fn new(url: &str, module_instance_id: ModuleInstanceId) -> anyhow::Result<Self> {
+        let channel = Endpoint::from_shared(url.to_string())?.connect_lazy();
+        Ok(RemoteSigner {
+            client: GuardianSignerClient::new(channel),
+            module_instance_id,
+        })
+    }
+}
+
+fn signer_failed(status: Status) -> anyhow::Error {
+    anyhow!("Guardian signer failed: {status}")
+}
+
+#[async_trait]
+impl IGuardianSigner for RemoteSigner {
+    async fn sign_ecdsa(
+        &self,
+        tweak: &Scalar,
+        digest: &Message,
+    ) -> anyhow::Result<ecdsa::Signature> {
+        let request = proto::SignEcdsaRequest {
+            module_instance_id: self.module_instance_id.into(),
+            tweak: tweak.to_be_bytes().to_vec(),
+            digest: digest.as_ref().to_vec(),
+        };
+        let response = self
+            .client
+            .clone()
+            .sign_ecdsa(request)
+            .await
+            .map_err(signer_failed)?
+            .into_inner();
+        Ok(ecdsa::Signature::from_compact(&response.signature)?)
+    }
+
+    async fn sign_blinded(
+        &self,
+        tier: Amount,
+        msg: tbs::BlindedMessage,
+    ) -> anyhow::Result<tbs::BlindedSignatureShare> {
+        let request = proto::SignBlindedRequest {
+            module_instance_id: self.module_instance_id.into(),
+            tier_msats: tier.msats,
+            message: msg.consensus_encode_to_vec()?,
+        };
+        let response = self
+            .client
+            .clone()
+            .sign_blinded(request)
+            .await
+            .map_err(signer_failed)?
+            .into_inner();
+        Ok(decode(&response.share)?)
+    }
+
+    async fn decrypt_share(&self, ciphertext: &Ciphertext) -> anyhow::Result<DecryptionShare> {
+        let request = proto::DecryptShareRequest {
+            module_instance_id: self.module_instance_id.into(),
+            ciphertext: SerdeCiphertext(ciphertext.clone()).consensus_encode_to_vec()?,
+        };
+        let response = self
+            .client
+            .clone()
+            .decrypt_share(request)
+            .await
+            .map_err(signer_failed)?
+            .into_inner();
+        Ok(decode::<SerdeDecryptionShare>(&response.share)?.0)
+    }
+
+    async fn frost_commit(
+        &self,
+        session: [u8; 32],
+        count: usize,
+    ) -> anyhow::Result<Vec<frost::round1::SigningCommitments>> {
+        let request = proto::FrostCommitRequest {
+            module_instance_id: self.module_instance_id.into(),
+            session: session.to_vec(),
+            count: count.try_into()?,
+        };
+        let response = self
+            .client
+            .clone()
+            .frost_commit(request)
+            .await
+            .map_err(signer_failed)?
+            .into_inner();
+        response
+            .commitments
+            .iter()
+            .map(|commitments| {
+                frost::round1::SigningCommitments::deserialize(commitments)
+                    .map_err(invalid_response)
+            })
+            .collect()
+    }
+
+    async fn sign_frost(
+        &self,
+        session: [u8; 32],
+        packages: &[(frost::SigningPackage, Scalar)],
+    ) -> anyhow::Result<Vec<frost::round2::SignatureShare>> {
+        let request = proto::SignFrostRequest {
+            module_instance_id: self.module_instance_id.into(),
+            session: session.to_vec(),
+            packages: packages
+                .iter()
+                .map(|(package, tweak)| {
+                    Ok(proto::FrostSigningPackage {
+                        signing_package: package.serialize()?,
+                        tweak: tweak.to_be_bytes().to_vec(),
+                    })
+                })
+                .collect::<anyhow::Result<_>>()?,
+        };
+        let response = self
+            .client
+            .clone()
+            .sign_frost(request)
+            .await
+            .map_err(signer_failed)?
+            .into_inner();
+        response
+            .shares
+            .iter()
+            .map(|share| {
+                frost::round2::SignatureShare::deserialize(share).map_err(invalid_response)
+            })
+            .collect()
+    }
+
+    async fn frost_discard(&self, session: [u8; 32]) -> anyhow::Result<()> {
+        let request = proto::FrostDiscardRequest {
+            module_instance_id: self.module_instance_id.into(),
+            session: session.to_vec(),
+        };
+        self.client
+            .clone()
+            .frost_discard(request)
+            .await
+            .map_err(signer_failed)?;
+        Ok(())
+    }
+}
+
+fn invalid_response(e: impl std::fmt::Display) -> anyhow::Error {
+    anyhow!("Invalid response from guardian signer: {e}")
+}
+
+fn decode<T: Decodable>(bytes: &[u8]) -> anyhow::Result<T> {
+    T::consensus_decode(&mut &bytes[..], &ModuleDecoderRegistry::default())
+        .map_err(invalid_response)
+}
+
+/// Serves the keys of a [`LocalSigner`] over gRPC, a reference implementation
+/// of the signer daemon
+pub async fn run_signer(
+    bind: SocketAddr,
+    signers: Vec<(ModuleInstanceId, LocalSigner)>,
+) -> anyhow::Result<JoinHandle<()>> {
+    let listener = tokio::net::TcpListener::bind(bind).await?;
+    let service = GuardianSignerServer::new(SignerService {
+        signers: signers.into_iter().collect(),
+    });
+
+    Ok(tokio::spawn(async move {
+        Server::builder()
+            .add_service(service)
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener))
+            .await
+            .expect("Guardian signer failed");
+    }))
+}
+
+struct SignerService {
+    signers: std::collections::BTreeMap<ModuleInstanceId, LocalSigner>,
+}
+
+impl SignerService {
+    fn signer(&self, module_instance_id: u32) -> Result<&LocalSigner, Status> {
+        ModuleInstanceId::try_from(module_instance_id)
+            .ok()
+            .and_then(|module_instance_id| self.signers.get(&module_instance_id))
+            .ok_or_else(|| {
+                Status::not_found(format!("No keys of module instance {module_instance_id}"))
+            })
+    }
+}
+
+fn invalid_argument(e: impl std::fmt::Display) -> Status {
+    Status::invalid_argument(e.to_string())
+}
+
+fn parse_tweak(bytes: &[u8]) -> Result<Scalar, Status> {
+    let tweak = <[u8; 32]>::try_from(bytes).map_err(invalid_argument)?;
+    Scalar::from_be_bytes(tweak).map_err(invalid_argument)
+}
+
+fn parse_session(bytes: &[u8]) -> Result<[u8; 32], Status> {
+    <[u8; 32]>::try_from(bytes).map_err(invalid_argument)
+}
+
+#[tonic::async_trait]
+impl GuardianSigner for SignerService {
+    async fn sign_ecdsa(
+        &self,
+        request: Request<proto::SignEcdsaRequest>,
+    ) -> Result<Response<proto::SignEcdsaResponse>, Status> {
+        let request = request.into_inner();
+        let tweak = parse_tweak(&request.tweak)?;
+        let digest = Message::from_slice(&request.digest).map_err(invalid_argument)?;
+
+        let signature = self
+            .signer(request.module_instance_id)?
+            .sign_ecdsa(&tweak, &digest)
+            .await
+            .map_err(invalid_argument)?;
+        Ok(Response::new(proto::SignEcdsaResponse {
+            signature: signature.serialize_compact().to_vec(),
+        }))
+    }
+
+    async fn sign_blinded(
+        &self,
+        request: Request<proto::SignBlindedRequest>,
+    ) -> Result<Response<proto::SignBlindedResponse>, Status> {
+        let request = request.into_inner();
+        let msg = decode(&request.message).map_err(invalid_argument)?;
+
+        let share = self
+            .signer(request.module_instance_id)?
+            .sign_blinded(Amount::from_msats(request.tier_msats), msg)
+            .await
+            .map_err(invalid_argument)?;
+        Ok(Response::new(proto::SignBlindedResponse {
+            share: share.consensus_encode_to_vec().map_err(invalid_argument)?,
+        }))
+    }
+
+    async fn decrypt_share(
+        &self,
+        request: Request<proto::DecryptShareRequest>,
+    ) -> Result<Response<proto::DecryptShareResponse>, Status> {
+        let request = request.into_inner();
+        let ciphertext: SerdeCiphertext = decode(&request.ciphertext).map_err(invalid_argument)?;
+
+        let share = self
+            .signer(request.module_instance_id)?
+            .decrypt_share(&ciphertext.0)
+            .await
+            .map_err(invalid_argument)?;
+        Ok(Response::new(proto::DecryptShareResponse {
+            share: SerdeDecryptionShare(share)
+                .consensus_encode_to_vec()
+                .map_err(invalid_argument)?,
+        }))
+    }
+
+    async fn frost_commit(
+        &self,
+        request: Request<proto::FrostCommitRequest>,
+    ) -> Result<Response<proto::FrostCommitResponse>, Status> {
+        let request = request.into_inner();
+        let session = parse_session(&request.session)?;
+        let count = usize::try_from(request.count).map_err(invalid_argument)?;
+
+        let commitments = self
+            .signer(request.module_instance_id)?
+            .frost_commit(session, count)
+            .await
+            .map_err(invalid_argument)?;
+        Ok(Response::new(proto::FrostCommitResponse {
+            commitments: commitments
+                .iter()
+                .map(|commitments| commitments.serialize().map_err(invalid_argument))
+                .collect::<Result<_, _>>()?,
+        }))
+    }
+
+    async fn sign_frost(
+        &self,
+        request: Request<proto::SignFrostRequest>,
+    ) -> Result<Response<proto::SignFrostResponse>, Status> {
+        let request = request.into_inner();
+        let session = parse_session(&request.session)?;
+        let packages = request
+            .packages
+            .iter()
+            .map(|package| {
+                let signing_package = frost::SigningPackage::deserialize(&package.signing_package)
+                    .map_err(invalid_argument)?;
+                Ok((signing_package, parse_tweak(&package.tweak)?))
+            })
+            .collect::<Result<Vec<_>, Status>>()?;
+
+        let shares = self
+            .signer(request.module_instance_id)?
+            .sign_frost(session, &packages)
+            .await
+            .map_err(invalid_argument)?;
+        Ok(Response::new(proto::SignFrostResponse {
+            shares: shares.iter().map(|share| share.serialize()).collect(),
+        }))
+    }
+
+    async fn frost_discard(
+        &self,
+        request: Request<proto::FrostDiscardRequest>,
+    ) -> Result<Response<proto::FrostDiscardResponse>, Status> {
+        let request = request.into_inner();
+        let session = parse_session(&request.session)?;
+
+        self.signer(request.module_instance_id)?
+            .frost_discard(session)
+            .await
+            .map_err(invalid_argument)?;
+        Ok(Response::new(proto::FrostDiscardResponse {}))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::tiered::Tiered;
+    use frost_secp256k1_tr::keys::IdentifierList;
+    use rand::rngs::OsRng;
+    use tbs::BlindingKey;
+    use threshold_crypto::SecretKeySet;
+
+    use super::*;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn remote_signer_matches_local_signer() {
+        let secp = Secp256k1::new();
+        let (ecdsa_key, _) = secp.generate_keypair(&mut OsRng);
+        let tbs_keys: Tiered<_> = [(Amount::from_msats(1), tbs::dealer_keygen(1, 1).2.remove(0))]
+            .into_iter()
+            .collect();
+        let threshold_keys = SecretKeySet::random(0, &mut OsRng);
+        let (frost_shares, _) =
+            frost::keys::generate_with_dealer(2, 2, IdentifierList::Default, &mut OsRng).unwrap();
+        let frost_keys = frost_shares
+            .into_values()
+            .map(|share| KeyPackage::try_from(share).unwrap())
+            .collect::<Vec<_>>();
+
+        let local = || {
+            LocalSigner::default()
+                .with_ecdsa_key(ecdsa_key)
+                .with_tbs_keys(tbs_keys.clone())
+                .with_threshold_key(threshold_keys.secret_key_share(0))
+                .with_frost_key(frost_keys[0].clone())
+        };
+
+        let bind = "127.0.0.1:0".parse().unwrap();
+        let listener = std::net::TcpListener::bind(bind).unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+        run_signer(addr, vec![(1, local())]).await.unwrap();
+
+        let local = local();
+        let remote = RemoteSigner::new(&format!("http://{addr}"), 1).unwrap();
+
+        let tweak = Scalar::from_be_bytes([1; 32]).unwrap();
+        let digest = Message::from_slice(&[2; 32]).unwrap();
+        assert_eq!(
+            remote.sign_ecdsa(&tweak, &digest).await.unwrap(),
+            local.sign_ecdsa(&tweak, &digest).await.unwrap()
+        );
+
+        let msg = tbs::blind_message(tbs::Message::from_bytes(b"note"), BlindingKey::random());
+        assert_eq!(
+            remote
+                .sign_blinded(Amount::from_msats(1), msg)
+                .await
+                .unwrap()
+                .0,
+            local
+                .sign_blinded(Amount::from_msats(1), msg)
+                .await
+                .unwrap()
+                .0
+        );
+        assert!(remote
+            .sign_blinded(Amount::from_msats(2), msg)
+            .await
+            .is_err());
+
+        let ciphertext = threshold_keys
+            .public_keys()
+            .public_key()
+            .encrypt(b"preimage");
+        assert_eq!(
+            remote.decrypt_share(&ciphertext).await.unwrap(),
+            local.decrypt_share(&ciphertext).await.unwrap()
+        );
+
+        // The nonces stay in the signer and are only used once
+        let session = [3; 32];
+        let commitments = remote.frost_commit(session, 1).await.unwrap();
+        assert_eq!(commitments.len(), 1);
+        let (_, other_commitments) =
+            frost::round1::commit(frost_keys[1].signing_share(), &mut OsRng);
+        let packages = [(
+            frost::SigningPackage::new(
+                [
+                    (*frost_keys[0].identifier(), commitments[0]),
+                    (*frost_keys[1].identifier(), other_commitments),
+                ]
+                .into_iter()
+                .collect(),
+                &[4; 32],
+            ),
+            tweak,
+        )];
+        assert_eq!(
+            remote.sign_frost(session, &packages).await.unwrap().len(),
+            1
+        );
+        assert!(remote.sign_frost(session, &packages).await.is_err());
+
+        // Another signer doesn't hold the nonces of the session
+        assert!(local.sign_frost(session, &packages).await.is_err());
+
+        remote.frost_commit(session, 1).await.unwrap();
+        remote.frost_discard(session).await.unwrap();
+        assert!(remote.sign_frost(session, &packages).await.is_err());
+
+        let unknown = RemoteSigner::new(&format!("http://{addr}"), 2).unwrap();
+        assert!(unknown.sign_ecdsa(&tweak, &digest).await.is_err());
+
+        // Without keys in the config every request fails
+        let keyless = LocalSigner::default();
+        assert!(keyless.sign_ecdsa(&tweak, &digest).await.is_err());
+        assert!(keyless.frost_commit(session, 1).await.is_err());
+    }
+}
@@ -0,0 +1,434 @@
+//! Online backups of the server database
+//!
+//! A backup directory contains chains of logical snapshots, each in its own
+//! `chain-<n>` directory. A chain starts with a full snapshot
+//! (`snapshot-<n>.bin`) and contains the manifest of its latest snapshot used
+//! to create the next incremental one. The `CURRENT` file names the chain
+//! backups are restored from.
+//!
+//! Every backup writes a new chain into a fresh directory, the snapshots of an
+//! extended chain are hard linked into it. Replacing `CURRENT` with a single
+//! rename is the only step that changes the backup, so an interrupted backup
+//! leaves the previous chain intact.
+//!
+//! Native checkpoints are written into their own `checkpoint-<unix time>`
+//! directories. Backup directories are always inside the guardian's data
+//! directory, but never inside the database.
+
+use std::fs;
+use std::io::{Cursor, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use anyhow::{bail, Context};
+use fedimint_core::admin_client::DatabaseBackupResponse;
+use fedimint_core::db::snapshot::{DatabaseSnapshot, SnapshotManifest};
+use fedimint_core::db::Database;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::task::block_in_place;
+use fedimint_core::time::now;
+use tracing::warn;
+
+use crate::config::io::DB_FILE;
+
+/// Names the chain directory of the backup
+const CURRENT_FILE: &str = "CURRENT";
+const CHAIN_DIR_PREFIX: &str = "chain-";
+/// Manifest of the latest snapshot in a chain directory
+const MANIFEST_FILE: &str = "manifest.bin";
+const SNAPSHOT_FILE_PREFIX: &str = "snapshot-";
+const SNAPSHOT_FILE_EXTENSION: &str = "bin";
+const CHECKPOINT_DIR_PREFIX: &str = "checkpoint-";
+/// Appended to `CURRENT` until it is complete
+const TMP_FILE_SUFFIX: &str = ".tmp";
+
+/// Resolves the backup directory `path` requested through the admin API,
+/// which has to be a directory inside `data_dir` outside of the database
+pub fn backup_dir(data_dir: &Path, path: &Path) -> anyhow::Result<PathBuf> {
+    if path.as_os_str().is_empty()
+        || !path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+    {
+        bail!("Backup path {path:?} has to be a directory in the data directory without `..`");
+    }
+
+    // Checked before creating anything, backups must not touch the database files
+    let db_dir = data_dir.join(DB_FILE);
+    let dir = data_dir.join(path);
+    if dir.starts_with(&db_dir) {
+        bail!("Backup path {path:?} is inside the database");
+    }
+
+    fs::create_dir_all(&dir)?;
+    // Symlinks could still point outside of the data directory or into the
+    // database
+    let canonical_dir = dir.canonicalize()?;
+    let canonical_data_dir = data_dir.canonicalize()?;
+    if canonical_dir == canonical_data_dir || !canonical_dir.starts_with(&canonical_data_dir) {
+        bail!("Backup path {path:?} leaves the data directory");
+    }
+    if db_dir.exists() && canonical_dir.starts_with(db_dir.canonicalize()?) {
+        bail!("Backup path {path:?} is inside the database");
+    }
+    Ok(dir)
+}
+
+/// Writes a new logical snapshot of `db` into `backup_dir`.
+///
+/// If `incremental` is set and the directory already contains a chain only
+/// the changes since its latest snapshot are written, otherwise a new chain is
+/// started with a full snapshot. Either way the result becomes the current
+/// chain at once, the previous chain is removed afterwards.
+pub async fn write_snapshot(
+    db: &Database,
+    backup_dir: &Path,
+    incremental: bool,
+) -> anyhow::Result<DatabaseBackupResponse> {
+    let current = block_in_place(|| current_chain(backup_dir))?;
+    let base_chain = current.as_ref().filter(|_| incremental);
+    let base = base_chain
+        .map(|chain| block_in_place(|| read_encoded::<SnapshotManifest>(&chain.manifest_path())))
+        .transpose()?;
+
+    let (snapshot, manifest) = db.export_snapshot(base.as_ref()).await?;
+
+    let index = current.as_ref().map_or(0, |chain| chain.index + 1);
+    let path = block_in_place(|| write_chain(backup_dir, index, base_chain, &snapshot, &manifest))?;
+
+    Ok(DatabaseBackupResponse {
+        path,
+        snapshot_id: Some(manifest.id),
+        base: snapshot.base,
+        entries: snapshot.entries.len() as u64,
+    })
+}
+
+/// Creates a native checkpoint of `db` in a new directory inside `backup_dir`
+pub fn write_checkpoint(
+    db: &Database,
+    backup_dir: &Path,
+) -> anyhow::Result<DatabaseBackupResponse> {
+    fs::create_dir_all(backup_dir)?;
+
+    let timestamp = now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time to work")
+        .as_secs();
+    let path = backup_dir.join(format!("{CHECKPOINT_DIR_PREFIX}{timestamp}"));
+    db.checkpoint(&path)?;
+
+    Ok(DatabaseBackupResponse {
+        path,
+        snapshot_id: None,
+        base: None,
+        entries: 0,
+    })
+}
+
+/// Restores the current chain from `backup_dir` into the (empty) database `db`
+pub async fn restore_snapshots(db: &Database, backup_dir: &Path) -> anyhow::Result<()> {
+    let Some(chain) = block_in_place(|| current_chain(backup_dir))? else {
+        bail!("No snapshots found in {backup_dir:?}");
+    };
+    let mut last_id = None;
+
+    for (_, path) in block_in_place(|| snapshot_files(&chain.dir))? {
+        let snapshot = block_in_place(|| read_encoded::<DatabaseSnapshot>(&path))?;
+        if snapshot.base != last_id {
+            bail!("Snapshot {path:?} does not build on the previous snapshot");
+        }
+        db.import_snapshot(&snapshot).await?;
+        last_id = Some(snapshot.id());
+    }
+
+    if last_id.is_none() {
+        bail!("No snapshots found in {backup_dir:?}");
+    }
+
+    Ok(())
+}
+
+/// A chain of snapshots in its own directory of the backup directory
+struct Chain {
+    index: u64,
+    dir: PathBuf,
+}
+
+impl Chain {
+    fn new(backup_dir: &Path, index: u64) -> Self {
+        Chain {
+            index,
+            dir: backup_dir.join(chain_dir_name(index)),
+        }
+    }
+
+    fn manifest_path(&self) -> PathBuf {
+        self.dir.join(MANIFEST_FILE)
+    }
+}
+
+fn chain_dir_name(index: u64) -> String {
+    format!("{CHAIN_DIR_PREFIX}{index}")
+}
+
+/// Returns the chain `CURRENT` points to, if a backup was written before
+fn current_chain(backup_dir: &Path) -> anyhow::Result<Option<Chain>> {
+    let current_path = backup_dir.join(CURRENT_FILE);
+    if !current_path.exists() {
+        return Ok(None);
+    }
+
+    let name = fs::read_to_string(&current_path)
+        .with_context(|| format!("Unable to read {current_path:?}"))?;
+    let index = name
+        .strip_prefix(CHAIN_DIR_PREFIX)
+        .and_then(|index| index.parse::<u64>().ok())
+        .with_context(|| format!("Invalid chain {name:?} in {current_path:?}"))?;
+    Ok(Some(Chain::new(backup_dir, index)))
+}
+
+/// Writes the chain `index` containing the snapshots of `base_chain` followed
+/// by `snapshot` and makes it the current chain, returns the path of
+/// `snapshot`
+fn write_chain(
+    backup_dir: &Path,
+    index: u64,
+    base_chain: Option<&Chain>,
+    snapshot: &DatabaseSnapshot,
+    manifest: &SnapshotManifest,
+) -> anyhow::Result<PathBuf> {
+    fs::create_dir_all(backup_dir)?;
+    let chain = Chain::new(backup_dir, index);
+    // Left behind by an interrupted backup
+    if chain.dir.exists() {
+        fs::remove_dir_all(&chain.dir)?;
+    }
+    fs::create_dir(&chain.dir)?;
+
+    let mut next_snapshot = 0;
+    if let Some(base_chain) = base_chain {
+        for (snapshot_index, base_path) in snapshot_files(&base_chain.dir)? {
+            // Snapshots are never modified, so chains can share them
+            let path = snapshot_path(&chain.dir, snapshot_index);
+            fs::hard_link(&base_path, &path)
+                .or_else(|_| fs::copy(&base_path, &path).map(|_| ()))
+                .with_context(|| format!("Unable to copy {base_path:?}"))?;
+            next_snapshot = snapshot_index + 1;
+        }
+    }
+
+    let path = snapshot_path(&chain.dir, next_snapshot);
+    write_encoded(&path, snapshot)?;
+    write_encoded(&chain.manifest_path(), manifest)?;
+    sync_dir(&chain.dir)?;
+
+    let current_path = backup_dir.join(CURRENT_FILE);
+    let tmp_current_path = tmp_path(&current_path);
+    write_synced(&tmp_current_path, chain_dir_name(index).as_bytes())?;
+    fs::rename(&tmp_current_path, &current_path)?;
+    sync_dir(backup_dir)?;
+
+    remove_stale_chains(backup_dir, index);
+    Ok(path)
+}
+
+/// Removes all chains except the current one. The backup was already written,
+/// so failures are only logged.
+fn remove_stale_chains(backup_dir: &Path, current_index: u64) {
+    let Ok(entries) = fs::read_dir(backup_dir) else {
+        return;
+    };
+    let current_name = chain_dir_name(current_index);
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else {
+            continue;
+        };
+        if name.starts_with(CHAIN_DIR_PREFIX) && name != current_name {
+            if let Err(e) = fs::remove_dir_all(entry.path()) {
+                warn!(
+                    "Unable to remove stale backup chain {:?}: {e}",
+                    entry.path()
+                );
+            }
+        }
+    }
+}
+
+fn snapshot_path(chain_dir: &Path, index: u64) -> PathBuf {
+    chain_dir.join(format!(
+        "{SNAPSHOT_FILE_PREFIX}{index}.{SNAPSHOT_FILE_EXTENSION}"
+    ))
+}
+
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(TMP_FILE_SUFFIX);
+    tmp_path.into()
+}
+
+/// Returns the snapshot files in `chain_dir` sorted by their index
+fn snapshot_files(chain_dir: &Path) -> anyhow::Result<Vec<(u64, PathBuf)>> {
+    let mut files = vec![];
+    for entry in fs::read_dir(chain_dir)? {
+        let path = entry?.path();
+        let index = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.strip_prefix(SNAPSHOT_FILE_PREFIX))
+            .and_then(|index| index.parse::<u64>().ok());
+
+        if let Some(index) = index {
+            files.push((index, path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn write_encoded<T: Encodable>(path: &Path, value: &T) -> anyhow::Result<()> {
+    write_synced(path, &value.consensus_encode_to_vec()?)
+}
+
+/// Writes `bytes` to `path` and waits until they are on disk
+fn write_synced(path: &Path, bytes: &[u8]) -> anyhow::Result<()> {
+    let mut file = fs::File::create(path).with_context(|| format!("Unable to write {path:?}"))?;
+    file.write_all(bytes)
+        .and_then(|_| file.sync_all())
+        .with_context(|| format!("Unable to write {path:?}"))
+}
+
+/// Makes renames and new files in `dir` durable
+fn sync_dir(dir: &Path) -> anyhow::Result<()> {
+    fs::File::open(dir)
+        .and_then(|dir| dir.sync_all())
+        .with_context(|| format!("Unable to sync {dir:?}"))
+}
+
+fn read_encoded<T: Decodable>(path: &Path) -> anyhow::Result<T> {
+    let bytes = fs::read(path).with_context(|| format!("Unable to read {path:?}"))?;
+    Ok(T::consensus_decode(
+        &mut Cursor::new(bytes),
+        &ModuleDecoderRegistry::default(),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::db::mem_impl::MemDatabase;
+    use fedimint_core::db::IDatabase;
+
+    use super::*;
+    use crate::db::{EpochHistoryKey, LastEpochKey};
+
+    async fn set_last_epoch(db: &Database, epoch: u64) {
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_entry(&LastEpochKey, &EpochHistoryKey(epoch))
+            .await;
+        dbtx.commit_tx().await;
+    }
+
+    fn chain_dirs(backup_dir: &Path) -> Vec<String> {
+        let mut dirs = fs::read_dir(backup_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .filter(|name| name.starts_with(CHAIN_DIR_PREFIX))
+            .collect::<Vec<_>>();
+        dirs.sort();
+        dirs
+    }
+
+    // `block_in_place` needs the multi-threaded runtime
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_backup_and_restore() {
+        let dir = tempfile::Builder::new()
+            .prefix("fedimint-snapshot-test")
+            .tempdir()
+            .unwrap();
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+
+        set_last_epoch(&db, 1).await;
+        let full = write_snapshot(&db, dir.path(), true).await.unwrap();
+        assert_eq!(full.base, None);
+        assert_eq!(full.entries, 1);
+
+        set_last_epoch(&db, 2).await;
+        let incremental = write_snapshot(&db, dir.path(), true).await.unwrap();
+        assert_eq!(incremental.base, full.snapshot_id);
+        assert_eq!(incremental.entries, 1);
+
+        let restored = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        restore_snapshots(&restored, dir.path()).await.unwrap();
+
+        let mut dbtx = restored.begin_transaction().await;
+        assert_eq!(
+            dbtx.get_value(&LastEpochKey).await.map(|key| key.0),
+            Some(2)
+        );
+
+        assert!(MemDatabase::new().checkpoint(dir.path()).is_err());
+
+        // A full snapshot replaces the chain
+        let full = write_snapshot(&db, dir.path(), false).await.unwrap();
+        assert_eq!(full.base, None);
+        let chain = current_chain(dir.path()).unwrap().unwrap();
+        assert_eq!(snapshot_files(&chain.dir).unwrap().len(), 1);
+        assert_eq!(chain_dirs(dir.path()), vec![chain_dir_name(chain.index)]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_interrupted_backup_keeps_chain() {
+        let dir = tempfile::Builder::new()
+            .prefix("fedimint-snapshot-test")
+            .tempdir()
+            .unwrap();
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+
+        set_last_epoch(&db, 1).await;
+        let full = write_snapshot(&db, dir.path(), true).await.unwrap();
+
+        // A backup that was interrupted before replacing `CURRENT`
+        let interrupted = Chain::new(dir.path(), 1);
+        fs::create_dir(&interrupted.dir).unwrap();
+        fs::write(snapshot_path(&interrupted.dir, 0), b"partial").unwrap();
+
+        let restored = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        restore_snapshots(&restored, dir.path()).await.unwrap();
+
+        // The next backup still builds on the current chain
+        set_last_epoch(&db, 2).await;
+        let incremental = write_snapshot(&db, dir.path(), true).await.unwrap();
+        assert_eq!(incremental.base, full.snapshot_id);
+        assert_eq!(chain_dirs(dir.path()), vec![chain_dir_name(1)]);
+
+        let restored = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        restore_snapshots(&restored, dir.path()).await.unwrap();
+        let mut dbtx = restored.begin_transaction().await;
+        assert_eq!(
+            dbtx.get_value(&LastEpochKey).await.map(|key| key.0),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn test_backup_dir_stays_in_data_dir() {
+        let data_dir = tempfile::Builder::new()
+            .prefix("fedimint-snapshot-test")
+            .tempdir()
+            .unwrap();
+
+        let dir = backup_dir(data_dir.path(), Path::new("backups/daily")).unwrap();
+        assert!(dir.starts_with(data_dir.path()));
+        assert!(backup_dir(data_dir.path(), Path::new("../backups")).is_err());
+        assert!(backup_dir(data_dir.path(), Path::new("/tmp/backups")).is_err());
+        assert!(backup_dir(data_dir.path(), Path::new("")).is_err());
+
+        fs::create_dir(data_dir.path().join(DB_FILE)).unwrap();
+        assert!(backup_dir(data_dir.path(), Path::new(DB_FILE)).is_err());
+        assert!(backup_dir(data_dir.path(), &Path::new(DB_FILE).join("backups")).is_err());
+        assert!(!data_dir.path().join(DB_FILE).join("backups").exists());
+    }
+}
@@ -13,8 +13,8 @@ use bitcoin::{
     TxOut,
 };
 use fedimint_bitcoind::{
-    register_bitcoind, DynBitcoindRpc, IBitcoindRpc, IBitcoindRpcFactory,
-    Result as BitcoinRpcResult,
+    register_bitcoind, BlockEventSender, BlockEvents, DynBitcoindRpc, IBitcoindRpc,
+    IBitcoindRpcFactory, Result as BitcoinRpcResult,
 };
 use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
 use fedimint_core::task::{sleep, TaskHandle};
@@ -25,6 +25,9 @@ use url::Url;
 
 use super::BitcoinTest;
 
+/// Compact representation of the easiest target allowed on regtest
+const REGTEST_BITS: u32 = 0x207fffff;
+
 #[derive(Debug, Clone)]
 pub struct FakeBitcoinFactory {
     pub bitcoin: FakeBitcoinTest,
@@ -66,6 +69,9 @@ pub struct FakeBitcoinTest {
     proofs: Arc<Mutex<BTreeMap<Txid, TxOutProof>>>,
     /// Simulates the script history
     scripts: Arc<Mutex<BTreeMap<Script, Vec<Transaction>>>>,
+    /// Notifies subscribers of mined blocks
+    block_sender: Arc<BlockEventSender>,
+    block_events: BlockEvents,
 }
 
 impl Default for FakeBitcoinTest {
@@ -76,12 +82,15 @@ impl Default for FakeBitcoinTest {
 
 impl FakeBitcoinTest {
     pub fn new() -> Self {
+        let (block_sender, block_events) = BlockEvents::channel();
         FakeBitcoinTest {
             blocks: Arc::new(Mutex::new(vec![])),
             pending: Arc::new(Mutex::new(vec![])),
             addresses: Arc::new(Mutex::new(Default::default())),
             proofs: Arc::new(Mutex::new(Default::default())),
             scripts: Arc::new(Mutex::new(Default::default())),
+            block_sender: Arc::new(block_sender),
+            block_events,
         }
     }
 
@@ -109,15 +118,20 @@ impl FakeBitcoinTest {
         let merkle_root = Self::pending_merkle_tree(pending)
             .extract_matches(&mut vec![], &mut vec![])
             .unwrap();
+        let mut header = BlockHeader {
+            version: 0,
+            prev_blockhash: blocks.last().map(|b| b.header.block_hash()).unwrap_or(root),
+            merkle_root,
+            time: 0,
+            // The wallet validates the proof of work, so we mine with the regtest target
+            bits: REGTEST_BITS,
+            nonce: 0,
+        };
+        while header.validate_pow(&header.target()).is_err() {
+            header.nonce += 1;
+        }
         let block = Block {
-            header: BlockHeader {
-                version: 0,
-                prev_blockhash: blocks.last().map(|b| b.header.block_hash()).unwrap_or(root),
-                merkle_root,
-                time: 0,
-                bits: 0,
-                nonce: 0,
-            },
+            header,
             txdata: pending.clone(),
         };
         pending.clear();
@@ -140,6 +154,8 @@ impl BitcoinTest for FakeBitcoinTest {
         for _ in 1..=block_num {
             FakeBitcoinTest::mine_block(&mut blocks, &mut pending);
         }
+        self.block_sender
+            .send_replace(blocks.last().map(|block| block.header.block_hash()));
     }
 
     async fn prepare_funding_wallet(&self) {
@@ -173,6 +189,8 @@ impl BitcoinTest for FakeBitcoinTest {
 
         FakeBitcoinTest::mine_block(&mut blocks, &mut pending);
         let block_header = blocks.last().unwrap().header;
+        self.block_sender
+            .send_replace(Some(block_header.block_hash()));
         let proof = TxOutProof {
             block_header,
             merkle_proof,
@@ -252,6 +270,10 @@ impl IBitcoindRpc for FakeBitcoinTest {
             .block_hash())
     }
 
+    async fn get_block_header(&self, height: u64) -> BitcoinRpcResult<BlockHeader> {
+        Ok(self.blocks.lock().unwrap()[(height - 1) as usize].header)
+    }
+
     async fn get_fee_rate(&self, _confirmation_target: u16) -> BitcoinRpcResult<Option<Feerate>> {
         Ok(None)
     }
@@ -295,6 +317,10 @@ impl IBitcoindRpc for FakeBitcoinTest {
         let proof = proofs.get(&txid);
         Ok(proof.ok_or(format_err!("No proof stored"))?.clone())
     }
+
+    async fn subscribe_blocks(&self) -> Option<BlockEvents> {
+        Some(self.block_events.resubscribe())
+    }
 }
 
 fn output_sum(tx: &Transaction) -> u64 {
@@ -189,6 +189,7 @@ pub fn local_config_gen_params(
                         "federation_name".to_string(),
                     )]),
                     modules: server_config_gen.clone(),
+                    misbehavior: Default::default(),
                 },
             };
             Ok((*peer, params))
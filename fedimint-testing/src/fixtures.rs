@@ -24,6 +24,7 @@ use crate::gateway::GatewayTest;
 use crate::ln::mock::FakeLightningTest;
 use crate::ln::real::{ClnLightningTest, LndLightningTest};
 use crate::ln::LightningTest;
+use crate::simulation::SimulatedFederation;
 
 /// A default timeout for things happening in tests
 pub const TIMEOUT: Duration = Duration::from_secs(10);
@@ -126,6 +127,19 @@ impl Fixtures {
         .await
     }
 
+    /// Creates a federation whose consensus is simulated deterministically
+    /// based on `seed`, without HBBFT or networking
+    pub async fn new_simulation(&self, num_peers: u16, seed: u64) -> SimulatedFederation {
+        SimulatedFederation::new(
+            num_peers,
+            seed,
+            self.params.clone(),
+            ServerModuleGenRegistry::from(self.servers.clone()),
+        )
+        .await
+        .expect("Failed to init simulation")
+    }
+
     /// Starts a new gateway with a given lightning node
     pub async fn new_gateway(&self, ln: Arc<dyn LightningTest>) -> GatewayTest {
         // TODO: Make construction easier
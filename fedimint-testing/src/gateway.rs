@@ -8,6 +8,7 @@ use fedimint_core::db::Database;
 use fedimint_core::module::registry::ModuleDecoderRegistry;
 use lightning::routing::gossip::RoutingFees;
 use ln_gateway::client::StandardGatewayClientBuilder;
+use ln_gateway::limits::PaymentLimits;
 use ln_gateway::rpc::rpc_client::GatewayRpcClient;
 use ln_gateway::rpc::rpc_server::run_webserver;
 use ln_gateway::rpc::{ConnectFedPayload, FederationInfo};
@@ -74,6 +75,7 @@ impl GatewayTest {
                 base_msat: 0,
                 proportional_millionths: 0,
             },
+            PaymentLimits::default(),
             gatewayd_db,
             address.clone(),
         )
@@ -4,3 +4,4 @@ pub mod federation;
 pub mod fixtures;
 pub mod gateway;
 pub mod ln;
+pub mod simulation;
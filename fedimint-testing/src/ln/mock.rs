@@ -98,6 +98,7 @@ impl ILnRpcClient for FakeLightningTest {
         Ok(GetNodeInfoResponse {
             pub_key: self.gateway_node_pub_key.serialize().to_vec(),
             alias: "FakeLightningNode".to_string(),
+            network: "regtest".to_string(),
         })
     }
 
@@ -108,6 +109,13 @@ impl ILnRpcClient for FakeLightningTest {
     }
 
     async fn pay(&self, invoice: PayInvoiceRequest) -> ln_gateway::Result<PayInvoiceResponse> {
+        if let Some(keysend) = invoice.keysend {
+            *self.amount_sent.lock().unwrap() += keysend.amount_msat;
+            return Ok(PayInvoiceResponse {
+                preimage: keysend.preimage,
+            });
+        }
+
         let signed = invoice.invoice.parse::<SignedRawInvoice>().unwrap();
         let invoice = Invoice::from_signed(signed).unwrap();
         *self.amount_sent.lock().unwrap() += invoice.amount_milli_satoshis().unwrap();
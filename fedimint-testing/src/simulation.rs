@@ -0,0 +1,274 @@
+//! Deterministic simulation of a federation's consensus
+//!
+//! Runs the consensus processing of all peers in-process without HBBFT or any
+//! sockets. A seeded scheduler decides which peers' proposals make it into an
+//! epoch based on simulated latencies measured by a virtual clock, so runs
+//! with the same seed order contributions the same way.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+use std::time::Duration;
+
+use anyhow::{bail, ensure};
+use fedimint_core::admin_client::PeerScore;
+use fedimint_core::config::{ServerModuleGenParamsRegistry, ServerModuleGenRegistry};
+use fedimint_core::db::mem_impl::MemDatabase;
+use fedimint_core::db::Database;
+use fedimint_core::epoch::{ConsensusItem, SignedEpochOutcome};
+use fedimint_core::task::TaskGroup;
+use fedimint_core::{NumPeers, PeerId};
+use fedimint_server::config::ServerConfig;
+use fedimint_server::consensus::server::ConsensusServer;
+use fedimint_server::consensus::{ApiEvent, HbbftConsensusOutcome};
+use fedimint_server::net::connect::mock::{MockNetwork, StreamReliability};
+use fedimint_server::net::peers::DelayCalculator;
+use futures::{FutureExt, StreamExt};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::federation::local_config_gen_params;
+
+/// Changes the items a byzantine peer contributes to an epoch
+pub type ProposalOverride = Box<dyn FnMut(Vec<ConsensusItem>) -> Vec<ConsensusItem> + Send>;
+
+/// Time of the simulation, only advanced by the scheduler
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct VirtualClock {
+    now: Duration,
+}
+
+impl VirtualClock {
+    pub fn now(&self) -> Duration {
+        self.now
+    }
+
+    pub fn advance(&mut self, duration: Duration) {
+        self.now += duration;
+    }
+}
+
+/// Decides which proposals are included in an epoch
+///
+/// Every contributing peer gets a latency drawn from `latency`. Like HBBFT
+/// the epoch waits for a threshold of proposals, proposals arriving within
+/// `grace` after that are included as well.
+pub struct SimScheduler {
+    rng: StdRng,
+    clock: VirtualClock,
+    latency: Range<Duration>,
+    grace: Duration,
+}
+
+impl SimScheduler {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            clock: VirtualClock::default(),
+            latency: Duration::from_millis(10)..Duration::from_millis(500),
+            grace: Duration::from_millis(100),
+        }
+    }
+
+    /// Returns the peers whose proposals are included in the next epoch,
+    /// fails if less than `threshold` peers contribute since consensus halts
+    pub fn schedule(
+        &mut self,
+        contributing: &BTreeSet<PeerId>,
+        threshold: usize,
+    ) -> anyhow::Result<BTreeSet<PeerId>> {
+        ensure!(
+            contributing.len() >= threshold,
+            "Only {} peers contribute but {threshold} are required, consensus halts",
+            contributing.len()
+        );
+
+        let mut arrivals: Vec<(Duration, PeerId)> = contributing
+            .iter()
+            .map(|peer| (self.rng.gen_range(self.latency.clone()), *peer))
+            .collect();
+        arrivals.sort();
+
+        let deadline = arrivals[threshold - 1].0 + self.grace;
+        let included = arrivals
+            .iter()
+            .filter(|(arrival, _)| *arrival <= deadline)
+            .map(|(_, peer)| *peer)
+            .collect();
+        self.clock.advance(deadline);
+
+        Ok(included)
+    }
+}
+
+/// A federation whose consensus is run by a [`SimScheduler`] instead of HBBFT
+pub struct SimulatedFederation {
+    servers: BTreeMap<PeerId, ConsensusServer>,
+    scheduler: SimScheduler,
+    muted: BTreeSet<PeerId>,
+    overrides: BTreeMap<PeerId, ProposalOverride>,
+    epoch: u64,
+    _task: TaskGroup,
+}
+
+impl SimulatedFederation {
+    pub async fn new(
+        num_peers: u16,
+        seed: u64,
+        params: ServerModuleGenParamsRegistry,
+        server_gen: ServerModuleGenRegistry,
+    ) -> anyhow::Result<Self> {
+        let peers = (0..num_peers).map(PeerId::from).collect::<Vec<_>>();
+        // Ports are never bound, connections only exist on the mock network
+        let params = local_config_gen_params(&peers, 1, params)?;
+        let configs = ServerConfig::trusted_dealer_gen(&params, server_gen.clone());
+        let network = MockNetwork::new();
+
+        let mut task = TaskGroup::new();
+        let mut servers = BTreeMap::new();
+        for (peer_id, config) in configs {
+            let connections = network
+                .connector(peer_id, StreamReliability::FullyReliable)
+                .into_dyn();
+            let decoders = server_gen.decoders(config.consensus.iter_module_instances())?;
+            let db = Database::new(MemDatabase::new(), decoders);
+
+            let server = ConsensusServer::new_with(
+                config,
+                db,
+                server_gen.clone(),
+                connections,
+                DelayCalculator::TEST_DEFAULT,
+                &mut task,
+            )
+            .await?;
+            servers.insert(peer_id, server);
+        }
+
+        Ok(Self {
+            servers,
+            scheduler: SimScheduler::new(seed),
+            muted: BTreeSet::new(),
+            overrides: BTreeMap::new(),
+            epoch: 0,
+            _task: task,
+        })
+    }
+
+    pub fn peers(&self) -> BTreeSet<PeerId> {
+        self.servers.keys().copied().collect()
+    }
+
+    pub fn server(&self, peer: PeerId) -> &ConsensusServer {
+        &self.servers[&peer]
+    }
+
+    pub fn clock(&self) -> VirtualClock {
+        self.scheduler.clock
+    }
+
+    /// Proposals of a muted peer never make it into an epoch, it still
+    /// processes the epochs of the other peers
+    pub fn set_muted(&mut self, peer: PeerId, muted: bool) {
+        if muted {
+            self.muted.insert(peer);
+        } else {
+            self.muted.remove(&peer);
+        }
+    }
+
+    /// Makes `peer` byzantine, its proposals are passed through `f`
+    pub fn override_proposal(
+        &mut self,
+        peer: PeerId,
+        f: impl FnMut(Vec<ConsensusItem>) -> Vec<ConsensusItem> + Send + 'static,
+    ) {
+        self.overrides.insert(peer, Box::new(f));
+    }
+
+    /// Includes `event` in the next proposal of `peer`, as if it was submitted
+    /// through the peer's API
+    pub fn submit_event(&mut self, peer: PeerId, event: ApiEvent) {
+        self.servers
+            .get_mut(&peer)
+            .expect("Peer exists")
+            .consensus
+            .api_event_cache
+            .insert(event);
+    }
+
+    /// Runs a single epoch, failing if consensus halts or the peers disagree
+    /// on its outcome
+    pub async fn run_epoch(&mut self) -> anyhow::Result<SignedEpochOutcome> {
+        let contributing: BTreeSet<PeerId> =
+            self.peers().difference(&self.muted).copied().collect();
+        let included = self
+            .scheduler
+            .schedule(&contributing, self.servers.threshold())?;
+
+        let mut contributions = BTreeMap::new();
+        for peer in included {
+            let server = self.servers.get_mut(&peer).expect("Peer exists");
+            while let Some(Some(event)) = server.api_receiver.next().now_or_never() {
+                if !matches!(event, ApiEvent::ForceProcessOutcome(_)) {
+                    server.consensus.api_event_cache.insert(event);
+                }
+            }
+            let mut items = server.consensus.get_consensus_proposal().await.items;
+            server.consensus.api_event_cache.clear();
+
+            if let Some(f) = self.overrides.get_mut(&peer) {
+                items = f(items);
+            }
+            contributions.insert(peer, items);
+        }
+
+        let outcome = HbbftConsensusOutcome {
+            epoch: self.epoch,
+            contributions,
+        };
+        for server in self.servers.values_mut() {
+            if let Err(e) = server.process_outcome(outcome.clone()).await {
+                bail!("Failed to process epoch {}: {e:?}", outcome.epoch);
+            }
+        }
+
+        let outcomes: BTreeSet<_> = self
+            .servers
+            .values()
+            .map(|server| {
+                server
+                    .last_processed_epoch
+                    .clone()
+                    .expect("Epoch processed")
+                    .hash
+            })
+            .collect();
+        ensure!(
+            outcomes.len() == 1,
+            "Peers disagree on the outcome of epoch {}",
+            self.epoch
+        );
+
+        self.epoch += 1;
+        Ok(self
+            .servers
+            .values()
+            .next()
+            .expect("Has peers")
+            .last_processed_epoch
+            .clone()
+            .expect("Epoch processed"))
+    }
+
+    pub async fn run_epochs(&mut self, epochs: usize) -> anyhow::Result<()> {
+        for _ in 0..epochs {
+            self.run_epoch().await?;
+        }
+        Ok(())
+    }
+
+    /// Misbehavior scores as seen by `peer`
+    pub async fn peer_scores(&self, peer: PeerId) -> BTreeMap<PeerId, PeerScore> {
+        self.servers[&peer].consensus.api.peer_scores().await
+    }
+}
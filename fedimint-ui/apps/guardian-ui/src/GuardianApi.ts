@@ -3,6 +3,7 @@ import {
   ConfigGenParams,
   ConsensusState,
   ConsensusStatus,
+  DkgProgress,
   PeerHashMap,
   ServerStatus,
   StatusResponse,
@@ -23,13 +24,19 @@ export interface ApiInterface {
   setPassword: (password: string) => Promise<void>;
   setConfigGenConnections: (
     ourName: string,
-    leaderUrl?: string
+    leaderUrl?: string,
+    inviteCode?: string
   ) => Promise<void>;
+  createConfigGenInvite: () => Promise<string>;
   getDefaultConfigGenParams: () => Promise<ConfigGenParams>;
   getConsensusConfigGenParams: () => Promise<ConsensusState>;
   setConfigGenParams: (params: ConfigGenParams) => Promise<void>;
   getVerifyConfigHash: () => Promise<PeerHashMap>;
   runDkg: () => Promise<void>;
+  subscribeDkgProgress: (
+    onProgress: (progress: DkgProgress) => void
+  ) => Promise<() => Promise<void>>;
+  restartDkg: (rerunModules: number[]) => Promise<void>;
   startConsensus: () => Promise<void>;
 
   // Running RPC methods (only exist after run_consensus)
@@ -141,16 +148,22 @@ export class GuardianApi implements ApiInterface {
 
   setConfigGenConnections = async (
     ourName: string,
-    leaderUrl?: string
+    leaderUrl?: string,
+    inviteCode?: string
   ): Promise<void> => {
     const connections = {
       our_name: ourName,
       leader_api_url: leaderUrl,
+      invite_code: inviteCode,
     };
 
     return this.rpc('set_config_gen_connections', connections);
   };
 
+  createConfigGenInvite = (): Promise<string> => {
+    return this.rpc('create_config_gen_invite');
+  };
+
   getDefaultConfigGenParams = (): Promise<ConfigGenParams> => {
     return this.rpc('get_default_config_gen_params');
   };
@@ -171,6 +184,38 @@ export class GuardianApi implements ApiInterface {
     return this.rpc('run_dkg');
   };
 
+  // Calls `onProgress` with the current DKG progress and every update, returns
+  // a function ending the subscription
+  subscribeDkgProgress = async (
+    onProgress: (progress: DkgProgress) => void
+  ): Promise<() => Promise<void>> => {
+    const websocket = await this.connect();
+    const subscription = await this.rpc<string | number>(
+      'subscribe_dkg_progress'
+    );
+
+    websocket.on(
+      'dkg_progress',
+      (notification: {
+        subscription: string | number;
+        result: DkgProgress;
+      }) => {
+        if (notification.subscription === subscription) {
+          onProgress(notification.result);
+        }
+      }
+    );
+
+    return async () => {
+      websocket.off('dkg_progress');
+      await websocket.call('unsubscribe_dkg_progress', [subscription]);
+    };
+  };
+
+  restartDkg = (rerunModules: number[]): Promise<void> => {
+    return this.rpc('restart_dkg', rerunModules);
+  };
+
   startConsensus = async (): Promise<void> => {
     const sleep = (time: number) =>
       new Promise((resolve) => setTimeout(resolve, time));
@@ -231,7 +276,7 @@ export class GuardianApi implements ApiInterface {
 
   private rpc = async <T>(
     method: string,
-    params: object | null = null
+    params: object | number | null = null
   ): Promise<T> => {
     try {
       const websocket = await this.connect();
@@ -54,6 +54,20 @@ export interface BitcoinRpc {
 
 export type PeerHashMap = Record<string, string>;
 
+export type DkgStage =
+  | 'Pending'
+  | 'Running'
+  | 'Done'
+  | {
+      Failed: string;
+    };
+
+export interface DkgProgress {
+  version: number;
+  global: DkgStage;
+  modules: Record<number, DkgStage>;
+}
+
 export type LnFedimintModule = [
   'ln',
   {
@@ -11,7 +11,7 @@ async fn client(connect_info: &WsClientConnectInfo) -> Result<fedimint_client::C
     let cfg = client.download_client_config(connect_info).await?;
     let mut builder = fedimint_client::ClientBuilder::default();
     builder.with_module(LightningClientGen);
-    builder.with_module(MintClientGen);
+    builder.with_module(MintClientGen::default());
     builder.with_module(WalletClientGen::default());
     builder.with_primary_module(1);
     builder.with_config(cfg);
@@ -130,7 +130,9 @@ mod tests {
 
         let bolt11 = faucet::generate_invoice(11).await?;
         let (pay_types, _contract_id) = client.pay_bolt11_invoice(bolt11.parse()?).await?;
-        let PayType::Lightning(operation_id) = pay_types else { unreachable!("paying invoice over lightning"); };
+        let PayType::Lightning(operation_id) = pay_types else {
+            unreachable!("paying invoice over lightning");
+        };
 
         let mut updates = client.subscribe_ln_pay(operation_id).await?.into_stream();
 
@@ -150,3 +152,80 @@ mod tests {
         Ok(())
     }
 }
+
+mod indexeddb_tests {
+    use fedimint_core::db::{Database, IDatabase};
+    use fedimint_core::module::registry::ModuleDecoderRegistry;
+    use fedimint_indexeddb::IndexedDb;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+
+    /// Databases of previous runs stay in the browser, so every test gets a
+    /// fresh name
+    fn db_name(test: &str) -> String {
+        format!("fedimint-test-{test}-{}", js_sys::Date::now())
+    }
+
+    async fn open_db(name: &str) -> Result<Database> {
+        Ok(Database::new(
+            IndexedDb::open(name).await?,
+            ModuleDecoderRegistry::default(),
+        ))
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_dbtx_insert_elements() -> Result<()> {
+        fedimint_core::db::verify_insert_elements(open_db(&db_name("insert")).await?).await;
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_dbtx_remove_existing() -> Result<()> {
+        fedimint_core::db::verify_remove_existing(open_db(&db_name("remove")).await?).await;
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_dbtx_find_by_prefix() -> Result<()> {
+        fedimint_core::db::verify_find_by_prefix(open_db(&db_name("prefix")).await?).await;
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_dbtx_rollback_to_savepoint() -> Result<()> {
+        fedimint_core::db::verify_rollback_to_savepoint(open_db(&db_name("savepoint")).await?)
+            .await;
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_dbtx_prevent_nonrepeatable_reads() -> Result<()> {
+        fedimint_core::db::verify_prevent_nonrepeatable_reads(
+            open_db(&db_name("nonrepeatable")).await?,
+        )
+        .await;
+        Ok(())
+    }
+
+    #[wasm_bindgen_test]
+    async fn data_survives_reopening() -> Result<()> {
+        let name = db_name("reopen");
+        let db = IndexedDb::open(&name).await?;
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.raw_insert_bytes(&[1, 2], &[3]).await?;
+        dbtx.raw_insert_bytes(&[4], &[5]).await?;
+        dbtx.commit_tx().await?;
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.raw_remove_entry(&[4]).await?;
+        dbtx.commit_tx().await?;
+        drop(dbtx);
+        drop(db);
+
+        let db = IndexedDb::open(&name).await?;
+        let mut dbtx = db.begin_transaction().await;
+        assert_eq!(dbtx.raw_get_bytes(&[1, 2]).await?, Some(vec![3]));
+        assert_eq!(dbtx.raw_get_bytes(&[4]).await?, None);
+        Ok(())
+    }
+}
@@ -0,0 +1,9 @@
+use fedimintd::fedimintd::Fedimintd;
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    Fedimintd::new()?
+        .with_default_modules()
+        .run_api_gateway()
+        .await
+}
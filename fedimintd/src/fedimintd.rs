@@ -1,30 +1,48 @@
+use std::cell::Cell;
 use std::collections::BTreeMap;
+use std::fs;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::rc::Rc;
 use std::time::Duration;
 
+use anyhow::Context;
 use clap::Parser;
 use fedimint_core::admin_client::ConfigGenParamsRequest;
 use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
 use fedimint_core::config::{ServerModuleGenParamsRegistry, ServerModuleGenRegistry};
 use fedimint_core::db::Database;
-use fedimint_core::module::ServerModuleGen;
-use fedimint_core::task::{sleep, TaskGroup};
+use fedimint_core::module::{ApiAuth, ServerModuleGen};
+use fedimint_core::task::{timeout, TaskGroup};
 use fedimint_core::util::write_overwrite;
-use fedimint_core::{timing, Amount};
+use fedimint_core::{timing, Amount, PeerId};
+#[cfg(feature = "ln")]
 use fedimint_ln_server::LightningGen;
 use fedimint_logging::TracingSetup;
+#[cfg(feature = "mint")]
+use fedimint_mint_server::common::rebate::FeeRebateConsensus;
+#[cfg(feature = "mint")]
 use fedimint_mint_server::MintGen;
 use fedimint_server::config::api::ConfigGenSettings;
-use fedimint_server::config::io::{CODE_VERSION, DB_FILE, PLAINTEXT_PASSWORD};
-use fedimint_server::FedimintServer;
+use fedimint_server::config::io::{
+    read_consensus_config, CODE_VERSION, DB_FILE, PLAINTEXT_PASSWORD,
+};
+use fedimint_server::encrypted_db::{database_encryption_key, EncryptedDatabase};
+use fedimint_server::net::gateway::{run_api_gateway, ApiGatewaySettings};
+use fedimint_server::{sd_notify, FedimintServer};
+#[cfg(feature = "wallet")]
 use fedimint_wallet_server::WalletGen;
-use futures::FutureExt;
 use tokio::select;
 use tracing::{debug, error, info, warn};
 use url::Url;
 
-use crate::attach_default_module_gen_params;
+#[cfg(feature = "ln")]
+use crate::attach_ln_gen_params;
+use crate::modules::select_configured_modules;
+#[cfg(feature = "mint")]
+use crate::{attach_mint_gen_params, default_mint_amounts};
+#[cfg(feature = "wallet")]
+use crate::{attach_second_wallet_gen_params, attach_wallet_gen_params};
 
 /// Time we will wait before forcefully shutting down tasks
 const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
@@ -58,19 +76,80 @@ pub struct ServerOpts {
     /// Our API address for clients to connect to us
     #[arg(long, env = "FM_API_URL", default_value = "ws://127.0.0.1:8174")]
     api_url: Url,
+    /// Address we bind to for additionally exposing the API over gRPC
+    #[arg(long, env = "FM_BIND_GRPC")]
+    bind_grpc: Option<SocketAddr>,
+    /// Nostr relays to announce the federation on, comma separated
+    #[arg(long, env = "FM_NOSTR_RELAYS", value_delimiter = ',')]
+    nostr_relays: Vec<Url>,
     /// Max denomination of notes issued by the federation (in millisats)
     /// default = 10 BTC
     #[arg(long, env = "FM_MAX_DENOMINATION", default_value = "1000000000000")]
     max_denomination: Amount,
+    /// Note denominations issued by the federation (in millisats, comma
+    /// separated), must include 1. Overrides `max_denomination`, by default
+    /// all powers of 2 up to it are issued.
+    #[arg(long, env = "FM_DENOMINATIONS", value_delimiter = ',')]
+    denominations: Vec<Amount>,
     /// The bitcoin network that fedimint will be running on
     #[arg(long, env = "FM_BITCOIN_NETWORK", default_value = "regtest")]
     network: bitcoin::network::constants::Network,
     /// The bitcoin network that fedimint will be running on
     #[arg(long, env = "FM_FINALITY_DELAY", default_value = "10")]
     finality_delay: u32,
+    /// Enforce daily spending limits on accounts registered by the guardians,
+    /// only needed by federations that have to comply with such rules
+    #[arg(long, env = "FM_VELOCITY_LIMITS", default_value = "false")]
+    velocity_limits: bool,
+    /// Return this share of the collected mint fees to the accounts that paid
+    /// them, in basis points. Fee rebates are disabled if not set.
+    #[arg(long, env = "FM_FEE_REBATE_SHARE_BPS")]
+    fee_rebate_share_bps: Option<u16>,
+    /// Length of a fee rebate period in blocks
+    #[arg(long, env = "FM_FEE_REBATE_PERIOD_BLOCKS", default_value = "1008")]
+    fee_rebate_period_blocks: u32,
+    /// Bitcoin network of a second wallet module, e.g. signet next to a
+    /// mainnet federation. Not run if not set.
+    #[arg(long, env = "FM_SECOND_WALLET_NETWORK")]
+    second_wallet_network: Option<bitcoin::network::constants::Network>,
+    /// Kind of the bitcoin RPC of the second wallet module
+    #[arg(long, env = "FM_SECOND_WALLET_BITCOIN_RPC_KIND")]
+    second_wallet_bitcoin_rpc_kind: Option<String>,
+    /// URL of the bitcoin RPC of the second wallet module
+    #[arg(long, env = "FM_SECOND_WALLET_BITCOIN_RPC_URL")]
+    second_wallet_bitcoin_rpc_url: Option<Url>,
 
     #[arg(long, env = "FM_BIND_METRICS_API")]
     bind_metrics_api: Option<SocketAddr>,
+
+    /// Encrypt database values at rest with a random key that is protected by
+    /// the password. Has to be enabled on the first start, an existing
+    /// unencrypted database can't be read with it.
+    #[arg(long, env = "FM_ENCRYPT_DATABASE", default_value = "false")]
+    encrypt_database: bool,
+}
+
+/// Options of `fedimint-api-gateway`, see [`Fedimintd::run_api_gateway`]
+#[derive(Parser)]
+pub struct ApiGatewayOpts {
+    /// Address we bind to for exposing the API
+    #[arg(long, env = "FM_BIND_API", default_value = "127.0.0.1:8184")]
+    bind_api: SocketAddr,
+    /// API of the guardian's consensus node, only needs to be reachable by the
+    /// gateways
+    #[arg(
+        long,
+        env = "FM_CONSENSUS_API_URL",
+        default_value = "ws://127.0.0.1:8174"
+    )]
+    consensus_api_url: Url,
+    /// Peer id of the guardian
+    #[arg(long, env = "FM_OUR_ID")]
+    our_id: u16,
+    /// Admin password of the guardian, authenticates the gateway to the
+    /// consensus node
+    #[arg(long, env = "FM_PASSWORD")]
+    password: String,
 }
 
 /// `fedimintd` builder
@@ -81,6 +160,11 @@ pub struct ServerOpts {
 /// To make this easier, [`Fedimintd`] builder is exposed, allowing
 /// building `fedimintd` with custom set of modules.
 ///
+/// The default modules are compiled in with the `ln`, `mint` and `wallet`
+/// features, which are all enabled by default. An existing federation can
+/// only be run if all modules of its config are attached, see
+/// [`crate::modules`].
+///
 ///
 /// Example:
 ///
@@ -134,10 +218,34 @@ impl Fedimintd {
         self
     }
 
-    pub fn with_default_modules(self) -> Self {
-        self.with_module(LightningGen)
-            .with_module(MintGen)
-            .with_module(WalletGen)
+    /// Attaches the default modules the binary was built with
+    pub fn with_default_modules(mut self) -> Self {
+        #[cfg(feature = "ln")]
+        self.server_gens.attach(LightningGen);
+        #[cfg(feature = "mint")]
+        self.server_gens.attach(MintGen);
+        #[cfg(feature = "wallet")]
+        self.server_gens.attach(WalletGen);
+        self
+    }
+
+    /// Runs a stateless API gateway in front of a guardian's consensus node
+    /// instead of the guardian itself, see [`fedimint_server::net::gateway`]
+    pub async fn run_api_gateway(self) -> anyhow::Result<()> {
+        let opts = ApiGatewayOpts::parse();
+        TracingSetup::default().init()?;
+
+        let mut task_group = TaskGroup::new();
+        task_group.install_kill_handler();
+
+        let settings = ApiGatewaySettings {
+            api_bind: opts.bind_api,
+            max_connections: fedimint_server::config::max_connections(),
+            consensus_api_url: opts.consensus_api_url,
+            our_id: PeerId::from(opts.our_id),
+            auth: ApiAuth(opts.password),
+        };
+        run_api_gateway(settings, &self.server_gens, task_group).await
     }
 
     pub async fn run(self) -> ! {
@@ -157,7 +265,9 @@ impl Fedimintd {
         let local_task_set = tokio::task::LocalSet::new();
         let _guard = local_task_set.enter();
 
+        let main_failed = Rc::new(Cell::new(false));
         let task_group = root_task_group.clone();
+        let main_failed_task = main_failed.clone();
         root_task_group
             .spawn_local("main", move |_task_handle| async move {
                 match run(
@@ -171,35 +281,44 @@ impl Fedimintd {
                     Ok(()) => {}
                     Err(e) => {
                         error!(?e, "Main task returned error, shutting down");
+                        main_failed_task.set(true);
                         task_group.shutdown().await;
                     }
                 }
             })
             .await;
 
-        let shutdown_future =
-            root_task_group
-                .make_handle()
-                .make_shutdown_rx()
-                .await
-                .then(|_| async {
-                    let shutdown_seconds = SHUTDOWN_TIMEOUT.as_secs();
-                    info!("Shutdown called, waiting {shutdown_seconds}s for main task to finish");
-                    sleep(SHUTDOWN_TIMEOUT).await;
-                });
-
-        select! {
-            _ = shutdown_future => {
-                debug!("Terminating main task");
+        let mut shutdown_rx = root_task_group.make_handle().make_shutdown_rx().await;
+        tokio::pin!(local_task_set);
+
+        // Consensus stops before proposing to the next epoch and commits the one it
+        // is processing, so we wait for it instead of killing it mid-epoch
+        let main_finished = select! {
+            _ = &mut shutdown_rx => {
+                sd_notify::stopping();
+                let shutdown_seconds = SHUTDOWN_TIMEOUT.as_secs();
+                info!("Shutdown called, waiting up to {shutdown_seconds}s for main task to finish");
+                match timeout(SHUTDOWN_TIMEOUT, local_task_set.as_mut()).await {
+                    Ok(()) => true,
+                    Err(_) => {
+                        warn!("Main task did not finish in time, terminating it");
+                        false
+                    }
+                }
             }
-            _ = local_task_set => {
-                warn!("local_task_set finished before shutdown was called");
+            _ = local_task_set.as_mut() => {
+                debug!("Main task finished");
+                true
             }
-        }
+        };
 
-        if let Err(err) = root_task_group.join_all(Some(SHUTDOWN_TIMEOUT)).await {
-            error!(?err, "Error while shutting down task group");
-        }
+        let tasks_finished = match root_task_group.join_all(Some(SHUTDOWN_TIMEOUT)).await {
+            Ok(()) => true,
+            Err(err) => {
+                error!(?err, "Error while shutting down task group");
+                false
+            }
+        };
 
         info!("Shutdown complete");
 
@@ -208,8 +327,9 @@ impl Fedimintd {
 
         drop(timing_total_runtime);
 
-        // Should we ever shut down without an error code?
-        std::process::exit(-1);
+        // A clean exit lets service managers tell a requested stop from a crash
+        let clean = main_finished && tasks_finished && !main_failed.get();
+        std::process::exit(if clean { 0 } else { 1 });
     }
 }
 
@@ -219,22 +339,41 @@ async fn run(
     module_gens: ServerModuleGenRegistry,
     mut module_gens_params: ServerModuleGenParamsRegistry,
 ) -> anyhow::Result<()> {
-    attach_default_module_gen_params(
-        BitcoinRpcConfig::from_env_vars()?,
-        &mut module_gens_params,
-        opts.max_denomination,
-        opts.network,
-        opts.finality_delay,
-    );
+    #[cfg(feature = "wallet")]
+    attach_wallet_params(&opts, &module_gens, &mut module_gens_params)?;
+    #[cfg(feature = "mint")]
+    attach_mint_params(&opts, &module_gens, &mut module_gens_params);
+    #[cfg(feature = "ln")]
+    attach_ln_params(&module_gens, &mut module_gens_params)?;
+    // Last, so its instance id is allocated after the ids of all other modules
+    #[cfg(feature = "wallet")]
+    attach_second_wallet_params(&opts, &module_gens, &mut module_gens_params)?;
 
-    let module_kinds = module_gens_params
-        .iter_modules()
-        .map(|(id, kind, _)| (id, kind));
-    let decoders = module_gens.decoders(module_kinds.into_iter())?;
-    let db = Database::new(
-        fedimint_rocksdb::RocksDb::open(opts.data_dir.join(DB_FILE))?,
-        decoders.clone(),
-    );
+    // A new federation is set up with all modules params were attached for, an
+    // existing one runs the modules of its config
+    let module_kinds = match read_consensus_config(&opts.data_dir)? {
+        Some(cfg) => select_configured_modules(&module_gens, &cfg)?,
+        None => module_gens_params
+            .iter_modules()
+            .map(|(id, kind, _)| (id, kind.clone()))
+            .collect(),
+    };
+    let decoders = module_gens.decoders(module_kinds.iter().map(|(id, kind)| (*id, kind)))?;
+    let rocksdb = fedimint_rocksdb::RocksDb::open(opts.data_dir.join(DB_FILE))?;
+    let db = if opts.encrypt_database {
+        let password = match &opts.password {
+            Some(password) => password.clone(),
+            None => fs::read_to_string(opts.data_dir.join(PLAINTEXT_PASSWORD))
+                .context("Database encryption requires a password")?,
+        };
+        let key = database_encryption_key(&password, &opts.data_dir)?;
+        let encrypted = EncryptedDatabase::open(rocksdb, key)
+            .await
+            .context("Unable to open the encrypted database, wrong password?")?;
+        Database::new(encrypted, decoders.clone())
+    } else {
+        Database::new(rocksdb, decoders.clone())
+    };
 
     // TODO: Fedimintd should use the config gen API
     // on each run we want to pass the currently passed passsword, so we need to
@@ -259,6 +398,8 @@ async fn run(
             registry: module_gens,
         },
         db,
+        grpc_bind: opts.bind_grpc,
+        nostr_relays: opts.nostr_relays,
     };
     if let Some(bind_metrics_api) = opts.bind_metrics_api.as_ref() {
         let (api_result, metrics_api_result) = futures::join!(
@@ -273,6 +414,102 @@ async fn run(
     Ok(())
 }
 
+/// Attaches the config gen params of the wallet module, unless it isn't
+/// attached
+#[cfg(feature = "wallet")]
+fn attach_wallet_params(
+    opts: &ServerOpts,
+    module_gens: &ServerModuleGenRegistry,
+    module_gens_params: &mut ServerModuleGenParamsRegistry,
+) -> anyhow::Result<()> {
+    if module_gens.get(&WalletGen::kind()).is_none() {
+        return Ok(());
+    }
+
+    attach_wallet_gen_params(
+        BitcoinRpcConfig::from_env_vars()?,
+        BitcoinRpcConfig::secondary_from_env_vars()?,
+        module_gens_params,
+        opts.network,
+        opts.finality_delay,
+    );
+    Ok(())
+}
+
+/// Attaches the config gen params of the second wallet module if configured,
+/// unless the wallet module isn't attached
+#[cfg(feature = "wallet")]
+fn attach_second_wallet_params(
+    opts: &ServerOpts,
+    module_gens: &ServerModuleGenRegistry,
+    module_gens_params: &mut ServerModuleGenParamsRegistry,
+) -> anyhow::Result<()> {
+    if module_gens.get(&WalletGen::kind()).is_none() {
+        return Ok(());
+    }
+
+    if let Some(network) = opts.second_wallet_network {
+        let bitcoin_rpc = BitcoinRpcConfig {
+            kind: opts
+                .second_wallet_bitcoin_rpc_kind
+                .clone()
+                .context("The second wallet module requires a bitcoin RPC kind")?,
+            url: opts
+                .second_wallet_bitcoin_rpc_url
+                .clone()
+                .context("The second wallet module requires a bitcoin RPC URL")?,
+        };
+        let instance_id = attach_second_wallet_gen_params(
+            bitcoin_rpc,
+            module_gens_params,
+            network,
+            opts.finality_delay,
+        );
+        info!("Attaching a second wallet module for {network} as instance {instance_id}");
+    }
+    Ok(())
+}
+
+/// Attaches the config gen params of the mint module, unless it isn't attached
+#[cfg(feature = "mint")]
+fn attach_mint_params(
+    opts: &ServerOpts,
+    module_gens: &ServerModuleGenRegistry,
+    module_gens_params: &mut ServerModuleGenParamsRegistry,
+) {
+    if module_gens.get(&MintGen::kind()).is_none() {
+        return;
+    }
+
+    attach_mint_gen_params(
+        module_gens_params,
+        if opts.denominations.is_empty() {
+            default_mint_amounts(opts.max_denomination)
+        } else {
+            opts.denominations.clone()
+        },
+        opts.velocity_limits,
+        opts.fee_rebate_share_bps
+            .map(|share_bps| FeeRebateConsensus {
+                share_bps,
+                period_blocks: opts.fee_rebate_period_blocks,
+            }),
+    );
+}
+
+/// Attaches the config gen params of the lightning module, unless it isn't
+/// attached
+#[cfg(feature = "ln")]
+fn attach_ln_params(
+    module_gens: &ServerModuleGenRegistry,
+    module_gens_params: &mut ServerModuleGenParamsRegistry,
+) -> anyhow::Result<()> {
+    if module_gens.get(&LightningGen::kind()).is_some() {
+        attach_ln_gen_params(BitcoinRpcConfig::from_env_vars()?, module_gens_params);
+    }
+    Ok(())
+}
+
 async fn spawn_metrics_server(
     bind_address: &SocketAddr,
     mut task_group: TaskGroup,
@@ -1,64 +1,161 @@
+#[cfg(feature = "wallet")]
 use bitcoin::Network;
+#[cfg(any(feature = "ln", feature = "wallet"))]
 use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
+#[cfg(any(feature = "ln", feature = "mint", feature = "wallet"))]
 use fedimint_core::config::ServerModuleGenParamsRegistry;
-use fedimint_core::core::{
-    LEGACY_HARDCODED_INSTANCE_ID_LN, LEGACY_HARDCODED_INSTANCE_ID_MINT,
-    LEGACY_HARDCODED_INSTANCE_ID_WALLET,
-};
+#[cfg(feature = "wallet")]
+use fedimint_core::core::ModuleInstanceId;
+#[cfg(feature = "ln")]
+use fedimint_core::core::LEGACY_HARDCODED_INSTANCE_ID_LN;
+#[cfg(feature = "mint")]
+use fedimint_core::core::LEGACY_HARDCODED_INSTANCE_ID_MINT;
+#[cfg(feature = "wallet")]
+use fedimint_core::core::LEGACY_HARDCODED_INSTANCE_ID_WALLET;
+#[cfg(any(feature = "ln", feature = "mint", feature = "wallet"))]
 use fedimint_core::module::ServerModuleGen;
 use fedimint_core::{Amount, Tiered};
+#[cfg(feature = "ln")]
 use fedimint_ln_server::common::config::LightningGenParams;
+#[cfg(feature = "ln")]
 use fedimint_ln_server::LightningGen;
+#[cfg(feature = "mint")]
 use fedimint_mint_server::common::config::{MintGenParams, MintGenParamsConsensus};
+#[cfg(feature = "mint")]
+use fedimint_mint_server::common::rebate::FeeRebateConsensus;
+#[cfg(feature = "mint")]
 use fedimint_mint_server::MintGen;
+#[cfg(feature = "wallet")]
 use fedimint_wallet_server::common::config::{
     WalletGenParams, WalletGenParamsConsensus, WalletGenParamsLocal,
 };
+#[cfg(feature = "wallet")]
 use fedimint_wallet_server::WalletGen;
 
 /// Module for creating `fedimintd` binary with custom modules
 pub mod fedimintd;
+/// Selecting the modules to run from the config and the compiled in modules
+pub mod modules;
+
+/// Note denominations as powers of 2 up to and including `max_denomination`
+pub fn default_mint_amounts(max_denomination: Amount) -> Vec<Amount> {
+    Tiered::gen_denominations(max_denomination)
+        .tiers()
+        .cloned()
+        .collect()
+}
 
 /// Generates the configuration for the modules configured in the server binary
+#[cfg(all(feature = "ln", feature = "mint", feature = "wallet"))]
 pub fn attach_default_module_gen_params(
     bitcoin_rpc: BitcoinRpcConfig,
+    secondary_bitcoin_rpc: Option<BitcoinRpcConfig>,
+    module_gen_params: &mut ServerModuleGenParamsRegistry,
+    mint_amounts: Vec<Amount>,
+    network: Network,
+    finality_delay: u32,
+    velocity_limits: bool,
+    fee_rebate: Option<FeeRebateConsensus>,
+) {
+    attach_wallet_gen_params(
+        bitcoin_rpc.clone(),
+        secondary_bitcoin_rpc,
+        module_gen_params,
+        network,
+        finality_delay,
+    );
+    attach_mint_gen_params(module_gen_params, mint_amounts, velocity_limits, fee_rebate);
+    attach_ln_gen_params(bitcoin_rpc, module_gen_params);
+}
+
+/// Generates the configuration of the wallet module
+#[cfg(feature = "wallet")]
+pub fn attach_wallet_gen_params(
+    bitcoin_rpc: BitcoinRpcConfig,
+    secondary_bitcoin_rpc: Option<BitcoinRpcConfig>,
     module_gen_params: &mut ServerModuleGenParamsRegistry,
-    max_denomination: Amount,
     network: Network,
     finality_delay: u32,
 ) {
-    module_gen_params
-        .attach_config_gen_params(
-            LEGACY_HARDCODED_INSTANCE_ID_WALLET,
-            WalletGen::kind(),
-            WalletGenParams {
-                local: WalletGenParamsLocal {
-                    bitcoin_rpc: bitcoin_rpc.clone(),
-                },
-                consensus: WalletGenParamsConsensus {
-                    network,
-                    // TODO this is not very elegant, but I'm planning to get rid of it in a next
-                    // commit anyway
-                    finality_delay,
-                },
+    module_gen_params.attach_config_gen_params(
+        LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+        WalletGen::kind(),
+        WalletGenParams {
+            local: WalletGenParamsLocal {
+                bitcoin_rpc,
+                secondary_bitcoin_rpc,
+            },
+            consensus: WalletGenParamsConsensus {
+                network,
+                // TODO this is not very elegant, but I'm planning to get rid of it in a next
+                // commit anyway
+                finality_delay,
+            },
+        },
+    );
+}
+
+/// Generates the configuration of the mint module
+#[cfg(feature = "mint")]
+pub fn attach_mint_gen_params(
+    module_gen_params: &mut ServerModuleGenParamsRegistry,
+    mint_amounts: Vec<Amount>,
+    velocity_limits: bool,
+    fee_rebate: Option<FeeRebateConsensus>,
+) {
+    module_gen_params.attach_config_gen_params(
+        LEGACY_HARDCODED_INSTANCE_ID_MINT,
+        MintGen::kind(),
+        MintGenParams {
+            local: Default::default(),
+            consensus: MintGenParamsConsensus {
+                mint_amounts,
+                velocity_limits,
+                fee_rebate,
+            },
+        },
+    );
+}
+
+/// Generates the configuration of the lightning module
+#[cfg(feature = "ln")]
+pub fn attach_ln_gen_params(
+    bitcoin_rpc: BitcoinRpcConfig,
+    module_gen_params: &mut ServerModuleGenParamsRegistry,
+) {
+    module_gen_params.attach_config_gen_params(
+        LEGACY_HARDCODED_INSTANCE_ID_LN,
+        LightningGen::kind(),
+        LightningGenParams::regtest(bitcoin_rpc),
+    );
+}
+
+/// Generates the configuration of a second wallet module with its own bitcoin
+/// backend, e.g. to run a signet wallet for testing next to the mainnet one.
+///
+/// The module gets the next instance id that isn't taken by the modules
+/// already in `module_gen_params`, which is returned.
+#[cfg(feature = "wallet")]
+pub fn attach_second_wallet_gen_params(
+    bitcoin_rpc: BitcoinRpcConfig,
+    module_gen_params: &mut ServerModuleGenParamsRegistry,
+    network: Network,
+    finality_delay: u32,
+) -> ModuleInstanceId {
+    let instance_id = module_gen_params.next_instance_id();
+    module_gen_params.attach_config_gen_params(
+        instance_id,
+        WalletGen::kind(),
+        WalletGenParams {
+            local: WalletGenParamsLocal {
+                bitcoin_rpc,
+                secondary_bitcoin_rpc: None,
             },
-        )
-        .attach_config_gen_params(
-            LEGACY_HARDCODED_INSTANCE_ID_MINT,
-            MintGen::kind(),
-            MintGenParams {
-                local: Default::default(),
-                consensus: MintGenParamsConsensus {
-                    mint_amounts: Tiered::gen_denominations(max_denomination)
-                        .tiers()
-                        .cloned()
-                        .collect(),
-                },
+            consensus: WalletGenParamsConsensus {
+                network,
+                finality_delay,
             },
-        )
-        .attach_config_gen_params(
-            LEGACY_HARDCODED_INSTANCE_ID_LN,
-            LightningGen::kind(),
-            LightningGenParams::regtest(bitcoin_rpc),
-        );
+        },
+    );
+    instance_id
 }
@@ -0,0 +1,91 @@
+//! Selecting the server modules `fedimintd` runs
+//!
+//! The modules a `fedimintd` binary can run are fixed at compile time: the
+//! default modules enabled by its `ln`, `mint` and `wallet` features plus
+//! the ones a custom binary attaches to the
+//! [`Fedimintd`](crate::fedimintd::Fedimintd) builder. A new federation is
+//! set up with all of them. Once the federation's config exists, the modules
+//! listed in it are instantiated instead, so the binary has to be able to
+//! run each of them in its configured consensus version.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use fedimint_core::config::ServerModuleGenRegistry;
+use fedimint_core::core::{ModuleInstanceId, ModuleKind};
+use fedimint_core::module::{IServerModuleGen, ModuleConsensusVersion};
+use fedimint_server::config::ServerConfigConsensus;
+use itertools::Itertools;
+use thiserror::Error;
+
+/// A module of the federation's config the binary can't run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MissingModule {
+    pub instance_id: ModuleInstanceId,
+    pub kind: ModuleKind,
+    pub version: ModuleConsensusVersion,
+    /// Consensus versions the compiled in module of the same kind supports,
+    /// `None` if no module of the kind is compiled in
+    pub supported_versions: Option<Vec<ModuleConsensusVersion>>,
+}
+
+impl fmt::Display for MissingModule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "instance {} of kind {} in consensus version {}",
+            self.instance_id, self.kind, self.version.0
+        )?;
+        match &self.supported_versions {
+            None => write!(f, ", not compiled in"),
+            Some(supported) => write!(
+                f,
+                ", only versions {} are supported",
+                supported.iter().map(|version| version.0).join(", ")
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error(
+    "This fedimintd can't run all modules of the federation config:\n{}",
+    .0.iter().map(|module| format!("  {module}")).join("\n")
+)]
+pub struct MissingModulesError(pub Vec<MissingModule>);
+
+/// Selects the modules of the federation's config to instantiate, fails with
+/// all modules the binary can't run
+pub fn select_configured_modules(
+    module_gens: &ServerModuleGenRegistry,
+    cfg: &ServerConfigConsensus,
+) -> Result<BTreeMap<ModuleInstanceId, ModuleKind>, MissingModulesError> {
+    let mut missing = vec![];
+    for (&instance_id, module) in &cfg.modules {
+        let supported_versions = match module_gens.get(&module.kind) {
+            Some(gen) => {
+                let supported = gen.versions(cfg.version);
+                if supported.contains(&module.version) {
+                    continue;
+                }
+                Some(supported.to_vec())
+            }
+            None => None,
+        };
+        missing.push(MissingModule {
+            instance_id,
+            kind: module.kind.clone(),
+            version: module.version,
+            supported_versions,
+        });
+    }
+
+    if !missing.is_empty() {
+        return Err(MissingModulesError(missing));
+    }
+    Ok(cfg
+        .modules
+        .iter()
+        .map(|(&instance_id, module)| (instance_id, module.kind.clone()))
+        .collect())
+}
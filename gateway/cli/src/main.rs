@@ -1,10 +1,20 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
+use anyhow::Context;
 use bitcoin::{Address, Amount};
 use clap::{CommandFactory, Parser, Subcommand};
 use fedimint_core::config::FederationId;
+use fedimint_ln_common::config::GatewayFee;
 use fedimint_logging::TracingSetup;
+use ln_gateway::recovery::RecoveryBundle;
 use ln_gateway::rpc::rpc_client::GatewayRpcClient;
 use ln_gateway::rpc::{
-    BackupPayload, BalancePayload, ConnectFedPayload, DepositAddressPayload, RestorePayload,
+    BackupPayload, BalancePayload, ConnectFedPayload, DepositAddressPayload, DisconnectFedPayload,
+    DrainPayload, ExportRecoveryPayload, ImportRecoveryPayload, ReloadPayload, RestorePayload,
     WithdrawPayload,
 };
 use serde::Serialize;
@@ -54,6 +64,15 @@ pub enum Commands {
     ConnectFed {
         /// ConnectInfo code to connect to the federation
         connect: String,
+        /// Only show the details of the federation and check the gateway can
+        /// serve it, without connecting
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Disconnect from a federation the gateway holds no funds in
+    DisconnectFed {
+        #[clap(long)]
+        federation_id: FederationId,
     },
     /// Make a backup of snapshot of all ecash
     Backup {
@@ -65,6 +84,39 @@ pub enum Commands {
         #[clap(long)]
         federation_id: FederationId,
     },
+    /// Write the secrets of the gateway's ecash and the channel backup of its
+    /// lightning node to a new file, from which the funds can be recovered
+    /// after losing the gateway's disk
+    ExportRecovery {
+        /// File to create, has to be stored as securely as the node's seed
+        #[clap(long)]
+        out: PathBuf,
+    },
+    /// Recover the funds of a lost gateway from its exported recovery file
+    ///
+    /// The gateway has to be started with an empty data directory and
+    /// connected to the lightning node recreated from its seed.
+    ImportRecovery {
+        /// File written by `export-recovery`
+        file: PathBuf,
+    },
+    /// Change the configuration of the running gateway
+    Reload {
+        /// Fees charged for payments routed into the federations, as base fee
+        /// in msat and millionths of the amount, e.g. `1000,100`
+        #[clap(long)]
+        fees: Option<GatewayFee>,
+        /// Address of the CLN extension, after it was reloaded to listen on a
+        /// different address
+        #[clap(long)]
+        cln_extension_addr: Option<Url>,
+    },
+    /// Stop accepting payments, wait for the ones in flight and shut down
+    Drain {
+        /// Seconds to wait for payments in flight before shutting down anyway
+        #[clap(long)]
+        timeout: Option<u64>,
+    },
     Completion {
         shell: clap_complete::Shell,
     },
@@ -115,12 +167,25 @@ async fn main() -> anyhow::Result<()> {
 
             print_response(response).await;
         }
-        Commands::ConnectFed { connect } => {
-            let response = client()
-                .connect_federation(ConnectFedPayload { connect })
-                .await?;
+        Commands::ConnectFed { connect, dry_run } => {
+            if dry_run {
+                let response = client()
+                    .preview_federation(ConnectFedPayload { connect })
+                    .await?;
 
-            print_response(response).await;
+                print_response(response).await;
+            } else {
+                let response = client()
+                    .connect_federation(ConnectFedPayload { connect })
+                    .await?;
+
+                print_response(response).await;
+            }
+        }
+        Commands::DisconnectFed { federation_id } => {
+            client()
+                .disconnect_federation(DisconnectFedPayload { federation_id })
+                .await?;
         }
         Commands::Backup { federation_id } => {
             client().backup(BackupPayload { federation_id }).await?;
@@ -128,6 +193,62 @@ async fn main() -> anyhow::Result<()> {
         Commands::Restore { federation_id } => {
             client().restore(RestorePayload { federation_id }).await?;
         }
+        Commands::ExportRecovery { out } => {
+            let bundle = client().export_recovery(ExportRecoveryPayload).await?;
+
+            let mut options = OpenOptions::new();
+            options.write(true).create_new(true);
+            #[cfg(unix)]
+            options.mode(0o600);
+            let mut file = options
+                .open(&out)
+                .with_context(|| format!("Unable to create {}", out.display()))?;
+            writeln!(file, "{bundle}")?;
+
+            print_response(serde_json::json!({
+                "federations": bundle.federations.iter().map(|federation| {
+                    serde_json::json!({
+                        "federation_id": federation.config.config.federation_id,
+                        "balance": federation.balance,
+                    })
+                }).collect::<Vec<_>>(),
+                "channel_backup": bundle.channel_backup.is_some(),
+            }))
+            .await;
+        }
+        Commands::ImportRecovery { file } => {
+            let bundle: RecoveryBundle = std::fs::read_to_string(&file)
+                .with_context(|| format!("Unable to read {}", file.display()))?
+                .parse()?;
+            let response = client()
+                .import_recovery(ImportRecoveryPayload { bundle })
+                .await?;
+
+            print_response(response).await;
+        }
+        Commands::Reload {
+            fees,
+            cln_extension_addr,
+        } => {
+            let response = client()
+                .reload(ReloadPayload {
+                    base_msat: fees.as_ref().map(|fees| fees.0.base_msat),
+                    proportional_millionths: fees.map(|fees| fees.0.proportional_millionths),
+                    cln_extension_addr,
+                })
+                .await?;
+
+            print_response(response).await;
+        }
+        Commands::Drain { timeout } => {
+            let response = client()
+                .drain(DrainPayload {
+                    timeout_secs: timeout,
+                })
+                .await?;
+
+            print_response(response).await;
+        }
         Commands::Completion { shell } => {
             clap_complete::generate(
                 shell,
@@ -52,27 +52,48 @@ async fn main() -> Result<(), anyhow::Error> {
         }
     }
 
-    let (service, listen, plugin) = ClnRpcService::new()
+    let (service, mut listen, plugin, mut reloads) = ClnRpcService::new()
         .await
         .expect("Failed to create cln rpc service");
+    let service = Arc::new(service);
 
-    debug!(
-        "Starting gateway-cln-extension with listen address : {}",
-        listen
-    );
-
-    Server::builder()
-        .add_service(GatewayLightningServer::new(service))
-        .serve_with_shutdown(listen, async {
-            // Wait for plugin to signal it's shutting down
-            // Shut down everything else via TaskGroup regardless of error
-            let _ = plugin.join().await;
-            // lightningd needs to see exit code 0 to notice the plugin has
-            // terminated -- even if we return from main().
-            std::process::exit(0);
-        })
-        .await
-        .map_err(|e| ClnExtensionError::Error(anyhow!("Failed to start server, {:?}", e)))?;
+    // The server is restarted on a new address whenever the plugin gets
+    // reloaded, HTLCs intercepted in the meantime are handed to gatewayd once
+    // it reconnects
+    loop {
+        debug!(
+            "Starting gateway-cln-extension with listen address : {}",
+            listen
+        );
+
+        let mut reloaded_listen = None;
+        Server::builder()
+            .add_service(GatewayLightningServer::from_arc(service.clone()))
+            .serve_with_shutdown(listen, async {
+                tokio::select! {
+                    _ = plugin.join() => {
+                        // Wait for plugin to signal it's shutting down
+                        // Shut down everything else via TaskGroup regardless of error
+                        // lightningd needs to see exit code 0 to notice the plugin has
+                        // terminated -- even if we return from main().
+                        std::process::exit(0);
+                    }
+                    Some(new_listen) = reloads.recv() => {
+                        // Ends the HTLC stream, the server waits for open streams before
+                        // shutting down
+                        service.interceptor.sender.lock().await.take();
+                        reloaded_listen = Some(new_listen);
+                    }
+                }
+            })
+            .await
+            .map_err(|e| ClnExtensionError::Error(anyhow!("Failed to start server, {:?}", e)))?;
+
+        match reloaded_listen {
+            Some(new_listen) => listen = new_listen,
+            None => break,
+        }
+    }
 
     Ok(())
 }
@@ -128,10 +149,49 @@ pub struct ClnRpcService {
     task_group: TaskGroup,
 }
 
+/// State shared with the callbacks of the CLN plugin
+#[derive(Clone)]
+pub struct ClnExtensionState {
+    interceptor: Arc<ClnHtlcInterceptor>,
+    /// Asks the gRPC server to listen on a new address
+    reloads: mpsc::Sender<SocketAddr>,
+}
+
+impl ClnExtensionState {
+    /// Handles the `gateway-reload` RPC, which takes the new listen address
+    /// of the extension either by name or as the only positional parameter
+    async fn reload(&self, params: serde_json::Value) -> Result<serde_json::Value, anyhow::Error> {
+        let listen = params
+            .get("listen")
+            .or_else(|| params.get(0))
+            .and_then(|listen| listen.as_str())
+            .ok_or_else(|| anyhow!("Missing listen address"))?;
+        let listen = SocketAddr::from_str(listen)?;
+
+        // Fail the RPC instead of the extension if the address can't be used
+        drop(tokio::net::TcpListener::bind(listen).await?);
+
+        self.reloads
+            .send(listen)
+            .await
+            .map_err(|_| anyhow!("Gateway extension is shutting down"))?;
+        info!(%listen, "Reloaded gateway extension");
+        Ok(serde_json::json!({ "listen": listen.to_string() }))
+    }
+}
+
 impl ClnRpcService {
-    pub async fn new(
-    ) -> Result<(Self, SocketAddr, Plugin<Arc<ClnHtlcInterceptor>>), ClnExtensionError> {
+    pub async fn new() -> Result<
+        (
+            Self,
+            SocketAddr,
+            Plugin<ClnExtensionState>,
+            mpsc::Receiver<SocketAddr>,
+        ),
+        ClnExtensionError,
+    > {
         let interceptor = Arc::new(ClnHtlcInterceptor::new());
+        let (reloads, reload_receiver) = mpsc::channel(1);
 
         if let Some(plugin) = Builder::new(stdin(), stdout())
             .option(options::ConfigOption::new(
@@ -144,13 +204,13 @@ impl ClnRpcService {
             ))
             .hook(
                 "htlc_accepted",
-                |plugin: Plugin<Arc<ClnHtlcInterceptor>>, value: serde_json::Value| async move {
+                |plugin: Plugin<ClnExtensionState>, value: serde_json::Value| async move {
                     // This callback needs to be `Sync`, so we use tokio::spawn
                     let handle = tokio::spawn(async move {
                         // Handle core-lightning "htlc_accepted" events
                         // by passing the HTLC to the interceptor in the plugin state
                         let payload: HtlcAccepted = serde_json::from_value(value)?;
-                        Ok(plugin.state().intercept_htlc(payload).await)
+                        Ok(plugin.state().interceptor.intercept_htlc(payload).await)
                     });
                     handle.await?
                 },
@@ -162,13 +222,25 @@ impl ClnRpcService {
             // https://lightning.readthedocs.io/PLUGINS.html?highlight=shutdown#shutdown
             .subscribe(
                 "shutdown",
-                |plugin: Plugin<Arc<ClnHtlcInterceptor>>, _: serde_json::Value| async move {
+                |plugin: Plugin<ClnExtensionState>, _: serde_json::Value| async move {
                     info!("Received \"shutdown\" notification from lightningd ... requesting cln_plugin shutdown");
                     plugin.shutdown()
                 },
             )
+            // Lets operators move the extension to a different listen address without
+            // restarting lightningd, the HTLC stream reconnects to the new address
+            .rpcmethod(
+                "gateway-reload",
+                "Restarts the gateway extension on a new listen address",
+                |plugin: Plugin<ClnExtensionState>, params: serde_json::Value| async move {
+                    plugin.state().reload(params).await
+                },
+            )
             .dynamic() // Allow reloading the plugin
-            .start(interceptor.clone())
+            .start(ClnExtensionState {
+                interceptor: interceptor.clone(),
+                reloads,
+            })
             .await?
         {
             let config = plugin.configuration();
@@ -200,6 +272,7 @@ impl ClnRpcService {
                 },
                 listen,
                 plugin,
+                reload_receiver,
             ))
         } else {
             Err(ClnExtensionError::Error(anyhow!(
@@ -221,7 +294,7 @@ impl ClnRpcService {
         })
     }
 
-    pub async fn info(&self) -> Result<(PublicKey, String), ClnExtensionError> {
+    pub async fn info(&self) -> Result<(PublicKey, String, String), ClnExtensionError> {
         self.rpc_client()
             .await?
             .call(cln_rpc::Request::Getinfo(
@@ -229,9 +302,9 @@ impl ClnRpcService {
             ))
             .await
             .map(|response| match response {
-                cln_rpc::Response::Getinfo(model::GetinfoResponse { id, alias, .. }) => {
-                    Ok((id, alias))
-                }
+                cln_rpc::Response::Getinfo(model::GetinfoResponse {
+                    id, alias, network, ..
+                }) => Ok((id, alias, network)),
                 _ => Err(ClnExtensionError::RpcWrongResponse),
             })
             .map_err(ClnExtensionError::RpcError)?
@@ -247,7 +320,7 @@ impl ClnRpcService {
             htlc_id,
             ..
         } = complete_request;
-        if let Some(outcome) = interceptors
+        if let Some(PendingHtlc { outcome, .. }) = interceptors
             .outcomes
             .lock()
             .await
@@ -319,10 +392,11 @@ impl GatewayLightning for ClnRpcService {
     ) -> Result<tonic::Response<GetNodeInfoResponse>, Status> {
         self.info()
             .await
-            .map(|(pub_key, alias)| {
+            .map(|(pub_key, alias, network)| {
                 tonic::Response::new(GetNodeInfoResponse {
                     pub_key: pub_key.serialize().to_vec(),
                     alias,
+                    network,
                 })
             })
             .map_err(|e| {
@@ -398,14 +472,19 @@ impl GatewayLightning for ClnRpcService {
 
             let channel = match channels_response {
                 cln_rpc::Response::ListChannels(channels) => {
-                    let Some(channel) = channels.channels.into_iter().find(|chan| chan.destination == node_info.0) else {
+                    let Some(channel) = channels
+                        .channels
+                        .into_iter()
+                        .find(|chan| chan.destination == node_info.0)
+                    else {
                         warn!(?scid, "Channel not found in graph");
                         continue;
                     };
                     Ok(channel)
                 }
                 _ => Err(ClnExtensionError::RpcWrongResponse),
-            }.map_err(|err| tonic::Status::internal(err.to_string()))?;
+            }
+            .map_err(|err| tonic::Status::internal(err.to_string()))?;
 
             let route_hint_hop = RouteHintHop {
                 src_node_id: peer_id.serialize().to_vec(),
@@ -435,8 +514,17 @@ impl GatewayLightning for ClnRpcService {
             max_delay,
             max_fee_percent,
             payment_hash: _,
+            keysend,
         } = request.into_inner();
 
+        // The keysend command of core-lightning generates its own preimage, so
+        // it can't pay the payment hash of the contract
+        if keysend.is_some() {
+            return Err(Status::unimplemented(
+                "Keysend payments with a given preimage are not supported by core-lightning",
+            ));
+        }
+
         let outcome = self
             .rpc_client()
             .await
@@ -485,8 +573,23 @@ impl GatewayLightning for ClnRpcService {
         let (gatewayd_sender, gatewayd_receiver) =
             mpsc::channel::<Result<InterceptHtlcRequest, Status>>(100);
 
-        let mut sender = self.interceptor.sender.lock().await;
-        *sender = Some(gatewayd_sender.clone());
+        *self.interceptor.sender.lock().await = Some(gatewayd_sender.clone());
+
+        // HTLCs that weren't resolved before gatewayd reconnected would otherwise
+        // wait for an outcome until they time out
+        let pending = self
+            .interceptor
+            .outcomes
+            .lock()
+            .await
+            .values()
+            .map(|pending| pending.request.clone())
+            .collect::<Vec<_>>();
+        for request in pending {
+            if gatewayd_sender.send(Ok(request)).await.is_err() {
+                break;
+            }
+        }
 
         // Spawn new thread that listens for events from the input stream
         let interceptors = self.interceptor.clone();
@@ -539,11 +642,18 @@ fn htlc_processing_failure() -> serde_json::Value {
 type HtlcInterceptionSender = mpsc::Sender<Result<InterceptHtlcRequest, Status>>;
 type HtlcOutcomeSender = oneshot::Sender<serde_json::Value>;
 
+/// An intercepted HTLC waiting for gatewayd to decide its outcome
+pub struct PendingHtlc {
+    /// Handed to gatewayd again if the HTLC stream reconnects
+    request: InterceptHtlcRequest,
+    outcome: HtlcOutcomeSender,
+}
+
 /// Functional structure to filter intercepted HTLCs into subscription streams.
 /// Used as a CLN plugin
 #[derive(Clone)]
 pub struct ClnHtlcInterceptor {
-    pub outcomes: Arc<Mutex<BTreeMap<(u64, u64), HtlcOutcomeSender>>>,
+    pub outcomes: Arc<Mutex<BTreeMap<(u64, u64), PendingHtlc>>>,
     sender: Arc<Mutex<Option<HtlcInterceptionSender>>>,
 }
 
@@ -584,7 +694,9 @@ impl ClnHtlcInterceptor {
 
         info!(?short_channel_id, "Intercepted htlc with SCID");
 
-        if let Some(sender) = &*self.sender.lock().await {
+        // Cloned so a reconnecting gatewayd can replace it while we wait
+        let sender = self.sender.lock().await.clone();
+        if let Some(sender) = sender {
             let payment_hash = payload.htlc.payment_hash.to_vec();
 
             let incoming_chan_id =
@@ -594,45 +706,49 @@ impl ClnHtlcInterceptor {
                     Err(_) => return serde_json::json!({ "result": "continue" }),
                 };
 
-            let htlc_ret = match sender
-                .send(Ok(InterceptHtlcRequest {
-                    payment_hash: payment_hash.clone(),
-                    incoming_amount_msat: payload.htlc.amount_msat.msats,
-                    outgoing_amount_msat: payload.onion.forward_msat.msats,
-                    incoming_expiry: htlc_expiry,
-                    short_channel_id,
-                    incoming_chan_id,
-                    htlc_id: payload.htlc.id,
-                }))
-                .await
-            {
-                Ok(_) => {
-                    // Open a channel to receive the outcome of the HTLC processing
-                    let (sender, receiver) = oneshot::channel::<serde_json::Value>();
-                    self.outcomes
-                        .lock()
-                        .await
-                        .insert((incoming_chan_id, payload.htlc.id), sender);
-
-                    // If the gateway does not respond within the HTLC expiry,
-                    // Automatically respond with a failure message.
-                    tokio::time::timeout(Duration::from_secs(30), async {
-                        receiver.await.unwrap_or_else(|e| {
-                            error!("Failed to receive outcome of intercepted htlc: {:?}", e);
-                            htlc_processing_failure()
-                        })
-                    })
-                    .await
-                    .unwrap_or_else(|e| {
-                        error!("await_htlc_processing error {:?}", e);
-                        htlc_processing_failure()
-                    })
-                }
-                Err(e) => {
-                    error!("Failed to send htlc to subscription: {:?}", e);
-                    htlc_processing_failure()
-                }
+            let request = InterceptHtlcRequest {
+                payment_hash: payment_hash.clone(),
+                incoming_amount_msat: payload.htlc.amount_msat.msats,
+                outgoing_amount_msat: payload.onion.forward_msat.msats,
+                incoming_expiry: htlc_expiry,
+                short_channel_id,
+                incoming_chan_id,
+                htlc_id: payload.htlc.id,
             };
+            let key = (incoming_chan_id, payload.htlc.id);
+
+            // Open a channel to receive the outcome of the HTLC processing. It is
+            // registered before sending the HTLC, so the outcome can't arrive first.
+            let (outcome, receiver) = oneshot::channel::<serde_json::Value>();
+            self.outcomes.lock().await.insert(
+                key,
+                PendingHtlc {
+                    request: request.clone(),
+                    outcome,
+                },
+            );
+
+            if let Err(e) = sender.send(Ok(request)).await {
+                // The HTLC is handed to gatewayd again when it reconnects
+                warn!("Failed to send htlc to subscription: {:?}", e);
+            }
+            // Don't keep the HTLC stream open while waiting for the outcome
+            drop(sender);
+
+            // If the gateway does not respond within the HTLC expiry,
+            // Automatically respond with a failure message.
+            let htlc_ret = tokio::time::timeout(Duration::from_secs(30), async {
+                receiver.await.unwrap_or_else(|e| {
+                    error!("Failed to receive outcome of intercepted htlc: {:?}", e);
+                    htlc_processing_failure()
+                })
+            })
+            .await
+            .unwrap_or_else(|e| {
+                error!("await_htlc_processing error {:?}", e);
+                htlc_processing_failure()
+            });
+            self.outcomes.lock().await.remove(&key);
 
             return htlc_ret;
         }
@@ -4,20 +4,16 @@ use std::process::exit;
 
 use clap::Parser;
 use fedimint_client::module::gen::ClientModuleGenRegistry;
-use fedimint_core::core::{
-    LEGACY_HARDCODED_INSTANCE_ID_LN, LEGACY_HARDCODED_INSTANCE_ID_MINT,
-    LEGACY_HARDCODED_INSTANCE_ID_WALLET,
-};
+use fedimint_core::core::LEGACY_HARDCODED_INSTANCE_ID_MINT;
 use fedimint_core::db::Database;
 use fedimint_core::module::registry::ModuleDecoderRegistry;
-use fedimint_core::module::{CommonModuleGen, ModuleCommon};
-use fedimint_ln_client::LightningCommonGen;
+use fedimint_core::Amount;
 use fedimint_ln_common::config::GatewayFee;
-use fedimint_ln_common::LightningModuleTypes;
 use fedimint_logging::TracingSetup;
-use fedimint_mint_client::{MintClientGen, MintCommonGen, MintModuleTypes};
-use fedimint_wallet_client::{WalletClientGen, WalletCommonGen, WalletModuleTypes};
+use fedimint_mint_client::MintClientGen;
+use fedimint_wallet_client::WalletClientGen;
 use ln_gateway::client::StandardGatewayClientBuilder;
+use ln_gateway::limits::PaymentLimits;
 use ln_gateway::{Gateway, GatewayError, LightningMode, DEFAULT_FEES};
 use tracing::info;
 use url::Url;
@@ -49,6 +45,23 @@ pub struct GatewayOpts {
     /// Format: <base_msat>,<proportional_millionths>
     #[arg(long = "fees", env = "FM_GATEWAY_FEES")]
     pub fees: Option<GatewayFee>,
+
+    /// Largest payment the gateway makes or receives for a user, in msat
+    #[arg(long = "max-payment-msat", env = "FM_GATEWAY_MAX_PAYMENT_MSAT")]
+    pub max_payment: Option<Amount>,
+
+    /// Largest volume the gateway pays out of or into a federation per hour,
+    /// in msat
+    #[arg(long = "max-hourly-msat", env = "FM_GATEWAY_MAX_HOURLY_MSAT")]
+    pub max_hourly: Option<Amount>,
+
+    /// Nostr relays to announce the gateway on, comma separated
+    #[arg(
+        long = "nostr-relays",
+        env = "FM_GATEWAY_NOSTR_RELAYS",
+        value_delimiter = ','
+    )]
+    pub nostr_relays: Vec<Url>,
 }
 
 /// Fedimint Gateway Binary
@@ -77,6 +90,9 @@ async fn main() -> Result<(), anyhow::Error> {
         api_addr,
         password,
         fees,
+        max_payment,
+        max_hourly,
+        nostr_relays,
     } = GatewayOpts::parse();
 
     info!(
@@ -86,7 +102,7 @@ async fn main() -> Result<(), anyhow::Error> {
 
     // Create federation client builder
     let mut registry = ClientModuleGenRegistry::new();
-    registry.attach(MintClientGen);
+    registry.attach(MintClientGen::default());
     registry.attach(WalletClientGen::default());
     let client_builder = StandardGatewayClientBuilder::new(
         data_dir.clone(),
@@ -94,36 +110,24 @@ async fn main() -> Result<(), anyhow::Error> {
         LEGACY_HARDCODED_INSTANCE_ID_MINT,
     );
 
-    // Create module decoder registry
-    let decoders = ModuleDecoderRegistry::from_iter([
-        (
-            LEGACY_HARDCODED_INSTANCE_ID_LN,
-            LightningCommonGen::KIND,
-            LightningModuleTypes::decoder(),
-        ),
-        (
-            LEGACY_HARDCODED_INSTANCE_ID_MINT,
-            MintCommonGen::KIND,
-            MintModuleTypes::decoder(),
-        ),
-        (
-            LEGACY_HARDCODED_INSTANCE_ID_WALLET,
-            WalletCommonGen::KIND,
-            WalletModuleTypes::decoder(),
-        ),
-    ]);
-
+    // The gateway database holds no module items and the client configs keep
+    // their module configs encoded, so no decoders are needed. Federations
+    // don't share module instance ids anyway.
     let gatewayd_db = Database::new(
         fedimint_rocksdb::RocksDb::open(data_dir.join(DB_FILE))
             .map_err(|_| GatewayError::DatabaseError)?,
-        decoders.clone(),
+        ModuleDecoderRegistry::default(),
     );
 
     // Create gateway instance
-    let gateway = Gateway::new(
+    let mut gateway = Gateway::new(
         mode,
         client_builder,
         fees.unwrap_or(GatewayFee(DEFAULT_FEES)).0,
+        PaymentLimits {
+            max_payment,
+            max_hourly,
+        },
         gatewayd_db,
         api_addr,
     )
@@ -133,6 +137,10 @@ async fn main() -> Result<(), anyhow::Error> {
         exit(1)
     });
 
+    if !nostr_relays.is_empty() {
+        gateway.spawn_nostr_announcer(nostr_relays).await?;
+    }
+
     gateway.spawn_blocking_webserver(listen, password).await;
 
     Ok(())
@@ -5,8 +5,9 @@ use std::sync::Arc;
 
 use fedimint_client::module::gen::ClientModuleGenRegistry;
 use fedimint_client::secret::PlainRootSecretStrategy;
-use fedimint_client::ClientBuilder;
-use fedimint_core::api::{DynGlobalApi, GlobalFederationApi, WsClientConnectInfo, WsFederationApi};
+use fedimint_client::{ClientBuilder, ClientSecret};
+use fedimint_core::api::{WsClientConnectInfo, WsFederationApi};
+use fedimint_core::config::FederationId;
 use fedimint_core::core::ModuleInstanceId;
 use fedimint_core::db::DatabaseTransaction;
 use fedimint_core::task::TaskGroup;
@@ -46,9 +47,56 @@ impl StandardGatewayClientBuilder {
         lnrpc: Arc<dyn ILnRpcClient>,
         tg: &mut TaskGroup,
     ) -> Result<fedimint_client::Client> {
+        self.client_builder(config, lnrpc)?
+            // TODO: make this configurable?
+            .build::<PlainRootSecretStrategy>(tg)
+            .await
+            .map_err(|error| {
+                tracing::warn!("Error building client: {:?}", error);
+                GatewayError::ClientNgError
+            })
+    }
+
+    /// Builds a client with a fresh database from the root `secret` of a lost
+    /// client and restores its ecash from the backup it uploaded to the
+    /// federation
+    pub async fn restore(
+        &self,
+        config: FederationConfig,
+        lnrpc: Arc<dyn ILnRpcClient>,
+        tg: &mut TaskGroup,
+        secret: ClientSecret<PlainRootSecretStrategy>,
+    ) -> Result<fedimint_client::Client> {
+        let federation_id = config.config.federation_id;
+        if self.db_path(federation_id).exists() {
+            return Err(GatewayError::Other(anyhow::anyhow!(
+                "Database of federation {federation_id} already exists, remove it before restoring"
+            )));
+        }
+
+        let (client, _metadata) = self
+            .client_builder(config, lnrpc)?
+            .build_restoring_from_backup(tg, secret)
+            .await
+            .map_err(|error| {
+                tracing::warn!("Error restoring client: {:?}", error);
+                GatewayError::ClientNgError
+            })?;
+        Ok(client)
+    }
+
+    fn db_path(&self, federation_id: FederationId) -> PathBuf {
+        self.work_dir.join(format!("{federation_id}.db"))
+    }
+
+    fn client_builder(
+        &self,
+        config: FederationConfig,
+        lnrpc: Arc<dyn ILnRpcClient>,
+    ) -> Result<ClientBuilder> {
         let federation_id = config.config.federation_id;
 
-        let db_path = self.work_dir.join(format!("{federation_id}.db"));
+        let db_path = self.db_path(federation_id);
 
         let db =
             fedimint_rocksdb::RocksDb::open(db_path).map_err(|_| GatewayError::DatabaseError)?;
@@ -66,15 +114,7 @@ impl StandardGatewayClientBuilder {
         client_builder.with_primary_module(self.primary_module);
         client_builder.with_config(config.config);
         client_builder.with_database(db);
-
-        client_builder
-            // TODO: make this configurable?
-            .build::<PlainRootSecretStrategy>(tg)
-            .await
-            .map_err(|error| {
-                tracing::warn!("Error building client: {:?}", error);
-                GatewayError::ClientNgError
-            })
+        Ok(client_builder)
     }
 
     pub async fn create_config(
@@ -83,8 +123,7 @@ impl StandardGatewayClientBuilder {
         mint_channel_id: u64,
         fees: RoutingFees,
     ) -> Result<FederationConfig> {
-        let api: DynGlobalApi = WsFederationApi::from_connect_info(&[connect.clone()]).into();
-        let client_config = api.download_client_config(&connect).await?;
+        let client_config = WsFederationApi::download_verified_client_config(&connect).await?;
         Ok(FederationConfig {
             mint_channel_id,
             timelock_delta: 10,
@@ -106,6 +145,18 @@ impl StandardGatewayClientBuilder {
             .map_err(|_| GatewayError::DatabaseError)
     }
 
+    pub async fn remove_config(
+        &self,
+        federation_id: FederationId,
+        mut dbtx: DatabaseTransaction<'_>,
+    ) -> Result<()> {
+        dbtx.remove_entry(&FederationIdKey { id: federation_id })
+            .await;
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|_| GatewayError::DatabaseError)
+    }
+
     pub async fn load_configs(
         &self,
         mut dbtx: DatabaseTransaction<'_>,
@@ -1,6 +1,13 @@
+use std::time::SystemTime;
+
+use bitcoin_hashes::sha256;
 use fedimint_core::config::{ClientConfig, FederationId};
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::{impl_db_lookup, impl_db_record};
+use fedimint_ln_common::contracts::Preimage;
+use fedimint_ln_common::lightning_address::{
+    LightningAddressPayment, LightningAddressRegistration,
+};
 use fedimint_ln_common::LightningGateway;
 use lightning::routing::gossip::RoutingFees;
 
@@ -9,6 +16,10 @@ use lightning::routing::gossip::RoutingFees;
 pub enum DbKeyPrefix {
     FederationConfig = 0x04,
     FederationRegistration = 0x05,
+    NostrSecretKey = 0x06,
+    LightningAddress = 0x07,
+    LightningAddressPayment = 0x08,
+    InterceptedHtlc = 0x09,
 }
 
 #[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Ord, PartialOrd)]
@@ -45,3 +56,88 @@ impl_db_record!(
     value = LightningGateway,
     db_prefix = DbKeyPrefix::FederationRegistration,
 );
+
+/// Key the gateway signs its nostr announcements with, generated on first use
+#[derive(Debug, Clone, Encodable, Decodable)]
+pub struct NostrSecretKeyKey;
+
+impl_db_record!(
+    key = NostrSecretKeyKey,
+    value = [u8; 32],
+    db_prefix = DbKeyPrefix::NostrSecretKey,
+);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct LightningAddressKey {
+    pub name: String,
+}
+
+impl_db_record!(
+    key = LightningAddressKey,
+    value = LightningAddressRegistration,
+    db_prefix = DbKeyPrefix::LightningAddress,
+);
+
+/// Invoices created for a lightning address, listed to its owner when they
+/// come online to claim the payments
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct LightningAddressPaymentKey {
+    pub name: String,
+    pub payment_hash: sha256::Hash,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct LightningAddressPaymentPrefix {
+    pub name: String,
+}
+
+impl_db_record!(
+    key = LightningAddressPaymentKey,
+    value = LightningAddressPayment,
+    db_prefix = DbKeyPrefix::LightningAddressPayment,
+);
+
+impl_db_lookup!(
+    key = LightningAddressPaymentKey,
+    query_prefix = LightningAddressPaymentPrefix
+);
+
+/// An HTLC intercepted for one of our federations, identified like the
+/// lightning node does
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash)]
+pub struct InterceptedHtlcKey {
+    pub incoming_chan_id: u64,
+    pub htlc_id: u64,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct InterceptedHtlcKeyPrefix;
+
+/// Processing state of an intercepted HTLC, so it can be recovered after a
+/// crash of the gateway
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq)]
+pub struct InterceptedHtlc {
+    pub federation_id: FederationId,
+    pub payment_hash: sha256::Hash,
+    pub intercepted_at: SystemTime,
+    /// `None` while the preimage is being bought from the federation
+    pub outcome: Option<HtlcOutcome>,
+}
+
+/// How an intercepted HTLC gets resolved on the lightning node
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq)]
+pub enum HtlcOutcome {
+    Settle(Preimage),
+    Cancel(String),
+}
+
+impl_db_record!(
+    key = InterceptedHtlcKey,
+    value = InterceptedHtlc,
+    db_prefix = DbKeyPrefix::InterceptedHtlc,
+);
+
+impl_db_lookup!(
+    key = InterceptedHtlcKey,
+    query_prefix = InterceptedHtlcKeyPrefix
+);
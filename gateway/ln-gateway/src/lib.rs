@@ -1,8 +1,11 @@
 pub mod client;
 pub mod db;
+pub mod limits;
 pub mod lnd;
 pub mod lnrpc_client;
+pub mod mpp;
 pub mod ng;
+pub mod recovery;
 pub mod rpc;
 pub mod types;
 pub mod utils;
@@ -16,19 +19,24 @@ use std::collections::BTreeMap;
 use std::env;
 use std::net::SocketAddr;
 use std::str::FromStr;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::anyhow;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use bitcoin::{Address, Txid};
+use bitcoin::{Address, Network, Txid};
 use bitcoin_hashes::hex::ToHex;
+use bitcoin_hashes::{sha256, Hash};
 use clap::Subcommand;
 use client::StandardGatewayClientBuilder;
-use fedimint_core::api::{FederationError, WsClientConnectInfo};
-use fedimint_core::config::FederationId;
+use fedimint_client::backup::Metadata;
+use fedimint_client::secret::PlainRootSecretStrategy;
+use fedimint_client::sm::OperationId;
+use fedimint_client::ClientSecret;
+use fedimint_core::api::{FederationError, WsClientConnectInfo, WsFederationApi};
+use fedimint_core::config::{ClientConfig, FederationId};
 use fedimint_core::db::Database;
 use fedimint_core::task::{sleep, RwLock, TaskGroup, TaskHandle};
 use fedimint_core::time::now;
@@ -36,33 +44,52 @@ use fedimint_core::util::NextOrPending;
 use fedimint_core::Amount;
 use fedimint_ln_client::contracts::Preimage;
 use fedimint_ln_client::pay::PayInvoicePayload;
+use fedimint_ln_common::config::LightningClientConfig;
+use fedimint_ln_common::lightning_address::{
+    tweak_pub_key, LightningAddressPayment, LightningAddressPaymentsRequest,
+    LightningAddressRegistration,
+};
 use fedimint_ln_common::route_hints::RouteHint;
 use fedimint_ln_common::KIND;
+use fedimint_mint_client::config::MintClientConfig;
+use fedimint_nostr::{GatewayAnnouncement, GatewayFees, NostrAnnouncer, ANNOUNCEMENT_INTERVAL};
+use fedimint_wallet_client::config::WalletClientConfig;
 use fedimint_wallet_client::{WalletClientExt, WithdrawState};
 use futures::stream::StreamExt;
 use gatewaylnrpc::intercept_htlc_response::{Action, Cancel};
 use gatewaylnrpc::{GetNodeInfoResponse, InterceptHtlcResponse};
 use lightning::routing::gossip::RoutingFees;
+use lightning_invoice::Invoice;
 use lnrpc_client::{ILnRpcClient, RouteHtlcStream};
-use ng::{GatewayClientExt, GatewayClientModule, GatewayExtRegisterStates};
+use ng::{GatewayClientExt, GatewayClientModule, GatewayExtRegisterStates, GatewayMeta};
 use rand::Rng;
 use rpc::FederationInfo;
-use secp256k1::PublicKey;
+use secp256k1::{PublicKey, Secp256k1};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::{mpsc, Mutex};
-use tracing::{error, info};
+use tracing::{error, info, warn};
 use url::Url;
 
+use crate::db::{
+    FederationIdKey, HtlcOutcome, InterceptedHtlc, InterceptedHtlcKey, InterceptedHtlcKeyPrefix,
+    LightningAddressKey, LightningAddressPaymentKey, LightningAddressPaymentPrefix,
+    NostrSecretKeyKey,
+};
 use crate::gatewaylnrpc::intercept_htlc_response::{Forward, Settle};
+use crate::limits::{PaymentDirection, PaymentLimitError, PaymentLimits, PaymentThrottle};
 use crate::lnd::GatewayLndClient;
 use crate::lnrpc_client::NetworkLnRpcClient;
+use crate::mpp::{HtlcSet, MPP_TIMEOUT};
 use crate::ng::{GatewayExtPayStates, GatewayExtReceiveStates, Htlc};
+use crate::recovery::{FederationRecovery, RecoveryBundle, RecoveryReport, RestoredFederation};
 use crate::rpc::rpc_server::run_webserver;
 use crate::rpc::{
-    BackupPayload, BalancePayload, ConnectFedPayload, DepositAddressPayload, GatewayInfo,
-    InfoPayload, RestorePayload, WithdrawPayload,
+    BackupPayload, BalancePayload, ConnectFedPayload, DepositAddressPayload, DisconnectFedPayload,
+    DrainPayload, DrainResponse, ExportRecoveryPayload, FederationFees, FederationPreview,
+    GatewayInfo, ImportRecoveryPayload, InFlightOperation, InfoPayload, LnurlPayResponse,
+    ReloadPayload, RestorePayload, WithdrawPayload,
 };
 
 /// LND HTLC interceptor can't handle SCID of 0, so start from 1
@@ -71,6 +98,15 @@ const INITIAL_SCID: u64 = 1;
 /// How long a gateway announcement stays valid
 pub const GW_ANNOUNCEMENT_TTL: Duration = Duration::from_secs(600);
 
+/// How long a drain waits for in-flight payments by default
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(300);
+
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How long the outcomes of intercepted HTLCs are kept, HTLCs expire long
+/// before that
+const INTERCEPTED_HTLC_RETENTION: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
 const ROUTE_HINT_RETRIES: usize = 10;
 const ROUTE_HINT_RETRY_SLEEP: Duration = Duration::from_secs(2);
 
@@ -82,6 +118,15 @@ pub const DEFAULT_FEES: RoutingFees = RoutingFees {
     proportional_millionths: 10000,
 };
 
+/// Smallest payment to a lightning address we create invoices for
+pub const LIGHTNING_ADDRESS_MIN_SENDABLE: Amount = Amount::from_sats(1);
+
+/// Largest payment to a lightning address we create invoices for
+pub const LIGHTNING_ADDRESS_MAX_SENDABLE: Amount = Amount::from_sats(1_000_000);
+
+/// Expiry of invoices for lightning addresses in seconds
+const LIGHTNING_ADDRESS_INVOICE_EXPIRY: u64 = 3600;
+
 pub type Result<T> = std::result::Result<T, GatewayError>;
 
 #[derive(Debug, Clone, Subcommand, Serialize, Deserialize)]
@@ -121,6 +166,8 @@ pub enum GatewayError {
     DatabaseError,
     #[error("Federation client error")]
     ClientNgError,
+    #[error("{0}")]
+    PaymentLimitExceeded(#[from] PaymentLimitError),
 }
 
 impl GatewayError {
@@ -132,6 +179,11 @@ impl GatewayError {
 
 impl IntoResponse for GatewayError {
     fn into_response(self) -> Response {
+        // Users are told which limit they hit so they can retry accordingly
+        if let GatewayError::PaymentLimitExceeded(error) = self {
+            return (StatusCode::FORBIDDEN, axum::Json(error)).into_response();
+        }
+
         let mut err = Cow::<'static, str>::Owned(format!("{self:?}")).into_response();
         *err.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
         err
@@ -141,42 +193,85 @@ impl IntoResponse for GatewayError {
 #[derive(Clone)]
 pub struct Gateway {
     lnrpc: Arc<dyn ILnRpcClient>,
-    lightning_mode: Option<LightningMode>,
+    /// Same client as `lnrpc` when connected to a CLN extension, kept to
+    /// change its address on reload
+    cln_lnrpc: Option<Arc<NetworkLnRpcClient>>,
+    lightning_mode: Arc<RwLock<Option<LightningMode>>>,
     clients: Arc<RwLock<BTreeMap<FederationId, Arc<fedimint_client::Client>>>>,
     scid_to_federation: Arc<RwLock<BTreeMap<u64, FederationId>>>,
     client_builder: StandardGatewayClientBuilder,
     channel_id_generator: Arc<Mutex<AtomicU64>>,
-    fees: RoutingFees,
+    fees: Arc<RwLock<RoutingFees>>,
+    payment_throttle: Arc<PaymentThrottle>,
+    /// Set once the gateway drains before shutting down, no new payments are
+    /// accepted from then on
+    draining: Arc<AtomicBool>,
     gatewayd_db: Database,
+    /// Parts of multi-part payments we hold until they add up to the offer
+    htlc_sets: Arc<Mutex<BTreeMap<sha256::Hash, HtlcSet>>>,
     api: Url,
     task_group: TaskGroup,
 }
 
+/// What to do with an intercepted HTLC after adding it to the set of its
+/// payment
+enum CollectedHtlc {
+    /// The federation can't handle it
+    Forward,
+    /// We already know its outcome
+    Resolved(HtlcOutcome),
+    /// Waiting for the remaining parts of the payment, `new_set` is the set it
+    /// started
+    Held { new_set: Option<HtlcSet> },
+    /// The parts add up to the offer and the contract can be funded
+    Complete(HtlcSet),
+}
+
 impl Gateway {
     #[allow(clippy::too_many_arguments)]
     pub async fn new(
         lightning_mode: LightningMode,
         client_builder: StandardGatewayClientBuilder,
         fees: RoutingFees,
+        payment_limits: PaymentLimits,
         gatewayd_db: Database,
         api: Url,
     ) -> Result<Self> {
-        let lnrpc = Self::create_lightning_client(lightning_mode.clone()).await;
+        let (lnrpc, cln_lnrpc) = match lightning_mode.clone() {
+            LightningMode::Cln { cln_extension_addr } => {
+                let cln_lnrpc = Arc::new(NetworkLnRpcClient::new(cln_extension_addr).await);
+                (cln_lnrpc.clone() as Arc<dyn ILnRpcClient>, Some(cln_lnrpc))
+            }
+            LightningMode::Lnd {
+                lnd_rpc_addr,
+                lnd_tls_cert,
+                lnd_macaroon,
+            } => (
+                Arc::new(GatewayLndClient::new(lnd_rpc_addr, lnd_tls_cert, lnd_macaroon).await)
+                    as Arc<dyn ILnRpcClient>,
+                None,
+            ),
+        };
 
         let mut gw = Self {
             lnrpc,
+            cln_lnrpc,
             clients: Arc::new(RwLock::new(BTreeMap::new())),
             scid_to_federation: Arc::new(RwLock::new(BTreeMap::new())),
             client_builder,
             channel_id_generator: Arc::new(Mutex::new(AtomicU64::new(INITIAL_SCID))),
-            lightning_mode: Some(lightning_mode),
-            fees,
+            lightning_mode: Arc::new(RwLock::new(Some(lightning_mode))),
+            fees: Arc::new(RwLock::new(fees)),
+            payment_throttle: Arc::new(PaymentThrottle::new(payment_limits)),
+            draining: Arc::new(AtomicBool::new(false)),
             gatewayd_db,
+            htlc_sets: Arc::new(Mutex::new(BTreeMap::new())),
             api,
             task_group: TaskGroup::new(),
         };
 
         gw.load_clients().await?;
+        gw.recover_intercepted_htlcs().await?;
         gw.route_htlcs().await?;
 
         Ok(gw)
@@ -187,41 +282,34 @@ impl Gateway {
         lnrpc: Arc<dyn ILnRpcClient>,
         client_builder: StandardGatewayClientBuilder,
         fees: RoutingFees,
+        payment_limits: PaymentLimits,
         gatewayd_db: Database,
         api: Url,
     ) -> Result<Self> {
         let mut gw = Self {
             lnrpc,
+            cln_lnrpc: None,
             clients: Arc::new(RwLock::new(BTreeMap::new())),
             scid_to_federation: Arc::new(RwLock::new(BTreeMap::new())),
             client_builder,
             channel_id_generator: Arc::new(Mutex::new(AtomicU64::new(INITIAL_SCID))),
-            lightning_mode: None,
-            fees,
+            lightning_mode: Arc::new(RwLock::new(None)),
+            fees: Arc::new(RwLock::new(fees)),
+            payment_throttle: Arc::new(PaymentThrottle::new(payment_limits)),
+            draining: Arc::new(AtomicBool::new(false)),
             gatewayd_db,
+            htlc_sets: Arc::new(Mutex::new(BTreeMap::new())),
             api,
             task_group: TaskGroup::new(),
         };
 
         gw.load_clients().await?;
+        gw.recover_intercepted_htlcs().await?;
         gw.route_htlcs().await?;
 
         Ok(gw)
     }
 
-    async fn create_lightning_client(mode: LightningMode) -> Arc<dyn ILnRpcClient> {
-        match mode {
-            LightningMode::Cln { cln_extension_addr } => {
-                Arc::new(NetworkLnRpcClient::new(cln_extension_addr).await)
-            }
-            LightningMode::Lnd {
-                lnd_rpc_addr,
-                lnd_tls_cert,
-                lnd_macaroon,
-            } => Arc::new(GatewayLndClient::new(lnd_rpc_addr, lnd_tls_cert, lnd_macaroon).await),
-        }
-    }
-
     async fn create_boxxed_lightning_client(mode: LightningMode) -> Box<dyn ILnRpcClient> {
         match mode {
             LightningMode::Cln { cln_extension_addr } => {
@@ -238,7 +326,12 @@ impl Gateway {
     pub async fn route_htlcs(&mut self) -> Result<()> {
         let scid_to_federation = self.scid_to_federation.clone();
         let clients = self.clients.clone();
-        let ln_mode = self.lightning_mode.clone();
+        let draining = self.draining.clone();
+        let gatewayd_db = self.gatewayd_db.clone();
+        let htlc_sets = self.htlc_sets.clone();
+        let payment_throttle = self.payment_throttle.clone();
+        let task_group = self.task_group.clone();
+        let lightning_mode = self.lightning_mode.clone();
         self.task_group
             .spawn(
                 "Subscribe to intercepted HTLCs in stream",
@@ -251,7 +344,9 @@ impl Gateway {
                         // Create a stream used to communicate with the Lightning implementation
                         let (sender, ln_receiver) = mpsc::channel::<InterceptHtlcResponse>(100);
 
-                        if let Some(ln_mode) = ln_mode.clone() {
+                        // Read on every reconnection to follow reloads of the configuration
+                        let ln_mode = lightning_mode.read().await.clone();
+                        if let Some(ln_mode) = ln_mode {
                             let mut lnrpc = Self::create_boxxed_lightning_client(ln_mode).await;
 
                             // Re-create the HTLC stream if the connection breaks
@@ -262,7 +357,7 @@ impl Gateway {
                                 Ok(stream) => {
                                     // Blocks until the connection to the lightning node breaks
                                     info!("Established HTLC stream");
-                                    Self::handle_htlc_stream(stream, sender, handle.clone(), scid_to_federation.clone(), clients.clone()).await;
+                                    Self::handle_htlc_stream(stream, sender, handle.clone(), scid_to_federation.clone(), clients.clone(), draining.clone(), gatewayd_db.clone(), htlc_sets.clone(), payment_throttle.clone(), task_group.clone()).await;
                                     tracing::warn!("HTLC Stream Lightning connection broken");
                                 }
                                 Err(_) => {
@@ -280,12 +375,18 @@ impl Gateway {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn handle_htlc_stream(
         mut stream: RouteHtlcStream<'_>,
         sender: Sender<InterceptHtlcResponse>,
         handle: TaskHandle,
         scid_to_federation: Arc<RwLock<BTreeMap<u64, FederationId>>>,
         clients: Arc<RwLock<BTreeMap<FederationId, Arc<fedimint_client::Client>>>>,
+        draining: Arc<AtomicBool>,
+        gatewayd_db: Database,
+        htlc_sets: Arc<Mutex<BTreeMap<sha256::Hash, HtlcSet>>>,
+        payment_throttle: Arc<PaymentThrottle>,
+        mut task_group: TaskGroup,
     ) {
         while let Some(Ok(htlc_request)) = stream.next().await {
             if handle.is_shutting_down() {
@@ -307,63 +408,56 @@ impl Gateway {
                         .try_into()
                         .map_err(|_| GatewayError::ClientNgError);
                     if let Ok(htlc) = htlc {
-                        let intercept_op = client.gateway_handle_intercepted_htlc(htlc).await;
+                        // While draining only HTLCs we already started handling are
+                        // settled, replays of them included
+                        let operation_id = OperationId(htlc.payment_hash.into_inner());
+                        if draining.load(Ordering::SeqCst)
+                            && client
+                                .operation_log()
+                                .get_operation(operation_id)
+                                .await
+                                .is_none()
+                        {
+                            let outcome =
+                                HtlcOutcome::Cancel("Gateway is shutting down".to_string());
+                            Self::send_htlc_response(&sender, &htlc, Some(outcome)).await;
+                            continue;
+                        }
+
                         // TODO: Refactor this into the state machine so we don't need to wait here
-                        if let Ok(intercept_op) = intercept_op {
-                            let intercept_sub =
-                                client.gateway_subscribe_ln_receive(intercept_op).await;
-                            if let Ok(intercept_sub) = intercept_sub {
-                                let mut intercept_sub = intercept_sub.into_stream();
-
-                                let outcome = loop {
-                                    if let Ok(state) = intercept_sub.ok().await {
-                                        match state {
-                                            GatewayExtReceiveStates::Preimage(preimage) => {
-                                                break InterceptHtlcResponse {
-                                                    action: Some(Action::Settle(Settle {
-                                                        preimage: preimage.0.to_vec(),
-                                                    })),
-                                                    incoming_chan_id: htlc_request.incoming_chan_id,
-                                                    htlc_id: htlc_request.htlc_id,
-                                                };
-                                            }
-                                            GatewayExtReceiveStates::FundingFailed(failed) => {
-                                                break InterceptHtlcResponse {
-                                                    action: Some(Action::Cancel(Cancel {
-                                                        reason: failed,
-                                                    })),
-                                                    incoming_chan_id: htlc_request.incoming_chan_id,
-                                                    htlc_id: htlc_request.htlc_id,
-                                                }
-                                            }
-                                            GatewayExtReceiveStates::RefundSuccess(_) => {
-                                                break InterceptHtlcResponse {
-                                                    action: Some(Action::Cancel(Cancel {
-                                                        reason: "Gateway is being refunded"
-                                                            .to_string(),
-                                                    })),
-                                                    incoming_chan_id: htlc_request.incoming_chan_id,
-                                                    htlc_id: htlc_request.htlc_id,
-                                                }
-                                            }
-                                            GatewayExtReceiveStates::RefundError(failed) => {
-                                                break InterceptHtlcResponse {
-                                                    action: Some(Action::Cancel(Cancel {
-                                                        reason: failed,
-                                                    })),
-                                                    incoming_chan_id: htlc_request.incoming_chan_id,
-                                                    htlc_id: htlc_request.htlc_id,
-                                                }
-                                            }
-                                            _ => {}
-                                        }
-                                    }
-                                };
-
-                                if let Err(error) = sender.send(outcome).await {
-                                    error!(
-                                        "Error sending HTLC response to lightning node: {error:?}"
-                                    );
+                        match Self::collect_htlc(
+                            &gatewayd_db,
+                            client,
+                            &htlc_sets,
+                            &payment_throttle,
+                            *federation_id,
+                            htlc.clone(),
+                        )
+                        .await
+                        {
+                            CollectedHtlc::Forward => {}
+                            CollectedHtlc::Resolved(outcome) => {
+                                Self::send_htlc_response(&sender, &htlc, Some(outcome)).await;
+                                continue;
+                            }
+                            CollectedHtlc::Held { new_set } => {
+                                if let Some(set) = new_set {
+                                    Self::spawn_htlc_set_timeout(
+                                        &mut task_group,
+                                        &gatewayd_db,
+                                        &htlc_sets,
+                                        &sender,
+                                        set,
+                                    )
+                                    .await;
+                                }
+                                continue;
+                            }
+                            CollectedHtlc::Complete(set) => {
+                                let outcome =
+                                    Self::process_htlc_set(&gatewayd_db, client, &set).await;
+                                for part in &set.parts {
+                                    Self::send_htlc_response(&sender, part, outcome.clone()).await;
                                 }
                                 continue;
                             }
@@ -384,6 +478,358 @@ impl Gateway {
         }
     }
 
+    /// Resolves `htlc` on the lightning node, it is forwarded without an
+    /// outcome
+    async fn send_htlc_response(
+        sender: &Sender<InterceptHtlcResponse>,
+        htlc: &Htlc,
+        outcome: Option<HtlcOutcome>,
+    ) {
+        let response = InterceptHtlcResponse {
+            action: Some(match outcome {
+                Some(HtlcOutcome::Settle(preimage)) => Action::Settle(Settle {
+                    preimage: preimage.0.to_vec(),
+                }),
+                Some(HtlcOutcome::Cancel(reason)) => Action::Cancel(Cancel { reason }),
+                None => Action::Forward(Forward {}),
+            }),
+            incoming_chan_id: htlc.incoming_chan_id,
+            htlc_id: htlc.htlc_id,
+        };
+        if let Err(error) = sender.send(response).await {
+            error!("Error sending HTLC response to lightning node: {error:?}");
+        }
+    }
+
+    /// Records an intercepted HTLC and adds it to the set of its payment, see
+    /// [`mpp`]
+    async fn collect_htlc(
+        gatewayd_db: &Database,
+        client: &fedimint_client::Client,
+        htlc_sets: &Mutex<BTreeMap<sha256::Hash, HtlcSet>>,
+        payment_throttle: &PaymentThrottle,
+        federation_id: FederationId,
+        htlc: Htlc,
+    ) -> CollectedHtlc {
+        let key = InterceptedHtlcKey {
+            incoming_chan_id: htlc.incoming_chan_id,
+            htlc_id: htlc.htlc_id,
+        };
+        let mut dbtx = gatewayd_db.begin_transaction().await;
+        match dbtx.get_value(&key).await {
+            // The lightning node hands us HTLCs again if it didn't see their outcome
+            Some(InterceptedHtlc {
+                outcome: Some(outcome),
+                ..
+            }) => return CollectedHtlc::Resolved(outcome),
+            Some(_) => {}
+            None => {
+                let intercepted = InterceptedHtlc {
+                    federation_id,
+                    payment_hash: htlc.payment_hash,
+                    intercepted_at: now(),
+                    outcome: None,
+                };
+                dbtx.insert_new_entry(&key, &intercepted).await;
+                if let Err(error) = dbtx.commit_tx_result().await {
+                    error!("Failed to persist intercepted HTLC: {error:?}");
+                    return CollectedHtlc::Forward;
+                }
+            }
+        }
+
+        // Once the contract is funded every part only waits for its preimage
+        let operation_id = OperationId(htlc.payment_hash.into_inner());
+        if client
+            .operation_log()
+            .get_operation(operation_id)
+            .await
+            .is_some()
+        {
+            let mut set = HtlcSet::new(
+                federation_id,
+                htlc.payment_hash,
+                htlc.outgoing_amount_msat,
+                now(),
+            );
+            set.add(htlc);
+            return CollectedHtlc::Complete(set);
+        }
+
+        let existing_set = htlc_sets.lock().await.contains_key(&htlc.payment_hash);
+        let offer_amount = if existing_set {
+            None
+        } else {
+            match client
+                .gateway_incoming_offer_amount(htlc.payment_hash)
+                .await
+            {
+                Ok(offer_amount) => Some(offer_amount),
+                Err(error) => {
+                    info!("Forwarding HTLC the federation can't handle: {error:?}");
+                    Self::remove_intercepted_htlc(gatewayd_db, &key).await;
+                    return CollectedHtlc::Forward;
+                }
+            }
+        };
+
+        // Checked once per payment, when its first part arrives
+        if let Some(offer_amount) = offer_amount {
+            if let Err(error) = payment_throttle.admit(
+                federation_id,
+                PaymentDirection::Incoming,
+                offer_amount,
+                now(),
+            ) {
+                warn!(payment_hash = %htlc.payment_hash, "Rejecting incoming payment: {error}");
+                let outcome = HtlcOutcome::Cancel(error.to_string());
+                Self::store_htlc_outcome(gatewayd_db, &key, outcome.clone()).await;
+                return CollectedHtlc::Resolved(outcome);
+            }
+        }
+
+        let payment_hash = htlc.payment_hash;
+        let mut htlc_sets = htlc_sets.lock().await;
+        let mut new_set = None;
+        let set = htlc_sets.entry(payment_hash).or_insert_with(|| {
+            let set = HtlcSet::new(
+                federation_id,
+                payment_hash,
+                offer_amount.unwrap_or(htlc.outgoing_amount_msat),
+                now(),
+            );
+            new_set = Some(set.clone());
+            set
+        });
+        set.add(htlc);
+
+        if set.is_complete() {
+            if set.parts.len() > 1 {
+                info!(
+                    payment_hash = %set.payment_hash,
+                    parts = set.parts.len(),
+                    "Received all parts of a multi-part payment"
+                );
+            }
+            let set = htlc_sets
+                .remove(&payment_hash)
+                .expect("Set was just accessed");
+            return CollectedHtlc::Complete(set);
+        }
+
+        info!(
+            payment_hash = %set.payment_hash,
+            received = %set.outgoing_amount(),
+            offer_amount = %set.offer_amount,
+            "Holding part of a multi-part payment"
+        );
+        CollectedHtlc::Held { new_set }
+    }
+
+    /// Cancels the parts of `set` if it isn't complete after [`MPP_TIMEOUT`]
+    async fn spawn_htlc_set_timeout(
+        task_group: &mut TaskGroup,
+        gatewayd_db: &Database,
+        htlc_sets: &Arc<Mutex<BTreeMap<sha256::Hash, HtlcSet>>>,
+        sender: &Sender<InterceptHtlcResponse>,
+        set: HtlcSet,
+    ) {
+        let gatewayd_db = gatewayd_db.clone();
+        let htlc_sets = htlc_sets.clone();
+        let sender = sender.clone();
+        task_group
+            .spawn("multi-part payment timeout", move |_| async move {
+                sleep(MPP_TIMEOUT).await;
+
+                let set = {
+                    let mut htlc_sets = htlc_sets.lock().await;
+                    match htlc_sets.get(&set.payment_hash) {
+                        Some(held) if held.started_at == set.started_at => {
+                            htlc_sets.remove(&set.payment_hash)
+                        }
+                        // Completed in time
+                        _ => None,
+                    }
+                };
+                let Some(set) = set else {
+                    return;
+                };
+
+                warn!(
+                    payment_hash = %set.payment_hash,
+                    received = %set.outgoing_amount(),
+                    offer_amount = %set.offer_amount,
+                    "Cancelling incomplete multi-part payment"
+                );
+                let outcome = HtlcOutcome::Cancel(
+                    "Timed out waiting for the remaining parts of the payment".to_string(),
+                );
+                for key in set.keys() {
+                    Self::store_htlc_outcome(&gatewayd_db, &key, outcome.clone()).await;
+                }
+                for part in &set.parts {
+                    Self::send_htlc_response(&sender, part, Some(outcome.clone())).await;
+                }
+            })
+            .await;
+    }
+
+    /// Buys the preimage of a complete set of HTLCs from the federation,
+    /// tracking its progress in the database so it can be recovered after a
+    /// crash
+    ///
+    /// Returns `None` if the federation can't handle the HTLCs and they
+    /// should be forwarded instead.
+    async fn process_htlc_set(
+        gatewayd_db: &Database,
+        client: &fedimint_client::Client,
+        set: &HtlcSet,
+    ) -> Option<HtlcOutcome> {
+        let operation_id = match client
+            .gateway_handle_intercepted_htlc(set.combined_htlc())
+            .await
+        {
+            Ok(operation_id) => operation_id,
+            Err(error) => {
+                info!("Forwarding HTLC the federation can't handle: {error:?}");
+                for key in set.keys() {
+                    Self::remove_intercepted_htlc(gatewayd_db, &key).await;
+                }
+                return None;
+            }
+        };
+
+        let outcome = Self::await_htlc_outcome(client, operation_id).await?;
+        for key in set.keys() {
+            Self::store_htlc_outcome(gatewayd_db, &key, outcome.clone()).await;
+        }
+        Some(outcome)
+    }
+
+    async fn remove_intercepted_htlc(gatewayd_db: &Database, key: &InterceptedHtlcKey) {
+        let mut dbtx = gatewayd_db.begin_transaction().await;
+        dbtx.remove_entry(key).await;
+        dbtx.commit_tx_result().await.ok();
+    }
+
+    async fn await_htlc_outcome(
+        client: &fedimint_client::Client,
+        operation_id: OperationId,
+    ) -> Option<HtlcOutcome> {
+        let mut updates = client
+            .gateway_subscribe_ln_receive(operation_id)
+            .await
+            .ok()?
+            .into_stream();
+        while let Some(update) = updates.next().await {
+            match update {
+                GatewayExtReceiveStates::Preimage(preimage) => {
+                    return Some(HtlcOutcome::Settle(preimage))
+                }
+                GatewayExtReceiveStates::FundingFailed(reason)
+                | GatewayExtReceiveStates::RefundError(reason) => {
+                    return Some(HtlcOutcome::Cancel(reason))
+                }
+                GatewayExtReceiveStates::RefundSuccess(_) => {
+                    return Some(HtlcOutcome::Cancel("Gateway is being refunded".to_string()))
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+
+    async fn store_htlc_outcome(
+        gatewayd_db: &Database,
+        key: &InterceptedHtlcKey,
+        outcome: HtlcOutcome,
+    ) {
+        let mut dbtx = gatewayd_db.begin_transaction().await;
+        if let Some(mut intercepted) = dbtx.get_value(key).await {
+            intercepted.outcome = Some(outcome);
+            dbtx.insert_entry(key, &intercepted).await;
+            if let Err(error) = dbtx.commit_tx_result().await {
+                error!("Failed to persist outcome of intercepted HTLC: {error:?}");
+            }
+        }
+    }
+
+    /// Picks up the HTLCs that were being processed when the gateway stopped
+    ///
+    /// HTLCs whose contract was funded keep waiting for the preimage in the
+    /// background, so their outcome is known once the lightning node hands
+    /// them to us again. HTLCs that weren't funded yet are cancelled since
+    /// nothing was spent on them. Outcomes older than
+    /// [`INTERCEPTED_HTLC_RETENTION`] are removed.
+    async fn recover_intercepted_htlcs(&mut self) -> Result<()> {
+        let mut dbtx = self.gatewayd_db.begin_transaction().await;
+        let intercepted_htlcs = dbtx
+            .find_by_prefix(&InterceptedHtlcKeyPrefix)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        for (key, mut intercepted) in intercepted_htlcs {
+            if intercepted.outcome.is_some() {
+                if intercepted.intercepted_at + INTERCEPTED_HTLC_RETENTION < now() {
+                    dbtx.remove_entry(&key).await;
+                }
+                continue;
+            }
+
+            let operation_id = OperationId(intercepted.payment_hash.into_inner());
+            let client = self
+                .clients
+                .read()
+                .await
+                .get(&intercepted.federation_id)
+                .cloned();
+            let client = match client {
+                Some(client)
+                    if client
+                        .operation_log()
+                        .get_operation(operation_id)
+                        .await
+                        .is_some() =>
+                {
+                    client
+                }
+                client => {
+                    if client.is_none() {
+                        warn!(?key, federation_id = %intercepted.federation_id, "Cancelling HTLC of a federation we are no longer connected to");
+                    } else {
+                        info!(
+                            ?key,
+                            "Cancelling HTLC that wasn't funded before the gateway stopped"
+                        );
+                    }
+                    intercepted.outcome = Some(HtlcOutcome::Cancel(
+                        "Gateway stopped while processing the HTLC".to_string(),
+                    ));
+                    dbtx.insert_entry(&key, &intercepted).await;
+                    continue;
+                }
+            };
+
+            info!(
+                ?key,
+                "Resuming HTLC that was funded before the gateway stopped"
+            );
+            let gatewayd_db = self.gatewayd_db.clone();
+            self.task_group
+                .spawn("recover intercepted htlc", move |_| async move {
+                    if let Some(outcome) = Self::await_htlc_outcome(&client, operation_id).await {
+                        Self::store_htlc_outcome(&gatewayd_db, &key, outcome).await;
+                    }
+                })
+                .await;
+        }
+
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|_| GatewayError::DatabaseError)
+    }
+
     async fn fetch_lightning_route_info(&self) -> Result<(Vec<RouteHint>, PublicKey, String)> {
         let mut num_retries = 0;
         let (route_hints, node_pub_key, alias) = loop {
@@ -394,7 +840,7 @@ impl Gateway {
                 .try_into()
                 .expect("Could not parse route hints");
 
-            let GetNodeInfoResponse { pub_key, alias } = self.lnrpc.info().await?;
+            let GetNodeInfoResponse { pub_key, alias, .. } = self.lnrpc.info().await?;
             let node_pub_key = PublicKey::from_slice(&pub_key)
                 .map_err(|e| GatewayError::Other(anyhow!("Invalid node pubkey {}", e)))?;
 
@@ -454,24 +900,8 @@ impl Gateway {
         scid: u64,
         route_hints: Vec<RouteHint>,
     ) -> Result<()> {
-        let register_op = client
-            .register_with_federation(self.api.clone(), route_hints, GW_ANNOUNCEMENT_TTL)
+        Self::register_and_wait(&client, self.api.clone(), route_hints, GW_ANNOUNCEMENT_TTL)
             .await?;
-        // TODO: Move this inside of the state machine
-        {
-            let mut register_sub = client
-                .gateway_subscribe_register(register_op)
-                .await?
-                .into_stream();
-            loop {
-                let state = register_sub.ok().await?;
-                match state {
-                    GatewayExtRegisterStates::Success => break,
-                    GatewayExtRegisterStates::Done => break,
-                    _ => {}
-                }
-            }
-        }
 
         self.clients
             .write()
@@ -484,6 +914,33 @@ impl Gateway {
         Ok(())
     }
 
+    /// Registers the gateway with the federation of `client`, superseding
+    /// previous registrations. A zero `time_to_live` withdraws the gateway.
+    async fn register_and_wait(
+        client: &fedimint_client::Client,
+        api: Url,
+        route_hints: Vec<RouteHint>,
+        time_to_live: Duration,
+    ) -> Result<()> {
+        let register_op = client
+            .register_with_federation(api, route_hints, time_to_live)
+            .await?;
+        // TODO: Move this inside of the state machine
+        let mut register_sub = client
+            .gateway_subscribe_register(register_op)
+            .await?
+            .into_stream();
+        loop {
+            let state = register_sub.ok().await?;
+            match state {
+                GatewayExtRegisterStates::Success => break,
+                GatewayExtRegisterStates::Done => break,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
     pub async fn remove_client(
         &self,
         federation_id: FederationId,
@@ -517,6 +974,8 @@ impl Gateway {
         &mut self,
         payload: ConnectFedPayload,
     ) -> Result<FederationInfo> {
+        self.ensure_not_draining()?;
+
         let connect = WsClientConnectInfo::from_str(&payload.connect).map_err(|e| {
             GatewayError::Other(anyhow::anyhow!("Invalid federation member string {}", e))
         })?;
@@ -535,7 +994,7 @@ impl Gateway {
         let gw_client_cfg = loop {
             match self
                 .client_builder
-                .create_config(connect.clone(), channel_id, self.fees)
+                .create_config(connect.clone(), channel_id, *self.fees.read().await)
                 .await
             {
                 Ok(gw_client_cfg) => break gw_client_cfg,
@@ -550,6 +1009,14 @@ impl Gateway {
         };
 
         let federation_id = gw_client_cfg.config.federation_id;
+        if self.clients.read().await.contains_key(&federation_id) {
+            return Err(GatewayError::Other(anyhow!(
+                "Already connected to federation {federation_id}"
+            )));
+        }
+        self.ensure_network_compatible(&gw_client_cfg.config)
+            .await?;
+
         let (route_hints, _, _) = self.fetch_lightning_route_info().await?;
 
         let client = self
@@ -583,6 +1050,139 @@ impl Gateway {
         })
     }
 
+    /// Downloads the config of a federation and reports whether the gateway
+    /// could serve it, without connecting
+    pub async fn handle_preview_federation(
+        &self,
+        payload: ConnectFedPayload,
+    ) -> Result<FederationPreview> {
+        let connect = WsClientConnectInfo::from_str(&payload.connect).map_err(|e| {
+            GatewayError::Other(anyhow::anyhow!("Invalid federation member string {}", e))
+        })?;
+        let config = WsFederationApi::download_verified_client_config(&connect).await?;
+
+        let network = Self::federation_network(&config)?;
+        let lightning_network = self.lightning_network().await?;
+        let fees = FederationFees {
+            lightning: config
+                .get_first_module_by_kind::<LightningClientConfig>(KIND)
+                .ok()
+                .map(|(_, cfg)| cfg.fee_consensus),
+            mint: config
+                .get_first_module_by_kind::<MintClientConfig>(fedimint_mint_client::KIND)
+                .ok()
+                .map(|(_, cfg)| cfg.fee_consensus),
+            wallet: config
+                .get_first_module_by_kind::<WalletClientConfig>(fedimint_wallet_client::KIND)
+                .ok()
+                .map(|(_, cfg)| cfg.fee_consensus),
+        };
+
+        Ok(FederationPreview {
+            federation_id: config.federation_id,
+            name: config.federation_name().map(str::to_owned),
+            guardians: config.api_endpoints.clone(),
+            modules: config
+                .modules
+                .iter()
+                .map(|(id, module)| (*id, module.kind().clone()))
+                .collect(),
+            fees,
+            network,
+            lightning_network,
+            network_compatible: lightning_network.map_or(true, |ln| ln == network),
+            already_connected: self
+                .clients
+                .read()
+                .await
+                .contains_key(&config.federation_id),
+        })
+    }
+
+    /// Disconnects from a federation the gateway holds no funds in, after
+    /// withdrawing its registration so users stop routing payments through us
+    pub async fn handle_disconnect_federation(&self, payload: DisconnectFedPayload) -> Result<()> {
+        let DisconnectFedPayload { federation_id } = payload;
+        let client = self.select_client(federation_id).await?;
+
+        let balance = client.get_balance().await;
+        if balance != Amount::ZERO {
+            return Err(GatewayError::Other(anyhow!(
+                "Gateway still holds {balance} in federation {federation_id}, withdraw it first"
+            )));
+        }
+        let in_flight = self
+            .in_flight_operations()
+            .await
+            .into_iter()
+            .filter(|op| op.federation_id == federation_id)
+            .count();
+        if in_flight != 0 {
+            return Err(GatewayError::Other(anyhow!(
+                "{in_flight} payments are still in flight in federation {federation_id}"
+            )));
+        }
+
+        // There is no way to remove a registration, so replace it with one that
+        // has already expired
+        let (route_hints, _, _) = self.fetch_lightning_route_info().await?;
+        Self::register_and_wait(&client, self.api.clone(), route_hints, Duration::ZERO).await?;
+
+        // TODO: the client keeps running its executor until the gateway restarts
+        self.remove_client(federation_id).await?;
+        self.scid_to_federation
+            .write()
+            .await
+            .retain(|_, id| *id != federation_id);
+        let dbtx = self.gatewayd_db.begin_transaction().await;
+        self.client_builder
+            .remove_config(federation_id, dbtx)
+            .await?;
+
+        info!(%federation_id, "Disconnected from federation");
+        Ok(())
+    }
+
+    /// Bitcoin network of the federation, as configured in its lightning
+    /// module
+    fn federation_network(config: &ClientConfig) -> Result<Network> {
+        config
+            .get_first_module_by_kind::<LightningClientConfig>(KIND)
+            .map(|(_, cfg)| cfg.network)
+            .map_err(GatewayError::Other)
+    }
+
+    /// Bitcoin network of the lightning node, `None` if it doesn't report one
+    async fn lightning_network(&self) -> Result<Option<Network>> {
+        let GetNodeInfoResponse { network, .. } = self.lnrpc.info().await?;
+        if network.is_empty() {
+            return Ok(None);
+        }
+        Network::from_str(&network).map(Some).map_err(|e| {
+            GatewayError::Other(anyhow!(
+                "Lightning node reported unknown network {network}: {e}"
+            ))
+        })
+    }
+
+    /// The gateway can only route payments of federations on the same network
+    /// as its lightning node
+    async fn ensure_network_compatible(&self, config: &ClientConfig) -> Result<()> {
+        let network = Self::federation_network(config)?;
+        match self.lightning_network().await? {
+            Some(lightning_network) if lightning_network != network => {
+                Err(GatewayError::Other(anyhow!(
+                    "Federation is on {network} but the lightning node is on {lightning_network}"
+                )))
+            }
+            Some(_) => Ok(()),
+            None => {
+                warn!("Lightning node doesn't report its network, can't check it matches the federation");
+                Ok(())
+            }
+        }
+    }
+
     pub async fn handle_get_info(&self, _payload: InfoPayload) -> Result<GatewayInfo> {
         let mut federations = Vec::new();
         let federation_clients = self.clients.read().await.clone().into_iter();
@@ -609,18 +1209,119 @@ impl Gateway {
             version_hash: env!("FEDIMINT_BUILD_CODE_VERSION").to_string(),
             lightning_pub_key: node_pub_key.to_hex(),
             lightning_alias: alias,
-            fees: self.fees,
+            fees: *self.fees.read().await,
         })
     }
 
+    /// Applies configuration changes to the running gateway, fields of the
+    /// payload left empty keep their current value
+    ///
+    /// New fees are stored with every connected federation and announced to
+    /// them by registering again.
+    pub async fn handle_reload_msg(&self, payload: ReloadPayload) -> Result<GatewayInfo> {
+        let ReloadPayload {
+            base_msat,
+            proportional_millionths,
+            cln_extension_addr,
+        } = payload;
+
+        if let Some(cln_extension_addr) = cln_extension_addr {
+            let mut lightning_mode = self.lightning_mode.write().await;
+            match (lightning_mode.as_mut(), &self.cln_lnrpc) {
+                (
+                    Some(LightningMode::Cln {
+                        cln_extension_addr: addr,
+                    }),
+                    Some(cln_lnrpc),
+                ) => {
+                    cln_lnrpc.set_connection_url(cln_extension_addr.clone());
+                    // The HTLC stream picks up the new address when it reconnects
+                    *addr = cln_extension_addr;
+                }
+                _ => {
+                    return Err(GatewayError::Other(anyhow!(
+                        "Gateway is not connected to a CLN extension"
+                    )))
+                }
+            }
+        }
+
+        let fees = {
+            let mut fees = self.fees.write().await;
+            let previous = *fees;
+            fees.base_msat = base_msat.unwrap_or(previous.base_msat);
+            fees.proportional_millionths =
+                proportional_millionths.unwrap_or(previous.proportional_millionths);
+            (*fees != previous).then_some(*fees)
+        };
+        if let Some(fees) = fees {
+            info!(?fees, "Gateway fees changed");
+            self.update_federation_fees(fees).await?;
+        }
+
+        self.handle_get_info(InfoPayload).await
+    }
+
+    async fn update_federation_fees(&self, fees: RoutingFees) -> Result<()> {
+        let clients = self.clients.read().await.clone();
+
+        let mut dbtx = self.gatewayd_db.begin_transaction().await;
+        for federation_id in clients.keys() {
+            let key = FederationIdKey { id: *federation_id };
+            if let Some(mut config) = dbtx.get_value(&key).await {
+                config.fees = fees;
+                dbtx.insert_entry(&key, &config).await;
+            }
+        }
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|_| GatewayError::DatabaseError)?;
+
+        let (route_hints, _, _) = self.fetch_lightning_route_info().await?;
+        for client in clients.values() {
+            let (gateway, _) = client.get_first_module::<GatewayClientModule>(&KIND);
+            gateway.set_fees(fees);
+            Self::register_and_wait(
+                client,
+                self.api.clone(),
+                route_hints.clone(),
+                GW_ANNOUNCEMENT_TTL,
+            )
+            .await?;
+        }
+        Ok(())
+    }
+
     async fn handle_pay_invoice_msg(&self, payload: PayInvoicePayload) -> Result<Preimage> {
         let PayInvoicePayload {
             federation_id,
             contract_id,
+            keysend,
         } = payload;
 
+        self.ensure_not_draining()?;
         let client = self.select_client(federation_id).await?;
-        let operation_id = client.gateway_pay_bolt11_invoice(contract_id).await?;
+        // Replays of a payment we already started aren't counted again
+        let operation_id = OperationId(contract_id.into_inner());
+        if client
+            .operation_log()
+            .get_operation(operation_id)
+            .await
+            .is_none()
+        {
+            let amount = client.gateway_outgoing_contract_amount(contract_id).await?;
+            self.payment_throttle
+                .admit(federation_id, PaymentDirection::Outgoing, amount, now())
+                .map_err(|error| {
+                    warn!(%contract_id, "Rejecting outgoing payment: {error}");
+                    error
+                })?;
+        }
+
+        let operation_id = match keysend {
+            Some(keysend) => client.gateway_pay_keysend(contract_id, keysend).await?,
+            None => client.gateway_pay_bolt11_invoice(contract_id).await?,
+        };
         let mut updates = client
             .gateway_subscribe_ln_pay(operation_id)
             .await?
@@ -704,6 +1405,86 @@ impl Gateway {
         )));
     }
 
+    /// Stops accepting new payments and waits for the ones in flight to
+    /// settle, then shuts the gateway down
+    ///
+    /// Operations still in flight when the timeout expires are reported and
+    /// resumed from the database by the next start of the gateway.
+    pub async fn handle_drain_msg(&self, payload: DrainPayload) -> Result<DrainResponse> {
+        let timeout = payload
+            .timeout_secs
+            .map_or(DEFAULT_DRAIN_TIMEOUT, Duration::from_secs);
+        if !self.draining.swap(true, Ordering::SeqCst) {
+            info!(?timeout, "Draining gateway");
+        }
+
+        let deadline = now() + timeout;
+        let in_flight = loop {
+            let in_flight = self.in_flight_operations().await;
+            if in_flight.is_empty() || now() >= deadline {
+                break in_flight;
+            }
+            sleep(DRAIN_POLL_INTERVAL).await;
+        };
+
+        if in_flight.is_empty() {
+            info!("Gateway drained, shutting down");
+        } else {
+            warn!(
+                in_flight = in_flight.len(),
+                "Drain timed out, shutting down with operations in flight"
+            );
+        }
+
+        // Shut down in the background so the webserver can still respond
+        let task_group = self.task_group.clone();
+        self.task_group
+            .clone()
+            .spawn("shutdown after drain", move |_| async move {
+                sleep(DRAIN_POLL_INTERVAL).await;
+                task_group.shutdown().await;
+            })
+            .await;
+
+        Ok(DrainResponse {
+            drained: in_flight.is_empty(),
+            in_flight,
+        })
+    }
+
+    /// Payments and HTLCs the gateway is still settling with its federations
+    async fn in_flight_operations(&self) -> Vec<InFlightOperation> {
+        let mut in_flight = Vec::new();
+        for (federation_id, client) in self.clients.read().await.iter() {
+            for operation_id in client.get_active_operations().await {
+                let Some(operation) = client.operation_log().get_operation(operation_id).await
+                else {
+                    continue;
+                };
+                if operation.operation_type() != KIND.as_str() {
+                    continue;
+                }
+                // Registrations stay active to renew themselves
+                if let GatewayMeta::Pay | GatewayMeta::Receive = operation.meta::<GatewayMeta>() {
+                    in_flight.push(InFlightOperation {
+                        federation_id: *federation_id,
+                        operation_id,
+                    });
+                }
+            }
+        }
+        in_flight
+    }
+
+    fn ensure_not_draining(&self) -> Result<()> {
+        if self.draining.load(Ordering::SeqCst) {
+            return Err(GatewayError::Other(anyhow!(
+                "Gateway is shutting down and doesn't accept new payments"
+            )));
+        }
+        Ok(())
+    }
+
     pub async fn handle_backup_msg(
         &self,
         BackupPayload { federation_id: _ }: BackupPayload,
@@ -718,6 +1499,373 @@ impl Gateway {
         unimplemented!("Restore is not currently supported");
     }
 
+    /// Collects what is needed to recover the gateway's funds into a
+    /// [`RecoveryBundle`], after uploading a fresh ecash backup to every
+    /// federation
+    pub async fn handle_export_recovery_msg(
+        &self,
+        _payload: ExportRecoveryPayload,
+    ) -> Result<RecoveryBundle> {
+        let GetNodeInfoResponse { pub_key, .. } = self.lnrpc.info().await?;
+        let channel_backup = match self.lnrpc.export_channel_backup().await {
+            Ok(backup) => Some(backup),
+            Err(e) => {
+                warn!("Exporting recovery bundle without a channel backup: {e:?}");
+                None
+            }
+        };
+
+        let clients = self.clients.read().await.clone();
+        let mut dbtx = self.gatewayd_db.begin_transaction().await;
+        let mut federations = Vec::new();
+        for (federation_id, client) in clients {
+            let config = dbtx
+                .get_value(&FederationIdKey { id: federation_id })
+                .await
+                .ok_or_else(|| {
+                    GatewayError::Other(anyhow!("No config for federation {federation_id}"))
+                })?;
+            client
+                .backup_to_federation(Metadata::empty())
+                .await
+                .map_err(GatewayError::Other)?;
+            federations.push(FederationRecovery {
+                config,
+                secret: ClientSecret::new(
+                    client
+                        .root_secret_encoding::<PlainRootSecretStrategy>()
+                        .await,
+                ),
+                balance: client.get_balance().await,
+            });
+        }
+
+        info!(
+            federations = federations.len(),
+            channels = channel_backup.is_some(),
+            "Exported recovery bundle"
+        );
+        Ok(RecoveryBundle {
+            node_pub_key: pub_key,
+            channel_backup,
+            federations,
+        })
+    }
+
+    /// Restores the ecash and channels of a [`RecoveryBundle`], see
+    /// [`recovery`] for the steps before
+    pub async fn handle_import_recovery_msg(
+        &mut self,
+        payload: ImportRecoveryPayload,
+    ) -> Result<RecoveryReport> {
+        let RecoveryBundle {
+            node_pub_key,
+            channel_backup,
+            federations,
+        } = payload.bundle;
+
+        // Checked first, so nothing is restored if the wrong node is connected
+        let GetNodeInfoResponse { pub_key, .. } = self.lnrpc.info().await?;
+        if channel_backup.is_some() && pub_key != node_pub_key {
+            return Err(GatewayError::Other(anyhow!(
+                "Channel backup belongs to node {} but the gateway is connected to {}, recreate \
+                 the node from its seed first",
+                node_pub_key.to_hex(),
+                pub_key.to_hex()
+            )));
+        }
+
+        let (route_hints, _, _) = self.fetch_lightning_route_info().await?;
+        let mut restored = Vec::new();
+        for FederationRecovery {
+            mut config,
+            secret,
+            balance,
+        } in federations
+        {
+            let federation_id = config.config.federation_id;
+            if self.clients.read().await.contains_key(&federation_id) {
+                info!(%federation_id, "Already connected to federation, not restoring it");
+                continue;
+            }
+            self.ensure_network_compatible(&config.config).await?;
+
+            // Federations connected before restoring may have taken the channel id
+            let channel_id_generator = self.channel_id_generator.lock().await;
+            if self
+                .scid_to_federation
+                .read()
+                .await
+                .contains_key(&config.mint_channel_id)
+            {
+                config.mint_channel_id = channel_id_generator.fetch_add(1, Ordering::SeqCst);
+            } else {
+                channel_id_generator.fetch_max(config.mint_channel_id + 1, Ordering::SeqCst);
+            }
+            drop(channel_id_generator);
+
+            let client = self
+                .client_builder
+                .restore(
+                    config.clone(),
+                    self.lnrpc.clone(),
+                    &mut self.task_group,
+                    secret,
+                )
+                .await?;
+            let restored_balance = client.get_balance().await;
+            self.register_client(
+                client,
+                federation_id,
+                config.mint_channel_id,
+                route_hints.clone(),
+            )
+            .await?;
+            let dbtx = self.gatewayd_db.begin_transaction().await;
+            self.client_builder.save_config(config, dbtx).await?;
+
+            info!(%federation_id, balance = %restored_balance, "Restored federation");
+            restored.push(RestoredFederation {
+                federation_id,
+                balance: restored_balance,
+                exported_balance: balance,
+            });
+        }
+
+        let channels_restored = match channel_backup {
+            Some(backup) => {
+                self.lnrpc.restore_channel_backup(backup).await?;
+                info!("Restored channel backup, the channels will be force closed");
+                true
+            }
+            None => false,
+        };
+
+        Ok(RecoveryReport {
+            federations: restored,
+            channels_restored,
+        })
+    }
+
+    /// Lets the owner of `registration.pub_key` receive payments to
+    /// `name@gateway`, a name can't be taken over by a different key
+    pub async fn handle_register_lightning_address(
+        &self,
+        registration: LightningAddressRegistration,
+    ) -> Result<()> {
+        registration.verify(&Secp256k1::verification_only())?;
+        // Payments can only be routed into federations we are connected to
+        self.select_client(registration.federation_id).await?;
+
+        let mut dbtx = self.gatewayd_db.begin_transaction().await;
+        let key = LightningAddressKey {
+            name: registration.name.clone(),
+        };
+        if let Some(existing) = dbtx.get_value(&key).await {
+            if existing.pub_key != registration.pub_key {
+                return Err(GatewayError::Other(anyhow!(
+                    "Lightning address {} is already taken",
+                    registration.name
+                )));
+            }
+        }
+        dbtx.insert_entry(&key, &registration).await;
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|_| GatewayError::DatabaseError)?;
+        Ok(())
+    }
+
+    /// The LUD-06 pay request served for `name@gateway`
+    pub async fn handle_lnurl_pay_request(&self, name: String) -> Result<LnurlPayResponse> {
+        self.lightning_address_registration(&name).await?;
+        let callback = self
+            .api
+            .join(&format!("lnurlp/{name}/callback"))
+            .map_err(|e| GatewayError::Other(e.into()))?;
+
+        Ok(LnurlPayResponse {
+            callback,
+            min_sendable: LIGHTNING_ADDRESS_MIN_SENDABLE,
+            max_sendable: LIGHTNING_ADDRESS_MAX_SENDABLE,
+            metadata: self.lnurl_metadata(&name),
+            tag: "payRequest".to_string(),
+        })
+    }
+
+    /// Creates an invoice paying `amount` into the ecash balance of the owner
+    /// of `name`
+    pub async fn handle_lnurl_callback(&self, name: String, amount: Amount) -> Result<Invoice> {
+        if amount < LIGHTNING_ADDRESS_MIN_SENDABLE || amount > LIGHTNING_ADDRESS_MAX_SENDABLE {
+            return Err(GatewayError::Other(anyhow!(
+                "Amount must be between {LIGHTNING_ADDRESS_MIN_SENDABLE} and {LIGHTNING_ADDRESS_MAX_SENDABLE}"
+            )));
+        }
+
+        self.ensure_not_draining()?;
+        let registration = self.lightning_address_registration(&name).await?;
+        let client = self.select_client(registration.federation_id).await?;
+        let tweak = rand::thread_rng().gen::<[u8; 32]>();
+        let preimage_key = tweak_pub_key(
+            &Secp256k1::verification_only(),
+            &registration.pub_key,
+            tweak,
+        )?;
+        let (route_hints, _, _) = self.fetch_lightning_route_info().await?;
+
+        // LUD-06 requires the invoice to commit to the metadata we served
+        let description_hash = sha256::Hash::hash(self.lnurl_metadata(&name).as_bytes());
+        let invoice = client
+            .gateway_create_offer_invoice(
+                amount,
+                description_hash,
+                preimage_key,
+                route_hints,
+                LIGHTNING_ADDRESS_INVOICE_EXPIRY,
+            )
+            .await?;
+
+        let mut dbtx = self.gatewayd_db.begin_transaction().await;
+        dbtx.insert_new_entry(
+            &LightningAddressPaymentKey {
+                name,
+                payment_hash: *invoice.payment_hash(),
+            },
+            &LightningAddressPayment {
+                invoice: invoice.clone(),
+                tweak,
+            },
+        )
+        .await;
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|_| GatewayError::DatabaseError)?;
+        Ok(invoice)
+    }
+
+    /// Lists the payments the owner of a lightning address can claim
+    pub async fn handle_lightning_address_payments(
+        &self,
+        request: LightningAddressPaymentsRequest,
+    ) -> Result<Vec<LightningAddressPayment>> {
+        let registration = self.lightning_address_registration(&request.name).await?;
+        request.verify(
+            &Secp256k1::verification_only(),
+            &registration.pub_key,
+            now(),
+        )?;
+
+        let mut dbtx = self.gatewayd_db.begin_transaction().await;
+        let payments = dbtx
+            .find_by_prefix(&LightningAddressPaymentPrefix { name: request.name })
+            .await
+            .map(|(_, payment)| payment)
+            .collect()
+            .await;
+        Ok(payments)
+    }
+
+    async fn lightning_address_registration(
+        &self,
+        name: &str,
+    ) -> Result<LightningAddressRegistration> {
+        self.gatewayd_db
+            .begin_transaction()
+            .await
+            .get_value(&LightningAddressKey {
+                name: name.to_owned(),
+            })
+            .await
+            .ok_or_else(|| GatewayError::Other(anyhow!("Unknown lightning address {name}")))
+    }
+
+    /// The LUD-06 metadata of `name`, its hash is the description of invoices
+    fn lnurl_metadata(&self, name: &str) -> String {
+        let identifier = format!("{name}@{}", self.api.host_str().unwrap_or_default());
+        serde_json::json!([
+            ["text/plain", format!("Payment to {identifier}")],
+            ["text/identifier", identifier],
+        ])
+        .to_string()
+    }
+
+    /// Announces every federation we are connected to on the nostr `relays`,
+    /// repeating it every [`ANNOUNCEMENT_INTERVAL`] to pick up new ones
+    pub async fn spawn_nostr_announcer(&mut self, relays: Vec<Url>) -> Result<()> {
+        let announcer = NostrAnnouncer::new(self.nostr_secret_key().await?, &relays).await?;
+        info!(public_key = %announcer.public_key(), "Announcing gateway on nostr");
+
+        let lnrpc = self.lnrpc.clone();
+        let clients = self.clients.clone();
+        let api = self.api.clone();
+        let gateway_fees = self.fees.clone();
+        self.task_group
+            .spawn("nostr announcer", move |handle| async move {
+                let mut shutdown_rx = handle.make_shutdown_rx().await;
+                loop {
+                    // Read on every announcement to follow reloads of the configuration
+                    let fees = {
+                        let fees = gateway_fees.read().await;
+                        GatewayFees {
+                            base_msat: fees.base_msat,
+                            proportional_millionths: fees.proportional_millionths,
+                        }
+                    };
+                    if let Err(e) =
+                        Self::announce_federations(&announcer, &*lnrpc, &clients, &api, fees).await
+                    {
+                        warn!("Failed to announce gateway on nostr: {e:?}");
+                    }
+                    tokio::select! {
+                        _ = sleep(ANNOUNCEMENT_INTERVAL) => {},
+                        _ = &mut shutdown_rx => break,
+                    }
+                }
+            })
+            .await;
+        Ok(())
+    }
+
+    async fn announce_federations(
+        announcer: &NostrAnnouncer,
+        lnrpc: &dyn ILnRpcClient,
+        clients: &RwLock<BTreeMap<FederationId, Arc<fedimint_client::Client>>>,
+        api: &Url,
+        fees: GatewayFees,
+    ) -> Result<()> {
+        let GetNodeInfoResponse { pub_key, .. } = lnrpc.info().await?;
+        for (federation_id, client) in clients.read().await.iter() {
+            let announcement = GatewayAnnouncement {
+                federation_id: *federation_id,
+                api: api.clone(),
+                lightning_pub_key: pub_key.to_hex(),
+                fees,
+                modules: client
+                    .get_config()
+                    .modules
+                    .values()
+                    .map(|module| module.kind().clone())
+                    .collect(),
+            };
+            announcer.announce(&announcement).await?;
+        }
+        Ok(())
+    }
+
+    async fn nostr_secret_key(&self) -> Result<[u8; 32]> {
+        let mut dbtx = self.gatewayd_db.begin_transaction().await;
+        if let Some(secret_key) = dbtx.get_value(&NostrSecretKeyKey).await {
+            return Ok(secret_key);
+        }
+
+        let secret_key = rand::thread_rng().gen::<[u8; 32]>();
+        dbtx.insert_new_entry(&NostrSecretKeyKey, &secret_key).await;
+        dbtx.commit_tx_result()
+            .await
+            .map_err(|_| GatewayError::DatabaseError)?;
+        Ok(secret_key)
+    }
+
     pub async fn spawn_blocking_webserver(self, listen: SocketAddr, password: String) {
         let rx = run_webserver(password, listen, self)
             .await
@@ -0,0 +1,197 @@
+//! Caps on the amounts the gateway moves for the users of its federations
+//!
+//! Operators bound the exposure of their hot wallet with a limit per payment
+//! and a limit on the volume per hour. Both apply to payments out of a
+//! federation (ecash to lightning) and into it (lightning to ecash) and are
+//! checked before the gateway commits funds, i.e. before paying the invoice
+//! of an outgoing contract or funding an incoming contract.
+//!
+//! Users are anonymous towards the gateway, outgoing contracts use a fresh
+//! key for every payment, so the hourly volume is tracked for all users of a
+//! federation together, separately for each direction. Payments count
+//! towards it once they are accepted, even if they fail later on.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use fedimint_core::config::FederationId;
+use fedimint_core::Amount;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Window of the hourly limit
+const THROTTLE_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PaymentLimits {
+    /// Largest single payment, unlimited if not set
+    pub max_payment: Option<Amount>,
+    /// Largest volume per federation and direction in any hour, unlimited if
+    /// not set
+    pub max_hourly: Option<Amount>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentDirection {
+    /// A user spends ecash to pay a lightning invoice
+    Outgoing,
+    /// A user receives ecash for a lightning payment
+    Incoming,
+}
+
+/// Why a payment was rejected, returned to the user
+#[derive(Debug, Clone, Error, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "error", rename_all = "snake_case")]
+pub enum PaymentLimitError {
+    #[error("Payment of {amount} exceeds the gateway's limit of {limit} per payment")]
+    PaymentTooLarge { amount: Amount, limit: Amount },
+    #[error("Payment of {amount} exceeds the gateway's hourly limit of {limit}, {remaining} remain in this hour")]
+    HourlyLimitExceeded {
+        amount: Amount,
+        limit: Amount,
+        remaining: Amount,
+        /// Seconds until enough of the hourly limit is available again
+        retry_after_secs: u64,
+    },
+}
+
+/// Enforces the [`PaymentLimits`] and keeps track of the recent payments
+#[derive(Debug, Default)]
+pub struct PaymentThrottle {
+    limits: PaymentLimits,
+    recent: Mutex<BTreeMap<(FederationId, PaymentDirection), VecDeque<(SystemTime, Amount)>>>,
+}
+
+impl PaymentThrottle {
+    pub fn new(limits: PaymentLimits) -> Self {
+        PaymentThrottle {
+            limits,
+            recent: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Checks a payment of `amount` against the limits and counts it towards
+    /// the hourly volume if it is accepted
+    pub fn admit(
+        &self,
+        federation_id: FederationId,
+        direction: PaymentDirection,
+        amount: Amount,
+        now: SystemTime,
+    ) -> Result<(), PaymentLimitError> {
+        if let Some(limit) = self.limits.max_payment {
+            if amount > limit {
+                return Err(PaymentLimitError::PaymentTooLarge { amount, limit });
+            }
+        }
+
+        let mut recent = self.recent.lock().expect("poisoned");
+        let payments = recent.entry((federation_id, direction)).or_default();
+        while payments
+            .front()
+            .map_or(false, |(time, _)| *time + THROTTLE_WINDOW <= now)
+        {
+            payments.pop_front();
+        }
+
+        if let Some(limit) = self.limits.max_hourly {
+            let volume = payments
+                .iter()
+                .fold(Amount::ZERO, |volume, (_, amount)| volume + *amount);
+            let remaining = limit.saturating_sub(volume);
+            if amount > remaining {
+                return Err(PaymentLimitError::HourlyLimitExceeded {
+                    amount,
+                    limit,
+                    remaining,
+                    retry_after_secs: Self::retry_after(payments, amount - remaining, now)
+                        .as_secs(),
+                });
+            }
+        }
+
+        payments.push_back((now, amount));
+        Ok(())
+    }
+
+    /// Time until payments adding up to `missing` drop out of the window
+    fn retry_after(
+        payments: &VecDeque<(SystemTime, Amount)>,
+        missing: Amount,
+        now: SystemTime,
+    ) -> Duration {
+        let mut freed = Amount::ZERO;
+        for (time, amount) in payments {
+            freed += *amount;
+            if freed >= missing {
+                return (*time + THROTTLE_WINDOW)
+                    .duration_since(now)
+                    .unwrap_or_default();
+            }
+        }
+        // The payment is larger than the hourly limit itself
+        THROTTLE_WINDOW
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use super::*;
+
+    fn throttle() -> PaymentThrottle {
+        PaymentThrottle::new(PaymentLimits {
+            max_payment: Some(Amount::from_sats(1_000)),
+            max_hourly: Some(Amount::from_sats(2_000)),
+        })
+    }
+
+    #[test]
+    fn rejects_large_payments() {
+        assert_eq!(
+            throttle().admit(
+                FederationId::dummy(),
+                PaymentDirection::Outgoing,
+                Amount::from_sats(1_001),
+                UNIX_EPOCH
+            ),
+            Err(PaymentLimitError::PaymentTooLarge {
+                amount: Amount::from_sats(1_001),
+                limit: Amount::from_sats(1_000),
+            })
+        );
+    }
+
+    #[test]
+    fn throttles_hourly_volume() {
+        let throttle = throttle();
+        let federation_id = FederationId::dummy();
+        let admit = |direction, sats, secs| {
+            throttle.admit(
+                federation_id,
+                direction,
+                Amount::from_sats(sats),
+                UNIX_EPOCH + Duration::from_secs(secs),
+            )
+        };
+
+        assert!(admit(PaymentDirection::Outgoing, 1_000, 0).is_ok());
+        assert!(admit(PaymentDirection::Outgoing, 800, 600).is_ok());
+        assert_eq!(
+            admit(PaymentDirection::Outgoing, 500, 1_200),
+            Err(PaymentLimitError::HourlyLimitExceeded {
+                amount: Amount::from_sats(500),
+                limit: Amount::from_sats(2_000),
+                remaining: Amount::from_sats(200),
+                retry_after_secs: 2_400,
+            })
+        );
+        // Directions are throttled separately
+        assert!(admit(PaymentDirection::Incoming, 500, 1_200).is_ok());
+        // The first payment dropped out of the window
+        assert!(admit(PaymentDirection::Outgoing, 500, 3_600).is_ok());
+    }
+}
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::fmt;
 use std::str::FromStr;
 use std::time::Duration;
@@ -10,7 +11,11 @@ use tokio::sync::mpsc;
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::Status;
 use tonic_lnd::lnrpc::failure::FailureCode;
-use tonic_lnd::lnrpc::{ChanInfoRequest, GetInfoRequest, ListChannelsRequest, SendRequest};
+use tonic_lnd::lnrpc::restore_chan_backup_request::Backup;
+use tonic_lnd::lnrpc::{
+    ChanBackupExportRequest, ChanInfoRequest, FeatureBit, GetInfoRequest, ListChannelsRequest,
+    RestoreChanBackupRequest, SendRequest,
+};
 use tonic_lnd::routerrpc::{
     CircuitKey, ForwardHtlcInterceptResponse, ResolveHoldForwardAction, TrackPaymentRequest,
 };
@@ -24,7 +29,7 @@ use crate::gatewaylnrpc::{
     GetNodeInfoResponse, GetRouteHintsResponse, InterceptHtlcRequest, InterceptHtlcResponse,
     PayInvoiceRequest, PayInvoiceResponse,
 };
-use crate::lnrpc_client::{ILnRpcClient, RouteHtlcStream, MAX_LIGHTNING_RETRIES};
+use crate::lnrpc_client::{ILnRpcClient, RouteHtlcStream, KEYSEND_TLV_TYPE, MAX_LIGHTNING_RETRIES};
 use crate::GatewayError;
 
 type HtlcSubscriptionSender = mpsc::Sender<Result<InterceptHtlcRequest, Status>>;
@@ -286,9 +291,17 @@ impl ILnRpcClient for GatewayLndClient {
             ))
         })?;
 
+        // LND calls the main network `mainnet`
+        let network = match info.chains.first() {
+            Some(chain) if chain.network == "mainnet" => "bitcoin".to_string(),
+            Some(chain) => chain.network.clone(),
+            None => String::new(),
+        };
+
         return Ok(GetNodeInfoResponse {
             pub_key: pub_key.serialize().to_vec(),
             alias: info.alias,
+            network,
         });
     }
 
@@ -381,12 +394,23 @@ impl ILnRpcClient for GatewayLndClient {
             bitcoin_hashes::hex::FromHex::from_hex(preimage.as_str())
                 .map_err(|_| anyhow::anyhow!("Failed to convert preimage"))?
         } else {
-            let send_response = client
-                .lightning()
-                .send_payment_sync(SendRequest {
+            let request = match invoice.keysend {
+                Some(keysend) => SendRequest {
+                    dest: keysend.destination,
+                    amt_msat: keysend.amount_msat as i64,
+                    payment_hash: invoice.payment_hash.clone(),
+                    dest_custom_records: HashMap::from([(KEYSEND_TLV_TYPE, keysend.preimage)]),
+                    dest_features: vec![FeatureBit::TlvOnionReq as i32],
+                    ..Default::default()
+                },
+                None => SendRequest {
                     payment_request: invoice.invoice.to_string(),
                     ..Default::default()
-                })
+                },
+            };
+            let send_response = client
+                .lightning()
+                .send_payment_sync(request)
                 .await
                 .map_err(|e| anyhow::anyhow!(format!("LND error: {e:?}")))?
                 .into_inner();
@@ -460,4 +484,53 @@ impl ILnRpcClient for GatewayLndClient {
 
         Ok(Box::pin(ReceiverStream::new(actor_receiver)))
     }
+
+    async fn export_channel_backup(&self) -> crate::Result<Vec<u8>> {
+        let mut client = Self::connect(
+            self.address.clone(),
+            self.tls_cert.clone(),
+            self.macaroon.clone(),
+        )
+        .await?;
+        let snapshot = client
+            .lightning()
+            .export_all_channel_backups(ChanBackupExportRequest {})
+            .await
+            .map_err(|e| {
+                GatewayError::LnRpcError(tonic::Status::new(
+                    tonic::Code::Internal,
+                    format!("LND error: {e:?}"),
+                ))
+            })?
+            .into_inner();
+
+        // The multi-channel backup covers all channels and is what LND writes
+        // to its `channel.backup` file
+        snapshot
+            .multi_chan_backup
+            .map(|backup| backup.multi_chan_backup)
+            .ok_or_else(|| GatewayError::Other(anyhow!("LND returned no channel backup")))
+    }
+
+    async fn restore_channel_backup(&self, backup: Vec<u8>) -> crate::Result<()> {
+        let mut client = Self::connect(
+            self.address.clone(),
+            self.tls_cert.clone(),
+            self.macaroon.clone(),
+        )
+        .await?;
+        client
+            .lightning()
+            .restore_channel_backups(RestoreChanBackupRequest {
+                backup: Some(Backup::MultiChanBackup(backup)),
+            })
+            .await
+            .map_err(|e| {
+                GatewayError::LnRpcError(tonic::Status::new(
+                    tonic::Code::Internal,
+                    format!("LND error: {e:?}"),
+                ))
+            })?;
+        Ok(())
+    }
 }
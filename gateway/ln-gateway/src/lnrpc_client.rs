@@ -1,4 +1,5 @@
 use std::fmt::Debug;
+use std::sync::RwLock;
 use std::time::Duration;
 
 use async_trait::async_trait;
@@ -22,6 +23,9 @@ pub type RouteHtlcStream<'a> =
 
 pub const MAX_LIGHTNING_RETRIES: u32 = 10;
 
+/// Type of the onion TLV record carrying the preimage of a keysend payment
+pub const KEYSEND_TLV_TYPE: u64 = 5482373484;
+
 #[async_trait]
 pub trait ILnRpcClient: Debug + Send + Sync {
     /// Get the public key and alias of the lightning node
@@ -30,7 +34,8 @@ pub trait ILnRpcClient: Debug + Send + Sync {
     /// Get route hints to the lightning node
     async fn routehints(&self) -> Result<GetRouteHintsResponse>;
 
-    /// Attempt to pay an invoice using the lightning node
+    /// Attempt to pay an invoice using the lightning node, or to push a
+    /// keysend payment to a node if `keysend` is set
     async fn pay(&self, invoice: PayInvoiceRequest) -> Result<PayInvoiceResponse>;
 
     async fn route_htlcs<'a>(
@@ -38,6 +43,23 @@ pub trait ILnRpcClient: Debug + Send + Sync {
         events: ReceiverStream<InterceptHtlcResponse>,
         task_group: &mut TaskGroup,
     ) -> Result<RouteHtlcStream<'a>>;
+
+    /// Export a static backup of all channels of the lightning node, from
+    /// which the node can force close them after losing its state
+    async fn export_channel_backup(&self) -> Result<Vec<u8>> {
+        Err(GatewayError::Other(anyhow::anyhow!(
+            "Lightning node doesn't support exporting channel backups"
+        )))
+    }
+
+    /// Restore channels from a backup created by
+    /// [`ILnRpcClient::export_channel_backup`], the node has to be recreated
+    /// from the same seed
+    async fn restore_channel_backup(&self, _backup: Vec<u8>) -> Result<()> {
+        Err(GatewayError::Other(anyhow::anyhow!(
+            "Lightning node doesn't support restoring channel backups"
+        )))
+    }
 }
 
 /// An `ILnRpcClient` that wraps around `GatewayLightningClient` for
@@ -46,7 +68,7 @@ pub trait ILnRpcClient: Debug + Send + Sync {
 /// `GatewayLightningServer`.
 #[derive(Debug)]
 pub struct NetworkLnRpcClient {
-    connection_url: Url,
+    connection_url: RwLock<Url>,
 }
 
 impl NetworkLnRpcClient {
@@ -56,10 +78,21 @@ impl NetworkLnRpcClient {
             url.to_string()
         );
         NetworkLnRpcClient {
-            connection_url: url,
+            connection_url: RwLock::new(url),
         }
     }
 
+    /// Connects to the remote lightning node at `url` from the next request
+    /// on, requests that are already running keep their connection
+    pub fn set_connection_url(&self, url: Url) {
+        info!("Gateway lightning connection changed to {url}");
+        *self.connection_url.write().expect("lock poisoned") = url;
+    }
+
+    fn connection_url(&self) -> Url {
+        self.connection_url.read().expect("lock poisoned").clone()
+    }
+
     async fn connect(connection_url: Url) -> Result<GatewayLightningClient<Channel>> {
         let mut retries = 0;
         let client = loop {
@@ -89,21 +122,21 @@ impl NetworkLnRpcClient {
 impl ILnRpcClient for NetworkLnRpcClient {
     async fn info(&self) -> Result<GetNodeInfoResponse> {
         let req = Request::new(EmptyRequest {});
-        let mut client = Self::connect(self.connection_url.clone()).await?;
+        let mut client = Self::connect(self.connection_url()).await?;
         let res = client.get_node_info(req).await?;
         Ok(res.into_inner())
     }
 
     async fn routehints(&self) -> Result<GetRouteHintsResponse> {
         let req = Request::new(EmptyRequest {});
-        let mut client = Self::connect(self.connection_url.clone()).await?;
+        let mut client = Self::connect(self.connection_url()).await?;
         let res = client.get_route_hints(req).await?;
         Ok(res.into_inner())
     }
 
     async fn pay(&self, invoice: PayInvoiceRequest) -> Result<PayInvoiceResponse> {
         let req = Request::new(invoice);
-        let mut client = Self::connect(self.connection_url.clone()).await?;
+        let mut client = Self::connect(self.connection_url()).await?;
         let res = client.pay_invoice(req).await?;
         Ok(res.into_inner())
     }
@@ -113,7 +146,7 @@ impl ILnRpcClient for NetworkLnRpcClient {
         events: ReceiverStream<InterceptHtlcResponse>,
         _task_group: &mut TaskGroup,
     ) -> Result<RouteHtlcStream<'a>> {
-        let mut client = Self::connect(self.connection_url.clone()).await?;
+        let mut client = Self::connect(self.connection_url()).await?;
         let res = client.route_htlcs(events).await?;
         Ok(Box::pin(res.into_inner()))
     }
@@ -0,0 +1,167 @@
+//! Multi-part payments to users of our federations
+//!
+//! Senders split large payments into several HTLCs with the same payment hash.
+//! The incoming contract can only be funded once for the full amount of the
+//! offer, so the parts are held until they add up to it and all of them are
+//! resolved with the outcome of that single contract.
+
+use std::time::{Duration, SystemTime};
+
+use bitcoin_hashes::sha256;
+use fedimint_core::config::FederationId;
+use fedimint_core::Amount;
+
+use crate::db::InterceptedHtlcKey;
+use crate::ng::Htlc;
+
+/// How long the parts of a payment are held before they are cancelled if the
+/// remaining ones don't arrive, senders usually give up on a payment earlier
+pub const MPP_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Parts of a payment to one of our federations that arrived so far
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct HtlcSet {
+    pub federation_id: FederationId,
+    pub payment_hash: sha256::Hash,
+    /// Amount of the offer the parts have to add up to
+    pub offer_amount: Amount,
+    pub parts: Vec<Htlc>,
+    /// Distinguishes the set from later ones for the same payment hash
+    pub started_at: SystemTime,
+}
+
+impl HtlcSet {
+    pub fn new(
+        federation_id: FederationId,
+        payment_hash: sha256::Hash,
+        offer_amount: Amount,
+        started_at: SystemTime,
+    ) -> Self {
+        HtlcSet {
+            federation_id,
+            payment_hash,
+            offer_amount,
+            parts: vec![],
+            started_at,
+        }
+    }
+
+    /// Adds a part, the lightning node hands us parts we hold again when the
+    /// HTLC stream reconnects and those are ignored
+    pub fn add(&mut self, htlc: Htlc) {
+        debug_assert_eq!(htlc.payment_hash, self.payment_hash);
+        if !self.parts.iter().any(|part| {
+            part.incoming_chan_id == htlc.incoming_chan_id && part.htlc_id == htlc.htlc_id
+        }) {
+            self.parts.push(htlc);
+        }
+    }
+
+    /// Sum of the parts we forward to the federation
+    pub fn outgoing_amount(&self) -> Amount {
+        self.parts
+            .iter()
+            .map(|part| part.outgoing_amount_msat)
+            .sum()
+    }
+
+    /// Whether the parts pay for the offer and the contract can be funded
+    pub fn is_complete(&self) -> bool {
+        !self.parts.is_empty() && self.outgoing_amount() >= self.offer_amount
+    }
+
+    /// A single HTLC standing in for all parts when funding the contract,
+    /// identified like the first part and expiring with the earliest one
+    pub fn combined_htlc(&self) -> Htlc {
+        let first = self.parts.first().expect("Sets always contain a part");
+        Htlc {
+            payment_hash: self.payment_hash,
+            incoming_amount_msat: self
+                .parts
+                .iter()
+                .map(|part| part.incoming_amount_msat)
+                .sum(),
+            outgoing_amount_msat: self.outgoing_amount(),
+            incoming_expiry: self
+                .parts
+                .iter()
+                .map(|part| part.incoming_expiry)
+                .min()
+                .expect("Sets always contain a part"),
+            short_channel_id: first.short_channel_id,
+            incoming_chan_id: first.incoming_chan_id,
+            htlc_id: first.htlc_id,
+        }
+    }
+
+    /// Database keys of the parts
+    pub fn keys(&self) -> impl Iterator<Item = InterceptedHtlcKey> + '_ {
+        self.parts.iter().map(|part| InterceptedHtlcKey {
+            incoming_chan_id: part.incoming_chan_id,
+            htlc_id: part.htlc_id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use bitcoin_hashes::Hash;
+
+    use super::*;
+
+    fn part(htlc_id: u64, msats: u64, incoming_expiry: u32) -> Htlc {
+        Htlc {
+            payment_hash: sha256::Hash::hash(b"payment"),
+            incoming_amount_msat: Amount::from_msats(msats + 1),
+            outgoing_amount_msat: Amount::from_msats(msats),
+            incoming_expiry,
+            short_channel_id: 1,
+            incoming_chan_id: 2,
+            htlc_id,
+        }
+    }
+
+    fn set(offer_msats: u64) -> HtlcSet {
+        HtlcSet::new(
+            FederationId::dummy(),
+            sha256::Hash::hash(b"payment"),
+            Amount::from_msats(offer_msats),
+            UNIX_EPOCH,
+        )
+    }
+
+    #[test]
+    fn completes_once_parts_cover_offer() {
+        let mut set = set(1_000);
+        assert!(!set.is_complete());
+
+        set.add(part(1, 400, 120));
+        set.add(part(2, 400, 110));
+        assert!(!set.is_complete());
+
+        // Replays of a part we hold don't count twice
+        set.add(part(2, 400, 110));
+        assert_eq!(set.outgoing_amount(), Amount::from_msats(800));
+        assert!(!set.is_complete());
+
+        set.add(part(3, 200, 130));
+        assert!(set.is_complete());
+        assert_eq!(set.keys().count(), 3);
+
+        let combined = set.combined_htlc();
+        assert_eq!(combined.outgoing_amount_msat, Amount::from_msats(1_000));
+        assert_eq!(combined.incoming_amount_msat, Amount::from_msats(1_003));
+        assert_eq!(combined.incoming_expiry, 110);
+        assert_eq!(combined.htlc_id, 1);
+    }
+
+    #[test]
+    fn single_part_payment_is_complete() {
+        let mut set = set(1_000);
+        set.add(part(1, 1_000, 120));
+        assert!(set.is_complete());
+        assert_eq!(set.combined_htlc(), part(1, 1_000, 120));
+    }
+}
@@ -1,8 +1,9 @@
 pub mod pay;
 pub mod register;
 
+use std::iter::once;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use async_stream::stream;
 use bitcoin_hashes::{sha256, Hash};
@@ -23,19 +24,27 @@ use fedimint_core::module::{
 };
 use fedimint_core::{apply, async_trait_maybe_send, Amount, OutPoint, TransactionId};
 use fedimint_ln_client::contracts::ContractId;
+use fedimint_ln_client::network_to_currency;
+use fedimint_ln_client::pay::KeysendPayment;
+use fedimint_ln_common::api::LnFederationApi;
 use fedimint_ln_common::config::LightningClientConfig;
-use fedimint_ln_common::contracts::Preimage;
+use fedimint_ln_common::contracts::incoming::IncomingContractOffer;
+use fedimint_ln_common::contracts::{EncryptedPreimage, Preimage};
 use fedimint_ln_common::incoming::{
     FundingOfferState, IncomingSmCommon, IncomingSmError, IncomingSmStates, IncomingStateMachine,
 };
 use fedimint_ln_common::route_hints::RouteHint;
 use fedimint_ln_common::{
-    create_incoming_contract_output, ln_operation, LightningClientContext, LightningCommonGen,
-    LightningGateway, LightningModuleTypes, LightningOutput, KIND,
+    create_incoming_contract_output, fetch_incoming_offer, ln_operation, LightningClientContext,
+    LightningCommonGen, LightningGateway, LightningModuleTypes, LightningOutput, KIND,
 };
 use futures::StreamExt;
+use lightning::ln::PaymentSecret;
 use lightning::routing::gossip::RoutingFees;
-use secp256k1::{KeyPair, PublicKey, Secp256k1};
+use lightning::routing::router::RouteHintHop;
+use lightning_invoice::{Invoice, InvoiceBuilder};
+use rand::Rng;
+use secp256k1::{KeyPair, PublicKey, Secp256k1, XOnlyPublicKey};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use url::Url;
@@ -95,6 +104,7 @@ pub enum GatewayMeta {
     Pay,
     Receive,
     Register,
+    LightningAddressOffer,
 }
 
 #[apply(async_trait_maybe_send!)]
@@ -105,6 +115,21 @@ pub trait GatewayClientExt {
         contract_id: ContractId,
     ) -> anyhow::Result<OperationId>;
 
+    /// Push the funds of an outgoing contract to a node on behalf of
+    /// federation user, the preimage is chosen by the user
+    async fn gateway_pay_keysend(
+        &self,
+        contract_id: ContractId,
+        keysend: KeysendPayment,
+    ) -> anyhow::Result<OperationId>;
+
+    /// Amount the user locked in the outgoing contract `contract_id`, the
+    /// most we spend paying for it
+    async fn gateway_outgoing_contract_amount(
+        &self,
+        contract_id: ContractId,
+    ) -> anyhow::Result<Amount>;
+
     /// Subscribe to update to lightning payment
     async fn gateway_subscribe_ln_pay(
         &self,
@@ -119,6 +144,13 @@ pub trait GatewayClientExt {
         time_to_live: Duration,
     ) -> anyhow::Result<OperationId>;
 
+    /// Amount the HTLCs paying to `payment_hash` have to add up to before we
+    /// can buy the preimage
+    async fn gateway_incoming_offer_amount(
+        &self,
+        payment_hash: sha256::Hash,
+    ) -> anyhow::Result<Amount>;
+
     /// Attempt fulfill HTLC by buying preimage from the federation
     async fn gateway_handle_intercepted_htlc(&self, htlc: Htlc) -> anyhow::Result<OperationId>;
 
@@ -133,6 +165,17 @@ pub trait GatewayClientExt {
         &self,
         operation_id: OperationId,
     ) -> anyhow::Result<UpdateStreamOrOutcome<'_, GatewayExtRegisterStates>>;
+
+    /// Create an invoice on behalf of a federation user that is offline, the
+    /// preimage is `preimage_key` so only the user can claim the payment
+    async fn gateway_create_offer_invoice(
+        &self,
+        amount: Amount,
+        description_hash: sha256::Hash,
+        preimage_key: XOnlyPublicKey,
+        route_hints: Vec<RouteHint>,
+        expiry_time: u64,
+    ) -> anyhow::Result<Invoice>;
 }
 
 #[apply(async_trait_maybe_send!)]
@@ -142,49 +185,25 @@ impl GatewayClientExt for Client {
         &self,
         contract_id: ContractId,
     ) -> anyhow::Result<OperationId> {
-        let (_, instance) = self.get_first_module::<GatewayClientModule>(&KIND);
-
-        self.db()
-            .autocommit(
-                |dbtx| {
-                    Box::pin(async move {
-                        let operation_id = OperationId(contract_id.into_inner());
-
-                        let state_machines =
-                            vec![GatewayClientStateMachines::Pay(GatewayPayStateMachine {
-                                common: GatewayPayCommon { operation_id },
-                                state: GatewayPayStates::PayInvoice(GatewayPayInvoice {
-                                    contract_id,
-                                }),
-                            })];
-
-                        let dyn_states = state_machines
-                            .into_iter()
-                            .map(|s| s.into_dyn(instance.id))
-                            .collect();
+        start_gateway_pay(self, contract_id, None).await
+    }
 
-                        self.add_state_machines(dbtx, dyn_states).await?;
-                        self.operation_log()
-                            .add_operation_log_entry(
-                                dbtx,
-                                operation_id,
-                                KIND.as_str(),
-                                GatewayMeta::Pay,
-                            )
-                            .await;
+    /// Pushes the funds of a contract to a node with our available funds
+    async fn gateway_pay_keysend(
+        &self,
+        contract_id: ContractId,
+        keysend: KeysendPayment,
+    ) -> anyhow::Result<OperationId> {
+        start_gateway_pay(self, contract_id, Some(keysend)).await
+    }
 
-                        Ok(operation_id)
-                    })
-                },
-                Some(100),
-            )
-            .await
-            .map_err(|e| match e {
-                AutocommitError::ClosureError { error, .. } => error,
-                AutocommitError::CommitFailed { last_error, .. } => {
-                    anyhow::anyhow!("Commit to DB failed: {last_error}")
-                }
-            })
+    async fn gateway_outgoing_contract_amount(
+        &self,
+        contract_id: ContractId,
+    ) -> anyhow::Result<Amount> {
+        let (gateway, _instance) = self.get_first_module::<GatewayClientModule>(&KIND);
+        let account = gateway.module_api.fetch_contract(contract_id).await?;
+        Ok(account.amount)
     }
 
     async fn gateway_subscribe_ln_pay(
@@ -296,7 +315,30 @@ impl GatewayClientExt for Client {
     }
 
     /// Handles an intercepted HTLC by buying a preimage from the federation
+    ///
+    /// The lightning node hands HTLCs that weren't resolved yet to the new
+    /// stream when the HTLC stream reconnects, those are already being handled
+    /// and the existing operation is returned.
+    async fn gateway_incoming_offer_amount(
+        &self,
+        payment_hash: sha256::Hash,
+    ) -> anyhow::Result<Amount> {
+        let (gateway, _instance) = self.get_first_module::<GatewayClientModule>(&KIND);
+        let offer = fetch_incoming_offer(&gateway.module_api, payment_hash).await?;
+        Ok(offer.amount)
+    }
+
     async fn gateway_handle_intercepted_htlc(&self, htlc: Htlc) -> anyhow::Result<OperationId> {
+        let operation_id = OperationId(htlc.payment_hash.into_inner());
+        if self
+            .operation_log()
+            .get_operation(operation_id)
+            .await
+            .is_some()
+        {
+            return Ok(operation_id);
+        }
+
         let (gateway, instance) = self.get_first_module::<GatewayClientModule>(&KIND);
         let (operation_id, output) = gateway
             .create_funding_incoming_contract_output(htlc)
@@ -367,6 +409,98 @@ impl GatewayClientExt for Client {
             }
         }))
     }
+
+    /// Submits an offer for the invoice and waits until the federation
+    /// accepted it, so the invoice can be paid
+    async fn gateway_create_offer_invoice(
+        &self,
+        amount: Amount,
+        description_hash: sha256::Hash,
+        preimage_key: XOnlyPublicKey,
+        route_hints: Vec<RouteHint>,
+        expiry_time: u64,
+    ) -> anyhow::Result<Invoice> {
+        let (gateway, instance) = self.get_first_module::<GatewayClientModule>(&KIND);
+        let (invoice, output) = gateway.create_offer_output(
+            amount,
+            description_hash,
+            preimage_key,
+            route_hints,
+            expiry_time,
+        )?;
+
+        // The payment hash is already used as the operation id when the HTLC for
+        // the invoice is intercepted
+        let operation_id = OperationId(rand::random());
+        let tx = TransactionBuilder::new().with_output(output.into_dyn(instance.id));
+        let operation_meta_gen =
+            |_: TransactionId, _: Option<OutPoint>| GatewayMeta::LightningAddressOffer;
+        let txid = self
+            .finalize_and_submit_transaction(operation_id, KIND.as_str(), operation_meta_gen, tx)
+            .await?;
+        self.transaction_updates(operation_id)
+            .await
+            .await_tx_accepted(txid)
+            .await
+            .map_err(|e| anyhow::anyhow!("Offer was rejected: {e:?}"))?;
+
+        Ok(invoice)
+    }
+}
+
+/// Starts the state machine paying the outgoing contract `contract_id`
+async fn start_gateway_pay(
+    client: &Client,
+    contract_id: ContractId,
+    keysend: Option<KeysendPayment>,
+) -> anyhow::Result<OperationId> {
+    let (_, instance) = client.get_first_module::<GatewayClientModule>(&KIND);
+
+    client
+        .db()
+        .autocommit(
+            |dbtx| {
+                let keysend = keysend.clone();
+                Box::pin(async move {
+                    let operation_id = OperationId(contract_id.into_inner());
+
+                    let state_machines =
+                        vec![GatewayClientStateMachines::Pay(GatewayPayStateMachine {
+                            common: GatewayPayCommon { operation_id },
+                            state: GatewayPayStates::PayInvoice(GatewayPayInvoice {
+                                contract_id,
+                                keysend,
+                            }),
+                        })];
+
+                    let dyn_states = state_machines
+                        .into_iter()
+                        .map(|s| s.into_dyn(instance.id))
+                        .collect();
+
+                    client.add_state_machines(dbtx, dyn_states).await?;
+                    client
+                        .operation_log()
+                        .add_operation_log_entry(
+                            dbtx,
+                            operation_id,
+                            KIND.as_str(),
+                            GatewayMeta::Pay,
+                        )
+                        .await;
+
+                    Ok(operation_id)
+                })
+            },
+            Some(100),
+        )
+        .await
+        .map_err(|e| match e {
+            AutocommitError::ClosureError { error, .. } => error,
+            AutocommitError::CommitFailed { last_error, .. } => {
+                anyhow::anyhow!("Commit to DB failed: {last_error}")
+            }
+        })
 }
 
 #[derive(Debug, Clone)]
@@ -401,7 +535,7 @@ impl ClientModuleGen for GatewayClientGen {
         _api: DynGlobalApi,
         module_api: DynModuleApi,
     ) -> anyhow::Result<Self::Module> {
-        let GetNodeInfoResponse { pub_key, alias: _ } = self.lightning_client.info().await?;
+        let GetNodeInfoResponse { pub_key, .. } = self.lightning_client.info().await?;
         let node_pub_key = PublicKey::from_slice(&pub_key)
             .map_err(|e| anyhow::anyhow!("Invalid node pubkey {}", e))?;
         Ok(GatewayClientModule {
@@ -414,7 +548,7 @@ impl ClientModuleGen for GatewayClientGen {
             lightning_client: self.lightning_client.clone(),
             timelock_delta: self.timelock_delta,
             mint_channel_id: self.mint_channel_id,
-            fees: self.fees,
+            fees: std::sync::Mutex::new(self.fees),
             module_api,
         })
     }
@@ -458,7 +592,8 @@ pub struct GatewayClientModule {
     node_pub_key: PublicKey,
     timelock_delta: u64,
     mint_channel_id: u64,
-    fees: RoutingFees,
+    /// Can change while the gateway is running, see [`Self::set_fees`]
+    fees: std::sync::Mutex<RoutingFees>,
     lightning_client: Arc<dyn ILnRpcClient>,
     module_api: DynModuleApi,
 }
@@ -497,12 +632,13 @@ impl ClientModule for GatewayClientModule {
                 amount: account_output.amount,
                 fee: self.cfg.fee_consensus.contract_output,
             },
-            LightningOutput::Offer(_) | LightningOutput::CancelOutgoing { .. } => {
-                TransactionItemAmount {
-                    amount: Amount::ZERO,
-                    fee: Amount::ZERO,
-                }
-            }
+            LightningOutput::Offer(_)
+            | LightningOutput::CancelOutgoing { .. }
+            | LightningOutput::CancelableOffer { .. }
+            | LightningOutput::CancelOffer { .. } => TransactionItemAmount {
+                amount: Amount::ZERO,
+                fee: Amount::ZERO,
+            },
         }
     }
 }
@@ -521,10 +657,16 @@ impl GatewayClientModule {
             api,
             route_hints,
             valid_until: fedimint_core::time::now() + time_to_live,
-            fees: self.fees,
+            fees: *self.fees.lock().expect("lock poisoned"),
         }
     }
 
+    /// Changes the fees announced to the federation, they take effect with the
+    /// next registration
+    pub fn set_fees(&self, fees: RoutingFees) {
+        *self.fees.lock().expect("lock poisoned") = fees;
+    }
+
     async fn await_paid_invoice(
         &self,
         operation_id: OperationId,
@@ -582,6 +724,93 @@ impl GatewayClientModule {
         };
         Ok((operation_id, client_output))
     }
+
+    /// Creates an invoice routed through this gateway together with the offer
+    /// that lets us buy its preimage when the HTLC arrives
+    fn create_offer_output(
+        &self,
+        amount: Amount,
+        description_hash: sha256::Hash,
+        preimage_key: XOnlyPublicKey,
+        route_hints: Vec<RouteHint>,
+        expiry_time: u64,
+    ) -> anyhow::Result<(
+        Invoice,
+        ClientOutput<LightningOutput, GatewayClientStateMachines>,
+    )> {
+        let secp = Secp256k1::new();
+        let mut rng = rand::thread_rng();
+        let preimage = preimage_key.serialize();
+        let payment_hash = sha256::Hash::hash(&preimage);
+
+        // Like client invoices, the payee is a temporary node reached through us
+        let (node_secret_key, node_public_key) = secp.generate_keypair(&mut rng);
+        let route_hint_last_hop = RouteHintHop {
+            src_node_id: self.node_pub_key,
+            short_channel_id: self.mint_channel_id,
+            fees: RoutingFees {
+                base_msat: 0,
+                proportional_millionths: 0,
+            },
+            cltv_expiry_delta: 30,
+            htlc_minimum_msat: None,
+            htlc_maximum_msat: None,
+        };
+        let route_hints = if route_hints.is_empty() {
+            vec![lightning::routing::router::RouteHint(vec![
+                route_hint_last_hop,
+            ])]
+        } else {
+            route_hints
+                .iter()
+                .map(|rh| {
+                    lightning::routing::router::RouteHint(
+                        rh.to_ldk_route_hint()
+                            .0
+                            .iter()
+                            .cloned()
+                            .chain(once(route_hint_last_hop.clone()))
+                            .collect(),
+                    )
+                })
+                .collect()
+        };
+
+        let duration_since_epoch =
+            fedimint_core::time::now().duration_since(SystemTime::UNIX_EPOCH)?;
+        let mut invoice_builder = InvoiceBuilder::new(network_to_currency(self.cfg.network))
+            .amount_milli_satoshis(amount.msats)
+            .description_hash(description_hash)
+            .payment_hash(payment_hash)
+            .payment_secret(PaymentSecret(rng.gen()))
+            .duration_since_epoch(duration_since_epoch)
+            .min_final_cltv_expiry(18)
+            .payee_pub_key(node_public_key)
+            .expiry_time(Duration::from_secs(expiry_time));
+        for rh in route_hints {
+            invoice_builder = invoice_builder.private_route(rh);
+        }
+        let invoice = invoice_builder
+            .build_signed(|hash| secp.sign_ecdsa_recoverable(hash, &node_secret_key))?;
+
+        let output = LightningOutput::Offer(IncomingContractOffer {
+            amount,
+            hash: payment_hash,
+            encrypted_preimage: EncryptedPreimage::new(
+                Preimage(preimage),
+                &self.cfg.threshold_pub_key,
+            ),
+            expiry_time: Some(expiry_time),
+        });
+
+        Ok((
+            invoice,
+            ClientOutput {
+                output,
+                state_machines: Arc::new(|_, _| vec![]),
+            },
+        ))
+    }
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -1,11 +1,13 @@
 use std::sync::Arc;
 
+use bitcoin_hashes::{sha256, Hash};
 use fedimint_client::sm::{ClientSMDatabaseTransaction, OperationId, State, StateTransition};
 use fedimint_client::transaction::{ClientInput, ClientOutput};
 use fedimint_client::DynGlobalClientContext;
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::{Amount, OutPoint, TransactionId};
 use fedimint_ln_client::contracts::IdentifiableContract;
+use fedimint_ln_client::pay::KeysendPayment;
 use fedimint_ln_common::api::LnFederationApi;
 use fedimint_ln_common::contracts::outgoing::OutgoingContractAccount;
 use fedimint_ln_common::contracts::{ContractId, FundedContract, Preimage};
@@ -15,6 +17,7 @@ use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
 use super::{GatewayClientContext, GatewayClientStateMachines};
+use crate::gatewaylnrpc::pay_invoice_request::Keysend;
 use crate::gatewaylnrpc::{PayInvoiceRequest, PayInvoiceResponse};
 
 #[cfg_attr(doc, aquamarine::aquamarine)]
@@ -110,6 +113,8 @@ pub enum OutgoingContractError {
     TimeoutTooClose,
     #[error("Gateway could not retrieve metadata about the contract.")]
     MissingContractData,
+    #[error("The keysend preimage doesn't match the payment hash of the contract")]
+    InvalidKeysendPreimage,
 }
 
 #[derive(Error, Debug, Serialize, Deserialize, Encodable, Decodable, Clone, Eq, PartialEq)]
@@ -128,6 +133,9 @@ pub enum OutgoingPaymentError {
 #[derive(Debug, Clone, Eq, PartialEq, Decodable, Encodable)]
 pub struct GatewayPayInvoice {
     pub contract_id: ContractId,
+    /// Set if the user asked us to push the payment to a node instead of
+    /// paying the invoice of the contract
+    pub keysend: Option<KeysendPayment>,
 }
 
 impl GatewayPayInvoice {
@@ -138,7 +146,12 @@ impl GatewayPayInvoice {
         common: GatewayPayCommon,
     ) -> Vec<StateTransition<GatewayPayStateMachine>> {
         vec![StateTransition::new(
-            Self::await_buy_preimage(global_context, self.contract_id, context),
+            Self::await_buy_preimage(
+                global_context,
+                self.contract_id,
+                self.keysend.clone(),
+                context,
+            ),
             move |_dbtx, result, _old_state| {
                 Box::pin(Self::transition_bought_preimage(result, common.clone()))
             },
@@ -148,6 +161,7 @@ impl GatewayPayInvoice {
     async fn await_buy_preimage(
         global_context: DynGlobalClientContext,
         contract_id: ContractId,
+        keysend: Option<KeysendPayment>,
         context: GatewayClientContext,
     ) -> Result<(OutgoingContractAccount, Preimage), OutgoingPaymentError> {
         let account = global_context
@@ -180,6 +194,7 @@ impl GatewayPayInvoice {
 
             let payment_parameters = Self::validate_outgoing_account(
                 &outgoing_contract_account,
+                keysend,
                 context.redeem_key,
                 context.timelock_delta,
                 consensus_block_height.unwrap(),
@@ -209,6 +224,11 @@ impl GatewayPayInvoice {
         let invoice = buy_preimage.invoice.clone();
         let max_delay = buy_preimage.max_delay;
         let max_fee_percent = buy_preimage.max_fee_percent();
+        let keysend = buy_preimage.keysend.map(|keysend| Keysend {
+            destination: keysend.destination.serialize().to_vec(),
+            preimage: keysend.preimage.0.to_vec(),
+            amount_msat: buy_preimage.invoice_amount.msats,
+        });
         match context
             .lnrpc
             .pay(PayInvoiceRequest {
@@ -216,6 +236,7 @@ impl GatewayPayInvoice {
                 max_delay,
                 max_fee_percent,
                 payment_hash: invoice.payment_hash().to_vec(),
+                keysend,
             })
             .await
         {
@@ -267,6 +288,7 @@ impl GatewayPayInvoice {
 
     async fn validate_outgoing_account(
         account: &OutgoingContractAccount,
+        keysend: Option<KeysendPayment>,
         redeem_key: bitcoin::KeyPair,
         timelock_delta: u64,
         consensus_block_height: u64,
@@ -281,6 +303,12 @@ impl GatewayPayInvoice {
             return Err(OutgoingContractError::NotOurKey);
         }
 
+        if let Some(keysend) = &keysend {
+            if sha256::Hash::hash(&keysend.preimage.0) != account.contract.hash {
+                return Err(OutgoingContractError::InvalidKeysendPreimage);
+            }
+        }
+
         let invoice = account.contract.invoice.clone();
         let invoice_amount = Amount::from_msats(
             invoice
@@ -307,6 +335,7 @@ impl GatewayPayInvoice {
             invoice_amount,
             max_send_amount: account.amount,
             invoice,
+            keysend,
         })
     }
 }
@@ -317,6 +346,7 @@ pub struct PaymentParameters {
     invoice_amount: Amount,
     max_send_amount: Amount,
     invoice: lightning_invoice::Invoice,
+    keysend: Option<KeysendPayment>,
 }
 
 impl PaymentParameters {
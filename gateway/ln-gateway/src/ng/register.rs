@@ -237,6 +237,15 @@ impl WaitForTimeToLive {
         mut common: RegisterWithFederationCommon,
         dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
     ) -> RegisterWithFederationStateMachine {
+        // A registration without time to live withdraws the gateway from the
+        // federation, it isn't renewed
+        if common.time_to_live.is_zero() {
+            return RegisterWithFederationStateMachine {
+                common,
+                state: RegisterWithFederationStates::Done,
+            };
+        }
+
         let mut dbtx = dbtx.module_tx();
         let registration_info = dbtx
             .get_value(&FederationRegistrationKey {
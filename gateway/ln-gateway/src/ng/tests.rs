@@ -13,6 +13,7 @@ use fedimint_core::{sats, Amount, OutPoint, TransactionId};
 use fedimint_dummy_client::{DummyClientExt, DummyClientGen};
 use fedimint_dummy_common::config::DummyGenParams;
 use fedimint_dummy_server::DummyGen;
+use fedimint_ln_client::pay::KeysendPayment;
 use fedimint_ln_client::{
     LightningClientExt, LightningClientGen, LightningClientModule, LightningClientStateMachines,
     LightningMeta, LnPayState, PayType,
@@ -29,10 +30,12 @@ use fedimint_testing::fixtures::Fixtures;
 use fedimint_testing::gateway::GatewayTest;
 use fedimint_testing::ln::LightningTest;
 use futures::Future;
+use ln_gateway::lnrpc_client::ILnRpcClient;
 use ln_gateway::ng::{
     GatewayClientExt, GatewayClientModule, GatewayClientStateMachines, GatewayExtPayStates,
     GatewayExtReceiveStates, GatewayExtRegisterStates, GatewayMeta, Htlc, GW_ANNOUNCEMENT_TTL,
 };
+use secp256k1::PublicKey;
 use url::Url;
 
 fn fixtures() -> Fixtures {
@@ -229,6 +232,46 @@ async fn test_gateway_client_pay_invalid_invoice() -> anyhow::Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_gateway_client_pay_keysend_invalid_preimage() -> anyhow::Result<()> {
+    gateway_test(
+        |gateway, other_lightning_client, fed, user_client| async move {
+            let gateway = gateway.remove_client(&fed).await;
+            // Print money for user client
+            let (_, outpoint) = user_client.print_money(sats(1000)).await?;
+            user_client.receive_money(outpoint).await?;
+            assert_eq!(user_client.get_balance().await, sats(1000));
+
+            // User client pushes a payment to the other node
+            let destination = PublicKey::from_slice(&other_lightning_client.info().await?.pub_key)?;
+            let (pay_op, contract_id) = user_client.pay_keysend(destination, sats(250)).await?;
+            let mut pay_sub = user_client.subscribe_ln_pay(pay_op).await?.into_stream();
+            assert_eq!(pay_sub.ok().await?, LnPayState::Created);
+            assert_matches!(pay_sub.ok().await?, LnPayState::Funded);
+
+            // The gateway refuses to pay with a preimage that doesn't match the contract
+            let keysend = KeysendPayment {
+                destination,
+                preimage: Preimage(rand::random()),
+            };
+            let gw_pay_op = gateway.gateway_pay_keysend(contract_id, keysend).await?;
+            let mut gw_pay_sub = gateway
+                .gateway_subscribe_ln_pay(gw_pay_op)
+                .await?
+                .into_stream();
+            assert_eq!(gw_pay_sub.ok().await?, GatewayExtPayStates::Created);
+            assert_eq!(gw_pay_sub.ok().await?, GatewayExtPayStates::Canceled);
+
+            // Assert that the user receives a refund
+            assert_matches!(pay_sub.ok().await?, LnPayState::WaitingForRefund { .. });
+            assert_matches!(pay_sub.ok().await?, LnPayState::Refunded { .. });
+
+            Ok(())
+        },
+    )
+    .await
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_gateway_client_intercept_valid_htlc() -> anyhow::Result<()> {
     gateway_test(|gateway, _, fed, user_client| async move {
@@ -382,6 +425,7 @@ async fn test_gateway_client_intercept_htlc_invalid_offer() -> anyhow::Result<()
             let operation_meta_gen = |txid, _| LightningMeta::Receive {
                 out_point: OutPoint { txid, out_idx: 0 },
                 invoice: invoice.clone(),
+                metadata: Default::default(),
             };
             let operation_id = OperationId(invoice.payment_hash().into_inner());
             let txid = user_client
@@ -0,0 +1,106 @@
+//! Recovering the funds of a gateway after losing its disk
+//!
+//! A gateway holds funds in two places: ecash in each connected federation
+//! and the balances of the channels of its lightning node. A
+//! [`RecoveryBundle`] contains what is needed to get both back on a new
+//! machine:
+//!
+//! * for every federation the gateway's config and the root secret of its
+//!   client. Exporting the bundle uploads a fresh ecash backup to each
+//!   federation, which the restored client starts from.
+//! * the static channel backup of the lightning node, if the node supports
+//!   exporting one (LND). Restoring it makes the node's peers force close the
+//!   channels, the node itself can only be recovered from its seed.
+//!
+//! Restoring a bundle (see `Gateway::handle_import_recovery_msg`) expects a
+//! gateway started with an empty data directory, connected to the lightning
+//! node recreated from its seed. It first checks the node is the one the
+//! channel backup belongs to, then restores the ecash of every federation and
+//! finally the channels. Federations the gateway is already connected to are
+//! skipped, so a failed restore can be retried.
+//!
+//! The bundle contains the secrets to spend the gateway's ecash and has to be
+//! stored as securely as the seed of the lightning node.
+
+use std::fmt;
+use std::str::FromStr;
+
+use fedimint_client::secret::PlainRootSecretStrategy;
+use fedimint_client::ClientSecret;
+use fedimint_core::config::FederationId;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::Amount;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::db::FederationConfig;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct RecoveryBundle {
+    /// Public key of the lightning node the channel backup belongs to
+    pub node_pub_key: Vec<u8>,
+    /// Static backup of all channels, `None` if the lightning node can't
+    /// export one
+    pub channel_backup: Option<Vec<u8>>,
+    pub federations: Vec<FederationRecovery>,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct FederationRecovery {
+    pub config: FederationConfig,
+    /// Root secret of the gateway's client, the ecash is derived from it
+    pub secret: ClientSecret<PlainRootSecretStrategy>,
+    /// Balance when the bundle was exported
+    pub balance: Amount,
+}
+
+/// Hex of the consensus encoding, which is also what operators store
+impl fmt::Display for RecoveryBundle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = self.consensus_encode_to_hex().map_err(|_| fmt::Error)?;
+        f.write_str(&hex)
+    }
+}
+
+impl FromStr for RecoveryBundle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // The module configs are kept encoded, so no decoders are needed
+        Ok(Self::consensus_decode_hex(
+            s.trim(),
+            &ModuleDecoderRegistry::default(),
+        )?)
+    }
+}
+
+impl Serialize for RecoveryBundle {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for RecoveryBundle {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        hex.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Outcome of restoring a [`RecoveryBundle`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecoveryReport {
+    pub federations: Vec<RestoredFederation>,
+    /// Whether the channel backup was handed to the lightning node, which
+    /// then has its peers force close the channels
+    pub channels_restored: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoredFederation {
+    pub federation_id: FederationId,
+    /// Balance right after restoring
+    pub balance: Amount,
+    /// Balance when the bundle was exported
+    pub exported_balance: Amount,
+}
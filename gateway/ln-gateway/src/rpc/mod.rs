@@ -2,13 +2,16 @@ pub mod rpc_client;
 pub mod rpc_server;
 
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::io::Cursor;
 
-use bitcoin::{Address, Txid};
+use bitcoin::{Address, Network, Txid};
 use bitcoin_hashes::hex::{FromHex, ToHex};
-use fedimint_core::config::FederationId;
+use fedimint_client::sm::OperationId;
+use fedimint_core::config::{FederationId, PeerUrl};
+use fedimint_core::core::{ModuleInstanceId, ModuleKind};
 use fedimint_core::task::TaskGroup;
-use fedimint_core::Amount;
+use fedimint_core::{Amount, PeerId};
 use fedimint_ln_client::contracts::Preimage;
 use fedimint_ln_client::pay::PayInvoicePayload;
 use fedimint_ln_common::{serde_routing_fees, LightningGateway};
@@ -16,7 +19,9 @@ use futures::Future;
 use lightning::routing::gossip::RoutingFees;
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use tokio::sync::oneshot;
+use url::Url;
 
+use crate::recovery::RecoveryBundle;
 use crate::{Gateway, Result};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -24,6 +29,11 @@ pub struct ConnectFedPayload {
     pub connect: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DisconnectFedPayload {
+    pub federation_id: FederationId,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct InfoPayload;
 
@@ -37,6 +47,14 @@ pub struct RestorePayload {
     pub federation_id: FederationId,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportRecoveryPayload;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportRecoveryPayload {
+    pub bundle: RecoveryBundle,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BalancePayload {
     pub federation_id: FederationId,
@@ -55,6 +73,72 @@ pub struct WithdrawPayload {
     pub address: Address,
 }
 
+/// Configuration changes applied to the running gateway, fields left empty
+/// keep their current value
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct ReloadPayload {
+    /// Base fee charged for payments routed into the federations
+    #[serde(default)]
+    pub base_msat: Option<u32>,
+    /// Fee charged in millionths of the amount of payments routed into the
+    /// federations
+    #[serde(default)]
+    pub proportional_millionths: Option<u32>,
+    /// Address of the CLN extension, after it was reloaded to listen on a
+    /// different address
+    #[serde(default)]
+    pub cln_extension_addr: Option<Url>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct DrainPayload {
+    /// How long to wait for payments in flight before shutting down anyway,
+    /// [`crate::DEFAULT_DRAIN_TIMEOUT`] if not set
+    #[serde(default)]
+    pub timeout_secs: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct DrainResponse {
+    /// Whether every payment settled before the timeout
+    pub drained: bool,
+    /// Operations still in flight when the gateway shut down, they resume when
+    /// it is started again
+    pub in_flight: Vec<InFlightOperation>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct InFlightOperation {
+    pub federation_id: FederationId,
+    pub operation_id: OperationId,
+}
+
+/// Details of a federation, shown before connecting to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FederationPreview {
+    pub federation_id: FederationId,
+    pub name: Option<String>,
+    pub guardians: BTreeMap<PeerId, PeerUrl>,
+    pub modules: BTreeMap<ModuleInstanceId, ModuleKind>,
+    pub fees: FederationFees,
+    /// Bitcoin network of the federation
+    pub network: Network,
+    /// Bitcoin network of our lightning node, `None` if it doesn't report it
+    pub lightning_network: Option<Network>,
+    /// Whether we can route payments for the federation, which requires both
+    /// to be on the same network
+    pub network_compatible: bool,
+    pub already_connected: bool,
+}
+
+/// Fees the federation charges for transactions, by module
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FederationFees {
+    pub lightning: Option<fedimint_ln_common::config::FeeConsensus>,
+    pub mint: Option<fedimint_mint_client::config::FeeConsensus>,
+    pub wallet: Option<fedimint_wallet_client::config::FeeConsensus>,
+}
+
 /// Information about one of the feds we are connected to
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FederationInfo {
@@ -64,6 +148,24 @@ pub struct FederationInfo {
     pub registration: LightningGateway,
 }
 
+/// LUD-06 response describing how to pay a lightning address
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LnurlPayResponse {
+    pub callback: Url,
+    pub min_sendable: Amount,
+    pub max_sendable: Amount,
+    /// JSON encoded list of `[mime type, content]` pairs
+    pub metadata: String,
+    pub tag: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LnurlCallbackParams {
+    /// Amount to pay in msat
+    pub amount: Amount,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GatewayInfo {
     pub version_hash: String,
@@ -10,10 +10,12 @@ use thiserror::Error;
 use url::Url;
 
 use super::{
-    BackupPayload, BalancePayload, ConnectFedPayload, DepositAddressPayload, RestorePayload,
-    WithdrawPayload,
+    BackupPayload, BalancePayload, ConnectFedPayload, DepositAddressPayload, DisconnectFedPayload,
+    DrainPayload, DrainResponse, ExportRecoveryPayload, ImportRecoveryPayload, ReloadPayload,
+    RestorePayload, WithdrawPayload,
 };
-use crate::rpc::{FederationInfo, GatewayInfo};
+use crate::recovery::{RecoveryBundle, RecoveryReport};
+use crate::rpc::{FederationInfo, FederationPreview, GatewayInfo};
 
 pub struct GatewayRpcClient {
     // Base URL to gateway web server
@@ -71,6 +73,28 @@ impl GatewayRpcClient {
         self.call(url, payload).await
     }
 
+    pub async fn preview_federation(
+        &self,
+        payload: ConnectFedPayload,
+    ) -> GatewayRpcResult<FederationPreview> {
+        let url = self
+            .base_url
+            .join("/preview-fed")
+            .expect("invalid base url");
+        self.call(url, payload).await
+    }
+
+    pub async fn disconnect_federation(
+        &self,
+        payload: DisconnectFedPayload,
+    ) -> GatewayRpcResult<()> {
+        let url = self
+            .base_url
+            .join("/disconnect-fed")
+            .expect("invalid base url");
+        self.call(url, payload).await
+    }
+
     pub async fn backup(&self, payload: BackupPayload) -> GatewayRpcResult<()> {
         let url = self.base_url.join("/backup").expect("invalid base url");
         self.call(url, payload).await
@@ -81,6 +105,38 @@ impl GatewayRpcClient {
         self.call(url, payload).await
     }
 
+    pub async fn export_recovery(
+        &self,
+        payload: ExportRecoveryPayload,
+    ) -> GatewayRpcResult<RecoveryBundle> {
+        let url = self
+            .base_url
+            .join("/export-recovery")
+            .expect("invalid base url");
+        self.call(url, payload).await
+    }
+
+    pub async fn import_recovery(
+        &self,
+        payload: ImportRecoveryPayload,
+    ) -> GatewayRpcResult<RecoveryReport> {
+        let url = self
+            .base_url
+            .join("/import-recovery")
+            .expect("invalid base url");
+        self.call(url, payload).await
+    }
+
+    pub async fn reload(&self, payload: ReloadPayload) -> GatewayRpcResult<GatewayInfo> {
+        let url = self.base_url.join("/reload").expect("invalid base url");
+        self.call(url, payload).await
+    }
+
+    pub async fn drain(&self, payload: DrainPayload) -> GatewayRpcResult<DrainResponse> {
+        let url = self.base_url.join("/drain").expect("invalid base url");
+        self.call(url, payload).await
+    }
+
     async fn call<P, T: DeserializeOwned>(&self, url: Url, payload: P) -> Result<T, GatewayRpcError>
     where
         P: Serialize,
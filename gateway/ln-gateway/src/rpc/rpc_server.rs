@@ -1,11 +1,15 @@
 use std::net::SocketAddr;
 
+use axum::extract::{Path, Query};
 use axum::response::IntoResponse;
-use axum::routing::post;
+use axum::routing::{get, post};
 use axum::{Extension, Json, Router};
 use axum_macros::debug_handler;
 use bitcoin_hashes::hex::ToHex;
 use fedimint_ln_client::pay::PayInvoicePayload;
+use fedimint_ln_common::lightning_address::{
+    LightningAddressPaymentsRequest, LightningAddressRegistration,
+};
 use serde_json::json;
 use tokio::sync::oneshot;
 use tower_http::auth::RequireAuthorizationLayer;
@@ -13,8 +17,9 @@ use tower_http::cors::CorsLayer;
 use tracing::{error, instrument};
 
 use super::{
-    BackupPayload, BalancePayload, ConnectFedPayload, DepositAddressPayload, InfoPayload,
-    RestorePayload, WithdrawPayload,
+    BackupPayload, BalancePayload, ConnectFedPayload, DepositAddressPayload, DisconnectFedPayload,
+    DrainPayload, ExportRecoveryPayload, ImportRecoveryPayload, InfoPayload, LnurlCallbackParams,
+    ReloadPayload, RestorePayload, WithdrawPayload,
 };
 use crate::{Gateway, GatewayError};
 
@@ -24,7 +29,12 @@ pub async fn run_webserver(
     mut gateway: Gateway,
 ) -> axum::response::Result<oneshot::Receiver<()>> {
     // Public routes on gateway webserver
-    let routes = Router::new().route("/pay_invoice", post(pay_invoice));
+    let routes = Router::new()
+        .route("/pay_invoice", post(pay_invoice))
+        .route("/.well-known/lnurlp/:name", get(lnurl_pay_request))
+        .route("/lnurlp/:name/callback", get(lnurl_callback))
+        .route("/lnaddress/register", post(register_lightning_address))
+        .route("/lnaddress/payments", post(lightning_address_payments));
 
     // Authenticated, public routes used for gateway administration
     let admin_routes = Router::new()
@@ -33,8 +43,14 @@ pub async fn run_webserver(
         .route("/address", post(address))
         .route("/withdraw", post(withdraw))
         .route("/connect-fed", post(connect_fed))
+        .route("/preview-fed", post(preview_fed))
+        .route("/disconnect-fed", post(disconnect_fed))
         .route("/backup", post(backup))
         .route("/restore", post(restore))
+        .route("/export-recovery", post(export_recovery))
+        .route("/import-recovery", post(import_recovery))
+        .route("/reload", post(reload))
+        .route("/drain", post(drain))
         .layer(RequireAuthorizationLayer::bearer(&authkey));
 
     let app = Router::new()
@@ -128,6 +144,59 @@ async fn pay_invoice(
     Ok(Json(json!(preimage.0.to_hex())))
 }
 
+/// LUD-16 endpoint resolving `name@gateway` to a pay request
+#[instrument(skip_all)]
+async fn lnurl_pay_request(
+    Extension(gateway): Extension<Gateway>,
+    Path(name): Path<String>,
+) -> impl IntoResponse {
+    gateway
+        .handle_lnurl_pay_request(name)
+        .await
+        .map(|response| Json(json!(response)))
+        .map_err(lnurl_error)
+}
+
+/// Returns an invoice for a payment to a lightning address
+#[instrument(skip_all)]
+async fn lnurl_callback(
+    Extension(gateway): Extension<Gateway>,
+    Path(name): Path<String>,
+    Query(params): Query<LnurlCallbackParams>,
+) -> impl IntoResponse {
+    gateway
+        .handle_lnurl_callback(name, params.amount)
+        .await
+        .map(|invoice| Json(json!({ "pr": invoice.to_string(), "routes": [] })))
+        .map_err(lnurl_error)
+}
+
+/// LNURL wallets expect errors as a JSON status instead of an HTTP error
+fn lnurl_error(error: GatewayError) -> Json<serde_json::Value> {
+    error!("LNURL request failed: {error:?}");
+    Json(json!({ "status": "ERROR", "reason": error.to_string() }))
+}
+
+/// Claim a lightning address for a federation user
+#[instrument(skip_all, err)]
+async fn register_lightning_address(
+    Extension(gateway): Extension<Gateway>,
+    Json(payload): Json<LightningAddressRegistration>,
+) -> Result<impl IntoResponse, GatewayError> {
+    gateway.handle_register_lightning_address(payload).await?;
+    Ok(())
+}
+
+/// List the payments to a lightning address for its owner to claim
+#[instrument(skip_all, err)]
+async fn lightning_address_payments(
+    Extension(gateway): Extension<Gateway>,
+    Json(payload): Json<LightningAddressPaymentsRequest>,
+) -> Result<impl IntoResponse, GatewayError> {
+    let payments = gateway.handle_lightning_address_payments(payload).await?;
+    Ok(Json(json!(payments)))
+}
+
 /// Connect a new federation
 #[instrument(skip_all, err)]
 async fn connect_fed(
@@ -138,6 +207,26 @@ async fn connect_fed(
     Ok(Json(json!(fed)))
 }
 
+/// Show the details of a federation without connecting to it
+#[instrument(skip_all, err)]
+async fn preview_fed(
+    Extension(gateway): Extension<Gateway>,
+    Json(payload): Json<ConnectFedPayload>,
+) -> Result<impl IntoResponse, GatewayError> {
+    let preview = gateway.handle_preview_federation(payload).await?;
+    Ok(Json(json!(preview)))
+}
+
+/// Disconnect from a federation the gateway holds no funds in
+#[instrument(skip_all, err)]
+async fn disconnect_fed(
+    Extension(gateway): Extension<Gateway>,
+    Json(payload): Json<DisconnectFedPayload>,
+) -> Result<impl IntoResponse, GatewayError> {
+    gateway.handle_disconnect_federation(payload).await?;
+    Ok(())
+}
+
 /// Backup a gateway actor state
 #[instrument(skip_all, err)]
 async fn backup(
@@ -157,3 +246,43 @@ async fn restore(
     gateway.handle_restore_msg(payload).await?;
     Ok(())
 }
+
+/// Export the secrets and channel backup needed to recover the gateway's funds
+#[instrument(skip_all, err)]
+async fn export_recovery(
+    Extension(gateway): Extension<Gateway>,
+    Json(payload): Json<ExportRecoveryPayload>,
+) -> Result<impl IntoResponse, GatewayError> {
+    let bundle = gateway.handle_export_recovery_msg(payload).await?;
+    Ok(Json(json!(bundle)))
+}
+
+/// Restore the funds of a lost gateway from its recovery bundle
+#[instrument(skip_all, err)]
+async fn import_recovery(
+    Extension(mut gateway): Extension<Gateway>,
+    Json(payload): Json<ImportRecoveryPayload>,
+) -> Result<impl IntoResponse, GatewayError> {
+    let report = gateway.handle_import_recovery_msg(payload).await?;
+    Ok(Json(json!(report)))
+}
+
+/// Apply configuration changes without restarting the gateway
+#[instrument(skip_all, err)]
+async fn reload(
+    Extension(gateway): Extension<Gateway>,
+    Json(payload): Json<ReloadPayload>,
+) -> Result<impl IntoResponse, GatewayError> {
+    let info = gateway.handle_reload_msg(payload).await?;
+    Ok(Json(json!(info)))
+}
+
+/// Wait for payments in flight to settle, then shut the gateway down
+#[instrument(skip_all, err)]
+async fn drain(
+    Extension(gateway): Extension<Gateway>,
+    Json(payload): Json<DrainPayload>,
+) -> Result<impl IntoResponse, GatewayError> {
+    let response = gateway.handle_drain_msg(payload).await?;
+    Ok(Json(json!(response)))
+}
@@ -14,7 +14,7 @@ use fedimint_bitcoind::{create_bitcoind, DynBitcoindRpc};
 use fedimint_client::module::gen::{ClientModuleGenRegistry, DynClientModuleGen};
 use fedimint_client_legacy::mint::SpendableNote;
 use fedimint_client_legacy::{module_decode_stubs, UserClientConfig};
-use fedimint_core::admin_client::{ConfigGenParamsConsensus, PeerServerParams};
+use fedimint_core::admin_client::{AdminStatus, ConfigGenParamsConsensus, PeerServerParams};
 use fedimint_core::api::WsClientConnectInfo;
 use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
 use fedimint_core::cancellable::Cancellable;
@@ -61,7 +61,7 @@ use fedimint_wallet_server::common::db::UTXOKey;
 use fedimint_wallet_server::common::SpendableUTXO;
 use fedimint_wallet_server::{Wallet, WalletGen};
 use futures::executor::block_on;
-use futures::future::{join_all, select_all};
+use futures::future::join_all;
 use futures::{FutureExt, StreamExt};
 use hbbft::honey_badger::Batch;
 use legacy::LegacyTestUser;
@@ -145,7 +145,7 @@ pub async fn fixtures(num_peers: u16) -> anyhow::Result<Fixtures> {
 
     let client_module_inits = ClientModuleGenRegistry::from(vec![
         DynClientModuleGen::from(WalletClientGen::default()),
-        DynClientModuleGen::from(MintClientGen),
+        DynClientModuleGen::from(MintClientGen::default()),
         DynClientModuleGen::from(LightningClientGen),
     ]);
 
@@ -161,10 +161,13 @@ pub async fn fixtures(num_peers: u16) -> anyhow::Result<Fixtures> {
         Ok(s) if s == "1" => {
             fedimintd::attach_default_module_gen_params(
                 BitcoinRpcConfig::from_env_vars()?,
+                None,
                 &mut module_gens_params,
-                msats(MAX_MSAT_DENOMINATION),
+                fedimintd::default_mint_amounts(msats(MAX_MSAT_DENOMINATION)),
                 bitcoin::network::constants::Network::Regtest,
                 10,
+                false,
+                None,
             );
             let params = gen_local(&peers, base_port, "test", module_gens_params).unwrap();
 
@@ -239,10 +242,13 @@ pub async fn fixtures(num_peers: u16) -> anyhow::Result<Fixtures> {
             let factory = FakeBitcoinFactory::register_new();
             fedimintd::attach_default_module_gen_params(
                 factory.config.clone(),
+                None,
                 &mut module_gens_params,
-                msats(MAX_MSAT_DENOMINATION),
+                fedimintd::default_mint_amounts(msats(MAX_MSAT_DENOMINATION)),
                 bitcoin::network::constants::Network::Regtest,
                 10,
+                false,
+                None,
             );
             let bitcoin_rpc = || factory.bitcoin.clone().into();
             let params = gen_local(&peers, base_port, "test", module_gens_params).unwrap();
@@ -357,6 +363,7 @@ pub fn gen_local(
                         federation_name.to_string(),
                     )]),
                     modules: modules.clone(),
+                    misbehavior: Default::default(),
                 },
             };
             Ok((*peer, params))
@@ -438,7 +445,6 @@ struct ServerTest {
     bitcoin_rpc: DynBitcoindRpc,
     database: Database,
     override_proposal: Option<ConsensusProposal>,
-    dropped_peers: Vec<PeerId>,
     process_outcomes: bool,
 }
 
@@ -481,7 +487,11 @@ impl FederationTest {
                         let wallet_item = module.as_any().downcast_ref::<<<Wallet as ServerModule>::Common as ModuleCommon>::ConsensusItem>().expect("test should use fixed module instances");
                         match wallet_item {
                             WalletConsensusItem::RoundConsensus(_) => true,
-                            WalletConsensusItem::PegOutSignature(_) => false
+                            WalletConsensusItem::PegOutSignature(_)
+                            | WalletConsensusItem::PegOutNonces(_)
+                            | WalletConsensusItem::PegOutSignatureShares(_)
+                            | WalletConsensusItem::FeeSubsidyRate(_)
+                            | WalletConsensusItem::UtxoFreeze(_) => false
                         }
                     },
                     _ => false
@@ -493,7 +503,6 @@ impl FederationTest {
 
             let proposal = ConsensusProposal {
                 items,
-                drop_peers: vec![],
                 // if we force it, we want to trigger an epoch
                 force_new_epoch: true,
             };
@@ -515,7 +524,7 @@ impl FederationTest {
                 .fedimint
                 .consensus
                 .api
-                .submit_transaction(transaction.clone())
+                .submit_transaction(transaction.clone(), None)
                 .await?;
         }
         Ok(())
@@ -666,13 +675,53 @@ impl FederationTest {
         self.max_balance_sheet.load(Ordering::SeqCst) as u64
     }
 
-    /// Returns true if all fed members have dropped this peer
-    pub async fn has_dropped_peer(&self, peer: u16) -> bool {
+    /// Votes for a new federation meta on all servers
+    pub async fn update_meta(&self, meta: BTreeMap<String, String>) {
         for server in &self.servers {
-            let mut s = server.lock().await;
-            let proposal = s.fedimint.consensus.get_consensus_proposal().await;
-            s.dropped_peers.append(&mut proposal.drop_peers.clone());
-            if !s.dropped_peers.contains(&PeerId::from(peer)) {
+            let server = server.lock().await;
+            server
+                .fedimint
+                .consensus
+                .api
+                .update_meta(meta.clone())
+                .await
+                .expect("consensus is running");
+        }
+    }
+
+    /// Returns the client configs currently served by the fed members
+    pub async fn client_configs(&self) -> Vec<ClientConfig> {
+        let mut configs = vec![];
+        for server in &self.servers {
+            let server = server.lock().await;
+            let consensus = &server.fedimint.consensus;
+            let mut dbtx = consensus.db.begin_transaction().await;
+            configs.push(consensus.api.client_config(&mut dbtx.get_isolated()).await);
+        }
+        configs
+    }
+
+    /// Returns the dashboard status of the first guardian
+    pub async fn admin_status(&self) -> AdminStatus {
+        let server = self.servers.first().unwrap().lock().await;
+        server
+            .fedimint
+            .consensus
+            .api
+            .admin_status()
+            .await
+            .expect("status is available")
+    }
+
+    /// Returns true if all fed members have scored misbehavior of this peer
+    pub async fn has_penalized_peer(&self, peer: u16) -> bool {
+        for server in &self.servers {
+            let s = server.lock().await;
+            let scores = s.fedimint.consensus.api.peer_scores().await;
+            if scores
+                .get(&PeerId::from(peer))
+                .map_or(true, |score| score.score == 0)
+            {
                 return false;
             }
         }
@@ -865,28 +914,6 @@ impl FederationTest {
         proposals
     }
 
-    /// Runs consensus, but delay peers and only wait for one to complete.
-    /// Useful for testing if a peer has become disconnected.
-    pub async fn race_consensus_epoch(&self, durations: Vec<Duration>) -> anyhow::Result<()> {
-        assert_eq!(durations.len(), self.servers.len());
-        // must drop `res` before calling `update_last_consensus`
-        {
-            let res = select_all(
-                self.servers
-                    .iter()
-                    .zip(durations)
-                    .map(|(server, duration)| {
-                        Box::pin(Self::consensus_epoch(server.clone(), duration))
-                    }),
-            )
-            .await;
-
-            res.0?;
-        }
-        self.update_last_consensus().await;
-        Ok(())
-    }
-
     /// Force these peers to rejoin consensus, simulating what happens upon node
     /// restart
     #[allow(clippy::await_holding_refcell_ref)]
@@ -947,11 +974,8 @@ impl FederationTest {
     ) -> anyhow::Result<()> {
         tokio::time::sleep(delay).await;
         let mut server = server.lock().await;
-        let mut proposal = server.fedimint.consensus.get_consensus_proposal().await;
         let override_proposal = server.override_proposal.clone();
 
-        server.dropped_peers.append(&mut proposal.drop_peers);
-
         server.last_consensus = server
             .fedimint
             .run_consensus_epoch(override_proposal, &mut rng())
@@ -1049,6 +1073,8 @@ impl FederationTest {
                     registry: module_inits.clone(),
                 },
                 db: db.clone(),
+                grpc_bind: None,
+                nostr_relays: vec![],
             };
 
             Arc::new(Mutex::new(ServerTest {
@@ -1057,7 +1083,6 @@ impl FederationTest {
                 database: db,
                 last_consensus: vec![],
                 override_proposal: None,
-                dropped_peers: vec![],
                 process_outcomes: true,
             }))
         }))
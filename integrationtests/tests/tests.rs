@@ -17,27 +17,30 @@
 //! is thus undesirable.
 mod fixtures;
 
-use std::time::Duration;
-
 use anyhow::Result;
 use assert_matches::assert_matches;
 use bitcoin::Amount;
 use fedimint_client_legacy::mint::backup::Metadata;
 use fedimint_core::api::{GlobalFederationApi, WsFederationApi};
+use fedimint_core::config::META_FEDERATION_NAME_KEY;
+use fedimint_core::core::{LEGACY_HARDCODED_INSTANCE_ID_LN, LEGACY_HARDCODED_INSTANCE_ID_WALLET};
 use fedimint_core::outcome::TransactionStatus;
 use fedimint_core::task::TaskGroup;
 use fedimint_core::{msats, sats};
+use fedimint_ln_server::common::LightningJoinSnapshot;
 use fedimint_logging::LOG_TEST;
 use fedimint_server::consensus::TransactionSubmissionError::TransactionError;
 use fedimint_server::epoch::ConsensusItem;
 use fedimint_server::transaction::TransactionError::UnbalancedTransaction;
-use fedimint_wallet_server::common::{PegOutFees, Rbf};
+use fedimint_wallet_server::common::{
+    PegOutFees, Rbf, WalletAdminStatus, PEG_OUT_SIGNATURE_TIMEOUT_EPOCHS,
+};
 use futures::future::{join_all, Either};
 use serde::{Deserialize, Serialize};
 use tracing::log::warn;
 use tracing::{info, instrument};
 
-use crate::fixtures::{peers, test, FederationTest};
+use crate::fixtures::{peers, test};
 
 #[tokio::test(flavor = "multi_thread")]
 async fn wallet_peg_outs_are_rejected_if_fees_are_too_low() -> Result<()> {
@@ -283,34 +286,8 @@ async fn ecash_in_wallet_can_sent_through_a_tx() -> Result<()> {
     .await
 }
 
-async fn drop_peer_3_during_epoch(fed: &FederationTest) -> Result<()> {
-    // ensure that peers 1,2,3 create an epoch, so they can see peer 3's bad
-    // proposal
-    fed.subset_peers(&[1, 2, 3])
-        .await
-        .run_consensus_epochs(1)
-        .await;
-    fed.subset_peers(&[0]).await.run_consensus_epochs(1).await;
-
-    // let peers run consensus, but delay peer 0 so if peer 3 wasn't dropped peer 0
-    // won't be included
-    for maybe_cancelled in join_all(vec![
-        Either::Left(fed.subset_peers(&[1, 2]).await.run_consensus_epochs_wait(1)),
-        Either::Right(
-            fed.subset_peers(&[0, 3])
-                .await
-                .race_consensus_epoch(vec![Duration::from_millis(500), Duration::from_millis(0)]),
-        ),
-    ])
-    .await
-    {
-        maybe_cancelled?;
-    }
-    Ok(())
-}
-
 #[tokio::test(flavor = "multi_thread")]
-async fn drop_peers_who_dont_contribute_peg_out_psbts() -> Result<()> {
+async fn penalizes_peers_who_dont_contribute_peg_out_psbts() -> Result<()> {
     test(4, |fed, user, bitcoin| async move {
         // This test has many assumptions about bitcoin L1 blocks
         // and FM epochs, so we just lock the node
@@ -328,14 +305,31 @@ async fn drop_peers_who_dont_contribute_peg_out_psbts() -> Result<()> {
         fed.subset_peers(&[3]).await.run_consensus_epochs(1).await;
 
         fed.subset_peers(&[3]).await.override_proposal(vec![]).await;
-        drop_peer_3_during_epoch(&fed).await.unwrap();
+        // The signatures are encrypted, so peer 3 is only penalized once the others
+        // waited long enough for its signature to be revealed
+        for _ in 0..PEG_OUT_SIGNATURE_TIMEOUT_EPOCHS + 2 {
+            if fed
+                .subset_peers(&[0, 1, 2])
+                .await
+                .has_penalized_peer(3)
+                .await
+            {
+                break;
+            }
+            fed.run_consensus_epochs(1).await;
+        }
+        assert!(
+            fed.subset_peers(&[0, 1, 2])
+                .await
+                .has_penalized_peer(3)
+                .await
+        );
 
         fed.broadcast_transactions().await;
         assert_eq!(
             bitcoin.mine_block_and_get_received(&peg_out_address).await,
             sats(1000)
         );
-        assert!(fed.subset_peers(&[0, 1, 2]).await.has_dropped_peer(3).await);
         assert_eq!(fed.max_balance_sheet(), 0);
     })
     .await
@@ -639,6 +633,104 @@ async fn limits_client_config_downloads() -> Result<()> {
     .await
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn client_config_is_verified_with_all_guardians() -> Result<()> {
+    test(4, |fed, user, _| async move {
+        fed.run_consensus_epochs(1).await;
+        let cfg = WsFederationApi::download_verified_client_config(&fed.connect_info)
+            .await
+            .unwrap();
+        assert_eq!(cfg, user.config());
+
+        // the guardians agree on the config with the bootstrap guardian
+        let api = WsFederationApi::from_config(&cfg);
+        assert_eq!(
+            api.client_config_hash().await.unwrap(),
+            cfg.consensus_hash()
+        );
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn joins_with_a_threshold_signed_snapshot() -> Result<()> {
+    test(4, |fed, user, _| async move {
+        fed.run_consensus_epochs(1).await;
+        let signed = WsFederationApi::download_join_snapshot_from_connect_info(&fed.connect_info)
+            .await
+            .unwrap();
+        assert!(signed.verify(&fed.connect_info.id));
+        assert_eq!(signed.config, user.config());
+
+        let ln_snapshot = signed
+            .snapshot
+            .module::<LightningJoinSnapshot>(LEGACY_HARDCODED_INSTANCE_ID_LN);
+        assert_matches!(ln_snapshot, Some(Ok(_)));
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn admin_status_aggregates_modules() -> Result<()> {
+    test(2, |fed, user, bitcoin| async move {
+        fed.mine_and_mint(&*user, &*bitcoin, sats(1000)).await;
+
+        let status = fed.admin_status().await;
+        assert_eq!(status.consensus.peers_flagged, 0);
+        assert_eq!(
+            status.net_assets_msat,
+            status
+                .modules
+                .values()
+                .map(|module| module.net_assets_msat)
+                .sum::<i64>()
+        );
+
+        let wallet = status.modules[&LEGACY_HARDCODED_INSTANCE_ID_WALLET]
+            .status
+            .clone()
+            .expect("wallet provides a status");
+        let wallet: WalletAdminStatus = serde_json::from_value(wallet).unwrap();
+        assert!(wallet.consensus_height.is_some());
+        assert!(wallet.bitcoind_block_height.is_some());
+        assert!(wallet.pending_peg_outs.is_empty());
+    })
+    .await
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn threshold_of_guardians_can_update_meta() -> Result<()> {
+    test(4, |fed, _, _| async move {
+        fed.run_consensus_epochs(1).await;
+        let mut meta = fed.client_configs().await[0].meta.clone();
+        meta.insert(META_FEDERATION_NAME_KEY.to_string(), "renamed".to_string());
+
+        // below the threshold the meta stays the same
+        fed.subset_peers(&[0, 1])
+            .await
+            .update_meta(meta.clone())
+            .await;
+        fed.run_empty_epochs(1).await;
+        assert!(fed
+            .client_configs()
+            .await
+            .iter()
+            .all(|cfg| cfg.meta != meta));
+
+        fed.subset_peers(&[2]).await.update_meta(meta.clone()).await;
+        fed.run_empty_epochs(2).await;
+        for cfg in fed.client_configs().await {
+            assert_eq!(cfg.federation_name(), Some("renamed"));
+        }
+
+        // the updated config gets signed again
+        let api = WsFederationApi::from_connect_info(&[fed.connect_info.clone()]);
+        let cfg = api.download_client_config(&fed.connect_info).await.unwrap();
+        assert_eq!(cfg.meta, meta);
+    })
+    .await
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn cannot_replay_transactions() -> Result<()> {
     test(4, |fed, user, bitcoin| async move {
@@ -2,6 +2,7 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::format_err;
+use fedimint_client::balance::ModuleBalanceChange;
 use fedimint_client::derivable_secret::DerivableSecret;
 use fedimint_client::module::gen::ClientModuleGen;
 use fedimint_client::module::{ClientModule, IClientModule};
@@ -296,7 +297,7 @@ impl ClientModule for DummyClientModule {
         get_funds(dbtc).await
     }
 
-    async fn subscribe_balance_changes(&self) -> BoxStream<'static, ()> {
+    async fn subscribe_balance_changes(&self) -> BoxStream<'static, ModuleBalanceChange> {
         Box::pin(
             self.notifier
                 .subscribe_all_operations()
@@ -304,8 +305,16 @@ impl ClientModule for DummyClientModule {
                 .filter_map(|state| async move {
                     match state {
                         // Since Done also happens for inputs we will fire too often, but that's ok
-                        DummyStateMachine::OutputDone(_, _) => Some(()),
-                        DummyStateMachine::Input { .. } => Some(()),
+                        DummyStateMachine::OutputDone(amount, operation_id) => {
+                            Some(ModuleBalanceChange {
+                                operation_id,
+                                received: Some(amount),
+                            })
+                        }
+                        DummyStateMachine::Input(_, _, operation_id) => Some(ModuleBalanceChange {
+                            operation_id,
+                            received: None,
+                        }),
                         _ => None,
                     }
                 }),
@@ -3,6 +3,7 @@ use std::fmt;
 use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::epoch::SerdeSignatureShare;
+use fedimint_core::module::error::{code, ModuleErrorCode};
 use fedimint_core::module::{CommonModuleGen, ModuleCommon, ModuleConsensusVersion};
 use fedimint_core::{plugin_types_trait_impl_common, Amount};
 use secp256k1::{KeyPair, Secp256k1, XOnlyPublicKey};
@@ -55,6 +56,14 @@ pub enum DummyError {
     NotEnoughFunds,
 }
 
+impl ModuleErrorCode for DummyError {
+    fn code(&self) -> i32 {
+        match self {
+            DummyError::NotEnoughFunds => code::CONFLICT,
+        }
+    }
+}
+
 /// Contains the types defined above
 pub struct DummyModuleTypes;
 
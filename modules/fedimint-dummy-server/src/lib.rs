@@ -7,13 +7,15 @@ use fedimint_core::config::{
     ClientModuleConfig, ConfigGenModuleParams, DkgResult, ServerModuleConfig,
     ServerModuleConsensusConfig, TypedServerModuleConfig, TypedServerModuleConsensusConfig,
 };
+use fedimint_core::core::ModuleInstanceId;
 use fedimint_core::db::{Database, DatabaseVersion, MigrationMap, ModuleDatabaseTransaction};
 use fedimint_core::epoch::{SerdeSignature, SerdeSignatureShare};
 use fedimint_core::module::audit::Audit;
+use fedimint_core::module::interconnect::DynModuleInterconnect;
 use fedimint_core::module::{
     api_endpoint, ApiEndpoint, ConsensusProposal, CoreConsensusVersion, ExtendsCommonModuleGen,
-    InputMeta, IntoModuleError, ModuleConsensusVersion, ModuleError, PeerHandle, ServerModuleGen,
-    SupportedModuleApiVersions, TransactionItemAmount,
+    InputMeta, IntoCodedModuleError, ModuleConsensusVersion, ModuleError, PeerHandle,
+    ServerModuleGen, SupportedModuleApiVersions, TransactionItemAmount,
 };
 use fedimint_core::server::DynServerModule;
 use fedimint_core::task::TaskGroup;
@@ -70,8 +72,11 @@ impl ServerModuleGen for DummyGen {
     async fn init(
         &self,
         cfg: ServerModuleConfig,
+        _module_instance_id: ModuleInstanceId,
+        _our_id: PeerId,
         _db: Database,
         _task_group: &mut TaskGroup,
+        _interconnect: DynModuleInterconnect,
     ) -> anyhow::Result<DynServerModule> {
         Ok(Dummy::new(cfg.to_typed()?).into())
     }
@@ -373,7 +378,7 @@ impl ServerModule for Dummy {
             });
         }
 
-        Err(DummyError::NotEnoughFunds).into_module_error_other()
+        Err(DummyError::NotEnoughFunds).into_module_error()
     }
 
     async fn apply_input<'a, 'b, 'c>(
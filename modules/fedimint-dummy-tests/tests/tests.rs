@@ -1,7 +1,7 @@
 use fedimint_core::config::ClientModuleConfig;
 use fedimint_core::core::ModuleKind;
 use fedimint_core::module::ModuleConsensusVersion;
-use fedimint_core::sats;
+use fedimint_core::{sats, PeerId};
 use fedimint_dummy_client::{DummyClientExt, DummyClientGen};
 use fedimint_dummy_common::config::DummyGenParams;
 use fedimint_dummy_server::DummyGen;
@@ -53,3 +53,23 @@ async fn client_ignores_unknown_module() {
     // Test that building the client worked
     let _client = fed.new_client_with_config(cfg).await;
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn simulated_peers_agree_and_count_missing_proposals() -> anyhow::Result<()> {
+    for seed in 0..5 {
+        let mut sim = fixtures().new_simulation(4, seed).await;
+        sim.run_epochs(3).await?;
+
+        let muted = PeerId::from(3);
+        sim.set_muted(muted, true);
+        sim.run_epochs(3).await?;
+
+        for peer in sim.peers() {
+            let scores = sim.peer_scores(peer).await;
+            // Missing proposals are counted, but don't add to the score by default
+            assert!(scores[&muted].missing_proposals > 0, "seed {seed}");
+            assert_eq!(scores[&muted].score, 0, "seed {seed}");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,46 @@
+use fedimint_core::api::{FederationApiExt, FederationResult, IModuleFederationApi};
+use fedimint_core::module::ApiRequestErased;
+use fedimint_core::task::{MaybeSend, MaybeSync};
+use fedimint_core::{apply, async_trait_maybe_send};
+use fedimint_escrow_common::{Escrow, EscrowId, EscrowState};
+
+#[apply(async_trait_maybe_send!)]
+pub trait EscrowFederationApi {
+    async fn escrow(&self, escrow: EscrowId) -> FederationResult<Option<Escrow>>;
+
+    async fn wait_escrow_update(
+        &self,
+        escrow: EscrowId,
+        state: Option<EscrowState>,
+    ) -> FederationResult<Escrow>;
+
+    async fn escrow_consensus_time(&self) -> FederationResult<u64>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<T: ?Sized> EscrowFederationApi for T
+where
+    T: IModuleFederationApi + MaybeSend + MaybeSync + 'static,
+{
+    async fn escrow(&self, escrow: EscrowId) -> FederationResult<Option<Escrow>> {
+        self.request_current_consensus("escrow".to_string(), ApiRequestErased::new(escrow))
+            .await
+    }
+
+    async fn wait_escrow_update(
+        &self,
+        escrow: EscrowId,
+        state: Option<EscrowState>,
+    ) -> FederationResult<Escrow> {
+        self.request_current_consensus(
+            "wait_escrow_update".to_string(),
+            ApiRequestErased::new((escrow, state)),
+        )
+        .await
+    }
+
+    async fn escrow_consensus_time(&self) -> FederationResult<u64> {
+        self.request_current_consensus("consensus_time".to_string(), ApiRequestErased::default())
+            .await
+    }
+}
@@ -0,0 +1,472 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, bail, Context as _};
+use async_stream::stream;
+use fedimint_client::derivable_secret::DerivableSecret;
+use fedimint_client::module::gen::ClientModuleGen;
+use fedimint_client::module::ClientModule;
+use fedimint_client::oplog::{OperationLogEntry, UpdateStreamOrOutcome};
+use fedimint_client::sm::{Context, ModuleNotifier, OperationId};
+use fedimint_client::transaction::{ClientInput, ClientOutput, TransactionBuilder};
+use fedimint_client::{Client, DynGlobalClientContext};
+use fedimint_core::api::{DynGlobalApi, DynModuleApi};
+use fedimint_core::core::{IntoDynInstance, KeyPair};
+use fedimint_core::db::Database;
+use fedimint_core::module::{
+    ApiVersion, ExtendsCommonModuleGen, ModuleCommon, MultiApiVersion, TransactionItemAmount,
+};
+use fedimint_core::util::NextOrPending;
+use fedimint_core::{apply, async_trait_maybe_send, Amount, OutPoint, TransactionId};
+pub use fedimint_escrow_common as common;
+use fedimint_escrow_common::config::EscrowClientConfig;
+use fedimint_escrow_common::{
+    Escrow, EscrowCommonGen, EscrowId, EscrowInput, EscrowModuleTypes, EscrowOutput, EscrowState,
+    Party, KIND,
+};
+use futures::{pin_mut, StreamExt};
+use secp256k1::{Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use states::EscrowStateMachine;
+
+use crate::api::EscrowFederationApi;
+
+pub mod api;
+mod states;
+
+/// Exposed API calls for client apps
+#[apply(async_trait_maybe_send!)]
+pub trait EscrowClientExt {
+    /// The key identifying us as buyer, seller or arbiter of escrows
+    async fn escrow_public_key(&self) -> XOnlyPublicKey;
+
+    /// Locks `amount` of ecash of the primary module in an escrow for the
+    /// seller
+    ///
+    /// The returned id has to be shared with the seller so they can accept
+    /// it. With an arbiter either party can dispute the escrow, the arbiter
+    /// may decide who receives the funds once `timeout` passed.
+    async fn create_escrow(
+        &self,
+        seller: XOnlyPublicKey,
+        arbiter: Option<XOnlyPublicKey>,
+        amount: Amount,
+        timeout: Duration,
+    ) -> anyhow::Result<(OperationId, EscrowId)>;
+
+    /// Agrees to an escrow as its seller
+    async fn accept_escrow(&self, escrow: EscrowId) -> anyhow::Result<OperationId>;
+
+    /// Takes back the funds of an escrow the seller hasn't accepted yet
+    async fn cancel_escrow(&self, escrow: EscrowId) -> anyhow::Result<OperationId>;
+
+    /// Lets the seller claim the funds, once the buyer received what they paid
+    /// for
+    async fn release_escrow(&self, escrow: EscrowId) -> anyhow::Result<OperationId>;
+
+    /// Lets the buyer claim the funds back as the seller
+    async fn refund_escrow(&self, escrow: EscrowId) -> anyhow::Result<OperationId>;
+
+    /// Asks the arbiter to decide who receives the funds
+    async fn dispute_escrow(&self, escrow: EscrowId) -> anyhow::Result<OperationId>;
+
+    /// Lets `beneficiary` claim the funds of a disputed escrow as its arbiter
+    async fn decide_escrow(
+        &self,
+        escrow: EscrowId,
+        beneficiary: Party,
+    ) -> anyhow::Result<OperationId>;
+
+    /// Claims the funds released to us as ecash of the primary module
+    async fn claim_escrow(&self, escrow: EscrowId) -> anyhow::Result<OperationId>;
+
+    /// Subscribe to the state of an operation started by one of the functions
+    /// above
+    async fn subscribe_escrow_operation(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<UpdateStreamOrOutcome<'_, EscrowOperationState>>;
+
+    async fn get_escrow(&self, escrow: EscrowId) -> anyhow::Result<Option<Escrow>>;
+
+    /// Waits until the escrow exists and isn't in `state` anymore, e.g. to see
+    /// when the other party acted
+    async fn await_escrow_update(
+        &self,
+        escrow: EscrowId,
+        state: Option<EscrowState>,
+    ) -> anyhow::Result<Escrow>;
+
+    /// The unix time in seconds the federation agreed on, the arbiter can
+    /// decide once it reached the timeout of a disputed escrow
+    async fn escrow_consensus_time(&self) -> anyhow::Result<u64>;
+}
+
+/// The high-level state of an escrow operation
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum EscrowOperationState {
+    /// The transaction was submitted to the federation
+    Created,
+    /// The transaction was accepted and the ecash for claimed funds or change
+    /// was issued
+    Done,
+    /// The transaction was rejected or the ecash couldn't be issued
+    Failed(String),
+}
+
+/// Stored in the operation log to show escrow operations to the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EscrowMeta {
+    pub txid: TransactionId,
+    /// The output of the primary module receiving claimed funds or change
+    pub change: Option<OutPoint>,
+    pub variant: EscrowMetaVariant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EscrowMetaVariant {
+    Create {
+        escrow: EscrowId,
+        seller: XOnlyPublicKey,
+        amount: Amount,
+    },
+    /// Any of the inputs changing or paying out an existing escrow
+    Spend { input: EscrowInput },
+}
+
+#[apply(async_trait_maybe_send!)]
+impl EscrowClientExt for Client {
+    async fn escrow_public_key(&self) -> XOnlyPublicKey {
+        let (escrows, _instance) = self.get_first_module::<EscrowClientModule>(&KIND);
+        escrows.public_key()
+    }
+
+    async fn create_escrow(
+        &self,
+        seller: XOnlyPublicKey,
+        arbiter: Option<XOnlyPublicKey>,
+        amount: Amount,
+        timeout: Duration,
+    ) -> anyhow::Result<(OperationId, EscrowId)> {
+        let (escrows, instance) = self.get_first_module::<EscrowClientModule>(&KIND);
+        let operation_id = OperationId(rand::random());
+        let escrow = EscrowId(rand::random());
+
+        // The primary module funds the escrow
+        let output = ClientOutput {
+            output: EscrowOutput {
+                escrow,
+                buyer: escrows.public_key(),
+                seller,
+                arbiter,
+                amount,
+                timeout: (fedimint_core::time::now() + timeout)
+                    .duration_since(SystemTime::UNIX_EPOCH)
+                    .expect("Time is after the unix epoch")
+                    .as_secs(),
+            },
+            state_machines: Arc::new(move |txid, _| {
+                vec![EscrowStateMachine::Submitted(txid, operation_id)]
+            }),
+        };
+        let tx = TransactionBuilder::new().with_output(output.into_dyn(instance.id));
+
+        let variant = EscrowMetaVariant::Create {
+            escrow,
+            seller,
+            amount,
+        };
+        self.submit_escrow_transaction(operation_id, variant, tx)
+            .await?;
+        Ok((operation_id, escrow))
+    }
+
+    async fn accept_escrow(&self, escrow: EscrowId) -> anyhow::Result<OperationId> {
+        self.spend_escrow(EscrowInput::Accept { escrow }).await
+    }
+
+    async fn cancel_escrow(&self, escrow: EscrowId) -> anyhow::Result<OperationId> {
+        let amount = self.fetch_escrow(escrow).await?.amount;
+        self.spend_escrow(EscrowInput::Cancel { escrow, amount })
+            .await
+    }
+
+    async fn release_escrow(&self, escrow: EscrowId) -> anyhow::Result<OperationId> {
+        self.spend_escrow(EscrowInput::Release { escrow }).await
+    }
+
+    async fn refund_escrow(&self, escrow: EscrowId) -> anyhow::Result<OperationId> {
+        self.spend_escrow(EscrowInput::Refund { escrow }).await
+    }
+
+    async fn dispute_escrow(&self, escrow: EscrowId) -> anyhow::Result<OperationId> {
+        let party = self
+            .fetch_escrow(escrow)
+            .await?
+            .party(&self.escrow_public_key().await)
+            .context("We are neither buyer nor seller of the escrow")?;
+        self.spend_escrow(EscrowInput::Dispute { escrow, party })
+            .await
+    }
+
+    async fn decide_escrow(
+        &self,
+        escrow: EscrowId,
+        beneficiary: Party,
+    ) -> anyhow::Result<OperationId> {
+        self.spend_escrow(EscrowInput::Decide {
+            escrow,
+            beneficiary,
+        })
+        .await
+    }
+
+    async fn claim_escrow(&self, escrow: EscrowId) -> anyhow::Result<OperationId> {
+        let amount = self.fetch_escrow(escrow).await?.amount;
+        self.spend_escrow(EscrowInput::Claim { escrow, amount })
+            .await
+    }
+
+    async fn subscribe_escrow_operation(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<UpdateStreamOrOutcome<'_, EscrowOperationState>> {
+        let (escrows, _instance) = self.get_first_module::<EscrowClientModule>(&KIND);
+        let operation = escrow_operation(self, operation_id).await?;
+        let meta = operation.meta::<EscrowMeta>();
+
+        let tx_accepted_future = escrows.await_tx_accepted(operation_id);
+        let ecash_issued_future = meta
+            .change
+            .map(|out_point| self.await_primary_module_output(operation_id, out_point));
+
+        Ok(operation.outcome_or_updates(self.db(), operation_id, || {
+            stream! {
+                yield EscrowOperationState::Created;
+
+                match tx_accepted_future.await {
+                    Ok(()) => match ecash_issued_future {
+                        Some(ecash_issued_future) => match ecash_issued_future.await {
+                            Ok(_) => yield EscrowOperationState::Done,
+                            Err(e) => yield EscrowOperationState::Failed(e.to_string()),
+                        },
+                        None => yield EscrowOperationState::Done,
+                    },
+                    Err(e) => yield EscrowOperationState::Failed(e),
+                }
+            }
+        }))
+    }
+
+    async fn get_escrow(&self, escrow: EscrowId) -> anyhow::Result<Option<Escrow>> {
+        let (_escrows, instance) = self.get_first_module::<EscrowClientModule>(&KIND);
+        Ok(instance.api.escrow(escrow).await?)
+    }
+
+    async fn await_escrow_update(
+        &self,
+        escrow: EscrowId,
+        state: Option<EscrowState>,
+    ) -> anyhow::Result<Escrow> {
+        let (_escrows, instance) = self.get_first_module::<EscrowClientModule>(&KIND);
+        Ok(instance.api.wait_escrow_update(escrow, state).await?)
+    }
+
+    async fn escrow_consensus_time(&self) -> anyhow::Result<u64> {
+        let (_escrows, instance) = self.get_first_module::<EscrowClientModule>(&KIND);
+        Ok(instance.api.escrow_consensus_time().await?)
+    }
+}
+
+#[apply(async_trait_maybe_send!)]
+trait EscrowClientExtPrivate {
+    async fn fetch_escrow(&self, escrow: EscrowId) -> anyhow::Result<Escrow>;
+
+    /// Submits a transaction with a single input spending the escrow, signed
+    /// with our key
+    async fn spend_escrow(&self, input: EscrowInput) -> anyhow::Result<OperationId>;
+
+    async fn submit_escrow_transaction(
+        &self,
+        operation_id: OperationId,
+        variant: EscrowMetaVariant,
+        tx: TransactionBuilder,
+    ) -> anyhow::Result<()>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl EscrowClientExtPrivate for Client {
+    async fn fetch_escrow(&self, escrow: EscrowId) -> anyhow::Result<Escrow> {
+        self.get_escrow(escrow)
+            .await?
+            .ok_or(anyhow!("Escrow {escrow} not found"))
+    }
+
+    async fn spend_escrow(&self, input: EscrowInput) -> anyhow::Result<OperationId> {
+        let (escrows, instance) = self.get_first_module::<EscrowClientModule>(&KIND);
+        let operation_id = OperationId(rand::random());
+
+        // The primary module receives paid out funds as change
+        let client_input = escrows.create_input(operation_id, input.clone());
+        let tx = TransactionBuilder::new().with_input(client_input.into_dyn(instance.id));
+
+        self.submit_escrow_transaction(operation_id, EscrowMetaVariant::Spend { input }, tx)
+            .await?;
+        Ok(operation_id)
+    }
+
+    async fn submit_escrow_transaction(
+        &self,
+        operation_id: OperationId,
+        variant: EscrowMetaVariant,
+        tx: TransactionBuilder,
+    ) -> anyhow::Result<()> {
+        let operation_meta = move |txid, change| EscrowMeta {
+            txid,
+            change,
+            variant: variant.clone(),
+        };
+        self.finalize_and_submit_transaction(operation_id, KIND.as_str(), operation_meta, tx)
+            .await?;
+        Ok(())
+    }
+}
+
+async fn escrow_operation(
+    client: &Client,
+    operation_id: OperationId,
+) -> anyhow::Result<OperationLogEntry> {
+    let operation = client
+        .operation_log()
+        .get_operation(operation_id)
+        .await
+        .ok_or(anyhow!("Operation not found"))?;
+
+    if operation.operation_type() != KIND.as_str() {
+        bail!("Operation is not an escrow operation");
+    }
+
+    Ok(operation)
+}
+
+#[derive(Debug)]
+pub struct EscrowClientModule {
+    cfg: EscrowClientConfig,
+    key: KeyPair,
+    notifier: ModuleNotifier<DynGlobalClientContext, EscrowStateMachine>,
+}
+
+/// Data needed by the state machine
+#[derive(Debug, Clone)]
+pub struct EscrowClientContext;
+
+// TODO: Boiler-plate
+impl Context for EscrowClientContext {}
+
+#[apply(async_trait_maybe_send!)]
+impl ClientModule for EscrowClientModule {
+    type Common = EscrowModuleTypes;
+    type ModuleStateMachineContext = EscrowClientContext;
+    type States = EscrowStateMachine;
+
+    fn context(&self) -> Self::ModuleStateMachineContext {
+        EscrowClientContext
+    }
+
+    fn input_amount(&self, input: &<Self::Common as ModuleCommon>::Input) -> TransactionItemAmount {
+        // Inputs that only change the state of an escrow don't need funding
+        if input.amount() == Amount::ZERO {
+            TransactionItemAmount::ZERO
+        } else {
+            TransactionItemAmount {
+                amount: input.amount(),
+                fee: self.cfg.tx_fee,
+            }
+        }
+    }
+
+    fn output_amount(
+        &self,
+        output: &<Self::Common as ModuleCommon>::Output,
+    ) -> TransactionItemAmount {
+        TransactionItemAmount {
+            amount: output.amount,
+            fee: self.cfg.tx_fee,
+        }
+    }
+}
+
+impl EscrowClientModule {
+    fn public_key(&self) -> XOnlyPublicKey {
+        self.key.x_only_public_key().0
+    }
+
+    fn create_input(
+        &self,
+        operation_id: OperationId,
+        input: EscrowInput,
+    ) -> ClientInput<EscrowInput, EscrowStateMachine> {
+        ClientInput {
+            input,
+            keys: vec![self.key],
+            state_machines: Arc::new(move |txid, _| {
+                vec![EscrowStateMachine::Submitted(txid, operation_id)]
+            }),
+        }
+    }
+
+    async fn await_tx_accepted(&self, operation_id: OperationId) -> Result<(), String> {
+        let stream = self
+            .notifier
+            .subscribe(operation_id)
+            .await
+            .filter_map(|state| async move {
+                match state {
+                    EscrowStateMachine::Accepted(_) => Some(Ok(())),
+                    EscrowStateMachine::Rejected(_, e) => Some(Err(e)),
+                    EscrowStateMachine::Submitted(_, _) => None,
+                }
+            });
+
+        pin_mut!(stream);
+
+        stream.next_or_pending().await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct EscrowClientGen;
+
+impl ExtendsCommonModuleGen for EscrowClientGen {
+    type Common = EscrowCommonGen;
+}
+
+/// Generates the client module
+#[apply(async_trait_maybe_send!)]
+impl ClientModuleGen for EscrowClientGen {
+    type Module = EscrowClientModule;
+    type Config = EscrowClientConfig;
+
+    fn supported_api_versions(&self) -> MultiApiVersion {
+        MultiApiVersion::try_from_iter([ApiVersion { major: 0, minor: 0 }])
+            .expect("no version conficts")
+    }
+
+    async fn init(
+        &self,
+        cfg: Self::Config,
+        _db: Database,
+        _api_version: ApiVersion,
+        module_root_secret: DerivableSecret,
+        notifier: ModuleNotifier<DynGlobalClientContext, <Self::Module as ClientModule>::States>,
+        _api: DynGlobalApi,
+        _module_api: DynModuleApi,
+    ) -> anyhow::Result<Self::Module> {
+        Ok(EscrowClientModule {
+            cfg,
+            key: module_root_secret.to_secp_key(&Secp256k1::new()),
+            notifier,
+        })
+    }
+}
@@ -0,0 +1,327 @@
+use std::fmt;
+
+use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::error::{code, ModuleErrorCode};
+use fedimint_core::module::{CommonModuleGen, ModuleCommon, ModuleConsensusVersion};
+use fedimint_core::{plugin_types_trait_impl_common, Amount};
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Common contains types shared by both the client and server
+
+// The client and server configuration
+pub mod config;
+
+/// Unique name for this module
+pub const KIND: ModuleKind = ModuleKind::from_static_str("escrow");
+
+/// Modules are non-compatible with older versions
+pub const CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion(0);
+
+/// Identifies an escrow, chosen randomly by the buyer so it can be shared with
+/// the seller before the federation accepted it
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Serialize,
+    Deserialize,
+    Encodable,
+    Decodable,
+)]
+pub struct EscrowId(pub [u8; 32]);
+
+/// The two users an escrow is between
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub enum Party {
+    /// Locks the funds and wants them to go to the seller only once they
+    /// received what they paid for
+    Buyer,
+    Seller,
+}
+
+/// Where an escrow is in its lifecycle
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub enum EscrowState {
+    /// Waiting for the seller to accept, the buyer may still cancel
+    Open,
+    /// Both parties agreed to the escrow, the funds are locked until one of
+    /// them gives them to the other
+    Accepted,
+    /// The party asked the arbiter to decide, which it may do once the timeout
+    /// passed
+    ///
+    /// The parties can still settle it themselves until the arbiter decided.
+    Disputed(Party),
+    /// The funds can be claimed by the party
+    Released(Party),
+    /// The funds were paid out to the party
+    Closed(Party),
+}
+
+/// Funds locked between a buyer and a seller
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct Escrow {
+    pub buyer: XOnlyPublicKey,
+    pub seller: XOnlyPublicKey,
+    /// Decides disputes, without one the parties have to agree
+    pub arbiter: Option<XOnlyPublicKey>,
+    pub amount: Amount,
+    /// Unix timestamp in seconds before which the arbiter can't decide
+    pub timeout: u64,
+    pub state: EscrowState,
+}
+
+impl Escrow {
+    pub fn key(&self, party: Party) -> XOnlyPublicKey {
+        match party {
+            Party::Buyer => self.buyer,
+            Party::Seller => self.seller,
+        }
+    }
+
+    /// The party `key` belongs to, if any
+    pub fn party(&self, key: &XOnlyPublicKey) -> Option<Party> {
+        if *key == self.buyer {
+            Some(Party::Buyer)
+        } else if *key == self.seller {
+            Some(Party::Seller)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the funds are still held by the federation
+    pub fn is_locked(&self) -> bool {
+        !matches!(self.state, EscrowState::Closed(_))
+    }
+}
+
+/// Input for a fedimint transaction, each one has to be signed by the key
+/// returned by [`EscrowInput::signer`]
+///
+/// Only [`EscrowInput::Cancel`] and [`EscrowInput::Claim`] contribute funds to
+/// the transaction, the other ones just change the state of the escrow.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub enum EscrowInput {
+    /// The seller agrees to the escrow
+    Accept { escrow: EscrowId },
+    /// The buyer takes back the funds of an escrow the seller hasn't accepted
+    Cancel { escrow: EscrowId, amount: Amount },
+    /// The buyer lets the seller claim the funds
+    Release { escrow: EscrowId },
+    /// The seller lets the buyer claim the funds back
+    Refund { escrow: EscrowId },
+    /// A party asks the arbiter to decide
+    Dispute { escrow: EscrowId, party: Party },
+    /// The arbiter lets a party claim the funds of a disputed escrow
+    Decide {
+        escrow: EscrowId,
+        beneficiary: Party,
+    },
+    /// The party the funds were released to takes them
+    Claim { escrow: EscrowId, amount: Amount },
+}
+
+impl EscrowInput {
+    pub fn escrow(&self) -> EscrowId {
+        match self {
+            EscrowInput::Accept { escrow }
+            | EscrowInput::Cancel { escrow, .. }
+            | EscrowInput::Release { escrow }
+            | EscrowInput::Refund { escrow }
+            | EscrowInput::Dispute { escrow, .. }
+            | EscrowInput::Decide { escrow, .. }
+            | EscrowInput::Claim { escrow, .. } => *escrow,
+        }
+    }
+
+    /// The key that has to sign the input, `None` if it can't be spent in the
+    /// current state of the escrow
+    pub fn signer(&self, escrow: &Escrow) -> Option<XOnlyPublicKey> {
+        match self {
+            EscrowInput::Accept { .. } | EscrowInput::Refund { .. } => Some(escrow.seller),
+            EscrowInput::Cancel { .. } | EscrowInput::Release { .. } => Some(escrow.buyer),
+            EscrowInput::Dispute { party, .. } => Some(escrow.key(*party)),
+            EscrowInput::Decide { .. } => escrow.arbiter,
+            EscrowInput::Claim { .. } => match escrow.state {
+                EscrowState::Released(party) => Some(escrow.key(party)),
+                _ => None,
+            },
+        }
+    }
+
+    /// Funds the input contributes to the transaction
+    pub fn amount(&self) -> Amount {
+        match self {
+            EscrowInput::Cancel { amount, .. } | EscrowInput::Claim { amount, .. } => *amount,
+            _ => Amount::ZERO,
+        }
+    }
+}
+
+/// Output for a fedimint transaction, locks the funds of a new escrow
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct EscrowOutput {
+    pub escrow: EscrowId,
+    pub buyer: XOnlyPublicKey,
+    pub seller: XOnlyPublicKey,
+    pub arbiter: Option<XOnlyPublicKey>,
+    pub amount: Amount,
+    pub timeout: u64,
+}
+
+/// Information needed by a client to follow its escrow
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct EscrowOutputOutcome(pub EscrowId);
+
+/// Non-transaction items that will be submitted to consensus, the unix time
+/// in seconds observed by a guardian
+///
+/// Guardians only vote once a disputed escrow timed out by their clock, the
+/// federation considers a time reached once a threshold of them did.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct EscrowConsensusItem {
+    pub time: u64,
+}
+
+/// Errors that might be returned by the server
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Error)]
+pub enum EscrowError {
+    #[error("Unknown escrow")]
+    UnknownEscrow,
+    #[error("An escrow with this id already exists")]
+    EscrowExists,
+    #[error("Escrow amount must not be zero")]
+    ZeroAmount,
+    #[error("Buyer, seller and arbiter must differ")]
+    SameParties,
+    #[error("Not possible while the escrow is {0}")]
+    WrongState(EscrowState),
+    #[error("The escrow has no arbiter")]
+    NoArbiter,
+    #[error("The arbiter can't decide before {0}")]
+    TimeoutNotReached(u64),
+    #[error("Wrong amount, the escrow holds {0}")]
+    WrongAmount(Amount),
+}
+
+impl ModuleErrorCode for EscrowError {
+    fn code(&self) -> i32 {
+        match self {
+            EscrowError::UnknownEscrow => code::NOT_FOUND,
+            EscrowError::EscrowExists | EscrowError::WrongState(_) => code::CONFLICT,
+            EscrowError::ZeroAmount
+            | EscrowError::SameParties
+            | EscrowError::NoArbiter
+            | EscrowError::TimeoutNotReached(_)
+            | EscrowError::WrongAmount(_) => code::BAD_REQUEST,
+        }
+    }
+
+    fn retriable(&self) -> bool {
+        match self {
+            EscrowError::TimeoutNotReached(_) => true,
+            _ => code::retriable_by_default(self.code()),
+        }
+    }
+}
+
+/// Contains the types defined above
+pub struct EscrowModuleTypes;
+
+// Wire together the types for this module
+plugin_types_trait_impl_common!(
+    EscrowModuleTypes,
+    EscrowInput,
+    EscrowOutput,
+    EscrowOutputOutcome,
+    EscrowConsensusItem
+);
+
+#[derive(Debug)]
+pub struct EscrowCommonGen;
+
+impl CommonModuleGen for EscrowCommonGen {
+    const CONSENSUS_VERSION: ModuleConsensusVersion = CONSENSUS_VERSION;
+    const KIND: ModuleKind = KIND;
+
+    fn decoder() -> Decoder {
+        EscrowModuleTypes::decoder_builder().build()
+    }
+}
+
+impl fmt::Display for EscrowId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        bitcoin_hashes::hex::format_hex(&self.0, f)
+    }
+}
+
+impl fmt::Display for Party {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Party::Buyer => write!(f, "buyer"),
+            Party::Seller => write!(f, "seller"),
+        }
+    }
+}
+
+impl fmt::Display for EscrowState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EscrowState::Open => write!(f, "open"),
+            EscrowState::Accepted => write!(f, "accepted"),
+            EscrowState::Disputed(party) => write!(f, "disputed by the {party}"),
+            EscrowState::Released(party) => write!(f, "released to the {party}"),
+            EscrowState::Closed(party) => write!(f, "paid out to the {party}"),
+        }
+    }
+}
+
+impl fmt::Display for EscrowInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EscrowInput::Accept { escrow } => write!(f, "EscrowInput Accept {escrow}"),
+            EscrowInput::Cancel { escrow, amount } => {
+                write!(f, "EscrowInput Cancel {escrow} {amount}")
+            }
+            EscrowInput::Release { escrow } => write!(f, "EscrowInput Release {escrow}"),
+            EscrowInput::Refund { escrow } => write!(f, "EscrowInput Refund {escrow}"),
+            EscrowInput::Dispute { escrow, party } => {
+                write!(f, "EscrowInput Dispute {escrow} by {party}")
+            }
+            EscrowInput::Decide {
+                escrow,
+                beneficiary,
+            } => write!(f, "EscrowInput Decide {escrow} for {beneficiary}"),
+            EscrowInput::Claim { escrow, amount } => {
+                write!(f, "EscrowInput Claim {escrow} {amount}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for EscrowOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EscrowOutput {} {}", self.escrow, self.amount)
+    }
+}
+
+impl fmt::Display for EscrowOutputOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EscrowOutputOutcome {}", self.0)
+    }
+}
+
+impl fmt::Display for EscrowConsensusItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EscrowConsensusItem time {}", self.time)
+    }
+}
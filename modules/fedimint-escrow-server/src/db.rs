@@ -0,0 +1,65 @@
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{impl_db_lookup, impl_db_record, OutPoint, PeerId};
+use fedimint_escrow_common::{Escrow, EscrowId, EscrowOutputOutcome};
+use serde::Serialize;
+use strum_macros::EnumIter;
+
+/// Namespaces DB keys for this module
+#[repr(u8)]
+#[derive(Clone, EnumIter, Debug)]
+pub enum DbKeyPrefix {
+    Escrow = 0x01,
+    Outcome = 0x02,
+    TimeVote = 0x03,
+}
+
+impl std::fmt::Display for DbKeyPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Lookup escrows by id or prefix, closed escrows are kept so clients can
+/// follow them
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct EscrowKey(pub EscrowId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct EscrowPrefix;
+
+impl_db_record!(
+    key = EscrowKey,
+    value = Escrow,
+    db_prefix = DbKeyPrefix::Escrow,
+    // Allows clients to wait for the other party
+    notify_on_modify = true
+);
+impl_db_lookup!(key = EscrowKey, query_prefix = EscrowPrefix);
+
+/// Lookup tx outputs by key or prefix
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct EscrowOutcomeKey(pub OutPoint);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct EscrowOutcomePrefix;
+
+impl_db_record!(
+    key = EscrowOutcomeKey,
+    value = EscrowOutputOutcome,
+    db_prefix = DbKeyPrefix::Outcome,
+);
+impl_db_lookup!(key = EscrowOutcomeKey, query_prefix = EscrowOutcomePrefix);
+
+/// The latest unix time in seconds a guardian voted for
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct TimeVoteKey(pub PeerId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct TimeVotePrefix;
+
+impl_db_record!(
+    key = TimeVoteKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::TimeVote,
+);
+impl_db_lookup!(key = TimeVoteKey, query_prefix = TimeVotePrefix);
@@ -0,0 +1,506 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::string::ToString;
+use std::time::Duration;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use fedimint_core::config::{
+    ClientModuleConfig, ConfigGenModuleParams, DkgResult, ServerModuleConfig,
+    ServerModuleConsensusConfig, TypedServerModuleConfig, TypedServerModuleConsensusConfig,
+};
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::db::{Database, DatabaseVersion, MigrationMap, ModuleDatabaseTransaction};
+use fedimint_core::module::audit::Audit;
+use fedimint_core::module::clock::{unix_time, ThresholdClock};
+use fedimint_core::module::interconnect::DynModuleInterconnect;
+use fedimint_core::module::{
+    api_endpoint, ApiEndpoint, ConsensusProposal, CoreConsensusVersion, ExtendsCommonModuleGen,
+    InputMeta, IntoCodedModuleError, ModuleConsensusVersion, ModuleError, PeerHandle,
+    ServerModuleGen, SupportedModuleApiVersions, TransactionItemAmount,
+};
+use fedimint_core::server::DynServerModule;
+use fedimint_core::task::{sleep, TaskGroup};
+use fedimint_core::{push_db_pair_items, Amount, NumPeers, OutPoint, PeerId, ServerModule};
+use fedimint_escrow_common::config::{
+    EscrowClientConfig, EscrowConfig, EscrowConfigConsensus, EscrowConfigLocal,
+    EscrowConfigPrivate, EscrowGenParams,
+};
+use fedimint_escrow_common::{
+    Escrow, EscrowCommonGen, EscrowConsensusItem, EscrowError, EscrowId, EscrowInput,
+    EscrowModuleTypes, EscrowOutput, EscrowOutputOutcome, EscrowState, Party, CONSENSUS_VERSION,
+};
+use futures::StreamExt;
+use strum::IntoEnumIterator;
+use tracing::info;
+
+use crate::db::{
+    DbKeyPrefix, EscrowKey, EscrowOutcomeKey, EscrowOutcomePrefix, EscrowPrefix, TimeVoteKey,
+    TimeVotePrefix,
+};
+
+mod db;
+
+/// How often to check whether a disputed escrow timed out
+const TIMEOUT_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Generates the module
+#[derive(Debug, Clone)]
+pub struct EscrowGen;
+
+impl ExtendsCommonModuleGen for EscrowGen {
+    type Common = EscrowCommonGen;
+}
+
+/// Implementation of server module non-consensus functions
+#[async_trait]
+impl ServerModuleGen for EscrowGen {
+    type Params = EscrowGenParams;
+    const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
+    /// Returns the version of this module
+    fn versions(&self, _core: CoreConsensusVersion) -> &[ModuleConsensusVersion] {
+        &[CONSENSUS_VERSION]
+    }
+
+    fn supported_api_versions(&self) -> SupportedModuleApiVersions {
+        SupportedModuleApiVersions::from_raw(0, 0, &[(0, 0)])
+    }
+
+    /// Initialize the module
+    async fn init(
+        &self,
+        cfg: ServerModuleConfig,
+        _module_instance_id: ModuleInstanceId,
+        _our_id: PeerId,
+        _db: Database,
+        _task_group: &mut TaskGroup,
+        _interconnect: DynModuleInterconnect,
+    ) -> anyhow::Result<DynServerModule> {
+        Ok(Escrows::new(cfg.to_typed()?).into())
+    }
+
+    /// DB migrations to move from old to newer versions
+    fn get_database_migrations(&self) -> MigrationMap {
+        MigrationMap::new()
+    }
+
+    /// Generates configs for all peers in a trusted manner for testing
+    fn trusted_dealer_gen(
+        &self,
+        peers: &[PeerId],
+        params: &ConfigGenModuleParams,
+    ) -> BTreeMap<PeerId, ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        peers
+            .iter()
+            .map(|&peer| {
+                let config = escrow_config(&params, peer, peers.threshold());
+                (peer, config.to_erased())
+            })
+            .collect()
+    }
+
+    /// Generates configs for all peers in an untrusted manner
+    async fn distributed_gen(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+    ) -> DkgResult<ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        // Escrows are secured by the keys of their users, so there is nothing
+        // to generate together
+        Ok(escrow_config(&params, peers.our_id, peers.peer_ids().threshold()).to_erased())
+    }
+
+    /// Converts the consensus config into the client config
+    fn get_client_config(
+        &self,
+        config: &ServerModuleConsensusConfig,
+    ) -> anyhow::Result<ClientModuleConfig> {
+        let config = EscrowConfigConsensus::from_erased(config)?;
+        Ok(ClientModuleConfig::from_typed(
+            config.kind(),
+            config.version(),
+            &(EscrowClientConfig {
+                tx_fee: config.tx_fee,
+            }),
+        )
+        .expect("Serialization can't fail"))
+    }
+
+    fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
+        let config = config.to_typed::<EscrowConfig>()?;
+        if config.local.peer_id != *identity {
+            bail!("Peer id doesn't match our identity");
+        }
+        if config.consensus.threshold == 0 {
+            bail!("Threshold must not be zero");
+        }
+        Ok(())
+    }
+
+    /// Dumps all database items for debugging
+    async fn dump_database(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        prefix_names: Vec<String>,
+    ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_> {
+        let mut items: BTreeMap<String, Box<dyn erased_serde::Serialize + Send>> = BTreeMap::new();
+        let filtered_prefixes = DbKeyPrefix::iter().filter(|f| {
+            prefix_names.is_empty() || prefix_names.contains(&f.to_string().to_lowercase())
+        });
+
+        for table in filtered_prefixes {
+            match table {
+                DbKeyPrefix::Escrow => {
+                    push_db_pair_items!(dbtx, EscrowPrefix, EscrowKey, Escrow, items, "Escrows");
+                }
+                DbKeyPrefix::Outcome => {
+                    push_db_pair_items!(
+                        dbtx,
+                        EscrowOutcomePrefix,
+                        EscrowOutcomeKey,
+                        EscrowOutputOutcome,
+                        items,
+                        "Escrow Outputs"
+                    );
+                }
+                DbKeyPrefix::TimeVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        TimeVotePrefix,
+                        TimeVoteKey,
+                        u64,
+                        items,
+                        "Time Votes"
+                    );
+                }
+            }
+        }
+
+        Box::new(items.into_iter())
+    }
+}
+
+fn escrow_config(params: &EscrowGenParams, peer_id: PeerId, threshold: usize) -> EscrowConfig {
+    EscrowConfig {
+        local: EscrowConfigLocal { peer_id },
+        private: EscrowConfigPrivate {},
+        consensus: EscrowConfigConsensus {
+            tx_fee: params.consensus.tx_fee,
+            threshold: threshold as u64,
+        },
+    }
+}
+
+/// Escrow module
+#[derive(Debug)]
+pub struct Escrows {
+    pub cfg: EscrowConfig,
+}
+
+/// Implementation of consensus for the server module
+#[async_trait]
+impl ServerModule for Escrows {
+    /// Define the consensus types
+    type Common = EscrowModuleTypes;
+    type Gen = EscrowGen;
+    type VerificationCache = EscrowVerificationCache;
+
+    async fn await_consensus_proposal(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) {
+        // Wait until a disputed escrow timed out by our clock
+        while !self.consensus_proposal(dbtx).await.forces_new_epoch() {
+            sleep(TIMEOUT_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn consensus_proposal(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> ConsensusProposal<EscrowConsensusItem> {
+        let now = unix_time();
+        let our_vote = self.clock().our_vote(dbtx).await;
+
+        // Only disputed escrows need the federation to agree on the time, so
+        // there are no epochs just to keep the clock running
+        let timed_out = dbtx
+            .find_by_prefix(&EscrowPrefix)
+            .await
+            .filter(|(_, escrow)| {
+                std::future::ready(
+                    matches!(escrow.state, EscrowState::Disputed(_))
+                        && our_vote < escrow.timeout
+                        && escrow.timeout <= now,
+                )
+            })
+            .next()
+            .await;
+
+        if timed_out.is_some() {
+            ConsensusProposal::Trigger(vec![EscrowConsensusItem { time: now }])
+        } else {
+            ConsensusProposal::empty()
+        }
+    }
+
+    async fn begin_consensus_epoch<'a, 'b>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        consensus_items: Vec<(PeerId, EscrowConsensusItem)>,
+        _consensus_peers: &BTreeSet<PeerId>,
+    ) -> Vec<PeerId> {
+        self.clock()
+            .process_votes(
+                dbtx,
+                consensus_items
+                    .into_iter()
+                    .map(|(peer_id, item)| (peer_id, item.time)),
+            )
+            .await;
+
+        vec![]
+    }
+
+    fn build_verification_cache<'a>(
+        &'a self,
+        _inputs: impl Iterator<Item = &'a EscrowInput> + Send,
+    ) -> Self::VerificationCache {
+        EscrowVerificationCache
+    }
+
+    async fn validate_input<'a, 'b>(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        _verification_cache: &Self::VerificationCache,
+        input: &'a EscrowInput,
+    ) -> Result<InputMeta, ModuleError> {
+        let escrow = get_escrow(dbtx, input.escrow()).await?;
+        let consensus_time = self.clock().consensus_time(dbtx).await;
+        next_state(&escrow, input, consensus_time).into_module_error()?;
+
+        Ok(InputMeta {
+            amount: self.input_amount(input),
+            // IMPORTANT: include the pubkey to validate the user signed this tx
+            pub_keys: vec![input.signer(&escrow).expect("Checked by next_state")],
+        })
+    }
+
+    async fn apply_input<'a, 'b, 'c>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'c>,
+        input: &'b EscrowInput,
+        cache: &Self::VerificationCache,
+    ) -> Result<InputMeta, ModuleError> {
+        let meta = self.validate_input(dbtx, cache, input).await?;
+
+        let mut escrow = get_escrow(dbtx, input.escrow()).await?;
+        let consensus_time = self.clock().consensus_time(dbtx).await;
+        escrow.state = next_state(&escrow, input, consensus_time).expect("Checked by validation");
+        dbtx.insert_entry(&EscrowKey(input.escrow()), &escrow).await;
+
+        info!(escrow = %input.escrow(), state = %escrow.state, "Escrow changed");
+        Ok(meta)
+    }
+
+    async fn validate_output(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        output: &EscrowOutput,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        if output.amount == Amount::ZERO {
+            return Err(EscrowError::ZeroAmount).into_module_error();
+        }
+        if output.buyer == output.seller
+            || output.arbiter.map_or(false, |arbiter| {
+                arbiter == output.buyer || arbiter == output.seller
+            })
+        {
+            return Err(EscrowError::SameParties).into_module_error();
+        }
+        if dbtx.get_value(&EscrowKey(output.escrow)).await.is_some() {
+            return Err(EscrowError::EscrowExists).into_module_error();
+        }
+
+        Ok(TransactionItemAmount {
+            amount: output.amount,
+            fee: self.cfg.consensus.tx_fee,
+        })
+    }
+
+    async fn apply_output<'a, 'b>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        output: &'a EscrowOutput,
+        out_point: OutPoint,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        let amount = self.validate_output(dbtx, output).await?;
+
+        let escrow = Escrow {
+            buyer: output.buyer,
+            seller: output.seller,
+            arbiter: output.arbiter,
+            amount: output.amount,
+            timeout: output.timeout,
+            state: EscrowState::Open,
+        };
+        dbtx.insert_new_entry(&EscrowKey(output.escrow), &escrow)
+            .await;
+        dbtx.insert_new_entry(
+            &EscrowOutcomeKey(out_point),
+            &EscrowOutputOutcome(output.escrow),
+        )
+        .await;
+
+        Ok(amount)
+    }
+
+    async fn end_consensus_epoch<'a, 'b>(
+        &'a self,
+        _consensus_peers: &BTreeSet<PeerId>,
+        _dbtx: &mut ModuleDatabaseTransaction<'b>,
+    ) -> Vec<PeerId> {
+        vec![]
+    }
+
+    async fn output_status(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        out_point: OutPoint,
+    ) -> Option<EscrowOutputOutcome> {
+        dbtx.get_value(&EscrowOutcomeKey(out_point)).await
+    }
+
+    async fn audit(&self, dbtx: &mut ModuleDatabaseTransaction<'_>, audit: &mut Audit) {
+        // Funds of escrows that weren't paid out yet are owed to their parties
+        audit
+            .add_items(dbtx, &EscrowPrefix, |_, escrow| {
+                if escrow.is_locked() {
+                    -(escrow.amount.msats as i64)
+                } else {
+                    0
+                }
+            })
+            .await;
+    }
+
+    fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
+        vec![
+            api_endpoint! {
+                "escrow",
+                async |_module: &Escrows, context, escrow: EscrowId| -> Option<Escrow> {
+                    Ok(context.dbtx().get_value(&EscrowKey(escrow)).await)
+                }
+            },
+            api_endpoint! {
+                // Waits until the escrow exists and isn't in the given state anymore
+                "wait_escrow_update",
+                async |_module: &Escrows, context, params: (EscrowId, Option<EscrowState>)| -> Escrow {
+                    let (escrow, state) = params;
+                    Ok(context
+                        .wait_value_matches(EscrowKey(escrow), move |escrow| Some(escrow.state) != state)
+                        .await)
+                }
+            },
+            api_endpoint! {
+                // The time the arbiter timeouts are checked against
+                "consensus_time",
+                async |module: &Escrows, context, _params: ()| -> u64 {
+                    Ok(module.clock().consensus_time(&mut context.dbtx()).await)
+                }
+            },
+        ]
+    }
+}
+
+/// An in-memory cache we could use for faster validation
+#[derive(Debug, Clone)]
+pub struct EscrowVerificationCache;
+
+impl fedimint_core::server::VerificationCache for EscrowVerificationCache {}
+
+impl Escrows {
+    /// Create new module instance
+    pub fn new(cfg: EscrowConfig) -> Escrows {
+        Escrows { cfg }
+    }
+
+    fn input_amount(&self, input: &EscrowInput) -> TransactionItemAmount {
+        // Inputs that only change the state of an escrow don't need funding
+        if input.amount() == Amount::ZERO {
+            TransactionItemAmount::ZERO
+        } else {
+            TransactionItemAmount {
+                amount: input.amount(),
+                fee: self.cfg.consensus.tx_fee,
+            }
+        }
+    }
+
+    /// The clock a threshold of guardians agrees on
+    fn clock(&self) -> ThresholdClock<TimeVoteKey, TimeVotePrefix> {
+        ThresholdClock::new(
+            TimeVoteKey,
+            TimeVotePrefix,
+            self.cfg.local.peer_id,
+            self.cfg.consensus.threshold as usize,
+        )
+    }
+}
+
+/// The state the escrow is in after spending `input`
+fn next_state(
+    escrow: &Escrow,
+    input: &EscrowInput,
+    consensus_time: u64,
+) -> Result<EscrowState, EscrowError> {
+    match (input, escrow.state) {
+        (EscrowInput::Accept { .. }, EscrowState::Open) => Ok(EscrowState::Accepted),
+        (EscrowInput::Cancel { amount, .. }, EscrowState::Open) => {
+            check_amount(escrow, *amount)?;
+            Ok(EscrowState::Closed(Party::Buyer))
+        }
+        (EscrowInput::Release { .. }, EscrowState::Accepted | EscrowState::Disputed(_)) => {
+            Ok(EscrowState::Released(Party::Seller))
+        }
+        (EscrowInput::Refund { .. }, EscrowState::Accepted | EscrowState::Disputed(_)) => {
+            Ok(EscrowState::Released(Party::Buyer))
+        }
+        (EscrowInput::Dispute { party, .. }, EscrowState::Accepted) => {
+            if escrow.arbiter.is_none() {
+                return Err(EscrowError::NoArbiter);
+            }
+            Ok(EscrowState::Disputed(*party))
+        }
+        (EscrowInput::Decide { beneficiary, .. }, EscrowState::Disputed(_)) => {
+            if escrow.arbiter.is_none() {
+                return Err(EscrowError::NoArbiter);
+            }
+            if consensus_time < escrow.timeout {
+                return Err(EscrowError::TimeoutNotReached(escrow.timeout));
+            }
+            Ok(EscrowState::Released(*beneficiary))
+        }
+        (EscrowInput::Claim { amount, .. }, EscrowState::Released(party)) => {
+            check_amount(escrow, *amount)?;
+            Ok(EscrowState::Closed(party))
+        }
+        _ => Err(EscrowError::WrongState(escrow.state)),
+    }
+}
+
+fn check_amount(escrow: &Escrow, amount: Amount) -> Result<(), EscrowError> {
+    if amount != escrow.amount {
+        return Err(EscrowError::WrongAmount(escrow.amount));
+    }
+    Ok(())
+}
+
+async fn get_escrow(
+    dbtx: &mut ModuleDatabaseTransaction<'_>,
+    escrow: EscrowId,
+) -> Result<Escrow, ModuleError> {
+    dbtx.get_value(&EscrowKey(escrow))
+        .await
+        .ok_or(EscrowError::UnknownEscrow)
+        .into_module_error()
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use fedimint_core::sats;
+use fedimint_core::task::sleep;
+use fedimint_core::util::NextOrPending;
+use fedimint_dummy_client::{DummyClientExt, DummyClientGen};
+use fedimint_dummy_common::config::DummyGenParams;
+use fedimint_dummy_server::DummyGen;
+use fedimint_escrow_client::{EscrowClientExt, EscrowClientGen, EscrowOperationState};
+use fedimint_escrow_common::config::EscrowGenParams;
+use fedimint_escrow_common::{EscrowState, Party};
+use fedimint_escrow_server::EscrowGen;
+use fedimint_testing::fixtures::Fixtures;
+
+fn fixtures() -> Fixtures {
+    let fixtures = Fixtures::new_primary(DummyClientGen, DummyGen, DummyGenParams::default());
+    fixtures.with_module(EscrowClientGen, EscrowGen, EscrowGenParams::default())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn buyer_releases_escrow_to_seller() -> anyhow::Result<()> {
+    let fed = fixtures().new_fed().await;
+    let (buyer, seller) = fed.two_clients().await;
+    let (_, outpoint) = buyer.print_money(sats(1000)).await?;
+    buyer.receive_money(outpoint).await?;
+
+    let seller_key = seller.escrow_public_key().await;
+    let (op, escrow) = buyer
+        .create_escrow(seller_key, None, sats(400), Duration::from_secs(3600))
+        .await?;
+    let mut sub = buyer.subscribe_escrow_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, EscrowOperationState::Created);
+    assert_eq!(sub.ok().await?, EscrowOperationState::Done);
+    assert_eq!(buyer.get_balance().await, sats(600));
+
+    let op = seller.accept_escrow(escrow).await?;
+    let mut sub = seller.subscribe_escrow_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, EscrowOperationState::Created);
+    assert_eq!(sub.ok().await?, EscrowOperationState::Done);
+
+    // The seller can't take the funds before the buyer released them
+    let op = seller.claim_escrow(escrow).await?;
+    let mut sub = seller.subscribe_escrow_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, EscrowOperationState::Created);
+    assert!(matches!(sub.ok().await?, EscrowOperationState::Failed(_)));
+
+    let op = buyer.release_escrow(escrow).await?;
+    let mut sub = buyer.subscribe_escrow_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, EscrowOperationState::Created);
+    assert_eq!(sub.ok().await?, EscrowOperationState::Done);
+
+    let op = seller.claim_escrow(escrow).await?;
+    let mut sub = seller.subscribe_escrow_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, EscrowOperationState::Created);
+    assert_eq!(sub.ok().await?, EscrowOperationState::Done);
+    assert_eq!(seller.get_balance().await, sats(400));
+    assert_eq!(
+        seller.get_escrow(escrow).await?.map(|escrow| escrow.state),
+        Some(EscrowState::Closed(Party::Seller))
+    );
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn arbiter_decides_disputed_escrow_after_timeout() -> anyhow::Result<()> {
+    let fed = fixtures().new_fed().await;
+    let (buyer, seller) = fed.two_clients().await;
+    let arbiter = fed.new_client().await;
+    let (_, outpoint) = buyer.print_money(sats(1000)).await?;
+    buyer.receive_money(outpoint).await?;
+
+    let (op, escrow) = buyer
+        .create_escrow(
+            seller.escrow_public_key().await,
+            Some(arbiter.escrow_public_key().await),
+            sats(400),
+            Duration::from_secs(2),
+        )
+        .await?;
+    let mut sub = buyer.subscribe_escrow_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, EscrowOperationState::Created);
+    assert_eq!(sub.ok().await?, EscrowOperationState::Done);
+
+    let op = seller.accept_escrow(escrow).await?;
+    let mut sub = seller.subscribe_escrow_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, EscrowOperationState::Created);
+    assert_eq!(sub.ok().await?, EscrowOperationState::Done);
+
+    let op = buyer.dispute_escrow(escrow).await?;
+    let mut sub = buyer.subscribe_escrow_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, EscrowOperationState::Created);
+    assert_eq!(sub.ok().await?, EscrowOperationState::Done);
+
+    // Wait until the guardians agree that the escrow timed out
+    let timeout = buyer.get_escrow(escrow).await?.expect("exists").timeout;
+    while arbiter.escrow_consensus_time().await? < timeout {
+        sleep(Duration::from_millis(500)).await;
+    }
+
+    let op = arbiter.decide_escrow(escrow, Party::Buyer).await?;
+    let mut sub = arbiter.subscribe_escrow_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, EscrowOperationState::Created);
+    assert_eq!(sub.ok().await?, EscrowOperationState::Done);
+
+    let op = buyer.claim_escrow(escrow).await?;
+    let mut sub = buyer.subscribe_escrow_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, EscrowOperationState::Created);
+    assert_eq!(sub.ok().await?, EscrowOperationState::Done);
+    assert_eq!(buyer.get_balance().await, sats(1000));
+    Ok(())
+}
@@ -1,4 +1,5 @@
 mod db;
+pub mod lnurl;
 pub mod pay;
 pub mod receive;
 
@@ -11,10 +12,13 @@ use async_stream::stream;
 use bitcoin::{KeyPair, Network};
 use bitcoin_hashes::Hash;
 use db::LightningGatewayKey;
+use fedimint_client::balance::BalanceEventKind;
+use fedimint_client::db::ChronologicalOperationLogKey;
 use fedimint_client::derivable_secret::{ChildId, DerivableSecret};
+use fedimint_client::fees::{FeeBreakdown, FeeEstimate};
 use fedimint_client::module::gen::ClientModuleGen;
 use fedimint_client::module::{ClientModule, IClientModule};
-use fedimint_client::oplog::UpdateStreamOrOutcome;
+use fedimint_client::oplog::{OperationLogEntry, UpdateStreamOrOutcome};
 use fedimint_client::sm::util::MapStateTransitions;
 use fedimint_client::sm::{DynState, ModuleNotifier, OperationId, State, StateTransition};
 use fedimint_client::transaction::{ClientOutput, TransactionBuilder};
@@ -22,7 +26,7 @@ use fedimint_client::{sm_enum_variant_translation, Client, DynGlobalClientContex
 use fedimint_core::api::{DynGlobalApi, DynModuleApi};
 use fedimint_core::config::FederationId;
 use fedimint_core::core::{IntoDynInstance, ModuleInstanceId};
-use fedimint_core::db::Database;
+use fedimint_core::db::{AutocommitError, Database};
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::module::{
     ApiVersion, CommonModuleGen, ExtendsCommonModuleGen, ModuleCommon, MultiApiVersion,
@@ -41,6 +45,10 @@ use fedimint_ln_common::contracts::{
 use fedimint_ln_common::incoming::{
     FundingOfferState, IncomingSmCommon, IncomingSmError, IncomingSmStates, IncomingStateMachine,
 };
+use fedimint_ln_common::lightning_address::{
+    LightningAddressPayment, LightningAddressPaymentsRequest, LightningAddressRegistration,
+};
+use fedimint_ln_common::listing::GatewayQuery;
 pub use fedimint_ln_common::*;
 use futures::StreamExt;
 use lightning::ln::PaymentSecret;
@@ -54,14 +62,15 @@ use secp256k1_zkp::{All, Secp256k1};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tracing::{debug, error};
+use url::Url;
 
 use crate::pay::{
-    GatewayPayError, LightningPayCommon, LightningPayCreatedOutgoingLnContract,
+    GatewayPayError, KeysendPayment, LightningPayCommon, LightningPayCreatedOutgoingLnContract,
     LightningPayStateMachine, LightningPayStates,
 };
 use crate::receive::{
-    LightningReceiveError, LightningReceiveStateMachine, LightningReceiveStates,
-    LightningReceiveSubmittedOffer,
+    offer_cancellation_keypair, LightningReceiveConfirmedInvoice, LightningReceiveError,
+    LightningReceiveStateMachine, LightningReceiveStates, LightningReceiveSubmittedOffer,
 };
 
 /// Number of blocks until outgoing lightning contracts times out and user
@@ -79,9 +88,35 @@ pub trait LightningClientExt {
     /// Gateways actively registered with the fed
     async fn fetch_registered_gateways(&self) -> anyhow::Result<Vec<LightningGateway>>;
 
+    /// A page of the gateways registered with the fed that match `query`, the
+    /// next page starts after the node key of the last gateway
+    async fn fetch_gateways_page(
+        &self,
+        query: &GatewayQuery,
+    ) -> anyhow::Result<Vec<LightningGateway>>;
+
     /// Pays a LN invoice with our available funds
     async fn pay_bolt11_invoice(&self, invoice: Invoice) -> anyhow::Result<(PayType, ContractId)>;
 
+    /// Like [`LightningClientExt::pay_bolt11_invoice`], but attaches
+    /// `metadata` to the payment operation, see
+    /// [`LightningClientExt::ln_operation_metadata`]
+    async fn pay_bolt11_invoice_with_metadata(
+        &self,
+        invoice: Invoice,
+        metadata: LightningOperationMetadata,
+    ) -> anyhow::Result<(PayType, ContractId)>;
+
+    /// Pushes `amount` to the lightning node `destination` through the active
+    /// gateway without an invoice, see [`KeysendPayment`]. The progress can be
+    /// observed using [`LightningClientExt::subscribe_ln_pay`], the funds are
+    /// refunded if the gateway fails to pay.
+    async fn pay_keysend(
+        &self,
+        destination: secp256k1::PublicKey,
+        amount: Amount,
+    ) -> anyhow::Result<(OperationId, ContractId)>;
+
     async fn subscribe_internal_pay(
         &self,
         operation_id: OperationId,
@@ -100,10 +135,66 @@ pub trait LightningClientExt {
         expiry_time: Option<u64>,
     ) -> anyhow::Result<(OperationId, Invoice)>;
 
+    /// Like [`LightningClientExt::create_bolt11_invoice`], but attaches
+    /// `metadata` to the receive operation, see
+    /// [`LightningClientExt::ln_operation_metadata`]
+    async fn create_bolt11_invoice_with_metadata(
+        &self,
+        amount: Amount,
+        description: String,
+        expiry_time: Option<u64>,
+        metadata: LightningOperationMetadata,
+    ) -> anyhow::Result<(OperationId, Invoice)>;
+
     async fn subscribe_ln_receive(
         &self,
         operation_id: OperationId,
     ) -> anyhow::Result<UpdateStreamOrOutcome<'_, LnReceiveState>>;
+
+    /// Metadata the lightning operation `operation_id` was started with, so
+    /// the updates of [`LightningClientExt::subscribe_ln_pay`],
+    /// [`LightningClientExt::subscribe_internal_pay`] and
+    /// [`LightningClientExt::subscribe_ln_receive`] can be matched to orders
+    async fn ln_operation_metadata(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<LightningOperationMetadata>;
+
+    /// Returns the last `limit` lightning operations along with their meta,
+    /// newest first. To fetch the next page, pass the last operation's
+    /// [`ChronologicalOperationLogKey`] as `start_after`.
+    async fn list_ln_operations(
+        &self,
+        limit: usize,
+        start_after: Option<ChronologicalOperationLogKey>,
+    ) -> Vec<(ChronologicalOperationLogKey, LightningMeta)>;
+
+    /// Claims the lightning address `name@gateway` on the gateway at
+    /// `gateway_api`, payments to it are received by this client
+    async fn register_lightning_address(
+        &self,
+        gateway_api: Url,
+        name: String,
+    ) -> anyhow::Result<()>;
+
+    /// Starts claiming the payments to our lightning address `name` that
+    /// weren't claimed before, their progress can be followed with
+    /// [`LightningClientExt::subscribe_ln_receive`]
+    async fn claim_lightning_address_payments(
+        &self,
+        gateway_api: Url,
+        name: String,
+    ) -> anyhow::Result<Vec<OperationId>>;
+}
+
+/// Information a merchant attaches to a payment or invoice to match the
+/// operation to its own records
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LightningOperationMetadata {
+    /// Free form note, e.g. what was paid for
+    pub memo: Option<String>,
+    /// Id of the order in an external system
+    pub external_id: Option<String>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
@@ -126,8 +217,9 @@ pub enum InternalPayState {
     Error(String),
 }
 
-/// The high-level state of a pay operation over lightning,
-/// started with [`LightningClientExt::pay_bolt11_invoice`].
+/// The high-level state of a pay operation over lightning, started with
+/// [`LightningClientExt::pay_bolt11_invoice`] or
+/// [`LightningClientExt::pay_keysend`].
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum LnPayState {
     Created,
@@ -152,11 +244,18 @@ pub enum LnPayState {
 #[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
 pub enum LnReceiveState {
     Created,
-    WaitingForPayment { invoice: String, timeout: Duration },
-    Canceled { reason: LightningReceiveError },
+    WaitingForPayment {
+        invoice: String,
+        timeout: Duration,
+    },
+    Canceled {
+        reason: LightningReceiveError,
+    },
     Funded,
     AwaitingFunds,
     Claimed,
+    /// The invoice wasn't paid before it expired and can't be paid anymore
+    Expired,
 }
 
 async fn invoice_has_internal_payment_markers(
@@ -173,6 +272,116 @@ async fn invoice_has_internal_payment_markers(
         == Some(markers)
 }
 
+/// Creates the output paying `invoice`, either directly to the recipient if
+/// they are a user of the federation too or to the active gateway
+async fn create_pay_output(
+    client: &Client,
+    operation_id: OperationId,
+    invoice: Invoice,
+) -> anyhow::Result<(
+    PayType,
+    ClientOutput<LightningOutput, LightningClientStateMachines>,
+    ContractId,
+)> {
+    let (lightning, instance) = client.get_first_module::<LightningClientModule>(&KIND);
+
+    let is_internal_payment =
+        invoice_has_internal_payment_markers(&invoice, client.get_internal_payment_markers()?)
+            .await
+            || invoice_routes_back_to_federation(
+                &invoice,
+                client.fetch_registered_gateways().await?,
+            )
+            .await;
+
+    if is_internal_payment {
+        let (output, contract_id) = lightning
+            .create_incoming_output(operation_id, invoice)
+            .await?;
+        Ok((PayType::Internal(operation_id), output, contract_id))
+    } else {
+        let active_gateway = client.select_active_gateway().await?;
+        let (output, contract_id) = lightning
+            .create_outgoing_output(
+                operation_id,
+                instance.api,
+                invoice,
+                active_gateway,
+                client.get_config().federation_id,
+                rand::rngs::OsRng,
+                None,
+            )
+            .await?;
+        Ok((PayType::Lightning(operation_id), output, contract_id))
+    }
+}
+
+/// Fee preview of [`LightningClientExt::pay_bolt11_invoice`]
+#[derive(Debug, Clone)]
+pub struct LnPayFeeEstimate {
+    pub invoice: Invoice,
+}
+
+#[apply(async_trait_maybe_send!)]
+impl FeeEstimate for LnPayFeeEstimate {
+    async fn estimate_fees(&self, client: &Client) -> anyhow::Result<FeeBreakdown> {
+        let (lightning, instance) = client.get_first_module::<LightningClientModule>(&KIND);
+        let invoice_amount = Amount::from_msats(
+            self.invoice
+                .amount_milli_satoshis()
+                .ok_or(anyhow::anyhow!("MissingInvoiceAmount"))?,
+        );
+
+        let (pay_type, output, _contract_id) =
+            create_pay_output(client, OperationId::new_random(), self.invoice.clone()).await?;
+        // Outgoing contracts lock the routing fee of the gateway on top of the
+        // invoice amount
+        let gateway_fee = match pay_type {
+            PayType::Internal(_) => Amount::ZERO,
+            PayType::Lightning(_) => {
+                ClientModule::output_amount(lightning, &output.output).amount - invoice_amount
+            }
+        };
+        let federation_fee = client
+            .estimate_transaction_fees(
+                TransactionBuilder::new().with_output(output.into_dyn(instance.id)),
+            )
+            .await?;
+
+        Ok(FeeBreakdown {
+            federation_fee,
+            gateway_fee,
+            ..FeeBreakdown::default()
+        })
+    }
+}
+
+/// Fee preview of receiving `amount` with an invoice from
+/// [`LightningClientExt::create_bolt11_invoice`]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LnReceiveFeeEstimate {
+    pub amount: Amount,
+}
+
+#[apply(async_trait_maybe_send!)]
+impl FeeEstimate for LnReceiveFeeEstimate {
+    async fn estimate_fees(&self, client: &Client) -> anyhow::Result<FeeBreakdown> {
+        let (lightning, _) = client.get_first_module::<LightningClientModule>(&KIND);
+
+        // Creating the offer is free, the incoming contract is claimed once paid
+        let claim_fee = lightning.cfg.fee_consensus.contract_input;
+        ensure!(
+            self.amount > claim_fee,
+            "Amount doesn't cover the claim fee {claim_fee}"
+        );
+
+        Ok(FeeBreakdown {
+            federation_fee: claim_fee + client.estimate_receive_fees(self.amount - claim_fee).await,
+            ..FeeBreakdown::default()
+        })
+    }
+}
+
 async fn invoice_routes_back_to_federation(
     invoice: &Invoice,
     gateways: Vec<LightningGateway>,
@@ -234,45 +443,37 @@ impl LightningClientExt for Client {
         Ok(instance.api.fetch_gateways().await?)
     }
 
+    async fn fetch_gateways_page(
+        &self,
+        query: &GatewayQuery,
+    ) -> anyhow::Result<Vec<LightningGateway>> {
+        let (_lightning, instance) = self.get_first_module::<LightningClientModule>(&KIND);
+        Ok(instance.api.fetch_gateways_page(query).await?)
+    }
+
     async fn pay_bolt11_invoice(&self, invoice: Invoice) -> anyhow::Result<(PayType, ContractId)> {
-        let (lightning, instance) = self.get_first_module::<LightningClientModule>(&KIND);
+        self.pay_bolt11_invoice_with_metadata(invoice, LightningOperationMetadata::default())
+            .await
+    }
+
+    async fn pay_bolt11_invoice_with_metadata(
+        &self,
+        invoice: Invoice,
+        metadata: LightningOperationMetadata,
+    ) -> anyhow::Result<(PayType, ContractId)> {
+        let (_lightning, instance) = self.get_first_module::<LightningClientModule>(&KIND);
         let payment_hash = invoice.payment_hash();
         let operation_id = OperationId(payment_hash.into_inner());
 
-        let is_internal_payment =
-            invoice_has_internal_payment_markers(&invoice, self.get_internal_payment_markers()?)
-                .await
-                || invoice_routes_back_to_federation(
-                    &invoice,
-                    self.fetch_registered_gateways().await?,
-                )
-                .await;
-
-        let (pay_type, output, contract_id) = if is_internal_payment {
-            let (output, contract_id) = lightning
-                .create_incoming_output(operation_id, invoice.clone())
-                .await?;
-            (PayType::Internal(operation_id), output, contract_id)
-        } else {
-            let active_gateway = self.select_active_gateway().await?;
-            let (output, contract_id) = lightning
-                .create_outgoing_output(
-                    operation_id,
-                    instance.api,
-                    invoice.clone(),
-                    active_gateway,
-                    self.get_config().federation_id,
-                    rand::rngs::OsRng,
-                )
-                .await?;
-            (PayType::Lightning(operation_id), output, contract_id)
-        };
+        let (pay_type, output, contract_id) =
+            create_pay_output(self, operation_id, invoice.clone()).await?;
 
         let tx = TransactionBuilder::new().with_output(output.into_dyn(instance.id));
         let operation_meta_gen = |txid, change_outpoint| LightningMeta::Pay {
             out_point: OutPoint { txid, out_idx: 0 },
             invoice: invoice.clone(),
             change_outpoint,
+            metadata: metadata.clone(),
         };
 
         self.finalize_and_submit_transaction(
@@ -286,11 +487,72 @@ impl LightningClientExt for Client {
         Ok((pay_type, contract_id))
     }
 
+    async fn pay_keysend(
+        &self,
+        destination: secp256k1::PublicKey,
+        amount: Amount,
+    ) -> anyhow::Result<(OperationId, ContractId)> {
+        let (lightning, instance) = self.get_first_module::<LightningClientModule>(&KIND);
+        let preimage = Preimage(rand::rngs::OsRng.gen());
+        let invoice = lightning.create_keysend_invoice(&preimage, amount, rand::rngs::OsRng)?;
+        let operation_id = OperationId(invoice.payment_hash().into_inner());
+
+        let active_gateway = self.select_active_gateway().await?;
+        let (output, contract_id) = lightning
+            .create_outgoing_output(
+                operation_id,
+                instance.api,
+                invoice,
+                active_gateway,
+                self.get_config().federation_id,
+                rand::rngs::OsRng,
+                Some(KeysendPayment {
+                    destination,
+                    preimage,
+                }),
+            )
+            .await?;
+
+        let tx = TransactionBuilder::new().with_output(output.into_dyn(instance.id));
+        let operation_meta_gen = |txid, change_outpoint| LightningMeta::PayKeysend {
+            out_point: OutPoint { txid, out_idx: 0 },
+            destination,
+            amount,
+            change_outpoint,
+        };
+
+        self.finalize_and_submit_transaction(
+            operation_id,
+            LightningCommonGen::KIND.as_str(),
+            operation_meta_gen,
+            tx,
+        )
+        .await?;
+
+        Ok((operation_id, contract_id))
+    }
+
     async fn create_bolt11_invoice(
         &self,
         amount: Amount,
         description: String,
         expiry_time: Option<u64>,
+    ) -> anyhow::Result<(OperationId, Invoice)> {
+        self.create_bolt11_invoice_with_metadata(
+            amount,
+            description,
+            expiry_time,
+            LightningOperationMetadata::default(),
+        )
+        .await
+    }
+
+    async fn create_bolt11_invoice_with_metadata(
+        &self,
+        amount: Amount,
+        description: String,
+        expiry_time: Option<u64>,
+        metadata: LightningOperationMetadata,
     ) -> anyhow::Result<(OperationId, Invoice)> {
         let (lightning, instance) = self.get_first_module::<LightningClientModule>(&KIND);
         let (src_node_id, short_channel_id, route_hints) = match self.select_active_gateway().await
@@ -322,6 +584,7 @@ impl LightningClientExt for Client {
         let operation_meta_gen = |txid, _| LightningMeta::Receive {
             out_point: OutPoint { txid, out_idx: 0 },
             invoice: invoice.clone(),
+            metadata: metadata.clone(),
         };
         let txid = self
             .finalize_and_submit_transaction(
@@ -350,15 +613,23 @@ impl LightningClientExt for Client {
         let (lightning, _instance) = self.get_first_module::<LightningClientModule>(&KIND);
 
         let operation = ln_operation(self, operation_id).await?;
-        let (out_point, invoice) = match operation.meta::<LightningMeta>() {
-            LightningMeta::Receive { out_point, invoice } => (out_point, invoice),
+        let (offer_txid, invoice) = match operation.meta::<LightningMeta>() {
+            LightningMeta::Receive {
+                out_point, invoice, ..
+            } => (Some(out_point.txid), invoice),
+            // The gateway submitted the offer before handing out the invoice
+            LightningMeta::LightningAddressReceive { invoice } => (None, invoice),
             _ => bail!("Operation is not a lightning payment"),
         };
 
-        let tx_accepted_future = self
-            .transaction_updates(operation_id)
-            .await
-            .await_tx_accepted(out_point.txid);
+        let tx_accepted_future = match offer_txid {
+            Some(txid) => Some(
+                self.transaction_updates(operation_id)
+                    .await
+                    .await_tx_accepted(txid),
+            ),
+            None => None,
+        };
 
         let receive_success = lightning.await_receive_success(operation_id);
         let claim_acceptance = lightning.await_claim_acceptance(operation_id);
@@ -367,10 +638,12 @@ impl LightningClientExt for Client {
             stream! {
                     yield LnReceiveState::Created;
 
-                    if tx_accepted_future.await.is_err() {
-                    yield LnReceiveState::Canceled { reason: LightningReceiveError::Rejected };
-                        return;
-                }
+                    if let Some(tx_accepted_future) = tx_accepted_future {
+                        if tx_accepted_future.await.is_err() {
+                            yield LnReceiveState::Canceled { reason: LightningReceiveError::Rejected };
+                            return;
+                        }
+                    }
                             yield LnReceiveState::WaitingForPayment { invoice: invoice.to_string(), timeout: invoice.expiry_time() };
 
                             match receive_success.await {
@@ -388,6 +661,9 @@ impl LightningClientExt for Client {
 
                         yield LnReceiveState::Canceled { reason: LightningReceiveError::Rejected };
                     }
+                    Err(LightningReceiveError::Timeout) => {
+                        yield LnReceiveState::Expired;
+                    }
                     Err(e) => {
                         yield LnReceiveState::Canceled { reason: e };
                     }
@@ -403,12 +679,17 @@ impl LightningClientExt for Client {
         let (lightning, _instance) = self.get_first_module::<LightningClientModule>(&KIND);
 
         let operation = ln_operation(self, operation_id).await?;
-        let (out_point, _, change_outpoint) = match operation.meta::<LightningMeta>() {
+        let (out_point, change_outpoint) = match operation.meta::<LightningMeta>() {
             LightningMeta::Pay {
                 out_point,
-                invoice,
                 change_outpoint,
-            } => (out_point, invoice, change_outpoint),
+                ..
+            }
+            | LightningMeta::PayKeysend {
+                out_point,
+                change_outpoint,
+                ..
+            } => (out_point, change_outpoint),
             _ => bail!("Operation is not a lightning payment"),
         };
 
@@ -499,6 +780,155 @@ impl LightningClientExt for Client {
             }
         }))
     }
+
+    async fn ln_operation_metadata(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<LightningOperationMetadata> {
+        let operation = ln_operation(self, operation_id).await?;
+        Ok(operation.meta::<LightningMeta>().metadata())
+    }
+
+    async fn list_ln_operations(
+        &self,
+        limit: usize,
+        mut start_after: Option<ChronologicalOperationLogKey>,
+    ) -> Vec<(ChronologicalOperationLogKey, LightningMeta)> {
+        let mut ln_operations = Vec::with_capacity(limit);
+        // Other modules' operations are interleaved, so we may need several pages
+        while ln_operations.len() < limit {
+            let page = self
+                .operation_log()
+                .list_operations(limit, start_after)
+                .await;
+            let Some((last_key, _)) = page.last() else {
+                break;
+            };
+            start_after = Some(*last_key);
+
+            ln_operations.extend(
+                page.into_iter()
+                    .filter(|(_, entry)| {
+                        entry.operation_type() == LightningCommonGen::KIND.as_str()
+                    })
+                    .map(|(key, entry)| (key, entry.meta::<LightningMeta>()))
+                    .take(limit - ln_operations.len()),
+            );
+        }
+        ln_operations
+    }
+
+    async fn register_lightning_address(
+        &self,
+        gateway_api: Url,
+        name: String,
+    ) -> anyhow::Result<()> {
+        let (lightning, _instance) = self.get_first_module::<LightningClientModule>(&KIND);
+        let registration = LightningAddressRegistration::new(
+            &lightning.secp,
+            name,
+            self.get_config().federation_id,
+            &lightning.lightning_address_key,
+        )?;
+
+        let response = reqwest::Client::new()
+            .post(gateway_api.join("lnaddress/register")?)
+            .json(&registration)
+            .send()
+            .await?;
+        ensure!(
+            response.status().is_success(),
+            "Gateway rejected the registration: {}",
+            response.text().await?
+        );
+        Ok(())
+    }
+
+    async fn claim_lightning_address_payments(
+        &self,
+        gateway_api: Url,
+        name: String,
+    ) -> anyhow::Result<Vec<OperationId>> {
+        let (lightning, instance) = self.get_first_module::<LightningClientModule>(&KIND);
+        let request = LightningAddressPaymentsRequest::new(
+            &lightning.secp,
+            name,
+            &lightning.lightning_address_key,
+        )?;
+
+        let response = reqwest::Client::new()
+            .post(gateway_api.join("lnaddress/payments")?)
+            .json(&request)
+            .send()
+            .await?;
+        ensure!(
+            response.status().is_success(),
+            "Gateway failed to list payments: {}",
+            response.text().await?
+        );
+        let payments: Vec<LightningAddressPayment> = response.json().await?;
+
+        let mut operation_ids = vec![];
+        for payment in payments {
+            let operation_id = OperationId(payment.invoice.payment_hash().into_inner());
+            if self
+                .operation_log()
+                .get_operation(operation_id)
+                .await
+                .is_some()
+            {
+                continue;
+            }
+
+            // The gateway already submitted the offer, so we only wait for the payment
+            let keypair =
+                payment.claim_keypair(&lightning.secp, &lightning.lightning_address_key)?;
+            let state_machine =
+                LightningClientStateMachines::Receive(LightningReceiveStateMachine {
+                    operation_id,
+                    state: LightningReceiveStates::ConfirmedInvoice(
+                        LightningReceiveConfirmedInvoice {
+                            invoice: payment.invoice.clone(),
+                            keypair,
+                        },
+                    ),
+                });
+
+            self.db()
+                .autocommit(
+                    |dbtx| {
+                        let state_machine = state_machine.clone();
+                        let invoice = payment.invoice.clone();
+                        Box::pin(async move {
+                            self.add_state_machines(
+                                dbtx,
+                                vec![state_machine.into_dyn(instance.id)],
+                            )
+                            .await?;
+                            self.operation_log()
+                                .add_operation_log_entry(
+                                    dbtx,
+                                    operation_id,
+                                    LightningCommonGen::KIND.as_str(),
+                                    LightningMeta::LightningAddressReceive { invoice },
+                                )
+                                .await;
+                            Ok(())
+                        })
+                    },
+                    Some(100),
+                )
+                .await
+                .map_err(|e| match e {
+                    AutocommitError::ClosureError { error, .. } => error,
+                    AutocommitError::CommitFailed { last_error, .. } => {
+                        anyhow::anyhow!("Commit to DB failed: {last_error}")
+                    }
+                })?;
+            operation_ids.push(operation_id);
+        }
+        Ok(operation_ids)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -507,13 +937,41 @@ pub enum LightningMeta {
         out_point: OutPoint,
         invoice: Invoice,
         change_outpoint: Option<OutPoint>,
+        #[serde(default)]
+        metadata: LightningOperationMetadata,
     },
     Receive {
         out_point: OutPoint,
         invoice: Invoice,
+        #[serde(default)]
+        metadata: LightningOperationMetadata,
+    },
+    LightningAddressReceive {
+        invoice: Invoice,
+    },
+    PayKeysend {
+        out_point: OutPoint,
+        destination: secp256k1::PublicKey,
+        amount: Amount,
+        change_outpoint: Option<OutPoint>,
     },
 }
 
+impl LightningMeta {
+    /// Metadata the operation was started with, operations that can't be
+    /// started with metadata have none
+    pub fn metadata(&self) -> LightningOperationMetadata {
+        match self {
+            LightningMeta::Pay { metadata, .. } | LightningMeta::Receive { metadata, .. } => {
+                metadata.clone()
+            }
+            LightningMeta::LightningAddressReceive { .. } | LightningMeta::PayKeysend { .. } => {
+                LightningOperationMetadata::default()
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct LightningClientGen;
 
@@ -546,6 +1004,7 @@ impl ClientModuleGen for LightningClientGen {
             cfg,
             notifier,
             redeem_key: module_root_secret.child_key(ChildId(0)).to_secp_key(&secp),
+            lightning_address_key: module_root_secret.child_key(ChildId(1)).to_secp_key(&secp),
             secp,
             module_api,
         })
@@ -557,6 +1016,8 @@ pub struct LightningClientModule {
     pub cfg: LightningClientConfig,
     notifier: ModuleNotifier<DynGlobalClientContext, LightningClientStateMachines>,
     redeem_key: KeyPair,
+    /// Key payments to our lightning addresses are locked to
+    lightning_address_key: KeyPair,
     secp: Secp256k1<All>,
     module_api: DynModuleApi,
 }
@@ -589,12 +1050,22 @@ impl ClientModule for LightningClientModule {
                 amount: account_output.amount,
                 fee: self.cfg.fee_consensus.contract_output,
             },
-            LightningOutput::Offer(_) | LightningOutput::CancelOutgoing { .. } => {
-                TransactionItemAmount {
-                    amount: Amount::ZERO,
-                    fee: Amount::ZERO,
-                }
+            LightningOutput::Offer(_)
+            | LightningOutput::CancelOutgoing { .. }
+            | LightningOutput::CancelableOffer { .. }
+            | LightningOutput::CancelOffer { .. } => TransactionItemAmount {
+                amount: Amount::ZERO,
+                fee: Amount::ZERO,
+            },
+        }
+    }
+
+    fn balance_event_kind(&self, operation: &OperationLogEntry) -> Option<BalanceEventKind> {
+        match operation.meta::<LightningMeta>() {
+            LightningMeta::Receive { .. } | LightningMeta::LightningAddressReceive { .. } => {
+                Some(BalanceEventKind::LightningPaymentSettled)
             }
+            LightningMeta::Pay { .. } => None,
         }
     }
 }
@@ -623,6 +1094,7 @@ impl LightningClientModule {
         gateway: LightningGateway,
         fed_id: FederationId,
         mut rng: impl RngCore + CryptoRng + 'a,
+        keysend: Option<KeysendPayment>,
     ) -> anyhow::Result<(
         ClientOutput<LightningOutput, LightningClientStateMachines>,
         ContractId,
@@ -692,6 +1164,7 @@ impl LightningClientModule {
                             funding_txid,
                             contract_id,
                             gateway: gateway.clone(),
+                            keysend: keysend.clone(),
                         },
                     ),
                 },
@@ -712,6 +1185,32 @@ impl LightningClientModule {
         ))
     }
 
+    /// Creates the invoice an outgoing contract for a keysend payment is made
+    /// for. It is signed by a throwaway key and only carries the payment hash
+    /// and amount, the gateway pays the destination of the [`KeysendPayment`]
+    /// instead.
+    fn create_keysend_invoice(
+        &self,
+        preimage: &Preimage,
+        amount: Amount,
+        mut rng: impl RngCore + CryptoRng,
+    ) -> anyhow::Result<Invoice> {
+        let (node_secret_key, node_public_key) = self.secp.generate_keypair(&mut rng);
+        let duration_since_epoch = fedimint_core::time::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap();
+
+        Ok(InvoiceBuilder::new(network_to_currency(self.cfg.network))
+            .amount_milli_satoshis(amount.msats)
+            .description("keysend".to_string())
+            .payment_hash(bitcoin_hashes::sha256::Hash::hash(&preimage.0))
+            .payment_secret(PaymentSecret(rng.gen()))
+            .duration_since_epoch(duration_since_epoch)
+            .min_final_cltv_expiry(18)
+            .payee_pub_key(node_public_key)
+            .build_signed(|hash| self.secp.sign_ecdsa_recoverable(hash, &node_secret_key))?)
+    }
+
     /// Create an output that funds an incoming contract within the federation
     /// This directly completes a transaction between users, without involving a
     /// gateway
@@ -768,6 +1267,9 @@ impl LightningClientModule {
                     LightningReceiveStates::Canceled(e) => {
                         return Err(e);
                     }
+                    LightningReceiveStates::Expired => {
+                        return Err(LightningReceiveError::Timeout);
+                    }
                     _ => {}
                 },
                 Some(_) => {}
@@ -788,6 +1290,9 @@ impl LightningClientModule {
                     LightningReceiveStates::Canceled(e) => {
                         return Err(e);
                     }
+                    LightningReceiveStates::Expired => {
+                        return Err(LightningReceiveError::Timeout);
+                    }
                     _ => {}
                 },
                 Some(_) => {}
@@ -931,15 +1436,20 @@ impl LightningClientModule {
             )]
         });
 
-        let ln_output = LightningOutput::Offer(IncomingContractOffer {
-            amount,
-            hash: payment_hash,
-            encrypted_preimage: EncryptedPreimage::new(
-                Preimage(preimage),
-                &self.cfg.threshold_pub_key,
-            ),
-            expiry_time,
-        });
+        let ln_output = LightningOutput::CancelableOffer {
+            offer: IncomingContractOffer {
+                amount,
+                hash: payment_hash,
+                encrypted_preimage: EncryptedPreimage::new(
+                    Preimage(preimage),
+                    &self.cfg.threshold_pub_key,
+                ),
+                expiry_time,
+            },
+            cancellation_key: offer_cancellation_keypair(&self.secp, &payment_keypair)
+                .x_only_public_key()
+                .0,
+        };
 
         Ok((
             operation_id,
@@ -0,0 +1,135 @@
+use anyhow::{ensure, format_err};
+use bitcoin::bech32::{self, FromBase32};
+use fedimint_client::scheduler::ScheduledPaymentHandler;
+use fedimint_client::sm::OperationId;
+use fedimint_client::Client;
+use fedimint_core::{apply, async_trait_maybe_send, Amount};
+use lightning_invoice::Invoice;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{LightningClientExt, PayType};
+
+/// Intent of a recurring payment made by [`LnurlPaymentHandler`]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct LnurlPaymentIntent {
+    /// A bech32 encoded LNURL, a lightning address like `name@domain` or the
+    /// URL of an LNURL-pay endpoint
+    pub lnurl: String,
+    pub amount: Amount,
+}
+
+/// Makes scheduled payments by fetching a new invoice from an LNURL-pay
+/// endpoint every time, see [`fedimint_client::scheduler`]
+#[derive(Debug, Clone, Default)]
+pub struct LnurlPaymentHandler;
+
+#[apply(async_trait_maybe_send!)]
+impl ScheduledPaymentHandler for LnurlPaymentHandler {
+    const KIND: &'static str = "lnurl_pay";
+
+    type Intent = LnurlPaymentIntent;
+
+    async fn pay(&self, client: &Client, intent: Self::Intent) -> anyhow::Result<OperationId> {
+        let invoice = fetch_lnurl_invoice(&intent.lnurl, intent.amount).await?;
+        let (pay_type, _) = client.pay_bolt11_invoice(invoice).await?;
+        Ok(match pay_type {
+            PayType::Internal(operation_id) | PayType::Lightning(operation_id) => operation_id,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LnurlPayResponse {
+    tag: String,
+    callback: Url,
+    min_sendable: u64,
+    max_sendable: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct LnurlCallbackResponse {
+    pr: Invoice,
+}
+
+/// Fetches an invoice over `amount` from the LNURL-pay endpoint `lnurl`
+pub async fn fetch_lnurl_invoice(lnurl: &str, amount: Amount) -> anyhow::Result<Invoice> {
+    let client = reqwest::Client::new();
+
+    let pay_response: LnurlPayResponse = client
+        .get(lnurl_pay_url(lnurl)?)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    ensure!(
+        pay_response.tag == "payRequest",
+        "Not an LNURL-pay endpoint"
+    );
+    ensure!(
+        (pay_response.min_sendable..=pay_response.max_sendable).contains(&amount.msats),
+        "Amount {amount} is outside of the range {}..={} msat accepted by the recipient",
+        pay_response.min_sendable,
+        pay_response.max_sendable
+    );
+
+    let mut callback = pay_response.callback;
+    callback
+        .query_pairs_mut()
+        .append_pair("amount", &amount.msats.to_string());
+    let callback_response: LnurlCallbackResponse = client
+        .get(callback)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    ensure!(
+        callback_response.pr.amount_milli_satoshis() == Some(amount.msats),
+        "Recipient returned an invoice over the wrong amount"
+    );
+    Ok(callback_response.pr)
+}
+
+fn lnurl_pay_url(lnurl: &str) -> anyhow::Result<Url> {
+    if let Some((name, domain)) = lnurl.split_once('@') {
+        return Ok(Url::parse(&format!(
+            "https://{domain}/.well-known/lnurlp/{name}"
+        ))?);
+    }
+
+    if lnurl.to_lowercase().starts_with("lnurl1") {
+        let (hrp, data, _) = bech32::decode(lnurl)?;
+        ensure!(hrp == "lnurl", "Invalid LNURL prefix {hrp}");
+        let url = String::from_utf8(Vec::<u8>::from_base32(&data)?)?;
+        return Ok(Url::parse(&url)?);
+    }
+
+    Url::parse(lnurl).map_err(|e| format_err!("Invalid LNURL {lnurl}: {e}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_lnurl_formats() {
+        assert_eq!(
+            lnurl_pay_url("alice@example.com").unwrap().as_str(),
+            "https://example.com/.well-known/lnurlp/alice"
+        );
+        // Example from the LUD-01 specification
+        assert_eq!(
+            lnurl_pay_url(
+                "LNURL1DP68GURN8GHJ7UM9WFMXJCM99E3K7MF0V9CXJ0M385EKVCENXC6R2C35XVUKXEFCV5MKVV34X5EKZD3EV56NYD3HXQURZEPEXEJXXEPNXSCRVWFNV9NXZCN9XQ6XYEFHVGCXXCMYXYMNSERXFQ5FNS"
+            )
+            .unwrap()
+            .as_str(),
+            "https://service.com/api?q=3fc3645b439ce8e7f2553a69e5267081d96dcd340693afabe04be7b0ccd178df"
+        );
+        assert!(lnurl_pay_url("not a url").is_err());
+    }
+}
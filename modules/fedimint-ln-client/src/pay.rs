@@ -11,7 +11,7 @@ use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::task::sleep;
 use fedimint_core::{OutPoint, TransactionId};
 use fedimint_ln_common::contracts::outgoing::OutgoingContractData;
-use fedimint_ln_common::contracts::ContractId;
+use fedimint_ln_common::contracts::{ContractId, Preimage};
 use fedimint_ln_common::{LightningGateway, LightningInput, LightningOutputOutcome};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
@@ -105,6 +105,9 @@ pub struct LightningPayCreatedOutgoingLnContract {
     pub funding_txid: TransactionId,
     pub contract_id: ContractId,
     pub gateway: LightningGateway,
+    /// Set if the gateway is asked to push the payment to a node instead of
+    /// paying the invoice of the contract
+    pub keysend: Option<KeysendPayment>,
 }
 
 impl LightningPayCreatedOutgoingLnContract {
@@ -119,6 +122,7 @@ impl LightningPayCreatedOutgoingLnContract {
         let funded_common = common.clone();
         let success_context = global_context.clone();
         let gateway = self.gateway.clone();
+        let keysend = self.keysend.clone();
         vec![StateTransition::new(
             Self::await_outgoing_contract_funded(
                 context.ln_decoder.clone(),
@@ -133,6 +137,7 @@ impl LightningPayCreatedOutgoingLnContract {
                     funded_common.clone(),
                     contract_id,
                     gateway.clone(),
+                    keysend.clone(),
                 ))
             },
         )]
@@ -169,6 +174,7 @@ impl LightningPayCreatedOutgoingLnContract {
         common: LightningPayCommon,
         contract_id: ContractId,
         gateway: LightningGateway,
+        keysend: Option<KeysendPayment>,
     ) -> LightningPayStateMachine {
         assert!(matches!(
             old_state.state,
@@ -178,7 +184,10 @@ impl LightningPayCreatedOutgoingLnContract {
         match result {
             Ok(timelock) => {
                 // Success case: funding transaction is accepted
-                let payload = PayInvoicePayload::new(common.federation_id, contract_id);
+                let payload = PayInvoicePayload {
+                    keysend,
+                    ..PayInvoicePayload::new(common.federation_id, contract_id)
+                };
                 LightningPayStateMachine {
                     common: old_state.common,
                     state: LightningPayStates::Funded(LightningPayFunded {
@@ -257,9 +266,15 @@ impl LightningPayFunded {
             })?;
 
         if !response.status().is_success() {
+            let status = response.status();
+            // The gateway explains rejections, e.g. which payment limit was hit
+            let error_message = match response.text().await {
+                Ok(body) if !body.is_empty() => body,
+                _ => status.to_string(),
+            };
             return Err(GatewayPayError::GatewayInternalError {
-                error_code: Some(response.status().as_u16()),
-                error_message: response.status().to_string(),
+                error_code: Some(status.as_u16()),
+                error_message,
             });
         }
 
@@ -478,6 +493,8 @@ impl LightningPayRefund {
 pub struct PayInvoicePayload {
     pub federation_id: FederationId,
     pub contract_id: ContractId,
+    #[serde(default)]
+    pub keysend: Option<KeysendPayment>,
 }
 
 impl PayInvoicePayload {
@@ -485,6 +502,19 @@ impl PayInvoicePayload {
         Self {
             contract_id,
             federation_id,
+            keysend: None,
         }
     }
 }
+
+/// Payment pushed to a node that didn't hand out an invoice
+///
+/// The preimage of the outgoing contract is chosen by the payer and sent to
+/// the destination in the keysend TLV record of the onion. Unlike for invoices
+/// the gateway learns the preimage before paying, so it could claim the
+/// contract without forwarding the payment.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Decodable, Encodable)]
+pub struct KeysendPayment {
+    pub destination: secp256k1::PublicKey,
+    pub preimage: Preimage,
+}
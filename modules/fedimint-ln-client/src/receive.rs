@@ -1,24 +1,29 @@
-use std::ops::Add;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime};
 
 use bitcoin::util::key::KeyPair;
+use bitcoin_hashes::{sha256, Hash, HashEngine};
 use fedimint_client::sm::{ClientSMDatabaseTransaction, OperationId, State, StateTransition};
-use fedimint_client::transaction::{ClientInput, TxSubmissionError};
+use fedimint_client::transaction::{ClientInput, ClientOutput, TxSubmissionError};
 use fedimint_client::DynGlobalClientContext;
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::task::sleep;
 use fedimint_core::{OutPoint, TransactionId};
-use fedimint_ln_common::contracts::incoming::IncomingContractAccount;
+use fedimint_ln_common::contracts::incoming::{IncomingContractAccount, OfferId};
 use fedimint_ln_common::contracts::DecryptedPreimage;
-use fedimint_ln_common::LightningInput;
+use fedimint_ln_common::{LightningInput, LightningOutput};
 use lightning_invoice::Invoice;
+use secp256k1::{Secp256k1, Signing};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use tracing::warn;
 
 use crate::api::LnFederationApi;
 use crate::{LightningClientContext, LightningClientStateMachines};
 
+/// Domain separation of the offer cancellation key derivation
+const OFFER_CANCELLATION_KEY_TAG: &[u8] = b"fedimint-offer-cancellation-key";
+
 #[cfg_attr(doc, aquamarine::aquamarine)]
 /// State machine that waits on the receipt of a Lightning payment.
 ///
@@ -29,7 +34,10 @@ use crate::{LightningClientContext, LightningClientStateMachines};
 ///     SubmittedOffer -- await transaction rejection --> Canceled
 ///     SubmittedOffer -- await invoice confirmation --> ConfirmedInvoice
 ///     ConfirmedInvoice -- await contract creation + decryption  --> Funded
-///     ConfirmedInvoice -- await offer timeout --> Canceled
+///     ConfirmedInvoice -- await invoice expiry, cancel offer --> Expiring
+///     ConfirmedInvoice -- await invoice expiry, cancellation failed --> Canceled
+///     Expiring -- await cancellation acceptance --> Expired
+///     Expiring -- await contract creation + decryption --> Funded
 ///     Funded -- await claim tx acceptance --> Success
 ///     Funded -- await claim tx rejection --> Canceled
 /// ```
@@ -40,6 +48,10 @@ pub enum LightningReceiveStates {
     ConfirmedInvoice(LightningReceiveConfirmedInvoice),
     Funded(LightningReceiveFunded),
     Success(TransactionId),
+    Expiring(LightningReceiveExpiring),
+    /// The invoice expired unpaid and its offer was removed from the
+    /// federation, so it can't be paid anymore
+    Expired,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Decodable, Encodable)]
@@ -73,6 +85,12 @@ impl State for LightningReceiveStateMachine {
             LightningReceiveStates::Success(_) => {
                 vec![]
             }
+            LightningReceiveStates::Expiring(expiring) => {
+                expiring.transitions(self.operation_id, global_context)
+            }
+            LightningReceiveStates::Expired => {
+                vec![]
+            }
         }
     }
 
@@ -100,6 +118,20 @@ pub enum LightningReceiveError {
     InvalidPreimage,
 }
 
+/// Key the offer for one of our invoices can be cancelled with. It is derived
+/// from the payment key since the public payment key is the preimage and must
+/// not be revealed.
+pub fn offer_cancellation_keypair<C: Signing>(
+    secp: &Secp256k1<C>,
+    payment_keypair: &KeyPair,
+) -> KeyPair {
+    let mut engine = sha256::HashEngine::default();
+    engine.input(OFFER_CANCELLATION_KEY_TAG);
+    engine.input(&payment_keypair.secret_bytes());
+    KeyPair::from_seckey_slice(secp, &sha256::Hash::from_engine(engine)[..])
+        .expect("Hash is a valid secret key with overwhelming probability")
+}
+
 impl LightningReceiveSubmittedOffer {
     fn transitions(
         &self,
@@ -155,8 +187,8 @@ impl LightningReceiveSubmittedOffer {
 
 #[derive(Debug, Clone, Eq, PartialEq, Decodable, Encodable)]
 pub struct LightningReceiveConfirmedInvoice {
-    invoice: Invoice,
-    keypair: KeyPair,
+    pub invoice: Invoice,
+    pub keypair: KeyPair,
 }
 
 impl LightningReceiveConfirmedInvoice {
@@ -165,26 +197,36 @@ impl LightningReceiveConfirmedInvoice {
         global_context: &DynGlobalClientContext,
     ) -> Vec<StateTransition<LightningReceiveStateMachine>> {
         let invoice = self.invoice.clone();
-        let timeout = invoice.expiry_time();
         let keypair = self.keypair;
         let global_context = global_context.clone();
         vec![
             StateTransition::new(
-                Self::await_incoming_contract_account(invoice, global_context.clone()),
-                move |dbtx, contract, old_state| {
-                    Box::pin(Self::transition_funded(
+                Self::await_incoming_contract_account(invoice.clone(), global_context.clone()),
+                {
+                    let global_context = global_context.clone();
+                    move |dbtx, contract, old_state| {
+                        Box::pin(Self::transition_funded(
+                            old_state,
+                            keypair,
+                            contract,
+                            dbtx,
+                            global_context.clone(),
+                        ))
+                    }
+                },
+            ),
+            StateTransition::new(
+                Self::await_invoice_expiry(invoice.clone()),
+                move |dbtx, (), old_state| {
+                    Box::pin(Self::transition_expiring(
                         old_state,
+                        invoice.clone(),
                         keypair,
-                        contract,
                         dbtx,
                         global_context.clone(),
                     ))
                 },
             ),
-            StateTransition::new(
-                Self::await_payment_timeout(timeout),
-                |_dbtx, (), old_state| Box::pin(Self::transition_timeout(old_state)),
-            ),
         ]
     }
 
@@ -258,20 +300,144 @@ impl LightningReceiveConfirmedInvoice {
         OutPoint { txid, out_idx: 0 }
     }
 
-    async fn await_payment_timeout(timeout: Duration) {
+    /// Waits until the invoice expired, measured from its creation so the wait
+    /// doesn't start over when the client restarts
+    async fn await_invoice_expiry(invoice: Invoice) {
         // Add 10% of the invoice expiry_time as a buffer before we stop awaiting the
         // payment
-        let timeout_buffer = timeout.as_secs_f64() * 0.1;
-        let payment_timeout = timeout.add(Duration::from_secs_f64(timeout_buffer));
-        sleep(payment_timeout).await
+        let expiry_time = invoice.expiry_time();
+        let expires_at = SystemTime::UNIX_EPOCH
+            + invoice.duration_since_epoch()
+            + expiry_time
+            + expiry_time.mul_f64(0.1);
+        let remaining = expires_at
+            .duration_since(fedimint_core::time::now())
+            .unwrap_or_default();
+        sleep(remaining).await
+    }
+
+    /// Submits the cancellation of the offer, so a late payment can't fund a
+    /// contract we no longer wait for
+    async fn transition_expiring(
+        old_state: LightningReceiveStateMachine,
+        invoice: Invoice,
+        keypair: KeyPair,
+        dbtx: &mut ClientSMDatabaseTransaction<'_, '_>,
+        global_context: DynGlobalClientContext,
+    ) -> LightningReceiveStateMachine {
+        let secp = Secp256k1::new();
+        let hash = *invoice.payment_hash();
+        let signature = secp.sign_schnorr(
+            &OfferId::from_hash(hash).cancellation_message().into(),
+            &offer_cancellation_keypair(&secp, &keypair),
+        );
+        let client_output = ClientOutput::<LightningOutput, LightningClientStateMachines> {
+            output: LightningOutput::CancelOffer { hash, signature },
+            state_machines: Arc::new(|_, _| vec![]),
+        };
+
+        match global_context.fund_output(dbtx, client_output).await {
+            Ok((cancel_txid, _)) => LightningReceiveStateMachine {
+                operation_id: old_state.operation_id,
+                state: LightningReceiveStates::Expiring(LightningReceiveExpiring {
+                    invoice,
+                    keypair,
+                    cancel_txid,
+                }),
+            },
+            Err(e) => {
+                warn!("Unable to submit the cancellation of an expired offer: {e:?}");
+                LightningReceiveStateMachine {
+                    operation_id: old_state.operation_id,
+                    state: LightningReceiveStates::Canceled(LightningReceiveError::Timeout),
+                }
+            }
+        }
+    }
+}
+
+/// The invoice expired and the cancellation of its offer was submitted, it
+/// can still be funded until the cancellation is accepted
+#[derive(Debug, Clone, Eq, PartialEq, Decodable, Encodable)]
+pub struct LightningReceiveExpiring {
+    pub invoice: Invoice,
+    pub keypair: KeyPair,
+    pub cancel_txid: TransactionId,
+}
+
+impl LightningReceiveExpiring {
+    fn transitions(
+        &self,
+        operation_id: OperationId,
+        global_context: &DynGlobalClientContext,
+    ) -> Vec<StateTransition<LightningReceiveStateMachine>> {
+        let keypair = self.keypair;
+        let funded_context = global_context.clone();
+        vec![
+            StateTransition::new(
+                LightningReceiveConfirmedInvoice::await_incoming_contract_account(
+                    self.invoice.clone(),
+                    global_context.clone(),
+                ),
+                move |dbtx, contract, old_state| {
+                    Box::pin(LightningReceiveConfirmedInvoice::transition_funded(
+                        old_state,
+                        keypair,
+                        contract,
+                        dbtx,
+                        funded_context.clone(),
+                    ))
+                },
+            ),
+            StateTransition::new(
+                Self::await_offer_cancellation(
+                    global_context.clone(),
+                    operation_id,
+                    self.cancel_txid,
+                    *self.invoice.payment_hash(),
+                ),
+                |_dbtx, (), old_state| Box::pin(Self::transition_expired(old_state)),
+            ),
+        ]
+    }
+
+    /// Returns once the offer can't be funded anymore. If the cancellation
+    /// was rejected because a contract was funded in the meantime this never
+    /// returns and the contract is claimed instead.
+    async fn await_offer_cancellation(
+        global_context: DynGlobalClientContext,
+        operation_id: OperationId,
+        cancel_txid: TransactionId,
+        payment_hash: sha256::Hash,
+    ) {
+        if global_context
+            .await_tx_accepted(operation_id, cancel_txid)
+            .await
+            .is_ok()
+        {
+            return;
+        }
+
+        // Offers created by gateways for lightning addresses can't be cancelled
+        // and remain, we stop waiting for them like before
+        loop {
+            match global_context.module_api().offer_exists(payment_hash).await {
+                Ok(true) => return,
+                Ok(false) => std::future::pending::<()>().await,
+                Err(e) => {
+                    warn!("Unable to check whether the expired offer still exists: {e:?}");
+                    sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
     }
 
-    async fn transition_timeout(
+    async fn transition_expired(
         old_state: LightningReceiveStateMachine,
     ) -> LightningReceiveStateMachine {
         LightningReceiveStateMachine {
             operation_id: old_state.operation_id,
-            state: LightningReceiveStates::Canceled(LightningReceiveError::Timeout),
+            state: LightningReceiveStates::Expired,
         }
     }
 }
@@ -8,6 +8,7 @@ use fedimint_core::{apply, async_trait_maybe_send, NumPeers};
 use crate::contracts::incoming::{IncomingContractAccount, IncomingContractOffer};
 use crate::contracts::outgoing::OutgoingContractAccount;
 use crate::contracts::{ContractId, FundedContract};
+use crate::listing::{GatewayQuery, OfferQuery};
 use crate::{ContractAccount, LightningGateway};
 
 #[apply(async_trait_maybe_send!)]
@@ -19,6 +20,16 @@ pub trait LnFederationApi {
         payment_hash: Sha256Hash,
     ) -> FederationResult<IncomingContractOffer>;
     async fn fetch_gateways(&self) -> FederationResult<Vec<LightningGateway>>;
+    /// Fetches a page of the registered gateways matching `query`
+    async fn fetch_gateways_page(
+        &self,
+        query: &GatewayQuery,
+    ) -> FederationResult<Vec<LightningGateway>>;
+    /// Fetches a page of the outstanding offers
+    async fn fetch_offers_page(
+        &self,
+        query: &OfferQuery,
+    ) -> FederationResult<Vec<IncomingContractOffer>>;
     async fn register_gateway(&self, gateway: &LightningGateway) -> FederationResult<()>;
     async fn offer_exists(&self, payment_hash: Sha256Hash) -> FederationResult<bool>;
 
@@ -68,6 +79,29 @@ where
         .await
     }
 
+    async fn fetch_gateways_page(
+        &self,
+        query: &GatewayQuery,
+    ) -> FederationResult<Vec<LightningGateway>> {
+        let gateways = self
+            .request_with_strategy(
+                UnionResponses::new(self.all_members().threshold()),
+                "list_gateways_page".to_string(),
+                ApiRequestErased::new(query),
+            )
+            .await?;
+        // Every guardian returns its own page, so we have to page their union again
+        Ok(query.page(gateways))
+    }
+
+    async fn fetch_offers_page(
+        &self,
+        query: &OfferQuery,
+    ) -> FederationResult<Vec<IncomingContractOffer>> {
+        self.request_current_consensus("list_offers_page".to_string(), ApiRequestErased::new(query))
+            .await
+    }
+
     async fn register_gateway(&self, gateway: &LightningGateway) -> FederationResult<()> {
         self.request_with_strategy(
             CurrentConsensus::new(self.all_members().threshold()),
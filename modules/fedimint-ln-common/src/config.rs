@@ -70,8 +70,10 @@ impl LightningConfigConsensus {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LightningConfigPrivate {
     // TODO: propose serde(with = "…") based protection upstream instead
-    /// Our secret key for decrypting preimages
-    pub threshold_sec_key: SerdeSecret<threshold_crypto::SecretKeyShare>,
+    /// Our secret key for decrypting preimages, `None` if it is held by an
+    /// external guardian signer
+    #[serde(default)]
+    pub threshold_sec_key: Option<SerdeSecret<threshold_crypto::SecretKeyShare>>,
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
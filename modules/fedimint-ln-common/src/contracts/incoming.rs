@@ -10,6 +10,8 @@ use serde::{Deserialize, Serialize};
 use crate::contracts::{ContractId, DecryptedPreimage, EncryptedPreimage, IdentifiableContract};
 use crate::LightningInput;
 
+const OFFER_CANCELLATION_TAG: &str = "incoming offer cancellation";
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
 pub struct IncomingContractOffer {
     /// Amount for which the user is willing to sell the preimage
@@ -91,6 +93,18 @@ hash_newtype!(
     doc = "The hash of a LN incoming contract offer"
 );
 
+impl OfferId {
+    /// Message the cancellation key of the offer has to sign to remove it, see
+    /// [`crate::LightningOutput::CancelOffer`]
+    pub fn cancellation_message(&self) -> Sha256 {
+        let mut engine = Sha256::engine();
+        Encodable::consensus_encode(&OFFER_CANCELLATION_TAG.as_bytes(), &mut engine)
+            .expect("Hashing never fails");
+        Encodable::consensus_encode(self, &mut engine).expect("Hashing never fails");
+        Sha256::from_engine(engine)
+    }
+}
+
 impl IdentifiableContract for IncomingContract {
     fn contract_id(&self) -> ContractId {
         ContractId::from_hash(self.hash)
@@ -1,6 +1,6 @@
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::{impl_db_lookup, impl_db_record, OutPoint, PeerId};
-use secp256k1::PublicKey;
+use secp256k1::{PublicKey, XOnlyPublicKey};
 use serde::Serialize;
 use strum_macros::EnumIter;
 
@@ -18,6 +18,7 @@ pub enum DbKeyPrefix {
     ContractUpdate = 0x44,
     LightningGateway = 0x45,
     BlockHeightVote = 0x46,
+    OfferCancellationKey = 0x47,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -70,6 +71,24 @@ impl_db_record!(
 );
 impl_db_lookup!(key = OfferKey, query_prefix = OfferKeyPrefix);
 
+/// Keys that can cancel the offer with the payment hash, only cancelable
+/// offers have one
+#[derive(Debug, Encodable, Decodable, Serialize)]
+pub struct OfferCancellationKey(pub bitcoin_hashes::sha256::Hash);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OfferCancellationKeyPrefix;
+
+impl_db_record!(
+    key = OfferCancellationKey,
+    value = XOnlyPublicKey,
+    db_prefix = DbKeyPrefix::OfferCancellationKey,
+);
+impl_db_lookup!(
+    key = OfferCancellationKey,
+    query_prefix = OfferCancellationKeyPrefix
+);
+
 // TODO: remove redundancy
 #[derive(Debug, Encodable, Decodable, Serialize)]
 pub struct ProposeDecryptionShareKey(pub ContractId);
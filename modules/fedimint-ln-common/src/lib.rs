@@ -14,6 +14,8 @@ pub mod config;
 pub mod contracts;
 pub mod db;
 pub mod incoming;
+pub mod lightning_address;
+pub mod listing;
 
 use std::time::{Duration, SystemTime};
 
@@ -25,6 +27,7 @@ use fedimint_client::Client;
 use fedimint_core::api::DynModuleApi;
 use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
 use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::error::{code, ModuleErrorCode};
 use fedimint_core::module::{CommonModuleGen, ModuleCommon, ModuleConsensusVersion};
 use fedimint_core::task::timeout;
 use fedimint_core::{plugin_types_trait_impl_common, Amount};
@@ -92,6 +95,21 @@ pub enum LightningOutput {
         /// Signature of gateway
         gateway_signature: secp256k1::schnorr::Signature,
     },
+    /// Create incoming contract offer that can be cancelled by its creator,
+    /// e.g. once the invoice expired unpaid
+    CancelableOffer {
+        offer: contracts::incoming::IncomingContractOffer,
+        /// Key that has to sign the cancellation
+        cancellation_key: secp256k1::XOnlyPublicKey,
+    },
+    /// Remove a cancelable offer that wasn't paid for yet
+    CancelOffer {
+        /// Payment hash of the offer
+        hash: bitcoin_hashes::sha256::Hash,
+        /// Signature of [`OfferId::cancellation_message`] by the cancellation
+        /// key
+        signature: secp256k1::schnorr::Signature,
+    },
 }
 
 impl std::fmt::Display for LightningOutput {
@@ -119,6 +137,16 @@ impl std::fmt::Display for LightningOutput {
             LightningOutput::CancelOutgoing { contract, .. } => {
                 write!(f, "LN outgoing contract cancellation {contract}")
             }
+            LightningOutput::CancelableOffer { offer, .. } => {
+                write!(
+                    f,
+                    "LN cancelable offer for {} with hash {}",
+                    offer.amount, offer.hash
+                )
+            }
+            LightningOutput::CancelOffer { hash, .. } => {
+                write!(f, "LN offer cancellation {hash}")
+            }
         }
     }
 }
@@ -147,6 +175,9 @@ pub enum LightningOutputOutcome {
     CancelOutgoingContract {
         id: ContractId,
     },
+    CancelOffer {
+        id: OfferId,
+    },
 }
 
 impl LightningOutputOutcome {
@@ -155,6 +186,7 @@ impl LightningOutputOutcome {
             LightningOutputOutcome::Contract { id: _, outcome } => outcome.is_permanent(),
             LightningOutputOutcome::Offer { .. } => true,
             LightningOutputOutcome::CancelOutgoingContract { .. } => true,
+            LightningOutputOutcome::CancelOffer { .. } => true,
         }
     }
 }
@@ -171,6 +203,9 @@ impl std::fmt::Display for LightningOutputOutcome {
             LightningOutputOutcome::CancelOutgoingContract { id: contract_id } => {
                 write!(f, "LN Outgoing Contract Cancellation {contract_id}")
             }
+            LightningOutputOutcome::CancelOffer { id } => {
+                write!(f, "LN Offer Cancellation {id}")
+            }
         }
     }
 }
@@ -199,6 +234,23 @@ pub struct LightningGateway {
     pub fees: RoutingFees,
 }
 
+/// State of the module a joining client needs, see [`fedimint_core::join`]
+#[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable)]
+pub struct LightningJoinSnapshot {
+    /// Block height the guardians agreed on, needed to set invoice expiries
+    pub block_height: u64,
+    /// Gateways currently registered with the federation
+    pub gateways: Vec<LightningGateway>,
+}
+
+/// State of the module shown on guardian dashboards
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightningAdminStatus {
+    pub block_height: u64,
+    /// Gateways currently registered with the federation
+    pub gateways: Vec<LightningGateway>,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Encodable, Decodable, Serialize, Deserialize)]
 pub enum LightningConsensusItem {
     DecryptPreimage(ContractId, PreimageDecryptionShare),
@@ -369,6 +421,27 @@ pub enum LightningError {
     NotOutgoingContract,
     #[error("Cancellation request wasn't properly signed")]
     InvalidCancellationSignature,
+    #[error("Offer for payment hash {0} can't be cancelled")]
+    OfferNotCancelable(secp256k1::hashes::sha256::Hash),
+}
+
+impl ModuleErrorCode for LightningError {
+    fn code(&self) -> i32 {
+        match self {
+            LightningError::UnknownContract(_) | LightningError::NoOffer(_) => code::NOT_FOUND,
+            LightningError::InsufficientFunds(_, _) | LightningError::OfferNotCancelable(_) => {
+                code::CONFLICT
+            }
+            LightningError::ContractNotReady => code::UNAVAILABLE,
+            LightningError::MissingPreimage
+            | LightningError::InvalidPreimage
+            | LightningError::ZeroOutput
+            | LightningError::InvalidEncryptedPreimage
+            | LightningError::InsufficientIncomingFunding(_, _)
+            | LightningError::NotOutgoingContract
+            | LightningError::InvalidCancellationSignature => code::BAD_REQUEST,
+        }
+    }
 }
 
 pub async fn ln_operation(
@@ -388,15 +461,24 @@ pub async fn ln_operation(
     Ok(operation)
 }
 
+/// Fetches the offer an incoming payment with `payment_hash` buys the preimage
+/// of
+pub async fn fetch_incoming_offer(
+    module_api: &DynModuleApi,
+    payment_hash: sha256::Hash,
+) -> Result<IncomingContractOffer, IncomingSmError> {
+    timeout(Duration::from_secs(5), module_api.fetch_offer(payment_hash))
+        .await
+        .map_err(|_| IncomingSmError::Timeout)?
+        .map_err(|_| IncomingSmError::FetchContractError)
+}
+
 async fn fetch_and_validate_offer(
     module_api: &DynModuleApi,
     payment_hash: sha256::Hash,
     amount_msat: Amount,
 ) -> anyhow::Result<IncomingContractOffer, IncomingSmError> {
-    let offer = timeout(Duration::from_secs(5), module_api.fetch_offer(payment_hash))
-        .await
-        .map_err(|_| IncomingSmError::Timeout)?
-        .map_err(|_| IncomingSmError::FetchContractError)?;
+    let offer = fetch_incoming_offer(module_api, payment_hash).await?;
 
     if offer.amount > amount_msat {
         return Err(IncomingSmError::ViolatedFeePolicy);
@@ -0,0 +1,243 @@
+//! Lightning addresses (LUD-16) hosted by gateways
+//!
+//! A user claims `name@gateway` by registering a public key with the gateway.
+//! For every payment the gateway tweaks that key with a fresh random tweak and
+//! uses the tweaked key as the preimage of the incoming contract offer, like
+//! clients do for their own invoices. Only the user can derive the matching
+//! secret key, so they can claim the payment whenever they come online.
+
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, ensure};
+use bitcoin_hashes::{sha256, Hash, HashEngine};
+use fedimint_core::config::FederationId;
+use fedimint_core::encoding::{Decodable, Encodable};
+use lightning_invoice::Invoice;
+use secp256k1::{
+    schnorr, KeyPair, Message, Scalar, Secp256k1, Signing, Verification, XOnlyPublicKey,
+};
+use serde::{Deserialize, Serialize};
+
+/// Domain separation of the signature over a registration
+const REGISTRATION_TAG: &[u8] = b"fedimint-lightning-address-registration";
+
+/// Domain separation of the signature over a request for payments
+const PAYMENTS_REQUEST_TAG: &[u8] = b"fedimint-lightning-address-payments";
+
+/// How far the timestamp of a [`LightningAddressPaymentsRequest`] may be off
+pub const MAX_REQUEST_AGE: Duration = Duration::from_secs(300);
+
+/// Maximum length of the name part of a lightning address
+pub const MAX_NAME_LENGTH: usize = 64;
+
+/// Request to claim `name` on a gateway, signed by the key payments to the
+/// address will be locked to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct LightningAddressRegistration {
+    pub name: String,
+    /// Federation the user receives payments in
+    pub federation_id: FederationId,
+    pub pub_key: XOnlyPublicKey,
+    pub signature: schnorr::Signature,
+}
+
+impl LightningAddressRegistration {
+    pub fn new<C: Signing>(
+        secp: &Secp256k1<C>,
+        name: String,
+        federation_id: FederationId,
+        keypair: &KeyPair,
+    ) -> anyhow::Result<Self> {
+        let pub_key = keypair.x_only_public_key().0;
+        let message = registration_message(&name, &federation_id, &pub_key)?;
+        Ok(LightningAddressRegistration {
+            name,
+            federation_id,
+            pub_key,
+            signature: secp.sign_schnorr(&message, keypair),
+        })
+    }
+
+    /// Checks the name is allowed and the signature is valid
+    pub fn verify<C: Verification>(&self, secp: &Secp256k1<C>) -> anyhow::Result<()> {
+        ensure!(is_valid_name(&self.name), "Invalid name {}", self.name);
+        let message = registration_message(&self.name, &self.federation_id, &self.pub_key)?;
+        secp.verify_schnorr(&self.signature, &message, &self.pub_key)?;
+        Ok(())
+    }
+}
+
+fn registration_message(
+    name: &str,
+    federation_id: &FederationId,
+    pub_key: &XOnlyPublicKey,
+) -> anyhow::Result<Message> {
+    let mut engine = sha256::HashEngine::default();
+    engine.input(REGISTRATION_TAG);
+    name.to_owned().consensus_encode(&mut engine)?;
+    federation_id.consensus_encode(&mut engine)?;
+    pub_key.consensus_encode(&mut engine)?;
+    Ok(Message::from_slice(
+        &sha256::Hash::from_engine(engine).into_inner(),
+    )?)
+}
+
+/// Request for the payments to a lightning address, signed by its owner so
+/// nobody else learns what they received
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LightningAddressPaymentsRequest {
+    pub name: String,
+    /// Unix timestamp in seconds, limits replays to [`MAX_REQUEST_AGE`]
+    pub timestamp: u64,
+    pub signature: schnorr::Signature,
+}
+
+impl LightningAddressPaymentsRequest {
+    pub fn new<C: Signing>(
+        secp: &Secp256k1<C>,
+        name: String,
+        keypair: &KeyPair,
+    ) -> anyhow::Result<Self> {
+        let timestamp = unix_time(fedimint_core::time::now())?;
+        let message = payments_request_message(&name, timestamp)?;
+        Ok(LightningAddressPaymentsRequest {
+            name,
+            timestamp,
+            signature: secp.sign_schnorr(&message, keypair),
+        })
+    }
+
+    /// Checks the request is recent and signed by the key `name` was
+    /// registered with
+    pub fn verify<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        pub_key: &XOnlyPublicKey,
+        now: SystemTime,
+    ) -> anyhow::Result<()> {
+        let now = unix_time(now)?;
+        ensure!(
+            now.abs_diff(self.timestamp) <= MAX_REQUEST_AGE.as_secs(),
+            "Request timestamp is too far from the current time"
+        );
+        let message = payments_request_message(&self.name, self.timestamp)?;
+        secp.verify_schnorr(&self.signature, &message, pub_key)?;
+        Ok(())
+    }
+}
+
+fn payments_request_message(name: &str, timestamp: u64) -> anyhow::Result<Message> {
+    let mut engine = sha256::HashEngine::default();
+    engine.input(PAYMENTS_REQUEST_TAG);
+    name.to_owned().consensus_encode(&mut engine)?;
+    timestamp.consensus_encode(&mut engine)?;
+    Ok(Message::from_slice(
+        &sha256::Hash::from_engine(engine).into_inner(),
+    )?)
+}
+
+fn unix_time(time: SystemTime) -> anyhow::Result<u64> {
+    Ok(time.duration_since(SystemTime::UNIX_EPOCH)?.as_secs())
+}
+
+/// LUD-16 only allows lowercase letters, digits, `-`, `_` and `.` in names
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| matches!(c, 'a'..='z' | '0'..='9' | '-' | '_' | '.'))
+}
+
+/// A payment the gateway created an invoice for on behalf of the user
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct LightningAddressPayment {
+    pub invoice: Invoice,
+    /// Tweak of the registered key that the incoming contract is locked to
+    pub tweak: [u8; 32],
+}
+
+impl LightningAddressPayment {
+    /// The key that can claim the incoming contract of this payment
+    pub fn claim_keypair<C: Signing + Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        keypair: &KeyPair,
+    ) -> anyhow::Result<KeyPair> {
+        let claim_keypair = keypair.add_xonly_tweak(secp, &Scalar::from_be_bytes(self.tweak)?)?;
+        let preimage = claim_keypair.x_only_public_key().0.serialize();
+        if sha256::Hash::hash(&preimage) != *self.invoice.payment_hash() {
+            bail!("Invoice is not payable to the tweaked key");
+        }
+        Ok(claim_keypair)
+    }
+}
+
+/// The key the incoming contract of a payment is locked to, its serialization
+/// is the preimage of the invoice
+pub fn tweak_pub_key<C: Verification>(
+    secp: &Secp256k1<C>,
+    pub_key: &XOnlyPublicKey,
+    tweak: [u8; 32],
+) -> anyhow::Result<XOnlyPublicKey> {
+    Ok(pub_key.add_tweak(secp, &Scalar::from_be_bytes(tweak)?)?.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registration_is_verified() {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::new(&secp, &mut rand::thread_rng());
+        let federation_id = FederationId(threshold_crypto::SecretKey::random().public_key());
+
+        let registration =
+            LightningAddressRegistration::new(&secp, "alice".to_string(), federation_id, &keypair)
+                .unwrap();
+        registration.verify(&secp).unwrap();
+
+        let mut renamed = registration.clone();
+        renamed.name = "bob".to_string();
+        assert!(renamed.verify(&secp).is_err());
+
+        let invalid =
+            LightningAddressRegistration::new(&secp, "Alice".to_string(), federation_id, &keypair)
+                .unwrap();
+        assert!(invalid.verify(&secp).is_err());
+    }
+
+    #[test]
+    fn payments_request_is_verified() {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::new(&secp, &mut rand::thread_rng());
+        let pub_key = keypair.x_only_public_key().0;
+
+        let request =
+            LightningAddressPaymentsRequest::new(&secp, "alice".to_string(), &keypair).unwrap();
+        let now = fedimint_core::time::now();
+        request.verify(&secp, &pub_key, now).unwrap();
+
+        let other_key = KeyPair::new(&secp, &mut rand::thread_rng())
+            .x_only_public_key()
+            .0;
+        assert!(request.verify(&secp, &other_key, now).is_err());
+        assert!(request
+            .verify(&secp, &pub_key, now + MAX_REQUEST_AGE * 2)
+            .is_err());
+    }
+
+    #[test]
+    fn tweaked_keys_match() {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::new(&secp, &mut rand::thread_rng());
+        let tweak = sha256::Hash::hash(b"tweak").into_inner();
+
+        let tweaked = tweak_pub_key(&secp, &keypair.x_only_public_key().0, tweak).unwrap();
+        let claim_keypair = keypair
+            .add_xonly_tweak(&secp, &Scalar::from_be_bytes(tweak).unwrap())
+            .unwrap();
+        assert_eq!(claim_keypair.x_only_public_key().0, tweaked);
+    }
+}
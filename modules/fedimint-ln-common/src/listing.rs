@@ -0,0 +1,185 @@
+//! Paginated and filtered listings of gateways and offers
+//!
+//! Public federations can have many gateways registered and offers
+//! outstanding, so instead of returning all of them the list endpoints return
+//! one page at a time. Entries are sorted by their key and a page starts after
+//! the key of the last entry of the previous page.
+use std::time::SystemTime;
+
+use bitcoin_hashes::sha256;
+use serde::{Deserialize, Serialize};
+
+use crate::contracts::incoming::IncomingContractOffer;
+use crate::LightningGateway;
+
+/// Maximum number of entries in a page
+pub const MAX_PAGE_SIZE: usize = 100;
+
+/// Selects a page of the gateways registered with the federation
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct GatewayQuery {
+    /// Only gateways with a node key sorting after this one, the node key of
+    /// the last gateway of the previous page
+    pub after: Option<secp256k1::PublicKey>,
+    /// Maximum number of gateways in the page, capped at [`MAX_PAGE_SIZE`]
+    pub limit: Option<usize>,
+    /// Only the gateway of this lightning node
+    pub node_pub_key: Option<secp256k1::PublicKey>,
+    /// Only gateways charging at most this base fee
+    pub max_base_msat: Option<u32>,
+    /// Only gateways charging at most this proportional fee
+    pub max_proportional_millionths: Option<u32>,
+    /// Only gateways whose registration is valid until at least this time.
+    /// Gateways renew their registration regularly, so this leaves out the
+    /// ones that weren't seen for a while.
+    pub min_valid_until: Option<SystemTime>,
+}
+
+impl GatewayQuery {
+    /// Whether `gateway` passes the filters of the query, ignoring the page
+    pub fn matches(&self, gateway: &LightningGateway) -> bool {
+        self.node_pub_key
+            .map_or(true, |key| gateway.node_pub_key == key)
+            && self
+                .max_base_msat
+                .map_or(true, |fee| gateway.fees.base_msat <= fee)
+            && self
+                .max_proportional_millionths
+                .map_or(true, |fee| gateway.fees.proportional_millionths <= fee)
+            && self
+                .min_valid_until
+                .map_or(true, |time| gateway.valid_until >= time)
+    }
+
+    /// Selects the page from `gateways` that pass the filters
+    ///
+    /// Gateways registered with several guardians can be included more than
+    /// once, only the most recent registration of each node is kept.
+    pub fn page(
+        &self,
+        gateways: impl IntoIterator<Item = LightningGateway>,
+    ) -> Vec<LightningGateway> {
+        let mut gateways = gateways
+            .into_iter()
+            .filter(|gateway| self.matches(gateway))
+            .filter(|gateway| {
+                self.after.map_or(true, |after| {
+                    gateway_sort_key(&gateway.node_pub_key) > gateway_sort_key(&after)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        gateways.sort_by(|a, b| {
+            gateway_sort_key(&a.node_pub_key)
+                .cmp(&gateway_sort_key(&b.node_pub_key))
+                .then(b.valid_until.cmp(&a.valid_until))
+        });
+        gateways.dedup_by_key(|gateway| gateway.node_pub_key);
+        gateways.truncate(page_size(self.limit));
+        gateways
+    }
+}
+
+/// Selects a page of the offers outstanding in the federation
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct OfferQuery {
+    /// Only offers with a payment hash sorting after this one, the hash of the
+    /// last offer of the previous page
+    pub after: Option<sha256::Hash>,
+    /// Maximum number of offers in the page, capped at [`MAX_PAGE_SIZE`]
+    pub limit: Option<usize>,
+}
+
+impl OfferQuery {
+    /// Selects the page from `offers`
+    pub fn page(
+        &self,
+        offers: impl IntoIterator<Item = IncomingContractOffer>,
+    ) -> Vec<IncomingContractOffer> {
+        let mut offers = offers
+            .into_iter()
+            .filter(|offer| self.after.map_or(true, |after| offer.hash > after))
+            .collect::<Vec<_>>();
+
+        offers.sort_by_key(|offer| offer.hash);
+        offers.truncate(page_size(self.limit));
+        offers
+    }
+}
+
+/// Gateways are sorted by their serialized node key, which unlike the `Ord`
+/// implementation of the key is stable across library versions
+fn gateway_sort_key(key: &secp256k1::PublicKey) -> [u8; 33] {
+    key.serialize()
+}
+
+fn page_size(limit: Option<usize>) -> usize {
+    limit.unwrap_or(MAX_PAGE_SIZE).min(MAX_PAGE_SIZE)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use fedimint_core::time::now;
+    use lightning::routing::gossip::RoutingFees;
+    use secp256k1::{KeyPair, Secp256k1};
+
+    use super::*;
+
+    fn gateway(base_msat: u32, valid_until: SystemTime) -> LightningGateway {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::new(&secp, &mut rand::thread_rng());
+        LightningGateway {
+            mint_channel_id: 0,
+            gateway_pub_key: keypair.x_only_public_key().0,
+            node_pub_key: keypair.public_key(),
+            api: "http://gateway.example".parse().unwrap(),
+            route_hints: vec![],
+            valid_until,
+            fees: RoutingFees {
+                base_msat,
+                proportional_millionths: 0,
+            },
+        }
+    }
+
+    #[test]
+    fn pages_through_matching_gateways() {
+        let now = now();
+        let mut gateways = (0..5)
+            .map(|_| gateway(1000, now + Duration::from_secs(600)))
+            .collect::<Vec<_>>();
+        gateways.push(gateway(5000, now + Duration::from_secs(600)));
+        gateways.push(gateway(1000, now));
+
+        let query = GatewayQuery {
+            limit: Some(2),
+            max_base_msat: Some(1000),
+            min_valid_until: Some(now + Duration::from_secs(60)),
+            ..Default::default()
+        };
+
+        let mut listed = vec![];
+        let mut page_query = query.clone();
+        loop {
+            let page = page_query.page(gateways.clone());
+            if page.is_empty() {
+                break;
+            }
+            assert!(page.len() <= 2);
+            page_query.after = page.last().map(|gateway| gateway.node_pub_key);
+            listed.extend(page);
+        }
+
+        assert_eq!(listed.len(), 5);
+        assert!(listed.iter().all(|gateway| query.matches(gateway)));
+
+        // A guardian with an older registration of the same node doesn't
+        // duplicate it
+        let mut outdated = listed[0].clone();
+        outdated.valid_until = now + Duration::from_secs(120);
+        let page = GatewayQuery::default().page(vec![outdated, listed[0].clone()]);
+        assert_eq!(page, vec![listed[0].clone()]);
+    }
+}
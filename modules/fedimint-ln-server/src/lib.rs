@@ -8,15 +8,18 @@ use fedimint_core::config::{
     ClientModuleConfig, ConfigGenModuleParams, DkgResult, ServerModuleConfig,
     ServerModuleConsensusConfig, TypedServerModuleConfig, TypedServerModuleConsensusConfig,
 };
+use fedimint_core::core::ModuleInstanceId;
 use fedimint_core::db::{Database, DatabaseVersion, ModuleDatabaseTransaction};
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::module::audit::Audit;
+use fedimint_core::module::interconnect::DynModuleInterconnect;
 use fedimint_core::module::{
     api_endpoint, ApiEndpoint, ApiEndpointContext, ConsensusProposal, CoreConsensusVersion,
-    ExtendsCommonModuleGen, InputMeta, IntoModuleError, ModuleConsensusVersion, ModuleError,
+    ExtendsCommonModuleGen, InputMeta, IntoCodedModuleError, ModuleConsensusVersion, ModuleError,
     PeerHandle, ServerModuleGen, SupportedModuleApiVersions, TransactionItemAmount,
 };
 use fedimint_core::server::DynServerModule;
+use fedimint_core::stats::ItemActivity;
 use fedimint_core::task::{sleep, TaskGroup};
 use fedimint_core::{
     apply, async_trait_maybe_send, push_db_pair_items, Amount, NumPeers, OutPoint, PeerId,
@@ -36,18 +39,22 @@ use fedimint_ln_common::db::{
     AgreedDecryptionShareContractIdPrefix, AgreedDecryptionShareKey,
     AgreedDecryptionShareKeyPrefix, BlockHeightVoteKey, BlockHeightVotePrefix, ContractKey,
     ContractKeyPrefix, ContractUpdateKey, ContractUpdateKeyPrefix, DbKeyPrefix,
-    LightningGatewayKey, LightningGatewayKeyPrefix, OfferKey, OfferKeyPrefix,
-    ProposeDecryptionShareKey, ProposeDecryptionShareKeyPrefix,
+    LightningGatewayKey, LightningGatewayKeyPrefix, OfferCancellationKey,
+    OfferCancellationKeyPrefix, OfferKey, OfferKeyPrefix, ProposeDecryptionShareKey,
+    ProposeDecryptionShareKeyPrefix,
 };
+use fedimint_ln_common::listing::{GatewayQuery, OfferQuery};
 use fedimint_ln_common::{
-    ContractAccount, LightningCommonGen, LightningConsensusItem, LightningError, LightningGateway,
-    LightningInput, LightningModuleTypes, LightningOutput, LightningOutputOutcome,
+    ContractAccount, ContractOutput, LightningAdminStatus, LightningCommonGen,
+    LightningConsensusItem, LightningError, LightningGateway, LightningInput,
+    LightningJoinSnapshot, LightningModuleTypes, LightningOutput, LightningOutputOutcome,
 };
 use fedimint_metrics::{
     histogram_opts, lazy_static, opts, prometheus, register_histogram, register_int_counter,
     Histogram, IntCounter,
 };
 use fedimint_server::config::distributedgen::PeerHandleOps;
+use fedimint_server::signer::{guardian_signer, DynGuardianSigner, LocalSigner};
 use futures::StreamExt;
 use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
@@ -124,14 +131,17 @@ impl ServerModuleGen for LightningGen {
     async fn init(
         &self,
         cfg: ServerModuleConfig,
+        module_instance_id: ModuleInstanceId,
+        _our_id: PeerId,
         _db: Database,
         task_group: &mut TaskGroup,
+        _interconnect: DynModuleInterconnect,
     ) -> anyhow::Result<DynServerModule> {
         // Ensure all metrics are initialized
         for metric in ALL_METRICS.iter() {
             metric.collect();
         }
-        Ok(Lightning::new(cfg.to_typed()?, task_group)?.into())
+        Ok(Lightning::new(cfg.to_typed()?, module_instance_id, task_group)?.into())
     }
 
     fn trusted_dealer_gen(
@@ -160,7 +170,7 @@ impl ServerModuleGen for LightningGen {
                             network: params.consensus.network,
                         },
                         private: LightningConfigPrivate {
-                            threshold_sec_key: threshold_crypto::serde_impl::SerdeSecret(sk),
+                            threshold_sec_key: Some(threshold_crypto::serde_impl::SerdeSecret(sk)),
                         },
                     }
                     .to_erased(),
@@ -191,7 +201,7 @@ impl ServerModuleGen for LightningGen {
                 network: params.consensus.network,
             },
             private: LightningConfigPrivate {
-                threshold_sec_key: keys.secret_key_share,
+                threshold_sec_key: Some(keys.secret_key_share),
             },
         };
 
@@ -200,7 +210,10 @@ impl ServerModuleGen for LightningGen {
 
     fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
         let config = config.to_typed::<LightningConfig>()?;
-        if config.private.threshold_sec_key.public_key_share()
+        let Some(threshold_sec_key) = &config.private.threshold_sec_key else {
+            return Ok(());
+        };
+        if threshold_sec_key.public_key_share()
             != config
                 .consensus
                 .threshold_pub_keys
@@ -310,6 +323,16 @@ impl ServerModuleGen for LightningGen {
                         "Block Height Votes"
                     );
                 }
+                DbKeyPrefix::OfferCancellationKey => {
+                    push_db_pair_items!(
+                        dbtx,
+                        OfferCancellationKeyPrefix,
+                        OfferCancellationKey,
+                        secp256k1::XOnlyPublicKey,
+                        lightning,
+                        "Offer Cancellation Keys"
+                    );
+                }
             }
         }
 
@@ -341,6 +364,8 @@ impl ServerModuleGen for LightningGen {
 pub struct Lightning {
     cfg: LightningConfig,
     btc_rpc: DynBitcoindRpc,
+    /// Decrypts preimage shares with our threshold key share
+    signer: DynGuardianSigner,
 }
 
 #[apply(async_trait_maybe_send!)]
@@ -557,14 +582,14 @@ impl ServerModule for Lightning {
             .get_contract_account(dbtx, input.contract_id)
             .await
             .ok_or(LightningError::UnknownContract(input.contract_id))
-            .into_module_error_other()?;
+            .into_module_error()?;
 
         if account.amount < input.amount {
             return Err(LightningError::InsufficientFunds(
                 account.amount,
                 input.amount,
             ))
-            .into_module_error_other();
+            .into_module_error();
         }
 
         let consensus_height = self.consensus_block_height(dbtx).await;
@@ -577,13 +602,13 @@ impl ServerModule for Lightning {
                             .witness
                             .as_ref()
                             .ok_or(LightningError::MissingPreimage)
-                            .into_module_error_other()?
+                            .into_module_error()?
                             .0,
                     );
 
                     // … and the spender provides a valid preimage …
                     if preimage_hash != outgoing.hash {
-                        return Err(LightningError::InvalidPreimage).into_module_error_other();
+                        return Err(LightningError::InvalidPreimage).into_module_error();
                     }
 
                     // … then the contract account can be spent using the gateway key,
@@ -596,14 +621,12 @@ impl ServerModule for Lightning {
             FundedContract::Incoming(incoming) => match incoming.contract.decrypted_preimage {
                 // Once the preimage has been decrypted …
                 DecryptedPreimage::Pending => {
-                    return Err(LightningError::ContractNotReady).into_module_error_other();
+                    return Err(LightningError::ContractNotReady).into_module_error();
                 }
                 // … either the user may spend the funds since they sold a valid preimage …
                 DecryptedPreimage::Some(preimage) => match preimage.to_public_key() {
                     Ok(pub_key) => pub_key,
-                    Err(_) => {
-                        return Err(LightningError::InvalidPreimage).into_module_error_other()
-                    }
+                    Err(_) => return Err(LightningError::InvalidPreimage).into_module_error(),
                 },
                 // … or the gateway may claim back funds for not receiving the advertised preimage.
                 DecryptedPreimage::Invalid => incoming.contract.gateway_key,
@@ -651,7 +674,7 @@ impl ServerModule for Lightning {
                         .get_value(&OfferKey(incoming.hash))
                         .await
                         .ok_or(LightningError::NoOffer(incoming.hash))
-                        .into_module_error_other()?;
+                        .into_module_error()?;
 
                     if contract.amount < offer.amount {
                         // If the account is not sufficiently funded fail the output
@@ -659,12 +682,12 @@ impl ServerModule for Lightning {
                             offer.amount,
                             contract.amount,
                         ))
-                        .into_module_error_other();
+                        .into_module_error();
                     }
                 }
 
                 if contract.amount == Amount::ZERO {
-                    Err(LightningError::ZeroOutput).into_module_error_other()
+                    Err(LightningError::ZeroOutput).into_module_error()
                 } else {
                     Ok(TransactionItemAmount {
                         amount: contract.amount,
@@ -672,13 +695,36 @@ impl ServerModule for Lightning {
                     })
                 }
             }
-            LightningOutput::Offer(offer) => {
+            LightningOutput::Offer(offer) | LightningOutput::CancelableOffer { offer, .. } => {
                 if !offer.encrypted_preimage.0.verify() {
-                    Err(LightningError::InvalidEncryptedPreimage).into_module_error_other()
+                    Err(LightningError::InvalidEncryptedPreimage).into_module_error()
                 } else {
                     Ok(TransactionItemAmount::ZERO)
                 }
             }
+            LightningOutput::CancelOffer { hash, signature } => {
+                let offer = dbtx
+                    .get_value(&OfferKey(*hash))
+                    .await
+                    .ok_or(LightningError::NoOffer(*hash))
+                    .into_module_error()?;
+                let cancellation_key = dbtx
+                    .get_value(&OfferCancellationKey(*hash))
+                    .await
+                    .ok_or(LightningError::OfferNotCancelable(*hash))
+                    .into_module_error()?;
+
+                secp256k1::global::SECP256K1
+                    .verify_schnorr(
+                        signature,
+                        &offer.id().cancellation_message().into(),
+                        &cancellation_key,
+                    )
+                    .map_err(|_| LightningError::InvalidCancellationSignature)
+                    .into_module_error()?;
+
+                Ok(TransactionItemAmount::ZERO)
+            }
             LightningOutput::CancelOutgoing {
                 contract,
                 gateway_signature,
@@ -687,12 +733,12 @@ impl ServerModule for Lightning {
                     .get_value(&ContractKey(*contract))
                     .await
                     .ok_or(LightningError::UnknownContract(*contract))
-                    .into_module_error_other()?;
+                    .into_module_error()?;
 
                 let outgoing_contract = match &contract_account.contract {
                     FundedContract::Outgoing(contract) => contract,
                     _ => {
-                        return Err(LightningError::NotOutgoingContract).into_module_error_other();
+                        return Err(LightningError::NotOutgoingContract).into_module_error();
                     }
                 };
 
@@ -703,7 +749,7 @@ impl ServerModule for Lightning {
                         &outgoing_contract.gateway_key,
                     )
                     .map_err(|_| LightningError::InvalidCancellationSignature)
-                    .into_module_error_other()?;
+                    .into_module_error()?;
 
                 Ok(TransactionItemAmount::ZERO)
             }
@@ -767,10 +813,9 @@ impl ServerModule for Lightning {
                         .expect("offer exists if output is valid");
 
                     let decryption_share = self
-                        .cfg
-                        .private
-                        .threshold_sec_key
+                        .signer
                         .decrypt_share(&incoming.encrypted_preimage.0)
+                        .await
                         .expect("We checked for decryption share validity on contract creation");
                     dbtx.insert_new_entry(
                         &ProposeDecryptionShareKey(contract.contract.contract_id()),
@@ -778,9 +823,10 @@ impl ServerModule for Lightning {
                     )
                     .await;
                     dbtx.remove_entry(&OfferKey(offer.hash)).await;
+                    dbtx.remove_entry(&OfferCancellationKey(offer.hash)).await;
                 }
             }
-            LightningOutput::Offer(offer) => {
+            LightningOutput::Offer(offer) | LightningOutput::CancelableOffer { offer, .. } => {
                 dbtx.insert_new_entry(
                     &ContractUpdateKey(out_point),
                     &LightningOutputOutcome::Offer { id: offer.id() },
@@ -789,8 +835,28 @@ impl ServerModule for Lightning {
                 // TODO: sanity-check encrypted preimage size
                 dbtx.insert_new_entry(&OfferKey(offer.hash), &(*offer).clone())
                     .await;
+                if let LightningOutput::CancelableOffer {
+                    cancellation_key, ..
+                } = output
+                {
+                    dbtx.insert_entry(&OfferCancellationKey(offer.hash), cancellation_key)
+                        .await;
+                }
                 LN_INCOMING_OFFER.inc();
             }
+            LightningOutput::CancelOffer { hash, .. } => {
+                let offer = dbtx
+                    .remove_entry(&OfferKey(*hash))
+                    .await
+                    .expect("Offer exists if output is valid");
+                dbtx.remove_entry(&OfferCancellationKey(*hash)).await;
+
+                dbtx.insert_new_entry(
+                    &ContractUpdateKey(out_point),
+                    &LightningOutputOutcome::CancelOffer { id: offer.id() },
+                )
+                .await;
+            }
             LightningOutput::CancelOutgoing { contract, .. } => {
                 let updated_contract_account = {
                     let mut contract_account = dbtx
@@ -848,6 +914,44 @@ impl ServerModule for Lightning {
             .await;
     }
 
+    fn output_activity(&self, output: &LightningOutput) -> ItemActivity {
+        // Funding a contract is the only step of a payment naming the gateway
+        let gateway = match output {
+            LightningOutput::Contract(ContractOutput {
+                contract: Contract::Incoming(incoming),
+                ..
+            }) => Some(incoming.gateway_key),
+            LightningOutput::Contract(ContractOutput {
+                contract: Contract::Outgoing(outgoing),
+                ..
+            }) => Some(outgoing.gateway_key),
+            _ => None,
+        };
+        ItemActivity {
+            gateway,
+            ..Default::default()
+        }
+    }
+
+    async fn join_snapshot(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) -> Option<Vec<u8>> {
+        let snapshot = LightningJoinSnapshot {
+            block_height: self.consensus_block_height(dbtx).await,
+            gateways: self.list_gateways(dbtx).await,
+        };
+        Some(snapshot.consensus_encode_to_vec_exact())
+    }
+
+    async fn admin_status(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> Option<serde_json::Value> {
+        let status = LightningAdminStatus {
+            block_height: self.consensus_block_height(dbtx).await,
+            gateways: self.list_gateways(dbtx).await,
+        };
+        Some(serde_json::to_value(status).expect("serialization can't fail"))
+    }
+
     fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
         vec![
             api_endpoint! {
@@ -894,6 +998,18 @@ impl ServerModule for Lightning {
                     Ok(module.list_gateways(&mut context.dbtx()).await)
                 }
             },
+            api_endpoint! {
+                "list_gateways_page",
+                async |module: &Lightning, context, query: GatewayQuery| -> Vec<LightningGateway> {
+                    Ok(query.page(module.list_gateways(&mut context.dbtx()).await))
+                }
+            },
+            api_endpoint! {
+                "list_offers_page",
+                async |module: &Lightning, context, query: OfferQuery| -> Vec<IncomingContractOffer> {
+                    Ok(query.page(module.get_offers(&mut context.dbtx()).await))
+                }
+            },
             api_endpoint! {
                 "register_gateway",
                 async |module: &Lightning, context, gateway: LightningGateway| -> () {
@@ -906,9 +1022,26 @@ impl ServerModule for Lightning {
 }
 
 impl Lightning {
-    pub fn new(cfg: LightningConfig, task_group: &mut TaskGroup) -> anyhow::Result<Self> {
+    pub fn new(
+        cfg: LightningConfig,
+        module_instance_id: ModuleInstanceId,
+        task_group: &mut TaskGroup,
+    ) -> anyhow::Result<Self> {
         let btc_rpc = create_bitcoind(&cfg.local.bitcoin_rpc, task_group.make_handle())?;
-        Ok(Lightning { cfg, btc_rpc })
+        let signer = guardian_signer(
+            module_instance_id,
+            LocalSigner::default().with_threshold_key(
+                cfg.private
+                    .threshold_sec_key
+                    .as_ref()
+                    .map(|key| key.0.clone()),
+            ),
+        )?;
+        Ok(Lightning {
+            cfg,
+            btc_rpc,
+            signer,
+        })
     }
 
     pub async fn block_height(&self) -> u64 {
@@ -1284,7 +1417,7 @@ mod fedimint_migration_tests {
                             "validate_migrations was not able to read any ProposeDecryptionShares"
                         );
                         }
-                        DbKeyPrefix::BlockHeightVote => {}
+                        DbKeyPrefix::BlockHeightVote | DbKeyPrefix::OfferCancellationKey => {}
                     }
                 }
             },
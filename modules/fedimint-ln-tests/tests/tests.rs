@@ -7,7 +7,8 @@ use fedimint_dummy_client::{DummyClientExt, DummyClientGen};
 use fedimint_dummy_common::config::DummyGenParams;
 use fedimint_dummy_server::DummyGen;
 use fedimint_ln_client::{
-    InternalPayState, LightningClientExt, LightningClientGen, LnReceiveState, PayType,
+    InternalPayState, LightningClientExt, LightningClientGen, LightningMeta,
+    LightningOperationMetadata, LnReceiveState, PayType,
 };
 use fedimint_ln_common::config::LightningGenParams;
 use fedimint_ln_server::LightningGen;
@@ -57,6 +58,87 @@ async fn can_switch_active_gateway() -> anyhow::Result<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn stores_operation_metadata() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed().await;
+    let (client1, client2) = fed.two_clients().await;
+    let (op, outpoint) = client2.print_money(sats(1000)).await?;
+    client2.await_primary_module_output(op, outpoint).await?;
+
+    let receive_metadata = LightningOperationMetadata {
+        memo: Some("coffee".to_string()),
+        external_id: Some("order-1".to_string()),
+    };
+    let (receive_op, invoice) = client1
+        .create_bolt11_invoice_with_metadata(
+            sats(250),
+            "coffee".to_string(),
+            None,
+            receive_metadata.clone(),
+        )
+        .await?;
+    assert_eq!(
+        client1.ln_operation_metadata(receive_op).await?,
+        receive_metadata
+    );
+
+    let pay_metadata = LightningOperationMetadata {
+        memo: None,
+        external_id: Some("purchase-7".to_string()),
+    };
+    let (pay_type, _) = client2
+        .pay_bolt11_invoice_with_metadata(invoice, pay_metadata.clone())
+        .await?;
+    let PayType::Internal(pay_op) = pay_type else {
+        panic!("Expected internal payment!");
+    };
+    assert_eq!(client2.ln_operation_metadata(pay_op).await?, pay_metadata);
+
+    // Other operations are skipped when listing, newest first
+    let operations = client2.list_ln_operations(10, None).await;
+    assert_eq!(operations.len(), 1);
+    assert_eq!(operations[0].0.operation_id, pay_op);
+    assert_matches!(&operations[0].1, LightningMeta::Pay { metadata, .. } if *metadata == pay_metadata);
+
+    // Operations without metadata default to none
+    let (op, _) = client1
+        .create_bolt11_invoice(sats(100), "plain".to_string(), None)
+        .await?;
+    assert_eq!(
+        client1.ln_operation_metadata(op).await?,
+        LightningOperationMetadata::default()
+    );
+    let operations = client1.list_ln_operations(1, None).await;
+    assert_eq!(operations.len(), 1);
+    assert_eq!(operations[0].0.operation_id, op);
+    let operations = client1.list_ln_operations(10, Some(operations[0].0)).await;
+    assert_eq!(operations.len(), 1);
+    assert_eq!(operations[0].0.operation_id, receive_op);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cancels_offer_of_expired_invoice() -> anyhow::Result<()> {
+    let fixtures = fixtures();
+    let fed = fixtures.new_fed().await;
+    let (client1, client2) = fed.two_clients().await;
+    let (op, outpoint) = client2.print_money(sats(1000)).await?;
+    client2.await_primary_module_output(op, outpoint).await?;
+
+    let (op, invoice) = client1
+        .create_bolt11_invoice(sats(250), "expires".to_string(), Some(1))
+        .await?;
+    let mut sub1 = client1.subscribe_ln_receive(op).await?.into_stream();
+    assert_eq!(sub1.ok().await?, LnReceiveState::Created);
+    assert_matches!(sub1.ok().await?, LnReceiveState::WaitingForPayment { .. });
+    assert_eq!(sub1.ok().await?, LnReceiveState::Expired);
+
+    // Without the offer the invoice can't be paid anymore
+    assert!(client2.pay_bolt11_invoice(invoice).await.is_err());
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn makes_internal_payments_within_federation() -> anyhow::Result<()> {
     let fixtures = fixtures();
@@ -0,0 +1,28 @@
+use fedimint_core::api::{FederationApiExt, FederationResult, IModuleFederationApi};
+use fedimint_core::module::ApiRequestErased;
+use fedimint_core::task::{MaybeSend, MaybeSync};
+use fedimint_core::{apply, async_trait_maybe_send};
+use fedimint_mailbox_common::StoredMessage;
+use secp256k1::XOnlyPublicKey;
+
+#[apply(async_trait_maybe_send!)]
+pub trait MailboxFederationApi {
+    async fn mailbox_messages(
+        &self,
+        recipient: XOnlyPublicKey,
+    ) -> FederationResult<Vec<StoredMessage>>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<T: ?Sized> MailboxFederationApi for T
+where
+    T: IModuleFederationApi + MaybeSend + MaybeSync + 'static,
+{
+    async fn mailbox_messages(
+        &self,
+        recipient: XOnlyPublicKey,
+    ) -> FederationResult<Vec<StoredMessage>> {
+        self.request_current_consensus("messages".to_string(), ApiRequestErased::new(recipient))
+            .await
+    }
+}
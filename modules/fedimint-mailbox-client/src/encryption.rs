@@ -0,0 +1,84 @@
+use std::io::Cursor;
+
+use fedimint_aead::{decrypt_with_aad, encrypt_with_aad, get_key_from_secret};
+use fedimint_core::core::KeyPair;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_mailbox_common::{EncryptedMessage, MessageContent};
+use secp256k1::ecdh::SharedSecret;
+use secp256k1::{Parity, Secp256k1, XOnlyPublicKey};
+
+/// Encrypts `content` so only the owner of `recipient` can read it
+///
+/// A fresh ephemeral key is used for every message, so messages can't be
+/// linked to their sender.
+pub fn encrypt_message(
+    recipient: XOnlyPublicKey,
+    content: &MessageContent,
+) -> anyhow::Result<EncryptedMessage> {
+    let ephemeral_key = KeyPair::new(&Secp256k1::new(), &mut rand::thread_rng());
+    // We only know the x coordinate of the recipient's key, so we agree on the
+    // point with an even y coordinate
+    let shared_secret = SharedSecret::new(
+        &recipient.public_key(Parity::Even),
+        &ephemeral_key.secret_key(),
+    );
+    let ciphertext = encrypt_with_aad(
+        content.consensus_encode_to_vec()?,
+        &get_key_from_secret(shared_secret.secret_bytes()),
+        &recipient.serialize(),
+    )?;
+
+    Ok(EncryptedMessage {
+        ephemeral_key: ephemeral_key.public_key(),
+        ciphertext,
+    })
+}
+
+/// Decrypts a message sent to the x-only public key of `key`
+pub fn decrypt_message(
+    key: &KeyPair,
+    message: &EncryptedMessage,
+) -> anyhow::Result<MessageContent> {
+    let (recipient, parity) = key.x_only_public_key();
+    // The sender used the point with an even y coordinate, which belongs to
+    // our negated secret key if our public key is odd
+    let secret_key = match parity {
+        Parity::Even => key.secret_key(),
+        Parity::Odd => key.secret_key().negate(),
+    };
+    let shared_secret = SharedSecret::new(&message.ephemeral_key, &secret_key);
+
+    let mut ciphertext = message.ciphertext.clone();
+    let plaintext = decrypt_with_aad(
+        &mut ciphertext,
+        &get_key_from_secret(shared_secret.secret_bytes()),
+        &recipient.serialize(),
+    )?;
+
+    Ok(MessageContent::consensus_decode(
+        &mut Cursor::new(plaintext),
+        &ModuleDecoderRegistry::default(),
+    )?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_recipient_can_decrypt() {
+        let secp = Secp256k1::new();
+        let content = MessageContent::Text("hello".to_string());
+        let other = KeyPair::new(&secp, &mut rand::thread_rng());
+
+        // Random keys cover both parities
+        for _ in 0..16 {
+            let recipient = KeyPair::new(&secp, &mut rand::thread_rng());
+            let message = encrypt_message(recipient.x_only_public_key().0, &content).unwrap();
+
+            assert_eq!(decrypt_message(&recipient, &message).unwrap(), content);
+            assert!(decrypt_message(&other, &message).is_err());
+        }
+    }
+}
@@ -0,0 +1,422 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail};
+use async_stream::stream;
+use fedimint_client::derivable_secret::DerivableSecret;
+use fedimint_client::module::gen::ClientModuleGen;
+use fedimint_client::module::ClientModule;
+use fedimint_client::oplog::{OperationLogEntry, UpdateStreamOrOutcome};
+use fedimint_client::sm::{Context, ModuleNotifier, OperationId};
+use fedimint_client::transaction::{ClientInput, ClientOutput, TransactionBuilder};
+use fedimint_client::{Client, DynGlobalClientContext};
+use fedimint_core::api::{DynGlobalApi, DynModuleApi};
+use fedimint_core::core::{IntoDynInstance, KeyPair};
+use fedimint_core::db::Database;
+use fedimint_core::module::{
+    ApiVersion, ExtendsCommonModuleGen, ModuleCommon, MultiApiVersion, TransactionItemAmount,
+};
+use fedimint_core::task::sleep;
+use fedimint_core::util::NextOrPending;
+use fedimint_core::{apply, async_trait_maybe_send, OutPoint, TransactionId};
+pub use fedimint_mailbox_common as common;
+use fedimint_mailbox_common::config::MailboxClientConfig;
+use fedimint_mailbox_common::{
+    MailboxCommonGen, MailboxInput, MailboxModuleTypes, MailboxOutput, MessageContent, KIND,
+};
+use futures::{pin_mut, StreamExt};
+use secp256k1::{Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use states::MailboxStateMachine;
+use tracing::warn;
+
+use crate::api::MailboxFederationApi;
+pub use crate::encryption::{decrypt_message, encrypt_message};
+
+pub mod api;
+mod encryption;
+mod states;
+
+/// Exposed API calls for client apps
+#[apply(async_trait_maybe_send!)]
+pub trait MailboxClientExt {
+    /// The key others address messages for us to
+    async fn mailbox_public_key(&self) -> XOnlyPublicKey;
+
+    /// Leaves an encrypted message in the mailbox of `recipient` that is
+    /// deleted after `ttl`, paying the fee with ecash of the primary module
+    async fn send_mailbox_message(
+        &self,
+        recipient: XOnlyPublicKey,
+        content: MessageContent,
+        ttl: Duration,
+    ) -> anyhow::Result<OperationId>;
+
+    /// Fetches and decrypts the messages waiting in our mailbox, messages we
+    /// can't decrypt are skipped
+    async fn fetch_mailbox_messages(&self) -> anyhow::Result<Vec<ReceivedMessage>>;
+
+    /// Polls our mailbox every `poll_interval` until a message we didn't know
+    /// of arrives and returns the new messages
+    async fn await_mailbox_messages(
+        &self,
+        known: Vec<OutPoint>,
+        poll_interval: Duration,
+    ) -> anyhow::Result<Vec<ReceivedMessage>>;
+
+    /// Deletes messages we picked up from our mailbox before they expire
+    async fn delete_mailbox_messages(&self, messages: Vec<OutPoint>)
+        -> anyhow::Result<OperationId>;
+
+    /// Subscribe to the state of an operation started by one of the functions
+    /// above
+    async fn subscribe_mailbox_operation(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<UpdateStreamOrOutcome<'_, MailboxOperationState>>;
+}
+
+/// A decrypted message from our mailbox
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ReceivedMessage {
+    /// Used to delete the message
+    pub id: OutPoint,
+    pub content: MessageContent,
+    /// Unix timestamp in seconds after which the federation deletes the message
+    pub expires_at: u64,
+}
+
+/// The high-level state of a mailbox operation
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MailboxOperationState {
+    /// The transaction was submitted to the federation
+    Created,
+    /// The transaction was accepted and the ecash for change was issued
+    Done,
+    /// The transaction was rejected or the ecash couldn't be issued
+    Failed(String),
+}
+
+/// Stored in the operation log to show mailbox operations to the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MailboxMeta {
+    pub txid: TransactionId,
+    /// The output of the primary module receiving change
+    pub change: Option<OutPoint>,
+    pub variant: MailboxMetaVariant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MailboxMetaVariant {
+    Send { recipient: XOnlyPublicKey, ttl: u64 },
+    Delete { messages: Vec<OutPoint> },
+}
+
+#[apply(async_trait_maybe_send!)]
+impl MailboxClientExt for Client {
+    async fn mailbox_public_key(&self) -> XOnlyPublicKey {
+        let (mailbox, _instance) = self.get_first_module::<MailboxClientModule>(&KIND);
+        mailbox.public_key()
+    }
+
+    async fn send_mailbox_message(
+        &self,
+        recipient: XOnlyPublicKey,
+        content: MessageContent,
+        ttl: Duration,
+    ) -> anyhow::Result<OperationId> {
+        let (mailbox, instance) = self.get_first_module::<MailboxClientModule>(&KIND);
+        let ttl = ttl.as_secs();
+        if ttl == 0 || ttl > mailbox.cfg.max_ttl {
+            bail!(
+                "Messages can be kept for between 1 and {} seconds",
+                mailbox.cfg.max_ttl
+            );
+        }
+        let message = encrypt_message(recipient, &content)?;
+        if message.ciphertext.len() as u64 > mailbox.cfg.max_message_size {
+            bail!(
+                "Message is longer than {} bytes",
+                mailbox.cfg.max_message_size
+            );
+        }
+
+        let operation_id = OperationId(rand::random());
+        let output = ClientOutput {
+            output: MailboxOutput {
+                recipient,
+                message,
+                ttl,
+            },
+            state_machines: Arc::new(move |txid, _| {
+                vec![MailboxStateMachine::Submitted(txid, operation_id)]
+            }),
+        };
+        let tx = TransactionBuilder::new().with_output(output.into_dyn(instance.id));
+
+        self.submit_mailbox_transaction(
+            operation_id,
+            MailboxMetaVariant::Send { recipient, ttl },
+            tx,
+        )
+        .await
+    }
+
+    async fn fetch_mailbox_messages(&self) -> anyhow::Result<Vec<ReceivedMessage>> {
+        let (mailbox, instance) = self.get_first_module::<MailboxClientModule>(&KIND);
+        let messages = instance.api.mailbox_messages(mailbox.public_key()).await?;
+
+        Ok(messages
+            .into_iter()
+            .filter_map(
+                |message| match decrypt_message(&mailbox.key, &message.message) {
+                    Ok(content) => Some(ReceivedMessage {
+                        id: message.id,
+                        content,
+                        expires_at: message.expires_at,
+                    }),
+                    Err(e) => {
+                        warn!(id = %message.id, "Skipping message we can't decrypt: {e}");
+                        None
+                    }
+                },
+            )
+            .collect())
+    }
+
+    async fn await_mailbox_messages(
+        &self,
+        known: Vec<OutPoint>,
+        poll_interval: Duration,
+    ) -> anyhow::Result<Vec<ReceivedMessage>> {
+        loop {
+            let new_messages = self
+                .fetch_mailbox_messages()
+                .await?
+                .into_iter()
+                .filter(|message| !known.contains(&message.id))
+                .collect::<Vec<_>>();
+            if !new_messages.is_empty() {
+                return Ok(new_messages);
+            }
+            sleep(poll_interval).await;
+        }
+    }
+
+    async fn delete_mailbox_messages(
+        &self,
+        messages: Vec<OutPoint>,
+    ) -> anyhow::Result<OperationId> {
+        if messages.is_empty() {
+            bail!("No messages to delete");
+        }
+        let (mailbox, instance) = self.get_first_module::<MailboxClientModule>(&KIND);
+        let operation_id = OperationId(rand::random());
+
+        let input = mailbox.create_input(
+            operation_id,
+            MailboxInput {
+                recipient: mailbox.public_key(),
+                messages: messages.clone(),
+            },
+        );
+        let tx = TransactionBuilder::new().with_input(input.into_dyn(instance.id));
+
+        self.submit_mailbox_transaction(operation_id, MailboxMetaVariant::Delete { messages }, tx)
+            .await
+    }
+
+    async fn subscribe_mailbox_operation(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<UpdateStreamOrOutcome<'_, MailboxOperationState>> {
+        let (mailbox, _instance) = self.get_first_module::<MailboxClientModule>(&KIND);
+        let operation = mailbox_operation(self, operation_id).await?;
+        let meta = operation.meta::<MailboxMeta>();
+
+        let tx_accepted_future = mailbox.await_tx_accepted(operation_id);
+        let ecash_issued_future = meta
+            .change
+            .map(|out_point| self.await_primary_module_output(operation_id, out_point));
+
+        Ok(operation.outcome_or_updates(self.db(), operation_id, || {
+            stream! {
+                yield MailboxOperationState::Created;
+
+                match tx_accepted_future.await {
+                    Ok(()) => match ecash_issued_future {
+                        Some(ecash_issued_future) => match ecash_issued_future.await {
+                            Ok(_) => yield MailboxOperationState::Done,
+                            Err(e) => yield MailboxOperationState::Failed(e.to_string()),
+                        },
+                        None => yield MailboxOperationState::Done,
+                    },
+                    Err(e) => yield MailboxOperationState::Failed(e),
+                }
+            }
+        }))
+    }
+}
+
+#[apply(async_trait_maybe_send!)]
+trait MailboxClientExtPrivate {
+    async fn submit_mailbox_transaction(
+        &self,
+        operation_id: OperationId,
+        variant: MailboxMetaVariant,
+        tx: TransactionBuilder,
+    ) -> anyhow::Result<OperationId>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl MailboxClientExtPrivate for Client {
+    async fn submit_mailbox_transaction(
+        &self,
+        operation_id: OperationId,
+        variant: MailboxMetaVariant,
+        tx: TransactionBuilder,
+    ) -> anyhow::Result<OperationId> {
+        let operation_meta = move |txid, change| MailboxMeta {
+            txid,
+            change,
+            variant: variant.clone(),
+        };
+        self.finalize_and_submit_transaction(operation_id, KIND.as_str(), operation_meta, tx)
+            .await?;
+        Ok(operation_id)
+    }
+}
+
+async fn mailbox_operation(
+    client: &Client,
+    operation_id: OperationId,
+) -> anyhow::Result<OperationLogEntry> {
+    let operation = client
+        .operation_log()
+        .get_operation(operation_id)
+        .await
+        .ok_or(anyhow!("Operation not found"))?;
+
+    if operation.operation_type() != KIND.as_str() {
+        bail!("Operation is not a mailbox operation");
+    }
+
+    Ok(operation)
+}
+
+#[derive(Debug)]
+pub struct MailboxClientModule {
+    cfg: MailboxClientConfig,
+    key: KeyPair,
+    notifier: ModuleNotifier<DynGlobalClientContext, MailboxStateMachine>,
+}
+
+/// Data needed by the state machine
+#[derive(Debug, Clone)]
+pub struct MailboxClientContext;
+
+// TODO: Boiler-plate
+impl Context for MailboxClientContext {}
+
+#[apply(async_trait_maybe_send!)]
+impl ClientModule for MailboxClientModule {
+    type Common = MailboxModuleTypes;
+    type ModuleStateMachineContext = MailboxClientContext;
+    type States = MailboxStateMachine;
+
+    fn context(&self) -> Self::ModuleStateMachineContext {
+        MailboxClientContext
+    }
+
+    fn input_amount(
+        &self,
+        _input: &<Self::Common as ModuleCommon>::Input,
+    ) -> TransactionItemAmount {
+        TransactionItemAmount::ZERO
+    }
+
+    fn output_amount(
+        &self,
+        _output: &<Self::Common as ModuleCommon>::Output,
+    ) -> TransactionItemAmount {
+        TransactionItemAmount {
+            amount: self.cfg.message_fee,
+            fee: self.cfg.tx_fee,
+        }
+    }
+}
+
+impl MailboxClientModule {
+    fn public_key(&self) -> XOnlyPublicKey {
+        self.key.x_only_public_key().0
+    }
+
+    fn create_input(
+        &self,
+        operation_id: OperationId,
+        input: MailboxInput,
+    ) -> ClientInput<MailboxInput, MailboxStateMachine> {
+        ClientInput {
+            input,
+            keys: vec![self.key],
+            state_machines: Arc::new(move |txid, _| {
+                vec![MailboxStateMachine::Submitted(txid, operation_id)]
+            }),
+        }
+    }
+
+    async fn await_tx_accepted(&self, operation_id: OperationId) -> Result<(), String> {
+        let stream = self
+            .notifier
+            .subscribe(operation_id)
+            .await
+            .filter_map(|state| async move {
+                match state {
+                    MailboxStateMachine::Accepted(_) => Some(Ok(())),
+                    MailboxStateMachine::Rejected(_, e) => Some(Err(e)),
+                    MailboxStateMachine::Submitted(_, _) => None,
+                }
+            });
+
+        pin_mut!(stream);
+
+        stream.next_or_pending().await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MailboxClientGen;
+
+impl ExtendsCommonModuleGen for MailboxClientGen {
+    type Common = MailboxCommonGen;
+}
+
+/// Generates the client module
+#[apply(async_trait_maybe_send!)]
+impl ClientModuleGen for MailboxClientGen {
+    type Module = MailboxClientModule;
+    type Config = MailboxClientConfig;
+
+    fn supported_api_versions(&self) -> MultiApiVersion {
+        MultiApiVersion::try_from_iter([ApiVersion { major: 0, minor: 0 }])
+            .expect("no version conficts")
+    }
+
+    async fn init(
+        &self,
+        cfg: Self::Config,
+        _db: Database,
+        _api_version: ApiVersion,
+        module_root_secret: DerivableSecret,
+        notifier: ModuleNotifier<DynGlobalClientContext, <Self::Module as ClientModule>::States>,
+        _api: DynGlobalApi,
+        _module_api: DynModuleApi,
+    ) -> anyhow::Result<Self::Module> {
+        Ok(MailboxClientModule {
+            cfg,
+            key: module_root_secret.to_secp_key(&Secp256k1::new()),
+            notifier,
+        })
+    }
+}
@@ -0,0 +1,69 @@
+use fedimint_client::sm::{DynState, OperationId, State, StateTransition};
+use fedimint_client::DynGlobalClientContext;
+use fedimint_core::core::{IntoDynInstance, ModuleInstanceId};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::TransactionId;
+
+use crate::MailboxClientContext;
+
+/// Tracks a transaction that sends or deletes messages
+///
+/// Fees are paid with ecash of the primary module, which takes care of its own
+/// inputs if the transaction is rejected, so there is nothing to refund here.
+#[derive(Debug, Clone, Eq, PartialEq, Decodable, Encodable)]
+pub enum MailboxStateMachine {
+    Submitted(TransactionId, OperationId),
+    Accepted(OperationId),
+    Rejected(OperationId, String),
+}
+
+impl State for MailboxStateMachine {
+    type ModuleContext = MailboxClientContext;
+    type GlobalContext = DynGlobalClientContext;
+
+    fn transitions(
+        &self,
+        _context: &Self::ModuleContext,
+        global_context: &Self::GlobalContext,
+    ) -> Vec<StateTransition<Self>> {
+        match self.clone() {
+            MailboxStateMachine::Submitted(txid, id) => vec![StateTransition::new(
+                await_tx_accepted(global_context.clone(), id, txid),
+                move |_dbtx, res, _state: Self| match res {
+                    Ok(()) => Box::pin(async move { MailboxStateMachine::Accepted(id) }),
+                    Err(e) => Box::pin(async move { MailboxStateMachine::Rejected(id, e) }),
+                },
+            )],
+            MailboxStateMachine::Accepted(_) => vec![],
+            MailboxStateMachine::Rejected(_, _) => vec![],
+        }
+    }
+
+    fn operation_id(&self) -> OperationId {
+        match self {
+            MailboxStateMachine::Submitted(_, id) => *id,
+            MailboxStateMachine::Accepted(id) => *id,
+            MailboxStateMachine::Rejected(id, _) => *id,
+        }
+    }
+}
+
+async fn await_tx_accepted(
+    context: DynGlobalClientContext,
+    id: OperationId,
+    txid: TransactionId,
+) -> Result<(), String> {
+    context
+        .await_tx_accepted(id, txid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// TODO: Boiler-plate
+impl IntoDynInstance for MailboxStateMachine {
+    type DynType = DynState<DynGlobalClientContext>;
+
+    fn into_dyn(self, instance_id: ModuleInstanceId) -> Self::DynType {
+        DynState::from_typed(instance_id, self)
+    }
+}
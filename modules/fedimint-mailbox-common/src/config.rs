@@ -0,0 +1,101 @@
+use fedimint_core::core::ModuleKind;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{plugin_types_trait_impl_config, Amount, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::MailboxCommonGen;
+
+/// Parameters necessary to generate this module's configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MailboxGenParams {
+    pub local: MailboxGenParamsLocal,
+    pub consensus: MailboxGenParamsConsensus,
+}
+
+/// Local parameters for config generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MailboxGenParamsLocal {}
+
+/// Consensus parameters for config generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MailboxGenParamsConsensus {
+    /// Fee for leaving a message, keeps senders from flooding mailboxes
+    pub message_fee: Amount,
+    /// Longest encrypted message in bytes
+    pub max_message_size: u64,
+    /// Longest time in seconds a message is kept
+    pub max_ttl: u64,
+    /// Most messages that can wait in a mailbox
+    pub max_messages: u64,
+    pub tx_fee: Amount,
+}
+
+impl Default for MailboxGenParams {
+    fn default() -> Self {
+        Self {
+            local: MailboxGenParamsLocal {},
+            consensus: MailboxGenParamsConsensus {
+                message_fee: Amount::from_sats(10),
+                max_message_size: 16 * 1024,
+                max_ttl: 30 * 24 * 60 * 60,
+                max_messages: 100,
+                tx_fee: Amount::ZERO,
+            },
+        }
+    }
+}
+
+/// Contains all the configuration for the server
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MailboxConfig {
+    pub local: MailboxConfigLocal,
+    pub private: MailboxConfigPrivate,
+    pub consensus: MailboxConfigConsensus,
+}
+
+/// Contains all the configuration for the client
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct MailboxClientConfig {
+    pub message_fee: Amount,
+    pub max_message_size: u64,
+    pub max_ttl: u64,
+    pub tx_fee: Amount,
+}
+
+/// Locally unencrypted config unique to each member
+#[derive(Clone, Debug, Serialize, Deserialize, Decodable, Encodable)]
+pub struct MailboxConfigLocal {
+    /// Our id, to know when we last voted for a time
+    pub peer_id: PeerId,
+}
+
+/// Will be the same for every federation member
+#[derive(Clone, Debug, Serialize, Deserialize, Decodable, Encodable)]
+pub struct MailboxConfigConsensus {
+    pub message_fee: Amount,
+    pub max_message_size: u64,
+    pub max_ttl: u64,
+    pub max_messages: u64,
+    pub tx_fee: Amount,
+    /// Number of guardians whose clocks have to reach a time before the
+    /// federation considers it reached
+    pub threshold: u64,
+}
+
+/// Will be encrypted and not shared such as private key material, mailboxes
+/// don't need any
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MailboxConfigPrivate {}
+
+// Wire together the configs for this module
+plugin_types_trait_impl_config!(
+    MailboxCommonGen,
+    MailboxGenParams,
+    MailboxGenParamsLocal,
+    MailboxGenParamsConsensus,
+    MailboxConfig,
+    MailboxConfigLocal,
+    MailboxConfigPrivate,
+    MailboxConfigConsensus,
+    MailboxClientConfig
+);
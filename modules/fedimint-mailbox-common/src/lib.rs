@@ -0,0 +1,191 @@
+use std::fmt;
+
+use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::error::{code, ModuleErrorCode};
+use fedimint_core::module::{CommonModuleGen, ModuleCommon, ModuleConsensusVersion};
+use fedimint_core::{plugin_types_trait_impl_common, OutPoint};
+use secp256k1::{PublicKey, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Common contains types shared by both the client and server
+
+// The client and server configuration
+pub mod config;
+
+/// Unique name for this module
+pub const KIND: ModuleKind = ModuleKind::from_static_str("mailbox");
+
+/// Modules are non-compatible with older versions
+pub const CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion(0);
+
+/// What a message contains once decrypted by its recipient
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum MessageContent {
+    /// Out-of-band ecash notes the recipient can reissue
+    Ecash(String),
+    /// An invoice or other request to be paid by the recipient
+    PaymentRequest(String),
+    Text(String),
+}
+
+/// A message only the recipient can decrypt
+///
+/// The content is encrypted with a key derived from a Diffie-Hellman exchange
+/// between the ephemeral key and the recipient's key, so the federation learns
+/// neither the content nor the sender.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct EncryptedMessage {
+    pub ephemeral_key: PublicKey,
+    pub ciphertext: Vec<u8>,
+}
+
+/// A message waiting in a mailbox to be picked up
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct StoredMessage {
+    /// Identifies the message when deleting it
+    pub id: OutPoint,
+    pub message: EncryptedMessage,
+    /// Unix timestamp in seconds after which the message is deleted
+    pub expires_at: u64,
+}
+
+impl StoredMessage {
+    pub fn is_expired(&self, time: u64) -> bool {
+        self.expires_at <= time
+    }
+}
+
+/// Input for a fedimint transaction, deletes messages from a mailbox and has to
+/// be signed by its recipient
+///
+/// Doesn't contribute any funds to the transaction.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct MailboxInput {
+    pub recipient: XOnlyPublicKey,
+    pub messages: Vec<OutPoint>,
+}
+
+/// Output for a fedimint transaction, pays the fee for leaving a message in the
+/// mailbox of `recipient` for `ttl` seconds
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct MailboxOutput {
+    pub recipient: XOnlyPublicKey,
+    pub message: EncryptedMessage,
+    pub ttl: u64,
+}
+
+/// Information needed by a client to follow its message
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct MailboxOutputOutcome {
+    pub expires_at: u64,
+}
+
+/// Non-transaction items that will be submitted to consensus, the unix time
+/// in seconds observed by a guardian
+///
+/// Messages expire at the time a threshold of guardians reached, so no single
+/// guardian can delete messages early.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct MailboxConsensusItem {
+    pub time: u64,
+}
+
+/// Errors that might be returned by the server
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Error)]
+pub enum MailboxError {
+    #[error("Messages must not be empty or longer than {0} bytes")]
+    InvalidMessageSize(u64),
+    #[error("Messages can be kept for between 1 and {0} seconds")]
+    InvalidTtl(u64),
+    #[error("Mailbox is full, at most {0} messages can be waiting")]
+    MailboxFull(u64),
+    #[error("No messages to delete")]
+    NothingToDelete,
+    #[error("Unknown or expired message {0}")]
+    UnknownMessage(OutPoint),
+    #[error("The federation didn't agree on the time yet")]
+    TimeUnknown,
+}
+
+impl ModuleErrorCode for MailboxError {
+    fn code(&self) -> i32 {
+        match self {
+            MailboxError::InvalidMessageSize(_)
+            | MailboxError::InvalidTtl(_)
+            | MailboxError::NothingToDelete => code::BAD_REQUEST,
+            MailboxError::MailboxFull(_) => code::LIMIT_EXCEEDED,
+            MailboxError::UnknownMessage(_) => code::NOT_FOUND,
+            MailboxError::TimeUnknown => code::UNAVAILABLE,
+        }
+    }
+
+    fn retriable(&self) -> bool {
+        match self {
+            // Waiting messages expire eventually
+            MailboxError::MailboxFull(_) => true,
+            _ => code::retriable_by_default(self.code()),
+        }
+    }
+}
+
+/// Contains the types defined above
+pub struct MailboxModuleTypes;
+
+// Wire together the types for this module
+plugin_types_trait_impl_common!(
+    MailboxModuleTypes,
+    MailboxInput,
+    MailboxOutput,
+    MailboxOutputOutcome,
+    MailboxConsensusItem
+);
+
+#[derive(Debug)]
+pub struct MailboxCommonGen;
+
+impl CommonModuleGen for MailboxCommonGen {
+    const CONSENSUS_VERSION: ModuleConsensusVersion = CONSENSUS_VERSION;
+    const KIND: ModuleKind = KIND;
+
+    fn decoder() -> Decoder {
+        MailboxModuleTypes::decoder_builder().build()
+    }
+}
+
+impl fmt::Display for MailboxInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MailboxInput Delete {} messages of {}",
+            self.messages.len(),
+            self.recipient
+        )
+    }
+}
+
+impl fmt::Display for MailboxOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "MailboxOutput {} bytes to {} for {}s",
+            self.message.ciphertext.len(),
+            self.recipient,
+            self.ttl
+        )
+    }
+}
+
+impl fmt::Display for MailboxOutputOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MailboxOutputOutcome expires at {}", self.expires_at)
+    }
+}
+
+impl fmt::Display for MailboxConsensusItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MailboxConsensusItem time {}", self.time)
+    }
+}
@@ -0,0 +1,73 @@
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{impl_db_lookup, impl_db_record, OutPoint, PeerId};
+use fedimint_mailbox_common::{MailboxOutputOutcome, StoredMessage};
+use secp256k1::XOnlyPublicKey;
+use serde::Serialize;
+use strum_macros::EnumIter;
+
+/// Namespaces DB keys for this module
+#[repr(u8)]
+#[derive(Clone, EnumIter, Debug)]
+pub enum DbKeyPrefix {
+    Message = 0x01,
+    Outcome = 0x02,
+    TimeVote = 0x03,
+}
+
+impl std::fmt::Display for DbKeyPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Lookup a message, the messages of a mailbox or all messages
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct MessageKey {
+    pub recipient: XOnlyPublicKey,
+    pub id: OutPoint,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MailboxPrefix(pub XOnlyPublicKey);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MessagePrefix;
+
+impl_db_record!(
+    key = MessageKey,
+    value = StoredMessage,
+    db_prefix = DbKeyPrefix::Message,
+);
+impl_db_lookup!(
+    key = MessageKey,
+    query_prefix = MailboxPrefix,
+    query_prefix = MessagePrefix
+);
+
+/// Lookup tx outputs by key or prefix
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct MailboxOutcomeKey(pub OutPoint);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MailboxOutcomePrefix;
+
+impl_db_record!(
+    key = MailboxOutcomeKey,
+    value = MailboxOutputOutcome,
+    db_prefix = DbKeyPrefix::Outcome,
+);
+impl_db_lookup!(key = MailboxOutcomeKey, query_prefix = MailboxOutcomePrefix);
+
+/// The latest unix time in seconds a guardian voted for
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct TimeVoteKey(pub PeerId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct TimeVotePrefix;
+
+impl_db_record!(
+    key = TimeVoteKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::TimeVote,
+);
+impl_db_lookup!(key = TimeVoteKey, query_prefix = TimeVotePrefix);
@@ -0,0 +1,513 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::string::ToString;
+use std::time::Duration;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use fedimint_core::config::{
+    ClientModuleConfig, ConfigGenModuleParams, DkgResult, ServerModuleConfig,
+    ServerModuleConsensusConfig, TypedServerModuleConfig, TypedServerModuleConsensusConfig,
+};
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::db::{Database, DatabaseVersion, MigrationMap, ModuleDatabaseTransaction};
+use fedimint_core::module::audit::Audit;
+use fedimint_core::module::clock::ThresholdClock;
+use fedimint_core::module::interconnect::DynModuleInterconnect;
+use fedimint_core::module::{
+    api_endpoint, ApiEndpoint, ConsensusProposal, CoreConsensusVersion, ExtendsCommonModuleGen,
+    InputMeta, IntoCodedModuleError, ModuleConsensusVersion, ModuleError, PeerHandle,
+    ServerModuleGen, SupportedModuleApiVersions, TransactionItemAmount,
+};
+use fedimint_core::server::DynServerModule;
+use fedimint_core::task::{sleep, TaskGroup};
+use fedimint_core::{push_db_pair_items, NumPeers, OutPoint, PeerId, ServerModule};
+use fedimint_mailbox_common::config::{
+    MailboxClientConfig, MailboxConfig, MailboxConfigConsensus, MailboxConfigLocal,
+    MailboxConfigPrivate, MailboxGenParams,
+};
+use fedimint_mailbox_common::{
+    MailboxCommonGen, MailboxConsensusItem, MailboxError, MailboxInput, MailboxModuleTypes,
+    MailboxOutput, MailboxOutputOutcome, StoredMessage, CONSENSUS_VERSION,
+};
+use futures::StreamExt;
+use secp256k1::XOnlyPublicKey;
+use strum::IntoEnumIterator;
+use tracing::{debug, info};
+
+use crate::db::{
+    DbKeyPrefix, MailboxOutcomeKey, MailboxOutcomePrefix, MailboxPrefix, MessageKey, MessagePrefix,
+    TimeVoteKey, TimeVotePrefix,
+};
+
+mod db;
+
+/// How often guardians vote for their time, which is how precise expiry is
+/// and how often expired messages are deleted
+const TIME_VOTE_INTERVAL: u64 = 600;
+
+/// How often to check whether it is time to vote again
+const TIME_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Generates the module
+#[derive(Debug, Clone)]
+pub struct MailboxGen;
+
+impl ExtendsCommonModuleGen for MailboxGen {
+    type Common = MailboxCommonGen;
+}
+
+/// Implementation of server module non-consensus functions
+#[async_trait]
+impl ServerModuleGen for MailboxGen {
+    type Params = MailboxGenParams;
+    const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
+    /// Returns the version of this module
+    fn versions(&self, _core: CoreConsensusVersion) -> &[ModuleConsensusVersion] {
+        &[CONSENSUS_VERSION]
+    }
+
+    fn supported_api_versions(&self) -> SupportedModuleApiVersions {
+        SupportedModuleApiVersions::from_raw(0, 0, &[(0, 0)])
+    }
+
+    /// Initialize the module
+    async fn init(
+        &self,
+        cfg: ServerModuleConfig,
+        _module_instance_id: ModuleInstanceId,
+        _our_id: PeerId,
+        _db: Database,
+        _task_group: &mut TaskGroup,
+        _interconnect: DynModuleInterconnect,
+    ) -> anyhow::Result<DynServerModule> {
+        Ok(Mailbox::new(cfg.to_typed()?).into())
+    }
+
+    /// DB migrations to move from old to newer versions
+    fn get_database_migrations(&self) -> MigrationMap {
+        MigrationMap::new()
+    }
+
+    /// Generates configs for all peers in a trusted manner for testing
+    fn trusted_dealer_gen(
+        &self,
+        peers: &[PeerId],
+        params: &ConfigGenModuleParams,
+    ) -> BTreeMap<PeerId, ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        peers
+            .iter()
+            .map(|&peer| {
+                let config = mailbox_config(&params, peer, peers.threshold());
+                (peer, config.to_erased())
+            })
+            .collect()
+    }
+
+    /// Generates configs for all peers in an untrusted manner
+    async fn distributed_gen(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+    ) -> DkgResult<ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        // Mailboxes are owned by the keys of their users, so there is nothing
+        // to generate together
+        Ok(mailbox_config(&params, peers.our_id, peers.peer_ids().threshold()).to_erased())
+    }
+
+    /// Converts the consensus config into the client config
+    fn get_client_config(
+        &self,
+        config: &ServerModuleConsensusConfig,
+    ) -> anyhow::Result<ClientModuleConfig> {
+        let config = MailboxConfigConsensus::from_erased(config)?;
+        Ok(ClientModuleConfig::from_typed(
+            config.kind(),
+            config.version(),
+            &(MailboxClientConfig {
+                message_fee: config.message_fee,
+                max_message_size: config.max_message_size,
+                max_ttl: config.max_ttl,
+                tx_fee: config.tx_fee,
+            }),
+        )
+        .expect("Serialization can't fail"))
+    }
+
+    fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
+        let config = config.to_typed::<MailboxConfig>()?;
+        if config.local.peer_id != *identity {
+            bail!("Peer id doesn't match our identity");
+        }
+        if config.consensus.max_message_size == 0 {
+            bail!("Max message size must not be zero");
+        }
+        if config.consensus.max_ttl == 0 {
+            bail!("Max TTL must not be zero");
+        }
+        if config.consensus.max_messages == 0 {
+            bail!("Max messages must not be zero");
+        }
+        if config.consensus.threshold == 0 {
+            bail!("Threshold must not be zero");
+        }
+        Ok(())
+    }
+
+    /// Dumps all database items for debugging
+    async fn dump_database(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        prefix_names: Vec<String>,
+    ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_> {
+        let mut items: BTreeMap<String, Box<dyn erased_serde::Serialize + Send>> = BTreeMap::new();
+        let filtered_prefixes = DbKeyPrefix::iter().filter(|f| {
+            prefix_names.is_empty() || prefix_names.contains(&f.to_string().to_lowercase())
+        });
+
+        for table in filtered_prefixes {
+            match table {
+                DbKeyPrefix::Message => {
+                    push_db_pair_items!(
+                        dbtx,
+                        MessagePrefix,
+                        MessageKey,
+                        StoredMessage,
+                        items,
+                        "Messages"
+                    );
+                }
+                DbKeyPrefix::Outcome => {
+                    push_db_pair_items!(
+                        dbtx,
+                        MailboxOutcomePrefix,
+                        MailboxOutcomeKey,
+                        MailboxOutputOutcome,
+                        items,
+                        "Mailbox Outputs"
+                    );
+                }
+                DbKeyPrefix::TimeVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        TimeVotePrefix,
+                        TimeVoteKey,
+                        u64,
+                        items,
+                        "Time Votes"
+                    );
+                }
+            }
+        }
+
+        Box::new(items.into_iter())
+    }
+}
+
+fn mailbox_config(params: &MailboxGenParams, peer_id: PeerId, threshold: usize) -> MailboxConfig {
+    MailboxConfig {
+        local: MailboxConfigLocal { peer_id },
+        private: MailboxConfigPrivate {},
+        consensus: MailboxConfigConsensus {
+            message_fee: params.consensus.message_fee,
+            max_message_size: params.consensus.max_message_size,
+            max_ttl: params.consensus.max_ttl,
+            max_messages: params.consensus.max_messages,
+            tx_fee: params.consensus.tx_fee,
+            threshold: threshold as u64,
+        },
+    }
+}
+
+/// Mailbox module
+#[derive(Debug)]
+pub struct Mailbox {
+    pub cfg: MailboxConfig,
+}
+
+/// Implementation of consensus for the server module
+#[async_trait]
+impl ServerModule for Mailbox {
+    /// Define the consensus types
+    type Common = MailboxModuleTypes;
+    type Gen = MailboxGen;
+    type VerificationCache = MailboxVerificationCache;
+
+    async fn await_consensus_proposal(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) {
+        while !self.consensus_proposal(dbtx).await.forces_new_epoch() {
+            sleep(TIME_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn consensus_proposal(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> ConsensusProposal<MailboxConsensusItem> {
+        match self.clock().proposal(dbtx, TIME_VOTE_INTERVAL).await {
+            Some(time) => ConsensusProposal::Trigger(vec![MailboxConsensusItem { time }]),
+            None => ConsensusProposal::empty(),
+        }
+    }
+
+    async fn begin_consensus_epoch<'a, 'b>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        consensus_items: Vec<(PeerId, MailboxConsensusItem)>,
+        _consensus_peers: &BTreeSet<PeerId>,
+    ) -> Vec<PeerId> {
+        let previous_time = self.clock().consensus_time(dbtx).await;
+        self.clock()
+            .process_votes(
+                dbtx,
+                consensus_items
+                    .into_iter()
+                    .map(|(peer_id, item)| (peer_id, item.time)),
+            )
+            .await;
+
+        let consensus_time = self.clock().consensus_time(dbtx).await;
+        if consensus_time > previous_time {
+            self.delete_expired_messages(dbtx, consensus_time).await;
+        }
+
+        vec![]
+    }
+
+    fn build_verification_cache<'a>(
+        &'a self,
+        _inputs: impl Iterator<Item = &'a MailboxInput> + Send,
+    ) -> Self::VerificationCache {
+        MailboxVerificationCache
+    }
+
+    async fn validate_input<'a, 'b>(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        _verification_cache: &Self::VerificationCache,
+        input: &'a MailboxInput,
+    ) -> Result<InputMeta, ModuleError> {
+        if input.messages.is_empty() {
+            return Err(MailboxError::NothingToDelete).into_module_error();
+        }
+        for id in &input.messages {
+            let key = MessageKey {
+                recipient: input.recipient,
+                id: *id,
+            };
+            if dbtx.get_value(&key).await.is_none() {
+                return Err(MailboxError::UnknownMessage(*id)).into_module_error();
+            }
+        }
+
+        Ok(InputMeta {
+            amount: TransactionItemAmount::ZERO,
+            // IMPORTANT: include the pubkey to validate the user signed this tx
+            pub_keys: vec![input.recipient],
+        })
+    }
+
+    async fn apply_input<'a, 'b, 'c>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'c>,
+        input: &'b MailboxInput,
+        cache: &Self::VerificationCache,
+    ) -> Result<InputMeta, ModuleError> {
+        let meta = self.validate_input(dbtx, cache, input).await?;
+
+        for id in &input.messages {
+            dbtx.remove_entry(&MessageKey {
+                recipient: input.recipient,
+                id: *id,
+            })
+            .await;
+        }
+
+        Ok(meta)
+    }
+
+    async fn validate_output(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        output: &MailboxOutput,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        self.expiry_after(dbtx, output).await.into_module_error()?;
+
+        Ok(TransactionItemAmount {
+            amount: self.cfg.consensus.message_fee,
+            fee: self.cfg.consensus.tx_fee,
+        })
+    }
+
+    async fn apply_output<'a, 'b>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        output: &'a MailboxOutput,
+        out_point: OutPoint,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        let amount = self.validate_output(dbtx, output).await?;
+
+        let expires_at = self
+            .expiry_after(dbtx, output)
+            .await
+            .expect("Checked by validation");
+        dbtx.insert_new_entry(
+            &MessageKey {
+                recipient: output.recipient,
+                id: out_point,
+            },
+            &StoredMessage {
+                id: out_point,
+                message: output.message.clone(),
+                expires_at,
+            },
+        )
+        .await;
+        dbtx.insert_new_entry(
+            &MailboxOutcomeKey(out_point),
+            &MailboxOutputOutcome { expires_at },
+        )
+        .await;
+
+        info!(recipient = %output.recipient, expires_at, "Message received");
+        Ok(amount)
+    }
+
+    async fn end_consensus_epoch<'a, 'b>(
+        &'a self,
+        _consensus_peers: &BTreeSet<PeerId>,
+        _dbtx: &mut ModuleDatabaseTransaction<'b>,
+    ) -> Vec<PeerId> {
+        vec![]
+    }
+
+    async fn output_status(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        out_point: OutPoint,
+    ) -> Option<MailboxOutputOutcome> {
+        dbtx.get_value(&MailboxOutcomeKey(out_point)).await
+    }
+
+    async fn audit(&self, _dbtx: &mut ModuleDatabaseTransaction<'_>, _audit: &mut Audit) {
+        // Message fees are kept by the federation, no funds are owed to anyone
+    }
+
+    fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
+        vec![
+            api_endpoint! {
+                // Returns the messages waiting for the recipient that didn't
+                // expire
+                "messages",
+                async |module: &Mailbox, context, recipient: XOnlyPublicKey| -> Vec<StoredMessage> {
+                    Ok(module.messages(&mut context.dbtx(), recipient).await)
+                }
+            },
+            api_endpoint! {
+                // The time the expiry of messages is checked against
+                "consensus_time",
+                async |module: &Mailbox, context, _params: ()| -> u64 {
+                    Ok(module.clock().consensus_time(&mut context.dbtx()).await)
+                }
+            },
+        ]
+    }
+}
+
+/// An in-memory cache we could use for faster validation
+#[derive(Debug, Clone)]
+pub struct MailboxVerificationCache;
+
+impl fedimint_core::server::VerificationCache for MailboxVerificationCache {}
+
+impl Mailbox {
+    /// Create new module instance
+    pub fn new(cfg: MailboxConfig) -> Mailbox {
+        Mailbox { cfg }
+    }
+
+    /// The messages waiting for `recipient` that didn't expire
+    async fn messages(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        recipient: XOnlyPublicKey,
+    ) -> Vec<StoredMessage> {
+        let consensus_time = self.clock().consensus_time(dbtx).await;
+        dbtx.find_by_prefix(&MailboxPrefix(recipient))
+            .await
+            .map(|(_, message)| message)
+            .filter(|message| std::future::ready(!message.is_expired(consensus_time)))
+            .collect()
+            .await
+    }
+
+    /// When the message expires, enforcing the anti-spam policy so mailboxes
+    /// can't be flooded and the federation doesn't store large or long-lived
+    /// messages
+    async fn expiry_after(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        output: &MailboxOutput,
+    ) -> Result<u64, MailboxError> {
+        let max_message_size = self.cfg.consensus.max_message_size;
+        let size = output.message.ciphertext.len() as u64;
+        if size == 0 || size > max_message_size {
+            return Err(MailboxError::InvalidMessageSize(max_message_size));
+        }
+        let max_ttl = self.cfg.consensus.max_ttl;
+        if output.ttl == 0 || output.ttl > max_ttl {
+            return Err(MailboxError::InvalidTtl(max_ttl));
+        }
+
+        // Counts expired messages too, they are deleted once the consensus
+        // time advances
+        let max_messages = self.cfg.consensus.max_messages;
+        let waiting = dbtx
+            .find_by_prefix(&MailboxPrefix(output.recipient))
+            .await
+            .collect::<Vec<_>>()
+            .await
+            .len() as u64;
+        if waiting >= max_messages {
+            return Err(MailboxError::MailboxFull(max_messages));
+        }
+
+        let consensus_time = self.clock().consensus_time(dbtx).await;
+        if consensus_time == 0 {
+            return Err(MailboxError::TimeUnknown);
+        }
+        Ok(consensus_time + output.ttl)
+    }
+
+    /// Garbage collects the messages whose TTL ran out
+    async fn delete_expired_messages(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        consensus_time: u64,
+    ) {
+        let expired = dbtx
+            .find_by_prefix(&MessagePrefix)
+            .await
+            .filter_map(|(key, message)| {
+                std::future::ready(message.is_expired(consensus_time).then_some(key))
+            })
+            .collect::<Vec<_>>()
+            .await;
+
+        debug!(count = expired.len(), "Deleting expired messages");
+        for key in expired {
+            dbtx.remove_entry(&key).await;
+        }
+    }
+
+    /// The clock a threshold of guardians agrees on
+    fn clock(&self) -> ThresholdClock<TimeVoteKey, TimeVotePrefix> {
+        ThresholdClock::new(
+            TimeVoteKey,
+            TimeVotePrefix,
+            self.cfg.local.peer_id,
+            self.cfg.consensus.threshold as usize,
+        )
+    }
+}
@@ -0,0 +1,89 @@
+use std::time::Duration;
+
+use fedimint_core::sats;
+use fedimint_core::util::NextOrPending;
+use fedimint_dummy_client::{DummyClientExt, DummyClientGen};
+use fedimint_dummy_common::config::DummyGenParams;
+use fedimint_dummy_server::DummyGen;
+use fedimint_mailbox_client::{MailboxClientExt, MailboxClientGen, MailboxOperationState};
+use fedimint_mailbox_common::config::MailboxGenParams;
+use fedimint_mailbox_common::MessageContent;
+use fedimint_mailbox_server::MailboxGen;
+use fedimint_testing::fixtures::Fixtures;
+
+const TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn fixtures(max_messages: u64) -> Fixtures {
+    let fixtures = Fixtures::new_primary(DummyClientGen, DummyGen, DummyGenParams::default());
+    let mut params = MailboxGenParams::default();
+    params.consensus.message_fee = sats(10);
+    params.consensus.max_messages = max_messages;
+    fixtures.with_module(MailboxClientGen, MailboxGen, params)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn recipient_picks_up_and_deletes_messages() -> anyhow::Result<()> {
+    let fed = fixtures(100).new_fed().await;
+    let (alice, bob) = fed.two_clients().await;
+    let (_, outpoint) = alice.print_money(sats(1000)).await?;
+    alice.receive_money(outpoint).await?;
+
+    let content = MessageContent::Ecash("notes".to_string());
+    let op = alice
+        .send_mailbox_message(bob.mailbox_public_key().await, content.clone(), TTL)
+        .await?;
+    let mut sub = alice.subscribe_mailbox_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, MailboxOperationState::Created);
+    assert_eq!(sub.ok().await?, MailboxOperationState::Done);
+    assert_eq!(alice.get_balance().await, sats(990));
+
+    // Only the recipient can read the message
+    assert!(alice.fetch_mailbox_messages().await?.is_empty());
+    let messages = bob
+        .await_mailbox_messages(vec![], Duration::from_millis(100))
+        .await?;
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].content, content);
+
+    let op = bob.delete_mailbox_messages(vec![messages[0].id]).await?;
+    let mut sub = bob.subscribe_mailbox_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, MailboxOperationState::Created);
+    assert_eq!(sub.ok().await?, MailboxOperationState::Done);
+    assert!(bob.fetch_mailbox_messages().await?.is_empty());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn full_mailboxes_reject_messages() -> anyhow::Result<()> {
+    let fed = fixtures(1).new_fed().await;
+    let (alice, bob) = fed.two_clients().await;
+    let (_, outpoint) = alice.print_money(sats(1000)).await?;
+    alice.receive_money(outpoint).await?;
+    let recipient = bob.mailbox_public_key().await;
+
+    let content = MessageContent::PaymentRequest("lnbc1".to_string());
+    let op = alice
+        .send_mailbox_message(recipient, content.clone(), TTL)
+        .await?;
+    let mut sub = alice.subscribe_mailbox_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, MailboxOperationState::Created);
+    assert_eq!(sub.ok().await?, MailboxOperationState::Done);
+
+    let op = alice.send_mailbox_message(recipient, content, TTL).await?;
+    let mut sub = alice.subscribe_mailbox_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, MailboxOperationState::Created);
+    assert!(matches!(sub.ok().await?, MailboxOperationState::Failed(_)));
+    assert_eq!(alice.get_balance().await, sats(990));
+
+    // Messages can't be kept longer than the federation allows
+    let max_ttl = Duration::from_secs(MailboxGenParams::default().consensus.max_ttl);
+    let too_long = alice
+        .send_mailbox_message(
+            recipient,
+            MessageContent::Text("hi".to_string()),
+            max_ttl * 2,
+        )
+        .await;
+    assert!(too_long.is_err());
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use fedimint_core::api::{FederationApiExt, FederationResult, IModuleFederationApi};
+use fedimint_core::module::ApiRequestErased;
+use fedimint_core::query::EventuallyConsistent;
+use fedimint_core::task::{MaybeSend, MaybeSync};
+use fedimint_core::{apply, async_trait_maybe_send, NumPeers};
+use fedimint_mint_common::rebate::FeeRebateStatus;
+use secp256k1::XOnlyPublicKey;
+
+#[apply(async_trait_maybe_send!)]
+pub trait MintFederationApi {
+    async fn fetch_fee_rebate(&self, account: XOnlyPublicKey) -> FederationResult<FeeRebateStatus>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<T: ?Sized> MintFederationApi for T
+where
+    T: IModuleFederationApi + MaybeSend + MaybeSync + 'static,
+{
+    async fn fetch_fee_rebate(&self, account: XOnlyPublicKey) -> FederationResult<FeeRebateStatus> {
+        self.request_with_strategy(
+            EventuallyConsistent::new(self.all_members().threshold()),
+            "fee_rebate".to_string(),
+            ApiRequestErased::new(account),
+        )
+        .await
+    }
+}
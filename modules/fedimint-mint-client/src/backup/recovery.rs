@@ -11,7 +11,7 @@ use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::{Amount, NumPeers, PeerId, TransactionId};
 use fedimint_derive_secret::DerivableSecret;
 use fedimint_logging::LOG_CLIENT_RECOVERY_MINT;
-use fedimint_mint_common::{MintConsensusItem, MintInput, MintOutput, Nonce};
+use fedimint_mint_common::{MintConsensusItem, MintInput, MintOutput, MintSignatureItem, Nonce};
 use futures::StreamExt;
 use tbs::{
     combine_valid_shares, verify_blind_share, AggregatePublicKey, BlindedMessage, PublicKeyShare,
@@ -565,7 +565,7 @@ impl MintRestoreInProgressState {
         }
     }
 
-    pub fn handle_output_confirmation(&mut self, peer_id: PeerId, sigs: &MintConsensusItem) {
+    pub fn handle_output_confirmation(&mut self, peer_id: PeerId, sigs: &MintSignatureItem) {
         let enough_shares = if let Some((output_data, peer_shares)) =
             self.pending_outputs.get_mut(&sigs.out_point)
         {
@@ -746,10 +746,15 @@ impl MintRestoreInProgressState {
             }
             ConsensusItem::Module(module_item) => {
                 if module_item.module_instance_id() == LEGACY_HARDCODED_INSTANCE_ID_MINT {
-                    let mint_item = module_item
+                    let mint_item = match module_item
                         .as_any()
                         .downcast_ref::<MintConsensusItem>()
-                        .expect("mint key just checked");
+                        .expect("mint key just checked")
+                    {
+                        MintConsensusItem::PartialSignatures(item) => item,
+                        MintConsensusItem::VelocityPolicy(_)
+                        | MintConsensusItem::ExitSafeMode(_) => return,
+                    };
 
                     debug!(
                         target: LOG_CLIENT_RECOVERY_MINT,
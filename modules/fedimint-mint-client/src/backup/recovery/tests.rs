@@ -8,6 +8,7 @@ use fedimint_core::{msats, Amount, OutPoint, PeerId, Tiered, TieredMulti};
 use fedimint_derive_secret::DerivableSecret;
 use fedimint_mint_common::{
     BlindNonce, MintConsensusItem, MintInput, MintOutput, MintOutputSignatureShare,
+    MintSignatureItem,
 };
 use tbs::{AggregatePublicKey, BlindedSignatureShare, PublicKeyShare, SecretKeyShare};
 
@@ -107,11 +108,15 @@ impl MicroMintClient {
         &self,
         spendable_notes: impl IntoIterator<Item = (Amount, SpendableNote)>,
     ) -> MintInput {
-        MintInput(TieredMulti::from_iter(
-            spendable_notes
-                .into_iter()
-                .map(|(amount, snote)| (amount, snote.note)),
-        ))
+        MintInput(
+            TieredMulti::from_iter(
+                spendable_notes
+                    .into_iter()
+                    .map(|(amount, snote)| (amount, snote.note)),
+            ),
+            vec![],
+            vec![],
+        )
     }
 }
 
@@ -166,7 +171,7 @@ impl MicroMintFed {
             .map(|(peer_id, sec_keys)| {
                 (
                     *peer_id,
-                    MintConsensusItem {
+                    MintConsensusItem::PartialSignatures(MintSignatureItem {
                         out_point,
                         signatures: MintOutputSignatureShare(TieredMulti::from_iter(
                             output.0.iter_items().map(|(amount, blind_nonce)| {
@@ -186,7 +191,7 @@ impl MicroMintFed {
                                 )
                             }),
                         )),
-                    },
+                    }),
                 )
             })
             .collect()
@@ -206,9 +211,13 @@ impl MicroMintFed {
         let mut confs_by_order: Vec<HashMap<PeerId, BlindedSignatureShare>> = vec![];
 
         for (peer_id, mint_output_conf) in confirmations {
-            for (i, (_amount, (_bn, sig_share))) in
-                mint_output_conf.signatures.0.iter_items().enumerate()
-            {
+            let signatures = match mint_output_conf {
+                MintConsensusItem::PartialSignatures(item) => &item.signatures,
+                MintConsensusItem::VelocityPolicy(_) | MintConsensusItem::ExitSafeMode(_) => {
+                    panic!("Not an output confirmation")
+                }
+            };
+            for (i, (_amount, (_bn, sig_share))) in signatures.0.iter_items().enumerate() {
                 if confs_by_order.len() <= i {
                     assert_eq!(i, confs_by_order.len());
                     confs_by_order.push(HashMap::new());
@@ -146,7 +146,7 @@ impl MintInputStateCreated {
             .unzip();
 
         let refund_input = ClientInput::<MintInput, MintClientStateMachines> {
-            input: MintInput(notes),
+            input: MintInput(notes, vec![], vec![]),
             keys: spend_keys,
             // The input of the refund tx is managed by this state machine, so no new state machines
             // need to be created
@@ -1,15 +1,22 @@
+/// Federation API of the mint module
+pub mod api;
 // Backup and restore logic
 pub(crate) mod backup;
 /// Database keys used throughout the mint client module
 mod db;
 /// State machines for mint inputs
 mod input;
+/// Notes locked to spend conditions
+pub mod locked;
 /// State machines for out-of-band transmitted e-cash notes
 mod oob;
 /// State machines for mint outputs
 mod output;
+/// Note selection that makes amounts harder to correlate
+pub mod privacy;
 
 use std::cmp::Ordering;
+use std::collections::BTreeSet;
 use std::ffi;
 use std::fmt::Formatter;
 use std::sync::Arc;
@@ -19,6 +26,8 @@ use anyhow::{anyhow, bail};
 use async_stream::stream;
 use backup::recovery::{MintRestoreStateMachine, MintRestoreStates};
 use bitcoin_hashes::{sha256, sha256t, Hash, HashEngine as BitcoinHashEngine};
+use fedimint_client::balance::{BalanceEventKind, ModuleBalanceChange};
+use fedimint_client::fees::{FeeBreakdown, FeeEstimate};
 use fedimint_client::module::gen::ClientModuleGen;
 use fedimint_client::module::{ClientModule, IClientModule};
 use fedimint_client::oplog::{OperationLogEntry, UpdateStreamOrOutcome};
@@ -47,25 +56,33 @@ use fedimint_core::{
 use fedimint_derive_secret::{ChildId, DerivableSecret};
 pub use fedimint_mint_common as common;
 use fedimint_mint_common::config::MintClientConfig;
+use fedimint_mint_common::rebate::{FeeRebateStatus, RebateClaim};
+use fedimint_mint_common::spend_condition::SpendCondition;
 pub use fedimint_mint_common::*;
-use futures::{pin_mut, StreamExt};
-use secp256k1::{All, KeyPair, Secp256k1};
+use futures::{future, pin_mut, StreamExt};
+use secp256k1::{All, KeyPair, Secp256k1, XOnlyPublicKey};
 use serde::{Deserialize, Serialize};
 use tbs::AggregatePublicKey;
 use thiserror::Error;
 use tracing::{debug, info, warn};
 
+use crate::api::MintFederationApi;
 use crate::backup::recovery::MintRestoreInProgressState;
 use crate::backup::EcashBackup;
 use crate::db::{NextECashNoteIndexKey, NoteKey, NoteKeyPrefix};
 use crate::input::{
     MintInputCommon, MintInputStateCreated, MintInputStateMachine, MintInputStates,
 };
+use crate::locked::{
+    create_locked_input, create_locked_output, finalize_locked_notes, LockedNoteRequest,
+    LockedNotes,
+};
 use crate::oob::{MintOOBStateMachine, MintOOBStates, MintOOBStatesCreated};
 use crate::output::{
     MintOutputCommon, MintOutputStateMachine, MintOutputStates, MintOutputStatesCreated,
     MultiNoteIssuanceRequest, NoteIssuanceRequest,
 };
+use crate::privacy::{randomize_denominations, select_uniform_notes, NoteSelectionStrategy};
 
 const MINT_E_CASH_TYPE_CHILD_ID: ChildId = ChildId(0);
 
@@ -124,6 +141,44 @@ pub trait MintClientExt {
 
     /// Awaits the backup restoration to complete
     async fn await_restore_finished(&self) -> anyhow::Result<()>;
+
+    /// Issues notes over `amount` locked to `condition` from our funds, e.g.
+    /// gift-cash only a designated recipient can reissue. The notes can be
+    /// fetched with [`MintClientExt::await_locked_notes`] and sent out of band.
+    async fn lock_notes<M: Serialize + Send>(
+        &self,
+        amount: Amount,
+        condition: SpendCondition,
+        extra_meta: M,
+    ) -> anyhow::Result<OperationId>;
+
+    /// Waits for the notes issued by [`MintClientExt::lock_notes`]
+    async fn await_locked_notes(&self, operation_id: OperationId) -> anyhow::Result<LockedNotes>;
+
+    /// Reissues notes locked to a condition we can satisfy with `key` and
+    /// optionally `preimage` into our wallet. The progress can be observed
+    /// using [`MintClientExt::subscribe_reissue_external_notes`].
+    async fn reissue_locked_notes<M: Serialize + Send>(
+        &self,
+        notes: LockedNotes,
+        key: KeyPair,
+        preimage: Option<[u8; 32]>,
+        extra_meta: M,
+    ) -> anyhow::Result<OperationId>;
+
+    /// Fetches the fee rebate balance of the account of `account`, i.e. the
+    /// key notes were locked to, and the fees it paid in the current period
+    async fn get_fee_rebate(&self, account: XOnlyPublicKey) -> anyhow::Result<FeeRebateStatus>;
+
+    /// Claims `amount` of the fee rebate balance of the account of `key` as
+    /// e-cash into our wallet. The progress can be observed using
+    /// [`MintClientExt::subscribe_reissue_external_notes`].
+    async fn claim_fee_rebate<M: Serialize + Send>(
+        &self,
+        key: KeyPair,
+        amount: Amount,
+        extra_meta: M,
+    ) -> anyhow::Result<OperationId>;
 }
 
 /// The high-level state of a reissue operation started with
@@ -377,6 +432,213 @@ impl MintClientExt for Client {
         let (mint, _instance) = self.get_first_module::<MintClientModule>(&KIND);
         mint.await_restore_finished().await
     }
+
+    async fn lock_notes<M: Serialize + Send>(
+        &self,
+        amount: Amount,
+        condition: SpendCondition,
+        extra_meta: M,
+    ) -> anyhow::Result<OperationId> {
+        let (mint, instance) = self.get_first_module::<MintClientModule>(&KIND);
+        let operation_id = OperationId::new_random();
+
+        let (requests, output) = create_locked_output(&condition, amount, &mint.cfg.issued_tiers());
+        let tx = TransactionBuilder::new().with_output(output.into_dyn(instance.id));
+
+        let extra_meta = serde_json::to_value(extra_meta)
+            .expect("MintClientExt::lock_notes extra_meta is serializable");
+        let operation_meta_gen = move |txid, _| MintMeta {
+            variant: MintMetaVariants::LockNotes {
+                out_point: OutPoint { txid, out_idx: 0 },
+                condition: condition.clone(),
+                requests: requests.clone(),
+            },
+            amount,
+            extra_meta: extra_meta.clone(),
+        };
+
+        self.finalize_and_submit_transaction(
+            operation_id,
+            MintCommonGen::KIND.as_str(),
+            operation_meta_gen,
+            tx,
+        )
+        .await?;
+
+        Ok(operation_id)
+    }
+
+    async fn await_locked_notes(&self, operation_id: OperationId) -> anyhow::Result<LockedNotes> {
+        let (mint, _instance) = self.get_first_module::<MintClientModule>(&KIND);
+
+        let operation = mint_operation(self, operation_id).await?;
+        let (out_point, condition, requests) = match operation.meta::<MintMeta>().variant {
+            MintMetaVariants::LockNotes {
+                out_point,
+                condition,
+                requests,
+            } => (out_point, condition, requests),
+            _ => bail!("Operation doesn't lock notes"),
+        };
+
+        self.transaction_updates(operation_id)
+            .await
+            .await_tx_accepted(out_point.txid)
+            .await
+            .map_err(|e| anyhow!("Transaction not accepted {e:?}"))?;
+
+        let bsigs = loop {
+            let outcome: MintOutputOutcome = self
+                .api()
+                .await_output_outcome(out_point, Duration::MAX, &mint.decoder())
+                .await?;
+            match outcome.0 {
+                Some(bsigs) => break bsigs,
+                None => fedimint_core::task::sleep(Duration::from_secs(1)).await,
+            }
+        };
+
+        Ok(finalize_locked_notes(
+            condition,
+            &requests,
+            bsigs,
+            &mint.cfg.tbs_pks,
+        )?)
+    }
+
+    async fn reissue_locked_notes<M: Serialize + Send>(
+        &self,
+        notes: LockedNotes,
+        key: KeyPair,
+        preimage: Option<[u8; 32]>,
+        extra_meta: M,
+    ) -> anyhow::Result<OperationId> {
+        let (_mint, instance) = self.get_first_module::<MintClientModule>(&KIND);
+
+        let operation_id = OperationId(
+            notes
+                .consensus_hash::<sha256t::Hash<OOBReissueTag>>()
+                .into_inner(),
+        );
+        if self
+            .operation_log()
+            .get_operation(operation_id)
+            .await
+            .is_some()
+        {
+            bail!("We already reissued these notes");
+        }
+
+        let amount = notes.notes.total_amount();
+        let (input, keys) = create_locked_input(&notes, key, preimage)?;
+        let input = ClientInput::<MintInput, MintClientStateMachines> {
+            input,
+            keys,
+            // If the transaction is rejected the notes are still locked to the
+            // condition, so there is nothing to refund
+            state_machines: Arc::new(|_, _| vec![]),
+        };
+        let tx = TransactionBuilder::new().with_input(input.into_dyn(instance.id));
+
+        let extra_meta = serde_json::to_value(extra_meta)
+            .expect("MintClientExt::reissue_locked_notes extra_meta is serializable");
+        let operation_meta_gen = move |txid, _| MintMeta {
+            variant: MintMetaVariants::Reissuance {
+                out_point: OutPoint { txid, out_idx: 0 },
+            },
+            amount,
+            extra_meta: extra_meta.clone(),
+        };
+
+        self.finalize_and_submit_transaction(
+            operation_id,
+            MintCommonGen::KIND.as_str(),
+            operation_meta_gen,
+            tx,
+        )
+        .await?;
+
+        Ok(operation_id)
+    }
+
+    async fn get_fee_rebate(&self, account: XOnlyPublicKey) -> anyhow::Result<FeeRebateStatus> {
+        let (mint, _instance) = self.get_first_module::<MintClientModule>(&KIND);
+
+        mint.get_fee_rebate(account).await
+    }
+
+    async fn claim_fee_rebate<M: Serialize + Send>(
+        &self,
+        key: KeyPair,
+        amount: Amount,
+        extra_meta: M,
+    ) -> anyhow::Result<OperationId> {
+        let (mint, instance) = self.get_first_module::<MintClientModule>(&KIND);
+
+        let account = key.x_only_public_key().0;
+        let balance = mint.get_fee_rebate(account).await?.balance;
+        if balance < amount {
+            bail!("The rebate balance of the account is only {balance}");
+        }
+
+        // Every claim is a separate operation, a rejected one can be retried
+        let operation_id = OperationId::new_random();
+        let input = ClientInput::<MintInput, MintClientStateMachines> {
+            input: MintInput(
+                TieredMulti::default(),
+                vec![],
+                vec![RebateClaim { account, amount }],
+            ),
+            keys: vec![key],
+            // If the transaction is rejected the balance wasn't claimed
+            state_machines: Arc::new(|_, _| vec![]),
+        };
+        let tx = TransactionBuilder::new().with_input(input.into_dyn(instance.id));
+
+        let extra_meta = serde_json::to_value(extra_meta)
+            .expect("MintClientExt::claim_fee_rebate extra_meta is serializable");
+        let operation_meta_gen = move |txid, _| MintMeta {
+            variant: MintMetaVariants::Reissuance {
+                out_point: OutPoint { txid, out_idx: 0 },
+            },
+            amount,
+            extra_meta: extra_meta.clone(),
+        };
+
+        self.finalize_and_submit_transaction(
+            operation_id,
+            MintCommonGen::KIND.as_str(),
+            operation_meta_gen,
+            tx,
+        )
+        .await?;
+
+        Ok(operation_id)
+    }
+}
+
+/// Fee preview of [`MintClientExt::reissue_external_notes`]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReissueFeeEstimate {
+    pub notes: TieredMulti<SpendableNote>,
+}
+
+#[apply(async_trait_maybe_send!)]
+impl FeeEstimate for ReissueFeeEstimate {
+    async fn estimate_fees(&self, client: &Client) -> anyhow::Result<FeeBreakdown> {
+        let (mint, _instance) = client.get_first_module::<MintClientModule>(&KIND);
+
+        let spend_fee = mint.cfg.fee_consensus.note_spend_abs * (self.notes.count_items() as u64);
+        let total = self.notes.total_amount();
+        if total <= spend_fee {
+            bail!("Notes don't cover the fee of spending them {spend_fee}");
+        }
+
+        Ok(FeeBreakdown {
+            federation_fee: spend_fee + client.estimate_receive_fees(total - spend_fee).await,
+            ..FeeBreakdown::default()
+        })
+    }
 }
 
 async fn mint_operation(
@@ -405,12 +667,27 @@ pub struct MintMeta {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 enum MintMetaVariants {
-    Reissuance { out_point: OutPoint },
-    SpendOOB { requested_amount: Amount },
+    Reissuance {
+        out_point: OutPoint,
+    },
+    SpendOOB {
+        requested_amount: Amount,
+    },
+    LockNotes {
+        out_point: OutPoint,
+        condition: SpendCondition,
+        requests: TieredMulti<LockedNoteRequest>,
+    },
 }
 
-#[derive(Debug, Clone)]
-pub struct MintClientGen;
+#[derive(Debug, Clone, Default)]
+pub struct MintClientGen(pub NoteSelectionStrategy);
+
+impl MintClientGen {
+    pub fn new(note_selection: NoteSelectionStrategy) -> Self {
+        Self(note_selection)
+    }
+}
 
 impl ExtendsCommonModuleGen for MintClientGen {
     type Common = MintCommonGen;
@@ -434,15 +711,17 @@ impl ClientModuleGen for MintClientGen {
         module_root_secret: DerivableSecret,
         notifier: ModuleNotifier<DynGlobalClientContext, <Self::Module as ClientModule>::States>,
         _api: DynGlobalApi,
-        _module_api: DynModuleApi,
+        module_api: DynModuleApi,
     ) -> anyhow::Result<Self::Module> {
         let (cancel_oob_payment_bc, _) = tokio::sync::broadcast::channel(16);
         Ok(MintClientModule {
             cfg,
+            module_api,
             secret: module_root_secret,
             secp: Secp256k1::new(),
             notifier,
             cancel_oob_payment_bc,
+            note_selection: self.0,
         })
     }
 }
@@ -450,10 +729,12 @@ impl ClientModuleGen for MintClientGen {
 #[derive(Debug)]
 pub struct MintClientModule {
     cfg: MintClientConfig,
+    module_api: DynModuleApi,
     secret: DerivableSecret,
     secp: Secp256k1<All>,
     notifier: ModuleNotifier<DynGlobalClientContext, MintClientStateMachines>,
     cancel_oob_payment_bc: tokio::sync::broadcast::Sender<OperationId>,
+    note_selection: NoteSelectionStrategy,
 }
 
 // TODO: wrap in Arc
@@ -687,42 +968,59 @@ impl ClientModule for MintClientModule {
         self.get_wallet_summary(dbtx).await.total_amount()
     }
 
-    async fn subscribe_balance_changes(&self) -> BoxStream<'static, ()> {
+    async fn subscribe_balance_changes(&self) -> BoxStream<'static, ModuleBalanceChange> {
         Box::pin(
             self.notifier
                 .subscribe_all_operations()
                 .await
                 .filter_map(|state| async move {
-                    match state {
+                    let operation_id = state.operation_id();
+                    let received = match state {
                         MintClientStateMachines::Output(MintOutputStateMachine {
-                            state: MintOutputStates::Succeeded(_),
+                            state: MintOutputStates::Succeeded(succeeded),
                             ..
-                        }) => Some(()),
+                        }) => Some(succeeded.amount),
                         MintClientStateMachines::Input(MintInputStateMachine {
                             state: MintInputStates::Created(_),
                             ..
-                        }) => Some(()),
+                        }) => None,
                         // We only trigger on created since refunds are already covered under the
                         // output state
                         MintClientStateMachines::OOB(MintOOBStateMachine {
                             state: MintOOBStates::Created(_),
                             ..
-                        }) => Some(()),
+                        }) => None,
                         // We don't want to scare users, so we only trigger on success instead of
                         // showing incremental progress. Ideally the balance isn't shown to them
                         // during recovery anyway.
                         MintClientStateMachines::Restore(MintRestoreStateMachine {
                             state: MintRestoreStates::Success,
                             ..
-                        }) => Some(()),
-                        _ => None,
-                    }
+                        }) => None,
+                        _ => return None,
+                    };
+                    Some(ModuleBalanceChange {
+                        operation_id,
+                        received,
+                    })
                 }),
         )
     }
+
+    fn balance_event_kind(&self, operation: &OperationLogEntry) -> Option<BalanceEventKind> {
+        match operation.meta::<MintMeta>().variant {
+            MintMetaVariants::Reissuance { .. } => Some(BalanceEventKind::EcashReceived),
+            _ => None,
+        }
+    }
 }
 
 impl MintClientModule {
+    /// Fetches the fee rebate status of `account` from the federation
+    pub async fn get_fee_rebate(&self, account: XOnlyPublicKey) -> anyhow::Result<FeeRebateStatus> {
+        Ok(self.module_api.fetch_fee_rebate(account).await?)
+    }
+
     /// Returns the number of held e-cash notes per denomination
     pub async fn get_wallet_summary(
         &self,
@@ -743,7 +1041,9 @@ impl MintClientModule {
     // TODO: put "notes per denomination" default into cfg
     /// Creates a mint output with exactly the given `amount`, issuing e-cash
     /// notes such that the client holds `notes_per_denomination` notes of each
-    /// e-cash note denomination held.
+    /// e-cash note denomination held. Retired tiers are never issued.
+    ///
+    /// With [`NoteSelectionStrategy::Privacy`] the notes are split randomly.
     pub async fn create_output(
         &self,
         dbtx: &mut ModuleDatabaseTransaction<'_>,
@@ -753,12 +1053,17 @@ impl MintClientModule {
     ) -> ClientOutput<MintOutput, MintClientStateMachines> {
         let mut amount_requests: Vec<((Amount, NoteIssuanceRequest), (Amount, BlindNonce))> =
             Vec::new();
-        let denominations = TieredSummary::represent_amount(
+        let issued_tiers = self.cfg.issued_tiers();
+        let mut denominations = TieredSummary::represent_amount(
             amount,
             &self.get_wallet_summary(dbtx).await,
-            &self.cfg.tbs_pks,
+            &issued_tiers,
             notes_per_denomination,
         );
+        if self.note_selection == NoteSelectionStrategy::Privacy {
+            denominations =
+                randomize_denominations(&denominations, &issued_tiers, &mut rand::thread_rng());
+        }
         for (amt, num) in denominations.iter() {
             for _ in 0..num {
                 let (request, blind_nonce) = self.new_ecash_note(amt, dbtx).await;
@@ -806,7 +1111,9 @@ impl MintClientModule {
             .subscribe(operation_id)
             .await
             .filter_map(|state| async move {
-                let MintClientStateMachines::Output(state) = state else { return None };
+                let MintClientStateMachines::Output(state) = state else {
+                    return None;
+                };
 
                 if state.common.out_point != out_point {
                     return None;
@@ -829,13 +1136,44 @@ impl MintClientModule {
 
     // FIXME: use lazy e-cash note loading implemented in #2183
     /// Creates a mint input of at least `min_amount`.
+    ///
+    /// All notes we hold in retired tiers are spent along with it, so they get
+    /// reissued as change in the tiers still issued before the federation
+    /// removes their keys.
+    ///
+    /// With [`NoteSelectionStrategy::Privacy`] notes of a single denomination
+    /// are spent if possible, see [`select_uniform_notes`].
     pub async fn create_input(
         &self,
         dbtx: &mut ModuleDatabaseTransaction<'_>,
         operation_id: OperationId,
         min_amount: Amount,
     ) -> anyhow::Result<ClientInput<MintInput, MintClientStateMachines>> {
-        let spendable_selected_notes = Self::select_notes(dbtx, min_amount).await?;
+        let retired_notes = self.get_retired_notes(dbtx).await;
+        if !retired_notes.is_empty() {
+            info!(
+                target: LOG_TARGET,
+                amount = %retired_notes.total_amount(),
+                "Reissuing notes held in retired tiers"
+            );
+        }
+
+        let remaining_amount = min_amount.saturating_sub(retired_notes.total_amount());
+        let mut spendable_selected_notes = if remaining_amount == Amount::ZERO {
+            TieredMulti::default()
+        } else {
+            match self.note_selection {
+                NoteSelectionStrategy::Minimal => {
+                    self.select_notes(dbtx, remaining_amount, &self.cfg.retired_tiers)
+                        .await?
+                }
+                NoteSelectionStrategy::Privacy => {
+                    self.select_notes_privately(dbtx, remaining_amount, &self.cfg.retired_tiers)
+                        .await?
+                }
+            }
+        };
+        spendable_selected_notes.extend(retired_notes.into_iter_items());
 
         for (amount, note) in spendable_selected_notes.iter_items() {
             dbtx.remove_entry(&NoteKey {
@@ -856,7 +1194,9 @@ impl MintClientModule {
         notes: TieredMulti<SpendableNote>,
     ) -> anyhow::Result<ClientInput<MintInput, MintClientStateMachines>> {
         if let Some((amt, invalid_note)) = notes.iter_items().find(|(amt, note)| {
-            let Some(mint_key) = self.cfg.tbs_pks.get(*amt) else {return true;};
+            let Some(mint_key) = self.cfg.tbs_pks.get(*amt) else {
+                return true;
+            };
             !note.note.verify(*mint_key)
         }) {
             return Err(anyhow!(
@@ -885,7 +1225,7 @@ impl MintClientModule {
         });
 
         Ok(ClientInput {
-            input: MintInput(selected_notes),
+            input: MintInput(selected_notes, vec![], vec![]),
             keys: spend_keys,
             state_machines: sm_gen,
         })
@@ -901,7 +1241,9 @@ impl MintClientModule {
         Vec<MintClientStateMachines>,
         TieredMulti<SpendableNote>,
     )> {
-        let spendable_selected_notes = Self::select_notes(dbtx, min_amount).await?;
+        let spendable_selected_notes = self
+            .select_notes(dbtx, min_amount, &BTreeSet::new())
+            .await?;
 
         let operation_id = OperationId(
             spendable_selected_notes
@@ -934,7 +1276,9 @@ impl MintClientModule {
                 .subscribe(operation_id)
                 .await
                 .filter_map(|state| async move {
-                    let MintClientStateMachines::OOB(state) = state else { return None };
+                    let MintClientStateMachines::OOB(state) = state else {
+                        return None;
+                    };
 
                     match state.state {
                         MintOOBStates::TimeoutRefund(refund) => Some(SpendOOBRefund {
@@ -983,18 +1327,75 @@ impl MintClientModule {
     /// requested amount of notes are returned it was because exact change
     /// couldn't be made, and the next smallest amount will be returned.
     ///
+    /// Notes in `excluded_tiers` and in tiers the federation has no keys for
+    /// (anymore) are never selected.
+    ///
     /// The caller can request change from the federation.
     async fn select_notes(
+        &self,
         dbtx: &mut ModuleDatabaseTransaction<'_>,
         amount: Amount,
+        excluded_tiers: &BTreeSet<Amount>,
     ) -> Result<TieredMulti<SpendableNote>, InsufficientBalanceError> {
         let note_stream = dbtx
             .find_by_prefix_sorted_descending(&NoteKeyPrefix)
             .await
+            .filter(|(key, _)| {
+                future::ready(
+                    self.cfg.tbs_pks.get(key.amount).is_some()
+                        && !excluded_tiers.contains(&key.amount),
+                )
+            })
             .map(|(key, note)| (key.amount, note));
         select_notes_from_stream(note_stream, amount).await
     }
 
+    /// Like [`Self::select_notes`], but selects notes of a single denomination
+    /// if we hold enough of one. The excess is meant to come back as change.
+    async fn select_notes_privately(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        amount: Amount,
+        excluded_tiers: &BTreeSet<Amount>,
+    ) -> Result<TieredMulti<SpendableNote>, InsufficientBalanceError> {
+        let notes = dbtx
+            .find_by_prefix(&NoteKeyPrefix)
+            .await
+            .filter(|(key, _)| {
+                future::ready(
+                    self.cfg.tbs_pks.get(key.amount).is_some()
+                        && !excluded_tiers.contains(&key.amount),
+                )
+            })
+            .map(|(key, note)| (key.amount, note))
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<TieredMulti<_>>();
+
+        if let Some(selected) = select_uniform_notes(&notes, amount) {
+            return Ok(selected);
+        }
+        debug!(
+            target: LOG_TARGET,
+            %amount,
+            "No single denomination covers the amount, selecting a mix of notes"
+        );
+        select_notes_from_stream(futures::stream::iter(notes.into_iter_items().rev()), amount).await
+    }
+
+    /// Returns the notes we hold in tiers the federation retired
+    async fn get_retired_notes(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> TieredMulti<SpendableNote> {
+        Self::get_all_spendable_notes(dbtx)
+            .await
+            .into_iter_items()
+            .filter(|(amount, _)| self.cfg.retired_tiers.contains(amount))
+            .collect()
+    }
+
     async fn get_all_spendable_notes(
         dbtx: &mut ModuleDatabaseTransaction<'_>,
     ) -> TieredMulti<SpendableNote> {
@@ -0,0 +1,161 @@
+use std::sync::Arc;
+
+use bitcoin_hashes::{sha256, Hash};
+use fedimint_client::transaction::ClientOutput;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{Amount, Tiered, TieredMulti, TieredSummary};
+use fedimint_mint_common::spend_condition::{ConditionWitness, SpendCondition};
+use fedimint_mint_common::{BlindNonce, MintInput, MintOutput, MintOutputBlindSignatures, Note};
+use rand::Rng;
+use secp256k1::KeyPair;
+use serde::{Deserialize, Serialize};
+use tbs::{blind_message, unblind_signature, AggregatePublicKey, BlindingKey};
+
+use crate::output::NoteFinalizationError;
+use crate::MintClientStateMachines;
+
+/// E-cash notes locked to a [`SpendCondition`], only who can satisfy it can
+/// reissue them
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct LockedNotes {
+    pub condition: SpendCondition,
+    pub notes: TieredMulti<LockedNote>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct LockedNote {
+    pub note: Note,
+    /// Needed to derive the note's nonce from the condition
+    pub salt: [u8; 32],
+}
+
+/// Data to unblind a [`LockedNote`] once the mint signed it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockedNoteRequest {
+    salt: [u8; 32],
+    blinding_key: BlindingKey,
+}
+
+/// Creates an output issuing notes over `amount` locked to `condition`
+///
+/// The notes aren't added to our wallet, so the output has no state machines.
+pub(crate) fn create_locked_output(
+    condition: &SpendCondition,
+    amount: Amount,
+    tiers: &Tiered<AggregatePublicKey>,
+) -> (
+    TieredMulti<LockedNoteRequest>,
+    ClientOutput<MintOutput, MintClientStateMachines>,
+) {
+    let denominations =
+        TieredSummary::represent_amount(amount, &TieredSummary::default(), tiers, 1);
+
+    let mut requests = vec![];
+    let mut blind_nonces = vec![];
+    for (amount, num) in denominations.iter() {
+        for _ in 0..num {
+            let salt: [u8; 32] = rand::thread_rng().gen();
+            let blinding_key = BlindingKey::random();
+            let nonce = condition.nonce(&salt);
+
+            requests.push((amount, LockedNoteRequest { salt, blinding_key }));
+            blind_nonces.push((
+                amount,
+                BlindNonce(blind_message(nonce.to_message(), blinding_key)),
+            ));
+        }
+    }
+
+    let output = ClientOutput {
+        output: MintOutput(blind_nonces.into_iter().collect()),
+        state_machines: Arc::new(|_, _| vec![]),
+    };
+    (requests.into_iter().collect(), output)
+}
+
+/// Unblinds the signatures of the mint on locked notes requested by
+/// [`create_locked_output`]
+pub(crate) fn finalize_locked_notes(
+    condition: SpendCondition,
+    requests: &TieredMulti<LockedNoteRequest>,
+    bsigs: MintOutputBlindSignatures,
+    mint_keys: &Tiered<AggregatePublicKey>,
+) -> Result<LockedNotes, NoteFinalizationError> {
+    if !requests.structural_eq(&bsigs.0) {
+        return Err(NoteFinalizationError::WrongMintAnswer);
+    }
+
+    let notes = requests
+        .iter_items()
+        .zip(bsigs.0)
+        .enumerate()
+        .map(|(idx, ((amount, request), (_, bsig)))| {
+            let mint_key = mint_keys
+                .tier(&amount)
+                .map_err(|e| NoteFinalizationError::InvalidAmountTier(e.0))?;
+            let note = Note(
+                condition.nonce(&request.salt),
+                unblind_signature(request.blinding_key, bsig),
+            );
+            if !note.verify(*mint_key) {
+                return Err(NoteFinalizationError::InvalidSignatureAtIdx(idx));
+            }
+
+            Ok((
+                amount,
+                LockedNote {
+                    note,
+                    salt: request.salt,
+                },
+            ))
+        })
+        .collect::<Result<_, _>>()?;
+
+    Ok(LockedNotes { condition, notes })
+}
+
+/// Creates an input spending `notes` by satisfying the first branch of their
+/// condition that `key` and `preimage` can satisfy
+///
+/// Whether a timelock expired is only checked by the federation.
+pub(crate) fn create_locked_input(
+    notes: &LockedNotes,
+    key: KeyPair,
+    preimage: Option<[u8; 32]>,
+) -> anyhow::Result<(MintInput, Vec<KeyPair>)> {
+    let branch = notes
+        .condition
+        .branches
+        .iter()
+        .position(|branch| {
+            branch.key == key.x_only_public_key().0
+                && branch.hash_lock.map_or(true, |hash| {
+                    preimage.map_or(false, |preimage| sha256::Hash::hash(&preimage) == hash)
+                })
+        })
+        .ok_or_else(|| anyhow::anyhow!("The key can't spend the notes"))?;
+
+    let witnesses = notes
+        .notes
+        .iter_items()
+        .map(|(_, locked)| ConditionWitness {
+            nonce: locked.note.0,
+            condition: notes.condition.clone(),
+            salt: locked.salt,
+            branch: branch as u32,
+            preimage,
+        })
+        .collect();
+    let input = MintInput(
+        notes
+            .notes
+            .iter_items()
+            .map(|(amount, locked)| (amount, locked.note))
+            .collect(),
+        witnesses,
+        vec![],
+    );
+
+    // The key signs once for every note it unlocks
+    Ok((input, vec![key; notes.notes.count_items()]))
+}
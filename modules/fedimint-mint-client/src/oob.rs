@@ -191,7 +191,7 @@ async fn try_cancel_oob_spend(
         .unzip();
 
     let input = ClientInput {
-        input: MintInput(notes),
+        input: MintInput(notes, vec![], vec![]),
         keys,
         state_machines: Arc::new(move |txid, input_idx| {
             vec![MintClientStateMachines::Input(MintInputStateMachine {
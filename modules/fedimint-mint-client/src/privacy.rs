@@ -0,0 +1,151 @@
+//! Spending and issuing notes such that amounts are harder to correlate
+//!
+//! The federation sees the denominations of all notes a transaction spends
+//! and issues. By default the client spends its largest notes first and exact
+//! change, so the spent notes spell out the amount of the payment in binary,
+//! and outputs are issued in the smallest number of notes, which does the same
+//! for the change. Both make it easy to match a transaction to the payment it
+//! funds or the change to the notes spent later.
+//!
+//! With [`NoteSelectionStrategy::Privacy`] the client instead
+//!
+//! * spends notes of a single denomination if it holds enough of one. Such
+//!   inputs only reveal the amount rounded up to the denomination and look
+//!   alike across users. The rest comes back as change.
+//! * issues outputs, including change, in randomly split denominations, so the
+//!   issued notes don't reveal how the amount was represented.
+//!
+//! Both cost some fees, as more notes are spent and issued.
+
+use fedimint_core::{Amount, Tiered, TieredMulti, TieredSummary};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// Largest number of notes of a single denomination spent instead of a mix
+/// of denominations
+const MAX_UNIFORM_NOTES: usize = 16;
+
+/// Randomly split outputs are never issued in more notes than this
+const MAX_SPLIT_NOTES: usize = 64;
+
+/// How the mint client selects the notes it spends and issues
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoteSelectionStrategy {
+    /// Spend and issue as few notes as possible
+    #[default]
+    Minimal,
+    /// Avoid note combinations that reveal amounts, at the cost of spending
+    /// and issuing more notes
+    Privacy,
+}
+
+/// Selects notes of the single denomination that covers `amount` with the
+/// least excess, `None` if no denomination has enough notes
+pub fn select_uniform_notes<Note: Clone>(
+    notes: &TieredMulti<Note>,
+    amount: Amount,
+) -> Option<TieredMulti<Note>> {
+    if amount == Amount::ZERO {
+        return Some(TieredMulti::default());
+    }
+
+    let (tier, needed) = notes
+        .iter()
+        .filter_map(|(tier, tier_notes)| {
+            let needed = ((amount.msats + tier.msats - 1) / tier.msats) as usize;
+            (needed <= tier_notes.len() && needed <= MAX_UNIFORM_NOTES).then_some((*tier, needed))
+        })
+        .min_by_key(|(tier, needed)| (tier.msats * *needed as u64 - amount.msats, *needed))?;
+
+    Some(
+        notes
+            .get(tier)?
+            .iter()
+            .take(needed)
+            .map(|note| (tier, note.clone()))
+            .collect(),
+    )
+}
+
+/// Randomly replaces notes of `denominations` by two notes of half their
+/// denomination, as far as that is one of `tiers`
+pub fn randomize_denominations<K>(
+    denominations: &TieredSummary,
+    tiers: &Tiered<K>,
+    rng: &mut impl Rng,
+) -> TieredSummary {
+    let mut notes = denominations
+        .iter()
+        .flat_map(|(tier, count)| std::iter::repeat(tier).take(count))
+        .collect::<Vec<_>>();
+
+    let splits = rng.gen_range(0..=notes.len());
+    for _ in 0..splits {
+        if notes.len() + 1 > MAX_SPLIT_NOTES {
+            break;
+        }
+        let idx = rng.gen_range(0..notes.len());
+        let half = Amount::from_msats(notes[idx].msats / 2);
+        if notes[idx].msats % 2 != 0 || tiers.get(half).is_none() {
+            continue;
+        }
+        notes[idx] = half;
+        notes.push(half);
+    }
+
+    let mut randomized = TieredSummary::default();
+    for note in notes {
+        randomized.inc(note, 1);
+    }
+    randomized
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn notes(notes: Vec<(u64, usize)>) -> TieredMulti<()> {
+        notes
+            .into_iter()
+            .flat_map(|(sats, count)| std::iter::repeat((Amount::from_sats(sats), ())).take(count))
+            .collect()
+    }
+
+    #[test]
+    fn selects_single_denomination_with_least_excess() {
+        let held = notes(vec![(1, 3), (8, 4), (64, 1)]);
+
+        // Two 8 sat notes waste less than the 64 sat one, the 1 sat notes
+        // don't suffice
+        assert_eq!(
+            select_uniform_notes(&held, Amount::from_sats(13)),
+            Some(notes(vec![(8, 2)]))
+        );
+        assert_eq!(
+            select_uniform_notes(&held, Amount::from_sats(40)),
+            Some(notes(vec![(64, 1)]))
+        );
+        assert_eq!(select_uniform_notes(&held, Amount::from_sats(100)), None);
+    }
+
+    #[test]
+    fn randomized_denominations_keep_the_amount() {
+        let max_amount = Amount::from_sats(1_000_000);
+        let tiers = Tiered::gen_denominations(max_amount);
+        let amount = Amount::from_msats(123_456_789);
+        let denominations =
+            TieredSummary::represent_amount(amount, &TieredSummary::default(), &tiers, 0);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let randomized = randomize_denominations(&denominations, &tiers, &mut rng);
+            assert_eq!(randomized.total_amount(), amount);
+            assert!(randomized.count_items() >= denominations.count_items());
+            assert!(randomized.count_items() <= MAX_SPLIT_NOTES);
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 use fedimint_core::config::EmptyGenParams;
 use fedimint_core::core::ModuleKind;
@@ -7,6 +7,7 @@ use fedimint_core::{plugin_types_trait_impl_config, Amount, PeerId, Tiered};
 use serde::{Deserialize, Serialize};
 use tbs::{AggregatePublicKey, PublicKeyShare};
 
+use crate::rebate::FeeRebateConsensus;
 use crate::MintCommonGen;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,7 +18,15 @@ pub struct MintGenParams {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MintGenParamsConsensus {
+    /// Denominations of the notes issued by the federation, any set of tiers
+    /// containing 1 msat works, powers of two are only the default
     pub mint_amounts: Vec<Amount>,
+    /// Enforce daily limits on registered accounts, see [`crate::velocity`]
+    #[serde(default)]
+    pub velocity_limits: bool,
+    /// Return part of the collected fees to accounts, see [`crate::rebate`]
+    #[serde(default)]
+    pub fee_rebate: Option<FeeRebateConsensus>,
 }
 
 const TEN_BTC_IN_SATS: u64 = 10 * 100_000_000;
@@ -30,6 +39,8 @@ impl Default for MintGenParams {
                     .tiers()
                     .cloned()
                     .collect(),
+                velocity_limits: false,
+                fee_rebate: None,
             },
             local: EmptyGenParams {},
         }
@@ -55,12 +66,27 @@ pub struct MintConfigConsensus {
     pub fee_consensus: FeeConsensus,
     /// The maximum amount of change a client can request
     pub max_notes_per_denomination: u16,
+    /// Whether daily limits on registered accounts are enforced, see
+    /// [`crate::velocity`]
+    #[serde(default)]
+    pub velocity_limits: bool,
+    /// Whether fee rebates are paid to accounts and how much, see
+    /// [`crate::rebate`]
+    #[serde(default)]
+    pub fee_rebate: Option<FeeRebateConsensus>,
+    /// Tiers we still redeem but no longer issue notes in. A tier has to be
+    /// retired before its keys can be removed, so clients get a chance to
+    /// reissue the notes they hold in it.
+    #[serde(default)]
+    pub retired_tiers: BTreeSet<Amount>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MintConfigPrivate {
-    /// Secret keys for blind-signing ecash of varying note denominations
-    pub tbs_sks: Tiered<tbs::SecretKeyShare>,
+    /// Secret keys for blind-signing ecash of varying note denominations,
+    /// `None` if they are held by an external guardian signer
+    #[serde(default)]
+    pub tbs_sks: Option<Tiered<tbs::SecretKeyShare>>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
@@ -69,6 +95,21 @@ pub struct MintClientConfig {
     pub fee_consensus: FeeConsensus,
     pub peer_tbs_pks: BTreeMap<PeerId, Tiered<tbs::PublicKeyShare>>,
     pub max_notes_per_denomination: u16,
+    /// Tiers that are redeemed but no longer issued, see
+    /// [`MintConfigConsensus::retired_tiers`]
+    #[serde(default)]
+    pub retired_tiers: BTreeSet<Amount>,
+}
+
+impl MintClientConfig {
+    /// Keys of the tiers new notes are issued in
+    pub fn issued_tiers(&self) -> Tiered<AggregatePublicKey> {
+        self.tbs_pks
+            .iter()
+            .filter(|(amount, _)| !self.retired_tiers.contains(amount))
+            .map(|(amount, key)| (amount, *key))
+            .collect()
+    }
 }
 
 // Wire together the configs for this module
@@ -5,6 +5,7 @@ use fedimint_core::{impl_db_lookup, impl_db_record, Amount, OutPoint, PeerId};
 use serde::{Deserialize, Serialize};
 use strum_macros::EnumIter;
 
+use crate::velocity::VelocityPolicy;
 use crate::{MintOutputBlindSignatures, MintOutputSignatureShare, Nonce};
 
 #[repr(u8)]
@@ -16,6 +17,18 @@ pub enum DbKeyPrefix {
     OutputOutcome = 0x13,
     MintAuditItem = 0x14,
     EcashBackup = 0x15,
+    VelocityPolicy = 0x16,
+    VelocityPolicyVote = 0x17,
+    ProposedVelocityPolicy = 0x18,
+    VelocityUsage = 0x19,
+    SafeMode = 0x1a,
+    RebatePeriod = 0x1b,
+    CollectedFees = 0x1c,
+    AccountFees = 0x1d,
+    RebateBalance = 0x1e,
+    SafeModeExitVote = 0x1f,
+    ProposedSafeModeExit = 0x20,
+    AcknowledgedLiabilityViolation = 0x21,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -127,6 +140,225 @@ impl_db_record!(
 );
 impl_db_lookup!(key = EcashBackupKey, query_prefix = EcashBackupKeyPrefix);
 
+/// The velocity policy the guardians agreed on
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct VelocityPolicyKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct VelocityPolicyKeyPrefix;
+
+impl_db_record!(
+    key = VelocityPolicyKey,
+    value = VelocityPolicy,
+    db_prefix = DbKeyPrefix::VelocityPolicy,
+);
+impl_db_lookup!(
+    key = VelocityPolicyKey,
+    query_prefix = VelocityPolicyKeyPrefix
+);
+
+/// Velocity policy a peer voted for that didn't reach the threshold yet
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct VelocityPolicyVoteKey(pub PeerId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct VelocityPolicyVoteKeyPrefix;
+
+impl_db_record!(
+    key = VelocityPolicyVoteKey,
+    value = VelocityPolicy,
+    db_prefix = DbKeyPrefix::VelocityPolicyVote,
+);
+impl_db_lookup!(
+    key = VelocityPolicyVoteKey,
+    query_prefix = VelocityPolicyVoteKeyPrefix
+);
+
+/// Velocity policy our guardian wants to vote for in the next epoch
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct ProposedVelocityPolicyKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ProposedVelocityPolicyKeyPrefix;
+
+impl_db_record!(
+    key = ProposedVelocityPolicyKey,
+    value = VelocityPolicy,
+    db_prefix = DbKeyPrefix::ProposedVelocityPolicy,
+);
+impl_db_lookup!(
+    key = ProposedVelocityPolicyKey,
+    query_prefix = ProposedVelocityPolicyKeyPrefix
+);
+
+/// Amount an account spent on a day, see [`crate::velocity::velocity_day`]
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct VelocityUsageKey {
+    pub account: secp256k1::XOnlyPublicKey,
+    pub day: u32,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct VelocityUsageKeyPrefix;
+
+impl_db_record!(
+    key = VelocityUsageKey,
+    value = Amount,
+    db_prefix = DbKeyPrefix::VelocityUsage,
+);
+impl_db_lookup!(
+    key = VelocityUsageKey,
+    query_prefix = VelocityUsageKeyPrefix
+);
+
+/// Present once the mint redeemed more e-cash than it issued, from then on it
+/// neither issues nor redeems notes until the guardians investigated
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct SafeModeKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct SafeModeKeyPrefix;
+
+impl_db_record!(
+    key = SafeModeKey,
+    value = LiabilityViolation,
+    db_prefix = DbKeyPrefix::SafeMode,
+);
+impl_db_lookup!(key = SafeModeKey, query_prefix = SafeModeKeyPrefix);
+
+/// Violation a peer voted to exit safe mode for that didn't reach the
+/// threshold yet
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct SafeModeExitVoteKey(pub PeerId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct SafeModeExitVoteKeyPrefix;
+
+impl_db_record!(
+    key = SafeModeExitVoteKey,
+    value = LiabilityViolation,
+    db_prefix = DbKeyPrefix::SafeModeExitVote,
+);
+impl_db_lookup!(
+    key = SafeModeExitVoteKey,
+    query_prefix = SafeModeExitVoteKeyPrefix
+);
+
+/// Violation our guardian wants to vote to exit safe mode for in the next
+/// epoch
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct ProposedSafeModeExitKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ProposedSafeModeExitKeyPrefix;
+
+impl_db_record!(
+    key = ProposedSafeModeExitKey,
+    value = LiabilityViolation,
+    db_prefix = DbKeyPrefix::ProposedSafeModeExit,
+);
+impl_db_lookup!(
+    key = ProposedSafeModeExitKey,
+    query_prefix = ProposedSafeModeExitKeyPrefix
+);
+
+/// Latest violation the guardians voted to exit safe mode for. Its shortfall
+/// doesn't trigger safe mode again, only a larger one does.
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct AcknowledgedLiabilityViolationKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct AcknowledgedLiabilityViolationKeyPrefix;
+
+impl_db_record!(
+    key = AcknowledgedLiabilityViolationKey,
+    value = LiabilityViolation,
+    db_prefix = DbKeyPrefix::AcknowledgedLiabilityViolation,
+);
+impl_db_lookup!(
+    key = AcknowledgedLiabilityViolationKey,
+    query_prefix = AcknowledgedLiabilityViolationKeyPrefix
+);
+
+/// Rebate period fees are currently accumulated for, see
+/// [`crate::rebate::FeeRebateConsensus::period`]
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct RebatePeriodKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct RebatePeriodKeyPrefix;
+
+impl_db_record!(
+    key = RebatePeriodKey,
+    value = u32,
+    db_prefix = DbKeyPrefix::RebatePeriod,
+);
+impl_db_lookup!(key = RebatePeriodKey, query_prefix = RebatePeriodKeyPrefix);
+
+/// All fees the mint collected in the current rebate period
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct CollectedFeesKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct CollectedFeesKeyPrefix;
+
+impl_db_record!(
+    key = CollectedFeesKey,
+    value = Amount,
+    db_prefix = DbKeyPrefix::CollectedFees,
+);
+impl_db_lookup!(
+    key = CollectedFeesKey,
+    query_prefix = CollectedFeesKeyPrefix
+);
+
+/// Fees an account paid in the current rebate period
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct AccountFeesKey(pub secp256k1::XOnlyPublicKey);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct AccountFeesKeyPrefix;
+
+impl_db_record!(
+    key = AccountFeesKey,
+    value = Amount,
+    db_prefix = DbKeyPrefix::AccountFees,
+);
+impl_db_lookup!(key = AccountFeesKey, query_prefix = AccountFeesKeyPrefix);
+
+/// Rebates credited to an account that weren't claimed yet
+#[derive(Debug, Clone, Copy, Encodable, Decodable, Serialize)]
+pub struct RebateBalanceKey(pub secp256k1::XOnlyPublicKey);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct RebateBalanceKeyPrefix;
+
+impl_db_record!(
+    key = RebateBalanceKey,
+    value = Amount,
+    db_prefix = DbKeyPrefix::RebateBalance,
+);
+impl_db_lookup!(
+    key = RebateBalanceKey,
+    query_prefix = RebateBalanceKeyPrefix
+);
+
+/// Audit totals at the time the mint entered safe mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Encodable, Decodable, Serialize, Deserialize)]
+pub struct LiabilityViolation {
+    /// Total value of the notes issued so far
+    pub issued: Amount,
+    /// Total value of the notes redeemed so far, exceeding `issued`
+    pub redeemed: Amount,
+}
+
+impl LiabilityViolation {
+    /// Value of the notes redeemed beyond the ones issued
+    pub fn shortfall(&self) -> Amount {
+        self.redeemed.saturating_sub(self.issued)
+    }
+}
+
 /// User's backup, received at certain time, containing encrypted payload
 #[derive(Debug, Clone, PartialEq, Eq, Encodable, Decodable, Serialize, Deserialize)]
 pub struct ECashUserBackupSnapshot {
@@ -0,0 +1,31 @@
+use fedimint_core::core::ModuleKind;
+use fedimint_core::module::interconnect::{DynModuleInterconnect, InterconnectMethod};
+use fedimint_core::module::ApiError;
+use fedimint_core::{apply, async_trait_maybe_send, Amount};
+
+use crate::KIND;
+
+/// Total value of the ecash issued by the mint that wasn't redeemed yet
+pub struct NoteSupplyMethod;
+
+impl InterconnectMethod for NoteSupplyMethod {
+    const KIND: ModuleKind = KIND;
+    const NAME: &'static str = "note_supply";
+    const VERSION: u32 = 0;
+    type Request = ();
+    type Response = Amount;
+}
+
+/// Lets other server modules learn about the ecash in circulation
+#[apply(async_trait_maybe_send!)]
+pub trait MintInterconnect {
+    /// Total value of the ecash in circulation
+    async fn note_supply(&self) -> Result<Amount, ApiError>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl MintInterconnect for DynModuleInterconnect {
+    async fn note_supply(&self) -> Result<Amount, ApiError> {
+        self.call::<NoteSupplyMethod>(()).await
+    }
+}
@@ -1,33 +1,56 @@
 use std::hash::Hash;
 
 pub use common::{BackupRequest, SignedBackupRequest};
+use db::LiabilityViolation;
 use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
 use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::error::{code, ModuleErrorCode};
 use fedimint_core::module::{CommonModuleGen, ModuleCommon, ModuleConsensusVersion};
 use fedimint_core::tiered::InvalidAmountTierError;
 use fedimint_core::{plugin_types_trait_impl_common, Amount, OutPoint, PeerId, TieredMulti};
 use impl_tools::autoimpl;
+use rebate::RebateClaim;
 use serde::{Deserialize, Serialize};
+use spend_condition::ConditionWitness;
 use thiserror::Error;
 use tracing::error;
+use velocity::VelocityPolicy;
 
 pub mod config;
 
 pub mod common;
 pub mod db;
+pub mod interconnect;
+pub mod rebate;
+pub mod spend_condition;
+pub mod velocity;
 
 pub const KIND: ModuleKind = ModuleKind::from_static_str("mint");
-const CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion(0);
+/// Version 1 changed the encoding of [`MintInput`] and [`MintConsensusItem`],
+/// guardians and epochs of version 0 can't be decoded anymore
+pub const CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion(1);
 
 /// By default, the maximum notes per denomination when change-making for users
 pub const DEFAULT_MAX_NOTES_PER_DENOMINATION: u16 = 3;
 
 /// Data structures taking into account different amount tiers
 
-/// A consenus item from one of the federation members contributing partials
-/// signatures to blind nonces submitted in it
+/// A consensus item from one of the federation members
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
-pub struct MintConsensusItem {
+pub enum MintConsensusItem {
+    PartialSignatures(MintSignatureItem),
+    /// Vote for the [`VelocityPolicy`] to enforce, it replaces the current one
+    /// once a threshold of guardians voted for it
+    VelocityPolicy(VelocityPolicy),
+    /// Vote to leave the safe mode entered because of the
+    /// [`LiabilityViolation`], the mint resumes once a threshold of guardians
+    /// voted for the same violation
+    ExitSafeMode(LiabilityViolation),
+}
+
+/// Partial signatures of a federation member for the blind nonces of an output
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct MintSignatureItem {
     /// Reference to a Federation Transaction containing an [`MintOutput`] with
     /// `BlindNonce`s the signatures` are for
     pub out_point: OutPoint,
@@ -43,7 +66,7 @@ pub struct MintOutputSignatureShare(
 );
 
 /// Result of Federation members confirming [`MintOutput`] by contributing
-/// partial signatures via [`MintConsensusItem`]
+/// partial signatures via [`MintSignatureItem`]
 ///
 /// A set of full blinded signatures.
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
@@ -115,7 +138,20 @@ impl CommonModuleGen for MintCommonGen {
 #[derive(
     Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable, Default,
 )]
-pub struct MintInput(pub TieredMulti<Note>);
+pub struct MintInput(
+    pub TieredMulti<Note>,
+    /// Witnesses for the notes that are locked to a spend condition
+    pub Vec<ConditionWitness>,
+    /// Fee rebates claimed in addition to the notes, see [`rebate`]
+    pub Vec<RebateClaim>,
+);
+
+impl MintInput {
+    /// Value of the notes and the claimed rebates
+    pub fn input_amount(&self) -> Amount {
+        self.0.total_amount() + self.2.iter().map(|claim| claim.amount).sum::<Amount>()
+    }
+}
 
 impl std::fmt::Display for MintInput {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -154,12 +190,25 @@ impl std::fmt::Display for MintOutputOutcome {
 
 impl std::fmt::Display for MintConsensusItem {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "Mint Blind Signature Shares worth {} for {}",
-            self.signatures.0.total_amount(),
-            self.out_point
-        )
+        match self {
+            MintConsensusItem::PartialSignatures(item) => write!(
+                f,
+                "Mint Blind Signature Shares worth {} for {}",
+                item.signatures.0.total_amount(),
+                item.out_point
+            ),
+            MintConsensusItem::VelocityPolicy(policy) => write!(
+                f,
+                "Mint Velocity Policy vote with {} tiers and {} accounts",
+                policy.tiers.len(),
+                policy.accounts.len()
+            ),
+            MintConsensusItem::ExitSafeMode(violation) => write!(
+                f,
+                "Mint vote to exit safe mode after redeeming {} of {} issued",
+                violation.redeemed, violation.issued
+            ),
+        }
     }
 }
 
@@ -171,6 +220,16 @@ impl Note {
         tbs::verify(self.0.to_message(), self.1, pk)
     }
 
+    /// Verify the validity of all `notes` under a mint key `pk` at once, see
+    /// [`tbs::verify_batch`]
+    pub fn verify_batch(notes: &[Note], pk: tbs::AggregatePublicKey) -> bool {
+        let items = notes
+            .iter()
+            .map(|note| (note.0.to_message(), note.1))
+            .collect::<Vec<_>>();
+        tbs::verify_batch(&items, pk)
+    }
+
     /// Access the nonce as the public key to the spend key
     pub fn spend_key(&self) -> &secp256k1_zkp::XOnlyPublicKey {
         &self.0 .0
@@ -254,6 +313,54 @@ pub enum MintError {
     InvalidSignature,
     #[error("Exceeded maximum notes per denomination {0}, found {1}")]
     ExceededMaxNotes(u16, usize),
+    #[error("A note isn't locked to the spend condition or it has no such branch")]
+    InvalidSpendCondition,
+    #[error("The preimage doesn't match the hash lock of the spend condition")]
+    InvalidPreimage,
+    #[error("The note can only be spent from block height {0}")]
+    Timelocked(u32),
+    #[error("Account {0} exceeded its daily limit of {1}")]
+    VelocityLimitExceeded(secp256k1::XOnlyPublicKey, Amount),
+    #[error("The block height needed to enforce velocity limits is unknown")]
+    VelocityDayUnknown,
+    #[error("The mint is in safe mode after redeeming more e-cash than it issued")]
+    SafeMode,
+    #[error("Fee rebates are not enabled in the federation config")]
+    RebatesDisabled,
+    #[error("Account {0} has a rebate balance of only {1}")]
+    InsufficientRebateBalance(secp256k1::XOnlyPublicKey, Amount),
+}
+
+impl ModuleErrorCode for MintError {
+    fn code(&self) -> i32 {
+        match self {
+            MintError::InvalidNote
+            | MintError::TooFewNotes(_, _)
+            | MintError::InvalidAmountTier(_)
+            | MintError::InvalidSignature
+            | MintError::InvalidSpendCondition
+            | MintError::InvalidPreimage
+            | MintError::Timelocked(_)
+            | MintError::RebatesDisabled
+            | MintError::InsufficientRebateBalance(_, _) => code::BAD_REQUEST,
+            MintError::SpentCoin => code::CONFLICT,
+            MintError::ExceededMaxNotes(_, _) | MintError::VelocityLimitExceeded(_, _) => {
+                code::LIMIT_EXCEEDED
+            }
+            MintError::VelocityDayUnknown | MintError::SafeMode => code::UNAVAILABLE,
+        }
+    }
+
+    fn retriable(&self) -> bool {
+        match self {
+            // Timelocked notes become spendable at a later block height and the
+            // daily limit resets
+            MintError::Timelocked(_) | MintError::VelocityLimitExceeded(_, _) => true,
+            // Leaving safe mode requires the guardians to intervene
+            MintError::SafeMode => false,
+            _ => code::retriable_by_default(self.code()),
+        }
+    }
 }
 
 impl From<InvalidAmountTierError> for MintError {
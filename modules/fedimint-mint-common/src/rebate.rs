@@ -0,0 +1,134 @@
+//! Returning part of the collected fees to the accounts that paid them
+//!
+//! Fee rebates are only paid if enabled in the federation config. Like for
+//! [velocity limits](crate::velocity), an account is identified by a key that
+//! notes are locked to (see [`crate::spend_condition`]). The spend fees of
+//! notes that require an account's signature count as fees paid by the
+//! account.
+//!
+//! Fees are accumulated over periods of the consensus block height, so all
+//! guardians agree on when a period ends. At the end of a period a share of
+//! all fees the mint collected in it is distributed among the accounts, in
+//! proportion to the fees they paid but never more than they paid. Rebates
+//! are credited to the balance of the account, which the holder of its key
+//! claims with a [`RebateClaim`] in a [`crate::MintInput`].
+use std::collections::BTreeMap;
+
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::Amount;
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+
+/// Rebate shares are given in basis points of the collected fees
+pub const REBATE_SHARE_SCALE: u16 = 10_000;
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct FeeRebateConsensus {
+    /// Share of the fees collected in a period that is returned to the
+    /// accounts, in basis points
+    pub share_bps: u16,
+    /// Length of a rebate period in blocks
+    pub period_blocks: u32,
+}
+
+impl FeeRebateConsensus {
+    /// Period of the block height, rebates are distributed when it changes
+    pub fn period(&self, block_height: u32) -> u32 {
+        block_height / self.period_blocks.max(1)
+    }
+
+    /// Rebates of the accounts that paid `account_fees` in a period in which
+    /// the mint collected `collected` fees in total
+    pub fn rebates(
+        &self,
+        collected: Amount,
+        account_fees: &BTreeMap<XOnlyPublicKey, Amount>,
+    ) -> BTreeMap<XOnlyPublicKey, Amount> {
+        let pool = u128::from(collected.msats) * u128::from(self.share_bps.min(REBATE_SHARE_SCALE))
+            / u128::from(REBATE_SHARE_SCALE);
+        let total_fees = account_fees
+            .values()
+            .map(|fees| u128::from(fees.msats))
+            .sum::<u128>();
+        if pool == 0 || total_fees == 0 {
+            return BTreeMap::new();
+        }
+
+        account_fees
+            .iter()
+            .filter_map(|(account, fees)| {
+                let share = pool * u128::from(fees.msats) / total_fees;
+                let rebate = fees.msats.min(share as u64);
+                (rebate != 0).then_some((*account, Amount::from_msats(rebate)))
+            })
+            .collect()
+    }
+}
+
+/// Claims `amount` of the rebate balance of `account`, the transaction has to
+/// be signed by the key of the account
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct RebateClaim {
+    pub account: XOnlyPublicKey,
+    pub amount: Amount,
+}
+
+/// Returned by the `fee_rebate` endpoint
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FeeRebateStatus {
+    /// Rebates that can be claimed
+    pub balance: Amount,
+    /// Fees paid in the current period, the rebate for them is credited when
+    /// the period ends
+    pub period_fees: Amount,
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::{KeyPair, SECP256K1};
+
+    use super::*;
+
+    fn account(seed: u8) -> XOnlyPublicKey {
+        KeyPair::from_seckey_slice(SECP256K1, &[seed; 32])
+            .unwrap()
+            .x_only_public_key()
+            .0
+    }
+
+    #[test]
+    fn rebates_are_proportional_to_fees_paid() {
+        let consensus = FeeRebateConsensus {
+            share_bps: 5_000,
+            period_blocks: 144,
+        };
+        let account_fees = BTreeMap::from([
+            (account(1), Amount::from_msats(300)),
+            (account(2), Amount::from_msats(100)),
+        ]);
+
+        assert_eq!(
+            consensus.rebates(Amount::from_msats(400), &account_fees),
+            BTreeMap::from([
+                (account(1), Amount::from_msats(150)),
+                (account(2), Amount::from_msats(50)),
+            ])
+        );
+        // Accounts never get back more than they paid
+        assert_eq!(
+            consensus.rebates(Amount::from_msats(10_000), &account_fees),
+            account_fees
+        );
+    }
+
+    #[test]
+    fn no_rebates_without_account_fees() {
+        let consensus = FeeRebateConsensus {
+            share_bps: REBATE_SHARE_SCALE,
+            period_blocks: 144,
+        };
+        assert!(consensus
+            .rebates(Amount::from_msats(1_000), &BTreeMap::new())
+            .is_empty());
+    }
+}
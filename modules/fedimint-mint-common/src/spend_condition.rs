@@ -0,0 +1,176 @@
+//! Conditions e-cash notes can be locked to when they are issued
+//!
+//! A locked note is a regular note whose [`Nonce`] commits to a
+//! [`SpendCondition`]. The nonce is derived from a point nobody knows the
+//! discrete logarithm of, so the note can't be spent like a regular note and
+//! has to be spent with a [`ConditionWitness`] that reveals the condition and
+//! satisfies it.
+use bitcoin_hashes::{sha256, Hash};
+use fedimint_core::encoding::{Decodable, Encodable};
+use secp256k1::{Parity, PublicKey, Scalar, Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+
+use crate::{MintError, Nonce};
+
+/// X coordinate of the "nothing up my sleeve" point from BIP-341, the hash of
+/// the generator point
+const NUMS_POINT_X: [u8; 32] = [
+    0x50, 0x92, 0x9b, 0x74, 0xc1, 0xa0, 0x49, 0x54, 0xb7, 0x8b, 0x4b, 0x60, 0x35, 0xe9, 0x7a, 0x5e,
+    0x07, 0x8a, 0x5a, 0x0f, 0x28, 0xec, 0x96, 0xd5, 0x47, 0xbf, 0xee, 0x9a, 0xce, 0x80, 0x3a, 0xc0,
+];
+
+/// Who can spend a locked note, satisfying any of the branches is enough
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct SpendCondition {
+    pub branches: Vec<SpendBranch>,
+}
+
+/// A way to spend a locked note, the holder of `key` has to sign the
+/// transaction and satisfy all predicates that are set
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct SpendBranch {
+    pub key: XOnlyPublicKey,
+    /// The spender has to reveal the preimage of the hash
+    pub hash_lock: Option<sha256::Hash>,
+    /// The note can only be spent once the federation reached the block height
+    pub min_block_height: Option<u32>,
+}
+
+impl SpendCondition {
+    /// Only the holder of `key` can spend the note
+    pub fn key(key: XOnlyPublicKey) -> Self {
+        SpendCondition {
+            branches: vec![SpendBranch {
+                key,
+                hash_lock: None,
+                min_block_height: None,
+            }],
+        }
+    }
+
+    /// The recipient can spend the note any time and the sender can take it
+    /// back once the federation reached `refund_height`
+    pub fn refundable(
+        recipient: XOnlyPublicKey,
+        sender: XOnlyPublicKey,
+        refund_height: u32,
+    ) -> Self {
+        SpendCondition {
+            branches: vec![
+                SpendBranch {
+                    key: recipient,
+                    hash_lock: None,
+                    min_block_height: None,
+                },
+                SpendBranch {
+                    key: sender,
+                    hash_lock: None,
+                    min_block_height: Some(refund_height),
+                },
+            ],
+        }
+    }
+
+    /// Nonce of a note locked to this condition, the `salt` keeps the nonces of
+    /// notes locked to the same condition apart
+    pub fn nonce(&self, salt: &[u8; 32]) -> Nonce {
+        let mut engine = sha256::Hash::engine();
+        self.consensus_encode(&mut engine)
+            .expect("Writing to a hash engine can't fail");
+        salt.consensus_encode(&mut engine)
+            .expect("Writing to a hash engine can't fail");
+        let tweak = Scalar::from_be_bytes(sha256::Hash::from_engine(engine).into_inner())
+            .expect("Hash is smaller than the curve order with overwhelming probability");
+
+        let nums_point = XOnlyPublicKey::from_slice(&NUMS_POINT_X)
+            .expect("Valid point")
+            .public_key(Parity::Even);
+        let nonce: PublicKey = nums_point
+            .add_exp_tweak(&Secp256k1::verification_only(), &tweak)
+            .expect("Tweak is a hash, so the result is not the point at infinity");
+
+        Nonce(nonce.x_only_public_key().0)
+    }
+}
+
+/// Reveals the condition a note in a [`crate::MintInput`] is locked to and how
+/// it is satisfied
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct ConditionWitness {
+    pub nonce: Nonce,
+    pub condition: SpendCondition,
+    pub salt: [u8; 32],
+    /// Index of the branch of the condition that is satisfied
+    pub branch: u32,
+    pub preimage: Option<[u8; 32]>,
+}
+
+impl ConditionWitness {
+    /// Checks that the note is locked to the condition and the witness
+    /// satisfies the branch, except for its block height which only the
+    /// federation knows
+    pub fn verify(&self) -> Result<&SpendBranch, MintError> {
+        if self.condition.nonce(&self.salt) != self.nonce {
+            return Err(MintError::InvalidSpendCondition);
+        }
+
+        let branch = self
+            .condition
+            .branches
+            .get(self.branch as usize)
+            .ok_or(MintError::InvalidSpendCondition)?;
+
+        if let Some(hash) = branch.hash_lock {
+            match self.preimage {
+                Some(preimage) if sha256::Hash::hash(&preimage) == hash => {}
+                _ => return Err(MintError::InvalidPreimage),
+            }
+        }
+
+        Ok(branch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::KeyPair;
+
+    use super::*;
+
+    #[test]
+    fn witness_has_to_satisfy_branch() {
+        let key = KeyPair::new(&Secp256k1::new(), &mut rand::thread_rng())
+            .x_only_public_key()
+            .0;
+        let preimage = [7; 32];
+        let condition = SpendCondition {
+            branches: vec![SpendBranch {
+                key,
+                hash_lock: Some(sha256::Hash::hash(&preimage)),
+                min_block_height: None,
+            }],
+        };
+        let salt = [1; 32];
+        assert_ne!(condition.nonce(&salt), condition.nonce(&[2; 32]));
+
+        let mut witness = ConditionWitness {
+            nonce: condition.nonce(&salt),
+            condition: condition.clone(),
+            salt,
+            branch: 0,
+            preimage: Some(preimage),
+        };
+        assert_eq!(witness.verify().unwrap().key, key);
+
+        witness.preimage = Some([8; 32]);
+        assert_eq!(witness.verify(), Err(MintError::InvalidPreimage));
+
+        witness.preimage = Some(preimage);
+        witness.branch = 1;
+        assert_eq!(witness.verify(), Err(MintError::InvalidSpendCondition));
+
+        witness.branch = 0;
+        witness.condition = SpendCondition::key(key);
+        assert_eq!(witness.verify(), Err(MintError::InvalidSpendCondition));
+    }
+}
@@ -0,0 +1,88 @@
+//! Daily limits on how much e-cash registered accounts can spend
+//!
+//! Velocity limits are a compliance feature some federations need, so they are
+//! only enforced if enabled in the federation config. An account is identified
+//! by a key that notes are locked to (see [`crate::spend_condition`]). Spending
+//! notes that require an account's signature counts towards the account's
+//! limit for the current day.
+//!
+//! The guardians agree on the [`VelocityPolicy`] by voting for it with the
+//! admin API, a new policy applies once a threshold of them voted for it.
+use std::collections::BTreeMap;
+
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::Amount;
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+
+/// Days are counted in blocks of the consensus block height, so all guardians
+/// agree on when a day ends
+pub const BLOCKS_PER_DAY: u32 = 144;
+
+/// Limit tiers and the accounts registered with them
+#[derive(
+    Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable,
+)]
+pub struct VelocityPolicy {
+    /// Amount an account in the tier can spend per day by name of the tier
+    pub tiers: BTreeMap<String, Amount>,
+    /// Name of the tier of each registered account
+    pub accounts: BTreeMap<XOnlyPublicKey, String>,
+}
+
+impl VelocityPolicy {
+    /// Checks that all accounts are registered with an existing tier
+    pub fn validate(&self) -> Result<(), String> {
+        match self
+            .accounts
+            .iter()
+            .find(|(_, tier)| !self.tiers.contains_key(*tier))
+        {
+            Some((account, tier)) => Err(format!("Account {account} has unknown tier {tier:?}")),
+            None => Ok(()),
+        }
+    }
+
+    /// Amount `account` can spend per day, `None` if it isn't registered
+    pub fn daily_limit(&self, account: &XOnlyPublicKey) -> Option<Amount> {
+        self.accounts
+            .get(account)
+            .and_then(|tier| self.tiers.get(tier))
+            .copied()
+    }
+}
+
+/// Day of the block height, limits are reset when it changes
+pub fn velocity_day(block_height: u32) -> u32 {
+    block_height / BLOCKS_PER_DAY
+}
+
+#[cfg(test)]
+mod tests {
+    use secp256k1::{KeyPair, Secp256k1};
+
+    use super::*;
+
+    #[test]
+    fn accounts_need_known_tier() {
+        let account = KeyPair::new(&Secp256k1::new(), &mut rand::thread_rng())
+            .x_only_public_key()
+            .0;
+        let mut policy = VelocityPolicy {
+            tiers: BTreeMap::from([("basic".to_string(), Amount::from_sats(10_000))]),
+            accounts: BTreeMap::from([(account, "basic".to_string())]),
+        };
+        assert_eq!(policy.validate(), Ok(()));
+        assert_eq!(
+            policy.daily_limit(&account),
+            Some(Amount::from_sats(10_000))
+        );
+
+        policy.accounts.insert(account, "premium".to_string());
+        assert!(policy.validate().is_err());
+        assert_eq!(policy.daily_limit(&account), None);
+
+        assert_eq!(velocity_day(BLOCKS_PER_DAY - 1), 0);
+        assert_eq!(velocity_day(BLOCKS_PER_DAY), 1);
+    }
+}
@@ -1,21 +1,27 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::iter::FromIterator;
+use std::sync::Arc;
 
 use anyhow::bail;
 use fedimint_core::config::{
     ClientModuleConfig, ConfigGenModuleParams, DkgResult, ServerModuleConfig,
     ServerModuleConsensusConfig, TypedServerModuleConfig, TypedServerModuleConsensusConfig,
 };
-use fedimint_core::db::{Database, DatabaseVersion, ModuleDatabaseTransaction};
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::db::{
+    Database, DatabaseTransaction, DatabaseVersion, MigrationMap, ModuleDatabaseTransaction,
+};
 use fedimint_core::module::audit::Audit;
+use fedimint_core::module::interconnect::{
+    interconnect_handler, DynModuleInterconnect, InterconnectHandler,
+};
 use fedimint_core::module::{
     api_endpoint, ApiEndpoint, ApiError, ConsensusProposal, CoreConsensusVersion,
-    ExtendsCommonModuleGen, InputMeta, IntoModuleError, ModuleConsensusVersion, ModuleError,
+    ExtendsCommonModuleGen, InputMeta, IntoCodedModuleError, ModuleConsensusVersion, ModuleError,
     PeerHandle, ServerModuleGen, SupportedModuleApiVersions, TransactionItemAmount,
 };
 use fedimint_core::server::DynServerModule;
 use fedimint_core::task::{MaybeSend, TaskGroup};
-use fedimint_core::tiered::InvalidAmountTierError;
 use fedimint_core::{
     apply, async_trait_maybe_send, push_db_key_items, push_db_pair_items, Amount, NumPeers,
     OutPoint, PeerId, ServerModule, Tiered, TieredMulti, TieredMultiZip,
@@ -26,30 +32,42 @@ use fedimint_mint_common::config::{
     MintConfigPrivate, MintGenParams,
 };
 use fedimint_mint_common::db::{
-    DbKeyPrefix, ECashUserBackupSnapshot, EcashBackupKey, EcashBackupKeyPrefix, MintAuditItemKey,
-    MintAuditItemKeyPrefix, NonceKey, NonceKeyPrefix, OutputOutcomeKey, OutputOutcomeKeyPrefix,
-    ProposedPartialSignatureKey, ProposedPartialSignaturesKeyPrefix, ReceivedPartialSignatureKey,
-    ReceivedPartialSignatureKeyOutputPrefix, ReceivedPartialSignaturesKeyPrefix,
+    AccountFeesKey, AccountFeesKeyPrefix, AcknowledgedLiabilityViolationKey,
+    AcknowledgedLiabilityViolationKeyPrefix, CollectedFeesKey, CollectedFeesKeyPrefix, DbKeyPrefix,
+    ECashUserBackupSnapshot, EcashBackupKey, EcashBackupKeyPrefix, LiabilityViolation,
+    MintAuditItemKey, MintAuditItemKeyPrefix, NonceKey, NonceKeyPrefix, OutputOutcomeKey,
+    OutputOutcomeKeyPrefix, ProposedPartialSignatureKey, ProposedPartialSignaturesKeyPrefix,
+    ProposedSafeModeExitKey, ProposedSafeModeExitKeyPrefix, ProposedVelocityPolicyKey,
+    ProposedVelocityPolicyKeyPrefix, RebateBalanceKey, RebateBalanceKeyPrefix, RebatePeriodKey,
+    RebatePeriodKeyPrefix, ReceivedPartialSignatureKey, ReceivedPartialSignatureKeyOutputPrefix,
+    ReceivedPartialSignaturesKeyPrefix, SafeModeExitVoteKey, SafeModeExitVoteKeyPrefix,
+    SafeModeKey, SafeModeKeyPrefix, VelocityPolicyKey, VelocityPolicyKeyPrefix,
+    VelocityPolicyVoteKey, VelocityPolicyVoteKeyPrefix, VelocityUsageKey, VelocityUsageKeyPrefix,
 };
+use fedimint_mint_common::interconnect::NoteSupplyMethod;
+use fedimint_mint_common::rebate::FeeRebateStatus;
+use fedimint_mint_common::velocity::{velocity_day, VelocityPolicy};
 pub use fedimint_mint_common::{BackupRequest, SignedBackupRequest};
 use fedimint_mint_common::{
     BlindNonce, MintCommonGen, MintConsensusItem, MintError, MintInput, MintModuleTypes,
-    MintOutput, MintOutputBlindSignatures, MintOutputOutcome, MintOutputSignatureShare, Note,
-    DEFAULT_MAX_NOTES_PER_DENOMINATION,
+    MintOutput, MintOutputBlindSignatures, MintOutputOutcome, MintOutputSignatureShare,
+    MintSignatureItem, Nonce, Note, CONSENSUS_VERSION, DEFAULT_MAX_NOTES_PER_DENOMINATION,
 };
 use fedimint_server::config::distributedgen::{scalar, PeerHandleOps};
-use futures::StreamExt;
+use fedimint_server::signer::{guardian_signer, DynGuardianSigner, LocalSigner};
+use fedimint_wallet_common::interconnect::WalletInterconnect;
+use futures::{FutureExt, StreamExt};
 use itertools::Itertools;
 use rayon::iter::ParallelIterator;
 use rayon::prelude::ParallelBridge;
-use secp256k1_zkp::SECP256K1;
+use secp256k1_zkp::{XOnlyPublicKey, SECP256K1};
 use strum::IntoEnumIterator;
 use tbs::{
-    combine_valid_shares, dealer_keygen, sign_blinded_msg, verify_blind_share, Aggregatable,
-    AggregatePublicKey, PublicKeyShare, SecretKeyShare,
+    combine_valid_shares, dealer_keygen, verify_blind_share, Aggregatable, AggregatePublicKey,
+    PublicKeyShare,
 };
 use threshold_crypto::group::Curve;
-use tracing::{debug, info, warn};
+use tracing::{debug, error, info, warn};
 
 #[derive(Debug, Clone)]
 pub struct MintGen;
@@ -61,23 +79,55 @@ impl ExtendsCommonModuleGen for MintGen {
 #[apply(async_trait_maybe_send!)]
 impl ServerModuleGen for MintGen {
     type Params = MintGenParams;
-    const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+    const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(1);
 
     fn versions(&self, _core: CoreConsensusVersion) -> &[ModuleConsensusVersion] {
-        &[ModuleConsensusVersion(0)]
+        &[CONSENSUS_VERSION]
     }
 
     fn supported_api_versions(&self) -> SupportedModuleApiVersions {
         SupportedModuleApiVersions::from_raw(0, 0, &[(0, 0)])
     }
 
+    fn parse_params(&self, params: &ConfigGenModuleParams) -> anyhow::Result<MintGenParams> {
+        let params = params.to_typed::<MintGenParams>()?;
+        let mint_amounts = &params.consensus.mint_amounts;
+        if mint_amounts.is_empty() {
+            bail!("No note denominations configured");
+        }
+        if mint_amounts.contains(&Amount::ZERO) {
+            bail!("Note denominations can't be zero");
+        }
+        if !mint_amounts.iter().all_unique() {
+            bail!("Duplicate note denomination");
+        }
+        Ok(params)
+    }
+
     async fn init(
         &self,
         cfg: ServerModuleConfig,
+        module_instance_id: ModuleInstanceId,
+        our_id: PeerId,
         _db: Database,
         _task_group: &mut TaskGroup,
+        interconnect: DynModuleInterconnect,
     ) -> anyhow::Result<DynServerModule> {
-        Ok(Mint::new(cfg.to_typed()?).into())
+        let cfg: MintConfig = cfg.to_typed()?;
+        let signer = guardian_signer(
+            module_instance_id,
+            LocalSigner::default().with_tbs_keys(cfg.private.tbs_sks.clone()),
+        )?;
+        Ok(Mint::new(cfg, our_id)
+            .with_signer(signer)
+            .with_interconnect(interconnect)
+            .into())
+    }
+
+    fn get_database_migrations(&self) -> MigrationMap {
+        let mut migrations = MigrationMap::new();
+        migrations.insert(DatabaseVersion(0), move |dbtx| migrate_to_v1(dbtx).boxed());
+        migrations
     }
 
     fn trusted_dealer_gen(
@@ -119,14 +169,19 @@ impl ServerModuleGen for MintGen {
                             .collect(),
                         fee_consensus: FeeConsensus::default(),
                         max_notes_per_denomination: DEFAULT_MAX_NOTES_PER_DENOMINATION,
+                        velocity_limits: params.consensus.velocity_limits,
+                        fee_rebate: params.consensus.fee_rebate.clone(),
+                        retired_tiers: BTreeSet::new(),
                     },
                     private: MintConfigPrivate {
-                        tbs_sks: params
-                            .consensus
-                            .mint_amounts
-                            .iter()
-                            .map(|amount| (*amount, tbs_keys[amount].2[peer.to_usize()]))
-                            .collect(),
+                        tbs_sks: Some(
+                            params
+                                .consensus
+                                .mint_amounts
+                                .iter()
+                                .map(|amount| (*amount, tbs_keys[amount].2[peer.to_usize()]))
+                                .collect(),
+                        ),
                     },
                 };
                 (peer, config)
@@ -158,10 +213,12 @@ impl ServerModuleGen for MintGen {
         let server = MintConfig {
             local: MintConfigLocal,
             private: MintConfigPrivate {
-                tbs_sks: amounts_keys
-                    .iter()
-                    .map(|(amount, (_, sks))| (*amount, *sks))
-                    .collect(),
+                tbs_sks: Some(
+                    amounts_keys
+                        .iter()
+                        .map(|(amount, (_, sks))| (*amount, *sks))
+                        .collect(),
+                ),
             },
             consensus: MintConfigConsensus {
                 peer_tbs_pks: peers
@@ -181,6 +238,9 @@ impl ServerModuleGen for MintGen {
                     .collect(),
                 fee_consensus: Default::default(),
                 max_notes_per_denomination: DEFAULT_MAX_NOTES_PER_DENOMINATION,
+                velocity_limits: params.consensus.velocity_limits,
+                fee_rebate: params.consensus.fee_rebate.clone(),
+                retired_tiers: BTreeSet::new(),
             },
         };
 
@@ -189,12 +249,6 @@ impl ServerModuleGen for MintGen {
 
     fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
         let config = config.to_typed::<MintConfig>()?;
-        let sks: BTreeMap<Amount, PublicKeyShare> = config
-            .private
-            .tbs_sks
-            .iter()
-            .map(|(amount, sk)| (amount, sk.to_pub_key_share()))
-            .collect();
         let pks: BTreeMap<Amount, PublicKeyShare> = config
             .consensus
             .peer_tbs_pks
@@ -204,12 +258,33 @@ impl ServerModuleGen for MintGen {
             .iter()
             .map(|(k, v)| (*k, *v))
             .collect();
-        if sks != pks {
-            bail!("Mint private key doesn't match pubkey share");
+        if let Some(tbs_sks) = &config.private.tbs_sks {
+            let sks: BTreeMap<Amount, PublicKeyShare> = tbs_sks
+                .iter()
+                .map(|(amount, sk)| (amount, sk.to_pub_key_share()))
+                .collect();
+            if sks != pks {
+                bail!("Mint private key doesn't match pubkey share");
+            }
         }
-        if !sks.keys().contains(&Amount::from_msats(1)) {
+        if !pks.keys().contains(&Amount::from_msats(1)) {
             bail!("No msat 1 denomination");
         }
+        if let Some(amount) = config
+            .consensus
+            .retired_tiers
+            .iter()
+            .find(|amount| !pks.contains_key(amount))
+        {
+            bail!("Retired denomination {amount} has no keys");
+        }
+        if config
+            .consensus
+            .retired_tiers
+            .contains(&Amount::from_msats(1))
+        {
+            bail!("The msat 1 denomination can't be retired");
+        }
 
         Ok(())
     }
@@ -244,6 +319,7 @@ impl ServerModuleGen for MintGen {
                 fee_consensus: config.fee_consensus.clone(),
                 peer_tbs_pks: config.peer_tbs_pks.clone(),
                 max_notes_per_denomination: config.max_notes_per_denomination,
+                retired_tiers: config.retired_tiers.clone(),
             },
         )
         .expect("Serialization can't fail"))
@@ -313,6 +389,126 @@ impl ServerModuleGen for MintGen {
                         "User Ecash Backup"
                     );
                 }
+                DbKeyPrefix::VelocityPolicy => {
+                    push_db_pair_items!(
+                        dbtx,
+                        VelocityPolicyKeyPrefix,
+                        VelocityPolicyKey,
+                        VelocityPolicy,
+                        mint,
+                        "Velocity Policy"
+                    );
+                }
+                DbKeyPrefix::VelocityPolicyVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        VelocityPolicyVoteKeyPrefix,
+                        VelocityPolicyVoteKey,
+                        VelocityPolicy,
+                        mint,
+                        "Velocity Policy Votes"
+                    );
+                }
+                DbKeyPrefix::ProposedVelocityPolicy => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ProposedVelocityPolicyKeyPrefix,
+                        ProposedVelocityPolicyKey,
+                        VelocityPolicy,
+                        mint,
+                        "Proposed Velocity Policy"
+                    );
+                }
+                DbKeyPrefix::VelocityUsage => {
+                    push_db_pair_items!(
+                        dbtx,
+                        VelocityUsageKeyPrefix,
+                        VelocityUsageKey,
+                        Amount,
+                        mint,
+                        "Velocity Usage"
+                    );
+                }
+                DbKeyPrefix::SafeMode => {
+                    push_db_pair_items!(
+                        dbtx,
+                        SafeModeKeyPrefix,
+                        SafeModeKey,
+                        LiabilityViolation,
+                        mint,
+                        "Safe Mode"
+                    );
+                }
+                DbKeyPrefix::SafeModeExitVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        SafeModeExitVoteKeyPrefix,
+                        SafeModeExitVoteKey,
+                        LiabilityViolation,
+                        mint,
+                        "Safe Mode Exit Votes"
+                    );
+                }
+                DbKeyPrefix::ProposedSafeModeExit => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ProposedSafeModeExitKeyPrefix,
+                        ProposedSafeModeExitKey,
+                        LiabilityViolation,
+                        mint,
+                        "Proposed Safe Mode Exit"
+                    );
+                }
+                DbKeyPrefix::AcknowledgedLiabilityViolation => {
+                    push_db_pair_items!(
+                        dbtx,
+                        AcknowledgedLiabilityViolationKeyPrefix,
+                        AcknowledgedLiabilityViolationKey,
+                        LiabilityViolation,
+                        mint,
+                        "Acknowledged Liability Violation"
+                    );
+                }
+                DbKeyPrefix::RebatePeriod => {
+                    push_db_pair_items!(
+                        dbtx,
+                        RebatePeriodKeyPrefix,
+                        RebatePeriodKey,
+                        u32,
+                        mint,
+                        "Rebate Period"
+                    );
+                }
+                DbKeyPrefix::CollectedFees => {
+                    push_db_pair_items!(
+                        dbtx,
+                        CollectedFeesKeyPrefix,
+                        CollectedFeesKey,
+                        Amount,
+                        mint,
+                        "Collected Fees"
+                    );
+                }
+                DbKeyPrefix::AccountFees => {
+                    push_db_pair_items!(
+                        dbtx,
+                        AccountFeesKeyPrefix,
+                        AccountFeesKey,
+                        Amount,
+                        mint,
+                        "Account Fees"
+                    );
+                }
+                DbKeyPrefix::RebateBalance => {
+                    push_db_pair_items!(
+                        dbtx,
+                        RebateBalanceKeyPrefix,
+                        RebateBalanceKey,
+                        Amount,
+                        mint,
+                        "Rebate Balances"
+                    );
+                }
             }
         }
 
@@ -323,9 +519,14 @@ impl ServerModuleGen for MintGen {
 #[derive(Debug)]
 pub struct Mint {
     cfg: MintConfig,
-    sec_key: Tiered<SecretKeyShare>,
+    /// Signs issued notes with our key shares
+    signer: DynGuardianSigner,
     pub_key_shares: BTreeMap<PeerId, Tiered<PublicKeyShare>>,
     pub_key: HashMap<Amount, AggregatePublicKey>,
+    our_id: PeerId,
+    /// Needed to learn the block height for timelocked spend conditions and
+    /// velocity limits
+    interconnect: Option<DynModuleInterconnect>,
 }
 #[apply(async_trait_maybe_send!)]
 impl ServerModule for Mint {
@@ -343,16 +544,27 @@ impl ServerModule for Mint {
         &self,
         dbtx: &mut ModuleDatabaseTransaction<'_>,
     ) -> ConsensusProposal<MintConsensusItem> {
-        ConsensusProposal::new_auto_trigger(
-            dbtx.find_by_prefix(&ProposedPartialSignaturesKeyPrefix)
-                .await
-                .map(|(key, signatures)| MintConsensusItem {
+        let mut items = dbtx
+            .find_by_prefix(&ProposedPartialSignaturesKeyPrefix)
+            .await
+            .map(|(key, signatures)| {
+                MintConsensusItem::PartialSignatures(MintSignatureItem {
                     out_point: key.0,
                     signatures,
                 })
-                .collect::<Vec<MintConsensusItem>>()
-                .await,
-        )
+            })
+            .collect::<Vec<MintConsensusItem>>()
+            .await;
+
+        if let Some(policy) = dbtx.get_value(&ProposedVelocityPolicyKey).await {
+            items.push(MintConsensusItem::VelocityPolicy(policy));
+        }
+
+        if let Some(violation) = dbtx.get_value(&ProposedSafeModeExitKey).await {
+            items.push(MintConsensusItem::ExitSafeMode(violation));
+        }
+
+        ConsensusProposal::new_auto_trigger(items)
     }
 
     async fn begin_consensus_epoch<'a, 'b>(
@@ -362,8 +574,22 @@ impl ServerModule for Mint {
         _consensus_peers: &BTreeSet<PeerId>,
     ) -> Vec<PeerId> {
         for (peer_id, consensus_item) in consensus_items {
-            let out_point = consensus_item.out_point;
-            let signatures = consensus_item.signatures;
+            let MintSignatureItem {
+                out_point,
+                signatures,
+            } = match consensus_item {
+                MintConsensusItem::PartialSignatures(item) => item,
+                MintConsensusItem::VelocityPolicy(policy) => {
+                    self.process_velocity_policy_vote(dbtx, peer_id, policy)
+                        .await;
+                    continue;
+                }
+                MintConsensusItem::ExitSafeMode(violation) => {
+                    self.process_safe_mode_exit_vote(dbtx, peer_id, violation)
+                        .await;
+                    continue;
+                }
+            };
 
             // check if we already obtained the blinded threshold signature
             if dbtx.get_value(&OutputOutcomeKey(out_point)).await.is_some() {
@@ -508,22 +734,41 @@ impl ServerModule for Mint {
         inputs: impl Iterator<Item = &'a MintInput> + MaybeSend,
     ) -> Self::VerificationCache {
         // We build a lookup table for checking the validity of all notes for certain
-        // amounts. This calculation can happen massively in parallel since
-        // verification is a pure function and thus has no side effects.
-        let iter = inputs.flat_map(|inputs| inputs.0.iter_items());
+        // amounts. Notes of the same amount are verified in batches, only if a batch
+        // is invalid we verify its notes one by one to find the invalid ones. This
+        // calculation can happen massively in parallel since verification is a pure
+        // function and thus has no side effects.
+        let notes_by_amount = inputs
+            .flat_map(|inputs| inputs.0.iter_items())
+            .map(|(amount, note)| (amount, *note))
+            .unique()
+            .into_group_map();
+
+        let batches = notes_by_amount.iter().flat_map(|(amount, notes)| {
+            notes
+                .chunks(NOTE_VERIFICATION_BATCH_SIZE)
+                .map(move |batch| (*amount, batch))
+        });
 
         #[cfg(not(target_family = "wasm"))]
-        let iter = iter.par_bridge();
+        let batches = batches.par_bridge();
 
-        let valid_notes = iter
-            .filter_map(|(amount, note)| {
-                let amount_key = self.pub_key.get(&amount)?;
-                if note.verify(*amount_key) {
-                    Some((*note, amount))
+        let valid_notes = batches
+            .map(|(amount, batch)| {
+                let Some(amount_key) = self.pub_key.get(&amount) else {
+                    return vec![];
+                };
+                if Note::verify_batch(batch, *amount_key) {
+                    batch.iter().map(|note| (*note, amount)).collect()
                 } else {
-                    None
+                    batch
+                        .iter()
+                        .filter(|note| note.verify(*amount_key))
+                        .map(|note| (*note, amount))
+                        .collect::<Vec<_>>()
                 }
             })
+            .flatten()
             .collect();
 
         VerifiedNotes { valid_notes }
@@ -535,6 +780,10 @@ impl ServerModule for Mint {
         verification_cache: &Self::VerificationCache,
         input: &'a MintInput,
     ) -> Result<InputMeta, ModuleError> {
+        if dbtx.get_value(&SafeModeKey).await.is_some() {
+            return Err(MintError::SafeMode).into_module_error();
+        }
+
         for (amount, note) in input.iter_items() {
             let note_valid = verification_cache
                 .valid_notes
@@ -543,23 +792,42 @@ impl ServerModule for Mint {
                 .unwrap_or(false); // If we didn't validate the note return false
 
             if !note_valid {
-                return Err(MintError::InvalidSignature).into_module_error_other();
+                return Err(MintError::InvalidSignature).into_module_error();
             }
 
             if dbtx.get_value(&NonceKey(note.0)).await.is_some() {
-                return Err(MintError::SpentCoin).into_module_error_other();
+                return Err(MintError::SpentCoin).into_module_error();
             }
         }
 
+        let locked_keys = self
+            .verify_spend_conditions(input)
+            .await
+            .into_module_error()?;
+        let mut pub_keys: Vec<XOnlyPublicKey> = input
+            .iter_items()
+            .map(|(_, note)| {
+                locked_keys
+                    .get(&note.0)
+                    .copied()
+                    .unwrap_or(*note.spend_key())
+            })
+            .collect();
+
+        self.velocity_spends(dbtx, input, &pub_keys)
+            .await
+            .into_module_error()?;
+
+        // The accounts have to sign for their claims, after the keys of the notes
+        let claims = self.rebate_claims(dbtx, input).await.into_module_error()?;
+        pub_keys.extend(claims.keys());
+
         Ok(InputMeta {
             amount: TransactionItemAmount {
-                amount: input.total_amount(),
+                amount: input.input_amount(),
                 fee: self.cfg.consensus.fee_consensus.note_spend_abs * (input.count_items() as u64),
             },
-            pub_keys: input
-                .iter_items()
-                .map(|(_, note)| *note.spend_key())
-                .collect(),
+            pub_keys,
         })
     }
 
@@ -571,11 +839,48 @@ impl ServerModule for Mint {
     ) -> Result<InputMeta, ModuleError> {
         let meta = self.validate_input(dbtx, cache, input).await?;
 
+        if let Some((day, spends)) = self
+            .velocity_spends(dbtx, input, &meta.pub_keys)
+            .await
+            .into_module_error()?
+        {
+            for (account, amount) in spends {
+                let key = VelocityUsageKey { account, day };
+                let spent = dbtx.get_value(&key).await.unwrap_or(Amount::ZERO);
+                dbtx.insert_entry(&key, &(spent + amount)).await;
+            }
+        }
+
+        for (account, amount) in self.rebate_claims(dbtx, input).await.into_module_error()? {
+            let key = RebateBalanceKey(account);
+            let balance = dbtx.get_value(&key).await.unwrap_or(Amount::ZERO);
+            if balance == amount {
+                dbtx.remove_entry(&key).await;
+            } else {
+                dbtx.insert_entry(&key, &(balance - amount)).await;
+            }
+        }
+
+        // Only the spend fees of locked notes can be attributed to an account
+        let locked_nonces = input
+            .1
+            .iter()
+            .map(|witness| witness.nonce)
+            .collect::<BTreeSet<_>>();
+        let mut account_fees = BTreeMap::<XOnlyPublicKey, Amount>::new();
+        for ((_, note), key) in input.iter_items().zip(&meta.pub_keys) {
+            if locked_nonces.contains(&note.0) {
+                *account_fees.entry(*key).or_insert(Amount::ZERO) +=
+                    self.cfg.consensus.fee_consensus.note_spend_abs;
+            }
+        }
+        self.record_fees(dbtx, meta.amount.fee, account_fees).await;
+
         for (amount, note) in input.iter_items() {
             let key = NonceKey(note.0);
 
             if dbtx.insert_entry(&key, &()).await.is_some() {
-                return Err(MintError::SpentCoin).into_module_error_other();
+                return Err(MintError::SpentCoin).into_module_error();
             }
 
             dbtx.insert_new_entry(&MintAuditItemKey::Redemption(key), &amount)
@@ -587,10 +892,14 @@ impl ServerModule for Mint {
 
     async fn validate_output(
         &self,
-        _dbtx: &mut ModuleDatabaseTransaction<'_>,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
         output: &MintOutput,
     ) -> Result<TransactionItemAmount, ModuleError> {
-        let max_tier = self.cfg.private.tbs_sks.max_tier();
+        if dbtx.get_value(&SafeModeKey).await.is_some() {
+            return Err(MintError::SafeMode).into_module_error();
+        }
+
+        let max_tier = self.pub_key_shares[&self.our_id].max_tier();
         if output.longest_tier_except(max_tier)
             > self.cfg.consensus.max_notes_per_denomination.into()
         {
@@ -598,17 +907,19 @@ impl ServerModule for Mint {
                 self.cfg.consensus.max_notes_per_denomination,
                 output.longest_tier_except(max_tier),
             ))
-            .into_module_error_other();
+            .into_module_error();
         }
 
         if let Some(amount) = output.iter_items().find_map(|(amount, _)| {
-            if self.pub_key.get(&amount).is_none() {
+            if self.pub_key.get(&amount).is_none()
+                || self.cfg.consensus.retired_tiers.contains(&amount)
+            {
                 Some(amount)
             } else {
                 None
             }
         }) {
-            Err(MintError::InvalidAmountTier(amount)).into_module_error_other()
+            Err(MintError::InvalidAmountTier(amount)).into_module_error()
         } else {
             Ok(TransactionItemAmount {
                 amount: output.total_amount(),
@@ -625,12 +936,14 @@ impl ServerModule for Mint {
         out_point: OutPoint,
     ) -> Result<TransactionItemAmount, ModuleError> {
         let amount = self.validate_output(dbtx, output).await?;
+        self.record_fees(dbtx, amount.fee, BTreeMap::new()).await;
 
         // TODO: move actual signing to worker thread
         // TODO: get rid of clone
         let partial_sig = self
             .blind_sign(output.clone().0)
-            .into_module_error_other()?;
+            .await
+            .into_module_error()?;
 
         dbtx.insert_new_entry(&ProposedPartialSignatureKey(out_point), &partial_sig)
             .await;
@@ -646,8 +959,10 @@ impl ServerModule for Mint {
     async fn end_consensus_epoch<'a, 'b>(
         &'a self,
         _consensus_peers: &BTreeSet<PeerId>,
-        _dbtx: &mut ModuleDatabaseTransaction<'b>,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
     ) -> Vec<PeerId> {
+        self.check_liabilities(dbtx).await;
+        self.distribute_rebates(dbtx).await;
         vec![]
     }
 
@@ -687,6 +1002,10 @@ impl ServerModule for Mint {
                 MintAuditItemKey::RedemptionTotal => v.msats as i64,
             })
             .await;
+        // Credited rebates are owed to the accounts
+        audit
+            .add_items(dbtx, &RebateBalanceKeyPrefix, |_, v| -(v.msats as i64))
+            .await;
     }
 
     fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
@@ -706,11 +1025,171 @@ impl ServerModule for Mint {
                         .handle_recover_request(&mut context.dbtx(), id).await)
                 }
             },
+            api_endpoint! {
+                "safe_mode",
+                async |_module: &Mint, context, _params: ()| -> Option<LiabilityViolation> {
+                    Ok(context.dbtx().get_value(&SafeModeKey).await)
+                }
+            },
+            api_endpoint! {
+                "fee_rebate",
+                async |_module: &Mint, context, account: XOnlyPublicKey| -> FeeRebateStatus {
+                    let mut dbtx = context.dbtx();
+                    Ok(FeeRebateStatus {
+                        balance: dbtx
+                            .get_value(&RebateBalanceKey(account))
+                            .await
+                            .unwrap_or(Amount::ZERO),
+                        period_fees: dbtx
+                            .get_value(&AccountFeesKey(account))
+                            .await
+                            .unwrap_or(Amount::ZERO),
+                    })
+                }
+            },
+            api_endpoint! {
+                "velocity_policy",
+                async |_module: &Mint, context, _params: ()| -> VelocityPolicy {
+                    Ok(context
+                        .dbtx()
+                        .get_value(&VelocityPolicyKey)
+                        .await
+                        .unwrap_or_default())
+                }
+            },
+            api_endpoint! {
+                "update_velocity_policy",
+                async |module: &Mint, context, policy: VelocityPolicy| -> () {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    if !module.cfg.consensus.velocity_limits {
+                        return Err(ApiError::bad_request(
+                            "Velocity limits are not enabled in the federation config".to_string(),
+                        ));
+                    }
+                    policy.validate().map_err(ApiError::bad_request)?;
+                    context
+                        .dbtx()
+                        .insert_entry(&ProposedVelocityPolicyKey, &policy)
+                        .await;
+                    Ok(())
+                }
+            },
+            api_endpoint! {
+                "velocity_policy_votes",
+                async |_module: &Mint, context, _params: ()| -> BTreeMap<PeerId, VelocityPolicy> {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    Ok(context
+                        .dbtx()
+                        .find_by_prefix(&VelocityPolicyVoteKeyPrefix)
+                        .await
+                        .map(|(key, policy)| (key.0, policy))
+                        .collect()
+                        .await)
+                }
+            },
+            api_endpoint! {
+                "exit_safe_mode",
+                async |_module: &Mint, context, violation: LiabilityViolation| -> () {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    let mut dbtx = context.dbtx();
+                    // Votes only count for the violation the mint is halted for, so they
+                    // can't end a later safe mode
+                    if dbtx.get_value(&SafeModeKey).await != Some(violation) {
+                        return Err(ApiError::bad_request(
+                            "The mint is not in safe mode because of this violation".to_string(),
+                        ));
+                    }
+                    dbtx.insert_entry(&ProposedSafeModeExitKey, &violation).await;
+                    Ok(())
+                }
+            },
+            api_endpoint! {
+                "safe_mode_exit_votes",
+                async |_module: &Mint, context, _params: ()| -> BTreeMap<PeerId, LiabilityViolation> {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    Ok(context
+                        .dbtx()
+                        .find_by_prefix(&SafeModeExitVoteKeyPrefix)
+                        .await
+                        .map(|(key, violation)| (key.0, violation))
+                        .collect()
+                        .await)
+                }
+            },
         ]
     }
+
+    fn interconnect_handlers(&self) -> Vec<InterconnectHandler<Self>> {
+        vec![interconnect_handler! {
+            NoteSupplyMethod,
+            async |_module: &Mint, dbtx, _request| {
+                let (issued, redeemed) = Mint::audit_totals(dbtx).await;
+                Ok(issued.saturating_sub(redeemed))
+            }
+        }]
+    }
 }
 
 impl Mint {
+    /// Total value of the notes issued and redeemed so far
+    async fn audit_totals(dbtx: &mut ModuleDatabaseTransaction<'_>) -> (Amount, Amount) {
+        dbtx.find_by_prefix(&MintAuditItemKeyPrefix)
+            .await
+            .fold(
+                (Amount::ZERO, Amount::ZERO),
+                |(issued, redeemed), (key, amount)| async move {
+                    match key {
+                        MintAuditItemKey::Issuance(_) | MintAuditItemKey::IssuanceTotal => {
+                            (issued + amount, redeemed)
+                        }
+                        MintAuditItemKey::Redemption(_) | MintAuditItemKey::RedemptionTotal => {
+                            (issued, redeemed + amount)
+                        }
+                    }
+                },
+            )
+            .await
+    }
+
+    /// Enters safe mode if we redeemed more e-cash than we ever issued, which
+    /// means notes were forged or counted twice. Halting is safer than letting
+    /// the supply inflate any further, the guardians have to find the cause
+    /// before a threshold of them votes to exit safe mode again.
+    ///
+    /// The shortfall of the violation they exited safe mode for is accepted,
+    /// only a larger one enters safe mode again.
+    ///
+    /// Runs at the end of every epoch on consensus state, so all guardians
+    /// enter safe mode in the same epoch.
+    async fn check_liabilities(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) {
+        if dbtx.get_value(&SafeModeKey).await.is_some() {
+            return;
+        }
+
+        let acknowledged_shortfall = dbtx
+            .get_value(&AcknowledgedLiabilityViolationKey)
+            .await
+            .map_or(Amount::ZERO, |violation| violation.shortfall());
+        let (issued, redeemed) = Self::audit_totals(dbtx).await;
+        if redeemed.saturating_sub(issued) > acknowledged_shortfall {
+            error!(
+                %issued,
+                %redeemed,
+                "Mint redeemed more e-cash than it issued, entering safe mode"
+            );
+            dbtx.insert_new_entry(&SafeModeKey, &LiabilityViolation { issued, redeemed })
+                .await;
+        }
+    }
+
     async fn handle_backup_request(
         &self,
         dbtx: &mut ModuleDatabaseTransaction<'_>,
@@ -751,15 +1230,21 @@ impl Mint {
 }
 
 impl Mint {
-    /// Constructs a new mint
+    /// Constructs the mint of peer `our_id`
     ///
     /// # Panics
     /// * If there are no amount tiers
-    /// * If the amount tiers for secret and public keys are inconsistent
-    /// * If the pub key belonging to the secret key share is not in the pub key
-    ///   list.
-    pub fn new(cfg: MintConfig) -> Mint {
-        assert!(cfg.private.tbs_sks.tiers().count() > 0);
+    /// * If the amount tiers of the peers' public keys are inconsistent
+    /// * If `our_id` has no public key shares
+    /// * If the config contains secret key shares that don't belong to our
+    ///   public key shares
+    pub fn new(cfg: MintConfig, our_id: PeerId) -> Mint {
+        let our_pub_key = cfg
+            .consensus
+            .peer_tbs_pks
+            .get(&our_id)
+            .expect("Own key not found among pub keys.");
+        assert!(our_pub_key.tiers().count() > 0);
 
         // The amount tiers are implicitly provided by the key sets, make sure they are
         // internally consistent.
@@ -767,27 +1252,19 @@ impl Mint {
             .consensus
             .peer_tbs_pks
             .values()
-            .all(|pk| pk.structural_eq(&cfg.private.tbs_sks)));
-
-        let ref_pub_key = cfg.private.tbs_sks.to_public();
-
-        // Find our key index and make sure we know the private key for all our public
-        // key shares
-        let our_id = cfg
-            .consensus // FIXME: make sure we use id instead of idx everywhere
-            .peer_tbs_pks
-            .iter()
-            .find_map(|(&id, pk)| if *pk == ref_pub_key { Some(id) } else { None })
-            .expect("Own key not found among pub keys.");
+            .all(|pk| pk.structural_eq(our_pub_key)));
 
-        assert_eq!(
-            cfg.consensus.peer_tbs_pks[&our_id],
-            cfg.private
-                .tbs_sks
-                .iter()
-                .map(|(amount, sk)| (amount, sk.to_pub_key_share()))
-                .collect()
-        );
+        // Without secret key shares we sign through an external guardian signer
+        if let Some(tbs_sks) = &cfg.private.tbs_sks {
+            assert_eq!(
+                *our_pub_key,
+                tbs_sks
+                    .iter()
+                    .map(|(amount, sk)| (amount, sk.to_pub_key_share()))
+                    .collect(),
+                "Secret key shares don't match our public key shares"
+            );
+        }
 
         let aggregate_pub_keys = TieredMultiZip::new(
             cfg.consensus
@@ -805,9 +1282,283 @@ impl Mint {
 
         Mint {
             cfg: cfg.clone(),
-            sec_key: cfg.private.tbs_sks,
+            signer: Arc::new(LocalSigner::default().with_tbs_keys(cfg.private.tbs_sks)),
             pub_key_shares: cfg.consensus.peer_tbs_pks.into_iter().collect(),
             pub_key: aggregate_pub_keys,
+            our_id,
+            interconnect: None,
+        }
+    }
+
+    /// Signs notes with `signer` instead of the key shares from the config
+    pub fn with_signer(mut self, signer: DynGuardianSigner) -> Mint {
+        self.signer = signer;
+        self
+    }
+
+    /// Allows spending notes with timelocked spend conditions, which can't be
+    /// spent without it
+    pub fn with_interconnect(mut self, interconnect: DynModuleInterconnect) -> Mint {
+        self.interconnect = Some(interconnect);
+        self
+    }
+
+    /// Checks the witnesses of the locked notes in `input`, returns the keys
+    /// that have to sign for them instead of their nonces
+    async fn verify_spend_conditions(
+        &self,
+        input: &MintInput,
+    ) -> Result<BTreeMap<Nonce, XOnlyPublicKey>, MintError> {
+        let mut keys = BTreeMap::new();
+
+        for witness in &input.1 {
+            if !input.iter_items().any(|(_, note)| note.0 == witness.nonce) {
+                return Err(MintError::InvalidSpendCondition);
+            }
+
+            let branch = witness.verify()?;
+
+            if let Some(min_block_height) = branch.min_block_height {
+                let block_height = match &self.interconnect {
+                    Some(interconnect) => interconnect.wallet_block_height().await.ok().flatten(),
+                    None => None,
+                };
+                if block_height.map_or(true, |height| height < min_block_height) {
+                    return Err(MintError::Timelocked(min_block_height));
+                }
+            }
+
+            if keys.insert(witness.nonce, branch.key).is_some() {
+                return Err(MintError::InvalidSpendCondition);
+            }
+        }
+
+        Ok(keys)
+    }
+
+    /// Returns the day and the amount each registered account spends with
+    /// `input` if velocity limits are enabled, `pub_keys` are the keys that
+    /// have to sign for its notes
+    async fn velocity_spends(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        input: &MintInput,
+        pub_keys: &[XOnlyPublicKey],
+    ) -> Result<Option<(u32, BTreeMap<XOnlyPublicKey, Amount>)>, MintError> {
+        if !self.cfg.consensus.velocity_limits {
+            return Ok(None);
+        }
+        let policy = match dbtx.get_value(&VelocityPolicyKey).await {
+            Some(policy) => policy,
+            None => return Ok(None),
+        };
+
+        let mut spends = BTreeMap::<XOnlyPublicKey, Amount>::new();
+        for ((amount, _), key) in input.iter_items().zip(pub_keys) {
+            if policy.daily_limit(key).is_some() {
+                *spends.entry(*key).or_insert(Amount::ZERO) += amount;
+            }
+        }
+        if spends.is_empty() {
+            return Ok(None);
+        }
+
+        let block_height = match &self.interconnect {
+            Some(interconnect) => interconnect.wallet_block_height().await.ok().flatten(),
+            None => None,
+        };
+        let day = velocity_day(block_height.ok_or(MintError::VelocityDayUnknown)?);
+
+        for (account, amount) in &spends {
+            let limit = policy.daily_limit(account).expect("Checked above");
+            let spent = dbtx
+                .get_value(&VelocityUsageKey {
+                    account: *account,
+                    day,
+                })
+                .await
+                .unwrap_or(Amount::ZERO);
+            if spent + *amount > limit {
+                return Err(MintError::VelocityLimitExceeded(*account, limit));
+            }
+        }
+
+        Ok(Some((day, spends)))
+    }
+
+    /// Sums up the rebates `input` claims per account and checks that the
+    /// balances of the accounts cover them
+    async fn rebate_claims(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        input: &MintInput,
+    ) -> Result<BTreeMap<XOnlyPublicKey, Amount>, MintError> {
+        if input.2.is_empty() {
+            return Ok(BTreeMap::new());
+        }
+        if self.cfg.consensus.fee_rebate.is_none() {
+            return Err(MintError::RebatesDisabled);
+        }
+
+        let mut claims = BTreeMap::<XOnlyPublicKey, Amount>::new();
+        for claim in &input.2 {
+            *claims.entry(claim.account).or_insert(Amount::ZERO) += claim.amount;
+        }
+        for (account, amount) in &claims {
+            let balance = dbtx
+                .get_value(&RebateBalanceKey(*account))
+                .await
+                .unwrap_or(Amount::ZERO);
+            if balance < *amount {
+                return Err(MintError::InsufficientRebateBalance(*account, balance));
+            }
+        }
+
+        Ok(claims)
+    }
+
+    /// Adds `fees` to the fees collected in the current rebate period and
+    /// `account_fees` to the fees the accounts paid in it, if rebates are
+    /// enabled
+    async fn record_fees(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        fees: Amount,
+        account_fees: BTreeMap<XOnlyPublicKey, Amount>,
+    ) {
+        if self.cfg.consensus.fee_rebate.is_none() || fees == Amount::ZERO {
+            return;
+        }
+
+        let collected = dbtx
+            .get_value(&CollectedFeesKey)
+            .await
+            .unwrap_or(Amount::ZERO);
+        dbtx.insert_entry(&CollectedFeesKey, &(collected + fees))
+            .await;
+        for (account, fees) in account_fees {
+            let key = AccountFeesKey(account);
+            let paid = dbtx.get_value(&key).await.unwrap_or(Amount::ZERO);
+            dbtx.insert_entry(&key, &(paid + fees)).await;
+        }
+    }
+
+    /// Credits the rebates for the fees of the last period to the accounts
+    /// once the consensus block height entered a new rebate period
+    async fn distribute_rebates(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) {
+        let Some(fee_rebate) = &self.cfg.consensus.fee_rebate else {
+            return;
+        };
+        let block_height = match &self.interconnect {
+            Some(interconnect) => interconnect.wallet_block_height().await.ok().flatten(),
+            None => None,
+        };
+        let Some(block_height) = block_height else {
+            return;
+        };
+
+        let period = fee_rebate.period(block_height);
+        match dbtx.get_value(&RebatePeriodKey).await {
+            Some(current) if period <= current => return,
+            // Fees collected before the block height was known count towards
+            // the first period
+            None => {
+                dbtx.insert_entry(&RebatePeriodKey, &period).await;
+                return;
+            }
+            Some(_) => {}
+        }
+
+        let collected = dbtx
+            .remove_entry(&CollectedFeesKey)
+            .await
+            .unwrap_or(Amount::ZERO);
+        let account_fees = dbtx
+            .find_by_prefix(&AccountFeesKeyPrefix)
+            .await
+            .map(|(key, fees)| (key.0, fees))
+            .collect::<BTreeMap<_, _>>()
+            .await;
+        dbtx.remove_by_prefix(&AccountFeesKeyPrefix).await;
+
+        let rebates = fee_rebate.rebates(collected, &account_fees);
+        info!(
+            period,
+            %collected,
+            accounts = rebates.len(),
+            "Crediting fee rebates"
+        );
+        for (account, rebate) in rebates {
+            let key = RebateBalanceKey(account);
+            let balance = dbtx.get_value(&key).await.unwrap_or(Amount::ZERO);
+            dbtx.insert_entry(&key, &(balance + rebate)).await;
+        }
+        dbtx.insert_entry(&RebatePeriodKey, &period).await;
+    }
+
+    /// Records the velocity policy vote of a peer and replaces the policy once
+    /// a threshold of peers voted for the same one
+    async fn process_velocity_policy_vote(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        peer_id: PeerId,
+        policy: VelocityPolicy,
+    ) {
+        if peer_id == self.our_id {
+            dbtx.remove_entry(&ProposedVelocityPolicyKey).await;
+        }
+        if !self.cfg.consensus.velocity_limits || policy.validate().is_err() {
+            warn!(%peer_id, "Ignoring invalid velocity policy vote");
+            return;
+        }
+
+        dbtx.insert_entry(&VelocityPolicyVoteKey(peer_id), &policy)
+            .await;
+
+        let votes = dbtx
+            .find_by_prefix(&VelocityPolicyVoteKeyPrefix)
+            .await
+            .filter(|(_, vote)| std::future::ready(*vote == policy))
+            .count()
+            .await;
+        if votes >= self.cfg.consensus.peer_tbs_pks.threshold() {
+            info!(?policy, "Velocity policy updated");
+            dbtx.insert_entry(&VelocityPolicyKey, &policy).await;
+            dbtx.remove_by_prefix(&VelocityPolicyVoteKeyPrefix).await;
+        }
+    }
+
+    /// Records the vote of a peer to exit safe mode and exits it once a
+    /// threshold of peers voted for the violation the mint is halted for
+    async fn process_safe_mode_exit_vote(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        peer_id: PeerId,
+        violation: LiabilityViolation,
+    ) {
+        if peer_id == self.our_id {
+            dbtx.remove_entry(&ProposedSafeModeExitKey).await;
+        }
+        if dbtx.get_value(&SafeModeKey).await != Some(violation) {
+            warn!(%peer_id, "Ignoring vote to exit safe mode for another violation");
+            return;
+        }
+
+        dbtx.insert_entry(&SafeModeExitVoteKey(peer_id), &violation)
+            .await;
+
+        let votes = dbtx
+            .find_by_prefix(&SafeModeExitVoteKeyPrefix)
+            .await
+            .filter(|(_, vote)| std::future::ready(*vote == violation))
+            .count()
+            .await;
+        if votes >= self.cfg.consensus.peer_tbs_pks.threshold() {
+            info!(?violation, "Guardians voted to exit safe mode");
+            dbtx.remove_entry(&SafeModeKey).await;
+            dbtx.insert_entry(&AcknowledgedLiabilityViolationKey, &violation)
+                .await;
+            dbtx.remove_by_prefix(&SafeModeExitVoteKeyPrefix).await;
         }
     }
 
@@ -815,26 +1566,40 @@ impl Mint {
         self.pub_key.clone()
     }
 
-    fn blind_sign(
+    async fn blind_sign(
         &self,
         output: TieredMulti<BlindNonce>,
     ) -> Result<MintOutputSignatureShare, MintError> {
-        Ok(MintOutputSignatureShare(output.map(
-            |amt, msg| -> Result<_, InvalidAmountTierError> {
-                let sec_key = self.sec_key.tier(&amt)?;
-                let blind_signature = sign_blinded_msg(msg.0, *sec_key);
-                Ok((msg.0, blind_signature))
-            },
-        )?))
+        let mut shares = Vec::with_capacity(output.count_items());
+        for (amt, msg) in output.into_iter_items() {
+            self.pub_key_shares[&self.our_id].tier(&amt)?;
+            let blind_signature = self
+                .signer
+                .sign_blinded(amt, msg.0)
+                .await
+                .expect("Guardian signer failed to sign note");
+            shares.push((amt, (msg.0, blind_signature)));
+        }
+        Ok(MintOutputSignatureShare(shares.into_iter().collect()))
     }
 }
 
 #[cfg(test)]
 mod test {
+    use std::collections::BTreeSet;
+
     use fedimint_core::config::{ClientModuleConfig, ConfigGenModuleParams, ServerModuleConfig};
+    use fedimint_core::core::LEGACY_HARDCODED_INSTANCE_ID_MINT;
+    use fedimint_core::db::mem_impl::MemDatabase;
+    use fedimint_core::db::Database;
+    use fedimint_core::module::registry::ModuleDecoderRegistry;
     use fedimint_core::module::ServerModuleGen;
-    use fedimint_core::{Amount, PeerId};
+    use fedimint_core::{Amount, PeerId, ServerModule};
     use fedimint_mint_common::config::FeeConsensus;
+    use fedimint_mint_common::db::{
+        LiabilityViolation, MintAuditItemKey, ProposedSafeModeExitKey, SafeModeKey,
+    };
+    use fedimint_mint_common::{MintConsensusItem, MintOutput};
 
     use crate::common::config::MintGenParamsConsensus;
     use crate::{
@@ -852,6 +1617,8 @@ mod test {
                 local: Default::default(),
                 consensus: MintGenParamsConsensus {
                     mint_amounts: vec![Amount::from_sats(1)],
+                    velocity_limits: false,
+                    fee_rebate: None,
                 },
             })
             .unwrap(),
@@ -864,33 +1631,152 @@ mod test {
     }
 
     #[test_log::test]
-    #[should_panic(expected = "Own key not found among pub keys.")]
-    fn test_new_panic_without_own_pub_key() {
+    fn test_parse_params_rejects_invalid_denominations() {
+        let params = |mint_amounts: Vec<u64>| {
+            ConfigGenModuleParams::from_typed(MintGenParams {
+                local: Default::default(),
+                consensus: MintGenParamsConsensus {
+                    mint_amounts: mint_amounts.into_iter().map(Amount::from_msats).collect(),
+                    velocity_limits: false,
+                    fee_rebate: None,
+                },
+            })
+            .unwrap()
+        };
+
+        assert!(MintGen.parse_params(&params(vec![1, 2, 5, 10])).is_ok());
+        assert!(MintGen.parse_params(&params(vec![])).is_err());
+        assert!(MintGen.parse_params(&params(vec![0, 1])).is_err());
+        assert!(MintGen.parse_params(&params(vec![1, 5, 5])).is_err());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_safe_mode_after_overspend() {
+        let (mint_cfg, _) = build_configs();
+        let mint = Mint::new(mint_cfg[0].to_typed().unwrap(), PeerId::from(0));
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let mut dbtx = db.begin_transaction().await;
+        let mut dbtx = dbtx.with_module_prefix(LEGACY_HARDCODED_INSTANCE_ID_MINT);
+
+        dbtx.insert_new_entry(&MintAuditItemKey::IssuanceTotal, &Amount::from_sats(10))
+            .await;
+        dbtx.insert_new_entry(&MintAuditItemKey::RedemptionTotal, &Amount::from_sats(10))
+            .await;
+        mint.end_consensus_epoch(&BTreeSet::new(), &mut dbtx).await;
+        assert_eq!(dbtx.get_value(&SafeModeKey).await, None);
+        assert!(mint
+            .validate_output(&mut dbtx, &MintOutput(Default::default()))
+            .await
+            .is_ok());
+
+        dbtx.insert_entry(&MintAuditItemKey::RedemptionTotal, &Amount::from_sats(11))
+            .await;
+        mint.end_consensus_epoch(&BTreeSet::new(), &mut dbtx).await;
+        assert_eq!(
+            dbtx.get_value(&SafeModeKey).await,
+            Some(LiabilityViolation {
+                issued: Amount::from_sats(10),
+                redeemed: Amount::from_sats(11),
+            })
+        );
+        assert!(mint
+            .validate_output(&mut dbtx, &MintOutput(Default::default()))
+            .await
+            .is_err());
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn test_exit_safe_mode_by_vote() {
+        let (mint_cfg, _) = build_configs();
+        let mint = Mint::new(mint_cfg[0].to_typed().unwrap(), PeerId::from(0));
+        let db = Database::new(MemDatabase::new(), ModuleDecoderRegistry::default());
+        let mut dbtx = db.begin_transaction().await;
+        let mut dbtx = dbtx.with_module_prefix(LEGACY_HARDCODED_INSTANCE_ID_MINT);
+        let peers = (0..MINTS as u16).map(PeerId::from).collect::<BTreeSet<_>>();
+        let threshold = mint.cfg.consensus.peer_tbs_pks.threshold();
+
+        dbtx.insert_new_entry(&MintAuditItemKey::IssuanceTotal, &Amount::from_sats(10))
+            .await;
+        dbtx.insert_new_entry(&MintAuditItemKey::RedemptionTotal, &Amount::from_sats(11))
+            .await;
+        mint.end_consensus_epoch(&peers, &mut dbtx).await;
+        let violation = dbtx.get_value(&SafeModeKey).await.expect("in safe mode");
+
+        // Votes for another violation don't count
+        let other = LiabilityViolation {
+            issued: Amount::from_sats(10),
+            redeemed: Amount::from_sats(12),
+        };
+        let votes = |violation: LiabilityViolation, count: usize| {
+            peers
+                .iter()
+                .take(count)
+                .map(|peer| (*peer, MintConsensusItem::ExitSafeMode(violation)))
+                .collect::<Vec<_>>()
+        };
+        mint.begin_consensus_epoch(&mut dbtx, votes(other, MINTS), &peers)
+            .await;
+        assert_eq!(dbtx.get_value(&SafeModeKey).await, Some(violation));
+
+        dbtx.insert_new_entry(&ProposedSafeModeExitKey, &violation)
+            .await;
+        mint.begin_consensus_epoch(&mut dbtx, votes(violation, threshold - 1), &peers)
+            .await;
+        assert_eq!(dbtx.get_value(&SafeModeKey).await, Some(violation));
+        assert_eq!(dbtx.get_value(&ProposedSafeModeExitKey).await, None);
+
+        mint.begin_consensus_epoch(&mut dbtx, votes(violation, threshold), &peers)
+            .await;
+        assert_eq!(dbtx.get_value(&SafeModeKey).await, None);
+
+        // The acknowledged shortfall doesn't enter safe mode again, a larger one does
+        mint.end_consensus_epoch(&peers, &mut dbtx).await;
+        assert_eq!(dbtx.get_value(&SafeModeKey).await, None);
+
+        dbtx.insert_entry(&MintAuditItemKey::RedemptionTotal, &Amount::from_sats(12))
+            .await;
+        mint.end_consensus_epoch(&peers, &mut dbtx).await;
+        assert_eq!(dbtx.get_value(&SafeModeKey).await, Some(other));
+    }
+
+    #[test_log::test]
+    #[should_panic(expected = "Secret key shares don't match our public key shares")]
+    fn test_new_panic_with_foreign_secret_keys() {
         let (mint_server_cfg1, _) = build_configs();
         let (mint_server_cfg2, _) = build_configs();
 
-        Mint::new(MintConfig {
-            local: MintConfigLocal,
-            consensus: MintConfigConsensus {
-                peer_tbs_pks: mint_server_cfg2[0]
-                    .to_typed::<MintConfig>()
-                    .unwrap()
-                    .consensus
-                    .peer_tbs_pks,
-                fee_consensus: FeeConsensus::default(),
-                max_notes_per_denomination: 0,
-            },
-            private: MintConfigPrivate {
-                tbs_sks: mint_server_cfg1[0]
-                    .to_typed::<MintConfig>()
-                    .unwrap()
-                    .private
-                    .tbs_sks,
+        Mint::new(
+            MintConfig {
+                local: MintConfigLocal,
+                consensus: MintConfigConsensus {
+                    peer_tbs_pks: mint_server_cfg2[0]
+                        .to_typed::<MintConfig>()
+                        .unwrap()
+                        .consensus
+                        .peer_tbs_pks,
+                    fee_consensus: FeeConsensus::default(),
+                    max_notes_per_denomination: 0,
+                    velocity_limits: false,
+                    fee_rebate: None,
+                    retired_tiers: BTreeSet::new(),
+                },
+                private: MintConfigPrivate {
+                    tbs_sks: mint_server_cfg1[0]
+                        .to_typed::<MintConfig>()
+                        .unwrap()
+                        .private
+                        .tbs_sks,
+                },
             },
-        });
+            PeerId::from(0),
+        );
     }
 }
 
+/// Number of notes verified at once, a failing batch is verified again note by
+/// note so we keep batches small enough for that to be cheap
+const NOTE_VERIFICATION_BATCH_SIZE: usize = 64;
+
 #[derive(Debug, Clone)]
 pub struct VerifiedNotes {
     valid_notes: HashMap<Note, Amount>,
@@ -898,14 +1784,60 @@ pub struct VerifiedNotes {
 
 impl fedimint_core::server::VerificationCache for VerifiedNotes {}
 
+/// Refuses to upgrade mints that were used under consensus version 0.
+///
+/// Version 1 changed the encoding of [`MintInput`] and [`MintConsensusItem`]
+/// and encoded epochs can't be re-encoded without invalidating their
+/// signatures, so the epoch history of such a mint could never be decoded
+/// again. Mints that never issued or redeemed notes have no items in the epoch
+/// history and are upgraded without changes.
+async fn migrate_to_v1(dbtx: &mut DatabaseTransaction<'_>) -> anyhow::Result<()> {
+    let has_nonces = dbtx.find_by_prefix(&NonceKeyPrefix).await.next().await;
+    let has_signatures = dbtx
+        .find_by_prefix(&ReceivedPartialSignaturesKeyPrefix)
+        .await
+        .next()
+        .await;
+    let has_outcomes = dbtx
+        .find_by_prefix(&OutputOutcomeKeyPrefix)
+        .await
+        .next()
+        .await;
+    let has_audit_items = dbtx
+        .find_by_prefix(&MintAuditItemKeyPrefix)
+        .await
+        .next()
+        .await;
+
+    if has_nonces.is_some()
+        || has_signatures.is_some()
+        || has_outcomes.is_some()
+        || has_audit_items.is_some()
+    {
+        bail!(
+            "The mint was used under consensus version 0, its epoch history can't be decoded by \
+             this version. Keep running the previous release or start a new federation."
+        );
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod fedimint_migration_tests {
     use std::collections::BTreeMap;
     use std::time::SystemTime;
 
     use bitcoin_hashes::Hash;
-    use fedimint_core::core::LEGACY_HARDCODED_INSTANCE_ID_MINT;
-    use fedimint_core::db::{apply_migrations, DatabaseTransaction};
+    use std::io::Cursor;
+
+    use fedimint_core::core::{
+        DynInput, DynModuleConsensusItem, LEGACY_HARDCODED_INSTANCE_ID_MINT,
+    };
+    use fedimint_core::db::mem_impl::MemDatabase;
+    use fedimint_core::db::{
+        apply_migrations, Database, DatabaseTransaction, DatabaseVersion, DatabaseVersionKey,
+    };
+    use fedimint_core::encoding::{Decodable, Encodable};
     use fedimint_core::module::registry::ModuleDecoderRegistry;
     use fedimint_core::module::{CommonModuleGen, DynServerModuleGen};
     use fedimint_core::{Amount, OutPoint, ServerModule, TieredMulti, TransactionId};
@@ -916,7 +1848,7 @@ mod fedimint_migration_tests {
         ReceivedPartialSignatureKey, ReceivedPartialSignaturesKeyPrefix,
     };
     use fedimint_mint_common::{
-        MintCommonGen, MintOutputBlindSignatures, MintOutputSignatureShare, Nonce,
+        MintCommonGen, MintOutputBlindSignatures, MintOutputSignatureShare, Nonce, Note,
     };
     use fedimint_testing::db::{prepare_snapshot, validate_migrations, BYTE_32, BYTE_8};
     use futures::StreamExt;
@@ -1023,25 +1955,106 @@ mod fedimint_migration_tests {
         .await;
     }
 
+    fn decoders() -> ModuleDecoderRegistry {
+        ModuleDecoderRegistry::from_iter([(
+            LEGACY_HARDCODED_INSTANCE_ID_MINT,
+            MintCommonGen::KIND,
+            <Mint as ServerModule>::decoder(),
+        )])
+    }
+
+    async fn apply_mint_migrations(db: &Database) -> anyhow::Result<()> {
+        let module = DynServerModuleGen::from(MintGen);
+        apply_migrations(
+            db,
+            module.module_kind().to_string(),
+            module.database_version(),
+            module.get_database_migrations(),
+        )
+        .await
+    }
+
+    /// Encoding of `MintInput` under consensus version 0
+    #[derive(Encodable)]
+    struct MintInputV0(TieredMulti<Note>);
+
+    /// Encoding of `MintConsensusItem` under consensus version 0
+    #[derive(Encodable)]
+    struct MintConsensusItemV0 {
+        out_point: OutPoint,
+        signatures: MintOutputSignatureShare,
+    }
+
+    /// Encodes `item` the way a module item of the mint is encoded in an epoch
+    fn encode_mint_item(item: &impl Encodable) -> Vec<u8> {
+        let mut bytes = LEGACY_HARDCODED_INSTANCE_ID_MINT
+            .consensus_encode_to_vec()
+            .unwrap();
+        bytes.extend(item.consensus_encode_to_vec().unwrap());
+        bytes
+    }
+
+    #[test]
+    fn test_v0_epoch_items_dont_decode() {
+        // Epochs of a used version 0 mint contain these items, which is why such
+        // mints aren't upgraded
+        let input = encode_mint_item(&MintInputV0(TieredMulti::default()));
+        assert!(DynInput::consensus_decode(&mut Cursor::new(input), &decoders()).is_err());
+
+        let item = encode_mint_item(&MintConsensusItemV0 {
+            out_point: OutPoint {
+                txid: TransactionId::from_slice(&BYTE_32).unwrap(),
+                out_idx: 0,
+            },
+            signatures: MintOutputSignatureShare(TieredMulti::default()),
+        });
+        assert!(
+            DynModuleConsensusItem::consensus_decode(&mut Cursor::new(item), &decoders()).is_err()
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_unused_v0_mint_is_upgraded() {
+        let db = Database::new(MemDatabase::new(), decoders());
+        let mut dbtx = db.begin_transaction().await;
+        dbtx.insert_new_entry(&DatabaseVersionKey, &DatabaseVersion(0))
+            .await;
+        dbtx.commit_tx().await;
+
+        apply_mint_migrations(&db)
+            .await
+            .expect("Unused mints are upgraded");
+        assert_eq!(
+            db.begin_transaction()
+                .await
+                .get_value(&DatabaseVersionKey)
+                .await,
+            Some(DatabaseVersion(1))
+        );
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_migrations() {
         validate_migrations(
             "mint",
             |db| async move {
-                let module = DynServerModuleGen::from(MintGen);
-                apply_migrations(
-                    &db,
-                    module.module_kind().to_string(),
-                    module.database_version(),
-                    module.get_database_migrations(),
-                )
-                .await
-                .expect("Error applying migrations to temp database");
-
-                // Verify that all of the data from the mint namespace can be read. If a
-                // database migration failed or was not properly supplied,
-                // the struct will fail to be read.
+                // The snapshot is of a used mint, which isn't upgraded from version 0
+                let mut dbtx = db.begin_transaction().await;
+                dbtx.insert_entry(&DatabaseVersionKey, &DatabaseVersion(0))
+                    .await;
+                dbtx.commit_tx().await;
+                let err = apply_mint_migrations(&db)
+                    .await
+                    .expect_err("Used version 0 mints must not be upgraded");
+                assert!(format!("{err:#}").contains("consensus version 0"));
+
+                // Verify that all of the data from the mint namespace can still be read, the
+                // failed migration must leave it untouched.
                 let mut dbtx = db.begin_transaction().await;
+                assert_eq!(
+                    dbtx.get_value(&DatabaseVersionKey).await,
+                    Some(DatabaseVersion(0))
+                );
 
                 for prefix in DbKeyPrefix::iter() {
                     match prefix {
@@ -1117,14 +2130,23 @@ mod fedimint_migration_tests {
                                 "validate_migrations was not able to read any EcashBackups"
                             );
                         }
+                        // Added after v0, the snapshot doesn't contain them
+                        DbKeyPrefix::VelocityPolicy
+                        | DbKeyPrefix::VelocityPolicyVote
+                        | DbKeyPrefix::ProposedVelocityPolicy
+                        | DbKeyPrefix::VelocityUsage
+                        | DbKeyPrefix::SafeMode
+                        | DbKeyPrefix::RebatePeriod
+                        | DbKeyPrefix::CollectedFees
+                        | DbKeyPrefix::AccountFees
+                        | DbKeyPrefix::RebateBalance
+                        | DbKeyPrefix::SafeModeExitVote
+                        | DbKeyPrefix::ProposedSafeModeExit
+                        | DbKeyPrefix::AcknowledgedLiabilityViolation => {}
                     }
                 }
             },
-            ModuleDecoderRegistry::from_iter([(
-                LEGACY_HARDCODED_INSTANCE_ID_MINT,
-                MintCommonGen::KIND,
-                <Mint as ServerModule>::decoder(),
-            )]),
+            decoders(),
         )
         .await;
     }
@@ -1,17 +1,18 @@
-use fedimint_core::sats;
 use fedimint_core::util::NextOrPending;
+use fedimint_core::{sats, Amount};
 use fedimint_dummy_client::{DummyClientExt, DummyClientGen};
 use fedimint_dummy_common::config::DummyGenParams;
 use fedimint_dummy_server::DummyGen;
 use fedimint_mint_client::{
     MintClientExt, MintClientGen, ReissueExternalNotesState, SpendOOBState,
 };
-use fedimint_mint_common::config::MintGenParams;
+use fedimint_mint_common::config::{MintGenParams, MintGenParamsConsensus};
 use fedimint_mint_server::MintGen;
 use fedimint_testing::fixtures::{Fixtures, TIMEOUT};
 
 fn fixtures() -> Fixtures {
-    let fixtures = Fixtures::new_primary(MintClientGen, MintGen, MintGenParams::default());
+    let fixtures =
+        Fixtures::new_primary(MintClientGen::default(), MintGen, MintGenParams::default());
     fixtures.with_module(DummyClientGen, DummyGen, DummyGenParams::default())
 }
 
@@ -40,3 +41,44 @@ async fn sends_ecash_out_of_band() -> anyhow::Result<()> {
     assert_eq!(client2.get_balance().await, sats(750));
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn sends_ecash_in_custom_denominations() -> anyhow::Result<()> {
+    // 1-2-5 series from 1 msat up to 5 BTC
+    let mint_amounts: Vec<_> = (0..12)
+        .flat_map(|exp| [1, 2, 5].map(|factor| Amount::from_msats(factor * 10u64.pow(exp))))
+        .collect();
+    let params = MintGenParams {
+        consensus: MintGenParamsConsensus {
+            mint_amounts: mint_amounts.clone(),
+            velocity_limits: false,
+            fee_rebate: None,
+        },
+        ..Default::default()
+    };
+    let fixtures = Fixtures::new_primary(MintClientGen::default(), MintGen, params).with_module(
+        DummyClientGen,
+        DummyGen,
+        DummyGenParams::default(),
+    );
+    let fed = fixtures.new_fed().await;
+    let (client1, client2) = fed.two_clients().await;
+    let (op, outpoint) = client1.print_money(sats(1000)).await?;
+    client1.await_primary_module_output(op, outpoint).await?;
+
+    let (_, notes) = client1.spend_notes(sats(750), TIMEOUT, ()).await?;
+    assert!(notes
+        .iter_tiers()
+        .all(|amount| mint_amounts.contains(amount)));
+
+    let op = client2.reissue_external_notes(notes, ()).await?;
+    let sub = client2.subscribe_reissue_external_notes(op).await?;
+    let mut sub = sub.into_stream();
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Created);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Issuing);
+    assert_eq!(sub.ok().await?, ReissueExternalNotesState::Done);
+
+    assert_eq!(client1.get_balance().await, sats(250));
+    assert_eq!(client2.get_balance().await, sats(750));
+    Ok(())
+}
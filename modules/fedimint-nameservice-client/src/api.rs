@@ -0,0 +1,21 @@
+use fedimint_core::api::{FederationApiExt, FederationResult, IModuleFederationApi};
+use fedimint_core::module::ApiRequestErased;
+use fedimint_core::task::{MaybeSend, MaybeSync};
+use fedimint_core::{apply, async_trait_maybe_send};
+use fedimint_nameservice_common::NameRecord;
+
+#[apply(async_trait_maybe_send!)]
+pub trait NameFederationApi {
+    async fn resolve_name(&self, name: String) -> FederationResult<Option<NameRecord>>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<T: ?Sized> NameFederationApi for T
+where
+    T: IModuleFederationApi + MaybeSend + MaybeSync + 'static,
+{
+    async fn resolve_name(&self, name: String) -> FederationResult<Option<NameRecord>> {
+        self.request_current_consensus("resolve".to_string(), ApiRequestErased::new(name))
+            .await
+    }
+}
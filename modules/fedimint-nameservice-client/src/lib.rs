@@ -0,0 +1,439 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail};
+use async_stream::stream;
+use fedimint_client::derivable_secret::DerivableSecret;
+use fedimint_client::module::gen::ClientModuleGen;
+use fedimint_client::module::ClientModule;
+use fedimint_client::oplog::{OperationLogEntry, UpdateStreamOrOutcome};
+use fedimint_client::sm::{Context, ModuleNotifier, OperationId};
+use fedimint_client::transaction::{ClientInput, ClientOutput, TransactionBuilder};
+use fedimint_client::{Client, DynGlobalClientContext};
+use fedimint_core::api::{DynGlobalApi, DynModuleApi};
+use fedimint_core::core::{IntoDynInstance, KeyPair};
+use fedimint_core::db::Database;
+use fedimint_core::module::{
+    ApiVersion, ExtendsCommonModuleGen, ModuleCommon, MultiApiVersion, TransactionItemAmount,
+};
+use fedimint_core::util::NextOrPending;
+use fedimint_core::{apply, async_trait_maybe_send, OutPoint, TransactionId};
+pub use fedimint_nameservice_common as common;
+use fedimint_nameservice_common::config::NameClientConfig;
+use fedimint_nameservice_common::{
+    validate_endpoints, validate_name, validate_periods, NameCommonGen, NameInput, NameModuleTypes,
+    NameOutput, NameRecord, PaymentEndpoint, KIND,
+};
+use futures::{pin_mut, StreamExt};
+use secp256k1::{Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use states::NameStateMachine;
+
+use crate::api::NameFederationApi;
+
+pub mod api;
+mod states;
+
+/// Exposed API calls for client apps
+#[apply(async_trait_maybe_send!)]
+pub trait NameClientExt {
+    /// The key names registered by us are owned by
+    async fn name_public_key(&self) -> XOnlyPublicKey;
+
+    /// Registers a free or expired name pointing to our key and `endpoints`
+    /// for `periods` registration periods, paying the fee with ecash of the
+    /// primary module
+    async fn register_name(
+        &self,
+        name: String,
+        endpoints: Vec<PaymentEndpoint>,
+        periods: u64,
+    ) -> anyhow::Result<OperationId>;
+
+    /// Extends a name by `periods` registration periods before it expires, no
+    /// matter who owns it
+    async fn renew_name(&self, name: String, periods: u64) -> anyhow::Result<OperationId>;
+
+    /// Replaces the payment endpoints of a name we own
+    async fn update_name(
+        &self,
+        name: String,
+        endpoints: Vec<PaymentEndpoint>,
+    ) -> anyhow::Result<OperationId>;
+
+    /// Gives a name we own to the owner of `new_owner`, keeping its endpoints
+    async fn transfer_name(
+        &self,
+        name: String,
+        new_owner: XOnlyPublicKey,
+    ) -> anyhow::Result<OperationId>;
+
+    /// Subscribe to the state of an operation started by one of the functions
+    /// above
+    async fn subscribe_name_operation(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<UpdateStreamOrOutcome<'_, NameOperationState>>;
+
+    /// Looks up who to pay for a name, `None` if it isn't registered or
+    /// expired
+    async fn resolve_name(&self, name: &str) -> anyhow::Result<Option<NameRecord>>;
+}
+
+/// The high-level state of a name service operation
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum NameOperationState {
+    /// The transaction was submitted to the federation
+    Created,
+    /// The transaction was accepted and the ecash for change was issued
+    Done,
+    /// The transaction was rejected or the ecash couldn't be issued
+    Failed(String),
+}
+
+/// Stored in the operation log to show name service operations to the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NameMeta {
+    pub txid: TransactionId,
+    /// The output of the primary module receiving change
+    pub change: Option<OutPoint>,
+    pub variant: NameMetaVariant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NameMetaVariant {
+    Register {
+        name: String,
+        periods: u64,
+    },
+    Renew {
+        name: String,
+        periods: u64,
+    },
+    Update {
+        name: String,
+    },
+    Transfer {
+        name: String,
+        new_owner: XOnlyPublicKey,
+    },
+}
+
+#[apply(async_trait_maybe_send!)]
+impl NameClientExt for Client {
+    async fn name_public_key(&self) -> XOnlyPublicKey {
+        let (names, _instance) = self.get_first_module::<NameClientModule>(&KIND);
+        names.public_key()
+    }
+
+    async fn register_name(
+        &self,
+        name: String,
+        endpoints: Vec<PaymentEndpoint>,
+        periods: u64,
+    ) -> anyhow::Result<OperationId> {
+        validate_name(&name)?;
+        validate_endpoints(&endpoints)?;
+        validate_periods(periods)?;
+        let (names, _instance) = self.get_first_module::<NameClientModule>(&KIND);
+        let output = NameOutput::Register {
+            name: name.clone(),
+            owner: names.public_key(),
+            endpoints,
+            periods,
+        };
+        self.pay_name_output(output, NameMetaVariant::Register { name, periods })
+            .await
+    }
+
+    async fn renew_name(&self, name: String, periods: u64) -> anyhow::Result<OperationId> {
+        validate_periods(periods)?;
+        let output = NameOutput::Renew {
+            name: name.clone(),
+            periods,
+        };
+        self.pay_name_output(output, NameMetaVariant::Renew { name, periods })
+            .await
+    }
+
+    async fn update_name(
+        &self,
+        name: String,
+        endpoints: Vec<PaymentEndpoint>,
+    ) -> anyhow::Result<OperationId> {
+        validate_endpoints(&endpoints)?;
+        let (names, _instance) = self.get_first_module::<NameClientModule>(&KIND);
+        let input = NameInput {
+            name: name.clone(),
+            owner: names.public_key(),
+            endpoints,
+        };
+        self.sign_name_input(input, NameMetaVariant::Update { name })
+            .await
+    }
+
+    async fn transfer_name(
+        &self,
+        name: String,
+        new_owner: XOnlyPublicKey,
+    ) -> anyhow::Result<OperationId> {
+        let record = self
+            .resolve_name(&name)
+            .await?
+            .ok_or(anyhow!("Name {name} is not registered"))?;
+        let input = NameInput {
+            name: name.clone(),
+            owner: new_owner,
+            endpoints: record.endpoints,
+        };
+        self.sign_name_input(input, NameMetaVariant::Transfer { name, new_owner })
+            .await
+    }
+
+    async fn subscribe_name_operation(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<UpdateStreamOrOutcome<'_, NameOperationState>> {
+        let (names, _instance) = self.get_first_module::<NameClientModule>(&KIND);
+        let operation = name_operation(self, operation_id).await?;
+        let meta = operation.meta::<NameMeta>();
+
+        let tx_accepted_future = names.await_tx_accepted(operation_id);
+        let ecash_issued_future = meta
+            .change
+            .map(|out_point| self.await_primary_module_output(operation_id, out_point));
+
+        Ok(operation.outcome_or_updates(self.db(), operation_id, || {
+            stream! {
+                yield NameOperationState::Created;
+
+                match tx_accepted_future.await {
+                    Ok(()) => match ecash_issued_future {
+                        Some(ecash_issued_future) => match ecash_issued_future.await {
+                            Ok(_) => yield NameOperationState::Done,
+                            Err(e) => yield NameOperationState::Failed(e.to_string()),
+                        },
+                        None => yield NameOperationState::Done,
+                    },
+                    Err(e) => yield NameOperationState::Failed(e),
+                }
+            }
+        }))
+    }
+
+    async fn resolve_name(&self, name: &str) -> anyhow::Result<Option<NameRecord>> {
+        let (_names, instance) = self.get_first_module::<NameClientModule>(&KIND);
+        Ok(instance.api.resolve_name(name.to_string()).await?)
+    }
+}
+
+#[apply(async_trait_maybe_send!)]
+trait NameClientExtPrivate {
+    /// Submits a transaction with the output, funded by the primary module
+    async fn pay_name_output(
+        &self,
+        output: NameOutput,
+        variant: NameMetaVariant,
+    ) -> anyhow::Result<OperationId>;
+
+    /// Submits a transaction with the input, signed with our key
+    async fn sign_name_input(
+        &self,
+        input: NameInput,
+        variant: NameMetaVariant,
+    ) -> anyhow::Result<OperationId>;
+
+    async fn submit_name_transaction(
+        &self,
+        operation_id: OperationId,
+        variant: NameMetaVariant,
+        tx: TransactionBuilder,
+    ) -> anyhow::Result<OperationId>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl NameClientExtPrivate for Client {
+    async fn pay_name_output(
+        &self,
+        output: NameOutput,
+        variant: NameMetaVariant,
+    ) -> anyhow::Result<OperationId> {
+        let (_names, instance) = self.get_first_module::<NameClientModule>(&KIND);
+        let operation_id = OperationId(rand::random());
+
+        let output = ClientOutput {
+            output,
+            state_machines: Arc::new(move |txid, _| {
+                vec![NameStateMachine::Submitted(txid, operation_id)]
+            }),
+        };
+        let tx = TransactionBuilder::new().with_output(output.into_dyn(instance.id));
+
+        self.submit_name_transaction(operation_id, variant, tx)
+            .await
+    }
+
+    async fn sign_name_input(
+        &self,
+        input: NameInput,
+        variant: NameMetaVariant,
+    ) -> anyhow::Result<OperationId> {
+        let (names, instance) = self.get_first_module::<NameClientModule>(&KIND);
+        let operation_id = OperationId(rand::random());
+
+        let input = names.create_input(operation_id, input);
+        let tx = TransactionBuilder::new().with_input(input.into_dyn(instance.id));
+
+        self.submit_name_transaction(operation_id, variant, tx)
+            .await
+    }
+
+    async fn submit_name_transaction(
+        &self,
+        operation_id: OperationId,
+        variant: NameMetaVariant,
+        tx: TransactionBuilder,
+    ) -> anyhow::Result<OperationId> {
+        let operation_meta = move |txid, change| NameMeta {
+            txid,
+            change,
+            variant: variant.clone(),
+        };
+        self.finalize_and_submit_transaction(operation_id, KIND.as_str(), operation_meta, tx)
+            .await?;
+        Ok(operation_id)
+    }
+}
+
+async fn name_operation(
+    client: &Client,
+    operation_id: OperationId,
+) -> anyhow::Result<OperationLogEntry> {
+    let operation = client
+        .operation_log()
+        .get_operation(operation_id)
+        .await
+        .ok_or(anyhow!("Operation not found"))?;
+
+    if operation.operation_type() != KIND.as_str() {
+        bail!("Operation is not a name service operation");
+    }
+
+    Ok(operation)
+}
+
+#[derive(Debug)]
+pub struct NameClientModule {
+    cfg: NameClientConfig,
+    key: KeyPair,
+    notifier: ModuleNotifier<DynGlobalClientContext, NameStateMachine>,
+}
+
+/// Data needed by the state machine
+#[derive(Debug, Clone)]
+pub struct NameClientContext;
+
+// TODO: Boiler-plate
+impl Context for NameClientContext {}
+
+#[apply(async_trait_maybe_send!)]
+impl ClientModule for NameClientModule {
+    type Common = NameModuleTypes;
+    type ModuleStateMachineContext = NameClientContext;
+    type States = NameStateMachine;
+
+    fn context(&self) -> Self::ModuleStateMachineContext {
+        NameClientContext
+    }
+
+    fn input_amount(
+        &self,
+        _input: &<Self::Common as ModuleCommon>::Input,
+    ) -> TransactionItemAmount {
+        TransactionItemAmount::ZERO
+    }
+
+    fn output_amount(
+        &self,
+        output: &<Self::Common as ModuleCommon>::Output,
+    ) -> TransactionItemAmount {
+        TransactionItemAmount {
+            amount: self.cfg.period_fee * output.periods(),
+            fee: self.cfg.tx_fee,
+        }
+    }
+}
+
+impl NameClientModule {
+    fn public_key(&self) -> XOnlyPublicKey {
+        self.key.x_only_public_key().0
+    }
+
+    fn create_input(
+        &self,
+        operation_id: OperationId,
+        input: NameInput,
+    ) -> ClientInput<NameInput, NameStateMachine> {
+        ClientInput {
+            input,
+            keys: vec![self.key],
+            state_machines: Arc::new(move |txid, _| {
+                vec![NameStateMachine::Submitted(txid, operation_id)]
+            }),
+        }
+    }
+
+    async fn await_tx_accepted(&self, operation_id: OperationId) -> Result<(), String> {
+        let stream = self
+            .notifier
+            .subscribe(operation_id)
+            .await
+            .filter_map(|state| async move {
+                match state {
+                    NameStateMachine::Accepted(_) => Some(Ok(())),
+                    NameStateMachine::Rejected(_, e) => Some(Err(e)),
+                    NameStateMachine::Submitted(_, _) => None,
+                }
+            });
+
+        pin_mut!(stream);
+
+        stream.next_or_pending().await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NameClientGen;
+
+impl ExtendsCommonModuleGen for NameClientGen {
+    type Common = NameCommonGen;
+}
+
+/// Generates the client module
+#[apply(async_trait_maybe_send!)]
+impl ClientModuleGen for NameClientGen {
+    type Module = NameClientModule;
+    type Config = NameClientConfig;
+
+    fn supported_api_versions(&self) -> MultiApiVersion {
+        MultiApiVersion::try_from_iter([ApiVersion { major: 0, minor: 0 }])
+            .expect("no version conficts")
+    }
+
+    async fn init(
+        &self,
+        cfg: Self::Config,
+        _db: Database,
+        _api_version: ApiVersion,
+        module_root_secret: DerivableSecret,
+        notifier: ModuleNotifier<DynGlobalClientContext, <Self::Module as ClientModule>::States>,
+        _api: DynGlobalApi,
+        _module_api: DynModuleApi,
+    ) -> anyhow::Result<Self::Module> {
+        Ok(NameClientModule {
+            cfg,
+            key: module_root_secret.to_secp_key(&Secp256k1::new()),
+            notifier,
+        })
+    }
+}
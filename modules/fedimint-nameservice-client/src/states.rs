@@ -0,0 +1,69 @@
+use fedimint_client::sm::{DynState, OperationId, State, StateTransition};
+use fedimint_client::DynGlobalClientContext;
+use fedimint_core::core::{IntoDynInstance, ModuleInstanceId};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::TransactionId;
+
+use crate::NameClientContext;
+
+/// Tracks a transaction that registers, renews or updates a name
+///
+/// Fees are paid with ecash of the primary module, which takes care of its own
+/// inputs if the transaction is rejected, so there is nothing to refund here.
+#[derive(Debug, Clone, Eq, PartialEq, Decodable, Encodable)]
+pub enum NameStateMachine {
+    Submitted(TransactionId, OperationId),
+    Accepted(OperationId),
+    Rejected(OperationId, String),
+}
+
+impl State for NameStateMachine {
+    type ModuleContext = NameClientContext;
+    type GlobalContext = DynGlobalClientContext;
+
+    fn transitions(
+        &self,
+        _context: &Self::ModuleContext,
+        global_context: &Self::GlobalContext,
+    ) -> Vec<StateTransition<Self>> {
+        match self.clone() {
+            NameStateMachine::Submitted(txid, id) => vec![StateTransition::new(
+                await_tx_accepted(global_context.clone(), id, txid),
+                move |_dbtx, res, _state: Self| match res {
+                    Ok(()) => Box::pin(async move { NameStateMachine::Accepted(id) }),
+                    Err(e) => Box::pin(async move { NameStateMachine::Rejected(id, e) }),
+                },
+            )],
+            NameStateMachine::Accepted(_) => vec![],
+            NameStateMachine::Rejected(_, _) => vec![],
+        }
+    }
+
+    fn operation_id(&self) -> OperationId {
+        match self {
+            NameStateMachine::Submitted(_, id) => *id,
+            NameStateMachine::Accepted(id) => *id,
+            NameStateMachine::Rejected(id, _) => *id,
+        }
+    }
+}
+
+async fn await_tx_accepted(
+    context: DynGlobalClientContext,
+    id: OperationId,
+    txid: TransactionId,
+) -> Result<(), String> {
+    context
+        .await_tx_accepted(id, txid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// TODO: Boiler-plate
+impl IntoDynInstance for NameStateMachine {
+    type DynType = DynState<DynGlobalClientContext>;
+
+    fn into_dyn(self, instance_id: ModuleInstanceId) -> Self::DynType {
+        DynState::from_typed(instance_id, self)
+    }
+}
@@ -0,0 +1,92 @@
+use fedimint_core::core::ModuleKind;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{plugin_types_trait_impl_config, Amount, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::NameCommonGen;
+
+/// Parameters necessary to generate this module's configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NameGenParams {
+    pub local: NameGenParamsLocal,
+    pub consensus: NameGenParamsConsensus,
+}
+
+/// Local parameters for config generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NameGenParamsLocal {}
+
+/// Consensus parameters for config generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NameGenParamsConsensus {
+    /// Length of a registration period in seconds
+    pub period: u64,
+    /// Fee for registering or renewing a name for one period
+    pub period_fee: Amount,
+    pub tx_fee: Amount,
+}
+
+impl Default for NameGenParams {
+    fn default() -> Self {
+        Self {
+            local: NameGenParamsLocal {},
+            consensus: NameGenParamsConsensus {
+                period: 365 * 24 * 60 * 60,
+                period_fee: Amount::from_sats(1000),
+                tx_fee: Amount::ZERO,
+            },
+        }
+    }
+}
+
+/// Contains all the configuration for the server
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NameConfig {
+    pub local: NameConfigLocal,
+    pub private: NameConfigPrivate,
+    pub consensus: NameConfigConsensus,
+}
+
+/// Contains all the configuration for the client
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct NameClientConfig {
+    pub period: u64,
+    pub period_fee: Amount,
+    pub tx_fee: Amount,
+}
+
+/// Locally unencrypted config unique to each member
+#[derive(Clone, Debug, Serialize, Deserialize, Decodable, Encodable)]
+pub struct NameConfigLocal {
+    /// Our id, to know when we last voted for a time
+    pub peer_id: PeerId,
+}
+
+/// Will be the same for every federation member
+#[derive(Clone, Debug, Serialize, Deserialize, Decodable, Encodable)]
+pub struct NameConfigConsensus {
+    pub period: u64,
+    pub period_fee: Amount,
+    pub tx_fee: Amount,
+    /// Number of guardians whose clocks have to reach a time before the
+    /// federation considers it reached
+    pub threshold: u64,
+}
+
+/// Will be encrypted and not shared such as private key material, the name
+/// service doesn't need any
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NameConfigPrivate {}
+
+// Wire together the configs for this module
+plugin_types_trait_impl_config!(
+    NameCommonGen,
+    NameGenParams,
+    NameGenParamsLocal,
+    NameGenParamsConsensus,
+    NameConfig,
+    NameConfigLocal,
+    NameConfigPrivate,
+    NameConfigConsensus,
+    NameClientConfig
+);
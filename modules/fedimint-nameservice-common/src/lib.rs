@@ -0,0 +1,280 @@
+use std::fmt;
+
+use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::error::{code, ModuleErrorCode};
+use fedimint_core::module::{CommonModuleGen, ModuleCommon, ModuleConsensusVersion};
+use fedimint_core::plugin_types_trait_impl_common;
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Common contains types shared by both the client and server
+
+// The client and server configuration
+pub mod config;
+
+/// Unique name for this module
+pub const KIND: ModuleKind = ModuleKind::from_static_str("nameservice");
+
+/// Modules are non-compatible with older versions
+pub const CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion(0);
+
+/// Longest name that can be registered
+pub const MAX_NAME_LENGTH: usize = 32;
+
+/// Most payment endpoints a name can point to
+pub const MAX_ENDPOINTS: usize = 8;
+
+/// Longest payment endpoint, enough for a BOLT12 offer or an invite code
+pub const MAX_ENDPOINT_LENGTH: usize = 1024;
+
+/// Most registration periods that can be paid for at once
+pub const MAX_PERIODS: u64 = 10;
+
+/// Somewhere a user can be paid, resolved from their name
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentEndpoint {
+    /// A lightning address like `alice@example.com`
+    LightningAddress(String),
+    /// A reusable BOLT12 offer
+    Bolt12Offer(String),
+    /// Invite code of another federation the user receives ecash in
+    Invite(String),
+}
+
+impl PaymentEndpoint {
+    fn value(&self) -> &str {
+        match self {
+            PaymentEndpoint::LightningAddress(value)
+            | PaymentEndpoint::Bolt12Offer(value)
+            | PaymentEndpoint::Invite(value) => value,
+        }
+    }
+}
+
+/// What a registered name points to
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct NameRecord {
+    /// Key of the user, can update the record
+    pub owner: XOnlyPublicKey,
+    pub endpoints: Vec<PaymentEndpoint>,
+    /// Unix timestamp in seconds after which anyone can register the name
+    pub expires_at: u64,
+}
+
+impl NameRecord {
+    pub fn is_expired(&self, time: u64) -> bool {
+        self.expires_at <= time
+    }
+}
+
+/// Checks that a name only consists of lowercase letters, digits and inner
+/// dashes, so names that look alike can't be registered twice
+pub fn validate_name(name: &str) -> Result<(), NameError> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !name.starts_with('-')
+        && !name.ends_with('-');
+    if !valid {
+        return Err(NameError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+pub fn validate_endpoints(endpoints: &[PaymentEndpoint]) -> Result<(), NameError> {
+    if endpoints.len() > MAX_ENDPOINTS {
+        return Err(NameError::TooManyEndpoints);
+    }
+    if endpoints
+        .iter()
+        .any(|endpoint| endpoint.value().is_empty() || endpoint.value().len() > MAX_ENDPOINT_LENGTH)
+    {
+        return Err(NameError::InvalidEndpoint);
+    }
+    Ok(())
+}
+
+pub fn validate_periods(periods: u64) -> Result<(), NameError> {
+    if periods == 0 || periods > MAX_PERIODS {
+        return Err(NameError::InvalidPeriods);
+    }
+    Ok(())
+}
+
+/// Input for a fedimint transaction, replaces the owner and endpoints of a
+/// name and has to be signed by its current owner
+///
+/// Doesn't contribute any funds to the transaction.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct NameInput {
+    pub name: String,
+    /// Transfers the name if it differs from the current owner
+    pub owner: XOnlyPublicKey,
+    pub endpoints: Vec<PaymentEndpoint>,
+}
+
+/// Output for a fedimint transaction, pays the fee for a number of
+/// registration periods
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub enum NameOutput {
+    /// Registers a name that is free or expired
+    Register {
+        name: String,
+        owner: XOnlyPublicKey,
+        endpoints: Vec<PaymentEndpoint>,
+        periods: u64,
+    },
+    /// Extends a name that didn't expire yet, anyone can pay for it
+    Renew { name: String, periods: u64 },
+}
+
+impl NameOutput {
+    pub fn name(&self) -> &str {
+        match self {
+            NameOutput::Register { name, .. } | NameOutput::Renew { name, .. } => name,
+        }
+    }
+
+    pub fn periods(&self) -> u64 {
+        match self {
+            NameOutput::Register { periods, .. } | NameOutput::Renew { periods, .. } => *periods,
+        }
+    }
+}
+
+/// Information needed by a client to follow its registration
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct NameOutputOutcome {
+    pub name: String,
+    pub expires_at: u64,
+}
+
+/// Non-transaction items that will be submitted to consensus, the unix time
+/// in seconds observed by a guardian
+///
+/// Expiry is checked against the time a threshold of guardians reached, so
+/// no single guardian can let a name expire early.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct NameConsensusItem {
+    pub time: u64,
+}
+
+/// Errors that might be returned by the server
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Error)]
+pub enum NameError {
+    #[error("Invalid name {0:?}, only lowercase letters, digits and inner dashes are allowed")]
+    InvalidName(String),
+    #[error("Too many payment endpoints, at most {MAX_ENDPOINTS} are allowed")]
+    TooManyEndpoints,
+    #[error("Payment endpoints must not be empty or longer than {MAX_ENDPOINT_LENGTH} bytes")]
+    InvalidEndpoint,
+    #[error("Between 1 and {MAX_PERIODS} periods can be paid for")]
+    InvalidPeriods,
+    #[error("Name is already registered until {0}")]
+    NameTaken(u64),
+    #[error("Unknown or expired name")]
+    UnknownName,
+    #[error("The federation didn't agree on the time yet")]
+    TimeUnknown,
+}
+
+impl ModuleErrorCode for NameError {
+    fn code(&self) -> i32 {
+        match self {
+            NameError::InvalidName(_)
+            | NameError::TooManyEndpoints
+            | NameError::InvalidEndpoint
+            | NameError::InvalidPeriods => code::BAD_REQUEST,
+            NameError::NameTaken(_) => code::CONFLICT,
+            NameError::UnknownName => code::NOT_FOUND,
+            NameError::TimeUnknown => code::UNAVAILABLE,
+        }
+    }
+}
+
+/// Contains the types defined above
+pub struct NameModuleTypes;
+
+// Wire together the types for this module
+plugin_types_trait_impl_common!(
+    NameModuleTypes,
+    NameInput,
+    NameOutput,
+    NameOutputOutcome,
+    NameConsensusItem
+);
+
+#[derive(Debug)]
+pub struct NameCommonGen;
+
+impl CommonModuleGen for NameCommonGen {
+    const CONSENSUS_VERSION: ModuleConsensusVersion = CONSENSUS_VERSION;
+    const KIND: ModuleKind = KIND;
+
+    fn decoder() -> Decoder {
+        NameModuleTypes::decoder_builder().build()
+    }
+}
+
+impl fmt::Display for NameInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NameInput Update {}", self.name)
+    }
+}
+
+impl fmt::Display for NameOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NameOutput::Register { name, periods, .. } => {
+                write!(f, "NameOutput Register {name} for {periods} periods")
+            }
+            NameOutput::Renew { name, periods } => {
+                write!(f, "NameOutput Renew {name} for {periods} periods")
+            }
+        }
+    }
+}
+
+impl fmt::Display for NameOutputOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "NameOutputOutcome {} expires at {}",
+            self.name, self.expires_at
+        )
+    }
+}
+
+impl fmt::Display for NameConsensusItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "NameConsensusItem time {}", self.time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_must_be_canonical() {
+        for name in ["alice", "bob-2", "7"] {
+            assert_eq!(validate_name(name), Ok(()));
+        }
+        for name in [
+            "",
+            "Alice",
+            "-bob",
+            "bob-",
+            "al ice",
+            "alíce",
+            &"a".repeat(33),
+        ] {
+            assert!(validate_name(name).is_err(), "{name}");
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{impl_db_lookup, impl_db_record, OutPoint, PeerId};
+use fedimint_nameservice_common::{NameOutputOutcome, NameRecord};
+use serde::Serialize;
+use strum_macros::EnumIter;
+
+/// Namespaces DB keys for this module
+#[repr(u8)]
+#[derive(Clone, EnumIter, Debug)]
+pub enum DbKeyPrefix {
+    Name = 0x01,
+    Outcome = 0x02,
+    TimeVote = 0x03,
+}
+
+impl std::fmt::Display for DbKeyPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Lookup names or all names, expired names are kept until they are
+/// registered again
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct NameKey(pub String);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct NamePrefix;
+
+impl_db_record!(
+    key = NameKey,
+    value = NameRecord,
+    db_prefix = DbKeyPrefix::Name,
+);
+impl_db_lookup!(key = NameKey, query_prefix = NamePrefix);
+
+/// Lookup tx outputs by key or prefix
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct NameOutcomeKey(pub OutPoint);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct NameOutcomePrefix;
+
+impl_db_record!(
+    key = NameOutcomeKey,
+    value = NameOutputOutcome,
+    db_prefix = DbKeyPrefix::Outcome,
+);
+impl_db_lookup!(key = NameOutcomeKey, query_prefix = NameOutcomePrefix);
+
+/// The latest unix time in seconds a guardian voted for
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct TimeVoteKey(pub PeerId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct TimeVotePrefix;
+
+impl_db_record!(
+    key = TimeVoteKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::TimeVote,
+);
+impl_db_lookup!(key = TimeVoteKey, query_prefix = TimeVotePrefix);
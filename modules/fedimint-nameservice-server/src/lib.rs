@@ -0,0 +1,460 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::string::ToString;
+use std::time::Duration;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use fedimint_core::config::{
+    ClientModuleConfig, ConfigGenModuleParams, DkgResult, ServerModuleConfig,
+    ServerModuleConsensusConfig, TypedServerModuleConfig, TypedServerModuleConsensusConfig,
+};
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::db::{Database, DatabaseVersion, MigrationMap, ModuleDatabaseTransaction};
+use fedimint_core::module::audit::Audit;
+use fedimint_core::module::clock::ThresholdClock;
+use fedimint_core::module::interconnect::DynModuleInterconnect;
+use fedimint_core::module::{
+    api_endpoint, ApiEndpoint, ConsensusProposal, CoreConsensusVersion, ExtendsCommonModuleGen,
+    InputMeta, IntoCodedModuleError, ModuleConsensusVersion, ModuleError, PeerHandle,
+    ServerModuleGen, SupportedModuleApiVersions, TransactionItemAmount,
+};
+use fedimint_core::server::DynServerModule;
+use fedimint_core::task::{sleep, TaskGroup};
+use fedimint_core::{push_db_pair_items, NumPeers, OutPoint, PeerId, ServerModule};
+use fedimint_nameservice_common::config::{
+    NameClientConfig, NameConfig, NameConfigConsensus, NameConfigLocal, NameConfigPrivate,
+    NameGenParams,
+};
+use fedimint_nameservice_common::{
+    validate_endpoints, validate_name, validate_periods, NameCommonGen, NameConsensusItem,
+    NameError, NameInput, NameModuleTypes, NameOutput, NameOutputOutcome, NameRecord,
+    CONSENSUS_VERSION,
+};
+use futures::StreamExt;
+use strum::IntoEnumIterator;
+use tracing::info;
+
+use crate::db::{
+    DbKeyPrefix, NameKey, NameOutcomeKey, NameOutcomePrefix, NamePrefix, TimeVoteKey,
+    TimeVotePrefix,
+};
+
+mod db;
+
+/// How often guardians vote for their time, which is how precise expiry is
+const TIME_VOTE_INTERVAL: u64 = 600;
+
+/// How often to check whether it is time to vote again
+const TIME_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Generates the module
+#[derive(Debug, Clone)]
+pub struct NameGen;
+
+impl ExtendsCommonModuleGen for NameGen {
+    type Common = NameCommonGen;
+}
+
+/// Implementation of server module non-consensus functions
+#[async_trait]
+impl ServerModuleGen for NameGen {
+    type Params = NameGenParams;
+    const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
+    /// Returns the version of this module
+    fn versions(&self, _core: CoreConsensusVersion) -> &[ModuleConsensusVersion] {
+        &[CONSENSUS_VERSION]
+    }
+
+    fn supported_api_versions(&self) -> SupportedModuleApiVersions {
+        SupportedModuleApiVersions::from_raw(0, 0, &[(0, 0)])
+    }
+
+    /// Initialize the module
+    async fn init(
+        &self,
+        cfg: ServerModuleConfig,
+        _module_instance_id: ModuleInstanceId,
+        _our_id: PeerId,
+        _db: Database,
+        _task_group: &mut TaskGroup,
+        _interconnect: DynModuleInterconnect,
+    ) -> anyhow::Result<DynServerModule> {
+        Ok(NameService::new(cfg.to_typed()?).into())
+    }
+
+    /// DB migrations to move from old to newer versions
+    fn get_database_migrations(&self) -> MigrationMap {
+        MigrationMap::new()
+    }
+
+    /// Generates configs for all peers in a trusted manner for testing
+    fn trusted_dealer_gen(
+        &self,
+        peers: &[PeerId],
+        params: &ConfigGenModuleParams,
+    ) -> BTreeMap<PeerId, ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        peers
+            .iter()
+            .map(|&peer| {
+                let config = name_config(&params, peer, peers.threshold());
+                (peer, config.to_erased())
+            })
+            .collect()
+    }
+
+    /// Generates configs for all peers in an untrusted manner
+    async fn distributed_gen(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+    ) -> DkgResult<ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        // Names are owned by the keys of their users, so there is nothing to
+        // generate together
+        Ok(name_config(&params, peers.our_id, peers.peer_ids().threshold()).to_erased())
+    }
+
+    /// Converts the consensus config into the client config
+    fn get_client_config(
+        &self,
+        config: &ServerModuleConsensusConfig,
+    ) -> anyhow::Result<ClientModuleConfig> {
+        let config = NameConfigConsensus::from_erased(config)?;
+        Ok(ClientModuleConfig::from_typed(
+            config.kind(),
+            config.version(),
+            &(NameClientConfig {
+                period: config.period,
+                period_fee: config.period_fee,
+                tx_fee: config.tx_fee,
+            }),
+        )
+        .expect("Serialization can't fail"))
+    }
+
+    fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
+        let config = config.to_typed::<NameConfig>()?;
+        if config.local.peer_id != *identity {
+            bail!("Peer id doesn't match our identity");
+        }
+        if config.consensus.period == 0 {
+            bail!("Registration period must not be zero");
+        }
+        if config.consensus.threshold == 0 {
+            bail!("Threshold must not be zero");
+        }
+        Ok(())
+    }
+
+    /// Dumps all database items for debugging
+    async fn dump_database(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        prefix_names: Vec<String>,
+    ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_> {
+        let mut items: BTreeMap<String, Box<dyn erased_serde::Serialize + Send>> = BTreeMap::new();
+        let filtered_prefixes = DbKeyPrefix::iter().filter(|f| {
+            prefix_names.is_empty() || prefix_names.contains(&f.to_string().to_lowercase())
+        });
+
+        for table in filtered_prefixes {
+            match table {
+                DbKeyPrefix::Name => {
+                    push_db_pair_items!(dbtx, NamePrefix, NameKey, NameRecord, items, "Names");
+                }
+                DbKeyPrefix::Outcome => {
+                    push_db_pair_items!(
+                        dbtx,
+                        NameOutcomePrefix,
+                        NameOutcomeKey,
+                        NameOutputOutcome,
+                        items,
+                        "Name Outputs"
+                    );
+                }
+                DbKeyPrefix::TimeVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        TimeVotePrefix,
+                        TimeVoteKey,
+                        u64,
+                        items,
+                        "Time Votes"
+                    );
+                }
+            }
+        }
+
+        Box::new(items.into_iter())
+    }
+}
+
+fn name_config(params: &NameGenParams, peer_id: PeerId, threshold: usize) -> NameConfig {
+    NameConfig {
+        local: NameConfigLocal { peer_id },
+        private: NameConfigPrivate {},
+        consensus: NameConfigConsensus {
+            period: params.consensus.period,
+            period_fee: params.consensus.period_fee,
+            tx_fee: params.consensus.tx_fee,
+            threshold: threshold as u64,
+        },
+    }
+}
+
+/// Name service module
+#[derive(Debug)]
+pub struct NameService {
+    pub cfg: NameConfig,
+}
+
+/// Implementation of consensus for the server module
+#[async_trait]
+impl ServerModule for NameService {
+    /// Define the consensus types
+    type Common = NameModuleTypes;
+    type Gen = NameGen;
+    type VerificationCache = NameVerificationCache;
+
+    async fn await_consensus_proposal(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) {
+        while !self.consensus_proposal(dbtx).await.forces_new_epoch() {
+            sleep(TIME_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn consensus_proposal(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> ConsensusProposal<NameConsensusItem> {
+        match self.clock().proposal(dbtx, TIME_VOTE_INTERVAL).await {
+            Some(time) => ConsensusProposal::Trigger(vec![NameConsensusItem { time }]),
+            None => ConsensusProposal::empty(),
+        }
+    }
+
+    async fn begin_consensus_epoch<'a, 'b>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        consensus_items: Vec<(PeerId, NameConsensusItem)>,
+        _consensus_peers: &BTreeSet<PeerId>,
+    ) -> Vec<PeerId> {
+        self.clock()
+            .process_votes(
+                dbtx,
+                consensus_items
+                    .into_iter()
+                    .map(|(peer_id, item)| (peer_id, item.time)),
+            )
+            .await;
+
+        vec![]
+    }
+
+    fn build_verification_cache<'a>(
+        &'a self,
+        _inputs: impl Iterator<Item = &'a NameInput> + Send,
+    ) -> Self::VerificationCache {
+        NameVerificationCache
+    }
+
+    async fn validate_input<'a, 'b>(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        _verification_cache: &Self::VerificationCache,
+        input: &'a NameInput,
+    ) -> Result<InputMeta, ModuleError> {
+        validate_endpoints(&input.endpoints).into_module_error()?;
+        let record = self
+            .resolve(dbtx, &input.name)
+            .await
+            .ok_or(NameError::UnknownName)
+            .into_module_error()?;
+
+        Ok(InputMeta {
+            amount: TransactionItemAmount::ZERO,
+            // IMPORTANT: include the pubkey to validate the user signed this tx
+            pub_keys: vec![record.owner],
+        })
+    }
+
+    async fn apply_input<'a, 'b, 'c>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'c>,
+        input: &'b NameInput,
+        cache: &Self::VerificationCache,
+    ) -> Result<InputMeta, ModuleError> {
+        let meta = self.validate_input(dbtx, cache, input).await?;
+
+        let key = NameKey(input.name.clone());
+        let mut record = dbtx.get_value(&key).await.expect("Checked by validation");
+        record.owner = input.owner;
+        record.endpoints = input.endpoints.clone();
+        dbtx.insert_entry(&key, &record).await;
+
+        Ok(meta)
+    }
+
+    async fn validate_output(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        output: &NameOutput,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        self.expiry_after(dbtx, output).await.into_module_error()?;
+
+        Ok(TransactionItemAmount {
+            amount: self.cfg.consensus.period_fee * output.periods(),
+            fee: self.cfg.consensus.tx_fee,
+        })
+    }
+
+    async fn apply_output<'a, 'b>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        output: &'a NameOutput,
+        out_point: OutPoint,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        let amount = self.validate_output(dbtx, output).await?;
+
+        let expires_at = self
+            .expiry_after(dbtx, output)
+            .await
+            .expect("Checked by validation");
+        let key = NameKey(output.name().to_string());
+        let record = match output {
+            NameOutput::Register {
+                owner, endpoints, ..
+            } => NameRecord {
+                owner: *owner,
+                endpoints: endpoints.clone(),
+                expires_at,
+            },
+            NameOutput::Renew { .. } => NameRecord {
+                expires_at,
+                ..dbtx.get_value(&key).await.expect("Checked by validation")
+            },
+        };
+        dbtx.insert_entry(&key, &record).await;
+        dbtx.insert_new_entry(
+            &NameOutcomeKey(out_point),
+            &NameOutputOutcome {
+                name: output.name().to_string(),
+                expires_at,
+            },
+        )
+        .await;
+
+        info!(name = %output.name(), expires_at, "Name registered");
+        Ok(amount)
+    }
+
+    async fn end_consensus_epoch<'a, 'b>(
+        &'a self,
+        _consensus_peers: &BTreeSet<PeerId>,
+        _dbtx: &mut ModuleDatabaseTransaction<'b>,
+    ) -> Vec<PeerId> {
+        vec![]
+    }
+
+    async fn output_status(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        out_point: OutPoint,
+    ) -> Option<NameOutputOutcome> {
+        dbtx.get_value(&NameOutcomeKey(out_point)).await
+    }
+
+    async fn audit(&self, _dbtx: &mut ModuleDatabaseTransaction<'_>, _audit: &mut Audit) {
+        // Registration fees are kept by the federation, no funds are owed to
+        // anyone
+    }
+
+    fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
+        vec![
+            api_endpoint! {
+                // Returns the record of the name unless it expired
+                "resolve",
+                async |module: &NameService, context, name: String| -> Option<NameRecord> {
+                    Ok(module.resolve(&mut context.dbtx(), &name).await)
+                }
+            },
+            api_endpoint! {
+                // The time the expiry of names is checked against
+                "consensus_time",
+                async |module: &NameService, context, _params: ()| -> u64 {
+                    Ok(module.clock().consensus_time(&mut context.dbtx()).await)
+                }
+            },
+        ]
+    }
+}
+
+/// An in-memory cache we could use for faster validation
+#[derive(Debug, Clone)]
+pub struct NameVerificationCache;
+
+impl fedimint_core::server::VerificationCache for NameVerificationCache {}
+
+impl NameService {
+    /// Create new module instance
+    pub fn new(cfg: NameConfig) -> NameService {
+        NameService { cfg }
+    }
+
+    /// The record of the name if it is registered and didn't expire
+    async fn resolve(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        name: &str,
+    ) -> Option<NameRecord> {
+        let consensus_time = self.clock().consensus_time(dbtx).await;
+        dbtx.get_value(&NameKey(name.to_string()))
+            .await
+            .filter(|record| !record.is_expired(consensus_time))
+    }
+
+    /// When the name expires after the output paid for it
+    async fn expiry_after(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        output: &NameOutput,
+    ) -> Result<u64, NameError> {
+        validate_periods(output.periods())?;
+        let paid = output.periods() * self.cfg.consensus.period;
+
+        match output {
+            NameOutput::Register {
+                name, endpoints, ..
+            } => {
+                validate_name(name)?;
+                validate_endpoints(endpoints)?;
+                if let Some(record) = self.resolve(dbtx, name).await {
+                    return Err(NameError::NameTaken(record.expires_at));
+                }
+                let consensus_time = self.clock().consensus_time(dbtx).await;
+                if consensus_time == 0 {
+                    return Err(NameError::TimeUnknown);
+                }
+                Ok(consensus_time + paid)
+            }
+            NameOutput::Renew { name, .. } => {
+                let record = self
+                    .resolve(dbtx, name)
+                    .await
+                    .ok_or(NameError::UnknownName)?;
+                Ok(record.expires_at + paid)
+            }
+        }
+    }
+
+    /// The clock a threshold of guardians agrees on
+    fn clock(&self) -> ThresholdClock<TimeVoteKey, TimeVotePrefix> {
+        ThresholdClock::new(
+            TimeVoteKey,
+            TimeVotePrefix,
+            self.cfg.local.peer_id,
+            self.cfg.consensus.threshold as usize,
+        )
+    }
+}
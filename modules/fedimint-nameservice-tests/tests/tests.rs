@@ -0,0 +1,85 @@
+use fedimint_core::sats;
+use fedimint_core::util::NextOrPending;
+use fedimint_dummy_client::{DummyClientExt, DummyClientGen};
+use fedimint_dummy_common::config::DummyGenParams;
+use fedimint_dummy_server::DummyGen;
+use fedimint_nameservice_client::{NameClientExt, NameClientGen, NameOperationState};
+use fedimint_nameservice_common::config::NameGenParams;
+use fedimint_nameservice_common::PaymentEndpoint;
+use fedimint_nameservice_server::NameGen;
+use fedimint_testing::fixtures::Fixtures;
+
+fn fixtures() -> Fixtures {
+    let fixtures = Fixtures::new_primary(DummyClientGen, DummyGen, DummyGenParams::default());
+    let mut params = NameGenParams::default();
+    params.consensus.period_fee = sats(100);
+    fixtures.with_module(NameClientGen, NameGen, params)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn registered_names_resolve_and_can_be_transferred() -> anyhow::Result<()> {
+    let fed = fixtures().new_fed().await;
+    let (alice, bob) = fed.two_clients().await;
+    let (_, outpoint) = alice.print_money(sats(1000)).await?;
+    alice.receive_money(outpoint).await?;
+
+    let endpoints = vec![PaymentEndpoint::LightningAddress(
+        "alice@example.com".to_string(),
+    )];
+    let op = alice
+        .register_name("alice".to_string(), endpoints.clone(), 2)
+        .await?;
+    let mut sub = alice.subscribe_name_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, NameOperationState::Created);
+    assert_eq!(sub.ok().await?, NameOperationState::Done);
+    assert_eq!(alice.get_balance().await, sats(800));
+
+    let record = bob.resolve_name("alice").await?.expect("registered");
+    assert_eq!(record.owner, alice.name_public_key().await);
+    assert_eq!(record.endpoints, endpoints);
+
+    // Names can't be taken while they are registered
+    let (_, outpoint) = bob.print_money(sats(1000)).await?;
+    bob.receive_money(outpoint).await?;
+    let op = bob.register_name("alice".to_string(), vec![], 1).await?;
+    let mut sub = bob.subscribe_name_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, NameOperationState::Created);
+    assert!(matches!(sub.ok().await?, NameOperationState::Failed(_)));
+
+    let op = alice
+        .transfer_name("alice".to_string(), bob.name_public_key().await)
+        .await?;
+    let mut sub = alice.subscribe_name_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, NameOperationState::Created);
+    assert_eq!(sub.ok().await?, NameOperationState::Done);
+
+    let transferred = alice.resolve_name("alice").await?.expect("registered");
+    assert_eq!(transferred.owner, bob.name_public_key().await);
+    assert_eq!(transferred.expires_at, record.expires_at);
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn renewing_extends_expiry() -> anyhow::Result<()> {
+    let fed = fixtures().new_fed().await;
+    let client = fed.new_client().await;
+    let (_, outpoint) = client.print_money(sats(1000)).await?;
+    client.receive_money(outpoint).await?;
+
+    let op = client.register_name("bob".to_string(), vec![], 1).await?;
+    let mut sub = client.subscribe_name_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, NameOperationState::Created);
+    assert_eq!(sub.ok().await?, NameOperationState::Done);
+    let registered = client.resolve_name("bob").await?.expect("registered");
+
+    let op = client.renew_name("bob".to_string(), 3).await?;
+    let mut sub = client.subscribe_name_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, NameOperationState::Created);
+    assert_eq!(sub.ok().await?, NameOperationState::Done);
+    let renewed = client.resolve_name("bob").await?.expect("registered");
+
+    let period = NameGenParams::default().consensus.period;
+    assert_eq!(renewed.expires_at, registered.expires_at + 3 * period);
+    assert_eq!(client.get_balance().await, sats(600));
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use fedimint_core::api::{FederationApiExt, FederationResult, IModuleFederationApi};
+use fedimint_core::module::ApiRequestErased;
+use fedimint_core::task::{MaybeSend, MaybeSync};
+use fedimint_core::{apply, async_trait_maybe_send};
+
+use crate::OracleOutcome;
+
+#[apply(async_trait_maybe_send!)]
+pub trait OracleFederationApi {
+    async fn oracle_outcome(
+        &self,
+        feed: String,
+        round: u64,
+    ) -> FederationResult<Option<OracleOutcome>>;
+
+    async fn latest_oracle_outcome(&self, feed: String) -> FederationResult<Option<OracleOutcome>>;
+
+    /// Waits for the outcome of `round`, or of a later one if the guardians
+    /// skipped it
+    async fn wait_oracle_outcome(
+        &self,
+        feed: String,
+        round: u64,
+    ) -> FederationResult<OracleOutcome>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<T: ?Sized> OracleFederationApi for T
+where
+    T: IModuleFederationApi + MaybeSend + MaybeSync + 'static,
+{
+    async fn oracle_outcome(
+        &self,
+        feed: String,
+        round: u64,
+    ) -> FederationResult<Option<OracleOutcome>> {
+        self.request_current_consensus("outcome".to_string(), ApiRequestErased::new((feed, round)))
+            .await
+    }
+
+    async fn latest_oracle_outcome(&self, feed: String) -> FederationResult<Option<OracleOutcome>> {
+        self.request_current_consensus("latest_outcome".to_string(), ApiRequestErased::new(feed))
+            .await
+    }
+
+    async fn wait_oracle_outcome(
+        &self,
+        feed: String,
+        round: u64,
+    ) -> FederationResult<OracleOutcome> {
+        self.request_current_consensus(
+            "wait_outcome".to_string(),
+            ApiRequestErased::new((feed, round)),
+        )
+        .await
+    }
+}
@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+
+use fedimint_core::core::ModuleKind;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{plugin_types_trait_impl_config, PeerId};
+use secp256k1::{SecretKey, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::{Aggregation, OracleCommonGen, OracleValue};
+
+/// Parameters necessary to generate this module's configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OracleGenParams {
+    pub local: OracleGenParamsLocal,
+    pub consensus: OracleGenParamsConsensus,
+}
+
+/// Local parameters for config generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OracleGenParamsLocal {
+    /// Where this guardian observes each feed
+    pub sources: BTreeMap<String, OracleSource>,
+}
+
+/// Consensus parameters for config generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OracleGenParamsConsensus {
+    pub feeds: BTreeMap<String, FeedConfig>,
+}
+
+impl Default for OracleGenParams {
+    fn default() -> Self {
+        Self {
+            local: OracleGenParamsLocal {
+                sources: BTreeMap::from([(
+                    "btc_usd".to_string(),
+                    OracleSource::Fixed {
+                        value: OracleValue::Number(3_000_000),
+                    },
+                )]),
+            },
+            consensus: OracleGenParamsConsensus {
+                feeds: BTreeMap::from([(
+                    "btc_usd".to_string(),
+                    FeedConfig {
+                        interval: 60,
+                        aggregation: Aggregation::Median,
+                    },
+                )]),
+            },
+        }
+    }
+}
+
+/// A data feed attested by the guardians
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct FeedConfig {
+    /// Seconds between rounds, round `n` is observed at unix time
+    /// `n * interval`
+    pub interval: u64,
+    pub aggregation: Aggregation,
+}
+
+/// Where a guardian observes the values of a feed
+#[derive(Debug, Clone, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum OracleSource {
+    /// Always reports the same value, for testing
+    Fixed { value: OracleValue },
+    /// Fetches JSON from `url` and reads the value at the JSON `pointer`,
+    /// e.g. `/data/amount`
+    Http {
+        url: Url,
+        pointer: String,
+        format: ValueFormat,
+    },
+}
+
+/// How values read from a source are converted into [`OracleValue`]s
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum ValueFormat {
+    /// A decimal number or string, multiplied by `10^decimals` to get an
+    /// integer, e.g. 2 for prices in cents
+    Decimal { decimals: u32 },
+    /// A hex string such as a block header
+    Hex,
+}
+
+/// Contains all the configuration for the server
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OracleConfig {
+    pub local: OracleConfigLocal,
+    pub private: OracleConfigPrivate,
+    pub consensus: OracleConfigConsensus,
+}
+
+/// Contains all the configuration for the client
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct OracleClientConfig {
+    pub feeds: BTreeMap<String, FeedConfig>,
+    /// Keys the guardians sign their observations with
+    pub pub_keys: BTreeMap<PeerId, XOnlyPublicKey>,
+    /// Number of observations an outcome is aggregated from
+    pub threshold: u64,
+}
+
+/// Locally unencrypted config unique to each member
+#[derive(Clone, Debug, Serialize, Deserialize, Decodable, Encodable)]
+pub struct OracleConfigLocal {
+    pub sources: BTreeMap<String, OracleSource>,
+}
+
+/// Will be the same for every federation member
+#[derive(Clone, Debug, Serialize, Deserialize, Decodable, Encodable)]
+pub struct OracleConfigConsensus {
+    pub feeds: BTreeMap<String, FeedConfig>,
+    pub pub_keys: BTreeMap<PeerId, XOnlyPublicKey>,
+    pub threshold: u64,
+}
+
+/// Will be encrypted and not shared such as private key material
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OracleConfigPrivate {
+    /// Signs our observations
+    pub observation_key: SecretKey,
+}
+
+// Wire together the configs for this module
+plugin_types_trait_impl_config!(
+    OracleCommonGen,
+    OracleGenParams,
+    OracleGenParamsLocal,
+    OracleGenParamsConsensus,
+    OracleConfig,
+    OracleConfigLocal,
+    OracleConfigPrivate,
+    OracleConfigConsensus,
+    OracleClientConfig
+);
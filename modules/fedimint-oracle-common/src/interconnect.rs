@@ -0,0 +1,57 @@
+use fedimint_core::core::ModuleKind;
+use fedimint_core::module::interconnect::{DynModuleInterconnect, InterconnectMethod};
+use fedimint_core::module::ApiError;
+use fedimint_core::{apply, async_trait_maybe_send};
+
+use crate::{OracleOutcome, KIND};
+
+/// The outcome of round `.1` of feed `.0`, `None` if it wasn't attested (yet)
+pub struct OutcomeMethod;
+
+impl InterconnectMethod for OutcomeMethod {
+    const KIND: ModuleKind = KIND;
+    const NAME: &'static str = "outcome";
+    const VERSION: u32 = 0;
+    type Request = (String, u64);
+    type Response = Option<OracleOutcome>;
+}
+
+/// The outcome of the latest attested round of a feed
+pub struct LatestOutcomeMethod;
+
+impl InterconnectMethod for LatestOutcomeMethod {
+    const KIND: ModuleKind = KIND;
+    const NAME: &'static str = "latest_outcome";
+    const VERSION: u32 = 0;
+    type Request = String;
+    type Response = Option<OracleOutcome>;
+}
+
+/// Lets other server modules consume the outcomes of the oracle module
+#[apply(async_trait_maybe_send!)]
+pub trait OracleInterconnect {
+    /// The outcome of a round, `None` if it wasn't attested (yet)
+    async fn oracle_outcome(
+        &self,
+        feed: &str,
+        round: u64,
+    ) -> Result<Option<OracleOutcome>, ApiError>;
+
+    /// The outcome of the latest attested round of a feed
+    async fn latest_oracle_outcome(&self, feed: &str) -> Result<Option<OracleOutcome>, ApiError>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl OracleInterconnect for DynModuleInterconnect {
+    async fn oracle_outcome(
+        &self,
+        feed: &str,
+        round: u64,
+    ) -> Result<Option<OracleOutcome>, ApiError> {
+        self.call::<OutcomeMethod>((feed.to_string(), round)).await
+    }
+
+    async fn latest_oracle_outcome(&self, feed: &str) -> Result<Option<OracleOutcome>, ApiError> {
+        self.call::<LatestOutcomeMethod>(feed.to_string()).await
+    }
+}
@@ -0,0 +1,393 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{bail, ensure, Context};
+use bitcoin_hashes::{sha256, Hash, HashEngine};
+use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::error::{code, ModuleErrorCode};
+use fedimint_core::module::{CommonModuleGen, ModuleCommon, ModuleConsensusVersion};
+use fedimint_core::{plugin_types_trait_impl_common, PeerId};
+use secp256k1::{schnorr, KeyPair, Message, Secp256k1, Signing, Verification, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::OracleClientConfig;
+
+// Common contains types shared by both the client and server
+
+// The client and server configuration
+pub mod config;
+
+// Calls to the oracle API, for clients
+pub mod api;
+
+// Calls to the oracle API, for other server modules
+pub mod interconnect;
+
+/// Unique name for this module
+pub const KIND: ModuleKind = ModuleKind::from_static_str("oracle");
+
+/// Modules are non-compatible with older versions
+pub const CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion(0);
+
+/// Domain separation for signed observations
+const OBSERVATION_TAG: &[u8] = b"fedimint-oracle-observation";
+
+/// A data point observed by guardians
+#[derive(
+    Debug, Clone, Eq, PartialEq, Hash, Ord, PartialOrd, Serialize, Deserialize, Encodable, Decodable,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum OracleValue {
+    /// Numbers such as prices, scaled to integers by the sources
+    Number(i64),
+    /// Arbitrary data such as the block header of another chain
+    Bytes(Vec<u8>),
+}
+
+/// How the observations of the guardians are combined into one value
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum Aggregation {
+    /// The median of the numbers observed by a threshold of guardians, which
+    /// lies between the observations of honest guardians
+    Median,
+    /// A value observed identically by a threshold of guardians
+    Exact,
+}
+
+impl Aggregation {
+    /// Combines the observations once there are enough of them, `None`
+    /// otherwise
+    pub fn aggregate<'a>(
+        &self,
+        threshold: usize,
+        values: impl IntoIterator<Item = &'a OracleValue>,
+    ) -> Option<OracleValue> {
+        match self {
+            Aggregation::Median => {
+                let mut numbers = values
+                    .into_iter()
+                    .filter_map(|value| match value {
+                        OracleValue::Number(number) => Some(*number),
+                        OracleValue::Bytes(_) => None,
+                    })
+                    .collect::<Vec<_>>();
+                if numbers.len() < threshold || numbers.is_empty() {
+                    return None;
+                }
+                numbers.sort_unstable();
+                Some(OracleValue::Number(numbers[numbers.len() / 2]))
+            }
+            Aggregation::Exact => {
+                let mut counts = BTreeMap::<&OracleValue, usize>::new();
+                for value in values {
+                    *counts.entry(value).or_default() += 1;
+                }
+                counts
+                    .into_iter()
+                    .find(|(_, count)| *count >= threshold.max(1))
+                    .map(|(value, _)| value.clone())
+            }
+        }
+    }
+}
+
+/// A guardian's signed observation for a round of a feed
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct SignedObservation {
+    pub value: OracleValue,
+    pub signature: schnorr::Signature,
+}
+
+impl SignedObservation {
+    pub fn new<C: Signing>(
+        secp: &Secp256k1<C>,
+        keypair: &KeyPair,
+        feed: &str,
+        round: u64,
+        value: OracleValue,
+    ) -> Self {
+        let message = observation_message(feed, round, &value);
+        SignedObservation {
+            signature: secp.sign_schnorr(&message, keypair),
+            value,
+        }
+    }
+
+    pub fn verify<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        pub_key: &XOnlyPublicKey,
+        feed: &str,
+        round: u64,
+    ) -> bool {
+        let message = observation_message(feed, round, &self.value);
+        secp.verify_schnorr(&self.signature, &message, pub_key)
+            .is_ok()
+    }
+}
+
+fn observation_message(feed: &str, round: u64, value: &OracleValue) -> Message {
+    let mut engine = sha256::HashEngine::default();
+    engine.input(OBSERVATION_TAG);
+    feed.to_owned()
+        .consensus_encode(&mut engine)
+        .expect("Writing to HashEngine can't fail");
+    round
+        .consensus_encode(&mut engine)
+        .expect("Writing to HashEngine can't fail");
+    value
+        .consensus_encode(&mut engine)
+        .expect("Writing to HashEngine can't fail");
+    Message::from_slice(&sha256::Hash::from_engine(engine).into_inner())
+        .expect("Hashes have the right length")
+}
+
+/// The result of a round of a feed, including the observations it was
+/// aggregated from so anyone can verify it with the guardians' keys
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct OracleOutcome {
+    pub feed: String,
+    pub round: u64,
+    pub value: OracleValue,
+    pub observations: BTreeMap<PeerId, SignedObservation>,
+}
+
+impl OracleOutcome {
+    /// Checks the outcome was attested by a threshold of guardians
+    pub fn verify<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        config: &OracleClientConfig,
+    ) -> anyhow::Result<()> {
+        let feed = config
+            .feeds
+            .get(&self.feed)
+            .with_context(|| format!("Unknown feed {}", self.feed))?;
+        for (peer_id, observation) in &self.observations {
+            let pub_key = config
+                .pub_keys
+                .get(peer_id)
+                .with_context(|| format!("Unknown guardian {peer_id}"))?;
+            ensure!(
+                observation.verify(secp, pub_key, &self.feed, self.round),
+                "Invalid signature of guardian {peer_id}"
+            );
+        }
+        let value = feed.aggregation.aggregate(
+            config.threshold as usize,
+            self.observations
+                .values()
+                .map(|observation| &observation.value),
+        );
+        if value.as_ref() != Some(&self.value) {
+            bail!("Observations don't aggregate to {}", self.value);
+        }
+        Ok(())
+    }
+}
+
+/// The oracle doesn't take part in transactions, inputs are never valid
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct OracleInput;
+
+/// The oracle doesn't take part in transactions, outputs are never valid
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct OracleOutput;
+
+/// Never created since there are no valid outputs
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct OracleOutputOutcome;
+
+/// Non-transaction items that will be submitted to consensus
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct OracleConsensusItem {
+    pub feed: String,
+    pub round: u64,
+    pub observation: SignedObservation,
+}
+
+/// Errors that might be returned by the server
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Error)]
+pub enum OracleError {
+    #[error("The oracle module doesn't support transactions")]
+    NoTransactions,
+}
+
+impl ModuleErrorCode for OracleError {
+    fn code(&self) -> i32 {
+        match self {
+            OracleError::NoTransactions => code::BAD_REQUEST,
+        }
+    }
+}
+
+/// Contains the types defined above
+pub struct OracleModuleTypes;
+
+// Wire together the types for this module
+plugin_types_trait_impl_common!(
+    OracleModuleTypes,
+    OracleInput,
+    OracleOutput,
+    OracleOutputOutcome,
+    OracleConsensusItem
+);
+
+#[derive(Debug)]
+pub struct OracleCommonGen;
+
+impl CommonModuleGen for OracleCommonGen {
+    const CONSENSUS_VERSION: ModuleConsensusVersion = CONSENSUS_VERSION;
+    const KIND: ModuleKind = KIND;
+
+    fn decoder() -> Decoder {
+        OracleModuleTypes::decoder_builder().build()
+    }
+}
+
+impl fmt::Display for OracleValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OracleValue::Number(number) => write!(f, "{number}"),
+            OracleValue::Bytes(bytes) => {
+                for byte in bytes {
+                    write!(f, "{byte:02x}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for OracleInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OracleInput")
+    }
+}
+
+impl fmt::Display for OracleOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OracleOutput")
+    }
+}
+
+impl fmt::Display for OracleOutputOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "OracleOutputOutcome")
+    }
+}
+
+impl fmt::Display for OracleConsensusItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "OracleConsensusItem {} round {} value {}",
+            self.feed, self.round, self.observation.value
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use fedimint_core::PeerId;
+    use secp256k1::{KeyPair, Secp256k1};
+
+    use crate::config::{FeedConfig, OracleClientConfig};
+    use crate::{Aggregation, OracleOutcome, OracleValue, SignedObservation};
+
+    fn numbers(numbers: &[i64]) -> Vec<OracleValue> {
+        numbers.iter().map(|n| OracleValue::Number(*n)).collect()
+    }
+
+    #[test]
+    fn median_needs_threshold_of_numbers() {
+        let values = numbers(&[5, 1, 3]);
+        assert_eq!(Aggregation::Median.aggregate(4, &values), None);
+        assert_eq!(
+            Aggregation::Median.aggregate(3, &values),
+            Some(OracleValue::Number(3))
+        );
+
+        let mut values = numbers(&[5, 1]);
+        values.push(OracleValue::Bytes(vec![3]));
+        assert_eq!(Aggregation::Median.aggregate(3, &values), None);
+    }
+
+    #[test]
+    fn median_ignores_outliers() {
+        let values = numbers(&[100, 101, i64::MAX]);
+        assert_eq!(
+            Aggregation::Median.aggregate(3, &values),
+            Some(OracleValue::Number(101))
+        );
+    }
+
+    #[test]
+    fn exact_needs_threshold_of_equal_values() {
+        let header = OracleValue::Bytes(vec![1, 2, 3]);
+        let other = OracleValue::Bytes(vec![4, 5, 6]);
+        let values = vec![header.clone(), other.clone(), header.clone()];
+        assert_eq!(Aggregation::Exact.aggregate(3, &values), None);
+        assert_eq!(Aggregation::Exact.aggregate(2, &values), Some(header));
+    }
+
+    #[test]
+    fn verifies_outcomes() {
+        let secp = Secp256k1::new();
+        let keys = (0..4)
+            .map(|peer| {
+                (
+                    PeerId::from(peer),
+                    KeyPair::new(&secp, &mut rand::thread_rng()),
+                )
+            })
+            .collect::<BTreeMap<_, _>>();
+        let config = OracleClientConfig {
+            feeds: BTreeMap::from([(
+                "btc_usd".to_string(),
+                FeedConfig {
+                    interval: 60,
+                    aggregation: Aggregation::Median,
+                },
+            )]),
+            pub_keys: keys
+                .iter()
+                .map(|(peer, key)| (*peer, key.x_only_public_key().0))
+                .collect(),
+            threshold: 3,
+        };
+        let observations = keys
+            .iter()
+            .take(3)
+            .zip([100, 120, 110])
+            .map(|((peer, key), price)| {
+                let value = OracleValue::Number(price);
+                let observation = SignedObservation::new(&secp, key, "btc_usd", 7, value);
+                (*peer, observation)
+            })
+            .collect();
+        let mut outcome = OracleOutcome {
+            feed: "btc_usd".to_string(),
+            round: 7,
+            value: OracleValue::Number(110),
+            observations,
+        };
+        assert!(outcome.verify(&secp, &config).is_ok());
+
+        outcome.round = 8;
+        assert!(outcome.verify(&secp, &config).is_err());
+        outcome.round = 7;
+
+        outcome.value = OracleValue::Number(120);
+        assert!(outcome.verify(&secp, &config).is_err());
+        outcome.value = OracleValue::Number(110);
+
+        outcome.observations.remove(&PeerId::from(0));
+        assert!(outcome.verify(&secp, &config).is_err());
+    }
+}
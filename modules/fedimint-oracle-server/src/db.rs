@@ -0,0 +1,87 @@
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{impl_db_lookup, impl_db_record, PeerId};
+use fedimint_oracle_common::{OracleOutcome, SignedObservation};
+use serde::Serialize;
+use strum_macros::EnumIter;
+
+/// Namespaces DB keys for this module
+#[repr(u8)]
+#[derive(Clone, EnumIter, Debug)]
+pub enum DbKeyPrefix {
+    Observation = 0x01,
+    Outcome = 0x02,
+    LatestOutcome = 0x03,
+}
+
+impl std::fmt::Display for DbKeyPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// The latest observation of a guardian for a feed that isn't part of an
+/// outcome yet, only one is kept per guardian so they can't fill the database
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct ObservationKey {
+    pub feed: String,
+    pub peer_id: PeerId,
+}
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct PendingObservation {
+    pub round: u64,
+    pub observation: SignedObservation,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ObservationFeedPrefix {
+    pub feed: String,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ObservationPrefix;
+
+impl_db_record!(
+    key = ObservationKey,
+    value = PendingObservation,
+    db_prefix = DbKeyPrefix::Observation,
+);
+impl_db_lookup!(
+    key = ObservationKey,
+    query_prefix = ObservationFeedPrefix,
+    query_prefix = ObservationPrefix
+);
+
+/// Outcomes of all rounds of a feed that were attested
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct OutcomeKey {
+    pub feed: String,
+    pub round: u64,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OutcomePrefix;
+
+impl_db_record!(
+    key = OutcomeKey,
+    value = OracleOutcome,
+    db_prefix = DbKeyPrefix::Outcome,
+);
+impl_db_lookup!(key = OutcomeKey, query_prefix = OutcomePrefix);
+
+/// The outcome of the latest round of a feed, later rounds are the only ones
+/// guardians still observe
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct LatestOutcomeKey(pub String);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct LatestOutcomePrefix;
+
+impl_db_record!(
+    key = LatestOutcomeKey,
+    value = OracleOutcome,
+    db_prefix = DbKeyPrefix::LatestOutcome,
+    // Allows clients to wait for new rounds
+    notify_on_modify = true
+);
+impl_db_lookup!(key = LatestOutcomeKey, query_prefix = LatestOutcomePrefix);
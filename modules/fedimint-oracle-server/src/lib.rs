@@ -0,0 +1,641 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::string::ToString;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{bail, format_err};
+use async_trait::async_trait;
+use fedimint_core::config::{
+    ClientModuleConfig, ConfigGenModuleParams, DkgResult, ServerModuleConfig,
+    ServerModuleConsensusConfig, TypedServerModuleConfig, TypedServerModuleConsensusConfig,
+};
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::db::{Database, DatabaseVersion, MigrationMap, ModuleDatabaseTransaction};
+use fedimint_core::module::audit::Audit;
+use fedimint_core::module::interconnect::{
+    interconnect_handler, DynModuleInterconnect, InterconnectHandler,
+};
+use fedimint_core::module::{
+    api_endpoint, ApiEndpoint, ConsensusProposal, CoreConsensusVersion, ExtendsCommonModuleGen,
+    InputMeta, IntoCodedModuleError, ModuleConsensusVersion, ModuleError, PeerHandle,
+    ServerModuleGen, SupportedModuleApiVersions, TransactionItemAmount,
+};
+use fedimint_core::server::DynServerModule;
+use fedimint_core::task::{sleep, TaskGroup, TaskHandle};
+use fedimint_core::{push_db_pair_items, NumPeers, OutPoint, PeerId, ServerModule};
+use fedimint_oracle_common::config::{
+    OracleClientConfig, OracleConfig, OracleConfigConsensus, OracleConfigLocal,
+    OracleConfigPrivate, OracleGenParams,
+};
+use fedimint_oracle_common::interconnect::{LatestOutcomeMethod, OutcomeMethod};
+use fedimint_oracle_common::{
+    OracleCommonGen, OracleConsensusItem, OracleError, OracleInput, OracleModuleTypes,
+    OracleOutcome, OracleOutput, OracleOutputOutcome, SignedObservation, CONSENSUS_VERSION,
+};
+use fedimint_server::config::distributedgen::PeerHandleOps;
+use futures::StreamExt;
+use rand::rngs::OsRng;
+use secp256k1::{KeyPair, Secp256k1};
+use strum::IntoEnumIterator;
+use tokio::sync::Notify;
+use tracing::{debug, warn};
+
+use crate::db::{
+    DbKeyPrefix, LatestOutcomeKey, LatestOutcomePrefix, ObservationFeedPrefix, ObservationKey,
+    ObservationPrefix, OutcomeKey, OutcomePrefix, PendingObservation,
+};
+use crate::source::fetch_value;
+
+mod db;
+mod source;
+
+/// How long to wait before asking a source again after it failed
+const SOURCE_RETRY_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Generates the module
+#[derive(Debug, Clone)]
+pub struct OracleGen;
+
+impl ExtendsCommonModuleGen for OracleGen {
+    type Common = OracleCommonGen;
+}
+
+/// Implementation of server module non-consensus functions
+#[async_trait]
+impl ServerModuleGen for OracleGen {
+    type Params = OracleGenParams;
+    const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
+    /// Returns the version of this module
+    fn versions(&self, _core: CoreConsensusVersion) -> &[ModuleConsensusVersion] {
+        &[CONSENSUS_VERSION]
+    }
+
+    fn supported_api_versions(&self) -> SupportedModuleApiVersions {
+        SupportedModuleApiVersions::from_raw(0, 0, &[(0, 0)])
+    }
+
+    /// Initialize the module
+    async fn init(
+        &self,
+        cfg: ServerModuleConfig,
+        _module_instance_id: ModuleInstanceId,
+        _our_id: PeerId,
+        _db: Database,
+        task_group: &mut TaskGroup,
+        _interconnect: DynModuleInterconnect,
+    ) -> anyhow::Result<DynServerModule> {
+        Ok(Oracle::new(cfg.to_typed()?, task_group).await.into())
+    }
+
+    /// DB migrations to move from old to newer versions
+    fn get_database_migrations(&self) -> MigrationMap {
+        MigrationMap::new()
+    }
+
+    /// Generates configs for all peers in a trusted manner for testing
+    fn trusted_dealer_gen(
+        &self,
+        peers: &[PeerId],
+        params: &ConfigGenModuleParams,
+    ) -> BTreeMap<PeerId, ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        let secp = Secp256k1::new();
+        let keys = peers
+            .iter()
+            .map(|&peer| (peer, secp.generate_keypair(&mut OsRng)))
+            .collect::<BTreeMap<_, _>>();
+        let pub_keys = keys
+            .iter()
+            .map(|(peer, (_, pk))| (*peer, pk.x_only_public_key().0))
+            .collect::<BTreeMap<_, _>>();
+
+        keys.into_iter()
+            .map(|(peer, (sk, _))| {
+                let config = OracleConfig {
+                    local: OracleConfigLocal {
+                        sources: params.local.sources.clone(),
+                    },
+                    private: OracleConfigPrivate {
+                        observation_key: sk,
+                    },
+                    consensus: OracleConfigConsensus {
+                        feeds: params.consensus.feeds.clone(),
+                        pub_keys: pub_keys.clone(),
+                        threshold: peers.threshold() as u64,
+                    },
+                };
+                (peer, config.to_erased())
+            })
+            .collect()
+    }
+
+    /// Generates configs for all peers in an untrusted manner
+    async fn distributed_gen(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+    ) -> DkgResult<ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        let (sk, pk) = Secp256k1::new().generate_keypair(&mut OsRng);
+        let pub_keys = peers
+            .exchange_pubkeys("oracle".to_string(), pk)
+            .await?
+            .into_iter()
+            .map(|(peer, pk)| (peer, pk.x_only_public_key().0))
+            .collect();
+
+        Ok(OracleConfig {
+            local: OracleConfigLocal {
+                sources: params.local.sources.clone(),
+            },
+            private: OracleConfigPrivate {
+                observation_key: sk,
+            },
+            consensus: OracleConfigConsensus {
+                feeds: params.consensus.feeds.clone(),
+                pub_keys,
+                threshold: peers.peer_ids().threshold() as u64,
+            },
+        }
+        .to_erased())
+    }
+
+    /// Converts the consensus config into the client config
+    fn get_client_config(
+        &self,
+        config: &ServerModuleConsensusConfig,
+    ) -> anyhow::Result<ClientModuleConfig> {
+        let config = OracleConfigConsensus::from_erased(config)?;
+        Ok(ClientModuleConfig::from_typed(
+            config.kind(),
+            config.version(),
+            &(OracleClientConfig {
+                feeds: config.feeds.clone(),
+                pub_keys: config.pub_keys.clone(),
+                threshold: config.threshold,
+            }),
+        )
+        .expect("Serialization can't fail"))
+    }
+
+    fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
+        let config = config.to_typed::<OracleConfig>()?;
+        let keypair = KeyPair::from_secret_key(&Secp256k1::new(), &config.private.observation_key);
+        if config
+            .consensus
+            .pub_keys
+            .get(identity)
+            .ok_or_else(|| format_err!("No observation key for our identity"))?
+            != &keypair.x_only_public_key().0
+        {
+            bail!("Observation key doesn't match our public key");
+        }
+        if let Some((feed, _)) = config
+            .consensus
+            .feeds
+            .iter()
+            .find(|(_, feed)| feed.interval == 0)
+        {
+            bail!("Interval of feed {feed} must not be zero");
+        }
+        Ok(())
+    }
+
+    /// Dumps all database items for debugging
+    async fn dump_database(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        prefix_names: Vec<String>,
+    ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_> {
+        let mut items: BTreeMap<String, Box<dyn erased_serde::Serialize + Send>> = BTreeMap::new();
+        let filtered_prefixes = DbKeyPrefix::iter().filter(|f| {
+            prefix_names.is_empty() || prefix_names.contains(&f.to_string().to_lowercase())
+        });
+
+        for table in filtered_prefixes {
+            match table {
+                DbKeyPrefix::Observation => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ObservationPrefix,
+                        ObservationKey,
+                        PendingObservation,
+                        items,
+                        "Oracle Observations"
+                    );
+                }
+                DbKeyPrefix::Outcome => {
+                    push_db_pair_items!(
+                        dbtx,
+                        OutcomePrefix,
+                        OutcomeKey,
+                        OracleOutcome,
+                        items,
+                        "Oracle Outcomes"
+                    );
+                }
+                DbKeyPrefix::LatestOutcome => {
+                    push_db_pair_items!(
+                        dbtx,
+                        LatestOutcomePrefix,
+                        LatestOutcomeKey,
+                        OracleOutcome,
+                        items,
+                        "Oracle Latest Outcomes"
+                    );
+                }
+            }
+        }
+
+        Box::new(items.into_iter())
+    }
+}
+
+/// Oracle module
+#[derive(Debug)]
+pub struct Oracle {
+    pub cfg: OracleConfig,
+    /// Our latest signed observation for each feed
+    observations: Arc<Mutex<BTreeMap<String, OracleConsensusItem>>>,
+    observation_notify: Arc<Notify>,
+}
+
+/// Implementation of consensus for the server module
+#[async_trait]
+impl ServerModule for Oracle {
+    /// Define the consensus types
+    type Common = OracleModuleTypes;
+    type Gen = OracleGen;
+    type VerificationCache = OracleVerificationCache;
+
+    async fn await_consensus_proposal(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) {
+        // Wait until we observed something new
+        if !self.consensus_proposal(dbtx).await.forces_new_epoch() {
+            self.observation_notify.notified().await;
+        }
+    }
+
+    async fn consensus_proposal(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> ConsensusProposal<OracleConsensusItem> {
+        let observations = self
+            .observations
+            .lock()
+            .expect("Not poisoned")
+            .values()
+            .cloned()
+            .collect::<Vec<_>>();
+
+        // Only propose observations that aren't part of consensus yet
+        let mut items = vec![];
+        for item in observations {
+            let latest_round = latest_round(dbtx, &item.feed).await;
+            let pending = dbtx
+                .get_value(&ObservationKey {
+                    feed: item.feed.clone(),
+                    peer_id: self.our_peer_id(),
+                })
+                .await;
+            let is_new = pending.map_or(true, |pending| pending.round < item.round);
+            if latest_round.map_or(true, |latest| latest < item.round) && is_new {
+                items.push(item);
+            }
+        }
+        ConsensusProposal::new_auto_trigger(items)
+    }
+
+    async fn begin_consensus_epoch<'a, 'b>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        consensus_items: Vec<(PeerId, OracleConsensusItem)>,
+        _consensus_peers: &BTreeSet<PeerId>,
+    ) -> Vec<PeerId> {
+        let secp = Secp256k1::verification_only();
+        let mut drop_peers = vec![];
+
+        for (peer_id, item) in consensus_items {
+            let Some(feed) = self.cfg.consensus.feeds.get(&item.feed) else {
+                warn!(%peer_id, feed = %item.feed, "Observation for unknown feed");
+                drop_peers.push(peer_id);
+                continue;
+            };
+            let pub_key = self.cfg.consensus.pub_keys[&peer_id];
+            if !item
+                .observation
+                .verify(&secp, &pub_key, &item.feed, item.round)
+            {
+                warn!(%peer_id, feed = %item.feed, "Invalid observation signature");
+                drop_peers.push(peer_id);
+                continue;
+            }
+
+            // Rounds can't be changed once they have an outcome
+            if latest_round(dbtx, &item.feed)
+                .await
+                .map_or(false, |latest| latest >= item.round)
+            {
+                continue;
+            }
+
+            let key = ObservationKey {
+                feed: item.feed.clone(),
+                peer_id,
+            };
+            if let Some(pending) = dbtx.get_value(&key).await {
+                if pending.round >= item.round {
+                    continue;
+                }
+            }
+            dbtx.insert_entry(
+                &key,
+                &PendingObservation {
+                    round: item.round,
+                    observation: item.observation,
+                },
+            )
+            .await;
+
+            let observations = dbtx
+                .find_by_prefix(&ObservationFeedPrefix {
+                    feed: item.feed.clone(),
+                })
+                .await
+                .filter_map(|(key, pending)| async move {
+                    (pending.round == item.round).then_some((key.peer_id, pending.observation))
+                })
+                .collect::<BTreeMap<_, _>>()
+                .await;
+            let Some(value) = feed.aggregation.aggregate(
+                self.cfg.consensus.threshold as usize,
+                observations.values().map(|observation| &observation.value),
+            ) else {
+                continue;
+            };
+
+            let outcome = OracleOutcome {
+                feed: item.feed.clone(),
+                round: item.round,
+                value,
+                observations,
+            };
+            debug!(feed = %outcome.feed, round = outcome.round, value = %outcome.value, "Oracle outcome");
+            self.finish_round(dbtx, outcome).await;
+        }
+
+        drop_peers
+    }
+
+    fn build_verification_cache<'a>(
+        &'a self,
+        _inputs: impl Iterator<Item = &'a OracleInput> + Send,
+    ) -> Self::VerificationCache {
+        OracleVerificationCache
+    }
+
+    async fn validate_input<'a, 'b>(
+        &self,
+        _dbtx: &mut ModuleDatabaseTransaction<'b>,
+        _verification_cache: &Self::VerificationCache,
+        _input: &'a OracleInput,
+    ) -> Result<InputMeta, ModuleError> {
+        Err(OracleError::NoTransactions).into_module_error()
+    }
+
+    async fn apply_input<'a, 'b, 'c>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'c>,
+        input: &'b OracleInput,
+        cache: &Self::VerificationCache,
+    ) -> Result<InputMeta, ModuleError> {
+        self.validate_input(dbtx, cache, input).await
+    }
+
+    async fn validate_output(
+        &self,
+        _dbtx: &mut ModuleDatabaseTransaction<'_>,
+        _output: &OracleOutput,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        Err(OracleError::NoTransactions).into_module_error()
+    }
+
+    async fn apply_output<'a, 'b>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        output: &'a OracleOutput,
+        _out_point: OutPoint,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        self.validate_output(dbtx, output).await
+    }
+
+    async fn end_consensus_epoch<'a, 'b>(
+        &'a self,
+        _consensus_peers: &BTreeSet<PeerId>,
+        _dbtx: &mut ModuleDatabaseTransaction<'b>,
+    ) -> Vec<PeerId> {
+        vec![]
+    }
+
+    async fn output_status(
+        &self,
+        _dbtx: &mut ModuleDatabaseTransaction<'_>,
+        _out_point: OutPoint,
+    ) -> Option<OracleOutputOutcome> {
+        None
+    }
+
+    async fn audit(&self, _dbtx: &mut ModuleDatabaseTransaction<'_>, _audit: &mut Audit) {
+        // The oracle holds no funds
+    }
+
+    fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
+        vec![
+            api_endpoint! {
+                "outcome",
+                async |_module: &Oracle, context, params: (String, u64)| -> Option<OracleOutcome> {
+                    let (feed, round) = params;
+                    Ok(context.dbtx().get_value(&OutcomeKey { feed, round }).await)
+                }
+            },
+            api_endpoint! {
+                "latest_outcome",
+                async |_module: &Oracle, context, feed: String| -> Option<OracleOutcome> {
+                    Ok(context.dbtx().get_value(&LatestOutcomeKey(feed)).await)
+                }
+            },
+            api_endpoint! {
+                // Waits for the outcome of a round, or of a later one if it was skipped
+                "wait_outcome",
+                async |_module: &Oracle, context, params: (String, u64)| -> OracleOutcome {
+                    let (feed, round) = params;
+                    Ok(context.wait_value_matches(LatestOutcomeKey(feed), move |outcome| outcome.round >= round).await)
+                }
+            },
+        ]
+    }
+
+    fn interconnect_handlers(&self) -> Vec<InterconnectHandler<Self>> {
+        vec![
+            interconnect_handler! {
+                OutcomeMethod,
+                async |_module: &Oracle, dbtx, request| {
+                    let (feed, round) = request;
+                    Ok(dbtx.get_value(&OutcomeKey { feed, round }).await)
+                }
+            },
+            interconnect_handler! {
+                LatestOutcomeMethod,
+                async |_module: &Oracle, dbtx, feed| {
+                    Ok(dbtx.get_value(&LatestOutcomeKey(feed)).await)
+                }
+            },
+        ]
+    }
+}
+
+/// An in-memory cache we could use for faster validation
+#[derive(Debug, Clone)]
+pub struct OracleVerificationCache;
+
+impl fedimint_core::server::VerificationCache for OracleVerificationCache {}
+
+impl Oracle {
+    /// Create new module instance and start observing its feeds
+    pub async fn new(cfg: OracleConfig, task_group: &mut TaskGroup) -> Oracle {
+        let oracle = Oracle {
+            cfg,
+            observations: Default::default(),
+            observation_notify: Arc::new(Notify::new()),
+        };
+
+        let observer = Observer {
+            cfg: oracle.cfg.clone(),
+            observations: oracle.observations.clone(),
+            observation_notify: oracle.observation_notify.clone(),
+        };
+        task_group
+            .spawn_supervised("oracle observer", move |handle| {
+                let observer = observer.clone();
+                async move {
+                    observer.run(&handle).await;
+                }
+            })
+            .await;
+
+        oracle
+    }
+
+    fn our_peer_id(&self) -> PeerId {
+        let keypair =
+            KeyPair::from_secret_key(&Secp256k1::new(), &self.cfg.private.observation_key);
+        let pub_key = keypair.x_only_public_key().0;
+        *self
+            .cfg
+            .consensus
+            .pub_keys
+            .iter()
+            .find(|(_, key)| **key == pub_key)
+            .expect("Checked by config validation")
+            .0
+    }
+
+    async fn finish_round(&self, dbtx: &mut ModuleDatabaseTransaction<'_>, outcome: OracleOutcome) {
+        // Observations of this or earlier rounds can't be used anymore
+        let outdated = dbtx
+            .find_by_prefix(&ObservationFeedPrefix {
+                feed: outcome.feed.clone(),
+            })
+            .await
+            .filter_map(
+                |(key, pending)| async move { (pending.round <= outcome.round).then_some(key) },
+            )
+            .collect::<Vec<_>>()
+            .await;
+        for key in outdated {
+            dbtx.remove_entry(&key).await;
+        }
+
+        dbtx.insert_new_entry(
+            &OutcomeKey {
+                feed: outcome.feed.clone(),
+                round: outcome.round,
+            },
+            &outcome,
+        )
+        .await;
+        dbtx.insert_entry(&LatestOutcomeKey(outcome.feed.clone()), &outcome)
+            .await;
+    }
+}
+
+async fn latest_round(dbtx: &mut ModuleDatabaseTransaction<'_>, feed: &str) -> Option<u64> {
+    dbtx.get_value(&LatestOutcomeKey(feed.to_string()))
+        .await
+        .map(|outcome| outcome.round)
+}
+
+/// Observes the feeds at our sources once per round
+#[derive(Clone)]
+struct Observer {
+    cfg: OracleConfig,
+    observations: Arc<Mutex<BTreeMap<String, OracleConsensusItem>>>,
+    observation_notify: Arc<Notify>,
+}
+
+impl Observer {
+    async fn run(self, handle: &TaskHandle) {
+        let secp = Secp256k1::new();
+        let keypair = KeyPair::from_secret_key(&secp, &self.cfg.private.observation_key);
+        let mut observed_rounds = BTreeMap::<String, u64>::new();
+
+        while !handle.is_shutting_down() {
+            let now = unix_time();
+            let mut wake_at = u64::MAX;
+
+            for (feed, feed_cfg) in &self.cfg.consensus.feeds {
+                let Some(source) = self.cfg.local.sources.get(feed) else {
+                    continue;
+                };
+                let round = now / feed_cfg.interval;
+                if observed_rounds.get(feed) == Some(&round) {
+                    wake_at = wake_at.min((round + 1) * feed_cfg.interval);
+                    continue;
+                }
+
+                match fetch_value(source).await {
+                    Ok(value) => {
+                        let observation =
+                            SignedObservation::new(&secp, &keypair, feed, round, value);
+                        let item = OracleConsensusItem {
+                            feed: feed.clone(),
+                            round,
+                            observation,
+                        };
+                        self.observations
+                            .lock()
+                            .expect("Not poisoned")
+                            .insert(feed.clone(), item);
+                        self.observation_notify.notify_one();
+                        observed_rounds.insert(feed.clone(), round);
+                        wake_at = wake_at.min((round + 1) * feed_cfg.interval);
+                    }
+                    Err(e) => {
+                        warn!("Failed to observe oracle feed {feed}: {e:#}");
+                        wake_at = wake_at.min(now + SOURCE_RETRY_INTERVAL.as_secs());
+                    }
+                }
+            }
+
+            let sleep_secs = wake_at.saturating_sub(unix_time()).clamp(1, 3600);
+            sleep(Duration::from_secs(sleep_secs)).await;
+        }
+    }
+}
+
+fn unix_time() -> u64 {
+    fedimint_core::time::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Time is after the unix epoch")
+        .as_secs()
+}
@@ -0,0 +1,81 @@
+use anyhow::{ensure, Context};
+use bitcoin_hashes::hex::FromHex;
+use fedimint_oracle_common::config::{OracleSource, ValueFormat};
+use fedimint_oracle_common::OracleValue;
+use serde_json::Value;
+
+/// Observes the current value of a feed at `source`
+pub async fn fetch_value(source: &OracleSource) -> anyhow::Result<OracleValue> {
+    match source {
+        OracleSource::Fixed { value } => Ok(value.clone()),
+        OracleSource::Http {
+            url,
+            pointer,
+            format,
+        } => {
+            let response: Value = reqwest::get(url.clone())
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            let value = response
+                .pointer(pointer)
+                .with_context(|| format!("Response of {url} has no {pointer}"))?;
+            parse_value(value, *format)
+        }
+    }
+}
+
+fn parse_value(value: &Value, format: ValueFormat) -> anyhow::Result<OracleValue> {
+    match format {
+        ValueFormat::Decimal { decimals } => {
+            // APIs return numbers as JSON numbers or strings
+            let number = match value {
+                Value::Number(number) => number.as_f64(),
+                Value::String(string) => string.parse::<f64>().ok(),
+                _ => None,
+            }
+            .context("Value is not a number")?;
+            let scaled = (number * 10f64.powi(decimals as i32)).round();
+            ensure!(
+                scaled.is_finite() && scaled.abs() < i64::MAX as f64,
+                "Value {number} is out of range"
+            );
+            Ok(OracleValue::Number(scaled as i64))
+        }
+        ValueFormat::Hex => {
+            let hex = value.as_str().context("Value is not a string")?;
+            Ok(OracleValue::Bytes(Vec::<u8>::from_hex(hex)?))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_oracle_common::config::ValueFormat;
+    use fedimint_oracle_common::OracleValue;
+    use serde_json::json;
+
+    use crate::source::parse_value;
+
+    #[test]
+    fn parses_values() {
+        let cents = ValueFormat::Decimal { decimals: 2 };
+        assert_eq!(
+            parse_value(&json!(30123.456), cents).unwrap(),
+            OracleValue::Number(3_012_346)
+        );
+        assert_eq!(
+            parse_value(&json!("30123.45"), cents).unwrap(),
+            OracleValue::Number(3_012_345)
+        );
+        assert!(parse_value(&json!(true), cents).is_err());
+        assert!(parse_value(&json!(1e300), cents).is_err());
+
+        assert_eq!(
+            parse_value(&json!("00ff"), ValueFormat::Hex).unwrap(),
+            OracleValue::Bytes(vec![0, 255])
+        );
+        assert!(parse_value(&json!("0g"), ValueFormat::Hex).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use fedimint_core::api::{FederationApiExt, FederationResult, IModuleFederationApi};
+use fedimint_core::module::ApiRequestErased;
+use fedimint_core::task::{MaybeSend, MaybeSync};
+use fedimint_core::{apply, async_trait_maybe_send, Amount};
+use fedimint_predictionmarket_common::{Market, MarketId, Order, OrderId, Resolution};
+use secp256k1::XOnlyPublicKey;
+
+#[apply(async_trait_maybe_send!)]
+pub trait MarketFederationApi {
+    async fn market(&self, market: MarketId) -> FederationResult<Option<Market>>;
+
+    async fn market_order_book(&self, market: MarketId) -> FederationResult<Vec<(OrderId, Order)>>;
+
+    async fn market_order(&self, order: OrderId) -> FederationResult<Option<Order>>;
+
+    async fn wait_market_order_update(
+        &self,
+        order: OrderId,
+        filled: u64,
+    ) -> FederationResult<Order>;
+
+    async fn wait_market_resolved(&self, market: MarketId) -> FederationResult<Resolution>;
+
+    async fn market_balance(&self, owner: XOnlyPublicKey) -> FederationResult<Amount>;
+
+    async fn market_shares(
+        &self,
+        market: MarketId,
+        owner: XOnlyPublicKey,
+    ) -> FederationResult<Vec<u64>>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<T: ?Sized> MarketFederationApi for T
+where
+    T: IModuleFederationApi + MaybeSend + MaybeSync + 'static,
+{
+    async fn market(&self, market: MarketId) -> FederationResult<Option<Market>> {
+        self.request_current_consensus("market".to_string(), ApiRequestErased::new(market))
+            .await
+    }
+
+    async fn market_order_book(&self, market: MarketId) -> FederationResult<Vec<(OrderId, Order)>> {
+        self.request_current_consensus("order_book".to_string(), ApiRequestErased::new(market))
+            .await
+    }
+
+    async fn market_order(&self, order: OrderId) -> FederationResult<Option<Order>> {
+        self.request_current_consensus("order".to_string(), ApiRequestErased::new(order))
+            .await
+    }
+
+    async fn wait_market_order_update(
+        &self,
+        order: OrderId,
+        filled: u64,
+    ) -> FederationResult<Order> {
+        self.request_current_consensus(
+            "wait_order_update".to_string(),
+            ApiRequestErased::new((order, filled)),
+        )
+        .await
+    }
+
+    async fn wait_market_resolved(&self, market: MarketId) -> FederationResult<Resolution> {
+        self.request_current_consensus(
+            "wait_market_resolved".to_string(),
+            ApiRequestErased::new(market),
+        )
+        .await
+    }
+
+    async fn market_balance(&self, owner: XOnlyPublicKey) -> FederationResult<Amount> {
+        self.request_current_consensus("balance".to_string(), ApiRequestErased::new(owner))
+            .await
+    }
+
+    async fn market_shares(
+        &self,
+        market: MarketId,
+        owner: XOnlyPublicKey,
+    ) -> FederationResult<Vec<u64>> {
+        self.request_current_consensus("shares".to_string(), ApiRequestErased::new((market, owner)))
+            .await
+    }
+}
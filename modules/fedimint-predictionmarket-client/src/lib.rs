@@ -0,0 +1,637 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context as _};
+use async_stream::stream;
+use fedimint_client::derivable_secret::DerivableSecret;
+use fedimint_client::module::gen::ClientModuleGen;
+use fedimint_client::module::ClientModule;
+use fedimint_client::oplog::{OperationLogEntry, UpdateStreamOrOutcome};
+use fedimint_client::sm::{Context, ModuleNotifier, OperationId};
+use fedimint_client::transaction::{ClientInput, ClientOutput, TransactionBuilder};
+use fedimint_client::{Client, DynGlobalClientContext};
+use fedimint_core::api::{DynGlobalApi, DynModuleApi};
+use fedimint_core::core::{IntoDynInstance, KeyPair};
+use fedimint_core::db::Database;
+use fedimint_core::module::{
+    ApiVersion, ExtendsCommonModuleGen, ModuleCommon, MultiApiVersion, TransactionItemAmount,
+};
+use fedimint_core::util::NextOrPending;
+use fedimint_core::{apply, async_trait_maybe_send, Amount, OutPoint, TransactionId};
+pub use fedimint_predictionmarket_common as common;
+use fedimint_predictionmarket_common::config::MarketClientConfig;
+use fedimint_predictionmarket_common::{
+    Market, MarketCommonGen, MarketId, MarketInput, MarketModuleTypes, MarketOutput, MarketParams,
+    NewOrder, Order, OrderId, OrderState, Resolution, Side, KIND,
+};
+use futures::{pin_mut, StreamExt};
+use secp256k1::{Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use states::MarketStateMachine;
+
+use crate::api::MarketFederationApi;
+
+pub mod api;
+mod states;
+
+/// Exposed API calls for client apps
+#[apply(async_trait_maybe_send!)]
+pub trait MarketClientExt {
+    /// Creates a market, paying the creation fee with ecash of the primary
+    /// module
+    async fn create_market(&self, params: MarketParams) -> anyhow::Result<(OperationId, MarketId)>;
+
+    /// Buys `quantity` sets of one share per outcome of the market for the
+    /// contract price each
+    async fn mint_outcome_sets(
+        &self,
+        market: MarketId,
+        quantity: u64,
+    ) -> anyhow::Result<OperationId>;
+
+    /// Sells `quantity` sets of one share per outcome back to the market for
+    /// the contract price each, the proceeds are issued as ecash
+    async fn redeem_outcome_sets(
+        &self,
+        market: MarketId,
+        quantity: u64,
+    ) -> anyhow::Result<OperationId>;
+
+    /// Places an order to buy or sell shares of an outcome for at most or at
+    /// least `price` each
+    ///
+    /// Buy orders are funded with ecash of the primary module, sell orders
+    /// with our shares of the outcome. The [`OrderId`] of the order is the
+    /// returned operation id.
+    async fn place_market_order(
+        &self,
+        market: MarketId,
+        outcome: u8,
+        side: Side,
+        price: Amount,
+        quantity: u64,
+    ) -> anyhow::Result<OperationId>;
+
+    /// Cancels the unfilled part of one of our orders, its funds or shares are
+    /// returned to our balance or shares
+    async fn cancel_market_order(&self, order: OrderId) -> anyhow::Result<OperationId>;
+
+    /// Withdraws funds from sales, cancelled orders and payouts into ecash of
+    /// the primary module
+    async fn withdraw_market_balance(&self, amount: Amount) -> anyhow::Result<OperationId>;
+
+    /// Subscribe to the state of an operation started by one of the functions
+    /// above
+    async fn subscribe_market_operation(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<UpdateStreamOrOutcome<'_, MarketOperationState>>;
+
+    async fn get_market(&self, market: MarketId) -> anyhow::Result<Option<Market>>;
+
+    /// Returns the open orders of a market
+    async fn market_order_book(&self, market: MarketId) -> anyhow::Result<Vec<(OrderId, Order)>>;
+
+    /// Returns our funds that can be withdrawn
+    async fn market_balance(&self) -> anyhow::Result<Amount>;
+
+    /// Returns our shares of each outcome of the market, not counting shares
+    /// offered in sell orders
+    async fn market_shares(&self, market: MarketId) -> anyhow::Result<Vec<u64>>;
+
+    /// Waits until the market is resolved and our shares were paid out into
+    /// our balance
+    async fn await_market_resolved(&self, market: MarketId) -> anyhow::Result<Resolution>;
+}
+
+/// The high-level state of a prediction market operation
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum MarketOperationState {
+    /// The transaction was submitted to the federation
+    Created,
+    /// The transaction was accepted and the ecash for withdrawn funds or change
+    /// was issued
+    Done,
+    /// The order rests in the order book, `filled` shares were traded so far
+    OrderOpen { filled: u64 },
+    /// All shares of the order were traded
+    OrderFilled,
+    /// The order was cancelled after `filled` shares were traded
+    OrderCancelled { filled: u64 },
+    /// The transaction was rejected or the ecash couldn't be issued
+    Failed(String),
+}
+
+/// Stored in the operation log to show prediction market operations to the
+/// user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketMeta {
+    pub txid: TransactionId,
+    /// The output of the primary module receiving withdrawn funds or change
+    pub change: Option<OutPoint>,
+    pub variant: MarketMetaVariant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketMetaVariant {
+    NewMarket {
+        question: String,
+    },
+    MintSets {
+        market: MarketId,
+        quantity: u64,
+    },
+    RedeemSets {
+        market: MarketId,
+        quantity: u64,
+    },
+    Order {
+        order: OrderId,
+        market: MarketId,
+        outcome: u8,
+        side: Side,
+        price: Amount,
+        quantity: u64,
+    },
+    CancelOrder {
+        order: OrderId,
+    },
+    Withdraw {
+        amount: Amount,
+    },
+}
+
+#[apply(async_trait_maybe_send!)]
+impl MarketClientExt for Client {
+    async fn create_market(&self, params: MarketParams) -> anyhow::Result<(OperationId, MarketId)> {
+        let (markets, instance) = self.get_first_module::<MarketClientModule>(&KIND);
+        let operation_id = OperationId::new_random();
+        params.validate()?;
+
+        let variant = MarketMetaVariant::NewMarket {
+            question: params.question.clone(),
+        };
+        let output = markets.create_output(operation_id, None, MarketOutput::NewMarket(params));
+        let tx = TransactionBuilder::new().with_output(output.into_dyn(instance.id));
+
+        let txid = self
+            .submit_market_transaction(operation_id, variant, tx)
+            .await?;
+        // Our output comes before the change added by the primary module
+        let market = MarketId(OutPoint { txid, out_idx: 0 });
+        Ok((operation_id, market))
+    }
+
+    async fn mint_outcome_sets(
+        &self,
+        market: MarketId,
+        quantity: u64,
+    ) -> anyhow::Result<OperationId> {
+        let (markets, instance) = self.get_first_module::<MarketClientModule>(&KIND);
+        let operation_id = OperationId::new_random();
+        let contract_price = self.market_contract_price(market).await?;
+
+        let output = markets.create_output(
+            operation_id,
+            None,
+            MarketOutput::MintSets {
+                owner: markets.owner(),
+                market,
+                quantity,
+                contract_price,
+            },
+        );
+        let tx = TransactionBuilder::new().with_output(output.into_dyn(instance.id));
+
+        self.submit_market_transaction(
+            operation_id,
+            MarketMetaVariant::MintSets { market, quantity },
+            tx,
+        )
+        .await?;
+        Ok(operation_id)
+    }
+
+    async fn redeem_outcome_sets(
+        &self,
+        market: MarketId,
+        quantity: u64,
+    ) -> anyhow::Result<OperationId> {
+        let (markets, instance) = self.get_first_module::<MarketClientModule>(&KIND);
+        let operation_id = OperationId::new_random();
+        let contract_price = self.market_contract_price(market).await?;
+
+        // The primary module receives the contract price as change
+        let input = markets.create_input(
+            operation_id,
+            MarketInput::RedeemSets {
+                owner: markets.owner(),
+                market,
+                quantity,
+                contract_price,
+            },
+        );
+        let tx = TransactionBuilder::new().with_input(input.into_dyn(instance.id));
+
+        self.submit_market_transaction(
+            operation_id,
+            MarketMetaVariant::RedeemSets { market, quantity },
+            tx,
+        )
+        .await?;
+        Ok(operation_id)
+    }
+
+    async fn place_market_order(
+        &self,
+        market: MarketId,
+        outcome: u8,
+        side: Side,
+        price: Amount,
+        quantity: u64,
+    ) -> anyhow::Result<OperationId> {
+        let (markets, instance) = self.get_first_module::<MarketClientModule>(&KIND);
+        let operation_id = OperationId::new_random();
+
+        let order = NewOrder {
+            id: OrderId(operation_id.0),
+            owner: markets.owner(),
+            market,
+            outcome,
+            price,
+            quantity,
+        };
+        order.total().context("Order is too large")?;
+
+        let tx = match side {
+            Side::Buy => {
+                let output = markets.create_output(
+                    operation_id,
+                    Some(order.id),
+                    MarketOutput::BuyOrder(order),
+                );
+                TransactionBuilder::new().with_output(output.into_dyn(instance.id))
+            }
+            Side::Sell => {
+                let input = markets.create_input(operation_id, MarketInput::SellOrder(order));
+                TransactionBuilder::new().with_input(input.into_dyn(instance.id))
+            }
+        };
+
+        let variant = MarketMetaVariant::Order {
+            order: OrderId(operation_id.0),
+            market,
+            outcome,
+            side,
+            price,
+            quantity,
+        };
+        self.submit_market_transaction(operation_id, variant, tx)
+            .await?;
+        Ok(operation_id)
+    }
+
+    async fn cancel_market_order(&self, order: OrderId) -> anyhow::Result<OperationId> {
+        let (markets, instance) = self.get_first_module::<MarketClientModule>(&KIND);
+        let operation_id = OperationId::new_random();
+
+        let input = markets.create_input(
+            operation_id,
+            MarketInput::CancelOrder {
+                owner: markets.owner(),
+                order,
+            },
+        );
+        let tx = TransactionBuilder::new().with_input(input.into_dyn(instance.id));
+
+        self.submit_market_transaction(operation_id, MarketMetaVariant::CancelOrder { order }, tx)
+            .await?;
+        Ok(operation_id)
+    }
+
+    async fn withdraw_market_balance(&self, amount: Amount) -> anyhow::Result<OperationId> {
+        let (markets, instance) = self.get_first_module::<MarketClientModule>(&KIND);
+        let operation_id = OperationId::new_random();
+
+        // The primary module receives the withdrawn funds as change
+        let input = markets.create_input(
+            operation_id,
+            MarketInput::Withdraw {
+                owner: markets.owner(),
+                amount,
+            },
+        );
+        let tx = TransactionBuilder::new().with_input(input.into_dyn(instance.id));
+
+        self.submit_market_transaction(operation_id, MarketMetaVariant::Withdraw { amount }, tx)
+            .await?;
+        Ok(operation_id)
+    }
+
+    async fn subscribe_market_operation(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<UpdateStreamOrOutcome<'_, MarketOperationState>> {
+        let (markets, _instance) = self.get_first_module::<MarketClientModule>(&KIND);
+        let operation = market_operation(self, operation_id).await?;
+        let meta = operation.meta::<MarketMeta>();
+        let is_order = matches!(meta.variant, MarketMetaVariant::Order { .. });
+
+        let tx_accepted_future = markets.await_tx_accepted(operation_id);
+        let ecash_issued_future = meta
+            .change
+            .map(|out_point| self.await_primary_module_output(operation_id, out_point));
+        let order_updates = markets.notifier.subscribe(operation_id).await;
+
+        Ok(operation.outcome_or_updates(self.db(), operation_id, || {
+            stream! {
+                yield MarketOperationState::Created;
+
+                if let Err(e) = tx_accepted_future.await {
+                    yield MarketOperationState::Failed(e);
+                    return;
+                }
+                if let Some(ecash_issued_future) = ecash_issued_future {
+                    if let Err(e) = ecash_issued_future.await {
+                        yield MarketOperationState::Failed(e.to_string());
+                        return;
+                    }
+                }
+                if !is_order {
+                    yield MarketOperationState::Done;
+                    return;
+                }
+
+                // Past states are replayed in no particular order, so only
+                // report fills that are new to us
+                let mut reported_filled = None;
+                pin_mut!(order_updates);
+                while let Some(state) = order_updates.next().await {
+                    match state {
+                        MarketStateMachine::OrderOpen { filled, .. } => {
+                            if reported_filled.map_or(true, |reported| filled > reported) {
+                                reported_filled = Some(filled);
+                                yield MarketOperationState::OrderOpen { filled };
+                            }
+                        }
+                        MarketStateMachine::OrderClosed { filled, state, .. } => {
+                            match state {
+                                OrderState::Filled => yield MarketOperationState::OrderFilled,
+                                _ => yield MarketOperationState::OrderCancelled { filled },
+                            }
+                            return;
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }))
+    }
+
+    async fn get_market(&self, market: MarketId) -> anyhow::Result<Option<Market>> {
+        let (_markets, instance) = self.get_first_module::<MarketClientModule>(&KIND);
+        Ok(instance.api.market(market).await?)
+    }
+
+    async fn market_order_book(&self, market: MarketId) -> anyhow::Result<Vec<(OrderId, Order)>> {
+        let (_markets, instance) = self.get_first_module::<MarketClientModule>(&KIND);
+        Ok(instance.api.market_order_book(market).await?)
+    }
+
+    async fn market_balance(&self) -> anyhow::Result<Amount> {
+        let (markets, instance) = self.get_first_module::<MarketClientModule>(&KIND);
+        Ok(instance.api.market_balance(markets.owner()).await?)
+    }
+
+    async fn market_shares(&self, market: MarketId) -> anyhow::Result<Vec<u64>> {
+        let (markets, instance) = self.get_first_module::<MarketClientModule>(&KIND);
+        Ok(instance.api.market_shares(market, markets.owner()).await?)
+    }
+
+    async fn await_market_resolved(&self, market: MarketId) -> anyhow::Result<Resolution> {
+        let (_markets, instance) = self.get_first_module::<MarketClientModule>(&KIND);
+        Ok(instance.api.wait_market_resolved(market).await?)
+    }
+}
+
+#[apply(async_trait_maybe_send!)]
+trait MarketClientExtPrivate {
+    async fn submit_market_transaction(
+        &self,
+        operation_id: OperationId,
+        variant: MarketMetaVariant,
+        tx: TransactionBuilder,
+    ) -> anyhow::Result<TransactionId>;
+
+    async fn market_contract_price(&self, market: MarketId) -> anyhow::Result<Amount>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl MarketClientExtPrivate for Client {
+    async fn submit_market_transaction(
+        &self,
+        operation_id: OperationId,
+        variant: MarketMetaVariant,
+        tx: TransactionBuilder,
+    ) -> anyhow::Result<TransactionId> {
+        let operation_meta = move |txid, change| MarketMeta {
+            txid,
+            change,
+            variant: variant.clone(),
+        };
+        self.finalize_and_submit_transaction(operation_id, KIND.as_str(), operation_meta, tx)
+            .await
+    }
+
+    async fn market_contract_price(&self, market: MarketId) -> anyhow::Result<Amount> {
+        let market = self
+            .get_market(market)
+            .await?
+            .ok_or(anyhow!("Market not found"))?;
+        Ok(market.params.contract_price)
+    }
+}
+
+async fn market_operation(
+    client: &Client,
+    operation_id: OperationId,
+) -> anyhow::Result<OperationLogEntry> {
+    let operation = client
+        .operation_log()
+        .get_operation(operation_id)
+        .await
+        .ok_or(anyhow!("Operation not found"))?;
+
+    if operation.operation_type() != KIND.as_str() {
+        bail!("Operation is not a prediction market operation");
+    }
+
+    Ok(operation)
+}
+
+#[derive(Debug)]
+pub struct MarketClientModule {
+    cfg: MarketClientConfig,
+    key: KeyPair,
+    notifier: ModuleNotifier<DynGlobalClientContext, MarketStateMachine>,
+}
+
+/// Data needed by the state machine
+#[derive(Debug, Clone)]
+pub struct MarketClientContext;
+
+// TODO: Boiler-plate
+impl Context for MarketClientContext {}
+
+#[apply(async_trait_maybe_send!)]
+impl ClientModule for MarketClientModule {
+    type Common = MarketModuleTypes;
+    type ModuleStateMachineContext = MarketClientContext;
+    type States = MarketStateMachine;
+
+    fn context(&self) -> Self::ModuleStateMachineContext {
+        MarketClientContext
+    }
+
+    fn input_amount(&self, input: &<Self::Common as ModuleCommon>::Input) -> TransactionItemAmount {
+        let amount = match input {
+            MarketInput::SellOrder(_) | MarketInput::CancelOrder { .. } => Amount::ZERO,
+            MarketInput::RedeemSets {
+                quantity,
+                contract_price,
+                ..
+            } => *contract_price * *quantity,
+            MarketInput::Withdraw { amount, .. } => *amount,
+        };
+        TransactionItemAmount {
+            amount,
+            fee: self.cfg.tx_fee,
+        }
+    }
+
+    fn output_amount(
+        &self,
+        output: &<Self::Common as ModuleCommon>::Output,
+    ) -> TransactionItemAmount {
+        match output {
+            MarketOutput::NewMarket(_) => TransactionItemAmount {
+                amount: Amount::ZERO,
+                fee: self.cfg.new_market_fee,
+            },
+            MarketOutput::BuyOrder(order) => TransactionItemAmount {
+                amount: order.total().expect("Checked when placing the order"),
+                fee: self.cfg.tx_fee,
+            },
+            MarketOutput::MintSets {
+                quantity,
+                contract_price,
+                ..
+            } => TransactionItemAmount {
+                amount: *contract_price * *quantity,
+                fee: self.cfg.tx_fee,
+            },
+        }
+    }
+}
+
+impl MarketClientModule {
+    /// The key owning our orders, shares and balance
+    fn owner(&self) -> XOnlyPublicKey {
+        self.key.x_only_public_key().0
+    }
+
+    fn create_input(
+        &self,
+        operation_id: OperationId,
+        input: MarketInput,
+    ) -> ClientInput<MarketInput, MarketStateMachine> {
+        let order = match &input {
+            MarketInput::SellOrder(order) => Some(order.id),
+            _ => None,
+        };
+        ClientInput {
+            input,
+            keys: vec![self.key],
+            state_machines: Arc::new(move |txid, _| {
+                vec![MarketStateMachine::Submitted {
+                    operation_id,
+                    txid,
+                    order,
+                }]
+            }),
+        }
+    }
+
+    fn create_output(
+        &self,
+        operation_id: OperationId,
+        order: Option<OrderId>,
+        output: MarketOutput,
+    ) -> ClientOutput<MarketOutput, MarketStateMachine> {
+        ClientOutput {
+            output,
+            state_machines: Arc::new(move |txid, _| {
+                vec![MarketStateMachine::Submitted {
+                    operation_id,
+                    txid,
+                    order,
+                }]
+            }),
+        }
+    }
+
+    async fn await_tx_accepted(&self, operation_id: OperationId) -> Result<(), String> {
+        let stream = self
+            .notifier
+            .subscribe(operation_id)
+            .await
+            .filter_map(|state| async move {
+                match state {
+                    MarketStateMachine::Submitted { .. } => None,
+                    MarketStateMachine::Rejected(_, e) => Some(Err(e)),
+                    // Any other state follows the acceptance
+                    _ => Some(Ok(())),
+                }
+            });
+
+        pin_mut!(stream);
+
+        stream.next_or_pending().await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MarketClientGen;
+
+impl ExtendsCommonModuleGen for MarketClientGen {
+    type Common = MarketCommonGen;
+}
+
+/// Generates the client module
+#[apply(async_trait_maybe_send!)]
+impl ClientModuleGen for MarketClientGen {
+    type Module = MarketClientModule;
+    type Config = MarketClientConfig;
+
+    fn supported_api_versions(&self) -> MultiApiVersion {
+        MultiApiVersion::try_from_iter([ApiVersion { major: 0, minor: 0 }])
+            .expect("no version conficts")
+    }
+
+    async fn init(
+        &self,
+        cfg: Self::Config,
+        _db: Database,
+        _api_version: ApiVersion,
+        module_root_secret: DerivableSecret,
+        notifier: ModuleNotifier<DynGlobalClientContext, <Self::Module as ClientModule>::States>,
+        _api: DynGlobalApi,
+        _module_api: DynModuleApi,
+    ) -> anyhow::Result<Self::Module> {
+        Ok(MarketClientModule {
+            cfg,
+            key: module_root_secret.to_secp_key(&Secp256k1::new()),
+            notifier,
+        })
+    }
+}
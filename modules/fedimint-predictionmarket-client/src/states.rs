@@ -0,0 +1,163 @@
+use std::time::Duration;
+
+use fedimint_client::sm::{DynState, OperationId, State, StateTransition};
+use fedimint_client::DynGlobalClientContext;
+use fedimint_core::core::{IntoDynInstance, ModuleInstanceId};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::task::sleep;
+use fedimint_core::TransactionId;
+use fedimint_predictionmarket_common::{Order, OrderId, OrderState};
+use tracing::warn;
+
+use crate::api::MarketFederationApi;
+use crate::MarketClientContext;
+
+/// How long to wait before asking the federation about an order again after
+/// the request failed
+const ORDER_RETRY_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Tracks a transaction of the prediction market module and, for new orders,
+/// the order until it is filled or cancelled
+///
+/// The funds themselves are held by the federation, so unlike the ecash
+/// modules there is nothing to refund if the transaction is rejected.
+///
+/// ```mermaid
+/// graph LR
+///     Submitted -- tx rejected --> Rejected
+///     Submitted -- tx accepted --> Accepted
+///     Submitted -- order tx accepted --> OrderOpen
+///     OrderOpen -- partially filled --> OrderOpen
+///     OrderOpen -- filled or cancelled --> OrderClosed
+/// ```
+#[derive(Debug, Clone, Eq, PartialEq, Decodable, Encodable)]
+pub enum MarketStateMachine {
+    Submitted {
+        operation_id: OperationId,
+        txid: TransactionId,
+        /// The order placed by the transaction, if any
+        order: Option<OrderId>,
+    },
+    Accepted(OperationId),
+    Rejected(OperationId, String),
+    OrderOpen {
+        operation_id: OperationId,
+        order: OrderId,
+        filled: u64,
+    },
+    OrderClosed {
+        operation_id: OperationId,
+        order: OrderId,
+        filled: u64,
+        state: OrderState,
+    },
+}
+
+impl State for MarketStateMachine {
+    type ModuleContext = MarketClientContext;
+    type GlobalContext = DynGlobalClientContext;
+
+    fn transitions(
+        &self,
+        _context: &Self::ModuleContext,
+        global_context: &Self::GlobalContext,
+    ) -> Vec<StateTransition<Self>> {
+        match self.clone() {
+            MarketStateMachine::Submitted {
+                operation_id,
+                txid,
+                order,
+            } => vec![StateTransition::new(
+                await_tx_accepted(global_context.clone(), operation_id, txid),
+                move |_dbtx, res, _state: Self| match (res, order) {
+                    (Ok(()), Some(order)) => Box::pin(async move {
+                        MarketStateMachine::OrderOpen {
+                            operation_id,
+                            order,
+                            filled: 0,
+                        }
+                    }),
+                    (Ok(()), None) => {
+                        Box::pin(async move { MarketStateMachine::Accepted(operation_id) })
+                    }
+                    (Err(e), _) => {
+                        Box::pin(async move { MarketStateMachine::Rejected(operation_id, e) })
+                    }
+                },
+            )],
+            MarketStateMachine::OrderOpen {
+                operation_id,
+                order: order_id,
+                filled,
+            } => vec![StateTransition::new(
+                await_order_update(global_context.clone(), order_id, filled),
+                move |_dbtx, order: Order, _state: Self| {
+                    Box::pin(async move {
+                        match order.state {
+                            OrderState::Open => MarketStateMachine::OrderOpen {
+                                operation_id,
+                                order: order_id,
+                                filled: order.filled,
+                            },
+                            state => MarketStateMachine::OrderClosed {
+                                operation_id,
+                                order: order_id,
+                                filled: order.filled,
+                                state,
+                            },
+                        }
+                    })
+                },
+            )],
+            MarketStateMachine::Accepted(_) => vec![],
+            MarketStateMachine::Rejected(_, _) => vec![],
+            MarketStateMachine::OrderClosed { .. } => vec![],
+        }
+    }
+
+    fn operation_id(&self) -> OperationId {
+        match self {
+            MarketStateMachine::Submitted { operation_id, .. } => *operation_id,
+            MarketStateMachine::Accepted(operation_id) => *operation_id,
+            MarketStateMachine::Rejected(operation_id, _) => *operation_id,
+            MarketStateMachine::OrderOpen { operation_id, .. } => *operation_id,
+            MarketStateMachine::OrderClosed { operation_id, .. } => *operation_id,
+        }
+    }
+}
+
+async fn await_tx_accepted(
+    context: DynGlobalClientContext,
+    id: OperationId,
+    txid: TransactionId,
+) -> Result<(), String> {
+    context
+        .await_tx_accepted(id, txid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// Waits until more than `filled` shares of the order were traded or it was
+/// closed
+async fn await_order_update(context: DynGlobalClientContext, order: OrderId, filled: u64) -> Order {
+    loop {
+        match context
+            .module_api()
+            .wait_market_order_update(order, filled)
+            .await
+        {
+            Ok(order) => return order,
+            Err(e) => warn!("Failed to get the state of order {order}: {e}"),
+        }
+        sleep(ORDER_RETRY_INTERVAL).await;
+    }
+}
+
+// TODO: Boiler-plate
+impl IntoDynInstance for MarketStateMachine {
+    type DynType = DynState<DynGlobalClientContext>;
+
+    fn into_dyn(self, instance_id: ModuleInstanceId) -> Self::DynType {
+        DynState::from_typed(instance_id, self)
+    }
+}
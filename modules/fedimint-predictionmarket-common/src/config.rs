@@ -0,0 +1,83 @@
+use fedimint_core::core::ModuleKind;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{plugin_types_trait_impl_config, Amount, PeerId};
+use serde::{Deserialize, Serialize};
+
+use crate::MarketCommonGen;
+
+/// Parameters necessary to generate this module's configuration
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MarketGenParams {
+    pub local: MarketGenParamsLocal,
+    pub consensus: MarketGenParamsConsensus,
+}
+
+/// Local parameters for config generation
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MarketGenParamsLocal {}
+
+/// Consensus parameters for config generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MarketGenParamsConsensus {
+    /// Fee for creating a market, keeps the list of markets from being spammed
+    pub new_market_fee: Amount,
+    pub tx_fee: Amount,
+}
+
+impl Default for MarketGenParamsConsensus {
+    fn default() -> Self {
+        Self {
+            new_market_fee: Amount::from_sats(1000),
+            tx_fee: Amount::ZERO,
+        }
+    }
+}
+
+/// Contains all the configuration for the server
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MarketConfig {
+    pub local: MarketConfigLocal,
+    pub private: MarketConfigPrivate,
+    pub consensus: MarketConfigConsensus,
+}
+
+/// Contains all the configuration for the client
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct MarketClientConfig {
+    pub new_market_fee: Amount,
+    pub tx_fee: Amount,
+}
+
+/// Locally unencrypted config unique to each member
+#[derive(Clone, Debug, Serialize, Deserialize, Decodable, Encodable)]
+pub struct MarketConfigLocal {
+    /// Our id, to know which markets we still need to vote on
+    pub peer_id: PeerId,
+}
+
+/// Will be the same for every federation member
+#[derive(Clone, Debug, Serialize, Deserialize, Decodable, Encodable)]
+pub struct MarketConfigConsensus {
+    pub new_market_fee: Amount,
+    pub tx_fee: Amount,
+    /// Number of matching votes required to resolve a market
+    pub threshold: u64,
+}
+
+/// Will be encrypted and not shared such as private key material, the markets
+/// don't need any
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MarketConfigPrivate {}
+
+// Wire together the configs for this module
+plugin_types_trait_impl_config!(
+    MarketCommonGen,
+    MarketGenParams,
+    MarketGenParamsLocal,
+    MarketGenParamsConsensus,
+    MarketConfig,
+    MarketConfigLocal,
+    MarketConfigPrivate,
+    MarketConfigConsensus,
+    MarketClientConfig
+);
@@ -0,0 +1,533 @@
+use std::fmt;
+
+use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::error::{code, ModuleErrorCode};
+use fedimint_core::module::{CommonModuleGen, ModuleCommon, ModuleConsensusVersion};
+use fedimint_core::{plugin_types_trait_impl_common, Amount, OutPoint};
+use fedimint_oracle_common::OracleValue;
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Common contains types shared by both the client and server
+
+// The client and server configuration
+pub mod config;
+
+// Matching of orders against the order book
+pub mod matching;
+
+/// Unique name for this module
+pub const KIND: ModuleKind = ModuleKind::from_static_str("predictionmarket");
+
+/// Modules are non-compatible with older versions
+pub const CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion(0);
+
+/// Most outcomes a market can have, so they fit into an `u8`
+pub const MAX_OUTCOMES: usize = 16;
+
+/// Identifies a market by the output that created it
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Serialize,
+    Deserialize,
+    Encodable,
+    Decodable,
+)]
+pub struct MarketId(pub OutPoint);
+
+/// Identifies an order, chosen randomly by the client placing it so it can
+/// follow the order without waiting for the federation
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Serialize,
+    Deserialize,
+    Encodable,
+    Decodable,
+)]
+pub struct OrderId(pub [u8; 32]);
+
+/// Describes a market when it is created
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct MarketParams {
+    /// What the market is about, e.g. "BTC/USD above 30000 on 2023-06-30"
+    pub question: String,
+    /// Names of the outcomes, one share of the winning outcome pays out
+    /// `contract_price`
+    pub outcomes: Vec<String>,
+    pub contract_price: Amount,
+    /// The oracle round that decides the winning outcome
+    pub feed: String,
+    pub round: u64,
+    pub rule: ResolutionRule,
+}
+
+impl MarketParams {
+    pub fn validate(&self) -> Result<(), MarketError> {
+        let invalid = |reason: &str| Err(MarketError::InvalidMarket(reason.to_string()));
+
+        if self.outcomes.len() < 2 || self.outcomes.len() > MAX_OUTCOMES {
+            return invalid("Markets need between 2 and 16 outcomes");
+        }
+        if self.outcomes.len() != self.rule.outcomes() {
+            return invalid("Resolution rule doesn't match the outcomes");
+        }
+        if self.contract_price == Amount::ZERO {
+            return invalid("Contract price must not be zero");
+        }
+        match &self.rule {
+            ResolutionRule::Ranges { bounds } => {
+                if bounds.windows(2).any(|pair| pair[0] >= pair[1]) {
+                    return invalid("Bounds must be strictly increasing");
+                }
+            }
+            ResolutionRule::Values { values } => {
+                if (1..values.len()).any(|i| values[..i].contains(&values[i])) {
+                    return invalid("Values must be unique");
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Maps the oracle outcome to the winning outcome of a market
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum ResolutionRule {
+    /// Outcome `i` wins if the number is below `bounds[i]` and not below the
+    /// previous bound, the last outcome if it is at least the last bound
+    ///
+    /// `[30000_00]` splits a price feed in cents into "below" and "at least"
+    /// 30000 dollars.
+    Ranges { bounds: Vec<i64> },
+    /// Outcome `i` wins if the value equals `values[i]`, the market is voided
+    /// if none does
+    Values { values: Vec<OracleValue> },
+}
+
+impl ResolutionRule {
+    /// The number of outcomes the rule distinguishes
+    pub fn outcomes(&self) -> usize {
+        match self {
+            ResolutionRule::Ranges { bounds } => bounds.len() + 1,
+            ResolutionRule::Values { values } => values.len(),
+        }
+    }
+
+    pub fn resolve(&self, value: &OracleValue) -> Resolution {
+        match (self, value) {
+            (ResolutionRule::Ranges { bounds }, OracleValue::Number(number)) => {
+                let outcome = bounds.iter().take_while(|bound| *bound <= number).count();
+                Resolution::Outcome(outcome as u8)
+            }
+            (ResolutionRule::Ranges { .. }, OracleValue::Bytes(_)) => Resolution::Void,
+            (ResolutionRule::Values { values }, value) => values
+                .iter()
+                .position(|candidate| candidate == value)
+                .map_or(Resolution::Void, |outcome| {
+                    Resolution::Outcome(outcome as u8)
+                }),
+        }
+    }
+}
+
+/// How a market ended
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum Resolution {
+    /// Every share of the outcome pays out the contract price
+    Outcome(u8),
+    /// The oracle skipped the round or its value didn't match the rule, every
+    /// share pays out an equal part of the contract price
+    Void,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum MarketState {
+    /// Shares can be traded
+    Open,
+    /// The oracle outcome is known to some guardians, so trading stopped to
+    /// not let anyone trade on it, the market is resolved once a threshold of
+    /// guardians agrees on it
+    Closed,
+    /// The shares were paid out
+    Resolved(Resolution),
+}
+
+/// A market and the funds locked in it
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct Market {
+    pub params: MarketParams,
+    pub state: MarketState,
+    /// Number of outstanding sets of one share per outcome, each backed by
+    /// the contract price
+    pub open_sets: u64,
+}
+
+impl Market {
+    /// The funds the federation owes to the holders of the shares
+    pub fn collateral(&self) -> Amount {
+        self.params.contract_price * self.open_sets
+    }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderState {
+    Open,
+    Filled,
+    /// Cancelled by the owner or because the market closed
+    Cancelled,
+}
+
+/// An order to buy or sell shares of an outcome at a limit price
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct Order {
+    pub owner: XOnlyPublicKey,
+    pub market: MarketId,
+    pub outcome: u8,
+    pub side: Side,
+    /// Price per share, between zero and the contract price
+    pub price: Amount,
+    pub quantity: u64,
+    pub filled: u64,
+    pub state: OrderState,
+    /// Position in the order book, orders with the same price are filled in
+    /// the order they were placed
+    pub sequence: u64,
+}
+
+impl Order {
+    pub fn remaining(&self) -> u64 {
+        match self.state {
+            OrderState::Open => self.quantity - self.filled,
+            OrderState::Filled | OrderState::Cancelled => 0,
+        }
+    }
+
+    /// Funds held for the remaining shares of a buy order
+    pub fn locked_funds(&self) -> Amount {
+        match self.side {
+            Side::Buy => self.price * self.remaining(),
+            Side::Sell => Amount::ZERO,
+        }
+    }
+}
+
+/// A new order, see [`MarketOutput::BuyOrder`] and [`MarketInput::SellOrder`]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct NewOrder {
+    pub id: OrderId,
+    pub owner: XOnlyPublicKey,
+    pub market: MarketId,
+    pub outcome: u8,
+    pub price: Amount,
+    pub quantity: u64,
+}
+
+impl NewOrder {
+    /// The price of all shares of the order, `None` on overflow
+    pub fn total(&self) -> Option<Amount> {
+        self.price
+            .msats
+            .checked_mul(self.quantity)
+            .map(Amount::from_msats)
+    }
+}
+
+/// Input for a fedimint transaction, signed by the owner of the funds
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub enum MarketInput {
+    /// Offers shares for sale, they are locked until the order is filled or
+    /// cancelled
+    SellOrder(NewOrder),
+    /// Returns the unfilled shares or funds of an order to its owner
+    CancelOrder {
+        owner: XOnlyPublicKey,
+        order: OrderId,
+    },
+    /// Exchanges sets of one share per outcome for the contract price before
+    /// the market is resolved
+    RedeemSets {
+        owner: XOnlyPublicKey,
+        market: MarketId,
+        quantity: u64,
+        /// Must match the market, so clients know the amount of the input
+        contract_price: Amount,
+    },
+    /// Withdraws funds from sales, cancelled orders and payouts into ecash
+    Withdraw {
+        owner: XOnlyPublicKey,
+        amount: Amount,
+    },
+}
+
+impl MarketInput {
+    pub fn owner(&self) -> XOnlyPublicKey {
+        match self {
+            MarketInput::SellOrder(order) => order.owner,
+            MarketInput::CancelOrder { owner, .. }
+            | MarketInput::RedeemSets { owner, .. }
+            | MarketInput::Withdraw { owner, .. } => *owner,
+        }
+    }
+}
+
+/// Output for a fedimint transaction
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub enum MarketOutput {
+    /// Creates a market, identified by the [`OutPoint`] of this output
+    NewMarket(MarketParams),
+    /// Bids for shares, funded with the total price of the order
+    BuyOrder(NewOrder),
+    /// Issues sets of one share per outcome for the contract price each, the
+    /// way new shares enter a market
+    MintSets {
+        owner: XOnlyPublicKey,
+        market: MarketId,
+        quantity: u64,
+        /// Must match the market, so clients know the amount of the output
+        contract_price: Amount,
+    },
+}
+
+/// Information needed by a client to follow its output
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub enum MarketOutputOutcome {
+    NewMarket(MarketId),
+    BuyOrder(OrderId),
+    MintSets(MarketId),
+}
+
+/// Non-transaction items that will be submitted to consensus
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct MarketConsensusItem {
+    pub market: MarketId,
+    /// The resolution according to the oracle outcome the guardian sees
+    pub resolution: Resolution,
+}
+
+/// Errors that might be returned by the server
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Error)]
+pub enum MarketError {
+    #[error("Invalid market: {0}")]
+    InvalidMarket(String),
+    #[error("Unknown market")]
+    UnknownMarket,
+    #[error("Market is not open for trading")]
+    MarketNotOpen,
+    #[error("Market was already resolved")]
+    MarketResolved,
+    #[error("Market has no outcome {0}")]
+    UnknownOutcome(u8),
+    #[error("Price must be between zero and the contract price {0}")]
+    InvalidPrice(Amount),
+    #[error("Contract price of the market is {0}")]
+    WrongContractPrice(Amount),
+    #[error("Quantity must not be zero")]
+    ZeroQuantity,
+    #[error("Amount is too large")]
+    Overflow,
+    #[error("Order already exists")]
+    OrderExists,
+    #[error("Unknown order")]
+    UnknownOrder,
+    #[error("Order is not open")]
+    OrderNotOpen,
+    #[error("Not enough shares, {0} available")]
+    NotEnoughShares(u64),
+    #[error("Not enough funds, {0} available")]
+    NotEnoughFunds(Amount),
+}
+
+impl ModuleErrorCode for MarketError {
+    fn code(&self) -> i32 {
+        match self {
+            MarketError::InvalidMarket(_)
+            | MarketError::UnknownOutcome(_)
+            | MarketError::InvalidPrice(_)
+            | MarketError::WrongContractPrice(_)
+            | MarketError::ZeroQuantity
+            | MarketError::Overflow => code::BAD_REQUEST,
+            MarketError::UnknownMarket | MarketError::UnknownOrder => code::NOT_FOUND,
+            MarketError::MarketNotOpen
+            | MarketError::MarketResolved
+            | MarketError::OrderExists
+            | MarketError::OrderNotOpen
+            | MarketError::NotEnoughShares(_)
+            | MarketError::NotEnoughFunds(_) => code::CONFLICT,
+        }
+    }
+}
+
+/// Contains the types defined above
+pub struct MarketModuleTypes;
+
+// Wire together the types for this module
+plugin_types_trait_impl_common!(
+    MarketModuleTypes,
+    MarketInput,
+    MarketOutput,
+    MarketOutputOutcome,
+    MarketConsensusItem
+);
+
+#[derive(Debug)]
+pub struct MarketCommonGen;
+
+impl CommonModuleGen for MarketCommonGen {
+    const CONSENSUS_VERSION: ModuleConsensusVersion = CONSENSUS_VERSION;
+    const KIND: ModuleKind = KIND;
+
+    fn decoder() -> Decoder {
+        MarketModuleTypes::decoder_builder().build()
+    }
+}
+
+impl fmt::Display for MarketId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl fmt::Display for OrderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        bitcoin_hashes::hex::format_hex(&self.0, f)
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Buy => write!(f, "buy"),
+            Side::Sell => write!(f, "sell"),
+        }
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Resolution::Outcome(outcome) => write!(f, "outcome {outcome}"),
+            Resolution::Void => write!(f, "void"),
+        }
+    }
+}
+
+impl fmt::Display for MarketInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketInput::SellOrder(order) => write!(
+                f,
+                "MarketInput SellOrder {} x{} at {}",
+                order.id, order.quantity, order.price
+            ),
+            MarketInput::CancelOrder { order, .. } => {
+                write!(f, "MarketInput CancelOrder {order}")
+            }
+            MarketInput::RedeemSets {
+                market, quantity, ..
+            } => write!(f, "MarketInput RedeemSets {market} x{quantity}"),
+            MarketInput::Withdraw { amount, .. } => write!(f, "MarketInput Withdraw {amount}"),
+        }
+    }
+}
+
+impl fmt::Display for MarketOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketOutput::NewMarket(params) => {
+                write!(f, "MarketOutput NewMarket {}", params.question)
+            }
+            MarketOutput::BuyOrder(order) => write!(
+                f,
+                "MarketOutput BuyOrder {} x{} at {}",
+                order.id, order.quantity, order.price
+            ),
+            MarketOutput::MintSets {
+                market, quantity, ..
+            } => write!(f, "MarketOutput MintSets {market} x{quantity}"),
+        }
+    }
+}
+
+impl fmt::Display for MarketOutputOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MarketOutputOutcome::NewMarket(market) => {
+                write!(f, "MarketOutputOutcome NewMarket {market}")
+            }
+            MarketOutputOutcome::BuyOrder(order) => {
+                write!(f, "MarketOutputOutcome BuyOrder {order}")
+            }
+            MarketOutputOutcome::MintSets(market) => {
+                write!(f, "MarketOutputOutcome MintSets {market}")
+            }
+        }
+    }
+}
+
+impl fmt::Display for MarketConsensusItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "MarketConsensusItem {} {}", self.market, self.resolution)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_oracle_common::OracleValue;
+
+    use crate::{Resolution, ResolutionRule};
+
+    #[test]
+    fn ranges_resolve_to_the_containing_range() {
+        let rule = ResolutionRule::Ranges {
+            bounds: vec![100, 200],
+        };
+        let resolve = |number| rule.resolve(&OracleValue::Number(number));
+
+        assert_eq!(resolve(-5), Resolution::Outcome(0));
+        assert_eq!(resolve(99), Resolution::Outcome(0));
+        assert_eq!(resolve(100), Resolution::Outcome(1));
+        assert_eq!(resolve(199), Resolution::Outcome(1));
+        assert_eq!(resolve(200), Resolution::Outcome(2));
+        assert_eq!(rule.resolve(&OracleValue::Bytes(vec![1])), Resolution::Void);
+    }
+
+    #[test]
+    fn unmatched_values_void_the_market() {
+        let rule = ResolutionRule::Values {
+            values: vec![OracleValue::Bytes(vec![0]), OracleValue::Bytes(vec![1])],
+        };
+
+        assert_eq!(
+            rule.resolve(&OracleValue::Bytes(vec![1])),
+            Resolution::Outcome(1)
+        );
+        assert_eq!(rule.resolve(&OracleValue::Bytes(vec![2])), Resolution::Void);
+    }
+}
@@ -0,0 +1,146 @@
+use std::cmp::Reverse;
+
+use fedimint_core::Amount;
+
+use crate::{Order, OrderId, OrderState, Side};
+
+/// A trade between an incoming order and an order resting in the book
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Fill {
+    pub maker: OrderId,
+    pub quantity: u64,
+    /// The price of the resting order, which the trade happens at
+    pub price: Amount,
+}
+
+/// Matches the `taker` order against the open orders of the other side for
+/// the same outcome in `book`
+///
+/// Orders with the best price are filled first, among those the oldest ones.
+/// Matching stops once the taker is filled or no resting order crosses its
+/// price anymore.
+pub fn match_order(taker: &Order, book: &[(OrderId, Order)]) -> Vec<Fill> {
+    let mut makers = book
+        .iter()
+        .filter(|(_, maker)| {
+            maker.market == taker.market
+                && maker.outcome == taker.outcome
+                && maker.side != taker.side
+                && maker.state == OrderState::Open
+                && match taker.side {
+                    Side::Buy => maker.price <= taker.price,
+                    Side::Sell => maker.price >= taker.price,
+                }
+        })
+        .collect::<Vec<_>>();
+    match taker.side {
+        Side::Buy => makers.sort_by_key(|(_, maker)| (maker.price, maker.sequence)),
+        Side::Sell => makers.sort_by_key(|(_, maker)| (Reverse(maker.price), maker.sequence)),
+    }
+
+    let mut remaining = taker.remaining();
+    let mut fills = vec![];
+    for (id, maker) in makers {
+        if remaining == 0 {
+            break;
+        }
+        let quantity = remaining.min(maker.remaining());
+        remaining -= quantity;
+        fills.push(Fill {
+            maker: *id,
+            quantity,
+            price: maker.price,
+        });
+    }
+    fills
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin_hashes::Hash;
+    use fedimint_core::{msats, OutPoint, TransactionId};
+    use secp256k1::{KeyPair, Secp256k1};
+
+    use super::*;
+    use crate::MarketId;
+
+    fn order(side: Side, price: u64, quantity: u64, sequence: u64) -> (OrderId, Order) {
+        let owner = KeyPair::from_seckey_slice(&Secp256k1::new(), &[1; 32])
+            .unwrap()
+            .x_only_public_key()
+            .0;
+        let order = Order {
+            owner,
+            market: MarketId(OutPoint {
+                txid: TransactionId::all_zeros(),
+                out_idx: 0,
+            }),
+            outcome: 0,
+            side,
+            price: msats(price),
+            quantity,
+            filled: 0,
+            state: OrderState::Open,
+            sequence,
+        };
+        (OrderId([sequence as u8; 32]), order)
+    }
+
+    #[test]
+    fn fills_best_price_first_then_oldest() {
+        let book = vec![
+            order(Side::Sell, 60, 5, 1),
+            order(Side::Sell, 50, 5, 2),
+            order(Side::Sell, 50, 5, 3),
+            order(Side::Sell, 70, 5, 4),
+            order(Side::Buy, 40, 5, 5),
+        ];
+        let (_, taker) = order(Side::Buy, 60, 12, 6);
+
+        let fills = match_order(&taker, &book);
+
+        assert_eq!(
+            fills,
+            vec![
+                Fill {
+                    maker: book[1].0,
+                    quantity: 5,
+                    price: msats(50)
+                },
+                Fill {
+                    maker: book[2].0,
+                    quantity: 5,
+                    price: msats(50)
+                },
+                Fill {
+                    maker: book[0].0,
+                    quantity: 2,
+                    price: msats(60)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn sells_only_match_higher_bids() {
+        let mut filled = order(Side::Buy, 80, 5, 3);
+        filled.1.state = OrderState::Filled;
+        let book = vec![
+            order(Side::Buy, 40, 5, 1),
+            order(Side::Buy, 55, 5, 2),
+            filled,
+        ];
+        let (_, taker) = order(Side::Sell, 50, 10, 4);
+
+        let fills = match_order(&taker, &book);
+
+        assert_eq!(
+            fills,
+            vec![Fill {
+                maker: book[1].0,
+                quantity: 5,
+                price: msats(55)
+            }]
+        );
+    }
+}
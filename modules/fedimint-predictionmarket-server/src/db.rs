@@ -0,0 +1,190 @@
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{impl_db_lookup, impl_db_record, Amount, OutPoint, PeerId};
+use fedimint_predictionmarket_common::{
+    Market, MarketId, MarketOutputOutcome, Order, OrderId, Resolution,
+};
+use secp256k1::XOnlyPublicKey;
+use serde::Serialize;
+use strum_macros::EnumIter;
+
+/// Namespaces DB keys for this module
+#[repr(u8)]
+#[derive(Clone, EnumIter, Debug)]
+pub enum DbKeyPrefix {
+    Market = 0x01,
+    Order = 0x02,
+    OpenOrder = 0x03,
+    OrderSequence = 0x04,
+    Shares = 0x05,
+    Balance = 0x06,
+    ResolutionVote = 0x07,
+    Outcome = 0x08,
+}
+
+impl std::fmt::Display for DbKeyPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Lookup markets by id or prefix
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct MarketKey(pub MarketId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MarketPrefix;
+
+impl_db_record!(
+    key = MarketKey,
+    value = Market,
+    db_prefix = DbKeyPrefix::Market,
+    // Allows clients to wait for resolutions
+    notify_on_modify = true
+);
+impl_db_lookup!(key = MarketKey, query_prefix = MarketPrefix);
+
+/// All orders ever placed, kept after they were filled or cancelled so
+/// clients can follow them
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct OrderKey(pub OrderId);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OrderPrefix;
+
+impl_db_record!(
+    key = OrderKey,
+    value = Order,
+    db_prefix = DbKeyPrefix::Order,
+    // Allows clients to wait for fills
+    notify_on_modify = true
+);
+impl_db_lookup!(key = OrderKey, query_prefix = OrderPrefix);
+
+/// The order book, a copy of the orders that are still open
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct OpenOrderKey {
+    pub market: MarketId,
+    pub outcome: u8,
+    pub order: OrderId,
+}
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct OpenOrderOutcomePrefix {
+    pub market: MarketId,
+    pub outcome: u8,
+}
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct OpenOrderMarketPrefix {
+    pub market: MarketId,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OpenOrderPrefix;
+
+impl_db_record!(
+    key = OpenOrderKey,
+    value = Order,
+    db_prefix = DbKeyPrefix::OpenOrder,
+);
+impl_db_lookup!(
+    key = OpenOrderKey,
+    query_prefix = OpenOrderOutcomePrefix,
+    query_prefix = OpenOrderMarketPrefix,
+    query_prefix = OpenOrderPrefix
+);
+
+/// Counts the orders placed so far, for time priority in the order book
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct OrderSequenceKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct OrderSequencePrefix;
+
+impl_db_record!(
+    key = OrderSequenceKey,
+    value = u64,
+    db_prefix = DbKeyPrefix::OrderSequence,
+);
+impl_db_lookup!(key = OrderSequenceKey, query_prefix = OrderSequencePrefix);
+
+/// Shares of each outcome of a market held by a user, not counting shares
+/// offered in sell orders
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct SharesKey {
+    pub market: MarketId,
+    pub owner: XOnlyPublicKey,
+}
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct MarketSharesPrefix {
+    pub market: MarketId,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct SharesPrefix;
+
+impl_db_record!(
+    key = SharesKey,
+    value = Vec<u64>,
+    db_prefix = DbKeyPrefix::Shares,
+);
+impl_db_lookup!(
+    key = SharesKey,
+    query_prefix = MarketSharesPrefix,
+    query_prefix = SharesPrefix
+);
+
+/// Funds of a user that can be withdrawn into ecash
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct BalanceKey(pub XOnlyPublicKey);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct BalancePrefix;
+
+impl_db_record!(
+    key = BalanceKey,
+    value = Amount,
+    db_prefix = DbKeyPrefix::Balance,
+);
+impl_db_lookup!(key = BalanceKey, query_prefix = BalancePrefix);
+
+/// Resolutions guardians voted for, removed once the market is resolved
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct ResolutionVoteKey {
+    pub market: MarketId,
+    pub peer_id: PeerId,
+}
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct ResolutionVoteMarketPrefix {
+    pub market: MarketId,
+}
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct ResolutionVotePrefix;
+
+impl_db_record!(
+    key = ResolutionVoteKey,
+    value = Resolution,
+    db_prefix = DbKeyPrefix::ResolutionVote,
+);
+impl_db_lookup!(
+    key = ResolutionVoteKey,
+    query_prefix = ResolutionVoteMarketPrefix,
+    query_prefix = ResolutionVotePrefix
+);
+
+/// Lookup tx outputs by key or prefix
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct MarketOutcomeKey(pub OutPoint);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct MarketOutcomePrefix;
+
+impl_db_record!(
+    key = MarketOutcomeKey,
+    value = MarketOutputOutcome,
+    db_prefix = DbKeyPrefix::Outcome,
+);
+impl_db_lookup!(key = MarketOutcomeKey, query_prefix = MarketOutcomePrefix);
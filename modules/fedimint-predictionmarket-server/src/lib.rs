@@ -0,0 +1,984 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::string::ToString;
+use std::time::Duration;
+
+use anyhow::bail;
+use async_trait::async_trait;
+use fedimint_core::config::{
+    ClientModuleConfig, ConfigGenModuleParams, DkgResult, ServerModuleConfig,
+    ServerModuleConsensusConfig, TypedServerModuleConfig, TypedServerModuleConsensusConfig,
+};
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::db::{Database, DatabaseVersion, MigrationMap, ModuleDatabaseTransaction};
+use fedimint_core::module::audit::Audit;
+use fedimint_core::module::interconnect::DynModuleInterconnect;
+use fedimint_core::module::{
+    api_endpoint, ApiEndpoint, ApiError, ConsensusProposal, CoreConsensusVersion,
+    ExtendsCommonModuleGen, InputMeta, IntoCodedModuleError, ModuleConsensusVersion, ModuleError,
+    PeerHandle, ServerModuleGen, SupportedModuleApiVersions, TransactionItemAmount,
+};
+use fedimint_core::server::DynServerModule;
+use fedimint_core::task::{sleep, TaskGroup};
+use fedimint_core::{push_db_pair_items, Amount, NumPeers, OutPoint, PeerId, ServerModule};
+use fedimint_oracle_common::interconnect::OracleInterconnect;
+use fedimint_predictionmarket_common::config::{
+    MarketClientConfig, MarketConfig, MarketConfigConsensus, MarketConfigLocal,
+    MarketConfigPrivate, MarketGenParams,
+};
+use fedimint_predictionmarket_common::matching::match_order;
+use fedimint_predictionmarket_common::{
+    Market, MarketCommonGen, MarketConsensusItem, MarketError, MarketId, MarketInput,
+    MarketModuleTypes, MarketOutput, MarketOutputOutcome, MarketParams, MarketState, NewOrder,
+    Order, OrderId, OrderState, Resolution, Side, CONSENSUS_VERSION,
+};
+use futures::StreamExt;
+use secp256k1::XOnlyPublicKey;
+use strum::IntoEnumIterator;
+use tracing::{info, warn};
+
+use crate::db::{
+    BalanceKey, BalancePrefix, DbKeyPrefix, MarketKey, MarketOutcomeKey, MarketOutcomePrefix,
+    MarketPrefix, MarketSharesPrefix, OpenOrderKey, OpenOrderMarketPrefix, OpenOrderOutcomePrefix,
+    OpenOrderPrefix, OrderKey, OrderPrefix, OrderSequenceKey, OrderSequencePrefix,
+    ResolutionVoteKey, ResolutionVoteMarketPrefix, ResolutionVotePrefix, SharesKey, SharesPrefix,
+};
+
+mod db;
+
+/// How often we check the oracle for outcomes of the markets we haven't voted
+/// on yet
+const RESOLUTION_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Generates the module
+#[derive(Debug, Clone)]
+pub struct MarketGen;
+
+impl ExtendsCommonModuleGen for MarketGen {
+    type Common = MarketCommonGen;
+}
+
+/// Implementation of server module non-consensus functions
+#[async_trait]
+impl ServerModuleGen for MarketGen {
+    type Params = MarketGenParams;
+    const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
+    /// Returns the version of this module
+    fn versions(&self, _core: CoreConsensusVersion) -> &[ModuleConsensusVersion] {
+        &[CONSENSUS_VERSION]
+    }
+
+    fn supported_api_versions(&self) -> SupportedModuleApiVersions {
+        SupportedModuleApiVersions::from_raw(0, 0, &[(0, 0)])
+    }
+
+    /// Initialize the module
+    async fn init(
+        &self,
+        cfg: ServerModuleConfig,
+        _module_instance_id: ModuleInstanceId,
+        _our_id: PeerId,
+        _db: Database,
+        _task_group: &mut TaskGroup,
+        interconnect: DynModuleInterconnect,
+    ) -> anyhow::Result<DynServerModule> {
+        Ok(PredictionMarkets::new(cfg.to_typed()?, interconnect).into())
+    }
+
+    /// DB migrations to move from old to newer versions
+    fn get_database_migrations(&self) -> MigrationMap {
+        MigrationMap::new()
+    }
+
+    /// Generates configs for all peers in a trusted manner for testing
+    fn trusted_dealer_gen(
+        &self,
+        peers: &[PeerId],
+        params: &ConfigGenModuleParams,
+    ) -> BTreeMap<PeerId, ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        peers
+            .iter()
+            .map(|&peer| {
+                let config = market_config(&params, peer, peers.threshold());
+                (peer, config.to_erased())
+            })
+            .collect()
+    }
+
+    /// Generates configs for all peers in an untrusted manner
+    async fn distributed_gen(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+    ) -> DkgResult<ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        // The markets have no keys, so there is nothing to generate together
+        Ok(market_config(&params, peers.our_id, peers.peer_ids().threshold()).to_erased())
+    }
+
+    /// Converts the consensus config into the client config
+    fn get_client_config(
+        &self,
+        config: &ServerModuleConsensusConfig,
+    ) -> anyhow::Result<ClientModuleConfig> {
+        let config = MarketConfigConsensus::from_erased(config)?;
+        Ok(ClientModuleConfig::from_typed(
+            config.kind(),
+            config.version(),
+            &(MarketClientConfig {
+                new_market_fee: config.new_market_fee,
+                tx_fee: config.tx_fee,
+            }),
+        )
+        .expect("Serialization can't fail"))
+    }
+
+    fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
+        let config = config.to_typed::<MarketConfig>()?;
+        if config.local.peer_id != *identity {
+            bail!("Peer id doesn't match our identity");
+        }
+        if config.consensus.threshold == 0 {
+            bail!("Threshold must not be zero");
+        }
+        Ok(())
+    }
+
+    /// Dumps all database items for debugging
+    async fn dump_database(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        prefix_names: Vec<String>,
+    ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_> {
+        let mut items: BTreeMap<String, Box<dyn erased_serde::Serialize + Send>> = BTreeMap::new();
+        let filtered_prefixes = DbKeyPrefix::iter().filter(|f| {
+            prefix_names.is_empty() || prefix_names.contains(&f.to_string().to_lowercase())
+        });
+
+        for table in filtered_prefixes {
+            match table {
+                DbKeyPrefix::Market => {
+                    push_db_pair_items!(dbtx, MarketPrefix, MarketKey, Market, items, "Markets");
+                }
+                DbKeyPrefix::Order => {
+                    push_db_pair_items!(dbtx, OrderPrefix, OrderKey, Order, items, "Orders");
+                }
+                DbKeyPrefix::OpenOrder => {
+                    push_db_pair_items!(
+                        dbtx,
+                        OpenOrderPrefix,
+                        OpenOrderKey,
+                        Order,
+                        items,
+                        "Order Book"
+                    );
+                }
+                DbKeyPrefix::OrderSequence => {
+                    push_db_pair_items!(
+                        dbtx,
+                        OrderSequencePrefix,
+                        OrderSequenceKey,
+                        u64,
+                        items,
+                        "Order Sequence"
+                    );
+                }
+                DbKeyPrefix::Shares => {
+                    push_db_pair_items!(dbtx, SharesPrefix, SharesKey, Vec<u64>, items, "Shares");
+                }
+                DbKeyPrefix::Balance => {
+                    push_db_pair_items!(dbtx, BalancePrefix, BalanceKey, Amount, items, "Balances");
+                }
+                DbKeyPrefix::ResolutionVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        ResolutionVotePrefix,
+                        ResolutionVoteKey,
+                        Resolution,
+                        items,
+                        "Resolution Votes"
+                    );
+                }
+                DbKeyPrefix::Outcome => {
+                    push_db_pair_items!(
+                        dbtx,
+                        MarketOutcomePrefix,
+                        MarketOutcomeKey,
+                        MarketOutputOutcome,
+                        items,
+                        "Market Outputs"
+                    );
+                }
+            }
+        }
+
+        Box::new(items.into_iter())
+    }
+}
+
+fn market_config(params: &MarketGenParams, peer_id: PeerId, threshold: usize) -> MarketConfig {
+    MarketConfig {
+        local: MarketConfigLocal { peer_id },
+        private: MarketConfigPrivate {},
+        consensus: MarketConfigConsensus {
+            new_market_fee: params.consensus.new_market_fee,
+            tx_fee: params.consensus.tx_fee,
+            threshold: threshold as u64,
+        },
+    }
+}
+
+/// Prediction market module
+#[derive(Debug)]
+pub struct PredictionMarkets {
+    pub cfg: MarketConfig,
+    /// Reads the outcomes markets are resolved with from the oracle module
+    interconnect: DynModuleInterconnect,
+}
+
+/// Implementation of consensus for the server module
+#[async_trait]
+impl ServerModule for PredictionMarkets {
+    /// Define the consensus types
+    type Common = MarketModuleTypes;
+    type Gen = MarketGen;
+    type VerificationCache = MarketVerificationCache;
+
+    async fn await_consensus_proposal(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) {
+        // Wait until the oracle attested the outcome of a market
+        while !self.consensus_proposal(dbtx).await.forces_new_epoch() {
+            sleep(RESOLUTION_POLL_INTERVAL).await;
+        }
+    }
+
+    async fn consensus_proposal(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> ConsensusProposal<MarketConsensusItem> {
+        let unresolved = dbtx
+            .find_by_prefix(&MarketPrefix)
+            .await
+            .filter_map(|(key, market)| async move {
+                (!matches!(market.state, MarketState::Resolved(_))).then_some((key.0, market))
+            })
+            .collect::<Vec<_>>()
+            .await;
+
+        let mut items = vec![];
+        for (market_id, market) in unresolved {
+            let vote_key = ResolutionVoteKey {
+                market: market_id,
+                peer_id: self.cfg.local.peer_id,
+            };
+            if dbtx.get_value(&vote_key).await.is_some() {
+                continue;
+            }
+
+            match self.oracle_resolution(&market.params).await {
+                Ok(Some(resolution)) => items.push(MarketConsensusItem {
+                    market: market_id,
+                    resolution,
+                }),
+                Ok(None) => {}
+                Err(e) => warn!("Failed to get the oracle outcome for market {market_id}: {e}"),
+            }
+        }
+        ConsensusProposal::new_auto_trigger(items)
+    }
+
+    async fn begin_consensus_epoch<'a, 'b>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        consensus_items: Vec<(PeerId, MarketConsensusItem)>,
+        _consensus_peers: &BTreeSet<PeerId>,
+    ) -> Vec<PeerId> {
+        let mut drop_peers = vec![];
+
+        for (peer_id, item) in consensus_items {
+            let Some(mut market) = dbtx.get_value(&MarketKey(item.market)).await else {
+                warn!(%peer_id, market = %item.market, "Resolution vote for unknown market");
+                drop_peers.push(peer_id);
+                continue;
+            };
+            if let Resolution::Outcome(outcome) = item.resolution {
+                if outcome as usize >= market.params.outcomes.len() {
+                    warn!(%peer_id, market = %item.market, "Resolution vote for unknown outcome");
+                    drop_peers.push(peer_id);
+                    continue;
+                }
+            }
+            // Votes for resolved markets are still in flight, ignore them
+            if matches!(market.state, MarketState::Resolved(_)) {
+                continue;
+            }
+
+            let key = ResolutionVoteKey {
+                market: item.market,
+                peer_id,
+            };
+            if dbtx.get_value(&key).await.is_some() {
+                continue;
+            }
+            dbtx.insert_new_entry(&key, &item.resolution).await;
+
+            if market.state == MarketState::Open {
+                self.close_market(dbtx, item.market, &mut market).await;
+            }
+
+            let votes = dbtx
+                .find_by_prefix(&ResolutionVoteMarketPrefix {
+                    market: item.market,
+                })
+                .await
+                .filter(|(_, resolution)| std::future::ready(*resolution == item.resolution))
+                .count()
+                .await;
+            if votes as u64 >= self.cfg.consensus.threshold {
+                self.resolve_market(dbtx, item.market, market, item.resolution)
+                    .await;
+            }
+        }
+
+        drop_peers
+    }
+
+    fn build_verification_cache<'a>(
+        &'a self,
+        _inputs: impl Iterator<Item = &'a MarketInput> + Send,
+    ) -> Self::VerificationCache {
+        MarketVerificationCache
+    }
+
+    async fn validate_input<'a, 'b>(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        _verification_cache: &Self::VerificationCache,
+        input: &'a MarketInput,
+    ) -> Result<InputMeta, ModuleError> {
+        let amount = match input {
+            MarketInput::SellOrder(order) => {
+                let market = get_market(dbtx, order.market).await?;
+                validate_order(dbtx, &market, order).await?;
+                let available =
+                    shares(dbtx, order.market, &market, order.owner).await[order.outcome as usize];
+                if available < order.quantity {
+                    return Err(MarketError::NotEnoughShares(available)).into_module_error();
+                }
+                Amount::ZERO
+            }
+            MarketInput::CancelOrder { owner, order } => {
+                let order = dbtx
+                    .get_value(&OrderKey(*order))
+                    .await
+                    .filter(|order| order.owner == *owner)
+                    .ok_or(MarketError::UnknownOrder)
+                    .into_module_error()?;
+                if order.state != OrderState::Open {
+                    return Err(MarketError::OrderNotOpen).into_module_error();
+                }
+                Amount::ZERO
+            }
+            MarketInput::RedeemSets {
+                owner,
+                market: market_id,
+                quantity,
+                contract_price,
+            } => {
+                let market = get_market(dbtx, *market_id).await?;
+                if matches!(market.state, MarketState::Resolved(_)) {
+                    return Err(MarketError::MarketResolved).into_module_error();
+                }
+                if *contract_price != market.params.contract_price {
+                    return Err(MarketError::WrongContractPrice(
+                        market.params.contract_price,
+                    ))
+                    .into_module_error();
+                }
+                if *quantity == 0 {
+                    return Err(MarketError::ZeroQuantity).into_module_error();
+                }
+                let complete_sets = shares(dbtx, *market_id, &market, *owner)
+                    .await
+                    .into_iter()
+                    .min()
+                    .unwrap_or_default();
+                if complete_sets < *quantity {
+                    return Err(MarketError::NotEnoughShares(complete_sets)).into_module_error();
+                }
+                market.params.contract_price * *quantity
+            }
+            MarketInput::Withdraw { owner, amount } => {
+                let balance = balance(dbtx, *owner).await;
+                if *amount > balance {
+                    return Err(MarketError::NotEnoughFunds(balance)).into_module_error();
+                }
+                *amount
+            }
+        };
+
+        Ok(InputMeta {
+            amount: TransactionItemAmount {
+                amount,
+                fee: self.cfg.consensus.tx_fee,
+            },
+            // IMPORTANT: include the pubkey to validate the user signed this tx
+            pub_keys: vec![input.owner()],
+        })
+    }
+
+    async fn apply_input<'a, 'b, 'c>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'c>,
+        input: &'b MarketInput,
+        cache: &Self::VerificationCache,
+    ) -> Result<InputMeta, ModuleError> {
+        let meta = self.validate_input(dbtx, cache, input).await?;
+
+        match input {
+            MarketInput::SellOrder(order) => {
+                // The shares are held by the order until it is filled or cancelled
+                let market = get_market(dbtx, order.market).await?;
+                let mut shares = shares(dbtx, order.market, &market, order.owner).await;
+                shares[order.outcome as usize] -= order.quantity;
+                save_shares(dbtx, order.market, order.owner, shares).await;
+                place_order(dbtx, &market, order, Side::Sell).await;
+            }
+            MarketInput::CancelOrder { order, .. } => {
+                let order_id = *order;
+                let order = dbtx
+                    .get_value(&OrderKey(order_id))
+                    .await
+                    .expect("Checked by validation");
+                cancel_order(dbtx, order_id, order).await;
+            }
+            MarketInput::RedeemSets {
+                owner,
+                market: market_id,
+                quantity,
+                ..
+            } => {
+                let mut market = get_market(dbtx, *market_id).await?;
+                let mut shares = shares(dbtx, *market_id, &market, *owner).await;
+                for outcome_shares in &mut shares {
+                    *outcome_shares -= quantity;
+                }
+                save_shares(dbtx, *market_id, *owner, shares).await;
+                market.open_sets -= quantity;
+                dbtx.insert_entry(&MarketKey(*market_id), &market).await;
+            }
+            MarketInput::Withdraw { owner, amount } => {
+                let balance = balance(dbtx, *owner).await;
+                save_balance(dbtx, *owner, balance - *amount).await;
+            }
+        }
+
+        Ok(meta)
+    }
+
+    async fn validate_output(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        output: &MarketOutput,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        match output {
+            MarketOutput::NewMarket(params) => {
+                params.validate().into_module_error()?;
+                Ok(TransactionItemAmount {
+                    amount: Amount::ZERO,
+                    fee: self.cfg.consensus.new_market_fee,
+                })
+            }
+            MarketOutput::BuyOrder(order) => {
+                let market = get_market(dbtx, order.market).await?;
+                validate_order(dbtx, &market, order).await?;
+                Ok(TransactionItemAmount {
+                    amount: order.total().expect("Checked by validation"),
+                    fee: self.cfg.consensus.tx_fee,
+                })
+            }
+            MarketOutput::MintSets {
+                market: market_id,
+                quantity,
+                contract_price,
+                ..
+            } => {
+                let market = get_market(dbtx, *market_id).await?;
+                if market.state != MarketState::Open {
+                    return Err(MarketError::MarketNotOpen).into_module_error();
+                }
+                if *contract_price != market.params.contract_price {
+                    return Err(MarketError::WrongContractPrice(
+                        market.params.contract_price,
+                    ))
+                    .into_module_error();
+                }
+                if *quantity == 0 {
+                    return Err(MarketError::ZeroQuantity).into_module_error();
+                }
+                // The collateral of all sets must stay representable
+                market
+                    .open_sets
+                    .checked_add(*quantity)
+                    .and_then(|sets| sets.checked_mul(market.params.contract_price.msats))
+                    .ok_or(MarketError::Overflow)
+                    .into_module_error()?;
+                Ok(TransactionItemAmount {
+                    amount: market.params.contract_price * *quantity,
+                    fee: self.cfg.consensus.tx_fee,
+                })
+            }
+        }
+    }
+
+    async fn apply_output<'a, 'b>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        output: &'a MarketOutput,
+        out_point: OutPoint,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        let meta = self.validate_output(dbtx, output).await?;
+
+        let outcome = match output {
+            MarketOutput::NewMarket(params) => {
+                let market_id = MarketId(out_point);
+                let market = Market {
+                    params: params.clone(),
+                    state: MarketState::Open,
+                    open_sets: 0,
+                };
+                dbtx.insert_new_entry(&MarketKey(market_id), &market).await;
+                MarketOutputOutcome::NewMarket(market_id)
+            }
+            MarketOutput::BuyOrder(order) => {
+                let market = get_market(dbtx, order.market).await?;
+                place_order(dbtx, &market, order, Side::Buy).await;
+                MarketOutputOutcome::BuyOrder(order.id)
+            }
+            MarketOutput::MintSets {
+                owner,
+                market: market_id,
+                quantity,
+                ..
+            } => {
+                let mut market = get_market(dbtx, *market_id).await?;
+                let mut shares = shares(dbtx, *market_id, &market, *owner).await;
+                for outcome_shares in &mut shares {
+                    *outcome_shares += quantity;
+                }
+                save_shares(dbtx, *market_id, *owner, shares).await;
+                market.open_sets += quantity;
+                dbtx.insert_entry(&MarketKey(*market_id), &market).await;
+                MarketOutputOutcome::MintSets(*market_id)
+            }
+        };
+        dbtx.insert_new_entry(&MarketOutcomeKey(out_point), &outcome)
+            .await;
+
+        Ok(meta)
+    }
+
+    async fn end_consensus_epoch<'a, 'b>(
+        &'a self,
+        _consensus_peers: &BTreeSet<PeerId>,
+        _dbtx: &mut ModuleDatabaseTransaction<'b>,
+    ) -> Vec<PeerId> {
+        vec![]
+    }
+
+    async fn output_status(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        out_point: OutPoint,
+    ) -> Option<MarketOutputOutcome> {
+        dbtx.get_value(&MarketOutcomeKey(out_point)).await
+    }
+
+    async fn audit(&self, dbtx: &mut ModuleDatabaseTransaction<'_>, audit: &mut Audit) {
+        // Balances, bids and the collateral of the shares are owed to the users
+        audit
+            .add_items(dbtx, &BalancePrefix, |_, balance| -(balance.msats as i64))
+            .await;
+        audit
+            .add_items(dbtx, &OpenOrderPrefix, |_, order| {
+                -(order.locked_funds().msats as i64)
+            })
+            .await;
+        audit
+            .add_items(dbtx, &MarketPrefix, |_, market| {
+                -(market.collateral().msats as i64)
+            })
+            .await;
+    }
+
+    fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
+        vec![
+            api_endpoint! {
+                "market",
+                async |_module: &PredictionMarkets, context, market: MarketId| -> Option<Market> {
+                    Ok(context.dbtx().get_value(&MarketKey(market)).await)
+                }
+            },
+            api_endpoint! {
+                "order_book",
+                async |_module: &PredictionMarkets, context, market: MarketId| -> Vec<(OrderId, Order)> {
+                    Ok(context
+                        .dbtx()
+                        .find_by_prefix(&OpenOrderMarketPrefix { market })
+                        .await
+                        .map(|(key, order)| (key.order, order))
+                        .collect::<Vec<_>>()
+                        .await)
+                }
+            },
+            api_endpoint! {
+                "order",
+                async |_module: &PredictionMarkets, context, order: OrderId| -> Option<Order> {
+                    Ok(context.dbtx().get_value(&OrderKey(order)).await)
+                }
+            },
+            api_endpoint! {
+                // Waits until more of the order than `filled` was filled or it was closed
+                "wait_order_update",
+                async |_module: &PredictionMarkets, context, params: (OrderId, u64)| -> Order {
+                    let (order, filled) = params;
+                    Ok(context
+                        .wait_value_matches(OrderKey(order), move |order| {
+                            order.filled > filled || order.state != OrderState::Open
+                        })
+                        .await)
+                }
+            },
+            api_endpoint! {
+                "wait_market_resolved",
+                async |_module: &PredictionMarkets, context, market: MarketId| -> Resolution {
+                    let market = context
+                        .wait_value_matches(MarketKey(market), |market| {
+                            matches!(market.state, MarketState::Resolved(_))
+                        })
+                        .await;
+                    match market.state {
+                        MarketState::Resolved(resolution) => Ok(resolution),
+                        _ => Err(ApiError::server_error("Market not resolved".to_string())),
+                    }
+                }
+            },
+            api_endpoint! {
+                "balance",
+                async |_module: &PredictionMarkets, context, owner: XOnlyPublicKey| -> Amount {
+                    Ok(balance(&mut context.dbtx(), owner).await)
+                }
+            },
+            api_endpoint! {
+                // Shares of each outcome, empty if the market doesn't exist
+                "shares",
+                async |_module: &PredictionMarkets, context, params: (MarketId, XOnlyPublicKey)| -> Vec<u64> {
+                    let (market_id, owner) = params;
+                    let mut dbtx = context.dbtx();
+                    match dbtx.get_value(&MarketKey(market_id)).await {
+                        Some(market) => Ok(shares(&mut dbtx, market_id, &market, owner).await),
+                        None => Ok(vec![]),
+                    }
+                }
+            },
+        ]
+    }
+}
+
+/// An in-memory cache we could use for faster validation
+#[derive(Debug, Clone)]
+pub struct MarketVerificationCache;
+
+impl fedimint_core::server::VerificationCache for MarketVerificationCache {}
+
+impl PredictionMarkets {
+    /// Create new module instance
+    pub fn new(cfg: MarketConfig, interconnect: DynModuleInterconnect) -> PredictionMarkets {
+        PredictionMarkets { cfg, interconnect }
+    }
+
+    /// The resolution of a market according to the oracle, `None` while its
+    /// round wasn't attested
+    async fn oracle_resolution(
+        &self,
+        params: &MarketParams,
+    ) -> Result<Option<Resolution>, ApiError> {
+        if let Some(outcome) = self
+            .interconnect
+            .oracle_outcome(&params.feed, params.round)
+            .await?
+        {
+            return Ok(Some(params.rule.resolve(&outcome.value)));
+        }
+
+        // The oracle skips rounds it couldn't observe, they never get an outcome
+        let latest = self
+            .interconnect
+            .latest_oracle_outcome(&params.feed)
+            .await?;
+        Ok(latest
+            .filter(|outcome| outcome.round > params.round)
+            .map(|_| Resolution::Void))
+    }
+
+    /// Stops trading once the first guardian knows the outcome, returning the
+    /// funds and shares held by open orders to their owners
+    async fn close_market(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        market_id: MarketId,
+        market: &mut Market,
+    ) {
+        let open_orders = dbtx
+            .find_by_prefix(&OpenOrderMarketPrefix { market: market_id })
+            .await
+            .collect::<Vec<_>>()
+            .await;
+        for (key, order) in open_orders {
+            cancel_order(dbtx, key.order, order).await;
+        }
+
+        market.state = MarketState::Closed;
+        dbtx.insert_entry(&MarketKey(market_id), market).await;
+    }
+
+    /// Pays out the shares of a closed market
+    async fn resolve_market(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        market_id: MarketId,
+        mut market: Market,
+        resolution: Resolution,
+    ) {
+        let contract_price = market.params.contract_price.msats as u128;
+        let outcomes = market.params.outcomes.len() as u128;
+        let holdings = dbtx
+            .find_by_prefix(&MarketSharesPrefix { market: market_id })
+            .await
+            .collect::<Vec<_>>()
+            .await;
+
+        for (key, shares) in holdings {
+            let payout = match resolution {
+                Resolution::Outcome(outcome) => contract_price * shares[outcome as usize] as u128,
+                // Every set pays out the contract price, split between its shares
+                Resolution::Void => {
+                    contract_price * shares.iter().map(|s| *s as u128).sum::<u128>() / outcomes
+                }
+            };
+            let balance = balance(dbtx, key.owner).await;
+            save_balance(dbtx, key.owner, balance + Amount::from_msats(payout as u64)).await;
+            dbtx.remove_entry(&key).await;
+        }
+
+        dbtx.remove_by_prefix(&ResolutionVoteMarketPrefix { market: market_id })
+            .await;
+        market.state = MarketState::Resolved(resolution);
+        market.open_sets = 0;
+        dbtx.insert_entry(&MarketKey(market_id), &market).await;
+
+        info!(market = %market_id, %resolution, "Resolved prediction market");
+    }
+}
+
+async fn get_market(
+    dbtx: &mut ModuleDatabaseTransaction<'_>,
+    market_id: MarketId,
+) -> Result<Market, ModuleError> {
+    dbtx.get_value(&MarketKey(market_id))
+        .await
+        .ok_or(MarketError::UnknownMarket)
+        .into_module_error()
+}
+
+/// Checks a new order can be placed in the market
+async fn validate_order(
+    dbtx: &mut ModuleDatabaseTransaction<'_>,
+    market: &Market,
+    order: &NewOrder,
+) -> Result<(), ModuleError> {
+    if market.state != MarketState::Open {
+        return Err(MarketError::MarketNotOpen).into_module_error();
+    }
+    if order.outcome as usize >= market.params.outcomes.len() {
+        return Err(MarketError::UnknownOutcome(order.outcome)).into_module_error();
+    }
+    // A share never pays out more than the contract price
+    if order.price == Amount::ZERO || order.price >= market.params.contract_price {
+        return Err(MarketError::InvalidPrice(market.params.contract_price)).into_module_error();
+    }
+    if order.quantity == 0 {
+        return Err(MarketError::ZeroQuantity).into_module_error();
+    }
+    if order.total().is_none() {
+        return Err(MarketError::Overflow).into_module_error();
+    }
+    if dbtx.get_value(&OrderKey(order.id)).await.is_some() {
+        return Err(MarketError::OrderExists).into_module_error();
+    }
+    Ok(())
+}
+
+/// Matches a new order against the order book and rests the rest of it there
+///
+/// Trades happen at the price of the resting order, so buyers are refunded the
+/// difference to their own price.
+async fn place_order(
+    dbtx: &mut ModuleDatabaseTransaction<'_>,
+    market: &Market,
+    new_order: &NewOrder,
+    side: Side,
+) {
+    let sequence = dbtx.get_value(&OrderSequenceKey).await.unwrap_or_default();
+    dbtx.insert_entry(&OrderSequenceKey, &(sequence + 1)).await;
+
+    let mut taker = Order {
+        owner: new_order.owner,
+        market: new_order.market,
+        outcome: new_order.outcome,
+        side,
+        price: new_order.price,
+        quantity: new_order.quantity,
+        filled: 0,
+        state: OrderState::Open,
+        sequence,
+    };
+    let book = dbtx
+        .find_by_prefix(&OpenOrderOutcomePrefix {
+            market: taker.market,
+            outcome: taker.outcome,
+        })
+        .await
+        .map(|(key, order)| (key.order, order))
+        .collect::<Vec<_>>()
+        .await;
+
+    for fill in match_order(&taker, &book) {
+        let (_, maker) = book
+            .iter()
+            .find(|(id, _)| *id == fill.maker)
+            .expect("Fills are taken from the book");
+        let mut maker = maker.clone();
+        maker.filled += fill.quantity;
+        if maker.filled == maker.quantity {
+            maker.state = OrderState::Filled;
+        }
+        taker.filled += fill.quantity;
+
+        let (buyer, seller) = match side {
+            Side::Buy => (taker.owner, maker.owner),
+            Side::Sell => (maker.owner, taker.owner),
+        };
+        let seller_balance = balance(dbtx, seller).await;
+        save_balance(dbtx, seller, seller_balance + fill.price * fill.quantity).await;
+        let mut buyer_shares = shares(dbtx, taker.market, market, buyer).await;
+        buyer_shares[taker.outcome as usize] += fill.quantity;
+        save_shares(dbtx, taker.market, buyer, buyer_shares).await;
+        if side == Side::Buy {
+            let refund = (taker.price - fill.price) * fill.quantity;
+            let buyer_balance = balance(dbtx, buyer).await;
+            save_balance(dbtx, buyer, buyer_balance + refund).await;
+        }
+
+        save_order(dbtx, fill.maker, &maker).await;
+    }
+
+    if taker.filled == taker.quantity {
+        taker.state = OrderState::Filled;
+    }
+    save_order(dbtx, new_order.id, &taker).await;
+}
+
+/// Returns the funds or shares held by an open order to its owner
+async fn cancel_order(
+    dbtx: &mut ModuleDatabaseTransaction<'_>,
+    order_id: OrderId,
+    mut order: Order,
+) {
+    match order.side {
+        Side::Buy => {
+            let balance = balance(dbtx, order.owner).await;
+            save_balance(dbtx, order.owner, balance + order.locked_funds()).await;
+        }
+        Side::Sell => {
+            let market = dbtx
+                .get_value(&MarketKey(order.market))
+                .await
+                .expect("Orders belong to a market");
+            let mut shares = shares(dbtx, order.market, &market, order.owner).await;
+            shares[order.outcome as usize] += order.remaining();
+            save_shares(dbtx, order.market, order.owner, shares).await;
+        }
+    }
+
+    order.state = OrderState::Cancelled;
+    save_order(dbtx, order_id, &order).await;
+}
+
+/// Stores the order and keeps the order book in sync with it
+async fn save_order(dbtx: &mut ModuleDatabaseTransaction<'_>, order_id: OrderId, order: &Order) {
+    dbtx.insert_entry(&OrderKey(order_id), order).await;
+
+    let book_key = OpenOrderKey {
+        market: order.market,
+        outcome: order.outcome,
+        order: order_id,
+    };
+    if order.state == OrderState::Open {
+        dbtx.insert_entry(&book_key, order).await;
+    } else {
+        dbtx.remove_entry(&book_key).await;
+    }
+}
+
+async fn balance(dbtx: &mut ModuleDatabaseTransaction<'_>, owner: XOnlyPublicKey) -> Amount {
+    dbtx.get_value(&BalanceKey(owner))
+        .await
+        .unwrap_or(Amount::ZERO)
+}
+
+/// Stores the balance, removing it once it is zero
+async fn save_balance(
+    dbtx: &mut ModuleDatabaseTransaction<'_>,
+    owner: XOnlyPublicKey,
+    balance: Amount,
+) {
+    if balance == Amount::ZERO {
+        dbtx.remove_entry(&BalanceKey(owner)).await;
+    } else {
+        dbtx.insert_entry(&BalanceKey(owner), &balance).await;
+    }
+}
+
+/// The shares of each outcome of the market held by `owner`
+async fn shares(
+    dbtx: &mut ModuleDatabaseTransaction<'_>,
+    market_id: MarketId,
+    market: &Market,
+    owner: XOnlyPublicKey,
+) -> Vec<u64> {
+    dbtx.get_value(&SharesKey {
+        market: market_id,
+        owner,
+    })
+    .await
+    .unwrap_or_else(|| vec![0; market.params.outcomes.len()])
+}
+
+/// Stores the shares, removing them once there are none left
+async fn save_shares(
+    dbtx: &mut ModuleDatabaseTransaction<'_>,
+    market_id: MarketId,
+    owner: XOnlyPublicKey,
+    shares: Vec<u64>,
+) {
+    let key = SharesKey {
+        market: market_id,
+        owner,
+    };
+    if shares.iter().all(|shares| *shares == 0) {
+        dbtx.remove_entry(&key).await;
+    } else {
+        dbtx.insert_entry(&key, &shares).await;
+    }
+}
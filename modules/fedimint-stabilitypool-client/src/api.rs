@@ -0,0 +1,39 @@
+use fedimint_core::api::{FederationApiExt, FederationResult, IModuleFederationApi};
+use fedimint_core::module::ApiRequestErased;
+use fedimint_core::task::{MaybeSend, MaybeSync};
+use fedimint_core::{apply, async_trait_maybe_send};
+use fedimint_stabilitypool_common::{Account, PoolEpoch};
+use secp256k1::XOnlyPublicKey;
+
+#[apply(async_trait_maybe_send!)]
+pub trait PoolFederationApi {
+    async fn pool_account(&self, account: XOnlyPublicKey) -> FederationResult<Account>;
+
+    async fn current_pool_epoch(&self) -> FederationResult<PoolEpoch>;
+
+    async fn wait_pool_epoch_settled(&self, epoch_id: u64) -> FederationResult<PoolEpoch>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<T: ?Sized> PoolFederationApi for T
+where
+    T: IModuleFederationApi + MaybeSend + MaybeSync + 'static,
+{
+    async fn pool_account(&self, account: XOnlyPublicKey) -> FederationResult<Account> {
+        self.request_current_consensus("account".to_string(), ApiRequestErased::new(account))
+            .await
+    }
+
+    async fn current_pool_epoch(&self) -> FederationResult<PoolEpoch> {
+        self.request_current_consensus("current_epoch".to_string(), ApiRequestErased::default())
+            .await
+    }
+
+    async fn wait_pool_epoch_settled(&self, epoch_id: u64) -> FederationResult<PoolEpoch> {
+        self.request_current_consensus(
+            "wait_epoch_settled".to_string(),
+            ApiRequestErased::new(epoch_id),
+        )
+        .await
+    }
+}
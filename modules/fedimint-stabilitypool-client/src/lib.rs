@@ -0,0 +1,364 @@
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail};
+use async_stream::stream;
+use fedimint_client::derivable_secret::DerivableSecret;
+use fedimint_client::module::gen::ClientModuleGen;
+use fedimint_client::module::ClientModule;
+use fedimint_client::oplog::{OperationLogEntry, UpdateStreamOrOutcome};
+use fedimint_client::sm::{Context, ModuleNotifier, OperationId};
+use fedimint_client::transaction::{ClientInput, ClientOutput, TransactionBuilder};
+use fedimint_client::{Client, DynGlobalClientContext};
+use fedimint_core::api::{DynGlobalApi, DynModuleApi};
+use fedimint_core::core::{IntoDynInstance, KeyPair};
+use fedimint_core::db::Database;
+use fedimint_core::module::{
+    ApiVersion, ExtendsCommonModuleGen, ModuleCommon, MultiApiVersion, TransactionItemAmount,
+};
+use fedimint_core::util::NextOrPending;
+use fedimint_core::{apply, async_trait_maybe_send, Amount, OutPoint, TransactionId};
+pub use fedimint_stabilitypool_common as common;
+use fedimint_stabilitypool_common::config::PoolClientConfig;
+use fedimint_stabilitypool_common::{
+    Account, PoolCommonGen, PoolEpoch, PoolInput, PoolModuleTypes, PoolOutput, Side, KIND,
+};
+use futures::{pin_mut, StreamExt};
+use secp256k1::{Secp256k1, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use states::PoolStateMachine;
+
+use crate::api::PoolFederationApi;
+
+pub mod api;
+mod states;
+
+/// Exposed API calls for client apps
+#[apply(async_trait_maybe_send!)]
+pub trait PoolClientExt {
+    /// Deposits ecash of the primary module into a side of the pool, the
+    /// deposit takes part in settlements once the current epoch ended
+    async fn deposit_to_pool(&self, side: Side, amount: Amount) -> anyhow::Result<OperationId>;
+
+    /// Takes all funds of a side out of the pool, see [`PoolInput::Unlock`]
+    async fn unlock_pool_side(&self, side: Side) -> anyhow::Result<OperationId>;
+
+    /// Withdraws unlocked funds from the pool into ecash of the primary module
+    async fn withdraw_from_pool(&self, amount: Amount) -> anyhow::Result<OperationId>;
+
+    /// Subscribe to the state of an operation started by one of the functions
+    /// above
+    async fn subscribe_pool_operation(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<UpdateStreamOrOutcome<'_, PoolOperationState>>;
+
+    /// Returns our funds in the pool
+    async fn pool_account(&self) -> anyhow::Result<Account>;
+
+    /// Returns the currently running epoch of the pool
+    async fn pool_epoch(&self) -> anyhow::Result<PoolEpoch>;
+
+    /// Waits until the epoch with the given id was settled and returns the
+    /// next one
+    async fn await_pool_epoch_settled(&self, epoch_id: u64) -> anyhow::Result<PoolEpoch>;
+}
+
+/// The high-level state of a pool operation
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum PoolOperationState {
+    /// The transaction was submitted to the federation
+    Created,
+    /// The transaction was accepted and the ecash for withdrawn funds or change
+    /// was issued
+    Done,
+    /// The transaction was rejected or the ecash couldn't be issued
+    Failed(String),
+}
+
+/// Stored in the operation log to show pool operations to the user
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolMeta {
+    pub txid: TransactionId,
+    /// The output of the primary module receiving withdrawn funds or change
+    pub change: Option<OutPoint>,
+    pub variant: PoolMetaVariant,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PoolMetaVariant {
+    Deposit { side: Side, amount: Amount },
+    Unlock { side: Side },
+    Withdraw { amount: Amount },
+}
+
+#[apply(async_trait_maybe_send!)]
+impl PoolClientExt for Client {
+    async fn deposit_to_pool(&self, side: Side, amount: Amount) -> anyhow::Result<OperationId> {
+        let (pool, instance) = self.get_first_module::<PoolClientModule>(&KIND);
+        let operation_id = OperationId(rand::random());
+
+        // The primary module funds the deposit
+        let output = ClientOutput {
+            output: PoolOutput {
+                account: pool.account(),
+                side,
+                amount,
+            },
+            state_machines: Arc::new(move |txid, _| {
+                vec![PoolStateMachine::Submitted(txid, operation_id)]
+            }),
+        };
+        let tx = TransactionBuilder::new().with_output(output.into_dyn(instance.id));
+
+        self.submit_pool_transaction(operation_id, PoolMetaVariant::Deposit { side, amount }, tx)
+            .await
+    }
+
+    async fn unlock_pool_side(&self, side: Side) -> anyhow::Result<OperationId> {
+        let (pool, instance) = self.get_first_module::<PoolClientModule>(&KIND);
+        let operation_id = OperationId(rand::random());
+
+        let input = pool.create_input(
+            operation_id,
+            PoolInput::Unlock {
+                account: pool.account(),
+                side,
+            },
+        );
+        let tx = TransactionBuilder::new().with_input(input.into_dyn(instance.id));
+
+        self.submit_pool_transaction(operation_id, PoolMetaVariant::Unlock { side }, tx)
+            .await
+    }
+
+    async fn withdraw_from_pool(&self, amount: Amount) -> anyhow::Result<OperationId> {
+        let (pool, instance) = self.get_first_module::<PoolClientModule>(&KIND);
+        let operation_id = OperationId(rand::random());
+
+        // The primary module receives the withdrawn funds as change
+        let input = pool.create_input(
+            operation_id,
+            PoolInput::Withdraw {
+                account: pool.account(),
+                amount,
+            },
+        );
+        let tx = TransactionBuilder::new().with_input(input.into_dyn(instance.id));
+
+        self.submit_pool_transaction(operation_id, PoolMetaVariant::Withdraw { amount }, tx)
+            .await
+    }
+
+    async fn subscribe_pool_operation(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<UpdateStreamOrOutcome<'_, PoolOperationState>> {
+        let (pool, _instance) = self.get_first_module::<PoolClientModule>(&KIND);
+        let operation = pool_operation(self, operation_id).await?;
+        let meta = operation.meta::<PoolMeta>();
+
+        let tx_accepted_future = pool.await_tx_accepted(operation_id);
+        let ecash_issued_future = meta
+            .change
+            .map(|out_point| self.await_primary_module_output(operation_id, out_point));
+
+        Ok(operation.outcome_or_updates(self.db(), operation_id, || {
+            stream! {
+                yield PoolOperationState::Created;
+
+                match tx_accepted_future.await {
+                    Ok(()) => match ecash_issued_future {
+                        Some(ecash_issued_future) => match ecash_issued_future.await {
+                            Ok(_) => yield PoolOperationState::Done,
+                            Err(e) => yield PoolOperationState::Failed(e.to_string()),
+                        },
+                        None => yield PoolOperationState::Done,
+                    },
+                    Err(e) => yield PoolOperationState::Failed(e),
+                }
+            }
+        }))
+    }
+
+    async fn pool_account(&self) -> anyhow::Result<Account> {
+        let (pool, instance) = self.get_first_module::<PoolClientModule>(&KIND);
+        Ok(instance.api.pool_account(pool.account()).await?)
+    }
+
+    async fn pool_epoch(&self) -> anyhow::Result<PoolEpoch> {
+        let (_pool, instance) = self.get_first_module::<PoolClientModule>(&KIND);
+        Ok(instance.api.current_pool_epoch().await?)
+    }
+
+    async fn await_pool_epoch_settled(&self, epoch_id: u64) -> anyhow::Result<PoolEpoch> {
+        let (_pool, instance) = self.get_first_module::<PoolClientModule>(&KIND);
+        Ok(instance.api.wait_pool_epoch_settled(epoch_id).await?)
+    }
+}
+
+#[apply(async_trait_maybe_send!)]
+trait PoolClientExtPrivate {
+    async fn submit_pool_transaction(
+        &self,
+        operation_id: OperationId,
+        variant: PoolMetaVariant,
+        tx: TransactionBuilder,
+    ) -> anyhow::Result<OperationId>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl PoolClientExtPrivate for Client {
+    async fn submit_pool_transaction(
+        &self,
+        operation_id: OperationId,
+        variant: PoolMetaVariant,
+        tx: TransactionBuilder,
+    ) -> anyhow::Result<OperationId> {
+        let operation_meta = move |txid, change| PoolMeta {
+            txid,
+            change,
+            variant: variant.clone(),
+        };
+        self.finalize_and_submit_transaction(operation_id, KIND.as_str(), operation_meta, tx)
+            .await?;
+        Ok(operation_id)
+    }
+}
+
+async fn pool_operation(
+    client: &Client,
+    operation_id: OperationId,
+) -> anyhow::Result<OperationLogEntry> {
+    let operation = client
+        .operation_log()
+        .get_operation(operation_id)
+        .await
+        .ok_or(anyhow!("Operation not found"))?;
+
+    if operation.operation_type() != KIND.as_str() {
+        bail!("Operation is not a stability pool operation");
+    }
+
+    Ok(operation)
+}
+
+#[derive(Debug)]
+pub struct PoolClientModule {
+    cfg: PoolClientConfig,
+    key: KeyPair,
+    notifier: ModuleNotifier<DynGlobalClientContext, PoolStateMachine>,
+}
+
+/// Data needed by the state machine
+#[derive(Debug, Clone)]
+pub struct PoolClientContext;
+
+// TODO: Boiler-plate
+impl Context for PoolClientContext {}
+
+#[apply(async_trait_maybe_send!)]
+impl ClientModule for PoolClientModule {
+    type Common = PoolModuleTypes;
+    type ModuleStateMachineContext = PoolClientContext;
+    type States = PoolStateMachine;
+
+    fn context(&self) -> Self::ModuleStateMachineContext {
+        PoolClientContext
+    }
+
+    fn input_amount(&self, input: &<Self::Common as ModuleCommon>::Input) -> TransactionItemAmount {
+        match input {
+            PoolInput::Withdraw { amount, .. } => TransactionItemAmount {
+                amount: *amount,
+                fee: self.cfg.tx_fee,
+            },
+            PoolInput::Unlock { .. } => TransactionItemAmount::ZERO,
+        }
+    }
+
+    fn output_amount(
+        &self,
+        output: &<Self::Common as ModuleCommon>::Output,
+    ) -> TransactionItemAmount {
+        TransactionItemAmount {
+            amount: output.amount,
+            fee: self.cfg.tx_fee,
+        }
+    }
+}
+
+impl PoolClientModule {
+    /// The account holding our funds in the pool
+    fn account(&self) -> XOnlyPublicKey {
+        self.key.x_only_public_key().0
+    }
+
+    fn create_input(
+        &self,
+        operation_id: OperationId,
+        input: PoolInput,
+    ) -> ClientInput<PoolInput, PoolStateMachine> {
+        ClientInput {
+            input,
+            keys: vec![self.key],
+            state_machines: Arc::new(move |txid, _| {
+                vec![PoolStateMachine::Submitted(txid, operation_id)]
+            }),
+        }
+    }
+
+    async fn await_tx_accepted(&self, operation_id: OperationId) -> Result<(), String> {
+        let stream = self
+            .notifier
+            .subscribe(operation_id)
+            .await
+            .filter_map(|state| async move {
+                match state {
+                    PoolStateMachine::Accepted(_) => Some(Ok(())),
+                    PoolStateMachine::Rejected(_, e) => Some(Err(e)),
+                    PoolStateMachine::Submitted(_, _) => None,
+                }
+            });
+
+        pin_mut!(stream);
+
+        stream.next_or_pending().await
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PoolClientGen;
+
+impl ExtendsCommonModuleGen for PoolClientGen {
+    type Common = PoolCommonGen;
+}
+
+/// Generates the client module
+#[apply(async_trait_maybe_send!)]
+impl ClientModuleGen for PoolClientGen {
+    type Module = PoolClientModule;
+    type Config = PoolClientConfig;
+
+    fn supported_api_versions(&self) -> MultiApiVersion {
+        MultiApiVersion::try_from_iter([ApiVersion { major: 0, minor: 0 }])
+            .expect("no version conficts")
+    }
+
+    async fn init(
+        &self,
+        cfg: Self::Config,
+        _db: Database,
+        _api_version: ApiVersion,
+        module_root_secret: DerivableSecret,
+        notifier: ModuleNotifier<DynGlobalClientContext, <Self::Module as ClientModule>::States>,
+        _api: DynGlobalApi,
+        _module_api: DynModuleApi,
+    ) -> anyhow::Result<Self::Module> {
+        Ok(PoolClientModule {
+            cfg,
+            key: module_root_secret.to_secp_key(&Secp256k1::new()),
+            notifier,
+        })
+    }
+}
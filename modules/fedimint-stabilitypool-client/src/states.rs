@@ -0,0 +1,69 @@
+use fedimint_client::sm::{DynState, OperationId, State, StateTransition};
+use fedimint_client::DynGlobalClientContext;
+use fedimint_core::core::{IntoDynInstance, ModuleInstanceId};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::TransactionId;
+
+use crate::PoolClientContext;
+
+/// Tracks a transaction that deposits into, unlocks or withdraws from the pool
+///
+/// The funds themselves are held by the federation, so unlike the ecash
+/// modules there is nothing to refund if the transaction is rejected.
+#[derive(Debug, Clone, Eq, PartialEq, Decodable, Encodable)]
+pub enum PoolStateMachine {
+    Submitted(TransactionId, OperationId),
+    Accepted(OperationId),
+    Rejected(OperationId, String),
+}
+
+impl State for PoolStateMachine {
+    type ModuleContext = PoolClientContext;
+    type GlobalContext = DynGlobalClientContext;
+
+    fn transitions(
+        &self,
+        _context: &Self::ModuleContext,
+        global_context: &Self::GlobalContext,
+    ) -> Vec<StateTransition<Self>> {
+        match self.clone() {
+            PoolStateMachine::Submitted(txid, id) => vec![StateTransition::new(
+                await_tx_accepted(global_context.clone(), id, txid),
+                move |_dbtx, res, _state: Self| match res {
+                    Ok(()) => Box::pin(async move { PoolStateMachine::Accepted(id) }),
+                    Err(e) => Box::pin(async move { PoolStateMachine::Rejected(id, e) }),
+                },
+            )],
+            PoolStateMachine::Accepted(_) => vec![],
+            PoolStateMachine::Rejected(_, _) => vec![],
+        }
+    }
+
+    fn operation_id(&self) -> OperationId {
+        match self {
+            PoolStateMachine::Submitted(_, id) => *id,
+            PoolStateMachine::Accepted(id) => *id,
+            PoolStateMachine::Rejected(id, _) => *id,
+        }
+    }
+}
+
+async fn await_tx_accepted(
+    context: DynGlobalClientContext,
+    id: OperationId,
+    txid: TransactionId,
+) -> Result<(), String> {
+    context
+        .await_tx_accepted(id, txid)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+// TODO: Boiler-plate
+impl IntoDynInstance for PoolStateMachine {
+    type DynType = DynState<DynGlobalClientContext>;
+
+    fn into_dyn(self, instance_id: ModuleInstanceId) -> Self::DynType {
+        DynState::from_typed(instance_id, self)
+    }
+}
@@ -0,0 +1,105 @@
+use fedimint_core::core::ModuleKind;
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{plugin_types_trait_impl_config, Amount};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use crate::PoolCommonGen;
+
+/// Parameters necessary to generate this module's configuration
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolGenParams {
+    pub local: PoolGenParamsLocal,
+    pub consensus: PoolGenParamsConsensus,
+}
+
+/// Local parameters for config generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolGenParamsLocal {
+    pub price_source: PriceSource,
+}
+
+/// Consensus parameters for config generation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PoolGenParamsConsensus {
+    /// Length of a settlement epoch in seconds
+    pub epoch_length: u64,
+    /// Fee seekers pay providers every epoch, in parts per million
+    pub seeker_fee_ppm: u64,
+    pub tx_fee: Amount,
+}
+
+impl Default for PoolGenParams {
+    fn default() -> Self {
+        Self {
+            local: PoolGenParamsLocal {
+                price_source: PriceSource::Fixed { price: 3_000_000 },
+            },
+            consensus: PoolGenParamsConsensus {
+                epoch_length: 600,
+                seeker_fee_ppm: 100,
+                tx_fee: Amount::ZERO,
+            },
+        }
+    }
+}
+
+/// Where a guardian gets the bitcoin price in cents from
+#[derive(Clone, Debug, Serialize, Deserialize, Decodable, Encodable)]
+pub enum PriceSource {
+    /// Always reports the same price, for testing
+    Fixed { price: u64 },
+    /// Fetches JSON from `url` and reads the price in dollars at the JSON
+    /// `pointer`, e.g. `/data/amount`
+    Http { url: Url, pointer: String },
+}
+
+/// Contains all the configuration for the server
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PoolConfig {
+    pub local: PoolConfigLocal,
+    pub private: PoolConfigPrivate,
+    pub consensus: PoolConfigConsensus,
+}
+
+/// Contains all the configuration for the client
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct PoolClientConfig {
+    pub epoch_length: u64,
+    pub seeker_fee_ppm: u64,
+    pub tx_fee: Amount,
+}
+
+/// Locally unencrypted config unique to each member
+#[derive(Clone, Debug, Serialize, Deserialize, Decodable, Encodable)]
+pub struct PoolConfigLocal {
+    pub price_source: PriceSource,
+}
+
+/// Will be the same for every federation member
+#[derive(Clone, Debug, Serialize, Deserialize, Decodable, Encodable)]
+pub struct PoolConfigConsensus {
+    pub epoch_length: u64,
+    pub seeker_fee_ppm: u64,
+    pub tx_fee: Amount,
+    /// Number of price observations required to settle an epoch
+    pub threshold: u64,
+}
+
+/// Will be encrypted and not shared such as private key material, the pool
+/// doesn't need any
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PoolConfigPrivate {}
+
+// Wire together the configs for this module
+plugin_types_trait_impl_config!(
+    PoolCommonGen,
+    PoolGenParams,
+    PoolGenParamsLocal,
+    PoolGenParamsConsensus,
+    PoolConfig,
+    PoolConfigLocal,
+    PoolConfigPrivate,
+    PoolConfigConsensus,
+    PoolClientConfig
+);
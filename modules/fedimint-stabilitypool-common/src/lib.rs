@@ -0,0 +1,274 @@
+use std::collections::BTreeSet;
+use std::fmt;
+
+use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::error::{code, ModuleErrorCode};
+use fedimint_core::module::{CommonModuleGen, ModuleCommon, ModuleConsensusVersion};
+use fedimint_core::{plugin_types_trait_impl_common, Amount};
+use secp256k1::XOnlyPublicKey;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+// Common contains types shared by both the client and server
+
+// The client and server configuration
+pub mod config;
+
+// Payoff of an epoch between seekers and providers
+pub mod settlement;
+
+/// Unique name for this module
+pub const KIND: ModuleKind = ModuleKind::from_static_str("stabilitypool");
+
+/// Modules are non-compatible with older versions
+pub const CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion(0);
+
+/// The two sides of the pool
+///
+/// Seekers want to keep the fiat value of their deposit and pay providers a
+/// fee for it. Providers take the other side of the price movement, so they
+/// gain what seekers lose and vice versa.
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Hash,
+    Ord,
+    PartialOrd,
+    Serialize,
+    Deserialize,
+    Encodable,
+    Decodable,
+)]
+pub enum Side {
+    Seeker,
+    Provider,
+}
+
+/// Funds an account holds on each side of the pool
+#[derive(
+    Debug, Clone, Copy, Default, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable,
+)]
+pub struct Position {
+    pub seeker: Amount,
+    pub provider: Amount,
+}
+
+impl Position {
+    pub fn get(&self, side: Side) -> Amount {
+        match side {
+            Side::Seeker => self.seeker,
+            Side::Provider => self.provider,
+        }
+    }
+
+    pub fn get_mut(&mut self, side: Side) -> &mut Amount {
+        match side {
+            Side::Seeker => &mut self.seeker,
+            Side::Provider => &mut self.provider,
+        }
+    }
+
+    pub fn total(&self) -> Amount {
+        self.seeker + self.provider
+    }
+}
+
+impl std::ops::Add for Position {
+    type Output = Position;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Position {
+            seeker: self.seeker + rhs.seeker,
+            provider: self.provider + rhs.provider,
+        }
+    }
+}
+
+/// Funds of a user in the pool, identified by the key that signs for them
+#[derive(
+    Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable,
+)]
+pub struct Account {
+    /// Deposits that join the pool when the current epoch ends
+    pub staged: Position,
+    /// Funds in the current epoch, paid out according to the price movement
+    /// when it ends
+    pub locked: Position,
+    /// Sides whose locked funds leave the pool when the current epoch ends
+    pub unlocking: BTreeSet<Side>,
+    /// Funds that can be withdrawn into ecash
+    pub unlocked: Amount,
+}
+
+impl Account {
+    /// All funds of the account, the federation owes them to the user
+    pub fn total(&self) -> Amount {
+        self.staged.total() + self.locked.total() + self.unlocked
+    }
+}
+
+/// A settlement period of the pool, the payouts at its end depend on how the
+/// price moved since its start
+///
+/// The default first epoch holds no funds and ends right away to get a price.
+#[derive(
+    Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable,
+)]
+pub struct PoolEpoch {
+    pub id: u64,
+    /// Price at the start of the epoch in cents per bitcoin, unknown before
+    /// the first settlement
+    pub start_price: Option<u64>,
+    /// Unix timestamp in seconds at which the epoch started
+    pub start_time: u64,
+}
+
+impl PoolEpoch {
+    /// Unix timestamp in seconds after which guardians propose to settle
+    pub fn end_time(&self, epoch_length: u64) -> u64 {
+        self.start_time.saturating_add(epoch_length)
+    }
+}
+
+/// Input for a fedimint transaction
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub enum PoolInput {
+    /// Withdraws unlocked funds of the account into ecash
+    Withdraw {
+        account: XOnlyPublicKey,
+        amount: Amount,
+    },
+    /// Takes all funds of a side out of the pool, staged funds right away and
+    /// locked funds when the current epoch ends, since their amount depends on
+    /// its settlement
+    ///
+    /// Doesn't contribute any funds to the transaction, it is an input only so
+    /// the transaction has to be signed by the account.
+    Unlock { account: XOnlyPublicKey, side: Side },
+}
+
+impl PoolInput {
+    pub fn account(&self) -> XOnlyPublicKey {
+        match self {
+            PoolInput::Withdraw { account, .. } | PoolInput::Unlock { account, .. } => *account,
+        }
+    }
+}
+
+/// Output for a fedimint transaction, deposits into a side of the pool
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct PoolOutput {
+    pub account: XOnlyPublicKey,
+    pub side: Side,
+    pub amount: Amount,
+}
+
+/// Information needed by a client to follow its deposit
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
+pub struct PoolOutputOutcome {
+    pub account: XOnlyPublicKey,
+    /// The epoch the deposit was staged in, it joins the pool when it ends
+    pub epoch_id: u64,
+}
+
+/// Non-transaction items that will be submitted to consensus
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct PoolConsensusItem {
+    /// The epoch the guardian wants to end
+    pub epoch_id: u64,
+    /// Price observed by the guardian in cents per bitcoin
+    pub price: u64,
+    /// Unix timestamp in seconds of the observation
+    pub time: u64,
+}
+
+/// Errors that might be returned by the server
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Error)]
+pub enum PoolError {
+    #[error("Unknown account")]
+    UnknownAccount,
+    #[error("Not enough unlocked funds, {0} available")]
+    NotEnoughUnlocked(Amount),
+    #[error("No funds on the {0} side")]
+    NothingToUnlock(Side),
+    #[error("Deposit must not be zero")]
+    ZeroDeposit,
+}
+
+impl ModuleErrorCode for PoolError {
+    fn code(&self) -> i32 {
+        match self {
+            PoolError::UnknownAccount => code::NOT_FOUND,
+            PoolError::NotEnoughUnlocked(_) | PoolError::NothingToUnlock(_) => code::CONFLICT,
+            PoolError::ZeroDeposit => code::BAD_REQUEST,
+        }
+    }
+}
+
+/// Contains the types defined above
+pub struct PoolModuleTypes;
+
+// Wire together the types for this module
+plugin_types_trait_impl_common!(
+    PoolModuleTypes,
+    PoolInput,
+    PoolOutput,
+    PoolOutputOutcome,
+    PoolConsensusItem
+);
+
+#[derive(Debug)]
+pub struct PoolCommonGen;
+
+impl CommonModuleGen for PoolCommonGen {
+    const CONSENSUS_VERSION: ModuleConsensusVersion = CONSENSUS_VERSION;
+    const KIND: ModuleKind = KIND;
+
+    fn decoder() -> Decoder {
+        PoolModuleTypes::decoder_builder().build()
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Seeker => write!(f, "seeker"),
+            Side::Provider => write!(f, "provider"),
+        }
+    }
+}
+
+impl fmt::Display for PoolInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PoolInput::Withdraw { amount, .. } => write!(f, "PoolInput Withdraw {amount}"),
+            PoolInput::Unlock { side, .. } => write!(f, "PoolInput Unlock {side}"),
+        }
+    }
+}
+
+impl fmt::Display for PoolOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PoolOutput Deposit {} {}", self.amount, self.side)
+    }
+}
+
+impl fmt::Display for PoolOutputOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PoolOutputOutcome epoch {}", self.epoch_id)
+    }
+}
+
+impl fmt::Display for PoolConsensusItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "PoolConsensusItem epoch {} price {}",
+            self.epoch_id, self.price
+        )
+    }
+}
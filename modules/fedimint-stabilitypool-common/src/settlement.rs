@@ -0,0 +1,95 @@
+use fedimint_core::Amount;
+
+use crate::Position;
+
+/// One million, the denominator of fees in parts per million
+const PPM: u128 = 1_000_000;
+
+/// Splits the funds `locked` in an epoch between the two sides after the
+/// price moved from `start_price` to `end_price`, returning the new total of
+/// each side
+///
+/// Seekers first pay providers `seeker_fee_ppm` of their funds. The rest keeps
+/// its value in the fiat currency, as far as the funds of the providers cover
+/// it. Without providers nobody takes the other side, so seekers keep their
+/// funds and pay no fee.
+pub fn settle(locked: Position, start_price: u64, end_price: u64, seeker_fee_ppm: u64) -> Position {
+    if locked.provider == Amount::ZERO || start_price == 0 || end_price == 0 {
+        return locked;
+    }
+
+    let total = locked.total().msats as u128;
+    let fee = locked.seeker.msats as u128 * seeker_fee_ppm as u128 / PPM;
+    let hedged = locked.seeker.msats as u128 - fee.min(locked.seeker.msats as u128);
+    let seekers = (hedged * start_price as u128 / end_price as u128).min(total);
+
+    Position {
+        seeker: Amount::from_msats(seekers as u64),
+        provider: Amount::from_msats((total - seekers) as u64),
+    }
+}
+
+/// The part of `payout` owed to an account that had `share` of the `total`
+/// funds of a side, rounded down
+pub fn pro_rata(share: Amount, total: Amount, payout: Amount) -> Amount {
+    if total == Amount::ZERO {
+        return Amount::ZERO;
+    }
+    let owed = payout.msats as u128 * share.msats as u128 / total.msats as u128;
+    Amount::from_msats(owed as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use fedimint_core::sats;
+
+    use super::*;
+
+    fn position(seeker: u64, provider: u64) -> Position {
+        Position {
+            seeker: sats(seeker),
+            provider: sats(provider),
+        }
+    }
+
+    #[test]
+    fn seekers_keep_fiat_value() {
+        // Price halves, so seekers need twice the bitcoin
+        assert_eq!(
+            settle(position(1000, 2000), 200, 100, 0),
+            position(2000, 1000)
+        );
+        // Price doubles, so seekers need half the bitcoin
+        assert_eq!(
+            settle(position(1000, 2000), 100, 200, 0),
+            position(500, 2500)
+        );
+    }
+
+    #[test]
+    fn seekers_pay_fee_to_providers() {
+        assert_eq!(
+            settle(position(1000, 2000), 100, 100, 10_000),
+            position(990, 2010)
+        );
+    }
+
+    #[test]
+    fn providers_lose_at_most_their_funds() {
+        assert_eq!(settle(position(1000, 500), 1000, 100, 0), position(1500, 0));
+    }
+
+    #[test]
+    fn no_hedge_without_providers() {
+        assert_eq!(
+            settle(position(1000, 0), 200, 100, 10_000),
+            position(1000, 0)
+        );
+    }
+
+    #[test]
+    fn payouts_are_pro_rata() {
+        assert_eq!(pro_rata(sats(1), sats(4), sats(100)), sats(25));
+        assert_eq!(pro_rata(sats(1), Amount::ZERO, sats(100)), Amount::ZERO);
+    }
+}
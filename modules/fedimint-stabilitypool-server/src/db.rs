@@ -0,0 +1,88 @@
+use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::{impl_db_lookup, impl_db_record, OutPoint, PeerId};
+use fedimint_stabilitypool_common::{Account, PoolConsensusItem, PoolEpoch, PoolOutputOutcome};
+use secp256k1::XOnlyPublicKey;
+use serde::Serialize;
+use strum_macros::EnumIter;
+
+/// Namespaces DB keys for this module
+#[repr(u8)]
+#[derive(Clone, EnumIter, Debug)]
+pub enum DbKeyPrefix {
+    Account = 0x01,
+    Outcome = 0x02,
+    Epoch = 0x03,
+    PriceVote = 0x04,
+}
+
+impl std::fmt::Display for DbKeyPrefix {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{self:?}")
+    }
+}
+
+/// Lookup accounts by key or prefix
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct AccountKey(pub XOnlyPublicKey);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct AccountPrefix;
+
+impl_db_record!(
+    key = AccountKey,
+    value = Account,
+    db_prefix = DbKeyPrefix::Account,
+);
+impl_db_lookup!(key = AccountKey, query_prefix = AccountPrefix);
+
+/// Lookup tx outputs by key or prefix
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct PoolOutcomeKey(pub OutPoint);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct PoolOutcomePrefix;
+
+impl_db_record!(
+    key = PoolOutcomeKey,
+    value = PoolOutputOutcome,
+    db_prefix = DbKeyPrefix::Outcome,
+);
+impl_db_lookup!(key = PoolOutcomeKey, query_prefix = PoolOutcomePrefix);
+
+/// The epoch that is currently running, the default first epoch if missing
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct CurrentEpochKey;
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct CurrentEpochPrefix;
+
+impl_db_record!(
+    key = CurrentEpochKey,
+    value = PoolEpoch,
+    db_prefix = DbKeyPrefix::Epoch,
+    // Allows clients to wait for settlements
+    notify_on_modify = true
+);
+impl_db_lookup!(key = CurrentEpochKey, query_prefix = CurrentEpochPrefix);
+
+/// Price observations of guardians for ending an epoch, removed once it is
+/// settled
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct PriceVoteKey(pub u64, pub PeerId);
+
+#[derive(Debug, Clone, Encodable, Decodable, Eq, PartialEq, Hash, Serialize)]
+pub struct PriceVoteEpochPrefix(pub u64);
+
+#[derive(Debug, Encodable, Decodable)]
+pub struct PriceVotePrefix;
+
+impl_db_record!(
+    key = PriceVoteKey,
+    value = PoolConsensusItem,
+    db_prefix = DbKeyPrefix::PriceVote,
+);
+impl_db_lookup!(
+    key = PriceVoteKey,
+    query_prefix = PriceVoteEpochPrefix,
+    query_prefix = PriceVotePrefix
+);
@@ -0,0 +1,626 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::string::ToString;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use anyhow::bail;
+use async_trait::async_trait;
+use fedimint_core::config::{
+    ClientModuleConfig, ConfigGenModuleParams, DkgResult, ServerModuleConfig,
+    ServerModuleConsensusConfig, TypedServerModuleConfig, TypedServerModuleConsensusConfig,
+};
+use fedimint_core::core::ModuleInstanceId;
+use fedimint_core::db::{Database, DatabaseVersion, MigrationMap, ModuleDatabaseTransaction};
+use fedimint_core::module::audit::Audit;
+use fedimint_core::module::interconnect::DynModuleInterconnect;
+use fedimint_core::module::{
+    api_endpoint, ApiEndpoint, ConsensusProposal, CoreConsensusVersion, ExtendsCommonModuleGen,
+    InputMeta, IntoCodedModuleError, ModuleConsensusVersion, ModuleError, PeerHandle,
+    ServerModuleGen, SupportedModuleApiVersions, TransactionItemAmount,
+};
+use fedimint_core::server::DynServerModule;
+use fedimint_core::task::{sleep, TaskGroup};
+use fedimint_core::{push_db_pair_items, Amount, NumPeers, OutPoint, PeerId, ServerModule};
+use fedimint_stabilitypool_common::config::{
+    PoolClientConfig, PoolConfig, PoolConfigConsensus, PoolConfigLocal, PoolConfigPrivate,
+    PoolGenParams,
+};
+use fedimint_stabilitypool_common::settlement::{pro_rata, settle};
+use fedimint_stabilitypool_common::{
+    Account, PoolCommonGen, PoolConsensusItem, PoolEpoch, PoolError, PoolInput, PoolModuleTypes,
+    PoolOutput, PoolOutputOutcome, Position, Side, CONSENSUS_VERSION,
+};
+use futures::StreamExt;
+use secp256k1::XOnlyPublicKey;
+use strum::IntoEnumIterator;
+use tracing::{info, warn};
+
+use crate::db::{
+    AccountKey, AccountPrefix, CurrentEpochKey, CurrentEpochPrefix, DbKeyPrefix, PoolOutcomeKey,
+    PoolOutcomePrefix, PriceVoteEpochPrefix, PriceVoteKey, PriceVotePrefix,
+};
+use crate::oracle::fetch_price;
+
+mod db;
+pub mod oracle;
+
+/// How long to wait before asking the price source again after it failed
+const PRICE_RETRY_INTERVAL: u64 = 30;
+
+/// Generates the module
+#[derive(Debug, Clone)]
+pub struct PoolGen;
+
+impl ExtendsCommonModuleGen for PoolGen {
+    type Common = PoolCommonGen;
+}
+
+/// Implementation of server module non-consensus functions
+#[async_trait]
+impl ServerModuleGen for PoolGen {
+    type Params = PoolGenParams;
+    const DATABASE_VERSION: DatabaseVersion = DatabaseVersion(0);
+
+    /// Returns the version of this module
+    fn versions(&self, _core: CoreConsensusVersion) -> &[ModuleConsensusVersion] {
+        &[CONSENSUS_VERSION]
+    }
+
+    fn supported_api_versions(&self) -> SupportedModuleApiVersions {
+        SupportedModuleApiVersions::from_raw(0, 0, &[(0, 0)])
+    }
+
+    /// Initialize the module
+    async fn init(
+        &self,
+        cfg: ServerModuleConfig,
+        _module_instance_id: ModuleInstanceId,
+        _our_id: PeerId,
+        _db: Database,
+        _task_group: &mut TaskGroup,
+        _interconnect: DynModuleInterconnect,
+    ) -> anyhow::Result<DynServerModule> {
+        Ok(StabilityPool::new(cfg.to_typed()?).into())
+    }
+
+    /// DB migrations to move from old to newer versions
+    fn get_database_migrations(&self) -> MigrationMap {
+        MigrationMap::new()
+    }
+
+    /// Generates configs for all peers in a trusted manner for testing
+    fn trusted_dealer_gen(
+        &self,
+        peers: &[PeerId],
+        params: &ConfigGenModuleParams,
+    ) -> BTreeMap<PeerId, ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        peers
+            .iter()
+            .map(|&peer| {
+                let config = pool_config(&params, peers.threshold());
+                (peer, config.to_erased())
+            })
+            .collect()
+    }
+
+    /// Generates configs for all peers in an untrusted manner
+    async fn distributed_gen(
+        &self,
+        peers: &PeerHandle,
+        params: &ConfigGenModuleParams,
+    ) -> DkgResult<ServerModuleConfig> {
+        let params = self.parse_params(params).unwrap();
+        // The pool has no keys, so there is nothing to generate together
+        Ok(pool_config(&params, peers.peer_ids().threshold()).to_erased())
+    }
+
+    /// Converts the consensus config into the client config
+    fn get_client_config(
+        &self,
+        config: &ServerModuleConsensusConfig,
+    ) -> anyhow::Result<ClientModuleConfig> {
+        let config = PoolConfigConsensus::from_erased(config)?;
+        Ok(ClientModuleConfig::from_typed(
+            config.kind(),
+            config.version(),
+            &(PoolClientConfig {
+                epoch_length: config.epoch_length,
+                seeker_fee_ppm: config.seeker_fee_ppm,
+                tx_fee: config.tx_fee,
+            }),
+        )
+        .expect("Serialization can't fail"))
+    }
+
+    fn validate_config(
+        &self,
+        _identity: &PeerId,
+        config: ServerModuleConfig,
+    ) -> anyhow::Result<()> {
+        let config = config.to_typed::<PoolConfig>()?;
+        if config.consensus.epoch_length == 0 {
+            bail!("Epoch length must not be zero");
+        }
+        if config.consensus.threshold == 0 {
+            bail!("Threshold must not be zero");
+        }
+        Ok(())
+    }
+
+    /// Dumps all database items for debugging
+    async fn dump_database(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        prefix_names: Vec<String>,
+    ) -> Box<dyn Iterator<Item = (String, Box<dyn erased_serde::Serialize + Send>)> + '_> {
+        let mut items: BTreeMap<String, Box<dyn erased_serde::Serialize + Send>> = BTreeMap::new();
+        let filtered_prefixes = DbKeyPrefix::iter().filter(|f| {
+            prefix_names.is_empty() || prefix_names.contains(&f.to_string().to_lowercase())
+        });
+
+        for table in filtered_prefixes {
+            match table {
+                DbKeyPrefix::Account => {
+                    push_db_pair_items!(
+                        dbtx,
+                        AccountPrefix,
+                        AccountKey,
+                        Account,
+                        items,
+                        "Pool Accounts"
+                    );
+                }
+                DbKeyPrefix::Outcome => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PoolOutcomePrefix,
+                        PoolOutcomeKey,
+                        PoolOutputOutcome,
+                        items,
+                        "Pool Outputs"
+                    );
+                }
+                DbKeyPrefix::Epoch => {
+                    push_db_pair_items!(
+                        dbtx,
+                        CurrentEpochPrefix,
+                        CurrentEpochKey,
+                        PoolEpoch,
+                        items,
+                        "Pool Epoch"
+                    );
+                }
+                DbKeyPrefix::PriceVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PriceVotePrefix,
+                        PriceVoteKey,
+                        PoolConsensusItem,
+                        items,
+                        "Pool Price Votes"
+                    );
+                }
+            }
+        }
+
+        Box::new(items.into_iter())
+    }
+}
+
+fn pool_config(params: &PoolGenParams, threshold: usize) -> PoolConfig {
+    PoolConfig {
+        local: PoolConfigLocal {
+            price_source: params.local.price_source.clone(),
+        },
+        private: PoolConfigPrivate {},
+        consensus: PoolConfigConsensus {
+            epoch_length: params.consensus.epoch_length,
+            seeker_fee_ppm: params.consensus.seeker_fee_ppm,
+            tx_fee: params.consensus.tx_fee,
+            threshold: threshold as u64,
+        },
+    }
+}
+
+/// Our contribution to ending the current epoch
+#[derive(Debug, Clone)]
+enum Observation {
+    /// The price we propose until the epoch is settled
+    Price(PoolConsensusItem),
+    /// Fetching the price failed, we try again at the given unix time
+    Retry { epoch_id: u64, at: u64 },
+}
+
+impl Observation {
+    fn epoch_id(&self) -> u64 {
+        match self {
+            Observation::Price(item) => item.epoch_id,
+            Observation::Retry { epoch_id, .. } => *epoch_id,
+        }
+    }
+}
+
+/// Stability pool module
+#[derive(Debug)]
+pub struct StabilityPool {
+    pub cfg: PoolConfig,
+    observation: Mutex<Option<Observation>>,
+}
+
+/// Implementation of consensus for the server module
+#[async_trait]
+impl ServerModule for StabilityPool {
+    /// Define the consensus types
+    type Common = PoolModuleTypes;
+    type Gen = PoolGen;
+    type VerificationCache = PoolVerificationCache;
+
+    async fn await_consensus_proposal(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) {
+        let epoch = current_epoch(dbtx).await;
+        let wake_at = match self.observation(epoch.id) {
+            // Other guardians will trigger the epochs we need to settle
+            Some(Observation::Price(_)) => std::future::pending().await,
+            Some(Observation::Retry { at, .. }) => at,
+            None => epoch.end_time(self.cfg.consensus.epoch_length),
+        };
+
+        let now = unix_time();
+        if wake_at > now {
+            sleep(Duration::from_secs(wake_at - now)).await;
+        }
+    }
+
+    async fn consensus_proposal(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> ConsensusProposal<PoolConsensusItem> {
+        let epoch = current_epoch(dbtx).await;
+        let now = unix_time();
+        if now < epoch.end_time(self.cfg.consensus.epoch_length) {
+            return ConsensusProposal::empty();
+        }
+
+        match self.observation(epoch.id) {
+            Some(Observation::Price(item)) => return ConsensusProposal::Contribute(vec![item]),
+            Some(Observation::Retry { at, .. }) if now < at => return ConsensusProposal::empty(),
+            _ => {}
+        }
+
+        match fetch_price(&self.cfg.local.price_source).await {
+            Ok(price) => {
+                let item = PoolConsensusItem {
+                    epoch_id: epoch.id,
+                    price,
+                    time: now,
+                };
+                self.set_observation(Observation::Price(item.clone()));
+                ConsensusProposal::Trigger(vec![item])
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to fetch the price to settle pool epoch {}: {e:#}",
+                    epoch.id
+                );
+                self.set_observation(Observation::Retry {
+                    epoch_id: epoch.id,
+                    at: now + PRICE_RETRY_INTERVAL,
+                });
+                ConsensusProposal::empty()
+            }
+        }
+    }
+
+    async fn begin_consensus_epoch<'a, 'b>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        consensus_items: Vec<(PeerId, PoolConsensusItem)>,
+        _consensus_peers: &BTreeSet<PeerId>,
+    ) -> Vec<PeerId> {
+        let epoch = current_epoch(dbtx).await;
+        for (peer_id, item) in consensus_items {
+            // Items for settled epochs are still in flight, ignore them
+            if item.epoch_id != epoch.id || item.price == 0 {
+                continue;
+            }
+            let key = PriceVoteKey(epoch.id, peer_id);
+            if dbtx.get_value(&key).await.is_none() {
+                dbtx.insert_new_entry(&key, &item).await;
+            }
+        }
+
+        let votes = dbtx
+            .find_by_prefix(&PriceVoteEpochPrefix(epoch.id))
+            .await
+            .map(|(_, vote)| vote)
+            .collect::<Vec<_>>()
+            .await;
+        if votes.len() as u64 >= self.cfg.consensus.threshold {
+            self.settle_epoch(dbtx, epoch, votes).await;
+        }
+
+        vec![]
+    }
+
+    fn build_verification_cache<'a>(
+        &'a self,
+        _inputs: impl Iterator<Item = &'a PoolInput> + Send,
+    ) -> Self::VerificationCache {
+        PoolVerificationCache
+    }
+
+    async fn validate_input<'a, 'b>(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        _verification_cache: &Self::VerificationCache,
+        input: &'a PoolInput,
+    ) -> Result<InputMeta, ModuleError> {
+        let account = dbtx
+            .get_value(&AccountKey(input.account()))
+            .await
+            .ok_or(PoolError::UnknownAccount)
+            .into_module_error()?;
+
+        let amount = match input {
+            PoolInput::Withdraw { amount, .. } => {
+                if *amount > account.unlocked {
+                    return Err(PoolError::NotEnoughUnlocked(account.unlocked)).into_module_error();
+                }
+                TransactionItemAmount {
+                    amount: *amount,
+                    fee: self.cfg.consensus.tx_fee,
+                }
+            }
+            PoolInput::Unlock { side, .. } => {
+                if account.staged.get(*side) == Amount::ZERO
+                    && account.locked.get(*side) == Amount::ZERO
+                {
+                    return Err(PoolError::NothingToUnlock(*side)).into_module_error();
+                }
+                TransactionItemAmount::ZERO
+            }
+        };
+
+        Ok(InputMeta {
+            amount,
+            // IMPORTANT: include the pubkey to validate the user signed this tx
+            pub_keys: vec![input.account()],
+        })
+    }
+
+    async fn apply_input<'a, 'b, 'c>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'c>,
+        input: &'b PoolInput,
+        cache: &Self::VerificationCache,
+    ) -> Result<InputMeta, ModuleError> {
+        let meta = self.validate_input(dbtx, cache, input).await?;
+
+        let key = AccountKey(input.account());
+        let mut account = dbtx.get_value(&key).await.expect("Checked by validation");
+        match input {
+            PoolInput::Withdraw { amount, .. } => {
+                account.unlocked = account.unlocked - *amount;
+            }
+            PoolInput::Unlock { side, .. } => {
+                // Staged funds aren't exposed to the price yet, so they can leave now
+                account.unlocked += account.staged.get(*side);
+                *account.staged.get_mut(*side) = Amount::ZERO;
+                if account.locked.get(*side) != Amount::ZERO {
+                    account.unlocking.insert(*side);
+                }
+            }
+        }
+        save_account(dbtx, key, account).await;
+
+        Ok(meta)
+    }
+
+    async fn validate_output(
+        &self,
+        _dbtx: &mut ModuleDatabaseTransaction<'_>,
+        output: &PoolOutput,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        if output.amount == Amount::ZERO {
+            return Err(PoolError::ZeroDeposit).into_module_error();
+        }
+        Ok(TransactionItemAmount {
+            amount: output.amount,
+            fee: self.cfg.consensus.tx_fee,
+        })
+    }
+
+    async fn apply_output<'a, 'b>(
+        &'a self,
+        dbtx: &mut ModuleDatabaseTransaction<'b>,
+        output: &'a PoolOutput,
+        out_point: OutPoint,
+    ) -> Result<TransactionItemAmount, ModuleError> {
+        let meta = self.validate_output(dbtx, output).await?;
+
+        // Deposits join the pool at the end of the epoch, so nobody can pick
+        // a side after seeing the price movement
+        let key = AccountKey(output.account);
+        let mut account = dbtx.get_value(&key).await.unwrap_or_default();
+        *account.staged.get_mut(output.side) += output.amount;
+        save_account(dbtx, key, account).await;
+
+        let outcome = PoolOutputOutcome {
+            account: output.account,
+            epoch_id: current_epoch(dbtx).await.id,
+        };
+        dbtx.insert_new_entry(&PoolOutcomeKey(out_point), &outcome)
+            .await;
+
+        Ok(meta)
+    }
+
+    async fn end_consensus_epoch<'a, 'b>(
+        &'a self,
+        _consensus_peers: &BTreeSet<PeerId>,
+        _dbtx: &mut ModuleDatabaseTransaction<'b>,
+    ) -> Vec<PeerId> {
+        vec![]
+    }
+
+    async fn output_status(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        out_point: OutPoint,
+    ) -> Option<PoolOutputOutcome> {
+        dbtx.get_value(&PoolOutcomeKey(out_point)).await
+    }
+
+    async fn audit(&self, dbtx: &mut ModuleDatabaseTransaction<'_>, audit: &mut Audit) {
+        // The funds in the pool are owed to its users
+        audit
+            .add_items(dbtx, &AccountPrefix, |_, account| {
+                -(account.total().msats as i64)
+            })
+            .await;
+    }
+
+    fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
+        vec![
+            api_endpoint! {
+                "account",
+                async |_module: &StabilityPool, context, account: XOnlyPublicKey| -> Account {
+                    Ok(context.dbtx().get_value(&AccountKey(account)).await.unwrap_or_default())
+                }
+            },
+            api_endpoint! {
+                "current_epoch",
+                async |_module: &StabilityPool, context, _params: ()| -> PoolEpoch {
+                    Ok(current_epoch(&mut context.dbtx()).await)
+                }
+            },
+            api_endpoint! {
+                // Waits until the epoch with the given id was settled
+                "wait_epoch_settled",
+                async |_module: &StabilityPool, context, epoch_id: u64| -> PoolEpoch {
+                    Ok(context.wait_value_matches(CurrentEpochKey, move |epoch| epoch.id > epoch_id).await)
+                }
+            },
+        ]
+    }
+}
+
+/// An in-memory cache we could use for faster validation
+#[derive(Debug, Clone)]
+pub struct PoolVerificationCache;
+
+impl fedimint_core::server::VerificationCache for PoolVerificationCache {}
+
+impl StabilityPool {
+    /// Create new module instance
+    pub fn new(cfg: PoolConfig) -> StabilityPool {
+        StabilityPool {
+            cfg,
+            observation: Mutex::new(None),
+        }
+    }
+
+    fn observation(&self, epoch_id: u64) -> Option<Observation> {
+        self.observation
+            .lock()
+            .expect("Not poisoned")
+            .clone()
+            .filter(|observation| observation.epoch_id() == epoch_id)
+    }
+
+    fn set_observation(&self, observation: Observation) {
+        *self.observation.lock().expect("Not poisoned") = Some(observation);
+    }
+
+    /// Pays out the locked funds according to the median of the observed
+    /// prices and starts the next epoch with the staged deposits
+    async fn settle_epoch(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        epoch: PoolEpoch,
+        votes: Vec<PoolConsensusItem>,
+    ) {
+        let end_price = median(votes.iter().map(|vote| vote.price));
+        let end_time = median(votes.iter().map(|vote| vote.time));
+
+        let accounts = dbtx
+            .find_by_prefix(&AccountPrefix)
+            .await
+            .collect::<Vec<_>>()
+            .await;
+        let locked = accounts
+            .iter()
+            .fold(Position::default(), |total, (_, account)| {
+                total + account.locked
+            });
+        let payout = match epoch.start_price {
+            Some(start_price) => settle(
+                locked,
+                start_price,
+                end_price,
+                self.cfg.consensus.seeker_fee_ppm,
+            ),
+            None => locked,
+        };
+
+        for (key, mut account) in accounts {
+            let mut paid = Position {
+                seeker: pro_rata(account.locked.seeker, locked.seeker, payout.seeker),
+                provider: pro_rata(account.locked.provider, locked.provider, payout.provider),
+            };
+            for side in std::mem::take(&mut account.unlocking) {
+                account.unlocked += paid.get(side);
+                *paid.get_mut(side) = Amount::ZERO;
+            }
+            account.locked = paid + std::mem::take(&mut account.staged);
+            save_account(dbtx, key, account).await;
+        }
+
+        dbtx.remove_by_prefix(&PriceVoteEpochPrefix(epoch.id)).await;
+        dbtx.insert_entry(
+            &CurrentEpochKey,
+            &PoolEpoch {
+                id: epoch.id + 1,
+                start_price: Some(end_price),
+                start_time: end_time,
+            },
+        )
+        .await;
+
+        info!(
+            epoch = epoch.id,
+            price = end_price,
+            seekers = %payout.seeker,
+            providers = %payout.provider,
+            "Settled stability pool epoch"
+        );
+    }
+}
+
+async fn current_epoch(dbtx: &mut ModuleDatabaseTransaction<'_>) -> PoolEpoch {
+    dbtx.get_value(&CurrentEpochKey).await.unwrap_or_default()
+}
+
+/// Stores the account, removing it once it holds no funds
+async fn save_account(dbtx: &mut ModuleDatabaseTransaction<'_>, key: AccountKey, account: Account) {
+    if account == Account::default() {
+        dbtx.remove_entry(&key).await;
+    } else {
+        dbtx.insert_entry(&key, &account).await;
+    }
+}
+
+/// The median of a non-empty list, which at least one honest guardian agrees
+/// with as long as a threshold of observations is used
+fn median(values: impl Iterator<Item = u64>) -> u64 {
+    let mut values = values.collect::<Vec<_>>();
+    values.sort_unstable();
+    values[values.len() / 2]
+}
+
+fn unix_time() -> u64 {
+    fedimint_core::time::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .expect("Time is after the unix epoch")
+        .as_secs()
+}
@@ -0,0 +1,36 @@
+use anyhow::{ensure, Context};
+use fedimint_stabilitypool_common::config::PriceSource;
+use serde_json::Value;
+
+/// Fetches the current bitcoin price in cents from `source`
+pub async fn fetch_price(source: &PriceSource) -> anyhow::Result<u64> {
+    match source {
+        PriceSource::Fixed { price } => Ok(*price),
+        PriceSource::Http { url, pointer } => {
+            let response: Value = reqwest::get(url.clone())
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            let value = response
+                .pointer(pointer)
+                .with_context(|| format!("Price source response has no {pointer}"))?;
+            parse_dollars(value)
+        }
+    }
+}
+
+/// Exchanges return prices as JSON numbers or strings in dollars
+fn parse_dollars(value: &Value) -> anyhow::Result<u64> {
+    let dollars = match value {
+        Value::Number(number) => number.as_f64(),
+        Value::String(string) => string.parse::<f64>().ok(),
+        _ => None,
+    }
+    .context("Price is not a number")?;
+    ensure!(
+        dollars.is_finite() && dollars >= 0.01,
+        "Invalid price {dollars}"
+    );
+    Ok((dollars * 100.0).round() as u64)
+}
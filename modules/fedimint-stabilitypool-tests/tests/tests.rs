@@ -0,0 +1,79 @@
+use fedimint_core::sats;
+use fedimint_core::util::NextOrPending;
+use fedimint_dummy_client::{DummyClientExt, DummyClientGen};
+use fedimint_dummy_common::config::DummyGenParams;
+use fedimint_dummy_server::DummyGen;
+use fedimint_stabilitypool_client::{PoolClientExt, PoolClientGen, PoolOperationState};
+use fedimint_stabilitypool_common::config::PoolGenParams;
+use fedimint_stabilitypool_common::{Position, Side};
+use fedimint_stabilitypool_server::PoolGen;
+use fedimint_testing::fixtures::Fixtures;
+
+fn fixtures() -> Fixtures {
+    let fixtures = Fixtures::new_primary(DummyClientGen, DummyGen, DummyGenParams::default());
+    let mut params = PoolGenParams::default();
+    params.consensus.epoch_length = 1;
+    fixtures.with_module(PoolClientGen, PoolGen, params)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn deposits_join_the_pool_and_can_be_withdrawn() -> anyhow::Result<()> {
+    let fed = fixtures().new_fed().await;
+    let client = fed.new_client().await;
+    let (_, outpoint) = client.print_money(sats(1000)).await?;
+    client.receive_money(outpoint).await?;
+
+    let op = client.deposit_to_pool(Side::Seeker, sats(400)).await?;
+    let mut sub = client.subscribe_pool_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, PoolOperationState::Created);
+    assert_eq!(sub.ok().await?, PoolOperationState::Done);
+    assert_eq!(client.get_balance().await, sats(600));
+
+    // Deposits are staged until the current epoch is settled
+    let epoch = client.pool_epoch().await?;
+    let account = client.pool_account().await?;
+    assert_eq!(account.staged.seeker, sats(400));
+    client.await_pool_epoch_settled(epoch.id).await?;
+    let account = client.pool_account().await?;
+    assert_eq!(account.staged, Position::default());
+    // Without providers seekers keep their funds
+    assert_eq!(account.locked.seeker, sats(400));
+
+    // Locked funds become withdrawable once the epoch is settled
+    let op = client.unlock_pool_side(Side::Seeker).await?;
+    let mut sub = client.subscribe_pool_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, PoolOperationState::Created);
+    assert_eq!(sub.ok().await?, PoolOperationState::Done);
+    let epoch = client.pool_epoch().await?;
+    client.await_pool_epoch_settled(epoch.id).await?;
+    let account = client.pool_account().await?;
+    assert_eq!(account.locked, Position::default());
+    assert_eq!(account.unlocked, sats(400));
+
+    let op = client.withdraw_from_pool(sats(400)).await?;
+    let mut sub = client.subscribe_pool_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, PoolOperationState::Created);
+    assert_eq!(sub.ok().await?, PoolOperationState::Done);
+    assert_eq!(client.get_balance().await, sats(1000));
+    assert_eq!(client.pool_account().await?, Default::default());
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn cannot_withdraw_locked_funds() -> anyhow::Result<()> {
+    let fed = fixtures().new_fed().await;
+    let client = fed.new_client().await;
+    let (_, outpoint) = client.print_money(sats(1000)).await?;
+    client.receive_money(outpoint).await?;
+
+    let op = client.deposit_to_pool(Side::Provider, sats(400)).await?;
+    let mut sub = client.subscribe_pool_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, PoolOperationState::Created);
+    assert_eq!(sub.ok().await?, PoolOperationState::Done);
+
+    let op = client.withdraw_from_pool(sats(400)).await?;
+    let mut sub = client.subscribe_pool_operation(op).await?.into_stream();
+    assert_eq!(sub.ok().await?, PoolOperationState::Created);
+    assert!(matches!(sub.ok().await?, PoolOperationState::Failed(_)));
+    Ok(())
+}
@@ -4,6 +4,7 @@ use fedimint_core::module::ApiRequestErased;
 use fedimint_core::query::EventuallyConsistent;
 use fedimint_core::task::{MaybeSend, MaybeSync};
 use fedimint_core::{apply, async_trait_maybe_send, NumPeers};
+use fedimint_wallet_common::config::PegInPolicy;
 use fedimint_wallet_common::PegOutFees;
 
 #[apply(async_trait_maybe_send!)]
@@ -14,6 +15,7 @@ pub trait WalletFederationApi {
         address: &Address,
         amount: bitcoin::Amount,
     ) -> FederationResult<Option<PegOutFees>>;
+    async fn fetch_deposit_policy(&self) -> FederationResult<PegInPolicy>;
 }
 
 #[apply(async_trait_maybe_send!)]
@@ -42,4 +44,13 @@ where
         )
         .await
     }
+
+    async fn fetch_deposit_policy(&self) -> FederationResult<PegInPolicy> {
+        self.request_with_strategy(
+            EventuallyConsistent::new(self.all_members().threshold()),
+            "deposit_policy".to_string(),
+            ApiRequestErased::default(),
+        )
+        .await
+    }
 }
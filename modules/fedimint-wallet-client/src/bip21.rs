@@ -0,0 +1,87 @@
+use std::fmt::{self, Display, Formatter, Write};
+
+use bitcoin::Address;
+use serde::{Deserialize, Serialize};
+
+/// A deposit address along with the amount the depositor is asked to send,
+/// displayed as a BIP21 URI so wallets can scan it as a QR code
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DepositUri {
+    pub address: Address,
+    #[serde(default, with = "bitcoin::util::amount::serde::as_sat::opt")]
+    pub amount: Option<bitcoin::Amount>,
+    pub label: Option<String>,
+}
+
+impl Display for DepositUri {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "bitcoin:{}", self.address)?;
+        let mut separator = '?';
+        if let Some(amount) = self.amount {
+            write!(f, "{separator}amount={}", format_btc(amount))?;
+            separator = '&';
+        }
+        if let Some(label) = &self.label {
+            write!(f, "{separator}label=")?;
+            percent_encode(f, label)?;
+        }
+        Ok(())
+    }
+}
+
+/// BIP21 amounts are denominated in BTC, trailing zeros are left out
+fn format_btc(amount: bitcoin::Amount) -> String {
+    let sats = amount.to_sat();
+    let btc = format!("{}.{:08}", sats / 100_000_000, sats % 100_000_000);
+    btc.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// Encodes all characters that aren't unreserved according to RFC 3986
+fn percent_encode(f: &mut Formatter<'_>, value: &str) -> fmt::Result {
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            f.write_char(byte as char)?;
+        } else {
+            write!(f, "%{byte:02X}")?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::{Network, Script};
+
+    use super::*;
+
+    #[test]
+    fn formats_bip21_uri() {
+        let address = Address::p2wsh(&Script::new_op_return(b"deposit"), Network::Regtest);
+        let uri = |amount: Option<u64>, label: Option<&str>| {
+            DepositUri {
+                address: address.clone(),
+                amount: amount.map(bitcoin::Amount::from_sat),
+                label: label.map(str::to_string),
+            }
+            .to_string()
+        };
+
+        assert_eq!(uri(None, None), format!("bitcoin:{address}"));
+        assert_eq!(
+            uri(Some(150_000_000), None),
+            format!("bitcoin:{address}?amount=1.5")
+        );
+        assert_eq!(
+            uri(Some(100_000_000), Some("a")),
+            format!("bitcoin:{address}?amount=1&label=a")
+        );
+        assert_eq!(
+            uri(Some(5_000), Some("Alice & Bob")),
+            format!("bitcoin:{address}?amount=0.00005&label=Alice%20%26%20Bob")
+        );
+        assert_eq!(
+            uri(None, Some("ä")),
+            format!("bitcoin:{address}?label=%C3%A4")
+        );
+    }
+}
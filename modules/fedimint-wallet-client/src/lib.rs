@@ -1,8 +1,10 @@
 pub mod api;
 
+mod bip21;
 mod deposit;
 mod withdraw;
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
 use std::time::SystemTime;
 
@@ -10,14 +12,19 @@ use anyhow::{anyhow, bail, ensure};
 use async_stream::stream;
 use bitcoin::{Address, Network};
 use fedimint_bitcoind::{create_bitcoind, DynBitcoindRpc};
+use fedimint_client::balance::BalanceEventKind;
 use fedimint_client::derivable_secret::DerivableSecret;
+use fedimint_client::fees::{FeeBreakdown, FeeEstimate};
 use fedimint_client::module::gen::ClientModuleGen;
 use fedimint_client::module::{ClientModule, IClientModule};
-use fedimint_client::oplog::UpdateStreamOrOutcome;
+use fedimint_client::oplog::{OperationLogEntry, UpdateStreamOrOutcome};
+use fedimint_client::scheduler::ScheduledPaymentHandler;
 use fedimint_client::sm::util::MapStateTransitions;
 use fedimint_client::sm::{Context, DynState, ModuleNotifier, OperationId, State, StateTransition};
 use fedimint_client::transaction::{ClientOutput, TransactionBuilder};
-use fedimint_client::{sm_enum_variant_translation, Client, DynGlobalClientContext};
+use fedimint_client::{
+    sm_enum_variant_translation, Client, ClientModuleInstance, DynGlobalClientContext,
+};
 use fedimint_core::api::{DynGlobalApi, DynModuleApi};
 use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
 use fedimint_core::core::{Decoder, IntoDynInstance, ModuleInstanceId};
@@ -29,7 +36,7 @@ use fedimint_core::module::{
 };
 use fedimint_core::task::TaskGroup;
 use fedimint_core::{apply, async_trait_maybe_send, Amount, OutPoint};
-use fedimint_wallet_common::config::WalletClientConfig;
+use fedimint_wallet_common::config::{PegInPolicy, WalletClientConfig};
 use fedimint_wallet_common::tweakable::Tweakable;
 pub use fedimint_wallet_common::*;
 use futures::{Stream, StreamExt};
@@ -37,9 +44,11 @@ use miniscript::ToPublicKey;
 use rand::{thread_rng, Rng};
 use secp256k1::{All, KeyPair, Secp256k1};
 use serde::{Deserialize, Serialize};
+use tracing::warn;
 use url::Url;
 
 use crate::api::WalletFederationApi;
+pub use crate::bip21::DepositUri;
 use crate::deposit::{CreatedDepositState, DepositStateMachine, DepositStates};
 use crate::withdraw::{CreatedWithdrawState, WithdrawStateMachine, WithdrawStates};
 
@@ -50,6 +59,35 @@ pub trait WalletClientExt {
         valid_until: SystemTime,
     ) -> anyhow::Result<(OperationId, Address)>;
 
+    /// Like [`WalletClientExt::get_deposit_address`], but attaches `metadata`
+    /// to the deposit operation. The metadata is stored with the operation,
+    /// whose id is derived from the tweak of the address, and included in the
+    /// [`DepositState::Claimed`] outcome so deposits can be attributed to
+    /// whoever the address was handed out to.
+    async fn get_deposit_address_with_metadata(
+        &self,
+        valid_until: SystemTime,
+        metadata: DepositMetadata,
+    ) -> anyhow::Result<(OperationId, Address)>;
+
+    /// Like [`WalletClientExt::get_deposit_address_with_metadata`], but
+    /// returns a BIP21 URI asking for `expected_amount` and carrying the label
+    /// of `metadata`, e.g. to be shown as a QR code.
+    ///
+    /// Deposits of a different amount are still claimed, but the discrepancy
+    /// is logged and recorded in the [`DepositState::Claimed`] outcome. Fails
+    /// if the federation wouldn't accept a deposit of `expected_amount`.
+    async fn get_deposit_uri(
+        &self,
+        valid_until: SystemTime,
+        expected_amount: Option<bitcoin::Amount>,
+        metadata: DepositMetadata,
+    ) -> anyhow::Result<(OperationId, DepositUri)>;
+
+    /// Fetches the peg-in amounts the federation accepts, deposits outside of
+    /// them can't be claimed and should not be sent
+    async fn get_deposit_policy(&self) -> anyhow::Result<PegInPolicy>;
+
     async fn subscribe_deposit_updates(
         &self,
         operation_id: OperationId,
@@ -89,11 +127,41 @@ pub enum DepositState {
     WaitingForTransaction,
     WaitingForConfirmation,
     Confirmed,
-    // TODO: add amount
-    Claimed,
+    Claimed(ClaimedDeposit),
     Failed(String),
 }
 
+/// Label and arbitrary key-value pairs identifying who a deposit address was
+/// generated for
+#[derive(Debug, Clone, Default, Serialize, Deserialize, Eq, PartialEq)]
+pub struct DepositMetadata {
+    pub label: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// A peg-in that was claimed and added to the balance
+#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+pub struct ClaimedDeposit {
+    /// Bitcoin transaction paying to the deposit address
+    pub btc_txid: bitcoin::Txid,
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub amount: bitcoin::Amount,
+    /// Metadata the address was generated with
+    pub metadata: DepositMetadata,
+    /// Amount requested in the deposit URI, if any
+    #[serde(default, with = "bitcoin::util::amount::serde::as_sat::opt")]
+    pub expected_amount: Option<bitcoin::Amount>,
+}
+
+impl ClaimedDeposit {
+    /// Whether a different amount than requested in the deposit URI was
+    /// deposited
+    pub fn is_amount_mismatch(&self) -> bool {
+        self.expected_amount
+            .map_or(false, |expected_amount| expected_amount != self.amount)
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
 pub enum WithdrawState {
     Created,
@@ -110,13 +178,187 @@ impl WalletClientExt for Client {
         &self,
         valid_until: SystemTime,
     ) -> anyhow::Result<(OperationId, Address)> {
-        let (wallet_client, instance) =
-            self.get_first_module::<WalletClientModule>(&WalletCommonGen::KIND);
+        WalletInstance::first(self)?
+            .get_deposit_address(valid_until)
+            .await
+    }
+
+    async fn get_deposit_address_with_metadata(
+        &self,
+        valid_until: SystemTime,
+        metadata: DepositMetadata,
+    ) -> anyhow::Result<(OperationId, Address)> {
+        WalletInstance::first(self)?
+            .get_deposit_address_with_metadata(valid_until, metadata)
+            .await
+    }
+
+    async fn get_deposit_uri(
+        &self,
+        valid_until: SystemTime,
+        expected_amount: Option<bitcoin::Amount>,
+        metadata: DepositMetadata,
+    ) -> anyhow::Result<(OperationId, DepositUri)> {
+        WalletInstance::first(self)?
+            .get_deposit_uri(valid_until, expected_amount, metadata)
+            .await
+    }
+
+    async fn get_deposit_policy(&self) -> anyhow::Result<PegInPolicy> {
+        WalletInstance::first(self)?.get_deposit_policy().await
+    }
+
+    async fn subscribe_deposit_updates(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<UpdateStreamOrOutcome<DepositState>> {
+        subscribe_deposit_updates(self, operation_id).await
+    }
+
+    async fn get_withdraw_fee(
+        &self,
+        address: Address,
+        amount: bitcoin::Amount,
+    ) -> anyhow::Result<PegOutFees> {
+        WalletInstance::first(self)?
+            .get_withdraw_fee(address, amount)
+            .await
+    }
+
+    async fn withdraw(
+        &self,
+        address: Address,
+        amount: bitcoin::Amount,
+        fee: PegOutFees,
+    ) -> anyhow::Result<OperationId> {
+        WalletInstance::first(self)?
+            .withdraw(address, amount, fee)
+            .await
+    }
+
+    async fn subscribe_withdraw_updates(
+        &self,
+        operation_id: OperationId,
+    ) -> anyhow::Result<UpdateStreamOrOutcome<WithdrawState>> {
+        subscribe_withdraw_updates(self, operation_id).await
+    }
+}
+
+/// One of the wallet modules of a federation, which can run several, e.g. one
+/// per bitcoin network
+///
+/// [`WalletClientExt`] on the [`Client`] itself uses the wallet module with
+/// the lowest instance id. Updates of operations can be subscribed to on any
+/// of them, they come from the module the operation was started on.
+#[derive(Clone, Copy)]
+pub struct WalletInstance<'c> {
+    client: &'c Client,
+    id: ModuleInstanceId,
+}
+
+impl<'c> WalletInstance<'c> {
+    /// The wallet module with the lowest instance id
+    pub fn first(client: &'c Client) -> anyhow::Result<Self> {
+        Self::all(client)
+            .into_iter()
+            .next()
+            .ok_or(anyhow!("Federation has no wallet module"))
+    }
+
+    pub fn by_id(client: &'c Client, id: ModuleInstanceId) -> anyhow::Result<Self> {
+        client.get_module::<WalletClientModule>(id)?;
+        Ok(WalletInstance { client, id })
+    }
+
+    /// The wallet module on `network`, fails if the federation runs none or
+    /// several on it
+    pub fn by_network(client: &'c Client, network: Network) -> anyhow::Result<Self> {
+        let mut instances = Self::all(client)
+            .into_iter()
+            .filter(|instance| instance.network() == network);
+        let instance = instances
+            .next()
+            .ok_or(anyhow!("Federation has no wallet module on {network}"))?;
+        ensure!(
+            instances.next().is_none(),
+            "Federation has several wallet modules on {network}, select one by id"
+        );
+        Ok(instance)
+    }
+
+    /// All wallet modules of the federation, ordered by instance id
+    pub fn all(client: &'c Client) -> Vec<Self> {
+        client
+            .get_instances(&WalletCommonGen::KIND)
+            .into_iter()
+            .map(|id| WalletInstance { client, id })
+            .collect()
+    }
+
+    /// Operations from before clients supported several wallet modules don't
+    /// record theirs, they were started on the first one
+    fn of_operation(client: &'c Client, id: Option<ModuleInstanceId>) -> anyhow::Result<Self> {
+        match id {
+            Some(id) => Self::by_id(client, id),
+            None => Self::first(client),
+        }
+    }
+
+    pub fn id(&self) -> ModuleInstanceId {
+        self.id
+    }
+
+    pub fn network(&self) -> Network {
+        self.module().0.cfg.network
+    }
+
+    fn module(&self) -> (&'c WalletClientModule, ClientModuleInstance) {
+        self.client
+            .get_module(self.id)
+            .expect("Instance was checked to be a wallet module")
+    }
+}
+
+#[apply(async_trait_maybe_send!)]
+impl<'c> WalletClientExt for WalletInstance<'c> {
+    async fn get_deposit_address(
+        &self,
+        valid_until: SystemTime,
+    ) -> anyhow::Result<(OperationId, Address)> {
+        self.get_deposit_address_with_metadata(valid_until, DepositMetadata::default())
+            .await
+    }
+
+    async fn get_deposit_address_with_metadata(
+        &self,
+        valid_until: SystemTime,
+        metadata: DepositMetadata,
+    ) -> anyhow::Result<(OperationId, Address)> {
+        let (operation_id, uri) = self.get_deposit_uri(valid_until, None, metadata).await?;
+        Ok((operation_id, uri.address))
+    }
+
+    async fn get_deposit_uri(
+        &self,
+        valid_until: SystemTime,
+        expected_amount: Option<bitcoin::Amount>,
+        metadata: DepositMetadata,
+    ) -> anyhow::Result<(OperationId, DepositUri)> {
+        let (wallet_client, instance) = self.module();
+
+        if let Some(expected_amount) = expected_amount {
+            wallet_client
+                .get_deposit_policy()
+                .await?
+                .check(expected_amount)?;
+        }
 
         let (operation_id, address) = self
+            .client
             .db()
             .autocommit(
                 |dbtx| {
+                    let metadata = metadata.clone();
                     Box::pin(async move {
                         let (operation_id, sm, address) =
                             wallet_client.get_deposit_address(valid_until);
@@ -126,9 +368,11 @@ impl WalletClientExt for Client {
                             .watch_script_history(&address.script_pubkey())
                             .await?;
 
-                        self.add_state_machines(dbtx, vec![DynState::from_typed(instance.id, sm)])
+                        self.client
+                            .add_state_machines(dbtx, vec![DynState::from_typed(instance.id, sm)])
                             .await?;
-                        self.operation_log()
+                        self.client
+                            .operation_log()
                             .add_operation_log_entry(
                                 dbtx,
                                 operation_id,
@@ -136,6 +380,9 @@ impl WalletClientExt for Client {
                                 WalletOperationMeta::Deposit {
                                     address: address.clone(),
                                     expires_at: valid_until,
+                                    metadata,
+                                    expected_amount,
+                                    instance_id: Some(instance.id),
                                 },
                             )
                             .await;
@@ -154,85 +401,27 @@ impl WalletClientExt for Client {
                 AutocommitError::ClosureError { error, .. } => error,
             })?;
 
-        Ok((operation_id, address))
+        Ok((
+            operation_id,
+            DepositUri {
+                address,
+                amount: expected_amount,
+                label: metadata.label,
+            },
+        ))
+    }
+
+    async fn get_deposit_policy(&self) -> anyhow::Result<PegInPolicy> {
+        let (wallet_client, _) = self.module();
+
+        wallet_client.get_deposit_policy().await
     }
 
     async fn subscribe_deposit_updates(
         &self,
         operation_id: OperationId,
     ) -> anyhow::Result<UpdateStreamOrOutcome<DepositState>> {
-        let (wallet_client, _) =
-            self.get_first_module::<WalletClientModule>(&WalletCommonGen::KIND);
-
-        let operation_log_entry = self
-            .operation_log()
-            .get_operation(operation_id)
-            .await
-            .ok_or(anyhow!("Operation not found"))?;
-
-        if operation_log_entry.operation_type() != WalletCommonGen::KIND.as_str() {
-            bail!("Operation is not a wallet operation");
-        }
-
-        let operation_meta = operation_log_entry.meta::<WalletOperationMeta>();
-
-        if !matches!(operation_meta, WalletOperationMeta::Deposit { .. }) {
-            bail!("Operation is not a deposit operation");
-        }
-
-        let mut operation_stream = wallet_client.notifier.subscribe(operation_id).await;
-        let tx_subscriber = self.transaction_updates(operation_id).await;
-
-        Ok(
-            operation_log_entry.outcome_or_updates(self.db(), operation_id, || {
-                stream! {
-                    match next_deposit_state(&mut operation_stream).await {
-                        Some(DepositStates::Created(_)) => {
-                            yield DepositState::WaitingForTransaction;
-                        },
-                        Some(DepositStates::TimedOut(_)) => {
-                            yield DepositState::Failed("Deposit timed out".to_string());
-                            return;
-                        }
-                        Some(s) => {
-                            panic!("Unexpected state {s:?}")
-                        },
-                        None => return,
-                    }
-
-                    match next_deposit_state(&mut operation_stream).await {
-                        Some(DepositStates::WaitingForConfirmations(_)) => {
-                            yield DepositState::WaitingForConfirmation;
-                        },
-                        Some(s) => {
-                            panic!("Unexpected state {s:?}")
-                        },
-                        None => return,
-                    }
-
-                    let claiming = match next_deposit_state(&mut operation_stream).await {
-                        Some(DepositStates::Claiming(claiming)) => claiming,
-                        Some(s) => {
-                            panic!("Unexpected state {s:?}")
-                        },
-                        None => return,
-                    };
-                    yield DepositState::Confirmed;
-
-                    if let Err(e) = tx_subscriber.await_tx_accepted(claiming.transaction_id).await {
-                        yield DepositState::Failed(format!("Failed to claim: {e:?}"));
-                        return;
-                    }
-
-                    if let Some(out_point) = claiming.change.as_ref() {
-                        self.await_primary_module_output(operation_id, *out_point)
-                            .await
-                            .expect("Cannot fail if tx was accepted and federation is honest");
-                    }
-                    yield DepositState::Claimed;
-                }
-            }),
-        )
+        subscribe_deposit_updates(self.client, operation_id).await
     }
 
     async fn get_withdraw_fee(
@@ -240,8 +429,7 @@ impl WalletClientExt for Client {
         address: Address,
         amount: bitcoin::Amount,
     ) -> anyhow::Result<PegOutFees> {
-        let (wallet_client, _) =
-            self.get_first_module::<WalletClientModule>(&WalletCommonGen::KIND);
+        let (wallet_client, _) = self.module();
 
         wallet_client.get_withdraw_fees(address, amount).await
     }
@@ -252,8 +440,7 @@ impl WalletClientExt for Client {
         amount: bitcoin::Amount,
         fee: PegOutFees,
     ) -> anyhow::Result<OperationId> {
-        let (wallet_client, instance) =
-            self.get_first_module::<WalletClientModule>(&WalletCommonGen::KIND);
+        let (wallet_client, instance) = self.module();
 
         let operation_id = OperationId(thread_rng().gen());
 
@@ -263,18 +450,21 @@ impl WalletClientExt for Client {
         let tx_builder =
             TransactionBuilder::new().with_output(withdraw_output.into_dyn(instance.id));
 
-        self.finalize_and_submit_transaction(
-            operation_id,
-            WalletCommonGen::KIND.as_str(),
-            move |_, change| WalletOperationMeta::Withdraw {
-                address: address.clone(),
-                amount,
-                fee: fee.clone(),
-                change,
-            },
-            tx_builder,
-        )
-        .await?;
+        let instance_id = self.id;
+        self.client
+            .finalize_and_submit_transaction(
+                operation_id,
+                WalletCommonGen::KIND.as_str(),
+                move |_, change| WalletOperationMeta::Withdraw {
+                    address: address.clone(),
+                    amount,
+                    fee: fee.clone(),
+                    change,
+                    instance_id: Some(instance_id),
+                },
+                tx_builder,
+            )
+            .await?;
 
         Ok(operation_id)
     }
@@ -283,64 +473,275 @@ impl WalletClientExt for Client {
         &self,
         operation_id: OperationId,
     ) -> anyhow::Result<UpdateStreamOrOutcome<WithdrawState>> {
-        let (wallet_client, _) =
-            self.get_first_module::<WalletClientModule>(&WalletCommonGen::KIND);
+        subscribe_withdraw_updates(self.client, operation_id).await
+    }
+}
 
-        let operation = self
-            .operation_log()
-            .get_operation(operation_id)
-            .await
-            .ok_or(anyhow!("Operation not found"))?;
+/// Updates of a deposit from the wallet module it was started on
+async fn subscribe_deposit_updates(
+    client: &Client,
+    operation_id: OperationId,
+) -> anyhow::Result<UpdateStreamOrOutcome<'_, DepositState>> {
+    let operation_log_entry = client
+        .operation_log()
+        .get_operation(operation_id)
+        .await
+        .ok_or(anyhow!("Operation not found"))?;
+
+    if operation_log_entry.operation_type() != WalletCommonGen::KIND.as_str() {
+        bail!("Operation is not a wallet operation");
+    }
 
-        if operation.operation_type() != WalletCommonGen::KIND.as_str() {
-            bail!("Operation is not a wallet operation");
-        }
+    let operation_meta = operation_log_entry.meta::<WalletOperationMeta>();
 
-        let operation_meta = operation.meta::<WalletOperationMeta>();
+    let WalletOperationMeta::Deposit {
+        metadata,
+        expected_amount,
+        instance_id,
+        ..
+    } = operation_meta
+    else {
+        bail!("Operation is not a deposit operation");
+    };
+    let (wallet_client, _) = WalletInstance::of_operation(client, instance_id)?.module();
+
+    let mut operation_stream = wallet_client.notifier.subscribe(operation_id).await;
+    let tx_subscriber = client.transaction_updates(operation_id).await;
+
+    Ok(
+        operation_log_entry.outcome_or_updates(client.db(), operation_id, || {
+            stream! {
+                match next_deposit_state(&mut operation_stream).await {
+                    Some(DepositStates::Created(_)) => {
+                        yield DepositState::WaitingForTransaction;
+                    },
+                    Some(DepositStates::TimedOut(_)) => {
+                        yield DepositState::Failed("Deposit timed out".to_string());
+                        return;
+                    }
+                    Some(s) => {
+                        panic!("Unexpected state {s:?}")
+                    },
+                    None => return,
+                }
 
-        let WalletOperationMeta::Withdraw { change, .. } = operation_meta else {
-            bail!("Operation is not a withdraw operation");
-        };
+                let waiting = match next_deposit_state(&mut operation_stream).await {
+                    Some(DepositStates::WaitingForConfirmations(waiting)) => waiting,
+                    Some(s) => {
+                        panic!("Unexpected state {s:?}")
+                    },
+                    None => return,
+                };
+                let amount = bitcoin::Amount::from_sat(
+                    waiting.btc_transaction.output[waiting.out_idx as usize].value,
+                );
+                if expected_amount.map_or(false, |expected_amount| expected_amount != amount) {
+                    warn!(
+                        %operation_id,
+                        %amount,
+                        ?expected_amount,
+                        "Deposit doesn't match the requested amount"
+                    );
+                }
+                yield DepositState::WaitingForConfirmation;
+
+                let claiming = match next_deposit_state(&mut operation_stream).await {
+                    Some(DepositStates::Claiming(claiming)) => claiming,
+                    Some(s) => {
+                        panic!("Unexpected state {s:?}")
+                    },
+                    None => return,
+                };
+                yield DepositState::Confirmed;
+
+                if let Err(e) = tx_subscriber.await_tx_accepted(claiming.transaction_id).await {
+                    yield DepositState::Failed(format!("Failed to claim: {e:?}"));
+                    return;
+                }
 
-        let mut operation_stream = wallet_client.notifier.subscribe(operation_id).await;
-
-        Ok(
-            operation.outcome_or_updates(self.db(), operation_id, move || {
-                stream! {
-                    match next_withdraw_state(&mut operation_stream).await {
-                        Some(WithdrawStates::Created(_)) => {
-                            yield WithdrawState::Created;
-                        },
-                        Some(s) => {
-                            panic!("Unexpected state {s:?}")
-                        },
-                        None => return,
-                    }
+                if let Some(out_point) = claiming.change.as_ref() {
+                    client.await_primary_module_output(operation_id, *out_point)
+                        .await
+                        .expect("Cannot fail if tx was accepted and federation is honest");
+                }
+                yield DepositState::Claimed(ClaimedDeposit {
+                    btc_txid: waiting.btc_transaction.txid(),
+                    amount,
+                    metadata,
+                    expected_amount,
+                });
+            }
+        }),
+    )
+}
 
-                    // TODO: get rid of awaiting change here, there has to be a better way to make tests deterministic
-                    if let Some(change_out_point) = change {
-                        // Swallowing potential errors since the transaction failing  is handled by
-                        // output outcome fetching already
-                        let _ = self
-                            .await_primary_module_output(operation_id, change_out_point)
-                            .await;
-                    }
+/// Updates of a withdrawal from the wallet module it was started on
+async fn subscribe_withdraw_updates(
+    client: &Client,
+    operation_id: OperationId,
+) -> anyhow::Result<UpdateStreamOrOutcome<'_, WithdrawState>> {
+    let operation = client
+        .operation_log()
+        .get_operation(operation_id)
+        .await
+        .ok_or(anyhow!("Operation not found"))?;
+
+    if operation.operation_type() != WalletCommonGen::KIND.as_str() {
+        bail!("Operation is not a wallet operation");
+    }
 
-                    match next_withdraw_state(&mut operation_stream).await {
-                        Some(WithdrawStates::Aborted(inner)) => {
-                            yield WithdrawState::Failed(inner.error);
-                        },
-                        Some(WithdrawStates::Success(inner)) => {
-                            yield WithdrawState::Succeeded(inner.txid);
-                        },
-                        Some(s) => {
-                            panic!("Unexpected state {s:?}")
-                        },
-                        None => {},
-                    }
+    let operation_meta = operation.meta::<WalletOperationMeta>();
+
+    let WalletOperationMeta::Withdraw {
+        change,
+        instance_id,
+        ..
+    } = operation_meta
+    else {
+        bail!("Operation is not a withdraw operation");
+    };
+    let (wallet_client, _) = WalletInstance::of_operation(client, instance_id)?.module();
+
+    let mut operation_stream = wallet_client.notifier.subscribe(operation_id).await;
+
+    Ok(
+        operation.outcome_or_updates(client.db(), operation_id, move || {
+            stream! {
+                match next_withdraw_state(&mut operation_stream).await {
+                    Some(WithdrawStates::Created(_)) => {
+                        yield WithdrawState::Created;
+                    },
+                    Some(s) => {
+                        panic!("Unexpected state {s:?}")
+                    },
+                    None => return,
                 }
-            }),
-        )
+
+                // TODO: get rid of awaiting change here, there has to be a better way to make tests deterministic
+                if let Some(change_out_point) = change {
+                    // Swallowing potential errors since the transaction failing  is handled by
+                    // output outcome fetching already
+                    let _ = client
+                        .await_primary_module_output(operation_id, change_out_point)
+                        .await;
+                }
+
+                match next_withdraw_state(&mut operation_stream).await {
+                    Some(WithdrawStates::Aborted(inner)) => {
+                        yield WithdrawState::Failed(inner.error);
+                    },
+                    Some(WithdrawStates::Success(inner)) => {
+                        yield WithdrawState::Succeeded(inner.txid);
+                    },
+                    Some(s) => {
+                        panic!("Unexpected state {s:?}")
+                    },
+                    None => {},
+                }
+            }
+        }),
+    )
+}
+
+/// Fee preview of [`WalletClientExt::withdraw`]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WithdrawFeeEstimate {
+    pub address: Address,
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub amount: bitcoin::Amount,
+}
+
+#[apply(async_trait_maybe_send!)]
+impl FeeEstimate for WithdrawFeeEstimate {
+    async fn estimate_fees(&self, client: &Client) -> anyhow::Result<FeeBreakdown> {
+        let (wallet_client, instance) =
+            client.get_first_module::<WalletClientModule>(&WalletCommonGen::KIND);
+
+        let peg_out_fees = wallet_client
+            .get_withdraw_fees(self.address.clone(), self.amount)
+            .await?;
+        let withdraw_output = wallet_client
+            .create_withdraw_output(
+                OperationId::new_random(),
+                self.address.clone(),
+                self.amount,
+                peg_out_fees.clone(),
+            )
+            .await?;
+        let federation_fee = client
+            .estimate_transaction_fees(
+                TransactionBuilder::new().with_output(withdraw_output.into_dyn(instance.id)),
+            )
+            .await?;
+
+        Ok(FeeBreakdown {
+            federation_fee,
+            on_chain_fee: peg_out_fees.amount().into(),
+            gateway_fee: Amount::ZERO,
+        })
+    }
+}
+
+/// Fee preview of claiming a deposit of `amount` made to an address from
+/// [`WalletClientExt::get_deposit_address`]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct DepositFeeEstimate {
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub amount: bitcoin::Amount,
+}
+
+#[apply(async_trait_maybe_send!)]
+impl FeeEstimate for DepositFeeEstimate {
+    async fn estimate_fees(&self, client: &Client) -> anyhow::Result<FeeBreakdown> {
+        let (wallet_client, _) =
+            client.get_first_module::<WalletClientModule>(&WalletCommonGen::KIND);
+
+        let peg_in_fee = wallet_client.cfg.fee_consensus.peg_in_abs;
+        let deposit = Amount::from(self.amount);
+        ensure!(
+            deposit > peg_in_fee,
+            "Deposit doesn't cover the peg-in fee {peg_in_fee}"
+        );
+
+        Ok(FeeBreakdown {
+            federation_fee: peg_in_fee + client.estimate_receive_fees(deposit - peg_in_fee).await,
+            ..FeeBreakdown::default()
+        })
+    }
+}
+
+/// Intent of a recurring peg-out made by [`PegOutPaymentHandler`]
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PegOutPaymentIntent {
+    pub address: Address,
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub amount: bitcoin::Amount,
+    /// Payments are skipped while the on-chain fees are higher
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub max_fee: bitcoin::Amount,
+}
+
+/// Makes scheduled peg-outs, see [`fedimint_client::scheduler`]
+#[derive(Debug, Clone, Default)]
+pub struct PegOutPaymentHandler;
+
+#[apply(async_trait_maybe_send!)]
+impl ScheduledPaymentHandler for PegOutPaymentHandler {
+    const KIND: &'static str = "peg_out";
+
+    type Intent = PegOutPaymentIntent;
+
+    async fn pay(&self, client: &Client, intent: Self::Intent) -> anyhow::Result<OperationId> {
+        let fees = client
+            .get_withdraw_fee(intent.address.clone(), intent.amount)
+            .await?;
+        ensure!(
+            fees.amount() <= intent.max_fee,
+            "Peg-out fee {} exceeds maximum {}",
+            fees.amount(),
+            intent.max_fee
+        );
+        client.withdraw(intent.address, intent.amount, fees).await
     }
 }
 
@@ -352,7 +753,7 @@ where
         if let WalletClientStates::Deposit(ds) = stream.next().await? {
             return Some(ds.state);
         }
-        tokio::task::yield_now().await;
+        fedimint_core::task::yield_now().await;
     }
 }
 
@@ -364,7 +765,7 @@ where
         if let WalletClientStates::Withdraw(ds) = stream.next().await? {
             return Some(ds.state);
         }
-        tokio::task::yield_now().await;
+        fedimint_core::task::yield_now().await;
     }
 }
 
@@ -439,6 +840,15 @@ pub enum WalletOperationMeta {
     Deposit {
         address: bitcoin::Address,
         expires_at: SystemTime,
+        #[serde(default)]
+        metadata: DepositMetadata,
+        /// Amount requested in the deposit URI
+        #[serde(default, with = "bitcoin::util::amount::serde::as_sat::opt")]
+        expected_amount: Option<bitcoin::Amount>,
+        /// Wallet module the operation was started on, see
+        /// [`WalletInstance`]
+        #[serde(default)]
+        instance_id: Option<ModuleInstanceId>,
     },
     Withdraw {
         address: bitcoin::Address,
@@ -446,6 +856,8 @@ pub enum WalletOperationMeta {
         amount: bitcoin::Amount,
         fee: PegOutFees,
         change: Option<OutPoint>,
+        #[serde(default)]
+        instance_id: Option<ModuleInstanceId>,
     },
 }
 
@@ -487,6 +899,13 @@ impl ClientModule for WalletClientModule {
             fee: self.cfg.fee_consensus.peg_out_abs,
         }
     }
+
+    fn balance_event_kind(&self, operation: &OperationLogEntry) -> Option<BalanceEventKind> {
+        match operation.meta::<WalletOperationMeta>() {
+            WalletOperationMeta::Deposit { .. } => Some(BalanceEventKind::PegInConfirmed),
+            WalletOperationMeta::Withdraw { .. } => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -545,6 +964,10 @@ impl WalletClientModule {
             .ok_or(anyhow!("Federation didn't return peg-out fees"))
     }
 
+    pub async fn get_deposit_policy(&self) -> anyhow::Result<PegInPolicy> {
+        Ok(self.module_api.fetch_deposit_policy().await?)
+    }
+
     pub async fn create_withdraw_output(
         &self,
         operation_id: OperationId,
@@ -5,12 +5,13 @@ use fedimint_core::bitcoinrpc::BitcoinRpcConfig;
 use fedimint_core::core::ModuleKind;
 use fedimint_core::encoding::{Decodable, Encodable};
 use fedimint_core::{plugin_types_trait_impl_config, Feerate, PeerId};
-use miniscript::descriptor::Wsh;
+use miniscript::descriptor::{Tr, Wsh};
 use secp256k1::SecretKey;
 use serde::{Deserialize, Serialize};
 
+use crate::frost::FrostPublicKeys;
 use crate::keys::CompressedPublicKey;
-use crate::{PegInDescriptor, WalletCommonGen};
+use crate::{PegInDescriptor, PegInDescriptorId, WalletCommonGen, WalletError};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WalletGenParams {
@@ -21,7 +22,10 @@ pub struct WalletGenParams {
 impl WalletGenParams {
     pub fn regtest(bitcoin_rpc: BitcoinRpcConfig) -> WalletGenParams {
         WalletGenParams {
-            local: WalletGenParamsLocal { bitcoin_rpc },
+            local: WalletGenParamsLocal {
+                bitcoin_rpc,
+                secondary_bitcoin_rpc: None,
+            },
             consensus: WalletGenParamsConsensus {
                 network: Network::Regtest,
                 finality_delay: 10,
@@ -33,6 +37,8 @@ impl WalletGenParams {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WalletGenParamsLocal {
     pub bitcoin_rpc: BitcoinRpcConfig,
+    #[serde(default)]
+    pub secondary_bitcoin_rpc: Option<BitcoinRpcConfig>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,12 +58,32 @@ pub struct WalletConfig {
 pub struct WalletConfigLocal {
     /// Configures which bitcoin RPC to use
     pub bitcoin_rpc: BitcoinRpcConfig,
+    /// Bitcoin RPC of a different backend the primary one is cross-checked
+    /// against, we don't advance the block height while they disagree
+    #[serde(default)]
+    pub secondary_bitcoin_rpc: Option<BitcoinRpcConfig>,
+    /// How many blocks the heights of the two backends may differ
+    #[serde(default = "default_max_backend_divergence")]
+    pub max_backend_divergence: u32,
+}
+
+fn default_max_backend_divergence() -> u32 {
+    DEFAULT_MAX_BACKEND_DIVERGENCE
 }
 
+/// Backends see new blocks at slightly different times, so we tolerate their
+/// heights being off by one
+pub const DEFAULT_MAX_BACKEND_DIVERGENCE: u32 = 1;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct WalletConfigPrivate {
-    /// Secret key for signing bitcoin multisig transactions
-    pub peg_in_key: SecretKey,
+    /// Secret key for signing bitcoin multisig transactions, `None` if it is
+    /// held by an external guardian signer
+    #[serde(default)]
+    pub peg_in_key: Option<SecretKey>,
+    /// Our share of the FROST group key, if peg-ins are locked to it
+    #[serde(default)]
+    pub frost_key_share: Option<SecretKey>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, Encodable, Decodable)]
@@ -66,8 +92,18 @@ pub struct WalletConfigConsensus {
     pub network: Network,
     /// The federations public peg-in-descriptor
     pub peg_in_descriptor: PegInDescriptor,
+    /// Descriptors the federation used before `peg_in_descriptor`, oldest
+    /// first. Their UTXOs can still be spent and get swept into the current
+    /// descriptor, they have to use the same keys.
+    #[serde(default)]
+    pub legacy_peg_in_descriptors: Vec<PegInDescriptor>,
     /// The public keys for the bitcoin multisig
     pub peer_peg_in_keys: BTreeMap<PeerId, CompressedPublicKey>,
+    /// FROST keys of the federation, peg-ins are locked to a taproot key
+    /// spend of the group key and peg-outs signed with a threshold signature
+    /// if set
+    #[serde(default)]
+    pub frost_keys: Option<FrostPublicKeys>,
     /// How many bitcoin blocks to wait before considering a transaction
     /// confirmed
     pub finality_delay: u32,
@@ -76,6 +112,16 @@ pub struct WalletConfigConsensus {
     pub default_fee: Feerate,
     /// Fees for bitcoin transactions
     pub fee_consensus: FeeConsensus,
+    /// Which peg-outs the federation pays part of the on-chain fees of
+    #[serde(default)]
+    pub fee_subsidy: FeeSubsidyConsensus,
+    /// Which peg-in amounts the federation accepts, see
+    /// [`WalletConfigConsensus::deposit_policy`]
+    #[serde(default)]
+    pub peg_in_policy: PegInPolicy,
+    /// Into how many outputs the change of peg-outs is split
+    #[serde(default)]
+    pub change_split: ChangeSplitConsensus,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
@@ -104,6 +150,123 @@ impl Default for FeeConsensus {
     }
 }
 
+/// Subsidy rates are given in basis points of the consensus fee rate
+pub const FEE_SUBSIDY_RATE_SCALE: u16 = 10_000;
+
+/// The federation pays part of the on-chain fees of small peg-outs from a pool
+/// funded by the peg-in and peg-out fees it collects. The user pays a reduced
+/// fee rate and the federation tops the transaction up to the consensus fee
+/// rate, as long as the pool covers the difference.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct FeeSubsidyConsensus {
+    /// Share of the consensus fee rate covered until the guardians vote for a
+    /// different one, in basis points
+    pub initial_rate_bps: u16,
+    /// Only peg-outs up to this amount are subsidized
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub max_peg_out_amount: bitcoin::Amount,
+}
+
+impl Default for FeeSubsidyConsensus {
+    fn default() -> Self {
+        Self {
+            initial_rate_bps: 0,
+            max_peg_out_amount: bitcoin::Amount::from_sat(100_000),
+        }
+    }
+}
+
+impl FeeSubsidyConsensus {
+    /// Lowest fee rate a peg-out of `amount` may pay if the federation covers
+    /// `rate_bps` of `consensus_fee_rate`, `None` if it isn't subsidized
+    pub fn subsidized_fee_rate(
+        &self,
+        rate_bps: u16,
+        amount: bitcoin::Amount,
+        consensus_fee_rate: Feerate,
+    ) -> Option<Feerate> {
+        if rate_bps == 0 || amount > self.max_peg_out_amount {
+            return None;
+        }
+
+        let covered = consensus_fee_rate.sats_per_kvb
+            * u64::from(rate_bps.min(FEE_SUBSIDY_RATE_SCALE))
+            / u64::from(FEE_SUBSIDY_RATE_SCALE);
+        Some(Feerate {
+            sats_per_kvb: consensus_fee_rate.sats_per_kvb - covered,
+        })
+    }
+}
+
+/// The change of a peg-out can be split into several outputs of random
+/// amounts, so it is harder to tell on-chain which outputs belong to the
+/// federation. Every additional output adds to the fees the user pays.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct ChangeSplitConsensus {
+    /// Largest number of change outputs, 1 disables splitting
+    pub max_outputs: u8,
+    /// Most a peg-out pays in fees for its additional change outputs
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub max_fee_overhead: bitcoin::Amount,
+}
+
+impl Default for ChangeSplitConsensus {
+    fn default() -> Self {
+        Self {
+            max_outputs: 1,
+            max_fee_overhead: bitcoin::Amount::ZERO,
+        }
+    }
+}
+
+impl ChangeSplitConsensus {
+    /// Number of change outputs of a peg-out paying `fee_rate`, if a change
+    /// output weighs `output_weight`. Only depends on the fee rate, so the
+    /// weight quoted to users matches the transaction the federation builds.
+    pub fn change_outputs(&self, fee_rate: Feerate, output_weight: u64) -> usize {
+        let additional = u64::from(self.max_outputs.saturating_sub(1));
+        let output_fee = fee_rate.calculate_fee(output_weight).to_sat();
+        let affordable = self
+            .max_fee_overhead
+            .to_sat()
+            .checked_div(output_fee)
+            .unwrap_or(u64::MAX);
+        1 + additional.min(affordable) as usize
+    }
+}
+
+/// Bounds of the amounts the federation accepts as peg-ins, deposits outside
+/// of them can't be claimed
+#[derive(
+    Debug, Clone, Default, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable,
+)]
+pub struct PegInPolicy {
+    /// Smallest accepted peg-in
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub min_amount: bitcoin::Amount,
+    /// Largest accepted peg-in, if limited
+    #[serde(default, with = "bitcoin::util::amount::serde::as_sat::opt")]
+    pub max_amount: Option<bitcoin::Amount>,
+}
+
+impl PegInPolicy {
+    pub fn check(&self, amount: bitcoin::Amount) -> Result<(), WalletError> {
+        if amount < self.min_amount || self.max_amount.map_or(false, |max| max < amount) {
+            return Err(WalletError::AmountOutOfBounds(amount, self.clone()));
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for PegInPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.max_amount {
+            Some(max_amount) => write!(f, "{} to {max_amount}", self.min_amount),
+            None => write!(f, "at least {}", self.min_amount),
+        }
+    }
+}
+
 impl WalletConfig {
     pub fn new(
         pubkeys: BTreeMap<PeerId, CompressedPublicKey>,
@@ -112,24 +275,87 @@ impl WalletConfig {
         network: Network,
         finality_delay: u32,
         bitcoin_rpc: BitcoinRpcConfig,
+        secondary_bitcoin_rpc: Option<BitcoinRpcConfig>,
     ) -> Self {
         let peg_in_descriptor = PegInDescriptor::Wsh(
             Wsh::new_sortedmulti(threshold, pubkeys.values().copied().collect()).unwrap(),
         );
 
         Self {
-            local: WalletConfigLocal { bitcoin_rpc },
-            private: WalletConfigPrivate { peg_in_key: sk },
+            local: WalletConfigLocal {
+                bitcoin_rpc,
+                secondary_bitcoin_rpc,
+                max_backend_divergence: DEFAULT_MAX_BACKEND_DIVERGENCE,
+            },
+            private: WalletConfigPrivate {
+                peg_in_key: Some(sk),
+                frost_key_share: None,
+            },
             consensus: WalletConfigConsensus {
                 network,
                 peg_in_descriptor,
+                legacy_peg_in_descriptors: vec![],
                 peer_peg_in_keys: pubkeys,
+                frost_keys: None,
                 finality_delay,
                 default_fee: Feerate { sats_per_kvb: 1000 },
                 fee_consensus: Default::default(),
+                fee_subsidy: Default::default(),
+                peg_in_policy: Default::default(),
+                change_split: Default::default(),
             },
         }
     }
+
+    /// Locks peg-ins to a taproot key spend of the FROST group key instead of
+    /// the multisig, so every peg-out input needs a single signature
+    pub fn with_frost_keys(mut self, keys: FrostPublicKeys, key_share: SecretKey) -> Self {
+        self.consensus.peg_in_descriptor = PegInDescriptor::Tr(
+            Tr::new(CompressedPublicKey::new(keys.group_key), None)
+                .expect("a key spend only descriptor is always valid"),
+        );
+        self.consensus.frost_keys = Some(keys);
+        self.private.frost_key_share = Some(key_share);
+        self
+    }
+}
+
+impl WalletConfigConsensus {
+    /// The configured [`PegInPolicy`], with the minimum raised to what spending
+    /// a peg-in costs at the default fee rate, so the federation doesn't accept
+    /// deposits that are worth less than the fees of sweeping them
+    pub fn deposit_policy(&self) -> PegInPolicy {
+        let input_weight = self
+            .peg_in_descriptor
+            .max_satisfaction_weight()
+            .expect("is satisfyable") as u64
+            + 128 // TxOutHash
+            + 16 // TxOutIndex
+            + 16; // sequence
+        let dust_limit = self.default_fee.calculate_fee(input_weight);
+        PegInPolicy {
+            min_amount: self.peg_in_policy.min_amount.max(dust_limit),
+            max_amount: self.peg_in_policy.max_amount,
+        }
+    }
+
+    /// The current descriptor followed by the legacy ones
+    pub fn all_peg_in_descriptors(&self) -> impl Iterator<Item = &PegInDescriptor> {
+        std::iter::once(&self.peg_in_descriptor).chain(&self.legacy_peg_in_descriptors)
+    }
+
+    /// Descriptor of the UTXOs received before the wallet recorded the
+    /// descriptor of every UTXO
+    pub fn original_peg_in_descriptor(&self) -> &PegInDescriptor {
+        self.legacy_peg_in_descriptors
+            .first()
+            .unwrap_or(&self.peg_in_descriptor)
+    }
+
+    pub fn peg_in_descriptor_by_id(&self, id: &PegInDescriptorId) -> Option<&PegInDescriptor> {
+        self.all_peg_in_descriptors()
+            .find(|descriptor| PegInDescriptorId::new(descriptor) == *id)
+    }
 }
 
 impl WalletClientConfig {
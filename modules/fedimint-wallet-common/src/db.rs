@@ -1,12 +1,14 @@
-use bitcoin::{BlockHash, Txid};
+use bitcoin::{BlockHash, BlockHeader, Txid};
 use fedimint_core::encoding::{Decodable, Encodable};
-use fedimint_core::{impl_db_lookup, impl_db_record};
+use fedimint_core::{impl_db_lookup, impl_db_record, PeerId};
 use secp256k1::ecdsa::Signature;
 use serde::Serialize;
 use strum_macros::EnumIter;
 
 use crate::{
-    PendingTransaction, RoundConsensus, SpendableUTXO, UnsignedTransaction, WalletOutputOutcome,
+    DepositTweak, FeeSubsidyPool, FrostSigningState, PegInDescriptorId, PegOutNoncesItem,
+    PegOutSignatureSharesItem, PendingTransaction, RoundConsensus, SpendableUTXO, UnclaimedDeposit,
+    UnsignedTransaction, WalletOutputOutcome,
 };
 
 #[repr(u8)]
@@ -19,6 +21,22 @@ pub enum DbKeyPrefix {
     PendingTransaction = 0x35,
     PegOutTxSigCi = 0x36,
     PegOutBitcoinOutPoint = 0x37,
+    BlockHeader = 0x38,
+    UtxoDescriptor = 0x39,
+    FrostSigningState = 0x3a,
+    PegOutNoncesCi = 0x3b,
+    PegOutSignatureSharesCi = 0x3c,
+    FrostNonceAttempt = 0x3d,
+    FeeSubsidyPool = 0x3e,
+    FeeSubsidyRate = 0x3f,
+    FeeSubsidyRateVote = 0x40,
+    FeeSubsidyRateCi = 0x41,
+    DepositTweak = 0x42,
+    UnclaimedDeposit = 0x43,
+    FrozenUtxo = 0x44,
+    UtxoFreezeVote = 0x45,
+    UtxoFreezeCi = 0x46,
+    PegOutSignatureEpochs = 0x47,
 }
 
 impl std::fmt::Display for DbKeyPrefix {
@@ -40,6 +58,20 @@ impl_db_record!(
 );
 impl_db_lookup!(key = BlockHashKey, query_prefix = BlockHashKeyPrefix);
 
+/// Validated header of the block at the given height
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct BlockHeaderKey(pub u32);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct BlockHeaderKeyPrefix;
+
+impl_db_record!(
+    key = BlockHeaderKey,
+    value = BlockHeader,
+    db_prefix = DbKeyPrefix::BlockHeader,
+);
+impl_db_lookup!(key = BlockHeaderKey, query_prefix = BlockHeaderKeyPrefix);
+
 #[derive(Clone, Debug, Eq, PartialEq, Encodable, Decodable, Serialize)]
 pub struct UTXOKey(pub bitcoin::OutPoint);
 
@@ -53,6 +85,24 @@ impl_db_record!(
 );
 impl_db_lookup!(key = UTXOKey, query_prefix = UTXOPrefixKey);
 
+/// Descriptor a UTXO is locked to, UTXOs received before this was recorded
+/// have no entry and are locked to the original descriptor
+#[derive(Clone, Debug, Eq, PartialEq, Encodable, Decodable, Serialize)]
+pub struct UTXODescriptorKey(pub bitcoin::OutPoint);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct UTXODescriptorPrefixKey;
+
+impl_db_record!(
+    key = UTXODescriptorKey,
+    value = PegInDescriptorId,
+    db_prefix = DbKeyPrefix::UtxoDescriptor,
+);
+impl_db_lookup!(
+    key = UTXODescriptorKey,
+    query_prefix = UTXODescriptorPrefixKey
+);
+
 #[derive(Clone, Debug, Encodable, Decodable, Serialize)]
 pub struct RoundConsensusKey;
 
@@ -125,3 +175,218 @@ impl_db_lookup!(
     key = PegOutBitcoinTransaction,
     query_prefix = PegOutBitcoinTransactionPrefix
 );
+
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct FrostSigningStateKey(pub Txid);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct FrostSigningStatePrefix;
+
+impl_db_record!(
+    key = FrostSigningStateKey,
+    value = FrostSigningState,
+    db_prefix = DbKeyPrefix::FrostSigningState,
+);
+impl_db_lookup!(
+    key = FrostSigningStateKey,
+    query_prefix = FrostSigningStatePrefix
+);
+
+/// Our FROST nonce commitments for a peg-out, proposed until signing starts
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct PegOutNoncesCI(pub Txid);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct PegOutNoncesCIPrefix;
+
+impl_db_record!(
+    key = PegOutNoncesCI,
+    value = PegOutNoncesItem,
+    db_prefix = DbKeyPrefix::PegOutNoncesCi,
+);
+impl_db_lookup!(key = PegOutNoncesCI, query_prefix = PegOutNoncesCIPrefix);
+
+/// Our FROST signature shares for a peg-out, proposed until it is finalized
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct PegOutSignatureSharesCI(pub Txid);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct PegOutSignatureSharesCIPrefix;
+
+impl_db_record!(
+    key = PegOutSignatureSharesCI,
+    value = PegOutSignatureSharesItem,
+    db_prefix = DbKeyPrefix::PegOutSignatureSharesCi,
+);
+impl_db_lookup!(
+    key = PegOutSignatureSharesCI,
+    query_prefix = PegOutSignatureSharesCIPrefix
+);
+
+/// The signing attempt our [`PegOutNoncesCI`] commits to, the secret nonces
+/// behind it are held by the guardian signer only
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct FrostNonceAttemptKey(pub Txid);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct FrostNonceAttemptPrefix;
+
+impl_db_record!(
+    key = FrostNonceAttemptKey,
+    value = u32,
+    db_prefix = DbKeyPrefix::FrostNonceAttempt,
+);
+impl_db_lookup!(
+    key = FrostNonceAttemptKey,
+    query_prefix = FrostNonceAttemptPrefix
+);
+
+/// Collected fees available for subsidizing peg-outs, a liability on the
+/// balance sheet until they are spent
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct FeeSubsidyPoolKey;
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct FeeSubsidyPoolPrefix;
+
+impl_db_record!(
+    key = FeeSubsidyPoolKey,
+    value = FeeSubsidyPool,
+    db_prefix = DbKeyPrefix::FeeSubsidyPool,
+);
+impl_db_lookup!(key = FeeSubsidyPoolKey, query_prefix = FeeSubsidyPoolPrefix);
+
+/// Subsidy rate a threshold of peers voted for, the configured initial rate
+/// applies until then
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct FeeSubsidyRateKey;
+
+impl_db_record!(
+    key = FeeSubsidyRateKey,
+    value = u16,
+    db_prefix = DbKeyPrefix::FeeSubsidyRate,
+);
+
+/// Latest subsidy rate vote of a peer, cleared once a rate is agreed on
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct FeeSubsidyRateVoteKey(pub PeerId);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct FeeSubsidyRateVotePrefix;
+
+impl_db_record!(
+    key = FeeSubsidyRateVoteKey,
+    value = u16,
+    db_prefix = DbKeyPrefix::FeeSubsidyRateVote,
+);
+impl_db_lookup!(
+    key = FeeSubsidyRateVoteKey,
+    query_prefix = FeeSubsidyRateVotePrefix
+);
+
+/// Our subsidy rate vote, proposed until it is part of an epoch
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct FeeSubsidyRateCI;
+
+impl_db_record!(
+    key = FeeSubsidyRateCI,
+    value = u16,
+    db_prefix = DbKeyPrefix::FeeSubsidyRateCi,
+);
+
+/// Tweak of an address we received funds on, by its tweak
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct DepositTweakKey(pub [u8; 32]);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct DepositTweakPrefix;
+
+impl_db_record!(
+    key = DepositTweakKey,
+    value = DepositTweak,
+    db_prefix = DbKeyPrefix::DepositTweak,
+);
+impl_db_lookup!(key = DepositTweakKey, query_prefix = DepositTweakPrefix);
+
+/// Deposit to a reused address that is part of our UTXOs but wasn't claimed
+/// yet, a liability on the balance sheet until it is
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct UnclaimedDepositKey(pub bitcoin::OutPoint);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct UnclaimedDepositPrefix;
+
+impl_db_record!(
+    key = UnclaimedDepositKey,
+    value = UnclaimedDeposit,
+    db_prefix = DbKeyPrefix::UnclaimedDeposit,
+);
+impl_db_lookup!(
+    key = UnclaimedDepositKey,
+    query_prefix = UnclaimedDepositPrefix
+);
+
+/// UTXO excluded from peg-out transactions, moved here from [`UTXOKey`] once a
+/// threshold of guardians voted to freeze it
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct FrozenUtxoKey(pub bitcoin::OutPoint);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct FrozenUtxoPrefix;
+
+impl_db_record!(
+    key = FrozenUtxoKey,
+    value = SpendableUTXO,
+    db_prefix = DbKeyPrefix::FrozenUtxo,
+);
+impl_db_lookup!(key = FrozenUtxoKey, query_prefix = FrozenUtxoPrefix);
+
+/// Vote of a peer for freezing (`true`) or unfreezing a UTXO, removed once the
+/// vote reached the threshold
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct UtxoFreezeVoteKey {
+    pub outpoint: bitcoin::OutPoint,
+    pub peer: PeerId,
+}
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct UtxoFreezeVotePrefix;
+
+impl_db_record!(
+    key = UtxoFreezeVoteKey,
+    value = bool,
+    db_prefix = DbKeyPrefix::UtxoFreezeVote,
+);
+impl_db_lookup!(key = UtxoFreezeVoteKey, query_prefix = UtxoFreezeVotePrefix);
+
+/// Our freeze votes, proposed until they are part of an epoch
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct UtxoFreezeCIKey(pub bitcoin::OutPoint);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct UtxoFreezeCIPrefix;
+
+impl_db_record!(
+    key = UtxoFreezeCIKey,
+    value = bool,
+    db_prefix = DbKeyPrefix::UtxoFreezeCi,
+);
+impl_db_lookup!(key = UtxoFreezeCIKey, query_prefix = UtxoFreezeCIPrefix);
+
+/// Epochs a PSBT waited for the signatures of the remaining peers since the
+/// first one arrived, see [`crate::PEG_OUT_SIGNATURE_TIMEOUT_EPOCHS`]
+#[derive(Clone, Debug, Encodable, Decodable, Serialize)]
+pub struct PegOutSignatureEpochsKey(pub Txid);
+
+#[derive(Clone, Debug, Encodable, Decodable)]
+pub struct PegOutSignatureEpochsPrefix;
+
+impl_db_record!(
+    key = PegOutSignatureEpochsKey,
+    value = u32,
+    db_prefix = DbKeyPrefix::PegOutSignatureEpochs,
+);
+impl_db_lookup!(
+    key = PegOutSignatureEpochsKey,
+    query_prefix = PegOutSignatureEpochsPrefix
+);
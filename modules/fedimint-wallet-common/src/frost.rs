@@ -0,0 +1,711 @@
+//! FROST threshold Schnorr signatures for taproot peg-outs
+//!
+//! A threshold of guardians produces a single BIP-340 signature for the
+//! taproot key spend of every peg-out input, instead of each guardian signing
+//! the multisig script. The cryptography, including the distributed key
+//! generation, is the `frost-secp256k1-tr` crate of the Zcash Foundation. This
+//! module adapts it to the wallet: it maps peers to FROST identifiers, wraps
+//! its messages for the consensus encoding and keeps the keys in the config as
+//! plain secp256k1 keys.
+//!
+//! Every UTXO is locked to the group key tweaked with the pay-to-contract
+//! tweak of its peg-in and then with the BIP-341 taproot tweak. The
+//! pay-to-contract tweak is added to the key shares here, the crate applies
+//! the taproot tweak itself.
+
+use std::collections::BTreeMap;
+use std::hash::{Hash, Hasher};
+use std::io::{Error, ErrorKind, Read, Write};
+
+use bitcoin::util::schnorr::TapTweak;
+use fedimint_core::encoding::{Decodable, DecodeError, Encodable};
+use fedimint_core::module::registry::ModuleDecoderRegistry;
+use fedimint_core::{NumPeers, PeerId};
+use frost_secp256k1_tr as frost;
+use frost_secp256k1_tr::keys::dkg;
+use frost_secp256k1_tr::keys::{
+    IdentifierList, KeyPackage, PublicKeyPackage, SigningShare, Tweak, VerifyingShare,
+};
+use frost_secp256k1_tr::{Identifier, SigningPackage, VerifyingKey};
+use rand::{CryptoRng, RngCore};
+use secp256k1::schnorr::Signature;
+use secp256k1::{Message, PublicKey, Scalar, Secp256k1, SecretKey, Verification, XOnlyPublicKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum FrostError {
+    #[error("FROST failed: {0}")]
+    Frost(#[from] frost::Error),
+    #[error("Invalid FROST key: {0}")]
+    InvalidKey(#[from] secp256k1::Error),
+    #[error("Missing DKG message from {0}")]
+    MissingPeer(PeerId),
+    #[error("Not enough signers, need {0} got {1}")]
+    NotEnoughSigners(usize, usize),
+    #[error("{0} is not part of the signing session")]
+    UnknownSigner(PeerId),
+    #[error("Invalid signature share from {0}")]
+    InvalidSignatureShare(PeerId),
+    #[error("The aggregated signature is invalid")]
+    InvalidSignature,
+}
+
+/// Consensus encodes a wrapper of a frost type as the type's serialization
+macro_rules! impl_frost_encoding {
+    ($wrapper:ident, $inner:ty) => {
+        impl Encodable for $wrapper {
+            fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+                self.0
+                    .serialize()
+                    .map_err(|e| Error::new(ErrorKind::InvalidData, e))?
+                    .consensus_encode(writer)
+            }
+        }
+
+        impl Decodable for $wrapper {
+            fn consensus_decode<R: Read>(
+                r: &mut R,
+                modules: &ModuleDecoderRegistry,
+            ) -> Result<Self, DecodeError> {
+                let bytes = Vec::<u8>::consensus_decode(r, modules)?;
+                Ok($wrapper(
+                    <$inner>::deserialize(&bytes).map_err(DecodeError::from_err)?,
+                ))
+            }
+        }
+
+        impl Hash for $wrapper {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.consensus_encode_to_vec()
+                    .expect("encoding to vec can't fail")
+                    .hash(state)
+            }
+        }
+    };
+}
+
+/// The FROST identifier of a peer, offset by one since identifiers are
+/// non-zero
+pub fn identifier(peer: PeerId) -> Identifier {
+    Identifier::try_from(u16::from(peer) + 1).expect("identifier is non-zero")
+}
+
+fn secret_key(share: &SigningShare) -> Result<SecretKey, FrostError> {
+    Ok(SecretKey::from_slice(&share.serialize())?)
+}
+
+fn public_key(key: &VerifyingKey) -> Result<PublicKey, FrostError> {
+    Ok(PublicKey::from_slice(&key.serialize()?)?)
+}
+
+/// Public part of the federation's FROST key
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct FrostPublicKeys {
+    /// Group key the peg-in descriptor is derived from
+    pub group_key: PublicKey,
+    /// Public key of every peer's share of the group key, used to verify
+    /// their signature shares
+    pub verification_shares: BTreeMap<PeerId, PublicKey>,
+}
+
+impl FrostPublicKeys {
+    fn from_package(package: &PublicKeyPackage, peers: &[PeerId]) -> Result<Self, FrostError> {
+        let verification_shares = peers
+            .iter()
+            .map(|peer| {
+                let share = package
+                    .verifying_shares()
+                    .get(&identifier(*peer))
+                    .ok_or(FrostError::MissingPeer(*peer))?;
+                Ok((*peer, PublicKey::from_slice(&share.serialize()?)?))
+            })
+            .collect::<Result<_, FrostError>>()?;
+
+        Ok(FrostPublicKeys {
+            group_key: public_key(package.verifying_key())?,
+            verification_shares,
+        })
+    }
+
+    /// Number of peers needed to sign
+    pub fn threshold(&self) -> usize {
+        self.verification_shares.threshold()
+    }
+
+    /// Checks `key_share` is the share of `peer`
+    pub fn verify_key_share<C: secp256k1::Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        peer: PeerId,
+        key_share: &SecretKey,
+    ) -> bool {
+        self.verification_shares.get(&peer) == Some(&PublicKey::from_secret_key(secp, key_share))
+    }
+
+    /// The key package `peer` signs with, given its `key_share`
+    pub fn key_package(
+        &self,
+        peer: PeerId,
+        key_share: &SecretKey,
+    ) -> Result<KeyPackage, FrostError> {
+        let verification_share = self
+            .verification_shares
+            .get(&peer)
+            .ok_or(FrostError::UnknownSigner(peer))?;
+        Ok(KeyPackage::new(
+            identifier(peer),
+            SigningShare::deserialize(&key_share.secret_bytes())?,
+            VerifyingShare::deserialize(&verification_share.serialize())?,
+            VerifyingKey::deserialize(&self.group_key.serialize())?,
+            self.threshold() as u16,
+        ))
+    }
+
+    /// The public keys of the FROST key tweaked with `contract_tweak`, every
+    /// share is tweaked like the group key since the Lagrange coefficients
+    /// add up to one
+    fn tweaked_package<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        contract_tweak: &Scalar,
+    ) -> Result<PublicKeyPackage, FrostError> {
+        let verifying_shares = self
+            .verification_shares
+            .iter()
+            .map(|(peer, share)| {
+                let share = share.add_exp_tweak(secp, contract_tweak)?;
+                Ok((
+                    identifier(*peer),
+                    VerifyingShare::deserialize(&share.serialize())?,
+                ))
+            })
+            .collect::<Result<_, FrostError>>()?;
+        let group_key = self.group_key.add_exp_tweak(secp, contract_tweak)?;
+
+        Ok(PublicKeyPackage::new(
+            verifying_shares,
+            VerifyingKey::deserialize(&group_key.serialize())?,
+        ))
+    }
+}
+
+/// Generates the FROST keys with a trusted dealer, who learns the group
+/// secret
+pub fn trusted_dealer_keygen<R: RngCore + CryptoRng>(
+    peers: &[PeerId],
+    rng: &mut R,
+) -> Result<(FrostPublicKeys, BTreeMap<PeerId, SecretKey>), FrostError> {
+    let identifiers = peers
+        .iter()
+        .map(|peer| identifier(*peer))
+        .collect::<Vec<_>>();
+    let (shares, package) = frost::keys::generate_with_dealer(
+        peers.len() as u16,
+        peers.threshold() as u16,
+        IdentifierList::Custom(&identifiers),
+        rng,
+    )?;
+
+    let key_shares = peers
+        .iter()
+        .map(|peer| {
+            let share = shares
+                .get(&identifier(*peer))
+                .ok_or(FrostError::MissingPeer(*peer))?;
+            Ok((*peer, secret_key(share.signing_share())?))
+        })
+        .collect::<Result<_, FrostError>>()?;
+
+    Ok((FrostPublicKeys::from_package(&package, peers)?, key_shares))
+}
+
+/// First round message of the DKG, broadcast to every peer
+#[derive(Debug, Clone)]
+pub struct FrostDkgCommitment(pub dkg::round1::Package);
+
+impl_frost_encoding!(FrostDkgCommitment, dkg::round1::Package);
+
+/// Second round message of the DKG, our share of our secret for one peer
+#[derive(Debug, Clone)]
+pub struct FrostDkgShare(pub dkg::round2::Package);
+
+impl_frost_encoding!(FrostDkgShare, dkg::round2::Package);
+
+/// Messages of all peers but us, keyed by their identifiers
+fn from_others<T, U>(
+    our_id: PeerId,
+    peers: &[PeerId],
+    messages: &BTreeMap<PeerId, T>,
+    inner: impl Fn(&T) -> U,
+) -> Result<BTreeMap<Identifier, U>, FrostError> {
+    peers
+        .iter()
+        .filter(|peer| **peer != our_id)
+        .map(|peer| {
+            let message = messages.get(peer).ok_or(FrostError::MissingPeer(*peer))?;
+            Ok((identifier(*peer), inner(message)))
+        })
+        .collect()
+}
+
+/// Our state in the first round of the FROST DKG
+///
+/// The DKG takes two rounds: every peer broadcasts a [`FrostDkgCommitment`],
+/// then [`FrostDkg::share`] verifies the commitments of the others and
+/// creates a [`FrostDkgShare`] privately sent to every other peer.
+pub struct FrostDkg {
+    our_id: PeerId,
+    peers: Vec<PeerId>,
+    secret: dkg::round1::SecretPackage,
+}
+
+impl FrostDkg {
+    pub fn new<R: RngCore + CryptoRng>(
+        our_id: PeerId,
+        peers: &[PeerId],
+        rng: &mut R,
+    ) -> Result<(Self, FrostDkgCommitment), FrostError> {
+        let (secret, commitment) = dkg::part1(
+            identifier(our_id),
+            peers.len() as u16,
+            peers.threshold() as u16,
+            rng,
+        )?;
+        let dkg = FrostDkg {
+            our_id,
+            peers: peers.to_vec(),
+            secret,
+        };
+        Ok((dkg, FrostDkgCommitment(commitment)))
+    }
+
+    /// Verifies the commitments of the other peers and creates their shares
+    pub fn share(
+        self,
+        commitments: &BTreeMap<PeerId, FrostDkgCommitment>,
+    ) -> Result<(FrostDkgRound2, BTreeMap<PeerId, FrostDkgShare>), FrostError> {
+        let commitments = from_others(self.our_id, &self.peers, commitments, |commitment| {
+            commitment.0.clone()
+        })?;
+        let (secret, mut shares) = dkg::part2(self.secret, &commitments)?;
+
+        let shares = self
+            .peers
+            .iter()
+            .filter(|peer| **peer != self.our_id)
+            .map(|peer| {
+                let share = shares
+                    .remove(&identifier(*peer))
+                    .ok_or(FrostError::MissingPeer(*peer))?;
+                Ok((*peer, FrostDkgShare(share)))
+            })
+            .collect::<Result<_, FrostError>>()?;
+
+        let round2 = FrostDkgRound2 {
+            our_id: self.our_id,
+            peers: self.peers,
+            secret,
+            commitments,
+        };
+        Ok((round2, shares))
+    }
+}
+
+/// Our state in the second round of the FROST DKG
+pub struct FrostDkgRound2 {
+    our_id: PeerId,
+    peers: Vec<PeerId>,
+    secret: dkg::round2::SecretPackage,
+    commitments: BTreeMap<Identifier, dkg::round1::Package>,
+}
+
+impl FrostDkgRound2 {
+    /// Verifies the shares the other peers sent us and derives our key share
+    pub fn finish(
+        self,
+        shares: &BTreeMap<PeerId, FrostDkgShare>,
+    ) -> Result<(FrostPublicKeys, SecretKey), FrostError> {
+        let shares = from_others(self.our_id, &self.peers, shares, |share| share.0.clone())?;
+        let (key_package, package) = dkg::part3(&self.secret, &self.commitments, &shares)?;
+
+        Ok((
+            FrostPublicKeys::from_package(&package, &self.peers)?,
+            secret_key(key_package.signing_share())?,
+        ))
+    }
+}
+
+/// Public commitment to the secret nonces of one signature share, sent to the
+/// other signers first. The nonces themselves never leave the guardian signer.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct NonceCommitment(pub frost::round1::SigningCommitments);
+
+impl_frost_encoding!(NonceCommitment, frost::round1::SigningCommitments);
+
+/// A signer's share of the signature of one input
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SignatureShare(pub frost::round2::SignatureShare);
+
+impl Encodable for SignatureShare {
+    fn consensus_encode<W: Write>(&self, writer: &mut W) -> Result<usize, Error> {
+        self.0.serialize().consensus_encode(writer)
+    }
+}
+
+impl Decodable for SignatureShare {
+    fn consensus_decode<R: Read>(
+        r: &mut R,
+        modules: &ModuleDecoderRegistry,
+    ) -> Result<Self, DecodeError> {
+        let bytes = Vec::<u8>::consensus_decode(r, modules)?;
+        Ok(SignatureShare(
+            frost::round2::SignatureShare::deserialize(&bytes).map_err(DecodeError::from_err)?,
+        ))
+    }
+}
+
+impl Hash for SignatureShare {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.serialize().hash(state)
+    }
+}
+
+/// Output key of a UTXO whose internal key is the group key tweaked with
+/// `contract_tweak`
+pub fn output_key<C: Verification>(
+    secp: &Secp256k1<C>,
+    group_key: &PublicKey,
+    contract_tweak: &Scalar,
+) -> Result<XOnlyPublicKey, FrostError> {
+    let (internal_key, _) = group_key
+        .add_exp_tweak(secp, contract_tweak)?
+        .x_only_public_key();
+    Ok(internal_key.tap_tweak(secp, None).0.to_inner())
+}
+
+/// Signing a message for the key spend of one UTXO with the nonce
+/// commitments of a fixed set of signers
+#[derive(Debug, Clone)]
+pub struct SigningSession {
+    signing_package: SigningPackage,
+    contract_tweak: Scalar,
+    /// Public keys tweaked with the contract tweak, the crate adds the
+    /// taproot tweak when aggregating
+    public_keys: PublicKeyPackage,
+    /// Public keys tweaked with both tweaks, signature shares are verified
+    /// against them
+    output_keys: PublicKeyPackage,
+    output_key: XOnlyPublicKey,
+    message: [u8; 32],
+}
+
+impl SigningSession {
+    pub fn new<C: Verification>(
+        secp: &Secp256k1<C>,
+        keys: &FrostPublicKeys,
+        contract_tweak: &Scalar,
+        message: [u8; 32],
+        commitments: BTreeMap<PeerId, NonceCommitment>,
+    ) -> Result<Self, FrostError> {
+        if commitments.len() < keys.threshold() {
+            return Err(FrostError::NotEnoughSigners(
+                keys.threshold(),
+                commitments.len(),
+            ));
+        }
+        let commitments = commitments
+            .into_iter()
+            .map(|(peer, commitment)| {
+                if !keys.verification_shares.contains_key(&peer) {
+                    return Err(FrostError::UnknownSigner(peer));
+                }
+                Ok((identifier(peer), commitment.0))
+            })
+            .collect::<Result<_, FrostError>>()?;
+
+        let public_keys = keys.tweaked_package(secp, contract_tweak)?;
+        Ok(SigningSession {
+            signing_package: SigningPackage::new(commitments, &message),
+            contract_tweak: *contract_tweak,
+            output_keys: public_keys.clone().tweak(None::<&[u8]>),
+            public_keys,
+            output_key: output_key(secp, &keys.group_key, contract_tweak)?,
+            message,
+        })
+    }
+
+    /// What a signer needs to create its share, besides its nonces and key
+    pub fn signing_package(&self) -> &SigningPackage {
+        &self.signing_package
+    }
+
+    /// The tweak a signer adds to its key share
+    pub fn contract_tweak(&self) -> &Scalar {
+        &self.contract_tweak
+    }
+
+    /// Checks the signature share of `peer`, so a signer that sent an invalid
+    /// one can be excluded before aggregating
+    pub fn verify_share(&self, peer: PeerId, share: &SignatureShare) -> Result<(), FrostError> {
+        let identifier = identifier(peer);
+        if !self
+            .signing_package
+            .signing_commitments()
+            .contains_key(&identifier)
+        {
+            return Err(FrostError::UnknownSigner(peer));
+        }
+        let verification_share = self
+            .output_keys
+            .verifying_shares()
+            .get(&identifier)
+            .ok_or(FrostError::UnknownSigner(peer))?;
+
+        frost::frost::verify_signature_share(
+            identifier,
+            verification_share,
+            &share.0,
+            &self.signing_package,
+            self.output_keys.verifying_key(),
+        )
+        .map_err(|_| FrostError::InvalidSignatureShare(peer))
+    }
+
+    /// Combines the shares of all signers into a BIP-340 signature for the
+    /// output key
+    pub fn aggregate<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        shares: &BTreeMap<PeerId, SignatureShare>,
+    ) -> Result<Signature, FrostError> {
+        let shares = shares
+            .iter()
+            .map(|(peer, share)| (identifier(*peer), share.0))
+            .collect();
+        let signature =
+            frost::aggregate_with_tweak(&self.signing_package, &shares, &self.public_keys, None)?;
+
+        let signature = Signature::from_slice(&signature.serialize()?)?;
+        secp.verify_schnorr(
+            &signature,
+            &Message::from_slice(&self.message).expect("32 bytes"),
+            &self.output_key,
+        )
+        .map_err(|_| FrostError::InvalidSignature)?;
+        Ok(signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use bitcoin::util::address::Address;
+    use bitcoin::Network;
+    use fedimint_core::PeerId;
+    use miniscript::Descriptor;
+    use rand::rngs::OsRng;
+    use secp256k1::{Scalar, Secp256k1};
+
+    use super::*;
+    use crate::keys::CompressedPublicKey;
+    use crate::tweakable::{tweak_scalar, Tweakable};
+
+    fn peers(num: u16) -> Vec<PeerId> {
+        (0..num).map(PeerId::from).collect()
+    }
+
+    /// Runs the DKG of all `peers`, with the shares sent to every peer
+    /// passed through `tamper`
+    fn run_dkg(
+        peers: &[PeerId],
+        tamper: impl Fn(PeerId, &mut BTreeMap<PeerId, FrostDkgShare>),
+    ) -> Vec<Result<(FrostPublicKeys, SecretKey), FrostError>> {
+        let (dkgs, commitments): (Vec<_>, BTreeMap<_, _>) = peers
+            .iter()
+            .map(|peer| {
+                let (dkg, commitment) = FrostDkg::new(*peer, peers, &mut OsRng).unwrap();
+                ((*peer, dkg), (*peer, commitment))
+            })
+            .unzip();
+
+        let mut sent = BTreeMap::new();
+        let rounds = dkgs
+            .into_iter()
+            .map(|(sender, dkg)| {
+                let (round2, shares) = dkg.share(&commitments).expect("honest commitments");
+                for (receiver, share) in shares {
+                    sent.entry(receiver)
+                        .or_insert_with(BTreeMap::new)
+                        .insert(sender, share);
+                }
+                (sender, round2)
+            })
+            .collect::<Vec<_>>();
+
+        rounds
+            .into_iter()
+            .map(|(peer, round2)| {
+                let mut shares = sent[&peer].clone();
+                tamper(peer, &mut shares);
+                round2.finish(&shares)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn dkg_agrees_on_keys() {
+        let secp = Secp256k1::new();
+        let peers = peers(4);
+
+        let results = run_dkg(&peers, |_, _| {})
+            .into_iter()
+            .zip(&peers)
+            .map(|(result, peer)| {
+                let (public, share) = result.expect("honest DKG succeeds");
+                assert!(public.verify_key_share(&secp, *peer, &share));
+                public
+            })
+            .collect::<Vec<_>>();
+
+        assert!(results.windows(2).all(|pair| pair[0] == pair[1]));
+    }
+
+    #[test]
+    fn dkg_rejects_bad_share() {
+        let peers = peers(4);
+
+        let results = run_dkg(&peers, |receiver, shares| {
+            if receiver == peers[0] {
+                shares.remove(&peers[1]);
+            }
+        });
+        assert_eq!(
+            results[0].as_ref().unwrap_err(),
+            &FrostError::MissingPeer(peers[1])
+        );
+
+        // Peer 0 receives the share of peer 2 in place of the one of peer 1
+        let results = run_dkg(&peers, |receiver, shares| {
+            if receiver == peers[0] {
+                let share = shares[&peers[2]].clone();
+                shares.insert(peers[1], share);
+            }
+        });
+        assert!(matches!(results[0], Err(FrostError::Frost(_))));
+        assert!(results[1..].iter().all(Result::is_ok));
+    }
+
+    #[test]
+    fn threshold_signature_spends_taproot_descriptor() {
+        let secp = Secp256k1::new();
+        let peers = peers(4);
+        let (public, shares) = trusted_dealer_keygen(&peers, &mut OsRng).unwrap();
+
+        // The descriptor clients derive peg-in addresses from
+        let descriptor = Descriptor::new_tr(CompressedPublicKey::new(public.group_key), None)
+            .expect("valid descriptor");
+        let peg_in_tweak = [7u8; 32];
+        let tweaked = descriptor.tweak(&peg_in_tweak, &secp);
+
+        let contract_tweak = tweak_scalar(&public.group_key, &peg_in_tweak);
+        let key = output_key(&secp, &public.group_key, &contract_tweak).unwrap();
+        assert_eq!(
+            tweaked.address(Network::Regtest).expect("taproot address"),
+            Address::p2tr_tweaked(
+                bitcoin::util::schnorr::TweakedPublicKey::dangerous_assume_tweaked(key),
+                Network::Regtest
+            )
+        );
+
+        // Signers add the contract tweak to their key share, as the guardian
+        // signer does
+        let key_packages = peers
+            .iter()
+            .map(|peer| {
+                let share = shares[peer].add_tweak(&contract_tweak).unwrap();
+                let tweaked_keys = FrostPublicKeys {
+                    group_key: public
+                        .group_key
+                        .add_exp_tweak(&secp, &contract_tweak)
+                        .unwrap(),
+                    verification_shares: public
+                        .verification_shares
+                        .iter()
+                        .map(|(peer, share)| {
+                            (*peer, share.add_exp_tweak(&secp, &contract_tweak).unwrap())
+                        })
+                        .collect(),
+                };
+                (*peer, tweaked_keys.key_package(*peer, &share).unwrap())
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let message = [42u8; 32];
+        let signers = &peers[1..];
+        let (nonces, commitments): (BTreeMap<_, _>, BTreeMap<_, _>) = signers
+            .iter()
+            .map(|peer| {
+                let (nonces, commitment) =
+                    frost::round1::commit(key_packages[peer].signing_share(), &mut OsRng);
+                ((*peer, nonces), (*peer, NonceCommitment(commitment)))
+            })
+            .unzip();
+
+        let session =
+            SigningSession::new(&secp, &public, &contract_tweak, message, commitments).unwrap();
+        let signature_shares = nonces
+            .into_iter()
+            .map(|(peer, nonces)| {
+                let share = frost::round2::sign_with_tweak(
+                    session.signing_package(),
+                    &nonces,
+                    &key_packages[&peer],
+                    None,
+                )
+                .map(SignatureShare)
+                .unwrap();
+                session
+                    .verify_share(peer, &share)
+                    .expect("honest share verifies");
+                (peer, share)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        session
+            .aggregate(&secp, &signature_shares)
+            .expect("valid BIP-340 signature");
+
+        // A share of another signer doesn't verify as ours
+        let mut bad_shares = signature_shares.clone();
+        bad_shares.insert(peers[1], signature_shares[&peers[2]]);
+        assert_eq!(
+            session.verify_share(peers[1], &bad_shares[&peers[1]]),
+            Err(FrostError::InvalidSignatureShare(peers[1]))
+        );
+        assert!(session.aggregate(&secp, &bad_shares).is_err());
+    }
+
+    #[test]
+    fn session_needs_threshold_of_signers() {
+        let secp = Secp256k1::new();
+        let peers = peers(4);
+        let (public, shares) = trusted_dealer_keygen(&peers, &mut OsRng).unwrap();
+
+        let commitments = peers[..2]
+            .iter()
+            .map(|peer| {
+                let key_package = public.key_package(*peer, &shares[peer]).unwrap();
+                let (_, commitment) =
+                    frost::round1::commit(key_package.signing_share(), &mut OsRng);
+                (*peer, NonceCommitment(commitment))
+            })
+            .collect();
+
+        assert_eq!(
+            SigningSession::new(&secp, &public, &Scalar::ONE, [0; 32], commitments).unwrap_err(),
+            FrostError::NotEnoughSigners(3, 2)
+        );
+    }
+}
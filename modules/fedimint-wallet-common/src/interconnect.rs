@@ -0,0 +1,52 @@
+use bitcoin::BlockHash;
+use fedimint_core::core::ModuleKind;
+use fedimint_core::module::interconnect::{DynModuleInterconnect, InterconnectMethod};
+use fedimint_core::module::ApiError;
+use fedimint_core::{apply, async_trait_maybe_send};
+
+use crate::KIND;
+
+/// Whether the wallet saw a block with the hash up to its consensus height
+pub struct VerifyBlockKnownMethod;
+
+impl InterconnectMethod for VerifyBlockKnownMethod {
+    const KIND: ModuleKind = KIND;
+    const NAME: &'static str = "verify_block_known";
+    const VERSION: u32 = 0;
+    type Request = BlockHash;
+    type Response = bool;
+}
+
+/// The block height the wallet synced to in consensus, `None` before the first
+/// round
+pub struct ConsensusBlockHeightMethod;
+
+impl InterconnectMethod for ConsensusBlockHeightMethod {
+    const KIND: ModuleKind = KIND;
+    const NAME: &'static str = "consensus_block_height";
+    const VERSION: u32 = 0;
+    type Request = ();
+    type Response = Option<u32>;
+}
+
+/// Lets other server modules rely on the bitcoin chain as seen by the wallet
+/// module
+#[apply(async_trait_maybe_send!)]
+pub trait WalletInterconnect {
+    /// Whether the federation considers the block part of the chain
+    async fn verify_block_known(&self, block_hash: BlockHash) -> Result<bool, ApiError>;
+
+    /// The block height the federation agreed on
+    async fn wallet_block_height(&self) -> Result<Option<u32>, ApiError>;
+}
+
+#[apply(async_trait_maybe_send!)]
+impl WalletInterconnect for DynModuleInterconnect {
+    async fn verify_block_known(&self, block_hash: BlockHash) -> Result<bool, ApiError> {
+        self.call::<VerifyBlockKnownMethod>(block_hash).await
+    }
+
+    async fn wallet_block_height(&self) -> Result<Option<u32>, ApiError> {
+        self.call::<ConsensusBlockHeightMethod>(()).await
+    }
+}
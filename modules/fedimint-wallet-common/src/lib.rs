@@ -1,11 +1,14 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::hash::Hasher;
 
 use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::{sha256, Hash as BitcoinHash};
 use bitcoin::util::psbt::raw::ProprietaryKey;
 use bitcoin::util::psbt::PartiallySignedTransaction;
 use bitcoin::{Amount, BlockHash, Network, Script, Transaction, Txid};
 use fedimint_core::core::{Decoder, ModuleInstanceId, ModuleKind};
 use fedimint_core::encoding::{Decodable, Encodable, UnzipConsensus};
+use fedimint_core::module::error::{code, ModuleErrorCode};
 use fedimint_core::module::{CommonModuleGen, ModuleCommon, ModuleConsensusVersion};
 use fedimint_core::{plugin_types_trait_impl_common, Feerate, PeerId};
 use impl_tools::autoimpl;
@@ -15,11 +18,14 @@ use thiserror::Error;
 use tracing::error;
 
 use crate::db::UTXOKey;
+use crate::frost::{FrostError, NonceCommitment, SignatureShare};
 use crate::keys::CompressedPublicKey;
 use crate::txoproof::{PegInProof, PegInProofError};
 
 pub mod config;
 pub mod db;
+pub mod frost;
+pub mod interconnect;
 pub mod keys;
 pub mod tweakable;
 pub mod txoproof;
@@ -29,16 +35,93 @@ const CONSENSUS_VERSION: ModuleConsensusVersion = ModuleConsensusVersion(0);
 
 pub const CONFIRMATION_TARGET: u16 = 10;
 
+/// Epochs the signers of a FROST signing attempt get to send their shares,
+/// afterwards signing restarts without the ones that are late
+pub const FROST_SIGNING_TIMEOUT_EPOCHS: u32 = 10;
+
+/// Epochs the peers get to send their signatures for a peg-out once the first
+/// one arrived. They are encrypted and can be revealed in different epochs,
+/// so only peers still missing afterwards are penalized.
+pub const PEG_OUT_SIGNATURE_TIMEOUT_EPOCHS: u32 = 10;
+
 pub type PartialSig = Vec<u8>;
 
 pub type PegInDescriptor = Descriptor<CompressedPublicKey>;
 
+/// Identifies a peg-in descriptor by the hash of its encoding
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    Hash,
+    PartialOrd,
+    Ord,
+    Serialize,
+    Deserialize,
+    Encodable,
+    Decodable,
+)]
+pub struct PegInDescriptorId(pub bitcoin::hashes::sha256::Hash);
+
+impl PegInDescriptorId {
+    pub fn new(descriptor: &PegInDescriptor) -> Self {
+        PegInDescriptorId(descriptor.consensus_hash())
+    }
+}
+
+/// Progress of sweeping the UTXOs of legacy peg-in descriptors into the
+/// current one
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum DescriptorMigrationStatus {
+    /// The federation has no legacy descriptors
+    NotMigrating,
+    /// UTXOs of legacy descriptors are left, some get swept with every block
+    Sweeping {
+        remaining_utxos: u64,
+        #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+        remaining: Amount,
+    },
+    /// All UTXOs of legacy descriptors are spent by transactions that didn't
+    /// confirm yet
+    AwaitingConfirmation { transactions: u64 },
+    /// The legacy descriptors can be removed from the config
+    Complete,
+}
+
+/// Fees set aside to subsidize the on-chain fees of small peg-outs, see
+/// [`config::FeeSubsidyConsensus`]
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct FeeSubsidyPool {
+    /// Collected fees not spent on subsidies yet
+    pub balance: fedimint_core::Amount,
+    /// Total paid towards the on-chain fees of peg-outs
+    pub drawn: fedimint_core::Amount,
+}
+
+/// Returned by the `fee_subsidy` endpoint
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FeeSubsidyStatus {
+    pub config: config::FeeSubsidyConsensus,
+    /// Share of the consensus fee rate currently covered, in basis points
+    pub rate_bps: u16,
+    pub pool: FeeSubsidyPool,
+}
+
 #[derive(
     Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, UnzipConsensus, Encodable, Decodable,
 )]
 pub enum WalletConsensusItem {
     RoundConsensus(RoundConsensusItem),
     PegOutSignature(PegOutSignatureItem),
+    PegOutNonces(PegOutNoncesItem),
+    PegOutSignatureShares(PegOutSignatureSharesItem),
+    /// Vote for the share of the consensus fee rate subsidized, in basis
+    /// points
+    FeeSubsidyRate(u16),
+    /// Vote for freezing or unfreezing a UTXO of the federation
+    UtxoFreeze(UtxoFreezeVote),
 }
 
 impl std::fmt::Display for WalletConsensusItem {
@@ -50,6 +133,27 @@ impl std::fmt::Display for WalletConsensusItem {
             WalletConsensusItem::PegOutSignature(sig) => {
                 write!(f, "Wallet PegOut signature for Bitcoin TxId {}", sig.txid)
             }
+            WalletConsensusItem::PegOutNonces(nonces) => {
+                write!(
+                    f,
+                    "Wallet PegOut FROST nonces for Bitcoin TxId {}",
+                    nonces.txid
+                )
+            }
+            WalletConsensusItem::PegOutSignatureShares(shares) => {
+                write!(
+                    f,
+                    "Wallet PegOut FROST signature shares for Bitcoin TxId {}",
+                    shares.txid
+                )
+            }
+            WalletConsensusItem::FeeSubsidyRate(rate_bps) => {
+                write!(f, "Wallet fee subsidy rate vote {rate_bps} bps")
+            }
+            WalletConsensusItem::UtxoFreeze(vote) => {
+                let action = if vote.frozen { "freeze" } else { "unfreeze" };
+                write!(f, "Wallet vote to {action} UTXO {}", vote.outpoint)
+            }
         }
     }
 }
@@ -68,6 +172,64 @@ pub struct PegOutSignatureItem {
     pub signature: Vec<secp256k1::ecdsa::Signature>,
 }
 
+/// FROST nonce commitments of a peer for every input of a peg-out, the first
+/// round of signing it
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct PegOutNoncesItem {
+    pub txid: Txid,
+    /// The [`FrostSigningState::attempt`] the nonces are for
+    pub attempt: u32,
+    pub commitments: Vec<NonceCommitment>,
+}
+
+/// FROST signature shares of a peer for every input of a peg-out, the second
+/// round of signing it
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct PegOutSignatureSharesItem {
+    pub txid: Txid,
+    pub attempt: u32,
+    pub shares: Vec<SignatureShare>,
+}
+
+/// Progress of signing a peg-out with FROST, the same on every peer
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct FrostSigningState {
+    /// Incremented whenever signing restarts with fresh nonces
+    pub attempt: u32,
+    /// Peers that failed to sign in a previous attempt
+    pub excluded: BTreeSet<PeerId>,
+    /// Nonce commitments received in this attempt
+    pub commitments: BTreeMap<PeerId, Vec<NonceCommitment>>,
+    /// Fixed once a threshold of peers committed to nonces, only they sign
+    pub signers: Option<BTreeSet<PeerId>>,
+    /// Verified signature shares of the signers
+    pub shares: BTreeMap<PeerId, Vec<SignatureShare>>,
+    /// Epochs that ended since the signers were fixed, signers still missing
+    /// their shares after [`FROST_SIGNING_TIMEOUT_EPOCHS`] are excluded
+    pub epochs_waited: u32,
+}
+
+impl FrostSigningState {
+    /// Starts a new attempt without `failed` peers, unless that leaves too
+    /// few peers to sign
+    pub fn restart(
+        &mut self,
+        failed: impl IntoIterator<Item = PeerId>,
+        peers: usize,
+        threshold: usize,
+    ) {
+        self.attempt += 1;
+        self.excluded.extend(failed);
+        if peers - self.excluded.len() < threshold {
+            self.excluded.clear();
+        }
+        self.commitments.clear();
+        self.signers = None;
+        self.shares.clear();
+        self.epochs_waited = 0;
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize, Encodable, Decodable)]
 pub struct RoundConsensus {
     pub block_height: u32,
@@ -75,6 +237,16 @@ pub struct RoundConsensus {
     pub randomness_beacon: [u8; 32],
 }
 
+/// State of the module a joining client needs, see [`fedimint_core::join`]
+#[derive(Clone, Debug, PartialEq, Eq, Encodable, Decodable)]
+pub struct WalletJoinSnapshot {
+    /// Block height the guardians agreed on, peg-ins need to be confirmed
+    /// below it
+    pub consensus_height: u32,
+    /// Fee rate the guardians agreed on, peg-outs need to pay at least this
+    pub fee_rate: Feerate,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
 pub struct SpendableUTXO {
     pub tweak: [u8; 32],
@@ -82,6 +254,61 @@ pub struct SpendableUTXO {
     pub amount: bitcoin::Amount,
 }
 
+/// A UTXO held by the federation, as listed to guardians for reconciling the
+/// on-chain holdings
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WalletUtxo {
+    pub outpoint: bitcoin::OutPoint,
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub amount: bitcoin::Amount,
+    /// Identifies the tweak of the UTXO's script without revealing it
+    pub tweak_fingerprint: String,
+    /// Whether the guardians froze the UTXO
+    pub frozen: bool,
+    /// Number of blocks confirming the UTXO according to the guardian's
+    /// bitcoind, `None` if it couldn't tell
+    pub confirmations: Option<u64>,
+}
+
+impl WalletUtxo {
+    /// Short hash of a tweak, like the fingerprints of FROST scalars
+    pub fn tweak_fingerprint(tweak: &[u8; 32]) -> String {
+        sha256::Hash::hash(tweak).to_string()[..8].to_string()
+    }
+}
+
+/// An address the federation received funds on, deposits users send to it
+/// again are detected while syncing blocks
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct DepositTweak {
+    pub descriptor_id: PegInDescriptorId,
+    /// Whether the address received the change of a peg-out, users can't claim
+    /// deposits to these since the federation chose the tweak
+    pub is_change: bool,
+}
+
+/// Frozen UTXOs are excluded from peg-out transactions until a threshold of
+/// guardians votes to unfreeze them, e.g. while a peg-in proof they were
+/// claimed with is investigated
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize, Encodable, Decodable)]
+pub struct UtxoFreezeVote {
+    pub outpoint: bitcoin::OutPoint,
+    /// `false` votes for unfreezing the UTXO
+    pub frozen: bool,
+}
+
+/// A deposit to an address that received funds before, it was added to the
+/// UTXOs of the federation but no e-cash was issued for it yet
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize, Encodable, Decodable)]
+pub struct UnclaimedDeposit {
+    pub tweak: [u8; 32],
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub amount: bitcoin::Amount,
+    /// Height of the block the deposit was confirmed in
+    pub block_height: u32,
+    pub is_change: bool,
+}
+
 /// A peg-out tx that is ready to be broadcast with a tweak for the change UTXO
 #[derive(Clone, Debug, Encodable, Decodable)]
 pub struct PendingTransaction {
@@ -160,6 +387,82 @@ impl PegOutFees {
     }
 }
 
+/// A peg-out whose bitcoin transaction the federation hasn't seen confirmed
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PendingPegOut {
+    pub txid: Txid,
+    pub state: PendingPegOutState,
+    pub destination: Script,
+    #[serde(with = "bitcoin::util::amount::serde::as_sat")]
+    pub amount: bitcoin::Amount,
+    pub fees: PegOutFees,
+    /// Peg-out whose fees this transaction bumps
+    pub rbf: Option<Txid>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PendingPegOutState {
+    /// The guardians are still exchanging signatures
+    Signing { signatures: usize },
+    /// Fully signed and broadcast, waiting for confirmation
+    Broadcast,
+}
+
+/// Disagreement between the primary and secondary bitcoin backend of a
+/// guardian, the wallet doesn't propose a new block height while it lasts
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub enum BackendDivergence {
+    /// The block heights differ by more than the configured maximum
+    Height { primary: u64, secondary: u64 },
+    /// The backends report different blocks at the same height
+    BlockHash {
+        height: u64,
+        primary: BlockHash,
+        secondary: BlockHash,
+    },
+    /// The primary backend couldn't be queried
+    PrimaryUnavailable(String),
+    /// The secondary backend couldn't be queried
+    SecondaryUnavailable(String),
+}
+
+/// Our bitcoind couldn't be queried for the wallet's proposal
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BitcoindOutage {
+    /// Unix time in seconds of the first failed query
+    pub since: u64,
+    pub last_error: String,
+    /// Whether the last chain state bitcoind reported is still proposed, after
+    /// a grace period the wallet follows the consensus height and fee rate
+    pub proposing_stale_state: bool,
+}
+
+/// State of the wallet shown on guardian dashboards
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WalletAdminStatus {
+    /// `None` before the first round consensus
+    pub consensus_height: Option<u32>,
+    pub fee_rate: Option<Feerate>,
+    /// Chain tip of our bitcoind, `None` if it couldn't be reached
+    pub bitcoind_block_height: Option<u64>,
+    pub backend_divergence: Option<BackendDivergence>,
+    /// Set while our bitcoind can't be queried
+    #[serde(default)]
+    pub bitcoind_outage: Option<BitcoindOutage>,
+    pub pending_peg_outs: Vec<PendingPegOut>,
+    pub unclaimed_deposits: Vec<(bitcoin::OutPoint, UnclaimedDeposit)>,
+    pub frozen_utxos: Vec<(bitcoin::OutPoint, SpendableUTXO)>,
+}
+
+/// Result of fetching the block hashes up to the consensus height again
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct BlockResyncResponse {
+    pub consensus_height: u32,
+    /// Number of block hashes that were missing from the database
+    pub restored: u32,
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
 pub struct PegOut {
     pub recipient: bitcoin::Address,
@@ -288,6 +591,10 @@ pub enum WalletError {
     PegInProofError(#[from] PegInProofError),
     #[error("The peg-in was already claimed")]
     PegInAlreadyClaimed,
+    #[error("The peg-in UTXO is frozen by the guardians")]
+    PegInFrozen,
+    #[error("Peg-in amount {0} is out of bounds, the federation accepts {1}")]
+    AmountOutOfBounds(bitcoin::Amount, config::PegInPolicy),
     #[error("Peg-out fee rate {0:?} is set below consensus {1:?}")]
     PegOutFeeBelowConsensus(Feerate, Feerate),
     #[error("Not enough SpendableUTXO")]
@@ -302,6 +609,29 @@ pub enum WalletError {
     BelowMinRelayFee,
 }
 
+impl ModuleErrorCode for WalletError {
+    fn code(&self) -> i32 {
+        match self {
+            WalletError::WrongNetwork(_, _)
+            | WalletError::UnknownNetwork(_)
+            | WalletError::PegInProofError(_)
+            | WalletError::PegOutFeeBelowConsensus(_, _)
+            | WalletError::PegOutUnderDustLimit
+            | WalletError::AmountOutOfBounds(_, _)
+            | WalletError::TxWeightIncorrect(_, _)
+            | WalletError::BelowMinRelayFee => code::BAD_REQUEST,
+            WalletError::RpcError(_) => code::SERVER_ERROR,
+            // The guardian might not have synced the block yet
+            WalletError::UnknownPegInProofBlock(_) => code::NOT_FOUND,
+            WalletError::RbfTransactionIdNotFound => code::NOT_FOUND,
+            WalletError::PegInAlreadyClaimed => code::CONFLICT,
+            // Can be claimed once the guardians unfreeze the UTXO
+            WalletError::PegInFrozen => code::UNAVAILABLE,
+            WalletError::NotEnoughSpendableUTXO => code::UNAVAILABLE,
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum ProcessPegOutSigError {
     #[error("No unsigned transaction with id {0} exists")]
@@ -320,6 +650,10 @@ pub enum ProcessPegOutSigError {
     MissingOrMalformedChangeTweak,
     #[error("Error finalizing PSBT {0:?}")]
     ErrorFinalizingPsbt(Vec<miniscript::psbt::Error>),
+    #[error("Stale FROST message for attempt {0}")]
+    StaleAttempt(u32),
+    #[error("FROST signing failed: {0}")]
+    Frost(#[from] FrostError),
 }
 
 // FIXME: make tests not require Eq
@@ -20,17 +20,24 @@ pub trait Tweakable {
     ) -> Self;
 }
 
+/// The scalar that is added to the key pair of `pub_key` to tweak it with
+/// `tweak`
+///
+/// Lets a signer holding the secret key tweak it without knowing the
+/// contract.
+pub fn tweak_scalar<Ctr: Contract>(pub_key: &secp256k1::PublicKey, tweak: &Ctr) -> Scalar {
+    let mut hasher = HmacEngine::<sha256::Hash>::new(&pub_key.serialize()[..]);
+    tweak.encode(&mut hasher).expect("hashing is infallible");
+    Scalar::from_be_bytes(Hmac::from_engine(hasher).into_inner()).expect("can't fail")
+}
+
 impl Tweakable for secp256k1::PublicKey {
     fn tweak<Ctx: Verification + Signing, Ctr: Contract>(
         &self,
         tweak: &Ctr,
         secp: &Secp256k1<Ctx>,
     ) -> Self {
-        let mut hasher = HmacEngine::<sha256::Hash>::new(&self.serialize()[..]);
-        tweak.encode(&mut hasher).expect("hashing is infallible");
-        let tweak = Hmac::from_engine(hasher).into_inner();
-
-        self.add_exp_tweak(secp, &Scalar::from_be_bytes(tweak).expect("can't fail"))
+        self.add_exp_tweak(secp, &tweak_scalar(self, tweak))
             .expect("tweak is always 32 bytes, other failure modes are negligible")
     }
 }
@@ -43,13 +50,7 @@ impl Tweakable for secp256k1::SecretKey {
     ) -> Self {
         let pub_key = secp256k1::PublicKey::from_secret_key(secp, self);
 
-        let tweak = {
-            let mut hasher = HmacEngine::<sha256::Hash>::new(&pub_key.serialize()[..]);
-            tweak_in.encode(&mut hasher).expect("hashing is infallible");
-            Hmac::from_engine(hasher).into_inner()
-        };
-
-        self.add_tweak(&Scalar::from_be_bytes(tweak).expect("can't fail"))
+        self.add_tweak(&tweak_scalar(&pub_key, tweak_in))
             .expect("Tweaking priv key failed") // TODO: why could this happen?
     }
 }
@@ -3,6 +3,7 @@ use std::hash::Hash;
 
 use bitcoin::{BlockHash, OutPoint, Transaction};
 use fedimint_core::encoding::{Decodable, DecodeError, Encodable};
+use fedimint_core::module::error::{code, ModuleErrorCode};
 use fedimint_core::module::registry::ModuleDecoderRegistry;
 use fedimint_core::txoproof::TxOutProof;
 use miniscript::{Descriptor, TranslatePk};
@@ -200,6 +201,12 @@ pub enum PegInProofError {
     ScriptDoesNotMatch,
 }
 
+impl ModuleErrorCode for PegInProofError {
+    fn code(&self) -> i32 {
+        code::BAD_REQUEST
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::Cursor;
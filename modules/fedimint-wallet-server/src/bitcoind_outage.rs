@@ -0,0 +1,139 @@
+//! Proposing while our bitcoind is unreachable
+//!
+//! Every round the wallet proposes the block height and fee rate its bitcoind
+//! reports. The bitcoind clients retry failed calls until they succeed, so an
+//! unreachable bitcoind stalled our proposals and a call failing during
+//! shutdown crashed the guardian.
+//!
+//! Instead the wallet waits a bounded time for bitcoind during a proposal.
+//! If it doesn't answer, the last chain state it reported is proposed again
+//! as long as it is younger than [`STALE_CHAIN_STATE_GRACE_PERIOD`], which
+//! rides out restarts of bitcoind. Afterwards the wallet follows the
+//! consensus height and fee rate until bitcoind answers again. The outage is
+//! shown in the admin status the whole time.
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use fedimint_core::Feerate;
+use fedimint_wallet_common::BitcoindOutage;
+use tracing::{error, info};
+
+/// How long the last chain state reported by bitcoind is proposed again
+/// while it is unreachable
+pub const STALE_CHAIN_STATE_GRACE_PERIOD: Duration = Duration::from_secs(30 * 60);
+
+/// What our bitcoind reported when it was last queried for a proposal
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ChainState {
+    pub block_height: u64,
+    pub fee_rate: Feerate,
+}
+
+/// Remembers the last chain state bitcoind reported and tracks outages
+#[derive(Debug, Default)]
+pub struct ChainStateCache(Mutex<ChainStateCacheInner>);
+
+#[derive(Debug, Default)]
+struct ChainStateCacheInner {
+    last: Option<(ChainState, SystemTime)>,
+    outage: Option<BitcoindOutage>,
+}
+
+impl ChainStateCache {
+    /// Records a chain state bitcoind reported, ending an outage
+    pub fn update(&self, state: ChainState, now: SystemTime) {
+        let mut inner = self.0.lock().expect("lock poisoned");
+        if let Some(outage) = inner.outage.take() {
+            info!(
+                "Bitcoind is reachable again after failing since {}",
+                outage.since
+            );
+        }
+        inner.last = Some((state, now));
+    }
+
+    /// Records that bitcoind couldn't be queried, returns the last chain state
+    /// if it may still be proposed
+    pub fn failed(&self, error: String, now: SystemTime) -> Option<ChainState> {
+        let mut guard = self.0.lock().expect("lock poisoned");
+        let inner = &mut *guard;
+        let last = inner
+            .last
+            .filter(|(_, at)| {
+                now.duration_since(*at)
+                    .map_or(true, |age| age <= STALE_CHAIN_STATE_GRACE_PERIOD)
+            })
+            .map(|(state, _)| state);
+
+        match &mut inner.outage {
+            Some(outage) => {
+                if outage.proposing_stale_state && last.is_none() {
+                    error!(
+                        "Bitcoind is still unreachable, following the consensus height and fee rate: {error}"
+                    );
+                }
+                outage.last_error = error;
+                outage.proposing_stale_state = last.is_some();
+            }
+            None => {
+                error!(
+                    stale = last.is_some(),
+                    "Bitcoind is unreachable, proposing without it: {error}"
+                );
+                inner.outage = Some(BitcoindOutage {
+                    since: now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+                    last_error: error,
+                    proposing_stale_state: last.is_some(),
+                });
+            }
+        }
+        last
+    }
+
+    pub fn outage(&self) -> Option<BitcoindOutage> {
+        self.0.lock().expect("lock poisoned").outage.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn proposes_stale_state_during_grace_period() {
+        let cache = ChainStateCache::default();
+        let start = UNIX_EPOCH + Duration::from_secs(1_000);
+        let state = ChainState {
+            block_height: 100,
+            fee_rate: Feerate { sats_per_kvb: 1000 },
+        };
+
+        // Nothing to fall back to before bitcoind answered once
+        assert_eq!(cache.failed("down".to_string(), start), None);
+        cache.update(state, start);
+        assert_eq!(cache.outage(), None);
+
+        let later = start + STALE_CHAIN_STATE_GRACE_PERIOD;
+        assert_eq!(cache.failed("down".to_string(), later), Some(state));
+        let outage = cache.outage().unwrap();
+        assert_eq!(
+            outage.since,
+            1_000 + STALE_CHAIN_STATE_GRACE_PERIOD.as_secs()
+        );
+        assert!(outage.proposing_stale_state);
+
+        let expired = later + Duration::from_secs(1);
+        assert_eq!(cache.failed("still down".to_string(), expired), None);
+        let outage = cache.outage().unwrap();
+        assert_eq!(
+            outage.since,
+            1_000 + STALE_CHAIN_STATE_GRACE_PERIOD.as_secs()
+        );
+        assert_eq!(outage.last_error, "still down");
+        assert!(!outage.proposing_stale_state);
+
+        cache.update(state, expired);
+        assert_eq!(cache.outage(), None);
+    }
+}
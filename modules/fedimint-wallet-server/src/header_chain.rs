@@ -0,0 +1,178 @@
+//! Validation of the bitcoin header chain served by bitcoind
+//!
+//! The wallet only accepts peg-in proofs for blocks it knows, so a
+//! compromised bitcoind could make a guardian accept proofs for a fake chain.
+//! To prevent this every header we sync has to connect to the previous one,
+//! carry enough proof of work and follow the difficulty adjustment rules of
+//! the network. The first header synced is trusted as an anchor.
+
+use bitcoin::consensus::params::Params;
+use bitcoin::util::uint::Uint256;
+use bitcoin::{BlockHash, BlockHeader};
+use thiserror::Error;
+
+#[derive(Debug, Error, Eq, PartialEq)]
+pub enum HeaderChainError {
+    #[error("Header doesn't connect to the previous block {0}")]
+    Disconnected(BlockHash),
+    #[error("Header target {0:#010x} is easier than the network allows")]
+    TargetAboveLimit(u32),
+    #[error("Header target {actual:#010x} doesn't match the expected {expected:#010x}")]
+    UnexpectedTarget { expected: u32, actual: u32 },
+    #[error("Header hash doesn't meet its target")]
+    InsufficientWork,
+}
+
+/// Checks that `header` extends `prev` and satisfies its proof of work
+///
+/// `expected_bits` is the difficulty the header must have, it is `None` if we
+/// can't compute it because the headers it depends on weren't synced.
+pub fn validate_header(
+    params: &Params,
+    header: &BlockHeader,
+    prev: &BlockHeader,
+    expected_bits: Option<u32>,
+) -> Result<BlockHash, HeaderChainError> {
+    if header.prev_blockhash != prev.block_hash() {
+        return Err(HeaderChainError::Disconnected(prev.block_hash()));
+    }
+
+    if header.target() > params.pow_limit {
+        return Err(HeaderChainError::TargetAboveLimit(header.bits));
+    }
+
+    if let Some(expected) = expected_bits {
+        if header.bits != expected {
+            return Err(HeaderChainError::UnexpectedTarget {
+                expected,
+                actual: header.bits,
+            });
+        }
+    }
+
+    header
+        .validate_pow(&header.target())
+        .map_err(|_| HeaderChainError::InsufficientWork)
+}
+
+/// Whether the difficulty gets adjusted at `height`
+pub fn is_retarget_height(params: &Params, height: u32) -> bool {
+    u64::from(height) % params.difficulty_adjustment_interval() == 0
+}
+
+/// Computes the difficulty of the first block of a new period from the first
+/// and last header of the previous period
+pub fn retarget(params: &Params, period_start: &BlockHeader, period_end: &BlockHeader) -> u32 {
+    let timespan = u64::from(period_end.time.saturating_sub(period_start.time)).clamp(
+        params.pow_target_timespan / 4,
+        params.pow_target_timespan * 4,
+    );
+
+    let target = period_end.target().mul_u32(timespan as u32)
+        / Uint256::from_u64(params.pow_target_timespan).expect("fits");
+
+    BlockHeader::compact_target_from_u256(&target.min(params.pow_limit))
+}
+
+/// Compact representation of the easiest target the network allows
+pub fn min_difficulty_bits(params: &Params) -> u32 {
+    BlockHeader::compact_target_from_u256(&params.pow_limit)
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::blockdata::constants::genesis_block;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Network, TxMerkleNode};
+
+    use super::*;
+
+    fn mine(prev: &BlockHeader, bits: u32) -> BlockHeader {
+        let mut header = BlockHeader {
+            version: 1,
+            prev_blockhash: prev.block_hash(),
+            merkle_root: TxMerkleNode::all_zeros(),
+            time: prev.time + 600,
+            bits,
+            nonce: 0,
+        };
+        while header.validate_pow(&header.target()).is_err() {
+            header.nonce += 1;
+        }
+        header
+    }
+
+    #[test]
+    fn validates_regtest_chain() {
+        let params = Params::new(Network::Regtest);
+        let genesis = genesis_block(Network::Regtest).header;
+        let bits = min_difficulty_bits(&params);
+
+        let first = mine(&genesis, bits);
+        let second = mine(&first, bits);
+        assert_eq!(
+            validate_header(&params, &first, &genesis, Some(genesis.bits)),
+            Ok(first.block_hash())
+        );
+        assert_eq!(
+            validate_header(&params, &second, &first, Some(first.bits)),
+            Ok(second.block_hash())
+        );
+
+        assert_eq!(
+            validate_header(&params, &second, &genesis, Some(genesis.bits)),
+            Err(HeaderChainError::Disconnected(genesis.block_hash()))
+        );
+
+        let mut forged = second;
+        while forged.validate_pow(&forged.target()).is_ok() {
+            forged.nonce += 1;
+        }
+        assert_eq!(
+            validate_header(&params, &forged, &first, Some(first.bits)),
+            Err(HeaderChainError::InsufficientWork)
+        );
+    }
+
+    #[test]
+    fn rejects_targets_easier_than_the_network_allows() {
+        let params = Params::new(Network::Bitcoin);
+        let genesis = genesis_block(Network::Bitcoin).header;
+        let header = mine(
+            &genesis,
+            min_difficulty_bits(&Params::new(Network::Regtest)),
+        );
+
+        assert_eq!(
+            validate_header(&params, &header, &genesis, None),
+            Err(HeaderChainError::TargetAboveLimit(header.bits))
+        );
+    }
+
+    #[test]
+    fn retarget_follows_timespan() {
+        let params = Params::new(Network::Bitcoin);
+        let start = genesis_block(Network::Bitcoin).header;
+        let mut end = start;
+        let bits = BlockHeader::compact_target_from_u256(&(params.pow_limit >> 4));
+        end.bits = bits;
+
+        end.time = start.time + params.pow_target_timespan as u32;
+        assert_eq!(retarget(&params, &start, &end), bits);
+
+        // Blocks came twice as fast, so the target halves
+        end.time = start.time + params.pow_target_timespan as u32 / 2;
+        assert_eq!(
+            retarget(&params, &start, &end),
+            BlockHeader::compact_target_from_u256(&(params.pow_limit >> 5))
+        );
+
+        // The target never exceeds the limit
+        end.bits = BlockHeader::compact_target_from_u256(&(params.pow_limit >> 1));
+        end.time = start.time + params.pow_target_timespan as u32 * 100;
+        assert_eq!(
+            retarget(&params, &start, &end),
+            min_difficulty_bits(&params)
+        );
+    }
+}
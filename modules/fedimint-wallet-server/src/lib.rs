@@ -1,64 +1,95 @@
 use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::convert::{Infallible, TryInto};
+use std::future::Future;
 use std::ops::Sub;
 #[cfg(not(target_family = "wasm"))]
 use std::time::Duration;
 
 use anyhow::{bail, format_err};
+use bitcoin::consensus::params::Params;
 use bitcoin::hashes::{sha256, Hash as BitcoinHash, HashEngine, Hmac, HmacEngine};
 use bitcoin::policy::DEFAULT_MIN_RELAY_TX_FEE;
 use bitcoin::secp256k1::{All, Secp256k1, Verification};
 use bitcoin::util::psbt::{Input, PartiallySignedTransaction};
-use bitcoin::util::sighash::SighashCache;
+use bitcoin::util::sighash::{Prevouts, SighashCache};
 use bitcoin::{
-    Address, BlockHash, EcdsaSig, EcdsaSighashType, Network, PackedLockTime, Script, Sequence,
-    Transaction, TxIn, TxOut, Txid,
+    Address, BlockHash, BlockHeader, EcdsaSig, EcdsaSighashType, Network, PackedLockTime,
+    SchnorrSighashType, Script, Sequence, Transaction, TxIn, TxOut, Txid, Witness,
 };
 use common::config::WalletConfigConsensus;
 use common::db::DbKeyPrefix;
 use common::{
-    proprietary_tweak_key, IterUnzipWalletConsensusItem, PegOutFees, PegOutSignatureItem,
-    PendingTransaction, ProcessPegOutSigError, RoundConsensus, RoundConsensusItem, SpendableUTXO,
-    UnsignedTransaction, UnzipWalletConsensusItem, WalletCommonGen, WalletConsensusItem,
-    WalletError, WalletInput, WalletModuleTypes, WalletOutput, WalletOutputOutcome,
-    CONFIRMATION_TARGET,
+    proprietary_tweak_key, BackendDivergence, BitcoindOutage, BlockResyncResponse, DepositTweak,
+    DescriptorMigrationStatus, FeeSubsidyPool, FeeSubsidyStatus, FrostSigningState,
+    IterUnzipWalletConsensusItem, PegInDescriptor, PegInDescriptorId, PegOut, PegOutFees,
+    PegOutNoncesItem, PegOutSignatureItem, PegOutSignatureSharesItem, PendingPegOut,
+    PendingPegOutState, PendingTransaction, ProcessPegOutSigError, RoundConsensus,
+    RoundConsensusItem, SpendableUTXO, UnclaimedDeposit, UnsignedTransaction,
+    UnzipWalletConsensusItem, UtxoFreezeVote, WalletAdminStatus, WalletCommonGen,
+    WalletConsensusItem, WalletError, WalletInput, WalletJoinSnapshot, WalletModuleTypes,
+    WalletOutput, WalletOutputOutcome, WalletUtxo, CONFIRMATION_TARGET,
+    FROST_SIGNING_TIMEOUT_EPOCHS, PEG_OUT_SIGNATURE_TIMEOUT_EPOCHS,
 };
 use fedimint_bitcoind::{create_bitcoind, DynBitcoindRpc};
 use fedimint_core::config::{
     ClientModuleConfig, ConfigGenModuleParams, DkgResult, ServerModuleConfig,
     ServerModuleConsensusConfig, TypedServerModuleConfig, TypedServerModuleConsensusConfig,
 };
+use fedimint_core::core::ModuleInstanceId;
 use fedimint_core::db::{
     Database, DatabaseTransaction, DatabaseVersion, ModuleDatabaseTransaction,
 };
 use fedimint_core::encoding::{Decodable, Encodable};
+use fedimint_core::module::__reexports::serde_json;
 use fedimint_core::module::audit::Audit;
+use fedimint_core::module::interconnect::{
+    interconnect_handler, DynModuleInterconnect, InterconnectHandler,
+};
 use fedimint_core::module::{
-    api_endpoint, ApiEndpoint, ConsensusProposal, CoreConsensusVersion, ExtendsCommonModuleGen,
-    InputMeta, IntoModuleError, ModuleConsensusVersion, ModuleError, PeerHandle, ServerModuleGen,
-    SupportedModuleApiVersions, TransactionItemAmount,
+    api_endpoint, ApiEndpoint, ApiError, ConsensusProposal, CoreConsensusVersion,
+    ExtendsCommonModuleGen, InputMeta, IntoCodedModuleError, ModuleConsensusVersion, ModuleError,
+    PeerHandle, ServerModuleGen, SupportedModuleApiVersions, TransactionItemAmount,
 };
 use fedimint_core::server::DynServerModule;
+use fedimint_core::stats::ItemActivity;
 #[cfg(not(target_family = "wasm"))]
 use fedimint_core::task::sleep;
-use fedimint_core::task::{TaskGroup, TaskHandle};
+use fedimint_core::task::{timeout, TaskGroup, TaskHandle};
 use fedimint_core::{
     apply, async_trait_maybe_send, push_db_key_items, push_db_pair_items, Feerate, NumPeers,
     OutPoint, PeerId, ServerModule,
 };
 use fedimint_server::config::distributedgen::PeerHandleOps;
+use fedimint_server::signer::{guardian_signer, DynGuardianSigner, IGuardianSigner, LocalSigner};
 pub use fedimint_wallet_common as common;
-use fedimint_wallet_common::config::{WalletClientConfig, WalletConfig, WalletGenParams};
+use fedimint_wallet_common::config::{
+    ChangeSplitConsensus, PegInPolicy, WalletClientConfig, WalletConfig, WalletGenParams,
+    FEE_SUBSIDY_RATE_SCALE,
+};
 use fedimint_wallet_common::db::{
-    BlockHashKey, BlockHashKeyPrefix, PegOutBitcoinTransaction, PegOutBitcoinTransactionPrefix,
-    PegOutTxSignatureCI, PegOutTxSignatureCIPrefix, PendingTransactionKey,
-    PendingTransactionPrefixKey, RoundConsensusKey, UTXOKey, UTXOPrefixKey, UnsignedTransactionKey,
-    UnsignedTransactionPrefixKey,
+    BlockHashKey, BlockHashKeyPrefix, BlockHeaderKey, BlockHeaderKeyPrefix, DepositTweakKey,
+    DepositTweakPrefix, FeeSubsidyPoolKey, FeeSubsidyPoolPrefix, FeeSubsidyRateCI,
+    FeeSubsidyRateKey, FeeSubsidyRateVoteKey, FeeSubsidyRateVotePrefix, FrostNonceAttemptKey,
+    FrostNonceAttemptPrefix, FrostSigningStateKey, FrostSigningStatePrefix, FrozenUtxoKey,
+    FrozenUtxoPrefix, PegOutBitcoinTransaction, PegOutBitcoinTransactionPrefix, PegOutNoncesCI,
+    PegOutNoncesCIPrefix, PegOutSignatureEpochsKey, PegOutSignatureEpochsPrefix,
+    PegOutSignatureSharesCI, PegOutSignatureSharesCIPrefix, PegOutTxSignatureCI,
+    PegOutTxSignatureCIPrefix, PendingTransactionKey, PendingTransactionPrefixKey,
+    RoundConsensusKey, UTXODescriptorKey, UTXODescriptorPrefixKey, UTXOKey, UTXOPrefixKey,
+    UnclaimedDepositKey, UnclaimedDepositPrefix, UnsignedTransactionKey,
+    UnsignedTransactionPrefixKey, UtxoFreezeCIKey, UtxoFreezeCIPrefix, UtxoFreezeVoteKey,
+    UtxoFreezeVotePrefix,
 };
+use fedimint_wallet_common::frost::{
+    trusted_dealer_keygen, FrostDkg, FrostError, FrostPublicKeys, NonceCommitment, SignatureShare,
+    SigningSession,
+};
+use fedimint_wallet_common::interconnect::{ConsensusBlockHeightMethod, VerifyBlockKnownMethod};
 use fedimint_wallet_common::keys::CompressedPublicKey;
-use fedimint_wallet_common::tweakable::Tweakable;
+use fedimint_wallet_common::tweakable::{tweak_scalar, Tweakable};
+use fedimint_wallet_common::txoproof::PegInProofError;
 use fedimint_wallet_common::Rbf;
-use futures::{stream, StreamExt};
+use futures::StreamExt;
 use miniscript::psbt::PsbtExt;
 use miniscript::{Descriptor, TranslatePk};
 use rand::rngs::OsRng;
@@ -68,6 +99,14 @@ use serde::{Deserialize, Serialize};
 use strum::IntoEnumIterator;
 use tracing::{debug, error, info, instrument, trace, warn};
 
+use crate::bitcoind_outage::{ChainState, ChainStateCache};
+use crate::header_chain::HeaderChainError;
+use crate::peg_out_verifier::{verify_peg_out_signature, PartialSignatures, PegOutVerifier};
+
+mod bitcoind_outage;
+mod header_chain;
+mod peg_out_verifier;
+
 #[derive(Debug, Clone)]
 pub struct WalletGen;
 
@@ -91,10 +130,17 @@ impl ServerModuleGen for WalletGen {
     async fn init(
         &self,
         cfg: ServerModuleConfig,
+        module_instance_id: ModuleInstanceId,
+        our_id: PeerId,
         db: Database,
         task_group: &mut TaskGroup,
+        _interconnect: DynModuleInterconnect,
     ) -> anyhow::Result<DynServerModule> {
-        Ok(Wallet::new(cfg.to_typed()?, db, task_group).await?.into())
+        Ok(
+            Wallet::new(cfg.to_typed()?, module_instance_id, our_id, db, task_group)
+                .await?
+                .into(),
+        )
     }
 
     fn trusted_dealer_gen(
@@ -109,6 +155,8 @@ impl ServerModuleGen for WalletGen {
             .iter()
             .map(|&id| (id, secp.generate_keypair(&mut OsRng)))
             .collect::<Vec<_>>();
+        let (frost_keys, frost_key_shares) =
+            trusted_dealer_keygen(peers, &mut OsRng).expect("dealer key generation can't fail");
 
         let wallet_cfg: BTreeMap<PeerId, WalletConfig> = btc_pegin_keys
             .iter()
@@ -123,7 +171,9 @@ impl ServerModuleGen for WalletGen {
                     params.consensus.network,
                     params.consensus.finality_delay,
                     params.local.bitcoin_rpc.clone(),
-                );
+                    params.local.secondary_bitcoin_rpc.clone(),
+                )
+                .with_frost_keys(frost_keys.clone(), frost_key_shares[id]);
                 (*id, cfg)
             })
             .collect();
@@ -150,6 +200,37 @@ impl ServerModuleGen for WalletGen {
             .map(|(k, key)| (k, CompressedPublicKey { key }))
             .collect();
 
+        // Every peer broadcasts commitments to its polynomial, once they are all
+        // verified every peer gets its share of every polynomial
+        let (dkg, commitment) = FrostDkg::new(peers.our_id, peers.peer_ids(), &mut OsRng)
+            .map_err(anyhow::Error::from)?;
+        let commitments = peers
+            .exchange_encodable(
+                "wallet-frost-commitments".to_string(),
+                peers
+                    .peer_ids()
+                    .iter()
+                    .map(|peer| (*peer, commitment.clone()))
+                    .collect(),
+            )
+            .await?;
+        let (dkg, shares) = dkg.share(&commitments).map_err(anyhow::Error::from)?;
+        // We don't send a share to ourselves
+        let shares = peers
+            .exchange_encodable(
+                "wallet-frost-shares".to_string(),
+                shares
+                    .into_iter()
+                    .map(|(peer, share)| (peer, Some(share)))
+                    .chain([(peers.our_id, None)])
+                    .collect(),
+            )
+            .await?
+            .into_iter()
+            .filter_map(|(peer, share)| Some((peer, share?)))
+            .collect();
+        let (frost_keys, frost_key_share) = dkg.finish(&shares).map_err(anyhow::Error::from)?;
+
         let wallet_cfg = WalletConfig::new(
             peer_peg_in_keys,
             sk,
@@ -157,23 +238,71 @@ impl ServerModuleGen for WalletGen {
             params.consensus.network,
             params.consensus.finality_delay,
             params.local.bitcoin_rpc.clone(),
-        );
+            params.local.secondary_bitcoin_rpc.clone(),
+        )
+        .with_frost_keys(frost_keys, frost_key_share);
 
         Ok(wallet_cfg.to_erased())
     }
 
     fn validate_config(&self, identity: &PeerId, config: ServerModuleConfig) -> anyhow::Result<()> {
         let config = config.to_typed::<WalletConfig>()?;
-        let pubkey = secp256k1::PublicKey::from_secret_key_global(&config.private.peg_in_key);
-
-        if config
+        let our_pubkey = config
             .consensus
             .peer_peg_in_keys
             .get(identity)
-            .ok_or_else(|| format_err!("Secret key doesn't match any public key"))?
-            != &CompressedPublicKey::new(pubkey)
+            .ok_or_else(|| format_err!("Own peg-in key not found among pub keys"))?;
+
+        // Without a secret key we sign through an external guardian signer
+        if let Some(peg_in_key) = &config.private.peg_in_key {
+            let pubkey = secp256k1::PublicKey::from_secret_key_global(peg_in_key);
+            if our_pubkey != &CompressedPublicKey::new(pubkey) {
+                bail!(" Bitcoin wallet private key doesn't match multisig pubkey");
+            }
+        }
+
+        match (
+            &config.consensus.peg_in_descriptor,
+            &config.consensus.frost_keys,
+        ) {
+            (Descriptor::Wsh(_), _) => {}
+            (Descriptor::Tr(tr), Some(frost_keys)) => {
+                if tr.internal_key().key != frost_keys.group_key || tr.taptree().is_some() {
+                    bail!("The taproot peg-in descriptor has to be a key spend of the FROST group key");
+                }
+                if let Some(key_share) = &config.private.frost_key_share {
+                    if !frost_keys.verify_key_share(secp256k1::SECP256K1, *identity, key_share) {
+                        bail!("FROST key share doesn't match our verification share");
+                    }
+                }
+                // A transaction is either signed with FROST or with the multisig keys
+                if !config.consensus.legacy_peg_in_descriptors.is_empty() {
+                    bail!("Legacy descriptors can't be swept into a FROST descriptor");
+                }
+            }
+            (descriptor, _) => {
+                bail!("Unsupported peg-in descriptor {descriptor}, only wsh and FROST taproot are supported")
+            }
+        }
+        // Legacy UTXOs are signed with the multisig keys, as segwit v0 inputs
+        for descriptor in &config.consensus.legacy_peg_in_descriptors {
+            if !matches!(descriptor, Descriptor::Wsh(_)) {
+                bail!("Unsupported legacy peg-in descriptor {descriptor}, only wsh is supported");
+            }
+        }
+        if config
+            .consensus
+            .legacy_peg_in_descriptors
+            .contains(&config.consensus.peg_in_descriptor)
+        {
+            bail!("The current peg-in descriptor can't also be a legacy one");
+        }
+        let peg_in_policy = &config.consensus.peg_in_policy;
+        if peg_in_policy
+            .max_amount
+            .map_or(false, |max_amount| max_amount < peg_in_policy.min_amount)
         {
-            bail!(" Bitcoin wallet private key doesn't match multisig pubkey");
+            bail!("The maximum peg-in amount is below the minimum");
         }
 
         Ok(())
@@ -211,6 +340,16 @@ impl ServerModuleGen for WalletGen {
                 DbKeyPrefix::BlockHash => {
                     push_db_key_items!(dbtx, BlockHashKeyPrefix, BlockHashKey, wallet, "Blocks");
                 }
+                DbKeyPrefix::BlockHeader => {
+                    push_db_pair_items!(
+                        dbtx,
+                        BlockHeaderKeyPrefix,
+                        BlockHeaderKey,
+                        BlockHeader,
+                        wallet,
+                        "Block Headers"
+                    );
+                }
                 DbKeyPrefix::PegOutBitcoinOutPoint => {
                     push_db_pair_items!(
                         dbtx,
@@ -267,6 +406,141 @@ impl ServerModuleGen for WalletGen {
                         "UTXOs"
                     );
                 }
+                DbKeyPrefix::UtxoDescriptor => {
+                    push_db_pair_items!(
+                        dbtx,
+                        UTXODescriptorPrefixKey,
+                        UTXODescriptorKey,
+                        PegInDescriptorId,
+                        wallet,
+                        "UTXO Descriptors"
+                    );
+                }
+                DbKeyPrefix::FrostSigningState => {
+                    push_db_pair_items!(
+                        dbtx,
+                        FrostSigningStatePrefix,
+                        FrostSigningStateKey,
+                        FrostSigningState,
+                        wallet,
+                        "FROST Signing States"
+                    );
+                }
+                DbKeyPrefix::PegOutNoncesCi => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PegOutNoncesCIPrefix,
+                        PegOutNoncesCI,
+                        PegOutNoncesItem,
+                        wallet,
+                        "Peg Out FROST Nonces"
+                    );
+                }
+                DbKeyPrefix::PegOutSignatureSharesCi => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PegOutSignatureSharesCIPrefix,
+                        PegOutSignatureSharesCI,
+                        PegOutSignatureSharesItem,
+                        wallet,
+                        "Peg Out FROST Signature Shares"
+                    );
+                }
+                DbKeyPrefix::FrostNonceAttempt => {
+                    push_db_pair_items!(
+                        dbtx,
+                        FrostNonceAttemptPrefix,
+                        FrostNonceAttemptKey,
+                        u32,
+                        wallet,
+                        "FROST Nonce Attempts"
+                    );
+                }
+                DbKeyPrefix::FeeSubsidyPool => {
+                    if let Some(pool) = dbtx.get_value(&FeeSubsidyPoolKey).await {
+                        wallet.insert("Fee Subsidy Pool".to_string(), Box::new(pool));
+                    }
+                }
+                DbKeyPrefix::FeeSubsidyRate => {
+                    if let Some(rate_bps) = dbtx.get_value(&FeeSubsidyRateKey).await {
+                        wallet.insert("Fee Subsidy Rate".to_string(), Box::new(rate_bps));
+                    }
+                }
+                DbKeyPrefix::FeeSubsidyRateVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        FeeSubsidyRateVotePrefix,
+                        FeeSubsidyRateVoteKey,
+                        u16,
+                        wallet,
+                        "Fee Subsidy Rate Votes"
+                    );
+                }
+                DbKeyPrefix::FeeSubsidyRateCi => {
+                    if let Some(rate_bps) = dbtx.get_value(&FeeSubsidyRateCI).await {
+                        wallet.insert("Fee Subsidy Rate Proposal".to_string(), Box::new(rate_bps));
+                    }
+                }
+                DbKeyPrefix::DepositTweak => {
+                    push_db_pair_items!(
+                        dbtx,
+                        DepositTweakPrefix,
+                        DepositTweakKey,
+                        DepositTweak,
+                        wallet,
+                        "Deposit Tweaks"
+                    );
+                }
+                DbKeyPrefix::UnclaimedDeposit => {
+                    push_db_pair_items!(
+                        dbtx,
+                        UnclaimedDepositPrefix,
+                        UnclaimedDepositKey,
+                        UnclaimedDeposit,
+                        wallet,
+                        "Unclaimed Deposits"
+                    );
+                }
+                DbKeyPrefix::FrozenUtxo => {
+                    push_db_pair_items!(
+                        dbtx,
+                        FrozenUtxoPrefix,
+                        FrozenUtxoKey,
+                        SpendableUTXO,
+                        wallet,
+                        "Frozen UTXOs"
+                    );
+                }
+                DbKeyPrefix::UtxoFreezeVote => {
+                    push_db_pair_items!(
+                        dbtx,
+                        UtxoFreezeVotePrefix,
+                        UtxoFreezeVoteKey,
+                        bool,
+                        wallet,
+                        "UTXO Freeze Votes"
+                    );
+                }
+                DbKeyPrefix::UtxoFreezeCi => {
+                    push_db_pair_items!(
+                        dbtx,
+                        UtxoFreezeCIPrefix,
+                        UtxoFreezeCIKey,
+                        bool,
+                        wallet,
+                        "UTXO Freeze Proposals"
+                    );
+                }
+                DbKeyPrefix::PegOutSignatureEpochs => {
+                    push_db_pair_items!(
+                        dbtx,
+                        PegOutSignatureEpochsPrefix,
+                        PegOutSignatureEpochsKey,
+                        u32,
+                        wallet,
+                        "Peg Out Signature Epochs"
+                    );
+                }
             }
         }
 
@@ -281,8 +555,21 @@ impl ServerModule for Wallet {
     type VerificationCache = WalletVerificationCache;
 
     async fn await_consensus_proposal(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) {
+        let mut blocks = self.btc_rpc.subscribe_blocks().await;
         while !self.consensus_proposal(dbtx).await.forces_new_epoch() {
-            sleep(Duration::from_millis(1000)).await;
+            match &mut blocks {
+                // Votes of the guardians aren't notified, they are still picked up by the
+                // slower poll
+                Some(events) => {
+                    if let Ok(None) =
+                        timeout(PROPOSAL_POLL_WITH_BLOCK_EVENTS, events.next_block()).await
+                    {
+                        warn!("Bitcoin backend stopped sending block notifications, polling");
+                        blocks = None;
+                    }
+                }
+                None => sleep(PROPOSAL_POLL_INTERVAL).await,
+            }
         }
     }
 
@@ -290,37 +577,64 @@ impl ServerModule for Wallet {
         &'a self,
         dbtx: &mut ModuleDatabaseTransaction<'_>,
     ) -> ConsensusProposal<WalletConsensusItem> {
-        let our_target_height = self.target_height().await;
-
         // In case the wallet just got created the height is not committed to the DB yet
         // but will be set to 0 first, so we can assume that here.
         let last_consensus_height = self.consensus_height(dbtx).await.unwrap_or(0);
 
-        let proposed_height = if our_target_height >= last_consensus_height {
-            our_target_height
-        } else {
-            warn!(
-                "The block height shrunk, new proposal would be {}, but we are sticking to the last consensus height {}.",
-                our_target_height,
-                last_consensus_height
-            );
-            last_consensus_height
+        let (proposed_height, fee_rate) = match self.query_chain_state().await {
+            Ok(chain_state) => {
+                self.chain_state
+                    .update(chain_state, fedimint_core::time::now());
+                let our_target_height = self.target_height(chain_state);
+                let divergence = self
+                    .cross_check_backends(chain_state.block_height, our_target_height)
+                    .await
+                    .err();
+                let proposed_height = if let Some(divergence) = &divergence {
+                    warn!(
+                        ?divergence,
+                        "Bitcoin backends diverge, sticking to the last consensus height {}.",
+                        last_consensus_height
+                    );
+                    last_consensus_height
+                } else if our_target_height >= last_consensus_height {
+                    our_target_height
+                } else {
+                    warn!(
+                        "The block height shrunk, new proposal would be {}, but we are sticking to the last consensus height {}.",
+                        our_target_height,
+                        last_consensus_height
+                    );
+                    last_consensus_height
+                };
+                *self.backend_divergence.lock().expect("lock poisoned") = divergence;
+                (proposed_height, chain_state.fee_rate)
+            }
+            Err(e) => match self
+                .chain_state
+                .failed(e.to_string(), fedimint_core::time::now())
+            {
+                Some(stale) => (
+                    self.target_height(stale).max(last_consensus_height),
+                    stale.fee_rate,
+                ),
+                // Without a recent chain state we don't push consensus anywhere new
+                None => (
+                    last_consensus_height,
+                    self.current_round_consensus(dbtx)
+                        .await
+                        .map_or(self.cfg.consensus.default_fee, |rc| rc.fee_rate),
+                ),
+            },
         };
 
-        let fee_rate = self
-            .btc_rpc
-            .get_fee_rate(CONFIRMATION_TARGET)
-            .await
-            .expect("bitcoind rpc failed")
-            .unwrap_or(self.cfg.consensus.default_fee);
-
         let round_ci = WalletConsensusItem::RoundConsensus(RoundConsensusItem {
             block_height: proposed_height,
             fee_rate,
             randomness: OsRng.gen(),
         });
 
-        let items = dbtx
+        let mut items = dbtx
             .find_by_prefix(&PegOutTxSignatureCIPrefix)
             .await
             .map(|(key, val)| {
@@ -329,12 +643,41 @@ impl ServerModule for Wallet {
                     signature: val,
                 })
             })
-            .chain(stream::once(async { round_ci }))
             .collect::<Vec<WalletConsensusItem>>()
             .await;
+        items.extend(
+            dbtx.find_by_prefix(&PegOutNoncesCIPrefix)
+                .await
+                .map(|(_, nonces)| WalletConsensusItem::PegOutNonces(nonces))
+                .collect::<Vec<_>>()
+                .await,
+        );
+        items.extend(
+            dbtx.find_by_prefix(&PegOutSignatureSharesCIPrefix)
+                .await
+                .map(|(_, shares)| WalletConsensusItem::PegOutSignatureShares(shares))
+                .collect::<Vec<_>>()
+                .await,
+        );
+        if let Some(rate_bps) = dbtx.get_value(&FeeSubsidyRateCI).await {
+            items.push(WalletConsensusItem::FeeSubsidyRate(rate_bps));
+        }
+        items.extend(
+            dbtx.find_by_prefix(&UtxoFreezeCIPrefix)
+                .await
+                .map(|(key, frozen)| {
+                    WalletConsensusItem::UtxoFreeze(UtxoFreezeVote {
+                        outpoint: key.0,
+                        frozen,
+                    })
+                })
+                .collect::<Vec<_>>()
+                .await,
+        );
+        items.push(round_ci);
 
-        // We force new epochs only if height changed, or we have peg-outs (more than
-        // just round_ci item)
+        // We force new epochs only if height changed, or we have peg-outs or a vote
+        // (more than just round_ci item)
         if last_consensus_height < proposed_height || 1 < items.len() {
             ConsensusProposal::Trigger(items)
         } else {
@@ -342,6 +685,23 @@ impl ServerModule for Wallet {
         }
     }
 
+    fn encrypt_consensus_item(&self, consensus_item: &WalletConsensusItem) -> bool {
+        // Hide which peg-out is being signed, so it can't be censored based on its amount
+        // or destination
+        matches!(
+            consensus_item,
+            WalletConsensusItem::PegOutSignature(_)
+                | WalletConsensusItem::PegOutNonces(_)
+                | WalletConsensusItem::PegOutSignatureShares(_)
+        )
+    }
+
+    fn journal_consensus_item(&self, consensus_item: &WalletConsensusItem) -> bool {
+        // Our peg-out signatures must not get lost if we restart before they were
+        // ordered, duplicates are ignored when saving them
+        matches!(consensus_item, WalletConsensusItem::PegOutSignature(_))
+    }
+
     async fn begin_consensus_epoch<'a, 'b>(
         &'a self,
         dbtx: &mut ModuleDatabaseTransaction<'b>,
@@ -355,11 +715,26 @@ impl ServerModule for Wallet {
         // need to be available at once.
         let UnzipWalletConsensusItem {
             peg_out_signature: peg_out_signatures,
+            peg_out_nonces,
+            peg_out_signature_shares,
+            fee_subsidy_rate: fee_subsidy_votes,
+            utxo_freeze: utxo_freeze_votes,
             round_consensus: round_items,
         } = consensus_items.into_iter().unzip_wallet_consensus_item();
 
         // Save signatures to the database
         self.save_peg_out_signatures(dbtx, peg_out_signatures).await;
+        let mut misbehaving_peers = self.save_frost_nonces(dbtx, peg_out_nonces).await;
+        misbehaving_peers.extend(
+            self.save_frost_signature_shares(dbtx, peg_out_signature_shares)
+                .await,
+        );
+        misbehaving_peers.extend(
+            self.process_fee_subsidy_votes(dbtx, fee_subsidy_votes)
+                .await,
+        );
+        self.process_utxo_freeze_votes(dbtx, utxo_freeze_votes)
+            .await;
 
         let last_height = self.consensus_height(dbtx).await.unwrap_or(0);
 
@@ -370,36 +745,79 @@ impl ServerModule for Wallet {
 
                 dbtx.insert_entry(&RoundConsensusKey, &round_consensus)
                     .await;
-                vec![]
+
+                if round_consensus.block_height > last_height {
+                    self.sweep_legacy_utxos(dbtx, &round_consensus).await;
+                }
+                misbehaving_peers
+            }
+            Err(mut dropped_peers) => {
+                dropped_peers.extend(misbehaving_peers);
+                dropped_peers
             }
-            Err(dropped_peers) => dropped_peers,
         }
     }
 
     fn build_verification_cache<'a>(
         &'a self,
-        _inputs: impl Iterator<Item = &'a WalletInput>,
+        inputs: impl Iterator<Item = &'a WalletInput>,
     ) -> Self::VerificationCache {
-        WalletVerificationCache
+        // Every peer submits its own copy of a transaction, so we verify each peg-in
+        // proof only once per epoch. The outpoint commits to the transaction, so the
+        // result of the verification is fully determined by it and the tweak.
+        let proofs = inputs
+            .map(|input| ((input.outpoint(), *input.tweak_contract_key()), input))
+            .collect::<BTreeMap<_, _>>();
+
+        let valid_peg_ins = proofs
+            .into_iter()
+            .filter_map(|(key, input)| Some((key, self.verify_peg_in(input).ok()?)))
+            .collect();
+
+        WalletVerificationCache { valid_peg_ins }
     }
 
     async fn validate_input<'a, 'b>(
         &self,
         dbtx: &mut ModuleDatabaseTransaction<'b>,
-        _verification_cache: &Self::VerificationCache,
+        verification_cache: &Self::VerificationCache,
         input: &'a WalletInput,
     ) -> Result<InputMeta, ModuleError> {
         if !self.block_is_known(dbtx, input.proof_block()).await {
             return Err(WalletError::UnknownPegInProofBlock(input.proof_block()))
-                .into_module_error_other();
+                .into_module_error();
+        }
+
+        // Only verify again if the proof is missing from the cache to get the error
+        if !verification_cache
+            .valid_peg_ins
+            .contains_key(&(input.outpoint(), *input.tweak_contract_key()))
+        {
+            self.verify_peg_in(input).into_module_error()?;
         }
 
-        input
-            .verify(&self.secp, &self.cfg.consensus.peg_in_descriptor)
-            .into_module_error_other()?;
+        self.cfg
+            .consensus
+            .deposit_policy()
+            .check(bitcoin::Amount::from_sat(input.tx_output().value))
+            .into_module_error()?;
+
+        if dbtx
+            .get_value(&FrozenUtxoKey(input.outpoint()))
+            .await
+            .is_some()
+        {
+            return Err(WalletError::PegInFrozen).into_module_error();
+        }
 
-        if dbtx.get_value(&UTXOKey(input.outpoint())).await.is_some() {
-            return Err(WalletError::PegInAlreadyClaimed).into_module_error_other();
+        // Deposits to reused addresses are part of our UTXOs before they are claimed
+        if dbtx.get_value(&UTXOKey(input.outpoint())).await.is_some()
+            && dbtx
+                .get_value(&UnclaimedDepositKey(input.outpoint()))
+                .await
+                .is_none()
+        {
+            return Err(WalletError::PegInAlreadyClaimed).into_module_error();
         }
 
         Ok(InputMeta {
@@ -419,6 +837,28 @@ impl ServerModule for Wallet {
     ) -> Result<InputMeta, ModuleError> {
         let meta = self.validate_input(dbtx, cache, input).await?;
         debug!(outpoint = %input.outpoint(), amount = %meta.amount.amount, "Claiming peg-in");
+        self.fund_fee_subsidy_pool(dbtx, meta.amount.fee).await;
+
+        if dbtx
+            .remove_entry(&UnclaimedDepositKey(input.outpoint()))
+            .await
+            .is_some()
+        {
+            // Already added to our UTXOs when the block was synced
+            return Ok(meta);
+        }
+
+        let descriptor_id = match cache
+            .valid_peg_ins
+            .get(&(input.outpoint(), *input.tweak_contract_key()))
+        {
+            Some(descriptor_id) => *descriptor_id,
+            None => self
+                .verify_peg_in(input)
+                .expect("Peg-in proof was validated"),
+        };
+        dbtx.insert_new_entry(&UTXODescriptorKey(input.outpoint()), &descriptor_id)
+            .await;
 
         dbtx.insert_new_entry(
             &UTXOKey(input.outpoint()),
@@ -428,6 +868,14 @@ impl ServerModule for Wallet {
             },
         )
         .await;
+        dbtx.insert_entry(
+            &DepositTweakKey(input.tweak_contract_key().serialize()),
+            &DepositTweak {
+                descriptor_id,
+                is_change: false,
+            },
+        )
+        .await;
 
         Ok(meta)
     }
@@ -438,14 +886,18 @@ impl ServerModule for Wallet {
         output: &WalletOutput,
     ) -> Result<TransactionItemAmount, ModuleError> {
         let fee_rate = self.current_round_consensus(dbtx).await.unwrap().fee_rate;
+        // The transaction of a subsidized peg-out pays the consensus fee rate, the user
+        // only pays for the part the federation doesn't cover
+        let subsidized = self.subsidized_peg_out(dbtx, output, fee_rate).await;
+        let on_chain_output = subsidized.as_ref().map_or(output, |(output, _)| output);
         let tx = self
-            .create_peg_out_tx(dbtx, output)
+            .create_peg_out_tx(dbtx, on_chain_output)
             .await
-            .into_module_error_other()?;
+            .into_module_error()?;
 
         self.offline_wallet()
-            .validate_tx(&tx, output, fee_rate, self.cfg.consensus.network)
-            .into_module_error_other()?;
+            .validate_tx(&tx, on_chain_output, fee_rate, self.cfg.consensus.network)
+            .into_module_error()?;
 
         Ok(TransactionItemAmount {
             amount: output.amount().into(),
@@ -461,52 +913,21 @@ impl ServerModule for Wallet {
     ) -> Result<TransactionItemAmount, ModuleError> {
         let amount = self.validate_output(dbtx, output).await?;
 
-        let mut tx = self
-            .create_peg_out_tx(dbtx, output)
+        let fee_rate = self.current_round_consensus(dbtx).await.unwrap().fee_rate;
+        let subsidized = self.subsidized_peg_out(dbtx, output, fee_rate).await;
+        let tx = self
+            .create_peg_out_tx(
+                dbtx,
+                subsidized.as_ref().map_or(output, |(output, _)| output),
+            )
             .await
             .expect("Should have been validated");
-        self.offline_wallet().sign_psbt(&mut tx.psbt);
-        let txid = tx.psbt.unsigned_tx.txid();
-        info!(
-            %txid,
-            "Signing peg out",
-        );
-
-        let sigs = tx
-            .psbt
-            .inputs
-            .iter_mut()
-            .map(|input| {
-                assert_eq!(
-                    input.partial_sigs.len(),
-                    1,
-                    "There was already more than one (our) or no signatures in input"
-                );
-
-                // TODO: don't put sig into PSBT in the first place
-                // We actually take out our own signature so everyone finalizes the tx in the
-                // same epoch.
-                let sig = std::mem::take(&mut input.partial_sigs)
-                    .into_values()
-                    .next()
-                    .expect("asserted previously");
-
-                // We drop SIGHASH_ALL, because we always use that and it is only present in the
-                // PSBT for compatibility with other tools.
-                secp256k1::ecdsa::Signature::from_der(&sig.to_vec()[..sig.to_vec().len() - 1])
-                    .expect("we serialized it ourselves that way")
-            })
-            .collect::<Vec<_>>();
-
-        // Delete used UTXOs
-        for input in tx.psbt.unsigned_tx.input.iter() {
-            dbtx.remove_entry(&UTXOKey(input.previous_output)).await;
+        if let Some((_, subsidy)) = subsidized {
+            self.draw_fee_subsidy(dbtx, subsidy).await;
         }
+        self.fund_fee_subsidy_pool(dbtx, amount.fee).await;
 
-        dbtx.insert_new_entry(&UnsignedTransactionKey(txid), &tx)
-            .await;
-        dbtx.insert_new_entry(&PegOutTxSignatureCI(txid), &sigs)
-            .await;
+        let txid = self.sign_unsigned_tx(dbtx, tx).await;
         dbtx.insert_new_entry(
             &PegOutBitcoinTransaction(out_point),
             &WalletOutputOutcome(txid),
@@ -533,24 +954,45 @@ impl ServerModule for Wallet {
 
         let mut drop_peers = Vec::<PeerId>::new();
         for (key, mut unsigned) in unsigned_txs {
-            let signers: BTreeSet<PeerId> = unsigned
-                .signatures
-                .iter()
-                .filter_map(|(peer, sig)| {
-                    match self.sign_peg_out_psbt(&mut unsigned.psbt, peer, sig) {
-                        Ok(_) => Some(*peer),
-                        Err(error) => {
-                            warn!("Error with {} partial sig {:?}", peer, error);
-                            None
-                        }
+            let mut signers = BTreeSet::<PeerId>::new();
+            for (peer, sig) in &unsigned.signatures {
+                // Signatures that arrived in this epoch were already verified by the workers
+                let verified = match self.peg_out_verifier.take_result(*peer, sig).await {
+                    Some(verified) => verified,
+                    None => verify_peg_out_signature(
+                        &self.secp,
+                        self.peer_peg_in_key(peer),
+                        &unsigned.psbt,
+                        sig,
+                    ),
+                };
+                match verified.and_then(|partial_sigs| {
+                    Self::sign_peg_out_psbt(&mut unsigned.psbt, partial_sigs)
+                }) {
+                    Ok(()) => {
+                        signers.insert(*peer);
                     }
-                })
-                .collect();
+                    Err(error) => warn!("Error with {} partial sig {:?}", peer, error),
+                }
+            }
 
-            for peer in consensus_peers.sub(&signers) {
-                error!("Dropping {:?} for not contributing sigs to PSBT", peer);
-                drop_peers.push(peer);
+            // The signatures are encrypted, a peer's may be revealed in a later epoch
+            // than the others, so we give everyone some time before penalizing them
+            let missing = consensus_peers.sub(&signers);
+            let epochs_key = PegOutSignatureEpochsKey(key.0);
+            if !missing.is_empty() {
+                let epochs_waited = dbtx.get_value(&epochs_key).await.unwrap_or(0) + 1;
+                if epochs_waited < PEG_OUT_SIGNATURE_TIMEOUT_EPOCHS {
+                    dbtx.insert_entry(&epochs_key, &epochs_waited).await;
+                    continue;
+                }
+
+                for peer in missing {
+                    error!("{:?} didn't contribute sigs to PSBT", peer);
+                    drop_peers.push(peer);
+                }
             }
+            dbtx.remove_entry(&epochs_key).await;
 
             match self.finalize_peg_out_psbt(unsigned) {
                 Ok(pending_tx) => {
@@ -568,6 +1010,9 @@ impl ServerModule for Wallet {
                 }
             }
         }
+        self.peg_out_verifier.clear();
+
+        drop_peers.extend(self.process_frost_signing(dbtx, consensus_peers).await);
         drop_peers
     }
 
@@ -583,6 +1028,12 @@ impl ServerModule for Wallet {
         audit
             .add_items(dbtx, &UTXOPrefixKey, |_, v| v.amount.to_sat() as i64 * 1000)
             .await;
+        // Still owned by the federation, listed separately so they stand out
+        audit
+            .add_items(dbtx, &FrozenUtxoPrefix, |_, v| {
+                v.amount.to_sat() as i64 * 1000
+            })
+            .await;
         audit
             .add_items(dbtx, &UnsignedTransactionPrefixKey, |_, v| match v.rbf {
                 None => v.change.to_sat() as i64 * 1000,
@@ -595,6 +1046,76 @@ impl ServerModule for Wallet {
                 Some(rbf) => rbf.fees.amount().to_sat() as i64 * -1000,
             })
             .await;
+        // Fees set aside for subsidies are earmarked, every subsidy paid lowers both
+        // the pool and the UTXOs
+        audit
+            .add_items(dbtx, &FeeSubsidyPoolPrefix, |_, pool| {
+                -(pool.balance.msats as i64)
+            })
+            .await;
+        // Owed to whoever sent them until e-cash is issued for them
+        audit
+            .add_items(dbtx, &UnclaimedDepositPrefix, |_, deposit| {
+                deposit.amount.to_sat() as i64 * -1000
+            })
+            .await;
+    }
+
+    fn input_activity(&self, input: &WalletInput) -> ItemActivity {
+        ItemActivity {
+            peg_in: fedimint_core::Amount::from_sats(input.tx_output().value),
+            ..Default::default()
+        }
+    }
+
+    fn output_activity(&self, output: &WalletOutput) -> ItemActivity {
+        match output {
+            WalletOutput::PegOut(peg_out) => ItemActivity {
+                peg_out: peg_out.amount.into(),
+                ..Default::default()
+            },
+            // Only bumps the fees of an earlier peg-out
+            WalletOutput::Rbf(_) => ItemActivity::default(),
+        }
+    }
+
+    async fn join_snapshot(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) -> Option<Vec<u8>> {
+        // Before the first round consensus there is nothing to share
+        let round_consensus = self.current_round_consensus(dbtx).await?;
+        let snapshot = WalletJoinSnapshot {
+            consensus_height: round_consensus.block_height,
+            fee_rate: round_consensus.fee_rate,
+        };
+        Some(snapshot.consensus_encode_to_vec_exact())
+    }
+
+    async fn admin_status(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> Option<serde_json::Value> {
+        let round_consensus = self.current_round_consensus(dbtx).await;
+        // The bitcoind client retries until it succeeds, an unreachable bitcoind
+        // shouldn't block the whole status
+        let bitcoind_block_height =
+            timeout(BITCOIND_STATUS_TIMEOUT, self.btc_rpc.get_block_height())
+                .await
+                .ok()
+                .and_then(Result::ok);
+        let status = WalletAdminStatus {
+            consensus_height: round_consensus.as_ref().map(|rc| rc.block_height),
+            fee_rate: round_consensus.map(|rc| rc.fee_rate),
+            bitcoind_block_height,
+            backend_divergence: self
+                .backend_divergence
+                .lock()
+                .expect("lock poisoned")
+                .clone(),
+            bitcoind_outage: self.chain_state.outage(),
+            pending_peg_outs: self.pending_peg_outs(dbtx).await,
+            unclaimed_deposits: self.unclaimed_deposits(dbtx).await,
+            frozen_utxos: self.frozen_utxos(dbtx).await,
+        };
+        Some(serde_json::to_value(status).expect("serialization can't fail"))
     }
 
     fn api_endpoints(&self) -> Vec<ApiEndpoint<Self>> {
@@ -610,11 +1131,12 @@ impl ServerModule for Wallet {
                 async |module: &Wallet, context, params: (Address, u64)| -> Option<PegOutFees> {
                     let (address, sats) = params;
                     let consensus = module.current_round_consensus(&mut context.dbtx()).await.unwrap();
-                    let tx = module.offline_wallet().create_tx(
+                    let utxos = module.available_utxos(&mut context.dbtx()).await;
+                    let tx = module.spending_wallet(&mut context.dbtx(), &utxos).await.create_tx(
                         bitcoin::Amount::from_sat(sats),
                         address.script_pubkey(),
                         vec![],
-                        module.available_utxos(&mut context.dbtx()).await,
+                        utxos,
                         consensus.fee_rate,
                         &consensus.randomness_beacon,
                         None
@@ -626,33 +1148,215 @@ impl ServerModule for Wallet {
                             warn!("Error returning peg-out fees {error}");
                             Ok(None)
                         }
-                        Ok(tx) => Ok(Some(tx.fees))
+                        Ok(tx) => Ok(Some(
+                            module
+                                .subsidized_fees(
+                                    &mut context.dbtx(),
+                                    bitcoin::Amount::from_sat(sats),
+                                    tx.fees,
+                                )
+                                .await,
+                        )),
                     }
                 }
             },
-        ]
-    }
-}
-
-#[derive(Debug)]
-pub struct Wallet {
+            api_endpoint! {
+                "deposit_policy",
+                async |module: &Wallet, _context, _params: ()| -> PegInPolicy {
+                    Ok(module.cfg.consensus.deposit_policy())
+                }
+            },
+            api_endpoint! {
+                "fee_subsidy",
+                async |module: &Wallet, context, _params: ()| -> FeeSubsidyStatus {
+                    Ok(FeeSubsidyStatus {
+                        config: module.cfg.consensus.fee_subsidy.clone(),
+                        rate_bps: module.fee_subsidy_rate(&mut context.dbtx()).await,
+                        pool: module.fee_subsidy_pool(&mut context.dbtx()).await,
+                    })
+                }
+            },
+            api_endpoint! {
+                "set_fee_subsidy_rate",
+                async |_module: &Wallet, context, rate_bps: u16| -> () {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    if rate_bps > FEE_SUBSIDY_RATE_SCALE {
+                        return Err(ApiError::bad_request(format!(
+                            "Subsidy rate can be at most {FEE_SUBSIDY_RATE_SCALE} bps"
+                        )));
+                    }
+                    // Proposed as a vote until it is part of an epoch, the rate changes once
+                    // a threshold of guardians voted for it
+                    let mut dbtx = context.dbtx();
+                    dbtx.insert_entry(&FeeSubsidyRateCI, &rate_bps).await;
+                    Ok(())
+                }
+            },
+            api_endpoint! {
+                "pending_peg_outs",
+                async |module: &Wallet, context, _params: ()| -> Vec<PendingPegOut> {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    Ok(module.pending_peg_outs(&mut context.dbtx()).await)
+                }
+            },
+            api_endpoint! {
+                "unclaimed_deposits",
+                async |module: &Wallet, context, _params: ()| -> Vec<(bitcoin::OutPoint, UnclaimedDeposit)> {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    Ok(module.unclaimed_deposits(&mut context.dbtx()).await)
+                }
+            },
+            api_endpoint! {
+                "freeze_utxo",
+                async |_module: &Wallet, context, vote: UtxoFreezeVote| -> () {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    let mut dbtx = context.dbtx();
+                    let (spendable, frozen) = (
+                        dbtx.get_value(&UTXOKey(vote.outpoint)).await.is_some(),
+                        dbtx.get_value(&FrozenUtxoKey(vote.outpoint)).await.is_some(),
+                    );
+                    if vote.frozen && !spendable && !frozen {
+                        return Err(ApiError::bad_request(format!(
+                            "{} is not an unspent UTXO of the federation",
+                            vote.outpoint
+                        )));
+                    }
+                    if !vote.frozen && !frozen {
+                        return Err(ApiError::bad_request(format!(
+                            "{} is not frozen",
+                            vote.outpoint
+                        )));
+                    }
+                    // Proposed as a vote until it is part of an epoch, the UTXO is frozen
+                    // or unfrozen once a threshold of guardians voted for it
+                    dbtx.insert_entry(&UtxoFreezeCIKey(vote.outpoint), &vote.frozen)
+                        .await;
+                    Ok(())
+                }
+            },
+            api_endpoint! {
+                "frozen_utxos",
+                async |module: &Wallet, context, _params: ()| -> Vec<(bitcoin::OutPoint, SpendableUTXO)> {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    Ok(module.frozen_utxos(&mut context.dbtx()).await)
+                }
+            },
+            api_endpoint! {
+                "utxos",
+                async |module: &Wallet, context, _params: ()| -> Vec<WalletUtxo> {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    Ok(module.wallet_utxos(&mut context.dbtx()).await)
+                }
+            },
+            api_endpoint! {
+                "resync_block_hashes",
+                async |module: &Wallet, context, from_height: u32| -> BlockResyncResponse {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    module
+                        .resync_block_hashes(&mut context.dbtx(), from_height)
+                        .await
+                        .map_err(|e| ApiError::server_error(format!("Resync failed: {e}")))
+                }
+            },
+            api_endpoint! {
+                "backend_divergence",
+                async |module: &Wallet, context, _params: ()| -> Option<BackendDivergence> {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    Ok(module.backend_divergence.lock().expect("lock poisoned").clone())
+                }
+            },
+            api_endpoint! {
+                "bitcoind_outage",
+                async |module: &Wallet, context, _params: ()| -> Option<BitcoindOutage> {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    Ok(module.chain_state.outage())
+                }
+            },
+            api_endpoint! {
+                "descriptor_migration",
+                async |module: &Wallet, context, _params: ()| -> DescriptorMigrationStatus {
+                    if !context.has_auth() {
+                        return Err(ApiError::unauthorized());
+                    }
+                    Ok(module.descriptor_migration_status(&mut context.dbtx()).await)
+                }
+            },
+        ]
+    }
+
+    fn interconnect_handlers(&self) -> Vec<InterconnectHandler<Self>> {
+        vec![
+            interconnect_handler! {
+                VerifyBlockKnownMethod,
+                async |module: &Wallet, dbtx, block_hash| {
+                    Ok(module.block_is_known(dbtx, block_hash).await)
+                }
+            },
+            interconnect_handler! {
+                ConsensusBlockHeightMethod,
+                async |module: &Wallet, dbtx, _request| {
+                    Ok(module.consensus_height(dbtx).await)
+                }
+            },
+        ]
+    }
+}
+
+#[derive(Debug)]
+pub struct Wallet {
     cfg: WalletConfig,
     secp: Secp256k1<All>,
     btc_rpc: DynBitcoindRpc,
+    /// Backend the primary one is cross-checked against, if configured
+    secondary_btc_rpc: Option<DynBitcoindRpc>,
+    /// Result of the last cross-check of the bitcoin backends
+    backend_divergence: std::sync::Mutex<Option<BackendDivergence>>,
+    /// Last chain state our bitcoind reported for a proposal
+    chain_state: ChainStateCache,
+    peg_out_verifier: PegOutVerifier,
+    peg_in_public_key: secp256k1::PublicKey,
+    /// Signs peg-outs with our peg-in key or our FROST key share
+    signer: DynGuardianSigner,
+    our_id: PeerId,
 }
 
 impl Wallet {
     pub async fn new(
         cfg: WalletConfig,
+        module_instance_id: ModuleInstanceId,
+        our_id: PeerId,
         db: Database,
         task_group: &mut TaskGroup,
     ) -> anyhow::Result<Wallet> {
         let btc_rpc = create_bitcoind(&cfg.local.bitcoin_rpc, task_group.make_handle())?;
-        Ok(Self::new_with_bitcoind(cfg, db, btc_rpc, task_group).await?)
+        Ok(
+            Self::new_with_bitcoind(cfg, module_instance_id, our_id, db, btc_rpc, task_group)
+                .await?,
+        )
     }
 
     pub async fn new_with_bitcoind(
         cfg: WalletConfig,
+        module_instance_id: ModuleInstanceId,
+        our_id: PeerId,
         db: Database,
         bitcoind: DynBitcoindRpc,
         task_group: &mut TaskGroup,
@@ -660,8 +1364,12 @@ impl Wallet {
         let broadcaster_bitcoind_rpc = bitcoind.clone();
         let broadcaster_db = db.clone();
         task_group
-            .spawn("broadcast pending", |handle| async move {
-                run_broadcast_pending_tx(broadcaster_db, broadcaster_bitcoind_rpc, &handle).await;
+            .spawn_supervised("broadcast pending", move |handle| {
+                let db = broadcaster_db.clone();
+                let rpc = broadcaster_bitcoind_rpc.clone();
+                async move {
+                    run_broadcast_pending_tx(db, rpc, &handle).await;
+                }
             })
             .await;
 
@@ -693,10 +1401,63 @@ impl Wallet {
             Err(err) => warn!("Bitcoin fee estimation failed. Please configure your nodes to enable fee estimation: {:?}", err),
         }
 
+        let secondary_btc_rpc = match &cfg.local.secondary_bitcoin_rpc {
+            Some(rpc_config) => {
+                let secondary = create_bitcoind(rpc_config, task_group.make_handle())
+                    .map_err(WalletError::RpcError)?;
+                let secondary_net = secondary
+                    .get_network()
+                    .await
+                    .map_err(WalletError::RpcError)?;
+                if secondary_net != cfg.consensus.network {
+                    return Err(WalletError::WrongNetwork(
+                        cfg.consensus.network,
+                        secondary_net,
+                    ));
+                }
+                info!(
+                    kind = %rpc_config.kind,
+                    "Cross-checking with secondary bitcoin backend"
+                );
+                Some(secondary)
+            }
+            None => None,
+        };
+
+        let secp = Secp256k1::new();
+        let peg_out_verifier = PegOutVerifier::new(&secp, task_group).await;
+        let peg_in_public_key = cfg
+            .consensus
+            .peer_peg_in_keys
+            .get(&our_id)
+            .ok_or_else(|| format_err!("Own peg-in key not found among pub keys"))?
+            .key;
+        let frost_key = match (&cfg.consensus.frost_keys, &cfg.private.frost_key_share) {
+            (Some(frost_keys), Some(key_share)) => Some(
+                frost_keys
+                    .key_package(our_id, key_share)
+                    .map_err(anyhow::Error::from)?,
+            ),
+            _ => None,
+        };
+        let signer = guardian_signer(
+            module_instance_id,
+            LocalSigner::default()
+                .with_ecdsa_key(cfg.private.peg_in_key)
+                .with_frost_key(frost_key),
+        )?;
+
         let wallet = Wallet {
             cfg,
-            secp: Default::default(),
+            secp,
             btc_rpc: bitcoind_rpc,
+            secondary_btc_rpc,
+            backend_divergence: Default::default(),
+            chain_state: Default::default(),
+            peg_out_verifier,
+            peg_in_public_key,
+            signer,
+            our_id,
         };
 
         Ok(wallet)
@@ -716,7 +1477,28 @@ impl Wallet {
 
         for (peer, sig) in signatures.into_iter() {
             match cache.get_mut(&sig.txid) {
-                Some(unsigned) => unsigned.signatures.push((peer, sig)),
+                Some(unsigned)
+                    if unsigned
+                        .signatures
+                        .iter()
+                        .any(|(signer, _)| *signer == peer) =>
+                {
+                    debug!(
+                        "{} sent a duplicate peg-out signature for {}",
+                        peer, sig.txid
+                    )
+                }
+                Some(unsigned) => {
+                    self.peg_out_verifier
+                        .submit(
+                            peer,
+                            *self.peer_peg_in_key(&peer),
+                            unsigned.psbt.clone(),
+                            sig.clone(),
+                        )
+                        .await;
+                    unsigned.signatures.push((peer, sig))
+                }
                 None => warn!(
                     "{} sent peg-out signature for unknown PSBT {}",
                     peer, sig.txid
@@ -730,65 +1512,307 @@ impl Wallet {
         }
     }
 
-    /// Try to attach signatures to a pending peg-out tx.
-    fn sign_peg_out_psbt(
-        &self,
-        psbt: &mut PartiallySignedTransaction,
-        peer: &PeerId,
-        signature: &PegOutSignatureItem,
-    ) -> Result<(), ProcessPegOutSigError> {
-        let peer_key = self
-            .cfg
+    fn peer_peg_in_key(&self, peer: &PeerId) -> &CompressedPublicKey {
+        self.cfg
             .consensus
             .peer_peg_in_keys
             .get(peer)
-            .expect("always called with valid peer id");
+            .expect("always called with valid peer id")
+    }
 
-        if psbt.inputs.len() != signature.signature.len() {
-            return Err(ProcessPegOutSigError::WrongSignatureCount(
-                psbt.inputs.len(),
-                signature.signature.len(),
-            ));
+    fn our_peer_id(&self) -> Option<PeerId> {
+        self.cfg
+            .consensus
+            .peer_peg_in_keys
+            .iter()
+            .find(|(_, key)| key.key == self.peg_in_public_key)
+            .map(|(peer, _)| *peer)
+    }
+
+    /// Records the subsidy rate votes of peers and switches to a rate once a
+    /// threshold of peers voted for it
+    async fn process_fee_subsidy_votes(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        votes: Vec<(PeerId, u16)>,
+    ) -> Vec<PeerId> {
+        if votes.is_empty() {
+            return vec![];
         }
 
-        let mut tx_hasher = SighashCache::new(&psbt.unsigned_tx);
-        for (idx, (input, signature)) in psbt
-            .inputs
-            .iter_mut()
-            .zip(signature.signature.iter())
-            .enumerate()
-        {
-            let tx_hash = tx_hasher
-                .segwit_signature_hash(
-                    idx,
-                    input
-                        .witness_script
-                        .as_ref()
-                        .expect("Missing witness script"),
-                    input.witness_utxo.as_ref().expect("Missing UTXO").value,
-                    EcdsaSighashType::All,
-                )
-                .map_err(|_| ProcessPegOutSigError::SighashError)?;
+        let mut misbehaving_peers = vec![];
+        for (peer, rate_bps) in votes {
+            if rate_bps > FEE_SUBSIDY_RATE_SCALE {
+                warn!(%peer, rate_bps, "Peer voted for a subsidy rate above 100%");
+                misbehaving_peers.push(peer);
+                continue;
+            }
+            if Some(peer) == self.our_peer_id() {
+                dbtx.remove_entry(&FeeSubsidyRateCI).await;
+            }
+            dbtx.insert_entry(&FeeSubsidyRateVoteKey(peer), &rate_bps)
+                .await;
+        }
 
-            let tweak = input
-                .proprietary
-                .get(&proprietary_tweak_key())
-                .expect("we saved it with a tweak");
+        let votes = dbtx
+            .find_by_prefix(&FeeSubsidyRateVotePrefix)
+            .await
+            .map(|(_, rate_bps)| rate_bps)
+            .collect::<Vec<_>>()
+            .await;
+        let threshold = self.cfg.consensus.peer_peg_in_keys.threshold();
+        let agreed = votes
+            .iter()
+            .find(|rate_bps| votes.iter().filter(|vote| vote == rate_bps).count() >= threshold);
 
-            let tweaked_peer_key = peer_key.tweak(tweak, &self.secp);
-            self.secp
-                .verify_ecdsa(
-                    &Message::from_slice(&tx_hash[..]).unwrap(),
-                    signature,
-                    &tweaked_peer_key.key,
+        if let Some(rate_bps) = agreed {
+            info!(rate_bps, "Fee subsidy rate updated");
+            dbtx.insert_entry(&FeeSubsidyRateKey, rate_bps).await;
+            dbtx.remove_by_prefix(&FeeSubsidyRateVotePrefix).await;
+        }
+        misbehaving_peers
+    }
+
+    /// Records the UTXO freeze votes of peers and freezes or unfreezes a UTXO
+    /// once a threshold of peers agreed on it
+    ///
+    /// Frozen UTXOs are moved out of the spendable UTXOs, so peg-out
+    /// transactions can't select them.
+    async fn process_utxo_freeze_votes(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        votes: Vec<(PeerId, UtxoFreezeVote)>,
+    ) {
+        let threshold = self.cfg.consensus.peer_peg_in_keys.threshold();
+        for (peer, vote) in votes {
+            let outpoint = vote.outpoint;
+            if Some(peer) == self.our_peer_id() {
+                dbtx.remove_entry(&UtxoFreezeCIKey(outpoint)).await;
+            }
+            dbtx.insert_entry(&UtxoFreezeVoteKey { outpoint, peer }, &vote.frozen)
+                .await;
+
+            let outpoint_votes = dbtx
+                .find_by_prefix(&UtxoFreezeVotePrefix)
+                .await
+                .collect::<Vec<_>>()
+                .await
+                .into_iter()
+                .filter(|(key, _)| key.outpoint == outpoint)
+                .collect::<Vec<_>>();
+            let agreeing = outpoint_votes
+                .iter()
+                .filter(|(_, frozen)| *frozen == vote.frozen)
+                .count();
+            if agreeing < threshold {
+                continue;
+            }
+            for (key, _) in outpoint_votes {
+                dbtx.remove_entry(&key).await;
+            }
+
+            if vote.frozen {
+                match dbtx.remove_entry(&UTXOKey(outpoint)).await {
+                    Some(utxo) => {
+                        info!(%outpoint, amount = %utxo.amount, "Froze UTXO");
+                        dbtx.insert_entry(&FrozenUtxoKey(outpoint), &utxo).await;
+                    }
+                    None => warn!(%outpoint, "Voted to freeze a UTXO that isn't spendable"),
+                }
+            } else if let Some(utxo) = dbtx.remove_entry(&FrozenUtxoKey(outpoint)).await {
+                info!(%outpoint, amount = %utxo.amount, "Unfroze UTXO");
+                dbtx.insert_entry(&UTXOKey(outpoint), &utxo).await;
+            }
+        }
+    }
+
+    /// UTXOs excluded from peg-out transactions by a vote of the guardians
+    pub async fn frozen_utxos(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> Vec<(bitcoin::OutPoint, SpendableUTXO)> {
+        dbtx.find_by_prefix(&FrozenUtxoPrefix)
+            .await
+            .map(|(key, utxo)| (key.0, utxo))
+            .collect()
+            .await
+    }
+
+    /// All UTXOs of the federation including the frozen ones, with their
+    /// confirmations according to our bitcoind
+    pub async fn wallet_utxos(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) -> Vec<WalletUtxo> {
+        let utxos = self
+            .available_utxos(dbtx)
+            .await
+            .into_iter()
+            .map(|(key, utxo)| (key.0, utxo, false))
+            .chain(
+                self.frozen_utxos(dbtx)
+                    .await
+                    .into_iter()
+                    .map(|(outpoint, utxo)| (outpoint, utxo, true)),
+            )
+            .collect::<Vec<_>>();
+
+        // Like the admin status, an unreachable bitcoind only leaves the
+        // confirmations unknown
+        let tip = timeout(BITCOIND_STATUS_TIMEOUT, self.btc_rpc.get_block_height())
+            .await
+            .ok()
+            .and_then(Result::ok);
+        let mut tx_heights = BTreeMap::new();
+        if tip.is_some() {
+            for (outpoint, _, _) in &utxos {
+                if tx_heights.contains_key(&outpoint.txid) {
+                    continue;
+                }
+                let height = timeout(
+                    BITCOIND_STATUS_TIMEOUT,
+                    self.btc_rpc.get_tx_block_height(&outpoint.txid),
                 )
-                .map_err(|_| ProcessPegOutSigError::InvalidSignature)?;
+                .await
+                .ok()
+                .and_then(Result::ok)
+                .flatten();
+                tx_heights.insert(outpoint.txid, height);
+            }
+        }
 
-            if input
-                .partial_sigs
-                .insert(tweaked_peer_key.into(), EcdsaSig::sighash_all(*signature))
-                .is_some()
-            {
+        utxos
+            .into_iter()
+            .map(|(outpoint, utxo, frozen)| WalletUtxo {
+                outpoint,
+                amount: utxo.amount,
+                tweak_fingerprint: WalletUtxo::tweak_fingerprint(&utxo.tweak),
+                frozen,
+                confirmations: tip
+                    .zip(tx_heights.get(&outpoint.txid).copied().flatten())
+                    .map(|(tip, height)| tip.saturating_sub(height) + 1),
+            })
+            .collect()
+    }
+
+    /// Share of the consensus fee rate currently subsidized, in basis points
+    pub async fn fee_subsidy_rate(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) -> u16 {
+        dbtx.get_value(&FeeSubsidyRateKey)
+            .await
+            .unwrap_or(self.cfg.consensus.fee_subsidy.initial_rate_bps)
+    }
+
+    pub async fn fee_subsidy_pool(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> FeeSubsidyPool {
+        dbtx.get_value(&FeeSubsidyPoolKey).await.unwrap_or_default()
+    }
+
+    /// Sets the fees we collected aside for subsidies
+    async fn fund_fee_subsidy_pool(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        fee: fedimint_core::Amount,
+    ) {
+        if fee == fedimint_core::Amount::ZERO {
+            return;
+        }
+
+        let mut pool = self.fee_subsidy_pool(dbtx).await;
+        pool.balance += fee;
+        dbtx.insert_entry(&FeeSubsidyPoolKey, &pool).await;
+    }
+
+    async fn draw_fee_subsidy(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        subsidy: bitcoin::Amount,
+    ) {
+        let subsidy = fedimint_core::Amount::from(subsidy);
+        let mut pool = self.fee_subsidy_pool(dbtx).await;
+        pool.balance -= subsidy;
+        pool.drawn += subsidy;
+        debug!(%subsidy, balance = %pool.balance, "Subsidizing peg-out fees");
+        dbtx.insert_entry(&FeeSubsidyPoolKey, &pool).await;
+    }
+
+    /// Fees the user pays for a peg-out of `amount` whose transaction pays
+    /// `fees`, lower than `fees` if the federation subsidizes it
+    async fn subsidized_fees(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        amount: bitcoin::Amount,
+        fees: PegOutFees,
+    ) -> PegOutFees {
+        let rate_bps = self.fee_subsidy_rate(dbtx).await;
+        let Some(fee_rate) =
+            self.cfg
+                .consensus
+                .fee_subsidy
+                .subsidized_fee_rate(rate_bps, amount, fees.fee_rate)
+        else {
+            return fees;
+        };
+
+        let subsidized = PegOutFees {
+            fee_rate,
+            total_weight: fees.total_weight,
+        };
+        let subsidy = fees.amount() - subsidized.amount();
+        if self.fee_subsidy_pool(dbtx).await.balance < subsidy.into() {
+            return fees;
+        }
+        subsidized
+    }
+
+    /// The peg-out with its fees topped up to `consensus_fee_rate` and the
+    /// subsidy that costs, `None` if the federation doesn't subsidize it
+    async fn subsidized_peg_out(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        output: &WalletOutput,
+        consensus_fee_rate: Feerate,
+    ) -> Option<(WalletOutput, bitcoin::Amount)> {
+        let WalletOutput::PegOut(peg_out) = output else {
+            return None;
+        };
+        if peg_out.fees.fee_rate >= consensus_fee_rate {
+            return None;
+        }
+
+        let rate_bps = self.fee_subsidy_rate(dbtx).await;
+        let min_fee_rate = self.cfg.consensus.fee_subsidy.subsidized_fee_rate(
+            rate_bps,
+            peg_out.amount,
+            consensus_fee_rate,
+        )?;
+        if peg_out.fees.fee_rate < min_fee_rate {
+            return None;
+        }
+
+        let fees = PegOutFees {
+            fee_rate: consensus_fee_rate,
+            total_weight: peg_out.fees.total_weight,
+        };
+        let subsidy = fees.amount() - peg_out.fees.amount();
+        if self.fee_subsidy_pool(dbtx).await.balance < subsidy.into() {
+            return None;
+        }
+
+        Some((
+            WalletOutput::PegOut(PegOut {
+                fees,
+                ..peg_out.clone()
+            }),
+            subsidy,
+        ))
+    }
+
+    /// Try to attach signatures to a pending peg-out tx.
+    fn sign_peg_out_psbt(
+        psbt: &mut PartiallySignedTransaction,
+        partial_signatures: PartialSignatures,
+    ) -> Result<(), ProcessPegOutSigError> {
+        for (input, (key, signature)) in psbt.inputs.iter_mut().zip(partial_signatures) {
+            if input.partial_sigs.insert(key, signature).is_some() {
                 // Should never happen since peers only sign a PSBT once
                 return Err(ProcessPegOutSigError::DuplicateSignature);
             }
@@ -799,6 +1823,17 @@ impl Wallet {
     fn finalize_peg_out_psbt(
         &self,
         mut unsigned: UnsignedTransaction,
+    ) -> Result<PendingTransaction, ProcessPegOutSigError> {
+        if let Err(error) = unsigned.psbt.finalize_mut(&self.secp) {
+            return Err(ProcessPegOutSigError::ErrorFinalizingPsbt(error));
+        }
+
+        Self::pending_transaction(unsigned)
+    }
+
+    /// Extracts the transaction of a PSBT whose inputs are all finalized
+    fn pending_transaction(
+        unsigned: UnsignedTransaction,
     ) -> Result<PendingTransaction, ProcessPegOutSigError> {
         // We need to save the change output's tweak key to be able to access the funds
         // later on. The tweak is extracted here because the psbt is moved next
@@ -814,22 +1849,422 @@ impl Wallet {
             .try_into()
             .map_err(|_| ProcessPegOutSigError::MissingOrMalformedChangeTweak)?;
 
-        if let Err(error) = unsigned.psbt.finalize_mut(&self.secp) {
-            return Err(ProcessPegOutSigError::ErrorFinalizingPsbt(error));
+        let tx = unsigned.psbt.extract_tx();
+
+        Ok(PendingTransaction {
+            tx,
+            tweak: change_tweak,
+            change: unsigned.change,
+            destination: unsigned.destination,
+            fees: unsigned.fees,
+            selected_utxos: unsigned.selected_utxos,
+            peg_out_amount: unsigned.peg_out_amount,
+            rbf: unsigned.rbf,
+        })
+    }
+
+    fn frost_keys(&self) -> &FrostPublicKeys {
+        self.cfg
+            .consensus
+            .frost_keys
+            .as_ref()
+            .expect("only called when signing with FROST")
+    }
+
+    /// Records the nonce commitments peers sent for the current attempt of
+    /// signing a peg-out with FROST, returning the peers that sent malformed
+    /// ones
+    async fn save_frost_nonces(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        items: Vec<(PeerId, PegOutNoncesItem)>,
+    ) -> Vec<PeerId> {
+        let mut misbehaving_peers = vec![];
+        for (peer, item) in items {
+            let mut state = match dbtx.get_value(&FrostSigningStateKey(item.txid)).await {
+                Some(state) => state,
+                None => {
+                    warn!("{} sent FROST nonces for unknown PSBT {}", peer, item.txid);
+                    continue;
+                }
+            };
+
+            // Nonces of a previous attempt or arriving after the signers were
+            // fixed are never used, so they are harmless
+            if item.attempt != state.attempt
+                || state.excluded.contains(&peer)
+                || state.signers.is_some()
+            {
+                continue;
+            }
+
+            let unsigned = dbtx
+                .get_value(&UnsignedTransactionKey(item.txid))
+                .await
+                .expect("signing state is removed with the transaction");
+            if item.commitments.len() != unsigned.psbt.inputs.len() {
+                warn!(
+                    "{} sent {} FROST nonces for the {} inputs of PSBT {}",
+                    peer,
+                    item.commitments.len(),
+                    unsigned.psbt.inputs.len(),
+                    item.txid
+                );
+                misbehaving_peers.push(peer);
+                continue;
+            }
+
+            state.commitments.entry(peer).or_insert(item.commitments);
+            dbtx.insert_entry(&FrostSigningStateKey(item.txid), &state)
+                .await;
+        }
+        misbehaving_peers
+    }
+
+    /// Verifies and records the FROST signature shares of the signers,
+    /// signing restarts without peers that sent invalid ones
+    async fn save_frost_signature_shares(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        items: Vec<(PeerId, PegOutSignatureSharesItem)>,
+    ) -> Vec<PeerId> {
+        let mut misbehaving_peers = vec![];
+        for (peer, item) in items {
+            let mut state = match dbtx.get_value(&FrostSigningStateKey(item.txid)).await {
+                Some(state) => state,
+                None => {
+                    warn!(
+                        "{} sent FROST signature shares for unknown PSBT {}",
+                        peer, item.txid
+                    );
+                    continue;
+                }
+            };
+
+            let is_signer = matches!(&state.signers, Some(signers) if signers.contains(&peer));
+            if item.attempt != state.attempt || !is_signer || state.shares.contains_key(&peer) {
+                continue;
+            }
+
+            let unsigned = dbtx
+                .get_value(&UnsignedTransactionKey(item.txid))
+                .await
+                .expect("signing state is removed with the transaction");
+            let sessions = match self.frost_sessions(&unsigned.psbt, &state) {
+                Ok(sessions) => sessions,
+                Err(error) => {
+                    warn!("Unable to verify FROST signature shares: {}", error);
+                    continue;
+                }
+            };
+
+            let frost_keys = self.frost_keys();
+            let verified = if sessions.len() == item.shares.len() {
+                sessions
+                    .iter()
+                    .zip(&item.shares)
+                    .try_for_each(|(session, share)| session.verify_share(peer, share))
+            } else {
+                Err(FrostError::InvalidSignatureShare(peer))
+            };
+
+            match verified {
+                Ok(()) => {
+                    state.shares.insert(peer, item.shares);
+                }
+                Err(error) => {
+                    warn!(
+                        "Restarting FROST signing of PSBT {} without {}: {}",
+                        item.txid, peer, error
+                    );
+                    misbehaving_peers.push(peer);
+                    state.restart(
+                        [peer],
+                        frost_keys.verification_shares.len(),
+                        frost_keys.threshold(),
+                    );
+                }
+            }
+            dbtx.insert_entry(&FrostSigningStateKey(item.txid), &state)
+                .await;
+        }
+        misbehaving_peers
+    }
+
+    /// Signing sessions of the fixed signers for every input of a peg-out,
+    /// which spends taproot outputs of the FROST group key
+    fn frost_sessions(
+        &self,
+        psbt: &PartiallySignedTransaction,
+        state: &FrostSigningState,
+    ) -> Result<Vec<SigningSession>, FrostError> {
+        let frost_keys = self.frost_keys();
+        let signers = state
+            .signers
+            .as_ref()
+            .ok_or(FrostError::NotEnoughSigners(frost_keys.threshold(), 0))?;
+
+        let prevouts = psbt
+            .inputs
+            .iter()
+            .map(|input| input.witness_utxo.clone().expect("Missing UTXO"))
+            .collect::<Vec<_>>();
+        let mut tx_hasher = SighashCache::new(&psbt.unsigned_tx);
+
+        psbt.inputs
+            .iter()
+            .enumerate()
+            .map(|(idx, input)| {
+                let tweak = input
+                    .proprietary
+                    .get(&proprietary_tweak_key())
+                    .expect("Malformed PSBT: expected tweak");
+                let tx_hash = tx_hasher
+                    .taproot_key_spend_signature_hash(
+                        idx,
+                        &Prevouts::All(&prevouts),
+                        SchnorrSighashType::Default,
+                    )
+                    .expect("Failed to create taproot sighash");
+
+                let commitments = signers
+                    .iter()
+                    .map(|peer| {
+                        let commitments = state
+                            .commitments
+                            .get(peer)
+                            .ok_or(FrostError::MissingPeer(*peer))?;
+                        Ok((*peer, commitments[idx]))
+                    })
+                    .collect::<Result<_, FrostError>>()?;
+
+                SigningSession::new(
+                    &self.secp,
+                    frost_keys,
+                    &tweak_scalar(&frost_keys.group_key, tweak),
+                    tx_hash.into_inner(),
+                    commitments,
+                )
+            })
+            .collect()
+    }
+
+    /// Advances the FROST signing of every peg-out: the signers are fixed
+    /// once a threshold of peers committed to nonces, and the transaction is
+    /// finalized once all of them sent their shares. Signers that didn't
+    /// send their shares within [`FROST_SIGNING_TIMEOUT_EPOCHS`] are
+    /// excluded, the ones of them that took part in this epoch are
+    /// returned.
+    async fn process_frost_signing(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        consensus_peers: &BTreeSet<PeerId>,
+    ) -> Vec<PeerId> {
+        let frost_keys = match &self.cfg.consensus.frost_keys {
+            Some(frost_keys) => frost_keys,
+            None => return vec![],
+        };
+        let peers = frost_keys.verification_shares.len();
+        let threshold = frost_keys.threshold();
+
+        let states = dbtx
+            .find_by_prefix(&FrostSigningStatePrefix)
+            .await
+            .collect::<Vec<(FrostSigningStateKey, FrostSigningState)>>()
+            .await;
+
+        let mut drop_peers = vec![];
+        for (key, mut state) in states {
+            let txid = key.0;
+            let unsigned = dbtx
+                .get_value(&UnsignedTransactionKey(txid))
+                .await
+                .expect("signing state is removed with the transaction");
+
+            if state.signers.is_none() && state.commitments.len() >= threshold {
+                // The commitments are part of consensus, so every peer fixes
+                // the same signers
+                state.signers = Some(state.commitments.keys().copied().collect());
+                dbtx.remove_entry(&PegOutNoncesCI(txid)).await;
+                match self.frost_sessions(&unsigned.psbt, &state) {
+                    Ok(sessions) => self.frost_sign(dbtx, txid, &state, &sessions).await,
+                    Err(error) => {
+                        warn!("Restarting FROST signing of PSBT {}: {}", txid, error);
+                        state.restart([], peers, threshold);
+                    }
+                }
+            } else if let Some(signers) = state.signers.clone() {
+                if signers.iter().all(|peer| state.shares.contains_key(peer)) {
+                    match self.finalize_frost_psbt(unsigned.clone(), &state) {
+                        Ok(pending_tx) => {
+                            dbtx.insert_new_entry(&PendingTransactionKey(txid), &pending_tx)
+                                .await;
+                            dbtx.remove_entry(&key).await;
+                            dbtx.remove_entry(&PegOutNoncesCI(txid)).await;
+                            dbtx.remove_entry(&PegOutSignatureSharesCI(txid)).await;
+                            dbtx.remove_entry(&FrostNonceAttemptKey(txid)).await;
+                            dbtx.remove_entry(&UnsignedTransactionKey(txid)).await;
+                            self.discard_frost_nonces(txid).await;
+                            continue;
+                        }
+                        Err(error) => {
+                            warn!("Unable to finalize PSBT due to {:?}", error);
+                            state.restart([], peers, threshold);
+                        }
+                    }
+                } else {
+                    // Counted in consensus, so every peer times out in the
+                    // same epoch
+                    state.epochs_waited += 1;
+                    if state.epochs_waited >= FROST_SIGNING_TIMEOUT_EPOCHS {
+                        let missing = signers
+                            .iter()
+                            .filter(|peer| !state.shares.contains_key(peer))
+                            .copied()
+                            .collect::<Vec<_>>();
+                        for peer in &missing {
+                            error!(
+                                "{:?} didn't contribute FROST shares to PSBT {} in time",
+                                peer, txid
+                            );
+                        }
+                        drop_peers.extend(
+                            missing
+                                .iter()
+                                .filter(|peer| consensus_peers.contains(peer))
+                                .copied(),
+                        );
+                        state.restart(missing, peers, threshold);
+                    }
+                }
+            }
+
+            dbtx.insert_entry(&key, &state).await;
+            self.refresh_frost_nonces(dbtx, txid, unsigned.psbt.inputs.len(), &state)
+                .await;
+        }
+        drop_peers
+    }
+
+    /// Creates our signature shares if we are one of the signers, the signer
+    /// deletes our nonces when signing with them. If it lost them in a
+    /// restart we can't sign, time out and are excluded from the attempt.
+    async fn frost_sign(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        txid: Txid,
+        state: &FrostSigningState,
+        sessions: &[SigningSession],
+    ) {
+        if !matches!(&state.signers, Some(signers) if signers.contains(&self.our_id)) {
+            return;
+        }
+
+        if dbtx.remove_entry(&FrostNonceAttemptKey(txid)).await != Some(state.attempt) {
+            warn!("Missing our FROST nonces for PSBT {}", txid);
+            return;
+        }
+
+        let packages = sessions
+            .iter()
+            .map(|session| (session.signing_package().clone(), *session.contract_tweak()))
+            .collect::<Vec<_>>();
+        let shares = match self.signer.sign_frost(txid.into_inner(), &packages).await {
+            Ok(shares) => shares.into_iter().map(SignatureShare).collect(),
+            Err(error) => {
+                warn!("Unable to sign PSBT {} with FROST: {}", txid, error);
+                return;
+            }
+        };
+        dbtx.insert_entry(
+            &PegOutSignatureSharesCI(txid),
+            &PegOutSignatureSharesItem {
+                txid,
+                attempt: state.attempt,
+                shares,
+            },
+        )
+        .await;
+    }
+
+    /// Commits to fresh nonces whenever a new signing attempt starts that we
+    /// take part in
+    async fn refresh_frost_nonces(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        txid: Txid,
+        inputs: usize,
+        state: &FrostSigningState,
+    ) {
+        if state.excluded.contains(&self.our_id) {
+            dbtx.remove_entry(&PegOutNoncesCI(txid)).await;
+            dbtx.remove_entry(&PegOutSignatureSharesCI(txid)).await;
+            if dbtx
+                .remove_entry(&FrostNonceAttemptKey(txid))
+                .await
+                .is_some()
+            {
+                self.discard_frost_nonces(txid).await;
+            }
+            return;
+        }
+
+        let has_nonces = dbtx.get_value(&FrostNonceAttemptKey(txid)).await == Some(state.attempt);
+        if has_nonces || state.signers.is_some() {
+            return;
+        }
+
+        // Replaces the signer's nonces of an earlier attempt
+        let commitments = match self.signer.frost_commit(txid.into_inner(), inputs).await {
+            Ok(commitments) => commitments.into_iter().map(NonceCommitment).collect(),
+            Err(error) => {
+                warn!("Unable to create FROST nonces for PSBT {}: {}", txid, error);
+                return;
+            }
+        };
+        dbtx.remove_entry(&PegOutSignatureSharesCI(txid)).await;
+        dbtx.insert_entry(&FrostNonceAttemptKey(txid), &state.attempt)
+            .await;
+        dbtx.insert_entry(
+            &PegOutNoncesCI(txid),
+            &PegOutNoncesItem {
+                txid,
+                attempt: state.attempt,
+                commitments,
+            },
+        )
+        .await;
+    }
+
+    /// Deletes nonces the signer holds for a peg-out we won't sign anymore
+    async fn discard_frost_nonces(&self, txid: Txid) {
+        if let Err(error) = self.signer.frost_discard(txid.into_inner()).await {
+            warn!(
+                "Unable to discard FROST nonces for PSBT {}: {}",
+                txid, error
+            );
         }
+    }
 
-        let tx = unsigned.psbt.clone().extract_tx();
+    /// Aggregates the signature shares into a key spend signature for every
+    /// input
+    fn finalize_frost_psbt(
+        &self,
+        mut unsigned: UnsignedTransaction,
+        state: &FrostSigningState,
+    ) -> Result<PendingTransaction, ProcessPegOutSigError> {
+        let sessions = self.frost_sessions(&unsigned.psbt, state)?;
+        for (idx, (input, session)) in unsigned.psbt.inputs.iter_mut().zip(sessions).enumerate() {
+            let shares = state
+                .shares
+                .iter()
+                .map(|(peer, shares)| (*peer, shares[idx]))
+                .collect();
+            let signature = session.aggregate(&self.secp, &shares)?;
+            input.final_script_witness = Some(Witness::from_vec(vec![signature[..].to_vec()]));
+        }
 
-        Ok(PendingTransaction {
-            tx,
-            tweak: change_tweak,
-            change: unsigned.change,
-            destination: unsigned.destination,
-            fees: unsigned.fees,
-            selected_utxos: unsigned.selected_utxos,
-            peg_out_amount: unsigned.peg_out_amount,
-            rbf: unsigned.rbf,
-        })
+        Self::pending_transaction(unsigned)
     }
 
     /// Calculates all the round items from peers, returning the same consensus
@@ -901,13 +2336,154 @@ impl Wallet {
         dbtx.get_value(&RoundConsensusKey).await
     }
 
-    pub async fn target_height(&self) -> u32 {
-        let our_network_height = self
-            .btc_rpc
-            .get_block_height()
+    /// Queries our bitcoind for the block height and fee rate we propose, gives
+    /// up after [`BITCOIND_PROPOSAL_TIMEOUT`]
+    async fn query_chain_state(&self) -> anyhow::Result<ChainState> {
+        let query = async {
+            let block_height = self.btc_rpc.get_block_height().await?;
+            let fee_rate = self
+                .btc_rpc
+                .get_fee_rate(CONFIRMATION_TARGET)
+                .await?
+                .unwrap_or(self.cfg.consensus.default_fee);
+            Ok(ChainState {
+                block_height,
+                fee_rate,
+            })
+        };
+        match timeout(BITCOIND_PROPOSAL_TIMEOUT, query).await {
+            Ok(result) => result,
+            Err(_) => Err(format_err!("Request timed out")),
+        }
+    }
+
+    /// Height of the last final block of the chain bitcoind reported
+    fn target_height(&self, chain_state: ChainState) -> u32 {
+        (chain_state.block_height as u32).saturating_sub(self.cfg.consensus.finality_delay)
+    }
+
+    /// Compares the chain tip and the block at `target_height` of the primary
+    /// backend with the secondary one if it is configured
+    async fn cross_check_backends(
+        &self,
+        primary_height: u64,
+        target_height: u32,
+    ) -> Result<(), BackendDivergence> {
+        let Some(secondary) = &self.secondary_btc_rpc else {
+            return Ok(());
+        };
+
+        let secondary_height = query_backend(
+            secondary.get_block_height(),
+            BackendDivergence::SecondaryUnavailable,
+        )
+        .await?;
+        if primary_height.abs_diff(secondary_height)
+            > u64::from(self.cfg.local.max_backend_divergence)
+        {
+            return Err(BackendDivergence::Height {
+                primary: primary_height,
+                secondary: secondary_height,
+            });
+        }
+
+        let height = u64::from(target_height).min(secondary_height);
+        let primary_hash = query_backend(
+            self.btc_rpc.get_block_hash(height),
+            BackendDivergence::PrimaryUnavailable,
+        )
+        .await?;
+        let secondary_hash = query_backend(
+            secondary.get_block_hash(height),
+            BackendDivergence::SecondaryUnavailable,
+        )
+        .await?;
+        if primary_hash != secondary_hash {
+            return Err(BackendDivergence::BlockHash {
+                height,
+                primary: primary_hash,
+                secondary: secondary_hash,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Peg-outs still collecting signatures followed by the broadcast ones
+    async fn pending_peg_outs(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> Vec<PendingPegOut> {
+        let mut peg_outs = dbtx
+            .find_by_prefix(&UnsignedTransactionPrefixKey)
             .await
-            .expect("bitcoind rpc failed") as u32;
-        our_network_height.saturating_sub(self.cfg.consensus.finality_delay)
+            .map(|(key, tx)| PendingPegOut {
+                txid: key.0,
+                state: PendingPegOutState::Signing {
+                    signatures: tx.signatures.len(),
+                },
+                destination: tx.destination,
+                amount: tx.peg_out_amount,
+                fees: tx.fees,
+                rbf: tx.rbf.map(|rbf| rbf.txid),
+            })
+            .collect::<Vec<_>>()
+            .await;
+        peg_outs.extend(
+            dbtx.find_by_prefix(&PendingTransactionPrefixKey)
+                .await
+                .map(|(key, tx)| PendingPegOut {
+                    txid: key.0,
+                    state: PendingPegOutState::Broadcast,
+                    destination: tx.destination,
+                    amount: tx.peg_out_amount,
+                    fees: tx.fees,
+                    rbf: tx.rbf.map(|rbf| rbf.txid),
+                })
+                .collect::<Vec<_>>()
+                .await,
+        );
+        peg_outs
+    }
+
+    /// Restores the hashes of the blocks from `from_height` up to the
+    /// consensus height missing from the database, peg-ins into these blocks
+    /// are rejected otherwise. Headers we haven't synced yet are fetched from
+    /// bitcoind and validated.
+    async fn resync_block_hashes(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        from_height: u32,
+    ) -> anyhow::Result<BlockResyncResponse> {
+        let consensus_height = self.consensus_height(dbtx).await.unwrap_or(0);
+        let mut restored = 0;
+        for height in from_height..=consensus_height {
+            let block_hash = match dbtx.get_value(&BlockHeaderKey(height)).await {
+                Some(header) => header.block_hash(),
+                None => {
+                    let header = self.btc_rpc.get_block_header(height as u64).await?;
+                    let block_hash = self.validate_block_header(dbtx, height, &header).await?;
+                    dbtx.insert_entry(&BlockHeaderKey(height), &header).await;
+                    block_hash
+                }
+            };
+            if dbtx
+                .insert_entry(&BlockHashKey(block_hash), &())
+                .await
+                .is_none()
+            {
+                restored += 1;
+            }
+        }
+        info!(
+            from_height,
+            consensus_height, restored, "Resynced block hashes"
+        );
+
+        Ok(BlockResyncResponse {
+            consensus_height,
+            restored,
+        })
     }
 
     pub async fn consensus_height(&self, dbtx: &mut ModuleDatabaseTransaction<'_>) -> Option<u32> {
@@ -950,12 +2526,19 @@ impl Wallet {
             }
 
             // TODO: use batching for mainnet syncing
-            trace!(block = height, "Fetching block hash");
-            let block_hash = self
+            trace!(block = height, "Fetching block header");
+            let header = self
                 .btc_rpc
-                .get_block_hash(height as u64)
+                .get_block_header(height as u64)
                 .await
                 .expect("bitcoind rpc backend failed"); // TODO: use u64 for height everywhere
+            let block_hash = self
+                .validate_block_header(dbtx, height, &header)
+                .await
+                .unwrap_or_else(|e| {
+                    panic!("bitcoind served an invalid header for block {height}: {e}")
+                });
+            dbtx.insert_entry(&BlockHeaderKey(height), &header).await;
 
             let pending_transactions = dbtx
                 .find_by_prefix(&PendingTransactionPrefixKey)
@@ -972,12 +2555,166 @@ impl Wallet {
                 }
             }
 
-            dbtx.insert_new_entry(
-                &BlockHashKey(BlockHash::from_inner(block_hash.into_inner())),
-                &(),
-            )
+            dbtx.insert_new_entry(&BlockHashKey(block_hash), &()).await;
+        }
+
+        self.recognize_reused_address_deposits(dbtx, old_height + 1, new_height)
+            .await;
+    }
+
+    /// Adds deposits confirmed between `from_height` and `to_height` to our
+    /// UTXOs if they pay to an address we already received funds on. Users
+    /// sometimes send to an address again after the first deposit was claimed,
+    /// otherwise these funds would sit unnoticed since we only learn about
+    /// deposits when a client claims them.
+    ///
+    /// They are tracked as unclaimed deposits until a client claims them, see
+    /// the `unclaimed_deposits` endpoint.
+    async fn recognize_reused_address_deposits(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        from_height: u32,
+        to_height: u32,
+    ) {
+        let deposit_tweaks = dbtx
+            .find_by_prefix(&DepositTweakPrefix)
+            .await
+            .map(|(key, deposit_tweak)| (key.0, deposit_tweak))
+            .collect::<Vec<_>>()
             .await;
+
+        for (tweak, deposit_tweak) in deposit_tweaks {
+            let Some(descriptor) = self
+                .cfg
+                .consensus
+                .peg_in_descriptor_by_id(&deposit_tweak.descriptor_id)
+            else {
+                continue;
+            };
+            let script_pubkey = descriptor.tweak(&tweak, &self.secp).script_pubkey();
+            let transactions = self
+                .btc_rpc
+                .watch_script_history(&script_pubkey)
+                .await
+                .expect("bitcoind rpc backend failed");
+
+            for tx in transactions {
+                let txid = tx.txid();
+                let Ok(Some(tx_height)) = self.btc_rpc.get_tx_block_height(&txid).await else {
+                    continue;
+                };
+                if !(u64::from(from_height)..=u64::from(to_height)).contains(&tx_height) {
+                    continue;
+                }
+
+                for (vout, output) in tx.output.iter().enumerate() {
+                    let outpoint = bitcoin::OutPoint {
+                        txid,
+                        vout: vout as u32,
+                    };
+                    if output.script_pubkey != script_pubkey
+                        || dbtx.get_value(&UTXOKey(outpoint)).await.is_some()
+                        || dbtx.get_value(&FrozenUtxoKey(outpoint)).await.is_some()
+                    {
+                        continue;
+                    }
+
+                    let amount = bitcoin::Amount::from_sat(output.value);
+                    info!(
+                        %outpoint,
+                        %amount,
+                        is_change = deposit_tweak.is_change,
+                        "Recognized deposit to a reused address"
+                    );
+                    dbtx.insert_entry(&UTXOKey(outpoint), &SpendableUTXO { tweak, amount })
+                        .await;
+                    dbtx.insert_entry(&UTXODescriptorKey(outpoint), &deposit_tweak.descriptor_id)
+                        .await;
+                    dbtx.insert_entry(
+                        &UnclaimedDepositKey(outpoint),
+                        &UnclaimedDeposit {
+                            tweak,
+                            amount,
+                            block_height: tx_height as u32,
+                            is_change: deposit_tweak.is_change,
+                        },
+                    )
+                    .await;
+                }
+            }
+        }
+    }
+
+    /// Deposits to reused addresses no e-cash was issued for yet
+    pub async fn unclaimed_deposits(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+    ) -> Vec<(bitcoin::OutPoint, UnclaimedDeposit)> {
+        dbtx.find_by_prefix(&UnclaimedDepositPrefix)
+            .await
+            .map(|(key, deposit)| (key.0, deposit))
+            .collect()
+            .await
+    }
+
+    /// Validates the header at `height` against the previously synced headers,
+    /// the first header synced has nothing to be validated against and is
+    /// trusted
+    async fn validate_block_header(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        height: u32,
+        header: &BlockHeader,
+    ) -> Result<BlockHash, HeaderChainError> {
+        let Some(prev) = dbtx
+            .get_value(&BlockHeaderKey(height.saturating_sub(1)))
+            .await
+        else {
+            info!(height, "No previous header synced, trusting block header");
+            return Ok(header.block_hash());
+        };
+
+        let params = Params::new(self.cfg.consensus.network);
+        let expected_bits = if params.no_pow_retargeting {
+            Some(prev.bits)
+        } else if header_chain::is_retarget_height(&params, height) {
+            let period_start = height - params.difficulty_adjustment_interval() as u32;
+            dbtx.get_value(&BlockHeaderKey(period_start))
+                .await
+                .map(|start| header_chain::retarget(&params, &start, &prev))
+        } else if params.allow_min_difficulty_blocks {
+            self.expected_min_difficulty_bits(dbtx, &params, height, header, &prev)
+                .await
+        } else {
+            Some(prev.bits)
+        };
+
+        header_chain::validate_header(&params, header, &prev, expected_bits)
+    }
+
+    /// Networks like testnet allow blocks with the minimum difficulty if no
+    /// block was found for twice the target spacing, otherwise blocks have the
+    /// difficulty of the last block that wasn't mined with the minimum one
+    async fn expected_min_difficulty_bits(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        params: &Params,
+        height: u32,
+        header: &BlockHeader,
+        prev: &BlockHeader,
+    ) -> Option<u32> {
+        let min_bits = header_chain::min_difficulty_bits(params);
+        if u64::from(header.time) > u64::from(prev.time) + 2 * params.pow_target_spacing {
+            return Some(min_bits);
+        }
+
+        let mut last = *prev;
+        let mut last_height = height - 1;
+        while last.bits == min_bits && !header_chain::is_retarget_height(params, last_height) {
+            last_height -= 1;
+            last = dbtx.get_value(&BlockHeaderKey(last_height)).await?;
         }
+        Some(last.bits)
     }
 
     /// Add a change UTXO to our spendable UTXO database after it was included
@@ -989,26 +2726,66 @@ impl Wallet {
     ) {
         self.remove_rbf_transactions(dbtx, pending_tx).await;
 
-        let script_pk = self
+        // The spent UTXOs are gone for good once the transaction confirmed
+        for (utxo_key, _) in &pending_tx.selected_utxos {
+            dbtx.remove_entry(&UTXODescriptorKey(utxo_key.0)).await;
+        }
+
+        // Change is paid to the current descriptor, unless the transaction was
+        // created before the descriptor was rotated. If the change was split
+        // every output has its own tweak, derived from the one of the transaction.
+        let change_tweaks = (0..pending_tx.tx.output.len())
+            .map(|index| {
+                let tweak: [u8; 32] = change_output_tweak(&pending_tx.tweak, index)
+                    .try_into()
+                    .expect("change tweaks are 32 bytes");
+                tweak
+            })
+            .collect::<Vec<_>>();
+        let script_pks = self
             .cfg
             .consensus
-            .peg_in_descriptor
-            .tweak(&pending_tx.tweak, &self.secp)
-            .script_pubkey();
+            .all_peg_in_descriptors()
+            .flat_map(|descriptor| {
+                change_tweaks.iter().map(move |tweak| {
+                    (
+                        *tweak,
+                        descriptor.tweak(tweak, &self.secp).script_pubkey(),
+                        PegInDescriptorId::new(descriptor),
+                    )
+                })
+            })
+            .collect::<Vec<_>>();
         for (idx, output) in pending_tx.tx.output.iter().enumerate() {
-            if output.script_pubkey == script_pk {
-                dbtx.insert_entry(
-                    &UTXOKey(bitcoin::OutPoint {
-                        txid: pending_tx.tx.txid(),
-                        vout: idx as u32,
-                    }),
-                    &SpendableUTXO {
-                        tweak: pending_tx.tweak,
-                        amount: bitcoin::Amount::from_sat(output.value),
-                    },
-                )
+            let Some((tweak, _, descriptor_id)) = script_pks
+                .iter()
+                .find(|(_, script_pk, _)| output.script_pubkey == *script_pk)
+            else {
+                continue;
+            };
+
+            let outpoint = bitcoin::OutPoint {
+                txid: pending_tx.tx.txid(),
+                vout: idx as u32,
+            };
+            dbtx.insert_entry(
+                &UTXOKey(outpoint),
+                &SpendableUTXO {
+                    tweak: *tweak,
+                    amount: bitcoin::Amount::from_sat(output.value),
+                },
+            )
+            .await;
+            dbtx.insert_entry(&UTXODescriptorKey(outpoint), descriptor_id)
                 .await;
-            }
+            dbtx.insert_entry(
+                &DepositTweakKey(*tweak),
+                &DepositTweak {
+                    descriptor_id: *descriptor_id,
+                    is_change: true,
+                },
+            )
+            .await;
         }
     }
 
@@ -1051,12 +2828,264 @@ impl Wallet {
         }
     }
 
-    async fn block_is_known(
+    /// Blocks are only known once their header was validated when syncing up
+    /// to the consensus height
+    async fn block_is_known(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        block_hash: BlockHash,
+    ) -> bool {
+        dbtx.get_value(&BlockHashKey(block_hash)).await.is_some()
+    }
+
+    /// Signs `tx` and stores it with our signatures, which are sent to the
+    /// other guardians as consensus items
+    async fn sign_unsigned_tx(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        mut tx: UnsignedTransaction,
+    ) -> Txid {
+        if self.cfg.consensus.frost_keys.is_some() {
+            return self.start_frost_signing(dbtx, tx).await;
+        }
+
+        self.offline_wallet().sign_psbt(&mut tx.psbt).await;
+        let txid = tx.psbt.unsigned_tx.txid();
+        info!(
+            %txid,
+            "Signing peg out",
+        );
+
+        let sigs = tx
+            .psbt
+            .inputs
+            .iter_mut()
+            .map(|input| {
+                assert_eq!(
+                    input.partial_sigs.len(),
+                    1,
+                    "There was already more than one (our) or no signatures in input"
+                );
+
+                // TODO: don't put sig into PSBT in the first place
+                // We actually take out our own signature so everyone finalizes the tx in the
+                // same epoch.
+                let sig = std::mem::take(&mut input.partial_sigs)
+                    .into_values()
+                    .next()
+                    .expect("asserted previously");
+
+                // We drop SIGHASH_ALL, because we always use that and it is only present in the
+                // PSBT for compatibility with other tools.
+                secp256k1::ecdsa::Signature::from_der(&sig.to_vec()[..sig.to_vec().len() - 1])
+                    .expect("we serialized it ourselves that way")
+            })
+            .collect::<Vec<_>>();
+
+        // Delete used UTXOs
+        for input in tx.psbt.unsigned_tx.input.iter() {
+            dbtx.remove_entry(&UTXOKey(input.previous_output)).await;
+        }
+
+        dbtx.insert_new_entry(&UnsignedTransactionKey(txid), &tx)
+            .await;
+        dbtx.insert_new_entry(&PegOutTxSignatureCI(txid), &sigs)
+            .await;
+        txid
+    }
+
+    /// Stores `tx` to be signed with FROST, which starts with every peer
+    /// committing to nonces for its inputs
+    async fn start_frost_signing(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        tx: UnsignedTransaction,
+    ) -> Txid {
+        let txid = tx.psbt.unsigned_tx.txid();
+        info!(
+            %txid,
+            "Signing peg out with FROST",
+        );
+
+        // Delete used UTXOs
+        for input in tx.psbt.unsigned_tx.input.iter() {
+            dbtx.remove_entry(&UTXOKey(input.previous_output)).await;
+        }
+
+        let state = FrostSigningState::default();
+        dbtx.insert_new_entry(&FrostSigningStateKey(txid), &state)
+            .await;
+        self.refresh_frost_nonces(dbtx, txid, tx.psbt.inputs.len(), &state)
+            .await;
+        dbtx.insert_new_entry(&UnsignedTransactionKey(txid), &tx)
+            .await;
+        txid
+    }
+
+    /// Verifies a peg-in proof against our descriptors, peg-ins to legacy
+    /// descriptors are accepted as long as clients still use them
+    fn verify_peg_in(&self, input: &WalletInput) -> Result<PegInDescriptorId, PegInProofError> {
+        let current = &self.cfg.consensus.peg_in_descriptor;
+        let error = match input.verify(&self.secp, current) {
+            Ok(()) => return Ok(PegInDescriptorId::new(current)),
+            Err(error) => error,
+        };
+
+        self.cfg
+            .consensus
+            .legacy_peg_in_descriptors
+            .iter()
+            .find(|descriptor| input.verify(&self.secp, descriptor).is_ok())
+            .map(PegInDescriptorId::new)
+            .ok_or(error)
+    }
+
+    /// Returns the descriptor `outpoint` is locked to
+    async fn utxo_descriptor(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        outpoint: bitcoin::OutPoint,
+    ) -> &PegInDescriptor {
+        match dbtx.get_value(&UTXODescriptorKey(outpoint)).await {
+            Some(id) => self
+                .cfg
+                .consensus
+                .peg_in_descriptor_by_id(&id)
+                .unwrap_or_else(|| panic!("Descriptor of unswept UTXO {outpoint} is missing")),
+            None => self.cfg.consensus.original_peg_in_descriptor(),
+        }
+    }
+
+    /// Returns the descriptors of the `outpoints` that aren't locked to the
+    /// current descriptor
+    async fn legacy_utxos(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        outpoints: impl IntoIterator<Item = bitcoin::OutPoint>,
+    ) -> BTreeMap<bitcoin::OutPoint, &PegInDescriptor> {
+        let mut legacy = BTreeMap::new();
+        if self.cfg.consensus.legacy_peg_in_descriptors.is_empty() {
+            return legacy;
+        }
+
+        for outpoint in outpoints {
+            let descriptor = self.utxo_descriptor(dbtx, outpoint).await;
+            if descriptor != &self.cfg.consensus.peg_in_descriptor {
+                legacy.insert(outpoint, descriptor);
+            }
+        }
+        legacy
+    }
+
+    /// Wallet able to spend `utxos`, which may be locked to legacy descriptors
+    async fn spending_wallet(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        utxos: &[(UTXOKey, SpendableUTXO)],
+    ) -> StatelessWallet {
+        let legacy_utxos = self
+            .legacy_utxos(dbtx, utxos.iter().map(|(key, _)| key.0))
+            .await;
+        StatelessWallet {
+            legacy_utxos,
+            ..self.offline_wallet()
+        }
+    }
+
+    /// Moves up to [`MAX_SWEEP_INPUTS`] UTXOs of legacy descriptors into the
+    /// current descriptor, unless the fees would exceed their value
+    async fn sweep_legacy_utxos(
+        &self,
+        dbtx: &mut ModuleDatabaseTransaction<'_>,
+        round_consensus: &RoundConsensus,
+    ) {
+        let utxos = self.available_utxos(dbtx).await;
+        let wallet = self.spending_wallet(dbtx, &utxos).await;
+        let mut legacy_utxos = utxos
+            .into_iter()
+            .filter(|(key, _)| wallet.legacy_utxos.contains_key(&key.0))
+            .collect::<Vec<_>>();
+        if legacy_utxos.is_empty() {
+            return;
+        }
+
+        // Sweep the largest UTXOs first, they are the most worth their fees
+        legacy_utxos
+            .sort_by(|(a_key, a), (b_key, b)| b.amount.cmp(&a.amount).then(a_key.0.cmp(&b_key.0)));
+        legacy_utxos.truncate(MAX_SWEEP_INPUTS);
+
+        let tx = match wallet.create_sweep_tx(
+            legacy_utxos,
+            round_consensus.fee_rate,
+            &round_consensus.randomness_beacon,
+        ) {
+            Ok(tx) => tx,
+            Err(error) => {
+                debug!(?error, "Not sweeping legacy UTXOs at the current fee rate");
+                return;
+            }
+        };
+
+        info!(
+            inputs = tx.selected_utxos.len(),
+            sats = tx.change.to_sat(),
+            "Sweeping UTXOs of legacy descriptors"
+        );
+        self.sign_unsigned_tx(dbtx, tx).await;
+    }
+
+    pub async fn descriptor_migration_status(
         &self,
         dbtx: &mut ModuleDatabaseTransaction<'_>,
-        block_hash: BlockHash,
-    ) -> bool {
-        dbtx.get_value(&BlockHashKey(block_hash)).await.is_some()
+    ) -> DescriptorMigrationStatus {
+        if self.cfg.consensus.legacy_peg_in_descriptors.is_empty() {
+            return DescriptorMigrationStatus::NotMigrating;
+        }
+
+        let utxos = self.available_utxos(dbtx).await;
+        let legacy = self
+            .legacy_utxos(dbtx, utxos.iter().map(|(key, _)| key.0))
+            .await;
+        if !legacy.is_empty() {
+            let remaining = utxos
+                .iter()
+                .filter(|(key, _)| legacy.contains_key(&key.0))
+                .map(|(_, utxo)| utxo.amount)
+                .sum();
+            return DescriptorMigrationStatus::Sweeping {
+                remaining_utxos: legacy.len() as u64,
+                remaining,
+            };
+        }
+
+        // Transactions can be replaced with RBF, so their inputs count until they confirm
+        let mut in_flight = dbtx
+            .find_by_prefix(&UnsignedTransactionPrefixKey)
+            .await
+            .map(|(_, tx)| tx.selected_utxos)
+            .collect::<Vec<_>>()
+            .await;
+        in_flight.extend(
+            dbtx.find_by_prefix(&PendingTransactionPrefixKey)
+                .await
+                .map(|(_, tx)| tx.selected_utxos)
+                .collect::<Vec<_>>()
+                .await,
+        );
+
+        let mut transactions = 0;
+        for selected_utxos in in_flight {
+            let outpoints = selected_utxos.into_iter().map(|(key, _)| key.0);
+            if !self.legacy_utxos(dbtx, outpoints).await.is_empty() {
+                transactions += 1;
+            }
+        }
+
+        if transactions == 0 {
+            DescriptorMigrationStatus::Complete
+        } else {
+            DescriptorMigrationStatus::AwaitingConfirmation { transactions }
+        }
     }
 
     async fn create_peg_out_tx(
@@ -1070,27 +3099,32 @@ impl Wallet {
             .unwrap()
             .randomness_beacon;
 
+        let available_utxos = self.available_utxos(dbtx).await;
         match output {
-            WalletOutput::PegOut(peg_out) => self.offline_wallet().create_tx(
-                peg_out.amount,
-                peg_out.recipient.script_pubkey(),
-                vec![],
-                self.available_utxos(dbtx).await,
-                peg_out.fees.fee_rate,
-                &change_tweak,
-                None,
-            ),
+            WalletOutput::PegOut(peg_out) => self
+                .spending_wallet(dbtx, &available_utxos)
+                .await
+                .create_tx(
+                    peg_out.amount,
+                    peg_out.recipient.script_pubkey(),
+                    vec![],
+                    available_utxos,
+                    peg_out.fees.fee_rate,
+                    &change_tweak,
+                    None,
+                ),
             WalletOutput::Rbf(rbf) => {
                 let tx = dbtx
                     .get_value(&PendingTransactionKey(rbf.txid))
                     .await
                     .ok_or(WalletError::RbfTransactionIdNotFound)?;
 
-                self.offline_wallet().create_tx(
+                let all_utxos = [tx.selected_utxos.clone(), available_utxos.clone()].concat();
+                self.spending_wallet(dbtx, &all_utxos).await.create_tx(
                     tx.peg_out_amount,
                     tx.destination,
                     tx.selected_utxos,
-                    self.available_utxos(dbtx).await,
+                    available_utxos,
                     tx.fees.fee_rate,
                     &change_tweak,
                     Some(rbf.clone()),
@@ -1125,12 +3159,49 @@ impl Wallet {
     fn offline_wallet(&self) -> StatelessWallet {
         StatelessWallet {
             descriptor: &self.cfg.consensus.peg_in_descriptor,
-            secret_key: &self.cfg.private.peg_in_key,
+            legacy_utxos: BTreeMap::new(),
+            public_key: self.peg_in_public_key,
+            signer: self.signer.as_ref(),
             secp: &self.secp,
+            change_split: &self.cfg.consensus.change_split,
         }
     }
 }
 
+/// Maximum number of legacy UTXOs swept per block, keeps the sweep
+/// transactions within standardness limits
+const MAX_SWEEP_INPUTS: usize = 50;
+
+/// How long we wait for a bitcoin backend while cross-checking them, their
+/// clients retry failed requests until the task is shut down
+const BACKEND_CROSS_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long we wait for our bitcoind when proposing before falling back to
+/// the last chain state it reported, see [`bitcoind_outage`]
+const BITCOIND_PROPOSAL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the block height is polled while waiting for a proposal, if the
+/// bitcoin backend doesn't notify us of new blocks
+const PROPOSAL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often we check for new votes while waiting for a proposal, new blocks
+/// wake us up right away
+const PROPOSAL_POLL_WITH_BLOCK_EVENTS: Duration = Duration::from_secs(10);
+
+/// How long the admin status waits for our bitcoind
+const BITCOIND_STATUS_TIMEOUT: Duration = Duration::from_secs(5);
+
+async fn query_backend<T>(
+    request: impl Future<Output = anyhow::Result<T>>,
+    unavailable: fn(String) -> BackendDivergence,
+) -> Result<T, BackendDivergence> {
+    match timeout(BACKEND_CROSS_CHECK_TIMEOUT, request).await {
+        Ok(Ok(value)) => Ok(value),
+        Ok(Err(e)) => Err(unavailable(e.to_string())),
+        Err(_) => Err(unavailable("Request timed out".to_string())),
+    }
+}
+
 #[instrument(level = "debug", skip_all)]
 pub async fn run_broadcast_pending_tx(db: Database, rpc: DynBitcoindRpc, tg_handle: &TaskHandle) {
     while !tg_handle.is_shutting_down() {
@@ -1165,15 +3236,24 @@ pub async fn broadcast_pending_tx(mut dbtx: DatabaseTransaction<'_>, rpc: &DynBi
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash, Deserialize, Serialize, Encodable, Decodable)]
-pub struct WalletVerificationCache;
+/// Peg-in proofs of the epoch that passed verification, identified by their
+/// outpoint and tweak, with the descriptor they pay to
+#[derive(Debug, Clone)]
+pub struct WalletVerificationCache {
+    valid_peg_ins: BTreeMap<(bitcoin::OutPoint, secp256k1::XOnlyPublicKey), PegInDescriptorId>,
+}
 
 impl fedimint_core::server::VerificationCache for WalletVerificationCache {}
 
 struct StatelessWallet<'a> {
     descriptor: &'a Descriptor<CompressedPublicKey>,
-    secret_key: &'a secp256k1::SecretKey,
+    /// Descriptors of the UTXOs that aren't locked to `descriptor`
+    legacy_utxos: BTreeMap<bitcoin::OutPoint, &'a PegInDescriptor>,
+    /// Our peg-in key, the secret key is held by `signer`
+    public_key: secp256k1::PublicKey,
+    signer: &'a dyn IGuardianSigner,
     secp: &'a secp256k1::Secp256k1<secp256k1::All>,
+    change_split: &'a ChangeSplitConsensus,
 }
 
 impl<'a> StatelessWallet<'a> {
@@ -1261,24 +3341,26 @@ impl<'a> StatelessWallet<'a> {
         // We then go on to calculate the base size of the transaction `total_weight`
         // and the maximum weight per added input which we will add every time
         // we select an input.
+        //
+        // The change can be split into several outputs, each with its own tweak
+        // derived from `change_tweak`. Their number only depends on the fee rate.
         let change_script = self.derive_script(change_tweak);
-        let out_weight = (destination.len() * 4 + 1 + 32
-            // Add change script weight, it's very likely to be needed if not we just overpay in fees
-            + 1 // script len varint, 1 byte for all addresses we accept
+        let change_weight = (1 // script len varint, 1 byte for all addresses we accept
             + change_script.len() * 4 // script len
             + 32) as u64; // value
+        let change_outputs = self.change_split.change_outputs(fee_rate, change_weight);
+        let change_tweaks = (0..change_outputs)
+            .map(|index| change_output_tweak(change_tweak, index))
+            .collect::<Vec<_>>();
+        let change_dust = change_script.dust_value() * change_outputs as u64;
+        let out_weight = (destination.len() * 4 + 1 + 32) as u64
+            // Add change outputs weight, they are very likely needed, if not we overpay in fees
+            + change_weight * change_outputs as u64;
         let mut total_weight = 16 + // version
             12 + // up to 2**16-1 inputs
             12 + // up to 2**16-1 outputs
             out_weight + // weight of all outputs
             16; // lock time
-        let max_input_weight = (self
-            .descriptor
-            .max_satisfaction_weight()
-            .expect("is satisfyable") +
-            128 + // TxOutHash
-            16 + // TxOutIndex
-            16) as u64; // sequence
 
         // Ensure deterministic ordering of UTXOs for all peers
         included_utxos.sort_by_key(|(_, utxo)| utxo.amount);
@@ -1290,11 +3372,11 @@ impl<'a> StatelessWallet<'a> {
         let mut selected_utxos: Vec<(UTXOKey, SpendableUTXO)> = vec![];
         let mut fees = fee_rate.calculate_fee(total_weight);
 
-        while total_selected_value < peg_out_amount + change_script.dust_value() + fees {
+        while total_selected_value < peg_out_amount + change_dust + fees {
             match included_utxos.pop() {
                 Some((utxo_key, utxo)) => {
                     total_selected_value += utxo.amount;
-                    total_weight += max_input_weight;
+                    total_weight += self.max_input_weight(&utxo_key);
                     fees = fee_rate.calculate_fee(total_weight);
                     selected_utxos.push((utxo_key, utxo));
                 }
@@ -1305,21 +3387,26 @@ impl<'a> StatelessWallet<'a> {
         // We always pay ourselves change back to ensure that we don't lose anything due
         // to dust
         let change = total_selected_value - fees - peg_out_amount;
-        let output: Vec<TxOut> = vec![
-            TxOut {
-                value: peg_out_amount.to_sat(),
-                script_pubkey: destination.clone(),
-            },
-            TxOut {
-                value: change.to_sat(),
-                script_pubkey: change_script,
-            },
-        ];
-        let mut change_out = bitcoin::util::psbt::Output::default();
-        change_out
-            .proprietary
-            .insert(proprietary_tweak_key(), change_tweak.to_vec());
-
+        let change_amounts = split_change(
+            change,
+            change_outputs,
+            change_script.dust_value(),
+            change_tweak,
+        );
+        let output: Vec<TxOut> = std::iter::once(TxOut {
+            value: peg_out_amount.to_sat(),
+            script_pubkey: destination.clone(),
+        })
+        .chain(
+            change_tweaks
+                .iter()
+                .zip(&change_amounts)
+                .map(|(tweak, amount)| TxOut {
+                    value: amount.to_sat(),
+                    script_pubkey: self.derive_script(tweak),
+                }),
+        )
+        .collect();
         info!(
             inputs = selected_utxos.len(),
             input_sats = total_selected_value.to_sat(),
@@ -1327,6 +3414,7 @@ impl<'a> StatelessWallet<'a> {
             fees_sats = fees.to_sat(),
             fee_rate = fee_rate.sats_per_kvb,
             change_sats = change.to_sat(),
+            change_outputs,
             "Creating peg-out tx",
         );
 
@@ -1356,47 +3444,15 @@ impl<'a> StatelessWallet<'a> {
             unknown: Default::default(),
             inputs: selected_utxos
                 .iter()
-                .map(|(_utxo_key, utxo)| {
-                    let script_pubkey = self
-                        .descriptor
-                        .tweak(&utxo.tweak, self.secp)
-                        .script_pubkey();
-                    Input {
-                        non_witness_utxo: None,
-                        witness_utxo: Some(TxOut {
-                            value: utxo.amount.to_sat(),
-                            script_pubkey,
-                        }),
-                        partial_sigs: Default::default(),
-                        sighash_type: None,
-                        redeem_script: None,
-                        witness_script: Some(
-                            self.descriptor
-                                .tweak(&utxo.tweak, self.secp)
-                                .script_code()
-                                .expect("Failed to tweak descriptor"),
-                        ),
-                        bip32_derivation: Default::default(),
-                        final_script_sig: None,
-                        final_script_witness: None,
-                        ripemd160_preimages: Default::default(),
-                        sha256_preimages: Default::default(),
-                        hash160_preimages: Default::default(),
-                        hash256_preimages: Default::default(),
-                        proprietary: vec![(proprietary_tweak_key(), utxo.tweak.to_vec())]
-                            .into_iter()
-                            .collect(),
-                        tap_key_sig: Default::default(),
-                        tap_script_sigs: Default::default(),
-                        tap_scripts: Default::default(),
-                        tap_key_origins: Default::default(),
-                        tap_internal_key: Default::default(),
-                        tap_merkle_root: Default::default(),
-                        unknown: Default::default(),
-                    }
-                })
+                .map(|(utxo_key, utxo)| self.psbt_input(utxo_key, utxo))
+                .collect(),
+            outputs: std::iter::once(Default::default())
+                .chain(
+                    change_tweaks
+                        .iter()
+                        .map(|tweak| Self::change_psbt_output(tweak)),
+                )
                 .collect(),
-            outputs: vec![Default::default(), change_out],
         };
 
         Ok(UnsignedTransaction {
@@ -1414,7 +3470,146 @@ impl<'a> StatelessWallet<'a> {
         })
     }
 
-    fn sign_psbt(&self, psbt: &mut PartiallySignedTransaction) {
+    /// Creates a tx moving `utxos` into a single change output of the current
+    /// descriptor, fails if the fees exceed their value
+    fn create_sweep_tx(
+        &self,
+        utxos: Vec<(UTXOKey, SpendableUTXO)>,
+        fee_rate: Feerate,
+        change_tweak: &[u8],
+    ) -> Result<UnsignedTransaction, WalletError> {
+        let change_script = self.derive_script(change_tweak);
+        let total_weight = 16 + // version
+            12 + // up to 2**16-1 inputs
+            12 + // up to 2**16-1 outputs
+            (1 + change_script.len() * 4 + 32) as u64 + // change output
+            16 + // lock time
+            utxos
+                .iter()
+                .map(|(utxo_key, _)| self.max_input_weight(utxo_key))
+                .sum::<u64>();
+        let fees = fee_rate.calculate_fee(total_weight);
+        let total_value = utxos
+            .iter()
+            .map(|(_, utxo)| utxo.amount)
+            .sum::<bitcoin::Amount>();
+        if total_value < fees + change_script.dust_value() {
+            return Err(WalletError::NotEnoughSpendableUTXO);
+        }
+        let change = total_value - fees;
+
+        let transaction = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: utxos
+                .iter()
+                .map(|(utxo_key, _utxo)| TxIn {
+                    previous_output: utxo_key.0,
+                    script_sig: Default::default(),
+                    sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+                    witness: bitcoin::Witness::new(),
+                })
+                .collect(),
+            output: vec![TxOut {
+                value: change.to_sat(),
+                script_pubkey: change_script.clone(),
+            }],
+        };
+
+        let psbt = PartiallySignedTransaction {
+            unsigned_tx: transaction,
+            version: 0,
+            xpub: Default::default(),
+            proprietary: Default::default(),
+            unknown: Default::default(),
+            inputs: utxos
+                .iter()
+                .map(|(utxo_key, utxo)| self.psbt_input(utxo_key, utxo))
+                .collect(),
+            outputs: vec![Self::change_psbt_output(change_tweak)],
+        };
+
+        Ok(UnsignedTransaction {
+            psbt,
+            signatures: vec![],
+            change,
+            fees: PegOutFees {
+                fee_rate,
+                total_weight,
+            },
+            destination: change_script,
+            selected_utxos: utxos,
+            peg_out_amount: bitcoin::Amount::ZERO,
+            rbf: None,
+        })
+    }
+
+    fn utxo_descriptor(&self, utxo_key: &UTXOKey) -> &PegInDescriptor {
+        self.legacy_utxos
+            .get(&utxo_key.0)
+            .copied()
+            .unwrap_or(self.descriptor)
+    }
+
+    fn max_input_weight(&self, utxo_key: &UTXOKey) -> u64 {
+        (self
+            .utxo_descriptor(utxo_key)
+            .max_satisfaction_weight()
+            .expect("is satisfyable") +
+            128 + // TxOutHash
+            16 + // TxOutIndex
+            16) as u64 // sequence
+    }
+
+    fn psbt_input(&self, utxo_key: &UTXOKey, utxo: &SpendableUTXO) -> Input {
+        let descriptor = self.utxo_descriptor(utxo_key).tweak(&utxo.tweak, self.secp);
+        Input {
+            non_witness_utxo: None,
+            witness_utxo: Some(TxOut {
+                value: utxo.amount.to_sat(),
+                script_pubkey: descriptor.script_pubkey(),
+            }),
+            partial_sigs: Default::default(),
+            sighash_type: None,
+            redeem_script: None,
+            // Taproot key spends don't have a witness script
+            witness_script: match descriptor {
+                Descriptor::Wsh(_) => Some(
+                    descriptor
+                        .script_code()
+                        .expect("Failed to tweak descriptor"),
+                ),
+                _ => None,
+            },
+            bip32_derivation: Default::default(),
+            final_script_sig: None,
+            final_script_witness: None,
+            ripemd160_preimages: Default::default(),
+            sha256_preimages: Default::default(),
+            hash160_preimages: Default::default(),
+            hash256_preimages: Default::default(),
+            proprietary: vec![(proprietary_tweak_key(), utxo.tweak.to_vec())]
+                .into_iter()
+                .collect(),
+            tap_key_sig: Default::default(),
+            tap_script_sigs: Default::default(),
+            tap_scripts: Default::default(),
+            tap_key_origins: Default::default(),
+            tap_internal_key: Default::default(),
+            tap_merkle_root: Default::default(),
+            unknown: Default::default(),
+        }
+    }
+
+    fn change_psbt_output(change_tweak: &[u8]) -> bitcoin::util::psbt::Output {
+        let mut change_out = bitcoin::util::psbt::Output::default();
+        change_out
+            .proprietary
+            .insert(proprietary_tweak_key(), change_tweak.to_vec());
+        change_out
+    }
+
+    async fn sign_psbt(&self, psbt: &mut PartiallySignedTransaction) {
         let mut tx_hasher = SighashCache::new(&psbt.unsigned_tx);
 
         for (idx, (psbt_input, _tx_input)) in psbt
@@ -1423,14 +3618,10 @@ impl<'a> StatelessWallet<'a> {
             .zip(psbt.unsigned_tx.input.iter())
             .enumerate()
         {
-            let tweaked_secret = {
-                let tweak = psbt_input
-                    .proprietary
-                    .get(&proprietary_tweak_key())
-                    .expect("Malformed PSBT: expected tweak");
-
-                self.secret_key.tweak(tweak, self.secp)
-            };
+            let tweak = psbt_input
+                .proprietary
+                .get(&proprietary_tweak_key())
+                .expect("Malformed PSBT: expected tweak");
 
             let tx_hash = tx_hasher
                 .segwit_signature_hash(
@@ -1449,13 +3640,18 @@ impl<'a> StatelessWallet<'a> {
                 .expect("Failed to create segwit sighash");
 
             let signature = self
-                .secp
-                .sign_ecdsa(&Message::from_slice(&tx_hash[..]).unwrap(), &tweaked_secret);
+                .signer
+                .sign_ecdsa(
+                    &tweak_scalar(&self.public_key, tweak),
+                    &Message::from_slice(&tx_hash[..]).unwrap(),
+                )
+                .await
+                .expect("Guardian signer failed to sign peg-out");
 
             psbt_input.partial_sigs.insert(
                 bitcoin::PublicKey {
                     compressed: true,
-                    inner: secp256k1::PublicKey::from_secret_key(self.secp, &tweaked_secret),
+                    inner: self.public_key.tweak(tweak, self.secp),
                 },
                 EcdsaSig::sighash_all(signature),
             );
@@ -1510,14 +3706,61 @@ impl<'a> StatelessWallet<'a> {
     }
 }
 
+/// Tweak of the change output with `index`, the first one uses the change
+/// tweak of the transaction itself
+fn change_output_tweak(change_tweak: &[u8], index: usize) -> Vec<u8> {
+    if index == 0 {
+        return change_tweak.to_vec();
+    }
+    sha256::Hash::hash(&[change_tweak, &(index as u64).to_be_bytes()].concat())
+        .into_inner()
+        .to_vec()
+}
+
+/// Splits `change` into `outputs` amounts of at least `dust` each. The amounts
+/// are random but derived from `seed`, so all peers build the same outputs.
+fn split_change(
+    change: bitcoin::Amount,
+    outputs: usize,
+    dust: bitcoin::Amount,
+    seed: &[u8],
+) -> Vec<bitcoin::Amount> {
+    let spare = change
+        .to_sat()
+        .saturating_sub(dust.to_sat() * outputs as u64);
+    let weights = (0..outputs)
+        .map(|index| {
+            let hash = sha256::Hash::hash(
+                &[seed, b"change-split", &(index as u64).to_be_bytes()].concat(),
+            );
+            u128::from(u32::from_be_bytes(hash[..4].try_into().expect("4 bytes")))
+        })
+        .collect::<Vec<_>>();
+    let total_weight = weights.iter().sum::<u128>().max(1);
+
+    let mut amounts = weights
+        .iter()
+        .map(|weight| {
+            dust + bitcoin::Amount::from_sat((u128::from(spare) * weight / total_weight) as u64)
+        })
+        .collect::<Vec<_>>();
+    // Rounding leaves a few sats, which go to the last output
+    let assigned = amounts.iter().copied().sum::<bitcoin::Amount>();
+    if let Some(last) = amounts.last_mut() {
+        *last += change - assigned;
+    }
+    amounts
+}
+
 #[cfg(test)]
 mod tests {
-    use std::collections::BTreeSet;
+    use std::collections::{BTreeMap, BTreeSet};
     use std::str::FromStr;
 
     use bitcoin::Network::{Bitcoin, Testnet};
     use bitcoin::{Address, Amount, Network, OutPoint, Txid};
     use fedimint_core::{BitcoinHash, Feerate, PeerId};
+    use fedimint_wallet_common::config::{ChangeSplitConsensus, FeeSubsidyConsensus};
     use fedimint_wallet_common::{
         PegOut, PegOutFees, Rbf, RoundConsensus, RoundConsensusItem, WalletOutput,
     };
@@ -1525,7 +3768,8 @@ mod tests {
 
     use crate::common::PegInDescriptor;
     use crate::{
-        CompressedPublicKey, OsRng, SpendableUTXO, StatelessWallet, UTXOKey, Wallet, WalletError,
+        change_output_tweak, split_change, CompressedPublicKey, LocalSigner, OsRng, SpendableUTXO,
+        StatelessWallet, Tweakable, UTXOKey, Wallet, WalletError,
     };
 
     fn round_item(block_height: u32, fee_rate: u64, random: u8) -> RoundConsensusItem {
@@ -1592,12 +3836,16 @@ mod tests {
             .unwrap(),
         );
 
-        let (secret_key, _) = secp.generate_keypair(&mut OsRng);
+        let (secret_key, public_key) = secp.generate_keypair(&mut OsRng);
+        let signer = LocalSigner::default().with_ecdsa_key(secret_key);
 
         let wallet = StatelessWallet {
             descriptor: &descriptor,
-            secret_key: &secret_key,
+            legacy_utxos: BTreeMap::new(),
+            public_key,
+            signer: &signer,
             secp: &secp,
+            change_split: &ChangeSplitConsensus::default(),
         };
 
         let spendable = SpendableUTXO {
@@ -1673,6 +3921,186 @@ mod tests {
         assert_eq!(res, Err(WalletError::WrongNetwork(Testnet, Bitcoin)));
     }
 
+    #[test]
+    fn create_tx_splits_change() {
+        let secp = secp256k1::Secp256k1::new();
+        let descriptor = PegInDescriptor::Wsh(
+            Wsh::new_sortedmulti(
+                3,
+                (0..4)
+                    .map(|_| secp.generate_keypair(&mut OsRng))
+                    .map(|(_, key)| CompressedPublicKey { key })
+                    .collect(),
+            )
+            .unwrap(),
+        );
+        let (secret_key, public_key) = secp.generate_keypair(&mut OsRng);
+        let signer = LocalSigner::default().with_ecdsa_key(secret_key);
+
+        // A change output costs 169 sats at this fee rate, the budget covers two more
+        let wallet = StatelessWallet {
+            descriptor: &descriptor,
+            legacy_utxos: BTreeMap::new(),
+            public_key,
+            signer: &signer,
+            secp: &secp,
+            change_split: &ChangeSplitConsensus {
+                max_outputs: 5,
+                max_fee_overhead: Amount::from_sat(400),
+            },
+        };
+        let spendable = SpendableUTXO {
+            tweak: [0; 32],
+            amount: Amount::from_sat(100_000),
+        };
+        let recipient = Address::from_str("32iVBEu4dxkUQk9dJbZUiBiQdmypcEyJRf").unwrap();
+        let change_tweak = [7; 32];
+
+        let tx = wallet
+            .create_tx(
+                Amount::from_sat(10_000),
+                recipient.script_pubkey(),
+                vec![],
+                vec![(UTXOKey(OutPoint::null()), spendable)],
+                Feerate { sats_per_kvb: 1000 },
+                &change_tweak,
+                None,
+            )
+            .expect("is ok");
+
+        let change_outputs = &tx.psbt.unsigned_tx.output[1..];
+        assert_eq!(change_outputs.len(), 3);
+        assert_eq!(
+            change_outputs
+                .iter()
+                .map(|output| output.value)
+                .sum::<u64>(),
+            tx.change.to_sat()
+        );
+        for (index, output) in change_outputs.iter().enumerate() {
+            let tweak = change_output_tweak(&change_tweak, index);
+            assert_eq!(
+                output.script_pubkey,
+                descriptor.tweak(&tweak, &secp).script_pubkey()
+            );
+            assert_eq!(
+                tx.psbt.outputs[index + 1].proprietary.values().next(),
+                Some(&tweak)
+            );
+        }
+    }
+
+    #[test]
+    fn split_change_is_deterministic_and_above_dust() {
+        let dust = Amount::from_sat(330);
+        let change = Amount::from_sat(50_000);
+
+        let amounts = split_change(change, 4, dust, &[1; 32]);
+        assert_eq!(amounts.len(), 4);
+        assert_eq!(amounts.iter().copied().sum::<Amount>(), change);
+        assert!(amounts.iter().all(|amount| *amount >= dust));
+        assert_eq!(amounts, split_change(change, 4, dust, &[1; 32]));
+        assert_ne!(amounts, split_change(change, 4, dust, &[2; 32]));
+
+        assert_eq!(split_change(change, 1, dust, &[1; 32]), vec![change]);
+    }
+
+    #[test]
+    fn sweep_moves_legacy_utxos_to_current_descriptor() {
+        let secp = secp256k1::Secp256k1::new();
+        let keys = (0..4)
+            .map(|_| secp.generate_keypair(&mut OsRng))
+            .map(|(_, key)| CompressedPublicKey { key })
+            .collect::<Vec<_>>();
+        let legacy = PegInDescriptor::Wsh(Wsh::new_sortedmulti(3, keys.clone()).unwrap());
+        let current = PegInDescriptor::Wsh(Wsh::new_sortedmulti(2, keys).unwrap());
+
+        let (secret_key, public_key) = secp.generate_keypair(&mut OsRng);
+        let signer = LocalSigner::default().with_ecdsa_key(secret_key);
+
+        let legacy_key = UTXOKey(OutPoint::null());
+        let legacy_utxo = SpendableUTXO {
+            tweak: [1; 32],
+            amount: Amount::from_sat(100_000),
+        };
+        let wallet = StatelessWallet {
+            descriptor: &current,
+            legacy_utxos: BTreeMap::from([(legacy_key.0, &legacy)]),
+            public_key,
+            signer: &signer,
+            secp: &secp,
+            change_split: &ChangeSplitConsensus::default(),
+        };
+
+        let fee = Feerate { sats_per_kvb: 1000 };
+        let tx = wallet
+            .create_sweep_tx(
+                vec![(legacy_key.clone(), legacy_utxo.clone())],
+                fee,
+                &[2; 32],
+            )
+            .expect("is ok");
+
+        let legacy_tweaked = legacy.tweak(&legacy_utxo.tweak, &secp);
+        assert_eq!(
+            tx.psbt.inputs[0].witness_script,
+            Some(legacy_tweaked.script_code().unwrap())
+        );
+        assert_eq!(
+            tx.psbt.inputs[0]
+                .witness_utxo
+                .as_ref()
+                .unwrap()
+                .script_pubkey,
+            legacy_tweaked.script_pubkey()
+        );
+
+        let change_script = current.tweak(&[2; 32], &secp).script_pubkey();
+        assert_eq!(tx.psbt.unsigned_tx.output.len(), 1);
+        assert_eq!(tx.psbt.unsigned_tx.output[0].script_pubkey, change_script);
+        assert_eq!(tx.change + tx.fees.amount(), legacy_utxo.amount);
+
+        // Dust isn't worth sweeping
+        let dust = SpendableUTXO {
+            tweak: [1; 32],
+            amount: Amount::from_sat(500),
+        };
+        let tx = wallet.create_sweep_tx(vec![(legacy_key, dust)], fee, &[2; 32]);
+        assert_eq!(tx, Err(WalletError::NotEnoughSpendableUTXO));
+    }
+
+    #[test]
+    fn subsidizes_small_peg_outs() {
+        let subsidy = FeeSubsidyConsensus {
+            initial_rate_bps: 0,
+            max_peg_out_amount: Amount::from_sat(100_000),
+        };
+        let consensus_fee_rate = Feerate {
+            sats_per_kvb: 2_000,
+        };
+
+        assert_eq!(
+            subsidy.subsidized_fee_rate(2_500, Amount::from_sat(50_000), consensus_fee_rate),
+            Some(Feerate {
+                sats_per_kvb: 1_500
+            })
+        );
+        assert_eq!(
+            subsidy.subsidized_fee_rate(10_000, Amount::from_sat(100_000), consensus_fee_rate),
+            Some(Feerate { sats_per_kvb: 0 })
+        );
+
+        // Without a rate or above the maximum amount the full fee rate is paid
+        assert_eq!(
+            subsidy.subsidized_fee_rate(0, Amount::from_sat(50_000), consensus_fee_rate),
+            None
+        );
+        assert_eq!(
+            subsidy.subsidized_fee_rate(2_500, Amount::from_sat(100_001), consensus_fee_rate),
+            None
+        );
+    }
+
     fn rbf(sats_per_kvb: u64, total_weight: u64) -> WalletOutput {
         WalletOutput::Rbf(Rbf {
             fees: PegOutFees::new(sats_per_kvb, total_weight),
@@ -1914,6 +4342,23 @@ mod fedimint_migration_tests {
                                 "validate_migrations was not able to read any BlockHashes"
                             );
                         }
+                        // Added after v0, the snapshot doesn't contain them
+                        DbKeyPrefix::BlockHeader
+                        | DbKeyPrefix::UtxoDescriptor
+                        | DbKeyPrefix::FrostSigningState
+                        | DbKeyPrefix::PegOutNoncesCi
+                        | DbKeyPrefix::PegOutSignatureSharesCi
+                        | DbKeyPrefix::FrostNonceAttempt
+                        | DbKeyPrefix::FeeSubsidyPool
+                        | DbKeyPrefix::FeeSubsidyRate
+                        | DbKeyPrefix::FeeSubsidyRateVote
+                        | DbKeyPrefix::FeeSubsidyRateCi
+                        | DbKeyPrefix::DepositTweak
+                        | DbKeyPrefix::UnclaimedDeposit
+                        | DbKeyPrefix::FrozenUtxo
+                        | DbKeyPrefix::UtxoFreezeVote
+                        | DbKeyPrefix::UtxoFreezeCi
+                        | DbKeyPrefix::PegOutSignatureEpochs => {}
                         DbKeyPrefix::PegOutBitcoinOutPoint => {
                             let outpoints = dbtx
                                 .find_by_prefix(&PegOutBitcoinTransactionPrefix)
@@ -0,0 +1,204 @@
+//! Verification of peg-out signatures off the consensus hot path
+//!
+//! Checking the signatures of every peer for every input of a peg-out
+//! transaction is expensive, so instead of doing it in `end_consensus_epoch`
+//! the signatures are handed to worker tasks as soon as they arrive in
+//! `begin_consensus_epoch`. The results are applied to the PSBTs in the same
+//! order as before, so the outcome doesn't depend on which worker finishes
+//! first.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use bitcoin::secp256k1::{All, Message, Secp256k1};
+use bitcoin::util::psbt::PartiallySignedTransaction;
+use bitcoin::util::sighash::SighashCache;
+use bitcoin::{EcdsaSig, EcdsaSighashType, Txid};
+use fedimint_core::task::{block_in_place, TaskGroup};
+use fedimint_core::PeerId;
+use fedimint_wallet_common::keys::CompressedPublicKey;
+use fedimint_wallet_common::tweakable::Tweakable;
+use fedimint_wallet_common::{proprietary_tweak_key, PegOutSignatureItem, ProcessPegOutSigError};
+use secp256k1::ecdsa::Signature;
+use tokio::sync::{mpsc, oneshot};
+use tracing::debug;
+
+/// Number of tasks verifying signatures in parallel
+const WORKERS: usize = 4;
+
+/// Number of signatures waiting for verification before submitting blocks
+const QUEUE_SIZE: usize = 256;
+
+/// Signatures of a peer for every input of a PSBT, ready to be added to it
+pub type PartialSignatures = Vec<(bitcoin::PublicKey, EcdsaSig)>;
+
+type VerificationResult = Result<PartialSignatures, ProcessPegOutSigError>;
+
+struct Job {
+    peer_key: CompressedPublicKey,
+    psbt: PartiallySignedTransaction,
+    signature: PegOutSignatureItem,
+    result: oneshot::Sender<VerificationResult>,
+}
+
+struct PendingVerification {
+    signatures: Vec<Signature>,
+    result: oneshot::Receiver<VerificationResult>,
+}
+
+#[derive(Debug)]
+pub struct PegOutVerifier {
+    jobs: mpsc::Sender<Job>,
+    pending: Mutex<BTreeMap<(Txid, PeerId), PendingVerification>>,
+}
+
+impl std::fmt::Debug for PendingVerification {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PendingVerification")
+            .field("signatures", &self.signatures)
+            .finish()
+    }
+}
+
+impl PegOutVerifier {
+    pub async fn new(secp: &Secp256k1<All>, task_group: &mut TaskGroup) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>(QUEUE_SIZE);
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+
+        for worker in 0..WORKERS {
+            let receiver = receiver.clone();
+            let secp = secp.clone();
+            task_group
+                .spawn(
+                    format!("peg-out signature verifier {worker}"),
+                    |_handle| async move {
+                        loop {
+                            let Some(job) = receiver.lock().await.recv().await else {
+                                break;
+                            };
+                            let result = block_in_place(|| {
+                                verify_peg_out_signature(
+                                    &secp,
+                                    &job.peer_key,
+                                    &job.psbt,
+                                    &job.signature,
+                                )
+                            });
+                            // The epoch may have ended without waiting for the result
+                            let _ = job.result.send(result);
+                        }
+                    },
+                )
+                .await;
+        }
+
+        PegOutVerifier {
+            jobs: sender,
+            pending: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Queues the verification of the signatures `peer` contributed to `psbt`
+    pub async fn submit(
+        &self,
+        peer: PeerId,
+        peer_key: CompressedPublicKey,
+        psbt: PartiallySignedTransaction,
+        signature: PegOutSignatureItem,
+    ) {
+        let (sender, receiver) = oneshot::channel();
+        let pending = PendingVerification {
+            signatures: signature.signature.clone(),
+            result: receiver,
+        };
+        self.pending
+            .lock()
+            .expect("lock poisoned")
+            .insert((signature.txid, peer), pending);
+
+        let job = Job {
+            peer_key,
+            psbt,
+            signature,
+            result: sender,
+        };
+        if self.jobs.send(job).await.is_err() {
+            debug!("Verification workers shut down, verifying in the epoch instead");
+        }
+    }
+
+    /// Waits for the result of a verification queued with [`Self::submit`],
+    /// `None` if the signature wasn't queued and has to be verified directly
+    pub async fn take_result(
+        &self,
+        peer: PeerId,
+        signature: &PegOutSignatureItem,
+    ) -> Option<VerificationResult> {
+        let pending = self
+            .pending
+            .lock()
+            .expect("lock poisoned")
+            .remove(&(signature.txid, peer))?;
+
+        if pending.signatures != signature.signature {
+            return None;
+        }
+
+        pending.result.await.ok()
+    }
+
+    /// Drops the results no PSBT asked for
+    pub fn clear(&self) {
+        self.pending.lock().expect("lock poisoned").clear();
+    }
+}
+
+/// Verifies the signatures of a peer for every input of `psbt`
+pub fn verify_peg_out_signature(
+    secp: &Secp256k1<All>,
+    peer_key: &CompressedPublicKey,
+    psbt: &PartiallySignedTransaction,
+    signature: &PegOutSignatureItem,
+) -> VerificationResult {
+    if psbt.inputs.len() != signature.signature.len() {
+        return Err(ProcessPegOutSigError::WrongSignatureCount(
+            psbt.inputs.len(),
+            signature.signature.len(),
+        ));
+    }
+
+    let mut tx_hasher = SighashCache::new(&psbt.unsigned_tx);
+    psbt.inputs
+        .iter()
+        .zip(signature.signature.iter())
+        .enumerate()
+        .map(|(idx, (input, signature))| {
+            let tx_hash = tx_hasher
+                .segwit_signature_hash(
+                    idx,
+                    input
+                        .witness_script
+                        .as_ref()
+                        .expect("Missing witness script"),
+                    input.witness_utxo.as_ref().expect("Missing UTXO").value,
+                    EcdsaSighashType::All,
+                )
+                .map_err(|_| ProcessPegOutSigError::SighashError)?;
+
+            let tweak = input
+                .proprietary
+                .get(&proprietary_tweak_key())
+                .expect("we saved it with a tweak");
+
+            let tweaked_peer_key = peer_key.tweak(tweak, secp);
+            secp.verify_ecdsa(
+                &Message::from_slice(&tx_hash[..]).unwrap(),
+                signature,
+                &tweaked_peer_key.key,
+            )
+            .map_err(|_| ProcessPegOutSigError::InvalidSignature)?;
+
+            Ok((tweaked_peer_key.into(), EcdsaSig::sighash_all(*signature)))
+        })
+        .collect()
+}
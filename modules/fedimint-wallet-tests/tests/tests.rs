@@ -6,7 +6,9 @@ use fedimint_dummy_client::DummyClientGen;
 use fedimint_dummy_common::config::DummyGenParams;
 use fedimint_dummy_server::DummyGen;
 use fedimint_testing::fixtures::{Fixtures, TIMEOUT};
-use fedimint_wallet_client::{DepositState, WalletClientExt, WalletClientGen, WithdrawState};
+use fedimint_wallet_client::{
+    ClaimedDeposit, DepositMetadata, DepositState, WalletClientExt, WalletClientGen, WithdrawState,
+};
 use fedimint_wallet_common::config::WalletGenParams;
 use fedimint_wallet_server::WalletGen;
 
@@ -31,8 +33,16 @@ async fn on_chain_peg_in_and_peg_out() -> anyhow::Result<()> {
     bitcoin.mine_blocks(finality_delay).await;
     let valid_until = SystemTime::now() + TIMEOUT;
 
-    let (op, address) = client.get_deposit_address(valid_until).await?;
-    bitcoin.send_and_mine_block(&address, bsats(5000)).await;
+    let metadata = DepositMetadata {
+        label: Some("alice".to_string()),
+        metadata: [("customer_id".to_string(), "42".to_string())].into(),
+    };
+    let (op, uri) = client
+        .get_deposit_uri(valid_until, Some(bsats(5000)), metadata.clone())
+        .await?;
+    assert_eq!(uri.amount, Some(bsats(5000)));
+    assert_eq!(uri.label, metadata.label);
+    let (_, btc_tx) = bitcoin.send_and_mine_block(&uri.address, bsats(5000)).await;
     let sub = client.subscribe_deposit_updates(op).await?;
     let mut sub = sub.into_stream();
     assert_eq!(sub.ok().await?, DepositState::WaitingForTransaction);
@@ -41,7 +51,15 @@ async fn on_chain_peg_in_and_peg_out() -> anyhow::Result<()> {
     // Need to mine blocks until deposit is confirmed
     bitcoin.mine_blocks(finality_delay).await;
     assert_eq!(sub.ok().await?, DepositState::Confirmed);
-    assert_eq!(sub.ok().await?, DepositState::Claimed);
+    assert_eq!(
+        sub.ok().await?,
+        DepositState::Claimed(ClaimedDeposit {
+            btc_txid: btc_tx.txid(),
+            amount: bsats(5000),
+            metadata,
+            expected_amount: Some(bsats(5000)),
+        })
+    );
     assert_eq!(client.get_balance().await, sats(5000));
 
     // Peg-out test, requires block to recognize change UTXOs
@@ -290,6 +290,10 @@ craneLib.overrideScope' (self: prev: {
     } // lib.optionalAttrs (target == null || target.name != "wasm32-unknown-unknown") {
       # broken on wasm32
       fedimint-sqlite = { };
+    } // lib.optionalAttrs (target != null && target.name == "wasm32-unknown-unknown") {
+      # browser only
+      fedimint-indexeddb = { };
+      fedimint-client-wasm = { };
     };
   };
 
@@ -11,8 +11,8 @@ use bitcoin::network::constants::Network;
 use bitcoin::OutPoint;
 use clap::{ArgGroup, Parser, Subcommand};
 use fedimint_core::core::{
-    DynModuleConsensusItem, LEGACY_HARDCODED_INSTANCE_ID_LN, LEGACY_HARDCODED_INSTANCE_ID_MINT,
-    LEGACY_HARDCODED_INSTANCE_ID_WALLET,
+    Decoder, DynModuleConsensusItem, ModuleInstanceId, ModuleKind, LEGACY_HARDCODED_INSTANCE_ID_LN,
+    LEGACY_HARDCODED_INSTANCE_ID_MINT,
 };
 use fedimint_core::db::Database;
 use fedimint_core::module::registry::ModuleDecoderRegistry;
@@ -25,11 +25,12 @@ use fedimint_mint_server::common::MintCommonGen;
 use fedimint_mint_server::Mint;
 use fedimint_rocksdb::RocksDb;
 use fedimint_server::config::io::read_server_config;
+use fedimint_server::config::ServerConfig;
 use fedimint_server::db::EpochHistoryKeyPrefix;
 use fedimint_server::epoch::{IterUnzipConsensusItem, SignedEpochOutcome, UnzipConsensusItem};
 use fedimint_server::transaction::Transaction;
 use fedimint_wallet_server::common::config::WalletConfig;
-use fedimint_wallet_server::common::db::{UTXOKey, UTXOPrefixKey};
+use fedimint_wallet_server::common::db::{FrozenUtxoKey, FrozenUtxoPrefix, UTXOKey, UTXOPrefixKey};
 use fedimint_wallet_server::common::keys::CompressedPublicKey;
 use fedimint_wallet_server::common::tweakable::Tweakable;
 use fedimint_wallet_server::common::{
@@ -65,6 +66,10 @@ struct RecoveryTool {
     /// Network to operate on, has to be specified if --cfg isn't present
     #[arg(long, default_value = "bitcoin", requires = "descriptor")]
     network: Network,
+    /// Instance id of the wallet module, has to be specified for utxos and
+    /// epochs if --cfg isn't present or the config contains several wallets
+    #[arg(long)]
+    wallet_instance_id: Option<ModuleInstanceId>,
     #[command(subcommand)]
     strategy: TweakSource,
 }
@@ -108,13 +113,28 @@ async fn main() -> anyhow::Result<()> {
 
     let opts: RecoveryTool = RecoveryTool::parse();
 
-    let (base_descriptor, base_key, network) = if let Some(config) = opts.config {
-        let cfg = read_server_config(&opts.password, config).expect("Could not read config file");
+    let cfg = opts.config.map(|config| {
+        read_server_config(&opts.password, config).expect("Could not read config file")
+    });
+
+    let wallet_instance_id = match &cfg {
+        Some(cfg) => Some(select_wallet_instance(cfg, opts.wallet_instance_id)?),
+        None => opts.wallet_instance_id,
+    };
+    let require_wallet_instance_id = || {
+        wallet_instance_id
+            .ok_or_else(|| anyhow!("--wallet-instance-id is required if --cfg isn't present"))
+    };
+
+    let (base_descriptor, base_key, network) = if let Some(cfg) = &cfg {
         let wallet_cfg: WalletConfig = cfg
-            .get_module_config_typed(LEGACY_HARDCODED_INSTANCE_ID_WALLET)
+            .get_module_config_typed(require_wallet_instance_id()?)
             .expect("Malformed wallet config");
         let base_descriptor = wallet_cfg.consensus.peg_in_descriptor;
-        let base_key = wallet_cfg.private.peg_in_key;
+        let base_key = wallet_cfg
+            .private
+            .peg_in_key
+            .expect("The config holds no peg-in key, use --descriptor and --key instead");
         let network = wallet_cfg.consensus.network;
 
         (base_descriptor, base_key, network)
@@ -141,15 +161,28 @@ async fn main() -> anyhow::Result<()> {
             let db = if legacy {
                 db
             } else {
-                db.new_isolated(LEGACY_HARDCODED_INSTANCE_ID_WALLET)
+                db.new_isolated(require_wallet_instance_id()?)
             };
 
-            let utxos: Vec<ImportableWallet> = db
-                .begin_transaction()
-                .await
+            let mut dbtx = db.begin_transaction().await;
+            let mut spendable_utxos: Vec<_> = dbtx
                 .find_by_prefix(&UTXOPrefixKey)
                 .await
-                .map(|(UTXOKey(outpoint), SpendableUTXO { tweak, amount })| {
+                .map(|(UTXOKey(outpoint), utxo)| (outpoint, utxo))
+                .collect()
+                .await;
+            // Frozen UTXOs are still owned by the federation
+            spendable_utxos.extend(
+                dbtx.find_by_prefix(&FrozenUtxoPrefix)
+                    .await
+                    .map(|(FrozenUtxoKey(outpoint), utxo)| (outpoint, utxo))
+                    .collect::<Vec<_>>()
+                    .await,
+            );
+
+            let utxos: Vec<ImportableWallet> = spendable_utxos
+                .into_iter()
+                .map(|(outpoint, SpendableUTXO { tweak, amount })| {
                     let descriptor = tweak_descriptor(&base_descriptor, &base_key, &tweak, network);
 
                     ImportableWallet {
@@ -158,30 +191,37 @@ async fn main() -> anyhow::Result<()> {
                         amount_sat: amount,
                     }
                 })
-                .collect()
-                .await;
+                .collect();
 
             serde_json::to_writer(std::io::stdout().lock(), &utxos)
                 .expect("Could not encode to stdout")
         }
         TweakSource::Epochs { db } => {
-            let decoders = ModuleDecoderRegistry::from_iter([
-                (
-                    LEGACY_HARDCODED_INSTANCE_ID_LN,
-                    LightningCommonGen::KIND,
-                    <Lightning as ServerModule>::decoder(),
-                ),
-                (
-                    LEGACY_HARDCODED_INSTANCE_ID_MINT,
-                    MintCommonGen::KIND,
-                    <Mint as ServerModule>::decoder(),
-                ),
-                (
-                    LEGACY_HARDCODED_INSTANCE_ID_WALLET,
-                    WalletCommonGen::KIND,
-                    <Wallet as ServerModule>::decoder(),
-                ),
-            ]);
+            let wallet_instance_id = require_wallet_instance_id()?;
+            let decoders = match &cfg {
+                Some(cfg) => cfg
+                    .iter_module_instances()
+                    .map(|(id, kind)| Ok((id, kind.clone(), module_decoder(kind)?)))
+                    .collect::<anyhow::Result<ModuleDecoderRegistry>>()?,
+                // Without a config we can only assume the legacy layout for the other modules
+                None => ModuleDecoderRegistry::from_iter([
+                    (
+                        LEGACY_HARDCODED_INSTANCE_ID_LN,
+                        LightningCommonGen::KIND,
+                        <Lightning as ServerModule>::decoder(),
+                    ),
+                    (
+                        LEGACY_HARDCODED_INSTANCE_ID_MINT,
+                        MintCommonGen::KIND,
+                        <Mint as ServerModule>::decoder(),
+                    ),
+                    (
+                        wallet_instance_id,
+                        WalletCommonGen::KIND,
+                        <Wallet as ServerModule>::decoder(),
+                    ),
+                ]),
+            };
 
             let db = Database::new(RocksDb::open(db).expect("Error opening DB"), decoders);
             let mut dbtx = db.begin_transaction().await;
@@ -200,9 +240,12 @@ async fn main() -> anyhow::Result<()> {
 
                     // Get all user-submitted tweaks and if we did a peg-out tx also return the
                     // consensus round's tweak used for change
-                    let epoch_tweak = round_tweak(module_cis.into_iter().map(|(_, ci)| ci));
-                    let (mut peg_in_tweaks, peg_out_present) =
-                        input_tweaks_output_present(transaction_cis.into_iter().map(|(_, ci)| ci));
+                    let epoch_tweak =
+                        round_tweak(module_cis.into_iter().map(|(_, ci)| ci), wallet_instance_id);
+                    let (mut peg_in_tweaks, peg_out_present) = input_tweaks_output_present(
+                        transaction_cis.into_iter().map(|(_, ci)| ci),
+                        wallet_instance_id,
+                    );
 
                     if peg_out_present {
                         peg_in_tweaks.insert(epoch_tweak);
@@ -228,41 +271,82 @@ async fn main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Picks the wallet module from the config, either the one selected by the
+/// user or the only one present
+fn select_wallet_instance(
+    cfg: &ServerConfig,
+    selected: Option<ModuleInstanceId>,
+) -> anyhow::Result<ModuleInstanceId> {
+    let wallet_kind = WalletCommonGen::KIND;
+    let wallets = cfg
+        .iter_module_instances()
+        .filter(|(_, kind)| **kind == wallet_kind)
+        .map(|(id, _)| id)
+        .collect::<Vec<_>>();
+
+    match (selected, wallets.as_slice()) {
+        (Some(id), _) if wallets.contains(&id) => Ok(id),
+        (Some(id), _) => Err(anyhow!("Module {id} is not a wallet module")),
+        (None, [id]) => Ok(*id),
+        (None, []) => Err(anyhow!("The config contains no wallet module")),
+        (None, _) => Err(anyhow!(
+            "The config contains several wallet modules, select one with --wallet-instance-id"
+        )),
+    }
+}
+
+fn module_decoder(kind: &ModuleKind) -> anyhow::Result<Decoder> {
+    if *kind == LightningCommonGen::KIND {
+        Ok(<Lightning as ServerModule>::decoder())
+    } else if *kind == MintCommonGen::KIND {
+        Ok(<Mint as ServerModule>::decoder())
+    } else if *kind == WalletCommonGen::KIND {
+        Ok(<Wallet as ServerModule>::decoder())
+    } else {
+        Err(anyhow!("Can't decode epochs containing module kind {kind}"))
+    }
+}
+
 fn input_tweaks_output_present(
     transactions: impl Iterator<Item = Transaction>,
+    wallet_instance_id: ModuleInstanceId,
 ) -> (BTreeSet<[u8; 32]>, bool) {
     let mut contains_peg_out = false;
-    let tweaks =
-        transactions
-            .flat_map(|tx| {
-                if tx.outputs.iter().any(|output| {
-                    output.module_instance_id() == LEGACY_HARDCODED_INSTANCE_ID_WALLET
-                }) {
-                    contains_peg_out = true;
-                }
+    let tweaks = transactions
+        .flat_map(|tx| {
+            if tx
+                .outputs
+                .iter()
+                .any(|output| output.module_instance_id() == wallet_instance_id)
+            {
+                contains_peg_out = true;
+            }
 
-                tx.inputs.into_iter().filter_map(|input| {
-                    if input.module_instance_id() != LEGACY_HARDCODED_INSTANCE_ID_WALLET {
-                        return None;
-                    }
+            tx.inputs.into_iter().filter_map(|input| {
+                if input.module_instance_id() != wallet_instance_id {
+                    return None;
+                }
 
-                    Some(
-                        input
-                            .as_any()
-                            .downcast_ref::<WalletInput>()
-                            .expect("Instance id mapping incorrect")
-                            .0
-                            .tweak_contract_key()
-                            .serialize(),
-                    )
-                })
+                Some(
+                    input
+                        .as_any()
+                        .downcast_ref::<WalletInput>()
+                        .expect("Instance id mapping incorrect")
+                        .0
+                        .tweak_contract_key()
+                        .serialize(),
+                )
             })
-            .collect::<BTreeSet<_>>();
+        })
+        .collect::<BTreeSet<_>>();
 
     (tweaks, contains_peg_out)
 }
 
-fn round_tweak(module_cis: impl Iterator<Item = DynModuleConsensusItem>) -> [u8; 32] {
+fn round_tweak(
+    module_cis: impl Iterator<Item = DynModuleConsensusItem>,
+    wallet_instance_id: ModuleInstanceId,
+) -> [u8; 32] {
     fn xor(mut lhs: [u8; 32], rhs: [u8; 32]) -> [u8; 32] {
         lhs.iter_mut().zip(rhs).for_each(|(lhs, rhs)| *lhs ^= rhs);
         lhs
@@ -270,7 +354,7 @@ fn round_tweak(module_cis: impl Iterator<Item = DynModuleConsensusItem>) -> [u8;
 
     module_cis
         .filter_map(|mci| {
-            if mci.module_instance_id() != LEGACY_HARDCODED_INSTANCE_ID_WALLET {
+            if mci.module_instance_id() != wallet_instance_id {
                 return None;
             }
 
@@ -280,7 +364,11 @@ fn round_tweak(module_cis: impl Iterator<Item = DynModuleConsensusItem>) -> [u8;
                 .expect("Instance id mapping incorrect");
             match wci {
                 WalletConsensusItem::RoundConsensus(rci) => Some(rci.randomness),
-                WalletConsensusItem::PegOutSignature(_) => None,
+                WalletConsensusItem::PegOutSignature(_)
+                | WalletConsensusItem::PegOutNonces(_)
+                | WalletConsensusItem::PegOutSignatureShares(_)
+                | WalletConsensusItem::FeeSubsidyRate(_)
+                | WalletConsensusItem::UtxoFreeze(_) => None,
             }
         })
         .fold([0; 32], xor)
@@ -0,0 +1,8 @@
+#!/usr/bin/env bash
+# Runs a test paying between two federations over their gateways' lightning nodes
+
+set -euo pipefail
+export RUST_LOG="${RUST_LOG:-info}"
+source ./scripts/build.sh
+
+devimint cross-federation-test
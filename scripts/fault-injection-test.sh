@@ -0,0 +1,8 @@
+#!/usr/bin/env bash
+# Runs a test injecting network faults, bitcoind outages and crashing peers
+
+set -euo pipefail
+export RUST_LOG="${RUST_LOG:-info}"
+source ./scripts/build.sh
+
+devimint fault-injection-test
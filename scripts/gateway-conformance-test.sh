@@ -0,0 +1,8 @@
+#!/usr/bin/env bash
+# Runs the same payment scenarios against the gateway of every lightning backend
+
+set -euo pipefail
+export RUST_LOG="${RUST_LOG:-info}"
+source ./scripts/build.sh
+
+devimint gateway-conformance-test
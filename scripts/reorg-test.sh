@@ -0,0 +1,8 @@
+#!/usr/bin/env bash
+# Runs a test reorging the chain and moving block timestamps forward
+
+set -euo pipefail
+export RUST_LOG="${RUST_LOG:-info}"
+source ./scripts/build.sh
+
+devimint reorg-test
@@ -0,0 +1,9 @@
+#!/usr/bin/env bash
+# Performs random operations against a federation for hours while restarting
+# peers, pass `--seed` to replay a failed run. Too slow for CI.
+
+set -euo pipefail
+export RUST_LOG="${RUST_LOG:-info}"
+source ./scripts/build.sh
+
+devimint soak-test "$@"
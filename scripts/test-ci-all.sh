@@ -47,6 +47,46 @@ function cli_test_lightning_reconnect() {
 }
 export -f cli_test_lightning_reconnect
 
+function cli_test_cross_federation() {
+  set -eo pipefail # pipefail must be set manually again
+  trap 'echo "## FAILED: ${FUNCNAME[0]}"' ERR
+
+  echo "## START: ${FUNCNAME[0]}"
+  unshare -rn bash -c "ip link set lo up && exec unshare --user ./scripts/cross-federation-test.sh" 2>&1 | ts -s
+  echo "## COMPLETE: ${FUNCNAME[0]}"
+}
+export -f cli_test_cross_federation
+
+function cli_test_fault_injection() {
+  set -eo pipefail # pipefail must be set manually again
+  trap 'echo "## FAILED: ${FUNCNAME[0]}"' ERR
+
+  echo "## START: ${FUNCNAME[0]}"
+  unshare -rn bash -c "ip link set lo up && exec unshare --user ./scripts/fault-injection-test.sh" 2>&1 | ts -s
+  echo "## COMPLETE: ${FUNCNAME[0]}"
+}
+export -f cli_test_fault_injection
+
+function cli_test_reorg() {
+  set -eo pipefail # pipefail must be set manually again
+  trap 'echo "## FAILED: ${FUNCNAME[0]}"' ERR
+
+  echo "## START: ${FUNCNAME[0]}"
+  unshare -rn bash -c "ip link set lo up && exec unshare --user ./scripts/reorg-test.sh" 2>&1 | ts -s
+  echo "## COMPLETE: ${FUNCNAME[0]}"
+}
+export -f cli_test_reorg
+
+function cli_test_gateway_conformance() {
+  set -eo pipefail # pipefail must be set manually again
+  trap 'echo "## FAILED: ${FUNCNAME[0]}"' ERR
+
+  echo "## START: ${FUNCNAME[0]}"
+  unshare -rn bash -c "ip link set lo up && exec unshare --user ./scripts/gateway-conformance-test.sh" 2>&1 | ts -s
+  echo "## COMPLETE: ${FUNCNAME[0]}"
+}
+export -f cli_test_gateway_conformance
+
 function cli_test_latency() {
   set -eo pipefail # pipefail must be set manually again
   trap 'echo "## FAILED: ${FUNCNAME[0]}"' ERR
@@ -155,6 +195,10 @@ if parallel \
   cli_test_latency \
   cli_test_reconnect \
   cli_test_lightning_reconnect \
+  cli_test_cross_federation \
+  cli_test_fault_injection \
+  cli_test_reorg \
+  cli_test_gateway_conformance \
   cli_test_cli \
   cli_load_test_tool_test ; then
   >&2 echo "All tests successful"